@@ -0,0 +1,103 @@
+use reqwest::Method;
+
+use gateway::model::{
+    feature_flag::{FeatureFlagQuery, FeatureFlagStatus},
+    import::{ImportQuery, ImportRequest, RepoImport},
+    mirror::{Mirror, MirrorSync, MirrorUpdate},
+    ref_trigger::{RefTrigger, RefTriggerRun, RefTriggerUpdate},
+    search::SearchReindex,
+};
+
+use crate::{ClientError, MegaClient};
+
+/// Administration: imports, mirrors, ref triggers, feature flags and the search index.
+impl MegaClient {
+    /// Import a repository from an external remote, see [`ImportRequest`].
+    pub async fn import_repo(&self, request: &ImportRequest) -> Result<RepoImport, ClientError> {
+        self.call(Method::POST, &["admin", "imports"], Some(request))
+            .await
+    }
+
+    pub async fn list_imports(&self, query: &ImportQuery) -> Result<Vec<RepoImport>, ClientError> {
+        self.get(&["admin", "imports"], query).await
+    }
+
+    pub async fn get_import(&self, id: i64) -> Result<RepoImport, ClientError> {
+        self.get(&["admin", "imports", &id.to_string()], &()).await
+    }
+
+    pub async fn list_mirrors(&self) -> Result<Vec<Mirror>, ClientError> {
+        self.get(&["admin", "mirrors"], &()).await
+    }
+
+    /// Mirror an upstream repository, or change the settings of the mirror of its path.
+    pub async fn save_mirror(&self, update: &MirrorUpdate) -> Result<Mirror, ClientError> {
+        self.call(Method::PUT, &["admin", "mirrors"], Some(update))
+            .await
+    }
+
+    pub async fn get_mirror(&self, id: i64) -> Result<Mirror, ClientError> {
+        self.get(&["admin", "mirrors", &id.to_string()], &()).await
+    }
+
+    pub async fn delete_mirror(&self, id: i64) -> Result<Mirror, ClientError> {
+        self.call::<(), _>(Method::DELETE, &["admin", "mirrors", &id.to_string()], None)
+            .await
+    }
+
+    /// Sync a mirror with its upstream right away.
+    pub async fn sync_mirror(&self, id: i64) -> Result<MirrorSync, ClientError> {
+        self.call::<(), _>(
+            Method::POST,
+            &["admin", "mirrors", &id.to_string(), "sync"],
+            None,
+        )
+        .await
+    }
+
+    pub async fn list_ref_triggers(&self) -> Result<Vec<RefTrigger>, ClientError> {
+        self.get(&["admin", "ref-triggers"], &()).await
+    }
+
+    pub async fn save_ref_trigger(
+        &self,
+        name: &str,
+        update: &RefTriggerUpdate,
+    ) -> Result<RefTrigger, ClientError> {
+        self.call(Method::PUT, &["admin", "ref-triggers", name], Some(update))
+            .await
+    }
+
+    pub async fn delete_ref_trigger(&self, name: &str) -> Result<(), ClientError> {
+        self.call_empty::<()>(Method::DELETE, &["admin", "ref-triggers", name], None)
+            .await
+    }
+
+    /// Run a ref trigger now, outside of its schedule.
+    pub async fn run_ref_trigger(&self, name: &str) -> Result<RefTriggerRun, ClientError> {
+        self.call::<(), _>(Method::POST, &["admin", "ref-triggers", name, "run"], None)
+            .await
+    }
+
+    /// Whether the flag `name` is on for `query.org`.
+    pub async fn feature_flag(
+        &self,
+        name: &str,
+        query: &FeatureFlagQuery,
+    ) -> Result<FeatureFlagStatus, ClientError> {
+        self.get(&["feature-flags", name], query).await
+    }
+
+    /// Index a repository for search again.
+    pub async fn reindex(&self, repo_path: &str) -> Result<(), ClientError> {
+        let reindex = SearchReindex {
+            repo_path: repo_path.to_owned(),
+        };
+        self.call_empty(
+            Method::POST,
+            &["admin", "search", "reindex"],
+            Some(&reindex),
+        )
+        .await
+    }
+}
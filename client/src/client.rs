@@ -0,0 +1,530 @@
+use std::env;
+use std::time::Duration;
+
+use reqwest::header::{ACCEPT_LANGUAGE, RETRY_AFTER};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use gateway::model::error::ApiError;
+use gateway::model::page::{Page, PageQuery};
+
+use crate::config::Config;
+use crate::error::{ClientError, ConfigError};
+
+/// Items asked for a page when reading whole lists, the most the server returns.
+const MAX_PAGE: u64 = 100;
+
+/// The server a remote url points to: the url itself, or its origin when it is the url of a
+/// repository, ending with `.git`.
+fn server_url(url: &str) -> Result<String, ClientError> {
+    let parsed = Url::parse(url).map_err(|e| ClientError::InvalidUrl(format!("{url}: {e}")))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ClientError::InvalidUrl(format!(
+            "{url}: the API is only served over HTTP"
+        )));
+    }
+    if !parsed.path().trim_end_matches('/').ends_with(".git") {
+        return Ok(url.to_owned());
+    }
+    Ok(parsed.origin().ascii_serialization())
+}
+
+/// Credentials sent with every call.
+#[derive(Clone, Debug)]
+pub enum Auth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Auth {
+    /// The `credential.token`, or `credential.username` and `credential.password`, of `config`.
+    pub fn from_config(config: &Config) -> Option<Auth> {
+        match (
+            config.get("credential.token"),
+            config.get("credential.username"),
+        ) {
+            (Some(token), _) => Some(Auth::Bearer(token.to_owned())),
+            (_, Some(username)) => Some(Auth::Basic {
+                username: username.to_owned(),
+                password: config
+                    .get("credential.password")
+                    .unwrap_or_default()
+                    .to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Auth::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Auth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+/// When and how often calls are retried. Only calls that can be repeated without changing their
+/// outcome are: `GET`, `PUT` and `DELETE`. They are retried when the server can't be reached or
+/// answers 429, 502, 503 or 504, waiting `initial_backoff` and twice as long before each next
+/// try, or as long as the `Retry-After` header of the response asks, up to `max_backoff`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MegaClient {
+    http: reqwest::Client,
+    base_url: Url,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+    locale: Option<String>,
+}
+
+impl MegaClient {
+    /// Client of the server at `base_url`, e.g. `http://localhost:8000`.
+    pub fn new(base_url: &str) -> Result<MegaClient, ClientError> {
+        let mut base_url = Url::parse(base_url)
+            .map_err(|e| ClientError::InvalidUrl(format!("{base_url}: {e}")))?;
+        if base_url.cannot_be_a_base() {
+            return Err(ClientError::InvalidUrl(base_url.to_string()));
+        }
+        base_url
+            .path_segments_mut()
+            .expect("checked to be a base url")
+            .pop_if_empty()
+            .extend(["api", "v1"]);
+        Ok(MegaClient {
+            http: reqwest::Client::new(),
+            base_url,
+            auth: None,
+            retry: RetryPolicy::default(),
+            locale: None,
+        })
+    }
+
+    /// Client of the server at `MEGA_URL`, authenticated with `MEGA_TOKEN` or with
+    /// `MEGA_USERNAME` and `MEGA_PASSWORD` when they are set.
+    pub fn from_env() -> Result<MegaClient, ClientError> {
+        let url = env::var("MEGA_URL").unwrap_or_else(|_| "http://localhost:8000".to_owned());
+        let client = MegaClient::new(&url)?;
+        let auth = match (env::var("MEGA_TOKEN"), env::var("MEGA_USERNAME")) {
+            (Ok(token), _) => Some(Auth::Bearer(token)),
+            (_, Ok(username)) => Some(Auth::Basic {
+                username,
+                password: env::var("MEGA_PASSWORD").unwrap_or_default(),
+            }),
+            _ => None,
+        };
+        Ok(match auth {
+            Some(auth) => client.with_auth(auth),
+            None => client,
+        })
+    }
+
+    /// Client of the server `remote.<remote>.url` of `config` points to, authenticated with its
+    /// `credential.token`, or with `credential.username` and `credential.password`. The url may
+    /// be that of a repository cloned from the server, the server being at its origin.
+    pub fn from_config(config: &Config, remote: &str) -> Result<MegaClient, ClientError> {
+        let key = format!("remote.{remote}.url");
+        let url = config.get(&key).ok_or(ConfigError::Missing(key))?;
+        let client = MegaClient::new(&server_url(url)?)?;
+        Ok(match Auth::from_config(config) {
+            Some(auth) => client.with_auth(auth),
+            None => client,
+        })
+    }
+
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Ask for messages in `language`, an `Accept-Language` value such as `zh-CN`.
+    pub fn with_locale(mut self, language: &str) -> Self {
+        self.locale = Some(language.to_owned());
+        self
+    }
+
+    /// Use `http` for the calls, e.g. to set timeouts or proxies.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Url of the API path made of `segments`, each of them escaped.
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("checked to be a base url")
+            .extend(segments);
+        url
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let mut request = self.http.request(method, url);
+        if let Some(auth) = &self.auth {
+            request = auth.apply(request);
+        }
+        match &self.locale {
+            Some(locale) => request.header(ACCEPT_LANGUAGE, locale),
+            None => request,
+        }
+    }
+
+    /// Send the call to `segments` set up by `prepare`, retrying it as the policy allows, and
+    /// turn responses refusing it into errors.
+    async fn send(
+        &self,
+        method: Method,
+        segments: &[&str],
+        prepare: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Response, ClientError> {
+        let url = self.url(segments);
+        let repeatable = matches!(method, Method::GET | Method::PUT | Method::DELETE);
+        let mut attempt = 0;
+        loop {
+            let result = prepare(self.request(method.clone(), url.clone()))
+                .send()
+                .await;
+            let wait = match &result {
+                _ if !repeatable || attempt >= self.retry.max_retries => None,
+                Ok(response) if should_retry(response.status()) => Some(
+                    retry_after(response)
+                        .unwrap_or_else(|| self.retry.backoff(attempt))
+                        .min(self.retry.max_backoff),
+                ),
+                Err(e) if e.is_connect() || e.is_timeout() => Some(self.retry.backoff(attempt)),
+                _ => None,
+            };
+            match wait {
+                Some(wait) => {
+                    tracing::debug!("retrying {} {} in {:?}", method, url, wait);
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+                None => return check(result?).await,
+            }
+        }
+    }
+
+    pub(crate) async fn get<Q, T>(&self, segments: &[&str], query: &Q) -> Result<T, ClientError>
+    where
+        Q: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let response = self.send(Method::GET, segments, |r| r.query(query)).await?;
+        Ok(response.json().await?)
+    }
+
+    pub(crate) async fn get_bytes<Q>(
+        &self,
+        segments: &[&str],
+        query: &Q,
+    ) -> Result<bytes::Bytes, ClientError>
+    where
+        Q: Serialize + ?Sized,
+    {
+        let response = self.send(Method::GET, segments, |r| r.query(query)).await?;
+        Ok(response.bytes().await?)
+    }
+
+    /// The `page` of a paged list.
+    pub(crate) async fn get_page<Q, T>(
+        &self,
+        segments: &[&str],
+        query: &Q,
+        page: &PageQuery,
+    ) -> Result<Page<T>, ClientError>
+    where
+        Q: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let response = self
+            .send(Method::GET, segments, |r| r.query(query).query(page))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// All the items of a paged list, following its cursors page after page.
+    pub(crate) async fn get_all<Q, T>(
+        &self,
+        segments: &[&str],
+        query: &Q,
+    ) -> Result<Vec<T>, ClientError>
+    where
+        Q: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let mut items = Vec::new();
+        let mut page = PageQuery {
+            limit: Some(MAX_PAGE),
+            ..Default::default()
+        };
+        loop {
+            let next: Page<T> = self.get_page(segments, query, &page).await?;
+            items.extend(next.items);
+            match next.next_cursor {
+                Some(cursor) => page.cursor = Some(cursor),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    /// Call `segments` with `body` as JSON, and read the JSON response.
+    pub(crate) async fn call<B, T>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: Option<&B>,
+    ) -> Result<T, ClientError>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let response = self
+            .send(method, segments, |r| match body {
+                Some(body) => r.json(body),
+                None => r,
+            })
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Call `segments` with `body` as JSON, for calls answering with an empty response.
+    pub(crate) async fn call_empty<B>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: Option<&B>,
+    ) -> Result<(), ClientError>
+    where
+        B: Serialize + ?Sized,
+    {
+        self.send(method, segments, |r| match body {
+            Some(body) => r.json(body),
+            None => r,
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+fn should_retry(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Wait asked for by the `Retry-After` header, when given in seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+pub(crate) async fn check(response: Response) -> Result<Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let text = response.text().await.unwrap_or_default();
+    // servers before typed errors answered with the message alone
+    let (code, message) = match serde_json::from_str::<ApiError>(&text) {
+        Ok(error) => (error.code, error.message),
+        Err(_) => (String::new(), text),
+    };
+    Err(ClientError::Api {
+        status,
+        code,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::extract::Query;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use axum::Router;
+    use gateway::model::page::PageQuery;
+
+    use super::{server_url, Auth, MegaClient, RetryPolicy};
+    use crate::ClientError;
+
+    #[test]
+    fn test_url() {
+        let client = MegaClient::new("http://localhost:8000/").unwrap();
+        assert_eq!(
+            client.url(&["users", "a b/c", "ssh-keys"]).as_str(),
+            "http://localhost:8000/api/v1/users/a%20b%2Fc/ssh-keys"
+        );
+        let client = MegaClient::new("https://example.com/mega").unwrap();
+        assert_eq!(
+            client.url(&["mr", "7"]).as_str(),
+            "https://example.com/mega/api/v1/mr/7"
+        );
+        assert!(MegaClient::new("localhost").is_err());
+    }
+
+    #[test]
+    fn test_server_url() {
+        assert_eq!(
+            server_url("http://localhost:8000/third-party/repo.git").unwrap(),
+            "http://localhost:8000"
+        );
+        assert_eq!(
+            server_url("https://example.com/mega").unwrap(),
+            "https://example.com/mega"
+        );
+        assert!(server_url("ssh://git@localhost:2222/repo.git").is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry = RetryPolicy::default();
+        assert_eq!(retry.backoff(0), Duration::from_millis(200));
+        assert_eq!(retry.backoff(2), Duration::from_millis(800));
+        assert_eq!(retry.backoff(40), retry.max_backoff);
+    }
+
+    async fn serve(app: Router) -> MegaClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        MegaClient::new(&format!("http://{}", addr))
+            .unwrap()
+            .with_retry(RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            })
+    }
+
+    #[tokio::test]
+    async fn test_get_all() {
+        let app = Router::new().route(
+            "/api/v1/issues",
+            get(|Query(page): Query<PageQuery>| async move {
+                let (items, next_cursor) = match page.cursor.as_deref() {
+                    None => (vec![1, 2], Some("2".to_owned())),
+                    Some("2") => (vec![3], None),
+                    Some(_) => panic!("unknown cursor"),
+                };
+                axum::Json(serde_json::json!({ "items": items, "next_cursor": next_cursor }))
+            }),
+        );
+        let client = serve(app).await;
+        let items: Vec<i64> = client.get_all(&["issues"], &()).await.unwrap();
+        assert_eq!(items, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_and_auth() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counted = calls.clone();
+        let posts = Arc::new(AtomicU32::new(0));
+        let counted_posts = posts.clone();
+        let app = Router::new()
+            .route(
+                "/api/v1/status",
+                get(move |headers: HeaderMap| async move {
+                    if counted.fetch_add(1, Ordering::SeqCst) < 2 {
+                        return Err(StatusCode::SERVICE_UNAVAILABLE);
+                    }
+                    let auth = headers["authorization"].to_str().unwrap().to_owned();
+                    Ok(axum::Json(auth))
+                }),
+            )
+            .route(
+                "/api/v1/mr",
+                post(move || async move {
+                    counted_posts.fetch_add(1, Ordering::SeqCst);
+                    (StatusCode::SERVICE_UNAVAILABLE, "try later")
+                }),
+            )
+            .route(
+                "/api/v1/issues/1",
+                get(|| async {
+                    let error = serde_json::json!({ "code": "not_found", "message": "no issue 1" });
+                    (StatusCode::NOT_FOUND, axum::Json(error))
+                }),
+            );
+        let client = serve(app)
+            .await
+            .with_auth(Auth::Bearer("secret".to_owned()));
+
+        let auth: String = client.get(&["status"], &()).await.unwrap();
+        assert_eq!(auth, "Bearer secret");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let err = client
+            .call::<_, serde_json::Value>(reqwest::Method::POST, &["mr"], Some(&()))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Api { status, ref message, .. }
+                if status.as_u16() == 503 && message == "try later"
+        ));
+        assert_eq!(posts.load(Ordering::SeqCst), 1);
+
+        let err = client
+            .get::<_, serde_json::Value>(&["issues", "1"], &())
+            .await
+            .unwrap_err();
+        assert!(err.is_not_found());
+        assert!(matches!(
+            err,
+            ClientError::Api { ref code, ref message, .. }
+                if code == "not_found" && message == "no issue 1"
+        ));
+    }
+}
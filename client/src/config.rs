@@ -0,0 +1,674 @@
+//!
+//! Layered settings of the client, read from TOML files of three scopes.
+//!
+//! | scope    | file                                                               |
+//! |----------|--------------------------------------------------------------------|
+//! | `system` | `/etc/mega/config.toml`, or `MEGA_CONFIG_SYSTEM`                   |
+//! | `user`   | `$XDG_CONFIG_HOME/mega/config.toml`, `~/.config/mega/config.toml`, or `MEGA_CONFIG_USER` |
+//! | `repo`   | `.mega/config.toml` in the repository the current directory is in   |
+//!
+//! A key is a dotted path to a string, as in git: `<section>.<name>`, or
+//! `<section>.<subsection>.<name>`. In the files, the section and the subsection are tables, and
+//! a key taking several values is a list:
+//!
+//! ```toml
+//! [remote.origin]
+//! url = "https://mega.example.com"
+//! fetch = ["+refs/heads/*:refs/remotes/origin/*"]
+//!
+//! [credential]
+//! token = "..."
+//! ```
+//!
+//! A value of the repo scope wins over one of the user scope, which wins over the system one.
+//! The `.git/config` of the repository is read too, as part of the repo scope below its
+//! `.mega/config.toml`, so a clone keeps the remotes git recorded. It is never written.
+//!
+//! These keys are used by the client:
+//!
+//! - `remote.<name>.url`: the server, by its URL or by the URL of a repository on it, see
+//!   [`MegaClient::from_config`](crate::MegaClient::from_config)
+//! - `remote.<name>.fetch` and `remote.<name>.prune`: what fetching the remote records, see
+//!   [`remote`](crate::remote)
+//! - `credential.token`: access token sent as a bearer token
+//! - `credential.username` and `credential.password`: sent with basic authentication when
+//!   there is no token
+//!
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::error::ConfigError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    System,
+    User,
+    Repo,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::System => "system",
+            Scope::User => "user",
+            Scope::Repo => "repo",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Scope::System),
+            "user" => Ok(Scope::User),
+            "repo" => Ok(Scope::Repo),
+            _ => Err(ConfigError::InvalidScope(s.to_owned())),
+        }
+    }
+}
+
+/// Where the files of each scope are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigPaths {
+    pub system: PathBuf,
+    pub user: Option<PathBuf>,
+    /// Root of the repository, `None` outside of one
+    pub repo: Option<PathBuf>,
+}
+
+impl ConfigPaths {
+    /// The files of the scopes, with the repository the one `dir` is in.
+    pub fn discover(dir: &Path) -> Self {
+        let system = std::env::var_os("MEGA_CONFIG_SYSTEM")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/etc/mega/config.toml"));
+        let user = std::env::var_os("MEGA_CONFIG_USER")
+            .map(PathBuf::from)
+            .or_else(|| {
+                let config_home = std::env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| {
+                        std::env::var_os("HOME").map(|home| Path::new(&home).join(".config"))
+                    })?;
+                Some(config_home.join("mega").join("config.toml"))
+            });
+        let repo = dir
+            .ancestors()
+            .find(|dir| dir.join(".mega").is_dir() || dir.join(".git").exists())
+            .map(Path::to_path_buf);
+        ConfigPaths { system, user, repo }
+    }
+
+    /// The file `scope` is written to, `None` when there is no repository or home directory.
+    pub fn file(&self, scope: Scope) -> Option<PathBuf> {
+        match scope {
+            Scope::System => Some(self.system.clone()),
+            Scope::User => self.user.clone(),
+            Scope::Repo => self
+                .repo
+                .as_ref()
+                .map(|repo| repo.join(".mega").join("config.toml")),
+        }
+    }
+
+    fn git_config(&self) -> Option<PathBuf> {
+        self.repo
+            .as_ref()
+            .map(|repo| repo.join(".git").join("config"))
+    }
+}
+
+/// A value of one of the files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: String,
+    pub scope: Scope,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+struct Layer {
+    scope: Scope,
+    path: PathBuf,
+    entries: Vec<(String, String)>,
+}
+
+/// The settings of every scope, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct Config {
+    paths: ConfigPaths,
+    /// lowest precedence first
+    layers: Vec<Layer>,
+}
+
+impl Config {
+    /// The settings seen from the current directory.
+    pub fn load() -> Result<Config, ConfigError> {
+        let dir = std::env::current_dir().map_err(|source| ConfigError::Read {
+            path: PathBuf::from("."),
+            source,
+        })?;
+        Config::load_from(ConfigPaths::discover(&dir))
+    }
+
+    /// The settings of the files at `paths`, those missing being empty.
+    pub fn load_from(paths: ConfigPaths) -> Result<Config, ConfigError> {
+        let mut layers = Vec::new();
+        for scope in [Scope::System, Scope::User] {
+            if let Some(path) = paths.file(scope) {
+                layers.push(read_toml(scope, path)?);
+            }
+        }
+        if let Some(path) = paths.git_config() {
+            layers.push(read_git_config(path)?);
+        }
+        if let Some(path) = paths.file(Scope::Repo) {
+            layers.push(read_toml(Scope::Repo, path)?);
+        }
+        Ok(Config { paths, layers })
+    }
+
+    pub fn paths(&self) -> &ConfigPaths {
+        &self.paths
+    }
+
+    /// The value of `key` of the scope with the highest precedence setting it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key).ok()?;
+        self.layers
+            .iter()
+            .rev()
+            .flat_map(|layer| layer.entries.iter().rev())
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of every file, those of `scope` only when it is given, the lowest precedence
+    /// first like `git config --list`.
+    pub fn list(&self, scope: Option<Scope>) -> Vec<ConfigEntry> {
+        self.layers
+            .iter()
+            .filter(|layer| scope.is_none_or(|scope| layer.scope == scope))
+            .flat_map(|layer| {
+                layer.entries.iter().map(|(key, value)| ConfigEntry {
+                    key: key.clone(),
+                    value: value.clone(),
+                    scope: layer.scope,
+                    path: layer.path.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Set `key` to `value` in the file of `scope`, keeping the comments and layout of the
+    /// file.
+    pub fn set(&mut self, scope: Scope, key: &str, value: &str) -> Result<(), ConfigError> {
+        self.write_value(scope, key, toml_edit::value(value))
+    }
+
+    /// Set `key` to the list of `values`, for keys taking several values like the fetch
+    /// refspecs of a remote.
+    pub fn set_all(
+        &mut self,
+        scope: Scope,
+        key: &str,
+        values: &[String],
+    ) -> Result<(), ConfigError> {
+        let array: toml_edit::Array = values.iter().map(String::as_str).collect();
+        self.write_value(scope, key, toml_edit::value(array))
+    }
+
+    /// Every value of `key`, the lowest precedence first.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let Ok(key) = normalize_key(key) else {
+            return Vec::new();
+        };
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.entries.iter())
+            .filter(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Remove the section or subsection `name`, like `remote.origin`, from the file of `scope`.
+    /// Returns false when the file has no such section.
+    pub fn remove_section(&mut self, scope: Scope, name: &str) -> Result<bool, ConfigError> {
+        let path = self.scope_file(scope)?;
+        let mut doc = read_document(&path)?;
+        let (parent, last) = match name.split_once('.') {
+            Some((section, subsection)) => (Some(section.to_ascii_lowercase()), subsection),
+            None => (None, name),
+        };
+        let removed = match &parent {
+            Some(section) => doc
+                .get_mut(section)
+                .and_then(Item::as_table_mut)
+                .and_then(|table| table.remove(last)),
+            None => doc.remove(&last.to_ascii_lowercase()),
+        };
+        if removed.is_none() {
+            return Ok(false);
+        }
+        write_document(&path, &doc)?;
+        self.reload(scope, path)?;
+        Ok(true)
+    }
+
+    fn scope_file(&self, scope: Scope) -> Result<PathBuf, ConfigError> {
+        self.paths.file(scope).ok_or(match scope {
+            Scope::Repo => ConfigError::NoRepository,
+            _ => ConfigError::NoHome,
+        })
+    }
+
+    fn write_value(&mut self, scope: Scope, key: &str, value: Item) -> Result<(), ConfigError> {
+        let key = normalize_key(key)?;
+        let path = self.scope_file(scope)?;
+        let mut doc = read_document(&path)?;
+        let segments: Vec<&str> = split_key(&key);
+        let (name, tables) = segments
+            .split_last()
+            .expect("keys have a section and a name");
+        let mut table = doc.as_table_mut();
+        for segment in tables {
+            let item = table.entry(segment).or_insert_with(|| {
+                let mut t = Table::new();
+                t.set_implicit(true);
+                Item::Table(t)
+            });
+            table = item.as_table_mut().ok_or_else(|| ConfigError::Parse {
+                path: path.clone(),
+                message: format!("{} is not a table", segment),
+            })?;
+        }
+        table.insert(name, value);
+        write_document(&path, &doc)?;
+        self.reload(scope, path)
+    }
+
+    fn reload(&mut self, scope: Scope, path: PathBuf) -> Result<(), ConfigError> {
+        let layer = read_toml(scope, path)?;
+        let loaded = self
+            .layers
+            .iter_mut()
+            .find(|loaded| loaded.path == layer.path)
+            .expect("every scope with a file is loaded");
+        *loaded = layer;
+        Ok(())
+    }
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(text) => text.parse::<DocumentMut>().map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DocumentMut::new()),
+        Err(source) => Err(ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+fn write_document(path: &Path, doc: &DocumentMut) -> Result<(), ConfigError> {
+    let write_error = |source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(write_error)?;
+    }
+    fs::write(path, doc.to_string()).map_err(write_error)
+}
+
+/// `key` with its section and name in lower case as git compares them, the subsection being
+/// case sensitive.
+fn normalize_key(key: &str) -> Result<String, ConfigError> {
+    let invalid = || ConfigError::InvalidKey(key.to_owned());
+    let (section, rest) = key.split_once('.').ok_or_else(invalid)?;
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((subsection, name)) => (Some(subsection), name),
+        None => (None, rest),
+    };
+    let is_word =
+        |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+    if !is_word(section) || !is_word(name) || subsection.is_some_and(str::is_empty) {
+        return Err(invalid());
+    }
+    Ok(match subsection {
+        Some(subsection) => format!(
+            "{}.{}.{}",
+            section.to_ascii_lowercase(),
+            subsection,
+            name.to_ascii_lowercase()
+        ),
+        None => format!(
+            "{}.{}",
+            section.to_ascii_lowercase(),
+            name.to_ascii_lowercase()
+        ),
+    })
+}
+
+/// The section, the subsection if any, and the name of a normalized key.
+fn split_key(key: &str) -> Vec<&str> {
+    let (section, rest) = key.split_once('.').unwrap();
+    match rest.rsplit_once('.') {
+        Some((subsection, name)) => vec![section, subsection, name],
+        None => vec![section, rest],
+    }
+}
+
+fn read_toml(scope: Scope, path: PathBuf) -> Result<Layer, ConfigError> {
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(source) => return Err(ConfigError::Read { path, source }),
+    };
+    let table: toml::Table = toml::from_str(&text).map_err(|e| ConfigError::Parse {
+        path: path.clone(),
+        message: e.to_string(),
+    })?;
+    let mut entries = Vec::new();
+    flatten("", &table, &mut entries);
+    let entries = entries
+        .into_iter()
+        .filter_map(|(key, value)| Some((normalize_key(&key).ok()?, value)))
+        .collect();
+    Ok(Layer {
+        scope,
+        path,
+        entries,
+    })
+}
+
+fn flatten(prefix: &str, table: &toml::Table, entries: &mut Vec<(String, String)>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, entries),
+            toml::Value::String(s) => entries.push((key, s.clone())),
+            // a list holds the values of a key taking several
+            toml::Value::Array(values) => {
+                for value in values {
+                    let value = match value {
+                        toml::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    entries.push((key.clone(), value));
+                }
+            }
+            other => entries.push((key, other.to_string())),
+        }
+    }
+}
+
+fn read_git_config(path: PathBuf) -> Result<Layer, ConfigError> {
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        // a worktree or submodule has a .git file instead, which is left alone
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            String::new()
+        }
+        Err(source) => return Err(ConfigError::Read { path, source }),
+    };
+    let entries = parse_git_config(&text).map_err(|message| ConfigError::Parse {
+        path: path.clone(),
+        message,
+    })?;
+    Ok(Layer {
+        scope: Scope::Repo,
+        path,
+        entries,
+    })
+}
+
+/// The values of a git config file, as `<section>[.<subsection>].<name>` keys. Includes are not
+/// followed.
+fn parse_git_config(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    let mut section: Option<String> = None;
+    let mut lines = text.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        if let Some(header) = line.strip_prefix('[') {
+            let end = header.rfind(']').ok_or_else(|| error("unclosed section"))?;
+            let header = header[..end].trim();
+            section = Some(match header.split_once(char::is_whitespace) {
+                // [section "subsection"]
+                Some((name, subsection)) => {
+                    let subsection = subsection
+                        .trim()
+                        .strip_prefix('"')
+                        .and_then(|s| s.strip_suffix('"'))
+                        .ok_or_else(|| error("invalid subsection"))?
+                        .replace("\\\"", "\"")
+                        .replace("\\\\", "\\");
+                    format!("{}.{}", name.to_ascii_lowercase(), subsection)
+                }
+                // [section] or the deprecated [section.subsection]
+                None => match header.split_once('.') {
+                    Some((name, subsection)) => {
+                        format!(
+                            "{}.{}",
+                            name.to_ascii_lowercase(),
+                            subsection.to_ascii_lowercase()
+                        )
+                    }
+                    None => header.to_ascii_lowercase(),
+                },
+            });
+            continue;
+        }
+        let section = section
+            .as_ref()
+            .ok_or_else(|| error("value outside of a section"))?;
+        let (name, raw) = match line.split_once('=') {
+            Some((name, raw)) => (name.trim(), raw.trim().to_owned()),
+            // a name alone is true
+            None => (line, "true".to_owned()),
+        };
+        let mut raw = raw;
+        while raw.ends_with('\\') && !raw.ends_with("\\\\") {
+            raw.pop();
+            match lines.next() {
+                Some((_, next)) => raw.push_str(next.trim()),
+                None => break,
+            }
+        }
+        entries.push((
+            format!("{}.{}", section, name.to_ascii_lowercase()),
+            git_value(&raw),
+        ));
+    }
+    Ok(entries)
+}
+
+/// The value of a git config line, without its quotes, escapes and trailing comment.
+fn git_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('b') => {
+                    value.pop();
+                }
+                Some(other) => value.push(other),
+                None => {}
+            },
+            '#' | ';' if !quoted => break,
+            c => value.push(c),
+        }
+    }
+    if quoted {
+        value
+    } else {
+        value.trim_end().to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{normalize_key, parse_git_config, Config, ConfigPaths, Scope};
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(
+            normalize_key("Remote.Origin.URL").unwrap(),
+            "remote.Origin.url"
+        );
+        assert_eq!(
+            normalize_key("credential.token").unwrap(),
+            "credential.token"
+        );
+        assert_eq!(
+            normalize_key("remote.my.mirror.url").unwrap(),
+            "remote.my.mirror.url"
+        );
+        assert!(normalize_key("token").is_err());
+        assert!(normalize_key("remote..url").is_err());
+        assert!(normalize_key("remote.origin.").is_err());
+    }
+
+    #[test]
+    fn test_parse_git_config() {
+        let text = r#"
+[core]
+	repositoryformatversion = 0
+	bare
+[remote "origin"]
+	url = https://mega.example.com/third-party/repo.git ; the server
+	fetch = +refs/heads/*:refs/remotes/origin/*
+[branch.main]
+	remote = origin
+[user]
+	name = "Jane \"JD\" Doe"
+"#;
+        let entries = parse_git_config(text).unwrap();
+        let get = |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("core.repositoryformatversion"), Some("0"));
+        assert_eq!(get("core.bare"), Some("true"));
+        assert_eq!(
+            get("remote.origin.url"),
+            Some("https://mega.example.com/third-party/repo.git")
+        );
+        assert_eq!(get("branch.main.remote"), Some("origin"));
+        assert_eq!(get("user.name"), Some("Jane \"JD\" Doe"));
+        assert!(parse_git_config("url = x").is_err());
+    }
+
+    #[test]
+    fn test_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::write(
+            repo.join(".git").join("config"),
+            "[remote \"origin\"]\n\turl = http://git.example.com/repo.git\n",
+        )
+        .unwrap();
+        let system = dir.path().join("system.toml");
+        fs::write(
+            &system,
+            "# set up by the administrators\n[credential]\nusername = \"ci\"\n",
+        )
+        .unwrap();
+        let paths = ConfigPaths {
+            system: system.clone(),
+            user: Some(dir.path().join("user").join("config.toml")),
+            repo: Some(repo.clone()),
+        };
+        let mut config = Config::load_from(paths.clone()).unwrap();
+        assert_eq!(config.get("credential.username"), Some("ci"));
+        assert_eq!(
+            config.get("remote.origin.url"),
+            Some("http://git.example.com/repo.git")
+        );
+
+        config
+            .set(Scope::User, "credential.username", "jane")
+            .unwrap();
+        config
+            .set(Scope::Repo, "remote.origin.url", "http://mega.example.com")
+            .unwrap();
+        config.set(Scope::System, "credential.token", "t").unwrap();
+        assert_eq!(config.get("credential.username"), Some("jane"));
+        assert_eq!(
+            config.get("remote.origin.url"),
+            Some("http://mega.example.com")
+        );
+
+        // the files read back the same, the comments kept and .git/config left alone
+        let config = Config::load_from(paths).unwrap();
+        assert_eq!(config.get("Credential.Username"), Some("jane"));
+        assert_eq!(
+            config.get("remote.origin.url"),
+            Some("http://mega.example.com")
+        );
+        assert!(fs::read_to_string(&system)
+            .unwrap()
+            .starts_with("# set up by the administrators\n"));
+        assert_eq!(
+            fs::read_to_string(repo.join(".mega").join("config.toml")).unwrap(),
+            "[remote.origin]\nurl = \"http://mega.example.com\"\n"
+        );
+        let scopes: Vec<(Scope, String)> = config
+            .list(None)
+            .into_iter()
+            .map(|entry| (entry.scope, entry.key))
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![
+                (Scope::System, "credential.token".to_owned()),
+                (Scope::System, "credential.username".to_owned()),
+                (Scope::User, "credential.username".to_owned()),
+                (Scope::Repo, "remote.origin.url".to_owned()),
+                (Scope::Repo, "remote.origin.url".to_owned()),
+            ]
+        );
+        assert_eq!(config.list(Some(Scope::User)).len(), 1);
+    }
+}
@@ -0,0 +1,91 @@
+use std::io;
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use git::protocol::refspec::RefSpecError;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("invalid url {0}")]
+    InvalidUrl(String),
+
+    /// The server could not be reached, or its response could not be read.
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The server refused the call, with the code and message it gave. The code is empty when
+    /// the server gave none.
+    #[error("{status}: {message}")]
+    Api {
+        status: StatusCode,
+        code: String,
+        message: String,
+    },
+
+    /// The server answered with something other than what the protocol expects.
+    #[error("invalid response: {0}")]
+    Protocol(String),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+impl ClientError {
+    /// Status of the response refusing the call, if the server answered.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Api { status, .. } => Some(*status),
+            ClientError::Http(e) => e.status(),
+            ClientError::InvalidUrl(_) | ClientError::Protocol(_) | ClientError::Config(_) => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status() == Some(StatusCode::NOT_FOUND)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unable to read {path}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("unable to write {path}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("invalid config file {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("invalid key {0}, expected <section>.<name> or <section>.<subsection>.<name>")]
+    InvalidKey(String),
+
+    #[error("invalid scope {0}, expected system, user or repo")]
+    InvalidScope(String),
+
+    #[error("{0} is not set")]
+    Missing(String),
+
+    #[error("invalid remote name {0}")]
+    InvalidRemote(String),
+
+    #[error("remote {0} already exists")]
+    RemoteExists(String),
+
+    #[error("invalid ref name {0}")]
+    InvalidRef(String),
+
+    #[error(transparent)]
+    RefSpec(#[from] RefSpecError),
+
+    /// The setting is in a file the client only reads, like `.git/config`.
+    #[error("{0} is only read, change it with git")]
+    ReadOnly(PathBuf),
+
+    #[error("not in a repository, which the repo scope is stored in")]
+    NoRepository,
+
+    #[error("no home directory, which the user scope is stored in")]
+    NoHome,
+}
@@ -0,0 +1,35 @@
+use reqwest::Method;
+
+use gateway::model::issue::{Issue, IssueDetail, IssueQuery, IssueUpdate, NewIssue};
+
+use crate::{ClientError, MegaClient};
+
+/// Issues.
+impl MegaClient {
+    pub async fn list_issues(&self, query: &IssueQuery) -> Result<Vec<Issue>, ClientError> {
+        self.get_all(&["issues"], query).await
+    }
+
+    pub async fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue, ClientError> {
+        self.call(Method::POST, &["issues"], Some(new_issue)).await
+    }
+
+    pub async fn get_issue(&self, id: i64) -> Result<IssueDetail, ClientError> {
+        self.get(&["issues", &id.to_string()], &()).await
+    }
+
+    pub async fn update_issue(&self, id: i64, update: &IssueUpdate) -> Result<Issue, ClientError> {
+        self.call(Method::PATCH, &["issues", &id.to_string()], Some(update))
+            .await
+    }
+
+    pub async fn close_issue(&self, id: i64) -> Result<Issue, ClientError> {
+        self.call::<(), _>(Method::POST, &["issues", &id.to_string(), "close"], None)
+            .await
+    }
+
+    pub async fn reopen_issue(&self, id: i64) -> Result<Issue, ClientError> {
+        self.call::<(), _>(Method::POST, &["issues", &id.to_string(), "reopen"], None)
+            .await
+    }
+}
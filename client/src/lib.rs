@@ -0,0 +1,42 @@
+//!
+//! Typed client for the HTTP API of the mega server.
+//!
+//! [`MegaClient`] wraps the calls under `/api/v1` with the request and response models of the
+//! gateway itself, re-exported as [`model`], so the client changes together with the API it
+//! calls. Object ids are taken as [`SHA1`] hashes of venus.
+//!
+//! ```no_run
+//! use mega_client::{model::mr::MergeRequestQuery, Auth, MegaClient};
+//!
+//! # async fn run() -> Result<(), mega_client::ClientError> {
+//! let client = MegaClient::new("http://localhost:8000")?.with_auth(Auth::Bearer("token".into()));
+//! let open = MergeRequestQuery {
+//!     status: Some("open".to_owned()),
+//!     ..Default::default()
+//! };
+//! for mr in client.list_mrs(&open).await? {
+//!     println!("{} {}", mr.id, mr.title);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MegaClient::from_config`] finds the server and the credentials in the layered settings of
+//! [`config`], which `mega config` reads and writes.
+//!
+//! Calls that are safe to repeat are retried on connection errors and on responses telling the
+//! client to come back later, see [`RetryPolicy`].
+//!
+mod admin;
+mod client;
+pub mod config;
+mod error;
+mod issue;
+mod mr;
+pub mod remote;
+mod repo;
+
+pub use client::{Auth, MegaClient, RetryPolicy};
+pub use error::{ClientError, ConfigError};
+pub use gateway::model;
+pub use venus::hash::SHA1;
@@ -0,0 +1,45 @@
+use reqwest::Method;
+
+use gateway::model::mr::{
+    MergeOptions, MergeRequest, MergeRequestDetail, MergeRequestQuery, NewMergeRequest,
+};
+
+use crate::{ClientError, MegaClient};
+
+/// Merge requests.
+impl MegaClient {
+    pub async fn list_mrs(
+        &self,
+        query: &MergeRequestQuery,
+    ) -> Result<Vec<MergeRequest>, ClientError> {
+        self.get_all(&["mr"], query).await
+    }
+
+    pub async fn create_mr(&self, new_mr: &NewMergeRequest) -> Result<MergeRequest, ClientError> {
+        self.call(Method::POST, &["mr"], Some(new_mr)).await
+    }
+
+    pub async fn get_mr(&self, id: i64) -> Result<MergeRequestDetail, ClientError> {
+        self.get(&["mr", &id.to_string()], &()).await
+    }
+
+    pub async fn close_mr(&self, id: i64) -> Result<MergeRequest, ClientError> {
+        self.call::<(), _>(Method::POST, &["mr", &id.to_string(), "close"], None)
+            .await
+    }
+
+    pub async fn reopen_mr(&self, id: i64) -> Result<MergeRequest, ClientError> {
+        self.call::<(), _>(Method::POST, &["mr", &id.to_string(), "reopen"], None)
+            .await
+    }
+
+    /// Merge with `options`, or with a merge commit and the default message without them.
+    pub async fn merge_mr(
+        &self,
+        id: i64,
+        options: Option<&MergeOptions>,
+    ) -> Result<MergeRequest, ClientError> {
+        self.call(Method::POST, &["mr", &id.to_string(), "merge"], options)
+            .await
+    }
+}
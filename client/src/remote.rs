@@ -0,0 +1,541 @@
+//!
+//! Named remotes of a repository, and fetching their refs.
+//!
+//! A remote is a `[remote.<name>]` section of the settings, see [`config`](crate::config):
+//!
+//! ```toml
+//! [remote.sandbox]
+//! url = "https://mega.example.com/people/jane/project"
+//! fetch = ["+refs/heads/*:refs/remotes/sandbox/*"]
+//! prune = "true"
+//! ```
+//!
+//! `url` is the HTTP url of a repository on a mega server, `fetch` the refspecs mapping its refs
+//! to local ones, `+refs/heads/*:refs/remotes/<name>/*` unless set, negative ones like
+//! `^refs/heads/tmp/*` leaving refs out, and `prune` whether a fetch
+//! deletes the local refs of the refs gone from the remote. Remotes are added to the repo scope;
+//! those git recorded in `.git/config` are used as well, but are left to git to change.
+//!
+//! The client keeps no objects: a fetch records where the refs of the remote point, as files
+//! under `.mega/refs` of the repository like the loose refs of git, so the branches of several
+//! remotes, say a mega node and a sandbox namespace on another one, can be compared. Without the
+//! commits it can't tell a fast-forward either, so refspecs update their refs whether or not
+//! they are forced.
+//!
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use reqwest::Url;
+
+use git::protocol::refspec::RefSpecs;
+
+use crate::client::{check, Auth};
+use crate::config::{Config, Scope};
+use crate::error::{ClientError, ConfigError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+    pub fetch: Vec<String>,
+    pub prune: bool,
+}
+
+/// A local ref a fetch moved, created or deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub local: String,
+    /// Where the ref pointed before, `None` when it is new
+    pub old: Option<String>,
+    /// Where the ref points now, `None` when it was pruned
+    pub new: Option<String>,
+}
+
+impl Remote {
+    /// The refspec of a remote without `fetch`: its branches, as `refs/remotes/<name>/*`.
+    pub fn default_fetch(name: &str) -> String {
+        format!("+refs/heads/*:refs/remotes/{}/*", name)
+    }
+
+    /// The remote `name` of `config`.
+    pub fn load(config: &Config, name: &str) -> Result<Remote, ConfigError> {
+        let url_key = format!("remote.{}.url", name);
+        let url = config
+            .get(&url_key)
+            .ok_or(ConfigError::Missing(url_key))?
+            .to_owned();
+        let mut fetch: Vec<String> = Vec::new();
+        for spec in config.get_all(&format!("remote.{}.fetch", name)) {
+            if !fetch.iter().any(|s| s == spec) {
+                fetch.push(spec.to_owned());
+            }
+        }
+        if fetch.is_empty() {
+            fetch.push(Remote::default_fetch(name));
+        }
+        let prune = config
+            .get(&format!("remote.{}.prune", name))
+            .or_else(|| config.get("fetch.prune"))
+            .is_some_and(|prune| prune == "true");
+        Ok(Remote {
+            name: name.to_owned(),
+            url,
+            fetch,
+            prune,
+        })
+    }
+
+    /// Every remote of `config` with a url, by name.
+    pub fn list(config: &Config) -> Vec<Remote> {
+        let mut names: Vec<String> = Vec::new();
+        for entry in config.list(None) {
+            let Some(name) = entry
+                .key
+                .strip_prefix("remote.")
+                .and_then(|rest| rest.strip_suffix(".url"))
+            else {
+                continue;
+            };
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_owned());
+            }
+        }
+        names.sort();
+        names
+            .iter()
+            .filter_map(|name| Remote::load(config, name).ok())
+            .collect()
+    }
+
+    /// Add the remote `name` of the repository at `url`, fetching its branches.
+    pub fn add(config: &mut Config, name: &str, url: &str) -> Result<Remote, ConfigError> {
+        check_name(name)?;
+        if Remote::load(config, name).is_ok() {
+            return Err(ConfigError::RemoteExists(name.to_owned()));
+        }
+        config.set(Scope::Repo, &format!("remote.{}.url", name), url)?;
+        config.set_all(
+            Scope::Repo,
+            &format!("remote.{}.fetch", name),
+            &[Remote::default_fetch(name)],
+        )?;
+        Remote::load(config, name)
+    }
+
+    /// Remove the remote `name` from every scope, and the refs fetched from it.
+    pub fn remove(config: &mut Config, name: &str) -> Result<(), ConfigError> {
+        let remote = Remote::load(config, name)?;
+        check_writable(config, name)?;
+        let section = format!("remote.{}", name);
+        for scope in [Scope::Repo, Scope::User, Scope::System] {
+            if config.paths().file(scope).is_some() {
+                config.remove_section(scope, &section)?;
+            }
+        }
+        let specs = RefSpecs::fetch(&remote.fetch)?;
+        let store = RefStore::open(config)?;
+        for local in store.read()?.into_keys() {
+            if specs.reverse(&local).is_some() {
+                store.write(&local, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rename the remote `old` to `new`, moving the refs fetched from it along.
+    pub fn rename(config: &mut Config, old: &str, new: &str) -> Result<Remote, ConfigError> {
+        check_name(new)?;
+        let remote = Remote::load(config, old)?;
+        if Remote::load(config, new).is_ok() {
+            return Err(ConfigError::RemoteExists(new.to_owned()));
+        }
+        check_writable(config, old)?;
+        let old_prefix = format!("refs/remotes/{}/", old);
+        let new_prefix = format!("refs/remotes/{}/", new);
+        let fetch: Vec<String> = remote
+            .fetch
+            .iter()
+            .map(|spec| match spec.split_once(':') {
+                Some((src, dst)) => {
+                    format!("{}:{}", src, dst.replacen(&old_prefix, &new_prefix, 1))
+                }
+                None => spec.clone(),
+            })
+            .collect();
+
+        let store = RefStore::open(config)?;
+        let refs = store.read()?;
+        config.set(Scope::Repo, &format!("remote.{}.url", new), &remote.url)?;
+        config.set_all(Scope::Repo, &format!("remote.{}.fetch", new), &fetch)?;
+        if remote.prune {
+            config.set(Scope::Repo, &format!("remote.{}.prune", new), "true")?;
+        }
+        let section = format!("remote.{}", old);
+        for scope in [Scope::Repo, Scope::User, Scope::System] {
+            if config.paths().file(scope).is_some() {
+                config.remove_section(scope, &section)?;
+            }
+        }
+        for (local, id) in refs {
+            if let Some(rest) = local.strip_prefix(&old_prefix) {
+                store.write(&format!("{}{}", new_prefix, rest), Some(&id))?;
+                store.write(&local, None)?;
+            }
+        }
+        Remote::load(config, new)
+    }
+
+    /// Record where the refs of the remote point, deleting the local refs of the refs gone from
+    /// it when `prune` or the `prune` setting of the remote is set.
+    pub async fn fetch(&self, config: &Config, prune: bool) -> Result<Vec<RefUpdate>, ClientError> {
+        let specs = RefSpecs::fetch(&self.fetch).map_err(ConfigError::from)?;
+        let advertised: BTreeMap<String, String> =
+            self.ls_remote(config).await?.into_iter().collect();
+        let store = RefStore::open(config)?;
+        let local = store.read()?;
+        let wanted: BTreeMap<String, String> = specs
+            .map_all(advertised.keys().map(String::as_str))
+            .map_err(ConfigError::from)?
+            .into_iter()
+            .map(|mapping| {
+                let id = advertised[&mapping.src].clone();
+                (mapping.dst, id)
+            })
+            .collect();
+
+        let mut updates = Vec::new();
+        for (dst, id) in &wanted {
+            let old = local.get(dst);
+            if old != Some(id) {
+                store.write(dst, Some(id))?;
+                updates.push(RefUpdate {
+                    local: dst.clone(),
+                    old: old.cloned(),
+                    new: Some(id.clone()),
+                });
+            }
+        }
+        if prune || self.prune {
+            for (name, id) in &local {
+                if !wanted.contains_key(name) && specs.reverse(name).is_some() {
+                    store.write(name, None)?;
+                    updates.push(RefUpdate {
+                        local: name.clone(),
+                        old: Some(id.clone()),
+                        new: None,
+                    });
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    /// The refs the remote advertises, with the commits they point to.
+    pub async fn ls_remote(&self, config: &Config) -> Result<Vec<(String, String)>, ClientError> {
+        let mut url = Url::parse(&self.url)
+            .map_err(|e| ClientError::InvalidUrl(format!("{}: {}", self.url, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ClientError::InvalidUrl(format!(
+                "{}: only HTTP remotes can be fetched",
+                self.url
+            )));
+        }
+        url.path_segments_mut()
+            .map_err(|_| ClientError::InvalidUrl(self.url.clone()))?
+            .pop_if_empty()
+            .extend(["info", "refs"]);
+        url.set_query(Some("service=git-upload-pack"));
+        let mut request = reqwest::Client::new().get(url);
+        if let Some(auth) = Auth::from_config(config) {
+            request = auth.apply(request);
+        }
+        let body = check(request.send().await?).await?.bytes().await?;
+        parse_advertisement(&body).map_err(ClientError::Protocol)
+    }
+}
+
+fn check_name(name: &str) -> Result<(), ConfigError> {
+    let valid = !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+    if valid {
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidRemote(name.to_owned()))
+    }
+}
+
+/// Fail when the remote `name` is recorded in a file which is only read.
+fn check_writable(config: &Config, name: &str) -> Result<(), ConfigError> {
+    let prefix = format!("remote.{}.", name);
+    let writable: Vec<PathBuf> = [Scope::System, Scope::User, Scope::Repo]
+        .into_iter()
+        .filter_map(|scope| config.paths().file(scope))
+        .collect();
+    match config
+        .list(None)
+        .into_iter()
+        .find(|entry| entry.key.starts_with(&prefix) && !writable.contains(&entry.path))
+    {
+        Some(entry) => Err(ConfigError::ReadOnly(entry.path)),
+        None => Ok(()),
+    }
+}
+
+/// The refs a fetch recorded, as files under `.mega` of the repository holding their ids.
+struct RefStore {
+    root: PathBuf,
+}
+
+impl RefStore {
+    fn open(config: &Config) -> Result<RefStore, ConfigError> {
+        let repo = config
+            .paths()
+            .repo
+            .as_ref()
+            .ok_or(ConfigError::NoRepository)?;
+        Ok(RefStore {
+            root: repo.join(".mega"),
+        })
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, String>, ConfigError> {
+        let mut refs = BTreeMap::new();
+        self.read_dir(&self.root.join("refs"), "refs", &mut refs)?;
+        Ok(refs)
+    }
+
+    fn read_dir(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        refs: &mut BTreeMap<String, String>,
+    ) -> Result<(), ConfigError> {
+        let read_error = |source| ConfigError::Read {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(read_error(e)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(read_error)?;
+            let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type().map_err(read_error)?.is_dir() {
+                self.read_dir(&entry.path(), &name, refs)?;
+            } else {
+                let id = fs::read_to_string(entry.path()).map_err(read_error)?;
+                refs.insert(name, id.trim().to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Point the ref `name` at `id`, or delete it when `id` is `None`.
+    fn write(&self, name: &str, id: Option<&str>) -> Result<(), ConfigError> {
+        if name
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(ConfigError::InvalidRef(name.to_owned()));
+        }
+        let path = self.root.join(name);
+        let write_error = |source| ConfigError::Write {
+            path: path.clone(),
+            source,
+        };
+        match id {
+            Some(id) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(write_error)?;
+                }
+                fs::write(&path, format!("{}\n", id)).map_err(write_error)
+            }
+            None => match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(write_error(e)),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+/// The refs of the pkt-lines an `info/refs` request of git-upload-pack answers with, without
+/// `HEAD` and the peeled tags.
+fn parse_advertisement(mut data: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let mut refs = Vec::new();
+    while !data.is_empty() {
+        let len = data
+            .get(..4)
+            .and_then(|len| std::str::from_utf8(len).ok())
+            .and_then(|len| usize::from_str_radix(len, 16).ok())
+            .ok_or("invalid pkt-line length")?;
+        if len == 0 {
+            data = &data[4..];
+            continue;
+        }
+        if len < 4 || len > data.len() {
+            return Err(format!("invalid pkt-line length {}", len));
+        }
+        let line = String::from_utf8_lossy(&data[4..len]);
+        data = &data[len..];
+        let line = line.trim_end_matches('\n');
+        if line.starts_with('#') {
+            continue;
+        }
+        // the first ref carries the capabilities
+        let line = line.split('\0').next().unwrap_or_default();
+        let (id, name) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("invalid ref line {}", line))?;
+        if id.len() != 40 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid object id {}", id));
+        }
+        if name == "HEAD" || name.ends_with("^{}") {
+            continue;
+        }
+        refs.push((name.to_owned(), id.to_owned()));
+    }
+    Ok(refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::routing::get;
+    use axum::Router;
+
+    use super::{parse_advertisement, RefUpdate, Remote};
+    use crate::config::{Config, ConfigPaths, Scope};
+
+    fn pkt(line: &str) -> String {
+        format!("{:04x}{}", line.len() + 4, line)
+    }
+
+    fn advertisement(refs: &[(&str, &str)]) -> String {
+        let mut body = pkt("# service=git-upload-pack\n") + "0000";
+        for (i, (name, id)) in refs.iter().enumerate() {
+            let caps = if i == 0 {
+                "\0side-band-64k ofs-delta"
+            } else {
+                ""
+            };
+            body += &pkt(&format!("{} {}{}\n", id, name, caps));
+        }
+        body + "0000"
+    }
+
+    #[test]
+    fn test_parse_advertisement() {
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        let body = advertisement(&[
+            ("HEAD", &a),
+            ("refs/heads/main", &a),
+            ("refs/tags/v1", &b),
+            ("refs/tags/v1^{}", &a),
+        ]);
+        assert_eq!(
+            parse_advertisement(body.as_bytes()).unwrap(),
+            vec![
+                ("refs/heads/main".to_owned(), a.clone()),
+                ("refs/tags/v1".to_owned(), b)
+            ]
+        );
+        assert!(parse_advertisement(b"zzzz").is_err());
+        assert!(parse_advertisement(pkt("nope refs/heads/main\n").as_bytes()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remotes() {
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        let served = advertisement(&[("refs/heads/main", &a), ("refs/heads/dev", &b)]);
+        let app = Router::new().route(
+            "/project/info/refs",
+            get(move || {
+                let served = served.clone();
+                async move { served }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(repo.join(".mega")).unwrap();
+        let paths = ConfigPaths {
+            system: dir.path().join("system.toml"),
+            user: None,
+            repo: Some(repo.clone()),
+        };
+        let mut config = Config::load_from(paths).unwrap();
+        let url = format!("http://{}/project", addr);
+        let origin = Remote::add(&mut config, "origin", &url).unwrap();
+        assert!(Remote::add(&mut config, "origin", &url).is_err());
+        assert!(Remote::add(&mut config, "-x", &url).is_err());
+
+        let updates = origin.fetch(&config, false).await.unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(
+            fs::read_to_string(repo.join(".mega/refs/remotes/origin/main")).unwrap(),
+            format!("{}\n", a)
+        );
+        assert!(origin.fetch(&config, false).await.unwrap().is_empty());
+
+        // a ref gone from the remote is only deleted with prune
+        fs::write(
+            repo.join(".mega/refs/remotes/origin/old"),
+            format!("{}\n", a),
+        )
+        .unwrap();
+        assert!(origin.fetch(&config, false).await.unwrap().is_empty());
+        assert_eq!(
+            origin.fetch(&config, true).await.unwrap(),
+            vec![RefUpdate {
+                local: "refs/remotes/origin/old".to_owned(),
+                old: Some(a.clone()),
+                new: None,
+            }]
+        );
+
+        let sandbox = Remote::rename(&mut config, "origin", "sandbox").unwrap();
+        assert_eq!(sandbox.fetch, vec!["+refs/heads/*:refs/remotes/sandbox/*"]);
+        assert!(repo.join(".mega/refs/remotes/sandbox/dev").exists());
+        assert!(!repo.join(".mega/refs/remotes/origin/dev").exists());
+        assert_eq!(
+            Remote::list(&config)
+                .into_iter()
+                .map(|r| r.name)
+                .collect::<Vec<_>>(),
+            vec!["sandbox"]
+        );
+
+        // negative refspecs leave refs out
+        config
+            .set_all(
+                Scope::Repo,
+                "remote.sandbox.fetch",
+                &[
+                    "+refs/heads/*:refs/remotes/sandbox/*".to_owned(),
+                    "^refs/heads/dev".to_owned(),
+                ],
+            )
+            .unwrap();
+        fs::remove_file(repo.join(".mega/refs/remotes/sandbox/dev")).unwrap();
+        let sandbox = Remote::load(&config, "sandbox").unwrap();
+        assert!(sandbox.fetch(&config, true).await.unwrap().is_empty());
+        assert!(!repo.join(".mega/refs/remotes/sandbox/dev").exists());
+
+        Remote::remove(&mut config, "sandbox").unwrap();
+        assert!(Remote::list(&config).is_empty());
+        assert!(!repo.join(".mega/refs/remotes/sandbox/dev").exists());
+    }
+}
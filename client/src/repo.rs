@@ -0,0 +1,91 @@
+use gateway::model::{
+    blame::BlameResult,
+    merge::{MergeCheck, MergeCheckQuery},
+    objects::{BlobObjects, CommitSummary, Directories, RefInfo},
+    page::{Page, PageQuery},
+    query::{BlameQuery, CommitLogQuery, DirectoryQuery, RefQuery},
+    ref_trigger::{RefAuditEntry, RefAuditQuery},
+    search::{CodeSearchHit, CodeSearchQuery, SearchHit, SearchQuery},
+};
+use venus::hash::SHA1;
+
+use crate::{ClientError, MegaClient};
+
+/// Browsing repositories and searching them.
+impl MegaClient {
+    /// Whether the server is up.
+    pub async fn status(&self) -> Result<String, ClientError> {
+        self.get(&["status"], &()).await
+    }
+
+    /// The entries of a directory, the root of `repo_path` unless `query` names a tree.
+    pub async fn tree(&self, query: &DirectoryQuery) -> Result<Directories, ClientError> {
+        self.get(&["tree"], query).await
+    }
+
+    pub async fn blob(&self, id: &SHA1) -> Result<BlobObjects, ClientError> {
+        self.get(&["blob"], &[("object_id", id.to_plain_str())])
+            .await
+    }
+
+    /// Raw content of the object `id` of the repository at `repo_path`.
+    pub async fn object(&self, repo_path: &str, id: &SHA1) -> Result<bytes::Bytes, ClientError> {
+        self.get_bytes(
+            &["object"],
+            &[
+                ("object_id", id.to_plain_str().as_str()),
+                ("repo_path", repo_path),
+            ],
+        )
+        .await
+    }
+
+    pub async fn blame(&self, query: &BlameQuery) -> Result<BlameResult, ClientError> {
+        self.get(&["blame"], query).await
+    }
+
+    /// Whether the source of `query` would merge cleanly into its target.
+    pub async fn merge_check(&self, query: &MergeCheckQuery) -> Result<MergeCheck, ClientError> {
+        self.get(&["merge-check"], query).await
+    }
+
+    /// The history of a branch, tag or commit, newest first.
+    pub async fn commits(
+        &self,
+        query: &CommitLogQuery,
+        page: &PageQuery,
+    ) -> Result<Page<CommitSummary>, ClientError> {
+        self.get_page(&["commits"], query, page).await
+    }
+
+    /// All the refs of a repository, by name.
+    pub async fn refs(&self, query: &RefQuery) -> Result<Vec<RefInfo>, ClientError> {
+        self.get_all(&["refs"], query).await
+    }
+
+    /// Updates of the refs of a repository, newest first.
+    pub async fn ref_audit(
+        &self,
+        query: &RefAuditQuery,
+    ) -> Result<Vec<RefAuditEntry>, ClientError> {
+        self.get(&["ref-audit"], query).await
+    }
+
+    /// A page of the hits of a search of code, commits, issues and merge requests together,
+    /// best first.
+    pub async fn search(
+        &self,
+        query: &SearchQuery,
+        page: &PageQuery,
+    ) -> Result<Page<SearchHit>, ClientError> {
+        self.get_page(&["search"], query, page).await
+    }
+
+    pub async fn search_code(
+        &self,
+        query: &CodeSearchQuery,
+        page: &PageQuery,
+    ) -> Result<Page<CodeSearchHit>, ClientError> {
+        self.get_page(&["search", "code"], query, page).await
+    }
+}
@@ -1,4 +1,7 @@
 pub mod errors;
 pub mod utils;
 pub mod enums;
+pub mod metrics;
+pub mod missing_objects;
 pub mod model;
+pub mod operation;
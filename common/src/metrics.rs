@@ -0,0 +1,244 @@
+//!
+//! Prometheus metrics of a mega server, recorded by the crates doing the work and served at
+//! `/metrics` in the OpenMetrics text format.
+//!
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+
+/// The transport of a git request, `http` or `ssh`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct TransportLabels {
+    pub transport: String,
+}
+
+impl TransportLabels {
+    pub fn new(transport: &str) -> Self {
+        TransportLabels {
+            transport: transport.to_owned(),
+        }
+    }
+}
+
+/// Whether a database query succeeded, `ok` or `error`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct QueryLabels {
+    pub status: String,
+}
+
+/// Where an object was found missing, like `upload-pack` or `blame`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ContextLabels {
+    pub context: String,
+}
+
+/// The transport of an upload-pack, and why it stopped: `cancelled` or `timed_out`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct CancelLabels {
+    pub transport: String,
+    pub reason: String,
+}
+
+pub struct Metrics {
+    registry: Registry,
+    pub pack_bytes_served: Family<TransportLabels, Counter>,
+    pub object_cache_hits: Counter,
+    pub object_cache_misses: Counter,
+    pub db_query_duration: Family<QueryLabels, Histogram>,
+    pub receive_pack_duration: Family<TransportLabels, Histogram>,
+    pub active_connections: Family<TransportLabels, Gauge>,
+    pub pushes: Family<TransportLabels, Counter>,
+    pub missing_objects: Family<ContextLabels, Counter>,
+    pub upload_pack_cancellations: Family<CancelLabels, Counter>,
+}
+
+/// 1 ms to about 16 s
+fn query_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.001, 2.0, 15))
+}
+
+/// 50 ms to about 7 min
+fn push_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.05, 2.0, 14))
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut registry = Registry::with_prefix("mega");
+        let pack_bytes_served = Family::<TransportLabels, Counter>::default();
+        registry.register(
+            "pack_bytes_served",
+            "Bytes of the packs sent to fetches and clones",
+            pack_bytes_served.clone(),
+        );
+        let object_cache_hits = Counter::default();
+        registry.register(
+            "object_cache_hits",
+            "Delta bases found in the object cache while decoding pushed packs",
+            object_cache_hits.clone(),
+        );
+        let object_cache_misses = Counter::default();
+        registry.register(
+            "object_cache_misses",
+            "Delta bases read again from the pack while decoding pushed packs",
+            object_cache_misses.clone(),
+        );
+        let db_query_duration =
+            Family::<QueryLabels, Histogram>::new_with_constructor(query_histogram as fn() -> _);
+        registry.register(
+            "db_query_duration_seconds",
+            "Time database queries took",
+            db_query_duration.clone(),
+        );
+        let receive_pack_duration =
+            Family::<TransportLabels, Histogram>::new_with_constructor(push_histogram as fn() -> _);
+        registry.register(
+            "receive_pack_duration_seconds",
+            "Time receive-pack took to process a push, from its first byte to its last ref",
+            receive_pack_duration.clone(),
+        );
+        let active_connections = Family::default();
+        registry.register(
+            "active_connections",
+            "HTTP requests being served and SSH sessions open",
+            active_connections.clone(),
+        );
+        let pushes = Family::default();
+        registry.register("pushes", "Pushes received", pushes.clone());
+        let missing_objects = Family::default();
+        registry.register(
+            "missing_objects",
+            "Objects a ref, commit or tree names found missing from the object store",
+            missing_objects.clone(),
+        );
+        let upload_pack_cancellations = Family::default();
+        registry.register(
+            "upload_pack_cancellations",
+            "Fetches and clones stopped before their pack was sent, by an admin or their deadline",
+            upload_pack_cancellations.clone(),
+        );
+        Metrics {
+            registry,
+            pack_bytes_served,
+            object_cache_hits,
+            object_cache_misses,
+            db_query_duration,
+            receive_pack_duration,
+            active_connections,
+            pushes,
+            missing_objects,
+            upload_pack_cancellations,
+        }
+    }
+
+    pub fn observe_query(&self, elapsed: Duration, failed: bool) {
+        let status = if failed { "error" } else { "ok" };
+        self.db_query_duration
+            .get_or_create(&QueryLabels {
+                status: status.to_owned(),
+            })
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_push(&self, transport: &str, elapsed: Duration) {
+        let labels = TransportLabels::new(transport);
+        self.pushes.get_or_create(&labels).inc();
+        self.receive_pack_duration
+            .get_or_create(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_missing_object(&self, context: &str) {
+        self.missing_objects
+            .get_or_create(&ContextLabels {
+                context: context.to_owned(),
+            })
+            .inc();
+    }
+
+    pub fn observe_cancelled_upload_pack(&self, transport: &str, reason: &str) {
+        self.upload_pack_cancellations
+            .get_or_create(&CancelLabels {
+                transport: transport.to_owned(),
+                reason: reason.to_owned(),
+            })
+            .inc();
+    }
+
+    /// Count a connection of `transport` as active until the returned guard is dropped.
+    pub fn connection(&self, transport: &str) -> ConnectionGuard {
+        let gauge = self
+            .active_connections
+            .get_or_create(&TransportLabels::new(transport))
+            .clone();
+        gauge.inc();
+        ConnectionGuard { gauge }
+    }
+
+    /// The metrics in the OpenMetrics text format.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        // writing to a String doesn't fail
+        let _ = encode(&mut out, &self.registry);
+        out
+    }
+}
+
+/// Keeps a connection counted in `mega_active_connections`.
+pub struct ConnectionGuard {
+    gauge: Gauge,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+/// The metrics of this process.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Metrics, TransportLabels};
+
+    #[test]
+    fn test_encode_metrics() {
+        let metrics = Metrics::new();
+        metrics
+            .pack_bytes_served
+            .get_or_create(&TransportLabels::new("http"))
+            .inc_by(1024);
+        metrics.observe_push("ssh", Duration::from_millis(120));
+        metrics.observe_missing_object("blame");
+        metrics.observe_cancelled_upload_pack("http", "timed_out");
+        {
+            let _connection = metrics.connection("http");
+            assert!(metrics
+                .encode()
+                .contains("mega_active_connections{transport=\"http\"} 1"));
+        }
+        let text = metrics.encode();
+        assert!(text.contains("mega_pack_bytes_served_total{transport=\"http\"} 1024"));
+        assert!(text.contains("mega_pushes_total{transport=\"ssh\"} 1"));
+        assert!(text.contains("mega_missing_objects_total{context=\"blame\"} 1"));
+        assert!(text.contains(
+            "mega_upload_pack_cancellations_total{transport=\"http\",reason=\"timed_out\"} 1"
+        ));
+        assert!(text.contains("mega_receive_pack_duration_seconds_count{transport=\"ssh\"} 1"));
+        assert!(text.contains("mega_active_connections{transport=\"http\"} 0"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,108 @@
+//!
+//! Objects found missing from the object store: a ref, commit or tree names them, no row holds
+//! them.
+//!
+//! Such a gap is left by a write that failed halfway, or a row lost since. The code serving a
+//! repository [`report`]s each gap it meets, then goes on as well as it can: upload-pack refuses
+//! the fetch rather than send a pack git would find broken, tree listings show the entry as
+//! missing, blame stops at the commits it can't read. A gap is logged the first time it is met
+//! and counted in `mega_missing_objects` each time, by where it was met, for alerts. Admins list
+//! the gaps and repair them from a mirror or another server, [`resolve`] forgetting the objects
+//! stored again.
+//!
+//! Like operations, gaps are only known to the process which met them.
+//!
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use crate::metrics::metrics;
+
+/// Most gaps kept, those met beyond are still logged and counted.
+const MAX_TRACKED: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingObject {
+    pub id: String,
+    /// Repository the gap was met in, when the code meeting it knew
+    pub repo_path: Option<String>,
+    /// Where the gap was first met, like `upload-pack` or `blame`
+    pub context: &'static str,
+    /// Times it was met
+    pub count: u64,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+}
+
+static REGISTRY: OnceLock<Mutex<HashMap<String, MissingObject>>> = OnceLock::new();
+
+fn registry() -> &'static Mutex<HashMap<String, MissingObject>> {
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Report the object `id` missing, met by `context` while serving the repository at `repo_path`.
+pub fn report(repo_path: Option<&str>, id: &str, context: &'static str) {
+    metrics().observe_missing_object(context);
+    let now = SystemTime::now();
+    let mut registry = registry().lock().unwrap();
+    if let Some(gap) = registry.get_mut(id) {
+        gap.count += 1;
+        gap.last_seen = now;
+        if gap.repo_path.is_none() {
+            gap.repo_path = repo_path.map(str::to_owned);
+        }
+        return;
+    }
+    tracing::error!(
+        "object {} of {} is missing from the object store, met by {}",
+        id,
+        repo_path.unwrap_or("an unknown repository"),
+        context
+    );
+    if registry.len() < MAX_TRACKED {
+        registry.insert(
+            id.to_owned(),
+            MissingObject {
+                id: id.to_owned(),
+                repo_path: repo_path.map(str::to_owned),
+                context,
+                count: 1,
+                first_seen: now,
+                last_seen: now,
+            },
+        );
+    }
+}
+
+/// The gaps met and not resolved yet, first met first.
+pub fn list() -> Vec<MissingObject> {
+    let mut gaps: Vec<MissingObject> = registry().lock().unwrap().values().cloned().collect();
+    gaps.sort_by(|a, b| a.first_seen.cmp(&b.first_seen).then(a.id.cmp(&b.id)));
+    gaps
+}
+
+/// Forget the gap of `id`, the object being stored again. `false` when it wasn't known.
+pub fn resolve(id: &str) -> bool {
+    registry().lock().unwrap().remove(id).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list, report, resolve};
+
+    #[test]
+    fn test_report() {
+        let id = "0123456789012345678901234567890123456789";
+        report(None, id, "blame");
+        report(Some("/project"), id, "upload-pack");
+        let gap = list().into_iter().find(|gap| gap.id == id).unwrap();
+        assert_eq!(gap.count, 2);
+        // the first place it was met, and the repository once known
+        assert_eq!(gap.context, "blame");
+        assert_eq!(gap.repo_path.as_deref(), Some("/project"));
+
+        assert!(resolve(id));
+        assert!(!resolve(id));
+        assert!(list().iter().all(|gap| gap.id != id));
+    }
+}
@@ -0,0 +1,340 @@
+//!
+//! Long operations of a server, which admins can follow and cancel.
+//!
+//! Generating a pack, importing a repository, syncing a mirror, repairing missing objects and
+//! splitting the history of a published directory each register an [`Operation`] for as long as
+//! they run, see [`start`].
+//! The work reports how far it got with [`Operation::set_total`] and [`Operation::advance`], and
+//! checks [`Operation::check`] between its steps: once [`cancel`]led it stops at the next one
+//! with [`Cancelled`], instead of running on until it is done. An operation given a deadline with
+//! [`Operation::set_deadline`] is cancelled the same way once it is past it.
+//!
+//! Operations only live in the process running them, and are gone once they end.
+//!
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Instant, SystemTime};
+
+use thiserror::Error;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    Pack,
+    Import,
+    MirrorSync,
+    HistorySplit,
+    Repair,
+}
+
+impl OperationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperationKind::Pack => "pack",
+            OperationKind::Import => "import",
+            OperationKind::MirrorSync => "mirror_sync",
+            OperationKind::HistorySplit => "history_split",
+            OperationKind::Repair => "repair",
+        }
+    }
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why an operation stopped before it was done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// By an admin, see [`cancel`]
+    Cancelled,
+    /// Past its deadline
+    TimedOut,
+}
+
+impl CancelReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CancelReason::Cancelled => "cancelled",
+            CancelReason::TimedOut => "timed_out",
+        }
+    }
+}
+
+impl fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CancelReason::Cancelled => f.write_str("was cancelled"),
+            CancelReason::TimedOut => f.write_str("timed out"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind} of {target} {reason}")]
+pub struct Cancelled {
+    pub kind: OperationKind,
+    pub target: String,
+    pub reason: CancelReason,
+}
+
+impl From<Cancelled> for io::Error {
+    fn from(err: Cancelled) -> Self {
+        io::Error::new(io::ErrorKind::Interrupted, err)
+    }
+}
+
+struct Inner {
+    id: i64,
+    kind: OperationKind,
+    target: String,
+    started_at: SystemTime,
+    stage: Mutex<&'static str>,
+    done: AtomicU64,
+    total: AtomicU64,
+    cancelled: watch::Sender<bool>,
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        registry().lock().unwrap().remove(&self.id);
+    }
+}
+
+/// A running operation. Clones share it, it ends once every clone is dropped.
+#[derive(Clone)]
+pub struct Operation {
+    inner: Arc<Inner>,
+}
+
+static NEXT_ID: AtomicI64 = AtomicI64::new(1);
+static REGISTRY: OnceLock<Mutex<BTreeMap<i64, Weak<Inner>>>> = OnceLock::new();
+
+fn registry() -> &'static Mutex<BTreeMap<i64, Weak<Inner>>> {
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register an operation of `kind` on `target`, a repository path usually.
+pub fn start(kind: OperationKind, target: impl Into<String>) -> Operation {
+    let inner = Arc::new(Inner {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        kind,
+        target: target.into(),
+        started_at: SystemTime::now(),
+        stage: Mutex::new("starting"),
+        done: AtomicU64::new(0),
+        total: AtomicU64::new(0),
+        cancelled: watch::channel(false).0,
+        deadline: Mutex::new(None),
+    });
+    registry()
+        .lock()
+        .unwrap()
+        .insert(inner.id, Arc::downgrade(&inner));
+    Operation { inner }
+}
+
+/// The operations running, oldest first.
+pub fn list() -> Vec<Operation> {
+    registry()
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(Weak::upgrade)
+        .map(|inner| Operation { inner })
+        .collect()
+}
+
+pub fn find(id: i64) -> Option<Operation> {
+    let inner = registry().lock().unwrap().get(&id)?.upgrade()?;
+    Some(Operation { inner })
+}
+
+/// Cancel the operation `id`, `false` when no such operation runs.
+pub fn cancel(id: i64) -> bool {
+    match find(id) {
+        Some(operation) => {
+            operation.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+impl Operation {
+    pub fn id(&self) -> i64 {
+        self.inner.id
+    }
+
+    pub fn kind(&self) -> OperationKind {
+        self.inner.kind
+    }
+
+    pub fn target(&self) -> &str {
+        &self.inner.target
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.inner.started_at
+    }
+
+    /// What the operation does at the moment, like `counting objects`.
+    pub fn stage(&self) -> &'static str {
+        *self.inner.stage.lock().unwrap()
+    }
+
+    /// Start a stage of `total` steps, with none done yet.
+    pub fn set_stage(&self, stage: &'static str, total: u64) {
+        *self.inner.stage.lock().unwrap() = stage;
+        self.inner.done.store(0, Ordering::Relaxed);
+        self.inner.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.inner.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, steps: u64) {
+        self.inner.done.fetch_add(steps, Ordering::Relaxed);
+    }
+
+    /// Steps of the current stage done, and how many there are.
+    pub fn progress(&self) -> (u64, u64) {
+        (
+            self.inner.done.load(Ordering::Relaxed),
+            self.inner.total.load(Ordering::Relaxed),
+        )
+    }
+
+    /// How much of the current stage is done, `None` while its size isn't known.
+    pub fn percent(&self) -> Option<u8> {
+        let (done, total) = self.progress();
+        (total > 0).then(|| (done.min(total) * 100 / total) as u8)
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.cancelled.borrow()
+    }
+
+    /// Cancel the operation once it runs past `deadline`.
+    pub fn set_deadline(&self, deadline: Instant) {
+        *self.inner.deadline.lock().unwrap() = Some(deadline);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        *self.inner.deadline.lock().unwrap()
+    }
+
+    fn stopped(&self, reason: CancelReason) -> Cancelled {
+        Cancelled {
+            kind: self.kind(),
+            target: self.target().to_owned(),
+            reason,
+        }
+    }
+
+    /// Fail once the operation is cancelled or past its deadline, to be called between its steps.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(self.stopped(CancelReason::Cancelled))
+        } else if self
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Err(self.stopped(CancelReason::TimedOut))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Complete once the operation is cancelled or past its deadline, for the steps which are
+    /// waited for as a whole, like a clone run by git.
+    pub async fn cancelled(&self) -> Cancelled {
+        let mut receiver = self.inner.cancelled.subscribe();
+        let cancelled = receiver.wait_for(|cancelled| *cancelled);
+        match self.deadline() {
+            Some(deadline) => tokio::select! {
+                _ = cancelled => self.stopped(CancelReason::Cancelled),
+                _ = tokio::time::sleep_until(deadline.into()) => self.stopped(CancelReason::TimedOut),
+            },
+            None => {
+                let _ = cancelled.await;
+                self.stopped(CancelReason::Cancelled)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Operation")
+            .field("id", &self.id())
+            .field("kind", &self.kind())
+            .field("target", &self.target())
+            .field("stage", &self.stage())
+            .field("progress", &self.progress())
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{cancel, find, list, start, CancelReason, OperationKind};
+
+    #[test]
+    fn test_operation() {
+        let operation = start(OperationKind::Import, "/third-party/a");
+        let id = operation.id();
+        assert!(list().iter().any(|o| o.id() == id));
+        assert_eq!(operation.percent(), None);
+
+        operation.set_stage("storing", 4);
+        operation.advance(1);
+        let found = find(id).unwrap();
+        assert_eq!(found.stage(), "storing");
+        assert_eq!(found.percent(), Some(25));
+        operation.advance(10);
+        assert_eq!(operation.percent(), Some(100));
+
+        assert!(operation.check().is_ok());
+        assert!(cancel(id));
+        assert!(operation.is_cancelled());
+        assert_eq!(
+            operation.check().unwrap_err().to_string(),
+            "import of /third-party/a was cancelled"
+        );
+
+        // gone once every clone is dropped
+        drop(operation);
+        assert!(find(id).is_some());
+        drop(found);
+        assert!(find(id).is_none());
+        assert!(!cancel(id));
+    }
+
+    #[test]
+    fn test_operation_deadline() {
+        let operation = start(OperationKind::Pack, "/project");
+        operation.set_deadline(Instant::now() + Duration::from_secs(60));
+        assert!(operation.check().is_ok());
+
+        operation.set_deadline(Instant::now());
+        let err = operation.check().unwrap_err();
+        assert_eq!(err.reason, CancelReason::TimedOut);
+        assert_eq!(err.to_string(), "pack of /project timed out");
+        assert!(!operation.is_cancelled());
+    }
+}
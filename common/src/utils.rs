@@ -3,12 +3,18 @@
 //!
 
 use idgenerator::IdInstance;
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use sea_orm::Value;
 
 pub const ZERO_ID: &str = match std::str::from_utf8(&[b'0'; 40]) {
     Ok(s) => s,
     Err(_) => panic!("can't get ZERO_ID"),
 };
 
+/// Refs the server keeps for itself, like the wiki of a directory. They are not advertised to
+/// git clients nor taken for the default branch.
+pub const HIDDEN_REF_PREFIX: &str = "refs/mega/";
+
 pub fn generate_id() -> i64 {
     let mut new_id: i64 = 0;
     let mut times = 100;
@@ -19,3 +25,27 @@ pub fn generate_id() -> i64 {
     }
     new_id
 }
+
+/// Matches `column` equal to `path` or naming a path below it.
+pub fn under_path(column: &str, path: &str) -> SimpleExpr {
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    Expr::cust_with_values(
+        format!("({column} = ? OR LEFT({column}, ?) = ?)"),
+        [
+            Value::from(path),
+            Value::from(prefix.chars().count() as i32),
+            Value::from(prefix),
+        ],
+    )
+}
+
+/// `column` with its leading `from` replaced by `to`, for rows matched by [`under_path`].
+pub fn rebase_path(column: &str, from: &str, to: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        format!("CONCAT(?, SUBSTR({column}, ?))"),
+        [
+            Value::from(to),
+            Value::from(from.chars().count() as i32 + 1),
+        ],
+    )
+}
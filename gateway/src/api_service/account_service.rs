@@ -0,0 +1,450 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::{mega_access_token, mega_org, mega_org_member, mega_user};
+use jupiter::storage::access_token_storage::AccessTokenStorage;
+use jupiter::storage::org_storage::OrgStorage;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::api_service::ssh_key_service::parse_expiry;
+use crate::auth::token::{self, TokenScope};
+use crate::auth::{hash_password, verify_password};
+use crate::model::account::{
+    AccessToken, IssuedToken, MemberUpdate, Membership, NewAccessToken, NewOrg, NewUser, Org,
+    OrgMember, User, UserUpdate, ROLE_MEMBER, ROLE_OWNER,
+};
+
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// Accounts of users and organizations, the members of organizations and the access tokens
+/// issued to users.
+#[derive(Clone)]
+pub struct AccountService {
+    pub user_storage: UserStorage,
+    pub org_storage: OrgStorage,
+    pub token_storage: AccessTokenStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+/// Names of users and organizations: up to 64 letters, digits, `-`, `_` and `.`, starting with
+/// a letter or a digit.
+pub fn check_account_name(name: &str) -> Result<(), (StatusCode, String)> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(bad_request(format!(
+            "invalid name {:?}: use up to 64 letters, digits, '-', '_' and '.', starting with a \
+             letter or a digit",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn check_password(password: &str) -> Result<(), (StatusCode, String)> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(bad_request(format!(
+            "password must have at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Trimmed `value`, `None` when it is blank.
+fn non_blank(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_owned()).filter(|v| !v.is_empty())
+}
+
+/// Whether demoting or removing `username` leaves the organization without an owner.
+fn is_last_owner(members: &[mega_org_member::Model], username: &str) -> bool {
+    let owners: Vec<&str> = members
+        .iter()
+        .filter(|m| m.role == ROLE_OWNER)
+        .map(|m| m.username.as_str())
+        .collect();
+    owners == [username]
+}
+
+impl AccountService {
+    async fn find_user(&self, name: &str) -> Result<mega_user::Model, (StatusCode, String)> {
+        self.user_storage
+            .get_user_by_name(name)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no user {}", name)))
+    }
+
+    async fn find_org(&self, name: &str) -> Result<mega_org::Model, (StatusCode, String)> {
+        self.org_storage
+            .get_org_by_name(name)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no organization {}", name)))
+    }
+
+    /// Users and organizations share their names, a new one can't take the name of either.
+    async fn check_name_free(&self, name: &str) -> Result<(), (StatusCode, String)> {
+        let user = self
+            .user_storage
+            .get_user_by_name(name)
+            .await
+            .map_err(internal_error)?;
+        let org = self
+            .org_storage
+            .get_org_by_name(name)
+            .await
+            .map_err(internal_error)?;
+        if user.is_some() || org.is_some() {
+            return Err((StatusCode::CONFLICT, format!("{} is already taken", name)));
+        }
+        Ok(())
+    }
+
+    pub async fn register(&self, new_user: NewUser) -> Result<Json<User>, (StatusCode, String)> {
+        let name = new_user.name.trim();
+        check_account_name(name)?;
+        check_password(&new_user.password)?;
+        self.check_name_free(name).await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let user = mega_user::Model {
+            id: generate_id(),
+            name: name.to_owned(),
+            display_name: non_blank(new_user.display_name),
+            email: non_blank(new_user.email),
+            password_hash: Some(hash_password(&new_user.password).map_err(internal_error)?),
+            is_admin: false,
+            created_at: now,
+            updated_at: now,
+        };
+        self.user_storage
+            .save_user(user.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(user.into()))
+    }
+
+    pub async fn get_user(&self, name: &str) -> Result<Json<User>, (StatusCode, String)> {
+        Ok(Json(self.find_user(name).await?.into()))
+    }
+
+    pub async fn update_user(
+        &self,
+        name: &str,
+        update: UserUpdate,
+    ) -> Result<Json<User>, (StatusCode, String)> {
+        let mut user = self.find_user(name).await?;
+        if let Some(display_name) = update.display_name {
+            user.display_name = non_blank(Some(display_name));
+        }
+        if let Some(email) = update.email {
+            user.email = non_blank(Some(email));
+        }
+        if let Some(password) = update.password {
+            check_password(&password)?;
+            let current = update.current_password.unwrap_or_default();
+            let matches = match user.password_hash.as_deref() {
+                Some(hash) => verify_password(hash, &current),
+                None => true,
+            };
+            if !matches {
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "current password is wrong".to_owned(),
+                ));
+            }
+            user.password_hash = Some(hash_password(&password).map_err(internal_error)?);
+        }
+        user.updated_at = chrono::Utc::now().naive_utc();
+        self.user_storage
+            .save_user(user.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(user.into()))
+    }
+
+    pub async fn list_user_orgs(
+        &self,
+        name: &str,
+    ) -> Result<Json<Vec<Membership>>, (StatusCode, String)> {
+        let orgs = self
+            .org_storage
+            .list_user_orgs(name)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(
+            orgs.into_iter()
+                .map(|(member, org)| Membership {
+                    org: org.into(),
+                    role: member.role,
+                })
+                .collect(),
+        ))
+    }
+
+    pub async fn create_org(&self, new_org: NewOrg) -> Result<Json<Org>, (StatusCode, String)> {
+        let name = new_org.name.trim();
+        check_account_name(name)?;
+        let owner = self
+            .user_storage
+            .get_user_by_name(new_org.owner.trim())
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| bad_request(format!("owner {} has no account", new_org.owner)))?;
+        self.check_name_free(name).await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let org = mega_org::Model {
+            id: generate_id(),
+            name: name.to_owned(),
+            display_name: non_blank(new_org.display_name),
+            description: non_blank(new_org.description),
+            created_at: now,
+            updated_at: now,
+        };
+        self.org_storage
+            .save_org(org.clone())
+            .await
+            .map_err(internal_error)?;
+        self.org_storage
+            .save_member(mega_org_member::Model {
+                id: generate_id(),
+                org_id: org.id,
+                username: owner.name,
+                role: ROLE_OWNER.to_owned(),
+                created_at: now,
+            })
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(org.into()))
+    }
+
+    pub async fn get_org(&self, name: &str) -> Result<Json<Org>, (StatusCode, String)> {
+        Ok(Json(self.find_org(name).await?.into()))
+    }
+
+    pub async fn list_members(
+        &self,
+        org_name: &str,
+    ) -> Result<Json<Vec<OrgMember>>, (StatusCode, String)> {
+        let org = self.find_org(org_name).await?;
+        let members = self
+            .org_storage
+            .list_members(org.id)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(members.into_iter().map(OrgMember::from).collect()))
+    }
+
+    /// Add `username` to the organization, or change their role.
+    pub async fn set_member(
+        &self,
+        org_name: &str,
+        username: &str,
+        update: MemberUpdate,
+    ) -> Result<Json<OrgMember>, (StatusCode, String)> {
+        let role = update.role.unwrap_or_else(|| ROLE_MEMBER.to_owned());
+        if role != ROLE_OWNER && role != ROLE_MEMBER {
+            return Err(bad_request(format!(
+                "role must be {} or {}",
+                ROLE_OWNER, ROLE_MEMBER
+            )));
+        }
+        let org = self.find_org(org_name).await?;
+        let user = self.find_user(username).await?;
+        let members = self
+            .org_storage
+            .list_members(org.id)
+            .await
+            .map_err(internal_error)?;
+        if role != ROLE_OWNER && is_last_owner(&members, &user.name) {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} is the last owner of {}", user.name, org.name),
+            ));
+        }
+        let member = match members.into_iter().find(|m| m.username == user.name) {
+            Some(member) => mega_org_member::Model { role, ..member },
+            None => mega_org_member::Model {
+                id: generate_id(),
+                org_id: org.id,
+                username: user.name,
+                role,
+                created_at: chrono::Utc::now().naive_utc(),
+            },
+        };
+        self.org_storage
+            .save_member(member.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(member.into()))
+    }
+
+    pub async fn remove_member(
+        &self,
+        org_name: &str,
+        username: &str,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let org = self.find_org(org_name).await?;
+        let members = self
+            .org_storage
+            .list_members(org.id)
+            .await
+            .map_err(internal_error)?;
+        if is_last_owner(&members, username) {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} is the last owner of {}", username, org.name),
+            ));
+        }
+        match self.org_storage.delete_member(org.id, username).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("{} is not a member of {}", username, org.name),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    pub async fn list_tokens(
+        &self,
+        username: &str,
+    ) -> Result<Json<Vec<AccessToken>>, (StatusCode, String)> {
+        let tokens = self
+            .token_storage
+            .list_tokens(username)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(tokens.into_iter().map(AccessToken::from).collect()))
+    }
+
+    /// Issue a token to `username` after checking their password.
+    pub async fn issue_token(
+        &self,
+        username: &str,
+        new_token: NewAccessToken,
+    ) -> Result<Json<IssuedToken>, (StatusCode, String)> {
+        let user = self.find_user(username).await?;
+        let authenticated = user
+            .password_hash
+            .as_deref()
+            .is_some_and(|hash| verify_password(hash, &new_token.password));
+        if !authenticated {
+            return Err((StatusCode::UNAUTHORIZED, "wrong password".to_owned()));
+        }
+        let name = new_token.name.trim();
+        if name.is_empty() {
+            return Err(bad_request("token name is required".to_owned()));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let expires_at = match new_token.expires_at.as_deref() {
+            Some(value) => match parse_expiry(value) {
+                Some(time) if time > now => Some(time),
+                Some(_) => return Err(bad_request("expiry must be in the future".to_owned())),
+                None => return Err(bad_request(format!("invalid expiry: {}", value))),
+            },
+            None => None,
+        };
+        let mut scopes = new_token
+            .scopes
+            .iter()
+            .map(|s| s.trim().parse::<TokenScope>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(bad_request)?;
+        scopes.sort();
+        scopes.dedup();
+        if scopes.is_empty() {
+            scopes.push(TokenScope::Read);
+        }
+
+        let secret = token::generate_token();
+        let model = mega_access_token::Model {
+            id: generate_id(),
+            username: user.name,
+            name: name.to_owned(),
+            token_hash: token::token_hash(&secret),
+            scopes: scopes.iter().map(|s| s.as_str().to_owned()).collect(),
+            expires_at,
+            last_used_at: None,
+            created_at: now,
+        };
+        self.token_storage
+            .save_token(model.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(IssuedToken {
+            info: model.into(),
+            token: secret,
+        }))
+    }
+
+    pub async fn revoke_token(
+        &self,
+        username: &str,
+        id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        match self.token_storage.delete_token(username, id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("{} has no token {}", username, id),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use db_entity::mega_org_member;
+
+    use super::{check_account_name, is_last_owner};
+
+    fn member(username: &str, role: &str) -> mega_org_member::Model {
+        mega_org_member::Model {
+            id: 1,
+            org_id: 1,
+            username: username.to_owned(),
+            role: role.to_owned(),
+            created_at: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_check_account_name() {
+        assert!(check_account_name("mega").is_ok());
+        assert!(check_account_name("r2cn.dev_team-1").is_ok());
+        assert!(check_account_name("").is_err());
+        assert!(check_account_name(".hidden").is_err());
+        assert!(check_account_name("-dash").is_err());
+        assert!(check_account_name("a/b").is_err());
+        assert!(check_account_name("中文").is_err());
+        assert!(check_account_name(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_is_last_owner() {
+        let members = vec![member("alice", "owner"), member("bob", "member")];
+        assert!(is_last_owner(&members, "alice"));
+        assert!(!is_last_owner(&members, "bob"));
+
+        let members = vec![member("alice", "owner"), member("bob", "owner")];
+        assert!(!is_last_owner(&members, "alice"));
+    }
+}
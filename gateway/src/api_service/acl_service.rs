@@ -0,0 +1,180 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_path_grant;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::api_service::account_service::check_account_name;
+use crate::auth::acl::{normalize_path, Acl, Permission, SUBJECT_ORG, SUBJECT_USER};
+use crate::auth::Identity;
+use crate::model::acl::{AccessQuery, NewPathGrant, PathAccess, PathGrant, PathGrantQuery};
+
+/// Manages the grants of the ACL. A user with `admin` on a path manages the grants on it and
+/// below it, administrators manage them all.
+#[derive(Clone)]
+pub struct AclService {
+    pub acl: Acl,
+    pub user_storage: UserStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn checked_path(path: &str) -> Result<String, (StatusCode, String)> {
+    normalize_path(path).ok_or_else(|| bad_request(format!("invalid path {}", path)))
+}
+
+impl AclService {
+    /// Check that `caller` has `permission` on `path`, whether the ACL is enforced or not.
+    async fn require(
+        &self,
+        caller: Option<&Identity>,
+        path: &str,
+        permission: Permission,
+    ) -> Result<(), (StatusCode, String)> {
+        let Some(identity) = caller else {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "authentication required".to_owned(),
+            ));
+        };
+        let granted = self
+            .acl
+            .permission(Some(identity), path)
+            .await
+            .map_err(internal_error)?;
+        if granted.is_none_or(|p| p < permission) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("{} permission on {} required", permission, path),
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn list_grants(
+        &self,
+        caller: Option<&Identity>,
+        query: PathGrantQuery,
+    ) -> Result<Json<Vec<PathGrant>>, (StatusCode, String)> {
+        let path = checked_path(query.path.as_deref().unwrap_or("/"))?;
+        self.require(caller, &path, Permission::Admin).await?;
+        let grants = self
+            .acl
+            .grant_storage
+            .list_grants((path != "/").then_some(path.as_str()))
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(grants.into_iter().map(PathGrant::from).collect()))
+    }
+
+    /// Grant a permission, replacing the one the user or organization had on the path.
+    pub async fn save_grant(
+        &self,
+        caller: Option<&Identity>,
+        grant: NewPathGrant,
+    ) -> Result<Json<PathGrant>, (StatusCode, String)> {
+        let path = checked_path(&grant.path)?;
+        let permission: Permission = grant.permission.parse().map_err(bad_request)?;
+        let (kind, subject) = match (grant.user, grant.org) {
+            (Some(user), None) => (SUBJECT_USER, user),
+            (None, Some(org)) => (SUBJECT_ORG, org),
+            _ => return Err(bad_request("give either user or org".to_owned())),
+        };
+        check_account_name(&subject)?;
+        self.require(caller, &path, Permission::Admin).await?;
+        if kind == SUBJECT_ORG
+            && self
+                .acl
+                .org_storage
+                .get_org_by_name(&subject)
+                .await
+                .map_err(internal_error)?
+                .is_none()
+        {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("organization {} not found", subject),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let saved = self
+            .acl
+            .grant_storage
+            .save_grant(mega_path_grant::Model {
+                id: generate_id(),
+                path,
+                subject_kind: kind.to_owned(),
+                subject,
+                permission: permission.as_str().to_owned(),
+                granted_by: caller.map(|c| c.username.clone()),
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(saved.into()))
+    }
+
+    pub async fn delete_grant(
+        &self,
+        caller: Option<&Identity>,
+        id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let not_found = || (StatusCode::NOT_FOUND, format!("grant {} not found", id));
+        let grant = self
+            .acl
+            .grant_storage
+            .get_grant(id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(not_found)?;
+        self.require(caller, &grant.path, Permission::Admin).await?;
+        match self.acl.grant_storage.delete_grant(id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err(not_found()),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// The permission of the caller on a path, or of another user for those who manage the
+    /// grants on it.
+    pub async fn access(
+        &self,
+        caller: Option<&Identity>,
+        query: AccessQuery,
+    ) -> Result<Json<PathAccess>, (StatusCode, String)> {
+        let path = checked_path(&query.path)?;
+        let user = match query.user {
+            Some(user) if caller.map(|c| c.username.as_str()) != Some(user.as_str()) => {
+                self.require(caller, &path, Permission::Admin).await?;
+                let identity = self
+                    .user_storage
+                    .get_user_by_name(&user)
+                    .await
+                    .map_err(internal_error)?
+                    .map(Identity::from)
+                    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("user {} not found", user)))?;
+                Some(identity)
+            }
+            _ => caller.cloned(),
+        };
+        let permission = self
+            .acl
+            .permission(user.as_ref(), &path)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(PathAccess {
+            path,
+            user: user.map(|u| u.username),
+            permission: permission.map(|p| p.as_str().to_owned()),
+            enforced: self.acl.enabled(),
+        }))
+    }
+}
@@ -0,0 +1,58 @@
+use axum::http::StatusCode;
+
+use common::errors::MegaError;
+use db_entity::mega_archive;
+use jupiter::storage::archive_storage::ArchiveStorage;
+
+use crate::api_service::path_move::normalize_path;
+
+/// State of an archived path, which can be read but not written to.
+pub const STATE_ARCHIVED: &str = "archived";
+/// State of a deleted path, whose refs were removed and whose objects wait to be collected.
+pub const STATE_DELETED: &str = "deleted";
+
+/// Looks up whether paths may still be written to.
+#[derive(Clone)]
+pub struct PathArchives {
+    pub archive_storage: ArchiveStorage,
+}
+
+impl PathArchives {
+    /// The archive of `path` or of the closest of its parents, `None` when `path` can be
+    /// written to.
+    pub async fn find(&self, path: &str) -> Result<Option<mega_archive::Model>, MegaError> {
+        let Some(path) = normalize_path(path) else {
+            return Ok(None);
+        };
+        self.archive_storage.find_archive(&path).await
+    }
+
+    /// Fail with `403 Forbidden` when `path` is archived and `410 Gone` when it was deleted.
+    pub async fn check_writable(&self, path: &str) -> Result<(), (StatusCode, String)> {
+        let archive = self
+            .find(path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        match archive {
+            Some(archive) => Err((refusal_status(&archive), refusal(&archive))),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn refusal_status(archive: &mega_archive::Model) -> StatusCode {
+    if archive.state == STATE_DELETED {
+        StatusCode::GONE
+    } else {
+        StatusCode::FORBIDDEN
+    }
+}
+
+/// Why a path at or below `archive.path` can't be written to.
+pub fn refusal(archive: &mega_archive::Model) -> String {
+    if archive.state == STATE_DELETED {
+        format!("{} was deleted", archive.path)
+    } else {
+        format!("{} is archived and read-only", archive.path)
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_archive;
+use git::protocol::bundle::{BundleHeader, BundleRef};
+use git::protocol::{PackProtocol, Protocol};
+use jupiter::storage::archive_storage::ArchiveStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+
+use crate::api_service::archive::{self, STATE_ARCHIVED, STATE_DELETED};
+use crate::api_service::bundle_service;
+use crate::api_service::path_move;
+use crate::api_service::ref_update::RefUpdater;
+use crate::model::archive::{ArchivedRef, PathArchive, PathArchiveRequest};
+
+/// Days the objects of a deleted path are kept when `MEGA_ARCHIVE_GC_DAYS` is not set.
+const DEFAULT_GC_DAYS: i64 = 30;
+
+/// Archives directories and repositories of the monorepo, and deletes them.
+///
+/// Archiving makes a path read-only: pushes and API calls writing to it are refused until it is
+/// unarchived. Deleting removes the refs of every repository at or below the path and keeps a
+/// tombstone of them, with the time from which the objects they reached may be collected.
+/// Optionally a git bundle of each repository is written beforehand, for compliance.
+#[derive(Clone)]
+pub struct ArchiveService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub archive_storage: ArchiveStorage,
+    pub ref_updater: RefUpdater,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn check_path(path: &str) -> Result<String, (StatusCode, String)> {
+    path_move::normalize_path(path)
+        .filter(|p| p != "/")
+        .ok_or_else(|| bad_request(format!("invalid path: {}", path)))
+}
+
+/// File name of the bundle of `repo_path`, unique per second.
+fn bundle_name(repo_path: &str, now: chrono::NaiveDateTime) -> String {
+    format!(
+        "{}-{}.bundle",
+        repo_path.trim_start_matches('/').replace('/', "_"),
+        now.format("%Y%m%d%H%M%S")
+    )
+}
+
+impl ArchiveService {
+    pub async fn list_archives(&self) -> Result<Json<Vec<PathArchive>>, (StatusCode, String)> {
+        let archives = self
+            .archive_storage
+            .list_archives()
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(archives.into_iter().map(PathArchive::from).collect()))
+    }
+
+    /// Make `request.path` read-only. Archiving it again only updates the reason.
+    pub async fn archive(
+        &self,
+        request: PathArchiveRequest,
+        actor: &str,
+    ) -> Result<Json<PathArchive>, (StatusCode, String)> {
+        let path = check_path(&request.path)?;
+        let existing = self.check_not_covered(&path).await?;
+        let refs = self.refs_under(&path).await?;
+        let now = chrono::Utc::now().naive_utc();
+        let model = mega_archive::Model {
+            id: existing.as_ref().map_or_else(generate_id, |a| a.id),
+            path,
+            state: STATE_ARCHIVED.to_owned(),
+            reason: request.reason,
+            actor: actor.to_owned(),
+            refs: Some(serde_json::to_string(&refs).map_err(internal_error)?),
+            bundles: None,
+            gc_after: None,
+            created_at: existing.map_or(now, |a| a.created_at),
+            updated_at: now,
+        };
+        self.archive_storage
+            .save_archive(model.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(model.into()))
+    }
+
+    /// Make an archived path writable again, or drop the tombstone of a deleted one so the path
+    /// can be used again. The refs of a deleted path are not restored.
+    pub async fn unarchive(
+        &self,
+        request: PathArchiveRequest,
+    ) -> Result<Json<PathArchive>, (StatusCode, String)> {
+        let path = check_path(&request.path)?;
+        let archive = self
+            .archive_storage
+            .get_archive(&path)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("{} is not archived", path)))?;
+        self.archive_storage
+            .delete_archive(&path)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(archive.into()))
+    }
+
+    /// Delete `request.path`: write the bundles if asked to, then remove every ref at or below
+    /// the path, keeping them in its tombstone.
+    ///
+    /// The objects are left in storage. The tombstone records from when they may be collected,
+    /// `MEGA_ARCHIVE_GC_DAYS` after the deletion.
+    pub async fn delete(
+        &self,
+        request: PathArchiveRequest,
+        actor: &str,
+    ) -> Result<Json<PathArchive>, (StatusCode, String)> {
+        let path = check_path(&request.path)?;
+        let existing = self.check_not_covered(&path).await?;
+        let refs = self.refs_under(&path).await?;
+        if refs.is_empty() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("no repository at or below {}", path),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let bundles = if request.bundle {
+            self.write_bundles(&refs, now).await?
+        } else {
+            Vec::new()
+        };
+        let reason = match &request.reason {
+            Some(reason) => format!("delete {}: {}", path, reason),
+            None => format!("delete {}", path),
+        };
+        for r in &refs {
+            let old_id = SHA1::from_str(&r.ref_id).map_err(internal_error)?;
+            self.ref_updater
+                .tombstone(&r.repo_path, &r.ref_name, &old_id, actor, &reason)
+                .await?;
+        }
+        let gc_days = std::env::var("MEGA_ARCHIVE_GC_DAYS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_GC_DAYS);
+        let gc_after = now + chrono::Duration::days(gc_days);
+        let model = mega_archive::Model {
+            id: existing.as_ref().map_or_else(generate_id, |a| a.id),
+            path,
+            state: STATE_DELETED.to_owned(),
+            reason: request.reason,
+            actor: actor.to_owned(),
+            refs: Some(serde_json::to_string(&refs).map_err(internal_error)?),
+            bundles: (!bundles.is_empty()).then(|| bundles.join("\n")),
+            gc_after: Some(gc_after),
+            created_at: existing.map_or(now, |a| a.created_at),
+            updated_at: now,
+        };
+        self.archive_storage
+            .save_archive(model.clone())
+            .await
+            .map_err(internal_error)?;
+        tracing::info!(
+            "deleted {} with {} refs, objects may be collected after {}",
+            model.path,
+            refs.len(),
+            gc_after
+        );
+        Ok(Json(model.into()))
+    }
+
+    /// Fail when `path` was deleted or lies below another archived path. Returns the archive of
+    /// `path` itself, when it has one.
+    async fn check_not_covered(
+        &self,
+        path: &str,
+    ) -> Result<Option<mega_archive::Model>, (StatusCode, String)> {
+        let archive = self
+            .archive_storage
+            .find_archive(path)
+            .await
+            .map_err(internal_error)?;
+        match archive {
+            Some(archive) if archive.state == STATE_DELETED || archive.path != path => Err((
+                archive::refusal_status(&archive),
+                archive::refusal(&archive),
+            )),
+            archive => Ok(archive),
+        }
+    }
+
+    async fn refs_under(&self, path: &str) -> Result<Vec<ArchivedRef>, (StatusCode, String)> {
+        let refs = self
+            .storage
+            .get_refs_under_path(path)
+            .await
+            .map_err(internal_error)?;
+        let directory = self
+            .storage
+            .get_directory_by_full_path(path)
+            .await
+            .map_err(internal_error)?;
+        if refs.is_empty() && directory.is_none() {
+            return Err((StatusCode::NOT_FOUND, format!("{} not found", path)));
+        }
+        Ok(refs
+            .into_iter()
+            .map(|r| ArchivedRef {
+                repo_path: r.repo_path,
+                ref_name: r.ref_name,
+                ref_id: r.ref_git_id,
+            })
+            .collect())
+    }
+
+    /// Write a bundle of every repository of `refs` to `MEGA_ARCHIVE_BUNDLE_DIR`, returning
+    /// their paths.
+    async fn write_bundles(
+        &self,
+        refs: &[ArchivedRef],
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<String>, (StatusCode, String)> {
+        let dir = std::env::var("MEGA_ARCHIVE_BUNDLE_DIR").unwrap_or_default();
+        if dir.trim().is_empty() {
+            return Err(bad_request(
+                "MEGA_ARCHIVE_BUNDLE_DIR is not set, no bundle can be written".to_owned(),
+            ));
+        }
+        let dir = PathBuf::from(dir.trim());
+        fs::create_dir_all(&dir).map_err(internal_error)?;
+        let mut repos: BTreeMap<&str, Vec<&ArchivedRef>> = BTreeMap::new();
+        for r in refs {
+            repos.entry(r.repo_path.as_str()).or_default().push(r);
+        }
+        let mut bundles = Vec::new();
+        for (repo_path, refs) in repos {
+            let path = dir.join(bundle_name(repo_path, now));
+            let pack = PackProtocol::new(
+                PathBuf::from(repo_path),
+                self.storage.clone(),
+                Protocol::Local,
+            )
+            .get_full_pack_data(Path::new(repo_path))
+            .await
+            .map_err(internal_error)?;
+            let header = BundleHeader::new(
+                2,
+                refs.iter()
+                    .map(|r| BundleRef {
+                        id: r.ref_id.clone(),
+                        name: r.ref_name.clone(),
+                    })
+                    .collect(),
+            );
+            bundle_service::write_bundle(&path, &header, pack)
+                .await
+                .map_err(internal_error)?;
+            tracing::info!("wrote the bundle of {} to {}", repo_path, path.display());
+            bundles.push(path.to_string_lossy().into_owned());
+        }
+        Ok(bundles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bundle_name;
+
+    #[test]
+    fn test_bundle_name() {
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(12, 30, 5)
+            .unwrap();
+        assert_eq!(
+            bundle_name("/third-party/mega", now),
+            "third-party_mega-20240301123005.bundle"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Autolinks: references such as `JIRA-123` in commit messages, merge request titles and review
+//! comments, turned into links by the rules of organizations.
+//!
+//! A rule is a regular expression and the template of the url of a match, and applies to the
+//! repositories at or below its path. Links are resolved when a text is returned, so changing a
+//! rule changes the links of every text at once, and are returned next to the text with their
+//! offsets, for clients which don't render markdown on the server.
+use axum::http::StatusCode;
+use regex::{Regex, RegexBuilder};
+
+use jupiter::storage::autolink_storage::AutolinkStorage;
+
+use crate::model::autolink::Autolink;
+
+/// Most bytes a compiled pattern may take, patterns run on every text returned.
+const PATTERN_SIZE_LIMIT: usize = 1 << 16;
+
+/// Compile `pattern` as a rule does.
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("invalid pattern {}: {}", pattern, e))
+}
+
+struct Rule {
+    repo_path: String,
+    regex: Regex,
+    url_template: String,
+}
+
+/// The rules of every organization, compiled.
+#[derive(Default)]
+pub struct AutolinkRules {
+    rules: Vec<Rule>,
+}
+
+impl AutolinkRules {
+    pub fn push(&mut self, repo_path: &str, regex: Regex, url_template: &str) {
+        self.rules.push(Rule {
+            repo_path: repo_path.to_owned(),
+            regex,
+            url_template: url_template.to_owned(),
+        });
+    }
+
+    /// The links of `text` of the repository `repo_path`, in the order they appear. Where matches
+    /// overlap the one starting first wins, then the longest, then the one of the oldest rule.
+    pub fn resolve(&self, repo_path: &str, text: &str) -> Vec<Autolink> {
+        let mut links = Vec::new();
+        for rule in &self.rules {
+            let covered = rule.repo_path == "/"
+                || repo_path == rule.repo_path
+                || repo_path
+                    .strip_prefix(&rule.repo_path)
+                    .is_some_and(|rest| rest.starts_with('/'));
+            if !covered {
+                continue;
+            }
+            for captures in rule.regex.captures_iter(text) {
+                let whole = captures.get(0).expect("group 0 is the whole match");
+                if whole.is_empty() {
+                    continue;
+                }
+                let mut url = String::new();
+                captures.expand(&rule.url_template, &mut url);
+                links.push(Autolink {
+                    text: whole.as_str().to_owned(),
+                    url,
+                    start: whole.start(),
+                    end: whole.end(),
+                });
+            }
+        }
+        without_overlaps(links)
+    }
+}
+
+/// Sort the links of a text and leave out those overlapping a link before them: the one
+/// starting first wins, then the longest, then the first in `links`.
+pub fn without_overlaps(mut links: Vec<Autolink>) -> Vec<Autolink> {
+    links.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    let mut end = 0;
+    links.retain(|link| {
+        let keep = link.start >= end;
+        if keep {
+            end = link.end;
+        }
+        keep
+    });
+    links
+}
+
+/// Loads the autolink rules to resolve the links of texts.
+#[derive(Clone)]
+pub struct Autolinker {
+    pub autolink_storage: AutolinkStorage,
+}
+
+impl Autolinker {
+    /// The rules of every organization. A rule whose pattern doesn't compile any more is left out.
+    pub async fn rules(&self) -> Result<AutolinkRules, (StatusCode, String)> {
+        let models = self
+            .autolink_storage
+            .list_all()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let mut rules = AutolinkRules::default();
+        for model in models {
+            match compile(&model.pattern) {
+                Ok(regex) => rules.push(&model.repo_path, regex, &model.url_template),
+                Err(e) => tracing::warn!("skipping autolink rule {}: {}", model.id, e),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, AutolinkRules};
+
+    #[test]
+    fn test_resolve() {
+        let mut rules = AutolinkRules::default();
+        rules.push(
+            "/projects",
+            compile(r"JIRA-(\d+)").unwrap(),
+            "https://jira.example.com/browse/$0?id=$1",
+        );
+        rules.push(
+            "/",
+            compile(r"(?P<key>[A-Z]+)-\d+").unwrap(),
+            "https://tracker.example.com/${key}/$0",
+        );
+
+        let links = rules.resolve("/projects/mega", "Fix JIRA-12 and OPS-7");
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].text, "JIRA-12");
+        assert_eq!(
+            links[0].url,
+            "https://jira.example.com/browse/JIRA-12?id=12"
+        );
+        assert_eq!((links[0].start, links[0].end), (4, 11));
+        assert_eq!(links[1].url, "https://tracker.example.com/OPS/OPS-7");
+
+        // outside of /projects only the rule of / applies
+        let links = rules.resolve("/other", "Fix JIRA-12");
+        assert_eq!(links[0].url, "https://tracker.example.com/JIRA/JIRA-12");
+        assert!(rules.resolve("/projectsx", "JIRA-1")[0]
+            .url
+            .starts_with("https://tracker"));
+        assert!(rules.resolve("/projects", "no reference").is_empty());
+
+        assert!(compile("(unclosed").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::{mega_autolink, mega_org};
+use jupiter::storage::autolink_storage::AutolinkStorage;
+use jupiter::storage::org_storage::OrgStorage;
+
+use crate::api_service::autolink;
+use crate::api_service::path_move;
+use crate::model::autolink::{AutolinkRule, NewAutolinkRule};
+
+/// Manages the autolink rules of organizations.
+#[derive(Clone)]
+pub struct AutolinkService {
+    pub autolink_storage: AutolinkStorage,
+    pub org_storage: OrgStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+impl AutolinkService {
+    async fn find_org(&self, name: &str) -> Result<mega_org::Model, (StatusCode, String)> {
+        self.org_storage
+            .get_org_by_name(name)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no organization {}", name)))
+    }
+
+    pub async fn list_rules(
+        &self,
+        org_name: &str,
+    ) -> Result<Json<Vec<AutolinkRule>>, (StatusCode, String)> {
+        let org = self.find_org(org_name).await?;
+        let rules = self
+            .autolink_storage
+            .list_rules(org.id)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(rules.into_iter().map(AutolinkRule::from).collect()))
+    }
+
+    pub async fn create_rule(
+        &self,
+        org_name: &str,
+        new_rule: NewAutolinkRule,
+    ) -> Result<Json<AutolinkRule>, (StatusCode, String)> {
+        let org = self.find_org(org_name).await?;
+        autolink::compile(&new_rule.pattern).map_err(bad_request)?;
+        let url_template = new_rule.url_template.trim();
+        if !url_template.starts_with("https://") && !url_template.starts_with("http://") {
+            return Err(bad_request(format!(
+                "url template must be an http or https url: {}",
+                url_template
+            )));
+        }
+        let repo_path = path_move::normalize_path(&new_rule.repo_path)
+            .ok_or_else(|| bad_request(format!("invalid path: {}", new_rule.repo_path)))?;
+        let rule = mega_autolink::Model {
+            id: generate_id(),
+            org_id: org.id,
+            pattern: new_rule.pattern,
+            url_template: url_template.to_owned(),
+            repo_path,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        self.autolink_storage
+            .save_rule(rule.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(rule.into()))
+    }
+
+    pub async fn delete_rule(
+        &self,
+        org_name: &str,
+        id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let org = self.find_org(org_name).await?;
+        match self.autolink_storage.delete_rule(org.id, id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("{} has no autolink rule {}", org.name, id),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+}
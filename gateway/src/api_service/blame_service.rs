@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use jupiter::storage::mailmap_storage::MailmapStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::blame::{Blame, PendingLine};
+use venus::internal::object::tree::TreeItemMode;
+
+use crate::api_service::mailmap::Mailmap;
+use crate::api_service::object_loader::{commit_summary, ObjectLoader};
+use crate::model::blame::{BlameHunk, BlameResult};
+use crate::model::query::BlameQuery;
+
+/// Upper bound of commits visited for one blame request, lines still unexplained when the limit
+/// is hit are attributed to the oldest commit reached.
+const MAX_BLAME_COMMITS: usize = 10000;
+
+#[derive(Clone)]
+pub struct BlameService {
+    pub storage: Arc<dyn ObjectStorage>,
+    /// Identities shown in place of the authors of commits
+    pub mailmap_storage: MailmapStorage,
+}
+
+/// A commit queued for examination together with the lines its children handed to it.
+struct Candidate {
+    blob_id: SHA1,
+    lines: Vec<PendingLine>,
+}
+
+impl BlameService {
+    pub async fn get_blame(&self, query: BlameQuery) -> Result<Json<BlameResult>, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let head = loader
+            .resolve_ref(&query.repo_path, query.refs.as_deref())
+            .await?;
+        let head_commit = loader.commit(&head).await?;
+        let blob_id = self
+            .blob_at(&mut loader, &head_commit.tree_id, &query.path)
+            .await?
+            .ok_or((
+                StatusCode::NOT_FOUND,
+                format!("{} does not exist at {}", query.path, head.to_plain_str()),
+            ))?;
+
+        let content = self.text(&mut loader, &blob_id).await?;
+        let mut blame = Blame::new(&content);
+
+        // visit commits newest first so each one is examined once with all of its pending lines
+        let mut queue: BinaryHeap<(usize, Reverse<SHA1>)> = BinaryHeap::new();
+        let mut candidates: HashMap<SHA1, Candidate> = HashMap::new();
+        let mut contents: HashMap<SHA1, String> = HashMap::new();
+        contents.insert(blob_id, content);
+        queue.push((head_commit.committer.timestamp, Reverse(head)));
+        candidates.insert(
+            head,
+            Candidate {
+                blob_id,
+                lines: blame.initial_pending(),
+            },
+        );
+
+        let mut visited = 0;
+        let mut last = head;
+        while let Some((_, Reverse(commit_id))) = queue.pop() {
+            let Some(candidate) = candidates.remove(&commit_id) else {
+                continue;
+            };
+            last = commit_id;
+            visited += 1;
+            if visited > MAX_BLAME_COMMITS {
+                blame.attribute(commit_id, &candidate.lines);
+                continue;
+            }
+
+            let commit = loader.commit(&commit_id).await?;
+            let mut parent_blobs = Vec::with_capacity(commit.parent_commit_ids.len());
+            // a parent which can't be read is left out, its lines stay with the commit
+            for parent_id in &commit.parent_commit_ids {
+                let parent = loader.commit(parent_id).await;
+                let Some(parent) = loader.tolerate_missing(parent, &query.repo_path, "blame")?
+                else {
+                    continue;
+                };
+                let parent_blob = self
+                    .blob_at(&mut loader, &parent.tree_id, &query.path)
+                    .await;
+                let Some(parent_blob) =
+                    loader.tolerate_missing(parent_blob, &query.repo_path, "blame")?
+                else {
+                    continue;
+                };
+                parent_blobs.push((parent, parent_blob));
+            }
+
+            // the file is unchanged in a parent: every line passes through untouched
+            if let Some((parent, Some(parent_blob))) = parent_blobs
+                .iter()
+                .find(|(_, b)| *b == Some(candidate.blob_id))
+            {
+                Self::hand_down(
+                    &mut queue,
+                    &mut candidates,
+                    parent.id,
+                    parent.committer.timestamp,
+                    *parent_blob,
+                    candidate.lines,
+                );
+                continue;
+            }
+
+            let needed = parent_blobs.iter().filter_map(|(_, b)| *b);
+            for id in needed.chain(std::iter::once(candidate.blob_id)) {
+                if let Entry::Vacant(e) = contents.entry(id) {
+                    let text = self.text(&mut loader, &id).await;
+                    if let Some(text) = loader.tolerate_missing(text, &query.repo_path, "blame")? {
+                        e.insert(text);
+                    }
+                }
+            }
+            if !contents.contains_key(&candidate.blob_id) {
+                blame.attribute(commit_id, &candidate.lines);
+                continue;
+            }
+            let parent_contents: Vec<Option<&str>> = parent_blobs
+                .iter()
+                .map(|(_, b)| b.and_then(|id| contents.get(&id).map(String::as_str)))
+                .collect();
+            let handed = blame.pass_blame(
+                commit_id,
+                &contents[&candidate.blob_id],
+                candidate.lines,
+                &parent_contents,
+            );
+            for ((parent, parent_blob), lines) in parent_blobs.into_iter().zip(handed) {
+                if let (Some(parent_blob), false) = (parent_blob, lines.is_empty()) {
+                    Self::hand_down(
+                        &mut queue,
+                        &mut candidates,
+                        parent.id,
+                        parent.committer.timestamp,
+                        parent_blob,
+                        lines,
+                    );
+                }
+            }
+        }
+
+        let lines = blame.finish(last);
+        let mailmap = Mailmap::from(
+            self.mailmap_storage
+                .list_entries()
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        );
+        let mut hunks: Vec<BlameHunk> = Vec::new();
+        for line in lines {
+            match hunks.last_mut() {
+                Some(hunk)
+                    if hunk.commit_id == line.commit_id.to_plain_str()
+                        && hunk.orig_start_line + hunk.lines.len() == line.orig_line_no =>
+                {
+                    hunk.lines.push(line.content);
+                }
+                _ => {
+                    let commit = loader.commit(&line.commit_id).await?;
+                    let (author, author_email) =
+                        mailmap.resolve(&commit.author.name, &commit.author.email);
+                    hunks.push(BlameHunk {
+                        commit_id: line.commit_id.to_plain_str(),
+                        start_line: line.line_no,
+                        orig_start_line: line.orig_line_no,
+                        author,
+                        author_email,
+                        committed_at: commit.committer.timestamp,
+                        summary: commit_summary(&commit.message),
+                        lines: vec![line.content],
+                    });
+                }
+            }
+        }
+
+        Ok(Json(BlameResult {
+            path: query.path,
+            commit_id: head.to_plain_str(),
+            hunks,
+            missing_objects: loader.gaps().iter().map(|id| id.to_plain_str()).collect(),
+        }))
+    }
+
+    fn hand_down(
+        queue: &mut BinaryHeap<(usize, Reverse<SHA1>)>,
+        candidates: &mut HashMap<SHA1, Candidate>,
+        parent_id: SHA1,
+        timestamp: usize,
+        blob_id: SHA1,
+        lines: Vec<PendingLine>,
+    ) {
+        match candidates.get_mut(&parent_id) {
+            Some(candidate) => candidate.lines.extend(lines),
+            None => {
+                queue.push((timestamp, Reverse(parent_id)));
+                candidates.insert(parent_id, Candidate { blob_id, lines });
+            }
+        }
+    }
+
+    async fn blob_at(
+        &self,
+        loader: &mut ObjectLoader,
+        tree_id: &SHA1,
+        path: &str,
+    ) -> Result<Option<SHA1>, (StatusCode, String)> {
+        Ok(loader.find_path(tree_id, path).await?.and_then(|item| {
+            match item.mode {
+                TreeItemMode::Blob | TreeItemMode::BlobExecutable => Some(item.id),
+                _ => None,
+            }
+        }))
+    }
+
+    async fn text(&self, loader: &mut ObjectLoader, id: &SHA1) -> Result<String, (StatusCode, String)> {
+        String::from_utf8(loader.blob(id).await?).map_err(|_| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Can not blame a binary file".to_string(),
+            )
+        })
+    }
+}
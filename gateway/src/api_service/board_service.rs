@@ -0,0 +1,579 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::{mega_board, mega_board_card, mega_board_column};
+use jupiter::storage::board_storage::BoardStorage;
+use jupiter::storage::issue_storage::IssueStorage;
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use jupiter::storage::mr_storage::MrStorage;
+
+use crate::api_service::planning_service::{normalize_names, PlanningService};
+use crate::model::board::{
+    Board, BoardColumn, BoardDetail, BoardListQuery, BoardQuery, BoardUpdate, Card, CardMove,
+    ColumnUpdate, NewBoard, NewCard, NewColumn, DEFAULT_COLUMNS,
+};
+
+/// Project boards planning the issues and merge requests of a repository in columns.
+#[derive(Clone)]
+pub struct BoardService {
+    pub board_storage: BoardStorage,
+    pub issue_storage: IssueStorage,
+    pub mr_storage: MrStorage,
+    pub planning: PlanningService,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn check_name(kind: &str, name: &str) -> Result<(), (StatusCode, String)> {
+    if name.is_empty() || name.chars().count() > 255 {
+        return Err(bad_request(format!(
+            "{} name must have between 1 and 255 characters",
+            kind
+        )));
+    }
+    Ok(())
+}
+
+fn check_item_type(item_type: &str) -> Result<(), (StatusCode, String)> {
+    if item_type != ITEM_ISSUE && item_type != ITEM_MR {
+        return Err(bad_request(format!("unknown item type: {}", item_type)));
+    }
+    Ok(())
+}
+
+/// `ids` with `moving` put at index `position`, or last when it is missing or past the end.
+pub fn reorder(ids: &[i64], moving: i64, position: Option<usize>) -> Vec<i64> {
+    let mut ids: Vec<i64> = ids.iter().copied().filter(|id| *id != moving).collect();
+    let position = position.map_or(ids.len(), |p| p.min(ids.len()));
+    ids.insert(position, moving);
+    ids
+}
+
+/// Whether a card is shown by the filters of `query`.
+pub fn matches(card: &Card, query: &BoardQuery) -> bool {
+    query
+        .item_type
+        .as_deref()
+        .is_none_or(|t| card.item_type == t)
+        && query.state.as_deref().is_none_or(|s| card.state == s)
+        && query
+            .label
+            .as_deref()
+            .is_none_or(|l| card.labels.iter().any(|label| label == l))
+        && query
+            .assignee
+            .as_deref()
+            .is_none_or(|a| card.assignees.iter().any(|assignee| assignee == a))
+        && query.milestone.is_none_or(|m| card.milestone_id == Some(m))
+}
+
+impl BoardService {
+    pub async fn list(
+        &self,
+        query: BoardListQuery,
+    ) -> Result<Json<Vec<Board>>, (StatusCode, String)> {
+        let boards = self
+            .board_storage
+            .list_boards(&query.repo_path)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(boards.into_iter().map(Board::from).collect()))
+    }
+
+    pub async fn create(
+        &self,
+        new_board: NewBoard,
+    ) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+        let name = new_board.name.trim();
+        check_name("board", name)?;
+        let columns = match new_board.columns {
+            Some(columns) => normalize_names(columns),
+            None => DEFAULT_COLUMNS.iter().map(|c| (*c).to_owned()).collect(),
+        };
+        for column in &columns {
+            check_name("column", column)?;
+        }
+        self.ensure_board_free(&new_board.repo_path, name).await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let board = mega_board::Model {
+            id: generate_id(),
+            repo_path: new_board.repo_path,
+            name: name.to_owned(),
+            description: new_board.description.filter(|d| !d.trim().is_empty()),
+            created_at: now,
+            updated_at: now,
+        };
+        let columns = columns
+            .into_iter()
+            .enumerate()
+            .map(|(position, name)| mega_board_column::Model {
+                id: generate_id(),
+                board_id: board.id,
+                name,
+                position: position as i32,
+                created_at: now,
+                updated_at: now,
+            })
+            .collect();
+        self.board_storage
+            .save_board(board.clone(), columns)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(self.render(board, &BoardQuery::default()).await?))
+    }
+
+    /// The board with the cards `query` filters.
+    pub async fn detail(
+        &self,
+        id: i64,
+        query: BoardQuery,
+    ) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+        if let Some(item_type) = query.item_type.as_deref() {
+            check_item_type(item_type)?;
+        }
+        let board = self.get_board(id).await?;
+        Ok(Json(self.render(board, &query).await?))
+    }
+
+    pub async fn update(
+        &self,
+        id: i64,
+        update: BoardUpdate,
+    ) -> Result<Json<Board>, (StatusCode, String)> {
+        let mut board = self.get_board(id).await?;
+        if let Some(name) = update.name {
+            let name = name.trim();
+            check_name("board", name)?;
+            if name != board.name {
+                self.ensure_board_free(&board.repo_path, name).await?;
+                board.name = name.to_owned();
+            }
+        }
+        if let Some(description) = update.description {
+            board.description = Some(description).filter(|d| !d.trim().is_empty());
+        }
+        board.updated_at = chrono::Utc::now().naive_utc();
+        let board = self
+            .board_storage
+            .update_board(board)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(board.into()))
+    }
+
+    /// Remove a board with its columns and cards, the issues and merge requests are kept.
+    pub async fn delete(&self, id: i64) -> Result<StatusCode, (StatusCode, String)> {
+        match self.board_storage.delete_board(id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((StatusCode::NOT_FOUND, format!("board {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    pub async fn add_column(
+        &self,
+        board_id: i64,
+        new_column: NewColumn,
+    ) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+        let board = self.get_board(board_id).await?;
+        let name = new_column.name.trim();
+        check_name("column", name)?;
+        let columns = self.columns(board_id).await?;
+        let now = chrono::Utc::now().naive_utc();
+        let column = mega_board_column::Model {
+            id: generate_id(),
+            board_id,
+            name: name.to_owned(),
+            position: columns.len() as i32,
+            created_at: now,
+            updated_at: now,
+        };
+        self.board_storage
+            .save_column(column.clone())
+            .await
+            .map_err(internal_error)?;
+        if new_column.position.is_some() {
+            let ids: Vec<i64> = columns.iter().map(|c| c.id).collect();
+            self.board_storage
+                .order_columns(&reorder(&ids, column.id, new_column.position))
+                .await
+                .map_err(internal_error)?;
+        }
+        Ok(Json(self.render(board, &BoardQuery::default()).await?))
+    }
+
+    /// Rename a column or move it among the columns of its board.
+    pub async fn update_column(
+        &self,
+        board_id: i64,
+        column_id: i64,
+        update: ColumnUpdate,
+    ) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+        let board = self.get_board(board_id).await?;
+        let columns = self.columns(board_id).await?;
+        let Some(mut column) = columns.iter().find(|c| c.id == column_id).cloned() else {
+            return Err(column_not_found(board_id, column_id));
+        };
+        if let Some(name) = update.name {
+            let name = name.trim();
+            check_name("column", name)?;
+            column.name = name.to_owned();
+            column.updated_at = chrono::Utc::now().naive_utc();
+            self.board_storage
+                .update_column(column)
+                .await
+                .map_err(internal_error)?;
+        }
+        if update.position.is_some() {
+            let ids: Vec<i64> = columns.iter().map(|c| c.id).collect();
+            self.board_storage
+                .order_columns(&reorder(&ids, column_id, update.position))
+                .await
+                .map_err(internal_error)?;
+        }
+        Ok(Json(self.render(board, &BoardQuery::default()).await?))
+    }
+
+    /// Remove an empty column, one with cards is refused.
+    pub async fn delete_column(
+        &self,
+        board_id: i64,
+        column_id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        self.get_board(board_id).await?;
+        let columns = self.columns(board_id).await?;
+        if !columns.iter().any(|c| c.id == column_id) {
+            return Err(column_not_found(board_id, column_id));
+        }
+        let cards = self
+            .board_storage
+            .count_cards(column_id)
+            .await
+            .map_err(internal_error)?;
+        if cards > 0 {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("column {} still has {} cards", column_id, cards),
+            ));
+        }
+        self.board_storage
+            .delete_column(column_id)
+            .await
+            .map_err(internal_error)?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// Put an issue or merge request of the repository of the board in one of its columns.
+    pub async fn add_card(
+        &self,
+        board_id: i64,
+        new_card: NewCard,
+    ) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+        let board = self.get_board(board_id).await?;
+        let item_type = new_card.item_type.trim();
+        check_item_type(item_type)?;
+        let columns = self.columns(board_id).await?;
+        if !columns.iter().any(|c| c.id == new_card.column_id) {
+            return Err(column_not_found(board_id, new_card.column_id));
+        }
+        let repo_path = if item_type == ITEM_ISSUE {
+            let issue = self
+                .issue_storage
+                .get_issue(new_card.item_id)
+                .await
+                .map_err(internal_error)?;
+            issue.map(|issue| issue.repo_path)
+        } else {
+            let mr = self
+                .mr_storage
+                .get_mr(new_card.item_id)
+                .await
+                .map_err(internal_error)?;
+            mr.map(|mr| mr.repo_path)
+        };
+        if repo_path.as_deref() != Some(board.repo_path.as_str()) {
+            return Err(bad_request(format!(
+                "{} has no {} {}",
+                board.repo_path, item_type, new_card.item_id
+            )));
+        }
+        let cards = self.cards(board_id).await?;
+        if cards
+            .iter()
+            .any(|c| c.item_type == item_type && c.item_id == new_card.item_id)
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!(
+                    "{} {} is already on board {}",
+                    item_type, new_card.item_id, board.name
+                ),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let card = mega_board_card::Model {
+            id: generate_id(),
+            board_id,
+            column_id: new_card.column_id,
+            item_type: item_type.to_owned(),
+            item_id: new_card.item_id,
+            position: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        self.board_storage
+            .save_card(card.clone())
+            .await
+            .map_err(internal_error)?;
+        self.place_card(&cards, &card, new_card.column_id, new_card.position)
+            .await?;
+        Ok(Json(self.render(board, &BoardQuery::default()).await?))
+    }
+
+    /// Move a card to a position of the same or another column.
+    pub async fn move_card(
+        &self,
+        board_id: i64,
+        card_id: i64,
+        card_move: CardMove,
+    ) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+        let board = self.get_board(board_id).await?;
+        let columns = self.columns(board_id).await?;
+        if !columns.iter().any(|c| c.id == card_move.column_id) {
+            return Err(column_not_found(board_id, card_move.column_id));
+        }
+        let cards = self.cards(board_id).await?;
+        let Some(card) = cards.iter().find(|c| c.id == card_id) else {
+            return Err(card_not_found(board_id, card_id));
+        };
+        if card.column_id != card_move.column_id {
+            let remaining: Vec<i64> = cards
+                .iter()
+                .filter(|c| c.column_id == card.column_id && c.id != card_id)
+                .map(|c| c.id)
+                .collect();
+            self.board_storage
+                .order_cards(card.column_id, &remaining)
+                .await
+                .map_err(internal_error)?;
+        }
+        self.place_card(&cards, card, card_move.column_id, card_move.position)
+            .await?;
+        Ok(Json(self.render(board, &BoardQuery::default()).await?))
+    }
+
+    /// Take an issue or merge request off a board.
+    pub async fn remove_card(
+        &self,
+        board_id: i64,
+        card_id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        self.get_board(board_id).await?;
+        let cards = self.cards(board_id).await?;
+        if !cards.iter().any(|c| c.id == card_id) {
+            return Err(card_not_found(board_id, card_id));
+        }
+        self.board_storage
+            .delete_card(card_id)
+            .await
+            .map_err(internal_error)?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// Put `card` at `position` among the other cards of `column_id`.
+    async fn place_card(
+        &self,
+        cards: &[mega_board_card::Model],
+        card: &mega_board_card::Model,
+        column_id: i64,
+        position: Option<usize>,
+    ) -> Result<(), (StatusCode, String)> {
+        let ids: Vec<i64> = cards
+            .iter()
+            .filter(|c| c.column_id == column_id)
+            .map(|c| c.id)
+            .collect();
+        self.board_storage
+            .order_cards(column_id, &reorder(&ids, card.id, position))
+            .await
+            .map_err(internal_error)
+    }
+
+    async fn render(
+        &self,
+        board: mega_board::Model,
+        query: &BoardQuery,
+    ) -> Result<BoardDetail, (StatusCode, String)> {
+        let columns = self.columns(board.id).await?;
+        let cards = self.cards(board.id).await?;
+        let ids_of = |item_type: &str| -> Vec<i64> {
+            cards
+                .iter()
+                .filter(|c| c.item_type == item_type)
+                .map(|c| c.item_id)
+                .collect()
+        };
+        let (issue_ids, mr_ids) = (ids_of(ITEM_ISSUE), ids_of(ITEM_MR));
+        let mut issues = self
+            .issue_storage
+            .get_issues(&issue_ids)
+            .await
+            .map_err(internal_error)?;
+        let mut mrs = self
+            .mr_storage
+            .get_mrs(&mr_ids)
+            .await
+            .map_err(internal_error)?;
+        let mut issue_links = self.planning.links_of(ITEM_ISSUE, &issue_ids).await?;
+        let mut mr_links = self.planning.links_of(ITEM_MR, &mr_ids).await?;
+
+        let mut shown: Vec<(i64, Card)> = Vec::new();
+        for card in &cards {
+            let rendered = if card.item_type == ITEM_ISSUE {
+                let Some(at) = issues.iter().position(|i| i.id == card.item_id) else {
+                    continue;
+                };
+                let links = issue_links.remove(&card.item_id).unwrap_or_default();
+                Card::issue(card, issues.swap_remove(at), links)
+            } else {
+                let Some(at) = mrs.iter().position(|mr| mr.id == card.item_id) else {
+                    continue;
+                };
+                let links = mr_links.remove(&card.item_id).unwrap_or_default();
+                Card::mr(card, mrs.swap_remove(at), links)
+            };
+            if matches(&rendered, query) {
+                shown.push((card.column_id, rendered));
+            }
+        }
+        Ok(BoardDetail {
+            board: board.into(),
+            columns: columns
+                .into_iter()
+                .map(|column| BoardColumn {
+                    id: column.id,
+                    name: column.name,
+                    cards: shown
+                        .iter()
+                        .filter(|(column_id, _)| *column_id == column.id)
+                        .map(|(_, card)| card.clone())
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_board(&self, id: i64) -> Result<mega_board::Model, (StatusCode, String)> {
+        match self.board_storage.get_board(id).await {
+            Ok(Some(board)) => Ok(board),
+            Ok(None) => Err((StatusCode::NOT_FOUND, format!("board {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    async fn columns(
+        &self,
+        board_id: i64,
+    ) -> Result<Vec<mega_board_column::Model>, (StatusCode, String)> {
+        self.board_storage
+            .list_columns(board_id)
+            .await
+            .map_err(internal_error)
+    }
+
+    async fn cards(
+        &self,
+        board_id: i64,
+    ) -> Result<Vec<mega_board_card::Model>, (StatusCode, String)> {
+        self.board_storage
+            .list_cards(board_id)
+            .await
+            .map_err(internal_error)
+    }
+
+    async fn ensure_board_free(
+        &self,
+        repo_path: &str,
+        name: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        match self.board_storage.find_by_name(repo_path, name).await {
+            Ok(None) => Ok(()),
+            Ok(Some(_)) => Err((
+                StatusCode::CONFLICT,
+                format!("{} already has a board named {}", repo_path, name),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+}
+
+fn column_not_found(board_id: i64, column_id: i64) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("column {} not found on board {}", column_id, board_id),
+    )
+}
+
+fn card_not_found(board_id: i64, card_id: i64) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("card {} not found on board {}", card_id, board_id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(item_type: &str, state: &str, labels: &[&str], milestone_id: Option<i64>) -> Card {
+        Card {
+            id: 1,
+            item_type: item_type.to_owned(),
+            item_id: 2,
+            number: None,
+            title: "title".to_owned(),
+            state: state.to_owned(),
+            labels: labels.iter().map(|l| (*l).to_owned()).collect(),
+            assignees: vec!["alice".to_owned()],
+            milestone_id,
+        }
+    }
+
+    #[test]
+    fn test_reorder() {
+        assert_eq!(reorder(&[1, 2, 3], 3, Some(0)), [3, 1, 2]);
+        assert_eq!(reorder(&[1, 2, 3], 1, Some(1)), [2, 1, 3]);
+        assert_eq!(reorder(&[1, 2, 3], 1, None), [2, 3, 1]);
+        assert_eq!(reorder(&[1, 2], 4, Some(9)), [1, 2, 4]);
+        assert_eq!(reorder(&[], 4, Some(1)), [4]);
+    }
+
+    #[test]
+    fn test_matches() {
+        let bug = card(ITEM_ISSUE, "open", &["bug"], Some(7));
+        assert!(matches(&bug, &BoardQuery::default()));
+        let query = BoardQuery {
+            item_type: Some(ITEM_ISSUE.to_owned()),
+            state: Some("open".to_owned()),
+            label: Some("bug".to_owned()),
+            assignee: Some("alice".to_owned()),
+            milestone: Some(7),
+        };
+        assert!(matches(&bug, &query));
+        assert!(!matches(&card(ITEM_MR, "open", &["bug"], Some(7)), &query));
+        assert!(!matches(
+            &card(ITEM_ISSUE, "closed", &["bug"], Some(7)),
+            &query
+        ));
+        assert!(!matches(&card(ITEM_ISSUE, "open", &[], Some(7)), &query));
+        assert!(!matches(&card(ITEM_ISSUE, "open", &["bug"], None), &query));
+    }
+}
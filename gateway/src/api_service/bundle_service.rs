@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+use bytes::Bytes;
+
+use common::operation::{self, OperationKind};
+use common::utils::ZERO_ID;
+use git::internal::budget::SpillBuffer;
+use git::protocol::bundle::{BundleHeader, BundleRef};
+use git::protocol::{PackProtocol, Protocol, RefCommand};
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::api_service::archive::PathArchives;
+use crate::api_service::event_service::EventService;
+use crate::api_service::path_move;
+use crate::api_service::remote;
+use crate::model::bundle::{BundleImport, BundledRef};
+
+/// Exports repositories to git bundles, and seeds or updates repositories from bundles, for
+/// moving them between servers no network connects.
+#[derive(Clone)]
+pub struct BundleService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub archives: PathArchives,
+    pub events: EventService,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+/// The refs of `stored` a bundle of `wanted` holds, all of them when none is named. A name is
+/// looked up as it is, then as a tag and then as a branch, in the order git resolves them.
+fn select_refs(stored: &[(String, String)], wanted: &[String]) -> Result<Vec<BundleRef>, String> {
+    let bundle_ref = |(name, id): &(String, String)| BundleRef {
+        id: id.clone(),
+        name: name.clone(),
+    };
+    if wanted.is_empty() {
+        return Ok(stored.iter().map(bundle_ref).collect());
+    }
+    let mut refs: Vec<BundleRef> = Vec::new();
+    for name in wanted {
+        let candidates = [
+            name.clone(),
+            format!("refs/tags/{}", name),
+            format!("refs/heads/{}", name),
+        ];
+        let found = candidates
+            .iter()
+            .find_map(|c| stored.iter().find(|(n, _)| n == c))
+            .ok_or_else(|| format!("no ref {}", name))?;
+        if !refs.iter().any(|r| r.name == found.0) {
+            refs.push(bundle_ref(found));
+        }
+    }
+    Ok(refs)
+}
+
+/// Write the bundle of `header` and `pack` to `target`. It is written next to it first and moved
+/// in place once complete, so a bundle found at `target` is never partial.
+pub async fn write_bundle(
+    target: &Path,
+    header: &BundleHeader,
+    pack: SpillBuffer,
+) -> io::Result<()> {
+    let header = header.encode();
+    let target = target.to_path_buf();
+    tokio::task::spawn_blocking(move || -> io::Result<()> {
+        let partial = target.with_extension("bundle.partial");
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(&header)?;
+        io::copy(&mut pack.into_reader()?, &mut file)?;
+        file.sync_all()?;
+        fs::rename(&partial, &target)
+    })
+    .await?
+}
+
+impl BundleService {
+    /// The header and pack of a bundle of the refs of `repo_path` named in `wanted`, or of all its
+    /// refs. The pack holds every object of the repository, so the bundle needs no prerequisites.
+    pub async fn create(
+        &self,
+        repo_path: &str,
+        wanted: &[String],
+        version: u8,
+    ) -> Result<(BundleHeader, SpillBuffer), (StatusCode, String)> {
+        if version != 2 && version != 3 {
+            return Err(bad_request(format!(
+                "unsupported bundle version {}, expected 2 or 3",
+                version
+            )));
+        }
+        let repo_path = path_move::normalize_path(repo_path)
+            .ok_or_else(|| bad_request(format!("invalid path: {}", repo_path)))?;
+        let stored: Vec<(String, String)> = self
+            .storage
+            .get_all_refs_by_path(&repo_path)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|r| (r.ref_name, r.ref_git_id))
+            .collect();
+        if stored.is_empty() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("no repository at {}", repo_path),
+            ));
+        }
+        let refs = select_refs(&stored, wanted).map_err(|e| (StatusCode::NOT_FOUND, e))?;
+        let pack = PackProtocol::new(
+            PathBuf::from(&repo_path),
+            self.storage.clone(),
+            Protocol::Local,
+        )
+        .get_full_pack_data(Path::new(&repo_path))
+        .await
+        .map_err(internal_error)?;
+        Ok((BundleHeader::new(version, refs), pack))
+    }
+
+    /// Store the objects and refs of `bundle` at `repo_path`.
+    ///
+    /// A bundle without prerequisites seeds a new repository, the path must not be in use. One
+    /// with prerequisites updates the repository at the path, which must have every one of them;
+    /// its refs are moved to where the bundle has them as a push would.
+    pub async fn import(
+        &self,
+        repo_path: &str,
+        bundle: Bytes,
+        actor: &str,
+    ) -> Result<Json<BundleImport>, (StatusCode, String)> {
+        let repo_path = path_move::normalize_path(repo_path)
+            .filter(|p| p != "/")
+            .ok_or_else(|| bad_request(format!("invalid path: {}", repo_path)))?;
+        let (header, offset) = BundleHeader::parse(&bundle).map_err(bad_request)?;
+        self.archives.check_writable(&repo_path).await?;
+
+        let stored: HashMap<String, String> = self
+            .storage
+            .get_all_refs_by_path(&repo_path)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|r| (r.ref_name, r.ref_git_id))
+            .collect();
+        if header.prerequisites.is_empty() {
+            let directory = self
+                .storage
+                .get_directory_by_full_path(&repo_path)
+                .await
+                .map_err(internal_error)?;
+            if !stored.is_empty() || directory.is_some() {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "{} already exists, only a bundle building on it can update it",
+                        repo_path
+                    ),
+                ));
+            }
+        } else {
+            let ids: Vec<String> = header.prerequisites.iter().map(|p| p.id.clone()).collect();
+            let known = self
+                .storage
+                .find_known_ids(&repo_path, &ids)
+                .await
+                .map_err(internal_error)?;
+            if let Some(missing) = ids.iter().find(|id| !known.contains(*id)) {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "{} lacks the commit {} the bundle builds on",
+                        repo_path, missing
+                    ),
+                ));
+            }
+        }
+
+        let pack = bundle.slice(offset..);
+        let object_count = remote::pack_object_count(&pack);
+        let commands: Vec<RefCommand> = header
+            .refs
+            .iter()
+            .filter(|r| stored.get(&r.name) != Some(&r.id))
+            .map(|r| {
+                let old_id = stored.get(&r.name).map_or(ZERO_ID, |id| id.as_str());
+                RefCommand::new(old_id.to_owned(), r.id.clone(), r.name.clone())
+            })
+            .collect();
+        let unchanged = header.refs.len() - commands.len();
+        if !commands.is_empty() {
+            let operation = operation::start(OperationKind::Import, &repo_path);
+            operation.set_stage("storing", commands.len() as u64);
+            let mut pack_protocol = PackProtocol::new(
+                PathBuf::from(&repo_path),
+                self.storage.clone(),
+                Protocol::Local,
+            );
+            pack_protocol.command_list = commands.clone();
+            let report = pack_protocol
+                .git_receive_pack(pack)
+                .await
+                .map_err(internal_error)?;
+            if report.windows(3).any(|w| w == b"ng ") {
+                return Err(internal_error(format!(
+                    "unable to store the bundle at {}: {}",
+                    repo_path,
+                    String::from_utf8_lossy(&report)
+                )));
+            }
+            operation.advance(commands.len() as u64);
+            self.events
+                .publish_push(&repo_path, &pack_protocol.command_list, Some(actor))
+                .await;
+        }
+        tracing::info!(
+            "imported a bundle of {} refs and {} objects at {}",
+            header.refs.len(),
+            object_count,
+            repo_path
+        );
+        Ok(Json(BundleImport {
+            repo_path,
+            version: header.version,
+            prerequisites: header.prerequisites.len(),
+            refs: commands
+                .into_iter()
+                .map(|c| BundledRef {
+                    ref_name: c.ref_name,
+                    ref_id: c.new_id,
+                    old_id: (c.old_id != ZERO_ID).then_some(c.old_id),
+                })
+                .collect(),
+            unchanged,
+            object_count,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_refs;
+
+    #[test]
+    fn test_select_refs() {
+        let stored = vec![
+            ("refs/heads/main".to_owned(), "a".repeat(40)),
+            ("refs/tags/v1".to_owned(), "b".repeat(40)),
+            ("refs/heads/v1".to_owned(), "c".repeat(40)),
+        ];
+        assert_eq!(select_refs(&stored, &[]).unwrap().len(), 3);
+
+        let names = |wanted: &[&str]| -> Vec<String> {
+            let wanted: Vec<String> = wanted.iter().map(|w| w.to_string()).collect();
+            select_refs(&stored, &wanted)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.name)
+                .collect()
+        };
+        assert_eq!(names(&["main", "v1"]), ["refs/heads/main", "refs/tags/v1"]);
+        assert_eq!(
+            names(&["refs/tags/v1", "main", "refs/heads/main"]),
+            ["refs/tags/v1", "refs/heads/main"]
+        );
+        assert_eq!(
+            select_refs(&stored, &["dev".to_owned()]).unwrap_err(),
+            "no ref dev"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Changelogs built from conventional commits.
+//!
+//! A conventional commit summary reads `type(scope)!: description`, the scope and the `!` marking
+//! a breaking change being optional; a `BREAKING CHANGE:` footer marks it breaking too. Commits of
+//! the types below get a section of their type, the others, following the convention or not, are
+//! listed as other changes.
+//!
+use venus::internal::object::commit::Commit;
+
+use crate::api_service::object_loader;
+use crate::model::changelog::{ChangelogEntry, ChangelogSection};
+
+/// The types with a section of their own, in the order sections are rendered, with their titles.
+const SECTIONS: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("revert", "Reverts"),
+    ("docs", "Documentation"),
+    ("test", "Tests"),
+    ("build", "Build"),
+    ("ci", "Continuous Integration"),
+    ("style", "Style"),
+    ("chore", "Chores"),
+];
+
+const OTHER: (&str, &str) = ("other", "Other Changes");
+
+/// The parts of a conventional commit message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConventionalCommit<'a> {
+    /// The type, lowercased
+    pub kind: String,
+    pub scope: Option<&'a str>,
+    pub breaking: bool,
+    pub description: &'a str,
+}
+
+/// Parse the message of a commit, `None` when its summary doesn't follow the convention.
+pub fn parse(message: &str) -> Option<ConventionalCommit<'_>> {
+    let body = object_loader::commit_body(message);
+    let summary = body.lines().next()?.trim();
+    let (head, description) = summary.split_once(':')?;
+    let description = description.trim();
+    if description.is_empty() {
+        return None;
+    }
+    let (head, bang) = match head.strip_suffix('!') {
+        Some(head) => (head, true),
+        None => (head, false),
+    };
+    let (kind, scope) = match head.split_once('(') {
+        Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?)),
+        None => (head, None),
+    };
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    if scope.is_some_and(|scope| scope.is_empty() || scope.contains(['(', ')'])) {
+        return None;
+    }
+    let breaking = bang
+        || body.lines().skip(1).any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        });
+    Some(ConventionalCommit {
+        kind: kind.to_ascii_lowercase(),
+        scope,
+        breaking,
+        description,
+    })
+}
+
+/// Group `commits`, newest first, into sections, with the breaking changes among them. Merge
+/// commits are left out, the commits they merge are listed.
+pub fn group(commits: &[Commit]) -> (Vec<ChangelogSection>, Vec<ChangelogEntry>) {
+    let mut sections: Vec<ChangelogSection> = SECTIONS
+        .iter()
+        .chain([&OTHER])
+        .map(|(kind, title)| ChangelogSection {
+            kind: kind.to_string(),
+            title: title.to_string(),
+            entries: Vec::new(),
+        })
+        .collect();
+    let mut breaking = Vec::new();
+    for commit in commits.iter().filter(|c| c.parent_commit_ids.len() < 2) {
+        let parsed = parse(&commit.message);
+        let section = parsed
+            .as_ref()
+            .and_then(|parsed| SECTIONS.iter().position(|(kind, _)| *kind == parsed.kind))
+            .unwrap_or(SECTIONS.len());
+        let breaking_change = parsed.as_ref().is_some_and(|parsed| parsed.breaking);
+        let (scope, description) = match parsed.filter(|_| section < SECTIONS.len()) {
+            Some(parsed) => (
+                parsed.scope.map(str::to_owned),
+                parsed.description.to_owned(),
+            ),
+            None => (None, object_loader::commit_summary(&commit.message)),
+        };
+        let entry = ChangelogEntry {
+            commit_id: commit.id.to_plain_str(),
+            scope,
+            description,
+            breaking: breaking_change,
+            author: commit.author.name.clone(),
+        };
+        if entry.breaking {
+            breaking.push(entry.clone());
+        }
+        sections[section].entries.push(entry);
+    }
+    sections.retain(|section| !section.entries.is_empty());
+    (sections, breaking)
+}
+
+/// Render a changelog as markdown, under a heading naming `to`.
+pub fn render_markdown(
+    to: &str,
+    breaking: &[ChangelogEntry],
+    sections: &[ChangelogSection],
+) -> String {
+    let mut markdown = format!("## {}\n", to);
+    let mut render = |title: &str, entries: &[ChangelogEntry]| {
+        markdown.push_str(&format!("\n### {}\n\n", title));
+        for entry in entries {
+            let scope = match &entry.scope {
+                Some(scope) => format!("**{}:** ", scope),
+                None => String::new(),
+            };
+            markdown.push_str(&format!(
+                "- {}{} ({})\n",
+                scope,
+                entry.description,
+                &entry.commit_id[..7]
+            ));
+        }
+    };
+    if !breaking.is_empty() {
+        render("Breaking Changes", breaking);
+    }
+    for section in sections {
+        render(&section.title, &section.entries);
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use venus::hash::SHA1;
+    use venus::internal::object::commit::Commit;
+    use venus::internal::object::signature::{Signature, SignatureType};
+
+    use super::{group, parse, render_markdown, ConventionalCommit};
+
+    fn commit(n: u8, message: &str) -> Commit {
+        let signature = |signature_type| Signature {
+            signature_type,
+            name: "mega".to_owned(),
+            email: "mega@localhost".to_owned(),
+            timestamp: n as usize,
+            timezone: "+0000".to_owned(),
+        };
+        Commit {
+            id: SHA1([n; 20]),
+            tree_id: SHA1([0; 20]),
+            parent_commit_ids: vec![SHA1([n - 1; 20])],
+            author: signature(SignatureType::Author),
+            committer: signature(SignatureType::Committer),
+            message: format!("\n{}\n", message),
+            gpgsig: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_conventional_commit() {
+        assert_eq!(
+            parse("\nfeat(api)!: drop v1\n"),
+            Some(ConventionalCommit {
+                kind: "feat".to_owned(),
+                scope: Some("api"),
+                breaking: true,
+                description: "drop v1",
+            })
+        );
+        let parsed = parse("\nFix: handle empty trees\n\nBREAKING CHANGE: trees are required\n");
+        assert_eq!(parsed.as_ref().map(|p| p.kind.as_str()), Some("fix"));
+        assert!(parsed.is_some_and(|p| p.breaking && p.scope.is_none()));
+
+        assert!(parse("\nupdate readme\n").is_none());
+        assert!(parse("\nMerge branch 'dev': sync\n").is_none());
+        assert!(parse("\nfeat(): nothing\n").is_none());
+        assert!(parse("\nfeat: \n").is_none());
+    }
+
+    #[test]
+    fn test_group_and_render() {
+        let mut merge = commit(5, "Merge branch 'dev'");
+        merge.parent_commit_ids.push(SHA1([9; 20]));
+        let commits = [
+            merge,
+            commit(4, "fix(pack): check the trailer"),
+            commit(3, "update readme"),
+            commit(2, "feat!: stream pushes"),
+            commit(1, "wip: try things"),
+        ];
+        let (sections, breaking) = group(&commits);
+        let kinds: Vec<&str> = sections.iter().map(|s| s.kind.as_str()).collect();
+        assert_eq!(kinds, ["feat", "fix", "other"]);
+        assert_eq!(sections[2].entries.len(), 2);
+        assert_eq!(sections[2].entries[1].description, "wip: try things");
+        assert_eq!(breaking.len(), 1);
+
+        assert_eq!(
+            render_markdown("v2", &breaking, &sections),
+            "## v2\n\n\
+             ### Breaking Changes\n\n- stream pushes (0202020)\n\n\
+             ### Features\n\n- stream pushes (0202020)\n\n\
+             ### Bug Fixes\n\n- **pack:** check the trailer (0404040)\n\n\
+             ### Other Changes\n\n- update readme (0303030)\n- wip: try things (0101010)\n"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::api_service::changelog;
+use crate::api_service::merge;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::model::changelog::{Changelog, ChangelogQuery};
+
+/// Most commits listed in a changelog, the newest are kept.
+const MAX_CHANGELOG_COMMITS: usize = 1000;
+
+#[derive(Clone)]
+pub struct ChangelogService {
+    pub storage: Arc<dyn ObjectStorage>,
+}
+
+impl ChangelogService {
+    /// The changelog of the commits reachable from `to` and not from `from`, usually two tags.
+    pub async fn changelog(
+        &self,
+        query: ChangelogQuery,
+    ) -> Result<Json<Changelog>, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let from = loader
+            .resolve_ref(&query.repo_path, Some(&query.from))
+            .await?;
+        let from = loader.peel(&from).await?;
+        let to = loader
+            .resolve_ref(&query.repo_path, Some(&query.to))
+            .await?;
+        let to = loader.peel(&to).await?;
+
+        let search = merge::walk_merge_base(&mut loader, &from, &to).await?;
+        let (_, unique) = search.unique_commits();
+        let mut commits = Vec::with_capacity(unique.len());
+        for id in unique {
+            commits.push(loader.commit(&id).await?);
+        }
+        commits.sort_by_key(|c| Reverse(c.committer.timestamp));
+        let truncated = commits.len() > MAX_CHANGELOG_COMMITS;
+        commits.truncate(MAX_CHANGELOG_COMMITS);
+
+        let (sections, breaking) = changelog::group(&commits);
+        let markdown = changelog::render_markdown(&query.to, &breaking, &sections);
+        Ok(Json(Changelog {
+            from: query.from,
+            to: query.to,
+            from_id: from.to_plain_str(),
+            to_id: to.to_plain_str(),
+            breaking,
+            sections,
+            truncated,
+            markdown,
+        }))
+    }
+}
@@ -0,0 +1,256 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_check_run;
+use jupiter::storage::check_run_storage::CheckRunStorage;
+
+use crate::i18n;
+use crate::model::check::{
+    CheckRun, CommitChecks, NewCheckRun, RequiredChecks, RequiredChecksQuery, CHECK_ERROR,
+    CHECK_FAILURE, CHECK_PENDING, CHECK_STATES, CHECK_SUCCESS,
+};
+
+/// Statuses external CI systems report for commits, and the checks branches require before
+/// merge requests into them can be merged.
+#[derive(Clone)]
+pub struct CheckService {
+    pub storage: CheckRunStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn branch_ref(name: &str) -> String {
+    format!(
+        "refs/heads/{}",
+        name.strip_prefix("refs/heads/").unwrap_or(name)
+    )
+}
+
+fn check_name(name: &str) -> Result<(), (StatusCode, String)> {
+    if name.trim().is_empty() || name.len() > 255 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "check name must have 1 to 255 characters".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// The checks of `required` which haven't succeeded among `runs`.
+pub fn blocking_checks(required: &[String], runs: &[CheckRun]) -> Vec<String> {
+    required
+        .iter()
+        .filter(|name| {
+            !runs
+                .iter()
+                .any(|run| &run.name == *name && run.status == CHECK_SUCCESS)
+        })
+        .cloned()
+        .collect()
+}
+
+/// The state of a commit summing up its `runs` and the required checks `blocking` it.
+pub fn combined_state(runs: &[CheckRun], blocking: &[String]) -> &'static str {
+    if runs
+        .iter()
+        .any(|run| run.status == CHECK_FAILURE || run.status == CHECK_ERROR)
+    {
+        CHECK_FAILURE
+    } else if !blocking.is_empty() || runs.iter().any(|run| run.status == CHECK_PENDING) {
+        CHECK_PENDING
+    } else {
+        CHECK_SUCCESS
+    }
+}
+
+impl CheckService {
+    /// Record the status of a check for a commit, replacing the one reported before.
+    pub async fn report(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        new_run: NewCheckRun,
+        reporter: String,
+    ) -> Result<Json<CheckRun>, (StatusCode, String)> {
+        if commit_id.len() != 40 || !commit_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("invalid commit id: {}", commit_id),
+            ));
+        }
+        check_name(&new_run.name)?;
+        if !CHECK_STATES.contains(&new_run.status.as_str()) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "invalid status {}, expected one of {}",
+                    new_run.status,
+                    CHECK_STATES.join(", ")
+                ),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let run = self
+            .storage
+            .save_run(mega_check_run::Model {
+                id: generate_id(),
+                repo_path: repo_path.to_owned(),
+                commit_id: commit_id.to_lowercase(),
+                name: new_run.name,
+                status: new_run.status,
+                description: new_run.description,
+                target_url: new_run.target_url,
+                reporter,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(run.into()))
+    }
+
+    /// The checks of a commit, with the ones `branch` requires and they don't satisfy.
+    pub async fn commit_checks(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        branch: Option<&str>,
+    ) -> Result<CommitChecks, (StatusCode, String)> {
+        let commit_id = commit_id.to_lowercase();
+        let checks: Vec<CheckRun> = self
+            .storage
+            .list_runs(repo_path, &commit_id)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(CheckRun::from)
+            .collect();
+        let required = match branch {
+            Some(branch) => self
+                .storage
+                .required_checks(repo_path, &branch_ref(branch))
+                .await
+                .map_err(internal_error)?,
+            None => Vec::new(),
+        };
+        let blocking = blocking_checks(&required, &checks);
+        Ok(CommitChecks {
+            commit_id,
+            state: combined_state(&checks, &blocking).to_owned(),
+            checks,
+            required,
+            blocking,
+        })
+    }
+
+    pub async fn get_checks(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        branch: Option<&str>,
+    ) -> Result<Json<CommitChecks>, (StatusCode, String)> {
+        self.commit_checks(repo_path, commit_id, branch)
+            .await
+            .map(Json)
+    }
+
+    /// Refuse to merge `commit_id` into `branch` until the checks it requires have succeeded.
+    pub async fn ensure_passed(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        branch: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        let checks = self
+            .commit_checks(repo_path, commit_id, Some(branch))
+            .await?;
+        if checks.blocking.is_empty() {
+            return Ok(());
+        }
+        Err((
+            StatusCode::CONFLICT,
+            i18n::t(
+                "mr.checks_required",
+                &[("checks", &checks.blocking.join(", "))],
+            ),
+        ))
+    }
+
+    pub async fn required_checks(
+        &self,
+        query: RequiredChecksQuery,
+    ) -> Result<Json<RequiredChecks>, (StatusCode, String)> {
+        let checks = self
+            .storage
+            .required_checks(&query.repo_path, &branch_ref(&query.branch))
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(RequiredChecks {
+            repo_path: query.repo_path,
+            branch: query.branch,
+            checks,
+        }))
+    }
+
+    /// Replace the checks a branch requires, an empty list lifts the requirement.
+    pub async fn set_required_checks(
+        &self,
+        mut required: RequiredChecks,
+    ) -> Result<Json<RequiredChecks>, (StatusCode, String)> {
+        for name in &required.checks {
+            check_name(name)?;
+        }
+        required.checks.sort();
+        required.checks.dedup();
+        self.storage
+            .set_required_checks(
+                &required.repo_path,
+                &branch_ref(&required.branch),
+                &required.checks,
+            )
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(required))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blocking_checks, combined_state};
+    use crate::model::check::CheckRun;
+
+    fn run(name: &str, status: &str) -> CheckRun {
+        CheckRun {
+            name: name.to_owned(),
+            commit_id: "0".repeat(40),
+            status: status.to_owned(),
+            description: None,
+            target_url: None,
+            reporter: "ci".to_owned(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_required_checks_state() {
+        let required = vec!["build".to_owned(), "lint".to_owned(), "test".to_owned()];
+        let runs = [
+            run("build", "success"),
+            run("lint", "pending"),
+            run("docs", "success"),
+        ];
+        let blocking = blocking_checks(&required, &runs);
+        assert_eq!(blocking, ["lint", "test"]);
+        assert_eq!(combined_state(&runs, &blocking), "pending");
+
+        let runs = [run("build", "success"), run("docs", "error")];
+        assert_eq!(combined_state(&runs, &[]), "failure");
+        assert_eq!(combined_state(&runs[..1], &[]), "success");
+        assert_eq!(combined_state(&[], &[]), "success");
+        assert_eq!(combined_state(&[], &required), "pending");
+    }
+}
@@ -0,0 +1,470 @@
+use std::convert::Infallible;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Response;
+use axum::Json;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use futures::Stream;
+use sha2::{Digest, Sha256};
+
+use common::utils::generate_id;
+use db_entity::{mega_ci_log, mega_ci_log_chunk};
+use jupiter::storage::ci_log_storage::CiLogStorage;
+use storage::driver::file_storage::FileStorage;
+
+use crate::model::ci_log::{CiLog, CI_FINAL_STATES, CI_RUNNING};
+
+/// How often streams look for new output.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often logs past their retention are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Days a log is kept after it was last written to, unless `MEGA_CI_LOG_RETENTION_DAYS` says
+/// otherwise.
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Keeps job logs of CI runs, written in chunks while the job runs and readable at any time.
+#[derive(Clone)]
+pub struct CiLogService {
+    pub storage: CiLogStorage,
+    /// Content store holding the compressed chunks
+    pub content: Arc<dyn FileStorage>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn retention() -> chrono::Duration {
+    let days = std::env::var("MEGA_CI_LOG_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    chrono::Duration::days(days)
+}
+
+fn check_job(commit_id: &str, job: &str) -> Result<(), (StatusCode, String)> {
+    if commit_id.len() != 40 || !commit_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("invalid commit id: {}", commit_id),
+        ));
+    }
+    if job.trim().is_empty() || job.len() > 255 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "job name must have 1 to 255 characters".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Content store key of a chunk, hashed so keys spread over directories like other objects.
+fn chunk_object_id(chunk_id: i64) -> String {
+    format!("{:x}", Sha256::digest(chunk_id.to_be_bytes()))
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn decompress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+/// The byte range a `Range` header asks for from a log of `size` bytes, end exclusive.
+/// `None` when the header is malformed or the range starts past the end.
+pub fn parse_range(value: &str, size: i64) -> Option<(i64, i64)> {
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => (size - suffix.parse::<i64>().ok()?.min(size), size),
+        (start, "") => (start.parse().ok()?, size),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<i64>().ok()?.saturating_add(1),
+        ),
+    };
+    (start < size && start < end).then_some((start, end.min(size)))
+}
+
+/// Text of `bytes` for an event stream, and how many of the bytes it covers.
+///
+/// A character cut by the end of the written output is left for the next event unless the log
+/// is complete. Events cannot carry carriage returns, so they become line breaks.
+pub fn sse_text(bytes: &[u8], complete: bool) -> (String, usize) {
+    let used = match std::str::from_utf8(bytes) {
+        Err(e) if e.error_len().is_none() && !complete => e.valid_up_to(),
+        _ => bytes.len(),
+    };
+    let text = String::from_utf8_lossy(&bytes[..used])
+        .replace("\r\n", "\n")
+        .replace('\r', "\n");
+    (text, used)
+}
+
+impl CiLogService {
+    pub async fn list_logs(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+    ) -> Result<Json<Vec<CiLog>>, (StatusCode, String)> {
+        let logs = self
+            .storage
+            .list_logs(repo_path, &commit_id.to_lowercase())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(logs.into_iter().map(CiLog::from).collect()))
+    }
+
+    async fn get_log(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        job: &str,
+    ) -> Result<mega_ci_log::Model, (StatusCode, String)> {
+        self.storage
+            .get_log(repo_path, &commit_id.to_lowercase(), job)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("no log of job {} for commit {}", job, commit_id),
+                )
+            })
+    }
+
+    /// Add `data` to the end of the log of `job`, creating the log on the first call.
+    ///
+    /// Runners that retry pass `offset`, the size they expect the log to have, so a chunk that
+    /// was already stored is not added twice.
+    pub async fn append(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        job: &str,
+        offset: Option<i64>,
+        data: Bytes,
+    ) -> Result<Json<CiLog>, (StatusCode, String)> {
+        check_job(commit_id, job)?;
+        let now = chrono::Utc::now().naive_utc();
+        let mut log = self
+            .storage
+            .get_or_create_log(mega_ci_log::Model {
+                id: generate_id(),
+                repo_path: repo_path.to_owned(),
+                commit_id: commit_id.to_lowercase(),
+                job_name: job.to_owned(),
+                status: CI_RUNNING.to_owned(),
+                size: 0,
+                chunks: 0,
+                created_at: now,
+                updated_at: now,
+                finished_at: None,
+            })
+            .await
+            .map_err(internal_error)?;
+        if log.finished_at.is_some() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("log of job {} is complete", job),
+            ));
+        }
+        if offset.is_some_and(|offset| offset != log.size) {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("log of job {} has {} bytes", job, log.size),
+            ));
+        }
+        if data.is_empty() {
+            return Ok(Json(log.into()));
+        }
+
+        let chunk_id = generate_id();
+        let chunk = mega_ci_log_chunk::Model {
+            id: chunk_id,
+            log_id: log.id,
+            seq: log.chunks,
+            offset: log.size,
+            size: data.len() as i64,
+            object_id: chunk_object_id(chunk_id),
+        };
+        let compressed = compress(&data);
+        self.content
+            .put(&chunk.object_id, compressed.len() as i64, &compressed)
+            .await
+            .map_err(internal_error)?;
+        let object_id = chunk.object_id.clone();
+        if !self
+            .storage
+            .append_chunk(&log, chunk)
+            .await
+            .map_err(internal_error)?
+        {
+            if let Err(e) = self.content.remove(&object_id).await {
+                tracing::warn!("unable to remove ci log chunk {}: {}", object_id, e);
+            }
+            return Err((
+                StatusCode::CONFLICT,
+                format!("log of job {} was written concurrently, retry", job),
+            ));
+        }
+        log.size += data.len() as i64;
+        log.chunks += 1;
+        log.updated_at = chrono::Utc::now().naive_utc();
+        Ok(Json(log.into()))
+    }
+
+    /// Mark the log of `job` complete with the final state of the job.
+    pub async fn finish(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        job: &str,
+        status: &str,
+    ) -> Result<Json<CiLog>, (StatusCode, String)> {
+        if !CI_FINAL_STATES.contains(&status) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "invalid status {}, expected one of {}",
+                    status,
+                    CI_FINAL_STATES.join(", ")
+                ),
+            ));
+        }
+        let log = self.get_log(repo_path, commit_id, job).await?;
+        if !self
+            .storage
+            .finish_log(log.id, status)
+            .await
+            .map_err(internal_error)?
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("log of job {} is complete", job),
+            ));
+        }
+        Ok(Json(self.get_log(repo_path, commit_id, job).await?.into()))
+    }
+
+    /// Bytes `start..end` of a log.
+    async fn read_range(
+        &self,
+        log: &mega_ci_log::Model,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<u8>, (StatusCode, String)> {
+        let chunks = self
+            .storage
+            .chunks_from(log.id, start)
+            .await
+            .map_err(internal_error)?;
+        let mut data = Vec::new();
+        for chunk in chunks.iter().take_while(|c| c.offset < end) {
+            let stored = self
+                .content
+                .get(&chunk.object_id)
+                .await
+                .map_err(internal_error)?;
+            let bytes = decompress(&stored).map_err(internal_error)?;
+            let from = (start - chunk.offset).clamp(0, chunk.size) as usize;
+            let to = (end - chunk.offset).clamp(0, chunk.size) as usize;
+            data.extend_from_slice(&bytes[from..to]);
+        }
+        Ok(data)
+    }
+
+    /// The log of `job` from `offset`, or the part a `Range` header asks for.
+    pub async fn read(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        job: &str,
+        offset: Option<i64>,
+        range: Option<&str>,
+    ) -> Result<Response, (StatusCode, String)> {
+        let log = self.get_log(repo_path, commit_id, job).await?;
+        let complete = log.finished_at.is_some();
+        let total = if complete {
+            log.size.to_string()
+        } else {
+            "*".to_owned()
+        };
+        let (status, start, end) = match range {
+            Some(range) => match parse_range(range, log.size) {
+                Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end),
+                None => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header(header::CONTENT_RANGE, format!("bytes */{}", log.size))
+                        .body(Body::empty())
+                        .unwrap())
+                }
+            },
+            None => (
+                StatusCode::OK,
+                offset.unwrap_or(0).clamp(0, log.size),
+                log.size,
+            ),
+        };
+        let data = self.read_range(&log, start, end).await?;
+        let mut response = Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header("X-Log-Size", log.size)
+            .header("X-Log-Complete", complete.to_string())
+            .header("X-Log-Status", &log.status);
+        if status == StatusCode::PARTIAL_CONTENT {
+            response = response.header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end - 1, total),
+            );
+        }
+        Ok(response.body(Body::from(data)).unwrap())
+    }
+
+    /// Stream the log of `job` from `offset` as server-sent events while it is written.
+    ///
+    /// Every event carries the output written since the previous one, with the offset it ends
+    /// at as its id, so a client reconnecting with `Last-Event-ID` resumes where it left off.
+    /// An `end` event with the final state of the job closes the stream.
+    pub async fn stream(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        job: &str,
+        offset: i64,
+    ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+        let log = self.get_log(repo_path, commit_id, job).await?;
+        let service = self.clone();
+        let key = (log.repo_path, log.commit_id, log.job_name);
+        let stream = futures::stream::unfold(
+            (service, key, offset.max(0), false),
+            |(service, key, mut offset, done)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    let (event, done) = match service.next_event(&key, &mut offset).await {
+                        Ok(Some(event)) => event,
+                        Ok(None) => {
+                            tokio::time::sleep(POLL_INTERVAL).await;
+                            continue;
+                        }
+                        Err((_, e)) => (Event::default().event("error").data(e), true),
+                    };
+                    return Some((Ok(event), (service, key, offset, done)));
+                }
+            },
+        );
+        Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    }
+
+    /// The next event of a stream at `offset`, and whether it is the last one. `None` while
+    /// there is no new output.
+    async fn next_event(
+        &self,
+        (repo_path, commit_id, job): &(String, String, String),
+        offset: &mut i64,
+    ) -> Result<Option<(Event, bool)>, (StatusCode, String)> {
+        let log = self.get_log(repo_path, commit_id, job).await?;
+        let complete = log.finished_at.is_some();
+        if log.size > *offset {
+            let data = self.read_range(&log, *offset, log.size).await?;
+            let (text, used) = sse_text(&data, complete);
+            if used > 0 {
+                *offset += used as i64;
+                let event = Event::default().id(offset.to_string()).data(text);
+                return Ok(Some((event, false)));
+            }
+        }
+        if complete {
+            return Ok(Some((Event::default().event("end").data(log.status), true)));
+        }
+        Ok(None)
+    }
+
+    pub fn start_cleanup(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.remove_expired().await;
+            }
+        });
+    }
+
+    /// Remove logs not written to within the retention period, with their chunks.
+    async fn remove_expired(&self) {
+        let before = chrono::Utc::now().naive_utc() - retention();
+        let expired = match self.storage.expired_logs(before).await {
+            Ok(expired) => expired,
+            Err(e) => {
+                tracing::warn!("unable to load expired ci logs: {}", e);
+                return;
+            }
+        };
+        for log in expired {
+            let chunks = match self.storage.delete_log(log.id).await {
+                Ok(chunks) => chunks,
+                Err(e) => {
+                    tracing::warn!("unable to remove ci log {}: {}", log.id, e);
+                    continue;
+                }
+            };
+            for chunk in chunks {
+                if let Err(e) = self.content.remove(&chunk.object_id).await {
+                    tracing::warn!("unable to remove ci log chunk {}: {}", chunk.object_id, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, parse_range, sse_text};
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=10-", 100), Some((10, 100)));
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 20)));
+        assert_eq!(parse_range("bytes=90-200", 100), Some((90, 100)));
+        assert_eq!(parse_range("bytes=-30", 100), Some((70, 100)));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("items=0-5", 100), None);
+    }
+
+    #[test]
+    fn test_sse_text() {
+        assert_eq!(
+            sse_text(b"step 1\r\nprogress 10%\rprogress 20%\n", false),
+            ("step 1\nprogress 10%\nprogress 20%\n".to_owned(), 34)
+        );
+        let cut = "caf\u{e9}".as_bytes();
+        assert_eq!(sse_text(&cut[..4], false), ("caf".to_owned(), 3));
+        assert_eq!(sse_text(&cut[..4], true).1, 4);
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let data = b"Compiling mega v0.1.0\n".repeat(100);
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+}
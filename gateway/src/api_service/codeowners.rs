@@ -0,0 +1,240 @@
+//! Code owners, read from the `CODEOWNERS` file of the branch a merge request targets.
+//!
+//! Each line pairs a path pattern with the users owning the files it matches, the `@` before a
+//! name being optional; blank lines and lines starting with `#` are skipped:
+//!
+//! ```text
+//! *.rs                  @alice
+//! /docs/                @bob @carol
+//! gateway/**/router.rs  @dave
+//! /vendor/
+//! ```
+//!
+//! Patterns follow `.gitignore`: a pattern with a `/` before its end is anchored to the root,
+//! one without matches at any depth, a trailing `/` matches directories only, and a pattern
+//! matching a directory matches every file below it. `*` and `?` match within a path component,
+//! `**` any number of components. The last line matching a file decides its owners, a line
+//! without owners leaves the files it matches unowned.
+//!
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+
+use venus::hash::SHA1;
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::object_loader::ObjectLoader;
+use crate::model::review::CodeOwnerApproval;
+
+/// Where a branch keeps its code owners, the first file found is used.
+pub const FILE_PATHS: &[&str] = &["CODEOWNERS", ".mega/CODEOWNERS", "docs/CODEOWNERS"];
+
+#[derive(Debug)]
+struct Rule {
+    /// Components of the pattern, `**` included; unanchored patterns start with `**`
+    pattern: Vec<String>,
+    dir_only: bool,
+    owners: Vec<String>,
+}
+
+/// The rules of a `CODEOWNERS` file.
+#[derive(Debug, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    pub fn parse(text: &str) -> CodeOwners {
+        let rules = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let pattern = fields.next().filter(|p| !p.starts_with('#'))?;
+                let owners = fields
+                    .take_while(|owner| !owner.starts_with('#'))
+                    .map(|owner| owner.trim_start_matches('@').to_owned())
+                    .filter(|owner| !owner.is_empty())
+                    .collect();
+                let dir_only = pattern.ends_with('/');
+                let pattern = pattern.trim_end_matches('/');
+                let anchored = pattern.contains('/');
+                let mut components: Vec<String> = pattern
+                    .split('/')
+                    .filter(|c| !c.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                if !anchored {
+                    components.insert(0, "**".to_owned());
+                }
+                Some(Rule {
+                    pattern: components,
+                    dir_only,
+                    owners,
+                })
+            })
+            .collect();
+        CodeOwners { rules }
+    }
+
+    /// Owners of the file at `path`, none when it is unowned.
+    pub fn owners_of(&self, path: &str) -> &[String] {
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                // the directories holding the file, then the file itself
+                (1..=components.len()).any(|len| {
+                    (len < components.len() || !rule.dir_only)
+                        && match_components(&rule.pattern, &components[..len])
+                })
+            })
+            .map_or(&[], |rule| &rule.owners)
+    }
+
+    /// The approvals changing the files at `paths` needs: every set of owners owning some of
+    /// them, with their files, and which of `approvers` approved for it. The approval of one
+    /// owner of a set covers its files.
+    pub fn approvals<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a str>,
+        approvers: &[String],
+    ) -> Vec<CodeOwnerApproval> {
+        let mut owned: BTreeMap<&[String], BTreeSet<&str>> = BTreeMap::new();
+        for path in paths {
+            let owners = self.owners_of(path);
+            if !owners.is_empty() {
+                owned.entry(owners).or_default().insert(path);
+            }
+        }
+        owned
+            .into_iter()
+            .map(|(owners, paths)| {
+                let approved_by: Vec<String> = approvers
+                    .iter()
+                    .filter(|a| owners.iter().any(|o| o.eq_ignore_ascii_case(a)))
+                    .cloned()
+                    .collect();
+                CodeOwnerApproval {
+                    owners: owners.to_vec(),
+                    paths: paths.into_iter().map(str::to_owned).collect(),
+                    approved: !approved_by.is_empty(),
+                    approved_by,
+                }
+            })
+            .collect()
+    }
+}
+
+fn match_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(name, path)| {
+            wildcard(first.as_bytes(), name.as_bytes()) && match_components(rest, path)
+        }),
+    }
+}
+
+/// Match a path component against a pattern with `*` and `?`.
+fn wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| wildcard(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && wildcard(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && wildcard(rest, &name[1..]),
+    }
+}
+
+/// Collect the paths of the files that differ between tree `old` and tree `new` in `paths`,
+/// either can be missing. `prefix` is the path of the trees.
+pub fn changed_paths<'a>(
+    loader: &'a mut ObjectLoader,
+    prefix: String,
+    old: Option<SHA1>,
+    new: Option<SHA1>,
+    paths: &'a mut Vec<String>,
+) -> BoxFuture<'a, Result<(), (StatusCode, String)>> {
+    Box::pin(async move {
+        let old = match old {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let new = match new {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let names: BTreeSet<&String> = old.iter().chain(new.iter()).map(|i| &i.name).collect();
+        for name in names {
+            let o = old.iter().find(|i| &i.name == name);
+            let n = new.iter().find(|i| &i.name == name);
+            if let (Some(o), Some(n)) = (o, n) {
+                if o.id == n.id && o.mode == n.mode {
+                    continue;
+                }
+            }
+            let path = format!("{}{}", prefix, name);
+            let subtree = |item: Option<&TreeItem>| {
+                item.filter(|i| i.mode == TreeItemMode::Tree).map(|i| i.id)
+            };
+            let (old_tree, new_tree) = (subtree(o), subtree(n));
+            if old_tree.is_some() || new_tree.is_some() {
+                changed_paths(loader, format!("{}/", path), old_tree, new_tree, paths).await?;
+            }
+            let is_file =
+                |item: Option<&TreeItem>| item.is_some_and(|i| i.mode != TreeItemMode::Tree);
+            if is_file(o) || is_file(n) {
+                paths.push(path);
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CodeOwners;
+
+    #[test]
+    fn test_owners_of() {
+        let owners = CodeOwners::parse(
+            "# owners\n\
+             *          @lead\n\
+             *.rs       @alice # rust\n\
+             /docs/     @bob carol\n\
+             gateway/**/router.rs @dave\n\
+             build?/    @erin\n\
+             /docs/vendor\n",
+        );
+        assert_eq!(owners.owners_of("README.md"), ["lead"]);
+        assert_eq!(owners.owners_of("venus/src/lib.rs"), ["alice"]);
+        assert_eq!(owners.owners_of("docs/api.md"), ["bob", "carol"]);
+        assert_eq!(owners.owners_of("docs/src/main.rs"), ["bob", "carol"]);
+        assert!(owners.owners_of("docs/vendor/lib.js").is_empty());
+        assert_eq!(owners.owners_of("gateway/router.rs"), ["dave"]);
+        assert_eq!(owners.owners_of("gateway/src/api/router.rs"), ["dave"]);
+        assert_eq!(owners.owners_of("src/build1/out.txt"), ["erin"]);
+        // a directory pattern doesn't match a file of that name
+        assert_eq!(owners.owners_of("src/build1"), ["lead"]);
+        assert!(CodeOwners::parse("").owners_of("a.rs").is_empty());
+    }
+
+    #[test]
+    fn test_approvals() {
+        let owners = CodeOwners::parse("*.rs @alice @bob\n/docs/ @carol\n");
+        let approvals = owners.approvals(
+            ["src/lib.rs", "docs/api.md", "src/main.rs", "README.md"],
+            &["Bob".to_owned(), "dave".to_owned()],
+        );
+        assert_eq!(approvals.len(), 2);
+        assert_eq!(approvals[0].owners, ["alice", "bob"]);
+        assert_eq!(approvals[0].paths, ["src/lib.rs", "src/main.rs"]);
+        assert_eq!(approvals[0].approved_by, ["Bob"]);
+        assert!(approvals[0].approved);
+        assert_eq!(approvals[1].owners, ["carol"]);
+        assert!(!approvals[1].approved);
+    }
+}
@@ -0,0 +1,126 @@
+//! Diff statistics of two trees, for comparing refs.
+//!
+//! Files are compared line by line with the diff engine used by blame and merge; a file which
+//! isn't text on either side is reported as binary without counting its lines.
+//!
+use std::collections::BTreeSet;
+
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+
+use venus::hash::SHA1;
+use venus::internal::diff::{self, DiffOp};
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::object_loader::ObjectLoader;
+use crate::model::merge::FileStat;
+
+/// Lines added and removed turning `old` into `new`, `None` when either is not text.
+pub fn line_stat(old: &[u8], new: &[u8]) -> Option<(usize, usize)> {
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return None;
+    };
+    if old.contains('\0') || new.contains('\0') {
+        return None;
+    }
+    let ops = diff::diff(&diff::split_lines(old), &diff::split_lines(new));
+    Some(ops.iter().fold((0, 0), |(added, removed), op| match op {
+        DiffOp::Insert { new_len, .. } => (added + new_len, removed),
+        DiffOp::Delete { old_len, .. } => (added, removed + old_len),
+        DiffOp::Equal { .. } => (added, removed),
+    }))
+}
+
+/// `item` when it is a file, not a tree.
+fn file(item: Option<&TreeItem>) -> Option<&TreeItem> {
+    item.filter(|i| i.mode != TreeItemMode::Tree)
+}
+
+/// Collect the files changed from tree `old` to tree `new` in `files`, ordered by path. A missing
+/// tree has no files. `prefix` is the path of the trees.
+pub fn diff_trees<'a>(
+    loader: &'a mut ObjectLoader,
+    prefix: String,
+    old: Option<SHA1>,
+    new: Option<SHA1>,
+    files: &'a mut Vec<FileStat>,
+) -> BoxFuture<'a, Result<(), (StatusCode, String)>> {
+    Box::pin(async move {
+        let old = match old {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let new = match new {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let names: BTreeSet<&String> = old.iter().chain(new.iter()).map(|i| &i.name).collect();
+        for name in names {
+            let o = old.iter().find(|i| &i.name == name);
+            let n = new.iter().find(|i| &i.name == name);
+            if let (Some(o), Some(n)) = (o, n) {
+                if o.id == n.id && o.mode == n.mode {
+                    continue;
+                }
+            }
+            let path = format!("{}{}", prefix, name);
+            let subtree = |item: Option<&TreeItem>| {
+                item.filter(|i| i.mode == TreeItemMode::Tree).map(|i| i.id)
+            };
+            let (old_tree, new_tree) = (subtree(o), subtree(n));
+            if old_tree.is_some() || new_tree.is_some() {
+                diff_trees(loader, format!("{}/", path), old_tree, new_tree, files).await?;
+            }
+
+            let (o, n) = (file(o), file(n));
+            let status = match (o, n) {
+                (None, None) => continue,
+                (None, Some(_)) => "added",
+                (Some(_), None) => "deleted",
+                (Some(_), Some(_)) => "modified",
+            };
+            let stat = if o.map(|o| o.id) == n.map(|n| n.id) {
+                // only the mode changed
+                Some((0, 0))
+            } else {
+                let old_data = file_data(loader, o).await?;
+                let new_data = file_data(loader, n).await?;
+                line_stat(&old_data, &new_data)
+            };
+            let (additions, deletions) = stat.unwrap_or_default();
+            files.push(FileStat {
+                path,
+                status: status.to_owned(),
+                additions,
+                deletions,
+                binary: stat.is_none(),
+            });
+        }
+        Ok(())
+    })
+}
+
+/// The content of a file, nothing for a missing file or a submodule.
+async fn file_data(
+    loader: &mut ObjectLoader,
+    item: Option<&TreeItem>,
+) -> Result<Vec<u8>, (StatusCode, String)> {
+    match item {
+        Some(item) if item.mode != TreeItemMode::Commit => loader.blob(&item.id).await,
+        _ => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::line_stat;
+
+    #[test]
+    fn test_line_stat() {
+        assert_eq!(line_stat(b"a\nb\nc\n", b"a\nB\nc\nd\n"), Some((2, 1)));
+        assert_eq!(line_stat(b"", b"a\nb"), Some((2, 0)));
+        assert_eq!(line_stat(b"a\n", b""), Some((0, 1)));
+        assert_eq!(line_stat(b"a\n", b"\0\x01"), None);
+        assert_eq!(line_stat(&[0xff, 0xfe], b"a\n"), None);
+    }
+}
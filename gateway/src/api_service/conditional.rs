@@ -0,0 +1,141 @@
+//! Conditional requests on the read APIs, so clients and caches keep what they read.
+//!
+//! A response made of objects gets an `ETag` built from their ids, and from the options
+//! changing how they are shown. A call sending it back in `If-None-Match` gets a `304 Not
+//! Modified` instead of the response, once the objects are found. Objects never change, so a
+//! response for ids the caller named may be kept for a year without asking again; one for a ref
+//! or a path, which moves to other objects, is kept but checked on every use.
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use venus::hash::SHA1;
+
+/// Cache-Control of responses for objects named by their ids.
+const IMMUTABLE: &str = "max-age=31536000, immutable";
+/// Cache-Control of responses for what a ref or a path points to.
+const REVALIDATE: &str = "no-cache";
+
+/// The `ETag` of a response, and whether what it is for can change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Validator {
+    etag: String,
+    immutable: bool,
+}
+
+impl Validator {
+    /// The validator of a response showing `object_id` as it is, tagged with the id itself.
+    pub fn object(object_id: &SHA1, immutable: bool) -> Self {
+        Validator {
+            etag: format!("\"{}\"", object_id.to_plain_str()),
+            immutable,
+        }
+    }
+
+    /// The validator of a response made of several objects or showing them in some way, tagged
+    /// with the hash of the ids and options in `parts`.
+    pub fn of(parts: &[&str], immutable: bool) -> Self {
+        // no path or option holds a NUL
+        let key = parts.join("\0").into_bytes();
+        Validator::object(&SHA1::new(&key), immutable)
+    }
+
+    /// Whether the `If-None-Match` of `headers` lists this tag, by the weak comparison it calls
+    /// for, or is `*`. `*` matches anything that exists, so the response must be known to be
+    /// found before asking.
+    pub fn matches(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag)
+    }
+
+    /// `304 Not Modified` when `headers` match this tag.
+    pub fn check(&self, headers: &HeaderMap) -> Option<Response> {
+        self.matches(headers)
+            .then(|| (StatusCode::NOT_MODIFIED, self.headers()).into_response())
+    }
+
+    /// `response` with this tag and how long it may be cached.
+    pub fn respond(&self, response: impl IntoResponse) -> Response {
+        (self.headers(), response).into_response()
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let cache_control = if self.immutable {
+            IMMUTABLE
+        } else {
+            REVALIDATE
+        };
+        let mut headers = HeaderMap::new();
+        // the tag is a quoted hash, always a valid header value
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        );
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOB_ID: &str = "8ab686eafeb1f44702738c8b0f24f2567c36da6d";
+
+    fn blob_id() -> SHA1 {
+        BLOB_ID.parse().unwrap()
+    }
+
+    fn if_none_match(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header::IF_NONE_MATCH, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_matches() {
+        let validator = Validator::object(&blob_id(), true);
+        assert_eq!(validator.etag, format!("\"{}\"", BLOB_ID));
+        assert!(validator.matches(&if_none_match(&[&format!("\"{}\"", BLOB_ID)])));
+        assert!(validator.matches(&if_none_match(&[&format!("\"a\", W/\"{}\"", BLOB_ID)])));
+        assert!(validator.matches(&if_none_match(&["\"a\"", &format!("\"{}\"", BLOB_ID)])));
+        assert!(validator.matches(&if_none_match(&["*"])));
+        assert!(!validator.matches(&if_none_match(&[BLOB_ID])));
+        assert!(!validator.matches(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_of() {
+        let html = Validator::of(&[BLOB_ID, "src/main.rs", "html"], true);
+        assert_eq!(html, Validator::of(&[BLOB_ID, "src/main.rs", "html"], true));
+        assert_ne!(
+            html,
+            Validator::of(&[BLOB_ID, "src/main.rs", "tokens"], true)
+        );
+        assert_ne!(html, Validator::of(&[BLOB_ID, "src/lib.rs", "html"], true));
+    }
+
+    #[test]
+    fn test_respond() {
+        let validator = Validator::object(&blob_id(), false);
+        let response = validator.respond("data");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], validator.etag);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+
+        let headers = if_none_match(&[&validator.etag]);
+        let response = validator.check(&headers).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], validator.etag);
+        assert!(Validator::object(&blob_id(), true)
+            .check(&HeaderMap::new())
+            .is_none());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+
+use common::utils::{generate_id, ZERO_ID};
+use db_entity::{db_enums::MergeStatus, mega_diffstat, mega_event, mega_mr};
+use jupiter::storage::diffstat_storage::DiffstatStorage;
+use jupiter::storage::mr_storage::{MrFilter, MrStorage};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+
+use crate::api_service::compare;
+use crate::api_service::event_service::{EVENT_MERGE_REQUEST, EVENT_PUSH};
+use crate::api_service::merge::Merger;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::ref_hook::{RefChange, RefHook};
+use crate::model::merge::DiffStat;
+use crate::model::mr::MergeRequest;
+
+/// Most commits of a push given a stat, the newest first.
+const MAX_PUSH_COMMITS: usize = 100;
+
+/// Computes the diff statistics lists show, when commits are pushed and merge requests opened,
+/// and stores them with [`DiffstatStorage`].
+#[derive(Clone)]
+pub struct DiffstatService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub diffstat_storage: DiffstatStorage,
+    pub mr_storage: MrStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+impl DiffstatService {
+    /// Give a stat to the commits a push brought, and replace the stats of the active merge
+    /// requests from the branch pushed to.
+    pub async fn on_push(&self, change: &RefChange) -> Result<(), (StatusCode, String)> {
+        let Some(after) = &change.after else {
+            return Ok(());
+        };
+        let head = SHA1::from_str(after).map_err(internal_error)?;
+        self.save_commit_stats(&change.repo_path, head, change.before.as_deref())
+            .await?;
+        let mrs = self
+            .mr_storage
+            .list_mrs(MrFilter {
+                repo_path: Some(&change.repo_path),
+                source_ref: Some(&change.ref_name),
+                statuses: MergeStatus::ACTIVE.to_vec(),
+                ..Default::default()
+            })
+            .await
+            .map_err(internal_error)?;
+        for mr in mrs {
+            self.save_mr_stat(&mr).await?;
+        }
+        Ok(())
+    }
+
+    /// Walk the history of `head` back to `before`, storing the stat of every commit without
+    /// one; the history of a commit with a stat has them already.
+    async fn save_commit_stats(
+        &self,
+        repo_path: &str,
+        head: SHA1,
+        before: Option<&str>,
+    ) -> Result<(), (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([head]);
+        while let Some(id) = queue.pop_front() {
+            if seen.len() == MAX_PUSH_COMMITS {
+                break;
+            }
+            let commit_id = id.to_plain_str();
+            if !seen.insert(id) || before == Some(commit_id.as_str()) {
+                continue;
+            }
+            let stored = self
+                .diffstat_storage
+                .commit_stats(repo_path, std::slice::from_ref(&commit_id))
+                .await
+                .map_err(internal_error)?;
+            if !stored.is_empty() {
+                continue;
+            }
+            let commit = loader.commit(&id).await?;
+            let parent = commit.parent_commit_ids.first().copied();
+            let parent_tree = match parent {
+                Some(parent) => Some(loader.commit(&parent).await?.tree_id),
+                None => None,
+            };
+            let mut files = Vec::new();
+            compare::diff_trees(
+                &mut loader,
+                String::new(),
+                parent_tree,
+                Some(commit.tree_id),
+                &mut files,
+            )
+            .await?;
+            let stat = stat_model(repo_path, None, parent, id, DiffStat::of(&files));
+            self.diffstat_storage
+                .save_commit_stat(stat)
+                .await
+                .map_err(internal_error)?;
+            queue.extend(commit.parent_commit_ids);
+        }
+        Ok(())
+    }
+
+    /// Replace the stat of a merge request with the changes of the head of its source branch
+    /// since the merge base with its target.
+    pub async fn save_mr_stat(&self, mr: &mega_mr::Model) -> Result<(), (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let target = loader
+            .resolve_ref(&mr.repo_path, Some(&mr.target_ref))
+            .await?;
+        let source = loader
+            .resolve_ref(&mr.repo_path, Some(&mr.source_ref))
+            .await?;
+        let base = Merger::new(self.storage.clone())
+            .merge_base(&target, &source)
+            .await?;
+        let base_tree = match base {
+            Some(base) => Some(loader.commit(&base).await?.tree_id),
+            None => None,
+        };
+        let head_tree = loader.commit(&source).await?.tree_id;
+        let mut files = Vec::new();
+        compare::diff_trees(
+            &mut loader,
+            String::new(),
+            base_tree,
+            Some(head_tree),
+            &mut files,
+        )
+        .await?;
+        let stat = stat_model(
+            &mr.repo_path,
+            Some(mr.id),
+            base,
+            source,
+            DiffStat::of(&files),
+        );
+        self.diffstat_storage
+            .save_mr_stat(stat)
+            .await
+            .map_err(internal_error)
+    }
+}
+
+fn stat_model(
+    repo_path: &str,
+    mr_id: Option<i64>,
+    base: Option<SHA1>,
+    head: SHA1,
+    stat: DiffStat,
+) -> mega_diffstat::Model {
+    mega_diffstat::Model {
+        id: generate_id(),
+        repo_path: repo_path.to_owned(),
+        mr_id,
+        base_id: base.map_or_else(|| ZERO_ID.to_owned(), |id| id.to_plain_str()),
+        head_id: head.to_plain_str(),
+        files_changed: stat.files_changed as i32,
+        additions: stat.additions as i64,
+        deletions: stat.deletions as i64,
+        created_at: chrono::Utc::now().naive_utc(),
+    }
+}
+
+/// Keeps the diff statistics of pushed commits and of merge requests up to date.
+pub struct DiffstatHook {
+    pub service: DiffstatService,
+}
+
+#[async_trait]
+impl RefHook for DiffstatHook {
+    fn name(&self) -> &'static str {
+        "diffstats"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &[EVENT_PUSH, EVENT_MERGE_REQUEST]
+    }
+
+    async fn on_event(&self, event: &mega_event::Model) -> Result<(), String> {
+        if event.event_type == EVENT_PUSH {
+            return match RefChange::from_event(event) {
+                Some(change) => self.on_ref_update(&change).await,
+                None => Ok(()),
+            };
+        }
+        // pushes to the source branch keep the stat of a request current once it has one
+        if !matches!(event.action.as_str(), "opened" | "reopened") {
+            return Ok(());
+        }
+        let Ok(mr) = serde_json::from_str::<MergeRequest>(&event.payload) else {
+            return Ok(());
+        };
+        let model = self
+            .service
+            .mr_storage
+            .get_mr(mr.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        match model {
+            Some(model) if model.status.is_active() => {
+                self.service.save_mr_stat(&model).await.map_err(|(_, e)| e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn on_ref_update(&self, change: &RefChange) -> Result<(), String> {
+        self.service.on_push(change).await.map_err(|(_, e)| e)
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use common::utils::generate_id;
+use db_entity::{mega_erasure, mega_mailmap};
+use jupiter::storage::erasure_storage::ErasureStorage;
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::mailmap_storage::MailmapStorage;
+use jupiter::storage::signing_key_storage::SigningKeyStorage;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::api_service::mailmap::to_git_mailmap;
+use crate::model::erasure::{ErasureQuery, ErasureReport, ErasureRequest};
+
+/// Domain of the email shown in place of an erased user's commit emails, `.invalid` is reserved
+/// so it can't belong to anyone.
+const REDACTED_EMAIL_DOMAIN: &str = "redacted.invalid";
+
+/// Erases the personal data of users on request, keeping a report of each erasure.
+#[derive(Clone)]
+pub struct ErasureService {
+    pub storage: ErasureStorage,
+    pub mailmap_storage: MailmapStorage,
+    pub user_storage: UserStorage,
+    pub signing_key_storage: SigningKeyStorage,
+    pub event_storage: EventStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: &str) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg.to_owned())
+}
+
+pub fn subject_hash(username: &str) -> String {
+    format!("{:x}", Sha256::digest(username.as_bytes()))
+}
+
+/// Replace `username` and the `emails` of a user in the strings of an event payload. A string
+/// equal to the user name becomes `replacement`, and each email in a string becomes the email
+/// shown for it. Returns whether anything was replaced.
+pub fn redact_value(
+    value: &mut Value,
+    username: &str,
+    replacement: &str,
+    emails: &[(String, String)],
+) -> bool {
+    match value {
+        Value::String(s) => {
+            if s == username {
+                *s = replacement.to_owned();
+                return true;
+            }
+            let mut changed = false;
+            for (email, shown) in emails {
+                if s.contains(email.as_str()) {
+                    *s = s.replace(email.as_str(), shown);
+                    changed = true;
+                }
+            }
+            changed
+        }
+        Value::Array(values) => {
+            let mut changed = false;
+            for v in values {
+                changed |= redact_value(v, username, replacement, emails);
+            }
+            changed
+        }
+        Value::Object(map) => {
+            let mut changed = false;
+            for v in map.values_mut() {
+                changed |= redact_value(v, username, replacement, emails);
+            }
+            changed
+        }
+        _ => false,
+    }
+}
+
+impl ErasureService {
+    /// Erase the user's personal data: the account and its keys are removed, the user is named
+    /// by the replacement in issues, reviews, assignments and audit trails, event payloads are
+    /// redacted, and the user's commit emails are mapped to the replacement for display. The
+    /// erasure is recorded as `requested_by` the admin asking for it.
+    pub async fn erase(
+        &self,
+        request: ErasureRequest,
+        requested_by: &str,
+    ) -> Result<Json<ErasureReport>, (StatusCode, String)> {
+        let username = request.username.trim();
+        if username.is_empty() {
+            return Err(bad_request("username is required"));
+        }
+        let id = generate_id();
+        let replacement = match request.replacement.as_deref().map(str::trim) {
+            Some(r) if !r.is_empty() => r.to_owned(),
+            _ => format!("deleted-user-{}", id),
+        };
+        if replacement == username {
+            return Err(bad_request("replacement must differ from username"));
+        }
+        let shown_email = format!("{}@{}", replacement, REDACTED_EMAIL_DOMAIN);
+
+        // emails have to be collected before the account and its keys are gone
+        let mut emails: BTreeSet<String> = request
+            .emails
+            .iter()
+            .map(|e| e.trim().to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+        if let Some(user) = self
+            .user_storage
+            .get_user_by_name(username)
+            .await
+            .map_err(internal_error)?
+        {
+            emails.extend(user.email.map(|e| e.to_lowercase()));
+        }
+        for key in self
+            .signing_key_storage
+            .list_keys(username)
+            .await
+            .map_err(internal_error)?
+        {
+            emails.extend(key.emails.iter().map(|e| e.to_lowercase()));
+        }
+
+        let rows = self
+            .storage
+            .erase_user(username, &replacement)
+            .await
+            .map_err(internal_error)?;
+
+        let email_pairs: Vec<(String, String)> = emails
+            .iter()
+            .map(|e| (e.clone(), shown_email.clone()))
+            .collect();
+        let events_redacted = self
+            .redact_events(username, &replacement, &email_pairs)
+            .await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let entries: Vec<mega_mailmap::Model> = emails
+            .iter()
+            .map(|email| mega_mailmap::Model {
+                id: generate_id(),
+                email: email.clone(),
+                display_name: replacement.clone(),
+                display_email: shown_email.clone(),
+                created_at: now,
+            })
+            .collect();
+        self.mailmap_storage
+            .save_entries(entries)
+            .await
+            .map_err(internal_error)?;
+
+        let report = ErasureReport {
+            id,
+            subject_hash: subject_hash(username),
+            replacement,
+            requested_by: requested_by.to_owned(),
+            reason: request.reason.trim().to_owned(),
+            rows: rows
+                .into_iter()
+                .map(|(table, n)| (table.to_owned(), n))
+                .collect(),
+            events_redacted,
+            mailmap_entries: emails.len(),
+            created_at: now.to_string(),
+        };
+        self.storage
+            .save_erasure(mega_erasure::Model {
+                id,
+                subject_hash: report.subject_hash.clone(),
+                replacement: report.replacement.clone(),
+                requested_by: report.requested_by.clone(),
+                reason: report.reason.clone(),
+                report: serde_json::to_string(&report).map_err(internal_error)?,
+                created_at: now,
+            })
+            .await
+            .map_err(internal_error)?;
+        tracing::info!(
+            "erasure {} requested by {}: user {} is now {}, rows {:?}, {} events redacted",
+            report.id,
+            report.requested_by,
+            report.subject_hash,
+            report.replacement,
+            report.rows,
+            report.events_redacted
+        );
+        Ok(Json(report))
+    }
+
+    async fn redact_events(
+        &self,
+        username: &str,
+        replacement: &str,
+        emails: &[(String, String)],
+    ) -> Result<u64, (StatusCode, String)> {
+        let mut events = BTreeMap::new();
+        for text in std::iter::once(username).chain(emails.iter().map(|(e, _)| e.as_str())) {
+            for event in self
+                .event_storage
+                .events_mentioning(text)
+                .await
+                .map_err(internal_error)?
+            {
+                events.insert(event.id, event.payload);
+            }
+        }
+        let mut redacted = 0;
+        for (id, payload) in events {
+            let Ok(mut value) = serde_json::from_str::<Value>(&payload) else {
+                continue;
+            };
+            if redact_value(&mut value, username, replacement, emails) {
+                self.event_storage
+                    .update_payload(id, value.to_string())
+                    .await
+                    .map_err(internal_error)?;
+                redacted += 1;
+            }
+        }
+        Ok(redacted)
+    }
+
+    pub async fn list_erasures(
+        &self,
+        query: ErasureQuery,
+    ) -> Result<Json<Vec<ErasureReport>>, (StatusCode, String)> {
+        let hash = query.username.as_deref().map(|u| subject_hash(u.trim()));
+        let erasures = self
+            .storage
+            .list_erasures(hash.as_deref())
+            .await
+            .map_err(internal_error)?;
+        let reports = erasures
+            .iter()
+            .map(|e| serde_json::from_str(&e.report))
+            .collect::<Result<_, _>>()
+            .map_err(internal_error)?;
+        Ok(Json(reports))
+    }
+
+    pub async fn get_erasure(&self, id: i64) -> Result<Json<ErasureReport>, (StatusCode, String)> {
+        let erasure = self
+            .storage
+            .get_erasure(id)
+            .await
+            .map_err(internal_error)?
+            .ok_or((StatusCode::NOT_FOUND, format!("erasure {} not found", id)))?;
+        Ok(Json(
+            serde_json::from_str(&erasure.report).map_err(internal_error)?,
+        ))
+    }
+
+    pub async fn git_mailmap(&self) -> Result<String, (StatusCode, String)> {
+        let entries = self
+            .mailmap_storage
+            .list_entries()
+            .await
+            .map_err(internal_error)?;
+        Ok(to_git_mailmap(&entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::redact_value;
+
+    #[test]
+    fn test_redact_value() {
+        let emails = vec![(
+            "alice@example.com".to_owned(),
+            "ghost@redacted.invalid".to_owned(),
+        )];
+        let mut value = json!({
+            "author": "alice",
+            "assignees": ["bob", "alice"],
+            "title": "alice's crash",
+            "committer": "Alice <alice@example.com>",
+            "number": 3,
+        });
+        assert!(redact_value(&mut value, "alice", "ghost", &emails));
+        assert_eq!(
+            value,
+            json!({
+                "author": "ghost",
+                "assignees": ["bob", "ghost"],
+                "title": "alice's crash",
+                "committer": "Alice <ghost@redacted.invalid>",
+                "number": 3,
+            })
+        );
+        assert!(!redact_value(
+            &mut json!({"author": "bob"}),
+            "alice",
+            "ghost",
+            &emails
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+//! The same error body for the whole API, whichever module the error comes from.
+//!
+//! Services report errors as a status and a message. [`typed_errors`] turns those, and the plain
+//! text rejections of axum's extractors, into an [`ApiError`] on the way out, so clients parse a
+//! single error shape: a `code` to branch on, a `message` to show, and `details` for errors which
+//! carry more.
+use axum::{
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::model::error::ApiError;
+
+/// Largest error body [`typed_errors`] reads the message of.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+/// Turn the error responses of the API which aren't JSON, plain text from the services or from
+/// axum rejecting a call, and empty ones, into [`ApiError`] bodies. Their headers are kept.
+pub async fn typed_errors(response: Response) -> Response {
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) || is_json(response.headers()) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let message = match axum::body::to_bytes(body, MAX_MESSAGE).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_owned(),
+        Err(_) => String::new(),
+    };
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    (parts, Json(ApiError::new(status, message))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::model::error::code;
+
+    async fn body(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), MAX_MESSAGE)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(code(StatusCode::NOT_FOUND), "not_found");
+        assert_eq!(
+            code(StatusCode::INTERNAL_SERVER_ERROR),
+            "internal_server_error"
+        );
+        assert_eq!(code(StatusCode::IM_A_TEAPOT), "im_a_teapot");
+        assert_eq!(code(StatusCode::from_u16(499).unwrap()), "status_499");
+    }
+
+    #[tokio::test]
+    async fn test_typed_errors() {
+        let response = (StatusCode::CONFLICT, "mr 3 is not open".to_owned()).into_response();
+        let response = typed_errors(response).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(is_json(response.headers()));
+        assert_eq!(
+            body(response).await,
+            serde_json::json!({ "code": "conflict", "message": "mr 3 is not open" })
+        );
+
+        let response = typed_errors(StatusCode::NOT_FOUND.into_response()).await;
+        assert_eq!(body(response).await["message"], "Not Found");
+
+        let error = ApiError::new(StatusCode::BAD_REQUEST, "bad")
+            .with_details(serde_json::json!({ "field": "title" }));
+        let response = typed_errors(error.into_response()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body(response).await["details"]["field"], "title");
+
+        let response = typed_errors(Response::new(Body::from("fine"))).await;
+        assert!(!is_json(response.headers()));
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use serde::Serialize;
+
+use common::utils::{generate_id, ZERO_ID};
+use db_entity::mega_event;
+use git::protocol::{CommandType, RefCommand};
+use jupiter::storage::event_storage::EventStorage;
+
+use crate::model::event::{EventQuery, RefPush, RepoEvent};
+
+pub const EVENT_PUSH: &str = "push";
+pub const EVENT_MERGE_REQUEST: &str = "merge_request";
+pub const EVENT_ISSUE: &str = "issue";
+pub const EVENT_COMMENT: &str = "comment";
+
+/// How often streams look for new events.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Most events loaded by one look.
+const BATCH_SIZE: u64 = 100;
+
+/// Days events are kept so clients can catch up after a disconnect, unless
+/// `MEGA_EVENT_RETENTION_DAYS` says otherwise. Payloads name the people involved, so events
+/// aren't kept longer than needed.
+const DEFAULT_RETENTION_DAYS: i64 = 7;
+
+/// How often events past their retention are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Records what happens in repositories and streams it to clients as server-sent events.
+#[derive(Clone)]
+pub struct EventService {
+    pub storage: EventStorage,
+}
+
+fn retention() -> chrono::Duration {
+    let days = std::env::var("MEGA_EVENT_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    chrono::Duration::days(days)
+}
+
+/// The event types a stream asks for, all of them when the list is empty.
+pub fn parse_types(types: Option<&str>) -> Vec<String> {
+    types
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl EventService {
+    /// Record an event. Events are a side effect of the change they describe, so a failure is
+    /// only logged.
+    pub async fn publish(
+        &self,
+        event_type: &str,
+        action: &str,
+        repo_path: &str,
+        actor: Option<&str>,
+        payload: &impl Serialize,
+    ) {
+        let payload = match serde_json::to_string(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::warn!("unable to encode {} event: {}", event_type, e);
+                return;
+            }
+        };
+        let event = mega_event::Model {
+            id: generate_id(),
+            event_type: event_type.to_owned(),
+            action: action.to_owned(),
+            repo_path: repo_path.to_owned(),
+            actor: actor.map(str::to_owned),
+            payload,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        if let Err(e) = self.storage.save_event(event).await {
+            tracing::warn!("unable to record {} event: {}", event_type, e);
+        }
+    }
+
+    /// Record a push event for every ref a push moved.
+    pub async fn publish_push(
+        &self,
+        repo_path: &str,
+        commands: &[RefCommand],
+        actor: Option<&str>,
+    ) {
+        for command in commands.iter().filter(|c| c.status == "ok") {
+            let action = match command.command_type {
+                CommandType::Create => "created",
+                CommandType::Update => "updated",
+                CommandType::Delete => "deleted",
+            };
+            let id = |id: &str| (id != ZERO_ID).then(|| id.to_owned());
+            let push = RefPush {
+                ref_name: command.ref_name.clone(),
+                before: id(&command.old_id),
+                after: id(&command.new_id),
+                reason: None,
+            };
+            self.publish(EVENT_PUSH, action, repo_path, actor, &push)
+                .await;
+        }
+    }
+
+    /// Stream the events matching `query` as they happen.
+    ///
+    /// Every event is sent with its type as the event name and its id as the event id, so a
+    /// client reconnecting with `Last-Event-ID` gets the events it missed. Without a starting
+    /// point only events recorded after connecting are sent.
+    pub async fn stream(
+        &self,
+        query: EventQuery,
+        last_event_id: Option<i64>,
+    ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+        let after = match last_event_id.or(query.after) {
+            Some(after) => after,
+            None => self
+                .storage
+                .latest_id()
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .unwrap_or(0),
+        };
+        let types = parse_types(query.types.as_deref());
+        let state = (self.clone(), query.repo_path, types, after, VecDeque::new());
+        let stream = futures::stream::unfold(
+            state,
+            |(service, repo_path, types, mut after, mut pending)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        let event = to_sse(event);
+                        return Some((Ok(event), (service, repo_path, types, after, pending)));
+                    }
+                    match service
+                        .storage
+                        .events_after(after, repo_path.as_deref(), &types, BATCH_SIZE)
+                        .await
+                    {
+                        Ok(events) if !events.is_empty() => {
+                            after = events.last().map_or(after, |e| e.id);
+                            pending.extend(events);
+                        }
+                        Ok(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                        Err(e) => {
+                            tracing::warn!("unable to load events: {}", e);
+                            tokio::time::sleep(POLL_INTERVAL).await;
+                        }
+                    }
+                }
+            },
+        );
+        Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    }
+
+    pub fn start_cleanup(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let before = chrono::Utc::now().naive_utc() - retention();
+                if let Err(e) = self.storage.delete_before(before).await {
+                    tracing::warn!("unable to remove old events: {}", e);
+                }
+            }
+        });
+    }
+}
+
+fn to_sse(event: mega_event::Model) -> Event {
+    let id = event.id.to_string();
+    let name = event.event_type.clone();
+    let data = serde_json::to_string(&RepoEvent::from(event)).unwrap_or_default();
+    Event::default().id(id).event(name).data(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_types;
+
+    #[test]
+    fn test_parse_types() {
+        assert_eq!(
+            parse_types(Some("push, merge_request,,")),
+            vec!["push", "merge_request"]
+        );
+        assert!(parse_types(None).is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_feature_flag;
+use jupiter::storage::feature_flag_storage::FeatureFlagStorage;
+
+use crate::model::feature_flag::{FeatureFlag, FeatureFlagStatus, FeatureFlagUpdate};
+
+#[derive(Clone)]
+pub struct FeatureFlagService {
+    pub storage: FeatureFlagStorage,
+}
+
+impl FeatureFlagService {
+    pub async fn list_flags(&self) -> Result<Json<Vec<FeatureFlag>>, (StatusCode, String)> {
+        let flags = self
+            .storage
+            .list_flags()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(Json(flags.into_iter().map(FeatureFlag::from).collect()))
+    }
+
+    pub async fn save_flag(
+        &self,
+        name: String,
+        update: FeatureFlagUpdate,
+    ) -> Result<Json<FeatureFlag>, (StatusCode, String)> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("invalid feature flag name: {}", name),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let flag = mega_feature_flag::Model {
+            id: generate_id(),
+            name: name.clone(),
+            description: update.description,
+            enabled: update.enabled,
+            enabled_orgs: update.enabled_orgs,
+            disabled_orgs: update.disabled_orgs,
+            created_at: now,
+            updated_at: now,
+        };
+        self.storage
+            .save_flag(flag)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        match self.storage.get_flag(&name).await {
+            Ok(Some(saved)) => Ok(Json(saved.into())),
+            Ok(None) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("feature flag {} was not saved", name),
+            )),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+    }
+
+    pub async fn delete_flag(&self, name: &str) -> Result<StatusCode, (StatusCode, String)> {
+        match self.storage.delete_flag(name).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("feature flag {} not found", name),
+            )),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+    }
+
+    pub async fn flag_status(
+        &self,
+        name: String,
+        org: Option<String>,
+    ) -> Result<Json<FeatureFlagStatus>, (StatusCode, String)> {
+        let enabled = self.storage.is_enabled(&name, org.as_deref()).await;
+        Ok(Json(FeatureFlagStatus { name, org, enabled }))
+    }
+}
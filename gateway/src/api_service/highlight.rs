@@ -0,0 +1,255 @@
+//! Server side syntax highlighting of blobs with syntect, for clients which don't highlight
+//! code themselves.
+//!
+//! The syntax of a blob is the one asked for, or found from the extension or name of its path,
+//! or from its first line (a shebang such as `#!/usr/bin/env python3`, or an editor modeline),
+//! plain text otherwise. Blobs never change, so what a blob was highlighted into is cached by
+//! its id, syntax and format.
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use syntect::easy::ScopeRegionIterator;
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::model::objects::{HighlightFormat, HighlightToken, Highlighted};
+
+/// Largest blob highlighted, parsing is linear in the size of the content but slow.
+pub const MAX_HIGHLIGHT_SIZE: usize = 1024 * 1024;
+
+/// Most highlighted blobs kept, the oldest is dropped first.
+const CACHE_ENTRIES: usize = 256;
+
+/// The syntaxes syntect ships with, loaded once.
+pub fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// The syntax to highlight `content` with: the one named `language`, else the one of the file
+/// name or extension of `path`, else the one the first line of `content` announces.
+pub fn detect_syntax<'a>(
+    syntaxes: &'a SyntaxSet,
+    path: Option<&str>,
+    language: Option<&str>,
+    content: &str,
+) -> &'a SyntaxReference {
+    if let Some(syntax) = language.and_then(|l| syntaxes.find_syntax_by_token(l)) {
+        return syntax;
+    }
+    let file_name = path.and_then(|p| p.rsplit('/').next()).unwrap_or_default();
+    let by_name = || syntaxes.find_syntax_by_extension(file_name);
+    let by_extension = || {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        syntaxes.find_syntax_by_extension(extension)
+    };
+    let by_first_line = || syntaxes.find_syntax_by_first_line(content.lines().next()?);
+    by_name()
+        .or_else(by_extension)
+        .or_else(by_first_line)
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
+
+/// Split `content` into lines of tokens with the scopes syntect gives them. Adjacent text with
+/// the same scopes is one token, and line endings are left out.
+pub fn tokenize(
+    syntaxes: &SyntaxSet,
+    syntax: &SyntaxReference,
+    content: &str,
+) -> Result<Vec<Vec<HighlightToken>>, String> {
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(content) {
+        let ops = state
+            .parse_line(line, syntaxes)
+            .map_err(|e| e.to_string())?;
+        let mut tokens: Vec<HighlightToken> = Vec::new();
+        for (text, op) in ScopeRegionIterator::new(&ops, line) {
+            stack.apply(op).map_err(|e| format!("{:?}", e))?;
+            let text = text.trim_end_matches(['\n', '\r']);
+            if text.is_empty() {
+                continue;
+            }
+            let scopes: Vec<String> = stack.as_slice().iter().map(|s| s.build_string()).collect();
+            match tokens.last_mut() {
+                Some(last) if last.scopes == scopes => last.text.push_str(text),
+                _ => tokens.push(HighlightToken {
+                    text: text.to_owned(),
+                    scopes,
+                }),
+            }
+        }
+        lines.push(tokens);
+    }
+    Ok(lines)
+}
+
+/// Append `text` to `out` with the characters special to HTML escaped.
+pub fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// A line of tokens as HTML, each token in a `<span>` per scope, whose classes are the atoms of
+/// the scope like syntect's spaced class style. Every line closes the spans it opens.
+pub fn line_html(tokens: &[HighlightToken]) -> String {
+    let mut html = String::new();
+    for token in tokens {
+        for scope in &token.scopes {
+            html.push_str("<span class=\"");
+            escape_html(&scope.replace('.', " "), &mut html);
+            html.push_str("\">");
+        }
+        escape_html(&token.text, &mut html);
+        for _ in &token.scopes {
+            html.push_str("</span>");
+        }
+    }
+    html
+}
+
+/// Highlight `content` with `syntax`.
+pub fn highlight(
+    syntaxes: &SyntaxSet,
+    syntax: &SyntaxReference,
+    content: &str,
+    format: HighlightFormat,
+) -> Result<Highlighted, String> {
+    let lines = tokenize(syntaxes, syntax, content)?;
+    let (html, tokens) = match format {
+        HighlightFormat::Html => (Some(lines.iter().map(|l| line_html(l)).collect()), None),
+        HighlightFormat::Tokens => (None, Some(lines)),
+    };
+    Ok(Highlighted {
+        language: syntax.name.clone(),
+        html,
+        tokens,
+    })
+}
+
+type CacheKey = (String, String, HighlightFormat);
+
+#[derive(Default)]
+struct HighlightCache {
+    entries: HashMap<CacheKey, Highlighted>,
+    order: VecDeque<CacheKey>,
+}
+
+/// Highlights blobs and keeps the latest ones highlighted.
+#[derive(Clone, Default)]
+pub struct Highlighter {
+    cache: Arc<Mutex<HighlightCache>>,
+}
+
+impl Highlighter {
+    /// Highlight the blob `blob_id` with `content`, `None` when it is larger than
+    /// [`MAX_HIGHLIGHT_SIZE`]. Parsing runs on the blocking pool.
+    pub async fn highlight_blob(
+        &self,
+        blob_id: &str,
+        content: &str,
+        path: Option<&str>,
+        language: Option<&str>,
+        format: HighlightFormat,
+    ) -> Result<Option<Highlighted>, String> {
+        if content.len() > MAX_HIGHLIGHT_SIZE {
+            return Ok(None);
+        }
+        let syntaxes = syntaxes();
+        let syntax = detect_syntax(syntaxes, path, language, content);
+        let key = (blob_id.to_owned(), syntax.name.clone(), format);
+        if let Some(highlighted) = self.cache.lock().unwrap().entries.get(&key) {
+            return Ok(Some(highlighted.clone()));
+        }
+        let name = syntax.name.clone();
+        let content = content.to_owned();
+        let highlighted = tokio::task::spawn_blocking(move || {
+            let syntax = syntaxes
+                .find_syntax_by_name(&name)
+                .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+            highlight(syntaxes, syntax, &content, format)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache
+            .entries
+            .insert(key.clone(), highlighted.clone())
+            .is_none()
+        {
+            cache.order.push_back(key);
+            while cache.order.len() > CACHE_ENTRIES {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.entries.remove(&oldest);
+                }
+            }
+        }
+        Ok(Some(highlighted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_syntax() {
+        let syntaxes = syntaxes();
+        let name = |path: Option<&str>, language: Option<&str>, content: &str| {
+            detect_syntax(syntaxes, path, language, content)
+                .name
+                .clone()
+        };
+        assert_eq!(name(Some("src/main.rs"), None, ""), "Rust");
+        assert_eq!(name(Some("Makefile"), None, ""), "Makefile");
+        assert_eq!(
+            name(Some("bin/tool"), None, "#!/usr/bin/env python3\n"),
+            "Python"
+        );
+        assert_eq!(name(Some("notes.txt"), Some("rust"), ""), "Rust");
+        assert_eq!(name(None, None, "just text\n"), "Plain Text");
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let syntaxes = syntaxes();
+        let syntax = syntaxes.find_syntax_by_extension("rs").unwrap();
+        let lines = tokenize(syntaxes, syntax, "fn main() {}\n// done\n").unwrap();
+        assert_eq!(lines.len(), 2);
+        let first: String = lines[0].iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(first, "fn main() {}");
+        let keyword = lines[0].iter().find(|t| t.text == "fn").unwrap();
+        assert_eq!(keyword.scopes[0], "source.rust");
+        assert!(keyword.scopes.len() > 1);
+        assert!(lines[1][0].scopes.iter().any(|s| s.starts_with("comment")));
+    }
+
+    #[test]
+    fn test_line_html() {
+        let tokens = vec![
+            HighlightToken {
+                text: "a < b".to_owned(),
+                scopes: vec!["source.rust".to_owned()],
+            },
+            HighlightToken {
+                text: "\"x\"".to_owned(),
+                scopes: vec!["source.rust".to_owned(), "string.quoted.rust".to_owned()],
+            },
+        ];
+        assert_eq!(
+            line_html(&tokens),
+            "<span class=\"source rust\">a &lt; b</span><span class=\"source rust\">\
+             <span class=\"string quoted rust\">&quot;x&quot;</span></span>"
+        );
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Json;
+use bytes::Bytes;
+
+use common::operation::{self, OperationKind};
+use common::utils::{generate_id, ZERO_ID};
+use db_entity::{mega_import, mega_import_job, mega_import_job_repo};
+use git::protocol::{PackProtocol, Protocol, RefCommand};
+use jupiter::storage::import_storage::{
+    ImportStorage, JOB_REPO_DONE, JOB_REPO_FAILED, JOB_REPO_PENDING,
+};
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::api_service::event_service::EventService;
+use crate::api_service::mr_service::DEFAULT_COMMITTER;
+use crate::api_service::path_move;
+use crate::api_service::path_move_service::ensure_free;
+use crate::api_service::remote::{self, ImportThrottle, RemoteClone};
+use crate::model::import::{
+    ImportJob, ImportJobRepo, ImportJobRequest, ImportJobRequeued, ImportQuery, ImportRequest,
+    RepoImport,
+};
+
+/// How often the runner looks for repositories of import jobs that are due.
+const RUNNER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Most repositories claimed in one look.
+const BATCH_SIZE: u64 = 50;
+
+/// Attempts at a repository of an import job before it fails for good.
+const MAX_ATTEMPTS: i32 = 5;
+
+const FIRST_RETRY: Duration = Duration::from_secs(60);
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// How long a repository is held by the server instance importing it. A repository still
+/// running after that is taken to be interrupted and imported again, from where it was.
+const LEASE: Duration = Duration::from_secs(30 * 60);
+
+/// Imports repositories from external Git remotes into the monorepo, one at a time or many in an
+/// import job which survives restarts of the server.
+#[derive(Clone)]
+pub struct ImportService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub import_storage: ImportStorage,
+    pub events: EventService,
+    pub throttle: ImportThrottle,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn after(delay: Duration) -> chrono::NaiveDateTime {
+    chrono::Utc::now().naive_utc() + chrono::Duration::from_std(delay).unwrap_or_default()
+}
+
+/// Time to wait before attempting a repository again after `attempts` failed.
+fn backoff(attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    FIRST_RETRY.saturating_mul(1 << doublings).min(MAX_RETRY)
+}
+
+/// The branches and tags of an interrupted import to create: those of `refs` not stored yet.
+/// Fails when a stored one no longer matches the remote, which moved it meanwhile.
+fn refs_to_create<'a>(
+    refs: &'a [(String, String)],
+    stored: &HashMap<String, String>,
+) -> Result<Vec<&'a (String, String)>, String> {
+    let mut missing = Vec::new();
+    for entry in refs {
+        match stored.get(&entry.0) {
+            None => missing.push(entry),
+            Some(id) if *id == entry.1 => {}
+            Some(_) => {
+                return Err(format!(
+                    "{} moved in the remote since the import was interrupted",
+                    entry.0
+                ))
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// Where a repository of an import job is, for the attempt importing it.
+struct JobProgress {
+    repo_id: i64,
+    /// Whether storing the repository started, in this attempt or an earlier one
+    storing: bool,
+}
+
+fn imported_by(name: Option<&str>) -> String {
+    match name.map(str::trim) {
+        Some(name) if !name.is_empty() => name.to_owned(),
+        _ => DEFAULT_COMMITTER.0.to_owned(),
+    }
+}
+
+impl ImportService {
+    /// Clone the repository at `request.url` and store its branches, tags and their history at
+    /// `request.path`, the same way a push of them to that path would. Where it came from is
+    /// recorded with the import.
+    pub async fn import(
+        &self,
+        request: ImportRequest,
+    ) -> Result<Json<RepoImport>, (StatusCode, String)> {
+        let url = request.url.trim();
+        remote::check_url(url).map_err(bad_request)?;
+        let repo_path = path_move::normalize_path(&request.path)
+            .filter(|p| p != "/")
+            .ok_or_else(|| bad_request(format!("invalid path: {}", request.path)))?;
+        ensure_free(self.storage.as_ref(), &repo_path).await?;
+        let imported_by = imported_by(request.imported_by.as_deref());
+
+        let _permit = self.throttle.acquire().await;
+        let id = generate_id();
+        let import = self
+            .import_repo(id, url, &repo_path, &imported_by, None)
+            .await?;
+        Ok(Json(import.into()))
+    }
+
+    /// Clone `url` as the import `id` and store it at `repo_path`. For a repository of an import
+    /// job which an earlier attempt started storing, the refs that attempt stored are kept and
+    /// the missing ones created, objects stored twice are ignored.
+    ///
+    /// The import is an operation which can be cancelled until it starts storing.
+    async fn import_repo(
+        &self,
+        id: i64,
+        url: &str,
+        repo_path: &str,
+        imported_by: &str,
+        mut job: Option<&mut JobProgress>,
+    ) -> Result<mega_import::Model, (StatusCode, String)> {
+        let operation = operation::start(OperationKind::Import, repo_path);
+        let resume = job.as_ref().is_some_and(|job| job.storing);
+        if job.is_some() && !resume {
+            ensure_free(self.storage.as_ref(), repo_path).await?;
+        }
+        let stored: HashMap<String, String> = if resume {
+            // stored and recorded, only the progress of the job wasn't
+            if let Some(import) = self
+                .import_storage
+                .find_import(repo_path)
+                .await
+                .map_err(internal_error)?
+            {
+                return Ok(import);
+            }
+            self.storage
+                .get_all_refs_by_path(repo_path)
+                .await
+                .map_err(internal_error)?
+                .into_iter()
+                .filter(|r| {
+                    r.ref_name.starts_with("refs/heads/") || r.ref_name.starts_with("refs/tags/")
+                })
+                .map(|r| (r.ref_name, r.ref_git_id))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        let clone_name = match &job {
+            Some(job) => format!("import-job-{}", job.repo_id),
+            None => format!("import-{}", id),
+        };
+        operation.set_stage("cloning", 0);
+        let clone = remote::cancellable(&operation, RemoteClone::fetch(url, &clone_name)).await?;
+        let refs = clone.refs().await?;
+        if refs.is_empty() {
+            return Err(bad_request(format!(
+                "{} has no branches or tags to import",
+                remote::strip_credentials(url)
+            )));
+        }
+        let missing = refs_to_create(&refs, &stored)
+            .map_err(|e| (StatusCode::CONFLICT, e))?;
+        let default_branch = clone
+            .head()
+            .await
+            .filter(|head| refs.iter().any(|(name, _)| name == head));
+        let wanted: Vec<&str> = refs.iter().map(|(_, id)| id.as_str()).collect();
+        operation.set_stage("packing", 0);
+        let pack = remote::cancellable(&operation, clone.pack(&wanted, &[])).await?;
+        drop(clone);
+        let object_count = remote::pack_object_count(&pack);
+        operation.check().map_err(remote::cancelled_error)?;
+        operation.set_stage("storing", missing.len() as u64);
+
+        if let Some(job) = job.as_mut().filter(|job| !job.storing) {
+            self.import_storage
+                .mark_storing(job.repo_id, after(LEASE))
+                .await
+                .map_err(internal_error)?;
+            job.storing = true;
+        }
+        if !missing.is_empty() {
+            let mut pack_protocol = PackProtocol::new(
+                PathBuf::from(repo_path),
+                self.storage.clone(),
+                Protocol::Local,
+            );
+            pack_protocol.command_list = missing
+                .iter()
+                .map(|(name, id)| RefCommand::new(ZERO_ID.to_owned(), id.clone(), name.clone()))
+                .collect();
+            let report = pack_protocol
+                .git_receive_pack(Bytes::from(pack))
+                .await
+                .map_err(internal_error)?;
+            if report.windows(3).any(|w| w == b"ng ") {
+                return Err(internal_error(format!(
+                    "unable to store the objects of {}",
+                    remote::strip_credentials(url)
+                )));
+            }
+            operation.advance(missing.len() as u64);
+            self.events
+                .publish_push(repo_path, &pack_protocol.command_list, Some(imported_by))
+                .await;
+        }
+
+        let head_commit = default_branch.as_ref().and_then(|head| {
+            refs.iter()
+                .find(|(name, _)| name == head)
+                .map(|(_, id)| id.clone())
+        });
+        let import = mega_import::Model {
+            id,
+            repo_path: repo_path.to_owned(),
+            source_url: remote::strip_credentials(url),
+            default_branch,
+            head_commit,
+            ref_count: refs.len() as i32,
+            object_count,
+            imported_by: imported_by.to_owned(),
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        self.import_storage
+            .save_import(import.clone())
+            .await
+            .map_err(internal_error)?;
+        tracing::info!(
+            "imported {} at {}: {} refs, {} objects",
+            import.source_url,
+            import.repo_path,
+            import.ref_count,
+            import.object_count
+        );
+        Ok(import)
+    }
+
+    pub async fn list(
+        &self,
+        query: ImportQuery,
+    ) -> Result<Json<Vec<RepoImport>>, (StatusCode, String)> {
+        let imports = match query.repo_path {
+            Some(repo_path) => self
+                .import_storage
+                .find_import(&repo_path)
+                .await
+                .map_err(internal_error)?
+                .into_iter()
+                .collect(),
+            None => self
+                .import_storage
+                .list_imports()
+                .await
+                .map_err(internal_error)?,
+        };
+        Ok(Json(imports.into_iter().map(RepoImport::from).collect()))
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Json<RepoImport>, (StatusCode, String)> {
+        match self
+            .import_storage
+            .get_import(id)
+            .await
+            .map_err(internal_error)?
+        {
+            Some(import) => Ok(Json(import.into())),
+            None => Err((StatusCode::NOT_FOUND, format!("import {} not found", id))),
+        }
+    }
+
+    /// Queue the import of every repository of `request`. The runner imports them in the
+    /// background as the throttle allows, retrying the ones failing, and a restarted server
+    /// continues where the job was.
+    pub async fn create_job(
+        &self,
+        request: ImportJobRequest,
+    ) -> Result<Json<ImportJob>, (StatusCode, String)> {
+        if request.repos.is_empty() {
+            return Err(bad_request("no repositories to import".to_owned()));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let job = mega_import_job::Model {
+            id: generate_id(),
+            imported_by: imported_by(request.imported_by.as_deref()),
+            created_at: now,
+        };
+        let mut paths = HashSet::new();
+        let mut repos = Vec::new();
+        for target in &request.repos {
+            let url = target.url.trim();
+            remote::check_url(url).map_err(bad_request)?;
+            let repo_path = path_move::normalize_path(&target.path)
+                .filter(|p| p != "/")
+                .ok_or_else(|| bad_request(format!("invalid path: {}", target.path)))?;
+            if !paths.insert(repo_path.clone()) {
+                return Err(bad_request(format!("{} is imported twice", repo_path)));
+            }
+            ensure_free(self.storage.as_ref(), &repo_path).await?;
+            repos.push(mega_import_job_repo::Model {
+                id: generate_id(),
+                job_id: job.id,
+                url: url.to_owned(),
+                repo_path,
+                status: JOB_REPO_PENDING.to_owned(),
+                attempts: 0,
+                started_storing: false,
+                next_attempt_at: Some(now),
+                last_error: None,
+                import_id: None,
+                updated_at: now,
+            });
+        }
+        self.import_storage
+            .save_job(job.clone(), repos.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(ImportJob::new(job, &repos)))
+    }
+
+    pub async fn list_jobs(&self) -> Result<Json<Vec<ImportJob>>, (StatusCode, String)> {
+        let mut jobs = Vec::new();
+        for job in self
+            .import_storage
+            .list_jobs()
+            .await
+            .map_err(internal_error)?
+        {
+            let repos = self
+                .import_storage
+                .job_repos(job.id)
+                .await
+                .map_err(internal_error)?;
+            jobs.push(ImportJob::new(job, &repos));
+        }
+        Ok(Json(jobs))
+    }
+
+    pub async fn get_job(&self, id: i64) -> Result<Json<ImportJob>, (StatusCode, String)> {
+        let job = self.find_job(id).await?;
+        let repos = self
+            .import_storage
+            .job_repos(id)
+            .await
+            .map_err(internal_error)?;
+        let mut import_job = ImportJob::new(job, &repos);
+        import_job.repos = repos.into_iter().map(ImportJobRepo::from).collect();
+        Ok(Json(import_job))
+    }
+
+    /// Attempt the failed repositories of the job again, and the ones interrupted without
+    /// waiting for their lease to run out. A repository still being imported by another server
+    /// instance may then be imported twice at once, which stores nothing twice.
+    pub async fn requeue_job(
+        &self,
+        id: i64,
+    ) -> Result<Json<ImportJobRequeued>, (StatusCode, String)> {
+        self.find_job(id).await?;
+        let requeued = self
+            .import_storage
+            .requeue_job(id, chrono::Utc::now().naive_utc())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(ImportJobRequeued { requeued }))
+    }
+
+    async fn find_job(&self, id: i64) -> Result<mega_import_job::Model, (StatusCode, String)> {
+        self.import_storage
+            .get_job(id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("import job {} not found", id)))
+    }
+
+    /// Import the repositories of import jobs every [`RUNNER_INTERVAL`] for as long as the
+    /// server runs, as many at once as the throttle allows.
+    pub fn start_runner(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RUNNER_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.run_due().await {
+                    tracing::warn!("unable to run import jobs: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn run_due(&self) -> Result<(), String> {
+        let due = self
+            .import_storage
+            .due_job_repos(chrono::Utc::now().naive_utc(), BATCH_SIZE)
+            .await
+            .map_err(|e| e.to_string())?;
+        for repo in due {
+            let permit = self.throttle.acquire().await;
+            if !self
+                .import_storage
+                .claim_job_repo(&repo, after(LEASE))
+                .await
+                .map_err(|e| e.to_string())?
+            {
+                continue;
+            }
+            let service = self.clone();
+            tokio::spawn(async move {
+                service.run_job_repo(repo).await;
+                drop(permit);
+            });
+        }
+        Ok(())
+    }
+
+    /// Import a claimed repository of a job and record how it went. Requests the remote refuses
+    /// or the monorepo can't take fail for good, the others are attempted again later.
+    async fn run_job_repo(&self, repo: mega_import_job_repo::Model) {
+        let attempts = repo.attempts + 1;
+        let imported_by = match self.import_storage.get_job(repo.job_id).await {
+            Ok(job) => job.map(|job| job.imported_by).unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("unable to load import job {}: {}", repo.job_id, e);
+                return;
+            }
+        };
+        let mut progress = JobProgress {
+            repo_id: repo.id,
+            storing: repo.started_storing,
+        };
+        let result = self
+            .import_repo(
+                generate_id(),
+                &repo.url,
+                &repo.repo_path,
+                &imported_by,
+                Some(&mut progress),
+            )
+            .await;
+        let recorded = match result {
+            Ok(import) => {
+                self.import_storage
+                    .update_job_repo(repo.id, JOB_REPO_DONE, None, None, Some(import.id))
+                    .await
+            }
+            Err((status, err)) => {
+                let give_up = status.is_client_error() || attempts >= MAX_ATTEMPTS;
+                tracing::warn!(
+                    "attempt {} at importing {} failed{}: {}",
+                    attempts,
+                    repo.repo_path,
+                    if give_up { " for good" } else { "" },
+                    err
+                );
+                let status = if give_up {
+                    JOB_REPO_FAILED
+                } else {
+                    JOB_REPO_PENDING
+                };
+                let next = (!give_up).then(|| after(backoff(attempts)));
+                self.import_storage
+                    .update_job_repo(repo.id, status, next, Some(err), None)
+                    .await
+            }
+        };
+        if let Err(e) = recorded {
+            tracing::warn!("unable to record the import of {}: {}", repo.repo_path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::{backoff, refs_to_create};
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(60));
+        assert_eq!(backoff(3), Duration::from_secs(240));
+        assert_eq!(backoff(100), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_refs_to_create() {
+        let refs = vec![
+            ("refs/heads/main".to_owned(), "a".repeat(40)),
+            ("refs/tags/v1".to_owned(), "b".repeat(40)),
+        ];
+        let mut stored = HashMap::from([("refs/heads/main".to_owned(), "a".repeat(40))]);
+        assert_eq!(refs_to_create(&refs, &stored).unwrap(), vec![&refs[1]]);
+
+        stored.insert("refs/heads/main".to_owned(), "c".repeat(40));
+        assert!(refs_to_create(&refs, &stored).is_err());
+    }
+}
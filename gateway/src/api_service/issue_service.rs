@@ -0,0 +1,488 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::{mega_issue, mega_issue_ref, mega_reference};
+use jupiter::storage::issue_storage::{IssueFilter, IssueStorage};
+use jupiter::storage::label_storage::ITEM_ISSUE;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::api_service::autolink::{AutolinkRules, Autolinker};
+use crate::api_service::event_service::{EventService, EVENT_ISSUE};
+use crate::api_service::page::{Order, SortKey};
+use crate::api_service::planning_service::PlanningService;
+use crate::api_service::reference::{self, Referencer};
+use crate::api_service::webhook;
+use crate::model::issue::{
+    Issue, IssueDetail, IssueQuery, IssueReference, IssueUpdate, NewIssue, ISSUE_CLOSED, ISSUE_OPEN,
+};
+use crate::model::page::{Page, PageQuery};
+use crate::model::planning::{ItemAssignees, ItemLabels, ItemMilestone};
+
+/// Verbs that close the issue they precede once the change is merged, as in `fixes #42`.
+const CLOSING_KEYWORDS: &[&str] = &[
+    "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+];
+
+/// Issues are listed newest first, unless `order_by` names one of these.
+const DEFAULT_ORDER: Order = Order::desc("created_at");
+const ISSUE_ORDERS: [&str; 2] = ["updated_at", "number"];
+
+pub const REF_SOURCE_COMMIT: &str = "commit";
+pub const REF_SOURCE_MR: &str = "mr";
+
+#[derive(Clone)]
+pub struct IssueService {
+    pub issue_storage: IssueStorage,
+    pub user_storage: UserStorage,
+    pub planning: PlanningService,
+    pub events: EventService,
+    pub autolinks: Autolinker,
+    pub references: Referencer,
+}
+
+/// An issue number mentioned in a commit message or merge request title.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IssueMention {
+    pub number: i64,
+    pub closes: bool,
+}
+
+/// Find the `#<number>` mentions in `text`, in order of first appearance. A mention closes the
+/// issue when it follows one of the closing keywords, in any case and with an optional colon.
+pub fn parse_mentions(text: &str) -> Vec<IssueMention> {
+    let mut mentions: Vec<IssueMention> = Vec::new();
+    for (pos, _) in text.match_indices('#') {
+        let before = &text[..pos];
+        if before
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '&')
+        {
+            continue;
+        }
+        let digits: String = text[pos + 1..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        if text[pos + 1 + digits.len()..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        {
+            continue;
+        }
+        let Ok(number) = digits.parse::<i64>() else {
+            continue;
+        };
+        let keyword = before
+            .trim_end()
+            .trim_end_matches(':')
+            .rsplit(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let closes = CLOSING_KEYWORDS.contains(&keyword.as_str());
+        match mentions.iter_mut().find(|m| m.number == number) {
+            Some(mention) => mention.closes |= closes,
+            None => mentions.push(IssueMention { number, closes }),
+        }
+    }
+    mentions
+}
+
+/// Record the issues of `repo_path` mentioned in `text` as referenced by a commit or merge
+/// request. Returns each mentioned issue with whether the mention closes it.
+pub async fn link_mentions(
+    storage: &IssueStorage,
+    repo_path: &str,
+    source_type: &str,
+    source_id: &str,
+    text: &str,
+) -> Result<Vec<(mega_issue::Model, bool)>, MegaError> {
+    let mut linked = Vec::new();
+    for mention in parse_mentions(text) {
+        let Some(issue) = storage.find_by_number(repo_path, mention.number).await? else {
+            continue;
+        };
+        storage
+            .save_ref(mega_issue_ref::Model {
+                id: generate_id(),
+                issue_id: issue.id,
+                source_type: source_type.to_owned(),
+                source_id: source_id.to_owned(),
+                closes: mention.closes,
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+            .await?;
+        linked.push((issue, mention.closes));
+    }
+    Ok(linked)
+}
+
+/// Mark an issue closed, issues already closed are left as they are.
+pub async fn close_issue(
+    storage: &IssueStorage,
+    mut issue: mega_issue::Model,
+) -> Result<mega_issue::Model, MegaError> {
+    if issue.state == ISSUE_CLOSED {
+        return Ok(issue);
+    }
+    let now = chrono::Utc::now().naive_utc();
+    issue.state = ISSUE_CLOSED.to_owned();
+    issue.closed_at = Some(now);
+    issue.updated_at = now;
+    storage.update_issue(issue).await
+}
+
+/// The text of an issue whose references are recorded, the title and the body.
+fn issue_text(title: &str, body: &str) -> String {
+    format!("{}\n{}", title, body)
+}
+
+/// Link the references of the title and body of `issue`, recorded from its text together.
+fn link_references(
+    issue: &mut Issue,
+    references: &[mega_reference::Model],
+    rules: &AutolinkRules,
+    base_url: &str,
+) {
+    issue.autolinks = reference::with_references(
+        &issue.title,
+        references,
+        rules.resolve(&issue.repo_path, &issue.title),
+        base_url,
+    );
+    issue.body_autolinks = reference::with_references(
+        &issue.body,
+        references,
+        rules.resolve(&issue.repo_path, &issue.body),
+        base_url,
+    );
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn sort_column(order: &Order) -> mega_issue::Column {
+    match order.field {
+        "updated_at" => mega_issue::Column::UpdatedAt,
+        "number" => mega_issue::Column::Number,
+        _ => mega_issue::Column::CreatedAt,
+    }
+}
+
+fn sort_key(order: &Order, issue: &mega_issue::Model) -> SortKey {
+    match order.field {
+        "updated_at" => SortKey::Time(issue.updated_at),
+        "number" => SortKey::Int(issue.number),
+        _ => SortKey::Time(issue.created_at),
+    }
+}
+
+fn check_title(title: &str) -> Result<(), (StatusCode, String)> {
+    if title.is_empty() || title.chars().count() > 255 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "title must have between 1 and 255 characters".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+impl IssueService {
+    pub async fn create(&self, new_issue: NewIssue) -> Result<Json<Issue>, (StatusCode, String)> {
+        let title = new_issue.title.trim();
+        check_title(title)?;
+        let author = new_issue.author.trim();
+        if author.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "author is required".to_owned()));
+        }
+        // users of the LDAP and static providers have no row in mega_user
+        let sender_id = self
+            .user_storage
+            .get_user_by_name(author)
+            .await
+            .map_err(internal_error)?
+            .map_or(0, |user| user.id);
+        let number = self
+            .issue_storage
+            .next_number(&new_issue.repo_path)
+            .await
+            .map_err(internal_error)?;
+        let label_ids = self
+            .planning
+            .resolve_labels(&new_issue.repo_path, new_issue.labels)
+            .await?;
+        if let Some(milestone_id) = new_issue.milestone_id {
+            self.planning
+                .check_milestone(&new_issue.repo_path, milestone_id)
+                .await?;
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let model = mega_issue::Model {
+            id: generate_id(),
+            number,
+            repo_path: new_issue.repo_path,
+            title: title.to_owned(),
+            body: new_issue.body,
+            sender_name: author.to_owned(),
+            sender_id,
+            state: ISSUE_OPEN.to_owned(),
+            milestone_id: new_issue.milestone_id,
+            created_at: now,
+            updated_at: now,
+            closed_at: None,
+        };
+        self.issue_storage
+            .save_issue(model.clone())
+            .await
+            .map_err(internal_error)?;
+        self.planning
+            .set_labels(ITEM_ISSUE, model.id, &label_ids)
+            .await?;
+        self.planning
+            .set_assignees(ITEM_ISSUE, model.id, new_issue.assignees)
+            .await?;
+        self.record_references(&model).await;
+        let issue = self.with_links(model).await?;
+        self.events
+            .publish(
+                EVENT_ISSUE,
+                "opened",
+                &issue.repo_path,
+                Some(author),
+                &issue,
+            )
+            .await;
+        Ok(Json(issue))
+    }
+
+    /// A page of the issues matching `query`, newest first by default.
+    pub async fn list(
+        &self,
+        query: IssueQuery,
+        page: PageQuery,
+    ) -> Result<Json<Page<Issue>>, (StatusCode, String)> {
+        let order = page.order(&ISSUE_ORDERS, DEFAULT_ORDER)?;
+        let keyset = page.keyset(&order)?;
+        if let Some(state) = query.state.as_deref() {
+            if state != ISSUE_OPEN && state != ISSUE_CLOSED {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown issue state: {}", state),
+                ));
+            }
+        }
+        let filter = IssueFilter {
+            repo_path: query.repo_path.as_deref(),
+            state: query.state.as_deref(),
+            label: query.label.as_deref(),
+            assignee: query.assignee.as_deref(),
+            milestone_id: query.milestone,
+        };
+        let issues = self
+            .issue_storage
+            .list_issues_page(filter, sort_column(&order), &keyset)
+            .await
+            .map_err(internal_error)?;
+        let issues = Page::of(issues, keyset.limit, &order, |issue| {
+            (sort_key(&order, issue), issue.id)
+        });
+        let ids: Vec<i64> = issues.items.iter().map(|i| i.id).collect();
+        let mut links = self.planning.links_of(ITEM_ISSUE, &ids).await?;
+        let rules = self.autolinks.rules().await?;
+        let mut references = self
+            .references
+            .references_of(ITEM_ISSUE, &ids)
+            .await
+            .map_err(internal_error)?;
+        let base_url = webhook::public_url();
+        Ok(Json(issues.map(|issue| {
+            let item_links = links.remove(&issue.id).unwrap_or_default();
+            let issue_references = references.remove(&issue.id).unwrap_or_default();
+            let mut issue = Issue::new(issue, item_links);
+            link_references(&mut issue, &issue_references, &rules, &base_url);
+            issue
+        })))
+    }
+
+    /// The issue with the commits and merge requests referring to it.
+    pub async fn detail(&self, id: i64) -> Result<Json<IssueDetail>, (StatusCode, String)> {
+        let issue = self.get_issue(id).await?;
+        let references = self
+            .issue_storage
+            .list_refs(id)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(IssueDetail {
+            issue: self.with_links(issue).await?,
+            references: references.into_iter().map(IssueReference::from).collect(),
+        }))
+    }
+
+    pub async fn update(
+        &self,
+        id: i64,
+        update: IssueUpdate,
+    ) -> Result<Json<Issue>, (StatusCode, String)> {
+        let mut issue = self.get_issue(id).await?;
+        if let Some(title) = update.title {
+            let title = title.trim();
+            check_title(title)?;
+            issue.title = title.to_owned();
+        }
+        if let Some(body) = update.body {
+            issue.body = body;
+        }
+        self.record_references(&issue).await;
+        self.save(issue, "updated").await
+    }
+
+    pub async fn set_labels(
+        &self,
+        id: i64,
+        labels: ItemLabels,
+    ) -> Result<Json<Issue>, (StatusCode, String)> {
+        let issue = self.get_issue(id).await?;
+        let label_ids = self
+            .planning
+            .resolve_labels(&issue.repo_path, labels.labels)
+            .await?;
+        self.planning.set_labels(ITEM_ISSUE, id, &label_ids).await?;
+        self.save(issue, "updated").await
+    }
+
+    pub async fn set_assignees(
+        &self,
+        id: i64,
+        assignees: ItemAssignees,
+    ) -> Result<Json<Issue>, (StatusCode, String)> {
+        let issue = self.get_issue(id).await?;
+        self.planning
+            .set_assignees(ITEM_ISSUE, id, assignees.assignees)
+            .await?;
+        self.save(issue, "updated").await
+    }
+
+    pub async fn set_milestone(
+        &self,
+        id: i64,
+        milestone: ItemMilestone,
+    ) -> Result<Json<Issue>, (StatusCode, String)> {
+        let mut issue = self.get_issue(id).await?;
+        if let Some(milestone_id) = milestone.milestone_id {
+            self.planning
+                .check_milestone(&issue.repo_path, milestone_id)
+                .await?;
+        }
+        issue.milestone_id = milestone.milestone_id;
+        self.save(issue, "updated").await
+    }
+
+    pub async fn close(&self, id: i64) -> Result<Json<Issue>, (StatusCode, String)> {
+        let issue = self.get_issue(id).await?;
+        if issue.state != ISSUE_OPEN {
+            return Err((StatusCode::CONFLICT, format!("issue {} is not open", id)));
+        }
+        let issue = close_issue(&self.issue_storage, issue)
+            .await
+            .map_err(internal_error)?;
+        let issue = self.with_links(issue).await?;
+        self.events
+            .publish(EVENT_ISSUE, "closed", &issue.repo_path, None, &issue)
+            .await;
+        Ok(Json(issue))
+    }
+
+    pub async fn reopen(&self, id: i64) -> Result<Json<Issue>, (StatusCode, String)> {
+        let mut issue = self.get_issue(id).await?;
+        if issue.state != ISSUE_CLOSED {
+            return Err((StatusCode::CONFLICT, format!("issue {} is not closed", id)));
+        }
+        issue.state = ISSUE_OPEN.to_owned();
+        issue.closed_at = None;
+        self.save(issue, "reopened").await
+    }
+
+    async fn get_issue(&self, id: i64) -> Result<mega_issue::Model, (StatusCode, String)> {
+        match self.issue_storage.get_issue(id).await {
+            Ok(Some(issue)) => Ok(issue),
+            Ok(None) => Err((StatusCode::NOT_FOUND, format!("issue {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// Store a changed issue and publish an event with `action`.
+    async fn save(
+        &self,
+        mut issue: mega_issue::Model,
+        action: &str,
+    ) -> Result<Json<Issue>, (StatusCode, String)> {
+        issue.updated_at = chrono::Utc::now().naive_utc();
+        let issue = self
+            .issue_storage
+            .update_issue(issue)
+            .await
+            .map_err(internal_error)?;
+        let issue = self.with_links(issue).await?;
+        self.events
+            .publish(EVENT_ISSUE, action, &issue.repo_path, None, &issue)
+            .await;
+        Ok(Json(issue))
+    }
+
+    /// Record the references the title and body of a saved issue make.
+    async fn record_references(&self, issue: &mega_issue::Model) {
+        self.references
+            .record(
+                &issue.repo_path,
+                ITEM_ISSUE,
+                issue.id,
+                issue.id,
+                &issue_text(&issue.title, &issue.body),
+            )
+            .await;
+    }
+
+    async fn with_links(&self, issue: mega_issue::Model) -> Result<Issue, (StatusCode, String)> {
+        let mut links = self.planning.links_of(ITEM_ISSUE, &[issue.id]).await?;
+        let item_links = links.remove(&issue.id).unwrap_or_default();
+        let mut references = self
+            .references
+            .references_of(ITEM_ISSUE, &[issue.id])
+            .await
+            .map_err(internal_error)?;
+        let rules = self.autolinks.rules().await?;
+        let refs = references.remove(&issue.id).unwrap_or_default();
+        let mut issue = Issue::new(issue, item_links);
+        link_references(&mut issue, &refs, &rules, &webhook::public_url());
+        Ok(issue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mentions, IssueMention};
+
+    fn mention(number: i64, closes: bool) -> IssueMention {
+        IssueMention { number, closes }
+    }
+
+    #[test]
+    fn test_parse_mentions() {
+        assert_eq!(
+            parse_mentions("Fixes #42, see #7 and closes: #7"),
+            vec![mention(42, true), mention(7, true)]
+        );
+        assert_eq!(
+            parse_mentions("refactor parser (#12)\n\nResolved #3."),
+            vec![mention(12, false), mention(3, true)]
+        );
+        assert_eq!(parse_mentions("fix a#1 &#38; #x #1a issue"), vec![]);
+        assert_eq!(parse_mentions("prefix #5"), vec![mention(5, false)]);
+    }
+}
@@ -0,0 +1,571 @@
+//! Classification of the files of a tree into languages, the way GitHub's linguist does it, for
+//! the language breakdown of repositories and directories.
+//!
+//! A file is in the language its name names (`Makefile`), else the one of its extension, content
+//! heuristics telling apart the languages sharing one (`.h` is C, C++ or Objective-C), else the
+//! one of the interpreter of its shebang. Binary files and files in no known language aren't
+//! classified.
+//!
+//! Only programming and markup languages count towards the breakdown, and never vendored,
+//! generated or documentation files. `.gitattributes` overrides all of it with the attributes
+//! linguist reads: `linguist-language=<name>`, and `linguist-vendored`, `linguist-generated`,
+//! `linguist-documentation` and `linguist-detectable` set or unset.
+use std::collections::HashMap;
+
+use venus::internal::gitattributes::AttrValue;
+
+use Kind::{Data, Markup, Programming, Prose};
+
+/// Bytes of a file the heuristics look at.
+const HEURISTIC_BYTES: usize = 50 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Programming,
+    Markup,
+    Data,
+    Prose,
+}
+
+pub struct Language {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// Lowercase, without their dot
+    extensions: &'static [&'static str],
+    filenames: &'static [&'static str],
+    interpreters: &'static [&'static str],
+}
+
+const fn language(
+    name: &'static str,
+    kind: Kind,
+    extensions: &'static [&'static str],
+    filenames: &'static [&'static str],
+    interpreters: &'static [&'static str],
+) -> Language {
+    Language {
+        name,
+        kind,
+        extensions,
+        filenames,
+        interpreters,
+    }
+}
+
+/// The languages known, by name. Where several share an extension the heuristics choose, the
+/// first listed when none applies.
+const LANGUAGES: &[Language] = &[
+    language("Batchfile", Programming, &["bat", "cmd"], &[], &[]),
+    language("C", Programming, &["c", "h"], &[], &["tcc"]),
+    language("C#", Programming, &["cs", "csx"], &[], &[]),
+    language(
+        "C++",
+        Programming,
+        &["cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "h"],
+        &[],
+        &[],
+    ),
+    language("CMake", Programming, &["cmake"], &["CMakeLists.txt"], &[]),
+    language("CSS", Markup, &["css"], &[], &[]),
+    language("Dart", Programming, &["dart"], &[], &["dart"]),
+    language(
+        "Dockerfile",
+        Programming,
+        &["dockerfile"],
+        &["Dockerfile", "Containerfile"],
+        &[],
+    ),
+    language("Elixir", Programming, &["ex", "exs"], &[], &["elixir"]),
+    language(
+        "Erlang",
+        Programming,
+        &["erl", "hrl"],
+        &["rebar.config"],
+        &["escript"],
+    ),
+    language("Go", Programming, &["go"], &[], &[]),
+    language(
+        "Groovy",
+        Programming,
+        &["groovy", "gradle"],
+        &["Jenkinsfile"],
+        &["groovy"],
+    ),
+    language("HTML", Markup, &["html", "htm", "xhtml"], &[], &[]),
+    language("Haskell", Programming, &["hs", "lhs"], &[], &["runhaskell"]),
+    language("JSON", Data, &["json", "jsonc"], &[], &[]),
+    language("Java", Programming, &["java"], &[], &[]),
+    language(
+        "JavaScript",
+        Programming,
+        &["js", "cjs", "mjs", "jsx"],
+        &[],
+        &["node"],
+    ),
+    language("Jupyter Notebook", Markup, &["ipynb"], &[], &[]),
+    language("Kotlin", Programming, &["kt", "kts"], &[], &[]),
+    language("Lua", Programming, &["lua"], &[], &["lua"]),
+    language("MATLAB", Programming, &["m"], &[], &[]),
+    language(
+        "Makefile",
+        Programming,
+        &["mk", "mak"],
+        &["Makefile", "GNUmakefile", "makefile"],
+        &["make"],
+    ),
+    language("Markdown", Prose, &["md", "markdown"], &[], &[]),
+    language("Nix", Programming, &["nix"], &[], &[]),
+    language("Objective-C", Programming, &["m", "h"], &[], &[]),
+    language("PHP", Programming, &["php"], &[], &["php"]),
+    language("Perl", Programming, &["pl", "pm"], &[], &["perl"]),
+    language(
+        "PowerShell",
+        Programming,
+        &["ps1", "psm1", "psd1"],
+        &[],
+        &["pwsh"],
+    ),
+    language("Prolog", Programming, &["pl", "pro"], &[], &["swipl"]),
+    language("Protocol Buffer", Data, &["proto"], &[], &[]),
+    language(
+        "Python",
+        Programming,
+        &["py", "pyi", "pyw"],
+        &["SConstruct", "SConscript"],
+        &["python"],
+    ),
+    language("R", Programming, &["r"], &[], &["rscript"]),
+    language(
+        "Ruby",
+        Programming,
+        &["rb", "rake", "gemspec"],
+        &["Gemfile", "Rakefile"],
+        &["ruby"],
+    ),
+    language("Rust", Programming, &["rs"], &[], &[]),
+    language("SCSS", Markup, &["scss"], &[], &[]),
+    language("SQL", Data, &["sql"], &[], &[]),
+    language("Scala", Programming, &["scala", "sc"], &[], &["scala"]),
+    language(
+        "Shell",
+        Programming,
+        &["sh", "bash", "zsh", "ksh"],
+        &[],
+        &["sh", "bash", "zsh", "dash", "ksh"],
+    ),
+    language("Solidity", Programming, &["sol"], &[], &[]),
+    language(
+        "Starlark",
+        Programming,
+        &["bzl", "star"],
+        &["BUILD", "BUILD.bazel", "WORKSPACE"],
+        &[],
+    ),
+    language("Svelte", Markup, &["svelte"], &[], &[]),
+    language("Swift", Programming, &["swift"], &[], &[]),
+    language("TOML", Data, &["toml"], &[], &[]),
+    language("TSX", Programming, &["tsx"], &[], &[]),
+    language("Text", Prose, &["txt"], &[], &[]),
+    language(
+        "TypeScript",
+        Programming,
+        &["ts", "cts", "mts"],
+        &[],
+        &["deno", "ts-node"],
+    ),
+    language("Vue", Markup, &["vue"], &[], &[]),
+    language("XML", Data, &["xml", "xsd", "xsl"], &[], &[]),
+    language("YAML", Data, &["yml", "yaml"], &[], &[]),
+    language("Zig", Programming, &["zig"], &[], &[]),
+];
+
+/// Directories whose files are vendored wherever they are.
+const VENDORED_DIRS: &[&str] = &[
+    "node_modules",
+    "bower_components",
+    "vendor",
+    "vendors",
+    "third_party",
+    "third-party",
+    "Pods",
+    "Carthage",
+    ".yarn",
+    "dist",
+];
+
+/// Files generated by tools, by name.
+const GENERATED_NAMES: &[&str] = &[
+    "Cargo.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "package-lock.json",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "yarn.lock",
+];
+
+/// Files generated by tools, by the end of their name.
+const GENERATED_SUFFIXES: &[&str] = &[
+    ".min.js",
+    ".min.css",
+    ".pb.go",
+    ".pb.cc",
+    ".pb.h",
+    "_pb2.py",
+    ".designer.cs",
+    ".js.map",
+];
+
+/// Documentation files, by their name up to the first dot.
+const DOCUMENTATION_NAMES: &[&str] = &[
+    "changelog",
+    "changes",
+    "contributing",
+    "copying",
+    "install",
+    "licence",
+    "license",
+    "readme",
+];
+
+/// The language named `name`, ignoring case.
+pub fn find(name: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|l| l.name.eq_ignore_ascii_case(name))
+}
+
+/// What `.gitattributes` says about a file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub language: Option<String>,
+    pub vendored: Option<bool>,
+    pub generated: Option<bool>,
+    pub documentation: Option<bool>,
+    pub detectable: Option<bool>,
+}
+
+impl Overrides {
+    /// The overrides among the attributes of a file.
+    pub fn from_attributes(attributes: &HashMap<String, AttrValue>) -> Self {
+        let flag = |name: &str| match attributes.get(name)? {
+            AttrValue::Set => Some(true),
+            AttrValue::Unset => Some(false),
+            AttrValue::Value(value) => match value.as_str() {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => None,
+            },
+        };
+        Overrides {
+            language: match attributes.get("linguist-language") {
+                // `-` stands for the spaces attributes can't hold, as in `Jupyter-Notebook`
+                Some(AttrValue::Value(value)) => Some(value.replace('-', " ")),
+                _ => None,
+            },
+            vendored: flag("linguist-vendored"),
+            generated: flag("linguist-generated"),
+            documentation: flag("linguist-documentation"),
+            detectable: flag("linguist-detectable"),
+        }
+    }
+}
+
+/// The language of a file, and whether it counts towards the language breakdown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub language: String,
+    pub detectable: bool,
+}
+
+/// Classify the file at `path` with `content`, `None` when it is binary or in no known
+/// language and `.gitattributes` gives it none.
+pub fn classify(path: &str, content: &[u8], overrides: &Overrides) -> Option<Classification> {
+    let language = match &overrides.language {
+        Some(name) => match find(name).or_else(|| find(&name.replace(' ', "-"))) {
+            Some(language) => language.name.to_owned(),
+            None => name.clone(),
+        },
+        None => {
+            if content.iter().take(8000).any(|b| *b == 0) {
+                return None;
+            }
+            detect(path, content)?.name.to_owned()
+        }
+    };
+    let excluded = overrides.vendored.unwrap_or_else(|| is_vendored(path))
+        || overrides
+            .generated
+            .unwrap_or_else(|| is_generated(path, content))
+        || overrides
+            .documentation
+            .unwrap_or_else(|| is_documentation(path));
+    let by_kind = find(&language).is_some_and(|l| matches!(l.kind, Programming | Markup));
+    Some(Classification {
+        detectable: !excluded && overrides.detectable.unwrap_or(by_kind),
+        language,
+    })
+}
+
+/// The language of the file at `path` with `content`, from its name, extension or shebang.
+pub fn detect(path: &str, content: &[u8]) -> Option<&'static Language> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    if let Some(language) = LANGUAGES.iter().find(|l| l.filenames.contains(&name)) {
+        return Some(language);
+    }
+    let head = String::from_utf8_lossy(&content[..content.len().min(HEURISTIC_BYTES)]);
+    // a leading dot starts a hidden name, not an extension
+    let extension = name
+        .trim_start_matches('.')
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    if let Some(extension) = extension {
+        let candidates: Vec<&'static Language> = LANGUAGES
+            .iter()
+            .filter(|l| l.extensions.contains(&extension.as_str()))
+            .collect();
+        match candidates.as_slice() {
+            [] => {}
+            [language] => return Some(*language),
+            _ => return Some(disambiguate(&candidates, &head)),
+        }
+    }
+    let interpreter = interpreter(head.lines().next().unwrap_or_default())?;
+    let bare = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES
+        .iter()
+        .find(|l| l.interpreters.contains(&interpreter.as_str()))
+        .or_else(|| LANGUAGES.iter().find(|l| l.interpreters.contains(&bare)))
+}
+
+/// The interpreter the shebang `line` runs, lowercased, looking through `env`.
+fn interpreter(line: &str) -> Option<String> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    Some(program.to_ascii_lowercase())
+}
+
+/// Choose among `candidates` sharing an extension by what `content` looks like.
+fn disambiguate(candidates: &[&'static Language], content: &str) -> &'static Language {
+    let has = |name: &str| candidates.iter().find(|l| l.name == name).copied();
+    let lines = || content.lines().map(str::trim_start);
+    if let Some(objc) = has("Objective-C") {
+        if lines().any(|l| {
+            l.starts_with("@interface")
+                || l.starts_with("@implementation")
+                || l.starts_with("@protocol")
+                || l.starts_with("#import ")
+        }) {
+            return objc;
+        }
+    }
+    if let Some(cpp) = has("C++") {
+        let cpp_include = lines().any(|l| {
+            l.strip_prefix("#include <")
+                .and_then(|rest| rest.split_once('>'))
+                .is_some_and(|(header, _)| !header.contains('.'))
+        });
+        if cpp_include
+            || content.contains("std::")
+            || lines().any(|l| {
+                l.starts_with("namespace ")
+                    || l.starts_with("template <")
+                    || l.starts_with("class ")
+            })
+        {
+            return cpp;
+        }
+    }
+    if let Some(prolog) = has("Prolog") {
+        if lines().any(|l| l.starts_with(":-") || l.contains(") :-")) {
+            return prolog;
+        }
+    }
+    if let Some(matlab) = has("MATLAB") {
+        if lines().any(|l| l.starts_with("function ") || l.starts_with('%')) {
+            return matlab;
+        }
+    }
+    candidates[0]
+}
+
+/// Whether the file at `path` is vendored code, kept in the tree but not written there.
+pub fn is_vendored(path: &str) -> bool {
+    let mut dirs = path.split('/').rev().skip(1);
+    dirs.any(|dir| VENDORED_DIRS.contains(&dir))
+}
+
+/// Whether the file at `path` with `content` is generated, by its name or by the marker tools
+/// put at its top.
+pub fn is_generated(path: &str, content: &[u8]) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    if GENERATED_NAMES.contains(&name) || GENERATED_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&content[..content.len().min(1024)]);
+    head.lines().take(5).any(|line| {
+        (line.contains("Code generated") && line.contains("DO NOT EDIT"))
+            || line.contains("@generated")
+            || line.contains("<auto-generated")
+    })
+}
+
+/// Whether the file at `path` is documentation: below a top `docs` directory or any
+/// `documentation` or `examples` directory, or a readme, license or changelog.
+pub fn is_documentation(path: &str) -> bool {
+    let components: Vec<&str> = path.split('/').collect();
+    let Some((name, dirs)) = components.split_last() else {
+        return false;
+    };
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    DOCUMENTATION_NAMES.contains(&stem.as_str())
+        || dirs
+            .first()
+            .is_some_and(|d| matches!(d.to_ascii_lowercase().as_str(), "doc" | "docs"))
+        || dirs.iter().any(|d| {
+            matches!(
+                d.to_ascii_lowercase().as_str(),
+                "documentation" | "examples"
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(path: &str, content: &str) -> Option<&'static str> {
+        detect(path, content.as_bytes()).map(|l| l.name)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(name("src/main.rs", ""), Some("Rust"));
+        assert_eq!(name("web/App.TSX", ""), Some("TSX"));
+        assert_eq!(name("build/Makefile", ""), Some("Makefile"));
+        assert_eq!(
+            name("bin/tool", "#!/usr/bin/env python3\nprint(1)\n"),
+            Some("Python")
+        );
+        assert_eq!(name("bin/run", "#!/bin/bash -e\n"), Some("Shell"));
+        assert_eq!(
+            name("bin/run", "#!/usr/bin/env -S node --flag\n"),
+            Some("JavaScript")
+        );
+        assert_eq!(name(".bashrc", ""), None);
+        assert_eq!(name("LICENSE", "MIT\n"), None);
+    }
+
+    #[test]
+    fn test_heuristics() {
+        assert_eq!(name("a.h", "int add(int a, int b);\n"), Some("C"));
+        assert_eq!(name("a.h", "#include <vector>\nint f();\n"), Some("C++"));
+        assert_eq!(name("a.h", "namespace a {\n}\n"), Some("C++"));
+        assert_eq!(
+            name("a.h", "#import <Foundation/Foundation.h>\n"),
+            Some("Objective-C")
+        );
+        assert_eq!(
+            name("a.m", "@implementation A\n@end\n"),
+            Some("Objective-C")
+        );
+        assert_eq!(
+            name("a.m", "function y = f(x)\n  y = x;\nend\n"),
+            Some("MATLAB")
+        );
+        assert_eq!(name("a.pl", "use strict;\n"), Some("Perl"));
+        assert_eq!(
+            name("a.pl", "parent(a, b).\nanc(X, Y) :- parent(X, Y).\n"),
+            Some("Prolog")
+        );
+    }
+
+    #[test]
+    fn test_classify() {
+        let none = Overrides::default();
+        let check = |path: &str, content: &str, overrides: &Overrides| {
+            classify(path, content.as_bytes(), overrides).map(|c| (c.language, c.detectable))
+        };
+        let yes = |language: &str| Some((language.to_owned(), true));
+        let no = |language: &str| Some((language.to_owned(), false));
+        assert_eq!(check("src/lib.rs", "", &none), yes("Rust"));
+        assert_eq!(check("Cargo.toml", "", &none), no("TOML"));
+        assert_eq!(check("README.md", "", &none), no("Markdown"));
+        assert_eq!(
+            check("node_modules/a/index.js", "", &none),
+            no("JavaScript")
+        );
+        assert_eq!(check("web/app.min.js", "", &none), no("JavaScript"));
+        assert_eq!(check("docs/conf.py", "", &none), no("Python"));
+        assert_eq!(
+            check(
+                "gen/api.go",
+                "// Code generated by protoc. DO NOT EDIT.\n",
+                &none
+            ),
+            no("Go")
+        );
+        assert_eq!(check("logo.png", "\u{0}PNG", &none), None);
+        assert_eq!(check("data.bin", "", &none), None);
+
+        let overrides = Overrides {
+            language: Some("Jupyter Notebook".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            check("data.bin", "\u{0}", &overrides),
+            yes("Jupyter Notebook")
+        );
+        let overrides = Overrides {
+            vendored: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(check("vendor/lib.go", "", &overrides), yes("Go"));
+        let overrides = Overrides {
+            detectable: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(check("config.yaml", "", &overrides), yes("YAML"));
+        let overrides = Overrides {
+            generated: Some(true),
+            detectable: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(check("src/lib.rs", "", &overrides), no("Rust"));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut attributes = venus::internal::gitattributes::Gitattributes::default();
+        attributes.add(
+            "",
+            "*.inc linguist-language=C++\n*.ipynb linguist-language=Jupyter-Notebook\n\
+             vendor/** -linguist-vendored\n*.yml linguist-detectable=true\n",
+        );
+        let overrides = |path: &str| Overrides::from_attributes(&attributes.attributes(path));
+        assert_eq!(overrides("a.inc").language.as_deref(), Some("C++"));
+        assert_eq!(
+            overrides("a.ipynb").language.as_deref(),
+            Some("Jupyter Notebook")
+        );
+        assert_eq!(overrides("vendor/a/b.go").vendored, Some(false));
+        assert_eq!(overrides("ci.yml").detectable, Some(true));
+        assert_eq!(overrides("src/lib.rs"), Overrides::default());
+    }
+
+    #[test]
+    fn test_paths() {
+        assert!(is_vendored("web/node_modules/react/index.js"));
+        assert!(!is_vendored("src/vendor.rs"));
+        assert!(is_documentation("docs/api.md"));
+        assert!(is_documentation("src/examples/demo.rs"));
+        assert!(is_documentation("License.txt"));
+        assert!(!is_documentation("src/docs/mod.rs"));
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use db_entity::mega_mailmap;
+
+/// Name and email shown for commit identities, keyed by the lowercased email found in commits.
+#[derive(Default)]
+pub struct Mailmap {
+    entries: HashMap<String, (String, String)>,
+}
+
+impl From<Vec<mega_mailmap::Model>> for Mailmap {
+    fn from(value: Vec<mega_mailmap::Model>) -> Self {
+        Mailmap {
+            entries: value
+                .into_iter()
+                .map(|e| (e.email.to_lowercase(), (e.display_name, e.display_email)))
+                .collect(),
+        }
+    }
+}
+
+impl Mailmap {
+    /// The name and email to show for a commit identity, the identity itself if it isn't mapped.
+    pub fn resolve(&self, name: &str, email: &str) -> (String, String) {
+        match self.entries.get(&email.to_lowercase()) {
+            Some((name, email)) => (name.clone(), email.clone()),
+            None => (name.to_owned(), email.to_owned()),
+        }
+    }
+}
+
+/// The entries in the format of a git `.mailmap` file, one `Name <shown> <commit>` per line, so
+/// clients can show commits the same way.
+pub fn to_git_mailmap(entries: &[mega_mailmap::Model]) -> String {
+    entries
+        .iter()
+        .map(|e| format!("{} <{}> <{}>\n", e.display_name, e.display_email, e.email))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use db_entity::mega_mailmap;
+
+    use super::{to_git_mailmap, Mailmap};
+
+    #[test]
+    fn test_mailmap() {
+        let entries = vec![mega_mailmap::Model {
+            id: 1,
+            email: "alice@example.com".to_owned(),
+            display_name: "deleted-user-1".to_owned(),
+            display_email: "deleted-user-1@redacted.invalid".to_owned(),
+            created_at: chrono::Utc::now().naive_utc(),
+        }];
+        assert_eq!(
+            to_git_mailmap(&entries),
+            "deleted-user-1 <deleted-user-1@redacted.invalid> <alice@example.com>\n"
+        );
+        let mailmap = Mailmap::from(entries);
+        assert_eq!(
+            mailmap.resolve("Alice", "Alice@Example.com"),
+            (
+                "deleted-user-1".to_owned(),
+                "deleted-user-1@redacted.invalid".to_owned()
+            )
+        );
+        assert_eq!(
+            mailmap.resolve("Bob", "bob@example.com"),
+            ("Bob".to_owned(), "bob@example.com".to_owned())
+        );
+    }
+}
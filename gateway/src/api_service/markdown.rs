@@ -0,0 +1,144 @@
+//! Markdown rendered to HTML the same way everywhere: issues, review comments, wiki pages,
+//! READMEs and previews.
+//!
+//! Rendering follows GitHub flavored markdown for tables, task lists and strikethrough. Fenced
+//! `mermaid` blocks are passed through as `<pre class="mermaid">` for clients to draw. Markdown
+//! may contain raw HTML, so what is rendered is sanitized, keeping only harmless tags and
+//! attributes, before it leaves the server.
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::api_service::highlight::escape_html;
+
+/// Names of README files, the first found in a directory is its README.
+pub const README_NAMES: [&str; 3] = ["readme.md", "readme.markdown", "readme"];
+
+/// Position of `name` in [`README_NAMES`], ignoring case, `None` when it isn't a README.
+pub fn readme_rank(name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    README_NAMES.iter().position(|readme| *readme == name)
+}
+
+fn sanitizer() -> &'static Builder<'static> {
+    static SANITIZER: OnceLock<Builder<'static>> = OnceLock::new();
+    SANITIZER.get_or_init(|| {
+        let mut builder = Builder::default();
+        builder
+            // task list items, which can only be looked at
+            .add_tags(["input"])
+            .add_tag_attributes("input", ["checked"])
+            .set_tag_attribute_value("input", "type", "checkbox")
+            .set_tag_attribute_value("input", "disabled", "")
+            .add_tag_attributes("code", ["class"])
+            .add_tag_attributes("pre", ["class"])
+            .add_tag_attributes("th", ["style"])
+            .add_tag_attributes("td", ["style"])
+            .attribute_filter(filter_attribute);
+        builder
+    })
+}
+
+/// Keep the classes naming the language of code and mermaid diagrams, and the alignment of
+/// table cells, nothing else.
+fn filter_attribute<'u>(element: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    let keep = match (element, attribute) {
+        ("code", "class") => value.strip_prefix("language-").is_some_and(|language| {
+            !language.is_empty()
+                && language
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+#-_.".contains(c))
+        }),
+        ("pre", "class") => value == "mermaid",
+        ("th" | "td", "style") => matches!(
+            value,
+            "text-align: left" | "text-align: center" | "text-align: right"
+        ),
+        _ => true,
+    };
+    keep.then_some(Cow::Borrowed(value))
+}
+
+/// Render markdown `text` as sanitized HTML.
+pub fn render(text: &str) -> String {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+    let mut events = Vec::new();
+    let mut mermaid: Option<String> = None;
+    for event in Parser::new_ext(text, options) {
+        if let Some(diagram) = mermaid.as_mut() {
+            match event {
+                Event::Text(text) => diagram.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => {
+                    let mut pre = String::from("<pre class=\"mermaid\">");
+                    escape_html(diagram, &mut pre);
+                    pre.push_str("</pre>\n");
+                    events.push(Event::Html(pre.into()));
+                    mermaid = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                if info.split_whitespace().next() == Some("mermaid") =>
+            {
+                mermaid = Some(String::new());
+            }
+            event => events.push(event),
+        }
+    }
+    let mut unsafe_html = String::with_capacity(text.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, events.into_iter());
+    sanitizer().clean(&unsafe_html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{readme_rank, render};
+
+    #[test]
+    fn test_render_gfm() {
+        let html = render("| a | b |\n|:-:|---|\n| 1 | ~~2~~ |\n\n- [x] done\n- [ ] todo\n");
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<th style=\"text-align: center\">a</th>"));
+        assert!(html.contains("<del>2</del>"));
+        assert_eq!(html.matches("<input").count(), 2);
+        assert_eq!(html.matches("type=\"checkbox\"").count(), 2);
+        assert_eq!(html.matches("disabled=\"\"").count(), 2);
+        assert_eq!(html.matches("checked").count(), 1);
+    }
+
+    #[test]
+    fn test_render_code() {
+        let html = render("```rust\nfn main() {}\n```\n\n```mermaid\ngraph TD\n  A --> B\n```\n");
+        assert!(html.contains("<pre><code class=\"language-rust\">fn main() {}\n</code></pre>"));
+        assert!(html.contains("<pre class=\"mermaid\">graph TD\n  A --&gt; B\n</pre>"));
+    }
+
+    #[test]
+    fn test_render_sanitized() {
+        let html = render(
+            "<script>alert(1)</script>\n\n[x](javascript:alert(1)) <img src=x onerror=alert(1)>\n\n\
+             <input type=\"text\" value=\"a\"> <code class=\"x\" onclick=\"y\">z</code>\n",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("javascript"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("class"));
+        assert!(!html.contains("value"));
+        assert!(!html.contains("type=\"text\""));
+    }
+
+    #[test]
+    fn test_readme_rank() {
+        assert_eq!(readme_rank("README.md"), Some(0));
+        assert_eq!(readme_rank("Readme"), Some(2));
+        assert_eq!(readme_rank("README.rst"), None);
+        assert_eq!(readme_rank("main.rs"), None);
+    }
+}
@@ -0,0 +1,604 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+use sea_orm::{ActiveValue::NotSet, Set};
+
+use common::utils::generate_id;
+use entity::{commit, objects};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::merge::{self, ConflictKind, EntryMerge, MergeBase};
+use venus::internal::object::commit::Commit;
+use venus::internal::object::signature::{Signature, SignatureType};
+use venus::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use venus::internal::object::types::ObjectType;
+
+use crate::api_service::object_loader::{self, ObjectLoader};
+use crate::model::merge::{MergeConflict, MergeStrategy};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// Everything in the source is already part of the target.
+    UpToDate,
+    /// The target can simply be moved to the source commit.
+    FastForward {
+        commit_id: SHA1,
+    },
+    /// A fast-forward was requested but the target has commits the source lacks.
+    NotFastForward,
+    /// The trees merged cleanly, a commit of `tree_id` with these parents completes the merge.
+    Merged {
+        tree_id: SHA1,
+        parent_commit_ids: Vec<SHA1>,
+    },
+    Conflicts(Vec<MergeConflict>),
+}
+
+/// What replaying the commits of a branch onto another commit gave.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RebaseOutcome {
+    /// The branch already contains the commit it would be rebased onto.
+    UpToDate,
+    /// The commits were replayed, `head` is the last of them. Commits whose change was already
+    /// there are dropped and not counted in `replayed`.
+    Rebased { head: SHA1, replayed: usize },
+    /// Replaying `commit` conflicts.
+    Conflicts {
+        commit: SHA1,
+        conflicts: Vec<MergeConflict>,
+    },
+}
+
+/// Server side merge of two commits, working directly on stored objects.
+///
+/// Merging only hashes the blobs and trees it creates and keeps them in memory, so checking
+/// whether two commits can be merged costs the same as merging them. They are stored together
+/// with the merge commit by [`Merger::commit`].
+pub struct Merger {
+    storage: Arc<dyn ObjectStorage>,
+    loader: ObjectLoader,
+    created: HashMap<SHA1, (ObjectType, Vec<u8>)>,
+}
+
+/// Walk the history of two commits until their merge bases are known.
+pub async fn walk_merge_base(
+    loader: &mut ObjectLoader,
+    one: &SHA1,
+    two: &SHA1,
+) -> Result<MergeBase, (StatusCode, String)> {
+    let one_ts = loader.commit(one).await?.committer.timestamp;
+    let two_ts = loader.commit(two).await?.committer.timestamp;
+    let mut search = MergeBase::new((*one, one_ts), (*two, two_ts));
+    while let Some(id) = search.next_commit() {
+        let commit = loader.commit(&id).await?;
+        let mut parents = Vec::with_capacity(commit.parent_commit_ids.len());
+        for parent_id in &commit.parent_commit_ids {
+            let parent = loader.commit(parent_id).await?;
+            parents.push((*parent_id, parent.committer.timestamp));
+        }
+        search.visit(&parents);
+    }
+    Ok(search)
+}
+
+impl Merger {
+    pub fn new(storage: Arc<dyn ObjectStorage>) -> Self {
+        Merger {
+            loader: ObjectLoader::new(storage.clone()),
+            storage,
+            created: HashMap::new(),
+        }
+    }
+
+    /// The best common ancestor of two commits. With several equally good ones (criss-cross
+    /// history) the most recent is used.
+    pub async fn merge_base(
+        &mut self,
+        one: &SHA1,
+        two: &SHA1,
+    ) -> Result<Option<SHA1>, (StatusCode, String)> {
+        let search = walk_merge_base(&mut self.loader, one, two).await?;
+        Ok(search.finish().into_iter().next())
+    }
+
+    /// Merge `source` into `target` according to `strategy`.
+    pub async fn merge(
+        &mut self,
+        target: &SHA1,
+        source: &SHA1,
+        strategy: MergeStrategy,
+    ) -> Result<MergeOutcome, (StatusCode, String)> {
+        let base = self.merge_base(target, source).await?;
+        if base == Some(*source) {
+            return Ok(MergeOutcome::UpToDate);
+        }
+        let fast_forward = base == Some(*target);
+        if strategy == MergeStrategy::FastForward {
+            return Ok(if fast_forward {
+                MergeOutcome::FastForward { commit_id: *source }
+            } else {
+                MergeOutcome::NotFastForward
+            });
+        }
+
+        let target_tree = self.loader.commit(target).await?.tree_id;
+        let source_tree = self.loader.commit(source).await?.tree_id;
+        let tree_id = if fast_forward {
+            source_tree
+        } else {
+            let base_tree = match base {
+                Some(base) => Some(self.loader.commit(&base).await?.tree_id),
+                None => None,
+            };
+            let mut conflicts = Vec::new();
+            let merged = self
+                .merge_trees(
+                    String::new(),
+                    base_tree,
+                    target_tree,
+                    source_tree,
+                    &mut conflicts,
+                )
+                .await?;
+            if !conflicts.is_empty() {
+                return Ok(MergeOutcome::Conflicts(conflicts));
+            }
+            merged.unwrap_or_else(|| self.add_object(ObjectType::Tree, Vec::new()))
+        };
+
+        let parent_commit_ids = match strategy {
+            MergeStrategy::Squash => vec![*target],
+            _ => vec![*target, *source],
+        };
+        Ok(MergeOutcome::Merged {
+            tree_id,
+            parent_commit_ids,
+        })
+    }
+
+    /// Apply the change `commit` made to one of its parents onto `onto`, or undo it with
+    /// `revert`, as a three-way merge of trees. The change of a merge commit is taken against
+    /// its parent number `mainline`, counting from 1. A change already there is
+    /// [`MergeOutcome::UpToDate`].
+    pub async fn pick(
+        &mut self,
+        commit: &SHA1,
+        onto: &SHA1,
+        mainline: Option<usize>,
+        revert: bool,
+    ) -> Result<MergeOutcome, (StatusCode, String)> {
+        let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+        let picked = self.loader.commit(commit).await?;
+        let parent = match (picked.parent_commit_ids.as_slice(), mainline) {
+            ([], None) => None,
+            ([parent], None) => Some(*parent),
+            (parents, Some(n)) if parents.len() > 1 => match n.checked_sub(1) {
+                Some(i) if i < parents.len() => Some(parents[i]),
+                _ => {
+                    return Err(bad_request(format!(
+                        "commit {} has no parent {}",
+                        commit.to_plain_str(),
+                        n
+                    )))
+                }
+            },
+            (_, Some(_)) => {
+                return Err(bad_request(format!(
+                    "mainline was given but {} is not a merge",
+                    commit.to_plain_str()
+                )))
+            }
+            (_, None) => {
+                return Err(bad_request(format!(
+                    "{} is a merge, a mainline is needed",
+                    commit.to_plain_str()
+                )))
+            }
+        };
+        let parent_tree = match parent {
+            Some(parent) => Some(self.loader.commit(&parent).await?.tree_id),
+            None => None,
+        };
+        let (base, theirs) = if revert {
+            let Some(parent_tree) = parent_tree else {
+                return Err(bad_request(format!(
+                    "{} has no parent to revert to",
+                    commit.to_plain_str()
+                )));
+            };
+            (Some(picked.tree_id), parent_tree)
+        } else {
+            (parent_tree, picked.tree_id)
+        };
+
+        let ours = self.loader.commit(onto).await?.tree_id;
+        let mut conflicts = Vec::new();
+        let merged = self
+            .merge_trees(String::new(), base, ours, theirs, &mut conflicts)
+            .await?;
+        if !conflicts.is_empty() {
+            return Ok(MergeOutcome::Conflicts(conflicts));
+        }
+        let tree_id = merged.unwrap_or_else(|| self.add_object(ObjectType::Tree, Vec::new()));
+        if tree_id == ours {
+            return Ok(MergeOutcome::UpToDate);
+        }
+        Ok(MergeOutcome::Merged {
+            tree_id,
+            parent_commit_ids: vec![*onto],
+        })
+    }
+
+    /// Replay the commits `branch` has over `onto` on top of it, like `git rebase`: oldest first,
+    /// keeping their author and message, and leaving merge commits out. The new commits are
+    /// stored as they are made, refs are left alone.
+    pub async fn rebase(
+        &mut self,
+        repo_path: &str,
+        branch: &SHA1,
+        onto: &SHA1,
+        committer: Signature,
+    ) -> Result<RebaseOutcome, (StatusCode, String)> {
+        let search = walk_merge_base(&mut self.loader, onto, branch).await?;
+        let (_, unique) = search.unique_commits();
+        if search.finish().first() == Some(onto) {
+            return Ok(RebaseOutcome::UpToDate);
+        }
+        let mut parents = HashMap::with_capacity(unique.len());
+        for id in unique {
+            let commit = self.loader.commit(&id).await?;
+            parents.insert(id, commit.parent_commit_ids);
+        }
+
+        let mut head = *onto;
+        let mut replayed = 0;
+        for id in replay_order(*branch, &parents) {
+            let tree_id = match self.pick(&id, &head, None, false).await? {
+                MergeOutcome::Merged { tree_id, .. } => tree_id,
+                MergeOutcome::Conflicts(conflicts) => {
+                    return Ok(RebaseOutcome::Conflicts {
+                        commit: id,
+                        conflicts,
+                    })
+                }
+                _ => continue,
+            };
+            let picked = self.loader.commit(&id).await?;
+            head = self
+                .commit_authored(
+                    repo_path,
+                    tree_id,
+                    vec![head],
+                    picked.author,
+                    committer.clone(),
+                    object_loader::commit_body(&picked.message),
+                )
+                .await?;
+            replayed += 1;
+        }
+        Ok(RebaseOutcome::Rebased { head, replayed })
+    }
+
+    /// Merge three trees, returning the merged tree or `None` if it ended up empty. Conflicting
+    /// paths are collected in `conflicts`, in which case the returned tree is meaningless.
+    fn merge_trees<'a>(
+        &'a mut self,
+        path: String,
+        base: Option<SHA1>,
+        ours: SHA1,
+        theirs: SHA1,
+        conflicts: &'a mut Vec<MergeConflict>,
+    ) -> BoxFuture<'a, Result<Option<SHA1>, (StatusCode, String)>> {
+        Box::pin(async move {
+            let base = match base {
+                Some(id) => self.loader.tree(&id).await?.tree_items,
+                None => Vec::new(),
+            };
+            let ours = self.loader.tree(&ours).await?.tree_items;
+            let theirs = self.loader.tree(&theirs).await?.tree_items;
+            let names: BTreeSet<&String> = base
+                .iter()
+                .chain(ours.iter())
+                .chain(theirs.iter())
+                .map(|item| &item.name)
+                .collect();
+
+            let mut items = Vec::with_capacity(names.len());
+            for name in names {
+                let find = |items: &[TreeItem]| items.iter().find(|i| &i.name == name).cloned();
+                let (b, o, t) = (find(&base), find(&ours), find(&theirs));
+                let item_path = format!("{}{}", path, name);
+                match merge::merge_entry(b.as_ref(), o.as_ref(), t.as_ref()) {
+                    EntryMerge::Resolved(item) => items.extend(item),
+                    EntryMerge::Trees { base, ours, theirs } => {
+                        let merged = self
+                            .merge_trees(format!("{}/", item_path), base, ours, theirs, conflicts)
+                            .await?;
+                        if let Some(id) = merged {
+                            items.push(TreeItem::new(TreeItemMode::Tree, id, name.clone()));
+                        }
+                    }
+                    EntryMerge::Blobs {
+                        base,
+                        ours,
+                        theirs,
+                        mode,
+                    } => match self.merge_blobs(base, ours, theirs).await? {
+                        Some(id) => items.push(TreeItem::new(mode, id, name.clone())),
+                        None => conflicts.push(conflict(item_path, ConflictKind::Content)),
+                    },
+                    EntryMerge::Conflict(kind) => conflicts.push(conflict(item_path, kind)),
+                }
+            }
+
+            if items.is_empty() {
+                return Ok(None);
+            }
+            self.write_tree(items).map(Some)
+        })
+    }
+
+    /// Merge the content of a file, `None` when it conflicts or is not text.
+    async fn merge_blobs(
+        &mut self,
+        base: Option<SHA1>,
+        ours: SHA1,
+        theirs: SHA1,
+    ) -> Result<Option<SHA1>, (StatusCode, String)> {
+        let base = match base {
+            Some(id) => self.loader.blob(&id).await?,
+            None => Vec::new(),
+        };
+        let ours = self.loader.blob(&ours).await?;
+        let theirs = self.loader.blob(&theirs).await?;
+        let (Ok(base), Ok(ours), Ok(theirs)) = (
+            String::from_utf8(base),
+            String::from_utf8(ours),
+            String::from_utf8(theirs),
+        ) else {
+            return Ok(None);
+        };
+        let merged = merge::merge_text(&base, &ours, &theirs, ("target", "source"));
+        if merged.conflicts > 0 {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.add_object(ObjectType::Blob, merged.content.into_bytes()),
+        ))
+    }
+
+    fn add_object(&mut self, object_type: ObjectType, data: Vec<u8>) -> SHA1 {
+        let id = SHA1::from_type_and_data(object_type, &data);
+        self.created.entry(id).or_insert((object_type, data));
+        id
+    }
+
+    /// Create a tree of `items`, kept in memory until [`Merger::commit`] like merged trees.
+    pub fn write_tree(&mut self, mut items: Vec<TreeItem>) -> Result<SHA1, (StatusCode, String)> {
+        // git orders entries by name, comparing directories as if they ended with a slash
+        items.sort_by_cached_key(|item| {
+            let mut key = item.name.as_bytes().to_vec();
+            if item.mode == TreeItemMode::Tree {
+                key.push(b'/');
+            }
+            key
+        });
+        let data = Tree {
+            id: SHA1::default(),
+            tree_items: items,
+        }
+        .to_data()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(self.add_object(ObjectType::Tree, data))
+    }
+
+    pub fn write_blob(&mut self, data: Vec<u8>) -> SHA1 {
+        self.add_object(ObjectType::Blob, data)
+    }
+
+    /// Record a commit of `tree_id` in the repository at `repo_path`, storing it together with
+    /// the objects created while merging or written since. `committer` is also used as the author.
+    ///
+    /// Refs are left alone, moving the target branch is up to the caller.
+    pub async fn commit(
+        &mut self,
+        repo_path: &str,
+        tree_id: SHA1,
+        parent_commit_ids: Vec<SHA1>,
+        committer: Signature,
+        message: &str,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let author = Signature {
+            signature_type: SignatureType::Author,
+            ..committer.clone()
+        };
+        self.commit_authored(
+            repo_path,
+            tree_id,
+            parent_commit_ids,
+            author,
+            committer,
+            message,
+        )
+        .await
+    }
+
+    /// [`Merger::commit`] keeping the author of a change, like a cherry-pick does.
+    pub async fn commit_authored(
+        &mut self,
+        repo_path: &str,
+        tree_id: SHA1,
+        parent_commit_ids: Vec<SHA1>,
+        author: Signature,
+        committer: Signature,
+        message: &str,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+        let mut commit = Commit {
+            id: SHA1::default(),
+            tree_id,
+            parent_commit_ids,
+            author,
+            committer,
+            message: format!("\n{}\n", message.trim_end()),
+            gpgsig: None,
+        };
+        let data = commit.to_data().map_err(|e| internal(e.to_string()))?;
+        commit.id = self.add_object(ObjectType::Commit, data);
+
+        let objects = self
+            .created
+            .drain()
+            .map(|(id, (object_type, data))| objects::ActiveModel {
+                id: Set(generate_id()),
+                git_id: Set(id.to_plain_str()),
+                object_type: Set(object_type.to_string()),
+                data: Set(data),
+                link: Set(None),
+            })
+            .collect();
+        self.storage
+            .save_obj_data(None, objects)
+            .await
+            .map_err(|e| internal(e.to_string()))?;
+
+        let signature = |s: &Signature| {
+            s.to_data()
+                .map(|d| String::from_utf8_lossy(&d).into_owned())
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let model = commit::ActiveModel {
+            id: NotSet,
+            git_id: Set(commit.id.to_plain_str()),
+            tree: Set(commit.tree_id.to_plain_str()),
+            pid: Set(commit
+                .parent_commit_ids
+                .iter()
+                .map(|id| id.to_plain_str())
+                .collect()),
+            repo_path: Set(repo_path.to_owned()),
+            author: Set(Some(
+                signature(&commit.author).map_err(|e| internal(e.to_string()))?,
+            )),
+            committer: Set(Some(
+                signature(&commit.committer).map_err(|e| internal(e.to_string()))?,
+            )),
+            content: Set(Some(commit.message.clone())),
+            created_at: Set(now),
+            updated_at: Set(now),
+        };
+        self.storage
+            .save_commits(None, vec![model])
+            .await
+            .map_err(|e| internal(e.to_string()))?;
+        Ok(commit.id)
+    }
+}
+
+/// Put `item` at `path` below `tree_id`, or remove the entry there when `item` is `None`.
+/// Directories left empty are dropped, `None` is returned when the tree itself ends up empty.
+pub fn edit_tree<'a>(
+    loader: &'a mut ObjectLoader,
+    merger: &'a mut Merger,
+    tree_id: Option<SHA1>,
+    path: &'a [&'a str],
+    item: Option<TreeItem>,
+) -> BoxFuture<'a, Result<Option<SHA1>, (StatusCode, String)>> {
+    Box::pin(async move {
+        let mut items = match tree_id {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let Some((name, rest)) = path.split_first() else {
+            return Err((StatusCode::BAD_REQUEST, "empty path".to_owned()));
+        };
+        let existing = items
+            .iter()
+            .position(|i| i.name == *name)
+            .map(|pos| items.remove(pos));
+        let edited = if rest.is_empty() {
+            item.map(|item| TreeItem::new(item.mode, item.id, name.to_string()))
+        } else {
+            let subtree = match existing {
+                Some(existing) if existing.mode != TreeItemMode::Tree => {
+                    return Err((StatusCode::CONFLICT, format!("{} is a file", name)));
+                }
+                existing => existing.map(|e| e.id),
+            };
+            edit_tree(loader, merger, subtree, rest, item)
+                .await?
+                .map(|id| TreeItem::new(TreeItemMode::Tree, id, name.to_string()))
+        };
+        items.extend(edited);
+        if items.is_empty() {
+            return Ok(None);
+        }
+        merger.write_tree(items).map(Some)
+    })
+}
+
+/// The commits of `parents` reachable from `head`, parents before their children, without the
+/// merge commits. `parents` holds the commits to replay, the others are where the walk stops.
+pub fn replay_order(head: SHA1, parents: &HashMap<SHA1, Vec<SHA1>>) -> Vec<SHA1> {
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    // a commit is visited twice: to push its parents, then to take it once they are taken
+    let mut stack = vec![(head, false)];
+    while let Some((id, parents_taken)) = stack.pop() {
+        let Some(commit_parents) = parents.get(&id) else {
+            continue;
+        };
+        if parents_taken {
+            if commit_parents.len() < 2 {
+                order.push(id);
+            }
+        } else if seen.insert(id) {
+            stack.push((id, true));
+            // the first parent on top, so its history comes first
+            stack.extend(commit_parents.iter().rev().map(|p| (*p, false)));
+        }
+    }
+    order
+}
+
+fn conflict(path: String, kind: ConflictKind) -> MergeConflict {
+    let kind = match kind {
+        ConflictKind::Content => "content",
+        ConflictKind::ModifyDelete => "modify/delete",
+        ConflictKind::FileMode => "file mode",
+        ConflictKind::EntryType => "entry type",
+    };
+    MergeConflict {
+        path,
+        kind: kind.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use venus::hash::SHA1;
+
+    use super::replay_order;
+
+    fn id(n: u8) -> SHA1 {
+        SHA1([n; 20])
+    }
+
+    #[test]
+    fn test_replay_order() {
+        // 1 - 2 - 4 - 5, with 3 branching off 1 and merged by 4; 0 is the base, not replayed
+        let parents = HashMap::from([
+            (id(1), vec![id(0)]),
+            (id(2), vec![id(1)]),
+            (id(3), vec![id(1)]),
+            (id(4), vec![id(2), id(3)]),
+            (id(5), vec![id(4)]),
+        ]);
+        assert_eq!(replay_order(id(5), &parents), [id(1), id(2), id(3), id(5)]);
+        assert_eq!(replay_order(id(0), &parents), []);
+    }
+}
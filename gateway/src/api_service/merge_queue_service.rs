@@ -0,0 +1,458 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::db_enums::{MergeStatus, ReviewState};
+use db_entity::mega_merge_queue;
+use jupiter::storage::merge_queue_storage::{
+    MergeQueueStorage, QUEUE_ACTIVE, QUEUE_FAILED, QUEUE_MERGED, QUEUE_REMOVED, QUEUE_TESTING,
+    QUEUE_WAITING,
+};
+use venus::hash::SHA1;
+
+use crate::api_service::mr_service::{MrService, DEFAULT_COMMITTER};
+use crate::api_service::object_loader::ObjectLoader;
+use crate::i18n;
+use crate::model::check::{CHECK_ERROR, CHECK_FAILURE, CHECK_SUCCESS};
+use crate::model::merge_queue::{MergeQueueEntry, MergeQueueQuery};
+use crate::model::mr::{self, MergeOptions};
+use crate::model::review;
+
+/// How often the queues are moved on.
+const RUNNER_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Branches the merges of queued merge requests are pushed to, followed by the id of the request.
+const QUEUE_REF_PREFIX: &str = "refs/heads/mega-queue/";
+
+/// Merges approved merge requests one after the other, each only once its checks succeeded on
+/// top of the ones merged before it.
+///
+/// Each branch has a queue. The first entry is merged onto the branch, each next one onto the
+/// merge of the entry before it, and every merge is pushed to a branch of its own under
+/// `mega-queue/`, for pipelines and external CI systems to check. The first entry whose checks
+/// succeeded moves the branch to its merge, which contains exactly what was tested; an entry
+/// whose checks failed leaves the queue and the entries after it are merged again without it.
+#[derive(Clone)]
+pub struct MergeQueueService {
+    pub queue_storage: MergeQueueStorage,
+    pub mrs: MrService,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn queue_ref(mr_id: i64) -> String {
+    format!("{}{}", QUEUE_REF_PREFIX, mr_id)
+}
+
+fn branch_ref(name: &str) -> String {
+    format!(
+        "refs/heads/{}",
+        name.strip_prefix("refs/heads/").unwrap_or(name)
+    )
+}
+
+/// Whether the merge of `entry` still is the one of `source` onto `base`, or has to be made
+/// again.
+fn is_current(entry: &mega_merge_queue::Model, base: &SHA1, source: &SHA1) -> bool {
+    entry.status == QUEUE_TESTING
+        && entry.head_commit_id.is_some()
+        && entry.base_commit_id == Some(base.to_plain_str())
+        && entry.source_commit_id == Some(source.to_plain_str())
+}
+
+fn entry_of(model: mega_merge_queue::Model, position: Option<usize>) -> MergeQueueEntry {
+    let queue_ref = position.map(|_| queue_ref(model.mr_id));
+    MergeQueueEntry::new(model, position, queue_ref)
+}
+
+impl MergeQueueService {
+    /// The merge requests queued to be merged into a branch, first to merge first.
+    pub async fn list(
+        &self,
+        query: MergeQueueQuery,
+    ) -> Result<Json<Vec<MergeQueueEntry>>, (StatusCode, String)> {
+        let entries = self
+            .queue_storage
+            .list_entries(&query.repo_path, &branch_ref(&query.branch))
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(
+            entries
+                .into_iter()
+                .enumerate()
+                .map(|(i, model)| entry_of(model, Some(i + 1)))
+                .collect(),
+        ))
+    }
+
+    /// Where a merge request is in the queue of its target branch, or how it left it.
+    pub async fn get(&self, mr_id: i64) -> Result<Json<MergeQueueEntry>, (StatusCode, String)> {
+        let model = self.get_entry(mr_id).await?;
+        let position = self.position(&model).await?;
+        Ok(Json(entry_of(model, position)))
+    }
+
+    /// Put an open merge request which reviewers approved, and the code owners of the files it
+    /// changes too, at the end of the queue of its target branch.
+    pub async fn enqueue(
+        &self,
+        mr_id: i64,
+        actor: String,
+    ) -> Result<Json<MergeQueueEntry>, (StatusCode, String)> {
+        let mr = self.mrs.get_mr(mr_id).await?;
+        let id = mr_id.to_string();
+        if mr.status == MergeStatus::Draft {
+            return Err((StatusCode::CONFLICT, i18n::t("mr.draft", &[("id", &id)])));
+        }
+        if !mr::can_transition(&mr.status, &MergeStatus::Merged) {
+            return Err((StatusCode::CONFLICT, i18n::t("mr.not_open", &[("id", &id)])));
+        }
+        if let Some(entry) = self
+            .queue_storage
+            .get_entry(mr_id)
+            .await
+            .map_err(internal_error)?
+        {
+            if QUEUE_ACTIVE.contains(&entry.status.as_str()) {
+                return Err((
+                    StatusCode::CONFLICT,
+                    i18n::t("mr.already_queued", &[("id", &id)]),
+                ));
+            }
+        }
+        let reviews = self
+            .mrs
+            .review_storage
+            .list_reviews(mr_id)
+            .await
+            .map_err(internal_error)?;
+        if review::review_status(reviews.iter().map(|r| &r.state))
+            != review::review_state_name(&ReviewState::Approved)
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.not_approved", &[("id", &id)]),
+            ));
+        }
+        let loader = ObjectLoader::new(self.mrs.storage.clone());
+        let target = loader
+            .resolve_ref(&mr.repo_path, Some(&mr.target_ref))
+            .await?;
+        let source = loader
+            .resolve_ref(&mr.repo_path, Some(&mr.source_ref))
+            .await?;
+        self.mrs
+            .ensure_owners_approved(&mr, &target, &source)
+            .await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        self.queue_storage
+            .enqueue(mega_merge_queue::Model {
+                id: generate_id(),
+                mr_id,
+                repo_path: mr.repo_path,
+                target_ref: mr.target_ref,
+                status: QUEUE_WAITING.to_owned(),
+                source_commit_id: None,
+                base_commit_id: None,
+                head_commit_id: None,
+                reason: None,
+                enqueued_by: actor,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .map_err(internal_error)?;
+        self.get(mr_id).await
+    }
+
+    /// Take a merge request out of the queue of its target branch.
+    pub async fn dequeue(
+        &self,
+        mr_id: i64,
+        actor: String,
+    ) -> Result<Json<MergeQueueEntry>, (StatusCode, String)> {
+        let entry = self.get_entry(mr_id).await?;
+        if !QUEUE_ACTIVE.contains(&entry.status.as_str()) {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.not_queued", &[("id", &mr_id.to_string())]),
+            ));
+        }
+        let reason = format!("removed by {}", actor);
+        let entry = self.leave(entry, QUEUE_REMOVED, Some(&reason)).await?;
+        Ok(Json(entry_of(entry, None)))
+    }
+
+    /// Move the queues on every [`RUNNER_INTERVAL`].
+    pub fn start_runner(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RUNNER_INTERVAL);
+            loop {
+                interval.tick().await;
+                let targets = match self.queue_storage.queued_targets().await {
+                    Ok(targets) => targets,
+                    Err(e) => {
+                        tracing::warn!("unable to list the merge queues: {}", e);
+                        continue;
+                    }
+                };
+                for (repo_path, target_ref) in targets {
+                    if let Err((_, e)) = self.advance(&repo_path, &target_ref).await {
+                        tracing::warn!(
+                            "unable to move the merge queue of {} in {} on: {}",
+                            target_ref,
+                            repo_path,
+                            e
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    /// Bring the merges of the queue of a branch up to date with the branch and the source
+    /// branches, and merge the first entry once its checks succeeded.
+    async fn advance(&self, repo_path: &str, target_ref: &str) -> Result<(), (StatusCode, String)> {
+        let entries = self
+            .queue_storage
+            .list_entries(repo_path, target_ref)
+            .await
+            .map_err(internal_error)?;
+        let loader = ObjectLoader::new(self.mrs.storage.clone());
+        let mut target = loader.resolve_ref(repo_path, Some(target_ref)).await?;
+        let mut base = target;
+        let mut first = true;
+        for mut entry in entries {
+            let mr = match self.mrs.mr_storage.get_mr(entry.mr_id).await {
+                Ok(Some(mr)) if mr::can_transition(&mr.status, &MergeStatus::Merged) => mr,
+                Ok(_) => {
+                    self.leave(
+                        entry,
+                        QUEUE_REMOVED,
+                        Some("the merge request is no longer open, or is a draft"),
+                    )
+                    .await?;
+                    continue;
+                }
+                Err(e) => return Err(internal_error(e)),
+            };
+            let source = match loader.resolve_ref(repo_path, Some(&mr.source_ref)).await {
+                Ok(source) => source,
+                Err((_, e)) => {
+                    self.leave(entry, QUEUE_FAILED, Some(&e)).await?;
+                    continue;
+                }
+            };
+
+            if !is_current(&entry, &base, &source) {
+                let head = match self
+                    .mrs
+                    .merge_onto(&mr, &base, &source, MergeOptions::default())
+                    .await
+                {
+                    Ok(head) => head,
+                    Err((StatusCode::CONFLICT, e)) => {
+                        self.leave(entry, QUEUE_FAILED, Some(&e)).await?;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let old = entry
+                    .head_commit_id
+                    .as_deref()
+                    .and_then(|id| SHA1::from_str(id).ok());
+                self.mrs
+                    .ref_updater
+                    .update(
+                        repo_path,
+                        &queue_ref(entry.mr_id),
+                        old.as_ref(),
+                        &head,
+                        DEFAULT_COMMITTER.0,
+                        &format!("merge queue of {}", target_ref),
+                    )
+                    .await?;
+                entry.status = QUEUE_TESTING.to_owned();
+                entry.source_commit_id = Some(source.to_plain_str());
+                entry.base_commit_id = Some(base.to_plain_str());
+                entry.head_commit_id = Some(head.to_plain_str());
+                entry.updated_at = chrono::Utc::now().naive_utc();
+                self.queue_storage
+                    .update_entry(entry)
+                    .await
+                    .map_err(internal_error)?;
+                // the checks of the new merge have yet to run
+                first = false;
+                base = head;
+                continue;
+            }
+
+            let head_id = entry.head_commit_id.clone().unwrap_or_default();
+            let head = SHA1::from_str(&head_id).map_err(internal_error)?;
+            if !first {
+                base = head;
+                continue;
+            }
+            let checks = self
+                .mrs
+                .checks
+                .commit_checks(repo_path, &head_id, Some(target_ref))
+                .await?;
+            match checks.state.as_str() {
+                CHECK_FAILURE => {
+                    let failed: Vec<&str> = checks
+                        .checks
+                        .iter()
+                        .filter(|run| run.status == CHECK_FAILURE || run.status == CHECK_ERROR)
+                        .map(|run| run.name.as_str())
+                        .collect();
+                    let reason = format!("checks failed: {}", failed.join(", "));
+                    self.leave(entry, QUEUE_FAILED, Some(&reason)).await?;
+                }
+                CHECK_SUCCESS => {
+                    // the merge contains the branch as it was, unless it was pushed to since
+                    if loader.resolve_ref(repo_path, Some(target_ref)).await? != target {
+                        return Ok(());
+                    }
+                    self.mrs
+                        .ref_updater
+                        .update(
+                            repo_path,
+                            target_ref,
+                            Some(&target),
+                            &head,
+                            &entry.enqueued_by,
+                            &format!("merge request {} from the merge queue", entry.mr_id),
+                        )
+                        .await?;
+                    let actor = entry.enqueued_by.clone();
+                    self.mrs
+                        .mark_merged(mr, &target, &source, &head, &actor)
+                        .await?;
+                    self.leave(entry, QUEUE_MERGED, None).await?;
+                    target = head;
+                    base = head;
+                }
+                _ => {
+                    first = false;
+                    base = head;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Record how an entry left the queue and delete the branch of its merge.
+    async fn leave(
+        &self,
+        mut entry: mega_merge_queue::Model,
+        status: &str,
+        reason: Option<&str>,
+    ) -> Result<mega_merge_queue::Model, (StatusCode, String)> {
+        if let Some(head) = entry
+            .head_commit_id
+            .as_deref()
+            .and_then(|id| SHA1::from_str(id).ok())
+        {
+            if let Err((_, e)) = self
+                .mrs
+                .ref_updater
+                .delete(
+                    &entry.repo_path,
+                    &queue_ref(entry.mr_id),
+                    &head,
+                    DEFAULT_COMMITTER.0,
+                    &format!("merge request {} left the merge queue", entry.mr_id),
+                )
+                .await
+            {
+                tracing::warn!("unable to delete {}: {}", queue_ref(entry.mr_id), e);
+            }
+        }
+        entry.status = status.to_owned();
+        entry.reason = reason.map(str::to_owned);
+        entry.updated_at = chrono::Utc::now().naive_utc();
+        self.queue_storage
+            .update_entry(entry)
+            .await
+            .map_err(internal_error)
+    }
+
+    async fn get_entry(&self, mr_id: i64) -> Result<mega_merge_queue::Model, (StatusCode, String)> {
+        self.queue_storage
+            .get_entry(mr_id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    i18n::t("mr.not_queued", &[("id", &mr_id.to_string())]),
+                )
+            })
+    }
+
+    /// 1-based position of an entry in its queue, none once it left it.
+    async fn position(
+        &self,
+        entry: &mega_merge_queue::Model,
+    ) -> Result<Option<usize>, (StatusCode, String)> {
+        if !QUEUE_ACTIVE.contains(&entry.status.as_str()) {
+            return Ok(None);
+        }
+        let entries = self
+            .queue_storage
+            .list_entries(&entry.repo_path, &entry.target_ref)
+            .await
+            .map_err(internal_error)?;
+        Ok(entries.iter().position(|e| e.id == entry.id).map(|i| i + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use venus::hash::SHA1;
+
+    use db_entity::mega_merge_queue;
+    use jupiter::storage::merge_queue_storage::{QUEUE_TESTING, QUEUE_WAITING};
+
+    use super::{is_current, queue_ref};
+
+    #[test]
+    fn test_is_current() {
+        let (base, source, head) = (SHA1([1; 20]), SHA1([2; 20]), SHA1([3; 20]));
+        let now = chrono::Utc::now().naive_utc();
+        let mut entry = mega_merge_queue::Model {
+            id: 1,
+            mr_id: 7,
+            repo_path: "/project".to_owned(),
+            target_ref: "refs/heads/main".to_owned(),
+            status: QUEUE_WAITING.to_owned(),
+            source_commit_id: None,
+            base_commit_id: None,
+            head_commit_id: None,
+            reason: None,
+            enqueued_by: "mega".to_owned(),
+            created_at: now,
+            updated_at: now,
+        };
+        assert!(!is_current(&entry, &base, &source));
+
+        entry.status = QUEUE_TESTING.to_owned();
+        entry.base_commit_id = Some(base.to_plain_str());
+        entry.source_commit_id = Some(source.to_plain_str());
+        entry.head_commit_id = Some(head.to_plain_str());
+        assert!(is_current(&entry, &base, &source));
+        // the branch moved, or the entry before it was merged again
+        assert!(!is_current(&entry, &head, &source));
+        // the source branch was pushed to
+        assert!(!is_current(&entry, &base, &head));
+
+        assert_eq!(queue_ref(7), "refs/heads/mega-queue/7");
+    }
+}
@@ -0,0 +1,427 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use jupiter::storage::diffstat_storage::DiffstatStorage;
+use storage::driver::database::storage::ObjectStorage;
+
+use venus::hash::SHA1;
+use venus::internal::object::commit::Commit;
+use venus::internal::object::signature::{Signature, SignatureType};
+
+use crate::api_service::check_service::CheckService;
+use crate::api_service::compare;
+use crate::api_service::merge::{self, MergeOutcome, Merger};
+use crate::api_service::mr_service::DEFAULT_COMMITTER;
+use crate::api_service::object_loader::{self, ObjectLoader};
+use crate::api_service::ref_update::RefUpdater;
+use crate::model::merge::{
+    CompareQuery, ComparedCommit, Comparison, DiffStat, MergeBaseBatch, MergeCheck,
+    MergeCheckQuery, MergeStrategy, PickRequest, PickResult, RefComparison, RefPair,
+};
+
+/// Most ref pairs compared in one call.
+const MAX_PAIRS: usize = 100;
+
+/// Most commits listed by a comparison.
+const MAX_COMPARE_COMMITS: usize = 250;
+
+#[derive(Clone)]
+pub struct MergeService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub ref_updater: RefUpdater,
+    pub checks: CheckService,
+    pub diffstats: DiffstatStorage,
+}
+
+impl MergeService {
+    /// Try the merge without writing anything and report whether it would succeed.
+    pub async fn check(
+        &self,
+        query: MergeCheckQuery,
+    ) -> Result<Json<MergeCheck>, (StatusCode, String)> {
+        self.check_refs(
+            &query.repo_path,
+            &query.target,
+            &query.source,
+            query.strategy,
+        )
+        .await
+        .map(Json)
+    }
+
+    pub async fn check_refs(
+        &self,
+        repo_path: &str,
+        target: &str,
+        source: &str,
+        strategy: MergeStrategy,
+    ) -> Result<MergeCheck, (StatusCode, String)> {
+        let loader = ObjectLoader::new(self.storage.clone());
+        let target = loader.resolve_ref(repo_path, Some(target)).await?;
+        let source = loader.resolve_ref(repo_path, Some(source)).await?;
+
+        let mut merger = Merger::new(self.storage.clone());
+        let merge_base = merger.merge_base(&target, &source).await?;
+        let (status, tree_id, conflicts) = match merger.merge(&target, &source, strategy).await? {
+            MergeOutcome::UpToDate => ("up_to_date", None, Vec::new()),
+            MergeOutcome::FastForward { .. } => ("fast_forward", None, Vec::new()),
+            MergeOutcome::NotFastForward => ("not_fast_forward", None, Vec::new()),
+            MergeOutcome::Merged { tree_id, .. } => {
+                ("clean", Some(tree_id.to_plain_str()), Vec::new())
+            }
+            MergeOutcome::Conflicts(conflicts) => ("conflicts", None, conflicts),
+        };
+
+        Ok(MergeCheck {
+            target_id: target.to_plain_str(),
+            source_id: source.to_plain_str(),
+            merge_base: merge_base.map(|id| id.to_plain_str()),
+            mergeable: matches!(status, "fast_forward" | "clean"),
+            status: status.to_owned(),
+            tree_id,
+            conflicts,
+        })
+    }
+
+    /// Merge bases and ahead/behind counts of many ref pairs, like the rows of a merge request
+    /// list. The pairs share the commits loaded and the refs resolved, and a pair that can't be
+    /// compared has its `error` set instead of failing the others.
+    pub async fn compare_batch(
+        &self,
+        batch: MergeBaseBatch,
+    ) -> Result<Json<Vec<RefComparison>>, (StatusCode, String)> {
+        if batch.pairs.len() > MAX_PAIRS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("at most {} pairs can be compared at once", MAX_PAIRS),
+            ));
+        }
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut resolved = HashMap::new();
+        let mut comparisons = Vec::with_capacity(batch.pairs.len());
+        for pair in batch.pairs {
+            let mut comparison = RefComparison {
+                target: pair.target.clone(),
+                source: pair.source.clone(),
+                target_id: None,
+                source_id: None,
+                merge_base: None,
+                ahead: None,
+                behind: None,
+                error: None,
+            };
+            if let Err((_, e)) = compare_pair(
+                &mut loader,
+                &mut resolved,
+                &batch.repo_path,
+                &pair,
+                &mut comparison,
+            )
+            .await
+            {
+                comparison.error = Some(e);
+            }
+            comparisons.push(comparison);
+        }
+        Ok(Json(comparisons))
+    }
+
+    /// The commits `head` has over `base` and the files they change since the merge base, with
+    /// the lines added and removed, and the checks of `head`.
+    pub async fn compare(
+        &self,
+        query: CompareQuery,
+    ) -> Result<Json<Comparison>, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let base = loader
+            .resolve_ref(&query.repo_path, Some(&query.base))
+            .await?;
+        let head = loader
+            .resolve_ref(&query.repo_path, Some(&query.head))
+            .await?;
+
+        let search = merge::walk_merge_base(&mut loader, &base, &head).await?;
+        let (behind, ahead) = search.unique_counts();
+        let (_, unique) = search.unique_commits();
+        let merge_base = search.finish().into_iter().next();
+
+        let mut commits = Vec::with_capacity(unique.len());
+        for id in unique {
+            commits.push(loader.commit(&id).await?);
+        }
+        commits.sort_by_key(|c| Reverse(c.committer.timestamp));
+        commits.truncate(MAX_COMPARE_COMMITS);
+        let ids: Vec<String> = commits.iter().map(|c| c.id.to_plain_str()).collect();
+        let mut stats = self
+            .diffstats
+            .commit_stats(&query.repo_path, &ids)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let commits = commits
+            .into_iter()
+            .zip(ids)
+            .map(|(commit, id)| ComparedCommit {
+                stat: stats.remove(&id).map(DiffStat::from),
+                id,
+                summary: object_loader::commit_summary(&commit.message),
+                author: commit.author.name,
+                author_email: commit.author.email,
+                committed_at: commit.committer.timestamp,
+            })
+            .collect();
+
+        let base_tree = match merge_base {
+            Some(id) => Some(loader.commit(&id).await?.tree_id),
+            None => None,
+        };
+        let head_tree = loader.commit(&head).await?.tree_id;
+        let mut files = Vec::new();
+        compare::diff_trees(
+            &mut loader,
+            String::new(),
+            base_tree,
+            Some(head_tree),
+            &mut files,
+        )
+        .await?;
+
+        let checks = self
+            .checks
+            .commit_checks(&query.repo_path, &head.to_plain_str(), Some(&query.base))
+            .await?;
+
+        Ok(Json(Comparison {
+            base_id: base.to_plain_str(),
+            head_id: head.to_plain_str(),
+            merge_base: merge_base.map(|id| id.to_plain_str()),
+            ahead,
+            behind,
+            commits,
+            files_changed: files.len(),
+            additions: files.iter().map(|f| f.additions).sum(),
+            deletions: files.iter().map(|f| f.deletions).sum(),
+            files,
+            checks,
+        }))
+    }
+
+    /// Apply a commit onto a branch as a new commit keeping its author, like `git cherry-pick`.
+    pub async fn cherry_pick(
+        &self,
+        request: PickRequest,
+    ) -> Result<Json<PickResult>, (StatusCode, String)> {
+        self.pick(request, false).await.map(Json)
+    }
+
+    /// Undo a commit on a branch with a new commit, like `git revert`.
+    pub async fn revert(
+        &self,
+        request: PickRequest,
+    ) -> Result<Json<PickResult>, (StatusCode, String)> {
+        self.pick(request, true).await.map(Json)
+    }
+
+    /// Apply or undo the change of a commit on the target branch and move the branch to the new
+    /// commit. Conflicts are reported in the result, nothing is written then.
+    async fn pick(
+        &self,
+        request: PickRequest,
+        revert: bool,
+    ) -> Result<PickResult, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let target_ref = format!(
+            "refs/heads/{}",
+            request
+                .target
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&request.target)
+        );
+        let target = loader
+            .resolve_ref(&request.repo_path, Some(&target_ref))
+            .await?;
+        let commit = loader
+            .resolve_ref(&request.repo_path, Some(&request.commit))
+            .await?;
+
+        let mut merger = Merger::new(self.storage.clone());
+        let mut result = PickResult {
+            target_id: target.to_plain_str(),
+            status: String::new(),
+            commit_id: None,
+            conflicts: Vec::new(),
+        };
+        let tree_id = match merger
+            .pick(&commit, &target, request.mainline, revert)
+            .await?
+        {
+            MergeOutcome::Merged { tree_id, .. } => tree_id,
+            MergeOutcome::Conflicts(conflicts) => {
+                result.status = "conflicts".to_owned();
+                result.conflicts = conflicts;
+                return Ok(result);
+            }
+            _ => {
+                result.status = "empty".to_owned();
+                return Ok(result);
+            }
+        };
+
+        let picked = loader.commit(&commit).await?;
+        let message = request
+            .message
+            .unwrap_or_else(|| pick_message(&picked, revert));
+        let committer = Signature {
+            signature_type: SignatureType::Committer,
+            name: request
+                .committer_name
+                .unwrap_or_else(|| DEFAULT_COMMITTER.0.to_owned()),
+            email: request
+                .committer_email
+                .unwrap_or_else(|| DEFAULT_COMMITTER.1.to_owned()),
+            timestamp: chrono::Utc::now().timestamp() as usize,
+            timezone: "+0000".to_owned(),
+        };
+        // a revert is authored by whoever undoes the change
+        let author = if revert {
+            Signature {
+                signature_type: SignatureType::Author,
+                ..committer.clone()
+            }
+        } else {
+            picked.author.clone()
+        };
+        let actor = committer.name.clone();
+        let head = merger
+            .commit_authored(
+                &request.repo_path,
+                tree_id,
+                vec![target],
+                author,
+                committer,
+                &message,
+            )
+            .await?;
+
+        // a push may have moved the target while the change was applied
+        let current = loader
+            .resolve_ref(&request.repo_path, Some(&target_ref))
+            .await?;
+        if current != target {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} was updated meanwhile, retry", target_ref),
+            ));
+        }
+        let action = if revert { "revert" } else { "cherry-pick" };
+        let reason = format!("{} {}", action, commit.to_plain_str());
+        self.ref_updater
+            .update(
+                &request.repo_path,
+                &target_ref,
+                Some(&target),
+                &head,
+                &actor,
+                &reason,
+            )
+            .await?;
+        result.status = "applied".to_owned();
+        result.commit_id = Some(head.to_plain_str());
+        Ok(result)
+    }
+}
+
+/// The message git gives a cherry-pick or a revert of `commit`.
+fn pick_message(commit: &Commit, revert: bool) -> String {
+    let id = commit.id.to_plain_str();
+    if revert {
+        format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.",
+            object_loader::commit_summary(&commit.message),
+            id
+        )
+    } else {
+        format!(
+            "{}\n\n(cherry picked from commit {})",
+            object_loader::commit_body(&commit.message).trim_end(),
+            id
+        )
+    }
+}
+
+/// Fill in `comparison` for `pair`, keeping the refs resolved in `resolved`.
+async fn compare_pair(
+    loader: &mut ObjectLoader,
+    resolved: &mut HashMap<String, SHA1>,
+    repo_path: &str,
+    pair: &RefPair,
+    comparison: &mut RefComparison,
+) -> Result<(), (StatusCode, String)> {
+    let mut ids = Vec::with_capacity(2);
+    for name in [&pair.target, &pair.source] {
+        let id = match resolved.get(name) {
+            Some(id) => *id,
+            None => {
+                let id = loader.resolve_ref(repo_path, Some(name)).await?;
+                resolved.insert(name.clone(), id);
+                id
+            }
+        };
+        ids.push(id);
+    }
+    let (target, source) = (ids[0], ids[1]);
+    comparison.target_id = Some(target.to_plain_str());
+    comparison.source_id = Some(source.to_plain_str());
+
+    let search = merge::walk_merge_base(loader, &target, &source).await?;
+    let (behind, ahead) = search.unique_counts();
+    comparison.ahead = Some(ahead);
+    comparison.behind = Some(behind);
+    comparison.merge_base = search
+        .finish()
+        .into_iter()
+        .next()
+        .map(|id| id.to_plain_str());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use venus::hash::SHA1;
+    use venus::internal::object::commit::Commit;
+    use venus::internal::object::signature::{Signature, SignatureType};
+
+    use super::pick_message;
+
+    #[test]
+    fn test_pick_message() {
+        let signature = |signature_type| Signature {
+            signature_type,
+            name: "mega".to_owned(),
+            email: "mega@localhost".to_owned(),
+            timestamp: 0,
+            timezone: "+0000".to_owned(),
+        };
+        let commit = Commit {
+            id: SHA1([1; 20]),
+            tree_id: SHA1([2; 20]),
+            parent_commit_ids: vec![SHA1([3; 20])],
+            author: signature(SignatureType::Author),
+            committer: signature(SignatureType::Committer),
+            message: "\nfix build\n\ndetails\n".to_owned(),
+            gpgsig: None,
+        };
+        let id = "01".repeat(20);
+        assert_eq!(
+            pick_message(&commit, false),
+            format!("fix build\n\ndetails\n\n(cherry picked from commit {})", id)
+        );
+        assert_eq!(
+            pick_message(&commit, true),
+            format!("Revert \"fix build\"\n\nThis reverts commit {}.", id)
+        );
+    }
+}
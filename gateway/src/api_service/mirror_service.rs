@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Json;
+use bytes::Bytes;
+
+use common::operation::{self, OperationKind};
+use common::utils::generate_id;
+use db_entity::mega_mirror;
+use git::internal::budget::MemoryBudget;
+use git::protocol::profile::PushProfile;
+use git::protocol::refspec::{RefSpecError, RefSpecs};
+use git::protocol::{pack, PackProtocol, Protocol};
+use git::structure::conversion;
+use jupiter::storage::mirror_storage::MirrorStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+
+use crate::api_service::path_move;
+use crate::api_service::ref_update::RefUpdater;
+use crate::api_service::remote::{self, ImportThrottle, RemoteClone};
+use crate::model::mirror::{Mirror, MirrorSync, MirrorUpdate};
+
+/// How often the scheduler looks for mirrors that are due.
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shortest time between two scheduled syncs of a mirror.
+const MIN_INTERVAL_SECS: i64 = 60;
+
+/// Actor recorded in the ref audit for the updates of a sync.
+const MIRROR_ACTOR: &str = "mega";
+
+/// Refspecs of a mirror without any: the branches and tags, under their own names.
+pub const DEFAULT_REFSPECS: &[&str] = &["refs/heads/*:refs/heads/*", "refs/tags/*:refs/tags/*"];
+
+/// Keeps repositories in sync with the upstream remote they mirror.
+#[derive(Clone)]
+pub struct MirrorService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub mirror_storage: MirrorStorage,
+    pub ref_updater: RefUpdater,
+    /// Shared with the imports, so syncs and imports together don't overload the upstreams
+    pub throttle: ImportThrottle,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+/// What a sync does to the refs of the mirror, as ref names mapped to their upstream ids.
+#[derive(Debug, Default, PartialEq)]
+struct SyncPlan {
+    create: Vec<(String, String)>,
+    update: Vec<(String, String)>,
+    delete: Vec<String>,
+    conflicts: Vec<String>,
+}
+
+/// The refspecs of `mirror`, which were checked when it was saved.
+fn refspecs(mirror: &mega_mirror::Model) -> Result<RefSpecs, RefSpecError> {
+    match &mirror.refspecs {
+        Some(refspecs) => RefSpecs::fetch(refspecs.lines()),
+        None => RefSpecs::fetch(DEFAULT_REFSPECS),
+    }
+}
+
+/// Compare the `upstream` refs, under the names the refspecs map them to, with the `local` ones.
+/// A ref that moved upstream is updated when `fast_forward` or `forced` holds it, it is a
+/// conflict otherwise. Refs gone upstream are deleted only with `prune`.
+fn plan_sync(
+    upstream: &[(String, String)],
+    local: &HashMap<String, String>,
+    fast_forward: &HashSet<String>,
+    forced: &HashSet<String>,
+    prune: bool,
+) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+    for (name, id) in upstream {
+        match local.get(name) {
+            None => plan.create.push((name.clone(), id.clone())),
+            Some(local_id) if local_id == id => {}
+            Some(_) if forced.contains(name) || fast_forward.contains(name) => {
+                plan.update.push((name.clone(), id.clone()))
+            }
+            Some(_) => plan.conflicts.push(name.clone()),
+        }
+    }
+    if prune {
+        let upstream: HashSet<&String> = upstream.iter().map(|(name, _)| name).collect();
+        plan.delete = local
+            .keys()
+            .filter(|name| !upstream.contains(name))
+            .cloned()
+            .collect();
+        plan.delete.sort();
+    }
+    plan
+}
+
+impl MirrorService {
+    pub async fn list(&self) -> Result<Json<Vec<Mirror>>, (StatusCode, String)> {
+        let mirrors = self
+            .mirror_storage
+            .list_mirrors()
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(mirrors.into_iter().map(Mirror::from).collect()))
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Json<Mirror>, (StatusCode, String)> {
+        Ok(Json(self.find(id).await?.into()))
+    }
+
+    /// Mirror `update.url` at `update.repo_path`, or change the settings of the mirror already
+    /// there. The first sync happens on the next check of the scheduler.
+    pub async fn save(&self, update: MirrorUpdate) -> Result<Json<Mirror>, (StatusCode, String)> {
+        let url = update.url.trim().to_owned();
+        remote::check_url(&url).map_err(bad_request)?;
+        let refspecs: Vec<String> = update
+            .refspecs
+            .iter()
+            .map(|spec| spec.trim().to_owned())
+            .filter(|spec| !spec.is_empty())
+            .collect();
+        RefSpecs::fetch(&refspecs).map_err(bad_request)?;
+        let refspecs = (!refspecs.is_empty()).then(|| refspecs.join("\n"));
+        let repo_path = path_move::normalize_path(&update.repo_path)
+            .filter(|p| p != "/")
+            .ok_or_else(|| bad_request(format!("invalid path: {}", update.repo_path)))?;
+        if update.interval_secs < MIN_INTERVAL_SECS {
+            return Err(bad_request(format!(
+                "interval must be at least {} seconds",
+                MIN_INTERVAL_SECS
+            )));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let existing = self
+            .mirror_storage
+            .find_mirror(&repo_path)
+            .await
+            .map_err(internal_error)?;
+        let mirror = match existing {
+            Some(mirror) => {
+                let mirror = mega_mirror::Model {
+                    url,
+                    interval_secs: update.interval_secs,
+                    force: update.force,
+                    prune: update.prune,
+                    enabled: update.enabled,
+                    refspecs,
+                    updated_at: now,
+                    ..mirror
+                };
+                self.mirror_storage
+                    .update_mirror(mirror)
+                    .await
+                    .map_err(internal_error)?
+            }
+            None => {
+                let mirror = mega_mirror::Model {
+                    id: generate_id(),
+                    repo_path,
+                    url,
+                    interval_secs: update.interval_secs,
+                    force: update.force,
+                    prune: update.prune,
+                    enabled: update.enabled,
+                    refspecs,
+                    next_sync_at: now,
+                    last_sync_at: None,
+                    last_status: None,
+                    last_message: None,
+                    created_at: now,
+                    updated_at: now,
+                };
+                self.mirror_storage
+                    .save_mirror(mirror.clone())
+                    .await
+                    .map_err(internal_error)?;
+                mirror
+            }
+        };
+        Ok(Json(mirror.into()))
+    }
+
+    /// Stop mirroring, the refs and history of the repository stay.
+    pub async fn delete(&self, id: i64) -> Result<Json<Mirror>, (StatusCode, String)> {
+        let mirror = self.find(id).await?;
+        self.mirror_storage
+            .delete_mirror(id)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(mirror.into()))
+    }
+
+    /// Sync the mirror now, whether it is due or even enabled.
+    pub async fn sync_now(&self, id: i64) -> Result<Json<MirrorSync>, (StatusCode, String)> {
+        let mirror = self.find(id).await?;
+        let _permit = self.throttle.acquire().await;
+        let result = self.sync(&mirror).await;
+        self.record(&mirror, &result).await;
+        result.map(Json)
+    }
+
+    async fn find(&self, id: i64) -> Result<mega_mirror::Model, (StatusCode, String)> {
+        self.mirror_storage
+            .get_mirror(id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("mirror {} not found", id)))
+    }
+
+    /// Check for due mirrors every minute, in the background.
+    pub fn start_scheduler(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.sync_due().await;
+            }
+        });
+    }
+
+    /// Sync every enabled mirror whose time has come, as many at once as the throttle allows.
+    /// Syncs missed while the server was down happen once on the next check.
+    async fn sync_due(&self) {
+        let now = chrono::Utc::now().naive_utc();
+        let due = match self.mirror_storage.due_mirrors(now).await {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::warn!("unable to load mirrors: {}", e);
+                return;
+            }
+        };
+        for mirror in due {
+            let permit = self.throttle.acquire().await;
+            let next = now + chrono::Duration::seconds(mirror.interval_secs.max(MIN_INTERVAL_SECS));
+            match self
+                .mirror_storage
+                .claim_sync(mirror.id, mirror.next_sync_at, next)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    tracing::warn!("unable to schedule mirror of {}: {}", mirror.repo_path, e);
+                    continue;
+                }
+            }
+            let service = self.clone();
+            tokio::spawn(async move {
+                let result = service.sync(&mirror).await;
+                service.record(&mirror, &result).await;
+                drop(permit);
+            });
+        }
+    }
+
+    async fn record(
+        &self,
+        mirror: &mega_mirror::Model,
+        result: &Result<MirrorSync, (StatusCode, String)>,
+    ) {
+        let (status, message) = match result {
+            Ok(sync) => {
+                tracing::info!(
+                    "synced {}: {} created, {} updated, {} deleted, {} conflicts",
+                    mirror.repo_path,
+                    sync.created.len(),
+                    sync.updated.len(),
+                    sync.deleted.len(),
+                    sync.conflicts.len()
+                );
+                let message = (!sync.conflicts.is_empty())
+                    .then(|| format!("diverged from upstream: {}", sync.conflicts.join(", ")));
+                (sync.status.as_str(), message)
+            }
+            Err((_, err)) => {
+                tracing::warn!("unable to sync {}: {}", mirror.repo_path, err);
+                ("failed", Some(err.clone()))
+            }
+        };
+        if let Err(e) = self
+            .mirror_storage
+            .record_sync(mirror.id, status, message)
+            .await
+        {
+            tracing::warn!("unable to record sync of {}: {}", mirror.repo_path, e);
+        }
+    }
+
+    /// Fetch the upstream of `mirror`, store the objects the repository is missing and move its
+    /// branches and tags to where the upstream has them. A cancelled sync stops before the next
+    /// ref it would change, the refs changed so far stay.
+    async fn sync(&self, mirror: &mega_mirror::Model) -> Result<MirrorSync, (StatusCode, String)> {
+        let operation = operation::start(OperationKind::MirrorSync, &mirror.repo_path);
+        let specs = refspecs(mirror).map_err(internal_error)?;
+        operation.set_stage("cloning", 0);
+        let clone = remote::cancellable(
+            &operation,
+            RemoteClone::fetch(&mirror.url, &format!("mirror-{}", mirror.id)),
+        )
+        .await?;
+        let advertised: HashMap<String, String> = clone.refs().await?.into_iter().collect();
+        let mappings = specs
+            .map_all(advertised.keys().map(String::as_str))
+            .map_err(internal_error)?;
+        let mut upstream = Vec::new();
+        let mut forced = HashSet::new();
+        for mapping in mappings {
+            if mirror.force || mapping.force {
+                forced.insert(mapping.dst.clone());
+            }
+            upstream.push((mapping.dst, advertised[&mapping.src].clone()));
+        }
+        upstream.sort();
+        // the refs of the repository the refspecs don't store anything in are left alone
+        let local: HashMap<String, String> = self
+            .storage
+            .get_all_refs_by_path(&mirror.repo_path)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .filter(|r| specs.reverse(&r.ref_name).is_some())
+            .map(|r| (r.ref_name, r.ref_git_id))
+            .collect();
+
+        // local commits the upstream also has, everything reachable from them is stored already
+        let local_ids: Vec<&str> = local.values().map(String::as_str).collect();
+        let known = clone.contains(&local_ids).await?;
+        let mut fast_forward = HashSet::new();
+        operation.set_stage("comparing refs", upstream.len() as u64);
+        for (name, id) in &upstream {
+            operation.check().map_err(remote::cancelled_error)?;
+            operation.advance(1);
+            match local.get(name) {
+                Some(local_id)
+                    if local_id != id
+                        && known.contains(local_id)
+                        && clone.is_ancestor(local_id, id).await =>
+                {
+                    fast_forward.insert(name.clone());
+                }
+                _ => {}
+            }
+        }
+        let plan = plan_sync(&upstream, &local, &fast_forward, &forced, mirror.prune);
+
+        let wanted: Vec<&str> = plan
+            .create
+            .iter()
+            .chain(&plan.update)
+            .map(|(_, id)| id.as_str())
+            .collect();
+        let mut object_count = 0;
+        if !wanted.is_empty() {
+            let known: Vec<&str> = known.iter().map(String::as_str).collect();
+            operation.set_stage("packing", 0);
+            let pack = remote::cancellable(&operation, clone.pack(&wanted, &known)).await?;
+            object_count = remote::pack_object_count(&pack);
+            operation.check().map_err(remote::cancelled_error)?;
+            operation.set_stage("storing", 0);
+            if object_count > 0 {
+                self.store(&mirror.repo_path, pack).await?;
+            }
+        }
+        drop(clone);
+
+        let reason = format!("mirror {}", remote::strip_credentials(&mirror.url));
+        let parse = |id: &str| SHA1::from_str(id).map_err(internal_error);
+        let step = || {
+            operation.check().map_err(remote::cancelled_error)?;
+            operation.advance(1);
+            Ok::<_, (StatusCode, String)>(())
+        };
+        operation.set_stage(
+            "updating refs",
+            (plan.create.len() + plan.update.len() + plan.delete.len()) as u64,
+        );
+        for (name, id) in &plan.create {
+            step()?;
+            self.ref_updater
+                .update(
+                    &mirror.repo_path,
+                    name,
+                    None,
+                    &parse(id)?,
+                    MIRROR_ACTOR,
+                    &reason,
+                )
+                .await?;
+        }
+        for (name, id) in &plan.update {
+            step()?;
+            let old_id = parse(&local[name])?;
+            self.ref_updater
+                .update(
+                    &mirror.repo_path,
+                    name,
+                    Some(&old_id),
+                    &parse(id)?,
+                    MIRROR_ACTOR,
+                    &reason,
+                )
+                .await?;
+        }
+        for name in &plan.delete {
+            step()?;
+            self.ref_updater
+                .delete(
+                    &mirror.repo_path,
+                    name,
+                    &parse(&local[name])?,
+                    MIRROR_ACTOR,
+                    &reason,
+                )
+                .await?;
+        }
+
+        let names = |refs: Vec<(String, String)>| refs.into_iter().map(|(name, _)| name).collect();
+        Ok(MirrorSync {
+            status: if plan.conflicts.is_empty() {
+                "ok"
+            } else {
+                "conflicts"
+            }
+            .to_owned(),
+            created: names(plan.create),
+            updated: names(plan.update),
+            deleted: plan.delete,
+            conflicts: plan.conflicts,
+            object_count,
+        })
+    }
+
+    /// Store the objects of `pack` in the repository at `repo_path`, as a push of them would.
+    async fn store(&self, repo_path: &str, pack: Vec<u8>) -> Result<(), (StatusCode, String)> {
+        let mut pack = Bytes::from(pack);
+        let mr_id = pack::unpack(
+            self.storage.clone(),
+            &mut pack,
+            MemoryBudget::from_env(),
+            &mut PushProfile::default(),
+        )
+        .await
+        .map_err(internal_error)?;
+        let path = PathBuf::from(repo_path);
+        conversion::save_node_from_mr(self.storage.clone(), mr_id, &path)
+            .await
+            .map_err(internal_error)?;
+        PackProtocol::new(path, self.storage.clone(), Protocol::Local)
+            .handle_directory()
+            .await
+            .map_err(internal_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::plan_sync;
+
+    fn refs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, id)| (name.to_string(), id.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let upstream = refs(&[
+            ("refs/heads/main", "b"),
+            ("refs/heads/dev", "d2"),
+            ("refs/heads/new", "n"),
+            ("refs/tags/v1", "t"),
+        ]);
+        let local: HashMap<String, String> = refs(&[
+            ("refs/heads/main", "a"),
+            ("refs/heads/dev", "d1"),
+            ("refs/heads/gone", "g"),
+            ("refs/tags/v1", "t"),
+        ])
+        .into_iter()
+        .collect();
+        let fast_forward = HashSet::from(["refs/heads/main".to_owned()]);
+
+        let plan = plan_sync(&upstream, &local, &fast_forward, &HashSet::new(), false);
+        assert_eq!(plan.create, refs(&[("refs/heads/new", "n")]));
+        assert_eq!(plan.update, refs(&[("refs/heads/main", "b")]));
+        assert_eq!(plan.conflicts, vec!["refs/heads/dev".to_owned()]);
+        assert!(plan.delete.is_empty());
+
+        let forced = upstream.iter().map(|(name, _)| name.clone()).collect();
+        let plan = plan_sync(&upstream, &local, &fast_forward, &forced, true);
+        assert_eq!(
+            plan.update,
+            refs(&[("refs/heads/main", "b"), ("refs/heads/dev", "d2")])
+        );
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(plan.delete, vec!["refs/heads/gone".to_owned()]);
+    }
+}
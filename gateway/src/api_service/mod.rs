@@ -1,2 +1,70 @@
+pub mod account_service;
+pub mod acl_service;
+pub mod archive;
+pub mod archive_service;
+pub mod autolink;
+pub mod autolink_service;
+pub mod blame_service;
+pub mod board_service;
+pub mod bundle_service;
+pub mod changelog;
+pub mod changelog_service;
+pub mod check_service;
+pub mod ci_log_service;
+pub mod codeowners;
+pub mod compare;
+pub mod conditional;
+pub mod diffstat_service;
+pub mod erasure_service;
+pub mod error;
+pub mod event_service;
+pub mod feature_flag_service;
+pub mod highlight;
+pub mod import_service;
+pub mod issue_service;
+pub mod linguist;
+pub mod mailmap;
+pub mod markdown;
+pub mod merge;
+pub mod merge_queue_service;
+pub mod merge_service;
+pub mod mirror_service;
+pub mod mr_review_service;
+pub mod mr_service;
+pub mod notification;
+pub mod notification_service;
 pub mod obj_service;
-pub mod router;
\ No newline at end of file
+pub mod object_batch;
+pub mod object_loader;
+pub mod oidc_service;
+pub mod page;
+pub mod path_move;
+pub mod path_move_service;
+pub mod pipeline;
+pub mod pipeline_service;
+pub mod planning_service;
+pub mod push_profile_service;
+pub mod reaction_service;
+pub mod ref_hook;
+pub mod ref_hook_service;
+pub mod ref_trigger;
+pub mod ref_trigger_service;
+pub mod ref_update;
+pub mod reference;
+pub mod release_service;
+pub mod remote;
+pub mod repair_service;
+pub mod router;
+pub mod search_index;
+pub mod search_service;
+pub mod signing_key_service;
+pub mod snapshot;
+pub mod snapshot_export_service;
+pub mod snapshot_service;
+pub mod snippet_service;
+pub mod ssh_key_service;
+pub mod stats;
+pub mod stats_service;
+pub mod webhook;
+pub mod webhook_service;
+pub mod wiki_service;
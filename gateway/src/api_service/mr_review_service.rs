@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::db_enums::ReviewState;
+use db_entity::{mega_mr, mega_mr_comment, mega_mr_review, mega_mr_thread};
+use jupiter::storage::mr_review_storage::MrReviewStorage;
+use jupiter::storage::mr_storage::MrStorage;
+use jupiter::storage::reference_storage::{SOURCE_COMMENT, SOURCE_REVIEW};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::tree::TreeItemMode;
+
+use crate::api_service::autolink::Autolinker;
+use crate::api_service::event_service::{EventService, EVENT_COMMENT};
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::reference::{self, Referencer};
+use crate::api_service::webhook;
+use crate::model::event::CommentEvent;
+use crate::model::mr;
+use crate::model::review::{
+    self, NewComment, NewReview, NewThread, Review, ReviewComment, ReviewSummary, ReviewThread,
+    ThreadQuery,
+};
+
+#[derive(Clone)]
+pub struct MrReviewService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub mr_storage: MrStorage,
+    pub review_storage: MrReviewStorage,
+    pub events: EventService,
+    pub autolinks: Autolinker,
+    pub references: Referencer,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(message: &str) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.to_owned())
+}
+
+/// Trimmed body of a comment, which must be present.
+fn comment_body(body: &str) -> Result<&str, (StatusCode, String)> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(bad_request("comment body must not be empty"));
+    }
+    Ok(body)
+}
+
+/// The review `reviewer` gives on `mr` with `head` as the head of its source branch. The author
+/// of a merge request can't approve it.
+fn review_model(
+    mr: &mega_mr::Model,
+    reviewer: &str,
+    new_review: NewReview,
+    head: &SHA1,
+) -> Result<mega_mr_review::Model, (StatusCode, String)> {
+    let state = review::parse_review_state(&new_review.state).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("unknown review state: {}", new_review.state),
+        )
+    })?;
+    if state == ReviewState::Approved && review::is_author(mr.author.as_deref(), reviewer) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "the author of a merge request can't approve it".to_owned(),
+        ));
+    }
+    let now = chrono::Utc::now().naive_utc();
+    Ok(mega_mr_review::Model {
+        id: generate_id(),
+        mr_id: mr.id,
+        reviewer: reviewer.to_owned(),
+        state,
+        body: new_review.body.filter(|b| !b.trim().is_empty()),
+        commit_id: Some(head.to_plain_str()),
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+impl MrReviewService {
+    /// Threads of a merge request with their comments, oldest first.
+    pub async fn list_threads(
+        &self,
+        mr_id: i64,
+        query: ThreadQuery,
+    ) -> Result<Json<Vec<ReviewThread>>, (StatusCode, String)> {
+        let mr = self.get_mr(mr_id).await?;
+        let rules = self.autolinks.rules().await?;
+        let threads = self
+            .review_storage
+            .list_threads(mr_id, query.resolved)
+            .await
+            .map_err(internal_error)?;
+        let stored = self
+            .review_storage
+            .list_comments(mr_id)
+            .await
+            .map_err(internal_error)?;
+        let ids: Vec<i64> = stored.iter().map(|c| c.id).collect();
+        let mut references = self
+            .references
+            .references_of(SOURCE_COMMENT, &ids)
+            .await
+            .map_err(internal_error)?;
+        let base_url = webhook::public_url();
+        let mut comments: HashMap<i64, Vec<ReviewComment>> = HashMap::new();
+        for comment in stored {
+            let thread_id = comment.thread_id;
+            let mut comment: ReviewComment = comment.into();
+            comment.autolinks = reference::with_references(
+                &comment.body,
+                &references.remove(&comment.id).unwrap_or_default(),
+                rules.resolve(&mr.repo_path, &comment.body),
+                &base_url,
+            );
+            comments.entry(thread_id).or_default().push(comment);
+        }
+        Ok(Json(
+            threads
+                .into_iter()
+                .map(|thread| {
+                    let thread_comments = comments.remove(&thread.id).unwrap_or_default();
+                    ReviewThread::new(thread, thread_comments)
+                })
+                .collect(),
+        ))
+    }
+
+    /// Start a thread by `author` with its first comment, anchored to a line when `path` is
+    /// given.
+    pub async fn create_thread(
+        &self,
+        mr_id: i64,
+        author: &str,
+        new_thread: NewThread,
+    ) -> Result<Json<ReviewThread>, (StatusCode, String)> {
+        let mr = self.get_mr(mr_id).await?;
+        let body = comment_body(&new_thread.body)?;
+        let (path, line, commit_id) = match new_thread.path.as_deref().map(str::trim) {
+            Some(path) if !path.is_empty() => {
+                let line = match new_thread.line {
+                    Some(line) if line >= 1 => line,
+                    _ => {
+                        return Err(bad_request(
+                            "line must be given as a positive number with path",
+                        ))
+                    }
+                };
+                let commit_id = self
+                    .anchor_commit(&mr, new_thread.commit_id.as_deref(), path)
+                    .await?;
+                (
+                    Some(path.to_owned()),
+                    Some(line),
+                    Some(commit_id.to_plain_str()),
+                )
+            }
+            _ => {
+                if new_thread.line.is_some() {
+                    return Err(bad_request("line requires a path"));
+                }
+                (None, None, None)
+            }
+        };
+
+        let now = chrono::Utc::now().naive_utc();
+        let thread = mega_mr_thread::Model {
+            id: generate_id(),
+            mr_id,
+            path,
+            line,
+            commit_id,
+            resolved: false,
+            resolved_by: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let comment = mega_mr_comment::Model {
+            id: generate_id(),
+            mr_id,
+            thread_id: thread.id,
+            author: author.to_owned(),
+            body: body.to_owned(),
+            created_at: now,
+            updated_at: now,
+        };
+        self.review_storage
+            .save_thread(thread.clone())
+            .await
+            .map_err(internal_error)?;
+        self.review_storage
+            .save_comment(comment.clone())
+            .await
+            .map_err(internal_error)?;
+        let comment = self.linked_comment(&mr, comment).await?;
+        let event = CommentEvent {
+            mr_id,
+            thread_id: Some(thread.id),
+            comment: Some(&comment),
+            review: None,
+        };
+        self.events
+            .publish(
+                EVENT_COMMENT,
+                "created",
+                &mr.repo_path,
+                Some(author),
+                &event,
+            )
+            .await;
+        Ok(Json(ReviewThread::new(thread, vec![comment])))
+    }
+
+    pub async fn add_comment(
+        &self,
+        mr_id: i64,
+        thread_id: i64,
+        author: &str,
+        new_comment: NewComment,
+    ) -> Result<Json<ReviewComment>, (StatusCode, String)> {
+        let mr = self.get_mr(mr_id).await?;
+        self.get_thread(mr_id, thread_id).await?;
+        let body = comment_body(&new_comment.body)?;
+        let now = chrono::Utc::now().naive_utc();
+        let comment = mega_mr_comment::Model {
+            id: generate_id(),
+            mr_id,
+            thread_id,
+            author: author.to_owned(),
+            body: body.to_owned(),
+            created_at: now,
+            updated_at: now,
+        };
+        self.review_storage
+            .save_comment(comment.clone())
+            .await
+            .map_err(internal_error)?;
+        let comment = self.linked_comment(&mr, comment).await?;
+        let event = CommentEvent {
+            mr_id,
+            thread_id: Some(thread_id),
+            comment: Some(&comment),
+            review: None,
+        };
+        self.events
+            .publish(
+                EVENT_COMMENT,
+                "created",
+                &mr.repo_path,
+                Some(author),
+                &event,
+            )
+            .await;
+        Ok(Json(comment))
+    }
+
+    pub async fn resolve_thread(
+        &self,
+        mr_id: i64,
+        thread_id: i64,
+        user: &str,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let thread = self.get_thread(mr_id, thread_id).await?;
+        if thread.resolved {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("thread {} is already resolved", thread_id),
+            ));
+        }
+        self.review_storage
+            .set_resolved(thread_id, Some(user))
+            .await
+            .map_err(internal_error)?;
+        self.publish_thread(mr_id, thread_id, "resolved", Some(user))
+            .await;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    pub async fn unresolve_thread(
+        &self,
+        mr_id: i64,
+        thread_id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let thread = self.get_thread(mr_id, thread_id).await?;
+        if !thread.resolved {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("thread {} is not resolved", thread_id),
+            ));
+        }
+        self.review_storage
+            .set_resolved(thread_id, None)
+            .await
+            .map_err(internal_error)?;
+        self.publish_thread(mr_id, thread_id, "unresolved", None)
+            .await;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    pub async fn reviews(&self, mr_id: i64) -> Result<Json<ReviewSummary>, (StatusCode, String)> {
+        self.get_mr(mr_id).await?;
+        let reviews = self
+            .review_storage
+            .list_reviews(mr_id)
+            .await
+            .map_err(internal_error)?;
+        let unresolved_threads = self
+            .review_storage
+            .list_threads(mr_id, Some(false))
+            .await
+            .map_err(internal_error)?
+            .len();
+        let reviewers = |state: ReviewState| -> Vec<String> {
+            reviews
+                .iter()
+                .filter(|r| r.state == state)
+                .map(|r| r.reviewer.clone())
+                .collect()
+        };
+        Ok(Json(ReviewSummary {
+            status: review::review_status(reviews.iter().map(|r| &r.state)).to_owned(),
+            approved_by: reviewers(ReviewState::Approved),
+            changes_requested_by: reviewers(ReviewState::ChangesRequested),
+            unresolved_threads,
+            reviews: reviews.into_iter().map(Review::from).collect(),
+        }))
+    }
+
+    /// Record the verdict of `reviewer` on an active merge request, replacing their earlier one.
+    /// An open request whose reviews now add up to an approval is approved, an approved one
+    /// whose reviews no longer do is open again.
+    pub async fn submit_review(
+        &self,
+        mr_id: i64,
+        reviewer: &str,
+        new_review: NewReview,
+    ) -> Result<Json<Review>, (StatusCode, String)> {
+        let mut mr = self.get_mr(mr_id).await?;
+        if !mr.status.is_active() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("merge request {} is not open", mr_id),
+            ));
+        }
+        let head = ObjectLoader::new(self.storage.clone())
+            .resolve_ref(&mr.repo_path, Some(&mr.source_ref))
+            .await?;
+        let model = review_model(&mr, reviewer, new_review, &head)?;
+        self.review_storage
+            .save_review(model.clone())
+            .await
+            .map_err(internal_error)?;
+        let reviews = self
+            .review_storage
+            .list_reviews(mr_id)
+            .await
+            .map_err(internal_error)?;
+        // the stored review keeps the id of the reviewer's first one
+        if let Some(stored) = reviews.iter().find(|r| r.reviewer == reviewer) {
+            self.references
+                .record(
+                    &mr.repo_path,
+                    SOURCE_REVIEW,
+                    stored.id,
+                    mr_id,
+                    stored.body.as_deref().unwrap_or_default(),
+                )
+                .await;
+        }
+        let approved = review::review_status(reviews.iter().map(|r| &r.state))
+            == review::review_state_name(&ReviewState::Approved);
+        if let Some(status) = mr::review_transition(&mr.status, approved) {
+            mr.status = status;
+            mr.updated_at = chrono::Utc::now().naive_utc();
+            mr = self
+                .mr_storage
+                .update_mr(mr)
+                .await
+                .map_err(internal_error)?;
+        }
+        let review: Review = model.into();
+        let event = CommentEvent {
+            mr_id,
+            thread_id: None,
+            comment: None,
+            review: Some(&review),
+        };
+        self.events
+            .publish(
+                EVENT_COMMENT,
+                "reviewed",
+                &mr.repo_path,
+                Some(reviewer),
+                &event,
+            )
+            .await;
+        Ok(Json(review))
+    }
+
+    /// A saved comment with the links of the references its body makes, which are recorded.
+    async fn linked_comment(
+        &self,
+        mr: &mega_mr::Model,
+        comment: mega_mr_comment::Model,
+    ) -> Result<ReviewComment, (StatusCode, String)> {
+        let references = self
+            .references
+            .record(
+                &mr.repo_path,
+                SOURCE_COMMENT,
+                comment.id,
+                mr.id,
+                &comment.body,
+            )
+            .await;
+        let rule_links = self
+            .autolinks
+            .rules()
+            .await?
+            .resolve(&mr.repo_path, &comment.body);
+        let mut comment: ReviewComment = comment.into();
+        comment.autolinks = reference::with_references(
+            &comment.body,
+            &references,
+            rule_links,
+            &webhook::public_url(),
+        );
+        Ok(comment)
+    }
+
+    async fn publish_thread(&self, mr_id: i64, thread_id: i64, action: &str, actor: Option<&str>) {
+        let repo_path = match self.get_mr(mr_id).await {
+            Ok(mr) => mr.repo_path,
+            Err((_, err)) => {
+                tracing::warn!("unable to publish {} thread {}: {}", action, thread_id, err);
+                return;
+            }
+        };
+        let event = CommentEvent {
+            mr_id,
+            thread_id: Some(thread_id),
+            comment: None,
+            review: None,
+        };
+        self.events
+            .publish(EVENT_COMMENT, action, &repo_path, actor, &event)
+            .await;
+    }
+
+    /// The commit an inline comment refers to, which must contain the file at `path`.
+    async fn anchor_commit(
+        &self,
+        mr: &mega_mr::Model,
+        commit_id: Option<&str>,
+        path: &str,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let commit_id = match commit_id {
+            Some(id) => SHA1::from_str(id).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+            None => {
+                loader
+                    .resolve_ref(&mr.repo_path, Some(&mr.source_ref))
+                    .await?
+            }
+        };
+        let commit = loader.commit(&commit_id).await?;
+        match loader.find_path(&commit.tree_id, path).await? {
+            Some(item) if item.mode != TreeItemMode::Tree => Ok(commit_id),
+            _ => Err((
+                StatusCode::BAD_REQUEST,
+                format!("{} is not a file in {}", path, commit_id.to_plain_str()),
+            )),
+        }
+    }
+
+    async fn get_mr(&self, id: i64) -> Result<mega_mr::Model, (StatusCode, String)> {
+        match self.mr_storage.get_mr(id).await {
+            Ok(Some(model)) => Ok(model),
+            Ok(None) => Err((
+                StatusCode::NOT_FOUND,
+                format!("merge request {} not found", id),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    async fn get_thread(
+        &self,
+        mr_id: i64,
+        thread_id: i64,
+    ) -> Result<mega_mr_thread::Model, (StatusCode, String)> {
+        match self.review_storage.get_thread(mr_id, thread_id).await {
+            Ok(Some(thread)) => Ok(thread),
+            Ok(None) => Err((
+                StatusCode::NOT_FOUND,
+                format!("merge request {} has no thread {}", mr_id, thread_id),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use db_entity::db_enums::MergeStatus;
+    use db_entity::mega_mr;
+    use venus::hash::SHA1;
+
+    use super::review_model;
+    use crate::api_service::codeowners::CodeOwners;
+    use crate::model::review::{self, NewReview};
+
+    fn mr(author: Option<&str>) -> mega_mr::Model {
+        let now = chrono::Utc::now().naive_utc();
+        mega_mr::Model {
+            id: 1,
+            mr_link: "1".to_owned(),
+            mr_msg: "Fix the parser".to_owned(),
+            repo_path: "/projects/parser".to_owned(),
+            source_ref: "refs/heads/fix".to_owned(),
+            target_ref: "refs/heads/main".to_owned(),
+            merge_commit_id: None,
+            merge_date: None,
+            status: MergeStatus::Open,
+            milestone_id: None,
+            author: author.map(str::to_owned),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn approval(body: &str) -> NewReview {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn test_review_model() {
+        let head = SHA1::new(&b"head".to_vec());
+        let owners = CodeOwners::parse("*.rs @alice\n");
+        let mr = mr(Some("bob"));
+
+        // the reviewer is the caller, whatever name the body holds
+        let spoofed = approval(r#"{"reviewer": "alice", "state": "approved"}"#);
+        let review = review_model(&mr, "mallory", spoofed, &head).unwrap();
+        assert_eq!(review.reviewer, "mallory");
+        let approvers = review::approvers(vec![review], mr.author.as_deref());
+        assert!(!owners.approvals(["src/lib.rs"], &approvers)[0].approved);
+
+        let review = review_model(&mr, "alice", approval(r#"{"state": "approved"}"#), &head);
+        let approvers = review::approvers(vec![review.unwrap()], mr.author.as_deref());
+        assert!(owners.approvals(["src/lib.rs"], &approvers)[0].approved);
+
+        let own = review_model(&mr, "Bob", approval(r#"{"state": "approved"}"#), &head);
+        assert_eq!(own.unwrap_err().0, StatusCode::FORBIDDEN);
+        let changes = approval(r#"{"state": "changes_requested"}"#);
+        let own = review_model(&mr, "bob", changes, &head);
+        assert!(own.is_ok());
+    }
+
+    #[test]
+    fn test_approvers_skip_author() {
+        let head = SHA1::new(&b"head".to_vec());
+        // an approval the author gave before their merge request knew who they are
+        let anonymous = mr(None);
+        let approved = approval(r#"{"state": "approved"}"#);
+        let own = review_model(&anonymous, "bob", approved, &head);
+        let owners = CodeOwners::parse("*.rs @bob\n");
+        let approvers = review::approvers(vec![own.unwrap()], Some("bob"));
+        assert!(approvers.is_empty());
+        assert!(!owners.approvals(["src/lib.rs"], &approvers)[0].approved);
+    }
+}
@@ -0,0 +1,990 @@
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::db_enums::{MergeStatus, ReviewState};
+use db_entity::{mega_issue, mega_mr};
+use jupiter::storage::ci_log_storage::CiLogStorage;
+use jupiter::storage::diffstat_storage::DiffstatStorage;
+use jupiter::storage::issue_storage::IssueStorage;
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use jupiter::storage::mr_review_storage::MrReviewStorage;
+use jupiter::storage::mr_storage::{MrFilter, MrStorage};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::signature::{Signature, SignatureType};
+use venus::internal::object::tree::TreeItemMode;
+
+use crate::api_service::autolink::Autolinker;
+use crate::api_service::check_service::CheckService;
+use crate::api_service::codeowners::{self, CodeOwners};
+use crate::api_service::event_service::{EventService, EVENT_ISSUE, EVENT_MERGE_REQUEST};
+use crate::api_service::issue_service::{self, REF_SOURCE_COMMIT, REF_SOURCE_MR};
+use crate::api_service::merge::{MergeOutcome, Merger, RebaseOutcome};
+use crate::api_service::merge_service::MergeService;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::page::{Order, SortKey};
+use crate::api_service::planning_service::PlanningService;
+use crate::api_service::ref_update::RefUpdater;
+use crate::api_service::reference::{self, Referencer};
+use crate::api_service::webhook;
+use crate::i18n;
+use crate::model::ci_log::CiLog;
+use crate::model::issue::Issue;
+use crate::model::merge::{DiffStat, MergeStrategy};
+use crate::model::mr::{
+    self, BranchUpdate, MergeOptions, MergeRequest, MergeRequestDetail, MergeRequestQuery,
+    NewMergeRequest, UpdateBranchOptions, UpdateStrategy,
+};
+use crate::model::page::{Page, PageQuery};
+use crate::model::planning::{ItemAssignees, ItemLabels, ItemMilestone};
+use crate::model::review::{self, CodeOwnersReview};
+
+/// Used for merge commits when the request does not name a committer, and for tags the server
+/// creates.
+pub const DEFAULT_COMMITTER: (&str, &str) = ("mega", "mega@localhost");
+
+/// Most commits of a merged branch scanned for issue references.
+const MAX_LINKED_COMMITS: usize = 250;
+
+/// Merge requests are listed newest first, unless `order_by` names one of these.
+const DEFAULT_ORDER: Order = Order::desc("created_at");
+const MR_ORDERS: [&str; 2] = ["updated_at", "id"];
+
+#[derive(Clone)]
+pub struct MrService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub mr_storage: MrStorage,
+    pub issue_storage: IssueStorage,
+    pub ref_updater: RefUpdater,
+    pub planning: PlanningService,
+    pub ci_log_storage: CiLogStorage,
+    pub review_storage: MrReviewStorage,
+    pub diffstat_storage: DiffstatStorage,
+    pub checks: CheckService,
+    pub events: EventService,
+    pub autolinks: Autolinker,
+    pub references: Referencer,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn invalid_transition(model: &mega_mr::Model, to: &MergeStatus) -> (StatusCode, String) {
+    (
+        StatusCode::CONFLICT,
+        i18n::t(
+            "mr.invalid_transition",
+            &[
+                ("id", &model.id.to_string()),
+                ("from", mr::status_name(&model.status)),
+                ("to", mr::status_name(to)),
+            ],
+        ),
+    )
+}
+
+fn sort_column(order: &Order) -> mega_mr::Column {
+    match order.field {
+        "updated_at" => mega_mr::Column::UpdatedAt,
+        "id" => mega_mr::Column::Id,
+        _ => mega_mr::Column::CreatedAt,
+    }
+}
+
+fn sort_key(order: &Order, mr: &mega_mr::Model) -> SortKey {
+    match order.field {
+        "updated_at" => SortKey::Time(mr.updated_at),
+        "id" => SortKey::Int(mr.id),
+        _ => SortKey::Time(mr.created_at),
+    }
+}
+
+/// Full ref name of a branch given with or without the `refs/heads/` prefix.
+fn branch_ref(name: &str) -> String {
+    format!("refs/heads/{}", branch_name(name))
+}
+
+fn branch_name(ref_name: &str) -> &str {
+    ref_name.strip_prefix("refs/heads/").unwrap_or(ref_name)
+}
+
+impl MrService {
+    /// Open a merge request, by `author` when the caller is identified.
+    pub async fn create(
+        &self,
+        new_mr: NewMergeRequest,
+        author: Option<String>,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let title = new_mr.title.trim();
+        if title.is_empty() || title.chars().count() > 255 {
+            return Err((StatusCode::BAD_REQUEST, i18n::t("mr.invalid_title", &[])));
+        }
+        let source_ref = branch_ref(&new_mr.source);
+        let target_ref = branch_ref(&new_mr.target);
+        if source_ref == target_ref {
+            return Err((StatusCode::BAD_REQUEST, i18n::t("mr.same_branch", &[])));
+        }
+        let loader = ObjectLoader::new(self.storage.clone());
+        loader
+            .resolve_ref(&new_mr.repo_path, Some(&source_ref))
+            .await?;
+        loader
+            .resolve_ref(&new_mr.repo_path, Some(&target_ref))
+            .await?;
+        self.ensure_single_open(&new_mr.repo_path, &source_ref, &target_ref)
+            .await?;
+        let label_ids = self
+            .planning
+            .resolve_labels(&new_mr.repo_path, new_mr.labels)
+            .await?;
+        if let Some(milestone_id) = new_mr.milestone_id {
+            self.planning
+                .check_milestone(&new_mr.repo_path, milestone_id)
+                .await?;
+        }
+
+        let id = generate_id();
+        let now = chrono::Utc::now().naive_utc();
+        let model = mega_mr::Model {
+            id,
+            mr_link: id.to_string(),
+            mr_msg: title.to_owned(),
+            repo_path: new_mr.repo_path,
+            source_ref,
+            target_ref,
+            merge_commit_id: None,
+            merge_date: None,
+            status: if new_mr.draft {
+                MergeStatus::Draft
+            } else {
+                MergeStatus::Open
+            },
+            milestone_id: new_mr.milestone_id,
+            author,
+            created_at: now,
+            updated_at: now,
+        };
+        self.mr_storage
+            .save_mr(model.clone())
+            .await
+            .map_err(internal_error)?;
+        self.planning.set_labels(ITEM_MR, id, &label_ids).await?;
+        self.planning
+            .set_assignees(ITEM_MR, id, new_mr.assignees)
+            .await?;
+        if let Err(err) = issue_service::link_mentions(
+            &self.issue_storage,
+            &model.repo_path,
+            REF_SOURCE_MR,
+            &model.id.to_string(),
+            &model.mr_msg,
+        )
+        .await
+        {
+            tracing::warn!("unable to link issues of merge request {}: {}", id, err);
+        }
+        self.references
+            .record(&model.repo_path, ITEM_MR, id, id, &model.mr_msg)
+            .await;
+        let mr = self.with_links(model).await?;
+        self.events
+            .publish(
+                EVENT_MERGE_REQUEST,
+                "opened",
+                &mr.repo_path,
+                mr.author.as_deref(),
+                &mr,
+            )
+            .await;
+        Ok(Json(mr))
+    }
+
+    /// A page of the merge requests matching `query`, newest first by default.
+    pub async fn list(
+        &self,
+        query: MergeRequestQuery,
+        page: PageQuery,
+    ) -> Result<Json<Page<MergeRequest>>, (StatusCode, String)> {
+        let order = page.order(&MR_ORDERS, DEFAULT_ORDER)?;
+        let keyset = page.keyset(&order)?;
+        let statuses = match query.status.as_deref() {
+            Some(names) => mr::parse_statuses(names).map_err(|name| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    i18n::t("mr.unknown_status", &[("status", name)]),
+                )
+            })?,
+            None => Vec::new(),
+        };
+        let filter = MrFilter {
+            repo_path: query.repo_path.as_deref(),
+            source_ref: None,
+            statuses,
+            label: query.label.as_deref(),
+            assignee: query.assignee.as_deref(),
+            milestone_id: query.milestone,
+        };
+        let mrs = self
+            .mr_storage
+            .list_mrs_page(filter, sort_column(&order), &keyset)
+            .await
+            .map_err(internal_error)?;
+        let mrs = Page::of(mrs, keyset.limit, &order, |mr| {
+            (sort_key(&order, mr), mr.id)
+        });
+        let ids: Vec<i64> = mrs.items.iter().map(|mr| mr.id).collect();
+        let mut links = self.planning.links_of(ITEM_MR, &ids).await?;
+        let mut stats = self
+            .diffstat_storage
+            .mr_stats(&ids)
+            .await
+            .map_err(internal_error)?;
+        let rules = self.autolinks.rules().await?;
+        let mut references = self
+            .references
+            .references_of(ITEM_MR, &ids)
+            .await
+            .map_err(internal_error)?;
+        let base_url = webhook::public_url();
+        Ok(Json(mrs.map(|mr| {
+            let item_links = links.remove(&mr.id).unwrap_or_default();
+            let mut mr = MergeRequest::new(mr, item_links);
+            mr.autolinks = reference::with_references(
+                &mr.title,
+                &references.remove(&mr.id).unwrap_or_default(),
+                rules.resolve(&mr.repo_path, &mr.title),
+                &base_url,
+            );
+            mr.diffstat = stats.remove(&mr.id).map(DiffStat::from);
+            mr
+        })))
+    }
+
+    /// The merge request, with a fresh mergeability check while it is active.
+    pub async fn detail(&self, id: i64) -> Result<Json<MergeRequestDetail>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        let check = if model.status.is_active() {
+            let merge_service = MergeService {
+                storage: self.storage.clone(),
+                ref_updater: self.ref_updater.clone(),
+                checks: self.checks.clone(),
+                diffstats: self.diffstat_storage.clone(),
+            };
+            match merge_service
+                .check_refs(
+                    &model.repo_path,
+                    &model.target_ref,
+                    &model.source_ref,
+                    MergeStrategy::default(),
+                )
+                .await
+            {
+                Ok(check) => Some(check),
+                Err((_, err)) => {
+                    tracing::warn!("unable to check merge request {}: {}", id, err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let ci_logs = match &check {
+            Some(check) => self
+                .ci_log_storage
+                .list_logs(&model.repo_path, &check.source_id)
+                .await
+                .map_err(internal_error)?
+                .into_iter()
+                .map(CiLog::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        let checks = match &check {
+            Some(check) => Some(
+                self.checks
+                    .commit_checks(&model.repo_path, &check.source_id, Some(&model.target_ref))
+                    .await?,
+            ),
+            None => None,
+        };
+        let code_owners = match &check {
+            Some(check) => {
+                let target = SHA1::from_str(&check.target_id).map_err(internal_error)?;
+                let source = SHA1::from_str(&check.source_id).map_err(internal_error)?;
+                self.code_owners(&model, &target, &source).await?
+            }
+            None => None,
+        };
+        Ok(Json(MergeRequestDetail {
+            mr: self.with_links(model).await?,
+            check,
+            ci_logs,
+            checks,
+            code_owners,
+        }))
+    }
+
+    pub async fn close(&self, id: i64) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        if !model.status.is_active() {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.not_open", &[("id", &id.to_string())]),
+            ));
+        }
+        self.set_status(model, MergeStatus::Closed, "closed").await
+    }
+
+    pub async fn reopen(&self, id: i64) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        if model.status != MergeStatus::Closed {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.not_closed", &[("id", &id.to_string())]),
+            ));
+        }
+        self.ensure_single_open(&model.repo_path, &model.source_ref, &model.target_ref)
+            .await?;
+        self.set_status(model, MergeStatus::Reopened, "reopened")
+            .await
+    }
+
+    /// Mark a draft ready for review. It is approved right away when its reviews already add up
+    /// to an approval.
+    pub async fn ready(&self, id: i64) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        let reviews = self
+            .review_storage
+            .list_reviews(id)
+            .await
+            .map_err(internal_error)?;
+        let approved = review::review_status(reviews.iter().map(|r| &r.state))
+            == review::review_state_name(&ReviewState::Approved);
+        let status = if approved {
+            MergeStatus::Approved
+        } else {
+            MergeStatus::Open
+        };
+        if model.status != MergeStatus::Draft {
+            return Err(invalid_transition(&model, &status));
+        }
+        self.set_status(model, status, "ready").await
+    }
+
+    /// Turn an active merge request back into a draft, which can't be merged.
+    pub async fn draft(&self, id: i64) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        self.set_status(model, MergeStatus::Draft, "drafted").await
+    }
+
+    /// Merge the source branch into the target branch and mark the request as merged, once the
+    /// checks the target branch requires have succeeded for the head of the source branch and
+    /// the code owners of the files it changes have approved.
+    pub async fn merge(
+        &self,
+        id: i64,
+        options: MergeOptions,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        if model.status == MergeStatus::Draft {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.draft", &[("id", &id.to_string())]),
+            ));
+        }
+        if !mr::can_transition(&model.status, &MergeStatus::Merged) {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.not_open", &[("id", &id.to_string())]),
+            ));
+        }
+        let actor = options
+            .committer_name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_COMMITTER.0.to_owned());
+        let loader = ObjectLoader::new(self.storage.clone());
+        let target = loader
+            .resolve_ref(&model.repo_path, Some(&model.target_ref))
+            .await?;
+        let source = loader
+            .resolve_ref(&model.repo_path, Some(&model.source_ref))
+            .await?;
+        self.checks
+            .ensure_passed(&model.repo_path, &source.to_plain_str(), &model.target_ref)
+            .await?;
+        self.ensure_owners_approved(&model, &target, &source)
+            .await?;
+        let head = self.merge_onto(&model, &target, &source, options).await?;
+
+        // a push may have moved the target while the merge was computed
+        let current = loader
+            .resolve_ref(&model.repo_path, Some(&model.target_ref))
+            .await?;
+        if current != target {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.target_moved", &[("target", &model.target_ref)]),
+            ));
+        }
+        self.ref_updater
+            .update(
+                &model.repo_path,
+                &model.target_ref,
+                Some(&target),
+                &head,
+                &actor,
+                &format!("merge request {}", id),
+            )
+            .await?;
+        let mr = self
+            .mark_merged(model, &target, &source, &head, &actor)
+            .await?;
+        Ok(Json(mr))
+    }
+
+    /// Bring the source branch of an active request up to date with its target, merging the
+    /// target into it or rebasing it onto the target. The source branch is moved to the result
+    /// like a force push would, as long as nothing was pushed to it meanwhile.
+    pub async fn update_branch(
+        &self,
+        id: i64,
+        options: UpdateBranchOptions,
+        actor: &str,
+    ) -> Result<Json<BranchUpdate>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        if !model.status.is_active() {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.not_open", &[("id", &id.to_string())]),
+            ));
+        }
+        let loader = ObjectLoader::new(self.storage.clone());
+        let target = loader
+            .resolve_ref(&model.repo_path, Some(&model.target_ref))
+            .await?;
+        let source = loader
+            .resolve_ref(&model.repo_path, Some(&model.source_ref))
+            .await?;
+        let committer = Signature {
+            signature_type: SignatureType::Committer,
+            name: options
+                .committer_name
+                .unwrap_or_else(|| DEFAULT_COMMITTER.0.to_owned()),
+            email: options
+                .committer_email
+                .unwrap_or_else(|| DEFAULT_COMMITTER.1.to_owned()),
+            timestamp: chrono::Utc::now().timestamp() as usize,
+            timezone: "+0000".to_owned(),
+        };
+        let not_behind = || {
+            (
+                StatusCode::CONFLICT,
+                i18n::t(
+                    "mr.not_behind",
+                    &[("source", &model.source_ref), ("target", &model.target_ref)],
+                ),
+            )
+        };
+        let conflicted = |paths: Vec<String>| {
+            (
+                StatusCode::CONFLICT,
+                i18n::t("mr.conflicts", &[("paths", &paths.join(", "))]),
+            )
+        };
+
+        let mut merger = Merger::new(self.storage.clone());
+        let (head, replayed) = match options.strategy {
+            UpdateStrategy::Merge => {
+                match merger
+                    .merge(&source, &target, MergeStrategy::MergeCommit)
+                    .await?
+                {
+                    MergeOutcome::FastForward { commit_id } => (commit_id, None),
+                    MergeOutcome::Merged {
+                        tree_id,
+                        parent_commit_ids,
+                    } => {
+                        let message = format!(
+                            "Merge branch '{}' into {}",
+                            branch_name(&model.target_ref),
+                            branch_name(&model.source_ref)
+                        );
+                        let head = merger
+                            .commit(
+                                &model.repo_path,
+                                tree_id,
+                                parent_commit_ids,
+                                committer,
+                                &message,
+                            )
+                            .await?;
+                        (head, None)
+                    }
+                    MergeOutcome::Conflicts(conflicts) => {
+                        return Err(conflicted(conflicts.into_iter().map(|c| c.path).collect()))
+                    }
+                    MergeOutcome::UpToDate | MergeOutcome::NotFastForward => {
+                        return Err(not_behind())
+                    }
+                }
+            }
+            UpdateStrategy::Rebase => {
+                match merger
+                    .rebase(&model.repo_path, &source, &target, committer)
+                    .await?
+                {
+                    RebaseOutcome::Rebased { head, replayed } => (head, Some(replayed)),
+                    RebaseOutcome::Conflicts { conflicts, .. } => {
+                        return Err(conflicted(conflicts.into_iter().map(|c| c.path).collect()))
+                    }
+                    RebaseOutcome::UpToDate => return Err(not_behind()),
+                }
+            }
+        };
+
+        // a push may have moved the source while it was updated
+        let current = loader
+            .resolve_ref(&model.repo_path, Some(&model.source_ref))
+            .await?;
+        if current != source {
+            return Err((
+                StatusCode::CONFLICT,
+                i18n::t("mr.source_moved", &[("source", &model.source_ref)]),
+            ));
+        }
+        let action = match options.strategy {
+            UpdateStrategy::Merge => "update",
+            UpdateStrategy::Rebase => "rebase",
+        };
+        self.ref_updater
+            .update(
+                &model.repo_path,
+                &model.source_ref,
+                Some(&source),
+                &head,
+                actor,
+                &format!("{} merge request {}", action, id),
+            )
+            .await?;
+        Ok(Json(BranchUpdate {
+            strategy: options.strategy,
+            old_id: source.to_plain_str(),
+            new_id: head.to_plain_str(),
+            replayed,
+        }))
+    }
+
+    /// Refuse with a conflict while the code owners of the files changed from the merge base of
+    /// `target` and `source` haven't all approved.
+    pub async fn ensure_owners_approved(
+        &self,
+        model: &mega_mr::Model,
+        target: &SHA1,
+        source: &SHA1,
+    ) -> Result<(), (StatusCode, String)> {
+        if let Some(review) = self.code_owners(model, target, source).await? {
+            if !review.blocking.is_empty() {
+                let owners: Vec<String> = review.blocking.iter().map(|o| o.join("/")).collect();
+                return Err((
+                    StatusCode::CONFLICT,
+                    i18n::t("mr.code_owners_required", &[("owners", &owners.join(", "))]),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge `source` into `base` as the request asks, without moving any ref. Returns the commit
+    /// the target branch would be moved to.
+    pub async fn merge_onto(
+        &self,
+        model: &mega_mr::Model,
+        base: &SHA1,
+        source: &SHA1,
+        options: MergeOptions,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let mut merger = Merger::new(self.storage.clone());
+        let head = match merger.merge(base, source, options.strategy).await? {
+            MergeOutcome::FastForward { commit_id } => commit_id,
+            MergeOutcome::Merged {
+                tree_id,
+                parent_commit_ids,
+            } => {
+                let message = options.message.unwrap_or_else(|| {
+                    format!(
+                        "Merge branch '{}' into {}\n\n{}",
+                        branch_name(&model.source_ref),
+                        branch_name(&model.target_ref),
+                        model.mr_msg
+                    )
+                });
+                let committer = Signature {
+                    signature_type: SignatureType::Committer,
+                    name: options
+                        .committer_name
+                        .unwrap_or_else(|| DEFAULT_COMMITTER.0.to_owned()),
+                    email: options
+                        .committer_email
+                        .unwrap_or_else(|| DEFAULT_COMMITTER.1.to_owned()),
+                    timestamp: chrono::Utc::now().timestamp() as usize,
+                    timezone: "+0000".to_owned(),
+                };
+                merger
+                    .commit(
+                        &model.repo_path,
+                        tree_id,
+                        parent_commit_ids,
+                        committer,
+                        &message,
+                    )
+                    .await?
+            }
+            MergeOutcome::UpToDate => {
+                return Err((StatusCode::CONFLICT, i18n::t("mr.up_to_date", &[])))
+            }
+            MergeOutcome::NotFastForward => {
+                return Err((StatusCode::CONFLICT, i18n::t("mr.not_fast_forward", &[])))
+            }
+            MergeOutcome::Conflicts(conflicts) => {
+                let paths: Vec<String> = conflicts.into_iter().map(|c| c.path).collect();
+                return Err((
+                    StatusCode::CONFLICT,
+                    i18n::t("mr.conflicts", &[("paths", &paths.join(", "))]),
+                ));
+            }
+        };
+        Ok(head)
+    }
+
+    /// Record that the target branch, which pointed to `target`, now points to `head` merging
+    /// `source`: the request is merged and the issues it fixes are closed.
+    pub async fn mark_merged(
+        &self,
+        mut model: mega_mr::Model,
+        target: &SHA1,
+        source: &SHA1,
+        head: &SHA1,
+        actor: &str,
+    ) -> Result<MergeRequest, (StatusCode, String)> {
+        let now = chrono::Utc::now().naive_utc();
+        model.status = MergeStatus::Merged;
+        model.merge_commit_id = Some(head.to_plain_str());
+        model.merge_date = Some(now);
+        model.updated_at = now;
+        let model = self
+            .mr_storage
+            .update_mr(model)
+            .await
+            .map_err(internal_error)?;
+        let base = Merger::new(self.storage.clone())
+            .merge_base(target, source)
+            .await?;
+        self.close_fixed_issues(&model, source, base).await;
+        let mr = self.with_links(model).await?;
+        self.events
+            .publish(
+                EVENT_MERGE_REQUEST,
+                "merged",
+                &mr.repo_path,
+                Some(actor),
+                &mr,
+            )
+            .await;
+        Ok(mr)
+    }
+
+    pub async fn set_labels(
+        &self,
+        id: i64,
+        labels: ItemLabels,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        let label_ids = self
+            .planning
+            .resolve_labels(&model.repo_path, labels.labels)
+            .await?;
+        self.planning.set_labels(ITEM_MR, id, &label_ids).await?;
+        self.touch(model, "updated").await
+    }
+
+    pub async fn set_assignees(
+        &self,
+        id: i64,
+        assignees: ItemAssignees,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let model = self.get_mr(id).await?;
+        self.planning
+            .set_assignees(ITEM_MR, id, assignees.assignees)
+            .await?;
+        self.touch(model, "updated").await
+    }
+
+    pub async fn set_milestone(
+        &self,
+        id: i64,
+        milestone: ItemMilestone,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        let mut model = self.get_mr(id).await?;
+        if let Some(milestone_id) = milestone.milestone_id {
+            self.planning
+                .check_milestone(&model.repo_path, milestone_id)
+                .await?;
+        }
+        model.milestone_id = milestone.milestone_id;
+        self.touch(model, "updated").await
+    }
+
+    /// Link the issues mentioned by a merged request and by the commits it brought in, and close
+    /// the ones they fix. The merge is done by then, so failures are only logged.
+    async fn close_fixed_issues(&self, model: &mega_mr::Model, source: &SHA1, base: Option<SHA1>) {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut sources = vec![(REF_SOURCE_MR, model.id.to_string(), model.mr_msg.clone())];
+        let mut queue = VecDeque::from([*source]);
+        let mut seen: HashSet<SHA1> = base.into_iter().collect();
+        while let Some(id) = queue.pop_front() {
+            if sources.len() > MAX_LINKED_COMMITS || !seen.insert(id) {
+                continue;
+            }
+            match loader.commit(&id).await {
+                Ok(commit) => {
+                    queue.extend(commit.parent_commit_ids.iter().copied());
+                    sources.push((REF_SOURCE_COMMIT, id.to_plain_str(), commit.message));
+                }
+                Err((_, err)) => {
+                    tracing::warn!("unable to load commit {}: {}", id.to_plain_str(), err);
+                    break;
+                }
+            }
+        }
+
+        for (source_type, source_id, text) in sources {
+            let linked = match issue_service::link_mentions(
+                &self.issue_storage,
+                &model.repo_path,
+                source_type,
+                &source_id,
+                &text,
+            )
+            .await
+            {
+                Ok(linked) => linked,
+                Err(err) => {
+                    tracing::warn!(
+                        "unable to link issues of {} {}: {}",
+                        source_type,
+                        source_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+            for (issue, closes) in linked {
+                if !closes {
+                    continue;
+                }
+                let number = issue.number;
+                match issue_service::close_issue(&self.issue_storage, issue).await {
+                    Ok(issue) => self.publish_closed_issue(issue).await,
+                    Err(err) => tracing::warn!(
+                        "unable to close issue #{} of {}: {}",
+                        number,
+                        model.repo_path,
+                        err
+                    ),
+                }
+            }
+        }
+    }
+
+    async fn publish_closed_issue(&self, issue: mega_issue::Model) {
+        let item_links = match self.planning.links_of(ITEM_ISSUE, &[issue.id]).await {
+            Ok(mut links) => links.remove(&issue.id).unwrap_or_default(),
+            Err((_, err)) => {
+                tracing::warn!("unable to load labels of issue {}: {}", issue.id, err);
+                Default::default()
+            }
+        };
+        let issue = Issue::new(issue, item_links);
+        self.events
+            .publish(EVENT_ISSUE, "closed", &issue.repo_path, None, &issue)
+            .await;
+    }
+
+    /// The approvals the code owners named by the `CODEOWNERS` file of `target` have to give for
+    /// the files changed from the merge base to `source`, none without such a file.
+    async fn code_owners(
+        &self,
+        model: &mega_mr::Model,
+        target: &SHA1,
+        source: &SHA1,
+    ) -> Result<Option<CodeOwnersReview>, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let target_tree = loader.commit(target).await?.tree_id;
+        let mut file = None;
+        for path in codeowners::FILE_PATHS {
+            if let Some(item) = loader.find_path(&target_tree, path).await? {
+                if item.mode == TreeItemMode::Blob {
+                    file = Some((path, item.id));
+                    break;
+                }
+            }
+        }
+        let Some((file, blob_id)) = file else {
+            return Ok(None);
+        };
+        let owners = CodeOwners::parse(&String::from_utf8_lossy(&loader.blob(&blob_id).await?));
+
+        let base = Merger::new(self.storage.clone())
+            .merge_base(target, source)
+            .await?;
+        let base_tree = match base {
+            Some(base) => Some(loader.commit(&base).await?.tree_id),
+            None => None,
+        };
+        let source_tree = loader.commit(source).await?.tree_id;
+        let mut paths = Vec::new();
+        codeowners::changed_paths(
+            &mut loader,
+            String::new(),
+            base_tree,
+            Some(source_tree),
+            &mut paths,
+        )
+        .await?;
+
+        let reviews = self
+            .review_storage
+            .list_reviews(model.id)
+            .await
+            .map_err(internal_error)?;
+        let approvers = review::approvers(reviews, model.author.as_deref());
+        let required = owners.approvals(paths.iter().map(String::as_str), &approvers);
+        let blocking = required
+            .iter()
+            .filter(|a| !a.approved)
+            .map(|a| a.owners.clone())
+            .collect();
+        Ok(Some(CodeOwnersReview {
+            file: file.to_string(),
+            required,
+            blocking,
+        }))
+    }
+
+    pub async fn get_mr(&self, id: i64) -> Result<mega_mr::Model, (StatusCode, String)> {
+        match self.mr_storage.get_mr(id).await {
+            Ok(Some(model)) => Ok(model),
+            Ok(None) => Err((
+                StatusCode::NOT_FOUND,
+                i18n::t("mr.not_found", &[("id", &id.to_string())]),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    async fn ensure_single_open(
+        &self,
+        repo_path: &str,
+        source_ref: &str,
+        target_ref: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        let open = self
+            .mr_storage
+            .find_open_mr(repo_path, source_ref, target_ref)
+            .await
+            .map_err(internal_error)?;
+        match open {
+            Some(existing) => Err((
+                StatusCode::CONFLICT,
+                i18n::t(
+                    "mr.already_open",
+                    &[
+                        ("id", &existing.id.to_string()),
+                        ("source", source_ref),
+                        ("target", target_ref),
+                    ],
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Move a merge request to `status`, when its current state allows it.
+    async fn set_status(
+        &self,
+        mut model: mega_mr::Model,
+        status: MergeStatus,
+        action: &str,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        if !mr::can_transition(&model.status, &status) {
+            return Err(invalid_transition(&model, &status));
+        }
+        model.status = status;
+        self.touch(model, action).await
+    }
+
+    /// Store a changed merge request with a new update time and publish an event with `action`.
+    async fn touch(
+        &self,
+        mut model: mega_mr::Model,
+        action: &str,
+    ) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+        model.updated_at = chrono::Utc::now().naive_utc();
+        let model = self
+            .mr_storage
+            .update_mr(model)
+            .await
+            .map_err(internal_error)?;
+        let mr = self.with_links(model).await?;
+        self.events
+            .publish(EVENT_MERGE_REQUEST, action, &mr.repo_path, None, &mr)
+            .await;
+        Ok(Json(mr))
+    }
+
+    async fn with_links(
+        &self,
+        model: mega_mr::Model,
+    ) -> Result<MergeRequest, (StatusCode, String)> {
+        let mut links = self.planning.links_of(ITEM_MR, &[model.id]).await?;
+        let item_links = links.remove(&model.id).unwrap_or_default();
+        let mut stats = self
+            .diffstat_storage
+            .mr_stats(&[model.id])
+            .await
+            .map_err(internal_error)?;
+        let mut references = self
+            .references
+            .references_of(ITEM_MR, &[model.id])
+            .await
+            .map_err(internal_error)?;
+        let mut mr = MergeRequest::new(model, item_links);
+        mr.autolinks = reference::with_references(
+            &mr.title,
+            &references.remove(&mr.id).unwrap_or_default(),
+            self.autolinks.rules().await?.resolve(&mr.repo_path, &mr.title),
+            &webhook::public_url(),
+        );
+        mr.diffstat = stats.remove(&mr.id).map(DiffStat::from);
+        Ok(mr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{branch_name, branch_ref};
+
+    #[test]
+    fn test_branch_ref() {
+        assert_eq!(branch_ref("feature"), "refs/heads/feature");
+        assert_eq!(branch_ref("refs/heads/main"), "refs/heads/main");
+        assert_eq!(branch_ref("refs/tags/v1"), "refs/heads/refs/tags/v1");
+        assert_eq!(branch_name("refs/heads/fix/login"), "fix/login");
+    }
+}
@@ -0,0 +1,447 @@
+//! Who is notified of the events of issues and merge requests, and how.
+//!
+//! Users follow an issue or merge request through a subscription: its author, its assignees,
+//! those commenting on or reviewing it and those mentioned in it are subscribed as they take
+//! part, and anyone can subscribe or stop following it. Opening, closing, reopening and merging,
+//! marking a draft ready, comments and reviews notify the subscribers; users who just started
+//! taking part are notified of any event, and a mention notifies even a user who stopped
+//! following. Nobody is notified of what they did themselves.
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use db_entity::{mega_notification, mega_subscription};
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use jupiter::storage::reference_storage::TARGET_USER;
+
+use crate::api_service::event_service::{EVENT_COMMENT, EVENT_ISSUE, EVENT_MERGE_REQUEST};
+use crate::api_service::reference;
+use crate::model::notification::NotificationPrefs;
+
+/// Why a user follows an issue or merge request.
+pub const REASON_AUTHOR: &str = "author";
+pub const REASON_ASSIGNED: &str = "assigned";
+pub const REASON_MENTIONED: &str = "mentioned";
+pub const REASON_PARTICIPATING: &str = "participating";
+/// The user subscribed themselves.
+pub const REASON_MANUAL: &str = "manual";
+
+/// A user taking part in an issue or merge request through an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Participant {
+    pub username: String,
+    pub reason: &'static str,
+}
+
+/// The issue or merge request an event is about.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Subject {
+    pub subject_type: &'static str,
+    pub id: i64,
+    /// None for comments, whose payload doesn't carry the title of the merge request
+    pub title: Option<String>,
+    pub participants: Vec<Participant>,
+}
+
+/// The users `@name` mentions in `text`, each once, as [`reference::parse_references`] finds
+/// them.
+pub fn parse_user_mentions(text: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+    for found in reference::parse_references(text) {
+        let name = &found.text[1..];
+        if found.target_type == TARGET_USER && !mentions.iter().any(|m| m == name) {
+            mentions.push(name.to_owned());
+        }
+    }
+    mentions
+}
+
+/// Whether the subscribers of an issue or merge request are notified of events of `action`.
+pub fn notifies(event_type: &str, action: &str) -> bool {
+    match event_type {
+        EVENT_ISSUE => matches!(action, "opened" | "closed" | "reopened"),
+        EVENT_MERGE_REQUEST => {
+            matches!(
+                action,
+                "opened" | "closed" | "reopened" | "merged" | "ready"
+            )
+        }
+        EVENT_COMMENT => matches!(action, "created" | "reviewed"),
+        _ => false,
+    }
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key)?.as_str()
+}
+
+fn names(value: &Value, key: &str) -> Vec<String> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The subject of an event and who takes part in it through the event: the author of an issue,
+/// the assignees, the author of a comment or review, and who the text the event added mentions.
+pub fn subject_of(
+    event_type: &str,
+    action: &str,
+    actor: Option<&str>,
+    payload: &Value,
+) -> Option<Subject> {
+    let mut participants = Vec::new();
+    let mut take_part = |names: Vec<String>, reason| {
+        participants.extend(
+            names
+                .into_iter()
+                .map(|username| Participant { username, reason }),
+        );
+    };
+    let (subject_type, id, title) = match event_type {
+        EVENT_ISSUE | EVENT_MERGE_REQUEST => {
+            let title = str_field(payload, "title").unwrap_or_default();
+            if action == "opened" {
+                if let Some(author) = str_field(payload, "author").or(actor) {
+                    take_part(vec![author.to_owned()], REASON_AUTHOR);
+                }
+                let body = str_field(payload, "body").unwrap_or_default();
+                take_part(
+                    parse_user_mentions(&format!("{}\n{}", title, body)),
+                    REASON_MENTIONED,
+                );
+            }
+            take_part(names(payload, "assignees"), REASON_ASSIGNED);
+            let subject_type = if event_type == EVENT_ISSUE {
+                ITEM_ISSUE
+            } else {
+                ITEM_MR
+            };
+            let id = payload.get("id")?.as_i64()?;
+            (subject_type, id, Some(title.to_owned()))
+        }
+        EVENT_COMMENT if matches!(action, "created" | "reviewed") => {
+            if let Some(actor) = actor {
+                take_part(vec![actor.to_owned()], REASON_PARTICIPATING);
+            }
+            let text = ["comment", "review"]
+                .iter()
+                .filter_map(|key| str_field(payload.get(key)?, "body"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            take_part(parse_user_mentions(&text), REASON_MENTIONED);
+            (ITEM_MR, payload.get("mr_id")?.as_i64()?, None)
+        }
+        _ => return None,
+    };
+    Some(Subject {
+        subject_type,
+        id,
+        title,
+        participants,
+    })
+}
+
+/// Who an event notifies, and why: the subscribers when `notify_subscribers`, the participants
+/// who weren't subscribed before and the users mentioned, but never `actor`.
+pub fn recipients(
+    subscriptions: &[mega_subscription::Model],
+    participants: &[Participant],
+    notify_subscribers: bool,
+    actor: Option<&str>,
+) -> Vec<Participant> {
+    let mut seen: HashSet<&str> = actor.into_iter().collect();
+    let mut recipients = Vec::new();
+    for participant in participants {
+        let subscribed = subscriptions
+            .iter()
+            .any(|s| s.username == participant.username);
+        if (!subscribed || participant.reason == REASON_MENTIONED)
+            && seen.insert(participant.username.as_str())
+        {
+            recipients.push(participant.clone());
+        }
+    }
+    if notify_subscribers {
+        for subscription in subscriptions.iter().filter(|s| s.subscribed) {
+            if seen.insert(subscription.username.as_str()) {
+                recipients.push(Participant {
+                    username: subscription.username.clone(),
+                    reason: reason(&subscription.reason),
+                });
+            }
+        }
+    }
+    recipients
+}
+
+fn reason(name: &str) -> &'static str {
+    [
+        REASON_AUTHOR,
+        REASON_ASSIGNED,
+        REASON_MENTIONED,
+        REASON_PARTICIPATING,
+    ]
+    .into_iter()
+    .find(|r| *r == name)
+    .unwrap_or(REASON_MANUAL)
+}
+
+/// How a notification reaches a user.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Delivery {
+    pub in_app: bool,
+    pub email: bool,
+}
+
+/// How the preferences of a user deliver a notification for `reason`, none when they don't
+/// want it. `can_email` is whether the server sends mail and the user has an address.
+pub fn delivery(prefs: &NotificationPrefs, reason: &str, can_email: bool) -> Option<Delivery> {
+    if prefs.participating_only && reason == REASON_MANUAL {
+        return None;
+    }
+    let delivery = Delivery {
+        in_app: prefs.in_app,
+        email: prefs.email && can_email,
+    };
+    (delivery.in_app || delivery.email).then_some(delivery)
+}
+
+fn subject_url(base_url: &str, subject_type: &str, id: i64) -> String {
+    let kind = if subject_type == ITEM_ISSUE {
+        "issues"
+    } else {
+        "mr"
+    };
+    format!("{}/api/v1/{}/{}", base_url, kind, id)
+}
+
+/// The subject and text of the email of a notification, linking to the issue or merge request
+/// under `base_url`.
+pub fn email_message(notification: &mega_notification::Model, base_url: &str) -> (String, String) {
+    let kind = if notification.subject_type == ITEM_ISSUE {
+        "issue"
+    } else {
+        "merge request"
+    };
+    let repo = notification.repo_path.trim_matches('/');
+    let subject = format!("[{}] {}", repo, notification.title);
+    let what = match notification.action.as_str() {
+        "created" => format!("commented on the {}", kind),
+        "reviewed" => format!("reviewed the {}", kind),
+        "ready" => format!("marked the {} as ready for review", kind),
+        "drafted" => format!("converted the {} to a draft", kind),
+        action => format!("{} the {}", action, kind),
+    };
+    let why = match notification.reason.as_str() {
+        REASON_AUTHOR => "you opened it",
+        REASON_ASSIGNED => "you are assigned to it",
+        REASON_MENTIONED => "you were mentioned in it",
+        REASON_PARTICIPATING => "you took part in it",
+        _ => "you subscribed to it",
+    };
+    let body = format!(
+        "{} {} \"{}\" in {}.\n\n{}\n\nYou are receiving this because {}.\n",
+        notification.actor.as_deref().unwrap_or("Someone"),
+        what,
+        notification.title,
+        repo,
+        subject_url(
+            base_url,
+            &notification.subject_type,
+            notification.subject_id
+        ),
+        why
+    );
+    (subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use db_entity::mega_subscription;
+
+    use super::*;
+
+    fn subscription(username: &str, reason: &str, subscribed: bool) -> mega_subscription::Model {
+        let now = chrono::Utc::now().naive_utc();
+        mega_subscription::Model {
+            id: 1,
+            username: username.to_owned(),
+            subject_type: ITEM_MR.to_owned(),
+            subject_id: 7,
+            reason: reason.to_owned(),
+            subscribed,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn participant(username: &str, reason: &'static str) -> Participant {
+        Participant {
+            username: username.to_owned(),
+            reason,
+        }
+    }
+
+    #[test]
+    fn test_parse_user_mentions() {
+        assert_eq!(
+            parse_user_mentions("cc @alice and @bob.smith, thanks @alice."),
+            ["alice", "bob.smith"]
+        );
+        assert_eq!(
+            parse_user_mentions("(@carol_1)\n@dave-x"),
+            ["carol_1", "dave-x"]
+        );
+        assert!(parse_user_mentions("mail me at me@example.com or @ nobody").is_empty());
+        assert!(parse_user_mentions("in code `@eve`").is_empty());
+    }
+
+    #[test]
+    fn test_subject_of() {
+        let issue = json!({
+            "id": 3,
+            "title": "Crash on start",
+            "body": "seen by @bob",
+            "author": "alice",
+            "assignees": ["carol"],
+        });
+        let subject = subject_of(EVENT_ISSUE, "opened", Some("alice"), &issue).unwrap();
+        assert_eq!(subject.subject_type, ITEM_ISSUE);
+        assert_eq!(subject.id, 3);
+        assert_eq!(subject.title.as_deref(), Some("Crash on start"));
+        assert_eq!(
+            subject.participants,
+            [
+                participant("alice", REASON_AUTHOR),
+                participant("bob", REASON_MENTIONED),
+                participant("carol", REASON_ASSIGNED),
+            ]
+        );
+        // the text was there before
+        let subject = subject_of(EVENT_ISSUE, "closed", None, &issue).unwrap();
+        assert_eq!(
+            subject.participants,
+            [participant("carol", REASON_ASSIGNED)]
+        );
+
+        let comment = json!({
+            "mr_id": 7,
+            "thread_id": 1,
+            "comment": {"author": "dave", "body": "@erin look"},
+            "review": null,
+        });
+        let subject = subject_of(EVENT_COMMENT, "created", Some("dave"), &comment).unwrap();
+        assert_eq!(subject.subject_type, ITEM_MR);
+        assert_eq!(subject.id, 7);
+        assert_eq!(subject.title, None);
+        assert_eq!(
+            subject.participants,
+            [
+                participant("dave", REASON_PARTICIPATING),
+                participant("erin", REASON_MENTIONED),
+            ]
+        );
+        assert!(subject_of(EVENT_COMMENT, "resolved", Some("dave"), &comment).is_none());
+        assert!(subject_of("push", "updated", None, &comment).is_none());
+    }
+
+    #[test]
+    fn test_recipients() {
+        let subscriptions = [
+            subscription("alice", REASON_AUTHOR, true),
+            subscription("bob", REASON_MANUAL, true),
+            subscription("carol", REASON_PARTICIPATING, false),
+        ];
+        let participants = [
+            participant("alice", REASON_PARTICIPATING),
+            participant("carol", REASON_MENTIONED),
+            participant("dave", REASON_ASSIGNED),
+        ];
+        assert_eq!(
+            recipients(&subscriptions, &participants, true, Some("alice")),
+            [
+                participant("carol", REASON_MENTIONED),
+                participant("dave", REASON_ASSIGNED),
+                participant("bob", REASON_MANUAL),
+            ]
+        );
+        // only the new participants and the mentioned users
+        assert_eq!(
+            recipients(&subscriptions, &participants, false, None),
+            [
+                participant("carol", REASON_MENTIONED),
+                participant("dave", REASON_ASSIGNED),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delivery() {
+        let prefs = NotificationPrefs::default();
+        assert_eq!(
+            delivery(&prefs, REASON_MANUAL, true),
+            Some(Delivery {
+                in_app: true,
+                email: true
+            })
+        );
+        assert_eq!(
+            delivery(&prefs, REASON_MANUAL, false),
+            Some(Delivery {
+                in_app: true,
+                email: false
+            })
+        );
+        let prefs = NotificationPrefs {
+            in_app: false,
+            email: true,
+            participating_only: true,
+        };
+        assert_eq!(delivery(&prefs, REASON_MANUAL, true), None);
+        assert_eq!(delivery(&prefs, REASON_ASSIGNED, false), None);
+        assert_eq!(
+            delivery(&prefs, REASON_ASSIGNED, true),
+            Some(Delivery {
+                in_app: false,
+                email: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_email_message() {
+        let notification = mega_notification::Model {
+            id: 1,
+            username: "bob".to_owned(),
+            event_id: 2,
+            repo_path: "/project/mega".to_owned(),
+            subject_type: ITEM_MR.to_owned(),
+            subject_id: 7,
+            title: "Add notifications".to_owned(),
+            action: "reviewed".to_owned(),
+            actor: Some("alice".to_owned()),
+            reason: REASON_AUTHOR.to_owned(),
+            read_at: None,
+            email_state: None,
+            email_attempts: 0,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        let (subject, body) = email_message(&notification, "https://mega.example.com");
+        assert_eq!(subject, "[project/mega] Add notifications");
+        assert!(body.starts_with(
+            "alice reviewed the merge request \"Add notifications\" in project/mega."
+        ));
+        assert!(body.contains("https://mega.example.com/api/v1/mr/7"));
+        assert!(body.contains("because you opened it"));
+    }
+}
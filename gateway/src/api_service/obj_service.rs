@@ -1,40 +1,122 @@
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
 
-use axum::response::Json;
-use axum::{http::StatusCode, response::Response};
+use axum::body::Body;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
 
+use common::missing_objects;
+use common::utils::HIDDEN_REF_PREFIX;
 use git::internal::object::commit::Commit;
-use git::internal::object::tree::Tree;
+use git::internal::object::tree::{Tree, TreeItemMode as GitTreeItemMode};
 use git::internal::object::ObjectT;
 use git::internal::pack::counter::GitTypeCounter;
 use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::gitattributes;
+use venus::internal::gitmodules::{self, Gitmodules};
+use venus::internal::object::tree::TreeItemMode;
 
-use crate::model::objects::{BlobObjects, Directories, Item};
-use crate::model::query::DirectoryQuery;
+use crate::api_service::autolink::Autolinker;
+use crate::api_service::conditional::Validator;
+use crate::api_service::highlight::Highlighter;
+use crate::api_service::markdown;
+use crate::api_service::object_loader::{self, ObjectLoader};
+use crate::api_service::page::{encode_cursor, Order};
+use crate::api_service::webhook;
+use crate::model::markdown::{Readme, ReadmeQuery};
+use crate::model::objects::{
+    BlobObjects, CommitSummary, Directories, HighlightFormat, Item, RefInfo, Submodule,
+    SubmoduleLink,
+};
+use crate::model::page::{Page, PageQuery};
+use crate::model::query::{CommitLogQuery, DirectoryQuery, RefQuery, SubmoduleQuery};
 
 #[derive(Clone)]
 pub struct ObjectService {
     pub storage: Arc<dyn ObjectStorage>,
+    pub autolinks: Autolinker,
+    pub highlighter: Highlighter,
 }
 
+/// History is listed newest first, the only order a walk gives.
+const COMMIT_ORDER: Order = Order::desc("committed_at");
+/// Refs are listed by name.
+const REF_ORDER: Order = Order::asc("name");
+
 const SIGNATURE_END: &str = "-----END PGP SIGNATURE-----";
 
 impl ObjectService {
+    /// The blob `object_id` as text, with its line endings. When the repository and the path of
+    /// the blob are given, the `text` and `eol` attributes the `.gitattributes` at `refs` set on
+    /// the path come too. With `highlight` the text also comes highlighted in that format, as
+    /// `language` or the language its path or first line suggests. A caller sending back the
+    /// `ETag` it got gets `304 Not Modified` instead, once the blob is found, and the blob isn't
+    /// read.
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_blob_objects(
         &self,
         object_id: &str,
-    ) -> Result<Json<BlobObjects>, (StatusCode, String)> {
-        let blob_data = match self.storage.get_obj_data_by_id(object_id).await {
-            Ok(Some(node)) => {
-                if node.object_type == "blob" {
-                    node.data
-                } else {
-                    return Err((StatusCode::NOT_FOUND, "Blob not found".to_string()));
-                }
+        repo_path: Option<&str>,
+        path: Option<&str>,
+        refs: Option<&str>,
+        highlight: Option<HighlightFormat>,
+        language: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Result<Response, (StatusCode, String)> {
+        let id = SHA1::from_str(object_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if !self.has_object(object_id, "blob").await {
+            return Err((StatusCode::NOT_FOUND, "Blob not found".to_string()));
+        }
+
+        // the attributes of the path come from the commit the ref points to
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let attributes_commit = match (repo_path, path) {
+            (Some(repo_path), Some(_)) => Some(loader.resolve_ref(repo_path, refs).await?),
+            _ => None,
+        };
+        let validator = match (attributes_commit, highlight) {
+            (None, None) => Validator::object(&id, true),
+            _ => {
+                let commit_id = attributes_commit.map(|id| id.to_plain_str());
+                let format = match highlight {
+                    Some(HighlightFormat::Html) => "html",
+                    Some(HighlightFormat::Tokens) => "tokens",
+                    None => "",
+                };
+                Validator::of(
+                    &[
+                        object_id,
+                        commit_id.as_deref().unwrap_or_default(),
+                        path.unwrap_or_default(),
+                        format,
+                        language.unwrap_or_default(),
+                    ],
+                    // a ref moves, unless it is the id of the commit
+                    commit_id.is_none() || commit_id.as_deref() == refs,
+                )
             }
+        };
+        if let Some(not_modified) = validator.check(headers) {
+            return Ok(not_modified);
+        }
+
+        let blob_data = match self.storage.get_obj_data_by_id(object_id).await {
+            Ok(Some(node)) => node.data,
             _ => return Err((StatusCode::NOT_FOUND, "Blob not found".to_string())),
         };
+        let eol = gitattributes::detect_eol(&blob_data).to_owned();
+        let eol_attributes = match (attributes_commit, path) {
+            (Some(commit_id), Some(path)) => {
+                let commit = loader.commit(&commit_id).await?;
+                loader
+                    .attributes(&commit.tree_id, path)
+                    .await?
+                    .eol_attributes(path)
+            }
+            _ => None,
+        };
 
         let row_data = match String::from_utf8(blob_data) {
             Ok(str) => str,
@@ -46,20 +128,160 @@ impl ObjectService {
             }
         };
 
-        let data = BlobObjects { row_data };
-        Ok(Json(data))
+        let highlighted = match highlight {
+            Some(format) => self
+                .highlighter
+                .highlight_blob(object_id, &row_data, path, language, format)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+            None => None,
+        };
+
+        let data = BlobObjects {
+            row_data,
+            eol,
+            eol_attributes,
+            highlighted,
+        };
+        Ok(validator.respond(Json(data)))
+    }
+
+    /// The commit `object_id`, parsed.
+    pub async fn get_commit(
+        &self,
+        object_id: &str,
+    ) -> Result<Json<venus::internal::object::commit::Commit>, (StatusCode, String)> {
+        let id = SHA1::from_str(object_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let commit = ObjectLoader::new(self.storage.clone()).commit(&id).await?;
+        Ok(Json(commit))
+    }
+
+    /// A page of the history of `query.refs`, the newest commits first. The cursor holds the
+    /// commit the walk started from and the last commit of the page: the next page walks the same
+    /// history again and goes on after it, so no commit repeats or gets skipped however branches
+    /// merge, and the cursor stays small.
+    pub async fn list_commits(
+        &self,
+        query: CommitLogQuery,
+        page: PageQuery,
+    ) -> Result<Json<Page<CommitSummary>>, (StatusCode, String)> {
+        page.order(&[], COMMIT_ORDER)?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let parse = |id: &str| {
+            SHA1::from_str(id).map_err(|_| (StatusCode::BAD_REQUEST, "invalid cursor".to_owned()))
+        };
+        let (start, last) = match page.after::<(String, String)>(&COMMIT_ORDER)? {
+            Some((start, last)) => (parse(&start)?, Some(parse(&last)?)),
+            None => {
+                let id = loader
+                    .resolve_ref(&query.repo_path, query.refs.as_deref())
+                    .await?;
+                (loader.peel(&id).await?, None)
+            }
+        };
+        let mut pending =
+            BinaryHeap::from([(loader.commit(&start).await?.committer.timestamp, start)]);
+        let mut queued = HashSet::from([start]);
+        // the commits of the pages before are walked again, up to the last one
+        let mut skipping = last.is_some();
+        let limit = page.limit() as usize;
+        let mut items = Vec::with_capacity(limit);
+        while items.len() < limit {
+            let Some((_, id)) = pending.pop() else {
+                break;
+            };
+            let commit = loader.commit(&id).await?;
+            for parent in &commit.parent_commit_ids {
+                if queued.insert(*parent) {
+                    pending.push((loader.commit(parent).await?.committer.timestamp, *parent));
+                }
+            }
+            if skipping {
+                skipping = Some(id) != last;
+                continue;
+            }
+            items.push(CommitSummary {
+                id: id.to_plain_str(),
+                summary: object_loader::commit_summary(&commit.message),
+                author: commit.author.name,
+                author_email: commit.author.email,
+                committed_at: commit.committer.timestamp,
+                parent_ids: commit
+                    .parent_commit_ids
+                    .iter()
+                    .map(|id| id.to_plain_str())
+                    .collect(),
+            });
+        }
+        let next_cursor = match items.last() {
+            Some(last) if !pending.is_empty() => Some(encode_cursor(
+                &COMMIT_ORDER,
+                (start.to_plain_str(), last.id.clone()),
+            )),
+            _ => None,
+        };
+        Ok(Json(Page { items, next_cursor }))
+    }
+
+    /// A page of the refs of `query.repo_path` by name, the hidden ones left out. Polling
+    /// clients get `304 Not Modified` while none of the refs of the page moved.
+    pub async fn list_refs(
+        &self,
+        query: RefQuery,
+        page: PageQuery,
+        headers: &HeaderMap,
+    ) -> Result<Response, (StatusCode, String)> {
+        let order = page.order(&[], REF_ORDER)?;
+        let after: Option<String> = page.after(&order)?;
+        let mut refs: Vec<RefInfo> = self
+            .storage
+            .get_all_refs_by_path(&query.repo_path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .into_iter()
+            .filter(|r| !r.ref_name.starts_with(HIDDEN_REF_PREFIX))
+            .filter(|r| match &after {
+                Some(after) if order.descending => r.ref_name < *after,
+                Some(after) => r.ref_name > *after,
+                None => true,
+            })
+            .map(|r| RefInfo {
+                name: r.ref_name,
+                id: r.ref_git_id,
+            })
+            .collect();
+        refs.sort_by(|a, b| a.name.cmp(&b.name));
+        if order.descending {
+            refs.reverse();
+        }
+        let limit = page.limit();
+        refs.truncate(limit as usize + 1);
+        let refs = Page::of(refs, limit, &order, |r| r.name.clone());
+        // the page changes with the names and the ids of its refs, and with where it ends
+        let mut parts: Vec<&str> = refs
+            .items
+            .iter()
+            .flat_map(|r| [r.name.as_str(), r.id.as_str()])
+            .collect();
+        parts.extend(refs.next_cursor.as_deref());
+        let validator = Validator::of(&parts, false);
+        if let Some(not_modified) = validator.check(headers) {
+            return Ok(not_modified);
+        }
+        Ok(validator.respond(Json(refs)))
     }
 
     pub async fn get_directories(
         &self,
         query: DirectoryQuery,
-    ) -> Result<Json<Directories>, (StatusCode, String)> {
+        headers: &HeaderMap,
+    ) -> Result<Response, (StatusCode, String)> {
         let DirectoryQuery {
             object_id,
             repo_path,
         } = query;
         if let Some(obj_id) = object_id {
-            self.get_tree_objects(&obj_id, &repo_path).await
+            self.tree_response(&obj_id, &repo_path, true, headers).await
         } else {
             let directory = self
                 .storage
@@ -84,12 +306,13 @@ impl ObjectService {
                             Ok(Some(commit)) => commit.tree,
                             _ => return Err((StatusCode::NOT_FOUND, "Tree not found".to_string())),
                         };
-                        self.get_tree_objects(&tree_id, &repo_path).await
+                        self.tree_response(&tree_id, &repo_path, false, headers)
+                            .await
                     } else {
                         let dirs = self.storage.get_directory_by_pid(dir.id).await.unwrap();
                         let items = dirs.into_iter().map(|x| x.into()).collect();
                         let data = Directories { items };
-                        Ok(Json(data))
+                        Ok(Json(data).into_response())
                     }
                 }
                 None => Err((
@@ -100,6 +323,42 @@ impl ObjectService {
         }
     }
 
+    /// The listing of the tree `tree_id`, or `304 Not Modified` when the caller has it. A
+    /// listing with missing objects isn't tagged, since repairing them changes it, so a caller
+    /// with the tag has a complete listing and the tree only needs to be found.
+    async fn tree_response(
+        &self,
+        tree_id: &str,
+        repo_path: &str,
+        immutable: bool,
+        headers: &HeaderMap,
+    ) -> Result<Response, (StatusCode, String)> {
+        let id = SHA1::from_str(tree_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let validator = Validator::of(&[&id.to_plain_str(), repo_path], immutable);
+        if let Some(not_modified) = validator.check(headers) {
+            if self.has_object(tree_id, "tree").await {
+                return Ok(not_modified);
+            }
+        }
+        let directories = self.get_tree_objects(tree_id, repo_path).await?;
+        let missing = directories
+            .items
+            .iter()
+            .any(|item| item.content_type == "missing");
+        if missing {
+            return Ok(directories.into_response());
+        }
+        Ok(validator.respond(directories))
+    }
+
+    /// Whether `object_id` is stored as an object of `object_type`. Only its type is read.
+    async fn has_object(&self, object_id: &str, object_type: &str) -> bool {
+        matches!(
+            self.storage.get_obj_type_by_id(object_id).await,
+            Ok(Some(found)) if found == object_type
+        )
+    }
+
     pub async fn get_tree_objects(
         &self,
         object_id: &str,
@@ -124,6 +383,41 @@ impl ObjectService {
             .collect();
 
         let child_nodes = self.storage.get_nodes_by_hashes(child_ids, repo_path).await.unwrap();
+        // gitlinks have no nodes, the commits they pin are in other repositories
+        let gitlinks: Vec<_> = tree
+            .tree_items
+            .iter()
+            .filter(|item| item.mode == GitTreeItemMode::Commit)
+            .collect();
+        let dir = child_nodes
+            .first()
+            .and_then(|node| node.full_path.rsplit_once('/'))
+            .map(|(dir, _)| dir.to_owned())
+            .unwrap_or_else(|| repo_path.trim_end_matches('/').to_owned());
+
+        // entries without a node whose object is missing too are listed as missing
+        let listed: HashSet<String> = child_nodes.iter().map(|n| n.git_id.clone()).collect();
+        let unlisted: Vec<String> = tree
+            .tree_items
+            .iter()
+            .filter(|item| item.mode != GitTreeItemMode::Commit)
+            .map(|item| item.id.to_plain_str())
+            .filter(|id| !listed.contains(id))
+            .collect();
+        let known = match unlisted.is_empty() {
+            true => HashSet::new(),
+            false => self
+                .storage
+                .find_known_ids(repo_path, &unlisted)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        };
+        let missing: Vec<_> = tree
+            .tree_items
+            .iter()
+            .filter(|item| unlisted.contains(&item.id.to_plain_str()))
+            .filter(|item| !known.contains(&item.id.to_plain_str()))
+            .collect();
 
         let mut items: Vec<Item> = child_nodes
             .iter()
@@ -140,29 +434,169 @@ impl ObjectService {
             related_c_map.insert(c.git_id.clone(), c.into());
         }
 
+        let rules = self.autolinks.rules().await?;
         for item in &mut items {
             let related_c_id = item.commit_id.clone().unwrap();
-            let commit = related_c_map.get(&related_c_id).unwrap();
-            item.commit_msg = Some(utils::remove_useless_str(
-                commit.message.clone(),
-                SIGNATURE_END.to_owned(),
-            ));
+            let Some(commit) = related_c_map.get(&related_c_id) else {
+                missing_objects::report(Some(repo_path), &related_c_id, "browse");
+                continue;
+            };
+            let commit_msg =
+                utils::remove_useless_str(commit.message.clone(), SIGNATURE_END.to_owned());
+            item.commit_autolinks = rules.resolve(repo_path, &commit_msg);
+            item.commit_msg = Some(commit_msg);
             item.commit_date = Some(commit.committer.timestamp.to_string());
         }
 
+        for item in missing {
+            missing_objects::report(Some(repo_path), &item.id.to_plain_str(), "browse");
+            items.push(Item {
+                id: item.id.to_plain_str(),
+                name: item.name.clone(),
+                path: format!("{}/{}", dir, item.name),
+                content_type: "missing".to_owned(),
+                under_repo: true,
+                commit_msg: None,
+                commit_date: None,
+                commit_id: None,
+                commit_autolinks: Vec::new(),
+                submodule: None,
+            });
+        }
+
+        if !gitlinks.is_empty() {
+            let mut loader = ObjectLoader::new(self.storage.clone());
+            let head = loader.resolve_ref(repo_path, None).await?;
+            let modules = Self::gitmodules(&mut loader, &head).await?;
+            let base_url = webhook::clone_url(&webhook::public_url(), repo_path);
+            let below_repo = dir.strip_prefix(repo_path.trim_end_matches('/')).unwrap_or("");
+            for gitlink in gitlinks {
+                let path = format!("{}/{}", below_repo, gitlink.name);
+                let module = modules.find(&path);
+                items.push(Item {
+                    id: gitlink.id.to_plain_str(),
+                    name: gitlink.name.clone(),
+                    path: format!("{}/{}", dir, gitlink.name),
+                    content_type: "submodule".to_owned(),
+                    under_repo: true,
+                    commit_msg: None,
+                    commit_date: None,
+                    commit_id: None,
+                    commit_autolinks: Vec::new(),
+                    submodule: Some(SubmoduleLink {
+                        url: module.map(|m| gitmodules::resolve_url(&base_url, &m.url)),
+                        branch: module.and_then(|m| m.branch.clone()),
+                        commit_id: gitlink.id.to_plain_str(),
+                    }),
+                });
+            }
+        }
+
         let data = Directories { items };
         Ok(Json(data))
     }
 
+    /// The submodules `.gitmodules` names at the commit `refs` of the repository, with the
+    /// commits their gitlinks pin.
+    pub async fn get_submodules(
+        &self,
+        query: SubmoduleQuery,
+    ) -> Result<Json<Vec<Submodule>>, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let commit_id = loader
+            .resolve_ref(&query.repo_path, query.refs.as_deref())
+            .await?;
+        let tree_id = loader.commit(&commit_id).await?.tree_id;
+        let modules = Self::gitmodules(&mut loader, &commit_id).await?;
+        let base_url = webhook::clone_url(&webhook::public_url(), &query.repo_path);
+        let mut submodules = Vec::with_capacity(modules.submodules.len());
+        for module in modules.submodules {
+            let gitlink = loader
+                .find_path(&tree_id, &module.path)
+                .await?
+                .filter(|item| item.mode == TreeItemMode::Commit);
+            submodules.push(Submodule {
+                url: gitmodules::resolve_url(&base_url, &module.url),
+                name: module.name,
+                path: module.path,
+                branch: module.branch,
+                commit_id: gitlink.map(|item| item.id.to_plain_str()),
+            });
+        }
+        Ok(Json(submodules))
+    }
+
+    /// The README of the directory `path` at the commit `refs` of the repository, rendered.
+    pub async fn get_readme(
+        &self,
+        query: ReadmeQuery,
+    ) -> Result<Json<Readme>, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let commit_id = loader
+            .resolve_ref(&query.repo_path, query.refs.as_deref())
+            .await?;
+        let mut tree_id = loader.commit(&commit_id).await?.tree_id;
+        let dir = query.path.as_deref().unwrap_or_default().trim_matches('/');
+        if !dir.is_empty() {
+            tree_id = match loader.find_path(&tree_id, dir).await? {
+                Some(item) if item.mode == TreeItemMode::Tree => item.id,
+                _ => return Err((StatusCode::NOT_FOUND, format!("No directory {}", dir))),
+            };
+        }
+        let readme = loader
+            .tree(&tree_id)
+            .await?
+            .tree_items
+            .into_iter()
+            .filter(|item| item.mode == TreeItemMode::Blob)
+            .filter_map(|item| markdown::readme_rank(&item.name).map(|rank| (rank, item)))
+            .min_by_key(|(rank, _)| *rank)
+            .map(|(_, item)| item)
+            .ok_or((StatusCode::NOT_FOUND, "No README".to_owned()))?;
+        let data = loader.blob(&readme.id).await?;
+        let content = String::from_utf8_lossy(&data).into_owned();
+        Ok(Json(Readme {
+            path: if dir.is_empty() {
+                readme.name
+            } else {
+                format!("{}/{}", dir, readme.name)
+            },
+            blob_id: readme.id.to_plain_str(),
+            html: markdown::render(&content),
+            content,
+        }))
+    }
+
+    /// The `.gitmodules` at the root of the commit `commit_id`, empty when it has none.
+    async fn gitmodules(
+        loader: &mut ObjectLoader,
+        commit_id: &SHA1,
+    ) -> Result<Gitmodules, (StatusCode, String)> {
+        let tree_id = loader.commit(commit_id).await?.tree_id;
+        match loader.find_path(&tree_id, ".gitmodules").await? {
+            Some(item) if item.mode == TreeItemMode::Blob => {
+                let data = loader.blob(&item.id).await?;
+                Ok(Gitmodules::parse(&String::from_utf8_lossy(&data)))
+            }
+            _ => Ok(Gitmodules::default()),
+        }
+    }
+
     pub async fn get_objects_data(
         &self,
         object_id: &str,
         repo_path: &str,
+        headers: &HeaderMap,
     ) -> Result<Response, (StatusCode, String)> {
+        let id = SHA1::from_str(object_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
         let node = match self.storage.get_node_by_hash(object_id, repo_path).await {
             Ok(Some(node)) => node,
             _ => return Err((StatusCode::NOT_FOUND, "Blob not found".to_string())),
         };
+        let validator = Validator::object(&id, true);
+        if let Some(not_modified) = validator.check(headers) {
+            return Ok(not_modified);
+        }
         let raw_data = match self.storage.get_obj_data_by_id(object_id).await {
             Ok(Some(model)) => model,
             _ => return Err((StatusCode::NOT_FOUND, "Blob not found".to_string())),
@@ -171,9 +605,9 @@ impl ObjectService {
         let res = Response::builder()
             .header("Content-Type", "application/octet-stream")
             .header("Content-Disposition", file_name)
-            .body(raw_data.data.into())
+            .body(Body::from(raw_data.data))
             .unwrap();
-        Ok(res)
+        Ok(validator.respond(res))
     }
 
     pub async fn count_object_num(
@@ -219,3 +653,110 @@ pub mod utils {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::http::{header, HeaderMap, StatusCode};
+    use sea_orm::{DatabaseConnection, DatabaseTransaction};
+
+    use common::errors::MegaError;
+    use entity::{commit, objects, refs};
+    use jupiter::storage::autolink_storage::AutolinkStorage;
+    use storage::driver::database::storage::ObjectStorage;
+
+    use super::ObjectService;
+    use crate::api_service::autolink::Autolinker;
+
+    const BLOB_ID: &str = "8ab686eafeb1f44702738c8b0f24f2567c36da6d";
+
+    /// Holds one blob and counts how often its data is read.
+    struct OneBlob {
+        connection: DatabaseConnection,
+        reads: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ObjectStorage for OneBlob {
+        fn get_connection(&self) -> &DatabaseConnection {
+            &self.connection
+        }
+
+        async fn save_obj_data_to_db(
+            &self,
+            _txn: Option<&DatabaseTransaction>,
+            _obj_data: Vec<objects::ActiveModel>,
+        ) -> Result<bool, MegaError> {
+            unimplemented!()
+        }
+
+        async fn search_refs(&self, _path_str: &str) -> Result<Vec<refs::Model>, MegaError> {
+            unimplemented!()
+        }
+
+        async fn search_commits(&self, _path_str: &str) -> Result<Vec<commit::Model>, MegaError> {
+            unimplemented!()
+        }
+
+        async fn get_obj_type_by_id(&self, git_id: &str) -> Result<Option<String>, MegaError> {
+            Ok((git_id == BLOB_ID).then(|| "blob".to_owned()))
+        }
+
+        async fn get_obj_data_by_id(
+            &self,
+            git_id: &str,
+        ) -> Result<Option<objects::Model>, MegaError> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            Ok((git_id == BLOB_ID).then(|| objects::Model {
+                id: 1,
+                git_id: git_id.to_owned(),
+                object_type: "blob".to_owned(),
+                data: b"fn main() {}\n".to_vec(),
+                link: None,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_blob_is_not_read() {
+        let storage = Arc::new(OneBlob {
+            connection: DatabaseConnection::Disconnected,
+            reads: AtomicUsize::new(0),
+        });
+        let service = ObjectService {
+            storage: storage.clone(),
+            autolinks: Autolinker {
+                autolink_storage: AutolinkStorage::new(Arc::new(DatabaseConnection::Disconnected)),
+            },
+            highlighter: Default::default(),
+        };
+        let get = |headers: HeaderMap| {
+            let service = service.clone();
+            async move {
+                service
+                    .get_blob_objects(BLOB_ID, None, None, None, None, None, &headers)
+                    .await
+            }
+        };
+
+        let response = get(HeaderMap::new()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(storage.reads.load(Ordering::SeqCst), 1);
+
+        let mut headers = HeaderMap::new();
+        let etag = response.headers()[header::ETAG].clone();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let response = get(headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(storage.reads.load(Ordering::SeqCst), 1);
+
+        let missing = "0".repeat(40);
+        let response = service
+            .get_blob_objects(&missing, None, None, None, None, None, &HeaderMap::new())
+            .await;
+        assert_eq!(response.unwrap_err().0, StatusCode::NOT_FOUND);
+    }
+}
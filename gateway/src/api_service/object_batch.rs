@@ -0,0 +1,202 @@
+//! The batched object API, the first of the experimental v2 object APIs.
+//!
+//! Clients browsing a repository, like the FUSE client and the web UI, need a commit, then its
+//! tree, then the trees and blobs below, and pay a round trip for each over the v1 API. A batch
+//! asks for any mix of commits, trees and blobs in one `POST /objects/batch`, and gets them back
+//! in one binary response:
+//!
+//! ```text
+//! "MOBJ" version:u8 count:u32
+//! count times: status:u8 type:u8 id:[u8; 20] length:u32 data:[u8; length]
+//! ```
+//!
+//! Numbers are big endian. Objects come in the order they were asked for, with the data git
+//! stores for them, without the `<type> <size>` header. The status is [`FOUND`], [`MISSING`] for
+//! an object that doesn't exist or isn't of the type asked for, or [`DEFERRED`] for one left out
+//! because the response is full, which is asked for again in the next batch.
+//!
+//! The API is on while the `objects_v2` feature flag is, which `GET /capabilities` tells.
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::Json;
+
+use jupiter::storage::feature_flag_storage::{flags, FeatureFlagStorage};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+
+use crate::model::objects::{Capabilities, ObjectBatch, ObjectKind};
+
+pub const MAGIC: &[u8; 4] = b"MOBJ";
+pub const VERSION: u8 = 1;
+
+pub const FOUND: u8 = 0;
+pub const MISSING: u8 = 1;
+pub const DEFERRED: u8 = 2;
+
+/// Most objects one batch may ask for.
+pub const MAX_REQUESTS: usize = 1000;
+
+/// Bytes of object data after which the objects left are deferred, the first object found is
+/// always sent.
+const MAX_RESPONSE_DATA: usize = 32 * 1024 * 1024;
+
+pub const CONTENT_TYPE: &str = "application/x-mega-objects";
+
+/// What a batch answers for one object.
+pub struct BatchEntry<'a> {
+    pub status: u8,
+    pub kind: ObjectKind,
+    pub id: SHA1,
+    pub data: &'a [u8],
+}
+
+fn kind_code(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => 1,
+        ObjectKind::Tree => 2,
+        ObjectKind::Blob => 3,
+    }
+}
+
+/// Encode the response of a batch.
+pub fn encode(entries: &[BatchEntry]) -> Vec<u8> {
+    let size: usize = entries.iter().map(|e| 26 + e.data.len()).sum();
+    let mut out = Vec::with_capacity(9 + size);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for entry in entries {
+        out.push(entry.status);
+        out.push(kind_code(entry.kind));
+        out.extend_from_slice(&entry.id.0);
+        out.extend_from_slice(&(entry.data.len() as u32).to_be_bytes());
+        out.extend_from_slice(entry.data);
+    }
+    out
+}
+
+/// Serves the batched object API while it is turned on.
+#[derive(Clone)]
+pub struct ObjectBatchService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub feature_flags: FeatureFlagStorage,
+}
+
+impl ObjectBatchService {
+    pub async fn capabilities(&self) -> Json<Capabilities> {
+        let mut capabilities = Vec::new();
+        if self.feature_flags.is_enabled(flags::OBJECTS_V2, None).await {
+            capabilities.push(format!("objects-batch={}", VERSION));
+        }
+        Json(Capabilities { capabilities })
+    }
+
+    pub async fn batch(&self, batch: ObjectBatch) -> Result<Response, (StatusCode, String)> {
+        if !self.feature_flags.is_enabled(flags::OBJECTS_V2, None).await {
+            return Err((
+                StatusCode::NOT_FOUND,
+                "the batched object API is not enabled".to_owned(),
+            ));
+        }
+        if batch.requests.len() > MAX_REQUESTS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("a batch asks for at most {} objects", MAX_REQUESTS),
+            ));
+        }
+        let mut ids = Vec::with_capacity(batch.requests.len());
+        for request in &batch.requests {
+            let id = SHA1::from_str(&request.id).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid object id: {}", request.id),
+                )
+            })?;
+            ids.push(id);
+        }
+        let mut wanted: Vec<String> = ids.iter().map(|id| id.to_plain_str()).collect();
+        wanted.sort();
+        wanted.dedup();
+        let objects: HashMap<String, (String, Vec<u8>)> = self
+            .storage
+            .get_obj_data_by_ids(wanted)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .into_iter()
+            .map(|model| (model.git_id, (model.object_type, model.data)))
+            .collect();
+
+        let mut sent = 0;
+        let mut entries = Vec::with_capacity(ids.len());
+        for (request, id) in batch.requests.iter().zip(ids) {
+            let object = objects
+                .get(&id.to_plain_str())
+                .filter(|(object_type, _)| object_type == request.kind.type_name());
+            let (status, data) = match object {
+                Some((_, data)) if sent > 0 && sent + data.len() > MAX_RESPONSE_DATA => {
+                    (DEFERRED, &[][..])
+                }
+                Some((_, data)) => {
+                    sent += data.len();
+                    (FOUND, &data[..])
+                }
+                None => (MISSING, &[][..]),
+            };
+            entries.push(BatchEntry {
+                status,
+                kind: request.kind,
+                id,
+                data,
+            });
+        }
+        let res = Response::builder()
+            .header("Content-Type", CONTENT_TYPE)
+            .body(encode(&entries).into())
+            .unwrap();
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use venus::hash::SHA1;
+
+    use super::{encode, BatchEntry, FOUND, MAGIC, MISSING, VERSION};
+    use crate::model::objects::ObjectKind;
+
+    #[test]
+    fn test_encode() {
+        let tree = SHA1::from_str("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap();
+        let blob = SHA1::from_str("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391").unwrap();
+        let out = encode(&[
+            BatchEntry {
+                status: FOUND,
+                kind: ObjectKind::Blob,
+                id: blob,
+                data: b"hello",
+            },
+            BatchEntry {
+                status: MISSING,
+                kind: ObjectKind::Tree,
+                id: tree,
+                data: b"",
+            },
+        ]);
+        assert_eq!(&out[..4], MAGIC);
+        assert_eq!(out[4], VERSION);
+        assert_eq!(&out[5..9], &2u32.to_be_bytes());
+        assert_eq!(&out[9..11], &[FOUND, 3]);
+        assert_eq!(&out[11..31], &blob.0);
+        assert_eq!(&out[31..35], &5u32.to_be_bytes());
+        assert_eq!(&out[35..40], b"hello");
+        assert_eq!(&out[40..42], &[MISSING, 2]);
+        assert_eq!(&out[62..66], &0u32.to_be_bytes());
+        assert_eq!(out.len(), 66);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+
+use common::missing_objects;
+use common::utils::HIDDEN_REF_PREFIX;
+use entity::refs;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::gitattributes::{self, Gitattributes};
+use venus::internal::object::commit::Commit;
+use venus::internal::object::tag::Tag;
+use venus::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use venus::internal::object::ObjectTrait;
+
+/// Most annotated tags followed to the commit they tag.
+const MAX_TAG_DEPTH: usize = 8;
+
+/// Loads git objects from the object table and parses them into venus objects.
+///
+/// Commits and trees are cached by id, history walks revisit the same objects many times.
+pub struct ObjectLoader {
+    storage: Arc<dyn ObjectStorage>,
+    commits: HashMap<SHA1, Commit>,
+    trees: HashMap<SHA1, Tree>,
+    /// The object the last load failed on for lack of it
+    last_missing: Option<SHA1>,
+    gaps: Vec<SHA1>,
+}
+
+impl ObjectLoader {
+    pub fn new(storage: Arc<dyn ObjectStorage>) -> Self {
+        ObjectLoader {
+            storage,
+            commits: HashMap::new(),
+            trees: HashMap::new(),
+            last_missing: None,
+            gaps: Vec::new(),
+        }
+    }
+
+    async fn load(&mut self, id: &SHA1, object_type: &str) -> Result<Vec<u8>, (StatusCode, String)> {
+        match self.storage.get_obj_data_by_id(&id.to_plain_str()).await {
+            Ok(Some(model)) if model.object_type == object_type => Ok(model.data),
+            Ok(None) => {
+                self.last_missing = Some(*id);
+                Err((
+                    StatusCode::NOT_FOUND,
+                    format!("{} {} not found", object_type, id.to_plain_str()),
+                ))
+            }
+            Ok(_) => Err((
+                StatusCode::NOT_FOUND,
+                format!("{} {} not found", object_type, id.to_plain_str()),
+            )),
+            Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+        }
+    }
+
+    pub async fn commit(&mut self, id: &SHA1) -> Result<Commit, (StatusCode, String)> {
+        if let Some(commit) = self.commits.get(id) {
+            return Ok(commit.clone());
+        }
+        let data = self.load(id, "commit").await?;
+        let mut commit = Commit::from_bytes(&data)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        commit.id = *id;
+        self.commits.insert(*id, commit.clone());
+        Ok(commit)
+    }
+
+    pub async fn tree(&mut self, id: &SHA1) -> Result<Tree, (StatusCode, String)> {
+        if let Some(tree) = self.trees.get(id) {
+            return Ok(tree.clone());
+        }
+        let data = self.load(id, "tree").await?;
+        let mut tree = Tree::from_bytes(&data)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        tree.id = *id;
+        self.trees.insert(*id, tree.clone());
+        Ok(tree)
+    }
+
+    pub async fn blob(&mut self, id: &SHA1) -> Result<Vec<u8>, (StatusCode, String)> {
+        self.load(id, "blob").await
+    }
+
+    /// The outcome of loading objects a ref, commit or tree names, `None` when one of them is
+    /// missing from the store. The gap is reported as one of `repo_path` met by `context`, and
+    /// the caller goes on without the object, see `common::missing_objects`.
+    pub fn tolerate_missing<T>(
+        &mut self,
+        result: Result<T, (StatusCode, String)>,
+        repo_path: &str,
+        context: &'static str,
+    ) -> Result<Option<T>, (StatusCode, String)> {
+        match (result, self.last_missing.take()) {
+            (Ok(value), _) => Ok(Some(value)),
+            (Err((StatusCode::NOT_FOUND, _)), Some(id)) => {
+                missing_objects::report(Some(repo_path), &id.to_plain_str(), context);
+                if !self.gaps.contains(&id) {
+                    self.gaps.push(id);
+                }
+                Ok(None)
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    /// The commit `id` names, following annotated tags down to it. Any other object is returned
+    /// as it is.
+    pub async fn peel(&mut self, id: &SHA1) -> Result<SHA1, (StatusCode, String)> {
+        let mut id = *id;
+        // tags of tags are allowed, a loop of them is not
+        for _ in 0..MAX_TAG_DEPTH {
+            let model = match self.storage.get_obj_data_by_id(&id.to_plain_str()).await {
+                Ok(Some(model)) if model.object_type == "tag" => model,
+                Ok(_) => return Ok(id),
+                Err(err) => return Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+            };
+            id = Tag::from_bytes(&model.data)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .object_hash;
+        }
+        Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("tag {} nests too deep", id.to_plain_str()),
+        ))
+    }
+
+    /// The objects found missing which [`ObjectLoader::tolerate_missing`] went on without, in the
+    /// order they were met.
+    pub fn gaps(&self) -> &[SHA1] {
+        &self.gaps
+    }
+
+    /// Look up the entry at `path` (relative to the tree, `/` separated) starting from `tree_id`.
+    pub async fn find_path(
+        &mut self,
+        tree_id: &SHA1,
+        path: &str,
+    ) -> Result<Option<TreeItem>, (StatusCode, String)> {
+        let mut tree_id = *tree_id;
+        let components: Vec<String> = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect();
+        let mut components = components.into_iter().peekable();
+        while let Some(name) = components.next() {
+            let tree = self.tree(&tree_id).await?;
+            let Some(item) = tree.tree_items.into_iter().find(|item| item.name == name) else {
+                return Ok(None);
+            };
+            if components.peek().is_none() {
+                return Ok(Some(item));
+            }
+            if item.mode != TreeItemMode::Tree {
+                return Ok(None);
+            }
+            tree_id = item.id;
+        }
+        Ok(None)
+    }
+
+    /// The `.gitattributes` files of the tree `tree_id` which apply to `path`, those of the root
+    /// and of each directory above `path`.
+    pub async fn attributes(
+        &mut self,
+        tree_id: &SHA1,
+        path: &str,
+    ) -> Result<Gitattributes, (StatusCode, String)> {
+        let mut attributes = Gitattributes::default();
+        let mut dirs = vec![String::new()];
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        for end in 1..components.len() {
+            dirs.push(components[..end].join("/"));
+        }
+        for dir in dirs {
+            let file = format!("{}/{}", dir, gitattributes::FILE_NAME);
+            if let Some(item) = self.find_path(tree_id, &file).await? {
+                if item.mode == TreeItemMode::Blob {
+                    let data = self.blob(&item.id).await?;
+                    attributes.add(&dir, &String::from_utf8_lossy(&data));
+                }
+            }
+        }
+        Ok(attributes)
+    }
+
+    /// Resolve `refs` in the repository at `repo_path` to a commit id.
+    ///
+    /// Accepts a full commit id, a full ref name, or a short branch / tag name. When `refs` is not
+    /// given the [default branch](ObjectLoader::default_branch) is used.
+    pub async fn resolve_ref(
+        &self,
+        repo_path: &str,
+        refs: Option<&str>,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let Some(name) = refs else {
+            return Ok(self.default_branch(repo_path).await?.1);
+        };
+        if name.len() == 40 {
+            if let Ok(id) = SHA1::from_str(name) {
+                return Ok(id);
+            }
+        }
+        let all_refs = self.all_refs(repo_path).await?;
+        let candidates = [
+            name.to_owned(),
+            format!("refs/heads/{}", name),
+            format!("refs/tags/{}", name),
+        ];
+        match candidates
+            .iter()
+            .find_map(|c| all_refs.iter().find(|r| &r.ref_name == c))
+        {
+            Some(r) => {
+                SHA1::from_str(&r.ref_git_id).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+            }
+            None => Err((
+                StatusCode::NOT_FOUND,
+                format!("ref {} not found in {}", name, repo_path),
+            )),
+        }
+    }
+
+    /// The ref HEAD is taken to be in the repository at `repo_path`, main, then master, then the
+    /// first ref found which isn't hidden, with the commit it points to.
+    pub async fn default_branch(
+        &self,
+        repo_path: &str,
+    ) -> Result<(String, SHA1), (StatusCode, String)> {
+        let all_refs = self.all_refs(repo_path).await?;
+        let found = ["refs/heads/main", "refs/heads/master"]
+            .iter()
+            .find_map(|c| all_refs.iter().find(|r| &r.ref_name == c))
+            .or_else(|| {
+                all_refs
+                    .iter()
+                    .find(|r| !r.ref_name.starts_with(HIDDEN_REF_PREFIX))
+            });
+        match found {
+            Some(r) => {
+                let id = SHA1::from_str(&r.ref_git_id)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+                Ok((r.ref_name.clone(), id))
+            }
+            None => Err((
+                StatusCode::NOT_FOUND,
+                format!("ref HEAD not found in {}", repo_path),
+            )),
+        }
+    }
+
+    async fn all_refs(&self, repo_path: &str) -> Result<Vec<refs::Model>, (StatusCode, String)> {
+        self.storage
+            .get_all_refs_by_path(repo_path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+/// A commit message without any signature headers stored with it.
+pub fn commit_body(message: &str) -> &str {
+    match message.strip_prefix('\n') {
+        Some(body) => body,
+        None => message
+            .split_once("\n\n")
+            .map(|(_, body)| body)
+            .unwrap_or(message),
+    }
+}
+
+/// The first line of a commit message, skipping any signature headers stored with it.
+pub fn commit_summary(message: &str) -> String {
+    let body = commit_body(message);
+    body.lines().next().unwrap_or_default().trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::commit_summary;
+
+    #[test]
+    fn test_commit_summary() {
+        assert_eq!(commit_summary("\nfix build\n\ndetails\n"), "fix build");
+        assert_eq!(
+            commit_summary("gpgsig -----BEGIN PGP SIGNATURE-----\n abc\n -----END PGP SIGNATURE-----\n\nsigned commit\n"),
+            "signed commit"
+        );
+    }
+}
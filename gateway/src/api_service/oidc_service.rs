@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+
+use common::utils::generate_id;
+use db_entity::{
+    mega_access_token, mega_oidc_login, mega_org_member, mega_user, mega_user_identity,
+};
+use jupiter::storage::access_token_storage::AccessTokenStorage;
+use jupiter::storage::oidc_storage::OidcStorage;
+use jupiter::storage::org_storage::OrgStorage;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::api_service::account_service::check_account_name;
+use crate::auth::oidc::{self, OidcConfig, OidcEndpoints, OidcProfile};
+use crate::auth::token::{self, TokenScope};
+use crate::model::account::{IssuedToken, OidcCallbackQuery, OidcLoginQuery, ROLE_MEMBER};
+
+/// Minutes a user has to come back from the provider.
+const LOGIN_TIMEOUT_MINUTES: i64 = 10;
+
+/// Name of the tokens issued on login.
+const SESSION_TOKEN_NAME: &str = "oidc login";
+
+/// Logs users in through the OpenID Connect provider configured by `MEGA_OIDC_ISSUER`.
+///
+/// The first login of a provider account creates a mega user of the same name, without a
+/// password, and links the two; later logins find the user by the link even when the name at
+/// the provider changed. Groups of the user naming organizations make the user a member of them.
+/// Each login issues an access token, see [`crate::auth::http`] for its use.
+#[derive(Clone)]
+pub struct OidcService {
+    /// `None` when OIDC login is off
+    pub config: Option<Arc<OidcConfig>>,
+    pub endpoints: Arc<OnceCell<OidcEndpoints>>,
+    pub client: reqwest::Client,
+    pub oidc_storage: OidcStorage,
+    pub user_storage: UserStorage,
+    pub org_storage: OrgStorage,
+    pub token_storage: AccessTokenStorage,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn provider_error(err: impl ToString) -> (StatusCode, String) {
+    (
+        StatusCode::BAD_GATEWAY,
+        format!("OIDC provider error: {}", err.to_string()),
+    )
+}
+
+/// Only paths of this server are accepted, the login must not send users elsewhere.
+fn check_return_to(return_to: &str) -> Result<(), (StatusCode, String)> {
+    let local = return_to.starts_with('/')
+        && !return_to.starts_with("//")
+        && !return_to.contains('\\')
+        && !return_to.contains('#')
+        && !return_to.chars().any(char::is_control);
+    if !local {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("return_to must be a path of this server: {}", return_to),
+        ));
+    }
+    Ok(())
+}
+
+impl OidcService {
+    fn config(&self) -> Result<&Arc<OidcConfig>, (StatusCode, String)> {
+        self.config.as_ref().ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "OIDC login is not configured".to_owned(),
+            )
+        })
+    }
+
+    /// The configured endpoints, or the ones the issuer publishes, fetched once.
+    async fn endpoints(&self, config: &OidcConfig) -> Result<&OidcEndpoints, (StatusCode, String)> {
+        self.endpoints
+            .get_or_try_init(|| async {
+                if let Some(endpoints) = &config.endpoints {
+                    return Ok(endpoints.clone());
+                }
+                self.client
+                    .get(config.discovery_url())
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(provider_error)?
+                    .json::<OidcEndpoints>()
+                    .await
+                    .map_err(provider_error)
+            })
+            .await
+    }
+
+    /// Send the browser to the provider.
+    pub async fn login(&self, query: OidcLoginQuery) -> Result<Response, (StatusCode, String)> {
+        let config = self.config()?;
+        if let Some(return_to) = &query.return_to {
+            check_return_to(return_to)?;
+        }
+        let endpoints = self.endpoints(config).await?;
+        let now = chrono::Utc::now().naive_utc();
+        // logins abandoned at the provider
+        let expired = now - chrono::Duration::minutes(LOGIN_TIMEOUT_MINUTES);
+        if let Err(e) = self.oidc_storage.delete_logins_before(expired).await {
+            tracing::warn!("unable to remove expired OIDC logins: {}", e);
+        }
+        let login = mega_oidc_login::Model {
+            id: generate_id(),
+            state: oidc::random_secret(32),
+            code_verifier: oidc::random_secret(64),
+            return_to: query.return_to,
+            created_at: now,
+        };
+        let url = config.authorize_url(endpoints, &login.state, &login.code_verifier);
+        self.oidc_storage
+            .save_login(login)
+            .await
+            .map_err(internal_error)?;
+        Ok(Redirect::to(&url).into_response())
+    }
+
+    /// Complete a login the provider sent the browser back from.
+    pub async fn callback(
+        &self,
+        query: OidcCallbackQuery,
+    ) -> Result<Response, (StatusCode, String)> {
+        let config = self.config()?;
+        if let Some(error) = query.error {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                format!(
+                    "login refused by the provider: {}",
+                    query.error_description.unwrap_or(error)
+                ),
+            ));
+        }
+        let (Some(code), Some(state)) = (query.code, query.state) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "code and state are required".to_owned(),
+            ));
+        };
+        let expired =
+            chrono::Utc::now().naive_utc() - chrono::Duration::minutes(LOGIN_TIMEOUT_MINUTES);
+        let login = self
+            .oidc_storage
+            .take_login(&state)
+            .await
+            .map_err(internal_error)?
+            .filter(|login| login.created_at > expired)
+            .ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "unknown or expired login, start again".to_owned(),
+                )
+            })?;
+
+        let claims = self
+            .fetch_claims(config, &code, &login.code_verifier)
+            .await?;
+        let profile = oidc::profile_from_claims(&claims, config).map_err(provider_error)?;
+        let user = self.resolve_user(config, &profile).await?;
+        self.join_groups(&user.name, &profile.groups).await?;
+        let issued = self.issue_session(config, &user.name).await?;
+        tracing::info!("{} logged in through {}", user.name, config.issuer);
+
+        Ok(match login.return_to {
+            Some(return_to) => {
+                let location = format!("{}#token={}", return_to, issued.token);
+                let mut response = Redirect::to(&location).into_response();
+                response
+                    .headers_mut()
+                    .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+                response
+            }
+            None => Json(issued).into_response(),
+        })
+    }
+
+    /// Exchange the code for an access token and ask the provider who it belongs to.
+    async fn fetch_claims(
+        &self,
+        config: &OidcConfig,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<Value, (StatusCode, String)> {
+        let endpoints = self.endpoints(config).await?;
+        let tokens: TokenResponse = self
+            .client
+            .post(&endpoints.token_endpoint)
+            // GitHub answers with a form unless asked for JSON
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &config.redirect_url),
+                ("client_id", &config.client_id),
+                ("client_secret", &config.client_secret),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(provider_error)?
+            .json()
+            .await
+            .map_err(provider_error)?;
+        self.client
+            .get(&endpoints.userinfo_endpoint)
+            .bearer_auth(&tokens.access_token)
+            .header(reqwest::header::ACCEPT, "application/json")
+            // GitHub refuses requests without one
+            .header(reqwest::header::USER_AGENT, "mega")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(provider_error)?
+            .json()
+            .await
+            .map_err(provider_error)
+    }
+
+    /// The user linked to the provider account, created on its first login.
+    async fn resolve_user(
+        &self,
+        config: &OidcConfig,
+        profile: &OidcProfile,
+    ) -> Result<mega_user::Model, (StatusCode, String)> {
+        let linked = self
+            .oidc_storage
+            .find_identity(&config.issuer, &profile.subject)
+            .await
+            .map_err(internal_error)?;
+        let name = match &linked {
+            Some(identity) => identity.username.clone(),
+            None => profile.username.clone(),
+        };
+        check_account_name(&name)?;
+        let existing = self
+            .user_storage
+            .get_user_by_name(&name)
+            .await
+            .map_err(internal_error)?;
+        if linked.is_none() {
+            let org = self
+                .org_storage
+                .get_org_by_name(&name)
+                .await
+                .map_err(internal_error)?;
+            if org.is_some() || (existing.is_some() && !config.link_by_name) {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "{} is already taken, an administrator has to link the account",
+                        name
+                    ),
+                ));
+            }
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let is_admin = match &config.admin_group {
+            Some(group) => profile.groups.contains(group),
+            None => existing.as_ref().is_some_and(|u| u.is_admin),
+        };
+        let user = match existing {
+            Some(user) => mega_user::Model {
+                display_name: profile.display_name.clone().or(user.display_name),
+                email: profile.email.clone().or(user.email),
+                is_admin,
+                updated_at: now,
+                ..user
+            },
+            None => mega_user::Model {
+                id: generate_id(),
+                name: name.clone(),
+                display_name: profile.display_name.clone(),
+                email: profile.email.clone(),
+                // the account logs in through the provider only
+                password_hash: None,
+                is_admin,
+                created_at: now,
+                updated_at: now,
+            },
+        };
+        self.user_storage
+            .save_user(user.clone())
+            .await
+            .map_err(internal_error)?;
+        self.oidc_storage
+            .save_identity(mega_user_identity::Model {
+                id: generate_id(),
+                issuer: config.issuer.clone(),
+                subject: profile.subject.clone(),
+                username: name,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .map_err(internal_error)?;
+        Ok(user)
+    }
+
+    /// Make `username` a member of the organizations named by `groups`. Groups without an
+    /// organization are ignored, and roles in organizations the user already belongs to are kept.
+    async fn join_groups(
+        &self,
+        username: &str,
+        groups: &[String],
+    ) -> Result<(), (StatusCode, String)> {
+        let joined: HashSet<i64> = self
+            .org_storage
+            .list_user_orgs(username)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|(_, org)| org.id)
+            .collect();
+        for group in groups {
+            let Some(org) = self
+                .org_storage
+                .get_org_by_name(group)
+                .await
+                .map_err(internal_error)?
+            else {
+                continue;
+            };
+            if joined.contains(&org.id) {
+                continue;
+            }
+            self.org_storage
+                .save_member(mega_org_member::Model {
+                    id: generate_id(),
+                    org_id: org.id,
+                    username: username.to_owned(),
+                    role: ROLE_MEMBER.to_owned(),
+                    created_at: chrono::Utc::now().naive_utc(),
+                })
+                .await
+                .map_err(internal_error)?;
+        }
+        Ok(())
+    }
+
+    async fn issue_session(
+        &self,
+        config: &OidcConfig,
+        username: &str,
+    ) -> Result<IssuedToken, (StatusCode, String)> {
+        let now = chrono::Utc::now().naive_utc();
+        let secret = token::generate_token();
+        let model = mega_access_token::Model {
+            id: generate_id(),
+            username: username.to_owned(),
+            name: SESSION_TOKEN_NAME.to_owned(),
+            token_hash: token::token_hash(&secret),
+            scopes: vec![TokenScope::Write.as_str().to_owned()],
+            expires_at: Some(now + chrono::Duration::hours(config.session_hours)),
+            last_used_at: None,
+            created_at: now,
+        };
+        self.token_storage
+            .save_token(model.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(IssuedToken {
+            info: model.into(),
+            token: secret,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_return_to;
+
+    #[test]
+    fn test_check_return_to() {
+        assert!(check_return_to("/").is_ok());
+        assert!(check_return_to("/mr/1?tab=files").is_ok());
+        assert!(check_return_to("https://evil.example.com").is_err());
+        assert!(check_return_to("//evil.example.com").is_err());
+        assert!(check_return_to("/\\evil.example.com").is_err());
+        assert!(check_return_to("/page#x").is_err());
+    }
+}
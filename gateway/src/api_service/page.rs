@@ -0,0 +1,279 @@
+//! Cursor pagination of the list APIs.
+//!
+//! A list returns a [`Page`] of at most `limit` items and an opaque `next_cursor`, passed back as
+//! `cursor` for the page after it. A cursor holds where its page ended, the sort key and id of
+//! the last item for the lists read from the database, instead of an offset: a deep page costs
+//! as much as the first one, and items added while paging neither repeat nor get skipped. A
+//! cursor is only valid with the `order_by` it was made with.
+use axum::http::StatusCode;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::NaiveDateTime;
+use sea_orm::Value;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use jupiter::storage::page::Keyset;
+
+use crate::model::page::{Page, PageQuery};
+
+pub const DEFAULT_LIMIT: u64 = 30;
+pub const MAX_LIMIT: u64 = 100;
+
+/// The field a list is sorted by, and in which direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Order {
+    pub field: &'static str,
+    pub descending: bool,
+}
+
+impl Order {
+    pub const fn asc(field: &'static str) -> Self {
+        Order {
+            field,
+            descending: false,
+        }
+    }
+
+    pub const fn desc(field: &'static str) -> Self {
+        Order {
+            field,
+            descending: true,
+        }
+    }
+
+    /// The order as `order_by` gives it.
+    pub fn name(&self) -> String {
+        if self.descending {
+            format!("-{}", self.field)
+        } else {
+            self.field.to_owned()
+        }
+    }
+}
+
+/// A cursor before it is encoded: the order it is for, and where the page before it ended.
+#[derive(Serialize, Deserialize)]
+struct Cursor<K> {
+    order: String,
+    after: K,
+}
+
+/// Sort key of a row, for the lists read by keyset from the database.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SortKey {
+    Int(i64),
+    Time(NaiveDateTime),
+}
+
+impl SortKey {
+    /// Whether the key has the type of the sort `field`: the fields named `..._at` are times,
+    /// the others integers.
+    fn fits(&self, field: &str) -> bool {
+        match self {
+            SortKey::Int(_) => !field.ends_with("_at"),
+            SortKey::Time(_) => field.ends_with("_at"),
+        }
+    }
+}
+
+impl From<SortKey> for Value {
+    fn from(key: SortKey) -> Self {
+        match key {
+            SortKey::Int(value) => value.into(),
+            SortKey::Time(value) => value.into(),
+        }
+    }
+}
+
+fn bad_cursor() -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, "invalid cursor".to_owned())
+}
+
+pub fn encode_cursor<K: Serialize>(order: &Order, after: K) -> String {
+    let cursor = Cursor {
+        order: order.name(),
+        after,
+    };
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).unwrap())
+}
+
+impl PageQuery {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// The order `order_by` asks for, `default` when it is missing. The other orders a list can
+    /// be sorted in are those of `fields`, in both directions.
+    pub fn order(
+        &self,
+        fields: &[&'static str],
+        default: Order,
+    ) -> Result<Order, (StatusCode, String)> {
+        let Some(name) = self.order_by.as_deref() else {
+            return Ok(default);
+        };
+        let (field, descending) = match name.strip_prefix('-') {
+            Some(field) => (field, true),
+            None => (name, false),
+        };
+        match fields.iter().chain([&default.field]).find(|f| **f == field) {
+            Some(field) => Ok(Order {
+                field,
+                descending,
+            }),
+            None => Err((
+                StatusCode::BAD_REQUEST,
+                format!("unknown order_by: {}", name),
+            )),
+        }
+    }
+
+    /// Where the previous page ended in `order`, `None` for the first page.
+    pub fn after<K: DeserializeOwned>(
+        &self,
+        order: &Order,
+    ) -> Result<Option<K>, (StatusCode, String)> {
+        let Some(cursor) = self.cursor.as_deref() else {
+            return Ok(None);
+        };
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| bad_cursor())?;
+        let cursor: Cursor<K> = serde_json::from_slice(&bytes).map_err(|_| bad_cursor())?;
+        if cursor.order != order.name() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("the cursor is for order_by {}", cursor.order),
+            ));
+        }
+        Ok(Some(cursor.after))
+    }
+
+    /// The page of rows to read from the database in `order`.
+    pub fn keyset(&self, order: &Order) -> Result<Keyset, (StatusCode, String)> {
+        let after: Option<(SortKey, i64)> = self.after(order)?;
+        if matches!(&after, Some((key, _)) if !key.fits(order.field)) {
+            return Err(bad_cursor());
+        }
+        Ok(Keyset {
+            descending: order.descending,
+            after: after.map(|(key, id)| (key.into(), id)),
+            limit: self.limit(),
+        })
+    }
+}
+
+impl<T> Page<T> {
+    /// The page of the `items` read for a page of `limit`, one more than it when a next page
+    /// follows, whose cursor starts after the `key` of the last item kept.
+    pub fn of<K: Serialize>(
+        mut items: Vec<T>,
+        limit: u64,
+        order: &Order,
+        key: impl Fn(&T) -> K,
+    ) -> Self {
+        let limit = limit as usize;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|item| encode_cursor(order, key(item)))
+        } else {
+            None
+        };
+        Page { items, next_cursor }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREATED: Order = Order::desc("created_at");
+
+    fn query(cursor: Option<String>, limit: Option<u64>, order_by: Option<&str>) -> PageQuery {
+        PageQuery {
+            cursor,
+            limit,
+            order_by: order_by.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let fields = ["updated_at", "id"];
+        assert_eq!(query(None, None, None).order(&fields, CREATED), Ok(CREATED));
+        assert_eq!(
+            query(None, None, Some("updated_at")).order(&fields, CREATED),
+            Ok(Order::asc("updated_at"))
+        );
+        assert_eq!(
+            query(None, None, Some("created_at")).order(&fields, CREATED),
+            Ok(Order::asc("created_at"))
+        );
+        assert!(query(None, None, Some("-title"))
+            .order(&fields, CREATED)
+            .is_err());
+        assert_eq!(Order::desc("id").name(), "-id");
+    }
+
+    #[test]
+    fn test_limit() {
+        assert_eq!(query(None, None, None).limit(), DEFAULT_LIMIT);
+        assert_eq!(query(None, Some(0), None).limit(), 1);
+        assert_eq!(query(None, Some(1000), None).limit(), MAX_LIMIT);
+    }
+
+    #[test]
+    fn test_page() {
+        let page = Page::of(vec![5, 4, 3], 2, &CREATED, |n| (SortKey::Int(*n), *n));
+        assert_eq!(page.items, [5, 4]);
+        let next = query(page.next_cursor, None, None);
+        let after: Option<(SortKey, i64)> = next.after(&CREATED).unwrap();
+        assert_eq!(after, Some((SortKey::Int(4), 4)));
+        assert!(next.after::<(SortKey, i64)>(&Order::asc("id")).is_err());
+
+        let last = Page::of(vec![2, 1], 2, &CREATED, |n| *n);
+        assert_eq!(last.items, [2, 1]);
+        assert_eq!(last.next_cursor, None);
+
+        assert!(query(Some("not a cursor".to_owned()), None, None)
+            .after::<i64>(&CREATED)
+            .is_err());
+    }
+
+    #[test]
+    fn test_keyset() {
+        let time = chrono::DateTime::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc();
+        let cursor = encode_cursor(&CREATED, (SortKey::Time(time), 7));
+        let keyset = query(Some(cursor), Some(10), None)
+            .keyset(&CREATED)
+            .unwrap();
+        assert_eq!(
+            keyset,
+            Keyset {
+                descending: true,
+                after: Some((time.into(), 7)),
+                limit: 10,
+            }
+        );
+
+        // a key of the wrong type would only fail in the database
+        let cursor = encode_cursor(&CREATED, (SortKey::Int(1_700_000_000), 7));
+        let err = query(Some(cursor), None, None).keyset(&CREATED);
+        assert_eq!(err.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let by_id = Order::asc("id");
+        let cursor = encode_cursor(&by_id, (SortKey::Time(time), 7));
+        let err = query(Some(cursor), None, Some("id")).keyset(&by_id);
+        assert_eq!(err.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let cursor = encode_cursor(&by_id, (SortKey::Int(7), 7));
+        assert!(query(Some(cursor), None, Some("id")).keyset(&by_id).is_ok());
+    }
+}
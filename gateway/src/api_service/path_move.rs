@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use common::errors::MegaError;
+use jupiter::storage::path_redirect_storage::PathRedirectStorage;
+use storage::driver::database::storage::ObjectStorage;
+
+/// Where CODEOWNERS files are looked for, relative to the repository root.
+pub const CODEOWNERS_PATHS: &[&str] = &["CODEOWNERS", ".github/CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Normalize a monorepo path to the `/a/b` form, `None` for paths with `.` or `..` components.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let mut normalized = String::new();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        if component == "." || component == ".." {
+            return None;
+        }
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// `path` relative to `base`, both normalized. `Some("")` when they are equal and `None` when
+/// `path` is not below `base`.
+pub fn relative_to<'a>(path: &'a str, base: &str) -> Option<&'a str> {
+    if base == "/" {
+        return Some(path.trim_start_matches('/'));
+    }
+    match path.strip_prefix(base)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('/'),
+    }
+}
+
+/// `path` with its leading `from` replaced by `to`, `None` when `path` is not below `from`.
+pub fn rebase_path(path: &str, from: &str, to: &str) -> Option<String> {
+    match relative_to(path, from)? {
+        "" => Some(to.to_owned()),
+        rest => Some(format!("{}/{}", to, rest)),
+    }
+}
+
+/// Looks up where requests for moved paths should go.
+#[derive(Clone)]
+pub struct PathRedirects {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub redirect_storage: PathRedirectStorage,
+}
+
+impl PathRedirects {
+    /// The path `path` was moved to, `None` if it was not moved.
+    ///
+    /// A repository or directory created at the old path since takes it over again, so the
+    /// redirect is ignored then.
+    pub async fn moved_to(&self, path: &str) -> Result<Option<String>, MegaError> {
+        let Some(path) = normalize_path(path) else {
+            return Ok(None);
+        };
+        let Some(redirect) = self.redirect_storage.find_redirect(&path).await? else {
+            return Ok(None);
+        };
+        if !self.storage.get_refs_under_path(&path).await?.is_empty()
+            || self
+                .storage
+                .get_directory_by_full_path(&path)
+                .await?
+                .is_some()
+        {
+            return Ok(None);
+        }
+        Ok(rebase_path(&path, &redirect.from_path, &redirect.to_path))
+    }
+}
+
+/// Rewrite the CODEOWNERS patterns naming `from` or a path below it to use `to` instead, both
+/// relative to the repository root. Returns `None` when no pattern refers to the moved path.
+pub fn rewrite_codeowners(content: &str, from: &str, to: &str) -> Option<String> {
+    let mut changed = false;
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        match rewrite_codeowners_line(line, from, to) {
+            Some(line) => {
+                changed = true;
+                result.push_str(&line);
+            }
+            None => result.push_str(line),
+        }
+    }
+    changed.then_some(result)
+}
+
+fn rewrite_codeowners_line(line: &str, from: &str, to: &str) -> Option<String> {
+    let start = line.len() - line.trim_start().len();
+    let end = line[start..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |i| start + i);
+    let pattern = &line[start..end];
+    if pattern.starts_with('#') {
+        return None;
+    }
+    let (root, path) = match pattern.strip_prefix('/') {
+        Some(path) => ("/", path),
+        None => ("", pattern),
+    };
+    // without a slash before the last component a pattern matches at any depth, wherever the
+    // directory lives
+    if root.is_empty() && !path.trim_end_matches('/').contains('/') {
+        return None;
+    }
+    let suffix = path.strip_prefix(from)?;
+    if !suffix.is_empty() && !suffix.starts_with('/') {
+        return None;
+    }
+    Some(format!(
+        "{}{}{}{}{}",
+        &line[..start],
+        root,
+        to,
+        suffix,
+        &line[end..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_path, rebase_path, relative_to, rewrite_codeowners};
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("projects//mega/").as_deref(),
+            Some("/projects/mega")
+        );
+        assert_eq!(normalize_path("/").as_deref(), Some("/"));
+        assert_eq!(normalize_path("/projects/../etc"), None);
+    }
+
+    #[test]
+    fn test_relative_to() {
+        assert_eq!(
+            relative_to("/projects/mega/src", "/projects"),
+            Some("mega/src")
+        );
+        assert_eq!(relative_to("/projects", "/projects"), Some(""));
+        assert_eq!(relative_to("/projects-old/mega", "/projects"), None);
+        assert_eq!(relative_to("/projects/mega", "/"), Some("projects/mega"));
+    }
+
+    #[test]
+    fn test_rebase_path() {
+        assert_eq!(
+            rebase_path("/projects/old/src", "/projects/old", "/libs/new").as_deref(),
+            Some("/libs/new/src")
+        );
+        assert_eq!(
+            rebase_path("/projects/old", "/projects/old", "/new").as_deref(),
+            Some("/new")
+        );
+        assert_eq!(
+            rebase_path("/projects/older", "/projects/old", "/new"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rewrite_codeowners() {
+        let content = "# owners\n/libs/net/ @net\nlibs/net/**/*.rs @rust\n/libs/network @other\nnet/ @anywhere\n*.md @docs\n";
+        assert_eq!(
+            rewrite_codeowners(content, "libs/net", "core/net").as_deref(),
+            Some("# owners\n/core/net/ @net\ncore/net/**/*.rs @rust\n/libs/network @other\nnet/ @anywhere\n*.md @docs\n")
+        );
+        assert_eq!(
+            rewrite_codeowners("*.md @docs", "libs/net", "core/net"),
+            None
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_path_redirect;
+use entity::repo_directory;
+use jupiter::storage::path_redirect_storage::PathRedirectStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::signature::{Signature, SignatureType};
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::merge::{edit_tree, Merger};
+use crate::api_service::mr_service::DEFAULT_COMMITTER;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::path_move::{self, CODEOWNERS_PATHS};
+use crate::api_service::ref_update::RefUpdater;
+use crate::model::path_move::{PathMove, PathMoveResult, PathRedirect};
+
+/// Moves directories of the monorepo and keeps redirects from their old paths.
+#[derive(Clone)]
+pub struct PathMoveService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub redirect_storage: PathRedirectStorage,
+    pub ref_updater: RefUpdater,
+}
+
+/// A move inside the tree of one repository, computed but not committed yet.
+struct TreeMove {
+    repo_path: String,
+    ref_name: String,
+    head: SHA1,
+    tree_id: SHA1,
+    message: String,
+    codeowners: Vec<String>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn check_paths(from: &str, to: &str) -> Result<(String, String), (StatusCode, String)> {
+    let normalize = |path: &str| {
+        path_move::normalize_path(path)
+            .filter(|p| p != "/")
+            .ok_or_else(|| bad_request(format!("invalid path: {}", path)))
+    };
+    let (from, to) = (normalize(from)?, normalize(to)?);
+    if path_move::relative_to(&to, &from).is_some() || path_move::relative_to(&from, &to).is_some()
+    {
+        return Err(bad_request(format!(
+            "{} and {} must not contain each other",
+            from, to
+        )));
+    }
+    Ok((from, to))
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split('/').filter(|c| !c.is_empty()).collect()
+}
+
+/// Fail unless no repository or directory exists at `path`.
+pub(crate) async fn ensure_free(
+    storage: &dyn ObjectStorage,
+    path: &str,
+) -> Result<(), (StatusCode, String)> {
+    let refs = storage
+        .get_refs_under_path(path)
+        .await
+        .map_err(internal_error)?;
+    let directory = storage
+        .get_directory_by_full_path(path)
+        .await
+        .map_err(internal_error)?;
+    if !refs.is_empty() || directory.is_some() {
+        return Err((StatusCode::CONFLICT, format!("{} already exists", path)));
+    }
+    Ok(())
+}
+
+impl PathMoveService {
+    pub async fn list_redirects(&self) -> Result<Json<Vec<PathRedirect>>, (StatusCode, String)> {
+        let redirects = self
+            .redirect_storage
+            .list_redirects()
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(
+            redirects.into_iter().map(PathRedirect::from).collect(),
+        ))
+    }
+
+    /// Move the directory at `request.from` to `request.to`.
+    ///
+    /// If a repository contains the directory in its tree, the move is committed to its default
+    /// branch. Repositories at or below the old path are moved along with their refs and
+    /// history. Either way the old path is redirected to the new one afterwards.
+    pub async fn move_path(
+        &self,
+        request: PathMove,
+    ) -> Result<Json<PathMoveResult>, (StatusCode, String)> {
+        let (from, to) = check_paths(&request.from, &request.to)?;
+        let committer = Signature {
+            signature_type: SignatureType::Committer,
+            name: request
+                .committer_name
+                .unwrap_or_else(|| DEFAULT_COMMITTER.0.to_owned()),
+            email: request
+                .committer_email
+                .unwrap_or_else(|| DEFAULT_COMMITTER.1.to_owned()),
+            timestamp: chrono::Utc::now().timestamp() as usize,
+            timezone: "+0000".to_owned(),
+        };
+
+        let mut merger = Merger::new(self.storage.clone());
+        let tree_move = self
+            .plan_tree_move(&mut merger, &from, &to, request.message)
+            .await?;
+        let moved_repos: BTreeSet<String> = self
+            .storage
+            .get_refs_under_path(&from)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|r| r.repo_path)
+            .collect();
+        let directory = self
+            .storage
+            .get_directory_by_full_path(&from)
+            .await
+            .map_err(internal_error)?;
+        let move_repos = !moved_repos.is_empty() || directory.is_some();
+        if tree_move.is_none() && !move_repos {
+            return Err((StatusCode::NOT_FOUND, format!("{} not found", from)));
+        }
+        if move_repos {
+            ensure_free(self.storage.as_ref(), &to).await?;
+        }
+
+        let mut result = PathMoveResult {
+            from: from.clone(),
+            to: to.clone(),
+            repo_path: None,
+            commit_id: None,
+            moved_repos: moved_repos.into_iter().collect(),
+            codeowners: Vec::new(),
+        };
+        if let Some(tree_move) = tree_move {
+            let commit_id = merger
+                .commit(
+                    &tree_move.repo_path,
+                    tree_move.tree_id,
+                    vec![tree_move.head],
+                    committer.clone(),
+                    &tree_move.message,
+                )
+                .await?;
+            let current = ObjectLoader::new(self.storage.clone())
+                .resolve_ref(&tree_move.repo_path, Some(&tree_move.ref_name))
+                .await?;
+            if current != tree_move.head {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("{} was updated during the move, retry", tree_move.ref_name),
+                ));
+            }
+            self.ref_updater
+                .update(
+                    &tree_move.repo_path,
+                    &tree_move.ref_name,
+                    Some(&tree_move.head),
+                    &commit_id,
+                    &committer.name,
+                    &format!("move {} to {}", from, to),
+                )
+                .await?;
+            result.repo_path = Some(tree_move.repo_path);
+            result.commit_id = Some(commit_id.to_plain_str());
+            result.codeowners = tree_move.codeowners;
+        }
+        if move_repos {
+            let parent = to.rsplit_once('/').map_or("/", |(parent, _)| parent);
+            let pid = self.ensure_directory(parent).await?;
+            self.storage
+                .move_repo_path(&from, &to, pid)
+                .await
+                .map_err(internal_error)?;
+        }
+
+        self.redirect_storage
+            .record_move(mega_path_redirect::Model {
+                id: generate_id(),
+                from_path: from,
+                to_path: to,
+                commit_id: result.commit_id.clone(),
+                actor: committer.name,
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(result))
+    }
+
+    /// Build the tree of the repository containing `from` with the directory moved, `None` when
+    /// no repository tree has an entry at `from`.
+    async fn plan_tree_move(
+        &self,
+        merger: &mut Merger,
+        from: &str,
+        to: &str,
+        message: Option<String>,
+    ) -> Result<Option<TreeMove>, (StatusCode, String)> {
+        let refs = self
+            .storage
+            .search_refs(from)
+            .await
+            .map_err(internal_error)?;
+        let Some(repo_path) = refs
+            .into_iter()
+            .map(|r| r.repo_path)
+            .filter(|p| path_move::relative_to(from, p).is_some_and(|rel| !rel.is_empty()))
+            .max_by_key(String::len)
+        else {
+            return Ok(None);
+        };
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let (ref_name, head) = loader.default_branch(&repo_path).await?;
+        let root = loader.commit(&head).await?.tree_id;
+
+        let from_rel = path_move::relative_to(from, &repo_path).unwrap_or_default();
+        let Some(entry) = loader.find_path(&root, from_rel).await? else {
+            return Ok(None);
+        };
+        let to_rel = match path_move::relative_to(to, &repo_path) {
+            Some(rel) => rel,
+            None => {
+                return Err(bad_request(format!(
+                    "{} is part of {}, it can only move within that repository",
+                    from, repo_path
+                )))
+            }
+        };
+        if loader.find_path(&root, to_rel).await?.is_some() {
+            return Err((StatusCode::CONFLICT, format!("{} already exists", to)));
+        }
+
+        let removed =
+            edit_tree(&mut loader, merger, Some(root), &components(from_rel), None).await?;
+        let mut tree_id = edit_tree(
+            &mut loader,
+            merger,
+            removed,
+            &components(to_rel),
+            Some(entry),
+        )
+        .await?
+        .ok_or_else(|| internal_error("moved tree is empty"))?;
+
+        let mut codeowners = Vec::new();
+        for path in CODEOWNERS_PATHS {
+            let Some(file) = loader.find_path(&tree_id, path).await? else {
+                continue;
+            };
+            if file.mode == TreeItemMode::Tree {
+                continue;
+            }
+            let Ok(content) = String::from_utf8(loader.blob(&file.id).await?) else {
+                continue;
+            };
+            let Some(rewritten) = path_move::rewrite_codeowners(&content, from_rel, to_rel) else {
+                continue;
+            };
+            let blob_id = merger.write_blob(rewritten.into_bytes());
+            let item = TreeItem::new(file.mode, blob_id, String::new());
+            tree_id = edit_tree(
+                &mut loader,
+                merger,
+                Some(tree_id),
+                &components(path),
+                Some(item),
+            )
+            .await?
+            .ok_or_else(|| internal_error("tree is empty"))?;
+            codeowners.push(path.to_string());
+        }
+
+        Ok(Some(TreeMove {
+            repo_path,
+            ref_name,
+            head,
+            tree_id,
+            message: message.unwrap_or_else(|| format!("Move {} to {}", from_rel, to_rel)),
+            codeowners,
+        }))
+    }
+
+    /// Id of the directory entry at `path`, creating it and its parents when missing.
+    async fn ensure_directory(&self, path: &str) -> Result<i32, (StatusCode, String)> {
+        let mut pid = match self
+            .storage
+            .get_directory_by_full_path("/")
+            .await
+            .map_err(internal_error)?
+        {
+            Some(root) => root.id,
+            None => self
+                .storage
+                .save_directory(repo_directory::new(0, "root", "/"))
+                .await
+                .map_err(internal_error)?,
+        };
+        let mut current = String::new();
+        for name in components(path) {
+            current.push('/');
+            current.push_str(name);
+            pid = match self
+                .storage
+                .get_directory_by_full_path(&current)
+                .await
+                .map_err(internal_error)?
+            {
+                Some(dir) => dir.id,
+                None => self
+                    .storage
+                    .save_directory(repo_directory::new(pid, name, &current))
+                    .await
+                    .map_err(internal_error)?,
+            };
+        }
+        Ok(pid)
+    }
+}
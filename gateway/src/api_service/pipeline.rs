@@ -0,0 +1,384 @@
+//! Pipelines the server runs for the commits pushed to branches, configured by the `.mega/ci.toml`
+//! file of the commit:
+//!
+//! ```toml
+//! # defaults of the jobs
+//! image = "rust:1.77"
+//! timeout = 1800
+//!
+//! [[jobs]]
+//! name = "test"
+//! run = "cargo test"
+//! # only for these branches, a trailing `*` matching any rest; every branch when left out
+//! branches = ["main", "release/*"]
+//! ```
+//!
+//! Each job runs its `run` script with `sh` in a checkout of the commit, in the [`Runner`]
+//! `MEGA_CI_RUNNER` names: `shell` runs it on the server itself, `container` in a container of
+//! its `image`, see [`runner_from_env`].
+//!
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Where a commit configures its pipeline.
+pub const CONFIG_PATH: &str = ".mega/ci.toml";
+
+/// Most jobs of a pipeline.
+const MAX_JOBS: usize = 20;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 60 * 60;
+const MAX_TIMEOUT_SECS: u64 = 6 * 60 * 60;
+
+const DEFAULT_CONTAINER_CLI: &str = "docker";
+const DEFAULT_IMAGE: &str = "alpine:3.19";
+
+/// How long the output a job's script left to background processes is waited for once the
+/// script exited.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineFile {
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    timeout: Option<u64>,
+    #[serde(default)]
+    jobs: Vec<JobFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    name: String,
+    run: String,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    timeout: Option<u64>,
+    #[serde(default)]
+    branches: Vec<String>,
+}
+
+/// A job of a pipeline, with the defaults of the file applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobSpec {
+    pub name: String,
+    pub script: String,
+    pub image: Option<String>,
+    pub timeout: Duration,
+    pub branches: Vec<String>,
+}
+
+impl JobSpec {
+    /// Whether the job runs for the commits pushed to `branch`.
+    pub fn runs_on(&self, branch: &str) -> bool {
+        self.branches.is_empty()
+            || self
+                .branches
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => branch.starts_with(prefix),
+                    None => pattern == branch,
+                })
+    }
+}
+
+/// The jobs of a pipeline file, or why it is invalid.
+pub fn parse_config(text: &str) -> Result<Vec<JobSpec>, String> {
+    let file: PipelineFile =
+        toml::from_str(text).map_err(|e| format!("invalid {}: {}", CONFIG_PATH, e))?;
+    if file.jobs.len() > MAX_JOBS {
+        return Err(format!("a pipeline has at most {} jobs", MAX_JOBS));
+    }
+    let mut names = HashSet::new();
+    let mut jobs = Vec::with_capacity(file.jobs.len());
+    for job in file.jobs {
+        let valid_name = !job.name.is_empty()
+            && job.name.len() <= 100
+            && job
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !valid_name {
+            return Err(format!(
+                "invalid job name {:?}, use up to 100 letters, digits, `-`, `_` and `.`",
+                job.name
+            ));
+        }
+        if !names.insert(job.name.clone()) {
+            return Err(format!("job {} is defined twice", job.name));
+        }
+        if job.run.trim().is_empty() {
+            return Err(format!("job {} has nothing to run", job.name));
+        }
+        let timeout = job.timeout.or(file.timeout).unwrap_or(DEFAULT_TIMEOUT_SECS);
+        if timeout == 0 || timeout > MAX_TIMEOUT_SECS {
+            return Err(format!(
+                "timeout of job {} must be between 1 and {} seconds",
+                job.name, MAX_TIMEOUT_SECS
+            ));
+        }
+        jobs.push(JobSpec {
+            name: job.name,
+            script: job.run,
+            image: job.image.or(file.image.clone()),
+            timeout: Duration::from_secs(timeout),
+            branches: job.branches,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Receives what a job prints, as it prints it.
+pub type Output = mpsc::Sender<Vec<u8>>;
+
+/// Where the jobs of pipelines run.
+#[async_trait]
+pub trait Runner: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Run the script of `job` in `workdir`, a checkout of its commit, with the variables of
+    /// `env`, sending its output to `output`. Returns the exit code of the script, or why it
+    /// couldn't be run or didn't finish in time.
+    async fn run(
+        &self,
+        job: &JobSpec,
+        workdir: &Path,
+        env: &[(String, String)],
+        output: Output,
+    ) -> Result<i32, String>;
+}
+
+/// Runs jobs with `sh` on the server, as the user of the server. Only for servers running the
+/// pipelines of trusted repositories.
+pub struct ShellRunner;
+
+#[async_trait]
+impl Runner for ShellRunner {
+    fn name(&self) -> &'static str {
+        "shell"
+    }
+
+    async fn run(
+        &self,
+        job: &JobSpec,
+        workdir: &Path,
+        env: &[(String, String)],
+        output: Output,
+    ) -> Result<i32, String> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&job.script)
+            .current_dir(workdir)
+            // the settings of the server are not for the jobs to see
+            .env_clear()
+            .envs(
+                ["PATH", "HOME", "LANG"]
+                    .into_iter()
+                    .filter_map(|name| Some((name, std::env::var_os(name)?))),
+            )
+            .envs(env.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        run_command(command, job.timeout, output).await
+    }
+}
+
+/// Runs jobs in a container of their image, the checkout mounted at `/workspace`, with the
+/// `docker` command line or a compatible one named by `MEGA_CI_CONTAINER_CLI`, like `podman`.
+/// Jobs without an image use `MEGA_CI_IMAGE`.
+pub struct ContainerRunner {
+    pub cli: String,
+    pub default_image: String,
+}
+
+#[async_trait]
+impl Runner for ContainerRunner {
+    fn name(&self) -> &'static str {
+        "container"
+    }
+
+    async fn run(
+        &self,
+        job: &JobSpec,
+        workdir: &Path,
+        env: &[(String, String)],
+        output: Output,
+    ) -> Result<i32, String> {
+        let container = format!(
+            "mega-ci-{}",
+            workdir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        );
+        let mut command = Command::new(&self.cli);
+        command
+            .args(["run", "--rm", "--name", &container])
+            .arg("-v")
+            .arg(format!("{}:/workspace", workdir.display()))
+            .args(["-w", "/workspace"]);
+        for (name, value) in env {
+            command.arg("-e").arg(format!("{}={}", name, value));
+        }
+        command
+            .arg(job.image.as_deref().unwrap_or(&self.default_image))
+            .args(["sh", "-c", &job.script]);
+        let result = run_command(command, job.timeout, output).await;
+        if result.is_err() {
+            // killing the command line leaves the container running
+            let _ = Command::new(&self.cli)
+                .args(["rm", "-f", &container])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await;
+        }
+        result
+    }
+}
+
+/// Run `command` for at most `timeout`, sending what it prints on stdout and stderr to `output`.
+async fn run_command(
+    mut command: Command,
+    timeout: Duration,
+    output: Output,
+) -> Result<i32, String> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("unable to start the job: {}", e))?;
+    let mut forwards: Vec<JoinHandle<()>> = [
+        child.stdout.take().map(|out| forward(out, output.clone())),
+        child.stderr.take().map(|err| forward(err, output.clone())),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    drop(output);
+
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status.map_err(|e| format!("unable to wait for the job: {}", e))?,
+        Err(_) => {
+            let _ = child.kill().await;
+            forwards.iter().for_each(JoinHandle::abort);
+            return Err(format!("timed out after {} seconds", timeout.as_secs()));
+        }
+    };
+    let drain = async {
+        for forward in forwards.iter_mut() {
+            let _ = forward.await;
+        }
+    };
+    if tokio::time::timeout(DRAIN_TIMEOUT, drain).await.is_err() {
+        forwards.iter().for_each(JoinHandle::abort);
+    }
+    // killed by a signal
+    Ok(status.code().unwrap_or(-1))
+}
+
+fn forward(mut reader: impl AsyncRead + Unpin + Send + 'static, output: Output) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut buf = vec![0; 8 * 1024];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    if output.send(buf[..n].to_vec()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// The runner `MEGA_CI_RUNNER` names, `shell` or `container`. Pipelines are not run without
+/// one.
+pub fn runner_from_env() -> Option<Arc<dyn Runner>> {
+    let name = std::env::var("MEGA_CI_RUNNER").ok()?;
+    match name.trim() {
+        "" => None,
+        "shell" => Some(Arc::new(ShellRunner)),
+        "container" => Some(Arc::new(ContainerRunner {
+            cli: std::env::var("MEGA_CI_CONTAINER_CLI")
+                .ok()
+                .filter(|cli| !cli.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_CONTAINER_CLI.to_owned()),
+            default_image: std::env::var("MEGA_CI_IMAGE")
+                .ok()
+                .filter(|image| !image.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_IMAGE.to_owned()),
+        })),
+        other => {
+            tracing::warn!(
+                "unknown MEGA_CI_RUNNER {}, expected shell or container; pipelines are disabled",
+                other
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_config;
+
+    #[test]
+    fn test_parse_config() {
+        let jobs = parse_config(
+            r#"
+            image = "rust:1.77"
+            timeout = 600
+
+            [[jobs]]
+            name = "test"
+            run = "cargo test"
+            branches = ["main", "release/*"]
+
+            [[jobs]]
+            name = "lint"
+            run = "cargo clippy"
+            image = "rust:nightly"
+            timeout = 60
+            "#,
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].image.as_deref(), Some("rust:1.77"));
+        assert_eq!(jobs[0].timeout, Duration::from_secs(600));
+        assert_eq!(jobs[1].image.as_deref(), Some("rust:nightly"));
+        assert_eq!(jobs[1].timeout, Duration::from_secs(60));
+
+        assert!(jobs[0].runs_on("main"));
+        assert!(jobs[0].runs_on("release/1.0"));
+        assert!(!jobs[0].runs_on("mainline"));
+        assert!(jobs[1].runs_on("feature/x"));
+
+        assert!(parse_config("").unwrap().is_empty());
+        assert!(parse_config("[[jobs]]\nname = \"a b\"\nrun = \"true\"").is_err());
+        assert!(parse_config("[[jobs]]\nname = \"a\"\nrun = \" \"").is_err());
+        assert!(parse_config("[[jobs]]\nname = \"a\"\nrun = \"true\"\ntimeout = 0").is_err());
+        assert!(parse_config("[[jobs]]\nname = \"a\"\nrun = \"true\"\nrunner = \"x\"").is_err());
+        assert!(parse_config(
+            "[[jobs]]\nname = \"a\"\nrun = \"true\"\n[[jobs]]\nname = \"a\"\nrun = \"false\""
+        )
+        .is_err());
+    }
+}
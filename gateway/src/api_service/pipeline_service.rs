@@ -0,0 +1,480 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use axum::Json;
+use futures::future::BoxFuture;
+use tokio::sync::{mpsc, Semaphore};
+
+use common::utils::generate_id;
+use db_entity::mega_ci_job;
+use jupiter::storage::ci_job_storage::{
+    CiJobStorage, CI_JOB_ERROR, CI_JOB_FAILURE, CI_JOB_QUEUED, CI_JOB_SUCCESS,
+};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::tree::TreeItemMode;
+
+use crate::api_service::check_service::CheckService;
+use crate::api_service::ci_log_service::CiLogService;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::pipeline::{self, JobSpec, Runner, CONFIG_PATH};
+use crate::api_service::ref_hook::{RefChange, RefHook};
+use crate::model::check::{NewCheckRun, CHECK_ERROR, CHECK_FAILURE, CHECK_PENDING, CHECK_SUCCESS};
+use crate::model::ci_log::CiJob;
+
+/// How often the runner looks for queued jobs.
+const RUNNER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Most jobs claimed in one look.
+const BATCH_SIZE: u64 = 10;
+
+/// Attempts at a job interrupted by a stop of its server instance before it fails for good.
+const MAX_ATTEMPTS: i32 = 3;
+
+/// How long past its timeout a job is held by the server instance running it. A job still
+/// running after that is taken to be interrupted and run again.
+const LEASE_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Output of a job is written to its log once this much is waiting, or this long after it was
+/// printed.
+const LOG_CHUNK: usize = 64 * 1024;
+const LOG_FLUSH: Duration = Duration::from_secs(1);
+
+const DEFAULT_CONCURRENCY: usize = 2;
+
+/// Who the check runs of pipelines are reported by.
+const REPORTER: &str = "mega-ci";
+
+/// The check a pipeline reports for a commit whose `.mega/ci.toml` is invalid.
+const CONFIG_CHECK: &str = "ci";
+
+/// Runs the pipelines configured in `.mega/ci.toml` for the commits pushed to branches.
+///
+/// A push queues the jobs of its commit in `mega_ci_job`, which any server instance picks up,
+/// so a job queued before a restart still runs. Each job writes its output to the CI log of the
+/// commit and reports its state as the check run `ci/<job>`, which branches can require.
+#[derive(Clone)]
+pub struct PipelineService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub job_storage: CiJobStorage,
+    pub ci_logs: CiLogService,
+    pub checks: CheckService,
+    /// Pipelines are not run without one, see [`pipeline::runner_from_env`]
+    pub runner: Option<Arc<dyn Runner>>,
+    /// Jobs running at once, `MEGA_CI_CONCURRENCY`
+    pub slots: Arc<Semaphore>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn after(delay: Duration) -> chrono::NaiveDateTime {
+    chrono::Utc::now().naive_utc() + chrono::Duration::from_std(delay).unwrap_or_default()
+}
+
+/// Jobs running at once, unless `MEGA_CI_CONCURRENCY` says otherwise.
+pub fn concurrency() -> usize {
+    std::env::var("MEGA_CI_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_CONCURRENCY)
+}
+
+/// Where the checkouts of running jobs are made, `MEGA_CI_WORKDIR` or the temporary directory.
+fn work_root() -> PathBuf {
+    std::env::var_os("MEGA_CI_WORKDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("mega-ci"))
+}
+
+fn check_name(job: &str) -> String {
+    format!("ci/{}", job)
+}
+
+/// Write the tree `tree_id` to `dir`. Submodules are left as empty directories.
+fn checkout<'a>(
+    loader: &'a mut ObjectLoader,
+    tree_id: SHA1,
+    dir: PathBuf,
+) -> BoxFuture<'a, Result<(), String>> {
+    Box::pin(async move {
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+        let tree = loader.tree(&tree_id).await.map_err(|(_, e)| e)?;
+        for item in tree.tree_items {
+            if item.name.is_empty() || item.name == "." || item.name == ".." {
+                return Err(format!("invalid tree entry {:?}", item.name));
+            }
+            let path = dir.join(&item.name);
+            match item.mode {
+                TreeItemMode::Tree => checkout(loader, item.id, path).await?,
+                TreeItemMode::Commit => tokio::fs::create_dir_all(&path)
+                    .await
+                    .map_err(|e| e.to_string())?,
+                TreeItemMode::Link => {
+                    let target = loader.blob(&item.id).await.map_err(|(_, e)| e)?;
+                    let target = String::from_utf8_lossy(&target).into_owned();
+                    #[cfg(unix)]
+                    tokio::fs::symlink(&target, &path)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    #[cfg(not(unix))]
+                    tokio::fs::write(&path, target)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                TreeItemMode::Blob | TreeItemMode::BlobExecutable => {
+                    let data = loader.blob(&item.id).await.map_err(|(_, e)| e)?;
+                    tokio::fs::write(&path, data)
+                        .await
+                        .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+                    #[cfg(unix)]
+                    if item.mode == TreeItemMode::BlobExecutable {
+                        use std::os::unix::fs::PermissionsExt;
+                        tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                            .await
+                            .map_err(|e| e.to_string())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+impl PipelineService {
+    pub async fn list_jobs(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+    ) -> Result<Json<Vec<CiJob>>, (StatusCode, String)> {
+        let jobs = self
+            .job_storage
+            .list_jobs(repo_path, &commit_id.to_lowercase())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(jobs.into_iter().map(CiJob::from).collect()))
+    }
+
+    /// Queue the jobs of the pipeline of the commit a branch was pushed to, the ones the branch
+    /// runs. An invalid pipeline file is reported as the failed check `ci`.
+    pub async fn enqueue(&self, change: &RefChange) -> Result<(), String> {
+        let (Some(branch), Some(after)) = (
+            change.ref_name.strip_prefix("refs/heads/"),
+            change.after.as_deref(),
+        ) else {
+            return Ok(());
+        };
+        let commit_id = SHA1::from_str(after).map_err(|e| e.to_string())?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let commit = loader.commit(&commit_id).await.map_err(|(_, e)| e)?;
+        let Some(config) = loader
+            .find_path(&commit.tree_id, CONFIG_PATH)
+            .await
+            .map_err(|(_, e)| e)?
+            .filter(|item| item.mode == TreeItemMode::Blob)
+        else {
+            return Ok(());
+        };
+        let data = loader.blob(&config.id).await.map_err(|(_, e)| e)?;
+        let jobs = match std::str::from_utf8(&data)
+            .map_err(|e| format!("{} is not UTF-8: {}", CONFIG_PATH, e))
+            .and_then(pipeline::parse_config)
+        {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                self.report(&change.repo_path, after, CONFIG_CHECK, CHECK_FAILURE, &e)
+                    .await;
+                return Ok(());
+            }
+        };
+
+        let now = chrono::Utc::now().naive_utc();
+        let jobs = jobs
+            .into_iter()
+            .filter(|job| job.runs_on(branch))
+            .map(|job| mega_ci_job::Model {
+                id: generate_id(),
+                repo_path: change.repo_path.clone(),
+                ref_name: change.ref_name.clone(),
+                commit_id: after.to_owned(),
+                name: job.name,
+                script: job.script,
+                image: job.image,
+                timeout_secs: job.timeout.as_secs() as i32,
+                status: CI_JOB_QUEUED.to_owned(),
+                attempts: 0,
+                next_attempt_at: Some(now),
+                exit_code: None,
+                created_at: now,
+                updated_at: now,
+                finished_at: None,
+            })
+            .collect();
+        let queued = self
+            .job_storage
+            .enqueue(jobs)
+            .await
+            .map_err(|e| e.to_string())?;
+        for job in queued {
+            self.report(
+                &job.repo_path,
+                &job.commit_id,
+                &check_name(&job.name),
+                CHECK_PENDING,
+                "queued",
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Run queued jobs every [`RUNNER_INTERVAL`], as many at once as there are slots, when
+    /// there is a runner.
+    pub fn start_runner(self) {
+        if self.runner.is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RUNNER_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.run_due().await {
+                    tracing::warn!("unable to run ci jobs: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn run_due(&self) -> Result<(), String> {
+        let due = self
+            .job_storage
+            .due_jobs(chrono::Utc::now().naive_utc(), BATCH_SIZE)
+            .await
+            .map_err(|e| e.to_string())?;
+        for job in due {
+            if job.attempts >= MAX_ATTEMPTS {
+                self.finish(&job, CI_JOB_ERROR, None, "interrupted too many times")
+                    .await;
+                continue;
+            }
+            let permit = self.slots.clone().acquire_owned().await.unwrap();
+            let lease = Duration::from_secs(job.timeout_secs as u64) + LEASE_MARGIN;
+            if !self
+                .job_storage
+                .claim_job(&job, after(lease))
+                .await
+                .map_err(|e| e.to_string())?
+            {
+                continue;
+            }
+            let service = self.clone();
+            tokio::spawn(async move {
+                service.run_job(job).await;
+                drop(permit);
+            });
+        }
+        Ok(())
+    }
+
+    /// Run a claimed job in a fresh checkout of its commit and record how it went.
+    async fn run_job(&self, job: mega_ci_job::Model) {
+        let Some(runner) = self.runner.clone() else {
+            return;
+        };
+        self.report(
+            &job.repo_path,
+            &job.commit_id,
+            &check_name(&job.name),
+            CHECK_PENDING,
+            "running",
+        )
+        .await;
+        let workdir = work_root().join(format!("{}-{}", job.id, job.attempts + 1));
+        let result = self.execute(runner.as_ref(), &job, &workdir).await;
+        if let Err(e) = tokio::fs::remove_dir_all(&workdir).await {
+            tracing::warn!("unable to remove {}: {}", workdir.display(), e);
+        }
+        match result {
+            Ok(0) => self.finish(&job, CI_JOB_SUCCESS, Some(0), "passed").await,
+            Ok(code) => {
+                let description = format!("exited with {}", code);
+                self.finish(&job, CI_JOB_FAILURE, Some(code), &description)
+                    .await
+            }
+            Err(e) => {
+                self.append_log(&job, format!("\n{}\n", e).into_bytes())
+                    .await;
+                self.finish(&job, CI_JOB_ERROR, None, &e).await
+            }
+        }
+    }
+
+    async fn execute(
+        &self,
+        runner: &dyn Runner,
+        job: &mega_ci_job::Model,
+        workdir: &Path,
+    ) -> Result<i32, String> {
+        let commit_id = SHA1::from_str(&job.commit_id).map_err(|e| e.to_string())?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let commit = loader.commit(&commit_id).await.map_err(|(_, e)| e)?;
+        if tokio::fs::try_exists(workdir).await.unwrap_or(false) {
+            tokio::fs::remove_dir_all(workdir)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        checkout(&mut loader, commit.tree_id, workdir.to_owned()).await?;
+
+        let spec = JobSpec {
+            name: job.name.clone(),
+            script: job.script.clone(),
+            image: job.image.clone(),
+            timeout: Duration::from_secs(job.timeout_secs as u64),
+            branches: Vec::new(),
+        };
+        let env = [
+            ("CI", "true"),
+            ("MEGA_REPO_PATH", job.repo_path.as_str()),
+            ("MEGA_REF", job.ref_name.as_str()),
+            ("MEGA_COMMIT", job.commit_id.as_str()),
+            ("MEGA_JOB", job.name.as_str()),
+        ]
+        .map(|(name, value)| (name.to_owned(), value.to_owned()));
+        self.append_log(
+            job,
+            format!("$ {} runner, attempt {}\n", runner.name(), job.attempts + 1).into_bytes(),
+        )
+        .await;
+        let (output, received) = mpsc::channel(64);
+        let (result, _) = tokio::join!(
+            runner.run(&spec, workdir, &env, output),
+            self.write_log(job, received)
+        );
+        result
+    }
+
+    /// Write the output of a job to its log until the job closes `output`.
+    async fn write_log(&self, job: &mega_ci_job::Model, mut output: mpsc::Receiver<Vec<u8>>) {
+        let mut pending = Vec::new();
+        loop {
+            let next = tokio::time::timeout(LOG_FLUSH, output.recv()).await;
+            let done = matches!(next, Ok(None));
+            if let Ok(Some(chunk)) = next {
+                pending.extend_from_slice(&chunk);
+                if pending.len() < LOG_CHUNK {
+                    continue;
+                }
+            }
+            if !pending.is_empty() {
+                self.append_log(job, std::mem::take(&mut pending)).await;
+            }
+            if done {
+                return;
+            }
+        }
+    }
+
+    async fn append_log(&self, job: &mega_ci_job::Model, data: Vec<u8>) {
+        if let Err((_, e)) = self
+            .ci_logs
+            .append(
+                &job.repo_path,
+                &job.commit_id,
+                &job.name,
+                None,
+                Bytes::from(data),
+            )
+            .await
+        {
+            tracing::warn!("unable to write the log of ci job {}: {}", job.id, e);
+        }
+    }
+
+    /// Record the final `status` of a job, in the queue, its log and its check run.
+    async fn finish(
+        &self,
+        job: &mega_ci_job::Model,
+        status: &str,
+        exit_code: Option<i32>,
+        description: &str,
+    ) {
+        if let Err(e) = self.job_storage.finish_job(job.id, status, exit_code).await {
+            tracing::warn!("unable to record the end of ci job {}: {}", job.id, e);
+        }
+        let log_status = if status == CI_JOB_SUCCESS {
+            "success"
+        } else {
+            "failure"
+        };
+        // a job that printed nothing has no log yet
+        self.append_log(job, Vec::new()).await;
+        if let Err((_, e)) = self
+            .ci_logs
+            .finish(&job.repo_path, &job.commit_id, &job.name, log_status)
+            .await
+        {
+            tracing::warn!("unable to finish the log of ci job {}: {}", job.id, e);
+        }
+        let check_status = match status {
+            CI_JOB_SUCCESS => CHECK_SUCCESS,
+            CI_JOB_FAILURE => CHECK_FAILURE,
+            _ => CHECK_ERROR,
+        };
+        self.report(
+            &job.repo_path,
+            &job.commit_id,
+            &check_name(&job.name),
+            check_status,
+            description,
+        )
+        .await;
+    }
+
+    async fn report(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        name: &str,
+        status: &str,
+        description: &str,
+    ) {
+        let run = NewCheckRun {
+            name: name.to_owned(),
+            status: status.to_owned(),
+            description: Some(description.to_owned()),
+            target_url: None,
+        };
+        if let Err((_, e)) = self
+            .checks
+            .report(repo_path, commit_id, run, REPORTER.to_owned())
+            .await
+        {
+            tracing::warn!("unable to report check {} of {}: {}", name, commit_id, e);
+        }
+    }
+}
+
+/// Queues the pipelines of the commits pushed to branches.
+pub struct PipelineHook {
+    pub service: PipelineService,
+}
+
+#[async_trait]
+impl RefHook for PipelineHook {
+    fn name(&self) -> &'static str {
+        "ci-pipelines"
+    }
+
+    async fn on_ref_update(&self, change: &RefChange) -> Result<(), String> {
+        self.service.enqueue(change).await
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::NaiveDate;
+
+use common::utils::generate_id;
+use db_entity::{mega_label, mega_milestone};
+use jupiter::storage::assignee_storage::AssigneeStorage;
+use jupiter::storage::label_storage::LabelStorage;
+use jupiter::storage::milestone_storage::MilestoneStorage;
+
+use crate::model::issue::{ISSUE_CLOSED, ISSUE_OPEN};
+use crate::model::planning::{
+    ItemLinks, Label, LabelUpdate, Milestone, MilestoneUpdate, NewLabel, NewMilestone,
+    PlanningQuery, MILESTONE_CLOSED, MILESTONE_OPEN,
+};
+
+const DEFAULT_LABEL_COLOR: &str = "#ededed";
+
+/// Labels, milestones and assignees of issues and merge requests.
+#[derive(Clone)]
+pub struct PlanningService {
+    pub label_storage: LabelStorage,
+    pub milestone_storage: MilestoneStorage,
+    pub assignee_storage: AssigneeStorage,
+}
+
+/// Trim names, dropping empty ones and repeats while keeping the order they were given in.
+pub fn normalize_names(names: Vec<String>) -> Vec<String> {
+    let mut result: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        let name = name.trim();
+        if !name.is_empty() && !result.iter().any(|n| n == name) {
+            result.push(name.to_owned());
+        }
+    }
+    result
+}
+
+/// Accept colors written as `#rgb` or `#rrggbb`, returned in the long lowercase form.
+fn normalize_color(color: &str) -> Option<String> {
+    let hex = color.trim().strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        3 => Some(format!(
+            "#{}",
+            hex.chars()
+                .flat_map(|c| [c, c])
+                .collect::<String>()
+                .to_lowercase()
+        )),
+        6 => Some(format!("#{}", hex.to_lowercase())),
+        _ => None,
+    }
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn check_label_name(name: &str) -> Result<(), (StatusCode, String)> {
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(bad_request(
+            "label name must have between 1 and 64 characters".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_color(color: &str) -> Result<String, (StatusCode, String)> {
+    normalize_color(color).ok_or_else(|| bad_request(format!("invalid color: {}", color)))
+}
+
+fn check_milestone_title(title: &str) -> Result<(), (StatusCode, String)> {
+    if title.is_empty() || title.chars().count() > 255 {
+        return Err(bad_request(
+            "milestone title must have between 1 and 255 characters".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_due_date(value: &str) -> Result<NaiveDate, (StatusCode, String)> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| bad_request(format!("invalid due date, expected YYYY-MM-DD: {}", value)))
+}
+
+fn check_milestone_state(state: &str) -> Result<(), (StatusCode, String)> {
+    if state != MILESTONE_OPEN && state != MILESTONE_CLOSED {
+        return Err(bad_request(format!("unknown milestone state: {}", state)));
+    }
+    Ok(())
+}
+
+impl PlanningService {
+    pub async fn list_labels(
+        &self,
+        query: PlanningQuery,
+    ) -> Result<Json<Vec<Label>>, (StatusCode, String)> {
+        let labels = self
+            .label_storage
+            .list_labels(&query.repo_path)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(labels.into_iter().map(Label::from).collect()))
+    }
+
+    pub async fn create_label(
+        &self,
+        new_label: NewLabel,
+    ) -> Result<Json<Label>, (StatusCode, String)> {
+        let name = new_label.name.trim();
+        check_label_name(name)?;
+        let color = check_color(new_label.color.as_deref().unwrap_or(DEFAULT_LABEL_COLOR))?;
+        self.ensure_label_free(&new_label.repo_path, name).await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let label = mega_label::Model {
+            id: generate_id(),
+            repo_path: new_label.repo_path,
+            name: name.to_owned(),
+            color,
+            description: new_label.description.filter(|d| !d.trim().is_empty()),
+            created_at: now,
+            updated_at: now,
+        };
+        self.label_storage
+            .save_label(label.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(label.into()))
+    }
+
+    pub async fn update_label(
+        &self,
+        id: i64,
+        update: LabelUpdate,
+    ) -> Result<Json<Label>, (StatusCode, String)> {
+        let mut label = self.get_label(id).await?;
+        if let Some(name) = update.name {
+            let name = name.trim();
+            check_label_name(name)?;
+            if name != label.name {
+                self.ensure_label_free(&label.repo_path, name).await?;
+                label.name = name.to_owned();
+            }
+        }
+        if let Some(color) = update.color {
+            label.color = check_color(&color)?;
+        }
+        if let Some(description) = update.description {
+            label.description = Some(description).filter(|d| !d.trim().is_empty());
+        }
+        label.updated_at = chrono::Utc::now().naive_utc();
+        let label = self
+            .label_storage
+            .update_label(label)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(label.into()))
+    }
+
+    /// Remove a label, it is taken off every issue and merge request carrying it.
+    pub async fn delete_label(&self, id: i64) -> Result<StatusCode, (StatusCode, String)> {
+        match self.label_storage.delete_label(id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((StatusCode::NOT_FOUND, format!("label {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    pub async fn list_milestones(
+        &self,
+        query: PlanningQuery,
+    ) -> Result<Json<Vec<Milestone>>, (StatusCode, String)> {
+        if let Some(state) = query.state.as_deref() {
+            check_milestone_state(state)?;
+        }
+        let milestones = self
+            .milestone_storage
+            .list_milestones(&query.repo_path, query.state.as_deref())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(self.with_progress(milestones).await?))
+    }
+
+    pub async fn get_milestone(&self, id: i64) -> Result<Json<Milestone>, (StatusCode, String)> {
+        let milestone = self.find_milestone(id).await?;
+        let mut milestones = self.with_progress(vec![milestone]).await?;
+        Ok(Json(milestones.remove(0)))
+    }
+
+    pub async fn create_milestone(
+        &self,
+        new_milestone: NewMilestone,
+    ) -> Result<Json<Milestone>, (StatusCode, String)> {
+        let title = new_milestone.title.trim();
+        check_milestone_title(title)?;
+        let due_date = match new_milestone.due_date.as_deref() {
+            Some(value) if !value.trim().is_empty() => Some(parse_due_date(value)?),
+            _ => None,
+        };
+        self.ensure_milestone_free(&new_milestone.repo_path, title)
+            .await?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let milestone = mega_milestone::Model {
+            id: generate_id(),
+            repo_path: new_milestone.repo_path,
+            title: title.to_owned(),
+            description: new_milestone.description.filter(|d| !d.trim().is_empty()),
+            due_date,
+            state: MILESTONE_OPEN.to_owned(),
+            created_at: now,
+            updated_at: now,
+            closed_at: None,
+        };
+        self.milestone_storage
+            .save_milestone(milestone.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(Milestone::new(milestone, 0, 0)))
+    }
+
+    pub async fn update_milestone(
+        &self,
+        id: i64,
+        update: MilestoneUpdate,
+    ) -> Result<Json<Milestone>, (StatusCode, String)> {
+        let mut milestone = self.find_milestone(id).await?;
+        let now = chrono::Utc::now().naive_utc();
+        if let Some(title) = update.title {
+            let title = title.trim();
+            check_milestone_title(title)?;
+            if title != milestone.title {
+                self.ensure_milestone_free(&milestone.repo_path, title)
+                    .await?;
+                milestone.title = title.to_owned();
+            }
+        }
+        if let Some(description) = update.description {
+            milestone.description = Some(description).filter(|d| !d.trim().is_empty());
+        }
+        if let Some(due_date) = update.due_date {
+            milestone.due_date = match due_date.trim() {
+                "" => None,
+                value => Some(parse_due_date(value)?),
+            };
+        }
+        if let Some(state) = update.state {
+            check_milestone_state(&state)?;
+            if state != milestone.state {
+                milestone.closed_at = (state == MILESTONE_CLOSED).then_some(now);
+                milestone.state = state;
+            }
+        }
+        milestone.updated_at = now;
+        let milestone = self
+            .milestone_storage
+            .update_milestone(milestone)
+            .await
+            .map_err(internal_error)?;
+        let mut milestones = self.with_progress(vec![milestone]).await?;
+        Ok(Json(milestones.remove(0)))
+    }
+
+    /// Remove a milestone, its issues and merge requests are kept without a milestone.
+    pub async fn delete_milestone(&self, id: i64) -> Result<StatusCode, (StatusCode, String)> {
+        match self.milestone_storage.delete_milestone(id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((StatusCode::NOT_FOUND, format!("milestone {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// Labels and assignees of the items of `item_type` with the given ids.
+    pub async fn links_of(
+        &self,
+        item_type: &str,
+        item_ids: &[i64],
+    ) -> Result<HashMap<i64, ItemLinks>, (StatusCode, String)> {
+        let mut links: HashMap<i64, ItemLinks> = HashMap::new();
+        if item_ids.is_empty() {
+            return Ok(links);
+        }
+        let labels = self
+            .label_storage
+            .labels_of(item_type, item_ids)
+            .await
+            .map_err(internal_error)?;
+        for (item_id, label) in labels {
+            links.entry(item_id).or_default().labels.push(label.name);
+        }
+        let assignees = self
+            .assignee_storage
+            .assignees_of(item_type, item_ids)
+            .await
+            .map_err(internal_error)?;
+        for (item_id, username) in assignees {
+            links.entry(item_id).or_default().assignees.push(username);
+        }
+        Ok(links)
+    }
+
+    /// Ids of the labels of `repo_path` named in `names`, every one of them must exist.
+    pub async fn resolve_labels(
+        &self,
+        repo_path: &str,
+        names: Vec<String>,
+    ) -> Result<Vec<i64>, (StatusCode, String)> {
+        let names = normalize_names(names);
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let labels = self
+            .label_storage
+            .find_labels(repo_path, &names)
+            .await
+            .map_err(internal_error)?;
+        let missing: Vec<&str> = names
+            .iter()
+            .filter(|name| !labels.iter().any(|l| &l.name == *name))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(bad_request(format!(
+                "{} has no label named {}",
+                repo_path,
+                missing.join(", ")
+            )));
+        }
+        Ok(labels.into_iter().map(|l| l.id).collect())
+    }
+
+    /// Check that a milestone exists in `repo_path` and is still open.
+    pub async fn check_milestone(
+        &self,
+        repo_path: &str,
+        milestone_id: i64,
+    ) -> Result<(), (StatusCode, String)> {
+        let milestone = self.find_milestone(milestone_id).await?;
+        if milestone.repo_path != repo_path {
+            return Err(bad_request(format!(
+                "milestone {} does not belong to {}",
+                milestone_id, repo_path
+            )));
+        }
+        if milestone.state != MILESTONE_OPEN {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("milestone {} is closed", milestone.title),
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn set_labels(
+        &self,
+        item_type: &str,
+        item_id: i64,
+        label_ids: &[i64],
+    ) -> Result<(), (StatusCode, String)> {
+        self.label_storage
+            .set_labels(item_type, item_id, label_ids)
+            .await
+            .map_err(internal_error)
+    }
+
+    pub async fn set_assignees(
+        &self,
+        item_type: &str,
+        item_id: i64,
+        assignees: Vec<String>,
+    ) -> Result<(), (StatusCode, String)> {
+        self.assignee_storage
+            .set_assignees(item_type, item_id, &normalize_names(assignees))
+            .await
+            .map_err(internal_error)
+    }
+
+    async fn get_label(&self, id: i64) -> Result<mega_label::Model, (StatusCode, String)> {
+        match self.label_storage.get_label(id).await {
+            Ok(Some(label)) => Ok(label),
+            Ok(None) => Err((StatusCode::NOT_FOUND, format!("label {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    async fn ensure_label_free(
+        &self,
+        repo_path: &str,
+        name: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        let existing = self
+            .label_storage
+            .find_labels(repo_path, &[name.to_owned()])
+            .await
+            .map_err(internal_error)?;
+        if !existing.is_empty() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} already has a label named {}", repo_path, name),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn find_milestone(&self, id: i64) -> Result<mega_milestone::Model, (StatusCode, String)> {
+        match self.milestone_storage.get_milestone(id).await {
+            Ok(Some(milestone)) => Ok(milestone),
+            Ok(None) => Err((StatusCode::NOT_FOUND, format!("milestone {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    async fn ensure_milestone_free(
+        &self,
+        repo_path: &str,
+        title: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        let existing = self
+            .milestone_storage
+            .find_by_title(repo_path, title)
+            .await
+            .map_err(internal_error)?;
+        if existing.is_some() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} already has a milestone named {}", repo_path, title),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Attach the number of open and closed issues to each milestone.
+    async fn with_progress(
+        &self,
+        milestones: Vec<mega_milestone::Model>,
+    ) -> Result<Vec<Milestone>, (StatusCode, String)> {
+        let ids: Vec<i64> = milestones.iter().map(|m| m.id).collect();
+        let counts = if ids.is_empty() {
+            Vec::new()
+        } else {
+            self.milestone_storage
+                .issue_counts(&ids)
+                .await
+                .map_err(internal_error)?
+        };
+        let count = |id: i64, state: &str| {
+            counts
+                .iter()
+                .filter(|(m, s, _)| *m == id && s == state)
+                .map(|(_, _, n)| n)
+                .sum()
+        };
+        Ok(milestones
+            .into_iter()
+            .map(|m| {
+                let (open, closed) = (count(m.id, ISSUE_OPEN), count(m.id, ISSUE_CLOSED));
+                Milestone::new(m, open, closed)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_color, normalize_names};
+
+    #[test]
+    fn test_normalize_names() {
+        let names = vec![
+            " bug ".to_owned(),
+            "".to_owned(),
+            "ui".to_owned(),
+            "bug".to_owned(),
+        ];
+        assert_eq!(normalize_names(names), vec!["bug", "ui"]);
+    }
+
+    #[test]
+    fn test_normalize_color() {
+        assert_eq!(normalize_color("#D73A4A").as_deref(), Some("#d73a4a"));
+        assert_eq!(normalize_color(" #0aF ").as_deref(), Some("#00aaff"));
+        assert_eq!(normalize_color("d73a4a"), None);
+        assert_eq!(normalize_color("#d73a4"), None);
+        assert_eq!(normalize_color("#gggggg"), None);
+    }
+}
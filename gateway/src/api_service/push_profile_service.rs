@@ -0,0 +1,120 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::metrics::metrics;
+use common::utils::generate_id;
+use db_entity::mega_push_profile;
+use git::protocol::profile;
+use jupiter::storage::push_profile_storage::PushProfileStorage;
+
+use crate::model::push_profile::{PushProfile, PushProfileQuery, PushStageTiming};
+
+/// Profiles kept when `MEGA_PUSH_PROFILE_KEEP` is not set.
+const DEFAULT_KEEP: u64 = 1000;
+
+/// Pushes slower than this are logged when `MEGA_SLOW_PUSH_MS` is not set.
+const DEFAULT_SLOW_PUSH_MS: i64 = 30_000;
+
+const DEFAULT_LIMIT: u64 = 50;
+const MAX_LIMIT: u64 = 500;
+
+/// Keeps the stage timings of the recent pushes, for administrators to find out why a push was
+/// slow.
+#[derive(Clone)]
+pub struct PushProfileService {
+    pub storage: PushProfileStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+impl PushProfileService {
+    /// Save the profile of a push to `repo_path` and drop the oldest ones beyond
+    /// `MEGA_PUSH_PROFILE_KEEP`. The profile is a side effect of the push, so a failure is only
+    /// logged.
+    pub async fn record(
+        &self,
+        repo_path: &str,
+        pusher: Option<&str>,
+        transport: &str,
+        push: &profile::PushProfile,
+    ) {
+        metrics().observe_push(transport, push.total());
+        let total_ms = push.total().as_millis() as i64;
+        if total_ms >= env_number("MEGA_SLOW_PUSH_MS", DEFAULT_SLOW_PUSH_MS) {
+            tracing::warn!("slow push to {} over {}: {}", repo_path, transport, push);
+        }
+        let stages: Vec<PushStageTiming> = push
+            .stages()
+            .into_iter()
+            .map(|(stage, elapsed)| PushStageTiming {
+                stage: stage.as_str().to_owned(),
+                ms: elapsed.as_millis() as i64,
+            })
+            .collect();
+        let model = mega_push_profile::Model {
+            id: generate_id(),
+            repo_path: repo_path.to_owned(),
+            pusher: pusher.map(str::to_owned),
+            transport: transport.to_owned(),
+            status: match push.rejected {
+                Some(_) => "rejected",
+                None => "ok",
+            }
+            .to_owned(),
+            reason: push.rejected.clone(),
+            pack_bytes: push.pack_bytes as i64,
+            object_count: push.objects as i64,
+            total_ms,
+            slowest_stage: push.slowest().map(|s| s.as_str().to_owned()),
+            stages: serde_json::to_string(&stages).unwrap_or_default(),
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        if let Err(e) = self.storage.save_profile(model).await {
+            tracing::error!("failed to save the push profile of {}: {}", repo_path, e);
+            return;
+        }
+        if let Err(e) = self
+            .storage
+            .prune(env_number("MEGA_PUSH_PROFILE_KEEP", DEFAULT_KEEP))
+            .await
+        {
+            tracing::error!("failed to prune the push profiles: {}", e);
+        }
+    }
+
+    pub async fn list(
+        &self,
+        query: PushProfileQuery,
+    ) -> Result<Json<Vec<PushProfile>>, (StatusCode, String)> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let profiles = self
+            .storage
+            .list_profiles(query.repo_path.as_deref(), query.min_ms, limit)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(profiles.into_iter().map(PushProfile::from).collect()))
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Json<PushProfile>, (StatusCode, String)> {
+        self.storage
+            .get_profile(id)
+            .await
+            .map_err(internal_error)?
+            .map(|profile| Json(profile.into()))
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("push profile {} not found", id),
+                )
+            })
+    }
+}
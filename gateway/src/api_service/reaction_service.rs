@@ -0,0 +1,247 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_reaction;
+use jupiter::storage::issue_storage::IssueStorage;
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use jupiter::storage::mr_review_storage::MrReviewStorage;
+use jupiter::storage::mr_storage::MrStorage;
+use jupiter::storage::reaction_storage::ReactionStorage;
+use jupiter::storage::reference_storage::SOURCE_COMMENT;
+
+use crate::i18n;
+use crate::model::reaction::{NewReaction, Reaction};
+
+/// The reactions users can give, in the order they are listed.
+pub const REACTIONS: &[&str] = &[
+    "+1", "-1", "laugh", "hooray", "confused", "heart", "rocket", "eyes",
+];
+
+/// What a reaction is given to.
+#[derive(Debug, Clone, Copy)]
+pub enum ReactionSubject {
+    Issue(i64),
+    Mr(i64),
+    /// A review comment `id` of the merge request `mr_id`
+    Comment {
+        mr_id: i64,
+        id: i64,
+    },
+}
+
+impl ReactionSubject {
+    fn key(&self) -> (&'static str, i64) {
+        match *self {
+            ReactionSubject::Issue(id) => (ITEM_ISSUE, id),
+            ReactionSubject::Mr(id) => (ITEM_MR, id),
+            ReactionSubject::Comment { id, .. } => (SOURCE_COMMENT, id),
+        }
+    }
+}
+
+/// Emoji reactions to issues, merge requests and review comments.
+#[derive(Clone)]
+pub struct ReactionService {
+    pub reaction_storage: ReactionStorage,
+    pub issue_storage: IssueStorage,
+    pub mr_storage: MrStorage,
+    pub review_storage: MrReviewStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+/// The reactions counted by content in the order of [`REACTIONS`], flagging those of `viewer`.
+pub fn summarize(reactions: &[mega_reaction::Model], viewer: &str) -> Vec<Reaction> {
+    REACTIONS
+        .iter()
+        .filter_map(|content| {
+            let given: Vec<&mega_reaction::Model> =
+                reactions.iter().filter(|r| r.content == *content).collect();
+            if given.is_empty() {
+                return None;
+            }
+            Some(Reaction {
+                content: (*content).to_owned(),
+                count: given.len() as u64,
+                reacted: given.iter().any(|r| r.username == viewer),
+            })
+        })
+        .collect()
+}
+
+fn check_content(content: &str) -> Result<&'static str, (StatusCode, String)> {
+    REACTIONS
+        .iter()
+        .find(|r| **r == content.trim())
+        .copied()
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "unknown reaction: {}, expected one of {}",
+                    content,
+                    REACTIONS.join(", ")
+                ),
+            )
+        })
+}
+
+impl ReactionService {
+    /// The reactions to `subject`, as seen by `viewer`.
+    pub async fn list(
+        &self,
+        subject: ReactionSubject,
+        viewer: &str,
+    ) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+        self.check_subject(subject).await?;
+        self.summary(subject, viewer).await
+    }
+
+    /// React to `subject` as `username`, reacting again the same way changes nothing.
+    pub async fn add(
+        &self,
+        subject: ReactionSubject,
+        username: &str,
+        new_reaction: NewReaction,
+    ) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+        let content = check_content(&new_reaction.content)?;
+        self.check_subject(subject).await?;
+        let (subject_type, subject_id) = subject.key();
+        self.reaction_storage
+            .add_reaction(mega_reaction::Model {
+                id: generate_id(),
+                subject_type: subject_type.to_owned(),
+                subject_id,
+                username: username.to_owned(),
+                content: content.to_owned(),
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+            .await
+            .map_err(internal_error)?;
+        self.summary(subject, username).await
+    }
+
+    /// Take back the reaction `content` of `username`, if they gave it.
+    pub async fn remove(
+        &self,
+        subject: ReactionSubject,
+        username: &str,
+        content: &str,
+    ) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+        let content = check_content(content)?;
+        self.check_subject(subject).await?;
+        let (subject_type, subject_id) = subject.key();
+        self.reaction_storage
+            .remove_reaction(subject_type, subject_id, username, content)
+            .await
+            .map_err(internal_error)?;
+        self.summary(subject, username).await
+    }
+
+    async fn summary(
+        &self,
+        subject: ReactionSubject,
+        viewer: &str,
+    ) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+        let (subject_type, subject_id) = subject.key();
+        let reactions = self
+            .reaction_storage
+            .list_reactions(subject_type, subject_id)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(summarize(&reactions, viewer)))
+    }
+
+    async fn check_subject(&self, subject: ReactionSubject) -> Result<(), (StatusCode, String)> {
+        let mr_not_found = |id: i64| {
+            (
+                StatusCode::NOT_FOUND,
+                i18n::t("mr.not_found", &[("id", &id.to_string())]),
+            )
+        };
+        match subject {
+            ReactionSubject::Issue(id) => {
+                if self
+                    .issue_storage
+                    .get_issue(id)
+                    .await
+                    .map_err(internal_error)?
+                    .is_none()
+                {
+                    return Err((StatusCode::NOT_FOUND, format!("issue {} not found", id)));
+                }
+            }
+            ReactionSubject::Mr(id) => {
+                if self
+                    .mr_storage
+                    .get_mr(id)
+                    .await
+                    .map_err(internal_error)?
+                    .is_none()
+                {
+                    return Err(mr_not_found(id));
+                }
+            }
+            ReactionSubject::Comment { mr_id, id } => {
+                if self
+                    .review_storage
+                    .get_comment(mr_id, id)
+                    .await
+                    .map_err(internal_error)?
+                    .is_none()
+                {
+                    return Err((
+                        StatusCode::NOT_FOUND,
+                        format!("comment {} not found on merge request {}", id, mr_id),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reaction(username: &str, content: &str) -> mega_reaction::Model {
+        mega_reaction::Model {
+            id: generate_id(),
+            subject_type: ITEM_ISSUE.to_owned(),
+            subject_id: 1,
+            username: username.to_owned(),
+            content: content.to_owned(),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let reactions = [
+            reaction("alice", "heart"),
+            reaction("bob", "+1"),
+            reaction("alice", "+1"),
+            reaction("carol", "heart"),
+        ];
+        let count = |content: &str, count: u64, reacted: bool| Reaction {
+            content: content.to_owned(),
+            count,
+            reacted,
+        };
+        assert_eq!(
+            summarize(&reactions, "bob"),
+            [count("+1", 2, true), count("heart", 2, false)]
+        );
+        assert!(summarize(&[], "bob").is_empty());
+    }
+
+    #[test]
+    fn test_check_content() {
+        assert_eq!(check_content(" rocket ").unwrap(), "rocket");
+        assert!(check_content("thumbsup").is_err());
+    }
+}
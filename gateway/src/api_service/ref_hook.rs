@@ -0,0 +1,245 @@
+//! Hooks run after refs move, to keep what lives outside the repositories consistent with them.
+//!
+//! A hook sees every push event, whether the ref was moved by a push over HTTP or SSH or by the
+//! server itself, and runs after the ref update is committed. A hook failing does not undo the
+//! update: the event is retried later with [`backoff`], so handlers must be idempotent. A hook
+//! may ask for other events of the log too, like webhooks do for merge requests.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use db_entity::mega_event;
+
+use crate::api_service::event_service::EVENT_PUSH;
+use crate::model::event::RefPush;
+
+/// Attempts of an event before a hook gives up on it.
+pub const MAX_ATTEMPTS: i32 = 8;
+
+const FIRST_RETRY: Duration = Duration::from_secs(10);
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// A ref moved in a repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefChange {
+    /// Id of the push event, the same for every attempt
+    pub event_id: i64,
+    pub repo_path: String,
+    /// Full ref name, e.g. `refs/heads/main`
+    pub ref_name: String,
+    /// Commit the ref pointed to before, `None` when it was created
+    pub before: Option<String>,
+    /// Commit the ref points to now, `None` when it was deleted
+    pub after: Option<String>,
+    pub actor: Option<String>,
+}
+
+impl RefChange {
+    /// The change a push event records, `None` for other events.
+    pub fn from_event(event: &mega_event::Model) -> Option<Self> {
+        let push: RefPush = serde_json::from_str(&event.payload).ok()?;
+        Some(RefChange {
+            event_id: event.id,
+            repo_path: event.repo_path.clone(),
+            ref_name: push.ref_name,
+            before: push.before,
+            after: push.after,
+            actor: event.actor.clone(),
+        })
+    }
+
+    /// The branch or tag name without its `refs/heads/` or `refs/tags/` prefix.
+    pub fn short_ref(&self) -> &str {
+        self.ref_name
+            .strip_prefix("refs/heads/")
+            .or_else(|| self.ref_name.strip_prefix("refs/tags/"))
+            .unwrap_or(&self.ref_name)
+    }
+}
+
+/// Something to do after a ref moves. Register new hooks in [`hooks_from_env`] instead of
+/// patching receive-pack.
+#[async_trait]
+pub trait RefHook: Send + Sync {
+    /// Unique name, it keys the progress of the hook through the event log.
+    fn name(&self) -> &'static str;
+
+    /// The types of the events the hook is handed.
+    fn event_types(&self) -> &'static [&'static str] {
+        &[EVENT_PUSH]
+    }
+
+    /// Handle an event of the log, push events are handed to [`RefHook::on_ref_update`].
+    async fn on_event(&self, event: &mega_event::Model) -> Result<(), String> {
+        match RefChange::from_event(event) {
+            Some(change) => self.on_ref_update(&change).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn on_ref_update(&self, change: &RefChange) -> Result<(), String>;
+}
+
+/// How long to wait before attempting an event again after `attempts` failed attempts.
+pub fn backoff(attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    FIRST_RETRY.saturating_mul(1 << doublings).min(MAX_RETRY)
+}
+
+/// Asks a CDN to purge the raw file and archive URLs of a ref when it moves.
+///
+/// Enabled by `MEGA_CDN_PURGE_URL`, which is sent `{"paths": [...]}` as a POST request with
+/// `MEGA_CDN_PURGE_TOKEN` as a bearer token when it is set. The paths come from the comma
+/// separated templates of `MEGA_CDN_PURGE_PATHS`, see [`expand_path`].
+pub struct CdnPurgeHook {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub token: Option<String>,
+    pub paths: Vec<String>,
+}
+
+const DEFAULT_PURGE_PATHS: &str = "{repo}/raw/{ref}/*,{repo}/archive/{ref}.*";
+
+/// Fill in `{repo}` (the repository path without its leading slash), `{ref}` (the branch or tag
+/// name) and `{commit}` (the commit the ref points to, or pointed to before it was deleted).
+pub fn expand_path(template: &str, change: &RefChange) -> String {
+    let commit = change.after.as_ref().or(change.before.as_ref());
+    template
+        .replace("{repo}", change.repo_path.trim_matches('/'))
+        .replace("{ref}", change.short_ref())
+        .replace("{commit}", commit.map_or("", String::as_str))
+}
+
+impl CdnPurgeHook {
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("MEGA_CDN_PURGE_URL")
+            .ok()
+            .filter(|u| !u.trim().is_empty())?;
+        let paths = std::env::var("MEGA_CDN_PURGE_PATHS")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PURGE_PATHS.to_owned());
+        let client = match reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("unable to set up CDN purges, they are disabled: {}", e);
+                return None;
+            }
+        };
+        Some(CdnPurgeHook {
+            client,
+            url: url.trim().to_owned(),
+            token: std::env::var("MEGA_CDN_PURGE_TOKEN")
+                .ok()
+                .filter(|t| !t.is_empty()),
+            paths: paths
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl RefHook for CdnPurgeHook {
+    fn name(&self) -> &'static str {
+        "cdn-purge"
+    }
+
+    async fn on_ref_update(&self, change: &RefChange) -> Result<(), String> {
+        let paths: Vec<String> = self.paths.iter().map(|t| expand_path(t, change)).collect();
+        let mut request = self
+            .client
+            .post(&self.url)
+            .json(&serde_json::json!({ "paths": paths }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("purge request failed with {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Wakes the search indexer, so pushed commits and code are searchable without waiting for its
+/// next poll.
+pub struct SearchIndexHook {
+    pub wake: Arc<Notify>,
+}
+
+#[async_trait]
+impl RefHook for SearchIndexHook {
+    fn name(&self) -> &'static str {
+        "search-index"
+    }
+
+    async fn on_ref_update(&self, _change: &RefChange) -> Result<(), String> {
+        self.wake.notify_one();
+        Ok(())
+    }
+}
+
+/// The hooks the server runs: search indexing always, CDN purges when configured.
+pub fn hooks_from_env(search_wake: Arc<Notify>) -> Vec<Arc<dyn RefHook>> {
+    let mut hooks: Vec<Arc<dyn RefHook>> = vec![Arc::new(SearchIndexHook { wake: search_wake })];
+    if let Some(hook) = CdnPurgeHook::from_env() {
+        hooks.push(Arc::new(hook));
+    }
+    hooks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{backoff, expand_path, RefChange, DEFAULT_PURGE_PATHS};
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(10));
+        assert_eq!(backoff(2), Duration::from_secs(20));
+        assert_eq!(backoff(4), Duration::from_secs(80));
+        assert_eq!(backoff(10), Duration::from_secs(60 * 60));
+        assert_eq!(backoff(i32::MAX), Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let mut change = RefChange {
+            event_id: 1,
+            repo_path: "/projects/mega".to_owned(),
+            ref_name: "refs/heads/feature/x".to_owned(),
+            before: None,
+            after: Some("a".repeat(40)),
+            actor: None,
+        };
+        let paths: Vec<String> = DEFAULT_PURGE_PATHS
+            .split(',')
+            .map(|t| expand_path(t, &change))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "projects/mega/raw/feature/x/*",
+                "projects/mega/archive/feature/x.*"
+            ]
+        );
+
+        change.ref_name = "refs/tags/v1.0".to_owned();
+        change.before = Some("b".repeat(40));
+        change.after = None;
+        assert_eq!(
+            expand_path("/{repo}/{ref}/{commit}", &change),
+            format!("/projects/mega/v1.0/{}", "b".repeat(40))
+        );
+    }
+}
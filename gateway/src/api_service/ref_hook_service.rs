@@ -0,0 +1,254 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Json;
+use tokio::task::JoinHandle;
+
+use common::utils::generate_id;
+use db_entity::{mega_event, mega_ref_hook_retry};
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::ref_hook_storage::RefHookStorage;
+
+use crate::api_service::ref_hook::{self, RefHook, MAX_ATTEMPTS};
+use crate::model::ref_hook::{RefHookRequeued, RefHookRetry, RefHookStatus};
+use crate::shutdown;
+
+/// How often the runner looks for new push events and due retries.
+const RUNNER_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Most events handed to a hook in one look.
+const BATCH_SIZE: u64 = 50;
+
+/// Runs the registered [`RefHook`]s on the push events of the event log, and the other events
+/// they ask for.
+///
+/// Every hook keeps its own cursor, so a slow or failing hook does not hold the others back,
+/// and a hook added later starts with the pushes made after it was first run.
+#[derive(Clone)]
+pub struct RefHookService {
+    pub event_storage: EventStorage,
+    pub hook_storage: RefHookStorage,
+    pub hooks: Arc<Vec<Arc<dyn RefHook>>>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn after(delay: Duration) -> chrono::NaiveDateTime {
+    chrono::Utc::now().naive_utc() + chrono::Duration::from_std(delay).unwrap_or_default()
+}
+
+impl RefHookService {
+    pub async fn list_hooks(&self) -> Result<Json<Vec<RefHookStatus>>, (StatusCode, String)> {
+        let mut hooks = Vec::new();
+        for hook in self.hooks.iter() {
+            let last_event_id = self
+                .hook_storage
+                .get_cursor(hook.name())
+                .await
+                .map_err(internal_error)?;
+            let retries = self
+                .hook_storage
+                .list_retries(hook.name())
+                .await
+                .map_err(internal_error)?;
+            hooks.push(RefHookStatus {
+                name: hook.name().to_owned(),
+                last_event_id,
+                retries: retries.into_iter().map(RefHookRetry::from).collect(),
+            });
+        }
+        Ok(Json(hooks))
+    }
+
+    /// Attempt the events the hook `name` gave up on once more.
+    pub async fn retry_failed(
+        &self,
+        name: &str,
+    ) -> Result<Json<RefHookRequeued>, (StatusCode, String)> {
+        if !self.hooks.iter().any(|h| h.name() == name) {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("ref hook {} not found", name),
+            ));
+        }
+        let requeued = self
+            .hook_storage
+            .requeue_failed(name)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(RefHookRequeued { requeued }))
+    }
+
+    /// Run the hooks every [`RUNNER_INTERVAL`] until the server shuts down. The task completes
+    /// once the run in progress when the shutdown was requested is done.
+    pub fn start_runner(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RUNNER_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown::requested() => return,
+                }
+                for hook in self.hooks.iter() {
+                    if let Err(e) = self.run_new(hook.as_ref()).await {
+                        tracing::warn!("unable to run ref hook {}: {}", hook.name(), e);
+                    }
+                    if let Err(e) = self.run_retries(hook.as_ref()).await {
+                        tracing::warn!("unable to retry ref hook {}: {}", hook.name(), e);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Hand every push event not handed yet to the hooks, for the pushes made before a shutdown
+    /// to be delivered. The events a hook fails on are retried after the restart.
+    pub async fn flush(&self) {
+        for hook in self.hooks.iter() {
+            loop {
+                match self.run_new(hook.as_ref()).await {
+                    Ok(handed) if handed as u64 == BATCH_SIZE => {}
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::warn!("unable to run ref hook {}: {}", hook.name(), e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hand the events recorded since the last run to `hook`, returning how many. The
+    /// events are claimed before the hook runs, so with several server instances each event is
+    /// handled by one of them.
+    async fn run_new(&self, hook: &dyn RefHook) -> Result<usize, String> {
+        let name = hook.name();
+        let Some(cursor) = self
+            .hook_storage
+            .get_cursor(name)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            let latest = self
+                .event_storage
+                .latest_id()
+                .await
+                .map_err(|e| e.to_string())?;
+            return self
+                .hook_storage
+                .init_cursor(name, latest.unwrap_or(0))
+                .await
+                .map(|_| 0)
+                .map_err(|e| e.to_string());
+        };
+        let types: Vec<String> = hook.event_types().iter().map(|t| t.to_string()).collect();
+        let events = self
+            .event_storage
+            .events_after(cursor, None, &types, BATCH_SIZE)
+            .await
+            .map_err(|e| e.to_string())?;
+        let Some(last) = events.last().map(|e| e.id) else {
+            return Ok(0);
+        };
+        if !self
+            .hook_storage
+            .claim_events(name, cursor, last)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(0);
+        }
+        for event in &events {
+            if let Err(err) = hook.on_event(event).await {
+                tracing::warn!(
+                    "ref hook {} failed on {} event {} of {}: {}",
+                    name,
+                    event.event_type,
+                    event.id,
+                    event.repo_path,
+                    err
+                );
+                self.schedule_retry(name, event, &err).await;
+            }
+        }
+        Ok(events.len())
+    }
+
+    async fn schedule_retry(&self, name: &str, event: &mega_event::Model, err: &str) {
+        let now = chrono::Utc::now().naive_utc();
+        let retry = mega_ref_hook_retry::Model {
+            id: generate_id(),
+            hook: name.to_owned(),
+            event_id: event.id,
+            attempts: 1,
+            next_attempt_at: Some(after(ref_hook::backoff(1))),
+            last_error: err.to_owned(),
+            created_at: now,
+            updated_at: now,
+        };
+        if let Err(e) = self.hook_storage.save_retry(retry).await {
+            tracing::warn!(
+                "unable to schedule a retry of ref hook {} on event {}: {}",
+                name,
+                event.id,
+                e
+            );
+        }
+    }
+
+    /// Attempt again the events `hook` failed on whose time has come. The hook gives up on an
+    /// event after [`MAX_ATTEMPTS`] attempts, and on events removed from the log meanwhile.
+    async fn run_retries(&self, hook: &dyn RefHook) -> Result<(), String> {
+        let name = hook.name();
+        let due = self
+            .hook_storage
+            .due_retries(name, chrono::Utc::now().naive_utc(), BATCH_SIZE)
+            .await
+            .map_err(|e| e.to_string())?;
+        for retry in due {
+            let attempts = retry.attempts + 1;
+            // put off the next attempt while this one runs
+            let next = after(ref_hook::backoff(attempts));
+            if !self
+                .hook_storage
+                .claim_retry(&retry, next)
+                .await
+                .map_err(|e| e.to_string())?
+            {
+                continue;
+            }
+            let event = self
+                .event_storage
+                .get_event(retry.event_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let result = match &event {
+                Some(event) => hook.on_event(event).await,
+                None => Err("the event is no longer in the event log".to_owned()),
+            };
+            let outcome = match result {
+                Ok(()) => self.hook_storage.delete_retry(retry.id).await,
+                Err(err) => {
+                    let give_up = attempts >= MAX_ATTEMPTS || event.is_none();
+                    if give_up {
+                        tracing::warn!(
+                            "ref hook {} gave up on event {} after {} attempts: {}",
+                            name,
+                            retry.event_id,
+                            attempts,
+                            err
+                        );
+                    }
+                    self.hook_storage
+                        .record_failure(retry.id, (!give_up).then_some(next), &err)
+                        .await
+                }
+            };
+            outcome.map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
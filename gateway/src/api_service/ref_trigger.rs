@@ -0,0 +1,233 @@
+//! Schedules and ref name templates of ref triggers.
+//!
+//! A schedule is one of `hourly [:MM]`, `daily HH:MM` or `weekly <day> HH:MM`, in UTC, e.g.
+//! `daily 02:00` or `weekly mon 06:30`. A template names the ref created by a run and may use
+//! `{date}` (`20240131`), `{year}`, `{month}`, `{day}`, `{week}` (ISO week) and `{time}` (`0200`),
+//! all taken from the time of the run.
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Timelike, Weekday};
+
+use common::errors::MegaError;
+
+const PLACEHOLDERS: &[&str] = &["date", "year", "month", "day", "week", "time"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    Hourly { minute: u32 },
+    Daily { time: NaiveTime },
+    Weekly { weekday: Weekday, time: NaiveTime },
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, MegaError> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| MegaError::with_message(&format!("invalid time {}, expected HH:MM", value)))
+}
+
+impl Schedule {
+    pub fn parse(spec: &str) -> Result<Schedule, MegaError> {
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        match parts.as_slice() {
+            ["hourly"] => Ok(Schedule::Hourly { minute: 0 }),
+            ["hourly", minute] => minute
+                .strip_prefix(':')
+                .and_then(|m| m.parse().ok())
+                .filter(|m| *m < 60)
+                .map(|minute| Schedule::Hourly { minute })
+                .ok_or_else(|| {
+                    MegaError::with_message(&format!("invalid minute {}, expected :MM", minute))
+                }),
+            ["daily", time] => Ok(Schedule::Daily {
+                time: parse_time(time)?,
+            }),
+            ["weekly", day, time] => Ok(Schedule::Weekly {
+                weekday: day
+                    .parse()
+                    .map_err(|_| MegaError::with_message(&format!("invalid weekday {}", day)))?,
+                time: parse_time(time)?,
+            }),
+            _ => Err(MegaError::with_message(&format!(
+                "invalid schedule '{}', expected 'hourly [:MM]', 'daily HH:MM' or 'weekly <day> HH:MM'",
+                spec
+            ))),
+        }
+    }
+
+    /// The first time strictly after `after` the schedule fires.
+    pub fn next_after(&self, after: NaiveDateTime) -> NaiveDateTime {
+        match *self {
+            Schedule::Hourly { minute } => {
+                let hour = after.date().and_hms_opt(after.hour(), minute, 0).unwrap();
+                if hour > after {
+                    hour
+                } else {
+                    hour + Duration::hours(1)
+                }
+            }
+            Schedule::Daily { time } => {
+                let today = after.date().and_time(time);
+                if today > after {
+                    today
+                } else {
+                    today + Duration::days(1)
+                }
+            }
+            Schedule::Weekly { weekday, time } => {
+                let days = (7 + weekday.num_days_from_monday()
+                    - after.weekday().num_days_from_monday())
+                    % 7;
+                let day = after.date().and_time(time) + Duration::days(days as i64);
+                if day > after {
+                    day
+                } else {
+                    day + Duration::weeks(1)
+                }
+            }
+        }
+    }
+}
+
+/// Check that a template only uses known placeholders.
+pub fn check_template(template: &str) -> Result<(), MegaError> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| MegaError::with_message("unclosed '{' in template"))?;
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(MegaError::with_message(&format!(
+                "unknown placeholder {{{}}}, expected one of {}",
+                name,
+                PLACEHOLDERS.join(", ")
+            )));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+pub fn render_template(template: &str, time: NaiveDateTime) -> String {
+    template
+        .replace("{date}", &time.format("%Y%m%d").to_string())
+        .replace("{year}", &time.format("%Y").to_string())
+        .replace("{month}", &time.format("%m").to_string())
+        .replace("{day}", &time.format("%d").to_string())
+        .replace("{week}", &format!("{:02}", time.iso_week().week()))
+        .replace("{time}", &time.format("%H%M").to_string())
+}
+
+/// Check a branch or tag name (without `refs/heads/` or `refs/tags/`) the way
+/// `git check-ref-format` does.
+pub fn check_ref_name(name: &str) -> Result<(), MegaError> {
+    let invalid = |why: &str| {
+        Err(MegaError::with_message(&format!(
+            "invalid ref name {}: {}",
+            name, why
+        )))
+    };
+    if name.is_empty() {
+        return invalid("empty");
+    }
+    if name
+        .chars()
+        .any(|c| c.is_control() || " ~^:?*[\\".contains(c))
+    {
+        return invalid("contains a forbidden character");
+    }
+    if name.contains("..") || name.contains("@{") || name == "@" {
+        return invalid("contains '..' or '@{'");
+    }
+    for component in name.split('/') {
+        if component.is_empty() || component.starts_with('.') || component.ends_with(".lock") {
+            return invalid(
+                "has an empty component, or one starting with '.' or ending with '.lock'",
+            );
+        }
+    }
+    if name.ends_with('.') {
+        return invalid("ends with '.'");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+    use super::{check_ref_name, check_template, render_template, Schedule};
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-01-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        let two = NaiveTime::from_hms_opt(2, 0, 0).unwrap();
+        assert_eq!(
+            Schedule::parse("hourly").unwrap(),
+            Schedule::Hourly { minute: 0 }
+        );
+        assert_eq!(
+            Schedule::parse("hourly :15").unwrap(),
+            Schedule::Hourly { minute: 15 }
+        );
+        assert_eq!(
+            Schedule::parse("daily 02:00").unwrap(),
+            Schedule::Daily { time: two }
+        );
+        assert_eq!(
+            Schedule::parse("weekly fri 02:00").unwrap(),
+            Schedule::Weekly {
+                weekday: Weekday::Fri,
+                time: two
+            }
+        );
+        assert!(Schedule::parse("hourly :60").is_err());
+        assert!(Schedule::parse("daily 25:00").is_err());
+        assert!(Schedule::parse("monthly 1 02:00").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let daily = Schedule::parse("daily 02:00").unwrap();
+        assert_eq!(daily.next_after(at(3, 1, 0)), at(3, 2, 0));
+        assert_eq!(daily.next_after(at(3, 2, 0)), at(4, 2, 0));
+        let hourly = Schedule::parse("hourly :30").unwrap();
+        assert_eq!(hourly.next_after(at(3, 1, 30)), at(3, 2, 30));
+        assert_eq!(hourly.next_after(at(3, 1, 10)), at(3, 1, 30));
+        let weekly = Schedule::parse("weekly wed 06:00").unwrap();
+        assert_eq!(weekly.next_after(at(1, 12, 0)), at(3, 6, 0));
+        assert_eq!(weekly.next_after(at(3, 6, 0)), at(10, 6, 0));
+        assert_eq!(weekly.next_after(at(4, 0, 0)), at(10, 6, 0));
+    }
+
+    #[test]
+    fn test_template() {
+        assert!(check_template("nightly-{date}").is_ok());
+        assert!(check_template("release/{year}.{week}").is_ok());
+        assert!(check_template("nightly-{hour}").is_err());
+        assert!(check_template("nightly-{date").is_err());
+        assert_eq!(
+            render_template("nightly-{date}-{time}", at(9, 2, 5)),
+            "nightly-20240109-0205"
+        );
+        assert_eq!(
+            render_template("release/{year}.{week}", at(9, 2, 5)),
+            "release/2024.02"
+        );
+    }
+
+    #[test]
+    fn test_check_ref_name() {
+        assert!(check_ref_name("nightly-20240109").is_ok());
+        assert!(check_ref_name("release/2024.02").is_ok());
+        for name in [
+            "", "a b", "a..b", "a/", "/a", "a//b", ".a", "a.lock", "a:b", "a@{1}",
+        ] {
+            assert!(check_ref_name(name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::NaiveDateTime;
+use ed25519_dalek::SigningKey;
+use sea_orm::Set;
+
+use common::utils::generate_id;
+use db_entity::{db_enums::RefType, mega_ref_trigger};
+use entity::objects;
+use jupiter::storage::ref_audit_storage::RefAuditStorage;
+use jupiter::storage::ref_trigger_storage::RefTriggerStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::types::ObjectType;
+
+use crate::api_service::mr_service::DEFAULT_COMMITTER;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::ref_trigger::{self, Schedule};
+use crate::api_service::ref_update::RefUpdater;
+use crate::auth::signing;
+use crate::model::ref_trigger::{
+    self as model, RefAuditEntry, RefAuditQuery, RefTrigger, RefTriggerRun, RefTriggerUpdate,
+};
+
+/// How often the scheduler looks for triggers that are due.
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+pub struct RefTriggerService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub trigger_storage: RefTriggerStorage,
+    pub audit_storage: RefAuditStorage,
+    pub ref_updater: RefUpdater,
+    /// Signs the tags created by triggers, they are left unsigned without a key
+    pub signing_key: Option<Arc<SigningKey>>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+impl RefTriggerService {
+    pub async fn list_triggers(&self) -> Result<Json<Vec<RefTrigger>>, (StatusCode, String)> {
+        let triggers = self
+            .trigger_storage
+            .list_triggers()
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(triggers.into_iter().map(RefTrigger::from).collect()))
+    }
+
+    /// Create the trigger `name`, or replace its rule keeping its run history.
+    pub async fn save_trigger(
+        &self,
+        name: String,
+        update: RefTriggerUpdate,
+    ) -> Result<Json<RefTrigger>, (StatusCode, String)> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(bad_request(format!("invalid trigger name: {}", name)));
+        }
+        let ref_type = model::parse_ref_type(&update.ref_type).ok_or_else(|| {
+            bad_request(format!(
+                "unknown ref type {}, expected branch or tag",
+                update.ref_type
+            ))
+        })?;
+        let schedule = Schedule::parse(&update.schedule).map_err(bad_request)?;
+        ref_trigger::check_template(&update.name_template).map_err(bad_request)?;
+        let now = chrono::Utc::now().naive_utc();
+        ref_trigger::check_ref_name(&ref_trigger::render_template(&update.name_template, now))
+            .map_err(bad_request)?;
+        let source_ref = if update.source.starts_with("refs/") {
+            update.source
+        } else {
+            format!("refs/heads/{}", update.source)
+        };
+
+        let existing = self
+            .trigger_storage
+            .get_trigger(&name)
+            .await
+            .map_err(internal_error)?;
+        let trigger = mega_ref_trigger::Model {
+            id: existing.as_ref().map_or_else(generate_id, |t| t.id),
+            name,
+            repo_path: update.repo_path,
+            source_ref,
+            ref_type,
+            name_template: update.name_template,
+            schedule: update
+                .schedule
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            enabled: update.enabled,
+            next_run_at: schedule.next_after(now),
+            last_run_at: existing.as_ref().and_then(|t| t.last_run_at),
+            last_ref: existing.as_ref().and_then(|t| t.last_ref.clone()),
+            last_error: existing.as_ref().and_then(|t| t.last_error.clone()),
+            created_at: existing.as_ref().map_or(now, |t| t.created_at),
+            updated_at: now,
+        };
+        let trigger = match existing {
+            Some(_) => self.trigger_storage.update_trigger(trigger).await,
+            None => self
+                .trigger_storage
+                .save_trigger(trigger.clone())
+                .await
+                .map(|_| trigger),
+        }
+        .map_err(internal_error)?;
+        Ok(Json(trigger.into()))
+    }
+
+    pub async fn delete_trigger(&self, name: &str) -> Result<StatusCode, (StatusCode, String)> {
+        match self.trigger_storage.delete_trigger(name).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("ref trigger {} not found", name),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// Run a trigger now, outside of its schedule.
+    pub async fn run_trigger(
+        &self,
+        name: &str,
+    ) -> Result<Json<RefTriggerRun>, (StatusCode, String)> {
+        let trigger = match self.trigger_storage.get_trigger(name).await {
+            Ok(Some(trigger)) => trigger,
+            Ok(None) => {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("ref trigger {} not found", name),
+                ))
+            }
+            Err(e) => return Err(internal_error(e)),
+        };
+        let result = self.execute(&trigger, chrono::Utc::now().naive_utc()).await;
+        self.record(&trigger, &result).await;
+        result.map(Json)
+    }
+
+    pub async fn audit(
+        &self,
+        query: RefAuditQuery,
+    ) -> Result<Json<Vec<RefAuditEntry>>, (StatusCode, String)> {
+        let entries = self
+            .audit_storage
+            .list_audit(&query.repo_path, query.ref_name.as_deref())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(entries.into_iter().map(RefAuditEntry::from).collect()))
+    }
+
+    /// Check for due triggers every [`SCHEDULER_INTERVAL`] for as long as the server runs.
+    pub fn start_scheduler(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.run_due().await;
+            }
+        });
+    }
+
+    /// Run every enabled trigger whose time has come. A run missed while the server was down
+    /// happens once on the next check, not once per missed occurrence.
+    async fn run_due(&self) {
+        let now = chrono::Utc::now().naive_utc();
+        let due = match self.trigger_storage.due_triggers(now).await {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::warn!("unable to load ref triggers: {}", e);
+                return;
+            }
+        };
+        for trigger in due {
+            let next = match Schedule::parse(&trigger.schedule) {
+                Ok(schedule) => schedule.next_after(now),
+                Err(e) => {
+                    tracing::warn!(
+                        "ref trigger {} has an invalid schedule: {}",
+                        trigger.name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            match self
+                .trigger_storage
+                .claim_run(trigger.id, trigger.next_run_at, next)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    tracing::warn!("unable to schedule ref trigger {}: {}", trigger.name, e);
+                    continue;
+                }
+            }
+            let result = self.execute(&trigger, now).await;
+            self.record(&trigger, &result).await;
+        }
+    }
+
+    async fn record(
+        &self,
+        trigger: &mega_ref_trigger::Model,
+        result: &Result<RefTriggerRun, (StatusCode, String)>,
+    ) {
+        let outcome = match result {
+            Ok(run) => {
+                tracing::info!("ref trigger {} created {}", trigger.name, run.ref_name);
+                Ok(run.ref_name.as_str())
+            }
+            Err((_, err)) => {
+                tracing::warn!("ref trigger {} failed: {}", trigger.name, err);
+                Err(err.as_str())
+            }
+        };
+        if let Err(e) = self.trigger_storage.record_run(trigger.id, outcome).await {
+            tracing::warn!(
+                "unable to record run of ref trigger {}: {}",
+                trigger.name,
+                e
+            );
+        }
+    }
+
+    /// Create the ref of one run of `trigger`, named after `now`. Existing refs are never moved.
+    async fn execute(
+        &self,
+        trigger: &mega_ref_trigger::Model,
+        now: NaiveDateTime,
+    ) -> Result<RefTriggerRun, (StatusCode, String)> {
+        let name = ref_trigger::render_template(&trigger.name_template, now);
+        ref_trigger::check_ref_name(&name).map_err(bad_request)?;
+        let ref_name = match trigger.ref_type {
+            RefType::Branch => format!("refs/heads/{}", name),
+            RefType::Tag => format!("refs/tags/{}", name),
+        };
+        let refs = self
+            .storage
+            .get_all_refs_by_path(&trigger.repo_path)
+            .await
+            .map_err(internal_error)?;
+        if refs.iter().any(|r| r.ref_name == ref_name) {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} already exists in {}", ref_name, trigger.repo_path),
+            ));
+        }
+        let commit_id = ObjectLoader::new(self.storage.clone())
+            .resolve_ref(&trigger.repo_path, Some(&trigger.source_ref))
+            .await?;
+
+        let (target, tag_id) = match trigger.ref_type {
+            RefType::Branch => (commit_id, None),
+            RefType::Tag => {
+                let tag_id = self.create_tag(trigger, &name, &commit_id, now).await?;
+                (tag_id, Some(tag_id))
+            }
+        };
+        self.ref_updater
+            .update(
+                &trigger.repo_path,
+                &ref_name,
+                None,
+                &target,
+                &format!("trigger:{}", trigger.name),
+                &format!("scheduled by ref trigger {}", trigger.name),
+            )
+            .await?;
+        Ok(RefTriggerRun {
+            ref_name,
+            commit_id: commit_id.to_plain_str(),
+            tag_id: tag_id.map(|id| id.to_plain_str()),
+            signed: tag_id.is_some() && self.signing_key.is_some(),
+        })
+    }
+
+    /// Store an annotated tag of `commit_id`, signed with the server key when there is one.
+    async fn create_tag(
+        &self,
+        trigger: &mega_ref_trigger::Model,
+        name: &str,
+        commit_id: &SHA1,
+        now: NaiveDateTime,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let mut data = format!(
+            "object {}\ntype commit\ntag {}\ntagger {} <{}> {} +0000\n\nCreated by ref trigger {}\n",
+            commit_id.to_plain_str(),
+            name,
+            DEFAULT_COMMITTER.0,
+            DEFAULT_COMMITTER.1,
+            now.and_utc().timestamp(),
+            trigger.name
+        );
+        if let Some(key) = &self.signing_key {
+            // git appends the signature of a tag to its message
+            let signature = signing::ssh_sign(key, signing::SSH_GIT_NAMESPACE, data.as_bytes());
+            data.push_str(&signature);
+        }
+        let data = data.into_bytes();
+        let tag_id = SHA1::from_type_and_data(ObjectType::Tag, &data);
+        self.storage
+            .save_obj_data(
+                None,
+                vec![objects::ActiveModel {
+                    id: Set(generate_id()),
+                    git_id: Set(tag_id.to_plain_str()),
+                    object_type: Set(ObjectType::Tag.to_string()),
+                    data: Set(data),
+                    link: Set(None),
+                }],
+            )
+            .await
+            .map_err(internal_error)?;
+        Ok(tag_id)
+    }
+}
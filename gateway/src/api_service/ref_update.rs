@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use sea_orm::{ActiveValue::NotSet, Set};
+
+use common::utils::{generate_id, ZERO_ID};
+use db_entity::mega_ref_audit;
+use entity::refs;
+use jupiter::storage::ref_audit_storage::RefAuditStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+
+use crate::api_service::archive::PathArchives;
+use crate::api_service::event_service::{EventService, EVENT_PUSH};
+use crate::model::event::RefPush;
+
+/// Moves refs on behalf of the server and keeps an audit entry of every update.
+///
+/// Pushes update refs through the pack protocol, everything the server does on its own (merging
+/// a merge request, scheduled ref triggers) goes through here instead. Refs of archived or
+/// deleted paths are not moved.
+#[derive(Clone)]
+pub struct RefUpdater {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub audit_storage: RefAuditStorage,
+    pub events: EventService,
+    pub archives: PathArchives,
+}
+
+impl RefUpdater {
+    /// Point `ref_name` at `new_id`. `old_id` is what the caller saw the ref point to, `None` when
+    /// the ref is created.
+    pub async fn update(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+        old_id: Option<&SHA1>,
+        new_id: &SHA1,
+        actor: &str,
+        reason: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        self.archives.check_writable(repo_path).await?;
+        let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+        let now = chrono::Utc::now().naive_utc();
+        self.storage
+            .save_refs(vec![refs::ActiveModel {
+                id: NotSet,
+                repo_path: Set(repo_path.to_owned()),
+                ref_name: Set(ref_name.to_owned()),
+                ref_git_id: Set(new_id.to_plain_str()),
+                created_at: Set(now),
+                updated_at: Set(now),
+            }])
+            .await
+            .map_err(|e| internal(e.to_string()))?;
+        let action = if old_id.is_some() {
+            "updated"
+        } else {
+            "created"
+        };
+        self.record(
+            repo_path,
+            ref_name,
+            old_id.map(|id| id.to_plain_str()),
+            Some(new_id.to_plain_str()),
+            actor,
+            reason,
+            action,
+        )
+        .await
+    }
+
+    /// Delete `ref_name`, which the caller saw point to `old_id`.
+    pub async fn delete(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+        old_id: &SHA1,
+        actor: &str,
+        reason: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        self.archives.check_writable(repo_path).await?;
+        self.tombstone(repo_path, ref_name, old_id, actor, reason)
+            .await
+    }
+
+    /// Delete `ref_name` of a path being deleted, which may already be archived.
+    pub async fn tombstone(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+        old_id: &SHA1,
+        actor: &str,
+        reason: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        self.storage
+            .remove_ref(repo_path, ref_name)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        self.record(
+            repo_path,
+            ref_name,
+            Some(old_id.to_plain_str()),
+            None,
+            actor,
+            reason,
+            "deleted",
+        )
+        .await
+    }
+
+    /// Audit a ref change and publish it, `new_id` is `None` when the ref was deleted.
+    #[allow(clippy::too_many_arguments)]
+    async fn record(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+        old_id: Option<String>,
+        new_id: Option<String>,
+        actor: &str,
+        reason: &str,
+        action: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        self.audit_storage
+            .save_audit(mega_ref_audit::Model {
+                id: generate_id(),
+                repo_path: repo_path.to_owned(),
+                ref_name: ref_name.to_owned(),
+                old_id: old_id.clone(),
+                new_id: new_id.clone().unwrap_or_else(|| ZERO_ID.to_owned()),
+                actor: actor.to_owned(),
+                reason: reason.to_owned(),
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let push = RefPush {
+            ref_name: ref_name.to_owned(),
+            before: old_id,
+            after: new_id,
+            reason: Some(reason.to_owned()),
+        };
+        self.events
+            .publish(EVENT_PUSH, action, repo_path, Some(actor), &push)
+            .await;
+        Ok(())
+    }
+}
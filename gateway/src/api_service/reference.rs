@@ -0,0 +1,457 @@
+//! References in the markdown of issues, merge requests, review comments and reviews: `@user`,
+//! `#123` for the issue of that number in the repository, `!45` for the merge request of that
+//! id and commit ids of 7 to 40 hex digits. Code spans and fenced code blocks are left out.
+//!
+//! The references of a text are resolved when it is saved and stored as backlinks, only those
+//! naming something that exists in the repository. Texts are returned with the links of their
+//! stored references next to the ones of the autolink rules.
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::mega_reference;
+use jupiter::storage::issue_storage::IssueStorage;
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use jupiter::storage::mr_storage::MrStorage;
+use jupiter::storage::reference_storage::{ReferenceStorage, TARGET_COMMIT, TARGET_USER};
+use jupiter::storage::user_storage::UserStorage;
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::api_service::autolink;
+use crate::model::autolink::Autolink;
+use crate::model::reference::{Backlink, BacklinkQuery};
+
+const MIN_COMMIT_ID: usize = 7;
+const MAX_COMMIT_ID: usize = 40;
+
+/// Most references of a text resolved, each lookup is a query.
+const MAX_REFERENCES: usize = 50;
+
+/// A reference found in a text.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextReference {
+    /// `user`, `issue`, `mr` or `commit`
+    pub target_type: &'static str,
+    /// The reference as written, e.g. `#12`
+    pub text: String,
+    /// The user name, issue number, merge request id or commit id written
+    pub key: String,
+    /// Byte offsets in the text, the end excluded
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The byte ranges of the code spans and fenced code blocks of markdown `text`.
+fn code_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut fence: Option<(usize, &str)> = None;
+    let mut pos = 0;
+    for line in text.split_inclusive('\n') {
+        let start = pos;
+        pos += line.len();
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+        match (fence, marker) {
+            (Some((open, m)), Some(marker)) if m == marker => {
+                ranges.push(open..pos);
+                fence = None;
+            }
+            (Some(_), _) => {}
+            (None, Some(marker)) => fence = Some((start, marker)),
+            (None, None) => ranges.extend(code_spans(line, start)),
+        }
+    }
+    if let Some((open, _)) = fence {
+        ranges.push(open..text.len());
+    }
+    ranges
+}
+
+/// The code spans of a line starting at `offset`: a run of backticks up to the next run of as
+/// many.
+fn code_spans(line: &str, offset: usize) -> Vec<Range<usize>> {
+    let bytes = line.as_bytes();
+    let run = |from: usize| bytes[from..].iter().take_while(|b| **b == b'`').count();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+        let len = run(i);
+        let mut j = i + len;
+        let mut close = None;
+        while j < bytes.len() {
+            if bytes[j] == b'`' {
+                let n = run(j);
+                if n == len {
+                    close = Some(j + n);
+                    break;
+                }
+                j += n;
+            } else {
+                j += 1;
+            }
+        }
+        match close {
+            Some(end) => {
+                spans.push(offset + i..offset + end);
+                i = end;
+            }
+            None => i += len,
+        }
+    }
+    spans
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The references of markdown `text`, in the order they appear.
+pub fn parse_references(text: &str) -> Vec<TextReference> {
+    let code = code_ranges(text);
+    let mut references = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut next = 0;
+    for (i, c) in text.char_indices() {
+        let before = prev;
+        prev = Some(c);
+        if i < next || code.iter().any(|r| r.contains(&i)) {
+            continue;
+        }
+        if before.is_some_and(|p| is_word(p) || p == '&' || p == '/') {
+            continue;
+        }
+        let rest = &text[i + c.len_utf8()..];
+        let (target_type, key) = match c {
+            '@' => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                    .unwrap_or(rest.len());
+                // a sentence ending right after the name
+                (TARGET_USER, rest[..end].trim_end_matches('.'))
+            }
+            '#' | '!' => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let target_type = if c == '#' { ITEM_ISSUE } else { ITEM_MR };
+                (target_type, &rest[..end])
+            }
+            c if c.is_ascii_hexdigit() => {
+                let end = text[i..]
+                    .find(|c: char| !c.is_ascii_hexdigit())
+                    .map_or(text.len(), |end| i + end);
+                let key = &text[i..end];
+                if !(MIN_COMMIT_ID..=MAX_COMMIT_ID).contains(&key.len()) {
+                    next = end;
+                    continue;
+                }
+                (TARGET_COMMIT, key)
+            }
+            _ => continue,
+        };
+        if key.is_empty() {
+            continue;
+        }
+        let prefix = if target_type == TARGET_COMMIT { 0 } else { 1 };
+        let end = i + prefix + key.len();
+        next = end;
+        if text[end..].chars().next().is_some_and(is_word) && target_type != TARGET_USER {
+            continue;
+        }
+        references.push(TextReference {
+            target_type,
+            text: text[i..end].to_owned(),
+            key: key.to_ascii_lowercase(),
+            start: i,
+            end,
+        });
+    }
+    references
+}
+
+fn target_url(base_url: &str, target_type: &str, target_id: &str) -> String {
+    match target_type {
+        ITEM_ISSUE => format!("{}/api/v1/issues/{}", base_url, target_id),
+        ITEM_MR => format!("{}/api/v1/mr/{}", base_url, target_id),
+        TARGET_COMMIT => format!("{}/api/v1/commit?object_id={}", base_url, target_id),
+        _ => format!("{}/api/v1/users/{}", base_url, target_id),
+    }
+}
+
+/// The links of the references of `text` stored in `references`, linking under `base_url`.
+pub fn links(text: &str, references: &[mega_reference::Model], base_url: &str) -> Vec<Autolink> {
+    parse_references(text)
+        .into_iter()
+        .filter_map(|found| {
+            let stored = references.iter().find(|r| r.text == found.text)?;
+            Some(Autolink {
+                url: target_url(base_url, &stored.target_type, &stored.target_id),
+                text: found.text,
+                start: found.start,
+                end: found.end,
+            })
+        })
+        .collect()
+}
+
+/// The links of `text`: the ones of its stored `references`, then the ones of the autolink rules
+/// not overlapping them.
+pub fn with_references(
+    text: &str,
+    references: &[mega_reference::Model],
+    rule_links: Vec<Autolink>,
+    base_url: &str,
+) -> Vec<Autolink> {
+    let mut all = links(text, references, base_url);
+    all.extend(rule_links);
+    autolink::without_overlaps(all)
+}
+
+/// Resolves the references of texts when they are saved, and loads them back to link them.
+#[derive(Clone)]
+pub struct Referencer {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub reference_storage: ReferenceStorage,
+    pub issue_storage: IssueStorage,
+    pub mr_storage: MrStorage,
+    pub user_storage: UserStorage,
+}
+
+impl Referencer {
+    /// Replace the references of the text `source_id` of type `source_type`, which belongs to
+    /// the issue or merge request `subject_id` of `repo_path`, and return them. A failure is
+    /// logged and leaves the text without references.
+    pub async fn record(
+        &self,
+        repo_path: &str,
+        source_type: &str,
+        source_id: i64,
+        subject_id: i64,
+        text: &str,
+    ) -> Vec<mega_reference::Model> {
+        match self
+            .resolve(repo_path, source_type, source_id, subject_id, text)
+            .await
+        {
+            Ok(references) => references,
+            Err(e) => {
+                tracing::warn!(
+                    "unable to record the references of {} {}: {}",
+                    source_type,
+                    source_id,
+                    e
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    async fn resolve(
+        &self,
+        repo_path: &str,
+        source_type: &str,
+        source_id: i64,
+        subject_id: i64,
+        text: &str,
+    ) -> Result<Vec<mega_reference::Model>, MegaError> {
+        let mut found = parse_references(text);
+        let mut seen = Vec::new();
+        found.retain(|r| {
+            let new = !seen.contains(&r.text);
+            if new {
+                seen.push(r.text.clone());
+            }
+            new
+        });
+        found.truncate(MAX_REFERENCES);
+        let names: Vec<String> = found
+            .iter()
+            .filter(|r| r.target_type == TARGET_USER)
+            .map(|r| r.text[1..].to_owned())
+            .collect();
+        let users = self.user_storage.get_users_by_names(&names).await?;
+        let now = chrono::Utc::now().naive_utc();
+        let mut references = Vec::new();
+        for reference in found {
+            let target_id = match reference.target_type {
+                TARGET_USER => users
+                    .iter()
+                    .find(|u| u.name == reference.text[1..])
+                    .map(|u| u.name.clone()),
+                ITEM_ISSUE => match reference.key.parse() {
+                    Ok(number) => self
+                        .issue_storage
+                        .find_by_number(repo_path, number)
+                        .await?
+                        .map(|issue| issue.id.to_string()),
+                    Err(_) => None,
+                },
+                ITEM_MR => match reference.key.parse() {
+                    Ok(id) => self
+                        .mr_storage
+                        .get_mr(id)
+                        .await?
+                        .filter(|mr| mr.repo_path == repo_path)
+                        .map(|mr| mr.id.to_string()),
+                    Err(_) => None,
+                },
+                _ => {
+                    let commits = self
+                        .storage
+                        .get_commits_by_prefix(&reference.key, repo_path, 2)
+                        .await?;
+                    // an ambiguous prefix refers to nothing
+                    match commits.as_slice() {
+                        [commit] => Some(commit.git_id.clone()),
+                        _ => None,
+                    }
+                }
+            };
+            let Some(target_id) = target_id else {
+                continue;
+            };
+            if reference.target_type == source_type && target_id == source_id.to_string() {
+                continue;
+            }
+            references.push(mega_reference::Model {
+                id: generate_id(),
+                repo_path: repo_path.to_owned(),
+                source_type: source_type.to_owned(),
+                source_id,
+                subject_id,
+                target_type: reference.target_type.to_owned(),
+                target_id,
+                text: reference.text,
+                created_at: now,
+            });
+        }
+        self.reference_storage
+            .replace_references(source_type, source_id, references.clone())
+            .await?;
+        Ok(references)
+    }
+
+    /// The texts of a repository referring to a user, issue, merge request or commit.
+    pub async fn backlinks(
+        &self,
+        query: BacklinkQuery,
+    ) -> Result<Json<Vec<Backlink>>, (StatusCode, String)> {
+        let target_type = query.target_type.as_str();
+        if ![TARGET_USER, ITEM_ISSUE, ITEM_MR, TARGET_COMMIT].contains(&target_type) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("unknown reference target: {}", target_type),
+            ));
+        }
+        let references = self
+            .reference_storage
+            .list_backlinks(&query.repo_path, target_type, query.target_id.trim())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(Json(references.into_iter().map(Backlink::from).collect()))
+    }
+
+    /// The stored references of the texts `source_ids` of type `source_type`, by text id.
+    pub async fn references_of(
+        &self,
+        source_type: &str,
+        source_ids: &[i64],
+    ) -> Result<HashMap<i64, Vec<mega_reference::Model>>, MegaError> {
+        let mut by_source: HashMap<i64, Vec<mega_reference::Model>> = HashMap::new();
+        for reference in self
+            .reference_storage
+            .list_by_sources(source_type, source_ids)
+            .await?
+        {
+            by_source
+                .entry(reference.source_id)
+                .or_default()
+                .push(reference);
+        }
+        Ok(by_source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str) -> Vec<(&'static str, String, String)> {
+        parse_references(text)
+            .into_iter()
+            .map(|r| (r.target_type, r.text, r.key))
+            .collect()
+    }
+
+    fn reference(text: &str, target_type: &str, target_id: &str) -> mega_reference::Model {
+        mega_reference::Model {
+            id: 1,
+            repo_path: "/project".to_owned(),
+            source_type: ITEM_ISSUE.to_owned(),
+            source_id: 2,
+            subject_id: 2,
+            target_type: target_type.to_owned(),
+            target_id: target_id.to_owned(),
+            text: text.to_owned(),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let own = |t: &'static str, text: &str, key: &str| (t, text.to_owned(), key.to_owned());
+        assert_eq!(
+            texts("cc @alice, see #12 and !45. Fixed in 1A2b3c4d.\nThanks @bob."),
+            [
+                own(TARGET_USER, "@alice", "alice"),
+                own(ITEM_ISSUE, "#12", "12"),
+                own(ITEM_MR, "!45", "45"),
+                own(TARGET_COMMIT, "1A2b3c4d", "1a2b3c4d"),
+                own(TARGET_USER, "@bob", "bob"),
+            ]
+        );
+        // emails, anchors, words, short hex and ids longer than a commit id
+        assert!(texts(
+            "me@example.com a#1 #1a &#39; cafe 1234567890abcdef1234567890abcdef12345678a"
+        )
+        .is_empty());
+        assert!(texts("https://example.com/commit/1a2b3c4d").is_empty());
+    }
+
+    #[test]
+    fn test_parse_references_skips_code() {
+        let text = "see `#1` and ``a ` @x`` but #2\n```\n#3 @y\n```\n~~~rust\n!4\n";
+        assert_eq!(texts(text), [(ITEM_ISSUE, "#2".to_owned(), "2".to_owned())]);
+        let found = parse_references(text);
+        assert_eq!(&text[found[0].start..found[0].end], "#2");
+    }
+
+    #[test]
+    fn test_links() {
+        let text = "#12 by @alice, not #13, in abc1234";
+        let references = [
+            reference("#12", ITEM_ISSUE, "9001"),
+            reference("@alice", TARGET_USER, "alice"),
+            reference("abc1234", TARGET_COMMIT, "abc1234ffff"),
+        ];
+        let links = links(text, &references, "https://mega.example.com");
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].url, "https://mega.example.com/api/v1/issues/9001");
+        assert_eq!((links[0].start, links[0].end), (0, 3));
+        assert_eq!(links[1].url, "https://mega.example.com/api/v1/users/alice");
+        assert_eq!(
+            links[2].url,
+            "https://mega.example.com/api/v1/commit?object_id=abc1234ffff"
+        );
+        assert_eq!(links[2].text, "abc1234");
+    }
+}
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::Json;
+use sha2::{Digest, Sha256};
+
+use common::utils::generate_id;
+use db_entity::{mega_release, mega_release_asset};
+use jupiter::storage::release_storage::ReleaseStorage;
+use storage::driver::database::storage::ObjectStorage;
+use storage::driver::file_storage::FileStorage;
+
+use crate::api_service::object_loader::ObjectLoader;
+use crate::model::release::{NewRelease, Release, ReleaseUpdate};
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Releases published from the tags of a repository, with files such as binaries attached.
+#[derive(Clone)]
+pub struct ReleaseService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub release_storage: ReleaseStorage,
+    /// Content store holding the assets, under the SHA-256 of their content
+    pub assets: Arc<dyn FileStorage>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+/// Check that `name` can be used as the file name of an asset: it is downloaded under that
+/// name, so it can't hold a path or characters headers can't carry.
+pub fn check_asset_name(name: &str) -> Result<(), (StatusCode, String)> {
+    let valid = !name.trim().is_empty()
+        && name.len() <= 255
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '"'])
+        && !name.chars().any(char::is_control);
+    if valid {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            format!("invalid asset name: {:?}", name),
+        ))
+    }
+}
+
+impl ReleaseService {
+    async fn get_release(&self, id: i64) -> Result<mega_release::Model, (StatusCode, String)> {
+        match self.release_storage.get_release(id).await {
+            Ok(Some(release)) => Ok(release),
+            Ok(None) => Err((StatusCode::NOT_FOUND, format!("release {} not found", id))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// The commit tag `tag_name` points to, `None` when there is no such tag.
+    async fn tagged_commit(
+        &self,
+        loader: &mut ObjectLoader,
+        repo_path: &str,
+        tag_name: &str,
+    ) -> Result<Option<String>, (StatusCode, String)> {
+        let tag = format!("refs/tags/{}", tag_name);
+        match loader.resolve_ref(repo_path, Some(&tag)).await {
+            Ok(id) => Ok(Some(loader.peel(&id).await?.to_plain_str())),
+            Err((status, _)) if status == StatusCode::NOT_FOUND => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn release(
+        &self,
+        loader: &mut ObjectLoader,
+        release: mega_release::Model,
+    ) -> Result<Release, (StatusCode, String)> {
+        let commit_id = self
+            .tagged_commit(loader, &release.repo_path, &release.tag_name)
+            .await?;
+        let assets = self
+            .release_storage
+            .list_assets(release.id)
+            .await
+            .map_err(internal_error)?;
+        Ok(Release::new(release, commit_id, assets))
+    }
+
+    pub async fn list(&self, repo_path: &str) -> Result<Json<Vec<Release>>, (StatusCode, String)> {
+        let releases = self
+            .release_storage
+            .list_releases(repo_path)
+            .await
+            .map_err(internal_error)?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut res = Vec::with_capacity(releases.len());
+        for release in releases {
+            res.push(self.release(&mut loader, release).await?);
+        }
+        Ok(Json(res))
+    }
+
+    pub async fn detail(&self, id: i64) -> Result<Json<Release>, (StatusCode, String)> {
+        let release = self.get_release(id).await?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        Ok(Json(self.release(&mut loader, release).await?))
+    }
+
+    /// Publish a release of an existing tag, a tag has at most one release.
+    pub async fn publish(
+        &self,
+        new_release: NewRelease,
+        author: String,
+    ) -> Result<Json<Release>, (StatusCode, String)> {
+        let tag_name = new_release.tag_name.trim_start_matches("refs/tags/");
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        if self
+            .tagged_commit(&mut loader, &new_release.repo_path, tag_name)
+            .await?
+            .is_none()
+        {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("tag {} not found in {}", tag_name, new_release.repo_path),
+            ));
+        }
+        let title = new_release
+            .title
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| tag_name.to_owned());
+        let now = chrono::Utc::now().naive_utc();
+        let release = mega_release::Model {
+            id: generate_id(),
+            repo_path: new_release.repo_path,
+            tag_name: tag_name.to_owned(),
+            title,
+            notes: new_release.notes,
+            author,
+            created_at: now,
+            updated_at: now,
+        };
+        if !self
+            .release_storage
+            .create_release(release.clone())
+            .await
+            .map_err(internal_error)?
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("tag {} already has a release", release.tag_name),
+            ));
+        }
+        Ok(Json(self.release(&mut loader, release).await?))
+    }
+
+    pub async fn update(
+        &self,
+        id: i64,
+        update: ReleaseUpdate,
+    ) -> Result<Json<Release>, (StatusCode, String)> {
+        let mut release = self.get_release(id).await?;
+        if let Some(title) = update.title {
+            if title.trim().is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "title can't be empty".to_owned()));
+            }
+            release.title = title;
+        }
+        if let Some(notes) = update.notes {
+            release.notes = notes;
+        }
+        release.updated_at = chrono::Utc::now().naive_utc();
+        let release = self
+            .release_storage
+            .update_release(release)
+            .await
+            .map_err(internal_error)?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        Ok(Json(self.release(&mut loader, release).await?))
+    }
+
+    /// Delete a release and its assets, the tag is kept.
+    pub async fn delete(&self, id: i64) -> Result<StatusCode, (StatusCode, String)> {
+        self.get_release(id).await?;
+        let assets = self
+            .release_storage
+            .delete_release(id)
+            .await
+            .map_err(internal_error)?;
+        for asset in assets {
+            self.remove_content(&asset.sha256).await;
+        }
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// Attach `data` to a release as `name`, replacing the asset of that name.
+    pub async fn upload_asset(
+        &self,
+        id: i64,
+        name: &str,
+        content_type: Option<&str>,
+        data: Bytes,
+        uploader: String,
+    ) -> Result<Json<Release>, (StatusCode, String)> {
+        check_asset_name(name)?;
+        let release = self.get_release(id).await?;
+        let sha256 = format!("{:x}", Sha256::digest(&data));
+        if !self.assets.exist(&sha256) {
+            self.assets
+                .put(&sha256, data.len() as i64, &data)
+                .await
+                .map_err(internal_error)?;
+        }
+        let replaced = self
+            .release_storage
+            .get_asset(id, name)
+            .await
+            .map_err(internal_error)?;
+        self.release_storage
+            .save_asset(mega_release_asset::Model {
+                id: generate_id(),
+                release_id: id,
+                name: name.to_owned(),
+                content_type: content_type
+                    .filter(|value| !value.trim().is_empty())
+                    .unwrap_or(DEFAULT_CONTENT_TYPE)
+                    .to_owned(),
+                size: data.len() as i64,
+                sha256: sha256.clone(),
+                uploader,
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+            .await
+            .map_err(internal_error)?;
+        if let Some(replaced) = replaced.filter(|asset| asset.sha256 != sha256) {
+            self.remove_content(&replaced.sha256).await;
+        }
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        Ok(Json(self.release(&mut loader, release).await?))
+    }
+
+    async fn get_asset(
+        &self,
+        id: i64,
+        name: &str,
+    ) -> Result<mega_release_asset::Model, (StatusCode, String)> {
+        match self.release_storage.get_asset(id, name).await {
+            Ok(Some(asset)) => Ok(asset),
+            Ok(None) => Err((
+                StatusCode::NOT_FOUND,
+                format!("release {} has no asset {}", id, name),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// The content of an asset, as a file to save under its name.
+    pub async fn download_asset(
+        &self,
+        id: i64,
+        name: &str,
+    ) -> Result<Response, (StatusCode, String)> {
+        let asset = self.get_asset(id, name).await?;
+        let data = self
+            .assets
+            .get(&asset.sha256)
+            .await
+            .map_err(internal_error)?;
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, &asset.content_type)
+            .header(header::CONTENT_LENGTH, data.len())
+            .header(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", asset.name),
+            )
+            .header(header::ETAG, format!("\"{}\"", asset.sha256))
+            .body(Body::from(data))
+            .unwrap())
+    }
+
+    pub async fn delete_asset(
+        &self,
+        id: i64,
+        name: &str,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let asset = self.get_asset(id, name).await?;
+        self.release_storage
+            .delete_asset(asset.id)
+            .await
+            .map_err(internal_error)?;
+        self.remove_content(&asset.sha256).await;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// Remove the content stored under `sha256` unless another asset has the same content.
+    async fn remove_content(&self, sha256: &str) {
+        match self.release_storage.content_in_use(sha256).await {
+            Ok(false) => {
+                if let Err(e) = self.assets.remove(sha256).await {
+                    tracing::warn!("unable to remove release asset {}: {}", sha256, e);
+                }
+            }
+            Ok(true) => {}
+            Err(e) => tracing::warn!("unable to check release asset {}: {}", sha256, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_asset_name;
+
+    #[test]
+    fn test_check_asset_name() {
+        assert!(check_asset_name("mega-0.1.0-x86_64-linux.tar.gz").is_ok());
+        assert!(check_asset_name("checksums (sha256).txt").is_ok());
+        assert!(check_asset_name("").is_err());
+        assert!(check_asset_name("..").is_err());
+        assert!(check_asset_name("bin/mega").is_err());
+        assert!(check_asset_name("a\\b").is_err());
+        assert!(check_asset_name("say \"hi\"").is_err());
+        assert!(check_asset_name("line\nbreak").is_err());
+        assert!(check_asset_name(&"a".repeat(256)).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+use bytes::Bytes;
+
+use common::missing_objects;
+use common::operation::{self, Operation, OperationKind};
+use common::utils::generate_id;
+use git::internal::budget::MemoryBudget;
+use git::protocol::pack;
+use git::protocol::profile::PushProfile;
+use jupiter::storage::mirror_storage::MirrorStorage;
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::api_service::path_move;
+use crate::api_service::remote::{self, ImportThrottle, RemoteClone};
+use crate::model::repair::{RepairRequest, RepairResult};
+
+/// Stores again the objects found missing from a repository, fetching them from the upstream of
+/// its mirror or from other copies of it.
+#[derive(Clone)]
+pub struct RepairService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub mirror_storage: MirrorStorage,
+    /// Shared with the imports and mirror syncs, which clone from the same hosts
+    pub throttle: ImportThrottle,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+impl RepairService {
+    /// Fetch the objects missing from the repository of `request`, and those met without knowing
+    /// the repository, from each source in turn until none is left.
+    pub async fn repair(
+        &self,
+        request: RepairRequest,
+    ) -> Result<Json<RepairResult>, (StatusCode, String)> {
+        let repo_path = path_move::normalize_path(&request.repo_path)
+            .ok_or_else(|| bad_request(format!("invalid path {}", request.repo_path)))?;
+        let mut wanted: Vec<String> = missing_objects::list()
+            .into_iter()
+            .filter(|gap| gap.repo_path.as_deref().is_none_or(|p| p == repo_path))
+            .map(|gap| gap.id)
+            .collect();
+        let mut sources = Vec::new();
+        if let Some(mirror) = self
+            .mirror_storage
+            .find_mirror(&repo_path)
+            .await
+            .map_err(internal_error)?
+        {
+            sources.push(mirror.url);
+        }
+        for url in &request.urls {
+            let url = url.trim();
+            remote::check_url(url).map_err(bad_request)?;
+            sources.push(url.to_owned());
+        }
+        let mut result = RepairResult::default();
+        if wanted.is_empty() {
+            return Ok(Json(result));
+        }
+        if sources.is_empty() {
+            return Err(bad_request(format!(
+                "{} isn't a mirror, give the urls of other copies to repair it from",
+                repo_path
+            )));
+        }
+
+        let operation = operation::start(OperationKind::Repair, &repo_path);
+        for url in &sources {
+            if wanted.is_empty() {
+                break;
+            }
+            operation.check().map_err(remote::cancelled_error)?;
+            let shown = remote::strip_credentials(url);
+            result.sources.push(shown.clone());
+            match self.fetch(&operation, url, &wanted).await {
+                Ok(found) => {
+                    for id in &found {
+                        missing_objects::resolve(id);
+                    }
+                    wanted.retain(|id| !found.contains(id));
+                    result.repaired.extend(found);
+                }
+                Err(err) if operation.is_cancelled() => return Err(err),
+                Err((_, err)) => {
+                    tracing::warn!("unable to repair {} from {}: {}", repo_path, shown, err);
+                    result.errors.push(format!("{}: {}", shown, err));
+                }
+            }
+        }
+        result.missing = wanted;
+        Ok(Json(result))
+    }
+
+    /// Store those of `wanted` the remote at `url` has, returning them.
+    async fn fetch(
+        &self,
+        operation: &Operation,
+        url: &str,
+        wanted: &[String],
+    ) -> Result<Vec<String>, (StatusCode, String)> {
+        let _permit = self.throttle.acquire().await;
+        operation.set_stage("cloning", 0);
+        let clone = remote::cancellable(
+            operation,
+            RemoteClone::fetch(url, &format!("repair-{}", generate_id())),
+        )
+        .await?;
+        let ids: Vec<&str> = wanted.iter().map(String::as_str).collect();
+        let mut found: Vec<String> = clone.contains(&ids).await?.into_iter().collect();
+        if found.is_empty() {
+            return Ok(found);
+        }
+        found.sort();
+        let ids: Vec<&str> = found.iter().map(String::as_str).collect();
+        operation.set_stage("packing", 0);
+        let pack = remote::cancellable(operation, clone.pack_objects(&ids)).await?;
+        drop(clone);
+        operation.check().map_err(remote::cancelled_error)?;
+        operation.set_stage("storing", found.len() as u64);
+        let mut pack = Bytes::from(pack);
+        pack::unpack(
+            self.storage.clone(),
+            &mut pack,
+            MemoryBudget::from_env(),
+            &mut PushProfile::default(),
+        )
+        .await
+        .map_err(internal_error)?;
+        operation.advance(found.len() as u64);
+        Ok(found)
+    }
+}
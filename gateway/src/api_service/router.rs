@@ -1,25 +1,189 @@
 use std::collections::HashMap;
 
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Extension, OriginalUri, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
+use common::{missing_objects, operation};
 use git::internal::pack::counter::GitTypeCounter;
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 
 use crate::{
-    api_service::obj_service::ObjectService,
+    api_service::{
+        account_service::AccountService, acl_service::AclService, archive::PathArchives,
+        archive_service::ArchiveService, autolink_service::AutolinkService, blame_service::BlameService, board_service::BoardService, bundle_service::BundleService, changelog_service::ChangelogService, check_service::CheckService, ci_log_service::CiLogService, erasure_service::ErasureService,
+        error::typed_errors,
+        event_service::EventService, feature_flag_service::FeatureFlagService,
+        import_service::ImportService, issue_service::IssueService, markdown,
+        merge_queue_service::MergeQueueService, merge_service::MergeService,
+        mirror_service::MirrorService, mr_review_service::MrReviewService, mr_service::MrService,
+        notification_service::NotificationService, obj_service::ObjectService,
+        object_batch::ObjectBatchService,
+        oidc_service::OidcService,
+        path_move::PathRedirects, path_move_service::PathMoveService,
+        pipeline_service::PipelineService,
+        planning_service::PlanningService, push_profile_service::PushProfileService,
+        reaction_service::{ReactionService, ReactionSubject},
+        ref_hook_service::RefHookService,
+        ref_trigger_service::RefTriggerService, reference::Referencer,
+        release_service::ReleaseService,
+        repair_service::RepairService,
+        search_service::SearchService, signing_key_service::SigningKeyService,
+        snapshot_export_service::SnapshotExportService, snapshot_service::SnapshotService,
+        snippet_service::SnippetService,
+        ssh_key_service::SshKeyService, stats_service::StatsService,
+        webhook_service::WebhookService,
+        wiki_service::WikiService,
+    },
+    auth::{
+        acl::Permission,
+        http::{check_permission, HttpAuth},
+        Identity,
+    },
+    i18n::{self, Locale},
     model::{
-        objects::{BlobObjects, Directories},
-        query::DirectoryQuery,
+        account::{
+            AccessToken, IssuedToken, MemberUpdate, Membership, NewAccessToken, NewOrg, NewUser,
+            OidcCallbackQuery, OidcLoginQuery, Org, OrgMember, User, UserUpdate,
+        },
+        acl::{AccessQuery, NewPathGrant, PathAccess, PathGrant, PathGrantQuery},
+        archive::{PathArchive, PathArchiveRequest},
+        autolink::{AutolinkRule, NewAutolinkRule},
+        blame::BlameResult,
+        board::{
+            Board, BoardDetail, BoardListQuery, BoardQuery, BoardUpdate, CardMove, ColumnUpdate,
+            NewBoard, NewCard, NewColumn,
+        },
+        bundle::{BundleImport, BundleQuery},
+        changelog::{Changelog, ChangelogQuery},
+        check::{
+            CheckQuery, CheckRun, CommitChecks, NewCheckRun, RequiredChecks, RequiredChecksQuery,
+        },
+        ci_log::{CiJob, CiJobQuery, CiLog, CiLogFinish, CiLogQuery},
+        erasure::{ErasureQuery, ErasureReport, ErasureRequest},
+        error::ApiError,
+        event::EventQuery,
+        feature_flag::{FeatureFlag, FeatureFlagQuery, FeatureFlagStatus, FeatureFlagUpdate},
+        import::{
+            ImportJob, ImportJobRequest, ImportJobRequeued, ImportQuery, ImportRequest, RepoImport,
+        },
+        issue::{Issue, IssueDetail, IssueQuery, IssueUpdate, NewIssue},
+        markdown::{MarkdownPreview, Readme, ReadmeQuery, RenderedMarkdown},
+        merge::{
+            CompareQuery, Comparison, MergeBaseBatch, MergeCheck, MergeCheckQuery, PickRequest,
+            PickResult, RefComparison,
+        },
+        merge_queue::{MergeQueueEntry, MergeQueueQuery},
+        mirror::{Mirror, MirrorSync, MirrorUpdate},
+        mr::{
+            BranchUpdate, MergeOptions, MergeRequest, MergeRequestDetail, MergeRequestQuery,
+            NewMergeRequest, UpdateBranchOptions,
+        },
+        notification::{
+            MarkRead, MarkedRead, NotificationFeed, NotificationPrefs, NotificationQuery,
+            SetSubscription, Subscription,
+        },
+        objects::{
+            BlobObjects, Capabilities, CommitSummary, Directories, HighlightFormat, ObjectBatch,
+            RefInfo, Submodule,
+        },
+        operation::OperationStatus,
+        page::{Page, PageQuery},
+        path_move::{PathMove, PathMoveResult, PathRedirect},
+        planning::{
+            ItemAssignees, ItemLabels, ItemMilestone, Label, LabelUpdate, Milestone,
+            MilestoneUpdate, NewLabel, NewMilestone, PlanningQuery,
+        },
+        push_profile::{PushProfile, PushProfileQuery},
+        reaction::{NewReaction, Reaction},
+        query::{
+            BlameQuery, CommitLogQuery, DirectoryQuery, RefQuery, SnapshotQuery, SubmoduleQuery,
+        },
+        ref_hook::{RefHookRequeued, RefHookStatus},
+        ref_trigger::{RefAuditEntry, RefAuditQuery, RefTrigger, RefTriggerRun, RefTriggerUpdate},
+        reference::{Backlink, BacklinkQuery},
+        release::{AssetUpload, NewRelease, Release, ReleaseQuery, ReleaseUpdate},
+        repair::{MissingObjectStatus, RepairRequest, RepairResult},
+        review::{
+            NewComment, NewReview, NewThread, Review, ReviewComment, ReviewSummary, ReviewThread,
+            ThreadQuery,
+        },
+        search::{CodeSearchHit, CodeSearchQuery, SearchHit, SearchQuery, SearchReindex},
+        signing_key::{
+            CommitSignature, CommitSignatureQuery, KeyVerification, NewSigningKey, SignedCommit,
+            SigningKey,
+        },
+        snapshot_export::{
+            SnapshotExport, SnapshotExportRun, SnapshotExportRunQuery, SnapshotExportUpdate,
+        },
+        snippet::{NewSnippet, Snippet, SnippetQuery, SnippetUpdate},
+        ssh_key::{NewSshKey, SshKey},
+        stats::{ContributorStats, LanguageBreakdown, StatsQuery, WeekStats},
+        webhook::{Webhook, WebhookUpdate},
+        wiki::{
+            WikiChange, WikiHistoryQuery, WikiPage, WikiPageDelete, WikiPageQuery, WikiPageUpdate,
+            WikiPages, WikiQuery, WikiRevision,
+        },
     },
 };
 
 #[derive(Clone)]
 pub struct ApiServiceState {
     pub object_service: ObjectService,
+    pub object_batch_service: ObjectBatchService,
+    pub account_service: AccountService,
+    pub acl_service: AclService,
+    pub archive_service: ArchiveService,
+    /// Archived and deleted paths, which refuse the calls writing to them.
+    pub path_archives: PathArchives,
+    pub autolink_service: AutolinkService,
+    /// Identifies the callers whose permissions the ACL checks.
+    pub http_auth: HttpAuth,
+    pub oidc_service: OidcService,
+    pub blame_service: BlameService,
+    pub board_service: BoardService,
+    pub bundle_service: BundleService,
+    pub changelog_service: ChangelogService,
+    pub check_service: CheckService,
+    pub ci_log_service: CiLogService,
+    pub erasure_service: ErasureService,
+    pub event_service: EventService,
+    pub feature_flag_service: FeatureFlagService,
+    pub import_service: ImportService,
+    pub merge_queue_service: MergeQueueService,
+    pub merge_service: MergeService,
+    pub mirror_service: MirrorService,
+    pub mr_service: MrService,
+    pub mr_review_service: MrReviewService,
+    pub notification_service: NotificationService,
+    pub issue_service: IssueService,
+    pub planning_service: PlanningService,
+    pub path_move_service: PathMoveService,
+    pub path_redirects: PathRedirects,
+    pub pipeline_service: PipelineService,
+    pub push_profile_service: PushProfileService,
+    pub reaction_service: ReactionService,
+    pub ref_hook_service: RefHookService,
+    pub ref_trigger_service: RefTriggerService,
+    pub references: Referencer,
+    pub release_service: ReleaseService,
+    pub repair_service: RepairService,
+    pub search_service: SearchService,
+    pub ssh_key_service: SshKeyService,
+    pub signing_key_service: SigningKeyService,
+    pub snapshot_service: SnapshotService,
+    pub snapshot_export_service: SnapshotExportService,
+    pub snippet_service: SnippetService,
+    pub stats_service: StatsService,
+    pub webhook_service: WebhookService,
+    pub wiki_service: WikiService,
 }
 
 pub fn routers<S>(state: ApiServiceState) -> Router<S> {
@@ -27,43 +191,4473 @@ pub fn routers<S>(state: ApiServiceState) -> Router<S> {
         .route("/blob", get(get_blob_object))
         .route("/tree", get(get_directories))
         .route("/object", get(get_origin_object))
+        .route("/commit", get(get_commit))
+        .route("/commits", get(list_commits))
+        .route("/refs", get(list_refs))
         .route("/status", get(life_cycle_check))
         .route("/count-objs", get(get_count_nums))
+        .route("/blame", get(get_blame))
+        .route("/changelog", get(get_changelog))
+        .route("/submodules", get(get_submodules))
+        .route("/readme", get(get_readme))
+        .route("/markdown", post(render_markdown))
+        .route("/archive", get(get_archive))
+        .route("/capabilities", get(get_capabilities))
+        .route("/objects/batch", post(get_object_batch))
+        .route("/merge-check", get(get_merge_check))
+        .route("/merge-bases", post(compare_refs))
+        .route("/compare", get(get_comparison))
+        .route("/cherry-pick", post(cherry_pick))
+        .route("/revert", post(revert_commit))
+        .route("/mr", get(list_mrs).post(create_mr))
+        .route("/mr/:id", get(get_mr))
+        .route("/mr/:id/close", post(close_mr))
+        .route("/mr/:id/reopen", post(reopen_mr))
+        .route("/mr/:id/ready", post(ready_mr))
+        .route("/mr/:id/draft", post(draft_mr))
+        .route("/mr/:id/merge", post(merge_mr))
+        .route("/mr/:id/update-branch", post(update_mr_branch))
+        .route(
+            "/mr/:id/queue",
+            get(get_queued_mr).post(enqueue_mr).delete(dequeue_mr),
+        )
+        .route("/merge-queue", get(list_merge_queue))
+        .route("/mr/:id/labels", put(set_mr_labels))
+        .route("/mr/:id/assignees", put(set_mr_assignees))
+        .route("/mr/:id/milestone", put(set_mr_milestone))
+        .route("/mr/:id/threads", get(list_threads).post(create_thread))
+        .route("/mr/:id/threads/:thread_id/comments", post(add_comment))
+        .route("/mr/:id/threads/:thread_id/resolve", post(resolve_thread))
+        .route(
+            "/mr/:id/threads/:thread_id/unresolve",
+            post(unresolve_thread),
+        )
+        .route("/mr/:id/reviews", get(get_reviews).post(submit_review))
+        .route("/mr/:id/subscription", put(subscribe_mr))
+        .route(
+            "/mr/:id/reactions",
+            get(list_mr_reactions).post(add_mr_reaction),
+        )
+        .route("/mr/:id/reactions/:content", delete(remove_mr_reaction))
+        .route(
+            "/mr/:id/comments/:comment_id/reactions",
+            get(list_comment_reactions).post(add_comment_reaction),
+        )
+        .route(
+            "/mr/:id/comments/:comment_id/reactions/:content",
+            delete(remove_comment_reaction),
+        )
+        .route("/issues", get(list_issues).post(create_issue))
+        .route("/issues/:id", get(get_issue).patch(update_issue))
+        .route("/issues/:id/labels", put(set_issue_labels))
+        .route("/issues/:id/assignees", put(set_issue_assignees))
+        .route("/issues/:id/milestone", put(set_issue_milestone))
+        .route("/issues/:id/close", post(close_issue))
+        .route("/issues/:id/reopen", post(reopen_issue))
+        .route("/issues/:id/subscription", put(subscribe_issue))
+        .route(
+            "/issues/:id/reactions",
+            get(list_issue_reactions).post(add_issue_reaction),
+        )
+        .route(
+            "/issues/:id/reactions/:content",
+            delete(remove_issue_reaction),
+        )
+        .route("/backlinks", get(list_backlinks))
+        .route("/releases", get(list_releases).post(publish_release))
+        .route(
+            "/releases/:id",
+            get(get_release)
+                .patch(update_release)
+                .delete(delete_release),
+        )
+        // assets are build outputs, often larger than the default limit
+        .route(
+            "/releases/:id/assets",
+            post(upload_release_asset).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/releases/:id/assets/:name",
+            get(download_release_asset).delete(delete_release_asset),
+        )
+        .route("/labels", get(list_labels).post(create_label))
+        .route("/labels/:id", patch(update_label).delete(delete_label))
+        .route("/milestones", get(list_milestones).post(create_milestone))
+        .route(
+            "/milestones/:id",
+            get(get_milestone)
+                .patch(update_milestone)
+                .delete(delete_milestone),
+        )
+        .route("/boards", get(list_boards).post(create_board))
+        .route(
+            "/boards/:id",
+            get(get_board).patch(update_board).delete(delete_board),
+        )
+        .route("/boards/:id/columns", post(add_board_column))
+        .route(
+            "/boards/:id/columns/:column_id",
+            patch(update_board_column).delete(delete_board_column),
+        )
+        .route("/boards/:id/cards", post(add_board_card))
+        .route("/boards/:id/cards/:card_id", delete(remove_board_card))
+        .route("/boards/:id/cards/:card_id/move", post(move_board_card))
+        .route("/wiki", get(list_wiki_pages))
+        .route(
+            "/wiki/page",
+            get(get_wiki_page)
+                .put(save_wiki_page)
+                .delete(delete_wiki_page),
+        )
+        .route("/wiki/history", get(get_wiki_history))
+        .route("/snippets", get(list_snippets).post(create_snippet))
+        .route(
+            "/snippets/:id",
+            get(get_snippet)
+                .patch(update_snippet)
+                .delete(delete_snippet),
+        )
+        .route("/snippets/:id/raw/:name", get(get_snippet_raw))
+        .route("/stats/contributors", get(get_contributor_stats))
+        .route("/stats/code-frequency", get(get_code_frequency))
+        .route("/stats/languages", get(get_language_stats))
+        .route("/auth/oidc/login", get(oidc_login))
+        .route("/auth/oidc/callback", get(oidc_callback))
+        .route("/users", post(register_user))
+        .route("/users/:name", get(get_user).patch(update_user))
+        .route("/users/:name/orgs", get(list_user_orgs))
+        .route("/users/:name/tokens", get(list_tokens).post(issue_token))
+        .route("/users/:name/tokens/:id", delete(revoke_token))
+        .route("/users/:name/notifications", get(list_notifications))
+        .route(
+            "/users/:name/notifications/read",
+            post(mark_notifications_read),
+        )
+        .route(
+            "/users/:name/notification-preferences",
+            get(get_notification_prefs).put(set_notification_prefs),
+        )
+        .route("/acl/grants", get(list_path_grants).post(save_path_grant))
+        .route("/acl/grants/:id", delete(delete_path_grant))
+        .route("/acl/access", get(get_path_access))
+        .route("/orgs", post(create_org))
+        .route("/orgs/:name", get(get_org))
+        .route("/orgs/:name/members", get(list_org_members))
+        .route(
+            "/orgs/:name/members/:username",
+            put(set_org_member).delete(remove_org_member),
+        )
+        .route(
+            "/orgs/:name/autolinks",
+            get(list_autolinks).post(create_autolink),
+        )
+        .route("/orgs/:name/autolinks/:id", delete(delete_autolink))
+        .route(
+            "/users/:name/ssh-keys",
+            get(list_ssh_keys).post(add_ssh_key),
+        )
+        .route("/users/:name/ssh-keys/:id", delete(delete_ssh_key))
+        .route(
+            "/users/:name/signing-keys",
+            get(list_signing_keys).post(add_signing_key),
+        )
+        .route("/users/:name/signing-keys/:id", delete(delete_signing_key))
+        .route(
+            "/users/:name/signing-keys/:id/verify",
+            post(verify_signing_key),
+        )
+        .route("/commit-signature", get(get_commit_signature))
+        .route("/ref-audit", get(get_ref_audit))
+        .route("/checks/:commit_id", get(get_checks).post(report_check))
+        .route(
+            "/required-checks",
+            get(get_required_checks).put(set_required_checks),
+        )
+        .route("/ci/jobs/:commit_id", get(list_ci_jobs))
+        .route("/ci/logs/:commit_id", get(list_ci_logs))
+        .route(
+            "/ci/logs/:commit_id/:job",
+            get(read_ci_log).post(append_ci_log),
+        )
+        .route("/ci/logs/:commit_id/:job/finish", post(finish_ci_log))
+        .route("/ci/logs/:commit_id/:job/stream", get(stream_ci_log))
+        .route("/events", get(stream_events))
+        .route("/search", get(search))
+        .route("/search/code", get(search_code))
+        .route("/admin/search/reindex", post(reindex))
+        .route("/admin/ref-triggers", get(list_ref_triggers))
+        .route(
+            "/admin/ref-triggers/:name",
+            put(save_ref_trigger).delete(delete_ref_trigger),
+        )
+        .route("/admin/ref-triggers/:name/run", post(run_ref_trigger))
+        .route("/admin/snapshot-exports", get(list_snapshot_exports))
+        .route(
+            "/admin/snapshot-exports/:name",
+            put(save_snapshot_export).delete(delete_snapshot_export),
+        )
+        .route("/admin/snapshot-exports/:name/run", post(run_snapshot_export))
+        .route("/admin/ref-hooks", get(list_ref_hooks))
+        .route("/admin/ref-hooks/:name/retry", post(retry_ref_hook))
+        .route("/admin/webhooks", get(list_webhooks))
+        .route(
+            "/admin/webhooks/:name",
+            put(save_webhook).delete(delete_webhook),
+        )
+        .route("/admin/push-profiles", get(list_push_profiles))
+        .route("/admin/push-profiles/:id", get(get_push_profile))
+        .route("/admin/paths/move", post(move_path))
+        .route("/admin/path-redirects", get(list_path_redirects))
+        .route("/admin/paths/archive", post(archive_path))
+        .route("/admin/paths/unarchive", post(unarchive_path))
+        .route("/admin/paths/delete", post(delete_path))
+        .route("/admin/archives", get(list_archives))
+        // bundles are as large as the repositories they hold, like pushes
+        .route(
+            "/admin/bundles",
+            post(import_bundle).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/admin/erasures", get(list_erasures).post(erase_user))
+        .route("/admin/erasures/:id", get(get_erasure))
+        .route("/admin/mailmap", get(get_mailmap))
+        .route("/admin/imports", get(list_imports).post(import_repo))
+        .route("/admin/imports/:id", get(get_import))
+        .route(
+            "/admin/import-jobs",
+            get(list_import_jobs).post(create_import_job),
+        )
+        .route("/admin/import-jobs/:id", get(get_import_job))
+        .route("/admin/import-jobs/:id/retry", post(retry_import_job))
+        .route("/admin/mirrors", get(list_mirrors).put(save_mirror))
+        .route("/admin/mirrors/:id", get(get_mirror).delete(delete_mirror))
+        .route("/admin/mirrors/:id/sync", post(sync_mirror))
+        .route("/admin/operations", get(list_operations))
+        .route("/admin/operations/:id", get(get_operation))
+        .route("/admin/operations/:id/cancel", post(cancel_operation))
+        .route("/admin/missing-objects", get(list_missing_objects))
+        .route("/admin/missing-objects/repair", post(repair_missing_objects))
+        .route("/feature-flags/:name", get(get_feature_flag_status))
+        .route("/admin/feature-flags", get(list_feature_flags))
+        .route(
+            "/admin/feature-flags/:name",
+            put(save_feature_flag).delete(delete_feature_flag),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            refuse_archived_writes,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            authorize_paths,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            redirect_moved_paths,
+        ))
+        .layer(middleware::map_response(typed_errors))
+        .layer(middleware::from_fn(localize))
         .with_state(state)
 }
 
+/// The OpenAPI description of the API, served at `/api/openapi.json` along with a Swagger UI.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Mega API"),
+    servers((url = "/api/v1")),
+    paths(
+        get_blob_object, get_directories, get_origin_object, get_commit, list_commits, list_refs,
+        life_cycle_check, get_count_nums, get_blame, get_changelog, get_submodules, get_readme,
+        render_markdown, get_archive, get_capabilities, get_object_batch, get_merge_check,
+        compare_refs, get_comparison, cherry_pick, revert_commit, list_mrs, create_mr, get_mr,
+        close_mr, reopen_mr, ready_mr, draft_mr, merge_mr, update_mr_branch, get_queued_mr,
+        enqueue_mr, dequeue_mr, list_merge_queue, set_mr_labels, set_mr_assignees, set_mr_milestone,
+        list_threads, create_thread, add_comment, resolve_thread, unresolve_thread, get_reviews,
+        submit_review, subscribe_mr, list_mr_reactions, add_mr_reaction, remove_mr_reaction,
+        list_comment_reactions, add_comment_reaction, remove_comment_reaction, list_issues,
+        create_issue, get_issue, update_issue, set_issue_labels, set_issue_assignees,
+        set_issue_milestone, close_issue, reopen_issue, subscribe_issue, list_issue_reactions,
+        add_issue_reaction, remove_issue_reaction, list_backlinks, list_releases, publish_release,
+        get_release, update_release, delete_release, upload_release_asset, download_release_asset,
+        delete_release_asset, list_labels, create_label, update_label, delete_label,
+        list_milestones, create_milestone, get_milestone, update_milestone, delete_milestone,
+        list_boards, create_board, get_board, update_board, delete_board, add_board_column,
+        update_board_column, delete_board_column, add_board_card, remove_board_card,
+        move_board_card, list_wiki_pages, get_wiki_page, save_wiki_page, delete_wiki_page,
+        get_wiki_history, list_snippets, create_snippet, get_snippet, update_snippet,
+        delete_snippet, get_snippet_raw, get_contributor_stats, get_code_frequency,
+        get_language_stats, oidc_login, oidc_callback, register_user, get_user, update_user,
+        list_user_orgs, list_tokens, issue_token, revoke_token, list_notifications,
+        mark_notifications_read, get_notification_prefs, set_notification_prefs, list_path_grants,
+        save_path_grant, delete_path_grant, get_path_access, create_org, get_org, list_org_members,
+        set_org_member, remove_org_member, list_autolinks, create_autolink, delete_autolink,
+        list_ssh_keys, add_ssh_key, delete_ssh_key, list_signing_keys, add_signing_key,
+        delete_signing_key, verify_signing_key, get_commit_signature, get_ref_audit, get_checks,
+        report_check, get_required_checks, set_required_checks, list_ci_jobs, list_ci_logs,
+        read_ci_log, append_ci_log, finish_ci_log, stream_ci_log, stream_events, search,
+        search_code, reindex, list_ref_triggers, save_ref_trigger, delete_ref_trigger,
+        run_ref_trigger, list_snapshot_exports, save_snapshot_export, delete_snapshot_export,
+        run_snapshot_export, list_ref_hooks, retry_ref_hook, list_webhooks, save_webhook,
+        delete_webhook, list_push_profiles, get_push_profile, move_path, list_path_redirects,
+        archive_path, unarchive_path, delete_path, list_archives, import_bundle, list_erasures,
+        erase_user, get_erasure, get_mailmap, list_imports, import_repo, get_import,
+        list_import_jobs, create_import_job, get_import_job, retry_import_job, list_mirrors,
+        save_mirror, get_mirror, delete_mirror, sync_mirror, list_operations, get_operation,
+        cancel_operation, list_missing_objects, repair_missing_objects, get_feature_flag_status,
+        list_feature_flags, save_feature_flag, delete_feature_flag
+    ),
+    modifiers(&TokenAuth),
+    security((), ("token" = []), ("password" = []))
+)]
+pub struct ApiDoc;
+
+/// Calls identify their caller with an access token, or a password, sent as the
+/// `Authorization` header.
+struct TokenAuth;
+
+impl Modify for TokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        components.add_security_scheme(
+            "password",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+        );
+    }
+}
+
+/// Handle the call with messages in the language negotiated from its `Accept-Language` header.
+async fn localize(request: Request, next: Next) -> Response {
+    let locale = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(Locale::negotiate)
+        .unwrap_or_default();
+    let mut response = i18n::scope(locale, next.run(request)).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_LANGUAGE,
+        HeaderValue::from_static(locale.tag()),
+    );
+    headers.append(header::VARY, HeaderValue::from_static("accept-language"));
+    response
+}
+
+/// Redirect calls whose `repo_path` names a moved path to the same call with the new path.
+async fn redirect_moved_paths(
+    state: State<ApiServiceState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let uri = match request.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.clone(),
+        None => request.uri().clone(),
+    };
+    let query: Vec<(String, String)> = form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+        .into_owned()
+        .collect();
+    let Some((_, repo_path)) = query.iter().find(|(key, _)| key == "repo_path") else {
+        return next.run(request).await;
+    };
+    let moved_to = match state.path_redirects.moved_to(repo_path).await {
+        Ok(Some(moved_to)) => moved_to,
+        Ok(None) => return next.run(request).await,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let query = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(query.iter().map(|(key, value)| match key.as_str() {
+            "repo_path" => (key.as_str(), moved_to.as_str()),
+            _ => (key.as_str(), value.as_str()),
+        }))
+        .finish();
+    Redirect::permanent(&format!("{}?{}", uri.path(), query)).into_response()
+}
+
+/// Who made an API call, as identified by [`authorize_paths`].
+#[derive(Clone)]
+struct Caller(Option<Identity>);
+
+/// Largest JSON body [`authorize_paths`] reads the `repo_path` of.
+const MAX_JSON_BODY: usize = 16 * 1024 * 1024;
+
+/// The permission an API call needs on the path it names.
+fn required_permission(method: &Method, path: &str) -> Permission {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["admin", ..] => Permission::Admin,
+        ["mr", _, "merge"] => Permission::Maintain,
+        ["mr", _, "queue"] if method != Method::GET => Permission::Maintain,
+        ["required-checks"] if method == Method::PUT => Permission::Maintain,
+        // following an issue or merge request only needs to see it
+        ["issues" | "mr", _, "subscription"] => Permission::Read,
+        // and so does reacting to it
+        ["issues" | "mr", _, "reactions", ..] | ["mr", _, "comments", _, "reactions", ..] => {
+            Permission::Read
+        }
+        // only read, the refs and object ids are in the body
+        ["merge-bases"] | ["objects", "batch"] => Permission::Read,
+        // nothing is written, the markdown is only rendered
+        ["markdown"] => Permission::Read,
+        _ if method == Method::GET || method == Method::HEAD => Permission::Read,
+        _ => Permission::Write,
+    }
+}
+
+/// The repository an API call is about: the `repo_path` of its query or JSON body, or the one of
+/// the merge request, issue, board or release it names. The body is read and put back.
+async fn call_repo_path(
+    state: &ApiServiceState,
+    request: Request,
+) -> Result<(Request, Option<String>), Response> {
+    let query_path = form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+        .find(|(key, _)| key == "repo_path")
+        .map(|(_, value)| value.into_owned());
+    if query_path.is_some() {
+        return Ok((request, query_path));
+    }
+    let internal_error = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    let path = request.uri().path().to_owned();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["mr", id, ..] => {
+            let Ok(id) = id.parse::<i64>() else {
+                return Ok((request, None));
+            };
+            let mr = state.mr_service.mr_storage.get_mr(id).await;
+            let mr = mr.map_err(|e| internal_error(e.to_string()))?;
+            return Ok((request, mr.map(|mr| mr.repo_path)));
+        }
+        ["issues", id, ..] => {
+            let Ok(id) = id.parse::<i64>() else {
+                return Ok((request, None));
+            };
+            let issue = state.issue_service.issue_storage.get_issue(id).await;
+            let issue = issue.map_err(|e| internal_error(e.to_string()))?;
+            return Ok((request, issue.map(|issue| issue.repo_path)));
+        }
+        ["boards", id, ..] => {
+            let Ok(id) = id.parse::<i64>() else {
+                return Ok((request, None));
+            };
+            let board = state.board_service.board_storage.get_board(id).await;
+            let board = board.map_err(|e| internal_error(e.to_string()))?;
+            return Ok((request, board.map(|board| board.repo_path)));
+        }
+        ["releases", id, ..] => {
+            let Ok(id) = id.parse::<i64>() else {
+                return Ok((request, None));
+            };
+            let release = state.release_service.release_storage.get_release(id).await;
+            let release = release.map_err(|e| internal_error(e.to_string()))?;
+            return Ok((request, release.map(|release| release.repo_path)));
+        }
+        _ => {}
+    }
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return Ok((request, None));
+    }
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_JSON_BODY)
+        .await
+        .map_err(|e| (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response())?;
+    let repo_path = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|body| body.get("repo_path")?.as_str().map(str::to_owned));
+    Ok((Request::from_parts(parts, Body::from(bytes)), repo_path))
+}
+
+/// Check that the caller has the permission a call needs on the repository it is about, when
+/// the ACL is enabled. Calls that read need `read`, the others `write`, merging, queueing merges
+/// and setting the required checks need `maintain`. The `/admin` calls always need an
+/// authenticated caller with `admin` on `/`, even when the ACL is disabled. Calls about no
+/// repository are left to their handlers, like the `/acl` and `/snippets` calls, which always
+/// identify the caller.
+async fn authorize_paths(
+    state: State<ApiServiceState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    if path.starts_with("/admin/") {
+        let identity = match state.http_auth.authorize_admin(request.headers()).await {
+            Ok(identity) => identity,
+            Err(response) => return response,
+        };
+        request.extensions_mut().insert(Caller(Some(identity)));
+        return next.run(request).await;
+    }
+    let manages_grants = path.starts_with("/acl/");
+    // snippets are shown by who asks, whether the ACL is enabled or not
+    let identifies = manages_grants || path == "/snippets" || path.starts_with("/snippets/");
+    if path.starts_with("/auth/") || !(state.acl_service.acl.enabled() || identifies) {
+        return next.run(request).await;
+    }
+    let permission = required_permission(request.method(), &path);
+    let identity = match state
+        .http_auth
+        .identify(request.headers(), permission.token_scope())
+        .await
+    {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+    if state.acl_service.acl.enabled() && !identifies {
+        let (call, repo_path) = match call_repo_path(&state, request).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+        request = call;
+        if let Some(repo_path) = repo_path {
+            let acl = &state.acl_service.acl;
+            if let Err(response) =
+                check_permission(acl, identity.as_ref(), &repo_path, permission).await
+            {
+                return response;
+            }
+        }
+    }
+    request.extensions_mut().insert(Caller(identity));
+    next.run(request).await
+}
+
+/// Refuse the calls writing to an archived or deleted repository. The `/admin` calls are left
+/// through, admins archive and delete paths with them.
+async fn refuse_archived_writes(
+    state: State<ApiServiceState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    if matches!(
+        required_permission(request.method(), &path),
+        Permission::Read | Permission::Admin
+    ) {
+        return next.run(request).await;
+    }
+    let (request, repo_path) = match call_repo_path(&state, request).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if let Some(repo_path) = repo_path {
+        if let Err(err) = state.path_archives.check_writable(&repo_path).await {
+            return err.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/blob",
+    tag = "objects",
+    params(
+        ("object_id" = String, Query, description = "Id of the blob"),
+        ("repo_path" = Option<String>, Query, description = "Repository of the blob"),
+        ("path" = Option<String>, Query, description = "Path of the blob"),
+        ("ref" = Option<String>, Query, description = "Branch, tag or commit of the path"),
+        ("highlight" = Option<bool>, Query, description = "Highlight the blob"),
+        ("highlight_format" = Option<HighlightFormat>, Query, description = "How"),
+        ("language" = Option<String>, Query, description = "Language to highlight as"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy the caller has")
+    ),
+    responses(
+        (status = 200, body = BlobObjects),
+        (status = 304, description = "The copy of the caller is current"),
+        (status = "default", body = ApiError)
+    )
+)]
 async fn get_blob_object(
     Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     state: State<ApiServiceState>,
-) -> Result<Json<BlobObjects>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let object_id = query.get("object_id").unwrap();
-    state.object_service.get_blob_objects(object_id).await
+    let highlight = match query.get("highlight").map(String::as_str) {
+        Some("true") => {
+            let format = match query.get("highlight_format").map(String::as_str) {
+                None | Some("html") => HighlightFormat::Html,
+                Some("tokens") => HighlightFormat::Tokens,
+                Some(other) => {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        format!(
+                            "unknown highlight_format {}, expected html or tokens",
+                            other
+                        ),
+                    ))
+                }
+            };
+            Some(format)
+        }
+        _ => None,
+    };
+    state
+        .object_service
+        .get_blob_objects(
+            object_id,
+            query.get("repo_path").map(String::as_str),
+            query.get("path").map(String::as_str),
+            query.get("ref").map(String::as_str),
+            highlight,
+            query.get("language").map(String::as_str),
+            &headers,
+        )
+        .await
 }
 
+#[utoipa::path(
+    get,
+    path = "/tree",
+    tag = "objects",
+    params(
+        DirectoryQuery,
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy the caller has")
+    ),
+    responses(
+        (status = 200, body = Directories),
+        (status = 304, description = "The copy of the caller is current"),
+        (status = "default", body = ApiError)
+    )
+)]
 async fn get_directories(
     Query(query): Query<DirectoryQuery>,
+    headers: HeaderMap,
     state: State<ApiServiceState>,
-) -> Result<Json<Directories>, (StatusCode, String)> {
-    state.object_service.get_directories(query).await
+) -> Result<Response, (StatusCode, String)> {
+    state.object_service.get_directories(query, &headers).await
 }
 
+#[utoipa::path(
+    get,
+    path = "/object",
+    tag = "objects",
+    params(
+        ("object_id" = String, Query, description = "Id of the object"),
+        ("repo_path" = String, Query, description = "Repository the object is in"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy the caller has")
+    ),
+    responses(
+        (status = 200, description = "The object as git stores it"),
+        (status = 304, description = "The copy of the caller is current"),
+        (status = "default", body = ApiError)
+    )
+)]
 async fn get_origin_object(
     Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     state: State<ApiServiceState>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let object_id = query.get("object_id").unwrap();
     let repo_path = query.get("repo_path").expect("repo_path is required");
-    state.object_service.get_objects_data(object_id, repo_path).await
+    state
+        .object_service
+        .get_objects_data(object_id, repo_path, &headers)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/commit",
+    tag = "objects",
+    params(("object_id" = String, Query, description = "Id of the commit")),
+    responses(
+        (status = 200, description = "The commit and the status of its signature"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_commit(
+    Query(query): Query<HashMap<String, String>>,
+    state: State<ApiServiceState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let object_id = query
+        .get("object_id")
+        .ok_or((StatusCode::BAD_REQUEST, "object_id is required".to_owned()))?;
+    let Json(commit) = state.object_service.get_commit(object_id).await?;
+    let signature = state.signing_key_service.commit_signature(&commit).await;
+    Ok(Json(SignedCommit { commit, signature }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/commits",
+    tag = "objects",
+    params(CommitLogQuery, PageQuery),
+    responses(
+        (status = 200, body = Page<CommitSummary>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_commits(
+    Query(query): Query<CommitLogQuery>,
+    Query(page): Query<PageQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Page<CommitSummary>>, (StatusCode, String)> {
+    state.object_service.list_commits(query, page).await
 }
 
+#[utoipa::path(
+    get,
+    path = "/refs",
+    tag = "objects",
+    params(
+        RefQuery,
+        PageQuery,
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy the caller has")
+    ),
+    responses(
+        (status = 200, body = Page<RefInfo>),
+        (status = 304, description = "The copy of the caller is current"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_refs(
+    Query(query): Query<RefQuery>,
+    Query(page): Query<PageQuery>,
+    headers: HeaderMap,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    state.object_service.list_refs(query, page, &headers).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "objects",
+    responses(
+        (status = 200, body = String),
+        (status = "default", body = ApiError)
+    )
+)]
 async fn life_cycle_check() -> Result<impl IntoResponse, (StatusCode, String)> {
     Ok(Json("http ready"))
 }
 
+#[utoipa::path(
+    get,
+    path = "/count-objs",
+    tag = "objects",
+    params(("repo_path" = String, Query, description = "Path of the repository")),
+    responses(
+        (status = 200, description = "Objects of the repository by type"),
+        (status = "default", body = ApiError)
+    )
+)]
 async fn get_count_nums(
     Query(query): Query<HashMap<String, String>>,
     state: State<ApiServiceState>,
 ) -> Result<Json<GitTypeCounter>, (StatusCode, String)> {
     let repo_path = query.get("repo_path").unwrap();
     state.object_service.count_object_num(repo_path).await
-}
\ No newline at end of file
+}
+
+#[utoipa::path(
+    get,
+    path = "/blame",
+    tag = "objects",
+    params(BlameQuery),
+    responses(
+        (status = 200, body = BlameResult),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_blame(
+    Query(query): Query<BlameQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<BlameResult>, (StatusCode, String)> {
+    state.blame_service.get_blame(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/changelog",
+    tag = "objects",
+    params(ChangelogQuery),
+    responses(
+        (status = 200, body = Changelog),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_changelog(
+    Query(query): Query<ChangelogQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Changelog>, (StatusCode, String)> {
+    state.changelog_service.changelog(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/submodules",
+    tag = "objects",
+    params(SubmoduleQuery),
+    responses(
+        (status = 200, body = Vec<Submodule>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_submodules(
+    Query(query): Query<SubmoduleQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Submodule>>, (StatusCode, String)> {
+    state.object_service.get_submodules(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/readme",
+    tag = "objects",
+    params(ReadmeQuery),
+    responses(
+        (status = 200, body = Readme),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_readme(
+    Query(query): Query<ReadmeQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Readme>, (StatusCode, String)> {
+    state.object_service.get_readme(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/markdown",
+    tag = "objects",
+    request_body = MarkdownPreview,
+    responses(
+        (status = 200, body = RenderedMarkdown),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn render_markdown(Json(preview): Json<MarkdownPreview>) -> Json<RenderedMarkdown> {
+    Json(RenderedMarkdown {
+        html: markdown::render(&preview.text),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/archive",
+    tag = "objects",
+    params(SnapshotQuery),
+    responses(
+        (status = 200, description = "The snapshot as a tar.gz, tar or zip archive"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_archive(
+    Query(query): Query<SnapshotQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    state.snapshot_service.download(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/capabilities",
+    tag = "objects",
+    responses(
+        (status = 200, body = Capabilities),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_capabilities(state: State<ApiServiceState>) -> Json<Capabilities> {
+    state.object_batch_service.capabilities().await
+}
+
+#[utoipa::path(
+    post,
+    path = "/objects/batch",
+    tag = "objects",
+    request_body = ObjectBatch,
+    responses(
+        (status = 200, description = "The objects as a pack"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_object_batch(
+    state: State<ApiServiceState>,
+    Json(batch): Json<ObjectBatch>,
+) -> Result<Response, (StatusCode, String)> {
+    state.object_batch_service.batch(batch).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/merge-check",
+    tag = "merge",
+    params(MergeCheckQuery),
+    responses(
+        (status = 200, body = MergeCheck),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_merge_check(
+    Query(query): Query<MergeCheckQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeCheck>, (StatusCode, String)> {
+    state.merge_service.check(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/merge-bases",
+    tag = "merge",
+    request_body = MergeBaseBatch,
+    responses(
+        (status = 200, body = Vec<RefComparison>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn compare_refs(
+    state: State<ApiServiceState>,
+    Json(batch): Json<MergeBaseBatch>,
+) -> Result<Json<Vec<RefComparison>>, (StatusCode, String)> {
+    state.merge_service.compare_batch(batch).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/compare",
+    tag = "merge",
+    params(CompareQuery),
+    responses(
+        (status = 200, body = Comparison),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_comparison(
+    Query(query): Query<CompareQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Comparison>, (StatusCode, String)> {
+    state.merge_service.compare(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/cherry-pick",
+    tag = "merge",
+    request_body = PickRequest,
+    responses(
+        (status = 200, body = PickResult),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn cherry_pick(
+    state: State<ApiServiceState>,
+    Json(request): Json<PickRequest>,
+) -> Result<Json<PickResult>, (StatusCode, String)> {
+    state.merge_service.cherry_pick(request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/revert",
+    tag = "merge",
+    request_body = PickRequest,
+    responses(
+        (status = 200, body = PickResult),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn revert_commit(
+    state: State<ApiServiceState>,
+    Json(request): Json<PickRequest>,
+) -> Result<Json<PickResult>, (StatusCode, String)> {
+    state.merge_service.revert(request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr",
+    tag = "merge requests",
+    params(MergeRequestQuery, PageQuery),
+    responses(
+        (status = 200, body = Page<MergeRequest>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_mrs(
+    Query(query): Query<MergeRequestQuery>,
+    Query(page): Query<PageQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Page<MergeRequest>>, (StatusCode, String)> {
+    state.mr_service.list(query, page).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr",
+    tag = "merge requests",
+    request_body = NewMergeRequest,
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_mr(
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(new_mr): Json<NewMergeRequest>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.create(new_mr, identified(caller)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{id}",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeRequestDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeRequestDetail>, (StatusCode, String)> {
+    state.mr_service.detail(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/close",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn close_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.close(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/reopen",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn reopen_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.reopen(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/ready",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn ready_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.ready(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/draft",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn draft_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.draft(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/merge",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = Option<MergeOptions>,
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn merge_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    options: Option<Json<MergeOptions>>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    let options = options.map(|Json(options)| options).unwrap_or_default();
+    state.mr_service.merge(id, options).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/update-branch",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = Option<UpdateBranchOptions>,
+    responses(
+        (status = 200, body = BranchUpdate),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_mr_branch(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    options: Option<Json<UpdateBranchOptions>>,
+) -> Result<Json<BranchUpdate>, (StatusCode, String)> {
+    let options = options.map(|Json(options)| options).unwrap_or_default();
+    state
+        .mr_service
+        .update_branch(id, options, &actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{id}/queue",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeQueueEntry),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_queued_mr(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeQueueEntry>, (StatusCode, String)> {
+    state.merge_queue_service.get(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/queue",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeQueueEntry),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn enqueue_mr(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeQueueEntry>, (StatusCode, String)> {
+    state.merge_queue_service.enqueue(id, actor(caller)).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mr/{id}/queue",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = MergeQueueEntry),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn dequeue_mr(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MergeQueueEntry>, (StatusCode, String)> {
+    state.merge_queue_service.dequeue(id, actor(caller)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/merge-queue",
+    tag = "merge requests",
+    params(MergeQueueQuery),
+    responses(
+        (status = 200, body = Vec<MergeQueueEntry>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_merge_queue(
+    Query(query): Query<MergeQueueQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<MergeQueueEntry>>, (StatusCode, String)> {
+    state.merge_queue_service.list(query).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/mr/{id}/labels",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = ItemLabels,
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_mr_labels(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(labels): Json<ItemLabels>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.set_labels(id, labels).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/mr/{id}/assignees",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = ItemAssignees,
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_mr_assignees(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(assignees): Json<ItemAssignees>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.set_assignees(id, assignees).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/mr/{id}/milestone",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = ItemMilestone,
+    responses(
+        (status = 200, body = MergeRequest),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_mr_milestone(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(milestone): Json<ItemMilestone>,
+) -> Result<Json<MergeRequest>, (StatusCode, String)> {
+    state.mr_service.set_milestone(id, milestone).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{id}/threads",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ThreadQuery
+    ),
+    responses(
+        (status = 200, body = Vec<ReviewThread>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_threads(
+    Path(id): Path<i64>,
+    Query(query): Query<ThreadQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<ReviewThread>>, (StatusCode, String)> {
+    state.mr_review_service.list_threads(id, query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/threads",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = NewThread,
+    responses(
+        (status = 200, body = ReviewThread),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_thread(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(new_thread): Json<NewThread>,
+) -> Result<Json<ReviewThread>, (StatusCode, String)> {
+    state
+        .mr_review_service
+        .create_thread(id, &actor(caller), new_thread)
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/threads/{thread_id}/comments",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("thread_id" = i64, Path, description = "Id of the review thread")
+    ),
+    request_body = NewComment,
+    responses(
+        (status = 200, body = ReviewComment),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_comment(
+    Path((id, thread_id)): Path<(i64, i64)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(new_comment): Json<NewComment>,
+) -> Result<Json<ReviewComment>, (StatusCode, String)> {
+    state
+        .mr_review_service
+        .add_comment(id, thread_id, &actor(caller), new_comment)
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/threads/{thread_id}/resolve",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("thread_id" = i64, Path, description = "Id of the review thread")
+    ),
+    responses(
+        (status = 204, description = "Resolved"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn resolve_thread(
+    Path((id, thread_id)): Path<(i64, i64)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .mr_review_service
+        .resolve_thread(id, thread_id, &actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/threads/{thread_id}/unresolve",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("thread_id" = i64, Path, description = "Id of the review thread")
+    ),
+    responses(
+        (status = 204, description = "Unresolved"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn unresolve_thread(
+    Path((id, thread_id)): Path<(i64, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .mr_review_service
+        .unresolve_thread(id, thread_id)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{id}/reviews",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = ReviewSummary),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_reviews(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<ReviewSummary>, (StatusCode, String)> {
+    state.mr_review_service.reviews(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/reviews",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = NewReview,
+    responses(
+        (status = 200, body = Review),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn submit_review(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(new_review): Json<NewReview>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    state
+        .mr_review_service
+        .submit_review(id, &actor(caller), new_review)
+        .await
+}
+
+#[utoipa::path(
+    put,
+    path = "/mr/{id}/subscription",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = SetSubscription,
+    responses(
+        (status = 200, body = Subscription),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn subscribe_mr(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<SetSubscription>,
+) -> Result<Json<Subscription>, (StatusCode, String)> {
+    state
+        .notification_service
+        .subscribe(&actor(caller), ITEM_MR, id, json.subscribed)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{id}/reactions",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_mr_reactions(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .list(ReactionSubject::Mr(id), &actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/reactions",
+    tag = "merge requests",
+    params(("id" = i64, Path, description = "Id of the merge request")),
+    request_body = NewReaction,
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_mr_reaction(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<NewReaction>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .add(ReactionSubject::Mr(id), &actor(caller), json)
+        .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mr/{id}/reactions/{content}",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("content" = String, Path, description = "The reaction, such as `+1` or `heart`")
+    ),
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn remove_mr_reaction(
+    Path((id, content)): Path<(i64, String)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .remove(ReactionSubject::Mr(id), &actor(caller), &content)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{id}/comments/{comment_id}/reactions",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("comment_id" = i64, Path, description = "Id of the review comment")
+    ),
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_comment_reactions(
+    Path((mr_id, id)): Path<(i64, i64)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .list(ReactionSubject::Comment { mr_id, id }, &actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{id}/comments/{comment_id}/reactions",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("comment_id" = i64, Path, description = "Id of the review comment")
+    ),
+    request_body = NewReaction,
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_comment_reaction(
+    Path((mr_id, id)): Path<(i64, i64)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<NewReaction>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .add(ReactionSubject::Comment { mr_id, id }, &actor(caller), json)
+        .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/mr/{id}/comments/{comment_id}/reactions/{content}",
+    tag = "merge requests",
+    params(
+        ("id" = i64, Path, description = "Id of the merge request"),
+        ("comment_id" = i64, Path, description = "Id of the review comment"),
+        ("content" = String, Path, description = "The reaction, such as `+1` or `heart`")
+    ),
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn remove_comment_reaction(
+    Path((mr_id, id, content)): Path<(i64, i64, String)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .remove(
+            ReactionSubject::Comment { mr_id, id },
+            &actor(caller),
+            &content,
+        )
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/issues",
+    tag = "issues",
+    params(IssueQuery, PageQuery),
+    responses(
+        (status = 200, body = Page<Issue>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_issues(
+    Query(query): Query<IssueQuery>,
+    Query(page): Query<PageQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Page<Issue>>, (StatusCode, String)> {
+    state.issue_service.list(query, page).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/issues",
+    tag = "issues",
+    request_body = NewIssue,
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_issue(
+    state: State<ApiServiceState>,
+    Json(new_issue): Json<NewIssue>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.create(new_issue).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/issues/{id}",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, body = IssueDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_issue(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<IssueDetail>, (StatusCode, String)> {
+    state.issue_service.detail(id).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/issues/{id}",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    request_body = IssueUpdate,
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_issue(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(update): Json<IssueUpdate>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.update(id, update).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/issues/{id}/labels",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    request_body = ItemLabels,
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_issue_labels(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(labels): Json<ItemLabels>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.set_labels(id, labels).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/issues/{id}/assignees",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    request_body = ItemAssignees,
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_issue_assignees(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(assignees): Json<ItemAssignees>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.set_assignees(id, assignees).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/issues/{id}/milestone",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    request_body = ItemMilestone,
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_issue_milestone(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(milestone): Json<ItemMilestone>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.set_milestone(id, milestone).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/issues/{id}/close",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn close_issue(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.close(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/issues/{id}/reopen",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, body = Issue),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn reopen_issue(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Issue>, (StatusCode, String)> {
+    state.issue_service.reopen(id).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/issues/{id}/subscription",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    request_body = SetSubscription,
+    responses(
+        (status = 200, body = Subscription),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn subscribe_issue(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<SetSubscription>,
+) -> Result<Json<Subscription>, (StatusCode, String)> {
+    state
+        .notification_service
+        .subscribe(&actor(caller), ITEM_ISSUE, id, json.subscribed)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/issues/{id}/reactions",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_issue_reactions(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .list(ReactionSubject::Issue(id), &actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/issues/{id}/reactions",
+    tag = "issues",
+    params(("id" = i64, Path, description = "Id of the issue")),
+    request_body = NewReaction,
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_issue_reaction(
+    Path(id): Path<i64>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<NewReaction>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .add(ReactionSubject::Issue(id), &actor(caller), json)
+        .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/issues/{id}/reactions/{content}",
+    tag = "issues",
+    params(
+        ("id" = i64, Path, description = "Id of the issue"),
+        ("content" = String, Path, description = "The reaction, such as `+1` or `heart`")
+    ),
+    responses(
+        (status = 200, body = Vec<Reaction>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn remove_issue_reaction(
+    Path((id, content)): Path<(i64, String)>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Reaction>>, (StatusCode, String)> {
+    state
+        .reaction_service
+        .remove(ReactionSubject::Issue(id), &actor(caller), &content)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/backlinks",
+    tag = "issues",
+    params(BacklinkQuery),
+    responses(
+        (status = 200, body = Vec<Backlink>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_backlinks(
+    Query(query): Query<BacklinkQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Backlink>>, (StatusCode, String)> {
+    state.references.backlinks(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/releases",
+    tag = "releases",
+    params(ReleaseQuery),
+    responses(
+        (status = 200, body = Vec<Release>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_releases(
+    Query(query): Query<ReleaseQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Release>>, (StatusCode, String)> {
+    state.release_service.list(&query.repo_path).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/releases",
+    tag = "releases",
+    request_body = NewRelease,
+    responses(
+        (status = 200, body = Release),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn publish_release(
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(new_release): Json<NewRelease>,
+) -> Result<Json<Release>, (StatusCode, String)> {
+    state
+        .release_service
+        .publish(new_release, actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/releases/{id}",
+    tag = "releases",
+    params(("id" = i64, Path, description = "Id of the release")),
+    responses(
+        (status = 200, body = Release),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_release(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Release>, (StatusCode, String)> {
+    state.release_service.detail(id).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/releases/{id}",
+    tag = "releases",
+    params(("id" = i64, Path, description = "Id of the release")),
+    request_body = ReleaseUpdate,
+    responses(
+        (status = 200, body = Release),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_release(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(update): Json<ReleaseUpdate>,
+) -> Result<Json<Release>, (StatusCode, String)> {
+    state.release_service.update(id, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/releases/{id}",
+    tag = "releases",
+    params(("id" = i64, Path, description = "Id of the release")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_release(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.release_service.delete(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/releases/{id}/assets",
+    tag = "releases",
+    params(
+        ("id" = i64, Path, description = "Id of the release"),
+        AssetUpload
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = Release),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn upload_release_asset(
+    Path(id): Path<i64>,
+    Query(upload): Query<AssetUpload>,
+    caller: Option<Extension<Caller>>,
+    headers: HeaderMap,
+    state: State<ApiServiceState>,
+    body: Bytes,
+) -> Result<Json<Release>, (StatusCode, String)> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    state
+        .release_service
+        .upload_asset(id, &upload.name, content_type, body, actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/releases/{id}/assets/{name}",
+    tag = "releases",
+    params(
+        ("id" = i64, Path, description = "Id of the release"),
+        ("name" = String, Path, description = "Name of the asset")
+    ),
+    responses(
+        (status = 200, description = "The asset"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn download_release_asset(
+    Path((id, name)): Path<(i64, String)>,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    state.release_service.download_asset(id, &name).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/releases/{id}/assets/{name}",
+    tag = "releases",
+    params(
+        ("id" = i64, Path, description = "Id of the release"),
+        ("name" = String, Path, description = "Name of the asset")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_release_asset(
+    Path((id, name)): Path<(i64, String)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.release_service.delete_asset(id, &name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/labels",
+    tag = "planning",
+    params(PlanningQuery),
+    responses(
+        (status = 200, body = Vec<Label>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_labels(
+    Query(query): Query<PlanningQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Label>>, (StatusCode, String)> {
+    state.planning_service.list_labels(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/labels",
+    tag = "planning",
+    request_body = NewLabel,
+    responses(
+        (status = 200, body = Label),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_label(
+    state: State<ApiServiceState>,
+    Json(new_label): Json<NewLabel>,
+) -> Result<Json<Label>, (StatusCode, String)> {
+    state.planning_service.create_label(new_label).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/labels/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the label")),
+    request_body = LabelUpdate,
+    responses(
+        (status = 200, body = Label),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_label(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(update): Json<LabelUpdate>,
+) -> Result<Json<Label>, (StatusCode, String)> {
+    state.planning_service.update_label(id, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/labels/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the label")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_label(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.planning_service.delete_label(id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/milestones",
+    tag = "planning",
+    params(PlanningQuery),
+    responses(
+        (status = 200, body = Vec<Milestone>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_milestones(
+    Query(query): Query<PlanningQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Milestone>>, (StatusCode, String)> {
+    state.planning_service.list_milestones(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/milestones",
+    tag = "planning",
+    request_body = NewMilestone,
+    responses(
+        (status = 200, body = Milestone),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_milestone(
+    state: State<ApiServiceState>,
+    Json(new_milestone): Json<NewMilestone>,
+) -> Result<Json<Milestone>, (StatusCode, String)> {
+    state.planning_service.create_milestone(new_milestone).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/milestones/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the milestone")),
+    responses(
+        (status = 200, body = Milestone),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_milestone(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Milestone>, (StatusCode, String)> {
+    state.planning_service.get_milestone(id).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/milestones/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the milestone")),
+    request_body = MilestoneUpdate,
+    responses(
+        (status = 200, body = Milestone),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_milestone(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(update): Json<MilestoneUpdate>,
+) -> Result<Json<Milestone>, (StatusCode, String)> {
+    state.planning_service.update_milestone(id, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/milestones/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the milestone")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_milestone(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.planning_service.delete_milestone(id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/boards",
+    tag = "planning",
+    params(BoardListQuery),
+    responses(
+        (status = 200, body = Vec<Board>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_boards(
+    Query(query): Query<BoardListQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Board>>, (StatusCode, String)> {
+    state.board_service.list(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/boards",
+    tag = "planning",
+    request_body = NewBoard,
+    responses(
+        (status = 200, body = BoardDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_board(
+    state: State<ApiServiceState>,
+    Json(new_board): Json<NewBoard>,
+) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+    state.board_service.create(new_board).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/boards/{id}",
+    tag = "planning",
+    params(
+        ("id" = i64, Path, description = "Id of the board"),
+        BoardQuery
+    ),
+    responses(
+        (status = 200, body = BoardDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_board(
+    Path(id): Path<i64>,
+    Query(query): Query<BoardQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+    state.board_service.detail(id, query).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/boards/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the board")),
+    request_body = BoardUpdate,
+    responses(
+        (status = 200, body = Board),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_board(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(update): Json<BoardUpdate>,
+) -> Result<Json<Board>, (StatusCode, String)> {
+    state.board_service.update(id, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/boards/{id}",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the board")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_board(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.board_service.delete(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/boards/{id}/columns",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the board")),
+    request_body = NewColumn,
+    responses(
+        (status = 200, body = BoardDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_board_column(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(new_column): Json<NewColumn>,
+) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+    state.board_service.add_column(id, new_column).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/boards/{id}/columns/{column_id}",
+    tag = "planning",
+    params(
+        ("id" = i64, Path, description = "Id of the board"),
+        ("column_id" = i64, Path, description = "Id of the column")
+    ),
+    request_body = ColumnUpdate,
+    responses(
+        (status = 200, body = BoardDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_board_column(
+    Path((id, column_id)): Path<(i64, i64)>,
+    state: State<ApiServiceState>,
+    Json(update): Json<ColumnUpdate>,
+) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+    state
+        .board_service
+        .update_column(id, column_id, update)
+        .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/boards/{id}/columns/{column_id}",
+    tag = "planning",
+    params(
+        ("id" = i64, Path, description = "Id of the board"),
+        ("column_id" = i64, Path, description = "Id of the column")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_board_column(
+    Path((id, column_id)): Path<(i64, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.board_service.delete_column(id, column_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/boards/{id}/cards",
+    tag = "planning",
+    params(("id" = i64, Path, description = "Id of the board")),
+    request_body = NewCard,
+    responses(
+        (status = 200, body = BoardDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_board_card(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+    Json(new_card): Json<NewCard>,
+) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+    state.board_service.add_card(id, new_card).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/boards/{id}/cards/{card_id}/move",
+    tag = "planning",
+    params(
+        ("id" = i64, Path, description = "Id of the board"),
+        ("card_id" = i64, Path, description = "Id of the card")
+    ),
+    request_body = CardMove,
+    responses(
+        (status = 200, body = BoardDetail),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn move_board_card(
+    Path((id, card_id)): Path<(i64, i64)>,
+    state: State<ApiServiceState>,
+    Json(card_move): Json<CardMove>,
+) -> Result<Json<BoardDetail>, (StatusCode, String)> {
+    state.board_service.move_card(id, card_id, card_move).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/boards/{id}/cards/{card_id}",
+    tag = "planning",
+    params(
+        ("id" = i64, Path, description = "Id of the board"),
+        ("card_id" = i64, Path, description = "Id of the card")
+    ),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn remove_board_card(
+    Path((id, card_id)): Path<(i64, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.board_service.remove_card(id, card_id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/wiki",
+    tag = "wiki",
+    params(WikiQuery),
+    responses(
+        (status = 200, body = WikiPages),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_wiki_pages(
+    Query(query): Query<WikiQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<WikiPages>, (StatusCode, String)> {
+    state.wiki_service.list(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/wiki/page",
+    tag = "wiki",
+    params(WikiPageQuery),
+    responses(
+        (status = 200, body = WikiPage),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_wiki_page(
+    Query(query): Query<WikiPageQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<WikiPage>, (StatusCode, String)> {
+    state.wiki_service.page(query).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/wiki/page",
+    tag = "wiki",
+    request_body = WikiPageUpdate,
+    responses(
+        (status = 200, body = WikiChange),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_wiki_page(
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(update): Json<WikiPageUpdate>,
+) -> Result<Json<WikiChange>, (StatusCode, String)> {
+    state.wiki_service.save(update, &actor(caller)).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/wiki/page",
+    tag = "wiki",
+    params(WikiPageDelete),
+    responses(
+        (status = 200, body = WikiChange),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_wiki_page(
+    Query(delete): Query<WikiPageDelete>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<WikiChange>, (StatusCode, String)> {
+    state.wiki_service.delete(delete, &actor(caller)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/wiki/history",
+    tag = "wiki",
+    params(WikiHistoryQuery),
+    responses(
+        (status = 200, body = Vec<WikiRevision>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_wiki_history(
+    Query(query): Query<WikiHistoryQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<WikiRevision>>, (StatusCode, String)> {
+    state.wiki_service.history(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/snippets",
+    tag = "snippets",
+    params(SnippetQuery),
+    responses(
+        (status = 200, body = Vec<Snippet>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_snippets(
+    Query(query): Query<SnippetQuery>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Snippet>>, (StatusCode, String)> {
+    state.snippet_service.list(caller.as_ref(), query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/snippets",
+    tag = "snippets",
+    request_body = NewSnippet,
+    responses(
+        (status = 200, body = Snippet),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_snippet(
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+    Json(new_snippet): Json<NewSnippet>,
+) -> Result<Json<Snippet>, (StatusCode, String)> {
+    state
+        .snippet_service
+        .create(caller.as_ref(), new_snippet)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/snippets/{id}",
+    tag = "snippets",
+    params(("id" = i64, Path, description = "Id of the snippet")),
+    responses(
+        (status = 200, body = Snippet),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_snippet(
+    Path(id): Path<i64>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Snippet>, (StatusCode, String)> {
+    state.snippet_service.detail(id, caller.as_ref()).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/snippets/{id}",
+    tag = "snippets",
+    params(("id" = i64, Path, description = "Id of the snippet")),
+    request_body = SnippetUpdate,
+    responses(
+        (status = 200, body = Snippet),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_snippet(
+    Path(id): Path<i64>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+    Json(update): Json<SnippetUpdate>,
+) -> Result<Json<Snippet>, (StatusCode, String)> {
+    state
+        .snippet_service
+        .update(id, caller.as_ref(), update)
+        .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/snippets/{id}",
+    tag = "snippets",
+    params(("id" = i64, Path, description = "Id of the snippet")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_snippet(
+    Path(id): Path<i64>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.snippet_service.delete(id, caller.as_ref()).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/snippets/{id}/raw/{name}",
+    tag = "snippets",
+    params(
+        ("id" = i64, Path, description = "Id of the snippet"),
+        ("name" = String, Path, description = "Name of the file")
+    ),
+    responses(
+        (status = 200, description = "The file", body = String, content_type = "text/plain"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_snippet_raw(
+    Path((id, name)): Path<(i64, String)>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    state.snippet_service.raw(id, &name, caller.as_ref()).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/contributors",
+    tag = "stats",
+    params(StatsQuery),
+    responses(
+        (status = 200, body = Vec<ContributorStats>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_contributor_stats(
+    Query(query): Query<StatsQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<ContributorStats>>, (StatusCode, String)> {
+    state.stats_service.contributors(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/code-frequency",
+    tag = "stats",
+    params(StatsQuery),
+    responses(
+        (status = 200, body = Vec<WeekStats>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_code_frequency(
+    Query(query): Query<StatsQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<WeekStats>>, (StatusCode, String)> {
+    state.stats_service.code_frequency(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/languages",
+    tag = "stats",
+    params(
+        ("repo_path" = String, Query, description = "Path of the repository"),
+        ("path" = Option<String>, Query, description = "Directory, the root by default")
+    ),
+    responses(
+        (status = 200, body = LanguageBreakdown),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_language_stats(
+    Query(query): Query<HashMap<String, String>>,
+    state: State<ApiServiceState>,
+) -> Result<Json<LanguageBreakdown>, (StatusCode, String)> {
+    let repo_path = query
+        .get("repo_path")
+        .ok_or((StatusCode::BAD_REQUEST, "repo_path is required".to_owned()))?;
+    state
+        .stats_service
+        .languages(repo_path, query.get("path").map(String::as_str))
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/login",
+    tag = "accounts",
+    params(OidcLoginQuery),
+    responses(
+        (status = 303, description = "Redirect to the identity provider"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn oidc_login(
+    Query(query): Query<OidcLoginQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    state.oidc_service.login(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/callback",
+    tag = "accounts",
+    params(OidcCallbackQuery),
+    responses(
+        (status = 200, description = "The token, or a redirect to `return_to`", body = IssuedToken),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn oidc_callback(
+    Query(query): Query<OidcCallbackQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    state.oidc_service.callback(query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "accounts",
+    request_body = NewUser,
+    responses(
+        (status = 200, body = User),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn register_user(
+    state: State<ApiServiceState>,
+    Json(new_user): Json<NewUser>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    state.account_service.register(new_user).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    responses(
+        (status = 200, body = User),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_user(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    state.account_service.get_user(&name).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/users/{name}",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    request_body = UserUpdate,
+    responses(
+        (status = 200, body = User),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn update_user(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(update): Json<UserUpdate>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    state.account_service.update_user(&name, update).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}/orgs",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    responses(
+        (status = 200, body = Vec<Membership>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_user_orgs(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Membership>>, (StatusCode, String)> {
+    state.account_service.list_user_orgs(&name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}/tokens",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    responses(
+        (status = 200, body = Vec<AccessToken>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_tokens(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<AccessToken>>, (StatusCode, String)> {
+    state.account_service.list_tokens(&name).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{name}/tokens",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    request_body = NewAccessToken,
+    responses(
+        (status = 200, body = IssuedToken),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn issue_token(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(new_token): Json<NewAccessToken>,
+) -> Result<Json<IssuedToken>, (StatusCode, String)> {
+    state.account_service.issue_token(&name, new_token).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{name}/tokens/{id}",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the user"),
+        ("id" = i64, Path, description = "Id of the access token")
+    ),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn revoke_token(
+    Path((name, id)): Path<(String, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.account_service.revoke_token(&name, id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}/notifications",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the user"),
+        NotificationQuery
+    ),
+    responses(
+        (status = 200, body = NotificationFeed),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_notifications(
+    Path(name): Path<String>,
+    Query(query): Query<NotificationQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<NotificationFeed>, (StatusCode, String)> {
+    state.notification_service.feed(&name, query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{name}/notifications/read",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    request_body = Option<MarkRead>,
+    responses(
+        (status = 200, body = MarkedRead),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn mark_notifications_read(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    body: Option<Json<MarkRead>>,
+) -> Result<Json<MarkedRead>, (StatusCode, String)> {
+    let mark = body.map(|Json(mark)| mark).unwrap_or_default();
+    state.notification_service.mark_read(&name, mark).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}/notification-preferences",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    responses(
+        (status = 200, body = NotificationPrefs),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_notification_prefs(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<NotificationPrefs>, (StatusCode, String)> {
+    state.notification_service.prefs(&name).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{name}/notification-preferences",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    request_body = NotificationPrefs,
+    responses(
+        (status = 200, body = NotificationPrefs),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_notification_prefs(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(prefs): Json<NotificationPrefs>,
+) -> Result<Json<NotificationPrefs>, (StatusCode, String)> {
+    state.notification_service.set_prefs(&name, prefs).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/acl/grants",
+    tag = "acl",
+    params(PathGrantQuery),
+    responses(
+        (status = 200, body = Vec<PathGrant>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_path_grants(
+    Query(query): Query<PathGrantQuery>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<PathGrant>>, (StatusCode, String)> {
+    state.acl_service.list_grants(caller.as_ref(), query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/acl/grants",
+    tag = "acl",
+    request_body = NewPathGrant,
+    responses(
+        (status = 200, body = PathGrant),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_path_grant(
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+    Json(grant): Json<NewPathGrant>,
+) -> Result<Json<PathGrant>, (StatusCode, String)> {
+    state.acl_service.save_grant(caller.as_ref(), grant).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/acl/grants/{id}",
+    tag = "acl",
+    params(("id" = i64, Path, description = "Id of the path grant")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_path_grant(
+    Path(id): Path<i64>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.acl_service.delete_grant(caller.as_ref(), id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/acl/access",
+    tag = "acl",
+    params(AccessQuery),
+    responses(
+        (status = 200, body = PathAccess),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_path_access(
+    Query(query): Query<AccessQuery>,
+    Extension(Caller(caller)): Extension<Caller>,
+    state: State<ApiServiceState>,
+) -> Result<Json<PathAccess>, (StatusCode, String)> {
+    state.acl_service.access(caller.as_ref(), query).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/orgs",
+    tag = "accounts",
+    request_body = NewOrg,
+    responses(
+        (status = 200, body = Org),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_org(
+    state: State<ApiServiceState>,
+    Json(new_org): Json<NewOrg>,
+) -> Result<Json<Org>, (StatusCode, String)> {
+    state.account_service.create_org(new_org).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/orgs/{name}",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the organization")),
+    responses(
+        (status = 200, body = Org),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_org(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Org>, (StatusCode, String)> {
+    state.account_service.get_org(&name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/orgs/{name}/members",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the organization")),
+    responses(
+        (status = 200, body = Vec<OrgMember>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_org_members(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<OrgMember>>, (StatusCode, String)> {
+    state.account_service.list_members(&name).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/orgs/{name}/members/{username}",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the organization"),
+        ("username" = String, Path, description = "Name of the member")
+    ),
+    request_body = MemberUpdate,
+    responses(
+        (status = 200, body = OrgMember),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_org_member(
+    Path((name, username)): Path<(String, String)>,
+    state: State<ApiServiceState>,
+    Json(update): Json<MemberUpdate>,
+) -> Result<Json<OrgMember>, (StatusCode, String)> {
+    state
+        .account_service
+        .set_member(&name, &username, update)
+        .await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/orgs/{name}/members/{username}",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the organization"),
+        ("username" = String, Path, description = "Name of the member")
+    ),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn remove_org_member(
+    Path((name, username)): Path<(String, String)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.account_service.remove_member(&name, &username).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/orgs/{name}/autolinks",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the organization")),
+    responses(
+        (status = 200, body = Vec<AutolinkRule>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_autolinks(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<AutolinkRule>>, (StatusCode, String)> {
+    state.autolink_service.list_rules(&name).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/orgs/{name}/autolinks",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the organization")),
+    request_body = NewAutolinkRule,
+    responses(
+        (status = 200, body = AutolinkRule),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_autolink(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(new_rule): Json<NewAutolinkRule>,
+) -> Result<Json<AutolinkRule>, (StatusCode, String)> {
+    state.autolink_service.create_rule(&name, new_rule).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/orgs/{name}/autolinks/{id}",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the organization"),
+        ("id" = i64, Path, description = "Id of the autolink rule")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_autolink(
+    Path((name, id)): Path<(String, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.autolink_service.delete_rule(&name, id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}/ssh-keys",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    responses(
+        (status = 200, body = Vec<SshKey>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_ssh_keys(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<SshKey>>, (StatusCode, String)> {
+    state.ssh_key_service.list_keys(&name).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{name}/ssh-keys",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    request_body = NewSshKey,
+    responses(
+        (status = 200, body = SshKey),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_ssh_key(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(new_key): Json<NewSshKey>,
+) -> Result<Json<SshKey>, (StatusCode, String)> {
+    state.ssh_key_service.add_key(name, new_key).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{name}/ssh-keys/{id}",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the user"),
+        ("id" = i64, Path, description = "Id of the SSH key")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_ssh_key(
+    Path((name, id)): Path<(String, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.ssh_key_service.delete_key(&name, id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{name}/signing-keys",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    responses(
+        (status = 200, body = Vec<SigningKey>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_signing_keys(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<SigningKey>>, (StatusCode, String)> {
+    state.signing_key_service.list_keys(&name).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{name}/signing-keys",
+    tag = "accounts",
+    params(("name" = String, Path, description = "Name of the user")),
+    request_body = NewSigningKey,
+    responses(
+        (status = 200, body = SigningKey),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn add_signing_key(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(new_key): Json<NewSigningKey>,
+) -> Result<Json<SigningKey>, (StatusCode, String)> {
+    state.signing_key_service.add_key(name, new_key).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{name}/signing-keys/{id}",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the user"),
+        ("id" = i64, Path, description = "Id of the signing key")
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_signing_key(
+    Path((name, id)): Path<(String, i64)>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.signing_key_service.delete_key(&name, id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{name}/signing-keys/{id}/verify",
+    tag = "accounts",
+    params(
+        ("name" = String, Path, description = "Name of the user"),
+        ("id" = i64, Path, description = "Id of the signing key")
+    ),
+    request_body = KeyVerification,
+    responses(
+        (status = 200, body = SigningKey),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn verify_signing_key(
+    Path((name, id)): Path<(String, i64)>,
+    state: State<ApiServiceState>,
+    Json(verification): Json<KeyVerification>,
+) -> Result<Json<SigningKey>, (StatusCode, String)> {
+    state
+        .signing_key_service
+        .verify_key(&name, id, verification)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/commit-signature",
+    tag = "signing",
+    params(CommitSignatureQuery),
+    responses(
+        (status = 200, body = CommitSignature),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_commit_signature(
+    Query(query): Query<CommitSignatureQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommitSignature>, (StatusCode, String)> {
+    state.signing_key_service.verify_commit(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/ref-audit",
+    tag = "refs",
+    params(RefAuditQuery),
+    responses(
+        (status = 200, body = Vec<RefAuditEntry>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_ref_audit(
+    Query(query): Query<RefAuditQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<RefAuditEntry>>, (StatusCode, String)> {
+    state.ref_trigger_service.audit(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/ref-triggers",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<RefTrigger>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_ref_triggers(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<RefTrigger>>, (StatusCode, String)> {
+    state.ref_trigger_service.list_triggers().await
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/ref-triggers/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the trigger")),
+    request_body = RefTriggerUpdate,
+    responses(
+        (status = 200, body = RefTrigger),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_ref_trigger(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(update): Json<RefTriggerUpdate>,
+) -> Result<Json<RefTrigger>, (StatusCode, String)> {
+    state.ref_trigger_service.save_trigger(name, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/ref-triggers/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the trigger")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_ref_trigger(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.ref_trigger_service.delete_trigger(&name).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/ref-triggers/{name}/run",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the trigger")),
+    responses(
+        (status = 200, body = RefTriggerRun),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn run_ref_trigger(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<RefTriggerRun>, (StatusCode, String)> {
+    state.ref_trigger_service.run_trigger(&name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/snapshot-exports",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<SnapshotExport>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_snapshot_exports(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<SnapshotExport>>, (StatusCode, String)> {
+    state.snapshot_export_service.list_exports().await
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/snapshot-exports/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the export")),
+    request_body = SnapshotExportUpdate,
+    responses(
+        (status = 200, body = SnapshotExport),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_snapshot_export(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(update): Json<SnapshotExportUpdate>,
+) -> Result<Json<SnapshotExport>, (StatusCode, String)> {
+    state.snapshot_export_service.save_export(name, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/snapshot-exports/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the export")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_snapshot_export(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.snapshot_export_service.delete_export(&name).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/snapshot-exports/{name}/run",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Name of the export"),
+        SnapshotExportRunQuery
+    ),
+    responses(
+        (status = 200, body = SnapshotExportRun),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn run_snapshot_export(
+    Path(name): Path<String>,
+    Query(query): Query<SnapshotExportRunQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<SnapshotExportRun>, (StatusCode, String)> {
+    state
+        .snapshot_export_service
+        .run_export(&name, query.refs.as_deref())
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/ref-hooks",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<RefHookStatus>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_ref_hooks(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<RefHookStatus>>, (StatusCode, String)> {
+    state.ref_hook_service.list_hooks().await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/ref-hooks/{name}/retry",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the hook")),
+    responses(
+        (status = 200, body = RefHookRequeued),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn retry_ref_hook(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<Json<RefHookRequeued>, (StatusCode, String)> {
+    state.ref_hook_service.retry_failed(&name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<Webhook>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_webhooks(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Webhook>>, (StatusCode, String)> {
+    state.webhook_service.list_webhooks().await
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/webhooks/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the webhook")),
+    request_body = WebhookUpdate,
+    responses(
+        (status = 200, body = Webhook),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_webhook(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(update): Json<WebhookUpdate>,
+) -> Result<Json<Webhook>, (StatusCode, String)> {
+    state.webhook_service.save_webhook(name, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the webhook")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_webhook(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.webhook_service.delete_webhook(&name).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/push-profiles",
+    tag = "admin",
+    params(PushProfileQuery),
+    responses(
+        (status = 200, body = Vec<PushProfile>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_push_profiles(
+    Query(query): Query<PushProfileQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<PushProfile>>, (StatusCode, String)> {
+    state.push_profile_service.list(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/push-profiles/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the push profile")),
+    responses(
+        (status = 200, body = PushProfile),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_push_profile(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<PushProfile>, (StatusCode, String)> {
+    state.push_profile_service.get(id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/checks/{commit_id}",
+    tag = "checks",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        CheckQuery
+    ),
+    responses(
+        (status = 200, body = CommitChecks),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_checks(
+    Path(commit_id): Path<String>,
+    Query(query): Query<CheckQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<CommitChecks>, (StatusCode, String)> {
+    state
+        .check_service
+        .get_checks(&query.repo_path, &commit_id, query.branch.as_deref())
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/checks/{commit_id}",
+    tag = "checks",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        CheckQuery
+    ),
+    request_body = NewCheckRun,
+    responses(
+        (status = 200, body = CheckRun),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn report_check(
+    Path(commit_id): Path<String>,
+    Query(query): Query<CheckQuery>,
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<NewCheckRun>,
+) -> Result<Json<CheckRun>, (StatusCode, String)> {
+    state
+        .check_service
+        .report(&query.repo_path, &commit_id, json, actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/required-checks",
+    tag = "checks",
+    params(RequiredChecksQuery),
+    responses(
+        (status = 200, body = RequiredChecks),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_required_checks(
+    Query(query): Query<RequiredChecksQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<RequiredChecks>, (StatusCode, String)> {
+    state.check_service.required_checks(query).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/required-checks",
+    tag = "checks",
+    request_body = RequiredChecks,
+    responses(
+        (status = 200, body = RequiredChecks),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn set_required_checks(
+    state: State<ApiServiceState>,
+    Json(json): Json<RequiredChecks>,
+) -> Result<Json<RequiredChecks>, (StatusCode, String)> {
+    state.check_service.set_required_checks(json).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/ci/jobs/{commit_id}",
+    tag = "ci",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        CiJobQuery
+    ),
+    responses(
+        (status = 200, body = Vec<CiJob>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_ci_jobs(
+    Path(commit_id): Path<String>,
+    Query(query): Query<CiJobQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<CiJob>>, (StatusCode, String)> {
+    state
+        .pipeline_service
+        .list_jobs(&query.repo_path, &commit_id)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/ci/logs/{commit_id}",
+    tag = "ci",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        CiLogQuery
+    ),
+    responses(
+        (status = 200, body = Vec<CiLog>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_ci_logs(
+    Path(commit_id): Path<String>,
+    Query(query): Query<CiLogQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<CiLog>>, (StatusCode, String)> {
+    state
+        .ci_log_service
+        .list_logs(&query.repo_path, &commit_id)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/ci/logs/{commit_id}/{job}",
+    tag = "ci",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        ("job" = String, Path, description = "Name of the CI job"),
+        CiLogQuery
+    ),
+    responses(
+        (status = 200, description = "The log", body = String, content_type = "text/plain"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn read_ci_log(
+    Path((commit_id, job)): Path<(String, String)>,
+    Query(query): Query<CiLogQuery>,
+    headers: HeaderMap,
+    state: State<ApiServiceState>,
+) -> Result<Response, (StatusCode, String)> {
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    state
+        .ci_log_service
+        .read(&query.repo_path, &commit_id, &job, query.offset, range)
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/ci/logs/{commit_id}/{job}",
+    tag = "ci",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        ("job" = String, Path, description = "Name of the CI job"),
+        CiLogQuery
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = CiLog),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn append_ci_log(
+    Path((commit_id, job)): Path<(String, String)>,
+    Query(query): Query<CiLogQuery>,
+    state: State<ApiServiceState>,
+    body: Bytes,
+) -> Result<Json<CiLog>, (StatusCode, String)> {
+    state
+        .ci_log_service
+        .append(&query.repo_path, &commit_id, &job, query.offset, body)
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/ci/logs/{commit_id}/{job}/finish",
+    tag = "ci",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        ("job" = String, Path, description = "Name of the CI job"),
+        CiLogQuery
+    ),
+    request_body = CiLogFinish,
+    responses(
+        (status = 200, body = CiLog),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn finish_ci_log(
+    Path((commit_id, job)): Path<(String, String)>,
+    Query(query): Query<CiLogQuery>,
+    state: State<ApiServiceState>,
+    Json(json): Json<CiLogFinish>,
+) -> Result<Json<CiLog>, (StatusCode, String)> {
+    state
+        .ci_log_service
+        .finish(&query.repo_path, &commit_id, &job, &json.status)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/ci/logs/{commit_id}/{job}/stream",
+    tag = "ci",
+    params(
+        ("commit_id" = String, Path, description = "Commit id"),
+        ("job" = String, Path, description = "Name of the CI job"),
+        CiLogQuery
+    ),
+    responses(
+        (status = 200, body = String, content_type = "text/event-stream"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn stream_ci_log(
+    Path((commit_id, job)): Path<(String, String)>,
+    Query(query): Query<CiLogQuery>,
+    headers: HeaderMap,
+    state: State<ApiServiceState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // a reconnecting event source resumes after the last event it received
+    let offset = headers
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .or(query.offset)
+        .unwrap_or(0);
+    state
+        .ci_log_service
+        .stream(&query.repo_path, &commit_id, &job, offset)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    params(EventQuery),
+    responses(
+        (status = 200, body = String, content_type = "text/event-stream"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn stream_events(
+    Query(query): Query<EventQuery>,
+    headers: HeaderMap,
+    state: State<ApiServiceState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok()?.parse().ok());
+    state.event_service.stream(query, last_event_id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery, PageQuery),
+    responses(
+        (status = 200, body = Page<SearchHit>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn search(
+    Query(query): Query<SearchQuery>,
+    Query(page): Query<PageQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Page<SearchHit>>, (StatusCode, String)> {
+    state.search_service.search(query, page).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/search/code",
+    tag = "search",
+    params(CodeSearchQuery, PageQuery),
+    responses(
+        (status = 200, body = Page<CodeSearchHit>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn search_code(
+    Query(query): Query<CodeSearchQuery>,
+    Query(page): Query<PageQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Page<CodeSearchHit>>, (StatusCode, String)> {
+    state.search_service.search_code(query, page).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/search/reindex",
+    tag = "admin",
+    request_body = SearchReindex,
+    responses(
+        (status = 204, description = "Reindexed"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn reindex(
+    state: State<ApiServiceState>,
+    Json(json): Json<SearchReindex>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.search_service.reindex(&json.repo_path).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/paths/move",
+    tag = "admin",
+    request_body = PathMove,
+    responses(
+        (status = 200, body = PathMoveResult),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn move_path(
+    state: State<ApiServiceState>,
+    Json(json): Json<PathMove>,
+) -> Result<Json<PathMoveResult>, (StatusCode, String)> {
+    state.path_move_service.move_path(json).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/path-redirects",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<PathRedirect>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_path_redirects(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<PathRedirect>>, (StatusCode, String)> {
+    state.path_move_service.list_redirects().await
+}
+
+/// The username of the caller, when they are identified.
+fn identified(caller: Option<Extension<Caller>>) -> Option<String> {
+    caller
+        .and_then(|Extension(Caller(identity))| identity)
+        .map(|identity| identity.username)
+}
+
+/// Who a call is made by, `mega` when the caller is not identified.
+fn actor(caller: Option<Extension<Caller>>) -> String {
+    identified(caller).unwrap_or_else(|| "mega".to_owned())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/archives",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<PathArchive>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_archives(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<PathArchive>>, (StatusCode, String)> {
+    state.archive_service.list_archives().await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/paths/archive",
+    tag = "admin",
+    request_body = PathArchiveRequest,
+    responses(
+        (status = 200, body = PathArchive),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn archive_path(
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<PathArchiveRequest>,
+) -> Result<Json<PathArchive>, (StatusCode, String)> {
+    state.archive_service.archive(json, &actor(caller)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/paths/unarchive",
+    tag = "admin",
+    request_body = PathArchiveRequest,
+    responses(
+        (status = 200, body = PathArchive),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn unarchive_path(
+    state: State<ApiServiceState>,
+    Json(json): Json<PathArchiveRequest>,
+) -> Result<Json<PathArchive>, (StatusCode, String)> {
+    state.archive_service.unarchive(json).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/paths/delete",
+    tag = "admin",
+    request_body = PathArchiveRequest,
+    responses(
+        (status = 200, body = PathArchive),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_path(
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<PathArchiveRequest>,
+) -> Result<Json<PathArchive>, (StatusCode, String)> {
+    state.archive_service.delete(json, &actor(caller)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/bundles",
+    tag = "admin",
+    params(BundleQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = BundleImport),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn import_bundle(
+    caller: Option<Extension<Caller>>,
+    Query(query): Query<BundleQuery>,
+    state: State<ApiServiceState>,
+    body: Bytes,
+) -> Result<Json<BundleImport>, (StatusCode, String)> {
+    state
+        .bundle_service
+        .import(&query.repo_path, body, &actor(caller))
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/erasures",
+    tag = "admin",
+    request_body = ErasureRequest,
+    responses(
+        (status = 200, body = ErasureReport),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn erase_user(
+    caller: Option<Extension<Caller>>,
+    state: State<ApiServiceState>,
+    Json(json): Json<ErasureRequest>,
+) -> Result<Json<ErasureReport>, (StatusCode, String)> {
+    state.erasure_service.erase(json, &actor(caller)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/erasures",
+    tag = "admin",
+    params(ErasureQuery),
+    responses(
+        (status = 200, body = Vec<ErasureReport>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_erasures(
+    Query(query): Query<ErasureQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<ErasureReport>>, (StatusCode, String)> {
+    state.erasure_service.list_erasures(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/erasures/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the erasure")),
+    responses(
+        (status = 200, body = ErasureReport),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_erasure(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<ErasureReport>, (StatusCode, String)> {
+    state.erasure_service.get_erasure(id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/mailmap",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The mailmap", body = String, content_type = "text/plain"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_mailmap(state: State<ApiServiceState>) -> Result<String, (StatusCode, String)> {
+    state.erasure_service.git_mailmap().await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/imports",
+    tag = "admin",
+    request_body = ImportRequest,
+    responses(
+        (status = 200, body = RepoImport),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn import_repo(
+    state: State<ApiServiceState>,
+    Json(json): Json<ImportRequest>,
+) -> Result<Json<RepoImport>, (StatusCode, String)> {
+    state.import_service.import(json).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/imports",
+    tag = "admin",
+    params(ImportQuery),
+    responses(
+        (status = 200, body = Vec<RepoImport>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_imports(
+    Query(query): Query<ImportQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<RepoImport>>, (StatusCode, String)> {
+    state.import_service.list(query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/imports/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the import")),
+    responses(
+        (status = 200, body = RepoImport),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_import(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<RepoImport>, (StatusCode, String)> {
+    state.import_service.get(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/import-jobs",
+    tag = "admin",
+    request_body = ImportJobRequest,
+    responses(
+        (status = 200, body = ImportJob),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn create_import_job(
+    state: State<ApiServiceState>,
+    Json(json): Json<ImportJobRequest>,
+) -> Result<Json<ImportJob>, (StatusCode, String)> {
+    state.import_service.create_job(json).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/import-jobs",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<ImportJob>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_import_jobs(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<ImportJob>>, (StatusCode, String)> {
+    state.import_service.list_jobs().await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/import-jobs/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the import job")),
+    responses(
+        (status = 200, body = ImportJob),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_import_job(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<ImportJob>, (StatusCode, String)> {
+    state.import_service.get_job(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/import-jobs/{id}/retry",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the import job")),
+    responses(
+        (status = 200, body = ImportJobRequeued),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn retry_import_job(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<ImportJobRequeued>, (StatusCode, String)> {
+    state.import_service.requeue_job(id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/mirrors",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<Mirror>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_mirrors(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<Mirror>>, (StatusCode, String)> {
+    state.mirror_service.list().await
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/mirrors",
+    tag = "admin",
+    request_body = MirrorUpdate,
+    responses(
+        (status = 200, body = Mirror),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_mirror(
+    state: State<ApiServiceState>,
+    Json(json): Json<MirrorUpdate>,
+) -> Result<Json<Mirror>, (StatusCode, String)> {
+    state.mirror_service.save(json).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/mirrors/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the mirror")),
+    responses(
+        (status = 200, body = Mirror),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_mirror(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Mirror>, (StatusCode, String)> {
+    state.mirror_service.get(id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/mirrors/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the mirror")),
+    responses(
+        (status = 200, body = Mirror),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_mirror(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<Mirror>, (StatusCode, String)> {
+    state.mirror_service.delete(id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/mirrors/{id}/sync",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the mirror")),
+    responses(
+        (status = 200, body = MirrorSync),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn sync_mirror(
+    Path(id): Path<i64>,
+    state: State<ApiServiceState>,
+) -> Result<Json<MirrorSync>, (StatusCode, String)> {
+    state.mirror_service.sync_now(id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/operations",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<OperationStatus>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_operations() -> Json<Vec<OperationStatus>> {
+    Json(operation::list().iter().map(OperationStatus::from).collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/operations/{id}",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the operation")),
+    responses(
+        (status = 200, body = OperationStatus),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_operation(Path(id): Path<i64>) -> Result<Json<OperationStatus>, (StatusCode, String)> {
+    operation::find(id)
+        .map(|operation| Json(OperationStatus::from(&operation)))
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no operation {} is running", id)))
+}
+
+/// Cancel a running operation, which stops at its next step.
+#[utoipa::path(
+    post,
+    path = "/admin/operations/{id}/cancel",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Id of the operation")),
+    responses(
+        (status = 200, body = OperationStatus),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn cancel_operation(
+    Path(id): Path<i64>,
+) -> Result<Json<OperationStatus>, (StatusCode, String)> {
+    let operation = operation::find(id)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no operation {} is running", id)))?;
+    operation.cancel();
+    tracing::info!("cancelled {} of {}", operation.kind(), operation.target());
+    Ok(Json(OperationStatus::from(&operation)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/missing-objects",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<MissingObjectStatus>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_missing_objects() -> Json<Vec<MissingObjectStatus>> {
+    Json(
+        missing_objects::list()
+            .iter()
+            .map(MissingObjectStatus::from)
+            .collect(),
+    )
+}
+
+/// Fetch the objects missing from a repository again, from its mirror upstream or the urls given.
+#[utoipa::path(
+    post,
+    path = "/admin/missing-objects/repair",
+    tag = "admin",
+    request_body = RepairRequest,
+    responses(
+        (status = 200, body = RepairResult),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn repair_missing_objects(
+    state: State<ApiServiceState>,
+    Json(request): Json<RepairRequest>,
+) -> Result<Json<RepairResult>, (StatusCode, String)> {
+    state.repair_service.repair(request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/feature-flags/{name}",
+    tag = "feature flags",
+    params(
+        ("name" = String, Path, description = "Name of the flag"),
+        FeatureFlagQuery
+    ),
+    responses(
+        (status = 200, body = FeatureFlagStatus),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn get_feature_flag_status(
+    Path(name): Path<String>,
+    Query(query): Query<FeatureFlagQuery>,
+    state: State<ApiServiceState>,
+) -> Result<Json<FeatureFlagStatus>, (StatusCode, String)> {
+    state
+        .feature_flag_service
+        .flag_status(name, query.org)
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/feature-flags",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<FeatureFlag>),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn list_feature_flags(
+    state: State<ApiServiceState>,
+) -> Result<Json<Vec<FeatureFlag>>, (StatusCode, String)> {
+    state.feature_flag_service.list_flags().await
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/feature-flags/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the flag")),
+    request_body = FeatureFlagUpdate,
+    responses(
+        (status = 200, body = FeatureFlag),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn save_feature_flag(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+    Json(update): Json<FeatureFlagUpdate>,
+) -> Result<Json<FeatureFlag>, (StatusCode, String)> {
+    state.feature_flag_service.save_flag(name, update).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/feature-flags/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Name of the flag")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = "default", body = ApiError)
+    )
+)]
+async fn delete_feature_flag(
+    Path(name): Path<String>,
+    state: State<ApiServiceState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.feature_flag_service.delete_flag(&name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let get_mr = &doc["paths"]["/mr/{id}"]["get"];
+        assert_eq!(get_mr["tags"][0], "merge requests");
+        assert_eq!(get_mr["parameters"][0]["name"], "id");
+        assert_eq!(get_mr["parameters"][0]["in"], "path");
+        let query: Vec<&str> = doc["paths"]["/blame"]["get"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert!(query.contains(&"repo_path"));
+        assert!(doc["paths"]["/issues"]["post"]["requestBody"].is_object());
+        assert!(doc["paths"]["/issues/{id}"]["patch"]["responses"]["default"].is_object());
+        assert!(doc["components"]["schemas"]["ApiError"].is_object());
+        assert!(doc["components"]["securitySchemes"]["token"].is_object());
+    }
+}
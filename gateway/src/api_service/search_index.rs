@@ -0,0 +1,465 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use axum::http::StatusCode;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, STORED, STRING, TEXT};
+use tantivy::{
+    Document, Index, IndexReader, IndexWriter, ReloadPolicy, SnippetGenerator, TantivyError, Term,
+};
+
+use crate::model::search::SearchHit;
+
+/// Memory the index writer may use before it flushes a segment.
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Longest snippet returned with a hit, in characters.
+const SNIPPET_CHARS: usize = 200;
+
+/// Files larger than this are not indexed, they are rarely source code.
+pub const MAX_FILE_SIZE: usize = 1024 * 1024;
+
+pub const KIND_CODE: &str = "code";
+pub const KIND_COMMIT: &str = "commit";
+pub const KIND_ISSUE: &str = "issue";
+pub const KIND_MERGE_REQUEST: &str = "merge_request";
+
+/// A change to the documents of the index.
+pub enum IndexChange {
+    /// Add a file, replacing what was indexed at its path
+    PutFile {
+        repo_path: String,
+        path: String,
+        blob_id: String,
+        content: String,
+    },
+    RemoveFile {
+        repo_path: String,
+        path: String,
+    },
+    /// Remove every file of a repository, its commits, issues and merge requests stay
+    RemoveFiles {
+        repo_path: String,
+    },
+    /// Add a commit, issue or merge request, replacing the indexed one with the same id
+    PutItem(IndexItem),
+}
+
+pub struct IndexItem {
+    /// One of `KIND_COMMIT`, `KIND_ISSUE` or `KIND_MERGE_REQUEST`
+    pub kind: &'static str,
+    pub repo_path: String,
+    pub id: String,
+    pub title: String,
+    /// Message of a commit or body of an issue
+    pub content: String,
+    pub state: Option<String>,
+    pub labels: Vec<String>,
+}
+
+/// Search words split from the qualifiers narrowing the results, see [`parse_query`].
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedQuery {
+    /// Words in the query syntax of tantivy, empty to match everything the qualifiers allow
+    pub text: String,
+    /// Any of these kinds, all of them when empty
+    pub kinds: Vec<String>,
+    /// All of these labels
+    pub labels: Vec<String>,
+    /// Any of these states
+    pub states: Vec<String>,
+    pub repo_path: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    /// Kind, repository and path or id of a document, unique in the index
+    key: Field,
+    kind: Field,
+    repo_path: Field,
+    path: Field,
+    /// Every directory of the monorepo containing the document, to filter by path
+    scope: Field,
+    /// Blob id of a file, commit id, or id of an issue or merge request
+    item_id: Field,
+    title: Field,
+    content: Field,
+    state: Field,
+    label: Field,
+}
+
+/// A tantivy index of the files on the default branch of every repository, and of the commits,
+/// issues and merge requests of every repository.
+///
+/// Only one process may write to an index, the writer is shared by every clone.
+#[derive(Clone)]
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Arc<Mutex<IndexWriter>>,
+    fields: Fields,
+}
+
+fn document_key(kind: &str, repo_path: &str, id: &str) -> String {
+    // NUL cannot be part of a git path
+    format!("{}\0{}\0{}", kind, repo_path, id)
+}
+
+/// `dir` and the directories containing it, from the root down.
+fn dir_scopes(dir: &str) -> Vec<String> {
+    let mut scopes = vec!["/".to_owned()];
+    let mut current = String::new();
+    for name in dir.split('/').filter(|c| !c.is_empty()) {
+        current.push('/');
+        current.push_str(name);
+        scopes.push(current.clone());
+    }
+    scopes
+}
+
+/// The directories of the monorepo containing the file at `path` of the repository at
+/// `repo_path`, from the root down.
+pub fn scopes(repo_path: &str, path: &str) -> Vec<String> {
+    let full_path = format!("{}/{}", repo_path.trim_end_matches('/'), path);
+    let parent = full_path.rsplit_once('/').map_or("", |(parent, _)| parent);
+    dir_scopes(parent)
+}
+
+/// The text of a blob if it is worth indexing: small enough and valid UTF-8 without NUL bytes.
+pub fn indexable_text(data: Vec<u8>) -> Option<String> {
+    if data.len() > MAX_FILE_SIZE || data.contains(&0) {
+        return None;
+    }
+    String::from_utf8(data).ok()
+}
+
+/// Split the qualifiers `type:`, `label:`, `state:` and `repo:` from the words of a search.
+/// Values with spaces are quoted, e.g. `label:"good first issue"`.
+pub fn parse_query(q: &str) -> Result<ParsedQuery, (StatusCode, String)> {
+    let mut parsed = ParsedQuery::default();
+    let mut words = Vec::new();
+    for token in split_tokens(q) {
+        let Some((qualifier, value)) = token.split_once(':') else {
+            words.push(token);
+            continue;
+        };
+        let value = value.trim_matches('"').to_owned();
+        if value.is_empty() {
+            words.push(token);
+            continue;
+        }
+        match qualifier {
+            "type" => {
+                let kind = match value.as_str() {
+                    "mr" => KIND_MERGE_REQUEST,
+                    KIND_CODE => KIND_CODE,
+                    KIND_COMMIT => KIND_COMMIT,
+                    KIND_ISSUE => KIND_ISSUE,
+                    KIND_MERGE_REQUEST => KIND_MERGE_REQUEST,
+                    _ => {
+                        return Err((
+                            StatusCode::BAD_REQUEST,
+                            format!("unknown search type: {}", value),
+                        ))
+                    }
+                };
+                parsed.kinds.push(kind.to_owned());
+            }
+            "label" => parsed.labels.push(value),
+            "state" => parsed.states.push(value),
+            "repo" => parsed.repo_path = Some(value),
+            _ => words.push(token),
+        }
+    }
+    parsed.text = words.join(" ");
+    Ok(parsed)
+}
+
+/// Split `q` on whitespace outside of double quotes.
+fn split_tokens(q: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in q.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+impl SearchIndex {
+    /// Open the index in `dir`, creating it when it does not exist yet. An index written with
+    /// another schema is replaced by an empty one.
+    pub fn open(dir: &Path) -> Result<SearchIndex, TantivyError> {
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            key: builder.add_text_field("key", STRING),
+            kind: builder.add_text_field("kind", STRING | STORED),
+            repo_path: builder.add_text_field("repo_path", STRING | STORED),
+            path: builder.add_text_field("path", TEXT | STORED),
+            scope: builder.add_text_field("scope", STRING),
+            item_id: builder.add_text_field("item_id", STRING | STORED),
+            title: builder.add_text_field("title", TEXT | STORED),
+            content: builder.add_text_field("content", TEXT | STORED),
+            state: builder.add_text_field("state", STRING | STORED),
+            label: builder.add_text_field("label", STRING | STORED),
+        };
+        let schema = builder.build();
+        std::fs::create_dir_all(dir)?;
+        let index = match Index::open_or_create(MmapDirectory::open(dir)?, schema.clone()) {
+            Err(TantivyError::SchemaError(e)) => {
+                tracing::warn!(
+                    "replacing the search index at {}, repositories need a reindex: {}",
+                    dir.display(),
+                    e
+                );
+                std::fs::remove_dir_all(dir)?;
+                std::fs::create_dir_all(dir)?;
+                Index::create_in_dir(dir, schema)?
+            }
+            index => index?,
+        };
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommit)
+            .try_into()?;
+        let writer = index.writer(WRITER_MEMORY)?;
+        Ok(SearchIndex {
+            index,
+            reader,
+            writer: Arc::new(Mutex::new(writer)),
+            fields,
+        })
+    }
+
+    /// Id of the last event whose changes are in the index.
+    pub fn cursor(&self) -> Result<Option<i64>, TantivyError> {
+        let metas = self.index.load_metas()?;
+        Ok(metas.payload.and_then(|p| p.parse().ok()))
+    }
+
+    fn term(&self, field: Field, text: &str) -> Box<dyn Query> {
+        let term = Term::from_field_text(field, text);
+        Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+    }
+
+    /// Apply `changes` in one commit, which records `cursor` when given. Blocks until the commit
+    /// is written.
+    pub fn apply(
+        &self,
+        changes: Vec<IndexChange>,
+        cursor: Option<i64>,
+    ) -> Result<(), TantivyError> {
+        let mut writer = self.writer.lock().unwrap();
+        let cursor = match cursor {
+            Some(cursor) => Some(cursor),
+            None => self.cursor()?,
+        };
+        for change in changes {
+            match change {
+                IndexChange::PutFile {
+                    repo_path,
+                    path,
+                    blob_id,
+                    content,
+                } => {
+                    let key = document_key(KIND_CODE, &repo_path, &path);
+                    writer.delete_term(Term::from_field_text(self.fields.key, &key));
+                    let mut doc = Document::new();
+                    doc.add_text(self.fields.key, &key);
+                    doc.add_text(self.fields.kind, KIND_CODE);
+                    for scope in scopes(&repo_path, &path) {
+                        doc.add_text(self.fields.scope, scope);
+                    }
+                    doc.add_text(self.fields.repo_path, repo_path);
+                    doc.add_text(self.fields.path, path);
+                    doc.add_text(self.fields.item_id, blob_id);
+                    doc.add_text(self.fields.content, content);
+                    writer.add_document(doc)?;
+                }
+                IndexChange::RemoveFile { repo_path, path } => {
+                    let key = document_key(KIND_CODE, &repo_path, &path);
+                    writer.delete_term(Term::from_field_text(self.fields.key, &key));
+                }
+                IndexChange::RemoveFiles { repo_path } => {
+                    writer.delete_query(Box::new(BooleanQuery::new(vec![
+                        (Occur::Must, self.term(self.fields.kind, KIND_CODE)),
+                        (Occur::Must, self.term(self.fields.repo_path, &repo_path)),
+                    ])))?;
+                }
+                IndexChange::PutItem(item) => {
+                    let key = document_key(item.kind, &item.repo_path, &item.id);
+                    writer.delete_term(Term::from_field_text(self.fields.key, &key));
+                    let mut doc = Document::new();
+                    doc.add_text(self.fields.key, &key);
+                    doc.add_text(self.fields.kind, item.kind);
+                    for scope in dir_scopes(&item.repo_path) {
+                        doc.add_text(self.fields.scope, scope);
+                    }
+                    doc.add_text(self.fields.repo_path, item.repo_path);
+                    doc.add_text(self.fields.item_id, item.id);
+                    doc.add_text(self.fields.title, item.title);
+                    doc.add_text(self.fields.content, item.content);
+                    if let Some(state) = item.state {
+                        doc.add_text(self.fields.state, state);
+                    }
+                    for label in item.labels {
+                        doc.add_text(self.fields.label, label);
+                    }
+                    writer.add_document(doc)?;
+                }
+            }
+        }
+        let mut commit = writer.prepare_commit()?;
+        if let Some(cursor) = cursor {
+            commit.set_payload(&cursor.to_string());
+        }
+        commit.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// Best matches of `query` in contents, titles and file paths, limited to a path of the
+    /// monorepo when it is given, `limit` of them after the first `offset`.
+    pub fn search(
+        &self,
+        query: &ParsedQuery,
+        path: Option<&str>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>, (StatusCode, String)> {
+        let text_query: Box<dyn Query> = if query.text.trim().is_empty() {
+            Box::new(AllQuery)
+        } else {
+            let mut parser = QueryParser::for_index(
+                &self.index,
+                vec![self.fields.content, self.fields.title, self.fields.path],
+            );
+            parser.set_conjunction_by_default();
+            parser
+                .parse_query(&query.text)
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid query: {}", e)))?
+        };
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, text_query)];
+        let any_of = |field: Field, values: &[String]| -> Box<dyn Query> {
+            let terms = values
+                .iter()
+                .map(|v| (Occur::Should, self.term(field, v)))
+                .collect();
+            Box::new(BooleanQuery::new(terms))
+        };
+        if !query.kinds.is_empty() {
+            clauses.push((Occur::Must, any_of(self.fields.kind, &query.kinds)));
+        }
+        if !query.states.is_empty() {
+            clauses.push((Occur::Must, any_of(self.fields.state, &query.states)));
+        }
+        for label in &query.labels {
+            clauses.push((Occur::Must, self.term(self.fields.label, label)));
+        }
+        if let Some(repo_path) = &query.repo_path {
+            clauses.push((Occur::Must, self.term(self.fields.repo_path, repo_path)));
+        }
+        if let Some(path) = path
+            .map(|p| p.trim_end_matches('/'))
+            .filter(|p| !p.is_empty())
+        {
+            clauses.push((Occur::Must, self.term(self.fields.scope, path)));
+        }
+        let query = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher
+            .search(&query, &TopDocs::with_limit(limit).and_offset(offset))
+            .map_err(internal_error)?;
+        let mut snippets = SnippetGenerator::create(&searcher, &query, self.fields.content)
+            .map_err(internal_error)?;
+        snippets.set_max_num_chars(SNIPPET_CHARS);
+        let mut hits = Vec::with_capacity(top_docs.len());
+        for (score, address) in top_docs {
+            let doc = searcher.doc(address).map_err(internal_error)?;
+            let text = |field: Field| {
+                doc.get_first(field)
+                    .and_then(|v| v.as_text())
+                    .map(str::to_owned)
+            };
+            hits.push(SearchHit {
+                item_type: text(self.fields.kind).unwrap_or_default(),
+                repo_path: text(self.fields.repo_path).unwrap_or_default(),
+                id: text(self.fields.item_id).unwrap_or_default(),
+                path: text(self.fields.path),
+                title: text(self.fields.title).filter(|t| !t.is_empty()),
+                state: text(self.fields.state),
+                labels: doc
+                    .get_all(self.fields.label)
+                    .filter_map(|v| v.as_text())
+                    .map(str::to_owned)
+                    .collect(),
+                score,
+                snippet: snippets.snippet_from_doc(&doc).to_html(),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{indexable_text, parse_query, scopes, ParsedQuery};
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(
+            scopes("/projects/app", "src/main.rs"),
+            vec!["/", "/projects", "/projects/app", "/projects/app/src"]
+        );
+        assert_eq!(scopes("/", "README.md"), vec!["/"]);
+    }
+
+    #[test]
+    fn test_indexable_text() {
+        assert_eq!(
+            indexable_text(b"fn main() {}".to_vec()).as_deref(),
+            Some("fn main() {}")
+        );
+        assert_eq!(indexable_text(vec![0x89, b'P', b'N', b'G', 0]), None);
+        assert_eq!(indexable_text(vec![b'a'; super::MAX_FILE_SIZE + 1]), None);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query(r#"type:mr label:"good first issue" state:open "null pointer" path:src"#)
+                .unwrap(),
+            ParsedQuery {
+                text: r#""null pointer" path:src"#.to_owned(),
+                kinds: vec!["merge_request".to_owned()],
+                labels: vec!["good first issue".to_owned()],
+                states: vec!["open".to_owned()],
+                repo_path: None,
+            }
+        );
+        assert_eq!(
+            parse_query("type:issue label:bug").unwrap().text,
+            String::new()
+        );
+        assert!(parse_query("type:wiki").is_err());
+    }
+}
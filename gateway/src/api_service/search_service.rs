@@ -0,0 +1,501 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::Json;
+use futures::future::BoxFuture;
+use tokio::sync::Notify;
+
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::issue_storage::{IssueFilter, IssueStorage};
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+use jupiter::storage::mr_storage::{MrFilter, MrStorage};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::commit::Commit;
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::event_service::{EVENT_ISSUE, EVENT_MERGE_REQUEST, EVENT_PUSH};
+use crate::api_service::object_loader::{self, ObjectLoader};
+use crate::api_service::page::{encode_cursor, Order};
+use crate::api_service::planning_service::PlanningService;
+use crate::api_service::search_index::{
+    self, IndexChange, IndexItem, ParsedQuery, SearchIndex, KIND_CODE, KIND_COMMIT, KIND_ISSUE,
+    KIND_MERGE_REQUEST,
+};
+use crate::model::event::RefPush;
+use crate::model::issue::Issue;
+use crate::model::mr::MergeRequest;
+use crate::model::page::{Page, PageQuery};
+use crate::model::search::{CodeSearchHit, CodeSearchQuery, SearchHit, SearchQuery};
+
+const DEFAULT_INDEX_PATH: &str = "/tmp/.mega/search";
+
+/// Results are listed best first, by score.
+const ORDER: Order = Order::desc("score");
+
+/// How often the indexer looks for new events.
+const INDEX_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Most events indexed in one commit.
+const BATCH_SIZE: u64 = 50;
+
+/// Most commits indexed for one push, a new branch does not walk the whole history.
+const MAX_PUSH_COMMITS: usize = 500;
+
+/// Searches the code on the default branch of every repository, and the commits, issues and
+/// merge requests of every repository.
+///
+/// The index follows the event log, so pushes over SSH are indexed too when the SSH server runs
+/// in another process.
+#[derive(Clone)]
+pub struct SearchService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub event_storage: EventStorage,
+    pub issue_storage: IssueStorage,
+    pub mr_storage: MrStorage,
+    pub planning: PlanningService,
+    /// `None` when the index could not be opened, searches fail then.
+    pub index: Option<SearchIndex>,
+    /// Wakes the indexer before its next poll, see [`SearchIndexHook`](crate::api_service::ref_hook::SearchIndexHook)
+    pub wake: Arc<Notify>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+/// Open the index at `MEGA_CODE_SEARCH_INDEX_PATH`. Search is disabled when that fails, the rest
+/// of the server works without it.
+pub fn open_index() -> Option<SearchIndex> {
+    let path = std::env::var("MEGA_CODE_SEARCH_INDEX_PATH")
+        .unwrap_or_else(|_| DEFAULT_INDEX_PATH.to_owned());
+    match SearchIndex::open(&PathBuf::from(&path)) {
+        Ok(index) => Some(index),
+        Err(e) => {
+            tracing::warn!(
+                "unable to open the search index at {}, search is disabled: {}",
+                path,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Collect the changes turning the files of tree `old` into the files of tree `new`, either can
+/// be missing. `prefix` is the path of the trees in the repository.
+fn diff_trees<'a>(
+    loader: &'a mut ObjectLoader,
+    repo_path: &'a str,
+    prefix: String,
+    old: Option<SHA1>,
+    new: Option<SHA1>,
+    changes: &'a mut Vec<IndexChange>,
+) -> BoxFuture<'a, Result<(), (StatusCode, String)>> {
+    Box::pin(async move {
+        // the files below a tree missing from the store stay as the index has them
+        let Some(old) = tree_items(loader, repo_path, old).await? else {
+            return Ok(());
+        };
+        let Some(new) = tree_items(loader, repo_path, new).await? else {
+            return Ok(());
+        };
+        let names: BTreeSet<String> = old
+            .iter()
+            .chain(new.iter())
+            .map(|i| i.name.clone())
+            .collect();
+        for name in names {
+            let o = old.iter().find(|i| i.name == name);
+            let n = new.iter().find(|i| i.name == name);
+            if let (Some(o), Some(n)) = (o, n) {
+                if o.id == n.id && o.mode == n.mode {
+                    continue;
+                }
+            }
+            let path = format!("{}{}", prefix, name);
+            let subtree = |item: Option<&TreeItem>| {
+                item.filter(|i| i.mode == TreeItemMode::Tree).map(|i| i.id)
+            };
+            let (old_tree, new_tree) = (subtree(o), subtree(n));
+            if old_tree.is_some() || new_tree.is_some() {
+                diff_trees(
+                    loader,
+                    repo_path,
+                    format!("{}/", path),
+                    old_tree,
+                    new_tree,
+                    changes,
+                )
+                .await?;
+            }
+            if o.is_some_and(|o| o.mode != TreeItemMode::Tree) {
+                changes.push(IndexChange::RemoveFile {
+                    repo_path: repo_path.to_owned(),
+                    path: path.clone(),
+                });
+            }
+            // submodules and symlinks have no content of their own
+            let Some(n) =
+                n.filter(|n| matches!(n.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable))
+            else {
+                continue;
+            };
+            let blob = loader.blob(&n.id).await;
+            let Some(data) = loader.tolerate_missing(blob, repo_path, "index")? else {
+                continue;
+            };
+            if let Some(content) = search_index::indexable_text(data) {
+                changes.push(IndexChange::PutFile {
+                    repo_path: repo_path.to_owned(),
+                    path,
+                    blob_id: n.id.to_plain_str(),
+                    content,
+                });
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The items of the tree `id`, none without a tree, `None` when it is missing from the store.
+async fn tree_items(
+    loader: &mut ObjectLoader,
+    repo_path: &str,
+    id: Option<SHA1>,
+) -> Result<Option<Vec<TreeItem>>, (StatusCode, String)> {
+    let Some(id) = id else {
+        return Ok(Some(Vec::new()));
+    };
+    let tree = loader.tree(&id).await;
+    Ok(loader
+        .tolerate_missing(tree, repo_path, "index")?
+        .map(|tree| tree.tree_items))
+}
+
+async fn commit_tree(
+    loader: &mut ObjectLoader,
+    commit_id: Option<&str>,
+) -> Result<Option<SHA1>, (StatusCode, String)> {
+    let Some(commit_id) = commit_id else {
+        return Ok(None);
+    };
+    let id = SHA1::from_str(commit_id).map_err(internal_error)?;
+    Ok(Some(loader.commit(&id).await?.tree_id))
+}
+
+/// Commits reachable from `head` but not through `stop`, newest first and at most
+/// `MAX_PUSH_COMMITS` of them. Commits missing from the storage are skipped.
+async fn new_commits(
+    loader: &mut ObjectLoader,
+    head: SHA1,
+    stop: Option<SHA1>,
+) -> Result<Vec<Commit>, (StatusCode, String)> {
+    let mut seen = HashSet::from([head]);
+    let mut queue = VecDeque::from([head]);
+    let mut commits = Vec::new();
+    while let Some(id) = queue.pop_front() {
+        if commits.len() >= MAX_PUSH_COMMITS {
+            break;
+        }
+        if Some(id) == stop {
+            continue;
+        }
+        let commit = match loader.commit(&id).await {
+            Ok(commit) => commit,
+            Err((StatusCode::NOT_FOUND, _)) => continue,
+            Err(e) => return Err(e),
+        };
+        for parent in &commit.parent_commit_ids {
+            if seen.insert(*parent) {
+                queue.push_back(*parent);
+            }
+        }
+        commits.push(commit);
+    }
+    Ok(commits)
+}
+
+fn commit_item(repo_path: &str, commit: &Commit) -> IndexItem {
+    let body = object_loader::commit_body(&commit.message);
+    IndexItem {
+        kind: KIND_COMMIT,
+        repo_path: repo_path.to_owned(),
+        id: commit.id.to_plain_str(),
+        title: object_loader::commit_summary(&commit.message),
+        content: body.trim().to_owned(),
+        state: None,
+        labels: Vec::new(),
+    }
+}
+
+fn issue_item(issue: Issue) -> IndexItem {
+    IndexItem {
+        kind: KIND_ISSUE,
+        repo_path: issue.repo_path,
+        id: issue.id.to_string(),
+        title: issue.title,
+        content: issue.body,
+        state: Some(issue.state),
+        labels: issue.labels,
+    }
+}
+
+fn mr_item(mr: MergeRequest) -> IndexItem {
+    IndexItem {
+        kind: KIND_MERGE_REQUEST,
+        repo_path: mr.repo_path,
+        id: mr.id.to_string(),
+        title: mr.title,
+        content: String::new(),
+        state: Some(mr.status),
+        labels: mr.labels,
+    }
+}
+
+impl SearchService {
+    fn index(&self) -> Result<&SearchIndex, (StatusCode, String)> {
+        self.index.as_ref().ok_or_else(|| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "search is not available".to_owned(),
+            )
+        })
+    }
+
+    /// A page of the results of `query`. Scores have no keyset, so the cursor of a search is
+    /// the number of results of the pages before it.
+    async fn run_search(
+        &self,
+        query: ParsedQuery,
+        path: Option<String>,
+        page: PageQuery,
+    ) -> Result<Page<SearchHit>, (StatusCode, String)> {
+        let index = self.index()?.clone();
+        page.order(&[], ORDER)?;
+        let offset: u64 = page.after(&ORDER)?.unwrap_or(0);
+        let limit = page.limit();
+        let hits = tokio::task::spawn_blocking(move || {
+            index.search(&query, path.as_deref(), limit as usize + 1, offset as usize)
+        })
+        .await
+        .map_err(internal_error)??;
+        let mut results = Page::of(hits, limit, &ORDER, |_| ());
+        if results.next_cursor.is_some() {
+            results.next_cursor = Some(encode_cursor(&ORDER, offset + limit));
+        }
+        Ok(results)
+    }
+
+    /// Search files, commits, issues and merge requests at once.
+    pub async fn search(
+        &self,
+        query: SearchQuery,
+        page: PageQuery,
+    ) -> Result<Json<Page<SearchHit>>, (StatusCode, String)> {
+        let parsed = search_index::parse_query(&query.q)?;
+        if parsed == ParsedQuery::default() {
+            return Err((StatusCode::BAD_REQUEST, "q must not be empty".to_owned()));
+        }
+        let hits = self.run_search(parsed, query.path, page).await?;
+        Ok(Json(hits))
+    }
+
+    pub async fn search_code(
+        &self,
+        query: CodeSearchQuery,
+        page: PageQuery,
+    ) -> Result<Json<Page<CodeSearchHit>>, (StatusCode, String)> {
+        if query.q.trim().is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "q must not be empty".to_owned()));
+        }
+        let parsed = ParsedQuery {
+            text: query.q,
+            kinds: vec![KIND_CODE.to_owned()],
+            repo_path: query.repo_path,
+            ..Default::default()
+        };
+        let hits = self.run_search(parsed, query.path, page).await?;
+        Ok(Json(hits.map(|hit| CodeSearchHit {
+            repo_path: hit.repo_path,
+            path: hit.path.unwrap_or_default(),
+            blob_id: hit.id,
+            score: hit.score,
+            snippet: hit.snippet,
+        })))
+    }
+
+    /// Index the repository at `repo_path` from scratch, for repositories pushed before the
+    /// index existed: the files and history of its default branch, its issues and its merge
+    /// requests.
+    pub async fn reindex(&self, repo_path: &str) -> Result<StatusCode, (StatusCode, String)> {
+        let index = self.index()?.clone();
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let (_, head) = loader.default_branch(repo_path).await?;
+        let tree_id = loader.commit(&head).await?.tree_id;
+        let mut changes = vec![IndexChange::RemoveFiles {
+            repo_path: repo_path.to_owned(),
+        }];
+        diff_trees(
+            &mut loader,
+            repo_path,
+            String::new(),
+            None,
+            Some(tree_id),
+            &mut changes,
+        )
+        .await?;
+        for commit in new_commits(&mut loader, head, None).await? {
+            changes.push(IndexChange::PutItem(commit_item(repo_path, &commit)));
+        }
+
+        let issues = self
+            .issue_storage
+            .list_issues(IssueFilter {
+                repo_path: Some(repo_path),
+                ..Default::default()
+            })
+            .await
+            .map_err(internal_error)?;
+        let ids: Vec<i64> = issues.iter().map(|i| i.id).collect();
+        let mut links = self.planning.links_of(ITEM_ISSUE, &ids).await?;
+        for issue in issues {
+            let item_links = links.remove(&issue.id).unwrap_or_default();
+            changes.push(IndexChange::PutItem(issue_item(Issue::new(
+                issue, item_links,
+            ))));
+        }
+        let mrs = self
+            .mr_storage
+            .list_mrs(MrFilter {
+                repo_path: Some(repo_path),
+                ..Default::default()
+            })
+            .await
+            .map_err(internal_error)?;
+        let ids: Vec<i64> = mrs.iter().map(|mr| mr.id).collect();
+        let mut links = self.planning.links_of(ITEM_MR, &ids).await?;
+        for mr in mrs {
+            let item_links = links.remove(&mr.id).unwrap_or_default();
+            changes.push(IndexChange::PutItem(mr_item(MergeRequest::new(
+                mr, item_links,
+            ))));
+        }
+
+        tokio::task::spawn_blocking(move || index.apply(changes, None))
+            .await
+            .map_err(internal_error)?
+            .map_err(internal_error)?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// Keep the index up to date with the event log in the background.
+    pub fn start_indexer(self) {
+        let Some(index) = self.index.clone() else {
+            return;
+        };
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(INDEX_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = self.wake.notified() => {}
+                }
+                if let Err((_, e)) = self.index_events(&index).await {
+                    tracing::warn!("unable to update the search index: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Index the pushes, issues and merge requests recorded since the last run.
+    async fn index_events(&self, index: &SearchIndex) -> Result<(), (StatusCode, String)> {
+        let cursor = index.cursor().map_err(internal_error)?.unwrap_or(0);
+        let types = [EVENT_PUSH, EVENT_ISSUE, EVENT_MERGE_REQUEST].map(str::to_owned);
+        let events = self
+            .event_storage
+            .events_after(cursor, None, &types, BATCH_SIZE)
+            .await
+            .map_err(internal_error)?;
+        let Some(last) = events.last().map(|e| e.id) else {
+            return Ok(());
+        };
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut changes = Vec::new();
+        for event in events {
+            match event.event_type.as_str() {
+                EVENT_PUSH => {
+                    let Ok(push) = serde_json::from_str::<RefPush>(&event.payload) else {
+                        continue;
+                    };
+                    self.push_changes(&mut loader, &event.repo_path, &push, &mut changes)
+                        .await?;
+                }
+                EVENT_ISSUE => {
+                    if let Ok(issue) = serde_json::from_str::<Issue>(&event.payload) {
+                        changes.push(IndexChange::PutItem(issue_item(issue)));
+                    }
+                }
+                EVENT_MERGE_REQUEST => {
+                    if let Ok(mr) = serde_json::from_str::<MergeRequest>(&event.payload) {
+                        changes.push(IndexChange::PutItem(mr_item(mr)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let index = index.clone();
+        tokio::task::spawn_blocking(move || index.apply(changes, Some(last)))
+            .await
+            .map_err(internal_error)?
+            .map_err(internal_error)
+    }
+
+    /// The changes a push made: the commits it added to any ref, and the files it changed on
+    /// the default branch of its repository.
+    async fn push_changes(
+        &self,
+        loader: &mut ObjectLoader,
+        repo_path: &str,
+        push: &RefPush,
+        changes: &mut Vec<IndexChange>,
+    ) -> Result<(), (StatusCode, String)> {
+        if let Some(after) = push.after.as_deref() {
+            let head = SHA1::from_str(after).map_err(internal_error)?;
+            let stop = push.before.as_deref().and_then(|b| SHA1::from_str(b).ok());
+            for commit in new_commits(loader, head, stop).await? {
+                changes.push(IndexChange::PutItem(commit_item(repo_path, &commit)));
+            }
+        }
+
+        let default_ref = match loader.default_branch(repo_path).await {
+            Ok((default_ref, _)) => default_ref,
+            // the last ref of the repository was deleted
+            Err((StatusCode::NOT_FOUND, _)) => {
+                changes.push(IndexChange::RemoveFiles {
+                    repo_path: repo_path.to_owned(),
+                });
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        if default_ref != push.ref_name {
+            return Ok(());
+        }
+        // an old commit that cannot be read leaves nothing to compare to, start over
+        let old = commit_tree(loader, push.before.as_deref())
+            .await
+            .unwrap_or(None);
+        let new = commit_tree(loader, push.after.as_deref()).await?;
+        if old.is_none() {
+            changes.push(IndexChange::RemoveFiles {
+                repo_path: repo_path.to_owned(),
+            });
+        }
+        diff_trees(loader, repo_path, String::new(), old, new, changes).await
+    }
+}
@@ -0,0 +1,409 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum::Json;
+use rand::distributions::{Alphanumeric, DistString};
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::mega_signing_key;
+use git::protocol::verify::CommitVerifier;
+use jupiter::storage::signing_key_storage::SigningKeyStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::commit::Commit;
+use venus::internal::object::ObjectTrait;
+
+use crate::api_service::object_loader::ObjectLoader;
+use crate::auth::signing::{self, SignatureKind, SshSignature};
+use crate::model::signing_key::{
+    CommitSignature, CommitSignatureQuery, KeyVerification, NewSigningKey, SigningKey,
+};
+
+const CHALLENGE_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct SigningKeyService {
+    pub storage: SigningKeyStorage,
+    pub object_storage: Arc<dyn ObjectStorage>,
+}
+
+fn internal_error(e: MegaError) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Check a signature over the challenge of `key`. `echo` appends a newline to what it signs, so
+/// the challenge is accepted with or without one.
+fn verify_challenge(key: &mega_signing_key::Model, signature: &str) -> Result<bool, MegaError> {
+    let candidates = [key.challenge.clone(), format!("{}\n", key.challenge)];
+    match key.key_type.as_str() {
+        "gpg" => {
+            for data in &candidates {
+                if signing::verify_gpg(&key.public_key, signature, data.as_bytes())? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => {
+            let signature = SshSignature::parse(signature)?;
+            if signing::ssh_fingerprint(&signature.public_key) != key.key_id {
+                return Ok(false);
+            }
+            for data in &candidates {
+                if signature.verify(signing::SSH_CHALLENGE_NAMESPACE, data.as_bytes())? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+    }
+}
+
+impl SigningKeyService {
+    pub async fn list_keys(
+        &self,
+        username: &str,
+    ) -> Result<Json<Vec<SigningKey>>, (StatusCode, String)> {
+        let keys = self
+            .storage
+            .list_keys(username)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(keys.into_iter().map(SigningKey::from).collect()))
+    }
+
+    pub async fn add_key(
+        &self,
+        username: String,
+        new_key: NewSigningKey,
+    ) -> Result<Json<SigningKey>, (StatusCode, String)> {
+        let bad_request = |e: MegaError| (StatusCode::BAD_REQUEST, e.to_string());
+        let (key_id, subkey_ids, emails, public_key, default_title) =
+            match new_key.key_type.as_str() {
+                "gpg" => {
+                    let info = signing::gpg_key_info(&new_key.public_key).map_err(bad_request)?;
+                    let title = info.emails.first().cloned().unwrap_or_default();
+                    (
+                        info.key_id,
+                        info.subkey_ids,
+                        info.emails,
+                        new_key.public_key.trim().to_owned(),
+                        title,
+                    )
+                }
+                "ssh" => {
+                    let blob =
+                        signing::ssh_signing_key(&new_key.public_key).map_err(bad_request)?;
+                    let line = crate::auth::ssh_key::split_key_line(&new_key.public_key)
+                        .map_err(bad_request)?;
+                    (
+                        signing::ssh_fingerprint(&blob),
+                        vec![],
+                        vec![],
+                        format!("{} {}", line.key_type, line.base64),
+                        line.comment.unwrap_or(line.key_type).to_owned(),
+                    )
+                }
+                other => {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        format!("unknown key type {}, expected gpg or ssh", other),
+                    ))
+                }
+            };
+
+        if self
+            .storage
+            .find_by_key_id(&key_id)
+            .await
+            .map_err(internal_error)?
+            .is_some()
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("key {} is already registered", key_id),
+            ));
+        }
+
+        let title =
+            new_key
+                .title
+                .filter(|t| !t.trim().is_empty())
+                .unwrap_or(if default_title.is_empty() {
+                    key_id.clone()
+                } else {
+                    default_title
+                });
+        let key = mega_signing_key::Model {
+            id: generate_id(),
+            username,
+            title,
+            key_type: new_key.key_type,
+            key_id,
+            subkey_ids,
+            emails,
+            public_key,
+            challenge: Alphanumeric.sample_string(&mut rand::thread_rng(), CHALLENGE_LEN),
+            verified: false,
+            verified_at: None,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        self.storage
+            .save_key(key.clone())
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(key.into()))
+    }
+
+    /// Mark a key as verified once its owner has signed the key's challenge with it.
+    pub async fn verify_key(
+        &self,
+        username: &str,
+        id: i64,
+        verification: KeyVerification,
+    ) -> Result<Json<SigningKey>, (StatusCode, String)> {
+        let Some(mut key) = self
+            .storage
+            .get_key(username, id)
+            .await
+            .map_err(internal_error)?
+        else {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("{} has no signing key {}", username, id),
+            ));
+        };
+        if key.verified {
+            return Ok(Json(key.into()));
+        }
+        match verify_challenge(&key, &verification.signature) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!(
+                        "signature does not match the challenge of key {}",
+                        key.key_id
+                    ),
+                ))
+            }
+            Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string())),
+        }
+        self.storage
+            .mark_verified(key.id)
+            .await
+            .map_err(internal_error)?;
+        key.verified = true;
+        key.verified_at = Some(chrono::Utc::now().naive_utc());
+        Ok(Json(key.into()))
+    }
+
+    pub async fn delete_key(
+        &self,
+        username: &str,
+        id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        match self.storage.delete_key(username, id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("{} has no signing key {}", username, id),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// Check the signature of a commit and resolve the mega account that made it.
+    pub async fn verify_commit(
+        &self,
+        query: CommitSignatureQuery,
+    ) -> Result<Json<CommitSignature>, (StatusCode, String)> {
+        let commit_id =
+            SHA1::from_str(&query.commit_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let commit = ObjectLoader::new(self.object_storage.clone())
+            .commit(&commit_id)
+            .await?;
+        Ok(Json(self.commit_signature(&commit).await))
+    }
+
+    /// The signature status of `commit`.
+    ///
+    /// A signature only counts as verified when it was made by a verified key, and for GPG keys
+    /// when the committer email is one of the key's user ids.
+    pub async fn commit_signature(&self, commit: &Commit) -> CommitSignature {
+        let mut result = CommitSignature {
+            commit_id: commit.id.to_plain_str(),
+            signed: false,
+            verified: false,
+            signature_type: None,
+            key_id: None,
+            signer: None,
+            reason: None,
+        };
+        let Some(signature) = &commit.gpgsig else {
+            result.reason = Some("commit is not signed".to_owned());
+            return result;
+        };
+        let payload = match commit.signed_data() {
+            Ok(payload) => payload,
+            Err(e) => {
+                result.signed = true;
+                result.reason = Some(e.to_string());
+                return result;
+            }
+        };
+        self.check_signature(
+            result,
+            signature,
+            &payload,
+            ("committer", &commit.committer.email),
+        )
+        .await
+    }
+
+    /// Check `signature` over `payload`, made by the committer or tagger `signer` with its
+    /// email, and fill in `result`.
+    async fn check_signature(
+        &self,
+        mut result: CommitSignature,
+        signature: &str,
+        payload: &[u8],
+        signer: (&str, &str),
+    ) -> CommitSignature {
+        result.signed = true;
+        let Some(kind) = SignatureKind::of(signature) else {
+            result.reason = Some("unknown signature format".to_owned());
+            return result;
+        };
+        result.signature_type = Some(kind.name().to_owned());
+
+        let checked = match kind {
+            SignatureKind::Gpg => {
+                self.verify_gpg_commit(signature, payload, &mut result)
+                    .await
+            }
+            SignatureKind::Ssh => {
+                self.verify_ssh_commit(signature, payload, &mut result)
+                    .await
+            }
+        };
+        match checked {
+            Ok(Some(key)) => {
+                if kind == SignatureKind::Gpg {
+                    let (role, email) = signer;
+                    let email = email.to_lowercase();
+                    if !key.emails.contains(&email) {
+                        result.reason = Some(format!(
+                            "{} email {} is not an identity of key {}",
+                            role, email, key.key_id
+                        ));
+                        return result;
+                    }
+                }
+                result.verified = true;
+                result.signer = Some(key.username);
+            }
+            Ok(None) => {}
+            Err(e) => result.reason = Some(e.to_string()),
+        }
+        result
+    }
+
+    /// The verified key that made a GPG commit signature, `None` with a reason set otherwise.
+    async fn verify_gpg_commit(
+        &self,
+        signature: &str,
+        payload: &[u8],
+        result: &mut CommitSignature,
+    ) -> Result<Option<mega_signing_key::Model>, MegaError> {
+        let Some(issuer) = signing::gpg_issuer(signature)? else {
+            result.reason = Some("signature does not name its key".to_owned());
+            return Ok(None);
+        };
+        result.key_id = Some(issuer.clone());
+        let Some(key) = self.storage.find_verified_signer(&issuer).await? else {
+            result.reason = Some(format!("no verified key {}", issuer));
+            return Ok(None);
+        };
+        if !signing::verify_gpg(&key.public_key, signature, payload)? {
+            result.reason = Some("bad signature".to_owned());
+            return Ok(None);
+        }
+        Ok(Some(key))
+    }
+
+    /// The verified key that made an SSH commit signature, `None` with a reason set otherwise.
+    async fn verify_ssh_commit(
+        &self,
+        signature: &str,
+        payload: &[u8],
+        result: &mut CommitSignature,
+    ) -> Result<Option<mega_signing_key::Model>, MegaError> {
+        let signature = SshSignature::parse(signature)?;
+        let fingerprint = signing::ssh_fingerprint(&signature.public_key);
+        result.key_id = Some(fingerprint.clone());
+        let Some(key) = self.storage.find_verified_signer(&fingerprint).await? else {
+            result.reason = Some(format!("no verified key {}", fingerprint));
+            return Ok(None);
+        };
+        if !signature.verify(signing::SSH_GIT_NAMESPACE, payload)? {
+            result.reason = Some("bad signature".to_owned());
+            return Ok(None);
+        }
+        Ok(Some(key))
+    }
+}
+
+#[async_trait]
+impl CommitVerifier for SigningKeyService {
+    async fn unverified_reason(&self, id: &str, data: &[u8]) -> Option<String> {
+        let mut commit = match Commit::from_bytes(data) {
+            Ok(commit) => commit,
+            Err(e) => return Some(e.to_string()),
+        };
+        commit.id = SHA1::from_str(id).unwrap_or_default();
+        let signature = self.commit_signature(&commit).await;
+        if signature.verified {
+            return None;
+        }
+        signature.reason.or_else(|| Some("not verified".to_owned()))
+    }
+
+    async fn unverified_tag_reason(&self, id: &str, data: &[u8]) -> Option<String> {
+        let Some((payload, signature)) = signing::split_tag_signature(data) else {
+            return Some("tag is not signed".to_owned());
+        };
+        let tagger = tagger_email(payload).unwrap_or_default();
+        let result = CommitSignature {
+            commit_id: id.to_owned(),
+            signed: true,
+            verified: false,
+            signature_type: None,
+            key_id: None,
+            signer: None,
+            reason: None,
+        };
+        let signature = self
+            .check_signature(result, signature, payload, ("tagger", &tagger))
+            .await;
+        if signature.verified {
+            return None;
+        }
+        signature.reason.or_else(|| Some("not verified".to_owned()))
+    }
+}
+
+/// The email of the `tagger` header of a tag, tags made by old versions of git have none.
+fn tagger_email(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    let line = text
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .find(|line| line.starts_with("tagger "))?;
+    let start = line.find('<')? + 1;
+    let end = start + line[start..].find('>')?;
+    Some(line[start..end].to_owned())
+}
@@ -0,0 +1,631 @@
+//! Archives of a tree at a commit, as `git archive` writes them: tar, gzipped tar and zip.
+//!
+//! Every entry carries the time of the commit and no owner, so the archive of a commit is the
+//! same bytes each time it is made. The commit id is recorded in the archive: in a pax global
+//! header for tar, where `git get-tar-commit-id` finds it, and as the comment of a zip.
+use std::io::{self, Write};
+
+use chrono::{Datelike, Timelike};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{Compression, Crc};
+use venus::internal::object::tree::TreeItemMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "tar" => Some(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" => Some(ArchiveFormat::TarGz),
+            "zip" => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+/// What an entry of an archive is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Directory,
+    File,
+    Executable,
+    /// The data of a symlink is its target
+    Symlink,
+}
+
+impl EntryKind {
+    /// The entry of a tree item. Submodules are empty directories, as `git archive` leaves them.
+    pub fn of(mode: TreeItemMode) -> Self {
+        match mode {
+            TreeItemMode::Tree | TreeItemMode::Commit => EntryKind::Directory,
+            TreeItemMode::BlobExecutable => EntryKind::Executable,
+            TreeItemMode::Link => EntryKind::Symlink,
+            TreeItemMode::Blob => EntryKind::File,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        match self {
+            EntryKind::Directory => 0o40755,
+            EntryKind::File => 0o100644,
+            EntryKind::Executable => 0o100755,
+            EntryKind::Symlink => 0o120777,
+        }
+    }
+}
+
+const BLOCK: usize = 512;
+
+/// Tar archives are padded to a multiple of this, the record size of tar and `git archive`.
+const RECORD: u64 = 10240;
+
+/// Sizes from this on don't fit the octal size field of a ustar header.
+const USTAR_MAX_SIZE: u64 = 1 << 33;
+
+/// Writes an archive entry by entry, each call returning the bytes that come next.
+pub struct ArchiveWriter {
+    format: ArchiveFormat,
+    commit_id: String,
+    mtime: u64,
+    /// Bytes of the archive so far, before any compression
+    written: u64,
+    gzip: Option<GzEncoder<Vec<u8>>>,
+    /// The central directory of a zip, written at its end
+    central: Vec<u8>,
+    entries: u64,
+}
+
+impl ArchiveWriter {
+    /// A writer of the archive of `commit_id`, every entry modified at `mtime`.
+    pub fn new(format: ArchiveFormat, commit_id: &str, mtime: u64) -> Self {
+        ArchiveWriter {
+            format,
+            commit_id: commit_id.to_owned(),
+            mtime,
+            written: 0,
+            gzip: (format == ArchiveFormat::TarGz)
+                .then(|| GzEncoder::new(Vec::new(), Compression::default())),
+            central: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    /// The bytes before the first entry.
+    pub fn start(&mut self) -> io::Result<Vec<u8>> {
+        match self.format {
+            ArchiveFormat::Zip => Ok(Vec::new()),
+            _ => {
+                let comment = pax_record("comment", self.commit_id.as_bytes());
+                let mut raw = tar_header(
+                    b"pax_global_header",
+                    0o666,
+                    comment.len() as u64,
+                    self.mtime,
+                    b'g',
+                    b"",
+                )
+                .to_vec();
+                raw.extend_from_slice(&comment);
+                pad(&mut raw);
+                self.emit(raw)
+            }
+        }
+    }
+
+    /// Add the entry at `path`, `/` separated and without a trailing `/` for directories.
+    pub fn add(&mut self, path: &str, kind: EntryKind, data: &[u8]) -> io::Result<Vec<u8>> {
+        let name = match kind {
+            EntryKind::Directory => format!("{}/", path),
+            _ => path.to_owned(),
+        };
+        self.entries += 1;
+        match self.format {
+            ArchiveFormat::Zip => self.zip_entry(&name, kind, data),
+            _ => {
+                let raw = self.tar_entry(&name, kind, data);
+                self.emit(raw)
+            }
+        }
+    }
+
+    /// The bytes after the last entry.
+    pub fn finish(mut self) -> io::Result<Vec<u8>> {
+        match self.format {
+            ArchiveFormat::Zip => self.zip_end(),
+            _ => {
+                let mut raw = vec![0; 2 * BLOCK];
+                let end = self.written + raw.len() as u64;
+                raw.resize(raw.len() + ((RECORD - end % RECORD) % RECORD) as usize, 0);
+                let mut out = self.emit(raw)?;
+                if let Some(gzip) = self.gzip.take() {
+                    out.extend(gzip.finish()?);
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    fn emit(&mut self, raw: Vec<u8>) -> io::Result<Vec<u8>> {
+        self.written += raw.len() as u64;
+        match &mut self.gzip {
+            Some(gzip) => {
+                gzip.write_all(&raw)?;
+                Ok(std::mem::take(gzip.get_mut()))
+            }
+            None => Ok(raw),
+        }
+    }
+
+    fn tar_entry(&self, name: &str, kind: EntryKind, data: &[u8]) -> Vec<u8> {
+        let (typeflag, size, link) = match kind {
+            EntryKind::Directory => (b'5', 0, &[][..]),
+            EntryKind::Symlink => (b'2', 0, data),
+            _ => (b'0', data.len() as u64, &[][..]),
+        };
+        let mut raw = Vec::new();
+        // what doesn't fit the ustar header goes to a pax header before it
+        let mut pax = Vec::new();
+        if name.len() > 100 {
+            pax.extend(pax_record("path", name.as_bytes()));
+        }
+        if link.len() > 100 {
+            pax.extend(pax_record("linkpath", link));
+        }
+        if size >= USTAR_MAX_SIZE {
+            pax.extend(pax_record("size", size.to_string().as_bytes()));
+        }
+        if !pax.is_empty() {
+            raw.extend(tar_header(
+                name.as_bytes(),
+                0o644,
+                pax.len() as u64,
+                self.mtime,
+                b'x',
+                b"",
+            ));
+            raw.extend(pax);
+            pad(&mut raw);
+        }
+        let mode = kind.mode() & 0o7777;
+        raw.extend(tar_header(
+            name.as_bytes(),
+            mode,
+            size,
+            self.mtime,
+            typeflag,
+            link,
+        ));
+        if typeflag == b'0' {
+            raw.extend_from_slice(data);
+            pad(&mut raw);
+        }
+        raw
+    }
+
+    fn zip_entry(&mut self, name: &str, kind: EntryKind, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let (method, stored) = match kind {
+            EntryKind::File | EntryKind::Executable if !data.is_empty() => {
+                let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+                deflate.write_all(data)?;
+                let deflated = deflate.finish()?;
+                if deflated.len() < data.len() {
+                    (8u16, deflated)
+                } else {
+                    (0, data.to_vec())
+                }
+            }
+            _ => (0, data.to_vec()),
+        };
+        if data.len() as u64 >= u32::MAX as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is too large for a zip archive", name),
+            ));
+        }
+        let (time, date) = dos_time(self.mtime);
+        let offset = self.written;
+
+        let mut raw = Vec::with_capacity(30 + name.len() + stored.len());
+        put_u32(&mut raw, 0x04034b50);
+        put_u16(&mut raw, 20);
+        // names are UTF-8
+        put_u16(&mut raw, 0x0800);
+        put_u16(&mut raw, method);
+        put_u16(&mut raw, time);
+        put_u16(&mut raw, date);
+        put_u32(&mut raw, crc.sum());
+        put_u32(&mut raw, stored.len() as u32);
+        put_u32(&mut raw, data.len() as u32);
+        put_u16(&mut raw, name.len() as u16);
+        put_u16(&mut raw, 0);
+        raw.extend_from_slice(name.as_bytes());
+        raw.extend_from_slice(&stored);
+
+        // offsets past 4 GiB are given in a zip64 extra field
+        let zip64 = offset >= u32::MAX as u64;
+        let central = &mut self.central;
+        put_u32(central, 0x02014b50);
+        // made on unix, so that the mode in the external attributes is read
+        put_u16(central, (3 << 8) | if zip64 { 45 } else { 20 });
+        put_u16(central, if zip64 { 45 } else { 20 });
+        put_u16(central, 0x0800);
+        put_u16(central, method);
+        put_u16(central, time);
+        put_u16(central, date);
+        put_u32(central, crc.sum());
+        put_u32(central, stored.len() as u32);
+        put_u32(central, data.len() as u32);
+        put_u16(central, name.len() as u16);
+        put_u16(central, if zip64 { 12 } else { 0 });
+        put_u16(central, 0);
+        put_u16(central, 0);
+        put_u16(central, 0);
+        let dos_directory = if kind == EntryKind::Directory {
+            0x10
+        } else {
+            0
+        };
+        put_u32(central, (kind.mode() << 16) | dos_directory);
+        put_u32(central, if zip64 { u32::MAX } else { offset as u32 });
+        central.extend_from_slice(name.as_bytes());
+        if zip64 {
+            put_u16(central, 0x0001);
+            put_u16(central, 8);
+            put_u64(central, offset);
+        }
+        self.emit(raw)
+    }
+
+    fn zip_end(&mut self) -> io::Result<Vec<u8>> {
+        let mut raw = std::mem::take(&mut self.central);
+        let directory_size = raw.len() as u64;
+        let directory_offset = self.written;
+        let zip64 = self.entries >= u16::MAX as u64
+            || directory_offset >= u32::MAX as u64
+            || directory_size >= u32::MAX as u64;
+        if zip64 {
+            let record_offset = directory_offset + directory_size;
+            put_u32(&mut raw, 0x06064b50);
+            put_u64(&mut raw, 44);
+            put_u16(&mut raw, (3 << 8) | 45);
+            put_u16(&mut raw, 45);
+            put_u32(&mut raw, 0);
+            put_u32(&mut raw, 0);
+            put_u64(&mut raw, self.entries);
+            put_u64(&mut raw, self.entries);
+            put_u64(&mut raw, directory_size);
+            put_u64(&mut raw, directory_offset);
+            put_u32(&mut raw, 0x07064b50);
+            put_u32(&mut raw, 0);
+            put_u64(&mut raw, record_offset);
+            put_u32(&mut raw, 1);
+        }
+        let entries = self.entries.min(u16::MAX as u64) as u16;
+        put_u32(&mut raw, 0x06054b50);
+        put_u16(&mut raw, 0);
+        put_u16(&mut raw, 0);
+        put_u16(&mut raw, entries);
+        put_u16(&mut raw, entries);
+        put_u32(&mut raw, directory_size.min(u32::MAX as u64) as u32);
+        put_u32(&mut raw, directory_offset.min(u32::MAX as u64) as u32);
+        put_u16(&mut raw, self.commit_id.len() as u16);
+        raw.extend_from_slice(self.commit_id.as_bytes());
+        self.emit(raw)
+    }
+}
+
+/// A ustar header. Fields too long for it are cut, a pax header before it holds them whole.
+fn tar_header(
+    name: &[u8],
+    mode: u32,
+    size: u64,
+    mtime: u64,
+    typeflag: u8,
+    link: &[u8],
+) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    let name = &name[..name.len().min(100)];
+    header[..name.len()].copy_from_slice(name);
+    octal(&mut header[100..108], mode as u64);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    if size < USTAR_MAX_SIZE {
+        octal(&mut header[124..136], size);
+    }
+    octal(&mut header[136..148], mtime);
+    header[156] = typeflag;
+    let link = &link[..link.len().min(100)];
+    header[157..157 + link.len()].copy_from_slice(link);
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[265..269].copy_from_slice(b"root");
+    header[297..301].copy_from_slice(b"root");
+    // the checksum is computed with its own field taken as spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    header
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    let digits = &digits.as_bytes()[digits.len().saturating_sub(field.len() - 1)..];
+    field[..digits.len()].copy_from_slice(digits);
+    field[digits.len()] = 0;
+}
+
+/// A pax record, `<length> <key>=<value>\n` where the length counts itself.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut length = rest;
+    loop {
+        let next = rest + length.to_string().len();
+        if next == length {
+            break;
+        }
+        length = next;
+    }
+    let mut record = format!("{} {}=", length, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+fn pad(raw: &mut Vec<u8>) {
+    raw.resize(raw.len().div_ceil(BLOCK) * BLOCK, 0);
+}
+
+/// The MS-DOS time and date of a zip entry, in UTC and no earlier than 1980.
+fn dos_time(mtime: u64) -> (u16, u16) {
+    let Some(time) = chrono::DateTime::from_timestamp(mtime as i64, 0) else {
+        return (0, 0x21);
+    };
+    if time.year() < 1980 {
+        return (0, 0x21);
+    }
+    let year = (time.year() - 1980).min(127) as u16;
+    (
+        ((time.hour() as u16) << 11) | ((time.minute() as u16) << 5) | (time.second() as u16 / 2),
+        (year << 9) | ((time.month() as u16) << 5) | time.day() as u16,
+    )
+}
+
+fn put_u16(raw: &mut Vec<u8>, value: u16) {
+    raw.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(raw: &mut Vec<u8>, value: u32) {
+    raw.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(raw: &mut Vec<u8>, value: u64) {
+    raw.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::{Command, Stdio};
+
+    use flate2::read::GzDecoder;
+    use venus::internal::object::tree::TreeItemMode;
+
+    use super::{pax_record, ArchiveFormat, ArchiveWriter, EntryKind, BLOCK, RECORD};
+
+    const COMMIT: &str = "8ab686eafeb1f44702738c8b0f24f2567c36da6d";
+
+    fn archive(format: ArchiveFormat, long_name: &str) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(format, COMMIT, 1_700_000_000);
+        let mut out = writer.start().unwrap();
+        out.extend(writer.add("mega", EntryKind::Directory, b"").unwrap());
+        out.extend(
+            writer
+                .add("mega/README.md", EntryKind::File, b"# mega\n")
+                .unwrap(),
+        );
+        out.extend(
+            writer
+                .add("mega/run.sh", EntryKind::Executable, b"#!/bin/sh\n")
+                .unwrap(),
+        );
+        out.extend(
+            writer
+                .add("mega/docs", EntryKind::Symlink, b"README.md")
+                .unwrap(),
+        );
+        out.extend(writer.add(long_name, EntryKind::File, b"deep").unwrap());
+        out.extend(writer.finish().unwrap());
+        out
+    }
+
+    #[test]
+    fn test_pax_record() {
+        let record = pax_record("comment", COMMIT.as_bytes());
+        assert_eq!(record, format!("52 comment={}\n", COMMIT).into_bytes());
+        // the digit the length gains counts too: 98 bytes and two digits would make 100
+        let record = pax_record("k", &[b'v'; 94]);
+        assert_eq!(record.len(), 101);
+        assert!(record.starts_with(b"101 k=v"));
+    }
+
+    #[test]
+    fn test_tar() {
+        let long_name = format!("mega/{}", "d/".repeat(60) + "file");
+        let tar = archive(ArchiveFormat::Tar, &long_name);
+        assert_eq!(tar.len() as u64 % RECORD, 0);
+        assert_eq!(&tar[..17], b"pax_global_header");
+        assert_eq!(tar[156], b'g');
+        assert_eq!(
+            &tar[BLOCK..BLOCK + 52],
+            pax_record("comment", COMMIT.as_bytes())
+        );
+        let readme = &tar[3 * BLOCK..4 * BLOCK];
+        assert_eq!(&readme[..14], b"mega/README.md");
+        assert_eq!(&readme[100..107], b"0000644");
+        assert_eq!(&readme[124..135], b"00000000007");
+        assert_eq!(
+            &readme[136..147],
+            format!("{:011o}", 1_700_000_000).as_bytes()
+        );
+        let sum: u32 = readme
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if (148..156).contains(&i) {
+                    b' ' as u32
+                } else {
+                    *b as u32
+                }
+            })
+            .sum();
+        assert_eq!(&readme[148..155], format!("{:06o}\0", sum).as_bytes());
+        let record = pax_record("path", long_name.as_bytes());
+        assert!(tar.windows(record.len()).any(|w| w == record));
+
+        // the same commit gives the same bytes, gzipped or not
+        assert_eq!(tar, archive(ArchiveFormat::Tar, &long_name));
+        let gz = archive(ArchiveFormat::TarGz, &long_name);
+        assert_eq!(gz, archive(ArchiveFormat::TarGz, &long_name));
+        let mut unzipped = Vec::new();
+        GzDecoder::new(&gz[..]).read_to_end(&mut unzipped).unwrap();
+        assert_eq!(unzipped, tar);
+    }
+
+    #[test]
+    fn test_zip() {
+        let zip = archive(ArchiveFormat::Zip, "mega/deep/file");
+        assert_eq!(&zip[..4], &0x04034b50u32.to_le_bytes());
+        assert!(zip.ends_with(COMMIT.as_bytes()));
+        let end = zip.len() - COMMIT.len() - 22;
+        assert_eq!(&zip[end..end + 4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([zip[end + 10], zip[end + 11]]), 5);
+        let directory_offset =
+            u32::from_le_bytes(zip[end + 16..end + 20].try_into().unwrap()) as usize;
+        assert_eq!(
+            &zip[directory_offset..directory_offset + 4],
+            &0x02014b50u32.to_le_bytes()
+        );
+        // the small README is stored, with its crc
+        let readme = zip
+            .windows(14)
+            .position(|w| w == b"mega/README.md")
+            .unwrap();
+        assert_eq!(&zip[readme + 14..readme + 21], b"# mega\n");
+        assert_eq!(zip, archive(ArchiveFormat::Zip, "mega/deep/file"));
+    }
+
+    /// The items of a repository with an executable, a symlink and a submodule, by path.
+    const REPO: &[(&str, TreeItemMode, &[u8])] = &[
+        ("mega", TreeItemMode::Tree, b""),
+        ("mega/README.md", TreeItemMode::Blob, b"# mega\n"),
+        ("mega/bin", TreeItemMode::Tree, b""),
+        (
+            "mega/bin/run.sh",
+            TreeItemMode::BlobExecutable,
+            b"#!/bin/sh\necho mega\n",
+        ),
+        ("mega/docs", TreeItemMode::Link, b"README.md"),
+        ("mega/bin/run", TreeItemMode::Link, b"../bin/run.sh"),
+        ("mega/lib", TreeItemMode::Commit, b""),
+    ];
+
+    fn repo_archive(format: ArchiveFormat) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(format, COMMIT, 1_700_000_000);
+        let mut out = writer.start().unwrap();
+        for (path, mode, data) in REPO {
+            out.extend(writer.add(path, EntryKind::of(*mode), data).unwrap());
+        }
+        out.extend(writer.finish().unwrap());
+        out
+    }
+
+    #[test]
+    fn test_tar_round_trip() {
+        let dir = std::env::temp_dir().join(format!("mega-archive-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut tar = Command::new("tar")
+            .args(["-xf", "-", "-C"])
+            .arg(&dir)
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let archive = repo_archive(ArchiveFormat::Tar);
+        tar.stdin.take().unwrap().write_all(&archive).unwrap();
+        assert!(tar.wait().unwrap().success());
+
+        let mode = |path: &str| {
+            let metadata = std::fs::symlink_metadata(dir.join(path)).unwrap();
+            (metadata.file_type(), metadata.permissions().mode() & 0o777)
+        };
+        let (file_type, permissions) = mode("mega/bin/run.sh");
+        assert!(file_type.is_file());
+        assert_eq!(permissions, 0o755);
+        assert_eq!(mode("mega/README.md").1, 0o644);
+        for (link, target) in [
+            ("mega/docs", "README.md"),
+            ("mega/bin/run", "../bin/run.sh"),
+        ] {
+            assert!(mode(link).0.is_symlink());
+            assert_eq!(
+                std::fs::read_link(dir.join(link)).unwrap().to_str(),
+                Some(target)
+            );
+        }
+        // the links resolve inside the archive
+        assert_eq!(
+            std::fs::read(dir.join("mega/bin/run")).unwrap(),
+            b"#!/bin/sh\necho mega\n"
+        );
+        assert!(mode("mega/lib").0.is_dir());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip_modes() {
+        let zip = repo_archive(ArchiveFormat::Zip);
+        let end = zip.len() - COMMIT.len() - 22;
+        let mut at = u32::from_le_bytes(zip[end + 16..end + 20].try_into().unwrap()) as usize;
+        let mut modes = Vec::new();
+        while zip[at..at + 4] == 0x02014b50u32.to_le_bytes() {
+            let u16_at = |i: usize| u16::from_le_bytes([zip[at + i], zip[at + i + 1]]) as usize;
+            let (name_len, extra_len, comment_len) = (u16_at(28), u16_at(30), u16_at(32));
+            let attributes = u32::from_le_bytes(zip[at + 38..at + 42].try_into().unwrap());
+            let name = std::str::from_utf8(&zip[at + 46..at + 46 + name_len]).unwrap();
+            modes.push((name.to_owned(), attributes >> 16));
+            at += 46 + name_len + extra_len + comment_len;
+        }
+        let mode = |name: &str| modes.iter().find(|(n, _)| n == name).unwrap().1;
+        assert_eq!(modes.len(), REPO.len());
+        assert_eq!(mode("mega/bin/run.sh"), 0o100755);
+        assert_eq!(mode("mega/README.md"), 0o100644);
+        assert_eq!(mode("mega/docs"), 0o120777);
+        assert_eq!(mode("mega/lib/"), 0o40755);
+        // a symlink is stored with its target as data
+        let docs = zip.windows(9).position(|w| w == b"mega/docs").unwrap();
+        assert_eq!(&zip[docs + 9..docs + 18], b"README.md");
+    }
+}
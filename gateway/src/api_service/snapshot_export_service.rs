@@ -0,0 +1,510 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::NaiveDateTime;
+use futures::TryStreamExt;
+use sha2::{Digest, Sha256};
+
+use common::utils::generate_id;
+use db_entity::mega_snapshot_export;
+use jupiter::storage::snapshot_export_storage::SnapshotExportStorage;
+use storage::driver::file_storage::FileStorage;
+
+use crate::api_service::bundle_service::BundleService;
+use crate::api_service::path_move;
+use crate::api_service::ref_hook::{RefChange, RefHook};
+use crate::api_service::ref_trigger::Schedule;
+use crate::api_service::snapshot::ArchiveFormat;
+use crate::api_service::snapshot_service::SnapshotService;
+use crate::model::snapshot_export::{
+    split_paths, ExportArtifact, ExportManifest, SnapshotExport, SnapshotExportRun,
+    SnapshotExportUpdate,
+};
+
+/// How often the scheduler looks for exports that are due.
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Name of the format writing a git bundle of the repository instead of archives.
+const BUNDLE_FORMAT: &str = "bundle";
+
+/// Exports directories of repositories as archives, or repositories as bundles, to the object
+/// storage on a schedule or when tags are created, for consumers such as build farms to pick up
+/// without fetching from the server.
+///
+/// A run writes its artifacts and a `manifest.json` listing them under `<export>/<commit>/`, then
+/// points `<export>/latest.json` at the manifest, and `<export>/tags/<tag>.json` too for the runs
+/// of a tag. The manifest is written last, so an artifact a manifest lists is always complete.
+#[derive(Clone)]
+pub struct SnapshotExportService {
+    pub export_storage: SnapshotExportStorage,
+    pub snapshots: SnapshotService,
+    pub bundles: BundleService,
+    /// The `snapshot-exports` bucket, or directory with local storage
+    pub target: Arc<dyn FileStorage>,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+/// Whether `tag` matches `pattern`, a pattern ending in `*` matching every tag it is a prefix of.
+fn matches_tag(pattern: &str, tag: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => tag.starts_with(prefix),
+        None => tag == pattern,
+    }
+}
+
+/// A directory to export relative to the repository root, `""` for the root.
+fn normalize_dir(path: &str) -> Option<String> {
+    let path = path.trim().trim_matches('/');
+    if path
+        .split('/')
+        .any(|c| c == "." || c == ".." || (c.is_empty() && !path.is_empty()))
+    {
+        return None;
+    }
+    Some(path.to_owned())
+}
+
+/// File name of the artifact of `path`, the last component of the repository path for its root.
+fn artifact_name(repo_path: &str, path: &str, extension: &str) -> String {
+    let base = if path.is_empty() {
+        repo_path
+            .rsplit('/')
+            .find(|name| !name.is_empty())
+            .unwrap_or("mega")
+            .to_owned()
+    } else {
+        path.replace('/', "-")
+    };
+    format!("{}.{}", base, extension)
+}
+
+impl SnapshotExportService {
+    pub async fn list_exports(&self) -> Result<Json<Vec<SnapshotExport>>, (StatusCode, String)> {
+        let exports = self
+            .export_storage
+            .list_exports()
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(
+            exports.into_iter().map(SnapshotExport::from).collect(),
+        ))
+    }
+
+    /// Create the export `name`, or replace its configuration keeping its run history.
+    pub async fn save_export(
+        &self,
+        name: String,
+        update: SnapshotExportUpdate,
+    ) -> Result<Json<SnapshotExport>, (StatusCode, String)> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(bad_request(format!("invalid export name: {}", name)));
+        }
+        let repo_path = path_move::normalize_path(&update.repo_path)
+            .ok_or_else(|| bad_request(format!("invalid path: {}", update.repo_path)))?;
+        let format = update.format.unwrap_or_else(|| "tar.gz".to_owned());
+        if format != BUNDLE_FORMAT && ArchiveFormat::parse(&format).is_none() {
+            return Err(bad_request(format!(
+                "unknown export format {}, use tar.gz, tar, zip or bundle",
+                format
+            )));
+        }
+        let mut paths = Vec::new();
+        for path in &update.paths {
+            let dir = normalize_dir(path)
+                .ok_or_else(|| bad_request(format!("invalid directory: {}", path)))?;
+            if !paths.contains(&dir) {
+                paths.push(dir);
+            }
+        }
+        if paths.is_empty() {
+            paths.push(String::new());
+        }
+        if format == BUNDLE_FORMAT && paths.iter().any(|p| !p.is_empty()) {
+            return Err(bad_request(
+                "a bundle holds the whole repository, it can't export directories",
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let schedule = match update.schedule.as_deref().map(str::trim) {
+            Some(spec) if !spec.is_empty() => Some(Schedule::parse(spec).map_err(bad_request)?),
+            _ => None,
+        };
+        let tag_pattern = update
+            .tag_pattern
+            .map(|p| p.trim().trim_start_matches("refs/tags/").to_owned())
+            .filter(|p| !p.is_empty());
+        let source_ref = match update.source {
+            Some(source) if source.starts_with("refs/") => source,
+            Some(source) => format!("refs/heads/{}", source),
+            None => "refs/heads/main".to_owned(),
+        };
+
+        let existing = self
+            .export_storage
+            .get_export(&name)
+            .await
+            .map_err(internal_error)?;
+        let export = mega_snapshot_export::Model {
+            id: existing.as_ref().map_or_else(generate_id, |e| e.id),
+            name,
+            repo_path,
+            source_ref,
+            paths: paths.join("\n"),
+            format,
+            schedule: update
+                .schedule
+                .filter(|_| schedule.is_some())
+                .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" ")),
+            tag_pattern,
+            enabled: update.enabled,
+            next_run_at: schedule.map(|s| s.next_after(now)),
+            last_run_at: existing.as_ref().and_then(|e| e.last_run_at),
+            last_commit: existing.as_ref().and_then(|e| e.last_commit.clone()),
+            last_manifest: existing.as_ref().and_then(|e| e.last_manifest.clone()),
+            last_error: existing.as_ref().and_then(|e| e.last_error.clone()),
+            created_at: existing.as_ref().map_or(now, |e| e.created_at),
+            updated_at: now,
+        };
+        let export = match existing {
+            Some(_) => self.export_storage.update_export(export).await,
+            None => self
+                .export_storage
+                .save_export(export.clone())
+                .await
+                .map(|_| export),
+        }
+        .map_err(internal_error)?;
+        Ok(Json(export.into()))
+    }
+
+    pub async fn delete_export(&self, name: &str) -> Result<StatusCode, (StatusCode, String)> {
+        match self.export_storage.delete_export(name).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("snapshot export {} not found", name),
+            )),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    /// Run an export now, at `refs` or at its source ref.
+    pub async fn run_export(
+        &self,
+        name: &str,
+        refs: Option<&str>,
+    ) -> Result<Json<SnapshotExportRun>, (StatusCode, String)> {
+        let export = match self.export_storage.get_export(name).await {
+            Ok(Some(export)) => export,
+            Ok(None) => {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("snapshot export {} not found", name),
+                ))
+            }
+            Err(e) => return Err(internal_error(e)),
+        };
+        let refs = refs.unwrap_or(&export.source_ref).to_owned();
+        let result = self.execute(&export, &refs, None).await;
+        self.record(&export, &result).await;
+        result.map(Json)
+    }
+
+    /// Check for due exports every [`SCHEDULER_INTERVAL`] for as long as the server runs.
+    pub fn start_scheduler(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.run_due().await;
+            }
+        });
+    }
+
+    /// Run every enabled export whose time has come. A run missed while the server was down
+    /// happens once on the next check, not once per missed occurrence.
+    async fn run_due(&self) {
+        let now = chrono::Utc::now().naive_utc();
+        let due = match self.export_storage.due_exports(now).await {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::warn!("unable to load snapshot exports: {}", e);
+                return;
+            }
+        };
+        for export in due {
+            let (Some(scheduled), Some(spec)) = (export.next_run_at, export.schedule.as_deref())
+            else {
+                continue;
+            };
+            let next = match Schedule::parse(spec) {
+                Ok(schedule) => schedule.next_after(now),
+                Err(e) => {
+                    tracing::warn!(
+                        "snapshot export {} has an invalid schedule: {}",
+                        export.name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            match self
+                .export_storage
+                .claim_run(export.id, scheduled, next)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    tracing::warn!("unable to schedule snapshot export {}: {}", export.name, e);
+                    continue;
+                }
+            }
+            let result = self.execute(&export, &export.source_ref, None).await;
+            self.record(&export, &result).await;
+        }
+    }
+
+    async fn record(
+        &self,
+        export: &mega_snapshot_export::Model,
+        result: &Result<SnapshotExportRun, (StatusCode, String)>,
+    ) {
+        let outcome = match result {
+            Ok(run) => {
+                tracing::info!("snapshot export {} wrote {}", export.name, run.manifest_key);
+                Ok((run.manifest.commit_id.as_str(), run.manifest_key.as_str()))
+            }
+            Err((_, err)) => {
+                tracing::warn!("snapshot export {} failed: {}", export.name, err);
+                Err(err.as_str())
+            }
+        };
+        if let Err(e) = self.export_storage.record_run(export.id, outcome).await {
+            tracing::warn!(
+                "unable to record run of snapshot export {}: {}",
+                export.name,
+                e
+            );
+        }
+    }
+
+    /// Write the artifacts of `export` at `refs`, then its manifest. `tag` is the tag whose
+    /// creation started the run, if one did.
+    async fn execute(
+        &self,
+        export: &mega_snapshot_export::Model,
+        refs: &str,
+        tag: Option<&str>,
+    ) -> Result<SnapshotExportRun, (StatusCode, String)> {
+        let now = chrono::Utc::now().naive_utc();
+        let (commit_id, files) = if export.format == BUNDLE_FORMAT {
+            self.bundle(export, refs).await?
+        } else {
+            self.archives(export, refs).await?
+        };
+        let mut artifacts = Vec::new();
+        for (path, name, data) in files {
+            let key = format!("{}/{}/{}", export.name, commit_id, name);
+            self.target
+                .put_file(&key, &data)
+                .await
+                .map_err(internal_error)?;
+            artifacts.push(ExportArtifact {
+                path,
+                key,
+                size: data.len() as u64,
+                sha256: hex::encode(Sha256::digest(&data)),
+            });
+        }
+        let manifest = ExportManifest {
+            export: export.name.clone(),
+            repo_path: export.repo_path.clone(),
+            ref_name: refs.to_owned(),
+            commit_id: commit_id.clone(),
+            format: export.format.clone(),
+            created_at: format_time(now),
+            artifacts,
+        };
+        let data = serde_json::to_vec_pretty(&manifest).map_err(internal_error)?;
+        let manifest_key = format!("{}/{}/manifest.json", export.name, commit_id);
+        let mut keys = vec![manifest_key.clone(), format!("{}/latest.json", export.name)];
+        if let Some(tag) = tag {
+            keys.push(format!("{}/tags/{}.json", export.name, tag));
+        }
+        for key in &keys {
+            self.target
+                .put_file(key, &data)
+                .await
+                .map_err(internal_error)?;
+        }
+        Ok(SnapshotExportRun {
+            manifest_key,
+            manifest,
+        })
+    }
+
+    /// The archives of the directories of `export` at `refs`, with the commit they were taken at.
+    async fn archives(
+        &self,
+        export: &mega_snapshot_export::Model,
+        refs: &str,
+    ) -> Result<(String, Vec<(String, String, Vec<u8>)>), (StatusCode, String)> {
+        let format = ArchiveFormat::parse(&export.format)
+            .ok_or_else(|| internal_error(format!("unknown export format {}", export.format)))?;
+        // every directory is taken at the commit the first one resolved to
+        let mut at = refs.to_owned();
+        let mut commit_id = None;
+        let mut files = Vec::new();
+        for path in split_paths(&export.paths) {
+            let snapshot = self
+                .snapshots
+                .archive(&export.repo_path, Some(&at), &path, format)
+                .await?;
+            at.clone_from(&snapshot.commit_id);
+            commit_id = Some(snapshot.commit_id);
+            let mut chunks = snapshot.chunks;
+            let mut data = Vec::new();
+            while let Some(chunk) = chunks.try_next().await.map_err(internal_error)? {
+                data.extend_from_slice(&chunk);
+            }
+            let name = artifact_name(&export.repo_path, &path, format.extension());
+            files.push((path, name, data));
+        }
+        let commit_id = commit_id.ok_or_else(|| internal_error("the export has no paths"))?;
+        Ok((commit_id, files))
+    }
+
+    /// A bundle of the ref `refs` of the repository of `export`, with the commit it points to.
+    async fn bundle(
+        &self,
+        export: &mega_snapshot_export::Model,
+        refs: &str,
+    ) -> Result<(String, Vec<(String, String, Vec<u8>)>), (StatusCode, String)> {
+        let (header, pack) = self
+            .bundles
+            .create(&export.repo_path, &[refs.to_owned()], 2)
+            .await?;
+        let commit_id = header
+            .refs
+            .first()
+            .map(|r| r.id.clone())
+            .ok_or_else(|| internal_error(format!("no ref {}", refs)))?;
+        let mut data = header.encode();
+        let pack = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u8>> {
+            let mut pack_data = Vec::new();
+            pack.into_reader()?.read_to_end(&mut pack_data)?;
+            Ok(pack_data)
+        })
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+        data.extend_from_slice(&pack);
+        let name = artifact_name(&export.repo_path, "", BUNDLE_FORMAT);
+        Ok((commit_id, vec![(String::new(), name, data)]))
+    }
+}
+
+fn format_time(time: NaiveDateTime) -> String {
+    time.and_utc().to_rfc3339()
+}
+
+/// Runs the exports of a repository whose tag pattern matches a tag when it is created.
+pub struct SnapshotExportHook {
+    pub service: SnapshotExportService,
+}
+
+#[async_trait]
+impl RefHook for SnapshotExportHook {
+    fn name(&self) -> &'static str {
+        "snapshot-exports"
+    }
+
+    async fn on_ref_update(&self, change: &RefChange) -> Result<(), String> {
+        let Some(tag) = change.ref_name.strip_prefix("refs/tags/") else {
+            return Ok(());
+        };
+        if change.before.is_some() || change.after.is_none() {
+            return Ok(());
+        }
+        let exports = self
+            .service
+            .export_storage
+            .tag_exports(&change.repo_path)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut failed = Vec::new();
+        for export in exports {
+            if !export
+                .tag_pattern
+                .as_deref()
+                .is_some_and(|pattern| matches_tag(pattern, tag))
+            {
+                continue;
+            }
+            let result = self
+                .service
+                .execute(&export, &change.ref_name, Some(tag))
+                .await;
+            self.service.record(&export, &result).await;
+            if result.is_err() {
+                failed.push(export.name);
+            }
+        }
+        // the retry runs the exports that succeeded again, which rewrites the same keys
+        if !failed.is_empty() {
+            return Err(format!("snapshot exports {} failed", failed.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{artifact_name, matches_tag, normalize_dir};
+
+    #[test]
+    fn test_normalize_dir() {
+        assert_eq!(
+            normalize_dir("/firmware/boards/").unwrap(),
+            "firmware/boards"
+        );
+        assert_eq!(normalize_dir("").unwrap(), "");
+        assert_eq!(normalize_dir("/").unwrap(), "");
+        assert!(normalize_dir("firmware/../secrets").is_none());
+        assert!(normalize_dir("firmware//boards").is_none());
+    }
+
+    #[test]
+    fn test_artifact_name() {
+        assert_eq!(artifact_name("/projects/mega", "", "tar.gz"), "mega.tar.gz");
+        assert_eq!(
+            artifact_name("/projects/mega", "firmware/boards", "zip"),
+            "firmware-boards.zip"
+        );
+        assert_eq!(artifact_name("/", "", "bundle"), "mega.bundle");
+    }
+
+    #[test]
+    fn test_matches_tag() {
+        assert!(matches_tag("v*", "v1.0"));
+        assert!(matches_tag("release-1", "release-1"));
+        assert!(!matches_tag("release-1", "release-10"));
+        assert!(!matches_tag("v*", "nightly"));
+    }
+}
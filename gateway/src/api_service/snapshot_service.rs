@@ -0,0 +1,214 @@
+use std::io;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::StatusCode;
+use axum::response::Response;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use storage::driver::database::storage::ObjectStorage;
+use venus::internal::gitattributes::{self, Gitattributes};
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::snapshot::{ArchiveFormat, ArchiveWriter, EntryKind};
+use crate::model::query::SnapshotQuery;
+
+/// Serves the archive of a repository at a commit, generated while it is sent.
+#[derive(Clone)]
+pub struct SnapshotService {
+    pub storage: Arc<dyn ObjectStorage>,
+}
+
+/// The entries of an archive left to write, depth first.
+struct Walk {
+    loader: ObjectLoader,
+    writer: Option<ArchiveWriter>,
+    /// The path of each entry in the archive and in the repository, with its item
+    pending: Vec<(String, String, TreeItem)>,
+    /// The `.gitattributes` files read so far, entries with `export-ignore` are left out
+    attributes: Gitattributes,
+}
+
+impl Walk {
+    /// The bytes of the next entry, of the end of the archive after the last, `None` after that.
+    async fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some((path, repo_path, item)) = self.pending.pop() else {
+            return match self.writer.take() {
+                Some(writer) => writer.finish().map(Some),
+                None => Ok(None),
+            };
+        };
+        let to_io = |(_, err): (StatusCode, String)| io::Error::other(err);
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is taken after the last entry");
+        let chunk = match item.mode {
+            TreeItemMode::Tree => {
+                let tree = self.loader.tree(&item.id).await.map_err(to_io)?;
+                let file = tree.tree_items.iter().find(|child| {
+                    child.name == gitattributes::FILE_NAME && child.mode == TreeItemMode::Blob
+                });
+                if let Some(file) = file {
+                    let data = self.loader.blob(&file.id).await.map_err(to_io)?;
+                    self.attributes
+                        .add(&repo_path, &String::from_utf8_lossy(&data));
+                }
+                for child in tree.tree_items.into_iter().rev() {
+                    let child_path = join(&repo_path, &child.name);
+                    if self.attributes.is_set(&child_path, "export-ignore") {
+                        continue;
+                    }
+                    self.pending
+                        .push((format!("{}/{}", path, child.name), child_path, child));
+                }
+                writer.add(&path, EntryKind::of(item.mode), &[])?
+            }
+            TreeItemMode::Commit => writer.add(&path, EntryKind::of(item.mode), &[])?,
+            // the data of a symlink is its target
+            mode => {
+                let data = self.loader.blob(&item.id).await.map_err(to_io)?;
+                writer.add(&path, EntryKind::of(mode), &data)?
+            }
+        };
+        Ok(Some(chunk))
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// An archive generated while its chunks are read.
+pub struct Snapshot {
+    pub commit_id: String,
+    pub file_name: String,
+    pub chunks: BoxStream<'static, io::Result<Bytes>>,
+}
+
+impl SnapshotService {
+    /// The archive of `path` in the repository at the commit `refs` names. Entries are under a
+    /// directory named after the repository, or the path, and the commit, and those the
+    /// `.gitattributes` of the commit mark `export-ignore` are left out, as `git archive` does.
+    pub async fn download(&self, query: SnapshotQuery) -> Result<Response, (StatusCode, String)> {
+        let format_name = query.format.as_deref().unwrap_or("tar.gz");
+        let format = ArchiveFormat::parse(format_name).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "unknown archive format {}, use tar.gz, tar or zip",
+                    format_name
+                ),
+            )
+        })?;
+        let snapshot = self
+            .archive(
+                &query.repo_path,
+                query.refs.as_deref(),
+                query.path.as_deref().unwrap_or(""),
+                format,
+            )
+            .await?;
+        let res = Response::builder()
+            .header("Content-Type", format.content_type())
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", snapshot.file_name),
+            )
+            .body(Body::from_stream(snapshot.chunks))
+            .unwrap();
+        Ok(res)
+    }
+
+    /// The archive of `path` in the repository at the commit `refs` names, see [`Self::download`].
+    pub async fn archive(
+        &self,
+        repo_path: &str,
+        refs: Option<&str>,
+        path: &str,
+        format: ArchiveFormat,
+    ) -> Result<Snapshot, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let commit_id = loader.resolve_ref(repo_path, refs).await?;
+        let commit = loader.commit(&commit_id).await?;
+        let commit_id = commit_id.to_plain_str();
+        let path = path.trim_matches('/');
+        let root = if path.is_empty() {
+            TreeItem {
+                mode: TreeItemMode::Tree,
+                id: commit.tree_id,
+                name: String::new(),
+            }
+        } else {
+            loader
+                .find_path(&commit.tree_id, path)
+                .await?
+                .ok_or_else(|| {
+                    (
+                        StatusCode::NOT_FOUND,
+                        format!("{} does not exist at {}", path, commit_id),
+                    )
+                })?
+        };
+
+        let base = if path.is_empty() { repo_path } else { path };
+        let base = base
+            .rsplit('/')
+            .find(|name| !name.is_empty())
+            .unwrap_or("mega");
+        let prefix = format!("{}-{}", base, &commit_id[..7]);
+        let file_name = format!("{}.{}", prefix, format.extension());
+
+        let mut writer = ArchiveWriter::new(format, &commit_id, commit.committer.timestamp as u64);
+        let start = writer
+            .start()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let first = match root.mode {
+            TreeItemMode::Tree => prefix,
+            _ => format!("{}/{}", prefix, root.name),
+        };
+        let attributes = loader.attributes(&commit.tree_id, path).await?;
+        let pending = if !path.is_empty() && attributes.is_set(path, "export-ignore") {
+            Vec::new()
+        } else {
+            vec![(first, path.to_owned(), root)]
+        };
+        let walk = Walk {
+            loader,
+            writer: Some(writer),
+            pending,
+            attributes,
+        };
+        let id = commit_id.clone();
+        let entries = futures::stream::unfold(Some(walk), move |walk| {
+            let commit_id = id.clone();
+            async move {
+                let mut walk = walk?;
+                match walk.next_chunk().await {
+                    Ok(Some(chunk)) => Some((Ok(Bytes::from(chunk)), Some(walk))),
+                    Ok(None) => None,
+                    // the reader sees the archive cut short
+                    Err(e) => {
+                        tracing::warn!("archive of {} stopped: {}", commit_id, e);
+                        Some((Err(e), None))
+                    }
+                }
+            }
+        });
+        let chunks = futures::stream::once(async move { Ok(Bytes::from(start)) })
+            .chain(entries)
+            .boxed();
+        Ok(Snapshot {
+            commit_id,
+            file_name,
+            chunks,
+        })
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::Json;
+use sea_orm::Set;
+
+use common::utils::generate_id;
+use db_entity::{mega_snippet, mega_snippet_file};
+use entity::objects;
+use jupiter::storage::snippet_storage::{
+    SnippetStorage, VISIBILITY_INTERNAL, VISIBILITY_PRIVATE, VISIBILITY_PUBLIC,
+};
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::types::ObjectType;
+
+use crate::api_service::object_loader::ObjectLoader;
+use crate::auth::Identity;
+use crate::model::snippet::{
+    NewSnippet, NewSnippetFile, Snippet, SnippetFile, SnippetQuery, SnippetUpdate,
+};
+
+/// Most files a snippet has.
+pub const MAX_FILES: usize = 10;
+
+/// Most bytes the files of a snippet hold together.
+pub const MAX_CONTENT_SIZE: usize = 1024 * 1024;
+
+/// The language of files [`guess_language`] doesn't recognize.
+pub const PLAIN_TEXT: &str = "Text";
+
+/// File extensions and the languages they are highlighted as.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("c", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("cs", "C#"),
+    ("css", "CSS"),
+    ("diff", "Diff"),
+    ("go", "Go"),
+    ("h", "C"),
+    ("hpp", "C++"),
+    ("html", "HTML"),
+    ("java", "Java"),
+    ("js", "JavaScript"),
+    ("json", "JSON"),
+    ("kt", "Kotlin"),
+    ("lua", "Lua"),
+    ("md", "Markdown"),
+    ("patch", "Diff"),
+    ("php", "PHP"),
+    ("py", "Python"),
+    ("rb", "Ruby"),
+    ("rs", "Rust"),
+    ("scala", "Scala"),
+    ("sh", "Shell"),
+    ("sql", "SQL"),
+    ("swift", "Swift"),
+    ("toml", "TOML"),
+    ("ts", "TypeScript"),
+    ("tsx", "TSX"),
+    ("xml", "XML"),
+    ("yaml", "YAML"),
+    ("yml", "YAML"),
+];
+
+/// Snippets of code users share, each with one or more files kept as blobs in the object store.
+///
+/// Public snippets are seen by everyone, internal ones by every signed in user and private ones
+/// by their owner only. Admins see them all, and only owners and admins change them.
+#[derive(Clone)]
+pub struct SnippetService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub snippet_storage: SnippetStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+fn not_found(id: i64) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("snippet {} not found", id))
+}
+
+/// The language a file named `name` is highlighted as, from its extension.
+pub fn guess_language(name: &str) -> &'static str {
+    match name {
+        "Dockerfile" => return "Dockerfile",
+        "Makefile" => return "Makefile",
+        _ => {}
+    }
+    let Some((_, extension)) = name.rsplit_once('.') else {
+        return PLAIN_TEXT;
+    };
+    let extension = extension.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(e, _)| *e == extension)
+        .map_or(PLAIN_TEXT, |(_, language)| *language)
+}
+
+pub fn check_visibility(visibility: &str) -> Result<(), (StatusCode, String)> {
+    match visibility {
+        VISIBILITY_PUBLIC | VISIBILITY_INTERNAL | VISIBILITY_PRIVATE => Ok(()),
+        other => Err(bad_request(format!(
+            "unknown visibility {}, expected public, internal or private",
+            other
+        ))),
+    }
+}
+
+/// Check that a snippet gets between one and [`MAX_FILES`] files, with unique names which are
+/// no paths, and at most [`MAX_CONTENT_SIZE`] bytes of content.
+pub fn check_files(files: &[NewSnippetFile]) -> Result<(), (StatusCode, String)> {
+    if files.is_empty() || files.len() > MAX_FILES {
+        return Err(bad_request(format!(
+            "a snippet has 1 to {} files",
+            MAX_FILES
+        )));
+    }
+    let mut names = HashSet::new();
+    for file in files {
+        let name = file.name.as_str();
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(bad_request(format!("invalid file name: {}", name)));
+        }
+        if !names.insert(name) {
+            return Err(bad_request(format!("duplicate file name: {}", name)));
+        }
+    }
+    let size: usize = files.iter().map(|f| f.content.len()).sum();
+    if size > MAX_CONTENT_SIZE {
+        return Err(bad_request(format!(
+            "the files of a snippet hold at most {} bytes",
+            MAX_CONTENT_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `viewer` sees a snippet, `None` standing for anonymous callers.
+pub fn can_view(snippet: &mega_snippet::Model, viewer: Option<&Identity>) -> bool {
+    match viewer {
+        None => snippet.visibility == VISIBILITY_PUBLIC,
+        Some(identity) => {
+            snippet.visibility != VISIBILITY_PRIVATE
+                || identity.is_admin
+                || identity.username == snippet.owner
+        }
+    }
+}
+
+fn can_edit(snippet: &mega_snippet::Model, viewer: Option<&Identity>) -> bool {
+    viewer.is_some_and(|identity| identity.is_admin || identity.username == snippet.owner)
+}
+
+impl SnippetService {
+    /// The snippets `viewer` sees, newest first, those of `query.owner` when given.
+    pub async fn list(
+        &self,
+        viewer: Option<&Identity>,
+        query: SnippetQuery,
+    ) -> Result<Json<Vec<Snippet>>, (StatusCode, String)> {
+        let visibilities: &[&str] = match viewer {
+            None => &[VISIBILITY_PUBLIC],
+            Some(identity) if identity.is_admin => {
+                &[VISIBILITY_PUBLIC, VISIBILITY_INTERNAL, VISIBILITY_PRIVATE]
+            }
+            Some(_) => &[VISIBILITY_PUBLIC, VISIBILITY_INTERNAL],
+        };
+        let snippets = self
+            .snippet_storage
+            .list_snippets(
+                query.owner.as_deref(),
+                visibilities,
+                viewer.map(|identity| identity.username.as_str()),
+            )
+            .await
+            .map_err(internal_error)?;
+        let mut res = Vec::with_capacity(snippets.len());
+        for snippet in snippets {
+            let files = self
+                .snippet_storage
+                .list_files(snippet.id)
+                .await
+                .map_err(internal_error)?;
+            let files = files.into_iter().map(SnippetFile::from).collect();
+            res.push(Snippet::new(snippet, files));
+        }
+        Ok(Json(res))
+    }
+
+    /// The snippet `id` if `viewer` sees it.
+    async fn get_snippet(
+        &self,
+        id: i64,
+        viewer: Option<&Identity>,
+    ) -> Result<mega_snippet::Model, (StatusCode, String)> {
+        self.snippet_storage
+            .get_snippet(id)
+            .await
+            .map_err(internal_error)?
+            .filter(|snippet| can_view(snippet, viewer))
+            .ok_or_else(|| not_found(id))
+    }
+
+    /// The snippet `id` with the content of its files.
+    pub async fn detail(
+        &self,
+        id: i64,
+        viewer: Option<&Identity>,
+    ) -> Result<Json<Snippet>, (StatusCode, String)> {
+        let snippet = self.get_snippet(id, viewer).await?;
+        Ok(Json(self.with_content(snippet).await?))
+    }
+
+    async fn with_content(
+        &self,
+        snippet: mega_snippet::Model,
+    ) -> Result<Snippet, (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let models = self
+            .snippet_storage
+            .list_files(snippet.id)
+            .await
+            .map_err(internal_error)?;
+        let mut files = Vec::with_capacity(models.len());
+        for model in models {
+            let blob_id = SHA1::from_str(&model.blob_id).map_err(internal_error)?;
+            let data = loader.blob(&blob_id).await?;
+            let mut file = SnippetFile::from(model);
+            file.content = Some(String::from_utf8_lossy(&data).into_owned());
+            files.push(file);
+        }
+        Ok(Snippet::new(snippet, files))
+    }
+
+    pub async fn create(
+        &self,
+        viewer: Option<&Identity>,
+        new_snippet: NewSnippet,
+    ) -> Result<Json<Snippet>, (StatusCode, String)> {
+        let Some(identity) = viewer else {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "authentication required".to_owned(),
+            ));
+        };
+        let title = new_snippet.title.trim();
+        if title.is_empty() {
+            return Err(bad_request("the title of a snippet is required".to_owned()));
+        }
+        let visibility = new_snippet
+            .visibility
+            .unwrap_or_else(|| VISIBILITY_PRIVATE.to_owned());
+        check_visibility(&visibility)?;
+        check_files(&new_snippet.files)?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let snippet = mega_snippet::Model {
+            id: generate_id(),
+            title: title.to_owned(),
+            description: new_snippet.description,
+            visibility,
+            owner: identity.username.clone(),
+            created_at: now,
+            updated_at: now,
+        };
+        let files = self.save_files(snippet.id, new_snippet.files).await?;
+        self.snippet_storage
+            .save_snippet(snippet.clone(), files)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(self.with_content(snippet).await?))
+    }
+
+    pub async fn update(
+        &self,
+        id: i64,
+        viewer: Option<&Identity>,
+        update: SnippetUpdate,
+    ) -> Result<Json<Snippet>, (StatusCode, String)> {
+        let mut snippet = self.get_snippet(id, viewer).await?;
+        if !can_edit(&snippet, viewer) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("only the owner of snippet {} changes it", id),
+            ));
+        }
+        if let Some(title) = update.title {
+            let title = title.trim();
+            if title.is_empty() {
+                return Err(bad_request("the title of a snippet is required".to_owned()));
+            }
+            snippet.title = title.to_owned();
+        }
+        if let Some(description) = update.description {
+            snippet.description = Some(description).filter(|d| !d.is_empty());
+        }
+        if let Some(visibility) = update.visibility {
+            check_visibility(&visibility)?;
+            snippet.visibility = visibility;
+        }
+        let files = match update.files {
+            Some(files) => {
+                check_files(&files)?;
+                Some(self.save_files(id, files).await?)
+            }
+            None => None,
+        };
+        snippet.updated_at = chrono::Utc::now().naive_utc();
+        let snippet = self
+            .snippet_storage
+            .update_snippet(snippet, files)
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(self.with_content(snippet).await?))
+    }
+
+    pub async fn delete(
+        &self,
+        id: i64,
+        viewer: Option<&Identity>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let snippet = self.get_snippet(id, viewer).await?;
+        if !can_edit(&snippet, viewer) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("only the owner of snippet {} deletes it", id),
+            ));
+        }
+        if !self
+            .snippet_storage
+            .delete_snippet(id)
+            .await
+            .map_err(internal_error)?
+        {
+            return Err(not_found(id));
+        }
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// The content of the file `name` of a snippet as plain text, whatever its language.
+    pub async fn raw(
+        &self,
+        id: i64,
+        name: &str,
+        viewer: Option<&Identity>,
+    ) -> Result<Response, (StatusCode, String)> {
+        let snippet = self.get_snippet(id, viewer).await?;
+        let file = self
+            .snippet_storage
+            .list_files(snippet.id)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .find(|file| file.name == name)
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("snippet {} has no file {}", id, name),
+                )
+            })?;
+        let blob_id = SHA1::from_str(&file.blob_id).map_err(internal_error)?;
+        let data = ObjectLoader::new(self.storage.clone())
+            .blob(&blob_id)
+            .await?;
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .header(header::CONTENT_LENGTH, data.len())
+            // never rendered as the HTML or script it may hold
+            .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+            .header(header::ETAG, format!("\"{}\"", file.blob_id))
+            .body(Body::from(data))
+            .unwrap())
+    }
+
+    /// Store the content of `files` as blobs and describe them as the files of `snippet_id`.
+    async fn save_files(
+        &self,
+        snippet_id: i64,
+        files: Vec<NewSnippetFile>,
+    ) -> Result<Vec<mega_snippet_file::Model>, (StatusCode, String)> {
+        let mut blobs = Vec::new();
+        let mut seen = HashSet::new();
+        let mut models = Vec::with_capacity(files.len());
+        for (position, file) in files.into_iter().enumerate() {
+            let data = file.content.into_bytes();
+            let blob_id = SHA1::from_type_and_data(ObjectType::Blob, &data);
+            let language = file
+                .language
+                .filter(|l| !l.trim().is_empty())
+                .unwrap_or_else(|| guess_language(&file.name).to_owned());
+            models.push(mega_snippet_file::Model {
+                id: generate_id(),
+                snippet_id,
+                language,
+                name: file.name,
+                blob_id: blob_id.to_plain_str(),
+                size: data.len() as i64,
+                position: position as i32,
+            });
+            if seen.insert(blob_id) {
+                blobs.push(objects::ActiveModel {
+                    id: Set(generate_id()),
+                    git_id: Set(blob_id.to_plain_str()),
+                    object_type: Set(ObjectType::Blob.to_string()),
+                    data: Set(data),
+                    link: Set(None),
+                });
+            }
+        }
+        self.storage
+            .save_obj_data(None, blobs)
+            .await
+            .map_err(internal_error)?;
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, content: &str) -> NewSnippetFile {
+        NewSnippetFile {
+            name: name.to_owned(),
+            content: content.to_owned(),
+            language: None,
+        }
+    }
+
+    fn snippet(visibility: &str) -> mega_snippet::Model {
+        let now = chrono::Utc::now().naive_utc();
+        mega_snippet::Model {
+            id: 1,
+            title: "snippet".to_owned(),
+            description: None,
+            visibility: visibility.to_owned(),
+            owner: "alice".to_owned(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn identity(username: &str, is_admin: bool) -> Identity {
+        Identity {
+            username: username.to_owned(),
+            display_name: None,
+            email: None,
+            is_admin,
+        }
+    }
+
+    #[test]
+    fn test_guess_language() {
+        assert_eq!(guess_language("main.rs"), "Rust");
+        assert_eq!(guess_language("setup.PY"), "Python");
+        assert_eq!(guess_language("archive.tar.gz"), PLAIN_TEXT);
+        assert_eq!(guess_language("Dockerfile"), "Dockerfile");
+        assert_eq!(guess_language("README"), PLAIN_TEXT);
+    }
+
+    #[test]
+    fn test_check_files() {
+        assert!(check_files(&[file("a.rs", "fn main() {}"), file("b.rs", "")]).is_ok());
+        assert!(check_files(&[]).is_err());
+        assert!(check_files(&[file("a.rs", ""), file("a.rs", "")]).is_err());
+        assert!(check_files(&[file("src/a.rs", "")]).is_err());
+        assert!(check_files(&[file("..", "")]).is_err());
+        let many: Vec<_> = (0..=MAX_FILES).map(|i| file(&i.to_string(), "")).collect();
+        assert!(check_files(&many).is_err());
+        let large = "x".repeat(MAX_CONTENT_SIZE + 1);
+        assert!(check_files(&[file("a.txt", &large)]).is_err());
+    }
+
+    #[test]
+    fn test_can_view() {
+        let (alice, bob) = (identity("alice", false), identity("bob", false));
+        let admin = identity("root", true);
+        let public = snippet(VISIBILITY_PUBLIC);
+        assert!(can_view(&public, None));
+        let internal = snippet(VISIBILITY_INTERNAL);
+        assert!(!can_view(&internal, None));
+        assert!(can_view(&internal, Some(&bob)));
+        let private = snippet(VISIBILITY_PRIVATE);
+        assert!(!can_view(&private, None));
+        assert!(!can_view(&private, Some(&bob)));
+        assert!(can_view(&private, Some(&alice)));
+        assert!(can_view(&private, Some(&admin)));
+        assert!(!can_edit(&public, Some(&bob)));
+        assert!(can_edit(&public, Some(&admin)));
+    }
+}
@@ -0,0 +1,126 @@
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use common::utils::generate_id;
+use db_entity::mega_ssh_key;
+use jupiter::storage::ssh_key_storage::SshKeyStorage;
+
+use crate::auth::ssh_key;
+use crate::model::ssh_key::{NewSshKey, SshKey};
+
+#[derive(Clone)]
+pub struct SshKeyService {
+    pub storage: SshKeyStorage,
+}
+
+/// Parse an expiry given as an RFC 3339 timestamp, or as a date meaning the end of that day (UTC).
+pub fn parse_expiry(value: &str) -> Option<NaiveDateTime> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.naive_utc());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+}
+
+impl SshKeyService {
+    pub async fn list_keys(
+        &self,
+        username: &str,
+    ) -> Result<Json<Vec<SshKey>>, (StatusCode, String)> {
+        let keys = self
+            .storage
+            .list_keys(username)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(Json(keys.into_iter().map(SshKey::from).collect()))
+    }
+
+    pub async fn add_key(
+        &self,
+        username: String,
+        new_key: NewSshKey,
+    ) -> Result<Json<SshKey>, (StatusCode, String)> {
+        let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+        let line =
+            ssh_key::split_key_line(&new_key.public_key).map_err(|e| bad_request(e.to_string()))?;
+        let public_key = ssh_key::parse_key_line(&line).map_err(|e| bad_request(e.to_string()))?;
+        let now = chrono::Utc::now().naive_utc();
+        let expires_at = match new_key.expires_at.as_deref() {
+            Some(value) => match parse_expiry(value) {
+                Some(time) if time > now => Some(time),
+                Some(_) => return Err(bad_request("expiry must be in the future".to_owned())),
+                None => return Err(bad_request(format!("invalid expiry: {}", value))),
+            },
+            None => None,
+        };
+
+        let fingerprint = ssh_key::fingerprint(&public_key);
+        match self.storage.find_by_fingerprint(&fingerprint).await {
+            Ok(Some(_)) => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("key {} is already registered", fingerprint),
+                ))
+            }
+            Ok(None) => {}
+            Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+
+        let title = new_key
+            .title
+            .filter(|t| !t.trim().is_empty())
+            .or_else(|| line.comment.map(str::to_owned))
+            .unwrap_or_else(|| line.key_type.to_owned());
+        let key = mega_ssh_key::Model {
+            id: generate_id(),
+            username,
+            title,
+            key_type: line.key_type.to_owned(),
+            public_key: format!("{} {}", line.key_type, line.base64),
+            fingerprint,
+            expires_at,
+            last_used_at: None,
+            created_at: now,
+        };
+        self.storage
+            .save_key(key.clone())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(Json(key.into()))
+    }
+
+    pub async fn delete_key(
+        &self,
+        username: &str,
+        id: i64,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        match self.storage.delete_key(username, id).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((
+                StatusCode::NOT_FOUND,
+                format!("{} has no ssh key {}", username, id),
+            )),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::parse_expiry;
+
+    #[test]
+    fn test_parse_expiry() {
+        let date = NaiveDate::from_ymd_opt(2030, 1, 31).unwrap();
+        assert_eq!(parse_expiry("2030-01-31"), date.and_hms_opt(23, 59, 59));
+        assert_eq!(
+            parse_expiry("2030-01-31T10:00:00+02:00"),
+            date.and_hms_opt(8, 0, 0)
+        );
+        assert_eq!(parse_expiry("next week"), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Repository statistics from the commit and language stats gathered on push: commits by author
+//! and week, lines added and removed by week, and the languages of the tree.
+//!
+//! Weeks start on Monday (UTC), and merge commits are left out of the counts like `git shortlog
+//! --no-merges` does, since the lines they bring are counted in the commits merged.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+
+use db_entity::{mega_commit_stat, mega_language_stat};
+
+use crate::api_service::mailmap::Mailmap;
+use crate::model::stats::{ContributorStats, LanguageStats, WeekStats};
+
+/// The Monday starting the week of `time`.
+pub fn week_start(time: NaiveDateTime) -> NaiveDate {
+    let date = time.date();
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// A date given as `YYYY-MM-DD`, at its start.
+pub fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+fn add_commit(weeks: &mut BTreeMap<NaiveDate, WeekStats>, stat: &mega_commit_stat::Model) {
+    let week = week_start(stat.authored_at);
+    let entry = weeks.entry(week).or_insert_with(|| WeekStats {
+        week: week.to_string(),
+        ..Default::default()
+    });
+    entry.commits += 1;
+    entry.additions += stat.additions as u64;
+    entry.deletions += stat.deletions as u64;
+}
+
+/// The commits of `stats` by author, the identities the mailmap merges counted as one, most
+/// commits first.
+pub fn contributors(stats: &[mega_commit_stat::Model], mailmap: &Mailmap) -> Vec<ContributorStats> {
+    let mut authors: HashMap<String, (String, String, BTreeMap<NaiveDate, WeekStats>)> =
+        HashMap::new();
+    for stat in stats.iter().filter(|s| !s.is_merge) {
+        let (name, email) = mailmap.resolve(&stat.author_name, &stat.author_email);
+        let (_, _, weeks) = authors
+            .entry(email.to_lowercase())
+            .or_insert_with(|| (name, email, BTreeMap::new()));
+        add_commit(weeks, stat);
+    }
+    let mut contributors: Vec<ContributorStats> = authors
+        .into_values()
+        .map(|(name, email, weeks)| {
+            let weeks: Vec<WeekStats> = weeks.into_values().collect();
+            ContributorStats {
+                name,
+                email,
+                commits: weeks.iter().map(|w| w.commits).sum(),
+                additions: weeks.iter().map(|w| w.additions).sum(),
+                deletions: weeks.iter().map(|w| w.deletions).sum(),
+                weeks,
+            }
+        })
+        .collect();
+    contributors.sort_by(|a, b| b.commits.cmp(&a.commits).then_with(|| a.name.cmp(&b.name)));
+    contributors
+}
+
+/// The commits of `stats` by week, oldest first.
+pub fn code_frequency(stats: &[mega_commit_stat::Model]) -> Vec<WeekStats> {
+    let mut weeks = BTreeMap::new();
+    for stat in stats.iter().filter(|s| !s.is_merge) {
+        add_commit(&mut weeks, stat);
+    }
+    weeks.into_values().collect()
+}
+
+/// The languages of `stats` with their share of the bytes, largest first. Only the files
+/// [linguist](crate::api_service::linguist) counts are in the breakdown, the others are left out.
+pub fn languages(stats: &[mega_language_stat::Model]) -> Vec<LanguageStats> {
+    let known: Vec<&mega_language_stat::Model> = stats
+        .iter()
+        .filter(|s| s.detectable && s.files > 0)
+        .collect();
+    let total: i64 = known.iter().map(|s| s.bytes).sum();
+    let mut languages: Vec<LanguageStats> = known
+        .into_iter()
+        .map(|s| LanguageStats {
+            language: s.language.clone(),
+            files: s.files as u64,
+            bytes: s.bytes as u64,
+            percentage: if total > 0 {
+                (s.bytes as f64 * 10000.0 / total as f64).round() / 100.0
+            } else {
+                0.0
+            },
+        })
+        .collect();
+    languages.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.language.cmp(&b.language))
+    });
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use db_entity::mega_mailmap;
+
+    fn commit(email: &str, day: &str, additions: i64, is_merge: bool) -> mega_commit_stat::Model {
+        mega_commit_stat::Model {
+            id: 0,
+            repo_path: "/project".to_owned(),
+            commit_id: String::new(),
+            author_name: email.split('@').next().unwrap().to_owned(),
+            author_email: email.to_owned(),
+            authored_at: parse_date(day).unwrap(),
+            is_merge,
+            additions,
+            deletions: 1,
+            created_at: parse_date(day).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_week_start() {
+        let monday = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        assert_eq!(week_start(parse_date("2024-03-04").unwrap()), monday);
+        assert_eq!(week_start(parse_date("2024-03-10").unwrap()), monday);
+        assert_eq!(
+            week_start(parse_date("2024-03-11").unwrap()),
+            NaiveDate::from_ymd_opt(2024, 3, 11).unwrap()
+        );
+        assert!(parse_date("2024-13-01").is_none());
+    }
+
+    #[test]
+    fn test_contributors() {
+        let stats = [
+            commit("alice@old.example", "2024-03-04", 10, false),
+            commit("bob@example.com", "2024-03-05", 3, false),
+            commit("alice@example.com", "2024-03-06", 5, false),
+            commit("alice@example.com", "2024-03-13", 1, false),
+            commit("alice@example.com", "2024-03-14", 0, true),
+        ];
+        let mailmap = Mailmap::from(vec![mega_mailmap::Model {
+            id: 0,
+            email: "alice@old.example".to_owned(),
+            display_name: "Alice".to_owned(),
+            display_email: "alice@example.com".to_owned(),
+            created_at: parse_date("2024-01-01").unwrap(),
+        }]);
+        let contributors = contributors(&stats, &mailmap);
+        assert_eq!(contributors.len(), 2);
+        let alice = &contributors[0];
+        assert_eq!(
+            (alice.name.as_str(), alice.email.as_str()),
+            ("Alice", "alice@example.com")
+        );
+        assert_eq!(
+            (alice.commits, alice.additions, alice.deletions),
+            (3, 16, 3)
+        );
+        assert_eq!(
+            alice.weeks,
+            [
+                WeekStats {
+                    week: "2024-03-04".to_owned(),
+                    commits: 2,
+                    additions: 15,
+                    deletions: 2,
+                },
+                WeekStats {
+                    week: "2024-03-11".to_owned(),
+                    commits: 1,
+                    additions: 1,
+                    deletions: 1,
+                },
+            ]
+        );
+        assert_eq!(contributors[1].email, "bob@example.com");
+        assert_eq!(contributors[1].commits, 1);
+    }
+
+    #[test]
+    fn test_code_frequency() {
+        let stats = [
+            commit("a@example.com", "2024-03-04", 10, false),
+            commit("b@example.com", "2024-03-10", 3, false),
+            commit("a@example.com", "2024-03-18", 0, true),
+            commit("a@example.com", "2024-03-19", 2, false),
+        ];
+        let weeks = code_frequency(&stats);
+        let summary: Vec<(&str, u64, u64)> = weeks
+            .iter()
+            .map(|w| (w.week.as_str(), w.commits, w.additions))
+            .collect();
+        assert_eq!(summary, [("2024-03-04", 2, 13), ("2024-03-18", 1, 2)]);
+    }
+
+    #[test]
+    fn test_languages() {
+        let stat = |language: &str, files: i64, bytes: i64| mega_language_stat::Model {
+            id: 0,
+            repo_path: "/project".to_owned(),
+            path: String::new(),
+            language: language.to_owned(),
+            detectable: language != "Markdown",
+            files,
+            bytes,
+            commit_id: String::new(),
+            updated_at: parse_date("2024-01-01").unwrap(),
+        };
+        let stats = [
+            stat("Rust", 3, 600),
+            stat("Markdown", 4, 5000),
+            stat("TOML", 1, 200),
+            stat("Python", 0, 0),
+            stat("Shell", 2, 200),
+        ];
+        let summary: Vec<(String, u64, f64)> = languages(&stats)
+            .into_iter()
+            .map(|l| (l.language, l.files, l.percentage))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Rust".to_owned(), 3, 60.0),
+                ("Shell".to_owned(), 2, 20.0),
+                ("TOML".to_owned(), 1, 20.0),
+            ]
+        );
+    }
+}
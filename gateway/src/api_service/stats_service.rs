@@ -0,0 +1,400 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum::Json;
+use futures::future::BoxFuture;
+
+use common::utils::generate_id;
+use db_entity::{mega_commit_stat, mega_language_stat};
+use jupiter::storage::diffstat_storage::DiffstatStorage;
+use jupiter::storage::mailmap_storage::MailmapStorage;
+use jupiter::storage::stats_storage::StatsStorage;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::gitattributes::{self, Gitattributes};
+use venus::internal::object::commit::Commit;
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::compare;
+use crate::api_service::linguist::{self, Overrides};
+use crate::api_service::mailmap::Mailmap;
+use crate::api_service::object_loader::ObjectLoader;
+use crate::api_service::ref_hook::{RefChange, RefHook};
+use crate::api_service::stats;
+use crate::model::merge::DiffStat;
+use crate::model::stats::{ContributorStats, LanguageBreakdown, StatsQuery, WeekStats};
+
+/// A file changed between two trees: its path, with its blob before and after.
+type ChangedBlob = (String, Option<SHA1>, Option<SHA1>);
+
+/// Gathers the statistics of the default branch of repositories as it is pushed to, and
+/// serves the graphs drawn from them.
+///
+/// Every commit of the branch is counted once, the history of a counted commit is counted
+/// already, so a push only costs the commits it brings. The languages of every directory are
+/// moved from the commit counted last to the new head by the files changed in between, each
+/// classified by [`linguist`].
+#[derive(Clone)]
+pub struct StatsService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub stats_storage: StatsStorage,
+    pub diffstat_storage: DiffstatStorage,
+    pub mailmap_storage: MailmapStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+impl StatsService {
+    /// Count what a push to the default branch of a repository brought, pushes to other refs
+    /// are ignored.
+    pub async fn on_push(&self, change: &RefChange) -> Result<(), (StatusCode, String)> {
+        let Some(after) = &change.after else {
+            return Ok(());
+        };
+        let loader = ObjectLoader::new(self.storage.clone());
+        let (default_ref, _) = loader.default_branch(&change.repo_path).await?;
+        if default_ref != change.ref_name {
+            return Ok(());
+        }
+        let head = SHA1::from_str(after).map_err(internal_error)?;
+        self.count_commits(&change.repo_path, head).await?;
+        self.count_languages(&change.repo_path, head).await
+    }
+
+    /// Count the commits in the history of `head` which aren't counted yet, oldest first so a
+    /// failure leaves no gap behind counted commits for the next attempt to miss.
+    async fn count_commits(&self, repo_path: &str, head: SHA1) -> Result<(), (StatusCode, String)> {
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([head]);
+        let mut uncounted: Vec<Commit> = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            if !seen.insert(id) {
+                continue;
+            }
+            let counted = self
+                .stats_storage
+                .counted_commits(repo_path, &[id.to_plain_str()])
+                .await
+                .map_err(internal_error)?;
+            if !counted.is_empty() {
+                continue;
+            }
+            let commit = loader.commit(&id).await?;
+            queue.extend(commit.parent_commit_ids.iter().copied());
+            uncounted.push(commit);
+        }
+        for commit in uncounted.into_iter().rev() {
+            let stat = self.commit_stat(&mut loader, repo_path, commit).await?;
+            self.stats_storage
+                .save_commit_stat(stat)
+                .await
+                .map_err(internal_error)?;
+        }
+        Ok(())
+    }
+
+    /// The stat of a commit, with the lines it changes since its first parent; merges change
+    /// none. The diff stat stored for the commit is used when there is one.
+    async fn commit_stat(
+        &self,
+        loader: &mut ObjectLoader,
+        repo_path: &str,
+        commit: Commit,
+    ) -> Result<mega_commit_stat::Model, (StatusCode, String)> {
+        let commit_id = commit.id.to_plain_str();
+        let is_merge = commit.parent_commit_ids.len() > 1;
+        let stat = if is_merge {
+            DiffStat::default()
+        } else if let Some(stored) = self
+            .diffstat_storage
+            .commit_stats(repo_path, std::slice::from_ref(&commit_id))
+            .await
+            .map_err(internal_error)?
+            .remove(&commit_id)
+        {
+            stored.into()
+        } else {
+            let parent_tree = match commit.parent_commit_ids.first() {
+                Some(parent) => Some(loader.commit(parent).await?.tree_id),
+                None => None,
+            };
+            let mut files = Vec::new();
+            compare::diff_trees(
+                loader,
+                String::new(),
+                parent_tree,
+                Some(commit.tree_id),
+                &mut files,
+            )
+            .await?;
+            DiffStat::of(&files)
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let authored_at = chrono::DateTime::from_timestamp(commit.author.timestamp as i64, 0)
+            .map_or(now, |time| time.naive_utc());
+        Ok(mega_commit_stat::Model {
+            id: generate_id(),
+            repo_path: repo_path.to_owned(),
+            commit_id,
+            author_name: commit.author.name,
+            author_email: commit.author.email,
+            authored_at,
+            is_merge,
+            additions: stat.additions as i64,
+            deletions: stat.deletions as i64,
+            created_at: now,
+        })
+    }
+
+    /// Move the languages of a repository to the tree of `head`, from those counted at an
+    /// earlier commit by the files changed since, or from nothing when there are none. A change
+    /// to a `.gitattributes` file can classify any file again, the whole tree is counted then.
+    async fn count_languages(
+        &self,
+        repo_path: &str,
+        head: SHA1,
+    ) -> Result<(), (StatusCode, String)> {
+        let stored = self
+            .stats_storage
+            .language_stats(repo_path, None)
+            .await
+            .map_err(internal_error)?;
+        let head_id = head.to_plain_str();
+        let counted_at = stored.first().map(|s| s.commit_id.clone());
+        if counted_at.as_deref() == Some(head_id.as_str()) {
+            return Ok(());
+        }
+        let mut counts: HashMap<(String, String, bool), (i64, i64)> = stored
+            .into_iter()
+            .map(|s| ((s.path, s.language, s.detectable), (s.files, s.bytes)))
+            .collect();
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let old_tree = match counted_at.map(|id| SHA1::from_str(&id)) {
+            Some(Ok(id)) => match loader.commit(&id).await {
+                Ok(commit) => Some(commit.tree_id),
+                // the commit is gone, count the whole tree again
+                Err(_) => None,
+            },
+            _ => None,
+        };
+        let new_tree = loader.commit(&head).await?.tree_id;
+        let mut blobs = Vec::new();
+        changed_blobs(
+            &mut loader,
+            String::new(),
+            old_tree,
+            Some(new_tree),
+            &mut blobs,
+        )
+        .await?;
+        let attributes_changed = blobs
+            .iter()
+            .any(|(path, _, _)| path.rsplit('/').next() == Some(gitattributes::FILE_NAME));
+        if old_tree.is_none() || attributes_changed {
+            counts.clear();
+            if old_tree.is_some() {
+                blobs.clear();
+                changed_blobs(&mut loader, String::new(), None, Some(new_tree), &mut blobs).await?;
+            }
+        }
+        let attributes = tree_attributes(&mut loader, &new_tree, &blobs).await?;
+        for (path, old, new) in &blobs {
+            let overrides = Overrides::from_attributes(&attributes.attributes(path));
+            for (blob, sign) in [(old, -1), (new, 1)] {
+                let Some(blob) = blob else {
+                    continue;
+                };
+                let data = loader.blob(blob).await?;
+                let Some(class) = linguist::classify(path, &data, &overrides) else {
+                    continue;
+                };
+                for dir in directories(path) {
+                    let key = (dir.to_owned(), class.language.clone(), class.detectable);
+                    let (files, bytes) = counts.entry(key).or_default();
+                    *files += sign;
+                    *bytes += sign * data.len() as i64;
+                }
+            }
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let stats = counts
+            .into_iter()
+            .filter(|(_, (files, _))| *files > 0)
+            .map(
+                |((path, language, detectable), (files, bytes))| mega_language_stat::Model {
+                    id: generate_id(),
+                    repo_path: repo_path.to_owned(),
+                    path,
+                    language,
+                    detectable,
+                    files,
+                    bytes,
+                    commit_id: head_id.clone(),
+                    updated_at: now,
+                },
+            )
+            .collect();
+        self.stats_storage
+            .replace_language_stats(repo_path, stats)
+            .await
+            .map_err(internal_error)
+    }
+
+    async fn commit_stats(
+        &self,
+        query: &StatsQuery,
+    ) -> Result<Vec<mega_commit_stat::Model>, (StatusCode, String)> {
+        let date = |value: Option<&str>| match value {
+            Some(value) => stats::parse_date(value)
+                .map(Some)
+                .ok_or_else(|| bad_request(format!("invalid date {}, expected YYYY-MM-DD", value))),
+            None => Ok(None),
+        };
+        let since = date(query.since.as_deref())?;
+        let until = date(query.until.as_deref())?;
+        self.stats_storage
+            .list_commit_stats(&query.repo_path, since, until)
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Commits, lines added and lines removed by author and week.
+    pub async fn contributors(
+        &self,
+        query: StatsQuery,
+    ) -> Result<Json<Vec<ContributorStats>>, (StatusCode, String)> {
+        let commits = self.commit_stats(&query).await?;
+        let mailmap = Mailmap::from(
+            self.mailmap_storage
+                .list_entries()
+                .await
+                .map_err(internal_error)?,
+        );
+        Ok(Json(stats::contributors(&commits, &mailmap)))
+    }
+
+    /// Commits, lines added and lines removed by week.
+    pub async fn code_frequency(
+        &self,
+        query: StatsQuery,
+    ) -> Result<Json<Vec<WeekStats>>, (StatusCode, String)> {
+        let commits = self.commit_stats(&query).await?;
+        Ok(Json(stats::code_frequency(&commits)))
+    }
+
+    /// The languages of the directory `path` of a repository, the root when it isn't given.
+    pub async fn languages(
+        &self,
+        repo_path: &str,
+        path: Option<&str>,
+    ) -> Result<Json<LanguageBreakdown>, (StatusCode, String)> {
+        let path = path.unwrap_or_default().trim_matches('/');
+        let stored = self
+            .stats_storage
+            .language_stats(repo_path, Some(path))
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(LanguageBreakdown {
+            path: path.to_owned(),
+            commit_id: stored.first().map(|s| s.commit_id.clone()),
+            languages: stats::languages(&stored),
+        }))
+    }
+}
+
+/// Collect the regular files changed from tree `old` to tree `new` in `blobs`, ordered by path.
+/// A missing tree has no files, and a file whose mode only changed isn't collected.
+fn changed_blobs<'a>(
+    loader: &'a mut ObjectLoader,
+    prefix: String,
+    old: Option<SHA1>,
+    new: Option<SHA1>,
+    blobs: &'a mut Vec<ChangedBlob>,
+) -> BoxFuture<'a, Result<(), (StatusCode, String)>> {
+    Box::pin(async move {
+        let old = match old {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let new = match new {
+            Some(id) => loader.tree(&id).await?.tree_items,
+            None => Vec::new(),
+        };
+        let names: BTreeSet<&String> = old.iter().chain(new.iter()).map(|i| &i.name).collect();
+        for name in names {
+            let o = old.iter().find(|i| &i.name == name);
+            let n = new.iter().find(|i| &i.name == name);
+            let path = format!("{}{}", prefix, name);
+            let subtree = |item: Option<&TreeItem>| {
+                item.filter(|i| i.mode == TreeItemMode::Tree).map(|i| i.id)
+            };
+            let (old_tree, new_tree) = (subtree(o), subtree(n));
+            if old_tree != new_tree {
+                changed_blobs(loader, format!("{}/", path), old_tree, new_tree, blobs).await?;
+            }
+            let file = |item: Option<&TreeItem>| {
+                item.filter(|i| matches!(i.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable))
+                    .map(|i| i.id)
+            };
+            let (old_blob, new_blob) = (file(o), file(n));
+            if old_blob != new_blob {
+                blobs.push((path, old_blob, new_blob));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The directories `path` is in, the root `""` first.
+fn directories(path: &str) -> impl Iterator<Item = &str> {
+    std::iter::once("").chain(path.match_indices('/').map(move |(i, _)| &path[..i]))
+}
+
+/// The `.gitattributes` files of the tree `tree_id` which apply to any of the files `blobs`.
+async fn tree_attributes(
+    loader: &mut ObjectLoader,
+    tree_id: &SHA1,
+    blobs: &[ChangedBlob],
+) -> Result<Gitattributes, (StatusCode, String)> {
+    let dirs: BTreeSet<&str> = blobs
+        .iter()
+        .flat_map(|(path, _, _)| directories(path))
+        .collect();
+    let mut attributes = Gitattributes::default();
+    for dir in dirs {
+        let file = format!("{}/{}", dir, gitattributes::FILE_NAME);
+        if let Some(item) = loader.find_path(tree_id, &file).await? {
+            if item.mode == TreeItemMode::Blob {
+                let data = loader.blob(&item.id).await?;
+                attributes.add(dir, &String::from_utf8_lossy(&data));
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+/// Keeps the statistics of the default branches current as they are pushed to.
+pub struct StatsHook {
+    pub service: StatsService,
+}
+
+#[async_trait]
+impl RefHook for StatsHook {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    async fn on_ref_update(&self, change: &RefChange) -> Result<(), String> {
+        self.service.on_push(change).await.map_err(|(_, e)| e)
+    }
+}
@@ -0,0 +1,638 @@
+//! Webhooks: push, tag and merge request events POSTed to the URLs of subscriptions.
+//!
+//! Each subscription picks the dialect of its payloads. `mega` sends the events as the event
+//! stream does, `github` and `gitlab` map them to the payloads and headers of GitHub and GitLab
+//! webhooks, so CI systems and bots that parse those can be pointed at mega as they are. The
+//! mappings fill in the fields mega knows about: pushes don't list their commits, only
+//! `head_commit` or `checkout_sha`, and links lead to the API.
+//!
+//! Deliveries follow the event log through the ref hook runner, so a failed delivery is retried
+//! with [`backoff`](crate::api_service::ref_hook::backoff). An event is retried for every
+//! subscription it was sent to, receivers tell a delivery they already got by its id.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use common::utils::ZERO_ID;
+use db_entity::{mega_event, mega_webhook};
+use jupiter::storage::webhook_storage::WebhookStorage;
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::api_service::event_service::{EVENT_MERGE_REQUEST, EVENT_PUSH};
+use crate::api_service::ref_hook::{RefChange, RefHook};
+use crate::model::event::RepoEvent;
+use crate::model::mr::MergeRequest;
+
+/// The events a webhook can subscribe to, a tag being a push to `refs/tags/`.
+pub const WEBHOOK_EVENTS: &[&str] = &["push", "tag", "merge_request"];
+
+/// Where the server is reached when `MEGA_PUBLIC_URL` is not set.
+const DEFAULT_PUBLIC_URL: &str = "http://localhost:8000";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    Mega,
+    GitHub,
+    GitLab,
+}
+
+impl Dialect {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "mega" => Some(Dialect::Mega),
+            "github" => Some(Dialect::GitHub),
+            "gitlab" => Some(Dialect::GitLab),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dialect::Mega => "mega",
+            Dialect::GitHub => "github",
+            Dialect::GitLab => "gitlab",
+        }
+    }
+}
+
+/// The webhook event a log event is delivered as, `None` for those webhooks don't get.
+pub fn webhook_event(event: &mega_event::Model) -> Option<&'static str> {
+    match event.event_type.as_str() {
+        EVENT_PUSH => match RefChange::from_event(event) {
+            Some(change) if change.ref_name.starts_with("refs/tags/") => Some("tag"),
+            Some(_) => Some("push"),
+            None => None,
+        },
+        EVENT_MERGE_REQUEST => Some("merge_request"),
+        _ => None,
+    }
+}
+
+/// Commits the branches of a merge request point to when it is delivered.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BranchHeads {
+    pub source: Option<String>,
+    pub target: Option<String>,
+}
+
+/// A webhook request, before it is signed.
+#[derive(Debug, PartialEq)]
+pub struct Payload {
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Value,
+}
+
+fn short_branch(ref_name: &str) -> &str {
+    ref_name.strip_prefix("refs/heads/").unwrap_or(ref_name)
+}
+
+fn or_zero(id: &Option<String>) -> &str {
+    id.as_deref().unwrap_or(ZERO_ID)
+}
+
+fn web_url(base_url: &str, repo_path: &str) -> String {
+    format!("{}{}", base_url, repo_path)
+}
+
+/// Where clients reach the server, `MEGA_PUBLIC_URL`.
+pub fn public_url() -> String {
+    std::env::var("MEGA_PUBLIC_URL")
+        .ok()
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PUBLIC_URL.to_owned())
+}
+
+pub fn clone_url(base_url: &str, repo_path: &str) -> String {
+    format!("{}{}.git", base_url, repo_path)
+}
+
+fn mr_url(base_url: &str, id: i64) -> String {
+    format!("{}/api/v1/mr/{}", base_url, id)
+}
+
+fn repo_name(repo_path: &str) -> &str {
+    repo_path.rsplit('/').next().unwrap_or(repo_path)
+}
+
+fn github_repository(repo_path: &str, base_url: &str) -> Value {
+    json!({
+        "name": repo_name(repo_path),
+        "full_name": repo_path.trim_start_matches('/'),
+        "html_url": web_url(base_url, repo_path),
+        "clone_url": clone_url(base_url, repo_path),
+    })
+}
+
+fn gitlab_project(repo_path: &str, base_url: &str) -> Value {
+    let full = repo_path.trim_start_matches('/');
+    json!({
+        "name": repo_name(repo_path),
+        "path_with_namespace": full,
+        "namespace": full.rsplit_once('/').map_or("", |(parent, _)| parent),
+        "web_url": web_url(base_url, repo_path),
+        "git_http_url": clone_url(base_url, repo_path),
+    })
+}
+
+fn gitlab_repository(repo_path: &str, base_url: &str) -> Value {
+    json!({
+        "name": repo_name(repo_path),
+        "url": clone_url(base_url, repo_path),
+        "homepage": web_url(base_url, repo_path),
+        "git_http_url": clone_url(base_url, repo_path),
+    })
+}
+
+fn github_push(change: &RefChange, base_url: &str) -> Value {
+    let actor = change.actor.as_deref().unwrap_or("mega");
+    json!({
+        "ref": change.ref_name,
+        "before": or_zero(&change.before),
+        "after": or_zero(&change.after),
+        "created": change.before.is_none(),
+        "deleted": change.after.is_none(),
+        "forced": false,
+        "commits": [],
+        "head_commit": change.after.as_ref().map(|id| json!({ "id": id })),
+        "repository": github_repository(&change.repo_path, base_url),
+        "pusher": { "name": actor },
+        "sender": { "login": actor },
+    })
+}
+
+fn gitlab_push(change: &RefChange, base_url: &str) -> Value {
+    let kind = if change.ref_name.starts_with("refs/tags/") {
+        "tag_push"
+    } else {
+        "push"
+    };
+    let actor = change.actor.as_deref().unwrap_or("mega");
+    json!({
+        "object_kind": kind,
+        "event_name": kind,
+        "ref": change.ref_name,
+        "before": or_zero(&change.before),
+        "after": or_zero(&change.after),
+        "checkout_sha": change.after,
+        "user_name": actor,
+        "user_username": actor,
+        "project": gitlab_project(&change.repo_path, base_url),
+        "repository": gitlab_repository(&change.repo_path, base_url),
+        "commits": [],
+        "total_commits_count": 0,
+    })
+}
+
+fn github_pull_request(
+    mr: &MergeRequest,
+    action: &str,
+    actor: Option<&str>,
+    heads: &BranchHeads,
+    base_url: &str,
+) -> Value {
+    // GitHub has no merged action, a merged pull request is closed with `merged` set
+    let action = match action {
+        "opened" | "closed" | "reopened" => action,
+        "merged" => "closed",
+        "ready" => "ready_for_review",
+        "drafted" => "converted_to_draft",
+        _ => "edited",
+    };
+    let merged = mr.status == "merged";
+    let open = !matches!(mr.status.as_str(), "merged" | "closed");
+    json!({
+        "action": action,
+        "number": mr.id,
+        "pull_request": {
+            "id": mr.id,
+            "number": mr.id,
+            "title": mr.title,
+            "state": if open { "open" } else { "closed" },
+            "draft": mr.status == "draft",
+            "merged": merged,
+            "merge_commit_sha": mr.merge_commit_id,
+            "merged_at": if merged { mr.merge_date.clone() } else { None },
+            "html_url": mr_url(base_url, mr.id),
+            "head": { "ref": short_branch(&mr.source_ref), "sha": heads.source },
+            "base": { "ref": short_branch(&mr.target_ref), "sha": heads.target },
+            "labels": mr.labels.iter().map(|l| json!({ "name": l })).collect::<Vec<_>>(),
+            "assignees": mr.assignees.iter().map(|a| json!({ "login": a })).collect::<Vec<_>>(),
+            "created_at": mr.created_at,
+            "updated_at": mr.updated_at,
+        },
+        "repository": github_repository(&mr.repo_path, base_url),
+        "sender": { "login": actor.unwrap_or("mega") },
+    })
+}
+
+fn gitlab_merge_request(
+    mr: &MergeRequest,
+    action: &str,
+    actor: Option<&str>,
+    heads: &BranchHeads,
+    base_url: &str,
+) -> Value {
+    let action = match action {
+        "opened" => "open",
+        "closed" => "close",
+        "reopened" => "reopen",
+        "merged" => "merge",
+        _ => "update",
+    };
+    let state = match mr.status.as_str() {
+        "merged" | "closed" => mr.status.as_str(),
+        _ => "opened",
+    };
+    let actor = actor.unwrap_or("mega");
+    json!({
+        "object_kind": "merge_request",
+        "event_type": "merge_request",
+        "user": { "username": actor, "name": actor },
+        "project": gitlab_project(&mr.repo_path, base_url),
+        "repository": gitlab_repository(&mr.repo_path, base_url),
+        "object_attributes": {
+            "id": mr.id,
+            "iid": mr.id,
+            "title": mr.title,
+            "state": state,
+            "draft": mr.status == "draft",
+            "action": action,
+            "source_branch": short_branch(&mr.source_ref),
+            "target_branch": short_branch(&mr.target_ref),
+            "last_commit": heads.source.as_ref().map(|id| json!({ "id": id })),
+            "merge_commit_sha": mr.merge_commit_id,
+            "url": mr_url(base_url, mr.id),
+            "created_at": mr.created_at,
+            "updated_at": mr.updated_at,
+        },
+        "labels": mr.labels.iter().map(|l| json!({ "title": l })).collect::<Vec<_>>(),
+        "assignees": mr.assignees.iter().map(|a| json!({ "username": a })).collect::<Vec<_>>(),
+    })
+}
+
+/// The request delivering `event` in `dialect`, `None` for events webhooks don't get.
+pub fn payload(
+    dialect: Dialect,
+    event: &mega_event::Model,
+    heads: &BranchHeads,
+    base_url: &str,
+) -> Option<Payload> {
+    let kind = webhook_event(event)?;
+    let delivery = event.id.to_string();
+    let payload = match dialect {
+        Dialect::Mega => Payload {
+            headers: vec![
+                ("X-Mega-Event", kind.to_owned()),
+                ("X-Mega-Delivery", delivery),
+            ],
+            body: serde_json::to_value(RepoEvent::from(event.clone())).ok()?,
+        },
+        Dialect::GitHub => {
+            let (name, body) = match kind {
+                "merge_request" => {
+                    let mr: MergeRequest = serde_json::from_str(&event.payload).ok()?;
+                    let actor = event.actor.as_deref();
+                    let body = github_pull_request(&mr, &event.action, actor, heads, base_url);
+                    ("pull_request", body)
+                }
+                _ => (
+                    "push",
+                    github_push(&RefChange::from_event(event)?, base_url),
+                ),
+            };
+            Payload {
+                headers: vec![
+                    ("X-GitHub-Event", name.to_owned()),
+                    ("X-GitHub-Delivery", delivery),
+                    ("User-Agent", "GitHub-Hookshot/mega".to_owned()),
+                ],
+                body,
+            }
+        }
+        Dialect::GitLab => {
+            let (name, body) = match kind {
+                "merge_request" => {
+                    let mr: MergeRequest = serde_json::from_str(&event.payload).ok()?;
+                    let actor = event.actor.as_deref();
+                    let body = gitlab_merge_request(&mr, &event.action, actor, heads, base_url);
+                    ("Merge Request Hook", body)
+                }
+                "tag" => (
+                    "Tag Push Hook",
+                    gitlab_push(&RefChange::from_event(event)?, base_url),
+                ),
+                _ => (
+                    "Push Hook",
+                    gitlab_push(&RefChange::from_event(event)?, base_url),
+                ),
+            };
+            Payload {
+                headers: vec![
+                    ("X-Gitlab-Event", name.to_owned()),
+                    ("X-Gitlab-Event-UUID", delivery),
+                ],
+                body,
+            }
+        }
+    };
+    Some(payload)
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` keyed with `secret`, as GitHub signs payloads.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// The header authenticating a delivery with the secret of its webhook: the payload signed the
+/// way GitHub does, or the secret itself as GitLab sends it.
+fn secret_header(dialect: Dialect, secret: &str, body: &[u8]) -> (&'static str, String) {
+    match dialect {
+        Dialect::Mega => ("X-Mega-Signature-256", signature(secret, body)),
+        Dialect::GitHub => ("X-Hub-Signature-256", signature(secret, body)),
+        Dialect::GitLab => ("X-Gitlab-Token", secret.to_owned()),
+    }
+}
+
+/// Whether `webhook` subscribed to the webhook event `kind`, all events when it named none.
+fn subscribes_to(webhook: &mega_webhook::Model, kind: &str) -> bool {
+    let mut events = webhook
+        .events
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .peekable();
+    events.peek().is_none() || events.any(|e| e == kind)
+}
+
+/// Delivers the events of the log to the webhooks subscribed to them.
+pub struct WebhookHook {
+    pub client: reqwest::Client,
+    pub storage: Arc<dyn ObjectStorage>,
+    pub webhook_storage: WebhookStorage,
+    /// Where clients reach the server, for the links of the payloads
+    pub base_url: String,
+}
+
+impl WebhookHook {
+    pub fn new(storage: Arc<dyn ObjectStorage>, webhook_storage: WebhookStorage) -> Option<Self> {
+        let client = match reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("unable to set up webhooks, they are disabled: {}", e);
+                return None;
+            }
+        };
+        let base_url = public_url();
+        Some(WebhookHook {
+            client,
+            storage,
+            webhook_storage,
+            base_url: base_url.trim().trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// The commits the branches of the merge request of `event` point to now.
+    async fn branch_heads(&self, event: &mega_event::Model) -> BranchHeads {
+        let Ok(mr) = serde_json::from_str::<MergeRequest>(&event.payload) else {
+            return BranchHeads::default();
+        };
+        let refs = match self.storage.get_all_refs_by_path(&mr.repo_path).await {
+            Ok(refs) => refs,
+            Err(e) => {
+                tracing::warn!("unable to load the refs of {}: {}", mr.repo_path, e);
+                return BranchHeads::default();
+            }
+        };
+        let head = |name: &str| {
+            refs.iter()
+                .find(|r| r.ref_name == name)
+                .map(|r| r.ref_git_id.clone())
+        };
+        BranchHeads {
+            source: head(&mr.source_ref),
+            target: head(&mr.target_ref),
+        }
+    }
+
+    async fn deliver(
+        &self,
+        webhook: &mega_webhook::Model,
+        payload: &Payload,
+    ) -> Result<(), String> {
+        let dialect = Dialect::parse(&webhook.dialect)
+            .ok_or_else(|| format!("unknown dialect {}", webhook.dialect))?;
+        let body = serde_json::to_vec(&payload.body).map_err(|e| e.to_string())?;
+        let mut request = self
+            .client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json");
+        for (name, value) in &payload.headers {
+            request = request.header(*name, value);
+        }
+        if let Some(secret) = webhook.secret.as_deref().filter(|s| !s.is_empty()) {
+            let (name, value) = secret_header(dialect, secret, &body);
+            request = request.header(name, value);
+        }
+        let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("delivery failed with {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefHook for WebhookHook {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &[EVENT_PUSH, EVENT_MERGE_REQUEST]
+    }
+
+    async fn on_event(&self, event: &mega_event::Model) -> Result<(), String> {
+        let Some(kind) = webhook_event(event) else {
+            return Ok(());
+        };
+        let webhooks = self
+            .webhook_storage
+            .webhooks_for(&event.repo_path)
+            .await
+            .map_err(|e| e.to_string())?;
+        let webhooks: Vec<_> = webhooks
+            .into_iter()
+            .filter(|w| subscribes_to(w, kind))
+            .collect();
+        if webhooks.is_empty() {
+            return Ok(());
+        }
+        let heads = if kind == "merge_request" {
+            self.branch_heads(event).await
+        } else {
+            BranchHeads::default()
+        };
+        let mut errors = Vec::new();
+        for webhook in &webhooks {
+            let Some(dialect) = Dialect::parse(&webhook.dialect) else {
+                continue;
+            };
+            let Some(payload) = payload(dialect, event, &heads, &self.base_url) else {
+                continue;
+            };
+            if let Err(e) = self.deliver(webhook, &payload).await {
+                errors.push(format!("{}: {}", webhook.name, e));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// Webhooks are delivered from [`RefHook::on_event`], which sees the whole event.
+    async fn on_ref_update(&self, _change: &RefChange) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db_entity::mega_event;
+
+    use super::{payload, signature, webhook_event, BranchHeads, Dialect};
+
+    fn event(event_type: &str, action: &str, payload: &str) -> mega_event::Model {
+        mega_event::Model {
+            id: 42,
+            event_type: event_type.to_owned(),
+            action: action.to_owned(),
+            repo_path: "/projects/mega".to_owned(),
+            actor: Some("alice".to_owned()),
+            payload: payload.to_owned(),
+            created_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_push_payloads() {
+        let after = "a".repeat(40);
+        let push = event(
+            "push",
+            "created",
+            &format!(
+                r#"{{"ref":"refs/tags/v1.0","before":null,"after":"{}"}}"#,
+                after
+            ),
+        );
+        assert_eq!(webhook_event(&push), Some("tag"));
+        let base = "https://mega.example";
+
+        let github = payload(Dialect::GitHub, &push, &BranchHeads::default(), base).unwrap();
+        assert!(github
+            .headers
+            .contains(&("X-GitHub-Event", "push".to_owned())));
+        assert!(github
+            .headers
+            .contains(&("X-GitHub-Delivery", "42".to_owned())));
+        assert_eq!(github.body["ref"], "refs/tags/v1.0");
+        assert_eq!(github.body["before"], "0".repeat(40));
+        assert_eq!(github.body["created"], true);
+        assert_eq!(github.body["head_commit"]["id"], after);
+        assert_eq!(github.body["repository"]["full_name"], "projects/mega");
+        assert_eq!(
+            github.body["repository"]["clone_url"],
+            "https://mega.example/projects/mega.git"
+        );
+        assert_eq!(github.body["sender"]["login"], "alice");
+
+        let gitlab = payload(Dialect::GitLab, &push, &BranchHeads::default(), base).unwrap();
+        assert!(gitlab
+            .headers
+            .contains(&("X-Gitlab-Event", "Tag Push Hook".to_owned())));
+        assert_eq!(gitlab.body["object_kind"], "tag_push");
+        assert_eq!(gitlab.body["checkout_sha"], after);
+        assert_eq!(gitlab.body["project"]["namespace"], "projects");
+        assert_eq!(gitlab.body["user_username"], "alice");
+
+        let mega = payload(Dialect::Mega, &push, &BranchHeads::default(), base).unwrap();
+        assert!(mega.headers.contains(&("X-Mega-Event", "tag".to_owned())));
+        assert_eq!(mega.body["type"], "push");
+        assert_eq!(mega.body["payload"]["ref"], "refs/tags/v1.0");
+
+        assert_eq!(webhook_event(&event("issue", "opened", "{}")), None);
+    }
+
+    #[test]
+    fn test_merge_request_payloads() {
+        let mr = r#"{"id":7,"title":"Fix","repo_path":"/projects/mega","source_ref":"refs/heads/fix","target_ref":"refs/heads/main","status":"merged","status_description":"","merge_commit_id":"c","merge_date":"2024-03-01 00:00:00","labels":["bug"],"assignees":["bob"],"milestone_id":null,"created_at":"x","updated_at":"y"}"#;
+        let merged = event("merge_request", "merged", mr);
+        let heads = BranchHeads {
+            source: Some("s".repeat(40)),
+            target: Some("t".repeat(40)),
+        };
+        let base = "https://mega.example";
+
+        let github = payload(Dialect::GitHub, &merged, &heads, base).unwrap();
+        assert!(github
+            .headers
+            .contains(&("X-GitHub-Event", "pull_request".to_owned())));
+        assert_eq!(github.body["action"], "closed");
+        assert_eq!(github.body["number"], 7);
+        let pr = &github.body["pull_request"];
+        assert_eq!(pr["state"], "closed");
+        assert_eq!(pr["merged"], true);
+        assert_eq!(pr["head"]["ref"], "fix");
+        assert_eq!(pr["head"]["sha"], "s".repeat(40));
+        assert_eq!(pr["base"]["ref"], "main");
+        assert_eq!(pr["labels"][0]["name"], "bug");
+
+        let gitlab = payload(Dialect::GitLab, &merged, &heads, base).unwrap();
+        assert!(gitlab
+            .headers
+            .contains(&("X-Gitlab-Event", "Merge Request Hook".to_owned())));
+        let attributes = &gitlab.body["object_attributes"];
+        assert_eq!(attributes["action"], "merge");
+        assert_eq!(attributes["state"], "merged");
+        assert_eq!(attributes["source_branch"], "fix");
+        assert_eq!(attributes["last_commit"]["id"], "s".repeat(40));
+        assert_eq!(attributes["url"], "https://mega.example/api/v1/mr/7");
+
+        let opened = event("merge_request", "updated", &mr.replace("merged", "open"));
+        let github = payload(Dialect::GitHub, &opened, &heads, base).unwrap();
+        assert_eq!(github.body["action"], "edited");
+        assert_eq!(github.body["pull_request"]["state"], "open");
+        let gitlab = payload(Dialect::GitLab, &opened, &heads, base).unwrap();
+        assert_eq!(gitlab.body["object_attributes"]["state"], "opened");
+        assert_eq!(gitlab.body["object_attributes"]["action"], "update");
+
+        let drafted = event("merge_request", "drafted", &mr.replace("merged", "draft"));
+        let github = payload(Dialect::GitHub, &drafted, &heads, base).unwrap();
+        assert_eq!(github.body["action"], "converted_to_draft");
+        assert_eq!(github.body["pull_request"]["state"], "open");
+        assert_eq!(github.body["pull_request"]["draft"], true);
+        let gitlab = payload(Dialect::GitLab, &drafted, &heads, base).unwrap();
+        assert_eq!(gitlab.body["object_attributes"]["state"], "opened");
+        assert_eq!(gitlab.body["object_attributes"]["draft"], true);
+    }
+
+    #[test]
+    fn test_signature() {
+        // from the GitHub documentation on validating webhook deliveries
+        assert_eq!(
+            signature("It's a Secret to Everybody", b"Hello, World!"),
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use axum::http::StatusCode;
+use axum::Json;
+
+use common::utils::generate_id;
+use db_entity::mega_webhook;
+use jupiter::storage::webhook_storage::WebhookStorage;
+
+use crate::api_service::path_move;
+use crate::api_service::webhook::{Dialect, WEBHOOK_EVENTS};
+use crate::model::webhook::{Webhook, WebhookUpdate};
+
+#[derive(Clone)]
+pub struct WebhookService {
+    pub webhook_storage: WebhookStorage,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+impl WebhookService {
+    pub async fn list_webhooks(&self) -> Result<Json<Vec<Webhook>>, (StatusCode, String)> {
+        let webhooks = self
+            .webhook_storage
+            .list_webhooks()
+            .await
+            .map_err(internal_error)?;
+        Ok(Json(webhooks.into_iter().map(Webhook::from).collect()))
+    }
+
+    /// Create the webhook `name`, or replace its subscription.
+    pub async fn save_webhook(
+        &self,
+        name: String,
+        update: WebhookUpdate,
+    ) -> Result<Json<Webhook>, (StatusCode, String)> {
+        if name.is_empty()
+            || name.len() > 64
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(bad_request(format!("invalid webhook name: {}", name)));
+        }
+        match reqwest::Url::parse(&update.url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            _ => return Err(bad_request(format!("invalid webhook url: {}", update.url))),
+        }
+        let dialect = Dialect::parse(&update.dialect).ok_or_else(|| {
+            bad_request(format!(
+                "unknown dialect {}, expected mega, github or gitlab",
+                update.dialect
+            ))
+        })?;
+        if let Some(event) = update
+            .events
+            .iter()
+            .find(|e| !WEBHOOK_EVENTS.contains(&e.as_str()))
+        {
+            return Err(bad_request(format!(
+                "unknown event {}, expected one of {}",
+                event,
+                WEBHOOK_EVENTS.join(", ")
+            )));
+        }
+        let repo_path = path_move::normalize_path(&update.repo_path)
+            .ok_or_else(|| bad_request(format!("invalid path: {}", update.repo_path)))?;
+
+        let existing = self
+            .webhook_storage
+            .get_webhook(&name)
+            .await
+            .map_err(internal_error)?;
+        let secret = match update.secret {
+            Some(secret) if secret.is_empty() => None,
+            Some(secret) => Some(secret),
+            None => existing.as_ref().and_then(|w| w.secret.clone()),
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let webhook = mega_webhook::Model {
+            id: existing.as_ref().map_or_else(generate_id, |w| w.id),
+            name,
+            url: update.url,
+            repo_path,
+            events: update.events.join(","),
+            dialect: dialect.as_str().to_owned(),
+            secret,
+            enabled: update.enabled,
+            created_at: existing.as_ref().map_or(now, |w| w.created_at),
+            updated_at: now,
+        };
+        let webhook = match existing {
+            Some(_) => self.webhook_storage.update_webhook(webhook).await,
+            None => self
+                .webhook_storage
+                .save_webhook(webhook.clone())
+                .await
+                .map(|_| webhook),
+        }
+        .map_err(internal_error)?;
+        Ok(Json(webhook.into()))
+    }
+
+    pub async fn delete_webhook(&self, name: &str) -> Result<StatusCode, (StatusCode, String)> {
+        match self.webhook_storage.delete_webhook(name).await {
+            Ok(true) => Ok(StatusCode::NO_CONTENT),
+            Ok(false) => Err((StatusCode::NOT_FOUND, format!("webhook {} not found", name))),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Json;
+use futures::future::BoxFuture;
+
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+use venus::internal::object::signature::{Signature, SignatureType};
+use venus::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api_service::markdown;
+use crate::api_service::merge::{edit_tree, Merger};
+use crate::api_service::mr_service::DEFAULT_COMMITTER;
+use crate::api_service::object_loader::{self, ObjectLoader};
+use crate::api_service::path_move;
+use crate::api_service::ref_update::RefUpdater;
+use crate::model::wiki::{
+    WikiChange, WikiHistoryQuery, WikiPage, WikiPageDelete, WikiPageQuery, WikiPageUpdate,
+    WikiPages, WikiQuery, WikiRevision,
+};
+
+/// The hidden ref the wiki of a directory is committed to.
+pub const WIKI_REF: &str = "refs/mega/wiki";
+
+/// Pages are markdown files named after them.
+const PAGE_EXTENSION: &str = ".md";
+
+/// Most commits the history of a wiki or page lists.
+const MAX_HISTORY: usize = 100;
+
+/// The wiki of each directory, kept as markdown files in the commits of its [`WIKI_REF`].
+///
+/// Every change of a page is a commit on top of the previous one, so the wiki is versioned
+/// like code without a storage of its own.
+#[derive(Clone)]
+pub struct WikiService {
+    pub storage: Arc<dyn ObjectStorage>,
+    pub ref_updater: RefUpdater,
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
+}
+
+/// The file a page is kept in, `guides/Setup.md` for `guides/Setup`. `None` when the name has
+/// an empty, `.` or `..` component or one starting with a dot.
+pub fn page_path(name: &str) -> Option<String> {
+    let components: Vec<&str> = name.trim_matches('/').split('/').collect();
+    let valid = components.iter().all(|c| {
+        !c.is_empty() && !c.starts_with('.') && c.trim() == *c && !c.chars().any(char::is_control)
+    });
+    valid.then(|| format!("{}{}", components.join("/"), PAGE_EXTENSION))
+}
+
+/// The page kept in the file at `path`, `None` for files that aren't pages.
+pub fn page_name(path: &str) -> Option<&str> {
+    path.strip_suffix(PAGE_EXTENSION)
+        .filter(|name| !name.is_empty() && !name.ends_with('/'))
+}
+
+fn check_page(name: &str) -> Result<(String, String), (StatusCode, String)> {
+    let path =
+        page_path(name).ok_or_else(|| bad_request(format!("invalid page name: {}", name)))?;
+    let name = page_name(&path).unwrap_or_default().to_owned();
+    Ok((name, path))
+}
+
+fn check_repo_path(repo_path: &str) -> Result<String, (StatusCode, String)> {
+    path_move::normalize_path(repo_path)
+        .ok_or_else(|| bad_request(format!("invalid path: {}", repo_path)))
+}
+
+fn parse_commit(id: &str) -> Result<SHA1, (StatusCode, String)> {
+    SHA1::from_str(id).map_err(|_| bad_request(format!("invalid commit id: {}", id)))
+}
+
+/// Add the pages below `tree_id` to `pages`, `prefix` being the path of the tree.
+fn collect_pages<'a>(
+    loader: &'a mut ObjectLoader,
+    tree_id: SHA1,
+    prefix: String,
+    pages: &'a mut Vec<String>,
+) -> BoxFuture<'a, Result<(), (StatusCode, String)>> {
+    Box::pin(async move {
+        for item in loader.tree(&tree_id).await?.tree_items {
+            let path = format!("{}{}", prefix, item.name);
+            if item.mode == TreeItemMode::Tree {
+                collect_pages(loader, item.id, format!("{}/", path), pages).await?;
+            } else if let Some(name) = page_name(&path) {
+                pages.push(name.to_owned());
+            }
+        }
+        Ok(())
+    })
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split('/').collect()
+}
+
+impl WikiService {
+    /// The latest commit of the wiki of `repo_path`, `None` when it has no page yet.
+    async fn head(&self, repo_path: &str) -> Result<Option<SHA1>, (StatusCode, String)> {
+        match ObjectLoader::new(self.storage.clone())
+            .resolve_ref(repo_path, Some(WIKI_REF))
+            .await
+        {
+            Ok(id) => Ok(Some(id)),
+            Err((StatusCode::NOT_FOUND, _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The head of the wiki a change is made on, refusing the change when it was made on
+    /// another commit.
+    async fn base(
+        &self,
+        repo_path: &str,
+        base_commit: Option<&str>,
+    ) -> Result<Option<SHA1>, (StatusCode, String)> {
+        let head = self.head(repo_path).await?;
+        if let Some(base) = base_commit {
+            if head != Some(parse_commit(base)?) {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("the wiki of {} has changed since {}", repo_path, base),
+                ));
+            }
+        }
+        Ok(head)
+    }
+
+    /// Wikis belong to directories of the monorepo or imported repositories.
+    async fn ensure_directory(&self, repo_path: &str) -> Result<(), (StatusCode, String)> {
+        let refs = self
+            .storage
+            .get_all_refs_by_path(repo_path)
+            .await
+            .map_err(internal_error)?;
+        if !refs.is_empty() {
+            return Ok(());
+        }
+        match self
+            .storage
+            .get_directory_by_full_path(repo_path)
+            .await
+            .map_err(internal_error)?
+        {
+            Some(_) => Ok(()),
+            None => Err((StatusCode::NOT_FOUND, format!("{} not found", repo_path))),
+        }
+    }
+
+    pub async fn list(&self, query: WikiQuery) -> Result<Json<WikiPages>, (StatusCode, String)> {
+        let repo_path = check_repo_path(&query.repo_path)?;
+        let Some(head) = self.head(&repo_path).await? else {
+            return Ok(Json(WikiPages {
+                commit_id: None,
+                pages: Vec::new(),
+            }));
+        };
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let tree_id = loader.commit(&head).await?.tree_id;
+        let mut pages = Vec::new();
+        collect_pages(&mut loader, tree_id, String::new(), &mut pages).await?;
+        pages.sort();
+        Ok(Json(WikiPages {
+            commit_id: Some(head.to_plain_str()),
+            pages,
+        }))
+    }
+
+    pub async fn page(&self, query: WikiPageQuery) -> Result<Json<WikiPage>, (StatusCode, String)> {
+        let repo_path = check_repo_path(&query.repo_path)?;
+        let (name, path) = check_page(&query.name)?;
+        let not_found = || (StatusCode::NOT_FOUND, format!("page {} not found", name));
+        let commit_id = match query.revision.as_deref() {
+            Some(revision) => parse_commit(revision)?,
+            None => self.head(&repo_path).await?.ok_or_else(not_found)?,
+        };
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let tree_id = loader.commit(&commit_id).await?.tree_id;
+        let item = loader
+            .find_path(&tree_id, &path)
+            .await?
+            .filter(|item| item.mode != TreeItemMode::Tree)
+            .ok_or_else(not_found)?;
+        let content = String::from_utf8_lossy(&loader.blob(&item.id).await?).into_owned();
+        Ok(Json(WikiPage {
+            name,
+            html: markdown::render(&content),
+            content,
+            commit_id: commit_id.to_plain_str(),
+        }))
+    }
+
+    /// Create the page `update.name` or replace its content, in a commit by `actor`.
+    pub async fn save(
+        &self,
+        update: WikiPageUpdate,
+        actor: &str,
+    ) -> Result<Json<WikiChange>, (StatusCode, String)> {
+        let repo_path = check_repo_path(&update.repo_path)?;
+        let (name, path) = check_page(&update.name)?;
+        self.ensure_directory(&repo_path).await?;
+        let head = self.base(&repo_path, update.base_commit.as_deref()).await?;
+
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut merger = Merger::new(self.storage.clone());
+        let tree_id = match head {
+            Some(head) => Some(loader.commit(&head).await?.tree_id),
+            None => None,
+        };
+        let existing = match tree_id {
+            Some(tree_id) => loader.find_path(&tree_id, &path).await?,
+            None => None,
+        };
+        if existing
+            .as_ref()
+            .is_some_and(|item| item.mode == TreeItemMode::Tree)
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} is a directory of pages", path),
+            ));
+        }
+        let blob_id = merger.write_blob(update.content.into_bytes());
+        if let (Some(head), Some(existing)) = (head, &existing) {
+            if existing.id == blob_id {
+                return Ok(Json(WikiChange {
+                    name,
+                    commit_id: head.to_plain_str(),
+                }));
+            }
+        }
+        let item = TreeItem::new(TreeItemMode::Blob, blob_id, String::new());
+        let tree_id = edit_tree(
+            &mut loader,
+            &mut merger,
+            tree_id,
+            &components(&path),
+            Some(item),
+        )
+        .await?
+        .ok_or_else(|| internal_error("wiki tree is empty"))?;
+        let message = update.message.unwrap_or_else(|| match existing {
+            Some(_) => format!("Update {}", name),
+            None => format!("Create {}", name),
+        });
+        let commit_id = self
+            .commit(&repo_path, &mut merger, head, tree_id, &message, actor)
+            .await?;
+        Ok(Json(WikiChange {
+            name,
+            commit_id: commit_id.to_plain_str(),
+        }))
+    }
+
+    /// Delete the page `delete.name` in a commit by `actor`.
+    pub async fn delete(
+        &self,
+        delete: WikiPageDelete,
+        actor: &str,
+    ) -> Result<Json<WikiChange>, (StatusCode, String)> {
+        let repo_path = check_repo_path(&delete.repo_path)?;
+        let (name, path) = check_page(&delete.name)?;
+        let not_found = || (StatusCode::NOT_FOUND, format!("page {} not found", name));
+        let head = self
+            .base(&repo_path, delete.base_commit.as_deref())
+            .await?
+            .ok_or_else(not_found)?;
+
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        let mut merger = Merger::new(self.storage.clone());
+        let tree_id = loader.commit(&head).await?.tree_id;
+        loader
+            .find_path(&tree_id, &path)
+            .await?
+            .filter(|item| item.mode != TreeItemMode::Tree)
+            .ok_or_else(not_found)?;
+        let tree_id = match edit_tree(
+            &mut loader,
+            &mut merger,
+            Some(tree_id),
+            &components(&path),
+            None,
+        )
+        .await?
+        {
+            Some(tree_id) => tree_id,
+            // the wiki keeps its history once its last page is gone
+            None => merger.write_tree(Vec::new())?,
+        };
+        let message = delete.message.unwrap_or_else(|| format!("Delete {}", name));
+        let commit_id = self
+            .commit(
+                &repo_path,
+                &mut merger,
+                Some(head),
+                tree_id,
+                &message,
+                actor,
+            )
+            .await?;
+        Ok(Json(WikiChange {
+            name,
+            commit_id: commit_id.to_plain_str(),
+        }))
+    }
+
+    /// Commit `tree_id` on top of `head` and move the wiki to it, unless another change moved
+    /// it meanwhile.
+    async fn commit(
+        &self,
+        repo_path: &str,
+        merger: &mut Merger,
+        head: Option<SHA1>,
+        tree_id: SHA1,
+        message: &str,
+        actor: &str,
+    ) -> Result<SHA1, (StatusCode, String)> {
+        let committer = Signature {
+            signature_type: SignatureType::Committer,
+            name: actor.to_owned(),
+            email: DEFAULT_COMMITTER.1.to_owned(),
+            timestamp: chrono::Utc::now().timestamp() as usize,
+            timezone: "+0000".to_owned(),
+        };
+        let commit_id = merger
+            .commit(
+                repo_path,
+                tree_id,
+                head.into_iter().collect(),
+                committer,
+                message,
+            )
+            .await?;
+        if self.head(repo_path).await? != head {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("the wiki of {} was changed meanwhile, retry", repo_path),
+            ));
+        }
+        self.ref_updater
+            .update(
+                repo_path,
+                WIKI_REF,
+                head.as_ref(),
+                &commit_id,
+                actor,
+                "wiki",
+            )
+            .await?;
+        Ok(commit_id)
+    }
+
+    /// The latest commits of the wiki, newest first, those changing `query.name` when given.
+    pub async fn history(
+        &self,
+        query: WikiHistoryQuery,
+    ) -> Result<Json<Vec<WikiRevision>>, (StatusCode, String)> {
+        let repo_path = check_repo_path(&query.repo_path)?;
+        let path = match query.name.as_deref() {
+            Some(name) => Some(check_page(name)?.1),
+            None => None,
+        };
+        let mut revisions = Vec::new();
+        let mut next = self.head(&repo_path).await?;
+        let mut loader = ObjectLoader::new(self.storage.clone());
+        while let Some(id) = next {
+            if revisions.len() == MAX_HISTORY {
+                break;
+            }
+            let commit = loader.commit(&id).await?;
+            next = commit.parent_commit_ids.first().copied();
+            if let Some(path) = &path {
+                let blob = |item: Option<TreeItem>| item.map(|item| item.id);
+                let after = blob(loader.find_path(&commit.tree_id, path).await?);
+                let before = match next {
+                    Some(parent) => {
+                        let tree_id = loader.commit(&parent).await?.tree_id;
+                        blob(loader.find_path(&tree_id, path).await?)
+                    }
+                    None => None,
+                };
+                if after == before {
+                    continue;
+                }
+            }
+            revisions.push(WikiRevision {
+                commit_id: id.to_plain_str(),
+                summary: object_loader::commit_summary(&commit.message),
+                author: commit.author.name,
+                committed_at: commit.committer.timestamp,
+            });
+        }
+        Ok(Json(revisions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_path() {
+        assert_eq!(page_path("Home").as_deref(), Some("Home.md"));
+        assert_eq!(
+            page_path("/guides/Getting started/").as_deref(),
+            Some("guides/Getting started.md")
+        );
+        for name in [
+            "",
+            "/",
+            "a//b",
+            "../Home",
+            "guides/.hidden",
+            " Home",
+            "a\nb",
+        ] {
+            assert_eq!(page_path(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_page_name() {
+        assert_eq!(page_name("Home.md"), Some("Home"));
+        assert_eq!(page_name("guides/Setup.md"), Some("guides/Setup"));
+        assert_eq!(page_name("logo.png"), None);
+        assert_eq!(page_name(".md"), None);
+        assert_eq!(page_name("guides/.md"), None);
+        let path = page_path("guides/Setup").unwrap();
+        assert_eq!(page_name(&path), Some("guides/Setup"));
+    }
+}
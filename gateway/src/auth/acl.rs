@@ -0,0 +1,287 @@
+//! Permissions on monorepo paths.
+//!
+//! Users and organizations are granted `read`, `write`, `maintain` or `admin` on a path, each
+//! permission including the ones before it. A grant holds for the path and everything below it,
+//! and the permission of a user on a path is the highest one granted to them or to one of their
+//! organizations on the path or its parents. Administrators have every permission everywhere.
+//!
+//! The checks are off until `MEGA_ACL` is `on`. `MEGA_ACL_DEFAULT` then gives everyone, clients
+//! without credentials included, a permission on every path, e.g. `read` for an open monorepo
+//! with restricted pushes.
+//!
+use std::fmt;
+use std::str::FromStr;
+
+use common::errors::MegaError;
+use db_entity::mega_path_grant;
+use jupiter::storage::org_storage::OrgStorage;
+use jupiter::storage::path_grant_storage::PathGrantStorage;
+
+use crate::auth::token::TokenScope;
+use crate::auth::Identity;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// Fetch and browse
+    Read,
+    /// Push, and open, update and comment on merge requests and issues
+    Write,
+    /// Merge merge requests
+    Maintain,
+    /// Manage the grants on the path
+    Admin,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Maintain => "maintain",
+            Permission::Admin => "admin",
+        }
+    }
+
+    /// The scope an access token needs to act with this permission.
+    pub fn token_scope(&self) -> TokenScope {
+        match self {
+            Permission::Read => TokenScope::Read,
+            Permission::Write | Permission::Maintain => TokenScope::Write,
+            Permission::Admin => TokenScope::Admin,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Permission::Read),
+            "write" => Ok(Permission::Write),
+            "maintain" => Ok(Permission::Maintain),
+            "admin" => Ok(Permission::Admin),
+            _ => Err(format!(
+                "unknown permission {}, expected read, write, maintain or admin",
+                s
+            )),
+        }
+    }
+}
+
+/// Who a grant is for, stored in `mega_path_grant.subject_kind`.
+pub const SUBJECT_USER: &str = "user";
+pub const SUBJECT_ORG: &str = "org";
+
+/// `path` with a leading `/` and without empty segments or a trailing `/`, `None` when it climbs
+/// up with `..` or `.`.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.iter().any(|s| *s == ".." || *s == ".") {
+        return None;
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// The paths whose grants hold for the normalized `path`: `/`, its parents and itself.
+pub fn ancestors(path: &str) -> Vec<String> {
+    let mut paths = vec!["/".to_owned()];
+    paths.extend(
+        path.match_indices('/')
+            .skip(1)
+            .map(|(i, _)| path[..i].to_owned())
+            .chain([path.to_owned()])
+            .filter(|p| p.len() > 1),
+    );
+    paths
+}
+
+/// The highest permission `grants` give the user `username`, a member of `orgs`, or `default`
+/// when it is higher. Grants that are not for them are skipped, so `grants` may hold every grant
+/// on the path and its parents.
+pub fn effective_permission(
+    grants: &[mega_path_grant::Model],
+    username: Option<&str>,
+    orgs: &[String],
+    default: Option<Permission>,
+) -> Option<Permission> {
+    grants
+        .iter()
+        .filter(|g| match g.subject_kind.as_str() {
+            SUBJECT_USER => Some(g.subject.as_str()) == username,
+            SUBJECT_ORG => orgs.contains(&g.subject),
+            _ => false,
+        })
+        .filter_map(|g| g.permission.parse().ok())
+        .chain(default)
+        .max()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AclPolicy {
+    pub enabled: bool,
+    /// Permission of everyone on every path
+    pub default: Option<Permission>,
+}
+
+impl AclPolicy {
+    pub fn from_env() -> Result<Self, MegaError> {
+        let enabled = match std::env::var("MEGA_ACL").unwrap_or_default().trim() {
+            "" | "off" => false,
+            "on" => true,
+            other => {
+                return Err(MegaError::with_message(&format!(
+                    "unknown MEGA_ACL: {}, expected on or off",
+                    other
+                )))
+            }
+        };
+        let default = match std::env::var("MEGA_ACL_DEFAULT").unwrap_or_default().trim() {
+            "" | "none" => None,
+            value => Some(
+                value
+                    .parse()
+                    .map_err(|e: String| MegaError::with_message(&e))?,
+            ),
+        };
+        Ok(AclPolicy { enabled, default })
+    }
+}
+
+/// Evaluates the grants of `mega_path_grant`.
+#[derive(Clone)]
+pub struct Acl {
+    pub policy: AclPolicy,
+    pub grant_storage: PathGrantStorage,
+    pub org_storage: OrgStorage,
+}
+
+impl Acl {
+    pub fn enabled(&self) -> bool {
+        self.policy.enabled
+    }
+
+    /// The permission of `identity` on the normalized `path`, the default permission when it is
+    /// `None`. Checked whether the ACL is enabled or not.
+    pub async fn permission(
+        &self,
+        identity: Option<&Identity>,
+        path: &str,
+    ) -> Result<Option<Permission>, MegaError> {
+        if identity.is_some_and(|i| i.is_admin) {
+            return Ok(Some(Permission::Admin));
+        }
+        let Some(identity) = identity else {
+            return Ok(self.policy.default);
+        };
+        let grants = self.grant_storage.grants_on(&ancestors(path)).await?;
+        let orgs = if grants.iter().any(|g| g.subject_kind == SUBJECT_ORG) {
+            self.org_storage
+                .list_user_orgs(&identity.username)
+                .await?
+                .into_iter()
+                .map(|(_, org)| org.name)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(effective_permission(
+            &grants,
+            Some(&identity.username),
+            &orgs,
+            self.policy.default,
+        ))
+    }
+
+    /// Whether `identity` may do what needs `needed` on `path`, always when the ACL is off.
+    pub async fn allows(
+        &self,
+        identity: Option<&Identity>,
+        path: &str,
+        needed: Permission,
+    ) -> Result<bool, MegaError> {
+        if !self.enabled() {
+            return Ok(true);
+        }
+        let Some(path) = normalize_path(path) else {
+            return Ok(false);
+        };
+        Ok(self
+            .permission(identity, &path)
+            .await?
+            .is_some_and(|p| p >= needed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db_entity::mega_path_grant;
+
+    use super::{ancestors, effective_permission, normalize_path, Permission};
+
+    fn grant(path: &str, kind: &str, subject: &str, permission: &str) -> mega_path_grant::Model {
+        let now = chrono::Utc::now().naive_utc();
+        mega_path_grant::Model {
+            id: 1,
+            path: path.to_owned(),
+            subject_kind: kind.to_owned(),
+            subject: subject.to_owned(),
+            permission: permission.to_owned(),
+            granted_by: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("projects//mega/").as_deref(),
+            Some("/projects/mega")
+        );
+        assert_eq!(normalize_path("/").as_deref(), Some("/"));
+        assert_eq!(normalize_path(""), Some("/".to_owned()));
+        assert_eq!(normalize_path("/projects/../third-party"), None);
+    }
+
+    #[test]
+    fn test_ancestors() {
+        assert_eq!(ancestors("/"), ["/"]);
+        assert_eq!(
+            ancestors("/projects/mega/src"),
+            ["/", "/projects", "/projects/mega", "/projects/mega/src"]
+        );
+    }
+
+    #[test]
+    fn test_effective_permission() {
+        let grants = [
+            grant("/", "user", "alice", "read"),
+            grant("/projects", "org", "platform", "write"),
+            grant("/projects/mega", "user", "alice", "maintain"),
+            grant("/projects/mega", "user", "bob", "bogus"),
+        ];
+        let platform = ["platform".to_owned()];
+        assert_eq!(
+            effective_permission(&grants, Some("alice"), &[], None),
+            Some(Permission::Maintain)
+        );
+        assert_eq!(
+            effective_permission(&grants, Some("bob"), &platform, None),
+            Some(Permission::Write)
+        );
+        assert_eq!(effective_permission(&grants, Some("bob"), &[], None), None);
+        assert_eq!(
+            effective_permission(&grants, None, &[], Some(Permission::Read)),
+            Some(Permission::Read)
+        );
+        assert!(Permission::Admin > Permission::Maintain);
+        assert!("owner".parse::<Permission>().is_err());
+    }
+}
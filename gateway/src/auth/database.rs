@@ -0,0 +1,64 @@
+use async_trait::async_trait;
+
+use common::errors::MegaError;
+use db_entity::mega_user;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::auth::{verify_password, AuthProvider, Identity};
+
+/// Accounts managed by mega itself, stored in the `mega_user` table.
+pub struct DatabaseAuthProvider {
+    storage: UserStorage,
+}
+
+impl DatabaseAuthProvider {
+    pub fn new(storage: UserStorage) -> Self {
+        DatabaseAuthProvider { storage }
+    }
+}
+
+impl From<mega_user::Model> for Identity {
+    fn from(user: mega_user::Model) -> Self {
+        Identity {
+            username: user.name,
+            display_name: user.display_name,
+            email: user.email,
+            is_admin: user.is_admin,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for DatabaseAuthProvider {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    async fn verify_credentials(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, MegaError> {
+        let user = self.storage.get_user_by_name(username).await?;
+        Ok(user
+            .filter(|u| {
+                u.password_hash
+                    .as_deref()
+                    .is_some_and(|hash| verify_password(hash, password))
+            })
+            .map(Identity::from))
+    }
+
+    async fn resolve_identity(&self, username: &str) -> Result<Option<Identity>, MegaError> {
+        Ok(self
+            .storage
+            .get_user_by_name(username)
+            .await?
+            .map(Identity::from))
+    }
+
+    async fn list_public_keys(&self, _username: &str) -> Result<Vec<String>, MegaError> {
+        // keys of database accounts are registered through the API and checked by the transport
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,321 @@
+//! Authentication of git clients over smart HTTP.
+//!
+//! `MEGA_HTTP_AUTH` decides which requests need credentials: `push` for receive-pack and LFS
+//! uploads, `all` for fetches as well, and nothing is checked when it is unset. Clients send
+//! either a personal access token, as the password of basic auth or as a bearer token, or a
+//! username and password checked by the configured [`AuthProvider`]. When the ACL is enabled,
+//! the permissions of the user on the repository are checked as well, see [`crate::auth::acl`].
+//!
+use std::sync::Arc;
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use common::errors::MegaError;
+use jupiter::storage::access_token_storage::AccessTokenStorage;
+use jupiter::storage::user_storage::UserStorage;
+
+use crate::auth::acl::{Acl, Permission};
+use crate::auth::token::{self, TokenScope, TOKEN_PREFIX};
+use crate::auth::{AuthProvider, Identity};
+
+/// Which HTTP requests need credentials.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HttpAuthMode {
+    #[default]
+    None,
+    Push,
+    All,
+}
+
+impl HttpAuthMode {
+    pub fn from_env() -> Result<Self, MegaError> {
+        let value = std::env::var("MEGA_HTTP_AUTH").unwrap_or_default();
+        HttpAuthMode::parse(&value).map_err(|e| MegaError::with_message(&e))
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "" | "none" => Ok(HttpAuthMode::None),
+            "push" => Ok(HttpAuthMode::Push),
+            "all" => Ok(HttpAuthMode::All),
+            other => Err(format!(
+                "unknown MEGA_HTTP_AUTH: {}, expected none, push or all",
+                other
+            )),
+        }
+    }
+
+    /// The scope a request needs, `None` when it needs no credentials.
+    pub fn required_scope(&self, write: bool) -> Option<TokenScope> {
+        match (self, write) {
+            (HttpAuthMode::None, _) | (HttpAuthMode::Push, false) => None,
+            (_, true) => Some(TokenScope::Write),
+            (HttpAuthMode::All, false) => Some(TokenScope::Read),
+        }
+    }
+}
+
+/// Credentials in the `Authorization` header of a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+pub fn parse_authorization(headers: &HeaderMap) -> Option<Credentials> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, rest) = value.trim().split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return Some(Credentials::Bearer(rest.trim().to_owned()));
+    }
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(rest.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some(Credentials::Basic {
+        username: username.to_owned(),
+        password: password.to_owned(),
+    })
+}
+
+fn unauthorized(message: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"mega\"")],
+        format!("{}\n", message),
+    )
+        .into_response()
+}
+
+fn internal_error(e: MegaError) -> Response {
+    tracing::error!("HTTP authentication failed: {}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+}
+
+/// Check that `identity` has `permission` on `path`, asking for credentials when it is `None`.
+pub async fn check_permission(
+    acl: &Acl,
+    identity: Option<&Identity>,
+    path: &str,
+    permission: Permission,
+) -> Result<(), Response> {
+    match acl.allows(identity, path, permission).await {
+        Ok(true) => Ok(()),
+        Ok(false) if identity.is_none() => Err(unauthorized("authentication required")),
+        Ok(false) => Err((
+            StatusCode::FORBIDDEN,
+            format!("{} permission on {} required\n", permission, path),
+        )
+            .into_response()),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpAuth {
+    pub mode: HttpAuthMode,
+    pub provider: Option<Arc<dyn AuthProvider>>,
+    pub user_storage: UserStorage,
+    pub token_storage: AccessTokenStorage,
+    pub acl: Acl,
+}
+
+impl HttpAuth {
+    /// The user making a request, `Ok(None)` when the request needs no credentials and the
+    /// response to send back when they are missing or don't allow it.
+    pub async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        write: bool,
+    ) -> Result<Option<Identity>, Response> {
+        let Some(scope) = self.mode.required_scope(write) else {
+            return Ok(None);
+        };
+        self.verify_credentials(headers, scope).await.map(Some)
+    }
+
+    /// Like [`HttpAuth::authenticate`], but the credentials a request sends without needing
+    /// them are checked too, so that the ACL knows who is asking.
+    pub async fn identify(
+        &self,
+        headers: &HeaderMap,
+        scope: TokenScope,
+    ) -> Result<Option<Identity>, Response> {
+        let required = self.mode.required_scope(scope > TokenScope::Read);
+        if required.is_none() && parse_authorization(headers).is_none() {
+            return Ok(None);
+        }
+        self.verify_credentials(headers, scope).await.map(Some)
+    }
+
+    /// The user making a git request on `repo_path`, who needs `permission` on it when the ACL
+    /// is enabled.
+    pub async fn authorize(
+        &self,
+        headers: &HeaderMap,
+        repo_path: &str,
+        permission: Permission,
+    ) -> Result<Option<Identity>, Response> {
+        if !self.acl.enabled() {
+            return self
+                .authenticate(headers, permission > Permission::Read)
+                .await;
+        }
+        let identity = self.identify(headers, permission.token_scope()).await?;
+        check_permission(&self.acl, identity.as_ref(), repo_path, permission).await?;
+        Ok(identity)
+    }
+
+    /// The admin making a request to the `/admin` calls. They erase, delete and import, so an
+    /// admin is needed whether the ACL is enabled or not.
+    pub async fn authorize_admin(&self, headers: &HeaderMap) -> Result<Identity, Response> {
+        let identity = self
+            .verify_credentials(headers, Permission::Admin.token_scope())
+            .await?;
+        check_permission(&self.acl, Some(&identity), "/", Permission::Admin).await?;
+        Ok(identity)
+    }
+
+    async fn verify_credentials(
+        &self,
+        headers: &HeaderMap,
+        scope: TokenScope,
+    ) -> Result<Identity, Response> {
+        match parse_authorization(headers) {
+            None => Err(unauthorized("authentication required")),
+            Some(Credentials::Bearer(secret)) => self.verify_token(&secret, scope).await,
+            Some(Credentials::Basic { password, .. }) if password.starts_with(TOKEN_PREFIX) => {
+                self.verify_token(&password, scope).await
+            }
+            Some(Credentials::Basic { username, password }) => match &self.provider {
+                Some(provider) => provider
+                    .verify_credentials(&username, &password)
+                    .await
+                    .map_err(internal_error)?
+                    .ok_or_else(|| unauthorized("wrong username or password")),
+                None => Err(unauthorized("use a personal access token as the password")),
+            },
+        }
+    }
+
+    /// The owner of the token `secret` if it is valid and grants `scope`.
+    async fn verify_token(&self, secret: &str, scope: TokenScope) -> Result<Identity, Response> {
+        let invalid = || unauthorized("invalid or expired token");
+        let stored = self
+            .token_storage
+            .find_by_hash(&token::token_hash(secret))
+            .await
+            .map_err(internal_error)?
+            .filter(|t| token::verify_token(&t.token_hash, secret))
+            .ok_or_else(invalid)?;
+        let now = chrono::Utc::now().naive_utc();
+        if stored.expires_at.is_some_and(|expiry| expiry <= now) {
+            return Err(invalid());
+        }
+        if !scope.granted_by(&stored.scopes) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("token lacks the {} scope\n", scope.as_str()),
+            )
+                .into_response());
+        }
+        if let Err(e) = self.token_storage.touch_last_used(stored.id).await {
+            tracing::warn!("failed to record the use of token {}: {}", stored.id, e);
+        }
+        // the account may have been erased after the token was issued
+        self.user_storage
+            .get_user_by_name(&stored.username)
+            .await
+            .map_err(internal_error)?
+            .map(Identity::from)
+            .ok_or_else(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+    use sea_orm::DatabaseConnection;
+
+    use jupiter::storage::access_token_storage::AccessTokenStorage;
+    use jupiter::storage::org_storage::OrgStorage;
+    use jupiter::storage::path_grant_storage::PathGrantStorage;
+    use jupiter::storage::user_storage::UserStorage;
+
+    use crate::auth::acl::{Acl, AclPolicy};
+    use crate::auth::token::TokenScope;
+
+    use super::{parse_authorization, Credentials, HttpAuth, HttpAuthMode};
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_http_auth_mode() {
+        assert_eq!(HttpAuthMode::parse(""), Ok(HttpAuthMode::None));
+        assert_eq!(HttpAuthMode::parse("push"), Ok(HttpAuthMode::Push));
+        assert!(HttpAuthMode::parse("fetch").is_err());
+
+        assert_eq!(HttpAuthMode::None.required_scope(true), None);
+        assert_eq!(HttpAuthMode::Push.required_scope(false), None);
+        assert_eq!(
+            HttpAuthMode::Push.required_scope(true),
+            Some(TokenScope::Write)
+        );
+        assert_eq!(
+            HttpAuthMode::All.required_scope(false),
+            Some(TokenScope::Read)
+        );
+    }
+
+    #[test]
+    fn test_parse_authorization() {
+        // "mega:pass:word", passwords may contain colons
+        assert_eq!(
+            parse_authorization(&headers("Basic bWVnYTpwYXNzOndvcmQ=")),
+            Some(Credentials::Basic {
+                username: "mega".to_owned(),
+                password: "pass:word".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_authorization(&headers("bearer mega_abc")),
+            Some(Credentials::Bearer("mega_abc".to_owned()))
+        );
+        assert_eq!(parse_authorization(&headers("Basic !!!")), None);
+        assert_eq!(parse_authorization(&headers("Digest x")), None);
+        assert_eq!(parse_authorization(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_authorize_admin() {
+        // the default setup: no credentials needed and the ACL disabled
+        let connection = Arc::new(DatabaseConnection::Disconnected);
+        let auth = HttpAuth {
+            mode: HttpAuthMode::None,
+            provider: None,
+            user_storage: UserStorage::new(connection.clone()),
+            token_storage: AccessTokenStorage::new(connection.clone()),
+            acl: Acl {
+                policy: AclPolicy {
+                    enabled: false,
+                    default: None,
+                },
+                grant_storage: PathGrantStorage::new(connection.clone()),
+                org_storage: OrgStorage::new(connection),
+            },
+        };
+        let response = auth.authorize_admin(&HeaderMap::new()).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+}
@@ -0,0 +1,154 @@
+use std::env;
+
+use async_trait::async_trait;
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapError, Scope, SearchEntry};
+
+use common::errors::MegaError;
+
+use crate::auth::{AuthProvider, Identity};
+
+const DISPLAY_NAME_ATTR: &str = "cn";
+const EMAIL_ATTR: &str = "mail";
+const SSH_KEY_ATTR: &str = "sshPublicKey";
+const MEMBER_OF_ATTR: &str = "memberOf";
+
+/// Accounts of a directory server.
+///
+/// Users are found with a search under `MEGA_LDAP_BASE_DN` using `MEGA_LDAP_USER_FILTER`, where
+/// `{username}` is replaced by the (escaped) login name. The search runs as `MEGA_LDAP_BIND_DN`
+/// when it is set and anonymously otherwise. Passwords are checked by binding as the user found.
+pub struct LdapAuthProvider {
+    url: String,
+    bind_dn: Option<String>,
+    bind_password: String,
+    base_dn: String,
+    user_filter: String,
+    /// Members of this group are administrators.
+    admin_group: Option<String>,
+}
+
+fn ldap_err(err: LdapError) -> MegaError {
+    MegaError::new(err.into(), 1)
+}
+
+impl LdapAuthProvider {
+    pub fn from_env() -> Result<Self, MegaError> {
+        let required = |name: &str| {
+            env::var(name).map_err(|_| {
+                MegaError::with_message(&format!("{} is required by the ldap provider", name))
+            })
+        };
+        Ok(LdapAuthProvider {
+            url: required("MEGA_LDAP_URL")?,
+            bind_dn: env::var("MEGA_LDAP_BIND_DN").ok().filter(|s| !s.is_empty()),
+            bind_password: env::var("MEGA_LDAP_BIND_PASSWORD").unwrap_or_default(),
+            base_dn: required("MEGA_LDAP_BASE_DN")?,
+            user_filter: env::var("MEGA_LDAP_USER_FILTER")
+                .unwrap_or_else(|_| "(uid={username})".to_owned()),
+            admin_group: env::var("MEGA_LDAP_ADMIN_GROUP")
+                .ok()
+                .filter(|s| !s.is_empty()),
+        })
+    }
+
+    async fn connect(&self) -> Result<Ldap, MegaError> {
+        let (conn, ldap) = LdapConnAsync::new(&self.url).await.map_err(ldap_err)?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    /// Search the entry of `username`, `None` if there is no such user or the name is ambiguous.
+    async fn find_user(&self, username: &str) -> Result<Option<SearchEntry>, MegaError> {
+        let mut ldap = self.connect().await?;
+        if let Some(bind_dn) = &self.bind_dn {
+            ldap.simple_bind(bind_dn, &self.bind_password)
+                .await
+                .map_err(ldap_err)?
+                .success()
+                .map_err(ldap_err)?;
+        }
+        let filter = self
+            .user_filter
+            .replace("{username}", &ldap_escape(username));
+        let (mut entries, _) = ldap
+            .search(
+                &self.base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![DISPLAY_NAME_ATTR, EMAIL_ATTR, SSH_KEY_ATTR, MEMBER_OF_ATTR],
+            )
+            .await
+            .map_err(ldap_err)?
+            .success()
+            .map_err(ldap_err)?;
+        let _ = ldap.unbind().await;
+
+        if entries.len() > 1 {
+            tracing::warn!("ldap filter {} matches {} entries", filter, entries.len());
+            return Ok(None);
+        }
+        Ok(entries.pop().map(SearchEntry::construct))
+    }
+
+    fn identity(&self, username: &str, entry: &SearchEntry) -> Identity {
+        let first = |attr: &str| entry.attrs.get(attr).and_then(|v| v.first()).cloned();
+        let is_admin = match &self.admin_group {
+            Some(group) => entry
+                .attrs
+                .get(MEMBER_OF_ATTR)
+                .is_some_and(|groups| groups.iter().any(|g| g.eq_ignore_ascii_case(group))),
+            None => false,
+        };
+        Identity {
+            username: username.to_owned(),
+            display_name: first(DISPLAY_NAME_ATTR),
+            email: first(EMAIL_ATTR),
+            is_admin,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for LdapAuthProvider {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    async fn verify_credentials(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, MegaError> {
+        // a bind with an empty password is an anonymous bind, which most servers accept
+        if password.is_empty() {
+            return Ok(None);
+        }
+        let Some(entry) = self.find_user(username).await? else {
+            return Ok(None);
+        };
+        let mut ldap = self.connect().await?;
+        let bound = ldap
+            .simple_bind(&entry.dn, password)
+            .await
+            .map_err(ldap_err)?
+            .success()
+            .is_ok();
+        let _ = ldap.unbind().await;
+        Ok(bound.then(|| self.identity(username, &entry)))
+    }
+
+    async fn resolve_identity(&self, username: &str) -> Result<Option<Identity>, MegaError> {
+        Ok(self
+            .find_user(username)
+            .await?
+            .map(|entry| self.identity(username, &entry)))
+    }
+
+    async fn list_public_keys(&self, username: &str) -> Result<Vec<String>, MegaError> {
+        Ok(self
+            .find_user(username)
+            .await?
+            .and_then(|mut entry| entry.attrs.remove(SSH_KEY_ATTR))
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,158 @@
+//! Pluggable authentication.
+//!
+//! Transports never look at user records themselves: they hand credentials to an [`AuthProvider`]
+//! and get back an [`Identity`] or nothing. The provider is picked by `MEGA_AUTH_PROVIDER`:
+//!
+//! - `database`: accounts in the `mega_user` table, see [`database::DatabaseAuthProvider`].
+//! - `ldap`: a directory server, see [`ldap::LdapAuthProvider`].
+//! - `static`: a TOML file of users, see [`static_file::StaticFileAuthProvider`].
+//!
+//! Leaving it unset keeps the server open: every client is accepted, as before providers existed.
+//! Deployments with their own identity system implement [`AuthProvider`] and set it on the server.
+//!
+//! SSH keys registered through the API work with every provider, see [`ssh_key::verify_key`].
+//! Users may also log in through an OpenID Connect provider, see [`oidc`].
+//!
+//! What an authenticated user may do on each path of the monorepo is decided by [`acl`].
+//!
+use std::env;
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use async_trait::async_trait;
+use russh_keys::key::PublicKey;
+use russh_keys::PublicKeyBase64;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use common::errors::MegaError;
+use jupiter::storage::user_storage::UserStorage;
+
+pub mod acl;
+pub mod database;
+pub mod http;
+pub mod ldap;
+pub mod oidc;
+pub mod signing;
+pub mod ssh_key;
+pub mod static_file;
+pub mod token;
+
+/// The user behind an authenticated request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub is_admin: bool,
+}
+
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Short name of the provider, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Check a username and password, `None` when they do not match an account.
+    async fn verify_credentials(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, MegaError>;
+
+    /// Look up an account without checking credentials.
+    async fn resolve_identity(&self, username: &str) -> Result<Option<Identity>, MegaError>;
+
+    /// The SSH public keys of an account in OpenSSH format (`<type> <base64> [comment]`).
+    async fn list_public_keys(&self, username: &str) -> Result<Vec<String>, MegaError>;
+
+    /// Check that `key` is one of the SSH keys of `username`.
+    async fn verify_public_key(
+        &self,
+        username: &str,
+        key: &PublicKey,
+    ) -> Result<Option<Identity>, MegaError> {
+        let offered = key.public_key_base64();
+        let keys = self.list_public_keys(username).await?;
+        if keys
+            .iter()
+            .any(|line| line.split_whitespace().nth(1) == Some(offered.as_str()))
+        {
+            return self.resolve_identity(username).await;
+        }
+        Ok(None)
+    }
+}
+
+/// Create the provider configured by `MEGA_AUTH_PROVIDER`, `None` when authentication is off.
+pub fn init(
+    connection: Arc<DatabaseConnection>,
+) -> Result<Option<Arc<dyn AuthProvider>>, MegaError> {
+    let provider: Arc<dyn AuthProvider> = match env::var("MEGA_AUTH_PROVIDER")
+        .unwrap_or_default()
+        .as_str()
+    {
+        "" | "none" => {
+            tracing::warn!("MEGA_AUTH_PROVIDER is not set, all clients are accepted");
+            return Ok(None);
+        }
+        "database" => Arc::new(database::DatabaseAuthProvider::new(UserStorage::new(
+            connection,
+        ))),
+        "ldap" => Arc::new(ldap::LdapAuthProvider::from_env()?),
+        "static" => {
+            let path = env::var("MEGA_AUTH_STATIC_FILE").map_err(|_| {
+                MegaError::with_message("MEGA_AUTH_STATIC_FILE is required by the static provider")
+            })?;
+            Arc::new(static_file::StaticFileAuthProvider::load(path)?)
+        }
+        other => {
+            return Err(MegaError::with_message(&format!(
+                "unknown MEGA_AUTH_PROVIDER: {}",
+                other
+            )))
+        }
+    };
+    tracing::info!(
+        "authenticating clients with the {} provider",
+        provider.name()
+    );
+    Ok(Some(provider))
+}
+
+/// Hash a password for storage, as an argon2id PHC string.
+pub fn hash_password(password: &str) -> Result<String, MegaError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| MegaError::with_message(&e.to_string()))
+}
+
+/// Check a password against a hash produced by [`hash_password`].
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(err) => {
+            tracing::error!("malformed password hash: {}", err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_password, verify_password};
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password(&hash, "correct horse"));
+        assert!(!verify_password(&hash, "battery staple"));
+        assert!(!verify_password("not a hash", "correct horse"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Login through an OpenID Connect provider such as Keycloak or Google, or GitHub's OAuth2.
+//!
+//! The gateway runs the authorization code flow with PKCE: `/auth/oidc/login` sends the browser
+//! to the provider, which sends it back to `/auth/oidc/callback` with a code. The code is
+//! exchanged for an access token, and the claims of the userinfo endpoint name the user and the
+//! groups they belong to. Everything is configured by `MEGA_OIDC_*` variables, the endpoints are
+//! discovered from the issuer unless they are all given.
+//!
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use common::errors::MegaError;
+
+const DEFAULT_SCOPES: &str = "openid profile email";
+const DEFAULT_USERNAME_CLAIM: &str = "preferred_username";
+const DEFAULT_GROUPS_CLAIM: &str = "groups";
+const DEFAULT_SESSION_HOURS: i64 = 8;
+
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    /// Identifies the provider, linked accounts are keyed by it
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// The `/api/v1/auth/oidc/callback` URL of this server, as registered with the provider
+    pub redirect_url: String,
+    pub scopes: String,
+    /// Endpoints given in the configuration, `None` to discover them
+    pub endpoints: Option<OidcEndpoints>,
+    pub username_claim: String,
+    pub groups_claim: String,
+    /// Members of this group are administrators, when it is set
+    pub admin_group: Option<String>,
+    /// Whether a first login may take over an existing user of the same name
+    pub link_by_name: bool,
+    /// Lifetime of the access token issued on login
+    pub session_hours: i64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OidcEndpoints {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+fn env_value(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+fn required(name: &str) -> Result<String, MegaError> {
+    env_value(name)
+        .ok_or_else(|| MegaError::with_message(&format!("{} is required by OIDC login", name)))
+}
+
+impl OidcConfig {
+    /// The configuration of `MEGA_OIDC_ISSUER` and the variables next to it, `None` when OIDC
+    /// login is off.
+    pub fn from_env() -> Result<Option<Self>, MegaError> {
+        let Some(issuer) = env_value("MEGA_OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let endpoints = match (
+            env_value("MEGA_OIDC_AUTHORIZATION_URL"),
+            env_value("MEGA_OIDC_TOKEN_URL"),
+            env_value("MEGA_OIDC_USERINFO_URL"),
+        ) {
+            (Some(authorization_endpoint), Some(token_endpoint), Some(userinfo_endpoint)) => {
+                Some(OidcEndpoints {
+                    authorization_endpoint,
+                    token_endpoint,
+                    userinfo_endpoint,
+                })
+            }
+            (None, None, None) => None,
+            _ => {
+                return Err(MegaError::with_message(
+                    "set all of MEGA_OIDC_AUTHORIZATION_URL, MEGA_OIDC_TOKEN_URL and \
+                     MEGA_OIDC_USERINFO_URL, or none to discover them",
+                ))
+            }
+        };
+        let session_hours = match env_value("MEGA_OIDC_SESSION_HOURS") {
+            Some(hours) => hours.parse().ok().filter(|h| *h > 0).ok_or_else(|| {
+                MegaError::with_message(&format!("invalid MEGA_OIDC_SESSION_HOURS: {}", hours))
+            })?,
+            None => DEFAULT_SESSION_HOURS,
+        };
+        Ok(Some(OidcConfig {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            client_id: required("MEGA_OIDC_CLIENT_ID")?,
+            client_secret: required("MEGA_OIDC_CLIENT_SECRET")?,
+            redirect_url: required("MEGA_OIDC_REDIRECT_URL")?,
+            scopes: env_value("MEGA_OIDC_SCOPES").unwrap_or_else(|| DEFAULT_SCOPES.to_owned()),
+            endpoints,
+            username_claim: env_value("MEGA_OIDC_USERNAME_CLAIM")
+                .unwrap_or_else(|| DEFAULT_USERNAME_CLAIM.to_owned()),
+            groups_claim: env_value("MEGA_OIDC_GROUPS_CLAIM")
+                .unwrap_or_else(|| DEFAULT_GROUPS_CLAIM.to_owned()),
+            admin_group: env_value("MEGA_OIDC_ADMIN_GROUP"),
+            link_by_name: env_value("MEGA_OIDC_LINK_BY_NAME").is_some_and(|v| v == "true"),
+            session_hours,
+        }))
+    }
+
+    /// Where the provider publishes its endpoints.
+    pub fn discovery_url(&self) -> String {
+        format!("{}/.well-known/openid-configuration", self.issuer)
+    }
+
+    /// The provider page a login starts at.
+    pub fn authorize_url(&self, endpoints: &OidcEndpoints, state: &str, verifier: &str) -> String {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_url)
+            .append_pair("scope", &self.scopes)
+            .append_pair("state", state)
+            .append_pair("code_challenge", &code_challenge(verifier))
+            .append_pair("code_challenge_method", "S256")
+            .finish();
+        let separator = if endpoints.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!("{}{}{}", endpoints.authorization_endpoint, separator, query)
+    }
+}
+
+/// A random value for the `state` of a login or a PKCE code verifier.
+pub fn random_secret(len: usize) -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+}
+
+/// The S256 PKCE challenge of `verifier`.
+pub fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Who the provider says logged in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OidcProfile {
+    /// Stable id of the account at the provider
+    pub subject: String,
+    /// Wanted mega user name, used on the first login only
+    pub username: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub groups: Vec<String>,
+}
+
+fn claim_str(claims: &Value, name: &str) -> Option<String> {
+    match claims.get(name)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_owned()),
+        // GitHub user ids are numbers
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Read the profile out of the userinfo `claims`. The user name falls back to the part of the
+/// email before the `@` for providers without a user name claim, such as Google. Group paths
+/// of Keycloak lose their leading `/`.
+pub fn profile_from_claims(claims: &Value, config: &OidcConfig) -> Result<OidcProfile, String> {
+    let subject = claim_str(claims, "sub")
+        .or_else(|| claim_str(claims, "id"))
+        .ok_or("the provider did not send a subject")?;
+    let email_verified = claims.get("email_verified").and_then(Value::as_bool);
+    let email = claim_str(claims, "email").filter(|_| email_verified != Some(false));
+    let username = claim_str(claims, &config.username_claim)
+        .or_else(|| {
+            email
+                .as_deref()
+                .and_then(|e| e.split_once('@'))
+                .map(|(local, _)| local.to_owned())
+        })
+        .ok_or_else(|| format!("the provider did not send {}", config.username_claim))?;
+    let groups = match claims.get(&config.groups_claim) {
+        Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).collect(),
+        Some(Value::String(group)) => vec![group.as_str()],
+        _ => Vec::new(),
+    };
+    Ok(OidcProfile {
+        subject,
+        username,
+        display_name: claim_str(claims, "name"),
+        email,
+        groups: groups
+            .into_iter()
+            .map(|g| g.trim().trim_start_matches('/').to_owned())
+            .filter(|g| !g.is_empty())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{code_challenge, profile_from_claims, OidcConfig, OidcEndpoints};
+
+    fn config() -> OidcConfig {
+        OidcConfig {
+            issuer: "https://idp.example.com".to_owned(),
+            client_id: "mega".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_url: "https://mega.example.com/api/v1/auth/oidc/callback".to_owned(),
+            scopes: "openid profile email".to_owned(),
+            endpoints: None,
+            username_claim: "preferred_username".to_owned(),
+            groups_claim: "groups".to_owned(),
+            admin_group: None,
+            link_by_name: false,
+            session_hours: 8,
+        }
+    }
+
+    #[test]
+    fn test_code_challenge() {
+        // RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let endpoints = OidcEndpoints {
+            authorization_endpoint: "https://idp.example.com/auth".to_owned(),
+            token_endpoint: String::new(),
+            userinfo_endpoint: String::new(),
+        };
+        let url = config().authorize_url(&endpoints, "abc", "verifier");
+        assert!(url.starts_with("https://idp.example.com/auth?response_type=code&client_id=mega"));
+        assert!(url.contains("&scope=openid+profile+email&state=abc&"));
+        assert!(url.contains("redirect_uri=https%3A%2F%2Fmega.example.com%2Fapi%2Fv1"));
+        assert!(url.ends_with("&code_challenge_method=S256"));
+    }
+
+    #[test]
+    fn test_profile_from_claims() {
+        let profile = profile_from_claims(
+            &json!({
+                "sub": "f1d2",
+                "preferred_username": "alice",
+                "name": "Alice",
+                "email": "alice@example.com",
+                "groups": ["/platform", "infra"]
+            }),
+            &config(),
+        )
+        .unwrap();
+        assert_eq!(profile.subject, "f1d2");
+        assert_eq!(profile.username, "alice");
+        assert_eq!(profile.groups, ["platform", "infra"]);
+
+        // Google: no user name claim, unverified emails are ignored
+        let profile = profile_from_claims(
+            &json!({"sub": "1", "email": "bob@example.com", "email_verified": true}),
+            &config(),
+        )
+        .unwrap();
+        assert_eq!(profile.username, "bob");
+        assert!(profile_from_claims(
+            &json!({"sub": "1", "email": "bob@example.com", "email_verified": false}),
+            &config()
+        )
+        .is_err());
+
+        // GitHub: numeric id and login
+        let mut github = config();
+        github.username_claim = "login".to_owned();
+        let profile = profile_from_claims(&json!({"id": 42, "login": "carol"}), &github).unwrap();
+        assert_eq!(
+            (profile.subject.as_str(), profile.username.as_str()),
+            ("42", "carol")
+        );
+        assert!(profile_from_claims(&json!({"login": "carol"}), &github).is_err());
+    }
+}
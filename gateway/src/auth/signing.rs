@@ -0,0 +1,362 @@
+//! Signatures made with GPG and SSH keys, on commits, on annotated tags and on the challenge a
+//! user signs to prove that a key is theirs.
+//!
+//! GPG signatures are checked with rpgp. SSH signatures use the `SSHSIG` format written by
+//! `ssh-keygen -Y sign`, only ed25519 keys are supported for signing. Tags created by the server
+//! are signed in the same format with its ed25519 host key.
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use pgp::types::KeyTrait;
+use pgp::{Deserializable, SignedPublicKey, StandaloneSignature};
+use sha2::{Digest, Sha256, Sha512};
+
+use common::errors::MegaError;
+
+use crate::auth::ssh_key;
+
+/// Namespace git uses for SSH signatures on commits and tags.
+pub const SSH_GIT_NAMESPACE: &str = "git";
+/// Namespace of the SSH signature over a key verification challenge.
+pub const SSH_CHALLENGE_NAMESPACE: &str = "mega";
+
+const GPG_SIGNATURE_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+const SSH_SIGNATURE_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+const SSH_SIGNATURE_END: &str = "-----END SSH SIGNATURE-----";
+const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+const SSHSIG_HASH: &str = "sha512";
+const ED25519: &str = "ssh-ed25519";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureKind {
+    Gpg,
+    Ssh,
+}
+
+impl SignatureKind {
+    pub fn of(armored: &str) -> Option<SignatureKind> {
+        let armored = armored.trim_start();
+        if armored.starts_with(GPG_SIGNATURE_BEGIN) {
+            Some(SignatureKind::Gpg)
+        } else if armored.starts_with(SSH_SIGNATURE_BEGIN) {
+            Some(SignatureKind::Ssh)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SignatureKind::Gpg => "gpg",
+            SignatureKind::Ssh => "ssh",
+        }
+    }
+}
+
+/// Split the data of an annotated tag into what its signature is made over and the armored
+/// signature git appends to its message, `None` when the tag is not signed.
+pub fn split_tag_signature(data: &[u8]) -> Option<(&[u8], &str)> {
+    let text = std::str::from_utf8(data).ok()?;
+    let start = [GPG_SIGNATURE_BEGIN, SSH_SIGNATURE_BEGIN]
+        .iter()
+        .filter_map(|begin| text.rfind(&format!("\n{}", begin)))
+        .max()?
+        + 1;
+    Some((&data[..start], &text[start..]))
+}
+
+fn invalid(what: &str) -> MegaError {
+    MegaError::with_message(&format!("invalid {}", what))
+}
+
+/// Identifiers of a GPG public key, key ids are 16 upper case hex digits.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GpgKeyInfo {
+    pub key_id: String,
+    pub subkey_ids: Vec<String>,
+    /// Addresses of the key's user ids, lower cased
+    pub emails: Vec<String>,
+}
+
+fn hex_id(id: &impl AsRef<[u8]>) -> String {
+    id.as_ref().iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn parse_gpg_key(armored: &str) -> Result<SignedPublicKey, MegaError> {
+    let (key, _) = SignedPublicKey::from_string(armored)
+        .map_err(|e| MegaError::with_message(&format!("invalid GPG public key: {}", e)))?;
+    key.verify()
+        .map_err(|e| MegaError::with_message(&format!("GPG key self signature: {}", e)))?;
+    Ok(key)
+}
+
+/// Parse an armored GPG public key and check its self signatures.
+pub fn gpg_key_info(armored: &str) -> Result<GpgKeyInfo, MegaError> {
+    let key = parse_gpg_key(armored)?;
+    let emails = key
+        .details
+        .users
+        .iter()
+        .filter_map(|user| {
+            let id = user.id.id().to_string();
+            let start = id.rfind('<')?;
+            let end = id[start..].find('>')? + start;
+            Some(id[start + 1..end].to_lowercase())
+        })
+        .collect();
+    Ok(GpgKeyInfo {
+        key_id: hex_id(&key.key_id()),
+        subkey_ids: key
+            .public_subkeys
+            .iter()
+            .map(|k| hex_id(&k.key_id()))
+            .collect(),
+        emails,
+    })
+}
+
+/// Id of the key that made an armored GPG signature, if the signature names it.
+pub fn gpg_issuer(signature: &str) -> Result<Option<String>, MegaError> {
+    let (signature, _) =
+        StandaloneSignature::from_string(signature).map_err(|_| invalid("GPG signature"))?;
+    Ok(signature.signature.issuer().into_iter().next().map(hex_id))
+}
+
+/// Check a GPG signature over `data` against the primary key and the subkeys of `public_key`.
+pub fn verify_gpg(public_key: &str, signature: &str, data: &[u8]) -> Result<bool, MegaError> {
+    let key = parse_gpg_key(public_key)?;
+    let (signature, _) =
+        StandaloneSignature::from_string(signature).map_err(|_| invalid("GPG signature"))?;
+    let issuer = signature.signature.issuer().into_iter().next().map(hex_id);
+    let matches = |id: String| issuer.as_ref().is_none_or(|issuer| *issuer == id);
+    if matches(hex_id(&key.key_id())) && signature.verify(&key, data).is_ok() {
+        return Ok(true);
+    }
+    Ok(key
+        .public_subkeys
+        .iter()
+        .filter(|subkey| matches(hex_id(&subkey.key_id())))
+        .any(|subkey| signature.verify(subkey, data).is_ok()))
+}
+
+/// Read an SSH wire format string: a big endian u32 length followed by the bytes.
+fn read_string<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_be_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+    let value = buf.get(4..4 + len)?;
+    *buf = &buf[4 + len..];
+    Some(value)
+}
+
+fn write_string(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend((value.len() as u32).to_be_bytes());
+    out.extend(value);
+}
+
+/// The fingerprint of an SSH public key blob, matching [`ssh_key::fingerprint`].
+pub fn ssh_fingerprint(key_blob: &[u8]) -> String {
+    format!(
+        "SHA256:{}",
+        STANDARD_NO_PAD.encode(Sha256::digest(key_blob))
+    )
+}
+
+/// Decode an OpenSSH public key line into its key blob, accepting only keys that can sign.
+pub fn ssh_signing_key(line: &str) -> Result<Vec<u8>, MegaError> {
+    let line = ssh_key::split_key_line(line)?;
+    if line.key_type != ED25519 {
+        return Err(MegaError::with_message(&format!(
+            "{} keys are not supported for signing, use {}",
+            line.key_type, ED25519
+        )));
+    }
+    let blob = STANDARD
+        .decode(line.base64)
+        .map_err(|_| invalid("SSH public key"))?;
+    ed25519_public_key(&blob)?;
+    Ok(blob)
+}
+
+fn ed25519_public_key(blob: &[u8]) -> Result<VerifyingKey, MegaError> {
+    let mut buf = blob;
+    let key_type = read_string(&mut buf).ok_or_else(|| invalid("SSH public key"))?;
+    if key_type != ED25519.as_bytes() {
+        return Err(MegaError::with_message(&format!(
+            "unsupported SSH signing key {}",
+            String::from_utf8_lossy(key_type)
+        )));
+    }
+    let bytes: [u8; 32] = read_string(&mut buf)
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| invalid("ed25519 public key"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| invalid("ed25519 public key"))
+}
+
+/// A decoded `SSHSIG` signature.
+#[derive(Debug)]
+pub struct SshSignature {
+    pub public_key: Vec<u8>,
+    pub namespace: String,
+    reserved: Vec<u8>,
+    hash_algorithm: String,
+    signature: Vec<u8>,
+}
+
+impl SshSignature {
+    /// Decode an armored signature as written by `ssh-keygen -Y sign`.
+    pub fn parse(armored: &str) -> Result<SshSignature, MegaError> {
+        let body: String = armored
+            .trim()
+            .strip_prefix(SSH_SIGNATURE_BEGIN)
+            .and_then(|s| s.strip_suffix(SSH_SIGNATURE_END))
+            .ok_or_else(|| invalid("SSH signature armor"))?
+            .split_whitespace()
+            .collect();
+        let blob = STANDARD
+            .decode(body)
+            .map_err(|_| invalid("SSH signature"))?;
+        let mut buf = blob
+            .strip_prefix(SSHSIG_MAGIC)
+            .ok_or_else(|| invalid("SSH signature"))?;
+        let version = buf.get(..4).ok_or_else(|| invalid("SSH signature"))?;
+        if version != 1u32.to_be_bytes() {
+            return Err(invalid("SSH signature version"));
+        }
+        buf = &buf[4..];
+        let mut next = || read_string(&mut buf).ok_or_else(|| invalid("SSH signature"));
+        Ok(SshSignature {
+            public_key: next()?.to_vec(),
+            namespace: String::from_utf8_lossy(next()?).into_owned(),
+            reserved: next()?.to_vec(),
+            hash_algorithm: String::from_utf8_lossy(next()?).into_owned(),
+            signature: next()?.to_vec(),
+        })
+    }
+
+    /// Check the signature over `data`, made in `namespace` by the key embedded in it.
+    pub fn verify(&self, namespace: &str, data: &[u8]) -> Result<bool, MegaError> {
+        if self.namespace != namespace {
+            return Ok(false);
+        }
+        let digest = match self.hash_algorithm.as_str() {
+            "sha256" => Sha256::digest(data).to_vec(),
+            "sha512" => Sha512::digest(data).to_vec(),
+            other => {
+                return Err(MegaError::with_message(&format!(
+                    "unsupported SSH signature hash {}",
+                    other
+                )))
+            }
+        };
+        let mut signed = SSHSIG_MAGIC.to_vec();
+        write_string(&mut signed, self.namespace.as_bytes());
+        write_string(&mut signed, &self.reserved);
+        write_string(&mut signed, self.hash_algorithm.as_bytes());
+        write_string(&mut signed, &digest);
+
+        let key = ed25519_public_key(&self.public_key)?;
+        let mut buf = self.signature.as_slice();
+        let (Some(signature_type), Some(signature)) =
+            (read_string(&mut buf), read_string(&mut buf))
+        else {
+            return Err(invalid("SSH signature"));
+        };
+        if signature_type != ED25519.as_bytes() {
+            return Ok(false);
+        }
+        let signature =
+            Signature::from_slice(signature).map_err(|_| invalid("ed25519 signature"))?;
+        Ok(key.verify(&signed, &signature).is_ok())
+    }
+}
+
+/// The public key blob of an ed25519 key, as embedded in signatures and key lines.
+fn ed25519_key_blob(key: &VerifyingKey) -> Vec<u8> {
+    let mut blob = Vec::new();
+    write_string(&mut blob, ED25519.as_bytes());
+    write_string(&mut blob, key.as_bytes());
+    blob
+}
+
+/// The OpenSSH public key line of an ed25519 key.
+pub fn ssh_public_key_line(key: &VerifyingKey) -> String {
+    format!("{} {}", ED25519, STANDARD.encode(ed25519_key_blob(key)))
+}
+
+/// Sign `data` in `namespace` the way `ssh-keygen -Y sign` does, returning the armored signature.
+pub fn ssh_sign(key: &SigningKey, namespace: &str, data: &[u8]) -> String {
+    let mut signed = SSHSIG_MAGIC.to_vec();
+    write_string(&mut signed, namespace.as_bytes());
+    write_string(&mut signed, b"");
+    write_string(&mut signed, SSHSIG_HASH.as_bytes());
+    write_string(&mut signed, &Sha512::digest(data));
+    let mut signature = Vec::new();
+    write_string(&mut signature, ED25519.as_bytes());
+    write_string(&mut signature, &key.sign(&signed).to_bytes());
+
+    let mut blob = SSHSIG_MAGIC.to_vec();
+    blob.extend(1u32.to_be_bytes());
+    write_string(&mut blob, &ed25519_key_blob(&key.verifying_key()));
+    write_string(&mut blob, namespace.as_bytes());
+    write_string(&mut blob, b"");
+    write_string(&mut blob, SSHSIG_HASH.as_bytes());
+    write_string(&mut blob, &signature);
+    let encoded = STANDARD.encode(blob);
+    let mut armored = format!("{}\n", SSH_SIGNATURE_BEGIN);
+    for chunk in encoded.as_bytes().chunks(70) {
+        armored.push_str(&String::from_utf8_lossy(chunk));
+        armored.push('\n');
+    }
+    armored.push_str(SSH_SIGNATURE_END);
+    armored.push('\n');
+    armored
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+
+    use super::{
+        split_tag_signature, ssh_fingerprint, ssh_public_key_line, ssh_sign, ssh_signing_key,
+        SignatureKind, SshSignature,
+    };
+
+    #[test]
+    fn test_ssh_signature() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let armored = ssh_sign(&key, "git", b"tree abc\n");
+        assert_eq!(SignatureKind::of(&armored), Some(SignatureKind::Ssh));
+
+        let line = ssh_public_key_line(&key.verifying_key());
+        let public_key = ssh_signing_key(&line).unwrap();
+        let signature = SshSignature::parse(&armored).unwrap();
+        assert_eq!(signature.public_key, public_key);
+        assert!(signature.verify("git", b"tree abc\n").unwrap());
+        assert!(!signature.verify("git", b"tree abd\n").unwrap());
+        assert!(!signature.verify("mega", b"tree abc\n").unwrap());
+
+        assert_eq!(
+            ssh_signing_key(&format!("{} alice@laptop", line)).unwrap(),
+            public_key
+        );
+        assert!(ssh_fingerprint(&public_key).starts_with("SHA256:"));
+        assert!(ssh_signing_key("ssh-rsa AAAAB3NzaC1yc2E=").is_err());
+    }
+
+    #[test]
+    fn test_split_tag_signature() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let payload = "object 4b00093bee9b3ef5afc5f8e3645dc39cfa2f49aa\ntype commit\ntag v1.0\n\
+            tagger mega <mega@example.com> 1700000000 +0800\n\nrelease 1.0\n";
+        let armored = ssh_sign(&key, "git", payload.as_bytes());
+        let data = format!("{}{}", payload, armored);
+
+        let (signed, signature) = split_tag_signature(data.as_bytes()).unwrap();
+        assert_eq!(signed, payload.as_bytes());
+        assert_eq!(signature, armored);
+        assert!(SshSignature::parse(signature)
+            .unwrap()
+            .verify("git", signed)
+            .unwrap());
+        assert!(split_tag_signature(payload.as_bytes()).is_none());
+    }
+}
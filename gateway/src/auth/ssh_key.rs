@@ -0,0 +1,140 @@
+use chrono::NaiveDateTime;
+use russh_keys::key::PublicKey;
+
+use common::errors::MegaError;
+use db_entity::mega_ssh_key;
+use jupiter::storage::ssh_key_storage::SshKeyStorage;
+
+use crate::auth::{AuthProvider, Identity};
+
+/// Key algorithms accepted for registration.
+pub const SUPPORTED_KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+];
+
+/// A public key in OpenSSH format, split into its parts.
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeyLine<'a> {
+    pub key_type: &'a str,
+    pub base64: &'a str,
+    pub comment: Option<&'a str>,
+}
+
+/// Split `<type> <base64> [comment]`, rejecting unsupported key types.
+pub fn split_key_line(line: &str) -> Result<KeyLine<'_>, MegaError> {
+    let mut parts = line.trim().splitn(3, char::is_whitespace);
+    let (Some(key_type), Some(base64)) = (parts.next(), parts.next()) else {
+        return Err(MegaError::with_message(
+            "public key must look like '<type> <base64> [comment]'",
+        ));
+    };
+    if !SUPPORTED_KEY_TYPES.contains(&key_type) {
+        return Err(MegaError::with_message(&format!(
+            "unsupported key type {}, expected one of {}",
+            key_type,
+            SUPPORTED_KEY_TYPES.join(", ")
+        )));
+    }
+    let comment = parts.next().map(str::trim).filter(|c| !c.is_empty());
+    Ok(KeyLine {
+        key_type,
+        base64: base64.trim(),
+        comment,
+    })
+}
+
+/// Decode the key of an OpenSSH public key line.
+pub fn parse_key_line(line: &KeyLine) -> Result<PublicKey, MegaError> {
+    russh_keys::parse_public_key_base64(line.base64)
+        .map_err(|e| MegaError::with_message(&format!("invalid {} key: {}", line.key_type, e)))
+}
+
+/// The SHA256 fingerprint of a key, as printed by `ssh-keygen -l`.
+pub fn fingerprint(key: &PublicKey) -> String {
+    format!("SHA256:{}", key.fingerprint())
+}
+
+pub fn is_expired(key: &mega_ssh_key::Model, now: NaiveDateTime) -> bool {
+    key.expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
+/// Authenticate `username` by an SSH key.
+///
+/// Keys registered through the API are checked first, and a match records when the key was
+/// last used. Keys not registered here are left to the provider, so directory servers can still
+/// publish keys of their own.
+pub async fn verify_key(
+    keys: &SshKeyStorage,
+    provider: &dyn AuthProvider,
+    username: &str,
+    key: &PublicKey,
+) -> Result<Option<Identity>, MegaError> {
+    let Some(registered) = keys.find_by_fingerprint(&fingerprint(key)).await? else {
+        return provider.verify_public_key(username, key).await;
+    };
+    if registered.username != username {
+        return Ok(None);
+    }
+    if is_expired(&registered, chrono::Utc::now().naive_utc()) {
+        tracing::info!(
+            "ssh key {} of {} has expired",
+            registered.fingerprint,
+            username
+        );
+        return Ok(None);
+    }
+    let identity = provider.resolve_identity(username).await?;
+    if identity.is_some() {
+        keys.touch_last_used(registered.id).await?;
+    }
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use db_entity::mega_ssh_key;
+
+    use super::{is_expired, split_key_line, KeyLine};
+
+    #[test]
+    fn test_split_key_line() {
+        assert_eq!(
+            split_key_line("ssh-ed25519 AAAAC3Nza alice@laptop\n").unwrap(),
+            KeyLine {
+                key_type: "ssh-ed25519",
+                base64: "AAAAC3Nza",
+                comment: Some("alice@laptop"),
+            }
+        );
+        assert_eq!(split_key_line("ssh-rsa AAAAB3Nza").unwrap().comment, None);
+        assert!(split_key_line("ssh-dss AAAAB3Nza").is_err());
+        assert!(split_key_line("AAAAC3Nza").is_err());
+    }
+
+    #[test]
+    fn test_key_expiry() {
+        let now = Utc::now().naive_utc();
+        let mut key = mega_ssh_key::Model {
+            id: 1,
+            username: "alice".to_owned(),
+            title: "laptop".to_owned(),
+            key_type: "ssh-ed25519".to_owned(),
+            public_key: "ssh-ed25519 AAAAC3Nza".to_owned(),
+            fingerprint: "SHA256:abc".to_owned(),
+            expires_at: None,
+            last_used_at: None,
+            created_at: now,
+        };
+        assert!(!is_expired(&key, now));
+        key.expires_at = Some(now + Duration::days(1));
+        assert!(!is_expired(&key, now));
+        key.expires_at = Some(now - Duration::days(1));
+        assert!(is_expired(&key, now));
+    }
+}
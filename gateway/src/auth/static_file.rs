@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use common::errors::MegaError;
+
+use crate::auth::{verify_password, AuthProvider, Identity};
+
+/// Accounts listed in a TOML file, for small installations and tests.
+///
+/// ```toml
+/// [[users]]
+/// name = "alice"
+/// email = "alice@example.com"
+/// password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+/// is_admin = true
+/// public_keys = ["ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAA... alice@laptop"]
+/// ```
+///
+/// The file is read once at startup.
+pub struct StaticFileAuthProvider {
+    users: Vec<StaticUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StaticConfig {
+    #[serde(default)]
+    users: Vec<StaticUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StaticUser {
+    name: String,
+    display_name: Option<String>,
+    email: Option<String>,
+    password_hash: Option<String>,
+    #[serde(default)]
+    is_admin: bool,
+    #[serde(default)]
+    public_keys: Vec<String>,
+}
+
+impl StaticUser {
+    fn identity(&self) -> Identity {
+        Identity {
+            username: self.name.clone(),
+            display_name: self.display_name.clone(),
+            email: self.email.clone(),
+            is_admin: self.is_admin,
+        }
+    }
+}
+
+impl StaticFileAuthProvider {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MegaError> {
+        let content = fs::read_to_string(path.as_ref())?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, MegaError> {
+        let config: StaticConfig =
+            toml::from_str(content).map_err(|e| MegaError::with_message(&e.to_string()))?;
+        Ok(StaticFileAuthProvider {
+            users: config.users,
+        })
+    }
+
+    fn user(&self, username: &str) -> Option<&StaticUser> {
+        self.users.iter().find(|u| u.name == username)
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticFileAuthProvider {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    async fn verify_credentials(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, MegaError> {
+        Ok(self
+            .user(username)
+            .filter(|u| {
+                u.password_hash
+                    .as_deref()
+                    .is_some_and(|hash| verify_password(hash, password))
+            })
+            .map(StaticUser::identity))
+    }
+
+    async fn resolve_identity(&self, username: &str) -> Result<Option<Identity>, MegaError> {
+        Ok(self.user(username).map(StaticUser::identity))
+    }
+
+    async fn list_public_keys(&self, username: &str) -> Result<Vec<String>, MegaError> {
+        Ok(self
+            .user(username)
+            .map(|u| u.public_keys.clone())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StaticFileAuthProvider;
+    use crate::auth::hash_password;
+
+    #[test]
+    fn test_static_users() {
+        let hash = hash_password("secret").unwrap();
+        let config = format!(
+            r#"
+            [[users]]
+            name = "alice"
+            email = "alice@example.com"
+            password_hash = "{}"
+            is_admin = true
+            public_keys = ["ssh-ed25519 AAAAkey alice@laptop"]
+
+            [[users]]
+            name = "bob"
+            "#,
+            hash
+        );
+        let provider = StaticFileAuthProvider::from_toml(&config).unwrap();
+
+        let alice = provider.user("alice").unwrap();
+        assert!(alice.identity().is_admin);
+        assert_eq!(alice.public_keys.len(), 1);
+        let bob = provider.user("bob").unwrap();
+        assert!(bob.password_hash.is_none());
+        assert!(!bob.identity().is_admin);
+        assert!(provider.user("carol").is_none());
+
+        assert!(StaticFileAuthProvider::from_toml("[[users]]\nemail = \"x\"").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::str::FromStr;
+
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// Prefix of the tokens mega issues, so that secret scanners can recognize them.
+pub const TOKEN_PREFIX: &str = "mega_";
+const TOKEN_LEN: usize = 40;
+
+/// What a personal access token may do, each scope granting the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TokenScope {
+    /// Fetch and clone
+    Read,
+    /// Push
+    Write,
+    /// Everything the account can do
+    Admin,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+            TokenScope::Admin => "admin",
+        }
+    }
+
+    /// Whether a token with `scopes` may do what needs `self`.
+    pub fn granted_by(&self, scopes: &[String]) -> bool {
+        scopes
+            .iter()
+            .filter_map(|s| s.parse::<TokenScope>().ok())
+            .any(|s| s >= *self)
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "write" => Ok(TokenScope::Write),
+            "admin" => Ok(TokenScope::Admin),
+            _ => Err(format!(
+                "unknown scope {}, expected read, write or admin",
+                s
+            )),
+        }
+    }
+}
+
+/// A new random access token, shown to its owner once and only stored as its [`token_hash`].
+pub fn generate_token() -> String {
+    format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        Alphanumeric.sample_string(&mut rand::thread_rng(), TOKEN_LEN)
+    )
+}
+
+/// Hex SHA-256 of a token, the form tokens are stored and looked up in.
+pub fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Whether `token` hashes to `stored_hash`, comparing in constant time.
+pub fn verify_token(stored_hash: &str, token: &str) -> bool {
+    token_hash(token)
+        .as_bytes()
+        .ct_eq(stored_hash.as_bytes())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token, token_hash, verify_token, TokenScope, TOKEN_PREFIX};
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 40);
+        assert_ne!(token, generate_token());
+        assert_eq!(token_hash(&token).len(), 64);
+        assert!(verify_token(&token_hash(&token), &token));
+        assert!(!verify_token(&token_hash(&token), &generate_token()));
+        assert!(!verify_token("", &token));
+    }
+
+    #[test]
+    fn test_token_scope() {
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(TokenScope::Read.granted_by(&scopes(&["read"])));
+        assert!(!TokenScope::Write.granted_by(&scopes(&["read"])));
+        assert!(TokenScope::Write.granted_by(&scopes(&["read", "write"])));
+        assert!(TokenScope::Write.granted_by(&scopes(&["admin"])));
+        assert!(!TokenScope::Read.granted_by(&scopes(&["unknown"])));
+        assert!("push".parse::<TokenScope>().is_err());
+    }
+}
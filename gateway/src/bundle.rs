@@ -0,0 +1,75 @@
+//!
+//! Bundle export behind the `mega bundle create` command.
+//!
+//! Writes the refs of a monorepo repository and every object they reach to a git bundle, which
+//! `git clone` and `git fetch` read like a remote, and which the gateway takes back through
+//! `POST /api/v1/admin/bundles` to seed a repository on a server no network reaches.
+//!
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Args;
+
+use common::errors::MegaError;
+use common::model::CommonOptions;
+use jupiter::storage::archive_storage::ArchiveStorage;
+use jupiter::storage::event_storage::EventStorage;
+use storage::driver::database;
+
+use crate::api_service::archive::PathArchives;
+use crate::api_service::bundle_service::{self, BundleService};
+use crate::api_service::event_service::EventService;
+
+#[derive(Args, Clone, Debug)]
+pub struct BundleCreateOptions {
+    #[clap(flatten)]
+    pub common: CommonOptions,
+
+    /// Monorepo path of the repository, e.g. /third-party/mega
+    pub path: String,
+
+    /// Branches, tags or full ref names to bundle, every ref of the repository when none
+    pub refs: Vec<String>,
+
+    /// File the bundle is written to
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Version of the bundle format, 2 or 3
+    #[arg(long, default_value_t = 2)]
+    pub version: u8,
+}
+
+/// What a bundle was written with.
+pub struct CreatedBundle {
+    pub refs: Vec<String>,
+    pub bytes: u64,
+}
+
+pub async fn run_create(options: &BundleCreateOptions) -> Result<CreatedBundle, MegaError> {
+    let data_source = &options.common.data_source;
+    let connection = Arc::new(database::connect(data_source).await);
+    let service = BundleService {
+        storage: database::init(data_source).await,
+        archives: PathArchives {
+            archive_storage: ArchiveStorage::new(connection.clone()),
+        },
+        events: EventService {
+            storage: EventStorage::new(connection),
+        },
+    };
+    let (header, pack) = service
+        .create(&options.path, &options.refs, options.version)
+        .await
+        .map_err(|(status, msg)| MegaError::new(anyhow::anyhow!(msg), status.as_u16() as i32))?;
+    bundle_service::write_bundle(&options.output, &header, pack)
+        .await
+        .map_err(|e| MegaError::new(e.into(), 1))?;
+    let bytes = std::fs::metadata(&options.output)
+        .map(|m| m.len())
+        .unwrap_or_default();
+    Ok(CreatedBundle {
+        refs: header.refs.into_iter().map(|r| r.name).collect(),
+        bytes,
+    })
+}
@@ -4,14 +4,15 @@
 //!
 //!
 use std::collections::HashMap;
+use std::io::{self, Read};
 
 use anyhow::Result;
 use axum::body::Body;
 use axum::http::response::Builder;
 use axum::http::{Request, Response, StatusCode};
 use bytes::{Bytes, BytesMut};
-use futures::TryStreamExt;
-use tokio::io::AsyncReadExt;
+use futures::{StreamExt, TryStreamExt};
+use tokio_util::io::StreamReader;
 
 use git::protocol::{pack, PackProtocol, ServiceType};
 
@@ -74,29 +75,44 @@ pub async fn git_upload_pack(
         .await
         .unwrap();
     tracing::info!("send ack/nak message buf: {:?}", buf);
-    let mut res_bytes = BytesMut::new();
-    res_bytes.extend(buf);
-
     let resp = build_res_header("application/x-git-upload-pack-result".to_owned());
+    // a round of negotiation is answered with its acks alone, the client sending the next one
+    let Some(send_pack_data) = send_pack_data else {
+        return Ok(resp.body(Body::from(buf.freeze())).unwrap());
+    };
+    let reader = send_pack_data
+        .into_reader()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     tracing::info!("send response");
 
-    let mut reader = send_pack_data.as_slice();
-    loop {
-        let mut temp = BytesMut::new();
-        temp.reserve(65500);
-        let length = reader.read_buf(&mut temp).await.unwrap();
-        if length == 0 {
-            let bytes_out = Bytes::from_static(pack::PKT_LINE_END_MARKER);
-            tracing::info!("send back pkt-flush line '0000', actually: {:?}", bytes_out);
-            res_bytes.extend(bytes_out);
-            break;
-        }
-        let bytes_out = pack_protocol.build_side_band_format(temp, length);
-        tracing::info!("send pack file: length: {:?}", bytes_out.len());
-        res_bytes.extend(bytes_out);
-    }
-    let body = Body::from(res_bytes.freeze());
+    // the pack is read as it is sent, from memory or from the file it spilled to
+    let ack = futures::stream::once(async move { Ok(buf.freeze()) });
+    let pack_lines = futures::stream::unfold(
+        (Some(reader), pack_protocol),
+        |(reader, pack_protocol)| async move {
+            let mut reader = reader?;
+            if let Err(cancelled) = pack_protocol.check_sending() {
+                return Some((Err(cancelled.into()), (None, pack_protocol)));
+            }
+            let mut temp = vec![0; 65500];
+            match reader.read(&mut temp) {
+                Ok(0) => {
+                    let bytes_out = Bytes::from_static(pack::PKT_LINE_END_MARKER);
+                    tracing::info!("send back pkt-flush line '0000', actually: {:?}", bytes_out);
+                    Some((Ok(bytes_out), (None, pack_protocol)))
+                }
+                Ok(length) => {
+                    let bytes_out = pack_protocol
+                        .build_side_band_format(BytesMut::from(&temp[..length]), length);
+                    tracing::info!("send pack file: length: {:?}", bytes_out.len());
+                    Some((Ok(bytes_out.freeze()), (Some(reader), pack_protocol)))
+                }
+                Err(e) => Some((Err(e), (None, pack_protocol))),
+            }
+        },
+    );
+    let body = Body::from_stream(ack.chain(pack_lines));
     let resp = resp.body(body).unwrap();
     Ok(resp)
 }
@@ -106,13 +122,9 @@ pub async fn git_upload_pack(
 /// The function takes a `req` parameter representing the HTTP request received and a `pack_protocol`
 /// parameter containing the configuration for the Git pack protocol.
 ///
-/// The function extracts the request body into a vector of bytes, `combined_body_bytes`, by iterating over the
-/// chunks of the request body using `body.next().await`. The chunks are appended to the `combined_body_bytes`.
-///
-/// The `pack_protocol` is then used to process the `combined_body_bytes` using the `git_receive_pack` method.
-/// It returns the `pack_data` containing the response data.
-///
-/// The `pack_data` is passed to the `git_receive_pack` method again to obtain the final response data as a `buf`.
+/// The request body is read by the `git_receive_pack_stream` method of the `pack_protocol` as it
+/// arrives, so that a large push isn't held in memory before it is unpacked. It returns the
+/// report of the push as a `buf`.
 ///
 /// The `buf` is converted into a `Body` using `Body::from()` and assigned to `body`.
 /// Tracing information is logged regarding the status of the response body.
@@ -123,23 +135,18 @@ pub async fn git_upload_pack(
 /// Finally, the constructed response is returned.
 pub async fn git_receive_pack(
     req: Request<Body>,
-    mut pack_protocol: PackProtocol,
+    pack_protocol: &mut PackProtocol,
 ) -> Result<Response<Body>, (StatusCode, String)> {
-    let combined_body_bytes: BytesMut = req
-        .into_body()
-        .into_data_stream()
-        .try_fold(BytesMut::new(), |mut acc, chunk| async move {
-            acc.extend_from_slice(&chunk);
-            Ok(acc)
-        })
-        .await
-        .unwrap();
-
-
+    pack_protocol.push_profile.receiving();
+    let body = StreamReader::new(
+        req.into_body()
+            .into_data_stream()
+            .map_err(io::Error::other),
+    );
     let parse_report = pack_protocol
-        .git_receive_pack(combined_body_bytes.freeze())
+        .git_receive_pack_stream(body)
         .await
-        .unwrap();
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     tracing::info!("report status:{:?}", parse_report);
     let resp = build_res_header("application/x-git-receive-pack-result".to_owned());
     let resp = resp.body(Body::from(parse_report)).unwrap();
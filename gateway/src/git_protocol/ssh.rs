@@ -4,6 +4,7 @@
 //!
 //!
 use std::collections::HashMap;
+use std::io::Read;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -14,14 +15,26 @@ use chrono::{DateTime, Duration, Utc};
 use russh::server::{self, Auth, Msg, Response, Session};
 use russh::{Channel, ChannelId};
 use russh_keys::key;
-use tokio::io::AsyncReadExt;
+use tracing::Instrument;
 
+use common::errors::MegaError;
+use common::metrics::{metrics, ConnectionGuard};
 use git::lfs::lfs_structs::Link;
 use git::protocol::pack::{self};
 use git::protocol::ServiceType;
 use git::protocol::{PackProtocol, Protocol};
+use jupiter::storage::ssh_key_storage::SshKeyStorage;
 use storage::driver::database::storage::ObjectStorage;
 
+use crate::api_service::archive::{self, PathArchives};
+use crate::api_service::event_service::EventService;
+use crate::api_service::path_move::PathRedirects;
+use crate::api_service::push_profile_service::PushProfileService;
+use crate::api_service::signing_key_service::SigningKeyService;
+use crate::auth::acl::{Acl, Permission};
+use crate::auth::{ssh_key, AuthProvider, Identity};
+use crate::request_id::RequestId;
+
 type ClientMap = HashMap<(usize, ChannelId), Channel<Msg>>;
 
 #[derive(Clone)]
@@ -30,15 +43,36 @@ pub struct SshServer {
     pub clients: Arc<Mutex<ClientMap>>,
     pub id: usize,
     pub storage: Arc<dyn ObjectStorage>,
+    /// Checks client credentials, every client is accepted when this is `None`.
+    pub auth: Option<Arc<dyn AuthProvider>>,
+    pub ssh_keys: SshKeyStorage,
+    pub redirects: PathRedirects,
+    /// Archived and deleted paths, which refuse pushes.
+    pub archives: PathArchives,
+    pub events: EventService,
+    /// Verifies the commits pushed to signed branches.
+    pub signing_keys: SigningKeyService,
+    pub push_profiles: PushProfileService,
+    /// Decides which repositories the client may fetch from and push to.
+    pub acl: Acl,
+    /// The user the client authenticated as.
+    pub username: Option<String>,
+    /// The account of the user, `None` when no provider checked the credentials.
+    pub identity: Option<Identity>,
     // TODO: consider is it a good choice to bind data here, find a better solution to bind data with ssh client
     pub pack_protocol: Option<PackProtocol>,
     pub data_combined: Vec<u8>,
+    /// Counts the session in the active connections while its handler lives.
+    pub connection: Option<Arc<ConnectionGuard>>,
+    /// The span of the git command being served, carrying its request id.
+    pub span: tracing::Span,
 }
 
 impl server::Server for SshServer {
     type Handler = Self;
     fn new_client(&mut self, _: Option<std::net::SocketAddr>) -> Self {
-        let s = self.clone();
+        let mut s = self.clone();
+        s.connection = Some(Arc::new(metrics().connection("ssh")));
         self.id += 1;
         s
     }
@@ -86,13 +120,77 @@ impl server::Handler for SshServer {
         // LFS HTTP Authenticate: git-lfs-authenticate '/path/to/repo.git' download/upload
         let command: Vec<_> = data.split(' ').collect();
         let path = command[1];
-        let path = path.replace(".git", "").replace('\'', "");
+        let mut path = path.replace(".git", "").replace('\'', "");
+        // ssh has no redirects, serve the new path and tell the user on stderr
+        let moved_to = self.redirects.moved_to(&path).await.unwrap_or_else(|e| {
+            tracing::warn!("failed to look up the redirect of {}: {}", path, e);
+            None
+        });
+        if let Some(moved_to) = moved_to {
+            let hint = format!(
+                "warning: {} has moved to {}, please update your remote\n",
+                path, moved_to
+            );
+            session.extended_data(channel, 1, hint.into_bytes().into());
+            path = moved_to;
+        }
+        let request_id = RequestId::generate();
+        self.span = request_id.span(command[0], &path);
+        let needed = match command[0] {
+            "git-upload-pack" => Some(Permission::Read),
+            "git-receive-pack" => Some(Permission::Write),
+            _ => None,
+        };
+        if let Some(needed) = needed {
+            let allowed = self
+                .acl
+                .allows(self.identity.as_ref(), &path, needed)
+                .instrument(self.span.clone())
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("failed to check the permissions on {}: {}", path, e);
+                    false
+                });
+            if !allowed {
+                let message = format!(
+                    "fatal: {} permission on {} required\nrequest id: {}\n",
+                    needed, path, request_id
+                );
+                session.extended_data(channel, 1, message.into_bytes().into());
+                session.exit_status_request(channel, 1);
+                session.close(channel);
+                return Ok((self, session));
+            }
+        }
+        if command[0] == "git-receive-pack" {
+            let archived = self.archives.find(&path).await.unwrap_or_else(|e| {
+                tracing::error!("failed to look up the archive of {}: {}", path, e);
+                None
+            });
+            if let Some(archived) = archived {
+                let message = format!(
+                    "fatal: {}
+request id: {}
+",
+                    archive::refusal(&archived),
+                    request_id
+                );
+                session.extended_data(channel, 1, message.into_bytes().into());
+                session.exit_status_request(channel, 1);
+                session.close(channel);
+                return Ok((self, session));
+            }
+        }
         let mut pack_protocol =
             PackProtocol::new(PathBuf::from(&path), self.storage.clone(), Protocol::Ssh);
+        pack_protocol.verifier = Some(Arc::new(self.signing_keys.clone()));
         match command[0] {
             "git-upload-pack" | "git-receive-pack" => {
                 pack_protocol.service_type = ServiceType::from_str(command[0]).unwrap();
-                let res = pack_protocol.git_info_refs().await;
+                let res = pack_protocol
+                    .git_info_refs()
+                    .instrument(self.span.clone())
+                    .await;
                 self.pack_protocol = Some(pack_protocol);
                 session.data(channel, res.to_vec().into());
                 session.channel_success(channel);
@@ -124,12 +222,25 @@ impl server::Handler for SshServer {
     }
 
     async fn auth_publickey(
-        self,
+        mut self,
         user: &str,
         public_key: &key::PublicKey,
     ) -> Result<(Self, Auth), Self::Error> {
         tracing::info!("auth_publickey: {} / {:?}", user, public_key);
-        Ok((self, Auth::Accept))
+        let Some(provider) = self.auth.clone() else {
+            self.username = Some(user.to_owned());
+            return Ok((self, Auth::Accept));
+        };
+        let identity =
+            ssh_key::verify_key(&self.ssh_keys, provider.as_ref(), user, public_key).await;
+        if let Ok(Some(identity)) = &identity {
+            self.identity = Some(identity.clone());
+        }
+        let auth = auth_result(provider.name(), user, identity);
+        if matches!(auth, Auth::Accept) {
+            self.username = Some(user.to_owned());
+        }
+        Ok((self, auth))
     }
 
     async fn auth_keyboard_interactive(
@@ -139,13 +250,36 @@ impl server::Handler for SshServer {
         _: Option<Response<'async_trait>>,
     ) -> Result<(Self, Auth), Self::Error> {
         tracing::info!("auth_keyboard_interactive");
+        if self.auth.is_some() {
+            return Ok((
+                self,
+                Auth::Reject {
+                    proceed_with_methods: None,
+                },
+            ));
+        }
         Ok((self, Auth::Accept))
     }
 
-    async fn auth_password(self, user: &str, password: &str) -> Result<(Self, Auth), Self::Error> {
-        tracing::info!("auth_password: {} / {}", user, password);
-        // in this example implementation, any username/password combination is accepted
-        Ok((self, Auth::Accept))
+    async fn auth_password(
+        mut self,
+        user: &str,
+        password: &str,
+    ) -> Result<(Self, Auth), Self::Error> {
+        tracing::info!("auth_password: {}", user);
+        let Some(provider) = self.auth.clone() else {
+            self.username = Some(user.to_owned());
+            return Ok((self, Auth::Accept));
+        };
+        let identity = provider.verify_credentials(user, password).await;
+        if let Ok(Some(identity)) = &identity {
+            self.identity = Some(identity.clone());
+        }
+        let auth = auth_result(provider.name(), user, identity);
+        if matches!(auth, Auth::Accept) {
+            self.username = Some(user.to_owned());
+        }
+        Ok((self, auth))
     }
 
     async fn data(
@@ -163,9 +297,13 @@ impl server::Handler for SshServer {
 
         match pack_protocol.service_type {
             ServiceType::UploadPack => {
-                self.handle_upload_pack(channel, data, &mut session).await;
+                let span = self.span.clone();
+                self.handle_upload_pack(channel, data, &mut session)
+                    .instrument(span)
+                    .await;
             }
             ServiceType::ReceivePack => {
+                pack_protocol.push_profile.receiving();
                 self.data_combined.extend(data);
             }
         };
@@ -180,7 +318,10 @@ impl server::Handler for SshServer {
     ) -> Result<(Self, Session), Self::Error> {
         if let Some(pack_protocol) = self.pack_protocol.as_mut() {
             if pack_protocol.service_type == ServiceType::ReceivePack {
-                self.handle_receive_pack(channel, &mut session).await;
+                let span = self.span.clone();
+                self.handle_receive_pack(channel, &mut session)
+                    .instrument(span)
+                    .await;
             };
         }
 
@@ -206,16 +347,26 @@ impl SshServer {
         tracing::info!("buf is {:?}", buf);
         session.data(channel, String::from_utf8(buf.to_vec()).unwrap().into());
 
-        let mut reader = send_pack_data.as_slice();
+        // the client goes on negotiating in the session until there is a pack to send
+        let Some(send_pack_data) = send_pack_data else {
+            return;
+        };
+        let mut reader = send_pack_data.into_reader().unwrap();
+        let mut temp = vec![0; 65500];
         loop {
-            let mut temp = BytesMut::new();
-            temp.reserve(65500);
-            let length = reader.read_buf(&mut temp).await.unwrap();
-            if temp.is_empty() {
+            // the client gets a truncated pack, which it refuses
+            if pack_protocol.check_sending().is_err() {
+                session.close(channel);
+                return;
+            }
+            let length = reader.read(&mut temp).unwrap();
+            if length == 0 {
                 session.data(channel, pack::PKT_LINE_END_MARKER.to_vec().into());
+                pack_protocol.pack_sent();
                 return;
             }
-            let bytes_out = pack_protocol.build_side_band_format(temp, length);
+            let bytes_out =
+                pack_protocol.build_side_band_format(BytesMut::from(&temp[..length]), length);
             session.data(channel, bytes_out.to_vec().into());
         }
     }
@@ -229,5 +380,44 @@ impl SshServer {
             .unwrap();
         tracing::info!("report status: {:?}", buf);
         session.data(channel, buf.to_vec().into());
+        let repo_path = pack_protocol.path.to_string_lossy().into_owned();
+        self.events
+            .publish_push(
+                &repo_path,
+                &pack_protocol.command_list,
+                self.username.as_deref(),
+            )
+            .await;
+        self.push_profiles
+            .record(
+                &repo_path,
+                self.username.as_deref(),
+                "ssh",
+                &pack_protocol.push_profile,
+            )
+            .await;
+    }
+}
+
+fn auth_result(provider: &str, user: &str, identity: Result<Option<Identity>, MegaError>) -> Auth {
+    match identity {
+        Ok(Some(_)) => Auth::Accept,
+        Ok(None) => {
+            tracing::info!("{} provider rejected ssh login of {}", provider, user);
+            Auth::Reject {
+                proceed_with_methods: None,
+            }
+        }
+        Err(err) => {
+            tracing::error!(
+                "{} provider failed to authenticate {}: {}",
+                provider,
+                user,
+                err
+            );
+            Auth::Reject {
+                proceed_with_methods: None,
+            }
+        }
     }
 }
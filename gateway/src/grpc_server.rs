@@ -0,0 +1,464 @@
+//!
+//! The gRPC API for the services running beside mega, see `proto/storage.proto`.
+//!
+//! CI runners and indexers read objects and refs, and push packs, without the pkt-lines of the
+//! git protocol or the JSON of the REST API. Calls are authorized like git over HTTP is, and a
+//! pack goes through the same receive-pack as `git push`: the archive and signed branch checks,
+//! the pre-receive hooks and the push events all apply.
+//!
+use std::io::{self, Cursor};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use bytes::{BufMut, Bytes, BytesMut};
+use clap::Args;
+use futures::{stream, Stream, StreamExt};
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
+use tonic::transport::Server;
+use tonic::{Request, Status, Streaming};
+
+use common::model::CommonOptions;
+use common::utils::HIDDEN_REF_PREFIX;
+use git::protocol::pack::{add_pkt_line_string, read_pkt_line, PKT_LINE_END_MARKER};
+use git::protocol::{PackProtocol, Protocol};
+use jupiter::storage::access_token_storage::AccessTokenStorage;
+use jupiter::storage::archive_storage::ArchiveStorage;
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::org_storage::OrgStorage;
+use jupiter::storage::path_grant_storage::PathGrantStorage;
+use jupiter::storage::push_profile_storage::PushProfileStorage;
+use jupiter::storage::signing_key_storage::SigningKeyStorage;
+use jupiter::storage::user_storage::UserStorage;
+use storage::driver::database;
+use storage::driver::database::storage::ObjectStorage;
+use venus::hash::SHA1;
+
+use crate::api_service::archive::PathArchives;
+use crate::api_service::event_service::EventService;
+use crate::api_service::push_profile_service::PushProfileService;
+use crate::api_service::signing_key_service::SigningKeyService;
+use crate::auth;
+use crate::auth::acl::{Acl, AclPolicy, Permission};
+use crate::auth::http::{HttpAuth, HttpAuthMode};
+use crate::auth::Identity;
+use crate::shutdown;
+
+use proto::storage_server::{Storage, StorageServer};
+use proto::{
+    GetObjectRequest, GetObjectResponse, ListRefsRequest, ListRefsResponse, Ref, RefResult,
+    RefUpdate, WritePackRequest, WritePackResponse,
+};
+
+/// The messages and services generated from `proto/storage.proto`, the client included.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("mega.storage.v1");
+}
+
+/// Largest part of an object sent in a message of `GetObject`.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Args, Clone, Debug)]
+pub struct GrpcOptions {
+    #[clap(flatten)]
+    pub common: CommonOptions,
+
+    #[clap(flatten)]
+    pub custom: GrpcCustom,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct GrpcCustom {
+    #[arg(long, default_value_t = 50051)]
+    grpc_port: u16,
+}
+
+/// start a gRPC server
+pub async fn start_server(options: &GrpcOptions) {
+    let GrpcOptions {
+        common: CommonOptions { host, data_source },
+        custom: GrpcCustom { grpc_port },
+    } = options;
+    let connection = Arc::new(database::connect(data_source).await);
+    let storage = database::init(data_source).await;
+    let service = StorageService {
+        storage: storage.clone(),
+        archives: PathArchives {
+            archive_storage: ArchiveStorage::new(connection.clone()),
+        },
+        events: EventService {
+            storage: EventStorage::new(connection.clone()),
+        },
+        signing_keys: SigningKeyService {
+            storage: SigningKeyStorage::new(connection.clone()),
+            object_storage: storage,
+        },
+        push_profiles: PushProfileService {
+            storage: PushProfileStorage::new(connection.clone()),
+        },
+        http_auth: HttpAuth {
+            mode: HttpAuthMode::from_env().expect("Failed to read MEGA_HTTP_AUTH"),
+            provider: auth::init(connection.clone())
+                .expect("Failed to set up the authentication provider"),
+            user_storage: UserStorage::new(connection.clone()),
+            token_storage: AccessTokenStorage::new(connection.clone()),
+            acl: Acl {
+                policy: AclPolicy::from_env().expect("Failed to read the ACL configuration"),
+                grant_storage: PathGrantStorage::new(connection.clone()),
+                org_storage: OrgStorage::new(connection),
+            },
+        },
+    };
+    let server_url = format!("{}:{}", host, grpc_port);
+    let addr = SocketAddr::from_str(&server_url).unwrap();
+    shutdown::listen();
+    let server = Server::builder()
+        .add_service(StorageServer::new(service))
+        .serve_with_shutdown(addr, shutdown::requested());
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = shutdown::deadline() => {
+            tracing::warn!("dropped the gRPC calls still open after the shutdown timeout");
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct StorageService {
+    storage: Arc<dyn ObjectStorage>,
+    archives: PathArchives,
+    events: EventService,
+    signing_keys: SigningKeyService,
+    push_profiles: PushProfileService,
+    http_auth: HttpAuth,
+}
+
+impl StorageService {
+    async fn authorize(
+        &self,
+        headers: &HeaderMap,
+        repo_path: &str,
+        permission: Permission,
+    ) -> Result<Option<Identity>, Status> {
+        match self
+            .http_auth
+            .authorize(headers, repo_path, permission)
+            .await
+        {
+            Ok(identity) => Ok(identity),
+            Err(response) => Err(rejected(response).await),
+        }
+    }
+}
+
+/// The status of a call the HTTP authorization refused with `response`.
+async fn rejected(response: Response) -> Status {
+    let status = response.status();
+    let message = match axum::body::to_bytes(response.into_body(), 64 * 1024).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_owned(),
+        Err(_) => String::new(),
+    };
+    to_status((status, message))
+}
+
+/// The gRPC status of an error of the services.
+fn to_status((status, message): (StatusCode, String)) -> Status {
+    match status {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        StatusCode::NOT_FOUND | StatusCode::GONE => Status::not_found(message),
+        StatusCode::CONFLICT | StatusCode::LOCKED | StatusCode::PRECONDITION_FAILED => {
+            Status::failed_precondition(message)
+        }
+        StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// The pkt-lines of `updates` a receive-pack request starts with, up to the flush before the
+/// pack, asking for the report of the refs.
+#[allow(clippy::result_large_err)]
+fn ref_commands(updates: &[RefUpdate]) -> Result<Bytes, Status> {
+    if updates.is_empty() {
+        return Err(Status::invalid_argument("no ref to update"));
+    }
+    let mut commands = BytesMut::new();
+    for (i, update) in updates.iter().enumerate() {
+        if !update.name.starts_with("refs/") || update.name.contains(char::is_whitespace) {
+            return Err(Status::invalid_argument(format!(
+                "{:?} is not a ref name",
+                update.name
+            )));
+        }
+        for id in [&update.old_id, &update.new_id] {
+            if id.len() != 40 || SHA1::from_str(id).is_err() {
+                return Err(Status::invalid_argument(format!(
+                    "{:?} is not an object id",
+                    id
+                )));
+            }
+        }
+        let command = format!("{} {} {}", update.old_id, update.new_id, update.name);
+        if i == 0 {
+            add_pkt_line_string(&mut commands, format!("{}\0report-status", command));
+        } else {
+            add_pkt_line_string(&mut commands, command);
+        }
+    }
+    commands.put(&PKT_LINE_END_MARKER[..]);
+    Ok(commands.freeze())
+}
+
+/// The outcome of the unpack and of each ref in the report of a receive-pack.
+fn parse_report(mut report: Bytes) -> WritePackResponse {
+    let mut response = WritePackResponse::default();
+    while report.len() >= 4 {
+        let (length, line) = read_pkt_line(&mut report);
+        if length == 0 {
+            continue;
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\n');
+        if let Some(unpack) = line.strip_prefix("unpack ") {
+            response.unpack = unpack.to_owned();
+        } else if let Some(name) = line.strip_prefix("ok ") {
+            response.results.push(RefResult {
+                name: name.to_owned(),
+                ok: true,
+                error: String::new(),
+            });
+        } else if let Some(rest) = line.strip_prefix("ng ") {
+            let (name, error) = rest.split_once(' ').unwrap_or((rest, ""));
+            response.results.push(RefResult {
+                name: name.to_owned(),
+                ok: false,
+                error: error.to_owned(),
+            });
+        }
+    }
+    response
+}
+
+type ObjectStream = Pin<Box<dyn Stream<Item = Result<GetObjectResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Storage for StorageService {
+    type GetObjectStream = ObjectStream;
+
+    async fn get_object(
+        &self,
+        request: Request<GetObjectRequest>,
+    ) -> Result<tonic::Response<ObjectStream>, Status> {
+        let headers = request.metadata().clone().into_headers();
+        let GetObjectRequest {
+            repo_path,
+            object_id,
+        } = request.into_inner();
+        self.authorize(&headers, &repo_path, Permission::Read)
+            .await?;
+        let id = SHA1::from_str(&object_id).map_err(|_| {
+            Status::invalid_argument(format!("{:?} is not an object id", object_id))
+        })?;
+        let model = self
+            .storage
+            .get_obj_data_by_id(&id.to_plain_str())
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::not_found(format!("object {} not found", object_id)))?;
+        let size = model.data.len() as u64;
+        let data = Bytes::from(model.data);
+        let mut messages = vec![GetObjectResponse {
+            object_type: model.object_type,
+            size,
+            data: Vec::new(),
+        }];
+        for (i, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+            if i == 0 {
+                messages[0].data = chunk.to_vec();
+            } else {
+                messages.push(GetObjectResponse {
+                    data: chunk.to_vec(),
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(tonic::Response::new(Box::pin(stream::iter(
+            messages.into_iter().map(Ok),
+        ))))
+    }
+
+    async fn list_refs(
+        &self,
+        request: Request<ListRefsRequest>,
+    ) -> Result<tonic::Response<ListRefsResponse>, Status> {
+        let headers = request.metadata().clone().into_headers();
+        let repo_path = request.into_inner().repo_path;
+        self.authorize(&headers, &repo_path, Permission::Read)
+            .await?;
+        let refs = self
+            .storage
+            .get_all_refs_by_path(&repo_path)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .into_iter()
+            .filter(|r| !r.ref_name.starts_with(HIDDEN_REF_PREFIX))
+            .map(|r| Ref {
+                name: r.ref_name,
+                id: r.ref_git_id,
+            })
+            .collect();
+        Ok(tonic::Response::new(ListRefsResponse { refs }))
+    }
+
+    async fn write_pack(
+        &self,
+        request: Request<Streaming<WritePackRequest>>,
+    ) -> Result<tonic::Response<WritePackResponse>, Status> {
+        let headers = request.metadata().clone().into_headers();
+        let mut messages = request.into_inner();
+        let mut first = messages
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("no repository to write to"))?;
+        let identity = self
+            .authorize(&headers, &first.repo_path, Permission::Write)
+            .await?;
+        self.archives
+            .check_writable(&first.repo_path)
+            .await
+            .map_err(to_status)?;
+        let commands = ref_commands(&first.updates)?;
+
+        // the pack is read as it arrives, behind the commands git would send before it
+        let first_chunk: Result<Bytes, io::Error> =
+            Ok(Bytes::from(std::mem::take(&mut first.pack)));
+        let pack = stream::iter([first_chunk]).chain(messages.map(|message| {
+            message
+                .map(|message| Bytes::from(message.pack))
+                .map_err(io::Error::other)
+        }));
+        let body = Cursor::new(commands).chain(StreamReader::new(pack));
+        let mut pack_protocol = PackProtocol::new(
+            PathBuf::from(&first.repo_path),
+            self.storage.clone(),
+            Protocol::Grpc,
+        );
+        pack_protocol.verifier = Some(Arc::new(self.signing_keys.clone()));
+        pack_protocol.push_profile.receiving();
+        let report = pack_protocol
+            .git_receive_pack_stream(body)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let response = parse_report(report);
+
+        // only the refs the report says moved are pushes
+        for command in pack_protocol.command_list.iter_mut() {
+            if let Some(result) = response
+                .results
+                .iter()
+                .find(|r| r.name == command.ref_name && !r.ok)
+            {
+                command.failed(result.error.clone());
+            }
+        }
+        let repo_path = pack_protocol.path.to_string_lossy().into_owned();
+        let username = identity.as_ref().map(|i| i.username.as_str());
+        self.events
+            .publish_push(&repo_path, &pack_protocol.command_list, username)
+            .await;
+        self.push_profiles
+            .record(&repo_path, username, "grpc", &pack_protocol.push_profile)
+            .await;
+        Ok(tonic::Response::new(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::utils::ZERO_ID;
+
+    fn update(name: &str, old_id: &str, new_id: &str) -> RefUpdate {
+        RefUpdate {
+            name: name.to_owned(),
+            old_id: old_id.to_owned(),
+            new_id: new_id.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_ref_commands() {
+        let id = "8ab686eafeb1f44702738c8b0f24f2567c36da6d";
+        let commands = ref_commands(&[
+            update("refs/heads/main", ZERO_ID, id),
+            update("refs/tags/v1", id, ZERO_ID),
+        ])
+        .unwrap();
+        let first = format!("{} {} refs/heads/main\0report-status", ZERO_ID, id);
+        let second = format!("{} {} refs/tags/v1", id, ZERO_ID);
+        assert_eq!(
+            commands,
+            format!(
+                "{:04x}{}{:04x}{}0000",
+                first.len() + 4,
+                first,
+                second.len() + 4,
+                second
+            )
+        );
+
+        assert!(ref_commands(&[]).is_err());
+        assert!(ref_commands(&[update("main", ZERO_ID, id)]).is_err());
+        assert!(ref_commands(&[update("refs/heads/main", ZERO_ID, "abc")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let mut report = BytesMut::new();
+        add_pkt_line_string(&mut report, "unpack ok\n".to_owned());
+        add_pkt_line_string(&mut report, "ok refs/heads/main".to_owned());
+        add_pkt_line_string(&mut report, "ng refs/heads/dev unsigned commit".to_owned());
+        report.put(&PKT_LINE_END_MARKER[..]);
+        report.put(&PKT_LINE_END_MARKER[..]);
+        let response = parse_report(report.freeze());
+        assert_eq!(response.unpack, "ok");
+        assert_eq!(
+            response.results,
+            [
+                RefResult {
+                    name: "refs/heads/main".to_owned(),
+                    ok: true,
+                    error: String::new(),
+                },
+                RefResult {
+                    name: "refs/heads/dev".to_owned(),
+                    ok: false,
+                    error: "unsigned commit".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(parse_report(Bytes::new()), WritePackResponse::default());
+    }
+
+    #[test]
+    fn test_to_status() {
+        let status = to_status((
+            StatusCode::FORBIDDEN,
+            "write permission required".to_owned(),
+        ));
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert_eq!(status.message(), "write permission required");
+        assert_eq!(
+            to_status((StatusCode::GONE, String::new())).code(),
+            tonic::Code::NotFound
+        );
+    }
+}
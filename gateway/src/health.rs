@@ -0,0 +1,120 @@
+//!
+//! Liveness and readiness probes of the HTTP server, for Kubernetes and load balancers.
+//!
+//! `/healthz` answers as long as the server serves requests, and doesn't look at what the server
+//! depends on: a database outage should not get every instance restarted. `/readyz` checks that
+//! the database answers, that objects can be stored and that the schema has every table of the
+//! script the server was built with, and fails once a shutdown is requested, so instances only
+//! get traffic they can serve. It answers `503 Service Unavailable` with the failed checks.
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use tokio::sync::OnceCell;
+
+use storage::driver::file_storage::FileStorage;
+
+use crate::{schema, shutdown};
+
+/// Longest a check may take before it counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone)]
+pub struct HealthState {
+    pub connection: Arc<DatabaseConnection>,
+    pub objects: Arc<dyn FileStorage>,
+    /// Set once the schema was found up to date, it is not looked at again
+    pub schema_ok: Arc<OnceCell<()>>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+pub fn routers<S>(state: HealthState) -> Router<S> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state)
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(State(state): State<HealthState>) -> (StatusCode, Json<Readiness>) {
+    let (database, objects, schema) = tokio::join!(
+        run_check("database", async {
+            state.connection.ping().await.map_err(|e| e.to_string())
+        }),
+        run_check("object storage", async {
+            state.objects.check().await.map_err(|e| e.to_string())
+        }),
+        run_check("schema", async {
+            state
+                .schema_ok
+                .get_or_try_init(|| check_schema(&state.connection))
+                .await
+                .map(|_| ())
+        }),
+    );
+    let mut checks = vec![database, objects, schema];
+    if shutdown::is_requested() {
+        checks.push(Check {
+            name: "shutdown",
+            ok: false,
+            error: Some("the server is shutting down".to_owned()),
+        });
+    }
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(Readiness { ready, checks }))
+}
+
+async fn run_check(
+    name: &'static str,
+    check: impl std::future::Future<Output = Result<(), String>>,
+) -> Check {
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("no answer within {:?}", CHECK_TIMEOUT)),
+    };
+    if let Err(e) = &result {
+        tracing::warn!("readiness check {} failed: {}", name, e);
+    }
+    Check {
+        name,
+        ok: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+/// Fail when a table of the schema script is missing, the migrations not having been applied.
+async fn check_schema(connection: &DatabaseConnection) -> Result<(), String> {
+    let missing = schema::missing_tables(connection).await?;
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("missing tables: {}", missing.join(", ")))
+    }
+}
@@ -6,32 +6,136 @@
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{net::SocketAddr, sync::Arc};
 
 use anyhow::Result;
 use axum::body::Body;
 use axum::extract::{Query, State};
-use axum::http::{Request, StatusCode, Uri};
+use axum::http::{header, HeaderMap, Request, StatusCode, Uri};
+use axum::middleware::{self, Next};
 use axum::response::Response;
 use axum::routing::get;
 use axum::Router;
 use clap::Args;
 
+use ed25519_dalek::SigningKey;
 use regex::Regex;
+use russh_keys::key::KeyPair;
 use serde::Deserialize;
+use tokio::sync::{Notify, OnceCell, Semaphore};
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 
+use common::metrics::metrics;
 use common::model::CommonOptions;
 use git::lfs::LfsConfig;
 use git::protocol::{PackProtocol, Protocol};
+use jupiter::storage::access_token_storage::AccessTokenStorage;
+use jupiter::storage::archive_storage::ArchiveStorage;
+use jupiter::storage::autolink_storage::AutolinkStorage;
+use jupiter::storage::board_storage::BoardStorage;
+use jupiter::storage::assignee_storage::AssigneeStorage;
+use jupiter::storage::check_run_storage::CheckRunStorage;
+use jupiter::storage::ci_job_storage::CiJobStorage;
+use jupiter::storage::ci_log_storage::CiLogStorage;
+use jupiter::storage::diffstat_storage::DiffstatStorage;
+use jupiter::storage::erasure_storage::ErasureStorage;
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::feature_flag_storage::FeatureFlagStorage;
+use jupiter::storage::import_storage::ImportStorage;
+use jupiter::storage::issue_storage::IssueStorage;
+use jupiter::storage::label_storage::LabelStorage;
+use jupiter::storage::mailmap_storage::MailmapStorage;
+use jupiter::storage::merge_queue_storage::MergeQueueStorage;
+use jupiter::storage::milestone_storage::MilestoneStorage;
+use jupiter::storage::mirror_storage::MirrorStorage;
+use jupiter::storage::mr_review_storage::MrReviewStorage;
+use jupiter::storage::mr_storage::MrStorage;
+use jupiter::storage::notification_storage::NotificationStorage;
+use jupiter::storage::oidc_storage::OidcStorage;
+use jupiter::storage::org_storage::OrgStorage;
+use jupiter::storage::path_grant_storage::PathGrantStorage;
+use jupiter::storage::path_redirect_storage::PathRedirectStorage;
+use jupiter::storage::push_profile_storage::PushProfileStorage;
+use jupiter::storage::reaction_storage::ReactionStorage;
+use jupiter::storage::ref_audit_storage::RefAuditStorage;
+use jupiter::storage::ref_hook_storage::RefHookStorage;
+use jupiter::storage::ref_trigger_storage::RefTriggerStorage;
+use jupiter::storage::reference_storage::ReferenceStorage;
+use jupiter::storage::release_storage::ReleaseStorage;
+use jupiter::storage::signing_key_storage::SigningKeyStorage;
+use jupiter::storage::snapshot_export_storage::SnapshotExportStorage;
+use jupiter::storage::snippet_storage::SnippetStorage;
+use jupiter::storage::ssh_key_storage::SshKeyStorage;
+use jupiter::storage::stats_storage::StatsStorage;
+use jupiter::storage::user_storage::UserStorage;
+use jupiter::storage::webhook_storage::WebhookStorage;
 use storage::driver::database;
 use storage::driver::database::storage::ObjectStorage;
 use tower_http::trace::TraceLayer;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
+use crate::api_service::account_service::AccountService;
+use crate::api_service::acl_service::AclService;
+use crate::api_service::archive::PathArchives;
+use crate::api_service::archive_service::ArchiveService;
+use crate::api_service::autolink::Autolinker;
+use crate::api_service::autolink_service::AutolinkService;
+use crate::api_service::blame_service::BlameService;
+use crate::api_service::board_service::BoardService;
+use crate::api_service::bundle_service::BundleService;
+use crate::api_service::changelog_service::ChangelogService;
+use crate::api_service::check_service::CheckService;
+use crate::api_service::ci_log_service::CiLogService;
+use crate::api_service::diffstat_service::{DiffstatHook, DiffstatService};
+use crate::api_service::erasure_service::ErasureService;
+use crate::api_service::event_service::EventService;
+use crate::api_service::feature_flag_service::FeatureFlagService;
+use crate::api_service::highlight::Highlighter;
+use crate::api_service::import_service::ImportService;
+use crate::api_service::issue_service::IssueService;
+use crate::api_service::merge_queue_service::MergeQueueService;
+use crate::api_service::merge_service::MergeService;
+use crate::api_service::mirror_service::MirrorService;
+use crate::api_service::mr_review_service::MrReviewService;
+use crate::api_service::mr_service::MrService;
+use crate::api_service::notification_service::{Mailer, NotificationHook, NotificationService};
 use crate::api_service::obj_service::ObjectService;
-use crate::api_service::router::ApiServiceState;
-use crate::{api_service, git_protocol, lfs};
+use crate::api_service::object_batch::ObjectBatchService;
+use crate::api_service::oidc_service::OidcService;
+use crate::api_service::path_move::PathRedirects;
+use crate::api_service::path_move_service::PathMoveService;
+use crate::api_service::pipeline;
+use crate::api_service::pipeline_service::{self, PipelineHook, PipelineService};
+use crate::api_service::planning_service::PlanningService;
+use crate::api_service::push_profile_service::PushProfileService;
+use crate::api_service::reaction_service::ReactionService;
+use crate::api_service::ref_hook;
+use crate::api_service::ref_hook_service::RefHookService;
+use crate::api_service::ref_trigger_service::RefTriggerService;
+use crate::api_service::release_service::ReleaseService;
+use crate::api_service::ref_update::RefUpdater;
+use crate::api_service::reference::Referencer;
+use crate::api_service::remote::ImportThrottle;
+use crate::api_service::repair_service::RepairService;
+use crate::api_service::router::{ApiDoc, ApiServiceState};
+use crate::api_service::search_service::{self, SearchService};
+use crate::api_service::signing_key_service::SigningKeyService;
+use crate::api_service::snapshot_export_service::{SnapshotExportHook, SnapshotExportService};
+use crate::api_service::snapshot_service::SnapshotService;
+use crate::api_service::snippet_service::SnippetService;
+use crate::api_service::ssh_key_service::SshKeyService;
+use crate::api_service::stats_service::{StatsHook, StatsService};
+use crate::api_service::webhook::WebhookHook;
+use crate::api_service::webhook_service::WebhookService;
+use crate::api_service::wiki_service::WikiService;
+use crate::auth::acl::{Acl, AclPolicy, Permission};
+use crate::auth::http::{HttpAuth, HttpAuthMode};
+use crate::auth::oidc::OidcConfig;
+use crate::health::{self, HealthState};
+use crate::{api_service, auth, git_protocol, lfs, request_id, shutdown, ssh_server};
 
 #[derive(Args, Clone, Debug)]
 pub struct HttpOptions {
@@ -61,6 +165,14 @@ pub struct HttpCustom {
 pub struct AppState {
     pub storage: Arc<dyn ObjectStorage>,
     pub options: HttpOptions,
+    pub redirects: PathRedirects,
+    /// Archived and deleted paths, which refuse pushes.
+    pub archives: PathArchives,
+    pub events: EventService,
+    /// Verifies the commits pushed to signed branches.
+    pub signing_keys: SigningKeyService,
+    pub http_auth: HttpAuth,
+    pub push_profiles: PushProfileService,
 }
 
 #[derive(Deserialize, Debug)]
@@ -73,10 +185,58 @@ pub struct GetParams {
     pub cursor: Option<String>,
 }
 
+/// The ed25519 host key of the SSH server, which also signs the tags the server creates.
+fn server_signing_key() -> Option<Arc<SigningKey>> {
+    if std::env::var("MEGA_SSH_KEY").is_err() {
+        return None;
+    }
+    match ssh_server::load_key() {
+        Ok(KeyPair::Ed25519(key)) => Some(Arc::new(key)),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!("unable to load the server key, created tags stay unsigned: {}", e);
+            None
+        }
+    }
+}
+
 pub fn remove_git_suffix(uri: Uri, git_suffix: &str) -> PathBuf {
     PathBuf::from(uri.path().replace(".git", "").replace(git_suffix, ""))
 }
 
+/// Redirect a git request for a repository that was moved to the same request at its new path.
+///
+/// Git follows the redirect of the initial `info/refs` request and sends the requests that follow
+/// to the new path, printing a warning so users can update their remotes.
+async fn redirect_moved(
+    state: &AppState,
+    uri: &Uri,
+    git_suffix: &str,
+    status: StatusCode,
+) -> Result<Option<Response<Body>>, (StatusCode, String)> {
+    let repo_path = remove_git_suffix(uri.clone(), git_suffix);
+    let repo_path = repo_path.to_str().unwrap_or_default();
+    let moved_to = state
+        .redirects
+        .moved_to(repo_path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (Some(moved_to), Some(rest)) = (moved_to, uri.path().strip_prefix(repo_path)) else {
+        return Ok(None);
+    };
+    let mut location = format!("{}{}", moved_to, rest);
+    if let Some(query) = uri.query() {
+        location.push('?');
+        location.push_str(query);
+    }
+    let response = Response::builder()
+        .status(status)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap();
+    Ok(Some(response))
+}
+
 pub async fn start_server(options: &HttpOptions) {
     let HttpOptions {
         common: CommonOptions { host, data_source },
@@ -90,39 +250,423 @@ pub async fn start_server(options: &HttpOptions) {
     } = options;
     let server_url = format!("{}:{}", host, http_port);
 
+    // tables managed by jupiter share one pool, separate from the object storage
+    let connection = Arc::new(database::connect(data_source).await);
+    let storage = database::init(data_source).await;
     let state = AppState {
-        storage: database::init(data_source).await,
+        storage: storage.clone(),
         options: options.to_owned(),
+        redirects: PathRedirects {
+            storage: storage.clone(),
+            redirect_storage: PathRedirectStorage::new(connection.clone()),
+        },
+        archives: PathArchives {
+            archive_storage: ArchiveStorage::new(connection.clone()),
+        },
+        events: EventService {
+            storage: EventStorage::new(connection.clone()),
+        },
+        signing_keys: SigningKeyService {
+            storage: SigningKeyStorage::new(connection.clone()),
+            object_storage: storage,
+        },
+        push_profiles: PushProfileService {
+            storage: PushProfileStorage::new(connection.clone()),
+        },
+        http_auth: HttpAuth {
+            mode: HttpAuthMode::from_env().expect("Failed to read MEGA_HTTP_AUTH"),
+            provider: auth::init(connection.clone())
+                .expect("Failed to set up the authentication provider"),
+            user_storage: UserStorage::new(connection.clone()),
+            token_storage: AccessTokenStorage::new(connection.clone()),
+            acl: Acl {
+                policy: AclPolicy::from_env().expect("Failed to read the ACL configuration"),
+                grant_storage: PathGrantStorage::new(connection.clone()),
+                org_storage: OrgStorage::new(connection.clone()),
+            },
+        },
     };
-    
+    state.events.clone().start_cleanup();
+    let ref_updater = RefUpdater {
+        storage: state.storage.clone(),
+        audit_storage: RefAuditStorage::new(connection.clone()),
+        events: state.events.clone(),
+        archives: state.archives.clone(),
+    };
+    let ref_trigger_service = RefTriggerService {
+        storage: state.storage.clone(),
+        trigger_storage: RefTriggerStorage::new(connection.clone()),
+        audit_storage: RefAuditStorage::new(connection.clone()),
+        ref_updater: ref_updater.clone(),
+        signing_key: server_signing_key(),
+    };
+    ref_trigger_service.clone().start_scheduler();
+    // imports, mirror syncs and repairs share one limit on clones
+    let import_throttle = ImportThrottle::from_env();
+    let mirror_service = MirrorService {
+        storage: state.storage.clone(),
+        mirror_storage: MirrorStorage::new(connection.clone()),
+        ref_updater: ref_updater.clone(),
+        throttle: import_throttle.clone(),
+    };
+    mirror_service.clone().start_scheduler();
+    let planning_service = PlanningService {
+        label_storage: LabelStorage::new(connection.clone()),
+        milestone_storage: MilestoneStorage::new(connection.clone()),
+        assignee_storage: AssigneeStorage::new(connection.clone()),
+    };
+    let check_service = CheckService {
+        storage: CheckRunStorage::new(connection.clone()),
+    };
+    let ci_log_service = CiLogService {
+        storage: CiLogStorage::new(connection.clone()),
+        content: storage::driver::file_storage::init("ci-logs".to_owned()).await,
+    };
+    ci_log_service.clone().start_cleanup();
+    let pipeline_service = PipelineService {
+        storage: state.storage.clone(),
+        job_storage: CiJobStorage::new(connection.clone()),
+        ci_logs: ci_log_service.clone(),
+        checks: check_service.clone(),
+        runner: pipeline::runner_from_env(),
+        slots: Arc::new(Semaphore::new(pipeline_service::concurrency())),
+    };
+    pipeline_service.clone().start_runner();
+    let search_service = SearchService {
+        storage: state.storage.clone(),
+        event_storage: EventStorage::new(connection.clone()),
+        issue_storage: IssueStorage::new(connection.clone()),
+        mr_storage: MrStorage::new(connection.clone()),
+        planning: planning_service.clone(),
+        index: search_service::open_index(),
+        wake: Arc::new(Notify::new()),
+    };
+    search_service.clone().start_indexer();
+    let snapshot_export_service = SnapshotExportService {
+        export_storage: SnapshotExportStorage::new(connection.clone()),
+        snapshots: SnapshotService {
+            storage: state.storage.clone(),
+        },
+        bundles: BundleService {
+            storage: state.storage.clone(),
+            archives: state.archives.clone(),
+            events: state.events.clone(),
+        },
+        target: storage::driver::file_storage::init("snapshot-exports".to_owned()).await,
+    };
+    snapshot_export_service.clone().start_scheduler();
+    let mut hooks = ref_hook::hooks_from_env(search_service.wake.clone());
+    hooks.push(Arc::new(SnapshotExportHook {
+        service: snapshot_export_service.clone(),
+    }));
+    hooks.push(Arc::new(DiffstatHook {
+        service: DiffstatService {
+            storage: state.storage.clone(),
+            diffstat_storage: DiffstatStorage::new(connection.clone()),
+            mr_storage: MrStorage::new(connection.clone()),
+        },
+    }));
+    let stats_service = StatsService {
+        storage: state.storage.clone(),
+        stats_storage: StatsStorage::new(connection.clone()),
+        diffstat_storage: DiffstatStorage::new(connection.clone()),
+        mailmap_storage: MailmapStorage::new(connection.clone()),
+    };
+    hooks.push(Arc::new(StatsHook {
+        service: stats_service.clone(),
+    }));
+    let notification_service = NotificationService {
+        notification_storage: NotificationStorage::new(connection.clone()),
+        user_storage: UserStorage::new(connection.clone()),
+        issue_storage: IssueStorage::new(connection.clone()),
+        mr_storage: MrStorage::new(connection.clone()),
+        acl: state.http_auth.acl.clone(),
+        mailer: Mailer::from_env().map(Arc::new),
+    };
+    notification_service.clone().start_sender();
+    hooks.push(Arc::new(NotificationHook {
+        service: notification_service.clone(),
+    }));
+    if pipeline_service.runner.is_some() {
+        hooks.push(Arc::new(PipelineHook {
+            service: pipeline_service.clone(),
+        }));
+    }
+    if let Some(hook) =
+        WebhookHook::new(state.storage.clone(), WebhookStorage::new(connection.clone()))
+    {
+        hooks.push(Arc::new(hook));
+    }
+    let ref_hook_service = RefHookService {
+        event_storage: EventStorage::new(connection.clone()),
+        hook_storage: RefHookStorage::new(connection.clone()),
+        hooks: Arc::new(hooks),
+    };
+    let ref_hook_runner = ref_hook_service.clone().start_runner();
+    let import_service = ImportService {
+        storage: state.storage.clone(),
+        import_storage: ImportStorage::new(connection.clone()),
+        events: state.events.clone(),
+        throttle: import_throttle.clone(),
+    };
+    import_service.clone().start_runner();
+    let feature_flags = FeatureFlagStorage::new(connection.clone());
+    let autolinker = Autolinker {
+        autolink_storage: AutolinkStorage::new(connection.clone()),
+    };
+    let referencer = Referencer {
+        storage: state.storage.clone(),
+        reference_storage: ReferenceStorage::new(connection.clone()),
+        issue_storage: IssueStorage::new(connection.clone()),
+        mr_storage: MrStorage::new(connection.clone()),
+        user_storage: UserStorage::new(connection.clone()),
+    };
+    let mr_service = MrService {
+        storage: state.storage.clone(),
+        mr_storage: MrStorage::new(connection.clone()),
+        issue_storage: IssueStorage::new(connection.clone()),
+        ref_updater: ref_updater.clone(),
+        planning: planning_service.clone(),
+        ci_log_storage: CiLogStorage::new(connection.clone()),
+        review_storage: MrReviewStorage::new(connection.clone()),
+        diffstat_storage: DiffstatStorage::new(connection.clone()),
+        checks: check_service.clone(),
+        events: state.events.clone(),
+        autolinks: autolinker.clone(),
+        references: referencer.clone(),
+    };
+    let merge_queue_service = MergeQueueService {
+        queue_storage: MergeQueueStorage::new(connection.clone()),
+        mrs: mr_service.clone(),
+    };
+    merge_queue_service.clone().start_runner();
     let api_state = ApiServiceState {
         object_service: ObjectService {
             storage: state.storage.clone(),
-        }
+            autolinks: autolinker.clone(),
+            highlighter: Highlighter::default(),
+        },
+        object_batch_service: ObjectBatchService {
+            storage: state.storage.clone(),
+            feature_flags: feature_flags.clone(),
+        },
+        acl_service: AclService {
+            acl: state.http_auth.acl.clone(),
+            user_storage: UserStorage::new(connection.clone()),
+        },
+        http_auth: state.http_auth.clone(),
+        archive_service: ArchiveService {
+            storage: state.storage.clone(),
+            archive_storage: ArchiveStorage::new(connection.clone()),
+            ref_updater: ref_updater.clone(),
+        },
+        path_archives: state.archives.clone(),
+        autolink_service: AutolinkService {
+            autolink_storage: AutolinkStorage::new(connection.clone()),
+            org_storage: OrgStorage::new(connection.clone()),
+        },
+        account_service: AccountService {
+            user_storage: UserStorage::new(connection.clone()),
+            org_storage: OrgStorage::new(connection.clone()),
+            token_storage: AccessTokenStorage::new(connection.clone()),
+        },
+        oidc_service: OidcService {
+            config: OidcConfig::from_env()
+                .expect("Failed to read the OIDC configuration")
+                .map(Arc::new),
+            endpoints: Arc::new(OnceCell::new()),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("Failed to build the OIDC client"),
+            oidc_storage: OidcStorage::new(connection.clone()),
+            user_storage: UserStorage::new(connection.clone()),
+            org_storage: OrgStorage::new(connection.clone()),
+            token_storage: AccessTokenStorage::new(connection.clone()),
+        },
+        blame_service: BlameService {
+            storage: state.storage.clone(),
+            mailmap_storage: MailmapStorage::new(connection.clone()),
+        },
+        board_service: BoardService {
+            board_storage: BoardStorage::new(connection.clone()),
+            issue_storage: IssueStorage::new(connection.clone()),
+            mr_storage: MrStorage::new(connection.clone()),
+            planning: planning_service.clone(),
+        },
+        bundle_service: BundleService {
+            storage: state.storage.clone(),
+            archives: state.archives.clone(),
+            events: state.events.clone(),
+        },
+        changelog_service: ChangelogService {
+            storage: state.storage.clone(),
+        },
+        check_service: check_service.clone(),
+        ci_log_service,
+        erasure_service: ErasureService {
+            storage: ErasureStorage::new(connection.clone()),
+            mailmap_storage: MailmapStorage::new(connection.clone()),
+            user_storage: UserStorage::new(connection.clone()),
+            signing_key_storage: SigningKeyStorage::new(connection.clone()),
+            event_storage: EventStorage::new(connection.clone()),
+        },
+        feature_flag_service: FeatureFlagService {
+            storage: feature_flags,
+        },
+        import_service,
+        merge_service: MergeService {
+            storage: state.storage.clone(),
+            ref_updater: ref_updater.clone(),
+            checks: check_service,
+            diffstats: DiffstatStorage::new(connection.clone()),
+        },
+        mirror_service,
+        merge_queue_service,
+        mr_service,
+        mr_review_service: MrReviewService {
+            storage: state.storage.clone(),
+            mr_storage: MrStorage::new(connection.clone()),
+            review_storage: MrReviewStorage::new(connection.clone()),
+            events: state.events.clone(),
+            autolinks: autolinker.clone(),
+            references: referencer.clone(),
+        },
+        notification_service,
+        issue_service: IssueService {
+            issue_storage: IssueStorage::new(connection.clone()),
+            user_storage: UserStorage::new(connection.clone()),
+            planning: planning_service.clone(),
+            events: state.events.clone(),
+            autolinks: autolinker,
+            references: referencer.clone(),
+        },
+        event_service: state.events.clone(),
+        planning_service,
+        path_redirects: state.redirects.clone(),
+        pipeline_service,
+        push_profile_service: state.push_profiles.clone(),
+        reaction_service: ReactionService {
+            reaction_storage: ReactionStorage::new(connection.clone()),
+            issue_storage: IssueStorage::new(connection.clone()),
+            mr_storage: MrStorage::new(connection.clone()),
+            review_storage: MrReviewStorage::new(connection.clone()),
+        },
+        path_move_service: PathMoveService {
+            storage: state.storage.clone(),
+            redirect_storage: PathRedirectStorage::new(connection.clone()),
+            ref_updater: ref_updater.clone(),
+        },
+        ref_hook_service: ref_hook_service.clone(),
+        ref_trigger_service,
+        references: referencer,
+        release_service: ReleaseService {
+            storage: state.storage.clone(),
+            release_storage: ReleaseStorage::new(connection.clone()),
+            assets: storage::driver::file_storage::init("release-assets".to_owned()).await,
+        },
+        repair_service: RepairService {
+            storage: state.storage.clone(),
+            mirror_storage: MirrorStorage::new(connection.clone()),
+            throttle: import_throttle,
+        },
+        search_service,
+        ssh_key_service: SshKeyService {
+            storage: SshKeyStorage::new(connection.clone()),
+        },
+        signing_key_service: state.signing_keys.clone(),
+        snapshot_service: SnapshotService {
+            storage: state.storage.clone(),
+        },
+        snapshot_export_service,
+        snippet_service: SnippetService {
+            storage: state.storage.clone(),
+            snippet_storage: SnippetStorage::new(connection.clone()),
+        },
+        stats_service,
+        webhook_service: WebhookService {
+            webhook_storage: WebhookStorage::new(connection.clone()),
+        },
+        wiki_service: WikiService {
+            storage: state.storage.clone(),
+            ref_updater,
+        },
     };
     
+    let health_state = HealthState {
+        connection: connection.clone(),
+        objects: storage::driver::file_storage::init("lfs-files".to_owned()).await,
+        schema_ok: Arc::new(OnceCell::new()),
+    };
     let app = Router::new()
         .nest("/api/v1", api_service::router::routers(api_state))
+        .route("/metrics", get(get_metrics))
+        .merge(health::routers(health_state))
+        .merge(SwaggerUi::new("/api/swagger-ui").url("/api/openapi.json", ApiDoc::openapi()))
         .route(
             "/*path",
             get(get_method_router)
                 .post(post_method_router)
                 .put(put_method_router),
         )
-        .layer(ServiceBuilder::new().layer(CorsLayer::new().allow_origin(Any)))
+        .layer(
+            ServiceBuilder::new().layer(
+                CorsLayer::new()
+                    .allow_origin(Any)
+                    // for browser clients revalidating what they read with `If-None-Match`
+                    .expose_headers([header::ETAG]),
+            ),
+        )
         .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(count_connection))
+        .layer(middleware::from_fn(request_id::trace_request))
         .with_state(state);
 
     let addr = SocketAddr::from_str(&server_url).unwrap();
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
+    shutdown::listen();
+    let server = axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(shutdown::requested());
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = shutdown::deadline() => {
+            tracing::warn!("dropped the HTTP requests still open after the shutdown timeout");
+        }
+    }
+
+    // deliver the pushes made before the shutdown
+    let _ = ref_hook_runner.await;
+    if tokio::time::timeout(shutdown::timeout(), ref_hook_service.flush())
         .await
-        .unwrap();
+        .is_err()
+    {
+        tracing::warn!(
+            "gave up delivering push events to the ref hooks, they continue after the restart"
+        );
+    }
+}
+
+/// The Prometheus metrics of the server.
+async fn get_metrics() -> Response<Body> {
+    Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )
+        .body(Body::from(metrics().encode()))
+        .unwrap()
+}
+
+/// Count the request in the active HTTP connections while it is served.
+async fn count_connection(request: axum::extract::Request, next: Next) -> Response<Body> {
+    let _connection = metrics().connection("http");
+    next.run(request).await
 }
 
 async fn get_method_router(
     state: State<AppState>,
     Query(params): Query<GetParams>,
+    headers: HeaderMap,
     uri: Uri,
 ) -> Result<Response<Body>, (StatusCode, String)> {
     let mut lfs_config: LfsConfig = state.deref().to_owned().into();
@@ -136,11 +680,30 @@ async fn get_method_router(
     } else if Regex::new(r"/locks$").unwrap().is_match(uri.path()) {
         return lfs::lfs_retrieve_lock(&lfs_config, params).await;
     } else if Regex::new(r"/info/refs$").unwrap().is_match(uri.path()) {
-        let pack_protocol = PackProtocol::new(
-            remove_git_suffix(uri, "/info/refs"),
-            state.storage.clone(),
-            Protocol::Http,
-        );
+        if let Some(redirect) =
+            redirect_moved(&state, &uri, "/info/refs", StatusCode::MOVED_PERMANENTLY).await?
+        {
+            return Ok(redirect);
+        }
+        let permission = match params.service.as_deref() {
+            Some("git-receive-pack") => Permission::Write,
+            _ => Permission::Read,
+        };
+        let repo_path = remove_git_suffix(uri, "/info/refs");
+        if let Err(res) = state
+            .http_auth
+            .authorize(&headers, &repo_path.to_string_lossy(), permission)
+            .await
+        {
+            return Ok(res);
+        }
+        if permission == Permission::Write {
+            state
+                .archives
+                .check_writable(&repo_path.to_string_lossy())
+                .await?;
+        }
+        let pack_protocol = PackProtocol::new(repo_path, state.storage.clone(), Protocol::Http);
         return git_protocol::http::git_info_refs(params, pack_protocol).await;
     } else {
         return Err((
@@ -170,22 +733,75 @@ async fn post_method_router(
         .unwrap()
         .is_match(uri.path())
     {
-        let pack_protocol = PackProtocol::new(
-            remove_git_suffix(uri, "/git-upload-pack"),
-            state.storage.clone(),
-            Protocol::Http,
-        );
+        if let Some(redirect) =
+            redirect_moved(&state, &uri, "/git-upload-pack", StatusCode::PERMANENT_REDIRECT)
+                .await?
+        {
+            return Ok(redirect);
+        }
+        let repo_path = remove_git_suffix(uri, "/git-upload-pack");
+        if let Err(res) = state
+            .http_auth
+            .authorize(
+                req.headers(),
+                &repo_path.to_string_lossy(),
+                Permission::Read,
+            )
+            .await
+        {
+            return Ok(res);
+        }
+        let pack_protocol = PackProtocol::new(repo_path, state.storage.clone(), Protocol::Http);
         git_protocol::http::git_upload_pack(req, pack_protocol).await
     } else if Regex::new(r"/git-receive-pack$")
         .unwrap()
         .is_match(uri.path())
     {
-        let pack_protocol = PackProtocol::new(
-            remove_git_suffix(uri, "/git-receive-pack"),
-            state.storage.clone(),
-            Protocol::Http,
-        );
-        git_protocol::http::git_receive_pack(req, pack_protocol).await
+        if let Some(redirect) =
+            redirect_moved(&state, &uri, "/git-receive-pack", StatusCode::PERMANENT_REDIRECT)
+                .await?
+        {
+            return Ok(redirect);
+        }
+        let repo_path = remove_git_suffix(uri, "/git-receive-pack");
+        let identity = match state
+            .http_auth
+            .authorize(
+                req.headers(),
+                &repo_path.to_string_lossy(),
+                Permission::Write,
+            )
+            .await
+        {
+            Ok(identity) => identity,
+            Err(res) => return Ok(res),
+        };
+        state
+            .archives
+            .check_writable(&repo_path.to_string_lossy())
+            .await?;
+        let mut pack_protocol = PackProtocol::new(repo_path, state.storage.clone(), Protocol::Http);
+        pack_protocol.verifier = Some(Arc::new(state.signing_keys.clone()));
+        let res = git_protocol::http::git_receive_pack(req, &mut pack_protocol).await?;
+        let repo_path = pack_protocol.path.to_string_lossy().into_owned();
+        state
+            .events
+            .publish_push(
+                &repo_path,
+                &pack_protocol.command_list,
+                identity.as_ref().map(|i| i.username.as_str()),
+            )
+            .await;
+        state
+            .push_profiles
+            .record(
+                &repo_path,
+                identity.as_ref().map(|i| i.username.as_str()),
+                "http",
+                &pack_protocol.push_profile,
+            )
+            .await;
+        Ok(res)
     } else {
         Err((
             StatusCode::NOT_FOUND,
@@ -205,6 +821,9 @@ async fn put_method_router(
         .unwrap()
         .is_match(uri.path())
     {
+        if let Err(res) = state.http_auth.authenticate(req.headers(), true).await {
+            return Ok(res);
+        }
         lfs::lfs_upload_object(&lfs_config, uri.path(), req).await
     } else {
         Err((
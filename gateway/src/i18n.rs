@@ -0,0 +1,140 @@
+//!
+//! Message catalogs for the user-facing strings the server generates.
+//!
+//! Each supported language has a catalog in `gateway/locales`, mapping a message key to its
+//! text. The language of an API call is negotiated from its `Accept-Language` header and kept
+//! for the task handling the call, so code deep in a service can look messages up with [`t`]
+//! without passing the language along. Outside of an API call messages are in English.
+//!
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Zh,
+}
+
+tokio::task_local! {
+    static LOCALE: Locale;
+}
+
+impl Locale {
+    /// Language tag sent back in the `Content-Language` header.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Zh => "zh-CN",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Locale> {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "zh" => Some(Locale::Zh),
+            _ => None,
+        }
+    }
+
+    /// The supported language the client prefers most in an `Accept-Language` header, following
+    /// the quality values of the languages listed. English when none of them is supported.
+    pub fn negotiate(accept_language: &str) -> Locale {
+        let mut best: Option<(f32, Locale)> = None;
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let Some(locale) = Locale::from_tag(tag) else {
+                continue;
+            };
+            // the first of equally preferred languages wins
+            if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+                best = Some((quality, locale));
+            }
+        }
+        best.map(|(_, locale)| locale).unwrap_or_default()
+    }
+
+    fn catalog(&self) -> &'static HashMap<String, String> {
+        static EN: OnceLock<HashMap<String, String>> = OnceLock::new();
+        static ZH: OnceLock<HashMap<String, String>> = OnceLock::new();
+        match self {
+            Locale::En => EN.get_or_init(|| parse_catalog(include_str!("../locales/en.toml"))),
+            Locale::Zh => ZH.get_or_init(|| parse_catalog(include_str!("../locales/zh.toml"))),
+        }
+    }
+}
+
+fn parse_catalog(source: &str) -> HashMap<String, String> {
+    toml::from_str(source).expect("message catalogs are valid TOML tables of strings")
+}
+
+/// Run `f` with messages looked up in `locale`.
+pub async fn scope<F: Future>(locale: Locale, f: F) -> F::Output {
+    LOCALE.scope(locale, f).await
+}
+
+/// Language of the API call being handled.
+pub fn current() -> Locale {
+    LOCALE.try_with(|locale| *locale).unwrap_or_default()
+}
+
+/// The message `key` in `locale`, with each `{name}` replaced by the argument of that name.
+/// Messages missing from a catalog fall back to English, and to the key itself after that.
+pub fn translate(locale: Locale, key: &str, args: &[(&str, &str)]) -> String {
+    let template = locale
+        .catalog()
+        .get(key)
+        .or_else(|| Locale::En.catalog().get(key))
+        .map_or(key, String::as_str);
+    args.iter()
+        .fold(template.to_owned(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// The message `key` in the language of the API call being handled.
+pub fn t(key: &str, args: &[(&str, &str)]) -> String {
+    translate(current(), key, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scope, t, translate, Locale};
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Locale::negotiate("zh-CN,zh;q=0.9,en;q=0.8"), Locale::Zh);
+        assert_eq!(Locale::negotiate("en-US,zh;q=0.5"), Locale::En);
+        assert_eq!(Locale::negotiate("fr, zh_TW;q=0.3"), Locale::Zh);
+        assert_eq!(Locale::negotiate("zh;q=0, de"), Locale::En);
+        assert_eq!(Locale::negotiate(""), Locale::En);
+    }
+
+    #[test]
+    fn test_catalogs_have_the_same_keys() {
+        let mut en: Vec<&String> = Locale::En.catalog().keys().collect();
+        let mut zh: Vec<&String> = Locale::Zh.catalog().keys().collect();
+        en.sort();
+        zh.sort();
+        assert_eq!(en, zh);
+    }
+
+    #[tokio::test]
+    async fn test_translate() {
+        assert_eq!(
+            translate(Locale::Zh, "mr.not_open", &[("id", "7")]),
+            "合并请求 7 不处于开放状态"
+        );
+        assert_eq!(translate(Locale::En, "no.such.key", &[]), "no.such.key");
+        assert_eq!(t("mr.not_open", &[("id", "7")]), "merge request 7 is not open");
+        let message = scope(Locale::Zh, async { t("mr.same_branch", &[]) }).await;
+        assert_eq!(message, "源分支与目标分支不能相同");
+    }
+}
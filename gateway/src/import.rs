@@ -0,0 +1,62 @@
+//!
+//! Repository import behind the `mega import` command.
+//!
+//! Clones a repository from an external HTTPS or SSH remote, for example one hosted on GitHub
+//! or GitLab, and stores its branches, tags and history under a monorepo path, recording where
+//! it came from.
+//!
+use std::sync::Arc;
+
+use clap::Args;
+
+use common::errors::MegaError;
+use common::model::CommonOptions;
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::import_storage::ImportStorage;
+use storage::driver::database;
+
+use crate::api_service::event_service::EventService;
+use crate::api_service::import_service::ImportService;
+use crate::api_service::remote::ImportThrottle;
+use crate::model::import::ImportRequest;
+
+pub use crate::model::import::RepoImport;
+
+#[derive(Args, Clone, Debug)]
+pub struct ImportOptions {
+    #[clap(flatten)]
+    pub common: CommonOptions,
+
+    /// HTTPS or SSH url of the repository to import
+    pub url: String,
+
+    /// Monorepo path to import the repository at, e.g. /third-party/mega
+    #[arg(long)]
+    pub path: String,
+
+    /// User recorded as having imported the repository
+    #[arg(long)]
+    pub imported_by: Option<String>,
+}
+
+pub async fn run_import(options: &ImportOptions) -> Result<RepoImport, MegaError> {
+    let data_source = &options.common.data_source;
+    let connection = Arc::new(database::connect(data_source).await);
+    let service = ImportService {
+        storage: database::init(data_source).await,
+        import_storage: ImportStorage::new(connection.clone()),
+        events: EventService {
+            storage: EventStorage::new(connection),
+        },
+        throttle: ImportThrottle::from_env(),
+    };
+    let request = ImportRequest {
+        url: options.url.clone(),
+        path: options.path.clone(),
+        imported_by: options.imported_by.clone(),
+    };
+    match service.import(request).await {
+        Ok(import) => Ok(import.0),
+        Err((status, msg)) => Err(MegaError::new(anyhow::anyhow!(msg), status.as_u16() as i32)),
+    }
+}
@@ -11,11 +11,22 @@ use https_server::AppState;
 use storage::driver::file_storage::local_storage::LocalStorage;
 
 mod api_service;
+pub mod auth;
+pub mod bundle;
+pub mod doctor;
 mod git_protocol;
+pub mod grpc_server;
+pub mod health;
 pub mod https_server;
+mod i18n;
+pub mod import;
 pub mod init;
 mod lfs;
-mod model;
+pub mod model;
+pub mod request_id;
+mod schema;
+pub mod show;
+pub mod shutdown;
 pub mod ssh_server;
 
 impl From<AppState> for LfsConfig {
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_access_token, mega_org, mega_org_member, mega_user};
+
+pub const ROLE_OWNER: &str = "owner";
+pub const ROLE_MEMBER: &str = "member";
+
+/// Profile of an account, without its password.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub is_admin: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_user::Model> for User {
+    fn from(value: mega_user::Model) -> Self {
+        User {
+            id: value.id,
+            name: value.name,
+            display_name: value.display_name,
+            email: value.email,
+            is_admin: value.is_admin,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewUser {
+    /// Letters, digits, `-`, `_` and `.`, shared with organization names
+    pub name: String,
+    pub password: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// Fields of a profile to change, the others are kept.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UserUpdate {
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Changing the password needs the current one
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub current_password: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Org {
+    pub id: i64,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_org::Model> for Org {
+    fn from(value: mega_org::Model) -> Self {
+        Org {
+            id: value.id,
+            name: value.name,
+            display_name: value.display_name,
+            description: value.description,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewOrg {
+    pub name: String,
+    /// The user creating the organization, who becomes its first owner
+    pub owner: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct OrgMember {
+    pub username: String,
+    /// `owner` or `member`
+    pub role: String,
+    pub created_at: String,
+}
+
+impl From<mega_org_member::Model> for OrgMember {
+    fn from(value: mega_org_member::Model) -> Self {
+        OrgMember {
+            username: value.username,
+            role: value.role,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MemberUpdate {
+    /// Defaults to `member`
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+/// An organization a user belongs to, with their role in it.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Membership {
+    #[serde(flatten)]
+    pub org: Org,
+    pub role: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AccessToken {
+    pub id: i64,
+    pub name: String,
+    /// `read`, `write` or `admin`
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+impl From<mega_access_token::Model> for AccessToken {
+    fn from(value: mega_access_token::Model) -> Self {
+        AccessToken {
+            id: value.id,
+            name: value.name,
+            scopes: value.scopes,
+            expires_at: value.expires_at.map(|d| d.to_string()),
+            last_used_at: value.last_used_at.map(|d| d.to_string()),
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewAccessToken {
+    /// Password of the account the token is issued for
+    pub password: String,
+    pub name: String,
+    /// `read` to fetch, `write` to push as well, `admin` for everything; defaults to `read`
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD`, the token never expires when missing
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
+/// A token just issued, the only time the token itself is returned.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IssuedToken {
+    #[serde(flatten)]
+    pub info: AccessToken,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OidcLoginQuery {
+    /// Path of this server to send the browser to after the login, with the issued token in
+    /// the fragment; the token is returned as JSON when it is missing
+    #[serde(default)]
+    pub return_to: Option<String>,
+}
+
+/// What the provider sends the browser back with.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OidcCallbackQuery {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_description: Option<String>,
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_path_grant;
+
+use crate::auth::acl::SUBJECT_USER;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PathGrant {
+    pub id: i64,
+    pub path: String,
+    /// The user the grant is for, when it is not for an organization
+    pub user: Option<String>,
+    pub org: Option<String>,
+    /// `read`, `write`, `maintain` or `admin`
+    pub permission: String,
+    pub granted_by: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_path_grant::Model> for PathGrant {
+    fn from(value: mega_path_grant::Model) -> Self {
+        let (user, org) = if value.subject_kind == SUBJECT_USER {
+            (Some(value.subject), None)
+        } else {
+            (None, Some(value.subject))
+        };
+        PathGrant {
+            id: value.id,
+            path: value.path,
+            user,
+            org,
+            permission: value.permission,
+            granted_by: value.granted_by,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+/// Grant a permission on a path to a user or an organization, one of which is required.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewPathGrant {
+    pub path: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub org: Option<String>,
+    pub permission: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PathGrantQuery {
+    /// Only the grants on this path and below it
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AccessQuery {
+    pub path: String,
+    /// Whose permission to tell, the caller's when it is missing
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+/// The permission a user has on a path, through their grants and those of their organizations.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PathAccess {
+    pub path: String,
+    pub user: Option<String>,
+    /// Absent when the user has no permission on the path
+    pub permission: Option<String>,
+    /// Whether the permissions are enforced, see `MEGA_ACL`
+    pub enforced: bool,
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_archive;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PathArchiveRequest {
+    /// Monorepo path of the directory or repository, e.g. `/projects/mega`
+    pub path: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// When deleting, write a git bundle of every repository to `MEGA_ARCHIVE_BUNDLE_DIR` first
+    #[serde(default)]
+    pub bundle: bool,
+}
+
+/// A ref of an archived or deleted path, as it was when the path was archived or deleted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ArchivedRef {
+    pub repo_path: String,
+    pub ref_name: String,
+    pub ref_id: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PathArchive {
+    pub path: String,
+    /// `archived` or `deleted`
+    pub state: String,
+    pub reason: Option<String>,
+    pub actor: String,
+    pub refs: Vec<ArchivedRef>,
+    /// Bundles written before the path was deleted
+    pub bundles: Vec<String>,
+    /// When the objects of a deleted path may be collected
+    pub gc_after: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_archive::Model> for PathArchive {
+    fn from(value: mega_archive::Model) -> Self {
+        PathArchive {
+            path: value.path,
+            state: value.state,
+            reason: value.reason,
+            actor: value.actor,
+            refs: value
+                .refs
+                .and_then(|refs| serde_json::from_str(&refs).ok())
+                .unwrap_or_default(),
+            bundles: value
+                .bundles
+                .map(|b| b.lines().map(str::to_owned).collect())
+                .unwrap_or_default(),
+            gc_after: value.gc_after.map(|t| t.to_string()),
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_autolink;
+
+/// A link resolved in a text, by an autolink rule or as a reference to a user, issue, merge
+/// request or commit, for clients that don't render markdown.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct Autolink {
+    /// The text linked, e.g. `JIRA-123`
+    pub text: String,
+    pub url: String,
+    /// Byte offsets of the text in the message, the end excluded
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AutolinkRule {
+    pub id: i64,
+    pub pattern: String,
+    pub url_template: String,
+    pub repo_path: String,
+    pub created_at: String,
+}
+
+impl From<mega_autolink::Model> for AutolinkRule {
+    fn from(value: mega_autolink::Model) -> Self {
+        AutolinkRule {
+            id: value.id,
+            pattern: value.pattern,
+            url_template: value.url_template,
+            repo_path: value.repo_path,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewAutolinkRule {
+    /// Regular expression of the references to link, e.g. `JIRA-(\d+)`
+    pub pattern: String,
+    /// Url of a reference, `$0` standing for the whole reference and `$1`, `$2` or `${name}` for
+    /// the groups of the pattern
+    pub url_template: String,
+    /// The rule links the texts of repositories at or below this path
+    #[serde(default = "default_repo_path")]
+    pub repo_path: String,
+}
+
+fn default_repo_path() -> String {
+    "/".to_owned()
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BlameResult {
+    pub path: String,
+    /// The commit the blame was computed at
+    pub commit_id: String,
+    pub hunks: Vec<BlameHunk>,
+    /// Objects missing from the server the history stopped at, the lines they would have told
+    /// about are blamed on the commits naming them
+    pub missing_objects: Vec<String>,
+}
+
+/// Consecutive lines introduced together by one commit.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BlameHunk {
+    pub commit_id: String,
+    /// 1-based line number of the first line in the blamed file
+    pub start_line: usize,
+    /// 1-based line number of the first line in the commit that introduced it
+    pub orig_start_line: usize,
+    pub author: String,
+    pub author_email: String,
+    pub committed_at: usize,
+    pub summary: String,
+    pub lines: Vec<String>,
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_board, mega_board_card, mega_issue, mega_mr};
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+
+use crate::model::mr::status_name;
+use crate::model::planning::ItemLinks;
+
+/// The columns of a board created without any.
+pub const DEFAULT_COLUMNS: &[&str] = &["To do", "In progress", "Done"];
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Board {
+    pub id: i64,
+    pub repo_path: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_board::Model> for Board {
+    fn from(value: mega_board::Model) -> Self {
+        Board {
+            id: value.id,
+            repo_path: value.repo_path,
+            name: value.name,
+            description: value.description,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+/// A board with its columns left to right, and the cards of each top to bottom.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BoardDetail {
+    #[serde(flatten)]
+    pub board: Board,
+    pub columns: Vec<BoardColumn>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BoardColumn {
+    pub id: i64,
+    pub name: String,
+    pub cards: Vec<Card>,
+}
+
+/// An issue or merge request on a board.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Card {
+    pub id: i64,
+    /// `issue` or `mr`
+    pub item_type: String,
+    pub item_id: i64,
+    /// Number of an issue within its repository
+    pub number: Option<i64>,
+    pub title: String,
+    /// The state of an issue or the status of a merge request
+    pub state: String,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub milestone_id: Option<i64>,
+}
+
+impl Card {
+    pub fn issue(
+        card: &mega_board_card::Model,
+        issue: mega_issue::Model,
+        links: ItemLinks,
+    ) -> Self {
+        Card {
+            id: card.id,
+            item_type: ITEM_ISSUE.to_owned(),
+            item_id: issue.id,
+            number: Some(issue.number),
+            title: issue.title,
+            state: issue.state,
+            labels: links.labels,
+            assignees: links.assignees,
+            milestone_id: issue.milestone_id,
+        }
+    }
+
+    pub fn mr(card: &mega_board_card::Model, mr: mega_mr::Model, links: ItemLinks) -> Self {
+        Card {
+            id: card.id,
+            item_type: ITEM_MR.to_owned(),
+            item_id: mr.id,
+            number: None,
+            title: mr.mr_msg,
+            state: status_name(&mr.status).to_owned(),
+            labels: links.labels,
+            assignees: links.assignees,
+            milestone_id: mr.milestone_id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewBoard {
+    pub repo_path: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Names of the columns, `To do`, `In progress` and `Done` when missing
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+}
+
+/// Fields of a board to change, the others are kept.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BoardUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BoardListQuery {
+    pub repo_path: String,
+}
+
+/// Which cards of a board to show, all of them by default.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BoardQuery {
+    /// `issue` or `mr`
+    #[serde(default)]
+    pub item_type: Option<String>,
+    /// State of issues or status of merge requests
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Milestone id
+    #[serde(default)]
+    pub milestone: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewColumn {
+    pub name: String,
+    /// Index among the columns, the column is added last when missing
+    #[serde(default)]
+    pub position: Option<usize>,
+}
+
+/// Fields of a column to change, the others are kept.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ColumnUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Index among the columns to move it to
+    #[serde(default)]
+    pub position: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewCard {
+    /// `issue` or `mr`
+    pub item_type: String,
+    pub item_id: i64,
+    pub column_id: i64,
+    /// Index among the cards of the column, the card is added last when missing
+    #[serde(default)]
+    pub position: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CardMove {
+    pub column_id: i64,
+    /// Index among the cards of the column, the card goes last when missing
+    #[serde(default)]
+    pub position: Option<usize>,
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BundleQuery {
+    /// Monorepo path of the repository the bundle seeds or updates
+    pub repo_path: String,
+}
+
+/// A ref an imported bundle created or moved.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BundledRef {
+    pub ref_name: String,
+    pub ref_id: String,
+    /// Where the ref pointed before, `None` for the refs created
+    pub old_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BundleImport {
+    pub repo_path: String,
+    /// Version of the bundle format, 2 or 3
+    pub version: u8,
+    /// Commits the bundle built on, which the repository had
+    pub prerequisites: usize,
+    pub refs: Vec<BundledRef>,
+    /// Refs of the bundle the repository had already
+    pub unchanged: usize,
+    pub object_count: i64,
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangelogQuery {
+    pub repo_path: String,
+    /// Tag, branch or commit the changelog starts after
+    pub from: String,
+    /// Tag, branch or commit the changelog ends at
+    pub to: String,
+}
+
+/// The commits of `to` missing from `from`, grouped by their conventional-commit type.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Changelog {
+    pub from: String,
+    pub to: String,
+    pub from_id: String,
+    pub to_id: String,
+    /// Changes marked breaking, which are listed in their section too
+    pub breaking: Vec<ChangelogEntry>,
+    /// Sections in the order they are rendered, commits not following the convention last
+    pub sections: Vec<ChangelogSection>,
+    /// Whether older commits were left out of a long range
+    pub truncated: bool,
+    /// The changelog rendered as markdown
+    pub markdown: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ChangelogSection {
+    /// The conventional-commit type, like `feat`, or `other`
+    pub kind: String,
+    pub title: String,
+    /// Newest first
+    pub entries: Vec<ChangelogEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ChangelogEntry {
+    pub commit_id: String,
+    pub scope: Option<String>,
+    pub description: String,
+    pub breaking: bool,
+    pub author: String,
+}
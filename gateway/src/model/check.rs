@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_check_run;
+
+pub const CHECK_PENDING: &str = "pending";
+pub const CHECK_SUCCESS: &str = "success";
+pub const CHECK_FAILURE: &str = "failure";
+pub const CHECK_ERROR: &str = "error";
+/// States a check can report, `error` being a failure of the CI system rather than of the
+/// commit.
+pub const CHECK_STATES: &[&str] = &[CHECK_PENDING, CHECK_SUCCESS, CHECK_FAILURE, CHECK_ERROR];
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CheckRun {
+    pub name: String,
+    pub commit_id: String,
+    /// One of `pending`, `success`, `failure` or `error`
+    pub status: String,
+    pub description: Option<String>,
+    /// Where the details of the run can be found
+    pub target_url: Option<String>,
+    pub reporter: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_check_run::Model> for CheckRun {
+    fn from(value: mega_check_run::Model) -> Self {
+        CheckRun {
+            name: value.name,
+            commit_id: value.commit_id,
+            status: value.status,
+            description: value.description,
+            target_url: value.target_url,
+            reporter: value.reporter,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewCheckRun {
+    pub name: String,
+    pub status: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub target_url: Option<String>,
+}
+
+/// The checks reported for a commit.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CommitChecks {
+    pub commit_id: String,
+    /// `failure` when a check failed or errored, else `pending` while one hasn't finished or a
+    /// required check hasn't reported, else `success`; `success` too without any check
+    pub state: String,
+    pub checks: Vec<CheckRun>,
+    /// Checks the target branch requires, when there is one
+    pub required: Vec<String>,
+    /// The required checks which haven't succeeded
+    pub blocking: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CheckQuery {
+    pub repo_path: String,
+    /// Branch whose required checks are reported as blocking or not
+    #[serde(default)]
+    pub branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RequiredChecks {
+    pub repo_path: String,
+    pub branch: String,
+    pub checks: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RequiredChecksQuery {
+    pub repo_path: String,
+    pub branch: String,
+}
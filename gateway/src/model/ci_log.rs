@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_ci_job, mega_ci_log};
+
+pub const CI_RUNNING: &str = "running";
+/// Final states a job can report when its log is finished.
+pub const CI_FINAL_STATES: &[&str] = &["success", "failure", "cancelled", "skipped"];
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CiLog {
+    pub repo_path: String,
+    pub commit_id: String,
+    pub job: String,
+    /// `running` until the runner finishes the log, then the final state of the job
+    pub status: String,
+    /// Bytes written so far
+    pub size: i64,
+    pub complete: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub finished_at: Option<String>,
+}
+
+impl From<mega_ci_log::Model> for CiLog {
+    fn from(value: mega_ci_log::Model) -> Self {
+        CiLog {
+            repo_path: value.repo_path,
+            commit_id: value.commit_id,
+            job: value.job_name,
+            status: value.status,
+            size: value.size,
+            complete: value.finished_at.is_some(),
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+            finished_at: value.finished_at.map(|d| d.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CiLogQuery {
+    pub repo_path: String,
+    /// Where to start reading, or where an append is expected to go
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CiLogFinish {
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CiJobQuery {
+    pub repo_path: String,
+}
+
+/// A job of a pipeline the server runs, see `.mega/ci.toml`.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CiJob {
+    pub id: i64,
+    pub name: String,
+    pub ref_name: String,
+    pub commit_id: String,
+    /// `queued`, `running`, `success`, `failure`, or `error` when it couldn't be run
+    pub status: String,
+    pub image: Option<String>,
+    pub timeout_secs: i32,
+    pub attempts: i32,
+    pub exit_code: Option<i32>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub finished_at: Option<String>,
+}
+
+impl From<mega_ci_job::Model> for CiJob {
+    fn from(value: mega_ci_job::Model) -> Self {
+        CiJob {
+            id: value.id,
+            name: value.name,
+            ref_name: value.ref_name,
+            commit_id: value.commit_id,
+            status: value.status,
+            image: value.image,
+            timeout_secs: value.timeout_secs,
+            attempts: value.attempts,
+            exit_code: value.exit_code,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+            finished_at: value.finished_at.map(|d| d.to_string()),
+        }
+    }
+}
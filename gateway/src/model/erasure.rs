@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ErasureRequest {
+    /// Name of the user whose personal data is erased
+    pub username: String,
+    /// Name shown in place of the user from now on, defaults to `deleted-user-<id>`
+    #[serde(default)]
+    pub replacement: Option<String>,
+    /// Commit emails of the user besides the ones of the account and its signing keys
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// What an erasure changed. It names the user only by `subject_hash`, the SHA-256 of the user
+/// name, so keeping the report doesn't keep the data it erased.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErasureReport {
+    pub id: i64,
+    pub subject_hash: String,
+    pub replacement: String,
+    pub requested_by: String,
+    pub reason: String,
+    /// Rows rewritten or removed in each table
+    pub rows: BTreeMap<String, u64>,
+    /// Events whose payload mentioned the user
+    pub events_redacted: u64,
+    /// Commit emails now shown as the replacement
+    pub mailmap_entries: usize,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ErasureQuery {
+    /// Only erasures of this user
+    #[serde(default)]
+    pub username: Option<String>,
+}
@@ -0,0 +1,61 @@
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The body of every error of the API.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiError {
+    /// Status of the response, which the body doesn't repeat
+    #[serde(skip)]
+    pub status: StatusCode,
+    /// The status in snake case, such as `not_found` or `conflict`
+    pub code: String,
+    /// What went wrong, in the language of the call
+    pub message: String,
+    /// More about the error, depending on its code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ApiError {
+    /// An error with the code of `status`, and its reason as the message when `message` is empty.
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        let mut message = message.into();
+        if message.is_empty() {
+            message = status.canonical_reason().unwrap_or_default().to_owned();
+        }
+        ApiError {
+            status,
+            code: code(status),
+            message,
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        ApiError::new(status, message)
+    }
+}
+
+/// The code of the errors with `status`: its reason in snake case, `status_<code>` for those
+/// without one.
+pub fn code(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => reason
+            .chars()
+            .filter_map(|c| match c {
+                ' ' | '-' => Some('_'),
+                c if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+                _ => None,
+            })
+            .collect(),
+        None => format!("status_{}", status.as_u16()),
+    }
+}
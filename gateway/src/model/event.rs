@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+
+use db_entity::mega_event;
+
+use crate::model::review::{Review, ReviewComment};
+
+#[derive(Serialize, Deserialize)]
+pub struct RepoEvent {
+    pub id: i64,
+    /// One of `push`, `merge_request`, `issue` or `comment`
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// What happened, e.g. `opened`, `merged` or `created`
+    pub action: String,
+    pub repo_path: String,
+    pub actor: Option<String>,
+    /// The pushed ref, or the merge request, issue or comment as the API returns it
+    pub payload: serde_json::Value,
+    pub created_at: String,
+}
+
+impl From<mega_event::Model> for RepoEvent {
+    fn from(value: mega_event::Model) -> Self {
+        RepoEvent {
+            id: value.id,
+            event_type: value.event_type,
+            action: value.action,
+            repo_path: value.repo_path,
+            actor: value.actor,
+            payload: serde_json::from_str(&value.payload).unwrap_or_default(),
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+/// A ref moved by a push or by the server.
+#[derive(Serialize, Deserialize)]
+pub struct RefPush {
+    #[serde(rename = "ref")]
+    pub ref_name: String,
+    /// Commit the ref pointed to before, absent when it was created
+    pub before: Option<String>,
+    /// Commit the ref points to now, absent when it was deleted
+    pub after: Option<String>,
+    /// Why the server moved the ref, absent for pushes
+    pub reason: Option<String>,
+}
+
+/// Activity in the review of a merge request.
+#[derive(Serialize)]
+pub struct CommentEvent<'a> {
+    pub mr_id: i64,
+    pub thread_id: Option<i64>,
+    pub comment: Option<&'a ReviewComment>,
+    pub review: Option<&'a Review>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventQuery {
+    /// Only events of repositories at or below this path
+    #[serde(default)]
+    pub repo_path: Option<String>,
+    /// Comma separated event types, all types when absent
+    #[serde(default)]
+    pub types: Option<String>,
+    /// Id of the last event seen, only later events are sent
+    #[serde(default)]
+    pub after: Option<i64>,
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_feature_flag;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub description: Option<String>,
+    /// Default state for organizations not listed below
+    pub enabled: bool,
+    pub enabled_orgs: Vec<String>,
+    pub disabled_orgs: Vec<String>,
+    pub updated_at: String,
+}
+
+impl From<mega_feature_flag::Model> for FeatureFlag {
+    fn from(value: mega_feature_flag::Model) -> Self {
+        FeatureFlag {
+            name: value.name,
+            description: value.description,
+            enabled: value.enabled,
+            enabled_orgs: value.enabled_orgs,
+            disabled_orgs: value.disabled_orgs,
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct FeatureFlagUpdate {
+    #[serde(default)]
+    pub description: Option<String>,
+    pub enabled: bool,
+    #[serde(default)]
+    pub enabled_orgs: Vec<String>,
+    #[serde(default)]
+    pub disabled_orgs: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeatureFlagQuery {
+    #[serde(default)]
+    pub org: Option<String>,
+}
+
+/// Effective state of a flag for the caller.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FeatureFlagStatus {
+    pub name: String,
+    pub org: Option<String>,
+    pub enabled: bool,
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_import, mega_import_job, mega_import_job_repo};
+use jupiter::storage::import_storage::{JOB_REPO_DONE, JOB_REPO_FAILED};
+
+use crate::api_service::remote;
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ImportRequest {
+    /// HTTPS or SSH url of the repository to import, e.g. `https://github.com/web3infra-foundation/mega.git`
+    pub url: String,
+    /// Monorepo path the repository is imported at, it must not be in use yet
+    pub path: String,
+    /// User asking for the import, recorded with it
+    #[serde(default)]
+    pub imported_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RepoImport {
+    pub id: i64,
+    pub repo_path: String,
+    /// Where the repository was imported from, without any credentials in the url
+    pub source_url: String,
+    /// Branch HEAD pointed to in the source repository
+    pub default_branch: Option<String>,
+    pub head_commit: Option<String>,
+    /// Branches and tags imported
+    pub ref_count: i32,
+    pub object_count: i64,
+    pub imported_by: String,
+    pub created_at: String,
+}
+
+impl From<mega_import::Model> for RepoImport {
+    fn from(value: mega_import::Model) -> Self {
+        RepoImport {
+            id: value.id,
+            repo_path: value.repo_path,
+            source_url: value.source_url,
+            default_branch: value.default_branch,
+            head_commit: value.head_commit,
+            ref_count: value.ref_count,
+            object_count: value.object_count,
+            imported_by: value.imported_by,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Only the import of this repository
+    #[serde(default)]
+    pub repo_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ImportJobRequest {
+    /// Repositories to import, each at a monorepo path not in use yet
+    pub repos: Vec<ImportJobTarget>,
+    /// User asking for the imports, recorded with each of them
+    #[serde(default)]
+    pub imported_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ImportJobTarget {
+    /// HTTPS or SSH url of the repository
+    pub url: String,
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImportJob {
+    pub id: i64,
+    pub imported_by: String,
+    /// `running` while repositories are left to import, then `done`, or `failed` when some
+    /// failed for good
+    pub status: String,
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+    /// The repositories of the job, only listed for a single job
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<ImportJobRepo>,
+    pub created_at: String,
+}
+
+impl ImportJob {
+    pub fn new(job: mega_import_job::Model, repos: &[mega_import_job_repo::Model]) -> Self {
+        let count = |status: &str| repos.iter().filter(|r| r.status == status).count();
+        let done = count(JOB_REPO_DONE);
+        let failed = count(JOB_REPO_FAILED);
+        let status = if done + failed < repos.len() {
+            "running"
+        } else if failed > 0 {
+            "failed"
+        } else {
+            "done"
+        };
+        ImportJob {
+            id: job.id,
+            imported_by: job.imported_by,
+            status: status.to_owned(),
+            total: repos.len(),
+            done,
+            failed,
+            repos: Vec::new(),
+            created_at: job.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImportJobRepo {
+    /// Without any credentials in the url
+    pub url: String,
+    pub repo_path: String,
+    /// `pending`, `running`, `done` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    /// Whether an attempt started storing the repository, the attempts after it continue from
+    /// the refs it stored
+    pub started_storing: bool,
+    /// When the next attempt is due, absent once the repository is done or failed
+    pub next_attempt_at: Option<String>,
+    pub last_error: Option<String>,
+    /// The import the repository was recorded with once done
+    pub import_id: Option<i64>,
+    pub updated_at: String,
+}
+
+impl From<mega_import_job_repo::Model> for ImportJobRepo {
+    fn from(value: mega_import_job_repo::Model) -> Self {
+        ImportJobRepo {
+            url: remote::strip_credentials(&value.url),
+            repo_path: value.repo_path,
+            status: value.status,
+            attempts: value.attempts,
+            started_storing: value.started_storing,
+            next_attempt_at: value.next_attempt_at.map(|d| d.to_string()),
+            last_error: value.last_error,
+            import_id: value.import_id,
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImportJobRequeued {
+    pub requeued: u64,
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_issue, mega_issue_ref};
+
+use crate::api_service::markdown;
+use crate::model::autolink::Autolink;
+use crate::model::planning::ItemLinks;
+
+pub const ISSUE_OPEN: &str = "open";
+pub const ISSUE_CLOSED: &str = "closed";
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Issue {
+    pub id: i64,
+    /// Number of the issue within its repository, as written in `#42`
+    pub number: i64,
+    pub repo_path: String,
+    pub title: String,
+    /// Markdown
+    pub body: String,
+    /// The body rendered as sanitized HTML
+    #[serde(default)]
+    pub body_html: String,
+    pub author: String,
+    /// `open` or `closed`
+    pub state: String,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub milestone_id: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    /// References in the title linked, by the autolink rules or as mentions
+    #[serde(default)]
+    pub autolinks: Vec<Autolink>,
+    /// References in the body linked, by the autolink rules or as mentions
+    #[serde(default)]
+    pub body_autolinks: Vec<Autolink>,
+}
+
+impl Issue {
+    pub fn new(value: mega_issue::Model, links: ItemLinks) -> Self {
+        Issue {
+            id: value.id,
+            number: value.number,
+            repo_path: value.repo_path,
+            title: value.title,
+            body_html: markdown::render(&value.body),
+            body: value.body,
+            author: value.sender_name,
+            state: value.state,
+            labels: links.labels,
+            assignees: links.assignees,
+            milestone_id: value.milestone_id,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+            closed_at: value.closed_at.map(|d| d.to_string()),
+            autolinks: Vec::new(),
+            body_autolinks: Vec::new(),
+        }
+    }
+}
+
+/// A commit or merge request mentioning an issue.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IssueReference {
+    /// `commit` or `mr`
+    pub source_type: String,
+    /// Commit hash or merge request id
+    pub source_id: String,
+    /// Whether the reference closes the issue once merged, as in `fixes #42`
+    pub closes: bool,
+    pub created_at: String,
+}
+
+impl From<mega_issue_ref::Model> for IssueReference {
+    fn from(value: mega_issue_ref::Model) -> Self {
+        IssueReference {
+            source_type: value.source_type,
+            source_id: value.source_id,
+            closes: value.closes,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IssueDetail {
+    #[serde(flatten)]
+    pub issue: Issue,
+    pub references: Vec<IssueReference>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewIssue {
+    pub repo_path: String,
+    pub title: String,
+    pub author: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub assignees: Vec<String>,
+    #[serde(default)]
+    pub milestone_id: Option<i64>,
+}
+
+/// Fields of an issue to change, the others are kept.
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct IssueUpdate {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct IssueQuery {
+    #[serde(default)]
+    pub repo_path: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Milestone id
+    #[serde(default)]
+    pub milestone: Option<i64>,
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Markdown to preview as it will be rendered.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MarkdownPreview {
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RenderedMarkdown {
+    /// Sanitized HTML
+    pub html: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReadmeQuery {
+    pub repo_path: String,
+    /// Directory below the repository root, the root when missing
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Branch, tag or commit id, defaults to the main branch
+    #[serde(default)]
+    pub refs: Option<String>,
+}
+
+/// The README of a directory, rendered.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Readme {
+    /// Path of the README below the repository root
+    pub path: String,
+    pub blob_id: String,
+    pub content: String,
+    /// The content rendered as sanitized HTML
+    pub html: String,
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_diffstat;
+
+use crate::model::check::CommitChecks;
+
+/// How the source branch is brought into the target branch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Move the target to the source, only possible when the target has not diverged.
+    FastForward,
+    /// Record a commit with both the target and the source as parents.
+    #[default]
+    MergeCommit,
+    /// Record the merged tree as a single new commit on top of the target.
+    Squash,
+}
+
+/// A path that could not be merged.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MergeConflict {
+    pub path: String,
+    pub kind: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MergeCheckQuery {
+    pub repo_path: String,
+    /// Branch, tag or commit id the source would be merged into
+    pub target: String,
+    pub source: String,
+    #[serde(default)]
+    pub strategy: MergeStrategy,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MergeCheck {
+    pub target_id: String,
+    pub source_id: String,
+    pub merge_base: Option<String>,
+    /// One of `up_to_date`, `fast_forward`, `not_fast_forward`, `clean` or `conflicts`
+    pub status: String,
+    pub mergeable: bool,
+    /// Tree of the merge result, present when it needs a new commit
+    pub tree_id: Option<String>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Pairs of refs of a repository to compare in one call.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct MergeBaseBatch {
+    pub repo_path: String,
+    pub pairs: Vec<RefPair>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RefPair {
+    /// Branch, tag or commit id the source is compared with
+    pub target: String,
+    pub source: String,
+}
+
+/// How the source of a pair has diverged from its target.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefComparison {
+    pub target: String,
+    pub source: String,
+    pub target_id: Option<String>,
+    pub source_id: Option<String>,
+    pub merge_base: Option<String>,
+    /// Commits of the source the target lacks
+    pub ahead: Option<usize>,
+    /// Commits of the target the source lacks
+    pub behind: Option<usize>,
+    /// Why the pair could not be compared, e.g. a ref not found, `None` when it was
+    pub error: Option<String>,
+}
+
+/// A commit to apply onto a branch, or to revert on it.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct PickRequest {
+    pub repo_path: String,
+    /// Commit id of the change
+    pub commit: String,
+    /// Branch the new commit goes on, with or without `refs/heads/`
+    pub target: String,
+    /// Parent of a merge commit its change is taken against, counting from 1
+    #[serde(default)]
+    pub mainline: Option<usize>,
+    /// Message of the new commit, defaults to the one git would write
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub committer_name: Option<String>,
+    #[serde(default)]
+    pub committer_email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PickResult {
+    /// The target before the change
+    pub target_id: String,
+    /// One of `applied`, `empty` when the target already has the change, or `conflicts`
+    pub status: String,
+    /// The new commit the target now points to, when applied
+    pub commit_id: Option<String>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareQuery {
+    pub repo_path: String,
+    /// Branch, tag or commit id the head is compared with
+    pub base: String,
+    pub head: String,
+}
+
+/// What the head brings over the base, as a merge request would show it: the commits of the head
+/// the base lacks and the files they change since the merge base.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Comparison {
+    pub base_id: String,
+    pub head_id: String,
+    pub merge_base: Option<String>,
+    /// Commits of the head the base lacks
+    pub ahead: usize,
+    /// Commits of the base the head lacks
+    pub behind: usize,
+    /// The commits ahead, newest first, only the newest ones when there are many
+    pub commits: Vec<ComparedCommit>,
+    pub files: Vec<FileStat>,
+    pub files_changed: usize,
+    pub additions: usize,
+    pub deletions: usize,
+    /// Checks reported for the head, with the ones the base branch requires
+    pub checks: CommitChecks,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ComparedCommit {
+    pub id: String,
+    pub summary: String,
+    pub author: String,
+    pub author_email: String,
+    pub committed_at: usize,
+    /// What the commit changes, when the stat is cached
+    pub stat: Option<DiffStat>,
+}
+
+/// The lines a file gained and lost.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FileStat {
+    pub path: String,
+    /// One of `added`, `modified` or `deleted`
+    pub status: String,
+    pub additions: usize,
+    pub deletions: usize,
+    /// Whether a side isn't text, its lines aren't counted then
+    pub binary: bool,
+}
+
+/// How much a commit or a merge request changes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    pub fn of(files: &[FileStat]) -> Self {
+        DiffStat {
+            files_changed: files.len(),
+            additions: files.iter().map(|f| f.additions).sum(),
+            deletions: files.iter().map(|f| f.deletions).sum(),
+        }
+    }
+}
+
+impl From<mega_diffstat::Model> for DiffStat {
+    fn from(value: mega_diffstat::Model) -> Self {
+        DiffStat {
+            files_changed: value.files_changed as usize,
+            additions: value.additions as usize,
+            deletions: value.deletions as usize,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_merge_queue;
+
+/// A merge request in the merge queue of the branch it targets, or how it left the queue.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MergeQueueEntry {
+    pub mr_id: i64,
+    pub repo_path: String,
+    pub target_ref: String,
+    /// `waiting` to be merged onto the entries before it, `testing` while the checks of the
+    /// merge run, then `merged`, `failed` or `removed`
+    pub status: String,
+    /// 1 for the next entry to merge, none once it left the queue
+    pub position: Option<usize>,
+    /// Branch the merge is pushed to for its checks to run
+    pub queue_ref: Option<String>,
+    /// Head of the source branch which was merged
+    pub source_commit_id: Option<String>,
+    /// What the source branch was merged onto: the target branch, or the merge of the entry
+    /// before it
+    pub base_commit_id: Option<String>,
+    /// The merge, which the target branch is moved to once its checks succeeded
+    pub head_commit_id: Option<String>,
+    /// Why the entry failed or was removed
+    pub reason: Option<String>,
+    pub enqueued_by: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl MergeQueueEntry {
+    pub fn new(
+        value: mega_merge_queue::Model,
+        position: Option<usize>,
+        queue_ref: Option<String>,
+    ) -> Self {
+        MergeQueueEntry {
+            mr_id: value.mr_id,
+            repo_path: value.repo_path,
+            target_ref: value.target_ref,
+            status: value.status,
+            position,
+            queue_ref,
+            source_commit_id: value.source_commit_id,
+            base_commit_id: value.base_commit_id,
+            head_commit_id: value.head_commit_id,
+            reason: value.reason,
+            enqueued_by: value.enqueued_by,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MergeQueueQuery {
+    pub repo_path: String,
+    /// With or without the `refs/heads/` prefix
+    pub branch: String,
+}
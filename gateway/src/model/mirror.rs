@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_mirror;
+
+use crate::api_service::mirror_service::DEFAULT_REFSPECS;
+use crate::api_service::remote;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Mirror {
+    pub id: i64,
+    pub repo_path: String,
+    /// Upstream url, without any credentials in it
+    pub url: String,
+    pub interval_secs: i64,
+    pub force: bool,
+    pub prune: bool,
+    pub enabled: bool,
+    /// Refspecs mapping the upstream refs to those of the mirror
+    pub refspecs: Vec<String>,
+    pub next_sync_at: String,
+    pub last_sync_at: Option<String>,
+    /// `ok`, `conflicts` or `failed`
+    pub last_status: Option<String>,
+    pub last_message: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_mirror::Model> for Mirror {
+    fn from(value: mega_mirror::Model) -> Self {
+        Mirror {
+            id: value.id,
+            repo_path: value.repo_path,
+            url: remote::strip_credentials(&value.url),
+            interval_secs: value.interval_secs,
+            force: value.force,
+            prune: value.prune,
+            enabled: value.enabled,
+            refspecs: match value.refspecs {
+                Some(refspecs) => refspecs.lines().map(str::to_owned).collect(),
+                None => DEFAULT_REFSPECS.iter().map(|s| s.to_string()).collect(),
+            },
+            next_sync_at: value.next_sync_at.to_string(),
+            last_sync_at: value.last_sync_at.map(|d| d.to_string()),
+            last_status: value.last_status,
+            last_message: value.last_message,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct MirrorUpdate {
+    /// Repository kept in sync with the upstream
+    pub repo_path: String,
+    /// HTTPS or SSH url of the upstream repository
+    pub url: String,
+    /// Seconds between two syncs, at least a minute
+    #[serde(default = "default_interval")]
+    pub interval_secs: i64,
+    /// Move branches and tags to where the upstream has them even when that drops local commits
+    #[serde(default)]
+    pub force: bool,
+    /// Delete the branches and tags the upstream no longer has
+    #[serde(default)]
+    pub prune: bool,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Refspecs like `refs/heads/release/*:refs/heads/release/*` or `^refs/heads/tmp/*`, the
+    /// branches and tags under their own names when empty. A `+` forces the refs it maps
+    #[serde(default)]
+    pub refspecs: Vec<String>,
+}
+
+fn default_interval() -> i64 {
+    3600
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Outcome of a sync with the upstream.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MirrorSync {
+    /// `ok`, or `conflicts` when some refs were left alone
+    pub status: String,
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Refs that diverged from the upstream and were not forced
+    pub conflicts: Vec<String>,
+    pub object_count: i64,
+}
@@ -1,2 +1,44 @@
+pub mod account;
+pub mod acl;
+pub mod archive;
+pub mod autolink;
+pub mod blame;
+pub mod board;
+pub mod bundle;
+pub mod changelog;
+pub mod check;
+pub mod ci_log;
+pub mod erasure;
+pub mod error;
+pub mod event;
+pub mod feature_flag;
+pub mod import;
+pub mod issue;
+pub mod markdown;
+pub mod merge;
+pub mod merge_queue;
+pub mod mirror;
+pub mod mr;
+pub mod notification;
 pub mod objects;
+pub mod operation;
+pub mod page;
+pub mod path_move;
+pub mod planning;
 pub mod query;
+pub mod push_profile;
+pub mod reaction;
+pub mod ref_hook;
+pub mod ref_trigger;
+pub mod reference;
+pub mod release;
+pub mod repair;
+pub mod review;
+pub mod search;
+pub mod signing_key;
+pub mod snapshot_export;
+pub mod snippet;
+pub mod ssh_key;
+pub mod stats;
+pub mod webhook;
+pub mod wiki;
@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{db_enums::MergeStatus, mega_mr};
+
+use crate::i18n;
+use crate::model::autolink::Autolink;
+use crate::model::check::CommitChecks;
+use crate::model::ci_log::CiLog;
+use crate::model::merge::{DiffStat, MergeCheck, MergeStrategy};
+use crate::model::planning::ItemLinks;
+use crate::model::review::CodeOwnersReview;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MergeRequest {
+    pub id: i64,
+    pub title: String,
+    pub repo_path: String,
+    /// Full name of the branch being merged, e.g. `refs/heads/feature`
+    pub source_ref: String,
+    pub target_ref: String,
+    /// One of `draft`, `open`, `approved`, `reopened`, `merged` or `closed`
+    pub status: String,
+    /// What the status means, in the language asked for with `Accept-Language`
+    pub status_description: String,
+    pub merge_commit_id: Option<String>,
+    pub merge_date: Option<String>,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub milestone_id: Option<i64>,
+    /// Who opened the merge request, when they were identified
+    pub author: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// References in the title linked, by the autolink rules or as mentions
+    #[serde(default)]
+    pub autolinks: Vec<Autolink>,
+    /// What the head of the source branch changes since the merge base, once computed
+    #[serde(default)]
+    pub diffstat: Option<DiffStat>,
+}
+
+impl MergeRequest {
+    pub fn new(value: mega_mr::Model, links: ItemLinks) -> Self {
+        MergeRequest {
+            id: value.id,
+            title: value.mr_msg,
+            repo_path: value.repo_path,
+            source_ref: value.source_ref,
+            target_ref: value.target_ref,
+            status: status_name(&value.status).to_owned(),
+            status_description: i18n::t(
+                &format!("mr.status.{}", status_name(&value.status)),
+                &[],
+            ),
+            merge_commit_id: value.merge_commit_id,
+            merge_date: value.merge_date.map(|d| d.to_string()),
+            labels: links.labels,
+            assignees: links.assignees,
+            milestone_id: value.milestone_id,
+            author: value.author,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+            autolinks: Vec::new(),
+            diffstat: None,
+        }
+    }
+}
+
+/// A merge request together with whether it can currently be merged.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MergeRequestDetail {
+    #[serde(flatten)]
+    pub mr: MergeRequest,
+    /// Present while the request is open and both branches exist
+    pub check: Option<MergeCheck>,
+    /// Logs of the CI jobs run for the head of the source branch
+    pub ci_logs: Vec<CiLog>,
+    /// Checks reported for the head of the source branch, present with `check`
+    pub checks: Option<CommitChecks>,
+    /// Approvals the code owners of the changed files have to give, present with `check` when
+    /// the target branch has a `CODEOWNERS` file
+    pub code_owners: Option<CodeOwnersReview>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewMergeRequest {
+    pub repo_path: String,
+    pub title: String,
+    /// Branch name, with or without the `refs/heads/` prefix
+    pub source: String,
+    pub target: String,
+    /// Open the request as a draft, which can't be merged until it is marked ready
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub assignees: Vec<String>,
+    #[serde(default)]
+    pub milestone_id: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MergeRequestQuery {
+    #[serde(default)]
+    pub repo_path: Option<String>,
+    /// States separated by commas, or `active` for the ones neither merged nor closed
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Milestone id
+    #[serde(default)]
+    pub milestone: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct MergeOptions {
+    #[serde(default)]
+    pub strategy: MergeStrategy,
+    /// Message of the merge commit, defaults to one naming both branches
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub committer_name: Option<String>,
+    #[serde(default)]
+    pub committer_email: Option<String>,
+}
+
+/// How a source branch that fell behind its target catches up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStrategy {
+    /// Merge the target into the source with a merge commit.
+    #[default]
+    Merge,
+    /// Replay the commits of the source on top of the target, rewriting the source branch.
+    Rebase,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct UpdateBranchOptions {
+    #[serde(default)]
+    pub strategy: UpdateStrategy,
+    #[serde(default)]
+    pub committer_name: Option<String>,
+    #[serde(default)]
+    pub committer_email: Option<String>,
+}
+
+/// The source branch of a merge request brought up to date with its target.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BranchUpdate {
+    pub strategy: UpdateStrategy,
+    /// Head of the source branch before the update
+    pub old_id: String,
+    /// Head of the source branch now
+    pub new_id: String,
+    /// Commits of the source replayed by a rebase
+    pub replayed: Option<usize>,
+}
+
+pub fn status_name(status: &MergeStatus) -> &'static str {
+    match status {
+        MergeStatus::Draft => "draft",
+        MergeStatus::Open => "open",
+        MergeStatus::Approved => "approved",
+        MergeStatus::Merged => "merged",
+        MergeStatus::Closed => "closed",
+        MergeStatus::Reopened => "reopened",
+    }
+}
+
+pub fn parse_status(name: &str) -> Option<MergeStatus> {
+    match name {
+        "draft" => Some(MergeStatus::Draft),
+        "open" => Some(MergeStatus::Open),
+        "approved" => Some(MergeStatus::Approved),
+        "merged" => Some(MergeStatus::Merged),
+        "closed" => Some(MergeStatus::Closed),
+        "reopened" => Some(MergeStatus::Reopened),
+        _ => None,
+    }
+}
+
+/// Parse the states of a list filter, separated by commas, `active` standing for the states
+/// of [`MergeStatus::ACTIVE`]. Returns the name which isn't a state on error.
+pub fn parse_statuses(names: &str) -> Result<Vec<MergeStatus>, &str> {
+    let mut statuses = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if name == "active" {
+            statuses.extend(MergeStatus::ACTIVE);
+        } else {
+            statuses.push(parse_status(name).ok_or(name)?);
+        }
+    }
+    Ok(statuses)
+}
+
+/// Whether a merge request can go from state `from` to state `to`.
+///
+/// A draft is marked ready for review, open, or approved when reviewers already approved it.
+/// Open and reopened requests are approved by reviews, and go back to open when an approval is
+/// withdrawn. Any of them but a draft can be merged, any of them can be closed or made a draft,
+/// and a closed request can be reopened. A merged request stays merged.
+pub fn can_transition(from: &MergeStatus, to: &MergeStatus) -> bool {
+    use MergeStatus::*;
+    matches!(
+        (from, to),
+        (Draft, Open | Approved | Closed)
+            | (Open | Reopened, Draft | Approved | Merged | Closed)
+            | (Approved, Draft | Open | Merged | Closed)
+            | (Closed, Reopened)
+    )
+}
+
+/// The state an active merge request moves to once its reviews add up to approved, or no
+/// longer do; none when it stays as it is.
+pub fn review_transition(status: &MergeStatus, approved: bool) -> Option<MergeStatus> {
+    match (status, approved) {
+        (MergeStatus::Open | MergeStatus::Reopened, true) => Some(MergeStatus::Approved),
+        (MergeStatus::Approved, false) => Some(MergeStatus::Open),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db_entity::db_enums::MergeStatus;
+
+    use super::{can_transition, parse_statuses, review_transition};
+
+    #[test]
+    fn test_can_transition() {
+        use MergeStatus::*;
+        assert!(can_transition(&Draft, &Open));
+        assert!(!can_transition(&Draft, &Merged));
+        assert!(can_transition(&Open, &Merged));
+        assert!(can_transition(&Reopened, &Approved));
+        assert!(can_transition(&Approved, &Draft));
+        assert!(can_transition(&Closed, &Reopened));
+        assert!(!can_transition(&Closed, &Merged));
+        assert!(!can_transition(&Open, &Reopened));
+        for to in [Draft, Open, Approved, Closed, Reopened] {
+            assert!(!can_transition(&Merged, &to));
+        }
+    }
+
+    #[test]
+    fn test_review_transition() {
+        use MergeStatus::*;
+        assert_eq!(review_transition(&Reopened, true), Some(Approved));
+        assert_eq!(review_transition(&Approved, false), Some(Open));
+        assert_eq!(review_transition(&Approved, true), None);
+        assert_eq!(review_transition(&Draft, true), None);
+        assert_eq!(review_transition(&Closed, true), None);
+    }
+
+    #[test]
+    fn test_parse_statuses() {
+        use MergeStatus::*;
+        assert_eq!(parse_statuses("merged, closed"), Ok(vec![Merged, Closed]));
+        assert_eq!(
+            parse_statuses("active"),
+            Ok(vec![Draft, Open, Approved, Reopened])
+        );
+        assert_eq!(parse_statuses("open,wip"), Err("wip"));
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_notification, mega_notification_pref, mega_subscription};
+
+/// Something that happened to an issue or merge request a user follows.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Notification {
+    pub id: i64,
+    pub repo_path: String,
+    /// `issue` or `mr`
+    pub subject_type: String,
+    pub subject_id: i64,
+    pub title: String,
+    /// What happened, the action of the event, e.g. `opened`, `merged` or `reviewed`
+    pub action: String,
+    pub actor: Option<String>,
+    /// Why the user was notified: `author`, `assigned`, `mentioned`, `participating` or
+    /// `manual` when they subscribed themselves
+    pub reason: String,
+    pub read: bool,
+    pub created_at: String,
+}
+
+impl From<mega_notification::Model> for Notification {
+    fn from(value: mega_notification::Model) -> Self {
+        Notification {
+            id: value.id,
+            repo_path: value.repo_path,
+            subject_type: value.subject_type,
+            subject_id: value.subject_id,
+            title: value.title,
+            action: value.action,
+            actor: value.actor,
+            reason: value.reason,
+            read: value.read_at.is_some(),
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NotificationFeed {
+    /// How many notifications of the user are unread, of all of them
+    pub unread: u64,
+    pub notifications: Vec<Notification>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationQuery {
+    /// Only the unread notifications
+    #[serde(default)]
+    pub unread: bool,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct MarkRead {
+    /// The notifications to mark as read, all of them when missing
+    #[serde(default)]
+    pub ids: Option<Vec<i64>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MarkedRead {
+    /// How many of the notifications were unread
+    pub marked: u64,
+}
+
+/// How a user wants to be notified.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct NotificationPrefs {
+    /// Show notifications in the feed, they are stored as read otherwise
+    pub in_app: bool,
+    /// Email notifications, when the server can send mail and the user has an address
+    pub email: bool,
+    /// Only notify of the issues and merge requests the user takes part in, not of those they
+    /// subscribed to
+    pub participating_only: bool,
+}
+
+impl Default for NotificationPrefs {
+    fn default() -> Self {
+        NotificationPrefs {
+            in_app: true,
+            email: true,
+            participating_only: false,
+        }
+    }
+}
+
+impl From<mega_notification_pref::Model> for NotificationPrefs {
+    fn from(value: mega_notification_pref::Model) -> Self {
+        NotificationPrefs {
+            in_app: value.in_app,
+            email: value.email,
+            participating_only: value.participating_only,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetSubscription {
+    pub subscribed: bool,
+}
+
+/// Whether a user follows an issue or merge request.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Subscription {
+    pub username: String,
+    pub subject_type: String,
+    pub subject_id: i64,
+    pub subscribed: bool,
+    pub reason: String,
+}
+
+impl From<mega_subscription::Model> for Subscription {
+    fn from(value: mega_subscription::Model) -> Self {
+        Subscription {
+            username: value.username,
+            subject_type: value.subject_type,
+            subject_id: value.subject_id,
+            subscribed: value.subscribed,
+            reason: value.reason,
+        }
+    }
+}
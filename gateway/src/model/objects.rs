@@ -1,22 +1,33 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use entity::{node, repo_directory};
 
-#[derive(Serialize, Deserialize)]
+use crate::model::autolink::Autolink;
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Directories {
     pub items: Vec<Item>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Item {
     pub id: String,
     pub name: String,
     pub path: String,
+    /// `file`, `directory`, `submodule`, or `missing` for an entry whose object is missing from
+    /// the server
     pub content_type: String,
     pub under_repo: bool,
     pub commit_msg: Option<String>,
     pub commit_date: Option<String>,
     pub commit_id: Option<String>,
+    /// References in the commit message the autolink rules link
+    #[serde(default)]
+    pub commit_autolinks: Vec<Autolink>,
+    /// Where a gitlink points, for items of type `submodule`
+    #[serde(default)]
+    pub submodule: Option<SubmoduleLink>,
 }
 
 impl From<node::Model> for Item {
@@ -35,6 +46,8 @@ impl From<node::Model> for Item {
             commit_msg: None,
             commit_date: None,
             commit_id: Some(val.last_commit),
+            commit_autolinks: Vec::new(),
+            submodule: None,
         }
     }
 }
@@ -50,11 +63,130 @@ impl From<repo_directory::Model> for Item {
             commit_msg: None,
             commit_date: None,
             commit_id: None,
+            commit_autolinks: Vec::new(),
+            submodule: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The repository and commit a gitlink pins.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SubmoduleLink {
+    /// Url of the repository, `None` when `.gitmodules` doesn't name the gitlink
+    pub url: Option<String>,
+    pub branch: Option<String>,
+    pub commit_id: String,
+}
+
+/// A submodule of `.gitmodules`, with the commit its gitlink pins.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Submodule {
+    pub name: String,
+    /// Path of the gitlink below the repository root
+    pub path: String,
+    /// Url of the repository, relative urls resolved against the one of this repository
+    pub url: String,
+    pub branch: Option<String>,
+    /// `None` when the tree has no gitlink at the path
+    pub commit_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct BlobObjects {
     pub row_data: String,
+    /// Line endings of the content as `git ls-files --eol` shows them: `lf`, `crlf`, `mixed`,
+    /// `none`, or `-text` for binary content
+    pub eol: String,
+    /// The `text` and `eol` attributes of the path of the blob, e.g. `text eol=crlf`, when the
+    /// path was given and `.gitattributes` sets either
+    pub eol_attributes: Option<String>,
+    /// The content highlighted, when asked for with `highlight=true` and the blob isn't too
+    /// large for it
+    pub highlighted: Option<Highlighted>,
+}
+
+/// How a highlighted blob is returned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightFormat {
+    /// Each line as HTML
+    #[default]
+    Html,
+    /// Each line as its tokens
+    Tokens,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Highlighted {
+    /// Name of the syntax the content was highlighted as, e.g. `Rust` or `Plain Text`
+    pub language: String,
+    /// Each line as HTML, its tokens in nested `<span>`s with the atoms of their scopes as
+    /// classes, e.g. `<span class="keyword control rust">`
+    pub html: Option<Vec<String>>,
+    /// Each line as its tokens
+    pub tokens: Option<Vec<Vec<HighlightToken>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct HighlightToken {
+    pub text: String,
+    /// Scopes of the token, outermost first, e.g. `["source.rust", "keyword.control.rust"]`
+    pub scopes: Vec<String>,
+}
+
+/// The type of an object asked for in a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+}
+
+impl ObjectKind {
+    /// The `object_type` of the object table.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Blob => "blob",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ObjectRequest {
+    #[serde(rename = "type")]
+    pub kind: ObjectKind,
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ObjectBatch {
+    pub repo_path: String,
+    pub requests: Vec<ObjectRequest>,
+}
+
+/// The experimental APIs this server has turned on.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Capabilities {
+    pub capabilities: Vec<String>,
+}
+
+/// A commit of the history of a ref.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CommitSummary {
+    pub id: String,
+    pub summary: String,
+    pub author: String,
+    pub author_email: String,
+    pub committed_at: usize,
+    pub parent_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefInfo {
+    /// Full name, like `refs/heads/main`
+    pub name: String,
+    pub id: String,
 }
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use common::operation::Operation;
+
+/// A long operation running on the server.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct OperationStatus {
+    pub id: i64,
+    /// `pack`, `import`, `mirror_sync`, `history_split` or `repair`
+    pub kind: String,
+    /// Repository the operation works on
+    pub target: String,
+    /// What the operation does at the moment, like `cloning` or `writing objects`
+    pub stage: String,
+    /// Steps of the stage done and how many there are, 0 while that isn't known
+    pub done: u64,
+    pub total: u64,
+    /// How much of the stage is done
+    pub percent: Option<u8>,
+    /// Whether the operation was cancelled, it stops at its next step
+    pub cancelled: bool,
+    pub started_at: String,
+}
+
+impl From<&Operation> for OperationStatus {
+    fn from(value: &Operation) -> Self {
+        let (done, total) = value.progress();
+        OperationStatus {
+            id: value.id(),
+            kind: value.kind().to_string(),
+            target: value.target().to_owned(),
+            stage: value.stage().to_owned(),
+            done,
+            total,
+            percent: value.percent(),
+            cancelled: value.is_cancelled(),
+            started_at: chrono::DateTime::<chrono::Utc>::from(value.started_at())
+                .naive_utc()
+                .to_string(),
+        }
+    }
+}
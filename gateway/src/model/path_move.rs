@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_path_redirect;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PathMove {
+    /// Monorepo path of the directory to move, e.g. `/projects/mega`
+    pub from: String,
+    pub to: String,
+    /// Message of the rename commit, defaults to one naming both paths
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub committer_name: Option<String>,
+    #[serde(default)]
+    pub committer_email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PathMoveResult {
+    pub from: String,
+    pub to: String,
+    /// Repository whose tree contained the directory, it got the rename commit
+    pub repo_path: Option<String>,
+    pub commit_id: Option<String>,
+    /// Repositories at or below the old path, now below the new one
+    pub moved_repos: Vec<String>,
+    /// CODEOWNERS files rewritten in the rename commit
+    pub codeowners: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PathRedirect {
+    pub from_path: String,
+    pub to_path: String,
+    /// Commit that moved the directory, history before it is found under `from_path`
+    pub commit_id: Option<String>,
+    pub actor: String,
+    pub created_at: String,
+}
+
+impl From<mega_path_redirect::Model> for PathRedirect {
+    fn from(value: mega_path_redirect::Model) -> Self {
+        PathRedirect {
+            from_path: value.from_path,
+            to_path: value.to_path,
+            commit_id: value.commit_id,
+            actor: value.actor,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_label, mega_milestone};
+
+pub const MILESTONE_OPEN: &str = "open";
+pub const MILESTONE_CLOSED: &str = "closed";
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Label {
+    pub id: i64,
+    pub repo_path: String,
+    pub name: String,
+    /// Hex color such as `#d73a4a`
+    pub color: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_label::Model> for Label {
+    fn from(value: mega_label::Model) -> Self {
+        Label {
+            id: value.id,
+            repo_path: value.repo_path,
+            name: value.name,
+            color: value.color,
+            description: value.description,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewLabel {
+    pub repo_path: String,
+    pub name: String,
+    /// Defaults to a light gray
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Fields of a label to change, the others are kept.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LabelUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Milestone {
+    pub id: i64,
+    pub repo_path: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// `YYYY-MM-DD`
+    pub due_date: Option<String>,
+    /// `open` or `closed`
+    pub state: String,
+    pub open_issues: i64,
+    pub closed_issues: i64,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+}
+
+impl Milestone {
+    pub fn new(value: mega_milestone::Model, open_issues: i64, closed_issues: i64) -> Self {
+        Milestone {
+            id: value.id,
+            repo_path: value.repo_path,
+            title: value.title,
+            description: value.description,
+            due_date: value.due_date.map(|d| d.to_string()),
+            state: value.state,
+            open_issues,
+            closed_issues,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+            closed_at: value.closed_at.map(|d| d.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewMilestone {
+    pub repo_path: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub due_date: Option<String>,
+}
+
+/// Fields of a milestone to change, the others are kept. An empty `due_date` removes it.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MilestoneUpdate {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub due_date: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PlanningQuery {
+    pub repo_path: String,
+    /// Only used for milestones
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+/// Label names and assignees of an issue or merge request.
+#[derive(Debug, Default)]
+pub struct ItemLinks {
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+}
+
+/// Label names of an issue or merge request, replacing the current ones.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ItemLabels {
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ItemAssignees {
+    pub assignees: Vec<String>,
+}
+
+/// Milestone of an issue or merge request, `null` takes it out of its milestone.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ItemMilestone {
+    pub milestone_id: Option<i64>,
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_push_profile;
+
+/// Where the time of one push went.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PushProfile {
+    pub id: i64,
+    pub repo_path: String,
+    pub pusher: Option<String>,
+    /// `http` or `ssh`
+    pub transport: String,
+    /// `ok`, or `rejected` with the `reason`
+    pub status: String,
+    pub reason: Option<String>,
+    pub pack_bytes: i64,
+    pub object_count: i64,
+    pub total_ms: i64,
+    pub slowest_stage: Option<String>,
+    /// The stages which ran, in the order a push goes through them
+    pub stages: Vec<PushStageTiming>,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PushStageTiming {
+    /// `receive`, `index`, `delta-resolve`, `policy-hooks`, `db-write` or `ref-update`
+    pub stage: String,
+    pub ms: i64,
+}
+
+impl From<mega_push_profile::Model> for PushProfile {
+    fn from(value: mega_push_profile::Model) -> Self {
+        PushProfile {
+            id: value.id,
+            repo_path: value.repo_path,
+            pusher: value.pusher,
+            transport: value.transport,
+            status: value.status,
+            reason: value.reason,
+            pack_bytes: value.pack_bytes,
+            object_count: value.object_count,
+            total_ms: value.total_ms,
+            slowest_stage: value.slowest_stage,
+            stages: serde_json::from_str(&value.stages).unwrap_or_default(),
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PushProfileQuery {
+    /// Only pushes to this path and below it
+    #[serde(default)]
+    pub repo_path: Option<String>,
+    /// Only pushes which took at least this long
+    #[serde(default)]
+    pub min_ms: Option<i64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
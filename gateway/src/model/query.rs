@@ -1,6 +1,8 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DirectoryQuery {
     #[serde(default)] // Use default value if not provided in the query string
     pub object_id: Option<String>,
@@ -11,3 +13,53 @@ pub struct DirectoryQuery {
 fn default_path() -> String {
     "/".to_string()
 }
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BlameQuery {
+    pub repo_path: String,
+    /// File path relative to the repository root
+    pub path: String,
+    /// Branch, tag or commit id, defaults to the main branch
+    #[serde(default)]
+    pub refs: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnapshotQuery {
+    pub repo_path: String,
+    /// Branch, tag or commit id, defaults to the main branch
+    #[serde(default, rename = "ref", alias = "refs")]
+    pub refs: Option<String>,
+    /// Directory or file to archive, relative to the repository root, the whole tree by default
+    #[serde(default)]
+    pub path: Option<String>,
+    /// `tar.gz`, `tar` or `zip`, `tar.gz` by default
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubmoduleQuery {
+    pub repo_path: String,
+    /// Branch, tag or commit id, defaults to the main branch
+    #[serde(default)]
+    pub refs: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CommitLogQuery {
+    pub repo_path: String,
+    /// Branch, tag or commit id to start from, defaults to the main branch
+    #[serde(default)]
+    pub refs: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RefQuery {
+    pub repo_path: String,
+}
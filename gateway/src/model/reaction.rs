@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The reactions of one content to an issue, merge request or review comment.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Reaction {
+    /// `+1`, `-1`, `laugh`, `hooray`, `confused`, `heart`, `rocket` or `eyes`
+    pub content: String,
+    pub count: u64,
+    /// Whether the caller reacted so
+    pub reacted: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewReaction {
+    pub content: String,
+}
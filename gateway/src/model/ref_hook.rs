@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_ref_hook_retry;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefHookStatus {
+    pub name: String,
+    /// Id of the last push event handed to the hook, absent before the hook first ran
+    pub last_event_id: Option<i64>,
+    /// Events the hook failed on, including the ones it gave up on
+    pub retries: Vec<RefHookRetry>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefHookRetry {
+    pub event_id: i64,
+    pub attempts: i32,
+    /// Absent once the hook gave up, `POST /admin/ref-hooks/{name}/retry` tries again
+    pub next_attempt_at: Option<String>,
+    pub last_error: String,
+    pub created_at: String,
+}
+
+impl From<mega_ref_hook_retry::Model> for RefHookRetry {
+    fn from(value: mega_ref_hook_retry::Model) -> Self {
+        RefHookRetry {
+            event_id: value.event_id,
+            attempts: value.attempts,
+            next_attempt_at: value.next_attempt_at.map(|d| d.to_string()),
+            last_error: value.last_error,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefHookRequeued {
+    pub requeued: u64,
+}
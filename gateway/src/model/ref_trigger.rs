@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{db_enums::RefType, mega_ref_audit, mega_ref_trigger};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefTrigger {
+    pub name: String,
+    pub repo_path: String,
+    /// Ref the created ref points at, e.g. `refs/heads/main`
+    pub source_ref: String,
+    /// `branch` or `tag`
+    pub ref_type: String,
+    pub name_template: String,
+    pub schedule: String,
+    pub enabled: bool,
+    pub next_run_at: String,
+    pub last_run_at: Option<String>,
+    pub last_ref: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_ref_trigger::Model> for RefTrigger {
+    fn from(value: mega_ref_trigger::Model) -> Self {
+        RefTrigger {
+            name: value.name,
+            repo_path: value.repo_path,
+            source_ref: value.source_ref,
+            ref_type: ref_type_name(&value.ref_type).to_owned(),
+            name_template: value.name_template,
+            schedule: value.schedule,
+            enabled: value.enabled,
+            next_run_at: value.next_run_at.to_string(),
+            last_run_at: value.last_run_at.map(|d| d.to_string()),
+            last_ref: value.last_ref,
+            last_error: value.last_error,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RefTriggerUpdate {
+    pub repo_path: String,
+    /// Branch name or full ref name
+    pub source: String,
+    /// `branch` or `tag`
+    pub ref_type: String,
+    /// Name of the created branch or tag, with placeholders such as `{date}` filled in per run
+    pub name_template: String,
+    pub schedule: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The ref created by a run of a trigger.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefTriggerRun {
+    pub ref_name: String,
+    pub commit_id: String,
+    /// Id of the annotated tag object, for tags
+    pub tag_id: Option<String>,
+    pub signed: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefAuditEntry {
+    pub ref_name: String,
+    pub old_id: Option<String>,
+    pub new_id: String,
+    pub actor: String,
+    pub reason: String,
+    pub created_at: String,
+}
+
+impl From<mega_ref_audit::Model> for RefAuditEntry {
+    fn from(value: mega_ref_audit::Model) -> Self {
+        RefAuditEntry {
+            ref_name: value.ref_name,
+            old_id: value.old_id,
+            new_id: value.new_id,
+            actor: value.actor,
+            reason: value.reason,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RefAuditQuery {
+    pub repo_path: String,
+    #[serde(default)]
+    pub ref_name: Option<String>,
+}
+
+pub fn ref_type_name(ref_type: &RefType) -> &'static str {
+    match ref_type {
+        RefType::Branch => "branch",
+        RefType::Tag => "tag",
+    }
+}
+
+pub fn parse_ref_type(name: &str) -> Option<RefType> {
+    match name {
+        "branch" => Some(RefType::Branch),
+        "tag" => Some(RefType::Tag),
+        _ => None,
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_reference;
+use jupiter::storage::label_storage::{ITEM_ISSUE, ITEM_MR};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BacklinkQuery {
+    pub repo_path: String,
+    /// `user`, `issue`, `mr` or `commit`
+    pub target_type: String,
+    /// The user name, issue or merge request id, or full commit id
+    pub target_id: String,
+}
+
+/// A text referring to a user, issue, merge request or commit.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Backlink {
+    /// `issue` or `mr` for their title and body, `comment` or `review`
+    pub source_type: String,
+    pub source_id: i64,
+    /// `issue` or `mr`, the text belongs to
+    pub subject_type: String,
+    pub subject_id: i64,
+    /// The reference as written, e.g. `#12`
+    pub text: String,
+    pub created_at: String,
+}
+
+impl From<mega_reference::Model> for Backlink {
+    fn from(value: mega_reference::Model) -> Self {
+        // comments and reviews are on merge requests
+        let subject_type = if value.source_type == ITEM_ISSUE {
+            ITEM_ISSUE
+        } else {
+            ITEM_MR
+        };
+        Backlink {
+            subject_type: subject_type.to_owned(),
+            source_type: value.source_type,
+            source_id: value.source_id,
+            subject_id: value.subject_id,
+            text: value.text,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
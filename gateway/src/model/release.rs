@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_release, mega_release_asset};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Release {
+    pub id: i64,
+    pub repo_path: String,
+    /// Name of the tag the release is made from, without `refs/tags/`
+    pub tag_name: String,
+    /// Commit the tag points to
+    pub commit_id: Option<String>,
+    pub title: String,
+    /// Markdown
+    pub notes: String,
+    pub author: String,
+    pub assets: Vec<ReleaseAsset>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Release {
+    pub fn new(
+        value: mega_release::Model,
+        commit_id: Option<String>,
+        assets: Vec<mega_release_asset::Model>,
+    ) -> Self {
+        Release {
+            id: value.id,
+            repo_path: value.repo_path,
+            tag_name: value.tag_name,
+            commit_id,
+            title: value.title,
+            notes: value.notes,
+            author: value.author,
+            assets: assets.into_iter().map(ReleaseAsset::from).collect(),
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub sha256: String,
+    pub uploader: String,
+    pub created_at: String,
+}
+
+impl From<mega_release_asset::Model> for ReleaseAsset {
+    fn from(value: mega_release_asset::Model) -> Self {
+        ReleaseAsset {
+            name: value.name,
+            content_type: value.content_type,
+            size: value.size,
+            sha256: value.sha256,
+            uploader: value.uploader,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewRelease {
+    pub repo_path: String,
+    pub tag_name: String,
+    /// The tag name when not given
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+}
+
+/// Fields of a release to change, the others are kept.
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct ReleaseUpdate {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReleaseQuery {
+    pub repo_path: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AssetUpload {
+    /// File name the asset is listed and downloaded as
+    pub name: String,
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use common::missing_objects::MissingObject;
+
+/// An object the server found missing from its object store.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MissingObjectStatus {
+    pub id: String,
+    /// Repository it was met in, when known
+    pub repo_path: Option<String>,
+    /// Where it was first met: `upload-pack`, `browse`, `blame` or `index`
+    pub context: String,
+    /// Times it was met
+    pub count: u64,
+    pub first_seen_at: String,
+    pub last_seen_at: String,
+}
+
+impl From<&MissingObject> for MissingObjectStatus {
+    fn from(value: &MissingObject) -> Self {
+        let time = |time| {
+            chrono::DateTime::<chrono::Utc>::from(time)
+                .naive_utc()
+                .to_string()
+        };
+        MissingObjectStatus {
+            id: value.id.clone(),
+            repo_path: value.repo_path.clone(),
+            context: value.context.to_owned(),
+            count: value.count,
+            first_seen_at: time(value.first_seen),
+            last_seen_at: time(value.last_seen),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RepairRequest {
+    pub repo_path: String,
+    /// Urls of other copies of the repository, tried after the upstream of its mirror
+    #[serde(default)]
+    pub urls: Vec<String>,
+}
+
+#[derive(Default, Serialize, Deserialize, ToSchema)]
+pub struct RepairResult {
+    /// Urls fetched, in order and without any credentials in them
+    pub sources: Vec<String>,
+    /// Objects stored again
+    pub repaired: Vec<String>,
+    /// Objects none of the sources had
+    pub missing: Vec<String>,
+    /// Why some sources couldn't be used
+    pub errors: Vec<String>,
+}
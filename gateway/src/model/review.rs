@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{db_enums::ReviewState, mega_mr_comment, mega_mr_review, mega_mr_thread};
+
+use crate::api_service::markdown;
+use crate::model::autolink::Autolink;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewComment {
+    pub id: i64,
+    pub author: String,
+    pub body: String,
+    /// The body rendered as sanitized HTML
+    #[serde(default)]
+    pub body_html: String,
+    pub created_at: String,
+    /// References in the body linked, by the autolink rules or as mentions
+    #[serde(default)]
+    pub autolinks: Vec<Autolink>,
+}
+
+impl From<mega_mr_comment::Model> for ReviewComment {
+    fn from(value: mega_mr_comment::Model) -> Self {
+        ReviewComment {
+            id: value.id,
+            author: value.author,
+            body_html: markdown::render(&value.body),
+            body: value.body,
+            created_at: value.created_at.to_string(),
+            autolinks: Vec::new(),
+        }
+    }
+}
+
+/// A discussion on a merge request. Inline threads are anchored to a line of a file as it is in
+/// `commit_id`, the others are about the merge request as a whole.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewThread {
+    pub id: i64,
+    pub path: Option<String>,
+    pub line: Option<i32>,
+    pub commit_id: Option<String>,
+    pub resolved: bool,
+    pub resolved_by: Option<String>,
+    pub comments: Vec<ReviewComment>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl ReviewThread {
+    pub fn new(thread: mega_mr_thread::Model, comments: Vec<ReviewComment>) -> Self {
+        ReviewThread {
+            id: thread.id,
+            path: thread.path,
+            line: thread.line,
+            commit_id: thread.commit_id,
+            resolved: thread.resolved,
+            resolved_by: thread.resolved_by,
+            comments,
+            created_at: thread.created_at.to_string(),
+            updated_at: thread.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewThread {
+    pub body: String,
+    /// File the thread is about, relative to the repository root
+    #[serde(default)]
+    pub path: Option<String>,
+    /// 1-based line in `path`, required with `path`
+    #[serde(default)]
+    pub line: Option<i32>,
+    /// Commit the line refers to, defaults to the head of the source branch
+    #[serde(default)]
+    pub commit_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewComment {
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ThreadQuery {
+    #[serde(default)]
+    pub resolved: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Review {
+    pub reviewer: String,
+    /// `approved` or `changes_requested`
+    pub state: String,
+    pub body: Option<String>,
+    /// The body rendered as sanitized HTML
+    #[serde(default)]
+    pub body_html: Option<String>,
+    /// Head of the source branch when the review was given
+    pub commit_id: Option<String>,
+    pub updated_at: String,
+}
+
+impl From<mega_mr_review::Model> for Review {
+    fn from(value: mega_mr_review::Model) -> Self {
+        Review {
+            reviewer: value.reviewer,
+            state: review_state_name(&value.state).to_owned(),
+            body_html: value.body.as_deref().map(markdown::render),
+            body: value.body,
+            commit_id: value.commit_id,
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewReview {
+    /// `approved` or `changes_requested`
+    pub state: String,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+/// The latest review of every reviewer, and what they add up to.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewSummary {
+    /// `changes_requested` if any reviewer requested changes, otherwise `approved` if anyone
+    /// approved, otherwise `pending`
+    pub status: String,
+    pub approved_by: Vec<String>,
+    pub changes_requested_by: Vec<String>,
+    pub unresolved_threads: usize,
+    pub reviews: Vec<Review>,
+}
+
+/// Owners of some files a merge request changes, any one of whom can approve them.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CodeOwnerApproval {
+    pub owners: Vec<String>,
+    pub paths: Vec<String>,
+    /// Owners whose latest review approved
+    pub approved_by: Vec<String>,
+    pub approved: bool,
+}
+
+/// The approvals the code owners of the files a merge request changes have to give before it
+/// can be merged.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CodeOwnersReview {
+    /// The `CODEOWNERS` file of the target branch the owners are read from
+    pub file: String,
+    pub required: Vec<CodeOwnerApproval>,
+    /// Owners of which no one approved yet, one list per set of files
+    pub blocking: Vec<Vec<String>>,
+}
+
+pub fn review_state_name(state: &ReviewState) -> &'static str {
+    match state {
+        ReviewState::Approved => "approved",
+        ReviewState::ChangesRequested => "changes_requested",
+    }
+}
+
+pub fn parse_review_state(name: &str) -> Option<ReviewState> {
+    match name {
+        "approved" => Some(ReviewState::Approved),
+        "changes_requested" => Some(ReviewState::ChangesRequested),
+        _ => None,
+    }
+}
+
+/// Combine the states of the reviews given on a merge request, one per reviewer.
+pub fn review_status<'a>(states: impl IntoIterator<Item = &'a ReviewState>) -> &'static str {
+    let mut status = "pending";
+    for state in states {
+        match state {
+            ReviewState::ChangesRequested => return "changes_requested",
+            ReviewState::Approved => status = "approved",
+        }
+    }
+    status
+}
+
+/// Whether `name` is the `author` of a merge request, compared like code owners are.
+pub fn is_author(author: Option<&str>, name: &str) -> bool {
+    author.is_some_and(|author| author.eq_ignore_ascii_case(name))
+}
+
+/// The reviewers whose latest review approved, but the author of the merge request whose own
+/// approval never counts.
+pub fn approvers(reviews: Vec<mega_mr_review::Model>, author: Option<&str>) -> Vec<String> {
+    reviews
+        .into_iter()
+        .filter(|r| r.state == ReviewState::Approved && !is_author(author, &r.reviewer))
+        .map(|r| r.reviewer)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use db_entity::db_enums::ReviewState;
+
+    use super::review_status;
+
+    #[test]
+    fn test_review_status() {
+        assert_eq!(review_status([]), "pending");
+        assert_eq!(review_status([&ReviewState::Approved]), "approved");
+        assert_eq!(
+            review_status([&ReviewState::Approved, &ReviewState::ChangesRequested]),
+            "changes_requested"
+        );
+        assert_eq!(
+            review_status([&ReviewState::ChangesRequested, &ReviewState::Approved]),
+            "changes_requested"
+        );
+    }
+}
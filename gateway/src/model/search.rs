@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CodeSearchQuery {
+    /// Words to look for, with the query syntax of tantivy (`"exact phrase"`, `path:src`, `-word`)
+    pub q: String,
+    /// Only files of this repository
+    #[serde(default)]
+    pub repo_path: Option<String>,
+    /// Only files at or below this path of the monorepo
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CodeSearchHit {
+    pub repo_path: String,
+    /// Path of the file inside its repository
+    pub path: String,
+    pub blob_id: String,
+    pub score: f32,
+    /// Lines around the match, HTML escaped with the matched words in `<b>` tags
+    pub snippet: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Words to look for like in code search, with the qualifiers `type:`, `label:`, `state:`
+    /// and `repo:` to narrow the results, e.g. `type:issue label:bug crash`
+    pub q: String,
+    /// Only results at or below this path of the monorepo
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// A file, commit, issue or merge request matching a search.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SearchHit {
+    /// One of `code`, `commit`, `issue` or `merge_request`
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub repo_path: String,
+    /// Blob id of a file, commit id, or id of an issue or merge request
+    pub id: String,
+    /// Path of a file inside its repository
+    pub path: Option<String>,
+    /// First line of a commit message, title of an issue or merge request
+    pub title: Option<String>,
+    /// State of an issue or status of a merge request
+    pub state: Option<String>,
+    pub labels: Vec<String>,
+    pub score: f32,
+    /// Text around the match, HTML escaped with the matched words in `<b>` tags
+    pub snippet: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SearchReindex {
+    pub repo_path: String,
+}
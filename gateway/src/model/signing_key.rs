@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_signing_key;
+use venus::internal::object::commit::Commit;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SigningKey {
+    pub id: i64,
+    pub title: String,
+    /// `gpg` or `ssh`
+    pub key_type: String,
+    /// Long key id of a GPG key, `SHA256:` fingerprint of an SSH key
+    pub key_id: String,
+    pub subkey_ids: Vec<String>,
+    pub emails: Vec<String>,
+    pub public_key: String,
+    pub verified: bool,
+    /// Text to sign to prove ownership of the key, empty once verified
+    pub challenge: String,
+    pub verified_at: Option<String>,
+    pub created_at: String,
+}
+
+impl From<mega_signing_key::Model> for SigningKey {
+    fn from(value: mega_signing_key::Model) -> Self {
+        SigningKey {
+            id: value.id,
+            title: value.title,
+            key_type: value.key_type,
+            key_id: value.key_id,
+            subkey_ids: value.subkey_ids,
+            emails: value.emails,
+            public_key: value.public_key,
+            challenge: if value.verified {
+                String::new()
+            } else {
+                value.challenge
+            },
+            verified: value.verified,
+            verified_at: value.verified_at.map(|d| d.to_string()),
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewSigningKey {
+    /// `gpg` or `ssh`
+    pub key_type: String,
+    /// Armored GPG public key, or an OpenSSH public key line
+    pub public_key: String,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct KeyVerification {
+    /// Armored signature over the challenge of the key
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CommitSignatureQuery {
+    pub commit_id: String,
+}
+
+/// Whether a commit carries a signature by a key registered to a mega account.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CommitSignature {
+    pub commit_id: String,
+    pub signed: bool,
+    pub verified: bool,
+    /// `gpg` or `ssh`
+    pub signature_type: Option<String>,
+    pub key_id: Option<String>,
+    /// Account owning the signing key
+    pub signer: Option<String>,
+    /// Why the signature is not verified, `None` when it is
+    pub reason: Option<String>,
+}
+
+/// A commit along with the status of its signature.
+#[derive(Serialize)]
+pub struct SignedCommit {
+    #[serde(flatten)]
+    pub commit: Commit,
+    pub signature: CommitSignature,
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::mega_snapshot_export;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SnapshotExport {
+    pub name: String,
+    pub repo_path: String,
+    /// Ref the scheduled runs export, e.g. `refs/heads/main`
+    pub source_ref: String,
+    /// Directories exported, relative to the repository root, `""` for all of it
+    pub paths: Vec<String>,
+    /// `tar.gz`, `tar`, `zip` or `bundle`
+    pub format: String,
+    pub schedule: Option<String>,
+    pub tag_pattern: Option<String>,
+    pub enabled: bool,
+    pub next_run_at: Option<String>,
+    pub last_run_at: Option<String>,
+    pub last_commit: Option<String>,
+    /// Key of the manifest of the last successful run
+    pub last_manifest: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_snapshot_export::Model> for SnapshotExport {
+    fn from(value: mega_snapshot_export::Model) -> Self {
+        SnapshotExport {
+            name: value.name,
+            repo_path: value.repo_path,
+            source_ref: value.source_ref,
+            paths: split_paths(&value.paths),
+            format: value.format,
+            schedule: value.schedule,
+            tag_pattern: value.tag_pattern,
+            enabled: value.enabled,
+            next_run_at: value.next_run_at.map(|d| d.to_string()),
+            last_run_at: value.last_run_at.map(|d| d.to_string()),
+            last_commit: value.last_commit,
+            last_manifest: value.last_manifest,
+            last_error: value.last_error,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+/// The paths of an export, stored one per line.
+pub fn split_paths(paths: &str) -> Vec<String> {
+    paths.lines().map(str::to_owned).collect()
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SnapshotExportUpdate {
+    pub repo_path: String,
+    /// Branch name or full ref name, `main` by default
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The whole repository when empty
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Same format as the schedules of ref triggers, e.g. `daily 02:00`
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Tags whose creation runs the export, a pattern ending in `*` matching every tag it is a
+    /// prefix of
+    #[serde(default)]
+    pub tag_pattern: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnapshotExportRunQuery {
+    /// Branch, tag or commit id, the source ref of the export by default
+    #[serde(default, rename = "ref")]
+    pub refs: Option<String>,
+}
+
+/// One archive or bundle written by a run.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportArtifact {
+    /// Directory it holds, `""` for the whole repository
+    pub path: String,
+    pub key: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// What a run exported, written next to the artifacts as `manifest.json`.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExportManifest {
+    pub export: String,
+    pub repo_path: String,
+    /// Ref the run exported, as it was named
+    pub ref_name: String,
+    pub commit_id: String,
+    pub format: String,
+    pub created_at: String,
+    pub artifacts: Vec<ExportArtifact>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SnapshotExportRun {
+    /// Key of the manifest of the run
+    pub manifest_key: String,
+    pub manifest: ExportManifest,
+}
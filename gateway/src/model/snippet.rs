@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use db_entity::{mega_snippet, mega_snippet_file};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Snippet {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    /// `public`, `internal` or `private`
+    pub visibility: String,
+    pub owner: String,
+    pub files: Vec<SnippetFile>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Snippet {
+    pub fn new(value: mega_snippet::Model, files: Vec<SnippetFile>) -> Self {
+        Snippet {
+            id: value.id,
+            title: value.title,
+            description: value.description,
+            visibility: value.visibility,
+            owner: value.owner,
+            files,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SnippetFile {
+    pub name: String,
+    /// Language the file is highlighted as
+    pub language: String,
+    pub size: i64,
+    /// Id of the blob holding the content
+    pub blob_id: String,
+    /// Content of the file, only returned with a single snippet
+    pub content: Option<String>,
+}
+
+impl From<mega_snippet_file::Model> for SnippetFile {
+    fn from(value: mega_snippet_file::Model) -> Self {
+        SnippetFile {
+            name: value.name,
+            language: value.language,
+            size: value.size,
+            blob_id: value.blob_id,
+            content: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewSnippet {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `public`, `internal` or `private`, `private` when missing
+    #[serde(default)]
+    pub visibility: Option<String>,
+    pub files: Vec<NewSnippetFile>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewSnippetFile {
+    pub name: String,
+    pub content: String,
+    /// Language to highlight the file as, guessed from its name when missing
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// Fields of a snippet to change, the others are kept.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SnippetUpdate {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub visibility: Option<String>,
+    /// Files replacing all of those of the snippet
+    #[serde(default)]
+    pub files: Option<Vec<NewSnippetFile>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnippetQuery {
+    /// Only the snippets of this user
+    #[serde(default)]
+    pub owner: Option<String>,
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_ssh_key;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SshKey {
+    pub id: i64,
+    pub title: String,
+    /// Algorithm of the key, e.g. `ssh-ed25519`
+    pub key_type: String,
+    /// `SHA256:` fingerprint as printed by `ssh-keygen -l`
+    pub fingerprint: String,
+    pub public_key: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+impl From<mega_ssh_key::Model> for SshKey {
+    fn from(value: mega_ssh_key::Model) -> Self {
+        SshKey {
+            id: value.id,
+            title: value.title,
+            key_type: value.key_type,
+            fingerprint: value.fingerprint,
+            public_key: value.public_key,
+            expires_at: value.expires_at.map(|d| d.to_string()),
+            last_used_at: value.last_used_at.map(|d| d.to_string()),
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewSshKey {
+    /// Defaults to the comment of the key
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Public key in OpenSSH format: `<type> <base64> [comment]`
+    pub public_key: String,
+    /// RFC 3339 timestamp or `YYYY-MM-DD`, the key never expires when missing
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    pub repo_path: String,
+    /// Only commits authored on or after this date, `YYYY-MM-DD`
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Only commits authored before this date, `YYYY-MM-DD`
+    #[serde(default)]
+    pub until: Option<String>,
+}
+
+/// What the commits of a week, starting on Monday, add up to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct WeekStats {
+    /// The Monday starting the week, `YYYY-MM-DD`
+    pub week: String,
+    pub commits: u64,
+    pub additions: u64,
+    pub deletions: u64,
+}
+
+/// The commits of an author on the default branch, merges left out.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ContributorStats {
+    /// Name and email as the mailmap shows them
+    pub name: String,
+    pub email: String,
+    pub commits: u64,
+    pub additions: u64,
+    pub deletions: u64,
+    /// The weeks the author committed in, oldest first
+    pub weeks: Vec<WeekStats>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LanguageStats {
+    pub language: String,
+    pub files: u64,
+    pub bytes: u64,
+    /// Share of the bytes of all the languages, from 0 to 100
+    pub percentage: f64,
+}
+
+/// The languages of a directory of the tree of the default branch, those below it included.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LanguageBreakdown {
+    /// The directory, `""` for the root of the repository
+    pub path: String,
+    /// Commit the languages were counted at, `None` before the first push counted
+    pub commit_id: Option<String>,
+    /// Largest first
+    pub languages: Vec<LanguageStats>,
+}
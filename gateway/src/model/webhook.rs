@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use db_entity::mega_webhook;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    /// Events of repositories at or below this path are delivered
+    pub repo_path: String,
+    /// Any of `push`, `tag` and `merge_request`, every event when empty
+    pub events: Vec<String>,
+    /// `mega`, `github` or `gitlab`
+    pub dialect: String,
+    /// The secret itself is never returned
+    pub has_secret: bool,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<mega_webhook::Model> for Webhook {
+    fn from(value: mega_webhook::Model) -> Self {
+        Webhook {
+            name: value.name,
+            url: value.url,
+            repo_path: value.repo_path,
+            events: value
+                .events
+                .split(',')
+                .filter(|e| !e.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            dialect: value.dialect,
+            has_secret: value.secret.is_some_and(|s| !s.is_empty()),
+            enabled: value.enabled,
+            created_at: value.created_at.to_string(),
+            updated_at: value.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct WebhookUpdate {
+    pub url: String,
+    #[serde(default = "default_repo_path")]
+    pub repo_path: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default = "default_dialect")]
+    pub dialect: String,
+    /// Signs the deliveries, or is sent along for GitLab. Left out it stays as it was, an empty
+    /// one removes it
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_repo_path() -> String {
+    "/".to_owned()
+}
+
+fn default_dialect() -> String {
+    "mega".to_owned()
+}
+
+fn default_enabled() -> bool {
+    true
+}
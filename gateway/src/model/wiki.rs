@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WikiQuery {
+    pub repo_path: String,
+}
+
+/// The pages of a wiki at its latest commit.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WikiPages {
+    /// Latest commit of the wiki, `None` until its first page is saved
+    pub commit_id: Option<String>,
+    pub pages: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WikiPageQuery {
+    pub repo_path: String,
+    /// `/` separated name of the page, e.g. `guides/Setup`
+    pub name: String,
+    /// Commit of the wiki to read the page at, the latest one when missing
+    #[serde(default)]
+    pub revision: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WikiPage {
+    pub name: String,
+    pub content: String,
+    /// The content rendered as sanitized HTML
+    pub html: String,
+    /// Commit of the wiki the page was read at
+    pub commit_id: String,
+}
+
+/// Create a page or replace its content.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WikiPageUpdate {
+    pub repo_path: String,
+    pub name: String,
+    pub content: String,
+    /// Commit message, `Create <name>` or `Update <name>` when missing
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Commit of the wiki the change was made on, the change is refused if the wiki has moved
+    /// since. The change is made on the latest commit when missing.
+    #[serde(default)]
+    pub base_commit: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WikiPageDelete {
+    pub repo_path: String,
+    pub name: String,
+    /// Commit message, `Delete <name>` when missing
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Commit of the wiki the page was deleted from, as for [`WikiPageUpdate::base_commit`]
+    #[serde(default)]
+    pub base_commit: Option<String>,
+}
+
+/// The commit a change of the wiki made.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WikiChange {
+    pub name: String,
+    pub commit_id: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WikiHistoryQuery {
+    pub repo_path: String,
+    /// Only the commits changing this page, every commit of the wiki when missing
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WikiRevision {
+    pub commit_id: String,
+    pub summary: String,
+    pub author: String,
+    /// Unix timestamp of the commit
+    pub committed_at: usize,
+}
@@ -0,0 +1,162 @@
+//!
+//! Request ids, which tie the log lines of an HTTP request or SSH command together.
+//!
+//! Every request is handled in a `request` span carrying its id, so the logs written while
+//! serving it, down to the pack and storage layers, can be found by the id. The id is sent back
+//! in the `X-Request-Id` header and at the end of plain text error messages, for users to quote
+//! when they report a problem. A client can choose the id by sending the header itself.
+//!
+use std::fmt;
+
+use axum::body::{Body, HttpBody};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use rand::Rng;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// ids of clients longer than this are replaced
+const MAX_ID_LENGTH: usize = 64;
+
+// error bodies larger than this are sent as they are
+const MAX_ERROR_BODY: u64 = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// A random id of 16 hex digits.
+    pub fn generate() -> Self {
+        RequestId(format!("{:016x}", rand::thread_rng().gen::<u64>()))
+    }
+
+    /// The id the client sent in `X-Request-Id`, or a new one when it sent none or one unsafe to
+    /// log.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_ID_LENGTH
+                    && id
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+            })
+            .map(|id| RequestId(id.to_owned()))
+            .unwrap_or_else(RequestId::generate)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The span the work done for the request is recorded in, `method` being the HTTP method or
+    /// the SSH command.
+    pub fn span(&self, method: &str, path: &str) -> tracing::Span {
+        tracing::info_span!("request", id = %self, method, path)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Middleware serving the request in its span and sending its id back to the client.
+pub async fn trace_request(mut request: Request, next: Next) -> Response {
+    let id = RequestId::from_headers(request.headers());
+    let span = id.span(request.method().as_str(), request.uri().path());
+    request.extensions_mut().insert(id.clone());
+    let response = next.run(request).instrument(span.clone()).await;
+    if response.status().is_server_error() {
+        span.in_scope(|| tracing::error!("request failed with {}", response.status()));
+    }
+    with_request_id(response, &id).await
+}
+
+/// Add the id to the headers of `response`, and to its body if it is a plain text error.
+async fn with_request_id(response: Response, id: &RequestId) -> Response {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(id.as_str()).unwrap(),
+    );
+    let is_error = parts.status.is_client_error() || parts.status.is_server_error();
+    let is_text = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"text/plain"));
+    let is_small = body
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= MAX_ERROR_BODY);
+    if !(is_error && is_text && is_small) {
+        return Response::from_parts(parts, body);
+    }
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_ERROR_BODY as usize).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let mut message = String::from_utf8_lossy(&bytes).into_owned();
+    if !message.is_empty() && !message.ends_with('\n') {
+        message.push('\n');
+    }
+    message.push_str(&format!("request id: {}\n", id));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Json};
+
+    use super::{with_request_id, RequestId, REQUEST_ID_HEADER};
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, "build-42.retry_1".parse().unwrap());
+        assert_eq!(RequestId::from_headers(&headers).as_str(), "build-42.retry_1");
+
+        // ids which would garble the logs are replaced
+        headers.insert(REQUEST_ID_HEADER, "a b\"c".parse().unwrap());
+        let id = RequestId::from_headers(&headers);
+        assert_eq!(id.as_str().len(), 16);
+        assert!(id.as_str().bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(RequestId::from_headers(&HeaderMap::new()), id);
+    }
+
+    #[tokio::test]
+    async fn test_with_request_id() {
+        let id = RequestId("abc".to_owned());
+        let body = |response: axum::response::Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        let response = with_request_id(
+            (StatusCode::NOT_FOUND, "Operation not supported").into_response(),
+            &id,
+        )
+        .await;
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc");
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(body(response).await, "Operation not supported\nrequest id: abc\n");
+
+        // successes and structured errors keep their body
+        let response = with_request_id("ok".into_response(), &id).await;
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc");
+        assert_eq!(body(response).await, "ok");
+        let response = with_request_id(
+            (StatusCode::BAD_REQUEST, Json(vec!["invalid"])).into_response(),
+            &id,
+        )
+        .await;
+        assert_eq!(body(response).await, "[\"invalid\"]");
+    }
+}
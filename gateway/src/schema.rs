@@ -0,0 +1,137 @@
+//!
+//! The schema script the server applies to its database, and the tables and indexes it creates,
+//! for `mega doctor` and the readiness probe to compare a database against.
+//!
+use std::collections::HashSet;
+
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+
+const PG_SCRIPT: &str = "sql/postgres/pg_20240205__init.sql";
+const MYSQL_SCRIPT: &str = "sql/mysql/mysql_20231106__init.sql";
+
+const PG_SCHEMA: &str = include_str!("../../sql/postgres/pg_20240205__init.sql");
+const MYSQL_SCHEMA: &str = include_str!("../../sql/mysql/mysql_20231106__init.sql");
+
+/// The path and the statements of the schema script of `backend`.
+pub fn script(backend: DbBackend) -> (&'static str, &'static str) {
+    match backend {
+        DbBackend::MySql => (MYSQL_SCRIPT, MYSQL_SCHEMA),
+        _ => (PG_SCRIPT, PG_SCHEMA),
+    }
+}
+
+/// The tables a schema script creates.
+pub fn tables(script: &str) -> Vec<&str> {
+    script
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("CREATE TABLE")?;
+            let rest = rest.trim_start();
+            let rest = rest
+                .strip_prefix("IF NOT EXISTS")
+                .unwrap_or(rest)
+                .trim_start();
+            let name = rest.split(|c: char| c == '(' || c.is_whitespace()).next()?;
+            let name = unquote(name);
+            (!name.is_empty()).then_some(name)
+        })
+        .collect()
+}
+
+/// The plain indexes a schema script creates, as (index name, table, columns): the `CREATE
+/// INDEX` statements of postgres and the `KEY` lines of the MySQL tables.
+pub fn indexes(script: &str) -> Vec<(&str, &str, String)> {
+    let columns = |list: &str| {
+        list.trim()
+            .trim_start_matches('(')
+            .trim_end_matches([')', ';', ','])
+            .split(',')
+            .map(unquote)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut indexes = Vec::new();
+    let mut table = "";
+    for line in script.lines().map(str::trim) {
+        if let Some(name) = tables(line).first() {
+            table = name;
+        } else if let Some(rest) = line.strip_prefix("CREATE INDEX") {
+            let Some((name, rest)) = rest.split_once(" ON ") else {
+                continue;
+            };
+            let Some((on, list)) = rest.split_once('(') else {
+                continue;
+            };
+            indexes.push((unquote(name), unquote(on), columns(list)));
+        } else if let Some(rest) = line.strip_prefix("KEY") {
+            let Some((name, list)) = rest.split_once('(') else {
+                continue;
+            };
+            indexes.push((unquote(name), table, columns(list)));
+        }
+    }
+    indexes
+}
+
+/// The tables of the schema script missing from the database of `conn`, all of them when it
+/// was never initialized.
+pub async fn missing_tables(conn: &DatabaseConnection) -> Result<Vec<&'static str>, String> {
+    let backend = conn.get_database_backend();
+    let sql = match backend {
+        DbBackend::MySql => {
+            "SELECT table_name AS name FROM information_schema.tables WHERE table_schema = DATABASE()"
+        }
+        _ => "SELECT tablename::text AS name FROM pg_tables WHERE schemaname = current_schema()",
+    };
+    let rows = conn
+        .query_all(Statement::from_string(backend, sql))
+        .await
+        .map_err(|e| e.to_string())?;
+    let present = rows
+        .iter()
+        .map(|row| row.try_get::<String>("", "name"))
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(tables(script(backend).1)
+        .into_iter()
+        .filter(|table| !present.contains(*table))
+        .collect())
+}
+
+fn unquote(name: &str) -> &str {
+    name.trim().trim_matches(|c| c == '"' || c == '`')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{indexes, tables, MYSQL_SCHEMA, PG_SCHEMA};
+
+    #[test]
+    fn test_tables() {
+        let script = "CREATE TABLE IF NOT EXISTS \"mega_commit\" (\n  \"id\" BIGINT\n);\n\
+                      CREATE TABLE `commit` (\nCREATE TABLE refs(id INT);\n";
+        assert_eq!(tables(script), vec!["mega_commit", "commit", "refs"]);
+
+        let pg = tables(PG_SCHEMA);
+        assert!(pg.contains(&"mega_import_job_repo"));
+        assert_eq!(pg.len(), PG_SCHEMA.matches("CREATE TABLE").count());
+        assert!(tables(MYSQL_SCHEMA).contains(&"refs"));
+    }
+
+    #[test]
+    fn test_indexes() {
+        let pg = indexes(PG_SCHEMA);
+        assert_eq!(pg.len(), PG_SCHEMA.matches("CREATE INDEX").count());
+        assert!(pg.contains(&("idx_mc_git_id", "mega_commit", "commit_id".to_owned())));
+        assert!(pg.contains(&(
+            "idx_path_mapping_split_commit",
+            "mega_path_mapping",
+            "path, split_commit".to_owned()
+        )));
+
+        let mysql = indexes(MYSQL_SCHEMA);
+        assert!(mysql.contains(&("idx_mr_id", "mr", "mr_id, object_type".to_owned())));
+        assert!(mysql.contains(&("idx_mr_id", "mr_info", "mr_id".to_owned())));
+        assert!(!mysql.iter().any(|(name, _, _)| name.starts_with("uniq_")));
+    }
+}
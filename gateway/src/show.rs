@@ -0,0 +1,62 @@
+//!
+//! Object display behind the `mega show` command.
+//!
+use std::str::FromStr;
+
+use clap::Args;
+
+use common::errors::MegaError;
+use common::model::CommonOptions;
+use storage::driver::database;
+use venus::hash::SHA1;
+use venus::internal::object::blob::Blob;
+use venus::internal::object::commit::Commit;
+use venus::internal::object::tag::Tag;
+use venus::internal::object::tree::Tree;
+use venus::internal::object::ObjectTrait;
+use venus::render::Render;
+
+pub use venus::render::Style;
+
+#[derive(Args, Clone, Debug)]
+pub struct ShowOptions {
+    #[clap(flatten)]
+    pub common: CommonOptions,
+
+    /// Id of the commit, tree, blob or tag to show
+    pub id: String,
+
+    /// Output format, `ansi` when writing to a terminal and `plain` otherwise by default
+    #[arg(long, value_parser = ["plain", "ansi", "json"])]
+    pub format: Option<String>,
+}
+
+/// The object `options.id` rendered in `style`.
+pub async fn run_show(options: &ShowOptions, style: Style) -> Result<String, MegaError> {
+    let id = SHA1::from_str(&options.id).map_err(|e| MegaError::new(anyhow::anyhow!(e), 1))?;
+    let storage = database::init(&options.common.data_source).await;
+    let model = storage
+        .get_obj_data_by_id(&id.to_plain_str())
+        .await?
+        .ok_or_else(|| MegaError::new(anyhow::anyhow!("object {} not found", options.id), 1))?;
+    let data = model.data;
+    let rendered = match model.object_type.as_str() {
+        "commit" => {
+            let mut commit = Commit::from_bytes(&data).map_err(anyhow::Error::from)?;
+            commit.id = id;
+            commit.styled(style).to_string()
+        }
+        "tree" => {
+            let mut tree = Tree::from_bytes(&data).map_err(anyhow::Error::from)?;
+            tree.id = id;
+            tree.styled(style).to_string()
+        }
+        "tag" => {
+            let mut tag = Tag::from_bytes(&data).map_err(anyhow::Error::from)?;
+            tag.id = id;
+            tag.styled(style).to_string()
+        }
+        _ => Blob { data }.styled(style).to_string(),
+    };
+    Ok(rendered)
+}
@@ -0,0 +1,109 @@
+//!
+//! Graceful shutdown of the servers on SIGINT or SIGTERM.
+//!
+//! Once a signal arrives the servers stop accepting connections and let the fetches and pushes
+//! in flight finish, for at most `MEGA_SHUTDOWN_TIMEOUT_SECS` seconds, after which the streams
+//! still open are dropped. The ref hooks are then run a last time, so the pushes made before the
+//! signal are delivered before the process exits.
+//!
+use std::sync::{Once, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+static REQUESTED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+static LISTEN: Once = Once::new();
+
+fn requested_sender() -> &'static watch::Sender<bool> {
+    REQUESTED.get_or_init(|| watch::channel(false).0)
+}
+
+/// Wait for SIGINT or SIGTERM in the background, once per process whatever the servers
+/// started in it.
+pub fn listen() {
+    LISTEN.call_once(|| {
+        tokio::spawn(async {
+            wait_for_signal().await;
+            tracing::info!(
+                "shutting down, waiting up to {:?} for open streams",
+                timeout()
+            );
+            request();
+        });
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Shut the servers down as if a signal arrived.
+pub fn request() {
+    requested_sender().send_replace(true);
+}
+
+pub fn is_requested() -> bool {
+    *requested_sender().borrow()
+}
+
+/// Complete once the shutdown is requested.
+pub async fn requested() {
+    let mut receiver = requested_sender().subscribe();
+    let _ = receiver.wait_for(|requested| *requested).await;
+}
+
+/// How long open streams and the last ref hook run get, from `MEGA_SHUTDOWN_TIMEOUT_SECS`.
+pub fn timeout() -> Duration {
+    let secs = std::env::var("MEGA_SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .unwrap_or(DEFAULT_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Complete [`timeout`] after the shutdown is requested, when the streams still open are
+/// dropped.
+pub async fn deadline() {
+    requested().await;
+    tokio::time::sleep(timeout()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{is_requested, request, requested};
+
+    #[tokio::test]
+    async fn test_requested() {
+        assert!(!is_requested());
+        let waiting = tokio::spawn(requested());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        request();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        // later waiters don't wait at all
+        tokio::time::timeout(Duration::from_secs(1), requested())
+            .await
+            .unwrap();
+        assert!(is_requested());
+    }
+}
@@ -16,12 +16,31 @@ use clap::Args;
 use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
 use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey, EncodePublicKey};
 use ed25519_dalek::SigningKey;
+use russh::server::Server;
 use russh_keys::key::KeyPair;
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
 
 use common::model::CommonOptions;
+use jupiter::storage::archive_storage::ArchiveStorage;
+use jupiter::storage::event_storage::EventStorage;
+use jupiter::storage::org_storage::OrgStorage;
+use jupiter::storage::path_grant_storage::PathGrantStorage;
+use jupiter::storage::path_redirect_storage::PathRedirectStorage;
+use jupiter::storage::push_profile_storage::PushProfileStorage;
+use jupiter::storage::signing_key_storage::SigningKeyStorage;
+use jupiter::storage::ssh_key_storage::SshKeyStorage;
 use storage::driver::database;
 
+use crate::api_service::archive::PathArchives;
+use crate::api_service::event_service::EventService;
+use crate::api_service::path_move::PathRedirects;
+use crate::api_service::push_profile_service::PushProfileService;
+use crate::api_service::signing_key_service::SigningKeyService;
+use crate::auth;
+use crate::auth::acl::{Acl, AclPolicy};
 use crate::git_protocol::ssh::SshServer;
+use crate::shutdown;
 
 #[derive(Args, Clone, Debug)]
 pub struct SshOptions {
@@ -72,17 +91,96 @@ pub async fn start_server(command: &SshOptions) {
                 ssh_cert_path: _,
             },
     } = command;
+    // users and their keys live in tables managed by jupiter
+    let connection = Arc::new(database::connect(data_source).await);
+    let storage = database::init(data_source).await;
     let sh = SshServer {
         client_pubkey,
         clients: Arc::new(Mutex::new(HashMap::new())),
         id: 0,
-        storage: database::init(data_source).await,
+        storage: storage.clone(),
+        auth: auth::init(connection.clone()).expect("Failed to set up the authentication provider"),
+        ssh_keys: SshKeyStorage::new(connection.clone()),
+        redirects: PathRedirects {
+            storage: storage.clone(),
+            redirect_storage: PathRedirectStorage::new(connection.clone()),
+        },
+        archives: PathArchives {
+            archive_storage: ArchiveStorage::new(connection.clone()),
+        },
+        events: EventService {
+            storage: EventStorage::new(connection.clone()),
+        },
+        signing_keys: SigningKeyService {
+            storage: SigningKeyStorage::new(connection.clone()),
+            object_storage: storage,
+        },
+        push_profiles: PushProfileService {
+            storage: PushProfileStorage::new(connection.clone()),
+        },
+        acl: Acl {
+            policy: AclPolicy::from_env().expect("Failed to read the ACL configuration"),
+            grant_storage: PathGrantStorage::new(connection.clone()),
+            org_storage: OrgStorage::new(connection),
+        },
+        username: None,
+        identity: None,
         pack_protocol: None,
         data_combined: Vec::new(),
+        connection: None,
+        span: tracing::Span::none(),
     };
     let server_url = format!("{}:{}", host, ssh_port);
     let addr = SocketAddr::from_str(&server_url).unwrap();
-    russh::server::run(config, addr, sh).await.unwrap()
+    let listener = TcpListener::bind(addr).await.unwrap();
+    shutdown::listen();
+    serve(config, listener, sh).await
+}
+
+/// Accept SSH connections until the shutdown is requested, then wait for the open ones, with
+/// the fetches and pushes they run, until the shutdown timeout.
+async fn serve(config: Arc<russh::server::Config>, listener: TcpListener, mut sh: SshServer) {
+    let mut sessions = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("unable to accept an SSH connection: {}", e);
+                        continue;
+                    }
+                };
+                let config = config.clone();
+                let handler = sh.new_client(Some(peer));
+                sessions.spawn(async move {
+                    match russh::server::run_stream(config, socket, handler).await {
+                        Ok(session) => {
+                            if let Err(e) = session.await {
+                                tracing::debug!("SSH connection closed with error: {}", e);
+                            }
+                        }
+                        Err(e) => tracing::debug!("SSH connection setup failed: {}", e),
+                    }
+                });
+            }
+            // reap the closed connections
+            Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+            _ = shutdown::requested() => break,
+        }
+    }
+    drop(listener);
+    let drained = async { while sessions.join_next().await.is_some() {} };
+    if tokio::time::timeout(shutdown::timeout(), drained)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "dropped {} SSH connections still open after the shutdown timeout",
+            sessions.len()
+        );
+        sessions.shutdown().await;
+    }
 }
 
 /// # Loads an SSH keypair.
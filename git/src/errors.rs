@@ -7,6 +7,11 @@ use std::string::FromUtf8Error;
 
 use thiserror::Error;
 
+use common::errors::MegaError;
+use common::operation::Cancelled;
+
+use crate::protocol::limits::FetchRejection;
+
 #[derive(Error, Debug)]
 #[allow(unused)]
 pub enum GitError {
@@ -43,6 +48,9 @@ pub enum GitError {
     #[error("The `{0}` is not a valid pack header.")]
     InvalidPackHeader(String),
 
+    #[error("The pack bitmap is not valid: {0}")]
+    InvalidBitmap(String),
+
     #[error("The {0} is not a valid Hash value ")]
     InvalidHashValue(String),
 
@@ -63,6 +71,18 @@ pub enum GitError {
 
     #[error("UTF-8 conversion error: {0}")]
     ConversionError(String),
+
+    #[error("Fetch refused, {0}")]
+    FetchRejected(FetchRejection),
+
+    #[error("Can't read the objects from the storage: {0}")]
+    StorageError(String),
+
+    #[error("Can't spill pack data to disk: {0}")]
+    SpillError(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Cancelled(#[from] Cancelled),
 }
 
 impl From<FromUtf8Error> for GitError {
@@ -71,3 +91,9 @@ impl From<FromUtf8Error> for GitError {
         GitError::ConversionError(err.to_string())
     }
 }
+
+impl From<MegaError> for GitError {
+    fn from(err: MegaError) -> Self {
+        GitError::StorageError(err.to_string())
+    }
+}
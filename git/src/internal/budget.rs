@@ -0,0 +1,407 @@
+//!
+//! Memory accounting of pack operations.
+//!
+//! Each fetch and push gets a [`MemoryBudget`] bounding the bytes that encoding its pack, or
+//! decoding the pushed pack and resolving its deltas, keep in memory. Budgets also draw from a
+//! pool shared by every request, so that large operations running at once stay bounded as well.
+//! What doesn't fit is written to a temporary file, which is removed once it's dropped.
+//!
+//! - `MEGA_PACK_MEMORY_BUDGET`: bytes one request may hold, 256 MiB by default.
+//! - `MEGA_PACK_MEMORY_TOTAL`: bytes all requests may hold together, 2 GiB by default.
+//! - `MEGA_PACK_SPILL_DIR`: directory of the temporary files, the system one when empty.
+//!
+//! 0 turns a limit off.
+//!
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::protocol::limits::env_limit;
+
+const DEFAULT_BUDGET: usize = 256 << 20;
+const DEFAULT_TOTAL: usize = 2 << 30;
+
+/// Adds `bytes` to `used` unless that goes over `limit`, 0 meaning no limit.
+fn acquire(used: &AtomicUsize, limit: usize, bytes: usize) -> bool {
+    used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+        let next = current.checked_add(bytes)?;
+        (limit == 0 || next <= limit).then_some(next)
+    })
+    .is_ok()
+}
+
+/// Memory shared by the budgets drawing from it.
+pub struct MemoryPool {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryPool {
+    pub fn new(limit: usize) -> Self {
+        MemoryPool {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// The pool of every request, of `MEGA_PACK_MEMORY_TOTAL` bytes.
+    pub fn shared() -> Arc<MemoryPool> {
+        static SHARED: OnceLock<Arc<MemoryPool>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| {
+                Arc::new(MemoryPool::new(env_limit(
+                    "MEGA_PACK_MEMORY_TOTAL",
+                    DEFAULT_TOTAL,
+                )))
+            })
+            .clone()
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+}
+
+struct BudgetState {
+    limit: usize,
+    pool: Arc<MemoryPool>,
+    spill_dir: PathBuf,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    spilled: AtomicUsize,
+}
+
+/// The memory of one request, shared by its clones.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    state: Arc<BudgetState>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize, pool: Arc<MemoryPool>) -> Self {
+        MemoryBudget::with_spill_dir(limit, pool, std::env::temp_dir())
+    }
+
+    pub fn with_spill_dir(limit: usize, pool: Arc<MemoryPool>, spill_dir: PathBuf) -> Self {
+        MemoryBudget {
+            state: Arc::new(BudgetState {
+                limit,
+                pool,
+                spill_dir,
+                used: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                spilled: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// A budget which never spills, for tools and tests.
+    pub fn unlimited() -> Self {
+        MemoryBudget::new(0, Arc::new(MemoryPool::new(0)))
+    }
+
+    pub fn from_env() -> Self {
+        let spill_dir = match std::env::var("MEGA_PACK_SPILL_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir.trim()),
+            _ => std::env::temp_dir(),
+        };
+        MemoryBudget::with_spill_dir(
+            env_limit("MEGA_PACK_MEMORY_BUDGET", DEFAULT_BUDGET),
+            MemoryPool::shared(),
+            spill_dir,
+        )
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.limit
+    }
+
+    /// Bytes held in memory right now.
+    pub fn used(&self) -> usize {
+        self.state.used.load(Ordering::Acquire)
+    }
+
+    /// The most bytes held in memory at once.
+    pub fn peak(&self) -> usize {
+        self.state.peak.load(Ordering::Acquire)
+    }
+
+    /// Bytes written to temporary files.
+    pub fn spilled(&self) -> usize {
+        self.state.spilled.load(Ordering::Acquire)
+    }
+
+    /// An empty reservation, to grow as data is kept in memory.
+    pub fn reservation(&self) -> Reservation {
+        Reservation {
+            budget: self.clone(),
+            bytes: 0,
+        }
+    }
+
+    pub fn try_reserve(&self, bytes: usize) -> Option<Reservation> {
+        let mut reservation = self.reservation();
+        reservation.try_grow(bytes).then_some(reservation)
+    }
+
+    /// A temporary file in the spill directory, removed when it is closed.
+    pub fn spill_file(&self) -> io::Result<File> {
+        tempfile::tempfile_in(&self.state.spill_dir)
+    }
+
+    fn acquire(&self, bytes: usize) -> bool {
+        let state = &self.state;
+        if !acquire(&state.used, state.limit, bytes) {
+            return false;
+        }
+        if !acquire(&state.pool.used, state.pool.limit, bytes) {
+            state.used.fetch_sub(bytes, Ordering::AcqRel);
+            return false;
+        }
+        state
+            .peak
+            .fetch_max(state.used.load(Ordering::Acquire), Ordering::AcqRel);
+        true
+    }
+
+    fn release(&self, bytes: usize) {
+        self.state.used.fetch_sub(bytes, Ordering::AcqRel);
+        self.state.pool.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+
+    fn record_spill(&self, bytes: usize) {
+        self.state.spilled.fetch_add(bytes, Ordering::AcqRel);
+    }
+}
+
+/// Memory held under a budget, given back when dropped.
+pub struct Reservation {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Hold `bytes` more, unless the budget or its pool is out of them.
+    pub fn try_grow(&mut self, bytes: usize) -> bool {
+        let granted = self.budget.acquire(bytes);
+        if granted {
+            self.bytes += bytes;
+        }
+        granted
+    }
+
+    /// Give back everything held.
+    pub fn clear(&mut self) {
+        self.budget.release(self.bytes);
+        self.bytes = 0;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Bytes kept in memory while the budget allows, and in a temporary file from then on.
+pub struct SpillBuffer {
+    budget: MemoryBudget,
+    memory: Vec<u8>,
+    reservation: Reservation,
+    file: Option<BufWriter<File>>,
+    len: usize,
+}
+
+impl SpillBuffer {
+    pub fn new(budget: MemoryBudget) -> Self {
+        SpillBuffer {
+            reservation: budget.reservation(),
+            budget,
+            memory: Vec::new(),
+            file: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let mut file = BufWriter::new(self.budget.spill_file()?);
+        file.write_all(&self.memory)?;
+        self.budget.record_spill(self.memory.len());
+        self.memory = Vec::new();
+        self.reservation.clear();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Read the bytes back from the start.
+    pub fn into_reader(self) -> io::Result<SpillReader> {
+        match self.file {
+            None => Ok(SpillReader {
+                inner: Box::new(Cursor::new(self.memory)),
+                _reservation: Some(self.reservation),
+            }),
+            Some(file) => {
+                let mut file = file.into_inner().map_err(|e| e.into_error())?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(SpillReader {
+                    inner: Box::new(BufReader::new(file)),
+                    _reservation: None,
+                })
+            }
+        }
+    }
+
+    /// All the bytes at once, for callers which can't read them bit by bit.
+    pub fn into_vec(self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.len);
+        self.into_reader()?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() && !self.reservation.try_grow(buf.len()) {
+            self.spill()?;
+        }
+        match &mut self.file {
+            Some(file) => {
+                file.write_all(buf)?;
+                self.budget.record_spill(buf.len());
+            }
+            None => self.memory.extend_from_slice(buf),
+        }
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads a [`SpillBuffer`] back, holding its memory until dropped.
+pub struct SpillReader {
+    inner: Box<dyn Read + Send>,
+    _reservation: Option<Reservation>,
+}
+
+impl Read for SpillReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Chunks of bytes moved out of memory, read back by where they start.
+pub struct SpillFile {
+    budget: MemoryBudget,
+    file: Mutex<File>,
+    len: u64,
+}
+
+impl SpillFile {
+    pub fn new(budget: &MemoryBudget) -> io::Result<Self> {
+        Ok(SpillFile {
+            budget: budget.clone(),
+            file: Mutex::new(budget.spill_file()?),
+            len: 0,
+        })
+    }
+
+    /// Append `data`, returning where it starts.
+    pub fn append(&mut self, data: &[u8]) -> io::Result<u64> {
+        let file = self.file.get_mut().unwrap();
+        file.seek(SeekFrom::Start(self.len))?;
+        file.write_all(data)?;
+        self.budget.record_spill(data.len());
+        let pos = self.len;
+        self.len += data.len() as u64;
+        Ok(pos)
+    }
+
+    pub fn read(&self, pos: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(pos))?;
+        let mut data = vec![0; len];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    use super::{MemoryBudget, MemoryPool, SpillBuffer, SpillFile};
+
+    #[test]
+    fn test_reservations_stay_within_budget_and_pool() {
+        let pool = Arc::new(MemoryPool::new(150));
+        let first = MemoryBudget::new(100, pool.clone());
+        let second = MemoryBudget::new(100, pool.clone());
+
+        let mut held = first.try_reserve(80).unwrap();
+        assert!(!held.try_grow(30));
+        assert!(second.try_reserve(80).is_none());
+        let other = second.try_reserve(70).unwrap();
+        assert_eq!(pool.used(), 150);
+
+        drop(held);
+        assert_eq!((first.used(), first.peak()), (0, 80));
+        assert!(second.try_reserve(30).is_some());
+        drop(other);
+        assert_eq!(pool.used(), 0);
+    }
+
+    #[test]
+    fn test_spill_buffer_moves_to_disk_over_budget() {
+        let budget = MemoryBudget::new(8, Arc::new(MemoryPool::new(0)));
+        let mut buffer = SpillBuffer::new(budget.clone());
+        buffer.write_all(b"PACK").unwrap();
+        assert!(!buffer.is_spilled());
+        buffer.write_all(b" bytes past the budget").unwrap();
+        assert!(buffer.is_spilled());
+        assert_eq!(budget.used(), 0);
+        assert_eq!(budget.spilled(), buffer.len());
+
+        let mut data = String::new();
+        buffer
+            .into_reader()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "PACK bytes past the budget");
+    }
+
+    #[test]
+    fn test_spill_file_reads_chunks_back() {
+        let budget = MemoryBudget::unlimited();
+        let mut file = SpillFile::new(&budget).unwrap();
+        let first = file.append(b"tree").unwrap();
+        let second = file.append(b"blob data").unwrap();
+        assert_eq!(file.read(second, 9).unwrap(), b"blob data");
+        assert_eq!(file.read(first, 4).unwrap(), b"tree");
+    }
+}
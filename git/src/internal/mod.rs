@@ -7,8 +7,10 @@ use std::fmt::Display;
 
 use crate::errors::GitError;
 
+pub mod budget;
 pub mod object;
 pub mod pack;
+pub mod parallel;
 pub mod zlib;
 /// In Git, each object type is assigned a unique integer value, which is used to identify the
 /// type of the object in Git repositories.
@@ -0,0 +1,444 @@
+//! Reachability bitmaps, like the `.bitmap` files git writes next to its packs. A bitmap of a
+//! commit has a bit for each object of the repository, set for the objects reachable from the
+//! commit, so the objects a clone needs are the union of the bitmaps of the refs rather than
+//! the result of walking every commit and tree.
+//!
+//! The bitmaps are compressed with EWAH, as git's are: runs of words with every bit clear or
+//! every bit set take a single marker word, which also counts the literal words following it.
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::GitError;
+use crate::hash::Hash;
+
+const MAGIC: &[u8; 4] = b"MBTM";
+const VERSION: u32 = 1;
+
+const RUNNING_LEN_BITS: u32 = 32;
+const LARGEST_RUNNING_LEN: u64 = (1 << RUNNING_LEN_BITS) - 1;
+const LARGEST_LITERAL_COUNT: u64 = (1 << 31) - 1;
+
+/// A bitmap compressed with EWAH.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EwahBitmap {
+    /// The number of bits the bitmap covers
+    bit_size: usize,
+    /// Marker words, each followed by the literal words it counts
+    buffer: Vec<u64>,
+}
+
+impl EwahBitmap {
+    /// A bitmap of `bit_size` bits with the bits at `positions` set.
+    pub fn from_positions(bit_size: usize, positions: impl IntoIterator<Item = usize>) -> Self {
+        let mut words = vec![0u64; bit_size.div_ceil(64)];
+        for pos in positions {
+            words[pos / 64] |= 1 << (pos % 64);
+        }
+        EwahBitmap::from_words(bit_size, &words)
+    }
+
+    fn from_words(bit_size: usize, words: &[u64]) -> Self {
+        let mut buffer = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            let fill = words[i];
+            let mut running = 0;
+            if fill == 0 || fill == u64::MAX {
+                while i < words.len() && words[i] == fill && running < LARGEST_RUNNING_LEN {
+                    running += 1;
+                    i += 1;
+                }
+            }
+            let literals = i;
+            while i < words.len()
+                && words[i] != 0
+                && words[i] != u64::MAX
+                && ((i - literals) as u64) < LARGEST_LITERAL_COUNT
+            {
+                i += 1;
+            }
+            let running_bit = running > 0 && fill == u64::MAX;
+            buffer.push(
+                running_bit as u64
+                    | running << 1
+                    | ((i - literals) as u64) << (RUNNING_LEN_BITS + 1),
+            );
+            buffer.extend_from_slice(&words[literals..i]);
+        }
+        EwahBitmap { bit_size, buffer }
+    }
+
+    /// The uncompressed words of the bitmap.
+    fn words(&self) -> Vec<u64> {
+        let mut words = Vec::with_capacity(self.bit_size.div_ceil(64));
+        let mut i = 0;
+        while i < self.buffer.len() {
+            let (fill, running, literals) = marker(self.buffer[i]);
+            words.extend(std::iter::repeat_n(fill, running));
+            words.extend_from_slice(&self.buffer[i + 1..i + 1 + literals]);
+            i += 1 + literals;
+        }
+        words
+    }
+
+    pub fn bit_size(&self) -> usize {
+        self.bit_size
+    }
+
+    /// The positions of the bits set, in order.
+    pub fn ones(&self) -> Vec<usize> {
+        self.words()
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &word)| {
+                (0..64)
+                    .filter(move |bit| word & (1 << bit) != 0)
+                    .map(move |bit| i * 64 + bit)
+            })
+            .filter(|&pos| pos < self.bit_size)
+            .collect()
+    }
+
+    /// The bits set in either `self` or `other`.
+    pub fn or(&self, other: &EwahBitmap) -> EwahBitmap {
+        let mut words = self.words();
+        let other_words = other.words();
+        if words.len() < other_words.len() {
+            words.resize(other_words.len(), 0);
+        }
+        for (word, other) in words.iter_mut().zip(other_words) {
+            *word |= other;
+        }
+        EwahBitmap::from_words(self.bit_size.max(other.bit_size), &words)
+    }
+
+    /// The bitmap in git's EWAH layout: the number of bits and of words, the words, and the
+    /// position of the last marker word, all big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(12 + self.buffer.len() * 8);
+        data.extend_from_slice(&(self.bit_size as u32).to_be_bytes());
+        data.extend_from_slice(&(self.buffer.len() as u32).to_be_bytes());
+        let mut last_marker = 0;
+        let mut i = 0;
+        for (pos, word) in self.buffer.iter().enumerate() {
+            if pos == i {
+                last_marker = pos;
+                i += 1 + marker(*word).2;
+            }
+            data.extend_from_slice(&word.to_be_bytes());
+        }
+        data.extend_from_slice(&(last_marker as u32).to_be_bytes());
+        data
+    }
+
+    /// Read a bitmap written by [`EwahBitmap::to_bytes`] from the start of `data`, returning it
+    /// with the number of bytes it takes.
+    pub fn from_bytes(data: &[u8]) -> Result<(Self, usize), GitError> {
+        let invalid = || GitError::InvalidBitmap("truncated EWAH bitmap".to_owned());
+        let mut reader = Reader { data, pos: 0 };
+        let bit_size = reader.u32().ok_or_else(invalid)? as usize;
+        let len = reader.u32().ok_or_else(invalid)? as usize;
+        let buffer = (0..len)
+            .map(|_| reader.u64())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        reader.u32().ok_or_else(invalid)?;
+
+        // every marker has to count the words after it, and the words the bits they cover
+        let mut i = 0;
+        let mut words = 0;
+        while i < buffer.len() {
+            let (_, running, literals) = marker(buffer[i]);
+            words += running + literals;
+            i += 1 + literals;
+        }
+        if i != buffer.len() || words != bit_size.div_ceil(64) {
+            return Err(GitError::InvalidBitmap(
+                "EWAH words don't match its size".to_owned(),
+            ));
+        }
+        Ok((EwahBitmap { bit_size, buffer }, reader.pos))
+    }
+}
+
+/// The fill of the run of the marker `word`, the number of words in the run and the number of
+/// literal words after it.
+fn marker(word: u64) -> (u64, usize, usize) {
+    let fill = if word & 1 == 1 { u64::MAX } else { 0 };
+    let running = (word >> 1) & LARGEST_RUNNING_LEN;
+    let literals = word >> (RUNNING_LEN_BITS + 1);
+    (fill, running as usize, literals as usize)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+    }
+}
+
+/// The bitmaps of the commits the refs of a repository point at, over every object reachable
+/// from any of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReachabilityBitmaps {
+    /// The objects, ordered by id then in the order they were added, a bit of each bitmap
+    /// standing for the object at its position. A bitmap written before objects were added has
+    /// fewer bits, the ones past it are clear.
+    objects: Vec<Hash>,
+    commits: HashMap<Hash, EwahBitmap>,
+}
+
+impl ReachabilityBitmaps {
+    /// Bitmaps of the commits `tips`, where `edges` has the objects each commit and tree points
+    /// at: a commit its tree and parents, a tree its entries. Objects without edges, like
+    /// blobs, only have to appear as the target of one.
+    pub fn build(edges: &HashMap<Hash, Vec<Hash>>, tips: &[Hash]) -> Self {
+        let mut objects: Vec<Hash> = edges
+            .iter()
+            .flat_map(|(id, targets)| std::iter::once(id).chain(targets))
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        objects.sort();
+        let positions: HashMap<Hash, usize> =
+            objects.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        let mut commits = HashMap::new();
+        for tip in tips {
+            if !edges.contains_key(tip) || commits.contains_key(tip) {
+                continue;
+            }
+            let mut reachable = vec![false; objects.len()];
+            let mut stack = vec![*tip];
+            while let Some(id) = stack.pop() {
+                let pos = positions[&id];
+                if reachable[pos] {
+                    continue;
+                }
+                reachable[pos] = true;
+                if let Some(targets) = edges.get(&id) {
+                    stack.extend(targets.iter().filter(|t| !reachable[positions[*t]]));
+                }
+            }
+            let ones = reachable
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| **r)
+                .map(|(i, _)| i);
+            commits.insert(*tip, EwahBitmap::from_positions(objects.len(), ones));
+        }
+        ReachabilityBitmaps { objects, commits }
+    }
+
+    /// Whether there is a bitmap of `commit`.
+    pub fn contains(&self, commit: &Hash) -> bool {
+        self.commits.contains_key(commit)
+    }
+
+    /// The number of objects the bitmaps have a bit for.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// The objects reachable from any of `tips`, or `None` when one of them has no bitmap, as
+    /// the refs have moved since the bitmaps were written.
+    pub fn reachable(&self, tips: &[Hash]) -> Option<Vec<Hash>> {
+        let union = self.union(tips)?;
+        Some(union.ones().into_iter().map(|i| self.objects[i]).collect())
+    }
+
+    fn union(&self, tips: &[Hash]) -> Option<EwahBitmap> {
+        let mut union = EwahBitmap::from_positions(self.objects.len(), std::iter::empty());
+        for tip in tips {
+            union = union.or(self.commits.get(tip)?);
+        }
+        Some(union)
+    }
+
+    /// Add a bitmap of `commit`, reaching what the bitmaps of `bases` reach and `objects`. The
+    /// objects new to the bitmaps get the bits after the others, so the bitmaps there are stay
+    /// as they are. `None` when one of `bases` has no bitmap.
+    pub fn extend(&mut self, commit: Hash, bases: &[Hash], objects: &[Hash]) -> Option<()> {
+        let base = self.union(bases)?;
+        let mut positions: HashMap<Hash, usize> = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+        let mut ones = Vec::with_capacity(objects.len());
+        for id in objects {
+            let pos = *positions.entry(*id).or_insert_with(|| {
+                self.objects.push(*id);
+                self.objects.len() - 1
+            });
+            ones.push(pos);
+        }
+        let added = EwahBitmap::from_positions(self.objects.len(), ones);
+        self.commits.insert(commit, base.or(&added));
+        Some(())
+    }
+
+    /// Drop the bitmaps of the commits other than `commits`.
+    pub fn retain(&mut self, commits: &[Hash]) {
+        self.commits.retain(|id, _| commits.contains(id));
+    }
+
+    /// The bitmaps as stored: a magic and a version, the object ids in bit order, then each
+    /// commit id with its bitmap.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_be_bytes());
+        data.extend_from_slice(&(self.objects.len() as u32).to_be_bytes());
+        for id in &self.objects {
+            data.extend_from_slice(&id.0);
+        }
+        let mut commits: Vec<_> = self.commits.iter().collect();
+        commits.sort_by_key(|(id, _)| **id);
+        data.extend_from_slice(&(commits.len() as u32).to_be_bytes());
+        for (id, bitmap) in commits {
+            data.extend_from_slice(&id.0);
+            data.extend_from_slice(&bitmap.to_bytes());
+        }
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, GitError> {
+        let invalid = |what: &str| GitError::InvalidBitmap(what.to_owned());
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(4) != Some(&MAGIC[..]) || reader.u32() != Some(VERSION) {
+            return Err(invalid("unknown header"));
+        }
+        let count = reader.u32().ok_or_else(|| invalid("truncated objects"))? as usize;
+        let objects = (0..count)
+            .map(|_| reader.take(20).map(Hash::new_from_bytes))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("truncated objects"))?;
+
+        let count = reader.u32().ok_or_else(|| invalid("truncated commits"))? as usize;
+        let mut commits = HashMap::with_capacity(count);
+        for _ in 0..count {
+            let id = reader
+                .take(20)
+                .map(Hash::new_from_bytes)
+                .ok_or_else(|| invalid("truncated commits"))?;
+            let (bitmap, len) = EwahBitmap::from_bytes(&data[reader.pos..])?;
+            if bitmap.bit_size > objects.len() {
+                return Err(invalid("bitmap larger than the objects"));
+            }
+            reader.pos += len;
+            commits.insert(id, bitmap);
+        }
+        Ok(ReachabilityBitmaps { objects, commits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::hash::Hash;
+
+    use crate::internal::pack::bitmap::{EwahBitmap, ReachabilityBitmaps};
+
+    fn id(n: u8) -> Hash {
+        Hash([n; 20])
+    }
+
+    #[test]
+    fn test_ewah_round_trip() {
+        let positions = [0, 1, 63, 64, 200, 1000, 1001];
+        let bitmap = EwahBitmap::from_positions(5000, positions);
+        assert_eq!(bitmap.ones(), positions);
+        // the long runs of clear bits are a few marker words
+        assert!(bitmap.buffer.len() < 10);
+
+        let data = bitmap.to_bytes();
+        assert_eq!(EwahBitmap::from_bytes(&data).unwrap(), (bitmap, data.len()));
+        assert!(EwahBitmap::from_bytes(&data[..data.len() - 1]).is_err());
+
+        let full = EwahBitmap::from_positions(130, 0..130);
+        assert_eq!(full.buffer.len(), 2);
+        assert_eq!(full.ones(), (0..130).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ewah_or() {
+        let a = EwahBitmap::from_positions(300, [1, 100]);
+        let b = EwahBitmap::from_positions(300, [2, 100, 299]);
+        assert_eq!(a.or(&b).ones(), [1, 2, 100, 299]);
+        assert_eq!(a.or(&b).bit_size(), 300);
+    }
+
+    #[test]
+    fn test_reachability_bitmaps() {
+        // c2 -> c1, each commit with its tree, the trees sharing blob 9
+        let edges = HashMap::from([
+            (id(1), vec![id(11)]),
+            (id(2), vec![id(12), id(1)]),
+            (id(11), vec![id(9)]),
+            (id(12), vec![id(9), id(8)]),
+        ]);
+        let bitmaps = ReachabilityBitmaps::build(&edges, &[id(1), id(2)]);
+        assert_eq!(bitmaps.reachable(&[id(1)]).unwrap(), [id(1), id(9), id(11)]);
+        assert_eq!(bitmaps.reachable(&[id(2)]).unwrap().len(), 6);
+        assert_eq!(bitmaps.reachable(&[id(3)]), None);
+
+        let data = bitmaps.to_bytes();
+        assert_eq!(ReachabilityBitmaps::from_bytes(&data).unwrap(), bitmaps);
+        assert!(ReachabilityBitmaps::from_bytes(&data[..data.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_extend() {
+        let edges = HashMap::from([
+            (id(1), vec![id(11)]),
+            (id(2), vec![id(12), id(1)]),
+            (id(11), vec![id(9)]),
+            (id(12), vec![id(9), id(8)]),
+        ]);
+        let mut bitmaps = ReachabilityBitmaps::build(&edges, &[id(1)]);
+        assert_eq!(bitmaps.object_count(), 6);
+        // c2 was pushed on c1, only what c1 doesn't reach is added
+        bitmaps
+            .extend(id(2), &[id(1)], &[id(2), id(12), id(8)])
+            .unwrap();
+        let mut reachable = bitmaps.reachable(&[id(2)]).unwrap();
+        reachable.sort();
+        let mut expected = ReachabilityBitmaps::build(&edges, &[id(2)])
+            .reachable(&[id(2)])
+            .unwrap();
+        expected.sort();
+        assert_eq!(reachable, expected);
+        assert_eq!(bitmaps.reachable(&[id(1)]).unwrap(), [id(1), id(9), id(11)]);
+
+        // a new object gets a bit after the others, the bitmaps before have fewer bits
+        bitmaps.extend(id(3), &[id(2)], &[id(3)]).unwrap();
+        assert_eq!(bitmaps.object_count(), 7);
+        assert_eq!(bitmaps.reachable(&[id(3)]).unwrap().len(), 7);
+        let data = bitmaps.to_bytes();
+        assert_eq!(ReachabilityBitmaps::from_bytes(&data).unwrap(), bitmaps);
+
+        assert_eq!(bitmaps.extend(id(5), &[id(4)], &[id(5)]), None);
+        bitmaps.retain(&[id(3)]);
+        assert!(!bitmaps.contains(&id(1)));
+        assert!(bitmaps.contains(&id(3)));
+    }
+}
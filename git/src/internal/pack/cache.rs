@@ -1,4 +1,5 @@
 use std::{collections::HashMap, num::NonZeroUsize, cell::RefCell};
+use std::sync::Mutex;
 
 use lru::LruCache;
 
@@ -107,6 +108,84 @@ where
     
 }
 
+/// Bytes of delta bases a [`DeltaBaseCache`] keeps by default, the `core.deltaBaseCacheLimit` of
+/// git.
+pub const DEFAULT_DELTA_BASE_CACHE_SIZE: usize = 96 << 20;
+
+/// Where a delta base is: at an offset of the pack being decoded, or stored already.
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+pub enum BaseKey {
+    Offset(usize),
+    Hash(Hash),
+}
+
+/// The delta bases resolved while decoding a pack, shared by the threads decoding it.
+///
+/// Without it each delta re-applies the chain below it, so a pack holding many versions of one
+/// large file applies the deltas of the first versions again for every later one. The cache is
+/// bounded by the bytes of the bases it keeps rather than their number, it is for large objects
+/// mostly, and drops the least recently used first. A base larger than the whole cache isn't
+/// kept, a size of 0 turns it off.
+pub struct DeltaBaseCache<T> {
+    limit: usize,
+    inner: Mutex<DeltaBases<T>>,
+}
+
+struct DeltaBases<T> {
+    lru: LruCache<BaseKey, (T, usize)>,
+    bytes: usize,
+}
+
+impl<T: Clone> DeltaBaseCache<T> {
+    pub fn new(limit: usize) -> Self {
+        DeltaBaseCache {
+            limit,
+            inner: Mutex::new(DeltaBases {
+                lru: LruCache::unbounded(),
+                bytes: 0,
+            }),
+        }
+    }
+
+    /// A cache of the size `MEGA_PACK_DELTA_BASE_CACHE` sets, in bytes.
+    pub fn from_env() -> Self {
+        let mut limit = DEFAULT_DELTA_BASE_CACHE_SIZE;
+        crate::utils::get_env_number("MEGA_PACK_DELTA_BASE_CACHE", &mut limit);
+        DeltaBaseCache::new(limit)
+    }
+
+    pub fn get(&self, key: &BaseKey) -> Option<T> {
+        if self.limit == 0 {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.lru.get(key).map(|(base, _)| base.clone())
+    }
+
+    /// Keep `base`, of `size` bytes, dropping the least recently used bases over the limit.
+    pub fn put(&self, key: BaseKey, base: T, size: usize) {
+        if size > self.limit {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if let Some((_, old_size)) = inner.lru.put(key, (base, size)) {
+            inner.bytes -= old_size;
+        }
+        inner.bytes += size;
+        while inner.bytes > self.limit {
+            match inner.lru.pop_lru() {
+                Some((_, (_, dropped))) => inner.bytes -= dropped,
+                None => break,
+            }
+        }
+    }
+
+    /// Bytes of the bases kept.
+    pub fn bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+}
+
 pub mod kvstore{
     use std::cell::RefCell;
     use std::collections::HashMap;
@@ -167,6 +246,7 @@ mod test {
 
     use serde_json::to_vec;
 
+    use crate::internal::pack::cache::{BaseKey, DeltaBaseCache};
     use crate::internal::pack::{ObjectCache, _Cache};
     use crate::{hash::Hash, internal::object::blob};
     #[test] //TODO: to test
@@ -185,4 +265,26 @@ mod test {
         let h1 = Hash::new(&data);
         cache.put(4, h1, Arc::new(blob::Blob { id: h1, data }));
     }
+
+    #[test]
+    fn test_delta_base_cache() {
+        let cache = DeltaBaseCache::new(10);
+        cache.put(BaseKey::Offset(12), "a", 4);
+        cache.put(BaseKey::Offset(40), "b", 4);
+        assert_eq!(cache.get(&BaseKey::Offset(12)), Some("a"));
+        // over the limit, the least recently used base goes
+        cache.put(BaseKey::Hash(Hash::new(&b"c".to_vec())), "c", 4);
+        assert_eq!(cache.get(&BaseKey::Offset(40)), None);
+        assert_eq!(cache.get(&BaseKey::Offset(12)), Some("a"));
+        assert_eq!(cache.get(&BaseKey::Hash(Hash::new(&b"c".to_vec()))), Some("c"));
+        assert_eq!(cache.bytes(), 8);
+        // larger than the whole cache
+        cache.put(BaseKey::Offset(80), "d", 11);
+        assert_eq!(cache.get(&BaseKey::Offset(80)), None);
+        assert_eq!(cache.bytes(), 8);
+
+        let off = DeltaBaseCache::new(0);
+        off.put(BaseKey::Offset(12), "a", 0);
+        assert_eq!(off.get(&BaseKey::Offset(12)), None);
+    }
 }
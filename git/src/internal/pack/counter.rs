@@ -54,6 +54,7 @@ pub enum CounterType {
     Base,
     Delta,
     CacheHit,
+    CacheMiss,
     DB,
 }
 /// A Counter in The process of parsing and saving git objects
@@ -62,6 +63,7 @@ pub struct DecodeCounter {
     base: usize,
     delta: usize,
     cache_hit: usize,
+    cache_miss: usize,
     db_look: usize,
     delta_depth: usize,
     //TODO time count
@@ -73,19 +75,26 @@ impl DecodeCounter {
             CounterType::Base => self.base += 1,
             CounterType::Delta => self.delta += 1,
             CounterType::CacheHit => self.cache_hit += 1,
+            CounterType::CacheMiss => self.cache_miss += 1,
             CounterType::DB => self.db_look += 1,
         }
     }
     pub fn count_depth(&mut self, depth: usize) {
         self.delta_depth += depth;
     }
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hit
+    }
+    pub fn cache_misses(&self) -> usize {
+        self.cache_miss
+    }
 }
 impl Display for DecodeCounter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "base:{} delta:{}, cache_hit: :{},Storage operation:{},delta_depth:{}",
-            self.base, self.delta, self.cache_hit, self.db_look, self.delta_depth
+            "base:{} delta:{}, cache_hit: :{},cache_miss:{},Storage operation:{},delta_depth:{}",
+            self.base, self.delta, self.cache_hit, self.cache_miss, self.db_look, self.delta_depth
         )
     }
 }
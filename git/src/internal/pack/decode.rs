@@ -87,6 +87,7 @@ pub struct HashCounter<R> {
     inner: R,
     hash: CoreWrapper<sha1::Sha1Core>,
     count_hash: bool,
+    bytes: usize,
 }
 impl<R> HashCounter<R>
 where
@@ -97,12 +98,33 @@ where
             inner,
             hash: Sha1::new(),
             count_hash,
+            bytes: 0,
         }
     }
     pub fn final_hash(&self) -> Hash {
         let re: [u8; 20] = self.hash.clone().finalize().into();
         Hash(re)
     }
+    /// The bytes read so far.
+    pub fn bytes_read(&self) -> usize {
+        self.bytes
+    }
+    /// Read the checksum ending the pack, failing when it isn't the hash of what was read
+    /// before it, as the pack was cut short or corrupted on its way.
+    pub fn verify_checksum(&mut self) -> Result<Hash, GitError> {
+        let computed = self.final_hash();
+        let mut id = [0u8; 20];
+        self.read_exact(&mut id)
+            .map_err(|_| GitError::InvalidPackFile("missing the checksum".to_string()))?;
+        if Hash(id) != computed {
+            return Err(GitError::InvalidPackFile(format!(
+                "checksum {} isn't the hash of the pack, {}",
+                Hash(id).to_plain_str(),
+                computed.to_plain_str()
+            )));
+        }
+        Ok(computed)
+    }
 }
 impl<R> BufRead for HashCounter<R>
 where
@@ -117,6 +139,7 @@ where
         if self.count_hash {
             self.hash.update(&buffer[..amt]);
         }
+        self.bytes += amt;
         self.inner.consume(amt);
     }
 }
@@ -129,6 +152,7 @@ where
         if self.count_hash {
             self.hash.update(&buf[..o]);
         }
+        self.bytes += o;
         Ok(o)
     }
 }
@@ -143,10 +167,17 @@ fn read_tail_hash(tail: &mut impl Read) -> Hash {
 }
 #[cfg(test)]
 mod test {
+    use std::io::Cursor;
+    use std::sync::Arc;
     use std::{fs::File, path::Path};
 
     use tokio_test::block_on;
 
+    use crate::hash::Hash;
+    use crate::internal::object::{blob::Blob, ObjectT};
+    use crate::internal::pack::decode::HashCounter;
+    use crate::internal::pack::encode::pack_encode;
+    use crate::internal::pack::preload::PackPreload;
     use crate::internal::pack::Pack;
 
     #[test]
@@ -171,4 +202,30 @@ mod test {
         assert_eq!(p.version, 2);
         assert_eq!(p.number_of_objects, p.number_of_objects());
     }
+
+    #[test]
+    fn test_verify_checksum() {
+        let id = Hash([0u8; 20]);
+        let obj_vec: Vec<Arc<dyn ObjectT>> = vec![Arc::new(Blob {
+            id,
+            data: b"hello".to_vec(),
+        })];
+        let mut pack = pack_encode(obj_vec).unwrap();
+
+        let mut reader = HashCounter::new(Cursor::new(pack.clone()), true);
+        PackPreload::new(&mut reader);
+        assert!(reader.verify_checksum().is_ok());
+        assert_eq!(reader.bytes_read(), pack.len());
+
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        let mut reader = HashCounter::new(Cursor::new(pack.clone()), true);
+        PackPreload::new(&mut reader);
+        assert!(reader.verify_checksum().is_err());
+
+        // cut short
+        let mut reader = HashCounter::new(Cursor::new(pack[..last].to_vec()), true);
+        PackPreload::new(&mut reader);
+        assert!(reader.verify_checksum().is_err());
+    }
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::{Cursor, Error, Write};
 use std::sync::Arc;
 
@@ -6,9 +7,12 @@ use sha1::{Digest, Sha1};
 use delta;
 use entity::objects;
 
+use crate::hash::Hash;
 use crate::internal::object::ObjectT;
+use crate::internal::parallel;
 use crate::internal::pack::header::EntryHeader;
 use crate::internal::zlib::stream::deflate::Write as Writer;
+use crate::utils;
 
 const SLID_WINDWOS: usize = 20;
 
@@ -85,22 +89,134 @@ where
 //
 
 pub fn pack_encode(obj_vec: Vec<Arc<dyn ObjectT>>) -> Result<Vec<u8>, Error> {
+    pack_encode_to(obj_vec, Vec::new())
+}
+
+/// Encode the objects into `out_data`, dropping each one once it's written, so that a
+/// [`SpillBuffer`](crate::internal::budget::SpillBuffer) can keep the pack out of memory. The
+/// objects are compressed on several threads, see [`parallel::ordered_map`].
+pub fn pack_encode_to<W: Write>(obj_vec: Vec<Arc<dyn ObjectT>>, out_data: W) -> Result<W, Error> {
+    pack_encode_with(obj_vec, out_data, || Ok(()))
+}
+
+/// [`pack_encode_to`], calling `before_object` before each object is encoded, which stops the
+/// encoding when it fails.
+pub fn pack_encode_with<W: Write, F: FnMut() -> Result<(), Error>>(
+    obj_vec: Vec<Arc<dyn ObjectT>>,
+    mut out_data: W,
+    mut before_object: F,
+) -> Result<W, Error> {
     let mut hash = Sha1::new();
-    let mut out_data = Vec::new();
     let header_data = encode_header(obj_vec.len());
     hash.update(&header_data);
     out_data.write_all(&header_data)?;
 
-    for obj in obj_vec {
-        let obj_data = encode_one_object(obj)?;
+    // the objects are compressed across the rayon pool, and written in order
+    parallel::ordered_map(obj_vec, encode_one_object, |obj_data| {
+        before_object()?;
+        let obj_data = obj_data?;
         hash.update(&obj_data);
-        out_data.write_all(&obj_data)?;
-    }
+        out_data.write_all(&obj_data)
+    })?;
     let hash_result = hash.finalize();
     out_data.write_all(&hash_result)?;
     Ok(out_data)
 }
 
+/// The delta of an object against `base`, kept from the pack it was pushed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredDelta {
+    pub base: Hash,
+    pub data: Vec<u8>,
+}
+
+/// [`pack_encode_with`] for a client with the `ofs-delta` capability, sending the objects of
+/// `deltas` as their stored delta when their base is in the pack too, instead of in full. Each
+/// base is moved before its deltas, which point back at it by offset.
+pub fn pack_encode_reusing<W: Write, F: FnMut() -> Result<(), Error>>(
+    obj_vec: Vec<Arc<dyn ObjectT>>,
+    deltas: HashMap<Hash, StoredDelta>,
+    mut out_data: W,
+    mut before_object: F,
+) -> Result<W, Error> {
+    let mut hash = Sha1::new();
+    let header_data = encode_header(obj_vec.len());
+    hash.update(&header_data);
+    out_data.write_all(&header_data)?;
+
+    // the offset of each object written, for the deltas after it
+    let mut offsets: HashMap<Hash, usize> = HashMap::new();
+    let mut offset = header_data.len();
+    let entries = delta_order(obj_vec, deltas);
+    parallel::ordered_map(
+        entries,
+        |(obj, delta)| {
+            let id = obj.get_hash();
+            match delta {
+                Some(delta) => encode_one_ojbect(6, delta.data.len(), &delta.data)
+                    .map(|data| (id, Some(delta.base), data)),
+                None => encode_one_object(obj).map(|data| (id, None, data)),
+            }
+        },
+        |entry| {
+            before_object()?;
+            let (id, base, mut obj_data) = entry?;
+            if let Some(base) = base {
+                // the base offset goes between the type and size and the compressed delta
+                let size_len = obj_data.iter().position(|b| b & 0x80 == 0).unwrap() + 1;
+                let distance = utils::write_offset_encoding((offset - offsets[&base]) as u64);
+                obj_data.splice(size_len..size_len, distance);
+            }
+            offsets.insert(id, offset);
+            offset += obj_data.len();
+            hash.update(&obj_data);
+            out_data.write_all(&obj_data)
+        },
+    )?;
+    let hash_result = hash.finalize();
+    out_data.write_all(&hash_result)?;
+    Ok(out_data)
+}
+
+/// The objects in the order to write them, each with its delta to send when its base comes
+/// before it. A delta is dropped when its base isn't one of the objects, or when the stored
+/// deltas of objects turn out to be based on each other.
+fn delta_order(
+    obj_vec: Vec<Arc<dyn ObjectT>>,
+    mut deltas: HashMap<Hash, StoredDelta>,
+) -> Vec<(Arc<dyn ObjectT>, Option<StoredDelta>)> {
+    let ids: Vec<Hash> = obj_vec.iter().map(|obj| obj.get_hash()).collect();
+    let index: HashMap<Hash, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let mut objects: Vec<Option<Arc<dyn ObjectT>>> = obj_vec.into_iter().map(Some).collect();
+    let mut order = Vec::with_capacity(objects.len());
+    for i in 0..objects.len() {
+        if objects[i].is_none() {
+            continue;
+        }
+        // the chain of bases down to one sent in full, or already written
+        let mut chain = vec![i];
+        while let Some(&last) = chain.last() {
+            match deltas.get(&ids[last]).and_then(|d| index.get(&d.base)) {
+                Some(&base) if chain.contains(&base) => {
+                    deltas.remove(&ids[last]);
+                    break;
+                }
+                Some(&base) if objects[base].is_some() => chain.push(base),
+                _ => break,
+            }
+        }
+        while let Some(j) = chain.pop() {
+            if let Some(obj) = objects[j].take() {
+                let delta = deltas
+                    .remove(&ids[j])
+                    .filter(|d| index.contains_key(&d.base));
+                order.push((obj, delta));
+            }
+        }
+    }
+    order
+}
+
 fn encode_header(object_number: usize) -> Vec<u8> {
     let mut result: Vec<u8> = vec![
         b'P', b'A', b'C', b'K', // The logotype of the Pack File
@@ -188,15 +304,19 @@ fn u32_vec(value: u32) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::io::Cursor;
     use std::sync::Arc;
 
     use tokio_test::block_on;
 
     use crate::hash::Hash;
+    use crate::internal::budget::{MemoryBudget, MemoryPool, SpillBuffer};
     use crate::internal::object::blob::Blob;
     use crate::internal::object::ObjectT;
-    use crate::internal::pack::encode::{pack_encode, Encoder};
+    use crate::internal::pack::encode::{
+        delta_order, pack_encode, pack_encode_reusing, pack_encode_to, Encoder, StoredDelta,
+    };
     use crate::internal::pack::Pack;
 
     #[test]
@@ -215,6 +335,24 @@ mod tests {
         block_on(Pack::decode(&mut buff)).unwrap();
     }
 
+    #[test]
+    fn test_encode_spilled_to_disk() {
+        let id = Hash([0u8; 20]);
+        let obj_vec: Vec<Arc<dyn ObjectT>> = (0..100)
+            .map(|i| {
+                let data = format!("hello,{}", i).into_bytes();
+                Arc::new(Blob { id, data }) as Arc<dyn ObjectT>
+            })
+            .collect();
+        let budget = MemoryBudget::new(64, Arc::new(MemoryPool::new(0)));
+
+        let buffer = pack_encode_to(obj_vec, SpillBuffer::new(budget.clone())).unwrap();
+        assert!(buffer.is_spilled());
+        assert_eq!(budget.used(), 0);
+        let mut buff = Cursor::new(buffer.into_vec().unwrap());
+        block_on(Pack::decode(&mut buff)).unwrap();
+    }
+
     #[test]
     fn test_pack_encoder() {
         let id = Hash([0u8; 20]);
@@ -235,4 +373,64 @@ mod tests {
         let mut buff = Cursor::new(pack_data);
         block_on(Pack::decode(&mut buff)).unwrap();
     }
+
+    fn blob(n: u8, data: &str) -> Arc<dyn ObjectT> {
+        Arc::new(Blob {
+            id: Hash([n; 20]),
+            data: data.as_bytes().to_vec(),
+        })
+    }
+
+    #[test]
+    fn test_encode_reusing_deltas() {
+        let base = "hello, this is the first version of the file\n".repeat(10);
+        let new = format!("{}and a new line\n", base);
+        let deltas = HashMap::from([(
+            Hash([2; 20]),
+            StoredDelta {
+                base: Hash([1; 20]),
+                data: delta::encode(base.as_bytes(), new.as_bytes()),
+            },
+        )]);
+        // the delta comes first, before its base
+        let obj_vec = vec![blob(2, &new), blob(1, &base), blob(3, "hello")];
+
+        let reused = pack_encode_reusing(obj_vec.clone(), deltas, Vec::new(), || Ok(())).unwrap();
+        let full = pack_encode(obj_vec).unwrap();
+        assert!(reused.len() < full.len());
+        block_on(Pack::decode(&mut Cursor::new(reused))).unwrap();
+    }
+
+    #[test]
+    fn test_delta_order() {
+        let delta = |base: u8| StoredDelta {
+            base: Hash([base; 20]),
+            data: vec![],
+        };
+        // 1 is a delta of 2, itself a delta of 3; 4 and 5 are deltas of each other, 6 of a
+        // missing object
+        let deltas = HashMap::from([
+            (Hash([1; 20]), delta(2)),
+            (Hash([2; 20]), delta(3)),
+            (Hash([4; 20]), delta(5)),
+            (Hash([5; 20]), delta(4)),
+            (Hash([6; 20]), delta(9)),
+        ]);
+        let obj_vec = (1..=6).map(|n| blob(n, "")).collect();
+        let order: Vec<(u8, Option<u8>)> = delta_order(obj_vec, deltas)
+            .into_iter()
+            .map(|(obj, d)| (obj.get_hash().0[0], d.map(|d| d.base.0[0])))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (3, None),
+                (2, Some(3)),
+                (1, Some(2)),
+                (5, None),
+                (4, Some(5)),
+                (6, None)
+            ]
+        );
+    }
 }
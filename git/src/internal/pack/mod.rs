@@ -13,6 +13,7 @@ use self::{
 use crate::hash::Hash;
 use crate::internal::object::ObjectT;
 
+pub mod bitmap;
 mod cache;
 pub mod counter;
 mod cqueue;
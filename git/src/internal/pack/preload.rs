@@ -1,8 +1,9 @@
 use std::{
     collections::HashMap,
+    convert::Infallible,
     io::{Cursor, Read},
     sync::{Arc, Mutex},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use num_cpus;
@@ -13,12 +14,18 @@ use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use tokio::sync::{RwLock, RwLockReadGuard};
 
+use common::metrics;
+use db_entity::mega_object_delta;
 use delta;
 use entity::{mr, objects};
 use storage::{driver::database::storage::ObjectStorage, utils::id_generator::generate_id};
 
-use crate::internal::pack::cache::{kvstore::ObjectCache as kvObjectCache, ObjectCache, _Cache};
+use crate::internal::budget::{MemoryBudget, Reservation, SpillFile};
+use crate::internal::pack::cache::{
+    kvstore::ObjectCache as kvObjectCache, BaseKey, DeltaBaseCache, ObjectCache, _Cache,
+};
 use crate::internal::pack::{counter::GitTypeCounter, EntryHeader, Pack};
+use crate::internal::parallel;
 use crate::{
     errors::GitError,
     internal::{
@@ -59,6 +66,17 @@ impl Entry {
             link: Set(None),
         }
     }
+    /// The delta this object came as in the pack, against the object `base`, kept to be sent
+    /// again without computing it, see [`crate::internal::pack::encode::StoredDelta`].
+    fn convert_to_delta_model(&self, base: Hash, delta: &[u8]) -> mega_object_delta::ActiveModel {
+        mega_object_delta::ActiveModel {
+            id: Set(generate_id()),
+            git_id: Set(self.hash.unwrap().to_plain_str()),
+            base_id: Set(base.to_plain_str()),
+            data: Set(delta.to_vec()),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+        }
+    }
 }
 impl ToRedisArgs for Entry {
     fn write_redis_args<W>(&self, out: &mut W)
@@ -97,15 +115,27 @@ impl FromRedisValue for Entry {
 }
 
 /// All Git Objects pre loading in memeory of one pack file.
+/// The data of the objects past the memory budget is kept in a spill file instead.
 pub struct PackPreload {
     map: HashMap<usize, usize>, //Offset -> iterator in entity
     entries: Vec<Entry>,        // store git entries by vec.
     counter: GitTypeCounter,
+    budget: MemoryBudget,
+    _reservation: Reservation, // the memory of `entries`, held until the preload is dropped
+    spill: Option<SpillFile>,
+    spilled: HashMap<usize, (u64, usize)>, // index in entity -> position and length in spill
 }
 
 #[allow(unused)]
 impl PackPreload {
-    pub fn new<R>(mut r: R) -> PackPreload
+    pub fn new<R>(r: R) -> PackPreload
+    where
+        R: std::io::BufRead,
+    {
+        PackPreload::with_budget(r, MemoryBudget::unlimited())
+    }
+
+    pub fn with_budget<R>(mut r: R, budget: MemoryBudget) -> PackPreload
     where
         R: std::io::BufRead,
     {
@@ -118,6 +148,9 @@ impl PackPreload {
         let mut map = HashMap::new();
         let obj_number = pack.number_of_objects();
         let mut entries = Vec::with_capacity(obj_number);
+        let mut reservation = budget.reservation();
+        let mut spill: Option<SpillFile> = None;
+        let mut spilled = HashMap::new();
         tracing::info!("Start Preload git objects:{} ", obj_number);
         for i in 0..obj_number {
             if i % 10000 == 0 {
@@ -166,6 +199,14 @@ impl PackPreload {
             let mut content = Vec::with_capacity(size);
             reader.read_to_end(&mut content).unwrap();
             iter_offset += reader.decompressor.total_in() as usize;
+            if !reservation.try_grow(content.len()) {
+                let file = match &mut spill {
+                    Some(file) => file,
+                    None => spill.insert(SpillFile::new(&budget).unwrap()),
+                };
+                spilled.insert(i, (file.append(&content).unwrap(), content.len()));
+                content = Vec::new();
+            }
 
             //println!("offset :{},type :{}",offset,type_num);
             entries.push(Entry {
@@ -179,11 +220,66 @@ impl PackPreload {
         }
         let end = start.elapsed().as_millis();
         tracing::info!("Preload time cost:{} ms", end);
-        PackPreload {
+        if !spilled.is_empty() {
+            tracing::info!(
+                "Spilled {} of {} git objects over the memory budget to disk",
+                spilled.len(),
+                obj_number
+            );
+        }
+        let mut preload = PackPreload {
             map,
             entries,
             counter,
+            budget,
+            _reservation: reservation,
+            spill,
+            spilled,
+        };
+        preload.hash_bases();
+        preload
+    }
+
+    /// Compute the ids of the objects stored whole, across the rayon pool, see
+    /// [`parallel::ordered_map`]. Those of the deltas are only known once they are resolved.
+    fn hash_bases(&mut self) {
+        let start = Instant::now();
+        let mut hashes = Vec::with_capacity(self.entries.len());
+        let preload = &*self;
+        let hashed = parallel::ordered_map(
+            0..preload.len(),
+            |i| {
+                let entry = &preload.entries[i];
+                if !entry.header.is_base() {
+                    return None;
+                }
+                let hash = match (&preload.spill, preload.spilled.get(&i)) {
+                    (Some(spill), Some((pos, len))) => {
+                        object_hash(&entry.header, &spill.read(*pos, *len).unwrap())
+                    }
+                    _ => object_hash(&entry.header, &entry.data),
+                };
+                Some(hash)
+            },
+            |hash| {
+                hashes.push(hash);
+                Ok::<_, Infallible>(())
+            },
+        );
+        let Ok(()) = hashed;
+        for (entry, hash) in self.entries.iter_mut().zip(hashes) {
+            entry.hash = hash;
+        }
+        tracing::info!("Hash time cost:{} ms", start.elapsed().as_millis());
+    }
+
+    /// The entry at `i`, with its data read back when it was spilled.
+    fn entry(&self, i: usize) -> Entry {
+        let mut entry = self.entries[i].clone();
+        if let (Some(spill), Some((pos, len))) = (&self.spill, self.spilled.get(&i)) {
+            entry.data = spill.read(*pos, *len).unwrap();
         }
+        entry
     }
 
     pub fn len(&self) -> usize {
@@ -207,16 +303,19 @@ impl PackPreload {
 ///
 /// # Returns
 ///
-/// The function returns a `Result<i64, GitError>`, where the `i64` represents the `mr_id`
+/// The function returns a `Result<(i64, Duration), GitError>`, where the `i64` represents the `mr_id`,
+/// the `Duration` the longest time a thread spent saving objects,
 /// and `GitError` represents any potential error that might occur during the process.
 ///
-pub async fn decode_load(p: PackPreload, storage: Arc<dyn ObjectStorage>) -> Result<i64, GitError> {
+pub async fn decode_load(p: PackPreload, storage: Arc<dyn ObjectStorage>) -> Result<(i64, Duration), GitError> {
     let decode_counter: Arc<Mutex<DecodeCounter>> = Arc::new(Mutex::new(DecodeCounter::default()));
     let all_len = p.len();
     tracing::info!("Decode the preload git object\n{}", p.counter);
     let (cpu_number, chunk) = thread_chunk(all_len);
     tracing::info!("Deal with the object using {} threads. ", cpu_number);
     let share: Arc<RwLock<PackPreload>> = Arc::new(RwLock::new(p));
+    // shared by the threads, the base of a chain decoded by one is often needed by another
+    let bases: Arc<DeltaBaseCache<Entry>> = Arc::new(DeltaBaseCache::from_env());
     let mr_id = generate_id();
     
     let mut cache_type: String= String::new();
@@ -227,6 +326,7 @@ pub async fn decode_load(p: PackPreload, storage: Arc<dyn ObjectStorage>) -> Res
             let shard_clone = Arc::clone(&share);
             let st_clone = storage.clone();
             let counter_clone = decode_counter.clone();
+            let bases_clone = bases.clone();
             let begin = i * chunk;
             let end = if i == cpu_number - 1 {
                 all_len
@@ -236,32 +336,36 @@ pub async fn decode_load(p: PackPreload, storage: Arc<dyn ObjectStorage>) -> Res
             match &cache_type as &str {
                 "redis" => 
                 tokio::spawn(async move {
-                    produce_object::<kvObjectCache<Entry>>(shard_clone, st_clone, begin, end, counter_clone, mr_id).await
+                    produce_object::<kvObjectCache<Entry>>(shard_clone, st_clone, bases_clone, begin, end, counter_clone, mr_id).await
                 }),
                 "lru" =>
                 tokio::spawn(async move {
-                    produce_object::<ObjectCache<Entry>>(shard_clone, st_clone, begin, end, counter_clone, mr_id).await
+                    produce_object::<ObjectCache<Entry>>(shard_clone, st_clone, bases_clone, begin, end, counter_clone, mr_id).await
                 }),
                 _ =>
                 tokio::spawn(async move {
-                    produce_object::<ObjectCache<Entry>>(shard_clone, st_clone, begin, end, counter_clone, mr_id).await
+                    produce_object::<ObjectCache<Entry>>(shard_clone, st_clone, bases_clone, begin, end, counter_clone, mr_id).await
                 }),
             }
         })
         .collect();
 
     let mut batch_success = true;
+    let mut db_time = Duration::ZERO;
     for handle in producer_handles {
-        let res = handle.await.unwrap();
-        if res.is_err() {
-            batch_success = false;
+        match handle.await.unwrap() {
+            Ok(thread_db_time) => db_time = db_time.max(thread_db_time),
+            Err(_) => batch_success = false,
         }
     }
     assert!(batch_success);
     let re = decode_counter.lock().unwrap();
-    tracing::info!("Summary : {}", re);
+    tracing::info!("Summary : {}, delta bases cached: {} bytes", re, bases.bytes());
+    let metrics = metrics::metrics();
+    metrics.object_cache_hits.inc_by(re.cache_hits() as u64);
+    metrics.object_cache_misses.inc_by(re.cache_misses() as u64);
 
-    Ok(mr_id)
+    Ok((mr_id, db_time))
 }
 
 use crate::internal::pack::counter::CounterType::*;
@@ -274,6 +378,7 @@ use crate::internal::pack::counter::CounterType::*;
 /// - `txn`: An `Arc` containing the database transaction for database operations.
 /// - `data`: A shared `Arc<RwLock<PackPreload>>` containing the preload data.
 /// - `storage`: A shared `Arc<dyn ObjectStorage>` trait object providing storage capabilities.
+/// - `bases`: The delta bases resolved by all the threads, see [`DeltaBaseCache`].
 /// - `range_begin`: The starting index of the range of entries to process.
 /// - `range_end`: The ending index of the range of entries to process.
 /// - `counter`: A shared `Arc<Mutex<DecodeCounter>>` for counting decode operations.
@@ -286,27 +391,31 @@ use crate::internal::pack::counter::CounterType::*;
 /// # Returns
 ///
 /// This function returns a `Result`:
-/// - `Ok(())` if the Git objects are produced successfully.
+/// - `Ok(Duration)` with the time spent saving objects, if the Git objects are produced successfully.
 /// - `Err(GitError)` in case of any errors during the operation.
 ///
 async fn produce_object<TC>(
     data: Arc<RwLock<PackPreload>>,
     storage: Arc<dyn ObjectStorage>,
+    bases: Arc<DeltaBaseCache<Entry>>,
     range_begin: usize,
     range_end: usize,
     counter: Arc<Mutex<DecodeCounter>>,
     mr_id: i64,
-)  -> Result<(), GitError> where TC: _Cache<T = Entry> {
+)  -> Result<Duration, GitError> where TC: _Cache<T = Entry> {
 
     let thread_id: u16 = rand::thread_rng().gen();
     tracing::info!("thread begin : {}", thread_id);
     let mut mr_to_obj_model = Vec::<mr::ActiveModel>::with_capacity(1001);
     let mut git_obj_model = Vec::<objects::ActiveModel>::with_capacity(1001);
+    let mut delta_model = Vec::<mega_object_delta::ActiveModel>::new();
 
     let mut object_cache_size = 1000;
     utils::get_env_number("GIT_INTERNAL_DECODE_CACHE_SIZE", &mut object_cache_size);
 
     let cache= TC::new(Some(object_cache_size));
+    // the resolved objects waiting to be saved, saved early when they go over the memory budget
+    let mut batch_reservation = data.read().await.budget.reservation();
 
     let start = Instant::now();
     let mut db_cost = 0;
@@ -326,13 +435,17 @@ async fn produce_object<TC>(
         //     tracing::info!("thread id  : {} run to obj :{}", thread_id,i );
         // }
         let read_auth = data.read().await;
-        let e = &read_auth.entries[i];
+        let e = &read_auth.entry(i);
 
         let mut result_entity;
+        // the object this one is a delta of
+        let mut delta_base = None;
         match e.header {
             EntryHeader::RefDelta { base_id } => {
-                if let Some(entry) = get_ref_object_fromdb(storage.clone(), base_id, counter.clone(), e).await {
+                if let Some(entry) = get_ref_object_fromdb(storage.clone(), &bases, base_id, counter.clone(), e).await {
+                    bases.put(BaseKey::Offset(e.offset), entry.clone(), entry.data.len());
                     result_entity = entry;
+                    delta_base = Some(base_id);
                 } else {
                     continue;
                 }
@@ -345,23 +458,32 @@ async fn produce_object<TC>(
                     match front_entry.header{
 
                         EntryHeader::RefDelta { base_id } => {
+                            if let Some(t) = bases.get(&BaseKey::Hash(base_id)) {
+                                stack.push(t);
+                                continue;
+                            }
                             match storage.get_obj_data_by_id(&base_id.to_plain_str()).await{
                                 Ok(model) => {
                                     let model = model.unwrap();
-                                    stack.push(Entry { header: EntryHeader::from_string(&model.object_type), offset: 0, data: model.data, hash: None });
+                                    let base = Entry { header: EntryHeader::from_string(&model.object_type), offset: 0, data: model.data, hash: None };
+                                    bases.put(BaseKey::Hash(base_id), base.clone(), base.data.len());
+                                    stack.push(base);
                                 },
                                 Err(err) =>  tracing::error!("ID{}, ref delta ERROR:{}",thread_id,err),
                             }
                         },
                         EntryHeader::OfsDelta { base_distance } => {
-                            if let Some(t) = cache.get(base_distance){
+                            if let Some(t) = cache.get(base_distance).or_else(|| bases.get(&BaseKey::Offset(base_distance))){
                                 {
                                     counter.lock().unwrap().count(CacheHit);
                                 }
                                 stack.push(t);
                             }else{
+                                {
+                                    counter.lock().unwrap().count(CacheMiss);
+                                }
                                 let pos = read_auth.map.get(&base_distance).unwrap();
-                                stack.push(read_auth.entries[*pos].clone());
+                                stack.push(read_auth.entry(*pos));
                             }
                         },
                         _ => {break;},
@@ -374,10 +496,19 @@ async fn produce_object<TC>(
                 }
                 let mut base_obj = stack.pop().unwrap();
                 while let Some(e) = stack.pop() {
+                    if stack.is_empty() {
+                        let hash = base_obj.hash.unwrap_or_else(|| object_hash(&base_obj.header, &base_obj.data));
+                        delta_base = Some(hash);
+                    }
                     base_obj.data = match delta::decode(&mut Cursor::new(&e.data), &base_obj.data){
                         Ok(a) => a,
                         Err(err) => {tracing::error!("thread id:{} err:{}",thread_id,err); panic!("err!");},
-                    }
+                    };
+                    // the id is the one of the object resolved, not of its base
+                    base_obj.hash = None;
+                    // each object of the chain is the base of the deltas of the next versions
+                    base_obj.offset = e.offset;
+                    bases.put(BaseKey::Offset(e.offset), base_obj.clone(), base_obj.data.len());
                 };
 
                 result_entity = base_obj;
@@ -391,24 +522,29 @@ async fn produce_object<TC>(
             }
         }
 
-        // only compute the Hash value at last 
+        // only compute the Hash value at last, the objects stored whole have it already
         result_entity = compute_hash(result_entity);
         //DEBUG , NEED TO DELETE
         // tracing::info!("thread id:{},HEADER TYPE: {} offset :{}, HASH :{}",thread_id,result_entity.header,result_entity.offset,result_entity.hash.unwrap());
         //
         mr_to_obj_model.push(result_entity.convert_to_mr_model(mr_id));
         git_obj_model.push(result_entity.convert_to_data_model());
+        if let Some(base) = delta_base {
+            delta_model.push(result_entity.convert_to_delta_model(base, &e.data));
+        }
+        let over_budget = !batch_reservation.try_grow(result_entity.data.len());
         cache.put(e.offset, result_entity.hash.unwrap(), result_entity);
 
         //save to storage
 
-        if mr_to_obj_model.len() >= batch_size {
+        if mr_to_obj_model.len() >= batch_size || over_budget {
             tracing::debug!("starting put new batch, thread:{}, current_db_cost:{}", thread_id, db_cost);
             let db_start = Instant::now();
             let stc = storage.clone();
             // let h = tokio::spawn(async move {
                 stc.save_mr_objects(None, mr_to_obj_model).await.unwrap();
                 stc.save_obj_data(None, git_obj_model).await.unwrap();
+                stc.save_obj_deltas(std::mem::take(&mut delta_model)).await.unwrap();
             // });
             let cost = db_start.elapsed().as_millis();
             db_cost += cost;
@@ -427,6 +563,7 @@ async fn produce_object<TC>(
             // }
             mr_to_obj_model = Vec::with_capacity(batch_size);
             git_obj_model = Vec::with_capacity(batch_size);
+            batch_reservation.clear();
         }
     }
     let db_start = Instant::now();
@@ -436,6 +573,9 @@ async fn produce_object<TC>(
     if !git_obj_model.is_empty() {
         storage.save_obj_data(None, git_obj_model).await.unwrap();
     }
+    if !delta_model.is_empty() {
+        storage.save_obj_deltas(delta_model).await.unwrap();
+    }
     let cost = db_start.elapsed().as_millis();
     db_cost += cost;
     // await the remaining threads
@@ -448,7 +588,7 @@ async fn produce_object<TC>(
     // }
     let end = start.elapsed().as_millis();
     tracing::info!("Git Object Produce thread one  time cost:{} ms, db_time_cost:{}", end, db_cost);
-    Ok(())
+    Ok(Duration::from_millis(db_cost as u64))
 }
 
 /// Asynchronous function to perform delta offset operation.
@@ -484,8 +624,11 @@ fn delta_offset_obj<T>(
             }   
             b_obj = _obj; 
         } else {
+            {
+                counter.lock().unwrap().count(CacheMiss);
+            }
             let pos = share.map.get(&base_distance).unwrap();
-            b_obj = share.entries[*pos].clone();
+            b_obj = share.entry(*pos);
         }
 
         if !b_obj.header.is_base(){
@@ -511,15 +654,22 @@ fn compute_hash(mut e: Entry) -> Entry {
         _ => (),
     }
 
+    if e.hash.is_none() {
+        e.hash = Some(object_hash(&e.header, &e.data));
+    }
+    e
+}
+
+/// The id of the object of type `header` with `data`.
+fn object_hash(header: &EntryHeader, data: &[u8]) -> Hash {
     let mut h = Sha1::new();
-    h.update(e.header.to_bytes());
+    h.update(header.to_bytes());
     h.update(b" ");
-    h.update(e.data.len().to_string());
+    h.update(data.len().to_string());
     h.update(b"\0");
-    h.update(&e.data);
+    h.update(data);
     let re: [u8; 20] = h.finalize().into();
-    e.hash = Some(Hash(re));
-    e
+    Hash(re)
 }
 
 fn thread_chunk(len: usize) -> (usize, usize) {
@@ -534,53 +684,63 @@ fn thread_chunk(len: usize) -> (usize, usize) {
 
 async fn get_ref_object_fromdb(
     storage: Arc<dyn ObjectStorage>,
+    bases: &DeltaBaseCache<Entry>,
     base_id: Hash,
     counter: Arc<Mutex<DecodeCounter>>,
     e: &Entry,
 ) -> Option<Entry> {
-    // The ref object does not need to be queried from the cache because it must exist in different packages,
-    // So it is not allowed to be queried from the database
-    let base_type;
-    let base_data;
-    match storage.get_obj_data_by_id(&base_id.to_plain_str()).await {
-        Ok(model) => match model {
-            Some(db_obj) => {
-                base_type = EntryHeader::from_string(&db_obj.object_type);
+    // The base of a ref delta is in another pack, so it is read from the database, once for all the
+    // deltas of this pack based on it
+    let base = match bases.get(&BaseKey::Hash(base_id)) {
+        Some(base) => base,
+        None => match storage.get_obj_data_by_id(&base_id.to_plain_str()).await {
+            Ok(Some(db_obj)) => {
                 {
                     counter.lock().unwrap().count(DB);
                 }
-                base_data = db_obj.data;
-                let re = delta::decode(&mut Cursor::new(e.data.clone()), &base_data);
-                if re.is_err(){
-                    tracing::error!("REF_DELTA ERROR:{}",re.err().unwrap());
-                    return None}
-                let undelta_obj = Entry {
-                    header: base_type,
-                    offset: e.offset,
-                    data: re.unwrap(),
+                let base = Entry {
+                    header: EntryHeader::from_string(&db_obj.object_type),
+                    offset: 0,
+                    data: db_obj.data,
                     hash: None,
                 };
-                {
-                    counter.lock().unwrap().count(Delta);
-                }
-
-                Some(undelta_obj)
+                bases.put(BaseKey::Hash(base_id), base.clone(), base.data.len());
+                base
             }
-            None => {
+            Ok(None) => {
                 tracing::error!("REf Delta error from storage: not fount base object");
-                None
+                return None;
+            }
+            Err(err) => {
+                tracing::error!("REf Delta error from storage:{}", err);
+                return None;
             }
         },
-        Err(err) => {
-            tracing::error!("REf Delta error from storage:{}", err);
-            None
-        }
+    };
+    let re = delta::decode(&mut Cursor::new(e.data.clone()), &base.data);
+    if re.is_err(){
+        tracing::error!("REF_DELTA ERROR:{}",re.err().unwrap());
+        return None}
+    let undelta_obj = Entry {
+        header: base.header,
+        offset: e.offset,
+        data: re.unwrap(),
+        hash: None,
+    };
+    {
+        counter.lock().unwrap().count(Delta);
     }
+
+    Some(undelta_obj)
 }
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::BufReader, path::Path};
+    use std::{fs::File, io::BufReader, io::Cursor, path::Path, sync::Arc};
 
+    use crate::hash::Hash;
+    use crate::internal::budget::{MemoryBudget, MemoryPool};
+    use crate::internal::object::{blob::Blob, ObjectT};
+    use crate::internal::pack::encode::pack_encode;
     use crate::internal::pack::preload::PackPreload;
     use tokio::test;
 
@@ -595,4 +755,26 @@ mod tests {
         PackPreload::new(BufReader::new(file));
        
     }
+
+    #[test]
+    async fn preload_spills_over_budget() {
+        let id = Hash([0u8; 20]);
+        let obj_vec: Vec<Arc<dyn ObjectT>> = (0..3)
+            .map(|i| {
+                let data = format!("hello,{}", i).into_bytes();
+                Arc::new(Blob { id, data }) as Arc<dyn ObjectT>
+            })
+            .collect();
+        let pack = pack_encode(obj_vec).unwrap();
+        let budget = MemoryBudget::new(10, Arc::new(MemoryPool::new(0)));
+
+        let p = PackPreload::with_budget(Cursor::new(pack), budget.clone());
+        assert_eq!(budget.spilled(), 14);
+        for i in 0..3 {
+            assert_eq!(p.entry(i).data, format!("hello,{}", i).into_bytes());
+            // the objects stored whole are hashed while preloading, the spilled ones too
+            let object = format!("blob 7\0hello,{}", i).into_bytes();
+            assert_eq!(p.entries[i].hash, Some(Hash::new(&object)));
+        }
+    }
 }
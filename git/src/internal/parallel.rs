@@ -0,0 +1,126 @@
+//!
+//! Work on many objects spread over the rayon pool, with the results kept in order, like the
+//! objects of a pack which are compressed at once but written one after the other.
+//!
+//! The pool has a thread per core, `RAYON_NUM_THREADS` sets another number.
+//!
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+
+/// Items worked on or waiting for the sink at once, per thread of the pool.
+const WINDOW_PER_THREAD: usize = 4;
+
+/// Run `work` on each of `items` across the rayon pool and hand the results to `sink` in the
+/// order of the items, each one as soon as it and those before it are done.
+///
+/// `sink` runs on the calling thread, which mustn't be one of the pool. A few items per thread
+/// are worked on or wait for the sink at once, bounding the memory their results take. When
+/// `sink` fails no item is started anymore, those started are finished and dropped, and its error
+/// is returned. A panic of `work` is resumed on the calling thread.
+pub fn ordered_map<T, R, E, W, S>(
+    items: impl IntoIterator<Item = T>,
+    work: W,
+    mut sink: S,
+) -> Result<(), E>
+where
+    T: Send,
+    R: Send,
+    W: Fn(T) -> R + Sync,
+    S: FnMut(R) -> Result<(), E>,
+{
+    let window = rayon::current_num_threads() * WINDOW_PER_THREAD;
+    let work = &work;
+    let (sender, receiver) = mpsc::channel();
+    rayon::in_place_scope(|scope| {
+        let mut items = items.into_iter().enumerate();
+        let mut done = BTreeMap::new();
+        let mut started = 0;
+        let mut next = 0;
+        loop {
+            while started - next < window {
+                let Some((i, item)) = items.next() else {
+                    break;
+                };
+                let sender = sender.clone();
+                scope.spawn(move |_| {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| work(item)));
+                    // the receiver is gone once the sink failed
+                    let _ = sender.send((i, result));
+                });
+                started += 1;
+            }
+            if next == started {
+                return Ok(());
+            }
+            let (i, result) = receiver
+                .recv()
+                .expect("the sender lives as long as the scope");
+            done.insert(i, result);
+            while let Some(result) = done.remove(&next) {
+                next += 1;
+                match result {
+                    Ok(result) => sink(result)?,
+                    Err(payload) => panic::resume_unwind(payload),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ordered_map;
+
+    #[test]
+    fn test_ordered_map() {
+        let mut results = Vec::new();
+        // the later items take less time, so they are done first
+        ordered_map(
+            (0..200u64).collect::<Vec<_>>(),
+            |i| {
+                std::thread::sleep(Duration::from_micros((200 - i) * 20));
+                i
+            },
+            |i| {
+                results.push(i);
+                Ok::<_, ()>(())
+            },
+        )
+        .unwrap();
+        assert_eq!(results, (0..200).collect::<Vec<_>>());
+
+        let mut sunk = 0;
+        let result = ordered_map(
+            0..1000,
+            |i| i,
+            |i| {
+                sunk += 1;
+                if i == 10 {
+                    Err("stop")
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        assert_eq!(result, Err("stop"));
+        assert_eq!(sunk, 11);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad item")]
+    fn test_ordered_map_panic() {
+        let _ = ordered_map(
+            0..10,
+            |i| {
+                if i == 3 {
+                    panic!("bad item");
+                }
+                i
+            },
+            |_| Ok::<_, ()>(()),
+        );
+    }
+}
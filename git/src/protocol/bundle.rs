@@ -0,0 +1,245 @@
+//!
+//! Git bundles, a pack together with the refs it holds, to move repositories where no network
+//! reaches.
+//!
+//! A bundle starts with a header: `# v2 git bundle` or `# v3 git bundle`, for v3 its
+//! capabilities as `@<capability>[=<value>]` lines, then the prerequisites as
+//! `-<id> [<comment>]`, commits the objects of the pack build on which the receiving repository
+//! must have already, then the refs as `<id> <refname>`. An empty line ends the header and the
+//! pack follows it.
+//!
+//! Objects are named by SHA-1 here, so a v3 bundle of another `object-format` is refused, as are
+//! the partial bundles a `filter` makes and capabilities git doesn't know either.
+//!
+use thiserror::Error;
+
+use crate::protocol::limits::is_object_id;
+
+const V2_SIGNATURE: &str = "# v2 git bundle";
+const V3_SIGNATURE: &str = "# v3 git bundle";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BundleError {
+    #[error("not a git bundle")]
+    NotABundle,
+
+    #[error("invalid bundle header line: {0}")]
+    InvalidLine(String),
+
+    #[error("unsupported bundle capability: {0}")]
+    UnsupportedCapability(String),
+
+    #[error("the bundle header has no end")]
+    Truncated,
+
+    #[error("the bundle holds no refs")]
+    NoRefs,
+
+    #[error("no pack follows the bundle header")]
+    NoPack,
+}
+
+/// A ref of a bundle and the object it points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleRef {
+    pub id: String,
+    pub name: String,
+}
+
+/// A commit the objects of a bundle build on, which is not in its pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prerequisite {
+    pub id: String,
+    /// Usually the subject of the commit
+    pub comment: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleHeader {
+    /// 2 or 3
+    pub version: u8,
+    pub prerequisites: Vec<Prerequisite>,
+    pub refs: Vec<BundleRef>,
+}
+
+impl BundleHeader {
+    /// The header of a bundle of `refs` holding every object they reach.
+    pub fn new(version: u8, refs: Vec<BundleRef>) -> Self {
+        BundleHeader {
+            version,
+            prerequisites: Vec::new(),
+            refs,
+        }
+    }
+
+    /// Parse the header at the start of `data`, returning it with the offset of the pack.
+    pub fn parse(data: &[u8]) -> Result<(BundleHeader, usize), BundleError> {
+        let mut offset = 0;
+        let mut next_line = || -> Result<&str, BundleError> {
+            let rest = &data[offset..];
+            let end = rest
+                .iter()
+                .position(|b| *b == b'\n')
+                .ok_or(BundleError::Truncated)?;
+            offset += end + 1;
+            std::str::from_utf8(&rest[..end])
+                .map_err(|_| BundleError::InvalidLine(String::from_utf8_lossy(&rest[..end]).into()))
+        };
+
+        let version = match next_line().map_err(|_| BundleError::NotABundle)? {
+            V2_SIGNATURE => 2,
+            V3_SIGNATURE => 3,
+            _ => return Err(BundleError::NotABundle),
+        };
+        let mut header = BundleHeader {
+            version,
+            prerequisites: Vec::new(),
+            refs: Vec::new(),
+        };
+        let mut capabilities_done = version == 2;
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(capability) = line.strip_prefix('@') {
+                if capabilities_done {
+                    return Err(BundleError::InvalidLine(line.to_owned()));
+                }
+                if capability != "object-format=sha1" {
+                    return Err(BundleError::UnsupportedCapability(capability.to_owned()));
+                }
+                continue;
+            }
+            capabilities_done = true;
+            if let Some(prerequisite) = line.strip_prefix('-') {
+                let (id, comment) = prerequisite.split_once(' ').unwrap_or((prerequisite, ""));
+                if !is_object_id(id) {
+                    return Err(BundleError::InvalidLine(line.to_owned()));
+                }
+                header.prerequisites.push(Prerequisite {
+                    id: id.to_owned(),
+                    comment: comment.to_owned(),
+                });
+            } else {
+                match line.split_once(' ') {
+                    Some((id, name)) if is_object_id(id) && !name.is_empty() => {
+                        header.refs.push(BundleRef {
+                            id: id.to_owned(),
+                            name: name.to_owned(),
+                        })
+                    }
+                    _ => return Err(BundleError::InvalidLine(line.to_owned())),
+                }
+            }
+        }
+        if header.refs.is_empty() {
+            return Err(BundleError::NoRefs);
+        }
+        if !data[offset..].starts_with(b"PACK") {
+            return Err(BundleError::NoPack);
+        }
+        Ok((header, offset))
+    }
+
+    /// The header as written at the start of a bundle, its empty line included.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut header = String::new();
+        if self.version == 3 {
+            header.push_str(V3_SIGNATURE);
+            header.push_str("\n@object-format=sha1\n");
+        } else {
+            header.push_str(V2_SIGNATURE);
+            header.push('\n');
+        }
+        for prerequisite in &self.prerequisites {
+            header.push('-');
+            header.push_str(&prerequisite.id);
+            if !prerequisite.comment.is_empty() {
+                header.push(' ');
+                header.push_str(&prerequisite.comment);
+            }
+            header.push('\n');
+        }
+        for r in &self.refs {
+            header.push_str(&format!("{} {}\n", r.id, r.name));
+        }
+        header.push('\n');
+        header.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleError, BundleHeader, BundleRef, Prerequisite};
+
+    fn main_ref() -> BundleRef {
+        BundleRef {
+            id: "a".repeat(40),
+            name: "refs/heads/main".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_encode_and_parse() {
+        let header = BundleHeader::new(2, vec![main_ref()]);
+        let encoded = header.encode();
+        assert_eq!(
+            encoded,
+            format!("# v2 git bundle\n{} refs/heads/main\n\n", "a".repeat(40)).into_bytes()
+        );
+        let mut bundle = encoded.clone();
+        bundle.extend_from_slice(b"PACK\0\0\0\x02");
+        assert_eq!(
+            BundleHeader::parse(&bundle).unwrap(),
+            (header, encoded.len())
+        );
+
+        let mut header = BundleHeader::new(3, vec![main_ref()]);
+        header.prerequisites.push(Prerequisite {
+            id: "b".repeat(40),
+            comment: "Add the parser".to_owned(),
+        });
+        let mut bundle = header.encode();
+        assert!(bundle.starts_with(b"# v3 git bundle\n@object-format=sha1\n-bbb"));
+        bundle.extend_from_slice(b"PACK");
+        assert_eq!(BundleHeader::parse(&bundle).unwrap().0, header);
+    }
+
+    #[test]
+    fn test_parse_refused() {
+        let refs = format!("{} refs/heads/main\n\nPACK", "a".repeat(40));
+        let parse = |bundle: String| BundleHeader::parse(bundle.as_bytes()).unwrap_err();
+        assert_eq!(parse("PACK".to_owned()), BundleError::NotABundle);
+        assert_eq!(
+            parse(format!("# v3 git bundle\n@object-format=sha256\n{}", refs)),
+            BundleError::UnsupportedCapability("object-format=sha256".to_owned())
+        );
+        assert_eq!(
+            parse(format!("# v3 git bundle\n@filter=blob:none\n{}", refs)),
+            BundleError::UnsupportedCapability("filter=blob:none".to_owned())
+        );
+        assert!(matches!(
+            parse(format!("# v2 git bundle\n@object-format=sha1\n{}", refs)),
+            BundleError::InvalidLine(_)
+        ));
+        assert_eq!(
+            parse("# v2 git bundle\n\nPACK".to_owned()),
+            BundleError::NoRefs
+        );
+        assert_eq!(
+            parse(format!(
+                "# v2 git bundle\n{} refs/heads/main\n",
+                "a".repeat(40)
+            )),
+            BundleError::Truncated
+        );
+        assert_eq!(
+            parse(format!(
+                "# v2 git bundle\n{} refs/heads/main\n\n",
+                "a".repeat(40)
+            )),
+            BundleError::NoPack
+        );
+    }
+}
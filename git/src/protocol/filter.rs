@@ -0,0 +1,150 @@
+//! Partial clone support: the objects a client asks to leave out of a pack with a `filter`
+//! line, and the blobs sent anyway because it is likely to need them next.
+
+use std::str::FromStr;
+
+use crate::protocol::PackProtocol;
+use crate::utils::get_env_number;
+
+/// Objects a partial clone leaves out of its packs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFilter {
+    /// `blob:none`, no blobs at all
+    BlobNone,
+    /// `blob:limit=<n>`, no blobs of `n` bytes or more
+    BlobLimit(u64),
+}
+
+impl FromStr for ObjectFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "blob:none" {
+            return Ok(ObjectFilter::BlobNone);
+        }
+        let limit = s.strip_prefix("blob:limit=").ok_or(())?;
+        // git accepts the units k, m and g
+        let (digits, unit) = match limit.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => (&limit[..i], c.to_ascii_lowercase()),
+            _ => (limit, 'b'),
+        };
+        let scale = match unit {
+            'b' => 1,
+            'k' => 1024,
+            'm' => 1024 * 1024,
+            'g' => 1024 * 1024 * 1024,
+            _ => return Err(()),
+        };
+        let n: u64 = digits.parse().map_err(|_| ())?;
+        Ok(ObjectFilter::BlobLimit(n.saturating_mul(scale)))
+    }
+}
+
+impl ObjectFilter {
+    pub fn allows(&self, size: u64) -> bool {
+        match self {
+            ObjectFilter::BlobNone => false,
+            ObjectFilter::BlobLimit(limit) => size < *limit,
+        }
+    }
+}
+
+/// Blobs a filtered pack includes anyway because a client reads them right after cloning,
+/// each one saves it a round trip to fetch the blob on demand.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrefetchPolicy {
+    /// Blobs directly in the root tree, like the README and build files
+    pub top_level: bool,
+    /// Blobs smaller than this anywhere in the tree, 0 for none
+    pub small_blob_size: u64,
+    /// Blobs with one of these file names anywhere in the tree
+    pub names: Vec<String>,
+}
+
+impl PrefetchPolicy {
+    /// The policy set by `MEGA_PREFETCH_TOP_LEVEL` (on by default),
+    /// `MEGA_PREFETCH_SMALL_BLOB_SIZE` and `MEGA_PREFETCH_NAMES`, a comma separated list.
+    pub fn from_env() -> Self {
+        let mut policy = PrefetchPolicy {
+            top_level: true,
+            ..Default::default()
+        };
+        get_env_number("MEGA_PREFETCH_TOP_LEVEL", &mut policy.top_level);
+        get_env_number("MEGA_PREFETCH_SMALL_BLOB_SIZE", &mut policy.small_blob_size);
+        if let Ok(names) = std::env::var("MEGA_PREFETCH_NAMES") {
+            policy.names = names
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        policy
+    }
+
+    /// Whether the blob named `name`, `size` bytes large, in a tree `depth` levels below the
+    /// root tree is sent ahead.
+    pub fn includes(&self, name: &str, depth: usize, size: u64) -> bool {
+        (self.top_level && depth == 0)
+            || size < self.small_blob_size
+            || self.names.iter().any(|n| n == name)
+    }
+}
+
+/// What a filter did to a pack, logged to tell how much the prefetch policy sends, and the
+/// objects the pack needs found missing on the way.
+#[derive(Debug, Default)]
+pub struct FilterStats {
+    pub omitted: usize,
+    pub prefetched: usize,
+    pub missing: Vec<String>,
+}
+
+impl PackProtocol {
+    /// Whether a blob goes in the pack: always without a filter, otherwise when the filter or
+    /// the prefetch policy lets it through.
+    pub fn send_blob(&self, name: &str, depth: usize, size: u64, stats: &mut FilterStats) -> bool {
+        let Some(filter) = self.filter else {
+            return true;
+        };
+        if filter.allows(size) {
+            return true;
+        }
+        if self.prefetch.includes(name, depth, size) {
+            stats.prefetched += 1;
+            return true;
+        }
+        stats.omitted += 1;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ObjectFilter, PrefetchPolicy};
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!("blob:none".parse(), Ok(ObjectFilter::BlobNone));
+        assert_eq!("blob:limit=100".parse(), Ok(ObjectFilter::BlobLimit(100)));
+        assert_eq!("blob:limit=2k".parse(), Ok(ObjectFilter::BlobLimit(2048)));
+        assert_eq!("tree:0".parse::<ObjectFilter>(), Err(()));
+        assert_eq!("blob:limit=2x".parse::<ObjectFilter>(), Err(()));
+        assert!(ObjectFilter::BlobLimit(100).allows(99));
+        assert!(!ObjectFilter::BlobLimit(100).allows(100));
+    }
+
+    #[test]
+    fn test_prefetch_policy() {
+        let policy = PrefetchPolicy {
+            top_level: true,
+            small_blob_size: 512,
+            names: vec!["Cargo.toml".to_owned()],
+        };
+        assert!(policy.includes("README.md", 0, 1 << 20));
+        assert!(policy.includes("lib.rs", 2, 100));
+        assert!(policy.includes("Cargo.toml", 3, 1 << 20));
+        assert!(!policy.includes("lib.rs", 1, 1 << 20));
+        assert!(!PrefetchPolicy::default().includes("README.md", 0, 0));
+    }
+}
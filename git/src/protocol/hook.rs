@@ -0,0 +1,474 @@
+//!
+//! External pre-receive hooks, the commands admins set to accept or refuse pushes, run by
+//! receive-pack in a sandbox after the built-in push checks pass and before any ref moves.
+//!
+//! `MEGA_PRE_RECEIVE_HOOKS` lists the executables, comma separated, run one after the other like
+//! git's `pre-receive`: each is given a `<old-id> <new-id> <ref-name>` line per pushed ref on its
+//! stdin, and a non-zero exit status refuses the whole push. What a hook prints is relayed to the
+//! client on the progress side-band, where git shows it as `remote:` lines.
+//!
+//! The sandbox of a hook:
+//!
+//! - it runs in a process group of its own, which is killed once the hook exits, so background
+//!   processes it starts don't outlive it;
+//! - it is killed after `MEGA_HOOK_TIMEOUT_SECS`, refusing the push;
+//! - `MEGA_HOOK_CPU_SECS`, `MEGA_HOOK_MEMORY` and `MEGA_HOOK_MAX_FILES` bound its CPU time,
+//!   address space and open files, 0 turning a limit off;
+//! - it starts in an empty environment and working directory, seeing the objects of the push
+//!   through a read-only quarantine directory given as `GIT_ALTERNATE_OBJECT_DIRECTORIES` and
+//!   `GIT_QUARANTINE_PATH` of an empty `GIT_DIR`, so `git cat-file` and `git log` work on them;
+//! - only the first `MEGA_HOOK_OUTPUT_BYTES` of its stdout and stderr are kept.
+//!
+//! Objects pushed earlier are not in the quarantine, a hook sees the objects new to the server.
+//!
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::protocol::limits::env_limit;
+use crate::protocol::PackProtocol;
+use crate::utils::compress_zlib;
+
+const DEFAULT_TIMEOUT_SECS: usize = 60;
+const DEFAULT_CPU_SECS: usize = 60;
+const DEFAULT_MEMORY: usize = 1024 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 256;
+const DEFAULT_OUTPUT_BYTES: usize = 64 * 1024;
+
+// output still unread this long after a hook was killed belongs to a process which left its group
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+const OBJECT_TYPES: [&str; 4] = ["commit", "tree", "blob", "tag"];
+
+/// The pre-receive hooks and the sandbox they run in, no hooks by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreReceiveHooks {
+    pub commands: Vec<PathBuf>,
+    pub timeout: Duration,
+    /// CPU seconds a hook can use, 0 for no limit
+    pub cpu_secs: u64,
+    /// Bytes of address space a hook can map, 0 for no limit
+    pub memory: u64,
+    /// Files a hook can have open at once, 0 for no limit
+    pub max_files: u64,
+    /// Bytes of stdout and of stderr kept of a hook
+    pub max_output: usize,
+    /// Directory the quarantines are created in
+    pub temp_dir: PathBuf,
+}
+
+impl Default for PreReceiveHooks {
+    fn default() -> Self {
+        PreReceiveHooks {
+            commands: Vec::new(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS as u64),
+            cpu_secs: DEFAULT_CPU_SECS as u64,
+            memory: DEFAULT_MEMORY as u64,
+            max_files: DEFAULT_MAX_FILES as u64,
+            max_output: DEFAULT_OUTPUT_BYTES,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+/// How a hook ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookRun {
+    pub command: PathBuf,
+    /// Exit status, `None` when the hook was killed
+    pub status: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl HookRun {
+    /// Why the hook refuses the push, `None` when it accepts it.
+    pub fn rejection(&self, timeout: Duration) -> Option<String> {
+        let name = self.command.display();
+        if self.timed_out {
+            Some(format!(
+                "pre-receive hook {} timed out after {}s",
+                name,
+                timeout.as_secs()
+            ))
+        } else {
+            match self.status {
+                Some(0) => None,
+                Some(code) => Some(format!(
+                    "pre-receive hook {} declined with exit status {}",
+                    name, code
+                )),
+                None => Some(format!("pre-receive hook {} was killed", name)),
+            }
+        }
+    }
+}
+
+impl PreReceiveHooks {
+    pub fn from_env() -> Self {
+        let commands = std::env::var("MEGA_PRE_RECEIVE_HOOKS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        let temp_dir = match std::env::var("MEGA_PACK_SPILL_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir.trim()),
+            _ => std::env::temp_dir(),
+        };
+        PreReceiveHooks {
+            commands,
+            timeout: Duration::from_secs(
+                env_limit("MEGA_HOOK_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS) as u64,
+            ),
+            cpu_secs: env_limit("MEGA_HOOK_CPU_SECS", DEFAULT_CPU_SECS) as u64,
+            memory: env_limit("MEGA_HOOK_MEMORY", DEFAULT_MEMORY) as u64,
+            max_files: env_limit("MEGA_HOOK_MAX_FILES", DEFAULT_MAX_FILES) as u64,
+            max_output: env_limit("MEGA_HOOK_OUTPUT_BYTES", DEFAULT_OUTPUT_BYTES),
+            temp_dir,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Run `command` in the sandbox with `stdin`, the objects of the push in `quarantine`.
+    pub async fn run(
+        &self,
+        command: &Path,
+        stdin: &[u8],
+        quarantine: &Quarantine,
+        envs: &[(&str, String)],
+    ) -> io::Result<HookRun> {
+        let mut cmd = Command::new(command);
+        cmd.env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", quarantine.work_dir())
+            .env("GIT_DIR", quarantine.git_dir())
+            .env("GIT_ALTERNATE_OBJECT_DIRECTORIES", quarantine.objects_dir())
+            .env("GIT_QUARANTINE_PATH", quarantine.objects_dir())
+            .envs(envs.iter().map(|(k, v)| (*k, v)))
+            .current_dir(quarantine.work_dir())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true);
+        let limits = [
+            (libc::RLIMIT_CPU, self.cpu_secs),
+            (libc::RLIMIT_AS, self.memory),
+            (libc::RLIMIT_NOFILE, self.max_files),
+        ];
+        // SAFETY: setrlimit is async-signal-safe and the closure allocates nothing
+        unsafe {
+            cmd.pre_exec(move || {
+                for (resource, limit) in limits {
+                    if limit == 0 {
+                        continue;
+                    }
+                    let rlimit = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit as libc::rlim_t,
+                    };
+                    if libc::setrlimit(resource, &rlimit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = cmd.spawn()?;
+        let group = child.id().map(|id| id as libc::pid_t);
+        let mut input = child.stdin.take().unwrap();
+        let stdin = stdin.to_vec();
+        // a hook not reading its stdin closes it early, which is not an error
+        let writer = tokio::spawn(async move {
+            let _ = input.write_all(&stdin).await;
+        });
+        let stdout = tokio::spawn(read_capped(child.stdout.take().unwrap(), self.max_output));
+        let stderr = tokio::spawn(read_capped(child.stderr.take().unwrap(), self.max_output));
+
+        let (status, timed_out) = match tokio::time::timeout(self.timeout, child.wait()).await {
+            Ok(status) => (status?.code(), false),
+            Err(_) => {
+                kill_group(group);
+                child.wait().await?;
+                (None, true)
+            }
+        };
+        // whatever the hook left running goes with it
+        kill_group(group);
+        writer.abort();
+        let drain = |reader: tokio::task::JoinHandle<Vec<u8>>| async move {
+            match tokio::time::timeout(DRAIN_TIMEOUT, reader).await {
+                Ok(Ok(output)) => output,
+                _ => Vec::new(),
+            }
+        };
+        Ok(HookRun {
+            command: command.to_path_buf(),
+            status,
+            timed_out,
+            stdout: drain(stdout).await,
+            stderr: drain(stderr).await,
+        })
+    }
+}
+
+fn kill_group(group: Option<libc::pid_t>) {
+    if let Some(group) = group {
+        // SAFETY: the group is the one the hook was started in, ESRCH once it is gone
+        unsafe {
+            libc::killpg(group, libc::SIGKILL);
+        }
+    }
+}
+
+/// Read all of `reader`, keeping its first `cap` bytes: a hook blocked on a full pipe would only
+/// end with its timeout.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, cap: usize) -> Vec<u8> {
+    let mut kept = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = cap.saturating_sub(kept.len());
+                kept.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+    }
+    kept
+}
+
+/// A temporary directory holding the objects of a push as loose objects, read-only for hooks,
+/// and an empty repository to point git at them. Removed when dropped.
+pub struct Quarantine {
+    dir: tempfile::TempDir,
+}
+
+impl Quarantine {
+    /// Write the objects, given as their id, type and data, below `temp_dir`.
+    pub fn create<'a>(
+        temp_dir: &Path,
+        objects: impl IntoIterator<Item = (&'a str, &'a str, &'a [u8])>,
+    ) -> io::Result<Self> {
+        let quarantine = Quarantine {
+            dir: tempfile::Builder::new()
+                .prefix("mega-hook-")
+                .tempdir_in(temp_dir)?,
+        };
+        for (id, object_type, data) in objects {
+            if id.len() != 40 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid object id {}", id),
+                ));
+            }
+            let dir = quarantine.objects_dir().join(&id[..2]);
+            fs::create_dir_all(&dir)?;
+            let mut loose = format!("{} {}\0", object_type, data.len()).into_bytes();
+            loose.extend_from_slice(data);
+            fs::write(dir.join(&id[2..]), compress_zlib(&loose)?)?;
+        }
+        let git_dir = quarantine.git_dir();
+        fs::create_dir_all(quarantine.objects_dir())?;
+        fs::create_dir_all(git_dir.join("objects"))?;
+        fs::create_dir_all(git_dir.join("refs"))?;
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n")?;
+        fs::create_dir_all(quarantine.work_dir())?;
+        set_read_only(&quarantine.dir.path().join("objects"), true)?;
+        set_read_only(&git_dir, true)?;
+        Ok(quarantine)
+    }
+
+    pub fn objects_dir(&self) -> PathBuf {
+        self.dir.path().join("objects")
+    }
+
+    pub fn git_dir(&self) -> PathBuf {
+        self.dir.path().join("repo.git")
+    }
+
+    /// The working directory and home of hooks, the only place they can write to.
+    pub fn work_dir(&self) -> PathBuf {
+        self.dir.path().join("work")
+    }
+}
+
+impl Drop for Quarantine {
+    fn drop(&mut self) {
+        // files of read-only directories can't be removed
+        let _ = set_read_only(&self.objects_dir(), false);
+        let _ = set_read_only(&self.git_dir(), false);
+    }
+}
+
+/// Make the directories below and including `path` read-only, or writable again.
+fn set_read_only(path: &Path, read_only: bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        let mode = if read_only { 0o444 } else { 0o644 };
+        return fs::set_permissions(path, fs::Permissions::from_mode(mode));
+    }
+    if !read_only {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    for entry in fs::read_dir(path)? {
+        set_read_only(&entry?.path(), read_only)?;
+    }
+    if read_only {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o555))?;
+    }
+    Ok(())
+}
+
+impl PackProtocol {
+    /// Run the pre-receive hooks on the push `mr_id`, returning why the push is refused if a hook
+    /// refuses it. Their output is kept in `progress` for the client.
+    pub async fn run_pre_receive_hooks(&mut self, mr_id: i64) -> Result<(), String> {
+        if self.pre_receive.is_empty() {
+            return Ok(());
+        }
+        let mut git_ids = Vec::new();
+        for object_type in OBJECT_TYPES {
+            git_ids.extend(
+                self.storage
+                    .get_mr_objects_by_type(mr_id, object_type)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(|model| model.git_id),
+            );
+        }
+        let objects = self
+            .storage
+            .get_obj_data_by_ids(git_ids)
+            .await
+            .map_err(|e| e.to_string())?;
+        let quarantine = Quarantine::create(
+            &self.pre_receive.temp_dir,
+            objects
+                .iter()
+                .map(|o| (o.git_id.as_str(), o.object_type.as_str(), o.data.as_slice())),
+        )
+        .map_err(|e| {
+            tracing::error!("can't quarantine the push to {:?}: {}", self.path, e);
+            "pre-receive hooks failed to run".to_owned()
+        })?;
+
+        let stdin: String = self
+            .command_list
+            .iter()
+            .map(|c| format!("{} {} {}\n", c.old_id, c.new_id, c.ref_name))
+            .collect();
+        let envs = [
+            ("MEGA_REPO_PATH", self.path.to_string_lossy().into_owned()),
+            ("MEGA_PROTOCOL", self.transfer_protocol.as_str().to_owned()),
+        ];
+        let hooks = self.pre_receive.clone();
+        for command in &hooks.commands {
+            let run = match hooks
+                .run(command, stdin.as_bytes(), &quarantine, &envs)
+                .await
+            {
+                Ok(run) => run,
+                Err(e) => {
+                    tracing::error!("can't run pre-receive hook {:?}: {}", command, e);
+                    return Err(format!(
+                        "pre-receive hook {} failed to run",
+                        command.display()
+                    ));
+                }
+            };
+            self.progress.extend_from_slice(&run.stdout);
+            self.progress.extend_from_slice(&run.stderr);
+            if let Some(reason) = run.rejection(hooks.timeout) {
+                tracing::info!("push to {} rejected: {}", self.path.display(), reason);
+                return Err(reason);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use super::{PreReceiveHooks, Quarantine};
+
+    fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_pre_receive_hook_sandbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = PreReceiveHooks {
+            timeout: Duration::from_secs(2),
+            max_output: 16,
+            temp_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let blob = "ce013625030ba8dba906f756967f9e9ca394464a";
+        let quarantine = Quarantine::create(dir.path(), [(blob, "blob", &b"hello\n"[..])]).unwrap();
+        let object = quarantine.objects_dir().join(&blob[..2]).join(&blob[2..]);
+        assert!(object.exists());
+
+        // stdin is read, output kept up to the cap, and the exit status refuses the push
+        let declined = script(
+            dir.path(),
+            "declined",
+            "read old new name; echo \"no pushes to $name in $MEGA_REPO_PATH\"; exit 3",
+        );
+        let run = hooks
+            .run(
+                &declined,
+                b"0000 1111 refs/heads/main\n",
+                &quarantine,
+                &[("MEGA_REPO_PATH", "/project".to_owned())],
+            )
+            .await
+            .unwrap();
+        assert_eq!(run.status, Some(3));
+        assert_eq!(run.stdout, b"no pushes to ref");
+        assert!(run
+            .rejection(hooks.timeout)
+            .unwrap()
+            .contains("declined with exit status 3"));
+
+        // objects are read-only, and the environment of the server isn't passed on
+        let mode = std::fs::metadata(&object).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o444);
+        let mode = std::fs::metadata(quarantine.objects_dir()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o555);
+        let env = script(dir.path(), "env", "test -z \"$CARGO\" && test -n \"$GIT_DIR\"");
+        let run = hooks.run(&env, b"", &quarantine, &[]).await.unwrap();
+        assert_eq!(run.rejection(hooks.timeout), None);
+
+        // a hook running too long is killed with the processes it started
+        let sleeps = script(dir.path(), "sleeps", "sleep 30 & sleep 30");
+        let run = hooks.run(&sleeps, b"", &quarantine, &[]).await.unwrap();
+        assert!(run.timed_out);
+        assert!(run.rejection(hooks.timeout).unwrap().contains("timed out"));
+
+        drop(quarantine);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+}
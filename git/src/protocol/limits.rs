@@ -0,0 +1,290 @@
+//!
+//! Limits on what one upload-pack request can ask for, protecting the server from buggy or
+//! abusive clients.
+//!
+//! Each limit is read from an environment variable, 0 turning it off:
+//!
+//! - `MEGA_FETCH_MAX_WANTS`: objects wanted by one request.
+//! - `MEGA_FETCH_MAX_HAVES`: objects a request says the client has.
+//! - `MEGA_FETCH_MAX_ROUNDS`: batches of haves in one request. Stateless clients resend the haves
+//!   of the earlier rounds with every request, so this bounds the rounds of a negotiation.
+//! - `MEGA_FETCH_MAX_OBJECTS`: objects in the pack sent back.
+//! - `MEGA_FETCH_TIMEOUT_SECS`: seconds one request has to generate and send its pack, after
+//!   which it is cancelled so a stalled client doesn't hold on to the database and the workers.
+//!
+//! Wants naming objects the server doesn't have are always refused, without telling which ones:
+//! a client probing random ids learns nothing about which objects exist.
+//!
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_WANTS: usize = 50_000;
+const DEFAULT_MAX_HAVES: usize = 20_000;
+const DEFAULT_MAX_ROUNDS: usize = 64;
+const DEFAULT_MAX_OBJECTS: usize = 0;
+const DEFAULT_TIMEOUT_SECS: usize = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchLimits {
+    pub max_wants: usize,
+    pub max_haves: usize,
+    pub max_rounds: usize,
+    pub max_objects: usize,
+    pub timeout_secs: usize,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        FetchLimits {
+            max_wants: DEFAULT_MAX_WANTS,
+            max_haves: DEFAULT_MAX_HAVES,
+            max_rounds: DEFAULT_MAX_ROUNDS,
+            max_objects: DEFAULT_MAX_OBJECTS,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+        }
+    }
+}
+
+pub(crate) fn env_limit(name: &str, default: usize) -> usize {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value.trim().parse().unwrap_or_else(|_| {
+            tracing::error!("invalid {}: {}, using {}", name, value, default);
+            default
+        }),
+        _ => default,
+    }
+}
+
+impl FetchLimits {
+    pub fn from_env() -> Self {
+        FetchLimits {
+            max_wants: env_limit("MEGA_FETCH_MAX_WANTS", DEFAULT_MAX_WANTS),
+            max_haves: env_limit("MEGA_FETCH_MAX_HAVES", DEFAULT_MAX_HAVES),
+            max_rounds: env_limit("MEGA_FETCH_MAX_ROUNDS", DEFAULT_MAX_ROUNDS),
+            max_objects: env_limit("MEGA_FETCH_MAX_OBJECTS", DEFAULT_MAX_OBJECTS),
+            timeout_secs: env_limit("MEGA_FETCH_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS),
+        }
+    }
+
+    /// When a request starting at `start` is cancelled, `None` without a timeout.
+    pub fn deadline(&self, start: Instant) -> Option<Instant> {
+        (self.timeout_secs > 0).then(|| start + Duration::from_secs(self.timeout_secs as u64))
+    }
+
+    /// Check the size of a parsed request.
+    pub fn check_request(
+        &self,
+        wants: usize,
+        haves: usize,
+        rounds: usize,
+    ) -> Result<(), FetchRejection> {
+        let exceeded = |count: usize, limit: usize| limit > 0 && count > limit;
+        if wants == 0 {
+            return Err(FetchRejection::Malformed("no object wanted".to_owned()));
+        }
+        if exceeded(wants, self.max_wants) {
+            return Err(FetchRejection::TooManyWants {
+                count: wants,
+                limit: self.max_wants,
+            });
+        }
+        if exceeded(haves, self.max_haves) {
+            return Err(FetchRejection::TooManyHaves {
+                count: haves,
+                limit: self.max_haves,
+            });
+        }
+        if exceeded(rounds, self.max_rounds) {
+            return Err(FetchRejection::TooManyRounds {
+                count: rounds,
+                limit: self.max_rounds,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the number of objects about to be packed.
+    pub fn check_objects(&self, count: usize) -> Result<(), FetchRejection> {
+        if self.max_objects > 0 && count > self.max_objects {
+            return Err(FetchRejection::TooManyObjects {
+                count,
+                limit: self.max_objects,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Why an upload-pack request was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchRejection {
+    Malformed(String),
+    TooManyWants {
+        count: usize,
+        limit: usize,
+    },
+    TooManyHaves {
+        count: usize,
+        limit: usize,
+    },
+    TooManyRounds {
+        count: usize,
+        limit: usize,
+    },
+    TooManyObjects {
+        count: usize,
+        limit: usize,
+    },
+    /// Some wanted objects don't exist, `count` out of `wants`
+    UnknownWants {
+        count: usize,
+        wants: usize,
+    },
+    /// Objects the pack needs are missing from the server, see `common::missing_objects`
+    MissingObjects {
+        count: usize,
+    },
+}
+
+impl FetchRejection {
+    /// Stable code of the rejection, for clients and logs to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            FetchRejection::Malformed(_) => "malformed-request",
+            FetchRejection::TooManyWants { .. } => "too-many-wants",
+            FetchRejection::TooManyHaves { .. } => "too-many-haves",
+            FetchRejection::TooManyRounds { .. } => "too-many-rounds",
+            FetchRejection::TooManyObjects { .. } => "too-many-objects",
+            FetchRejection::UnknownWants { .. } => "unknown-wants",
+            FetchRejection::MissingObjects { .. } => "missing-objects",
+        }
+    }
+
+    /// Whether the request looks like probing for objects rather than a client error.
+    pub fn is_suspicious(&self) -> bool {
+        match self {
+            FetchRejection::UnknownWants { count, .. } => *count > 1,
+            _ => false,
+        }
+    }
+
+    /// The `ERR` packet line telling the client, git shows it as a remote error.
+    pub fn err_line(&self) -> String {
+        format!("ERR upload-pack: {}: {}\n", self.code(), self)
+    }
+}
+
+impl fmt::Display for FetchRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchRejection::Malformed(reason) => write!(f, "{}", reason),
+            FetchRejection::TooManyWants { count, limit } => {
+                write!(f, "{} objects wanted, at most {} allowed", count, limit)
+            }
+            FetchRejection::TooManyHaves { count, limit } => {
+                write!(f, "{} haves sent, at most {} allowed", count, limit)
+            }
+            FetchRejection::TooManyRounds { count, limit } => {
+                write!(f, "{} negotiation rounds, at most {} allowed", count, limit)
+            }
+            FetchRejection::TooManyObjects { count, limit } => write!(
+                f,
+                "the pack would have {} objects, at most {} allowed, fetch less history at once",
+                count, limit
+            ),
+            // the missing ids are not named, see the module documentation
+            FetchRejection::UnknownWants { .. } => write!(f, "not our ref"),
+            FetchRejection::MissingObjects { count } => write!(
+                f,
+                "{} objects of the repository are missing from the server, its admins are told",
+                count
+            ),
+        }
+    }
+}
+
+/// Whether `id` is a full object id as sent in want and have lines.
+pub fn is_object_id(id: &str) -> bool {
+    id.len() == 40
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{is_object_id, FetchLimits, FetchRejection};
+
+    #[test]
+    fn test_check_request() {
+        let limits = FetchLimits {
+            max_wants: 2,
+            max_haves: 10,
+            max_rounds: 3,
+            max_objects: 100,
+            timeout_secs: 60,
+        };
+        assert_eq!(limits.check_request(2, 10, 3), Ok(()));
+        assert_eq!(
+            limits.check_request(3, 0, 0),
+            Err(FetchRejection::TooManyWants { count: 3, limit: 2 })
+        );
+        assert_eq!(
+            limits.check_request(1, 11, 1).unwrap_err().code(),
+            "too-many-haves"
+        );
+        assert_eq!(
+            limits.check_request(1, 1, 4).unwrap_err().code(),
+            "too-many-rounds"
+        );
+        assert_eq!(
+            limits.check_request(0, 0, 0).unwrap_err().code(),
+            "malformed-request"
+        );
+        assert!(limits.check_objects(100).is_ok());
+        assert_eq!(
+            limits.check_objects(101).unwrap_err().code(),
+            "too-many-objects"
+        );
+
+        let unlimited = FetchLimits {
+            max_wants: 0,
+            max_haves: 0,
+            max_rounds: 0,
+            max_objects: 0,
+            timeout_secs: 0,
+        };
+        assert!(unlimited.check_request(1_000_000, 1_000_000, 1_000).is_ok());
+        assert!(unlimited.check_objects(usize::MAX).is_ok());
+
+        let start = Instant::now();
+        assert_eq!(
+            limits.deadline(start),
+            Some(start + Duration::from_secs(60))
+        );
+        assert_eq!(unlimited.deadline(start), None);
+    }
+
+    #[test]
+    fn test_err_line() {
+        let rejection = FetchRejection::UnknownWants { count: 5, wants: 6 };
+        assert_eq!(
+            rejection.err_line(),
+            "ERR upload-pack: unknown-wants: not our ref\n"
+        );
+        assert!(rejection.is_suspicious());
+        assert!(!FetchRejection::UnknownWants { count: 1, wants: 1 }.is_suspicious());
+        assert!(FetchRejection::MissingObjects { count: 2 }
+            .err_line()
+            .starts_with("ERR upload-pack: missing-objects: 2 objects"));
+    }
+
+    #[test]
+    fn test_is_object_id() {
+        assert!(is_object_id("4b825dc642cb6eb9a060e54bf8d69288fbee4904"));
+        assert!(!is_object_id("4B825DC642CB6EB9A060E54BF8D69288FBEE4904"));
+        assert!(!is_object_id("4b825dc6"));
+    }
+}
@@ -11,13 +11,31 @@ use std::{
 
 use sea_orm::{ActiveValue::NotSet, Set};
 
+use common::operation::Operation;
 use common::{errors::MegaError, utils::ZERO_ID};
 use entity::{mr_info, refs};
 use storage::driver::{database::mysql_storage::MysqlStorage, database::storage::ObjectStorage};
 
+use crate::internal::budget::MemoryBudget;
+use crate::protocol::filter::{ObjectFilter, PrefetchPolicy};
+use crate::protocol::hook::PreReceiveHooks;
+use crate::protocol::limits::FetchLimits;
+use crate::protocol::negotiate::Negotiation;
 use crate::protocol::pack::SP;
+use crate::protocol::profile::PushProfile;
+use crate::protocol::scan::ScanPolicy;
+use crate::protocol::verify::{CommitVerifier, SignedBranches, TagRules};
 
+pub mod bundle;
+pub mod filter;
+pub mod hook;
+pub mod limits;
+pub mod negotiate;
 pub mod pack;
+pub mod profile;
+pub mod refspec;
+pub mod scan;
+pub mod verify;
 #[derive(Clone)]
 pub struct PackProtocol {
     pub transfer_protocol: Protocol,
@@ -27,6 +45,31 @@ pub struct PackProtocol {
     pub command_list: Vec<RefCommand>,
     // only needed in ssh protocal
     pub service_type: ServiceType,
+    // objects a partial clone asked to leave out of the pack
+    pub filter: Option<ObjectFilter>,
+    pub prefetch: PrefetchPolicy,
+    // how much one upload-pack request may ask for
+    pub limits: FetchLimits,
+    // memory the packs of the request may hold before spilling to disk
+    pub budget: MemoryBudget,
+    // where the time of a push went
+    pub push_profile: PushProfile,
+    // checks of the files in a push
+    pub scan: ScanPolicy,
+    // branches which only take commits signed by a verified key, checked by the verifier
+    pub signed_branches: SignedBranches,
+    // tags which have to be signed by a verified key, and tags which can't be moved or deleted
+    pub signed_tags: TagRules,
+    pub protected_tags: TagRules,
+    pub verifier: Option<Arc<dyn CommitVerifier>>,
+    // commands run in a sandbox to accept or refuse a push
+    pub pre_receive: PreReceiveHooks,
+    // messages for the client, sent on the progress side-band with the report
+    pub progress: Vec<u8>,
+    // the pack being generated, which admins can follow and cancel
+    pub operation: Option<Operation>,
+    // wants and common commits of the earlier rounds of a stateful fetch
+    pub negotiation: Negotiation,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
@@ -37,6 +80,20 @@ pub enum Protocol {
     Ssh,
     Git,
     P2p,
+    Grpc,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Local => "local",
+            Protocol::Http => "http",
+            Protocol::Ssh => "ssh",
+            Protocol::Git => "git",
+            Protocol::P2p => "p2p",
+            Protocol::Grpc => "grpc",
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -82,6 +139,7 @@ pub enum Capability {
     OfsDelta,
     DeepenSince,
     DeepenNot,
+    Filter,
 }
 
 impl FromStr for Capability {
@@ -99,6 +157,7 @@ impl FromStr for Capability {
             "no-done" => Ok(Capability::NoDone),
             "deepen-since" => Ok(Capability::DeepenSince),
             "deepen-not" => Ok(Capability::DeepenNot),
+            "filter" => Ok(Capability::Filter),
             _ => Err(()),
         }
     }
@@ -228,6 +287,32 @@ impl RefCommand {
     }
 }
 
+/// Move the refs of `commands` of the repository at `path` together, none of them moving when
+/// one of them can't.
+pub async fn commit_refs(
+    storage: Arc<dyn ObjectStorage>,
+    path: &Path,
+    commands: &[RefCommand],
+) -> Result<(), MegaError> {
+    let repo_path = path.to_str().unwrap();
+    let (deleted, updated): (Vec<&RefCommand>, Vec<&RefCommand>) = commands
+        .iter()
+        .partition(|command| command.command_type == CommandType::Delete);
+    storage
+        .commit_refs(
+            repo_path,
+            updated
+                .iter()
+                .map(|command| command.convert_to_model(repo_path))
+                .collect(),
+            deleted
+                .iter()
+                .map(|command| command.ref_name.clone())
+                .collect(),
+        )
+        .await
+}
+
 pub fn new_mr_info(mr_id: i64) -> mr_info::ActiveModel {
     mr_info::ActiveModel {
         id: NotSet,
@@ -248,6 +333,20 @@ impl PackProtocol {
             storage,
             command_list: Vec::new(),
             service_type: ServiceType::ReceivePack,
+            filter: None,
+            prefetch: PrefetchPolicy::from_env(),
+            limits: FetchLimits::from_env(),
+            budget: MemoryBudget::from_env(),
+            push_profile: PushProfile::default(),
+            scan: ScanPolicy::from_env(),
+            signed_branches: SignedBranches::from_env(),
+            signed_tags: TagRules::from_env("MEGA_SIGNED_TAGS"),
+            protected_tags: TagRules::from_env("MEGA_PROTECTED_TAGS"),
+            verifier: None,
+            pre_receive: PreReceiveHooks::from_env(),
+            progress: Vec::new(),
+            operation: None,
+            negotiation: Negotiation::default(),
         }
     }
 
@@ -259,6 +358,20 @@ impl PackProtocol {
             storage: Arc::new(MysqlStorage::default()),
             command_list: Vec::new(),
             service_type: ServiceType::ReceivePack,
+            filter: None,
+            prefetch: PrefetchPolicy::default(),
+            limits: FetchLimits::default(),
+            budget: MemoryBudget::unlimited(),
+            push_profile: PushProfile::default(),
+            scan: ScanPolicy::default(),
+            signed_branches: SignedBranches::default(),
+            signed_tags: TagRules::default(),
+            protected_tags: TagRules::default(),
+            verifier: None,
+            pre_receive: PreReceiveHooks::default(),
+            progress: Vec::new(),
+            operation: None,
+            negotiation: Negotiation::default(),
         }
     }
 }
@@ -0,0 +1,201 @@
+//!
+//! Fetch negotiation: finding the commits a client has in common with the repository, so the
+//! pack only carries what it is missing.
+//!
+//! The client lists the commits it has in rounds of `have` lines. With `multi_ack_detailed`,
+//! each one the repository knows is acknowledged with `ACK <id> common`, and once every wanted
+//! commit leads to one of them the server says `ACK <id> ready`, telling the client to stop.
+//! A round without `done` ends with `NAK`, and only with `no-done` and a ready server does the
+//! pack follow it; otherwise the pack comes after the client's `done`, with a final `ACK` of the
+//! last common commit, or `NAK` when there is none.
+//!
+use std::collections::{HashMap, HashSet};
+
+use crate::hash::Hash;
+
+/// The commits of a repository with their parents, for walking its history in memory.
+#[derive(Debug, Default, Clone)]
+pub struct CommitGraph {
+    parents: HashMap<Hash, Vec<Hash>>,
+}
+
+impl CommitGraph {
+    pub fn new(commits: impl IntoIterator<Item = (Hash, Vec<Hash>)>) -> Self {
+        CommitGraph {
+            parents: commits.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, id: &Hash) -> bool {
+        self.parents.contains_key(id)
+    }
+
+    /// `from` and every commit reachable from them, without walking past the commits in
+    /// `stop`, which are left out.
+    pub fn ancestors(&self, from: &[Hash], stop: &HashSet<Hash>) -> HashSet<Hash> {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<Hash> = from
+            .iter()
+            .filter(|id| self.contains(id))
+            .copied()
+            .collect();
+        while let Some(id) = stack.pop() {
+            if stop.contains(&id) || !seen.insert(id) {
+                continue;
+            }
+            if let Some(parents) = self.parents.get(&id) {
+                stack.extend(parents.iter().filter(|p| self.contains(p)));
+            }
+        }
+        seen
+    }
+
+    /// Whether each commit of `wants` is one of `common` or has one as an ancestor, when the
+    /// client has found enough common commits for the pack not to get smaller. Wants which
+    /// aren't commits of the repository, like tags, are sent anyway and don't count.
+    pub fn reaches(&self, wants: &[Hash], common: &HashSet<Hash>) -> bool {
+        wants.iter().filter(|id| self.contains(id)).all(|want| {
+            let mut seen = HashSet::new();
+            let mut stack = vec![*want];
+            while let Some(id) = stack.pop() {
+                if common.contains(&id) {
+                    return true;
+                }
+                if seen.insert(id) {
+                    stack.extend(self.parents.get(&id).into_iter().flatten());
+                }
+            }
+            false
+        })
+    }
+
+    /// The commits reachable from `wants` but not from `haves`, which a client having `haves`
+    /// is missing.
+    pub fn missing(&self, wants: &[Hash], haves: &[Hash]) -> HashSet<Hash> {
+        let has = self.ancestors(haves, &HashSet::new());
+        self.ancestors(wants, &has)
+    }
+
+    /// The commits the client has right outside of `missing`: the parents of the missing
+    /// commits it has. Their trees and blobs don't need to be sent.
+    pub fn boundary(&self, missing: &HashSet<Hash>) -> HashSet<Hash> {
+        missing
+            .iter()
+            .flat_map(|id| self.parents.get(id).into_iter().flatten())
+            .filter(|p| !missing.contains(p) && self.contains(p))
+            .copied()
+            .collect()
+    }
+}
+
+/// The answer to the `have` lines of a request.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Acks {
+    /// The haves which are commits of the repository, in the order the client sent them
+    pub common: Vec<Hash>,
+    /// Whether the common commits lead to every wanted commit
+    pub ready: bool,
+}
+
+impl Acks {
+    pub fn new(graph: &CommitGraph, wants: &[Hash], haves: &[Hash]) -> Self {
+        let mut seen = HashSet::new();
+        let common: Vec<Hash> = haves
+            .iter()
+            .filter(|id| graph.contains(id) && seen.insert(**id))
+            .copied()
+            .collect();
+        let ready = !common.is_empty() && graph.reaches(wants, &seen);
+        Acks { common, ready }
+    }
+
+    /// The lines of `multi_ack_detailed` acknowledging the common commits.
+    pub fn detailed_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .common
+            .iter()
+            .map(|id| format!("ACK {} common\n", id.to_plain_str()))
+            .collect();
+        if let (true, Some(last)) = (self.ready, self.common.last()) {
+            lines.push(format!("ACK {} ready\n", last.to_plain_str()));
+        }
+        lines
+    }
+
+    /// The line ending the negotiation, before the pack.
+    pub fn final_line(&self) -> String {
+        match self.common.last() {
+            Some(last) => format!("ACK {}\n", last.to_plain_str()),
+            None => "NAK\n".to_owned(),
+        }
+    }
+}
+
+/// The negotiation of an upload-pack session kept across the rounds of a stateful transport,
+/// like ssh, whose later requests only carry new haves and `done`. Stateless http clients send
+/// their wants and the common haves with every request.
+#[derive(Debug, Default, Clone)]
+pub struct Negotiation {
+    pub want: Vec<String>,
+    pub common: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::hash::Hash;
+    use crate::protocol::negotiate::{Acks, CommitGraph};
+
+    fn id(n: u8) -> Hash {
+        Hash([n; 20])
+    }
+
+    /// 1 - 2 - 3 - 5 on main, 2 - 4 on a branch merged by 5
+    fn graph() -> CommitGraph {
+        CommitGraph::new([
+            (id(1), vec![]),
+            (id(2), vec![id(1)]),
+            (id(3), vec![id(2)]),
+            (id(4), vec![id(2)]),
+            (id(5), vec![id(3), id(4)]),
+        ])
+    }
+
+    #[test]
+    fn test_missing_commits() {
+        let graph = graph();
+        assert_eq!(
+            graph.missing(&[id(5)], &[id(3)]),
+            HashSet::from([id(5), id(4)])
+        );
+        assert_eq!(
+            graph.boundary(&graph.missing(&[id(5)], &[id(3)])),
+            HashSet::from([id(3), id(2)])
+        );
+        // a have the repository doesn't know is skipped
+        assert_eq!(graph.missing(&[id(3)], &[id(9)]).len(), 3);
+        assert!(graph.missing(&[id(3)], &[id(5)]).is_empty());
+    }
+
+    #[test]
+    fn test_acks() {
+        let graph = graph();
+        let acks = Acks::new(&graph, &[id(5)], &[id(9), id(4), id(4)]);
+        assert_eq!(acks.common, [id(4)]);
+        assert!(acks.ready);
+        assert_eq!(
+            acks.detailed_lines(),
+            [
+                format!("ACK {} common\n", id(4).to_plain_str()),
+                format!("ACK {} ready\n", id(4).to_plain_str())
+            ]
+        );
+        assert_eq!(acks.final_line(), format!("ACK {}\n", id(4).to_plain_str()));
+
+        // no have leads to the wanted branch
+        let acks = Acks::new(&graph, &[id(4), id(3)], &[id(3)]);
+        assert!(!acks.ready);
+        assert_eq!(Acks::new(&graph, &[id(5)], &[id(9)]).final_line(), "NAK\n");
+    }
+}
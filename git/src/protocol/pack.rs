@@ -3,21 +3,35 @@
 //!
 //!
 
-use std::io::Write;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::time::Instant;
 use std::{io::Cursor, sync::Arc};
 
 use anyhow::Result;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-
+use itertools::Itertools;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::SyncIoBridge;
+
+use common::metrics::{metrics, TransportLabels};
+use common::operation::{self, Cancelled, OperationKind};
+use common::utils::HIDDEN_REF_PREFIX;
+use storage::driver::database::quarantine::QuarantineStorage;
 use storage::driver::database::storage::ObjectStorage;
 
+use crate::hash::Hash;
+use crate::protocol::limits::{is_object_id, FetchRejection};
+use crate::protocol::negotiate::{Acks, Negotiation};
+use crate::protocol::profile::{PushProfile, PushStage};
 use crate::protocol::{
-    new_mr_info, Capability, PackProtocol, Protocol, RefCommand, ServiceType, SideBind,
+    commit_refs, new_mr_info, Capability, PackProtocol, Protocol, RefCommand, ServiceType, SideBind,
 };
 use crate::protocol::{RefsType, ZERO_ID};
 use crate::structure::conversion;
 use crate::{
     errors::GitError,
+    internal::budget::{MemoryBudget, SpillBuffer},
     internal::pack::{
         decode::HashCounter,
         preload::{decode_load, PackPreload},
@@ -32,6 +46,10 @@ const NUL: char = '\0';
 
 pub const PKT_LINE_END_MARKER: &[u8; 4] = b"0000";
 
+// The largest packets of the side-band-64k and side-band capabilities, with their length and band.
+const LARGE_PACKET_MAX: usize = 65520;
+const SMALL_PACKET_MAX: usize = 1000;
+
 // The atomic, report-status, report-status-v2, delete-refs, quiet,
 // and push-cert capabilities are sent and recognized by the receive-pack (push to server) process.
 const RECEIVE_CAP_LIST: &str = "report-status report-status-v2 delete-refs quiet atomic ";
@@ -42,7 +60,7 @@ const CAP_LIST: &str = "side-band-64k ofs-delta agent=mega/0.0.1";
 
 // All other capabilities are only recognized by the upload-pack (fetch from server) process.
 const UPLOAD_CAP_LIST: &str =
-    "shallow deepen-since deepen-not deepen-relative multi_ack_detailed no-done include-tag ";
+    "shallow deepen-since deepen-not deepen-relative multi_ack_detailed no-done include-tag filter ";
 
 impl PackProtocol {
     /// # Retrieves the information about Git references (refs) for the specified service type.
@@ -69,6 +87,7 @@ impl PackProtocol {
     /// Tracing information is logged regarding the response packet line stream.
     ///
     /// Finally, the constructed packet line stream is returned.
+    #[tracing::instrument(name = "info_refs", skip_all, fields(service = ?self.service_type))]
     pub async fn git_info_refs(&mut self) -> BytesMut {
         let service_type = self.service_type;
         // The stream MUST include capability declarations behind a NUL on the first ref.
@@ -91,7 +110,10 @@ impl PackProtocol {
             .get_all_refs_by_path(self.path.to_str().unwrap())
             .await
             .unwrap();
-        for git_ref in git_refs {
+        for git_ref in git_refs
+            .into_iter()
+            .filter(|r| !r.ref_name.starts_with(HIDDEN_REF_PREFIX))
+        {
             let pkt_line = format!("{}{}{}{}", git_ref.ref_git_id, SP, git_ref.ref_name, LF);
             ref_list.push(pkt_line);
         }
@@ -100,19 +122,83 @@ impl PackProtocol {
         pkt_line_stream
     }
 
+    /// Answer an upload-pack request, as an operation admins can follow and cancel while the
+    /// pack is generated and sent. It is cancelled past the timeout of the fetch limits too,
+    /// dropping the queries it waits for.
+    ///
+    /// The operation goes on while the pack is sent, which [`PackProtocol::check_sending`] checks
+    /// between its chunks, until [`PackProtocol::pack_sent`] or the protocol is dropped.
+    #[tracing::instrument(name = "upload_pack", skip_all)]
     pub async fn git_upload_pack(
         &mut self,
         upload_request: &mut Bytes,
-    ) -> Result<(Vec<u8>, BytesMut)> {
+    ) -> Result<(Option<SpillBuffer>, BytesMut)> {
+        let operation = operation::start(OperationKind::Pack, self.path.to_string_lossy());
+        if let Some(deadline) = self.limits.deadline(Instant::now()) {
+            operation.set_deadline(deadline);
+        }
+        self.operation = Some(operation.clone());
+        let result = tokio::select! {
+            result = self.upload_pack(upload_request) => Ok(result),
+            cancelled = operation.cancelled() => Err(cancelled),
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(cancelled) => Ok(self.cancelled(cancelled)),
+        };
+        if !matches!(result, Ok((Some(_), _))) {
+            self.operation = None;
+        } else if let Some(operation) = &self.operation {
+            operation.set_stage("sending pack", 0);
+        }
+        result
+    }
+
+    /// Fail once the upload-pack whose pack is being sent is cancelled or past its deadline, to
+    /// be called between the chunks sent so that a stalled client doesn't hold on to it.
+    pub fn check_sending(&self) -> Result<(), Cancelled> {
+        match &self.operation {
+            Some(operation) => operation.check().inspect_err(|cancelled| {
+                tracing::info!("{}", cancelled);
+                self.count_cancelled(cancelled);
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// End the operation of the upload-pack once its pack is sent, for a transport keeping the
+    /// protocol for the rest of its session.
+    pub fn pack_sent(&mut self) {
+        self.operation = None;
+    }
+
+    fn count_cancelled(&self, cancelled: &Cancelled) {
+        metrics().observe_cancelled_upload_pack(
+            self.transfer_protocol.as_str(),
+            cancelled.reason.as_str(),
+        );
+    }
+
+    async fn upload_pack(
+        &mut self,
+        upload_request: &mut Bytes,
+    ) -> Result<(Option<SpillBuffer>, BytesMut)> {
         let mut want: Vec<String> = Vec::new();
         let mut have: Vec<String> = Vec::new();
-        let mut last_common_commit = String::new();
+        let mut done = false;
+        // batches of haves, each ended by a flush-pkt or by done
+        let mut rounds = 0;
+        let mut batch_has_haves = false;
 
         let mut read_first_line = false;
         loop {
             let (bytes_take, pkt_line) = read_pkt_line(upload_request);
             // read 0000 to continue and read empty str to break
             if bytes_take == 0 {
+                if batch_has_haves {
+                    rounds += 1;
+                    batch_has_haves = false;
+                }
                 if upload_request.is_empty() {
                     break;
                 } else {
@@ -120,16 +206,41 @@ impl PackProtocol {
                 }
             }
             let dst = pkt_line.to_vec();
-            let commands = &dst[0..4];
+            let commands = dst.get(0..4).unwrap_or_default();
 
             match commands {
-                b"want" => {
-                    want.push(String::from_utf8(dst[5..45].to_vec()).unwrap());
+                b"want" | b"have" => {
+                    let Some(id) = dst
+                        .get(5..45)
+                        .and_then(|id| std::str::from_utf8(id).ok())
+                        .filter(|id| is_object_id(id))
+                    else {
+                        return Ok(self.rejected(FetchRejection::Malformed(format!(
+                            "invalid {} line",
+                            String::from_utf8_lossy(commands)
+                        ))));
+                    };
+                    if commands == b"want" {
+                        want.push(id.to_owned());
+                    } else {
+                        have.push(id.to_owned());
+                        batch_has_haves = true;
+                    }
                 }
-                b"have" => {
-                    have.push(String::from_utf8(dst[5..45].to_vec()).unwrap());
+                b"done" => {
+                    done = true;
+                    break;
+                }
+                b"filt" => {
+                    let spec = String::from_utf8_lossy(dst.get(7..).unwrap_or_default())
+                        .trim()
+                        .to_owned();
+                    self.filter = spec.parse().ok();
+                    if self.filter.is_none() {
+                        tracing::warn!("unsupported filter {}, sending all objects", spec);
+                    }
+                    continue;
                 }
-                b"done" => break,
                 other => {
                     tracing::error!(
                         "unsupported command: {:?}",
@@ -139,121 +250,372 @@ impl PackProtocol {
                 }
             };
             if !read_first_line {
-                self.parse_capabilities(&String::from_utf8(dst[46..].to_vec()).unwrap());
+                self.parse_capabilities(&String::from_utf8_lossy(
+                    dst.get(46..).unwrap_or_default(),
+                ));
                 read_first_line = true;
             }
         }
+        if batch_has_haves {
+            rounds += 1;
+        }
+
+        // the later requests of a stateful session only carry haves, and done
+        let session = std::mem::take(&mut self.negotiation);
+        if want.is_empty() {
+            want = session.want;
+        }
+        if !session.common.is_empty() {
+            have = [session.common, have].concat();
+        }
+
+        // a client may want an object more than once, under several refs
+        let mut seen = HashSet::new();
+        want.retain(|id| seen.insert(id.clone()));
+        if let Err(rejection) = self.limits.check_request(want.len(), have.len(), rounds) {
+            return Ok(self.rejected(rejection));
+        }
+        let known = self
+            .storage
+            .find_known_ids(self.path.to_str().unwrap(), &want)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let unknown = want.iter().filter(|id| !known.contains(*id)).count();
+        if unknown > 0 {
+            return Ok(self.rejected(FetchRejection::UnknownWants {
+                count: unknown,
+                wants: want.len(),
+            }));
+        }
 
         tracing::info!(
-            "want commands: {:?}\n have commans: {:?}\n caps:{:?}",
+            "want commands: {:?}\n have commans: {:?}\n caps:{:?}\n filter:{:?}",
             want,
             have,
-            self.capabilities
+            self.capabilities,
+            self.filter
         );
 
-        let mut pack_data = vec![];
         let mut buf = BytesMut::new();
-
-        if have.is_empty() {
-            pack_data = self.get_full_pack_data(&self.path).await.unwrap();
+        let pack = if have.is_empty() {
+            // a partial clone fetches the blobs and trees it is missing by id
+            let pack = match self.get_object_pack_data(&want).await {
+                Ok(Some(data)) => Ok(data),
+                Ok(None) => self.get_full_pack_data(&self.path).await,
+                Err(e) => Err(e),
+            };
             add_pkt_line_string(&mut buf, String::from("NAK\n"));
+            pack
         } else {
-            if self.capabilities.contains(&Capability::MultiAckDetailed) {
-                // multi_ack_detailed mode, the server will differentiate the ACKs where it is signaling that
-                // it is ready to send data with ACK obj-id ready lines,
-                // and signals the identified common commits with ACK obj-id common lines
-                for hash in &have {
-                    if self
-                        .storage
-                        .get_commit_by_hash(hash, self.path.to_str().unwrap())
-                        .await
-                        .unwrap()
-                        .is_some()
-                    {
-                        add_pkt_line_string(&mut buf, format!("ACK {} common\n", hash));
-                        if last_common_commit.is_empty() {
-                            last_common_commit = hash.to_string();
-                        }
-                    } else {
-                        //send NAK if missing common commit
-                        add_pkt_line_string(&mut buf, String::from("NAK\n"));
-                        return Ok((pack_data, buf));
-                    }
+            let (commits, graph) = self.commit_graph().await;
+            let ids = |ids: &[String]| ids.iter().map(|id| Hash::new_from_str(id)).collect_vec();
+            let acks = Acks::new(&graph, &ids(&want), &ids(&have));
+            let detailed = self.capabilities.contains(&Capability::MultiAckDetailed);
+            if detailed {
+                for line in acks.detailed_lines() {
+                    add_pkt_line_string(&mut buf, line);
                 }
-
-                for hash in &want {
-                    if self.capabilities.contains(&Capability::NoDone) {
-                        // If multi_ack_detailed and no-done are both present, then the sender is free to immediately send a pack
-                        // following its first "ACK obj-id ready" message.
-                        add_pkt_line_string(&mut buf, format!("ACK {} ready\n", hash));
-                    }
+            }
+            let common = acks.common.iter().map(|id| id.to_plain_str()).collect_vec();
+            if !done {
+                add_pkt_line_string(&mut buf, String::from("NAK\n"));
+                // with no-done the pack follows the ready, otherwise the client goes on with
+                // more haves or done in its next request
+                if !(detailed && acks.ready && self.capabilities.contains(&Capability::NoDone)) {
+                    self.negotiation = Negotiation { want, common };
+                    return Ok((None, buf));
                 }
-
-                pack_data = self.get_incremental_pack_data(want, have).await.unwrap();
-            } else {
-                tracing::error!("capability unsupported");
             }
-            add_pkt_line_string(&mut buf, format!("ACK {} \n", last_common_commit));
+            add_pkt_line_string(&mut buf, acks.final_line());
+            self.get_missing_pack_data(&commits, &graph, want, common)
+                .await
+        };
+        let pack_data = match pack {
+            Ok(data) => data,
+            Err(GitError::FetchRejected(rejection)) => return Ok(self.rejected(rejection)),
+            Err(GitError::Cancelled(cancelled)) => return Ok(self.cancelled(cancelled)),
+            Err(e) => return Err(e.into()),
+        };
+        self.log_budget();
+        metrics()
+            .pack_bytes_served
+            .get_or_create(&TransportLabels::new(self.transfer_protocol.as_str()))
+            .inc_by(pack_data.len() as u64);
+        Ok((Some(pack_data), buf))
+    }
+
+    fn log_budget(&self) {
+        if self.budget.spilled() > 0 {
+            tracing::info!(
+                "pack of {:?} went over its memory budget of {} bytes, spilled {} bytes to disk",
+                self.path,
+                self.budget.limit(),
+                self.budget.spilled()
+            );
         }
-        Ok((pack_data, buf))
     }
 
-    pub async fn git_receive_pack(&mut self, mut body_bytes: Bytes) -> Result<Bytes> {
-        if body_bytes.len() < 1000 {
-            tracing::debug!("bytes from client: {:?}", body_bytes);
+    /// The response refusing an upload-pack request: an `ERR` line and no pack.
+    fn rejected(&self, rejection: FetchRejection) -> (Option<SpillBuffer>, BytesMut) {
+        if rejection.is_suspicious() {
+            tracing::warn!(
+                "refused a fetch of {:?} which may be probing for objects: {:?}",
+                self.path,
+                rejection
+            );
         } else {
-            tracing::debug!("{} bytes from client", body_bytes.len())
+            tracing::info!("refused a fetch of {:?}: {:?}", self.path, rejection);
+        }
+        let mut buf = BytesMut::new();
+        add_pkt_line_string(&mut buf, rejection.err_line());
+        (None, buf)
+    }
+
+    /// The response to an upload-pack request whose operation an admin cancelled, or which ran
+    /// past its deadline.
+    fn cancelled(&self, cancelled: Cancelled) -> (Option<SpillBuffer>, BytesMut) {
+        tracing::info!("{}", cancelled);
+        self.count_cancelled(&cancelled);
+        let mut buf = BytesMut::new();
+        add_pkt_line_string(&mut buf, format!("ERR upload-pack: {}\n", cancelled));
+        (None, buf)
+    }
+
+    /// Answer a receive-pack request held in memory, see [`PackProtocol::git_receive_pack_stream`].
+    pub async fn git_receive_pack(&mut self, body_bytes: Bytes) -> Result<Bytes> {
+        tracing::debug!("{} bytes from client", body_bytes.len());
+        self.git_receive_pack_stream(Cursor::new(body_bytes)).await
+    }
+
+    /// Answer a receive-pack request as it is read from `body`: its commands, then its pack,
+    /// indexed as it arrives instead of once the whole push is in memory. The objects are stored
+    /// once the checksum ending the pack matched, and the refs move together after the checks of
+    /// the push.
+    #[tracing::instrument(name = "receive_pack", skip_all)]
+    pub async fn git_receive_pack_stream<R>(&mut self, mut body: R) -> Result<Bytes>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        self.push_profile.received();
+        // the commands come first, up to the pack
+        let mut has_pack = false;
+        loop {
+            let mut length = [0u8; 4];
+            match body.read_exact(&mut length).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            if &length == b"PACK" {
+                has_pack = true;
+                break;
+            }
+            let pkt_length = std::str::from_utf8(&length)
+                .ok()
+                .and_then(|length| usize::from_str_radix(length, 16).ok())
+                .ok_or_else(|| anyhow::anyhow!("{:?} is not a pkt-line length", length))?;
+            // a flush-pkt
+            if pkt_length < 4 {
+                continue;
+            }
+            let mut pkt_line = vec![0; pkt_length - 4];
+            body.read_exact(&mut pkt_line).await?;
+            let mut pkt_line = Bytes::from(pkt_line);
+            let command = self.parse_ref_command(&mut pkt_line);
+            self.parse_capabilities(&String::from_utf8_lossy(&pkt_line));
+            tracing::debug!("init command: {:?}, caps:{:?}", command, self.capabilities);
+            self.command_list.push(command);
         }
-        while !body_bytes.starts_with(&[b'P', b'A', b'C', b'K']) && !body_bytes.is_empty() {
-            let (bytes_take, mut pkt_line) = read_pkt_line(&mut body_bytes);
-            if bytes_take != 0 {
-                let command = self.parse_ref_command(&mut pkt_line);
-                self.parse_capabilities(&String::from_utf8(pkt_line.to_vec()).unwrap());
-                tracing::debug!("init command: {:?}, caps:{:?}", command, self.capabilities);
-                self.command_list.push(command);
+        // protected tags are refused before the pack is read, a push only deleting refs has none
+        if let Err(reason) = self.check_protected_tags() {
+            self.push_profile.rejected = Some(reason.clone());
+            let mut report_status = BytesMut::new();
+            add_pkt_line_string(&mut report_status, "unpack ok\n".to_owned());
+            for mut command in self.command_list.clone() {
+                command.failed(reason.clone());
+                add_pkt_line_string(&mut report_status, command.get_status());
             }
+            return Ok(self.build_report(report_status));
         }
         // handles situation when client send b"0000"
-        if body_bytes.is_empty() {
-            return Ok(body_bytes);
+        if !has_pack {
+            return Ok(Bytes::new());
         }
         // After receiving the pack data from the sender, the receiver sends a report
         let mut report_status = BytesMut::new();
 
-        //1. unpack progress
-        let mr_id = unpack(self.storage.clone(), &mut body_bytes).await?;
+        //1. unpack progress, the objects stay in quarantine until the push is accepted
+        let quarantine = Arc::new(QuarantineStorage::new(self.storage.clone()));
+        let storage = std::mem::replace(&mut self.storage, quarantine.clone());
+        let pack = AsyncReadExt::chain(Cursor::new(*b"PACK"), body);
+        let pack = BufReader::new(SyncIoBridge::new(pack));
+        let unpacked = unpack_from(
+            self.storage.clone(),
+            pack,
+            self.budget.clone(),
+            &mut self.push_profile,
+        )
+        .await;
+        let mr_id = match unpacked {
+            Ok(mr_id) => mr_id,
+            // a pack cut short or corrupted moves no ref
+            Err(e) => {
+                self.storage = storage;
+                self.discard(&quarantine, None).await;
+                tracing::error!("refused the pack pushed to {:?}: {}", self.path, e);
+                self.push_profile.rejected = Some(e.to_string());
+                add_pkt_line_string(&mut report_status, format!("unpack {}\n", e));
+                for mut command in self.command_list.clone() {
+                    command.failed(String::from("unpacker error"));
+                    add_pkt_line_string(&mut report_status, command.get_status());
+                }
+                return Ok(self.build_report(report_status));
+            }
+        };
+        self.log_budget();
         // write "unpack ok\n to report"
         add_pkt_line_string(&mut report_status, "unpack ok\n".to_owned());
+        // files the scan policy doesn't allow, unsigned commits on signed branches, or unsigned
+        // tags, reject the whole push
+        let start = Instant::now();
+        let checked = match self.scan_push(mr_id).await {
+            Ok(()) => self.check_signed_commits(mr_id).await,
+            Err(reason) => Err(reason),
+        };
+        let checked = match checked {
+            Ok(()) => self.check_signed_tags().await,
+            Err(reason) => Err(reason),
+        };
+        let checked = match checked {
+            Ok(()) => self.run_pre_receive_hooks(mr_id).await,
+            Err(reason) => Err(reason),
+        };
+        self.push_profile
+            .record_since(PushStage::PolicyHooks, start);
+        self.storage = storage;
+        let checked = match checked {
+            Ok(()) => quarantine.release().await.map_err(|e| {
+                tracing::error!(
+                    "failed to release the objects pushed to {:?}: {}",
+                    self.path,
+                    e
+                );
+                String::from("failed to store objects")
+            }),
+            Err(reason) => Err(reason),
+        };
+        if let Err(reason) = checked {
+            self.discard(&quarantine, Some(mr_id)).await;
+            self.push_profile.rejected = Some(reason.clone());
+            for mut command in self.command_list.clone() {
+                command.failed(reason.clone());
+                add_pkt_line_string(&mut report_status, command.get_status());
+            }
+            return Ok(self.build_report(report_status));
+        }
+        // a push to a directory of a repository goes to the repository
+        let start = Instant::now();
+        if let Some(published) = self.publish_subdir().await {
+            self.receive_published(mr_id, published).await;
+            self.push_profile.record_since(PushStage::RefUpdate, start);
+            for command in &self.command_list {
+                add_pkt_line_string(&mut report_status, command.get_status());
+            }
+            return Ok(self.build_report(report_status));
+        }
         //2. parse progress
+        let start = Instant::now();
         let parse_obj_result =
             conversion::save_node_from_mr(self.storage.clone(), mr_id, &self.path)
                 .await
                 .is_ok();
+        self.push_profile.record_since(PushStage::DbWrite, start);
 
         //3. update each refs and build report
-        for mut command in self.command_list.clone() {
-            if command.refs_type == RefsType::Tag {
-                // just update if refs type is tag
-                command.update_refs(self.storage.clone(), &self.path).await;
-            } else {
-                // TODO: Updates can be unsuccessful for a number of reasons.
-                // a.The reference can have changed since the reference discovery phase was originally sent, meaning someone pushed in the meantime.
-                // b.The reference being pushed could be a non-fast-forward reference and the update hooks or configuration could be set to not allow that, etc.
-                // c.Also, some references can be updated while others can be rejected.
-                if parse_obj_result {
-                    command.update_refs(self.storage.clone(), &self.path).await;
+        let start = Instant::now();
+        let mut commands = self.command_list.clone();
+        // TODO: Updates can be unsuccessful for a number of reasons.
+        // a.The reference can have changed since the reference discovery phase was originally sent, meaning someone pushed in the meantime.
+        // b.The reference being pushed could be a non-fast-forward reference and the update hooks or configuration could be set to not allow that, etc.
+        // c.Also, some references can be updated while others can be rejected.
+        for command in commands.iter_mut() {
+            // tags are updated anyway
+            if command.refs_type != RefsType::Tag && !parse_obj_result {
+                command.failed(String::from("parse commit tree from obj failed"));
+                self.push_profile.rejected = Some(command.error_msg.clone());
+            }
+        }
+        let moved = commands
+            .iter()
+            .filter(|command| command.status == RefCommand::OK_STATUS)
+            .cloned()
+            .collect_vec();
+        match commit_refs(self.storage.clone(), &self.path, &moved).await {
+            Ok(()) => {
+                if moved
+                    .iter()
+                    .any(|command| command.refs_type != RefsType::Tag)
+                {
                     self.handle_directory().await.unwrap()
-                } else {
-                    command.failed(String::from("parse commit tree from obj failed"));
                 }
             }
+            Err(e) => {
+                tracing::error!("failed to update the refs of {:?}: {}", self.path, e);
+                for command in commands.iter_mut() {
+                    if command.status == RefCommand::OK_STATUS {
+                        command.failed(String::from("failed to update ref"));
+                    }
+                }
+                self.push_profile.rejected = Some(e.to_string());
+            }
+        }
+        for command in &commands {
             add_pkt_line_string(&mut report_status, command.get_status());
         }
+        self.push_profile.record_since(PushStage::RefUpdate, start);
+        Ok(self.build_report(report_status))
+    }
+
+    /// Drop what a refused push left in quarantine, its refs are already refused so a failure
+    /// is only logged.
+    async fn discard(&self, quarantine: &QuarantineStorage, mr_id: Option<i64>) {
+        if let Err(e) = quarantine.discard(mr_id).await {
+            tracing::error!(
+                "failed to discard the quarantine {} of {:?}: {}",
+                quarantine.id(),
+                self.path,
+                e
+            );
+        }
+    }
+
+    fn build_report(&self, mut report_status: BytesMut) -> Bytes {
         report_status.put(&PKT_LINE_END_MARKER[..]);
         let length = report_status.len();
-        let mut buf = self.build_side_band_format(report_status, length);
+        let mut buf = self.build_progress();
+        buf.put(self.build_side_band_format(report_status, length));
         buf.put(&PKT_LINE_END_MARKER[..]);
-        Ok(buf.into())
+        buf.into()
+    }
+
+    /// # Builds the packets of the progress side-band carrying `progress`, the output of hooks.
+    ///
+    /// Progress can only be sent with the SideBand/64k capability, without it nothing is built.
+    fn build_progress(&self) -> BytesMut {
+        let mut buf = BytesMut::new();
+        let max_data = if self.capabilities.contains(&Capability::SideBand64k) {
+            LARGE_PACKET_MAX - 5
+        } else if self.capabilities.contains(&Capability::SideBand) {
+            SMALL_PACKET_MAX - 5
+        } else {
+            return buf;
+        };
+        for chunk in self.progress.chunks(max_data) {
+            buf.put(Bytes::from(format!("{:04x}", chunk.len() + 5)));
+            buf.put_u8(SideBind::ProgressInfo.value());
+            buf.put(chunk);
+        }
+        buf
     }
 
     /// # Builds the packet data in the sideband format if the SideBand/64k capability is enabled.
@@ -322,23 +684,50 @@ impl PackProtocol {
     }
 }
 
+#[tracing::instrument(skip_all, fields(bytes = pack_file.len()))]
 pub async fn unpack(
     storage: Arc<dyn ObjectStorage>,
     pack_file: &mut Bytes,
+    budget: MemoryBudget,
+    profile: &mut PushProfile,
 ) -> Result<i64, GitError> {
-    let count_hash: bool = true;
-    //ONLY FOR TEST .NEED TO DELETE
-    {
-        let path = "lines.pack";
-        let mut output = std::fs::File::create(path).unwrap();
-        output.write_all(pack_file).unwrap();
-    }
+    unpack_from(storage, Cursor::new(pack_file.clone()), budget, profile).await
+}
 
-    let curosr_pack = Cursor::new(pack_file);
-    let reader = HashCounter::new(curosr_pack, count_hash);
-    let p = PackPreload::new(reader);
-    let mr_id = decode_load(p, storage.clone()).await?;
+/// Store the objects of the pack read from `pack`, indexed as it is read, on a blocking thread
+/// as the reading may wait for the client. Nothing is stored unless the checksum ending the
+/// pack matches.
+pub async fn unpack_from<R: BufRead + Send + 'static>(
+    storage: Arc<dyn ObjectStorage>,
+    pack: R,
+    budget: MemoryBudget,
+    profile: &mut PushProfile,
+) -> Result<i64, GitError> {
+    let start = Instant::now();
+    let (p, pack_bytes) = tokio::task::spawn_blocking(move || {
+        let mut reader = HashCounter::new(pack, true);
+        let p = PackPreload::with_budget(&mut reader, budget);
+        reader.verify_checksum()?;
+        Ok::<_, GitError>((p, reader.bytes_read()))
+    })
+    .await
+    .map_err(|e| GitError::InvalidPackFile(format!("failed to read the pack: {}", e)))??;
+    profile.record_since(PushStage::Index, start);
+    profile.pack_bytes = pack_bytes;
+    profile.objects = p.len();
+
+    let start = Instant::now();
+    let (mr_id, db_time) = decode_load(p, storage.clone()).await?;
+    // the objects are saved as they are resolved
+    profile.record(
+        PushStage::DeltaResolve,
+        start.elapsed().saturating_sub(db_time),
+    );
+    profile.record(PushStage::DbWrite, db_time);
+
+    let start = Instant::now();
     storage.save_mr_info(new_mr_info(mr_id)).await.unwrap();
+    profile.record_since(PushStage::DbWrite, start);
     Ok(mr_id)
 }
 
@@ -354,7 +743,7 @@ fn read_until_white_space(bytes: &mut Bytes) -> String {
     String::from_utf8(buf).unwrap()
 }
 
-fn add_pkt_line_string(pkt_line_stream: &mut BytesMut, buf_str: String) {
+pub fn add_pkt_line_string(pkt_line_stream: &mut BytesMut, buf_str: String) {
     let buf_str_length = buf_str.len() + 4;
     pkt_line_stream.put(Bytes::from(format!("{buf_str_length:04x}")));
     pkt_line_stream.put(buf_str.as_bytes());
@@ -410,9 +799,20 @@ pub fn read_pkt_line(bytes: &mut Bytes) -> (usize, Bytes) {
 
 #[cfg(test)]
 pub mod test {
-    use bytes::{Bytes, BytesMut};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use common::operation::{self, CancelReason, OperationKind};
 
-    use crate::protocol::pack::{add_pkt_line_string, read_pkt_line, read_until_white_space};
+    use crate::hash::Hash;
+    use crate::internal::object::{blob::Blob, ObjectT};
+    use crate::internal::pack::encode::pack_encode;
+    use crate::protocol::pack::{
+        add_pkt_line_string, read_pkt_line, read_until_white_space, PKT_LINE_END_MARKER,
+    };
+    use crate::protocol::ZERO_ID;
     use crate::protocol::{Capability, CommandType, PackProtocol, RefCommand, RefsType};
 
     #[test]
@@ -490,4 +890,72 @@ pub mod test {
             vec![Capability::ReportStatusv2, Capability::SideBand64k]
         );
     }
+
+    #[test]
+    pub fn test_build_report_with_progress() {
+        let mut mock = PackProtocol::mock();
+        mock.progress = b"hook says no\n".to_vec();
+        let mut status = BytesMut::new();
+        add_pkt_line_string(&mut status, "unpack ok\n".to_owned());
+        // without a side-band the output of hooks can't be sent
+        assert_eq!(&mock.build_report(status.clone())[..], b"000eunpack ok\n00000000");
+
+        mock.capabilities = vec![Capability::SideBand64k];
+        assert_eq!(
+            &mock.build_report(status)[..],
+            b"0012\x02hook says no\n0017\x01000eunpack ok\n00000000"
+        );
+    }
+
+    #[test]
+    pub fn test_check_sending() {
+        let mut mock = PackProtocol::mock();
+        assert!(mock.check_sending().is_ok());
+
+        let operation = operation::start(OperationKind::Pack, "/project");
+        mock.operation = Some(operation.clone());
+        assert!(mock.check_sending().is_ok());
+        operation.set_deadline(Instant::now());
+        assert_eq!(
+            mock.check_sending().unwrap_err().reason,
+            CancelReason::TimedOut
+        );
+
+        mock.pack_sent();
+        assert!(mock.check_sending().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_receive_corrupted_pack() {
+        let mut mock = PackProtocol::mock();
+        let mut body = BytesMut::new();
+        add_pkt_line_string(
+            &mut body,
+            format!(
+                "{} {} refs/heads/main\0report-status\n",
+                ZERO_ID,
+                "1".repeat(40)
+            ),
+        );
+        body.put(&PKT_LINE_END_MARKER[..]);
+        let id = Hash([0u8; 20]);
+        let blob: Arc<dyn ObjectT> = Arc::new(Blob {
+            id,
+            data: b"hello".to_vec(),
+        });
+        let mut pack = pack_encode(vec![blob]).unwrap();
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        body.put(&pack[..]);
+
+        let report = mock.git_receive_pack(body.freeze()).await.unwrap();
+        let report = String::from_utf8_lossy(&report);
+        assert!(report.contains("is not a valid pack file"), "{}", report);
+        assert!(
+            report.contains("ng refs/heads/main unpacker error"),
+            "{}",
+            report
+        );
+        assert_eq!(mock.command_list.len(), 1);
+    }
 }
@@ -0,0 +1,152 @@
+//!
+//! Timings of the stages of a push, telling where the time of a slow push went.
+//!
+//! - `receive`: reading the pack from the client.
+//! - `index`: reading the objects out of the pack.
+//! - `delta-resolve`: resolving the deltas against their bases and hashing the objects.
+//! - `policy-hooks`: the file scan and the commit signature checks.
+//! - `db-write`: saving the objects, the merge request and the trees of the push.
+//! - `ref-update`: moving the refs and publishing the directories of the repository.
+//!
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushStage {
+    Receive,
+    Index,
+    DeltaResolve,
+    PolicyHooks,
+    DbWrite,
+    RefUpdate,
+}
+
+impl PushStage {
+    /// The stages in the order a push goes through them.
+    pub const ALL: [PushStage; 6] = [
+        PushStage::Receive,
+        PushStage::Index,
+        PushStage::DeltaResolve,
+        PushStage::PolicyHooks,
+        PushStage::DbWrite,
+        PushStage::RefUpdate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushStage::Receive => "receive",
+            PushStage::Index => "index",
+            PushStage::DeltaResolve => "delta-resolve",
+            PushStage::PolicyHooks => "policy-hooks",
+            PushStage::DbWrite => "db-write",
+            PushStage::RefUpdate => "ref-update",
+        }
+    }
+}
+
+impl fmt::Display for PushStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PushProfile {
+    stages: Vec<(PushStage, Duration)>,
+    receive_start: Option<Instant>,
+    /// Size of the pack, without the commands before it
+    pub pack_bytes: usize,
+    pub objects: usize,
+    /// Why the push was refused, `None` when its refs were updated
+    pub rejected: Option<String>,
+}
+
+impl PushProfile {
+    /// Add `elapsed` to the time of `stage`.
+    pub fn record(&mut self, stage: PushStage, elapsed: Duration) {
+        match self.stages.iter_mut().find(|(s, _)| *s == stage) {
+            Some((_, total)) => *total += elapsed,
+            None => self.stages.push((stage, elapsed)),
+        }
+    }
+
+    pub fn record_since(&mut self, stage: PushStage, start: Instant) {
+        self.record(stage, start.elapsed());
+    }
+
+    /// Note that bytes of the push arrived. The receive stage lasts from the first ones until
+    /// [`PushProfile::received`].
+    pub fn receiving(&mut self) {
+        self.receive_start.get_or_insert_with(Instant::now);
+    }
+
+    pub fn received(&mut self) {
+        if let Some(start) = self.receive_start.take() {
+            self.record_since(PushStage::Receive, start);
+        }
+    }
+
+    /// The stages which ran and their times, in the order of [`PushStage::ALL`].
+    pub fn stages(&self) -> Vec<(PushStage, Duration)> {
+        PushStage::ALL
+            .iter()
+            .filter_map(|stage| self.stages.iter().find(|(s, _)| s == stage).copied())
+            .collect()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, elapsed)| *elapsed).sum()
+    }
+
+    pub fn slowest(&self) -> Option<PushStage> {
+        self.stages
+            .iter()
+            .max_by_key(|(_, elapsed)| *elapsed)
+            .map(|(stage, _)| *stage)
+    }
+}
+
+impl fmt::Display for PushProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ms, {} objects in {} bytes:",
+            self.total().as_millis(),
+            self.objects,
+            self.pack_bytes
+        )?;
+        for (stage, elapsed) in self.stages() {
+            write!(f, " {} {} ms", stage, elapsed.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{PushProfile, PushStage};
+
+    #[test]
+    fn test_push_profile() {
+        let mut profile = PushProfile::default();
+        profile.record(PushStage::DbWrite, Duration::from_millis(30));
+        profile.record(PushStage::Index, Duration::from_millis(20));
+        profile.record(PushStage::DbWrite, Duration::from_millis(15));
+
+        assert_eq!(
+            profile.stages(),
+            [
+                (PushStage::Index, Duration::from_millis(20)),
+                (PushStage::DbWrite, Duration::from_millis(45)),
+            ]
+        );
+        assert_eq!(profile.total(), Duration::from_millis(65));
+        assert_eq!(profile.slowest(), Some(PushStage::DbWrite));
+        assert_eq!(
+            profile.to_string(),
+            "65 ms, 0 objects in 0 bytes: index 20 ms db-write 45 ms"
+        );
+    }
+}
@@ -0,0 +1,469 @@
+//!
+//! Refspecs, mapping the refs of one repository to the refs of another.
+//!
+//! A refspec is `[+]<src>:<dst>`: a ref matching `<src>` on the side the refs come from is
+//! stored as the ref `<dst>` names on the other side. `<src>` and `<dst>` hold one `*` each or
+//! none, the `*` standing for any part of a name, slashes included, so
+//! `refs/heads/*:refs/remotes/origin/*` maps `refs/heads/feature/x` to
+//! `refs/remotes/origin/feature/x`. A leading `+` lets the ref move even when that isn't a
+//! fast-forward. `^<src>` is a negative refspec: the refs it matches are left out, whatever the
+//! other refspecs say.
+//!
+//! A fetch refspec may leave `<dst>` out, the ref then being fetched without being stored. A push
+//! refspec may leave `<src>` out, `:refs/heads/x` deleting the branch, and `:<dst>` out when the
+//! ref keeps its name.
+//!
+//! The client fetches its remotes, the server syncs its mirrors and publishes the directories of
+//! a repository with them.
+//!
+use std::collections::HashMap;
+use std::fmt;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RefSpecError {
+    #[error("invalid refspec {spec}: {reason}")]
+    Invalid { spec: String, reason: &'static str },
+
+    #[error("both {first} and {second} map to {dst}")]
+    Conflict {
+        dst: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// Which way the refs of a refspec go, which decides what it may leave out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Fetch,
+    Push,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefSpec {
+    /// Move the ref even when it isn't a fast-forward
+    pub force: bool,
+    /// Leave out the refs `src` matches
+    pub negative: bool,
+    /// Empty for a push deleting `dst`
+    pub src: String,
+    /// `None` for a fetch storing nothing, and for negative refspecs
+    pub dst: Option<String>,
+}
+
+/// A ref a set of refspecs maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefMapping {
+    pub src: String,
+    pub dst: String,
+    pub force: bool,
+}
+
+impl RefSpec {
+    pub fn parse(spec: &str, direction: Direction) -> Result<RefSpec, RefSpecError> {
+        let invalid = |reason| RefSpecError::Invalid {
+            spec: spec.to_owned(),
+            reason,
+        };
+        if let Some(src) = spec.strip_prefix('^') {
+            if src.contains(':') {
+                return Err(invalid("a negative refspec has no destination"));
+            }
+            if src.starts_with('+') {
+                return Err(invalid("a negative refspec can't be forced"));
+            }
+            if src.is_empty() {
+                return Err(invalid("a negative refspec needs a source"));
+            }
+            check_pattern(src).map_err(invalid)?;
+            return Ok(RefSpec {
+                force: false,
+                negative: true,
+                src: src.to_owned(),
+                dst: None,
+            });
+        }
+
+        let (force, rest) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (src, dst) = match rest.split_once(':') {
+            Some((src, dst)) => (src, (!dst.is_empty()).then_some(dst)),
+            None => (rest, None),
+        };
+        let dst = match (direction, dst) {
+            (Direction::Push, None) => Some(src),
+            (_, dst) => dst,
+        };
+        if src.is_empty() {
+            match (direction, dst) {
+                (Direction::Push, Some(dst)) if !dst.contains('*') => {}
+                (Direction::Push, _) => return Err(invalid("a deletion needs a ref to delete")),
+                (Direction::Fetch, _) => return Err(invalid("a fetch needs a source")),
+            }
+        } else {
+            check_pattern(src).map_err(invalid)?;
+        }
+        if let Some(dst) = dst {
+            check_pattern(dst).map_err(invalid)?;
+            if !src.is_empty() && src.contains('*') != dst.contains('*') {
+                return Err(invalid("either both sides have a `*` or neither does"));
+            }
+        }
+        Ok(RefSpec {
+            force,
+            negative: false,
+            src: src.to_owned(),
+            dst: dst.map(str::to_owned),
+        })
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    /// Whether this is a push deleting `dst`.
+    pub fn is_delete(&self) -> bool {
+        self.src.is_empty() && !self.negative
+    }
+
+    pub fn matches_src(&self, name: &str) -> bool {
+        !self.src.is_empty() && matched(&self.src, name).is_some()
+    }
+
+    pub fn matches_dst(&self, name: &str) -> bool {
+        self.dst
+            .as_deref()
+            .is_some_and(|dst| matched(dst, name).is_some())
+    }
+
+    /// The ref `name` is stored as, `None` when it doesn't match or nothing is stored.
+    pub fn map(&self, name: &str) -> Option<String> {
+        if self.negative || self.src.is_empty() {
+            return None;
+        }
+        let dst = self.dst.as_deref()?;
+        let part = matched(&self.src, name)?;
+        Some(dst.replacen('*', part, 1))
+    }
+
+    /// The ref stored as `name` comes from, the other way round from [`RefSpec::map`].
+    pub fn reverse(&self, name: &str) -> Option<String> {
+        if self.negative || self.src.is_empty() {
+            return None;
+        }
+        let part = matched(self.dst.as_deref()?, name)?;
+        Some(self.src.replacen('*', part, 1))
+    }
+}
+
+impl fmt::Display for RefSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            return write!(f, "^{}", self.src);
+        }
+        if self.force {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", self.src)?;
+        match &self.dst {
+            Some(dst) => write!(f, ":{}", dst),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The refspecs of a remote, a mirror or a push, the negative ones applying to all the others.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefSpecs {
+    specs: Vec<RefSpec>,
+}
+
+impl RefSpecs {
+    pub fn parse<I, S>(specs: I, direction: Direction) -> Result<RefSpecs, RefSpecError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let specs = specs
+            .into_iter()
+            .map(|spec| RefSpec::parse(spec.as_ref().trim(), direction))
+            .collect::<Result<_, _>>()?;
+        Ok(RefSpecs { specs })
+    }
+
+    pub fn fetch<I, S>(specs: I) -> Result<RefSpecs, RefSpecError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RefSpecs::parse(specs, Direction::Fetch)
+    }
+
+    pub fn push<I, S>(specs: I) -> Result<RefSpecs, RefSpecError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RefSpecs::parse(specs, Direction::Push)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RefSpec> {
+        self.specs.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    /// Whether a negative refspec leaves the source ref `name` out.
+    pub fn excludes(&self, name: &str) -> bool {
+        self.specs
+            .iter()
+            .any(|spec| spec.negative && spec.matches_src(name))
+    }
+
+    /// Where the source ref `name` is stored, once per refspec mapping it, forced when one of
+    /// the refspecs storing it there is.
+    pub fn map(&self, name: &str) -> Vec<RefMapping> {
+        if self.excludes(name) {
+            return vec![];
+        }
+        let mut mappings: Vec<RefMapping> = vec![];
+        for spec in &self.specs {
+            let Some(dst) = spec.map(name) else { continue };
+            match mappings.iter_mut().find(|m| m.dst == dst) {
+                Some(mapping) => mapping.force |= spec.force,
+                None => mappings.push(RefMapping {
+                    src: name.to_owned(),
+                    dst,
+                    force: spec.force,
+                }),
+            }
+        }
+        mappings
+    }
+
+    /// Map every ref of `names`, failing when two of them would be stored as the same ref.
+    pub fn map_all<'a, I>(&self, names: I) -> Result<Vec<RefMapping>, RefSpecError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut sources: HashMap<String, String> = HashMap::new();
+        let mut mappings = vec![];
+        for name in names {
+            for mapping in self.map(name) {
+                if let Some(first) = sources.get(&mapping.dst) {
+                    if *first != mapping.src {
+                        return Err(RefSpecError::Conflict {
+                            dst: mapping.dst,
+                            first: first.clone(),
+                            second: mapping.src,
+                        });
+                    }
+                    continue;
+                }
+                sources.insert(mapping.dst.clone(), mapping.src.clone());
+                mappings.push(mapping);
+            }
+        }
+        Ok(mappings)
+    }
+
+    /// The source ref the ref `name` is stored from, when the refspecs store anything there.
+    /// The refs it gives are those a prune may delete.
+    pub fn reverse(&self, name: &str) -> Option<String> {
+        self.specs
+            .iter()
+            .filter_map(|spec| spec.reverse(name))
+            .find(|src| !self.excludes(src))
+    }
+
+    /// The refs the pushes of deleting refspecs delete.
+    pub fn deletions(&self) -> impl Iterator<Item = &str> {
+        self.specs
+            .iter()
+            .filter(|spec| spec.is_delete())
+            .filter_map(|spec| spec.dst.as_deref())
+    }
+}
+
+impl fmt::Display for RefSpecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, spec) in self.specs.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", spec)?;
+        }
+        Ok(())
+    }
+}
+
+/// The part of `name` the `*` of `pattern` stands for, empty when `pattern` has none and is
+/// `name`.
+fn matched<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            let rest = name.strip_prefix(prefix)?;
+            let part = rest.strip_suffix(suffix)?;
+            (!part.is_empty()).then_some(part)
+        }
+        None => (pattern == name).then_some(""),
+    }
+}
+
+/// Check one side of a refspec the way git checks ref names, a single `*` being allowed.
+fn check_pattern(pattern: &str) -> Result<(), &'static str> {
+    if pattern.matches('*').count() > 1 {
+        return Err("a side has at most one `*`");
+    }
+    if pattern.starts_with('/') || pattern.ends_with('/') || pattern.contains("//") {
+        return Err("empty component in the ref name");
+    }
+    if pattern.ends_with('.') || pattern.contains("..") || pattern.contains("@{") {
+        return Err("ref names don't have `..`, `@{` or a trailing `.`");
+    }
+    if pattern
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?[\\".contains(c))
+    {
+        return Err("ref names don't have spaces, control characters or any of `~^:?[\\`");
+    }
+    for component in pattern.split('/') {
+        if component.starts_with('.') || component.ends_with(".lock") {
+            return Err("a ref name component starts with `.` or ends with `.lock`");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, RefMapping, RefSpec, RefSpecError, RefSpecs};
+
+    #[test]
+    fn test_parse() {
+        let spec = RefSpec::parse("+refs/heads/*:refs/remotes/origin/*", Direction::Fetch).unwrap();
+        assert!(spec.force && spec.is_pattern() && !spec.negative);
+        assert_eq!(spec.dst.as_deref(), Some("refs/remotes/origin/*"));
+        assert_eq!(spec.to_string(), "+refs/heads/*:refs/remotes/origin/*");
+
+        let fetched = RefSpec::parse("refs/heads/main", Direction::Fetch).unwrap();
+        assert_eq!(fetched.dst, None);
+        assert_eq!(fetched.map("refs/heads/main"), None);
+        let pushed = RefSpec::parse("refs/heads/main", Direction::Push).unwrap();
+        assert_eq!(pushed.dst.as_deref(), Some("refs/heads/main"));
+        let delete = RefSpec::parse(":refs/heads/old", Direction::Push).unwrap();
+        assert!(delete.is_delete());
+        assert_eq!(delete.to_string(), ":refs/heads/old");
+
+        let negative = RefSpec::parse("^refs/heads/tmp/*", Direction::Fetch).unwrap();
+        assert!(negative.negative);
+        assert_eq!(negative.to_string(), "^refs/heads/tmp/*");
+
+        for spec in [
+            "refs/heads/*:refs/remotes/main",
+            "refs/heads/main:refs/remotes/*",
+            "refs/*/*:refs/x/*",
+            "^+refs/heads/x",
+            "^refs/heads/x:refs/y",
+            ":refs/heads/x",
+            "refs/heads/a..b:refs/heads/c",
+            "refs/heads/a b",
+            "refs/heads/x.lock",
+        ] {
+            assert!(
+                RefSpec::parse(spec, Direction::Fetch).is_err(),
+                "{} should be invalid",
+                spec
+            );
+        }
+        assert!(RefSpec::parse(":refs/heads/*", Direction::Push).is_err());
+    }
+
+    #[test]
+    fn test_map() {
+        let spec = RefSpec::parse("refs/heads/*:refs/remotes/origin/*", Direction::Fetch).unwrap();
+        assert_eq!(
+            spec.map("refs/heads/feature/x").as_deref(),
+            Some("refs/remotes/origin/feature/x")
+        );
+        assert_eq!(spec.map("refs/tags/v1"), None);
+        assert_eq!(spec.map("refs/heads/"), None);
+        assert_eq!(
+            spec.reverse("refs/remotes/origin/main").as_deref(),
+            Some("refs/heads/main")
+        );
+        assert_eq!(spec.reverse("refs/remotes/sandbox/main"), None);
+
+        let infix = RefSpec::parse("refs/heads/release-*-rc:refs/rc/*", Direction::Fetch).unwrap();
+        assert_eq!(
+            infix.map("refs/heads/release-1.2-rc").as_deref(),
+            Some("refs/rc/1.2")
+        );
+        assert_eq!(infix.map("refs/heads/release-1.2"), None);
+
+        let exact = RefSpec::parse("refs/heads/main:refs/sandbox/main", Direction::Fetch).unwrap();
+        assert_eq!(
+            exact.map("refs/heads/main").as_deref(),
+            Some("refs/sandbox/main")
+        );
+        assert_eq!(exact.map("refs/heads/mainline"), None);
+    }
+
+    #[test]
+    fn test_refspecs() {
+        let specs = RefSpecs::fetch([
+            "+refs/heads/*:refs/remotes/origin/*",
+            "refs/heads/main:refs/remotes/origin/main",
+            "refs/tags/*:refs/tags/*",
+            "^refs/heads/tmp/*",
+        ])
+        .unwrap();
+        assert_eq!(
+            specs.map("refs/heads/main"),
+            vec![RefMapping {
+                src: "refs/heads/main".to_owned(),
+                dst: "refs/remotes/origin/main".to_owned(),
+                force: true,
+            }]
+        );
+        assert!(specs.map("refs/heads/tmp/x").is_empty());
+        assert!(specs.excludes("refs/heads/tmp/x"));
+        assert_eq!(
+            specs.reverse("refs/remotes/origin/dev").as_deref(),
+            Some("refs/heads/dev")
+        );
+        assert_eq!(specs.reverse("refs/remotes/origin/tmp/x"), None);
+        assert_eq!(specs.reverse("refs/heads/dev"), None);
+
+        let mapped = specs
+            .map_all(["refs/heads/main", "refs/heads/tmp/x", "refs/tags/v1"])
+            .unwrap();
+        let dsts: Vec<&str> = mapped.iter().map(|m| m.dst.as_str()).collect();
+        assert_eq!(dsts, ["refs/remotes/origin/main", "refs/tags/v1"]);
+        assert!(!mapped[1].force);
+
+        let conflicting =
+            RefSpecs::fetch(["refs/heads/*:refs/x/*", "refs/tags/*:refs/x/*"]).unwrap();
+        assert_eq!(
+            conflicting.map_all(["refs/heads/v1", "refs/tags/v1"]),
+            Err(RefSpecError::Conflict {
+                dst: "refs/x/v1".to_owned(),
+                first: "refs/heads/v1".to_owned(),
+                second: "refs/tags/v1".to_owned(),
+            })
+        );
+
+        let push = RefSpecs::push(["refs/heads/main", ":refs/heads/old"]).unwrap();
+        assert_eq!(push.deletions().collect::<Vec<_>>(), ["refs/heads/old"]);
+        assert_eq!(
+            push.to_string(),
+            "refs/heads/main:refs/heads/main :refs/heads/old"
+        );
+    }
+}
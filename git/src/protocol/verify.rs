@@ -0,0 +1,339 @@
+//!
+//! Signed commits on protected branches: receive-pack rejects a push moving one of the branches
+//! in `MEGA_SIGNED_BRANCHES` to commits not signed by a verified key.
+//!
+//! Tags are checked the same way: those in `MEGA_SIGNED_TAGS` have to be annotated tags signed
+//! by a verified key, and those in `MEGA_PROTECTED_TAGS` can't be moved or deleted once pushed.
+//! Both take comma separated patterns, which hold for every repository, or only for those at and
+//! below a path when written `<path>:<pattern>`, e.g. `/release:v*`.
+//!
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use common::utils::ZERO_ID;
+
+use crate::hash::Hash;
+use crate::internal::object::commit::Commit;
+use crate::internal::object::ObjectT;
+use crate::protocol::{CommandType, PackProtocol, RefsType};
+
+/// Checks the signatures of pushed commits and tags, the keys they are verified against are up
+/// to it.
+#[async_trait]
+pub trait CommitVerifier: Send + Sync {
+    /// Why the commit `id` with the object data `data` is not signed by a verified key, `None`
+    /// when it is.
+    async fn unverified_reason(&self, id: &str, data: &[u8]) -> Option<String>;
+
+    /// Why the annotated tag `id` with the object data `data` is not signed by a verified key,
+    /// `None` when it is.
+    async fn unverified_tag_reason(&self, id: &str, data: &[u8]) -> Option<String>;
+}
+
+/// Whether `name` matches `pattern`, a pattern ending in `*` matching every name it is a prefix
+/// of.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Branches which only take signed commits, a name ending in `*` matching every branch it is a
+/// prefix of, e.g. `release/*`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignedBranches(pub Vec<String>);
+
+impl SignedBranches {
+    /// The comma separated branches of `MEGA_SIGNED_BRANCHES`, none when it isn't set.
+    pub fn from_env() -> Self {
+        let branches = std::env::var("MEGA_SIGNED_BRANCHES").unwrap_or_default();
+        SignedBranches::parse(&branches)
+    }
+
+    pub fn parse(branches: &str) -> Self {
+        SignedBranches(
+            branches
+                .split(',')
+                .map(|b| b.trim().to_owned())
+                .filter(|b| !b.is_empty())
+                .collect(),
+        )
+    }
+
+    /// Whether the ref `ref_name`, e.g. `refs/heads/main`, is a signed branch.
+    pub fn matches(&self, ref_name: &str) -> bool {
+        let Some(branch) = ref_name.strip_prefix("refs/heads/") else {
+            return false;
+        };
+        self.0
+            .iter()
+            .any(|pattern| matches_pattern(pattern, branch))
+    }
+}
+
+/// A tag pattern, for the repositories at and below `path` or for all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagRule {
+    pub path: Option<String>,
+    pub pattern: String,
+}
+
+/// Tags a check applies to, see the module docs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagRules(pub Vec<TagRule>);
+
+impl TagRules {
+    /// The rules in the environment variable `var`, none when it isn't set.
+    pub fn from_env(var: &str) -> Self {
+        TagRules::parse(&std::env::var(var).unwrap_or_default())
+    }
+
+    /// Parse comma separated `pattern` or `<path>:<pattern>` rules. Git doesn't allow `:` in ref
+    /// names, so the path is what comes before it.
+    pub fn parse(rules: &str) -> Self {
+        TagRules(
+            rules
+                .split(',')
+                .map(str::trim)
+                .filter(|r| !r.is_empty())
+                .map(|rule| match rule.rsplit_once(':') {
+                    Some((path, pattern)) => TagRule {
+                        path: Some(path.trim().to_owned()),
+                        pattern: pattern.trim().to_owned(),
+                    },
+                    None => TagRule {
+                        path: None,
+                        pattern: rule.to_owned(),
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether the ref `ref_name`, e.g. `refs/tags/v1.0`, of the repository at `repo_path` is
+    /// one of the tags.
+    pub fn matches(&self, repo_path: &Path, ref_name: &str) -> bool {
+        let Some(tag) = ref_name.strip_prefix("refs/tags/") else {
+            return false;
+        };
+        self.0.iter().any(|rule| {
+            rule.path
+                .as_ref()
+                .is_none_or(|path| repo_path.starts_with(path))
+                && matches_pattern(&rule.pattern, tag)
+        })
+    }
+}
+
+impl PackProtocol {
+    /// Check the commits of the pack `mr_id` that signed branches are moved to are all signed,
+    /// returning why the push is rejected if one isn't. Commits pushed before were checked then.
+    pub async fn check_signed_commits(&self, mr_id: i64) -> Result<(), String> {
+        let Some(verifier) = self.verifier.clone() else {
+            return Ok(());
+        };
+        let heads: Vec<Hash> = self
+            .command_list
+            .iter()
+            .filter(|c| c.refs_type == RefsType::Branch && c.new_id != ZERO_ID)
+            .filter(|c| self.signed_branches.matches(&c.ref_name))
+            .map(|c| Hash::new_from_str(&c.new_id))
+            .collect();
+        if heads.is_empty() {
+            return Ok(());
+        }
+        let git_ids = self
+            .storage
+            .get_mr_objects_by_type(mr_id, "commit")
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|model| model.git_id)
+            .collect();
+        let commits: HashMap<Hash, Vec<u8>> = self
+            .storage
+            .get_obj_data_by_ids(git_ids)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|model| (Hash::new_from_str(&model.git_id), model.data))
+            .collect();
+        check_commits(verifier, &commits, heads).await
+    }
+
+    /// Check no protected tag is moved or deleted, returning why the push is rejected if one is.
+    pub fn check_protected_tags(&self) -> Result<(), String> {
+        for command in &self.command_list {
+            if command.refs_type != RefsType::Tag
+                || command.command_type == CommandType::Create
+                || !self.protected_tags.matches(&self.path, &command.ref_name)
+            {
+                continue;
+            }
+            let action = match command.command_type {
+                CommandType::Delete => "deleted",
+                _ => "moved",
+            };
+            return Err(format!(
+                "tag {} is protected and can't be {}",
+                command.ref_name, action
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the tags pushed to signed tag names are annotated tags signed by a verified key,
+    /// returning why the push is rejected if one isn't.
+    pub async fn check_signed_tags(&self) -> Result<(), String> {
+        let Some(verifier) = self.verifier.clone() else {
+            return Ok(());
+        };
+        for command in &self.command_list {
+            if command.refs_type != RefsType::Tag
+                || command.new_id == ZERO_ID
+                || !self.signed_tags.matches(&self.path, &command.ref_name)
+            {
+                continue;
+            }
+            let tag = self
+                .storage
+                .get_obj_data_by_id(&command.new_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .filter(|model| model.object_type == "tag");
+            let Some(tag) = tag else {
+                return Err(format!(
+                    "tag {} is not an annotated tag and can't be signed",
+                    command.ref_name
+                ));
+            };
+            if let Some(reason) = verifier
+                .unverified_tag_reason(&command.new_id, &tag.data)
+                .await
+            {
+                return Err(format!(
+                    "tag {} is not verified: {}",
+                    command.ref_name, reason
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the commits in `commits` reachable from `heads`.
+async fn check_commits(
+    verifier: Arc<dyn CommitVerifier>,
+    commits: &HashMap<Hash, Vec<u8>>,
+    heads: Vec<Hash>,
+) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    let mut stack = heads;
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let Some(data) = commits.get(&id) else {
+            continue;
+        };
+        if let Some(reason) = verifier.unverified_reason(&id.to_plain_str(), data).await {
+            return Err(format!("commit {} is not verified: {}", id, reason));
+        }
+        stack.extend(Commit::new_from_data(data.clone()).parent_commit_ids);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+
+    use crate::hash::Hash;
+
+    use super::{check_commits, CommitVerifier, SignedBranches, TagRules};
+
+    /// Verifies the commits whose message says so.
+    struct MessageVerifier;
+
+    #[async_trait]
+    impl CommitVerifier for MessageVerifier {
+        async fn unverified_reason(&self, _: &str, data: &[u8]) -> Option<String> {
+            let signed = String::from_utf8_lossy(data).ends_with("\nsigned\n");
+            (!signed).then(|| "commit is not signed".to_owned())
+        }
+
+        async fn unverified_tag_reason(&self, id: &str, data: &[u8]) -> Option<String> {
+            self.unverified_reason(id, data).await
+        }
+    }
+
+    fn commit(parent: Option<Hash>, message: &str) -> Vec<u8> {
+        let parent = parent
+            .map(|p| format!("parent {}\n", p))
+            .unwrap_or_default();
+        format!(
+            "tree 341e54913a3a43069f2927cc0f703e5a9f730df1\n{}\
+            author mega <mega@example.com> 1700000000 +0800\n\
+            committer mega <mega@example.com> 1700000000 +0800\n\
+            \n{}\n",
+            parent, message
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_signed_branches() {
+        let branches = SignedBranches::parse("main, release/*,");
+        assert_eq!(branches.0, vec!["main", "release/*"]);
+        assert!(branches.matches("refs/heads/main"));
+        assert!(branches.matches("refs/heads/release/1.0"));
+        assert!(!branches.matches("refs/heads/main2"));
+        assert!(!branches.matches("refs/tags/main"));
+        assert!(SignedBranches::parse("").0.is_empty());
+    }
+
+    #[test]
+    fn test_tag_rules() {
+        let rules = TagRules::parse("stable, /release:v*");
+        assert!(rules.matches(Path::new("/projects/mega"), "refs/tags/stable"));
+        assert!(rules.matches(Path::new("/release"), "refs/tags/v1.0"));
+        assert!(rules.matches(Path::new("/release/mega"), "refs/tags/v1.0"));
+        assert!(!rules.matches(Path::new("/projects/mega"), "refs/tags/v1.0"));
+        assert!(!rules.matches(Path::new("/releases"), "refs/tags/v1.0"));
+        assert!(!rules.matches(Path::new("/release"), "refs/heads/v1.0"));
+        assert!(TagRules::parse("").0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_commits() {
+        let old = Hash::new_from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d");
+        let first = Hash::new_from_str("1111111111111111111111111111111111111111");
+        let second = Hash::new_from_str("2222222222222222222222222222222222222222");
+        let mut commits = HashMap::from([
+            (first, commit(Some(old), "signed")),
+            (second, commit(Some(first), "signed")),
+        ]);
+        // the parent pushed before isn't in the pack, and isn't checked again
+        assert_eq!(
+            check_commits(Arc::new(MessageVerifier), &commits, vec![second]).await,
+            Ok(())
+        );
+
+        commits.insert(first, commit(Some(old), "unsigned"));
+        assert_eq!(
+            check_commits(Arc::new(MessageVerifier), &commits, vec![second]).await,
+            Err(format!(
+                "commit {} is not verified: commit is not signed",
+                first
+            ))
+        );
+    }
+}
@@ -8,34 +8,63 @@ use itertools::Itertools;
 use sea_orm::ActiveValue::NotSet;
 use sea_orm::{DbErr, Set, TransactionTrait};
 
-use common::utils::ZERO_ID;
+use common::missing_objects;
+use common::operation::Operation;
+use common::utils::{HIDDEN_REF_PREFIX, ZERO_ID};
+use db_entity::mega_pack_bitmap;
 use entity::{objects, refs, repo_directory};
 use storage::driver::database::storage::ObjectStorage;
+use storage::utils::id_generator::generate_id;
 
 use crate::errors::GitError;
 use crate::hash::Hash;
+use crate::internal::budget::SpillBuffer;
 use crate::internal::object::blob::Blob;
 use crate::internal::object::commit::Commit;
 use crate::internal::object::tag::Tag;
-use crate::internal::object::tree::Tree;
+use crate::internal::object::tree::{Tree, TreeItemMode};
 use crate::internal::object::ObjectT;
-use crate::internal::pack::encode::pack_encode;
-use crate::protocol::PackProtocol;
+use crate::internal::pack::bitmap::ReachabilityBitmaps;
+use crate::internal::pack::encode::{pack_encode_reusing, pack_encode_with, StoredDelta};
+use crate::internal::ObjectType;
+use crate::protocol::filter::FilterStats;
+use crate::protocol::limits::FetchRejection;
+use crate::protocol::negotiate::CommitGraph;
+use crate::protocol::{Capability, PackProtocol};
 use crate::structure::nodes::NodeBuilder;
 
 impl PackProtocol {
     /// Asynchronously retrieves the full pack data for the specified repository path.
     /// This function collects commits and nodes from the storage and packs them into
-    /// a single binary vector. There is no need to build the entire tree; the function
+    /// a single pack. There is no need to build the entire tree; the function
     /// only sends all the data related to this repository.
     ///
     /// # Arguments
     /// * `repo_path` - The path to the repository.
     ///
     /// # Returns
-    /// * `Result<Vec<u8>, GitError>` - The packed binary data as a vector of bytes.
+    /// * `Result<SpillBuffer, GitError>` - The packed binary data, on disk when it is over the
+    ///   memory budget of the request.
     ///
-    pub async fn get_full_pack_data(&self, repo_path: &Path) -> Result<Vec<u8>, GitError> {
+    pub async fn get_full_pack_data(&self, repo_path: &Path) -> Result<SpillBuffer, GitError> {
+        let ref_ids = self
+            .storage
+            .get_all_refs_by_path(repo_path.to_str().unwrap())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.ref_git_id)
+            .collect_vec();
+        // a partial clone leaves objects out, which the bitmaps don't know about
+        if self.filter.is_none() {
+            if let Some(objects) = self.bitmap_objects(repo_path, &ref_ids).await? {
+                self.limits
+                    .check_objects(objects.len())
+                    .map_err(GitError::FetchRejected)?;
+                return self.encode_pack(objects).await;
+            }
+        }
+
         // container for reserve all commit,blob and tree objs
         let mut hash_meta: HashMap<Hash, Arc<dyn ObjectT>> = HashMap::new();
         let all_commits: Vec<Commit> = self
@@ -58,94 +87,109 @@ impl PackProtocol {
             .into_iter()
             .map(|m| (m.git_id.clone(), m))
             .collect();
+        let mut stats = FilterStats::default();
+        self.start_stage("counting objects", all_commits.len());
         for c in all_commits {
-            self.traverse_want_trees(
-                all_trees.get(&c.tree_id.to_plain_str()).unwrap(),
-                &mut hash_meta,
-                &HashSet::new(),
-            )
-            .await;
+            self.step()?;
+            let Some(tree) = all_trees.get(&c.tree_id.to_plain_str()) else {
+                stats.missing.push(c.tree_id.to_plain_str());
+                continue;
+            };
+            self.traverse_want_trees(tree, &mut hash_meta, &HashSet::new(), 0, &mut stats)
+                .await;
             hash_meta.insert(c.id, Arc::new(c));
         }
 
-        let tag_ids = self
+        self.get_all_tags(ref_ids.clone(), &mut hash_meta).await;
+        self.log_filter_stats(&stats);
+        self.check_missing(&stats)?;
+        if self.filter.is_none() {
+            self.save_bitmaps(repo_path, &ref_ids, &hash_meta).await;
+        }
+
+        self.limits
+            .check_objects(hash_meta.len())
+            .map_err(GitError::FetchRejected)?;
+        let meta_vec: Vec<Arc<dyn ObjectT>> = hash_meta.into_values().collect();
+        self.encode_pack(meta_vec).await
+    }
+
+    /// The commits of the repository by id, with the graph of their parents to negotiate what a
+    /// fetch is missing.
+    pub async fn commit_graph(&self) -> (HashMap<Hash, Commit>, CommitGraph) {
+        let commits: HashMap<Hash, Commit> = self
             .storage
-            .get_all_refs_by_path(repo_path.to_str().unwrap())
+            .get_all_commits_by_path(self.path.to_str().unwrap())
             .await
             .unwrap()
             .into_iter()
-            .map(|r| r.ref_git_id)
-            .collect_vec();
-        self.get_all_tags(tag_ids, &mut hash_meta).await;
-
-        let meta_vec: Vec<Arc<dyn ObjectT>> = hash_meta.into_values().collect();
-        let result: Vec<u8> = pack_encode(meta_vec).unwrap();
-        Ok(result)
+            .map(|m| {
+                let commit: Commit = m.into();
+                (commit.id, commit)
+            })
+            .collect();
+        let graph = CommitGraph::new(
+            commits
+                .values()
+                .map(|c| (c.id, c.parent_commit_ids.clone())),
+        );
+        (commits, graph)
     }
 
     pub async fn get_incremental_pack_data(
         &self,
         want: Vec<String>,
         have: Vec<String>,
-    ) -> Result<Vec<u8>, GitError> {
+    ) -> Result<SpillBuffer, GitError> {
+        let (commits, graph) = self.commit_graph().await;
+        self.get_missing_pack_data(&commits, &graph, want, have)
+            .await
+    }
+
+    /// Packs what a client having the commits `have` is missing of `want`: the commits
+    /// reachable from `want` but not from `have`, with their trees and blobs but those of the
+    /// commits it has right next to them. Wanted tags come with the commits they point at.
+    pub async fn get_missing_pack_data(
+        &self,
+        commits: &HashMap<Hash, Commit>,
+        graph: &CommitGraph,
+        want: Vec<String>,
+        have: Vec<String>,
+    ) -> Result<SpillBuffer, GitError> {
         let mut hash_meta: HashMap<Hash, Arc<dyn ObjectT>> = HashMap::new();
-        let mut commit_id = String::new();
-        let exist_want_objs = self.storage.get_obj_data_by_ids(want).await.unwrap();
-        for obj in exist_want_objs {
-            match obj.object_type.as_str() {
-                "commit" => {
-                    if commit_id.is_empty() {
-                        commit_id = obj.git_id;
-                    } else {
-                        panic!("only single commit id in want supported!")
-                    }
-                }
-                "tag" => {
-                    let tag: Tag = obj.into();
-                    hash_meta.insert(tag.id, Arc::new(tag));
-                }
-                other_type => panic!("want objetcs type invalid: {}!", other_type),
+        let mut want_commits = Vec::new();
+        for obj in self.storage.get_obj_data_by_ids(want).await.unwrap() {
+            if obj.object_type == "tag" {
+                let tag: Tag = obj.into();
+                want_commits.push(tag.object_hash);
+                hash_meta.insert(tag.id, Arc::new(tag));
+            } else {
+                want_commits.push(Hash::new_from_str(&obj.git_id));
             }
         }
+        let have_commits = have.iter().map(|id| Hash::new_from_str(id)).collect_vec();
 
+        self.start_stage("finding commits", 0);
+        self.step()?;
+        let missing = graph.missing(&want_commits, &have_commits);
         let mut exist_objs = HashSet::new();
-        let repo_path = self.path.to_str().unwrap();
-
-        let commit: Commit = self
+        let boundary_trees = graph
+            .boundary(&missing)
+            .iter()
+            .map(|id| commits[id].tree_id.to_plain_str())
+            .collect();
+        for tree in self
             .storage
-            .get_commit_by_hash(&commit_id, repo_path)
+            .get_obj_data_by_ids(boundary_trees)
             .await
             .unwrap()
-            .unwrap()
-            .into();
-        let mut traversal_list: Vec<Commit> = vec![commit.clone()];
-        let mut want_commits: Vec<Commit> = vec![commit];
-
-        // tarverse commit's all parents to find the commit that client does not have
-        while let Some(temp) = traversal_list.pop() {
-            for p_commit_id in temp.parent_commit_ids {
-                let p_commit_id = &p_commit_id.to_plain_str();
-
-                let want_commit_ids: Vec<String> =
-                    want_commits.iter().map(|x| x.id.to_plain_str()).collect();
-
-                if !have.contains(p_commit_id) && !want_commit_ids.contains(p_commit_id) {
-                    let parent: Commit = self
-                        .storage
-                        .get_commit_by_hash(p_commit_id, repo_path)
-                        .await
-                        .unwrap()
-                        .unwrap()
-                        .into();
-                    want_commits.push(parent.clone());
-                    traversal_list.push(parent);
-                }
-            }
+        {
+            self.add_to_exist_objs(&tree, &mut exist_objs).await;
         }
 
-        let want_tree_ids = want_commits
+        let want_tree_ids = missing
             .iter()
-            .map(|c| c.tree_id.to_plain_str())
+            .map(|id| commits[id].tree_id.to_plain_str())
             .collect();
         let want_trees: HashMap<String, objects::Model> = self
             .storage
@@ -156,42 +200,387 @@ impl PackProtocol {
             .map(|m| (m.git_id.clone(), m))
             .collect();
 
-        for c in want_commits {
-            let have_commit_hashes: Vec<String> = c
-                .parent_commit_ids
-                .clone()
+        let mut stats = FilterStats::default();
+        self.start_stage("counting objects", missing.len());
+        for id in &missing {
+            self.step()?;
+            let c = &commits[id];
+            let Some(tree) = want_trees.get(&c.tree_id.to_plain_str()) else {
+                stats.missing.push(c.tree_id.to_plain_str());
+                continue;
+            };
+            self.traverse_want_trees(tree, &mut hash_meta, &exist_objs, 0, &mut stats)
+                .await;
+            hash_meta.insert(c.id, Arc::new(c.clone()));
+        }
+        tracing::info!(
+            "{} commits missing from {} wanted and {} had",
+            missing.len(),
+            want_commits.len(),
+            have_commits.len()
+        );
+        self.log_filter_stats(&stats);
+        self.check_missing(&stats)?;
+
+        self.limits
+            .check_objects(hash_meta.len())
+            .map_err(GitError::FetchRejected)?;
+        let meta_vec: Vec<Arc<dyn ObjectT>> = hash_meta.into_values().collect();
+        self.encode_pack(meta_vec).await
+    }
+
+    /// Packs the wanted objects alone when they are all trees and blobs, which is how a partial
+    /// clone fetches what its filter left out. Each tree comes with its subtrees and the blobs
+    /// the filter and the prefetch policy let through, its own files counting as the top level.
+    ///
+    /// Returns `None` when a commit or tag is wanted, to send a pack of the repository instead.
+    pub async fn get_object_pack_data(
+        &self,
+        want: &[String],
+    ) -> Result<Option<SpillBuffer>, GitError> {
+        let objs = self
+            .storage
+            .get_obj_data_by_ids(want.to_vec())
+            .await
+            .unwrap();
+        if objs.is_empty()
+            || objs
+                .iter()
+                .any(|o| o.object_type != "tree" && o.object_type != "blob")
+        {
+            return Ok(None);
+        }
+        let mut hash_meta: HashMap<Hash, Arc<dyn ObjectT>> = HashMap::new();
+        let mut stats = FilterStats::default();
+        self.start_stage("counting objects", objs.len());
+        for obj in objs {
+            self.step()?;
+            if obj.object_type == "tree" {
+                self.traverse_want_trees(&obj, &mut hash_meta, &HashSet::new(), 0, &mut stats)
+                    .await;
+            } else {
+                // a blob asked for by id is always sent
+                let mut blob = Blob::new_from_data(obj.data);
+                let blob_id = Hash::new_from_str(&obj.git_id);
+                blob.set_hash(blob_id);
+                hash_meta.insert(blob_id, Arc::new(blob));
+            }
+        }
+        self.log_filter_stats(&stats);
+        self.check_missing(&stats)?;
+
+        self.limits
+            .check_objects(hash_meta.len())
+            .map_err(GitError::FetchRejected)?;
+        let meta_vec: Vec<Arc<dyn ObjectT>> = hash_meta.into_values().collect();
+        self.encode_pack(meta_vec).await.map(Some)
+    }
+
+    /// Start `stage` of the operation of the request, `total` being 0 when it isn't known.
+    fn start_stage(&self, stage: &'static str, total: usize) {
+        if let Some(operation) = &self.operation {
+            operation.set_stage(stage, total as u64);
+        }
+    }
+
+    /// Count a step of the operation of the request, failing once it is cancelled.
+    fn step(&self) -> Result<(), GitError> {
+        if let Some(operation) = &self.operation {
+            operation.check()?;
+            operation.advance(1);
+        }
+        Ok(())
+    }
+
+    /// Encode `objects` into a pack, object by object as a stage of the operation of the request.
+    /// A client with the `ofs-delta` capability gets the deltas the objects were pushed as,
+    /// without computing them again.
+    async fn encode_pack(&self, objects: Vec<Arc<dyn ObjectT>>) -> Result<SpillBuffer, GitError> {
+        let deltas = if self.capabilities.contains(&Capability::OfsDelta) {
+            self.stored_deltas(&objects).await
+        } else {
+            HashMap::new()
+        };
+        self.start_stage("writing objects", objects.len());
+        let before_object = || -> Result<(), std::io::Error> {
+            if let Some(operation) = &self.operation {
+                operation.check()?;
+                operation.advance(1);
+            }
+            Ok(())
+        };
+        let out = SpillBuffer::new(self.budget.clone());
+        if deltas.is_empty() {
+            pack_encode_with(objects, out, before_object)
+        } else {
+            pack_encode_reusing(objects, deltas, out, before_object)
+        }
+        .map_err(|e| match self.operation.as_ref().map(Operation::check) {
+            Some(Err(cancelled)) => cancelled.into(),
+            _ => e.into(),
+        })
+    }
+
+    /// The deltas kept for `objects` when they were pushed, by object id.
+    async fn stored_deltas(&self, objects: &[Arc<dyn ObjectT>]) -> HashMap<Hash, StoredDelta> {
+        let ids = objects
+            .iter()
+            .map(|o| o.get_hash().to_plain_str())
+            .collect();
+        match self.storage.get_obj_deltas_by_ids(ids).await {
+            Ok(models) => models
                 .into_iter()
-                .filter(|p_id| have.contains(&p_id.to_plain_str()))
-                .map(|hash| hash.to_plain_str())
-                .collect();
-            let have_commits = self
-                .storage
-                .get_commit_by_hashes(have_commit_hashes, self.path.to_str().unwrap())
-                .await
-                .unwrap();
-
-            for have_c in have_commits {
-                let have_tree = self
-                    .storage
-                    .get_obj_data_by_id(&have_c.tree)
-                    .await
-                    .unwrap()
-                    .unwrap();
-                self.add_to_exist_objs(&have_tree, &mut exist_objs).await;
+                .map(|m| {
+                    let delta = StoredDelta {
+                        base: Hash::new_from_str(&m.base_id),
+                        data: m.data,
+                    };
+                    (Hash::new_from_str(&m.git_id), delta)
+                })
+                .collect(),
+            Err(err) => {
+                tracing::warn!("sending the objects without their stored deltas: {}", err);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// The objects reachable from the refs `ref_ids` of `repo_path`, read from its reachability
+    /// bitmaps instead of walking its commits and trees. As git does, a ref that moved since the
+    /// bitmaps were written is walked only down to the commits that have one, and gets a bitmap
+    /// of its own for the next clone. `None` when there are no bitmaps, or an object has gone
+    /// missing.
+    async fn bitmap_objects(
+        &self,
+        repo_path: &Path,
+        ref_ids: &[String],
+    ) -> Result<Option<Vec<Arc<dyn ObjectT>>>, GitError> {
+        let Some(model) = self
+            .storage
+            .get_pack_bitmap(repo_path.to_str().unwrap())
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut bitmaps = match ReachabilityBitmaps::from_bytes(&model.data) {
+            Ok(bitmaps) => bitmaps,
+            Err(e) => {
+                tracing::warn!("ignoring the pack bitmaps of {:?}: {}", repo_path, e);
+                return Ok(None);
             }
+        };
+        self.start_stage("counting objects", ref_ids.len());
+        let tips = ref_ids
+            .iter()
+            .map(|id| Hash::new_from_str(id))
+            .collect_vec();
+        let mut loaded = HashMap::new();
+        let mut extended = false;
+        for tip in &tips {
+            self.step()?;
+            if bitmaps.contains(tip) {
+                continue;
+            }
+            let Some((bases, objects)) = self.walk_to_bitmaps(&bitmaps, *tip, &mut loaded).await?
+            else {
+                return Ok(None);
+            };
+            if bitmaps.extend(*tip, &bases, &objects).is_none() {
+                return Ok(None);
+            }
+            extended = true;
+        }
+        let Some(ids) = bitmaps.reachable(&tips) else {
+            return Ok(None);
+        };
 
-            self.traverse_want_trees(
-                want_trees.get(&c.tree_id.to_plain_str()).unwrap(),
-                &mut hash_meta,
-                &exist_objs,
+        let mut objs = self
+            .storage
+            .get_obj_data_by_ids(
+                ids.iter()
+                    .filter(|id| !loaded.contains_key(*id))
+                    .map(|id| id.to_plain_str())
+                    .collect(),
             )
-            .await;
-            hash_meta.insert(c.id, Arc::new(c));
+            .await?;
+        objs.extend(ids.iter().filter_map(|id| loaded.remove(id)));
+        if objs.len() != ids.len() {
+            return Ok(None);
+        }
+        if extended {
+            // the refs that moved away have descendants with bitmaps now
+            bitmaps.retain(&tips);
+            self.write_bitmaps(repo_path, &bitmaps).await;
         }
+        let objects = objs
+            .into_iter()
+            .map(|obj| match obj.object_type.as_str() {
+                "commit" => load_object::<Commit>(obj),
+                "tree" => load_object::<Tree>(obj),
+                "tag" => load_object::<Tag>(obj),
+                _ => load_object::<Blob>(obj),
+            })
+            .collect_vec();
+        tracing::info!(
+            "counted {} objects of {:?} from its pack bitmaps",
+            objects.len(),
+            repo_path
+        );
+        Ok(Some(objects))
+    }
 
-        let meta_vec: Vec<Arc<dyn ObjectT>> = hash_meta.into_values().collect();
-        let result: Vec<u8> = pack_encode(meta_vec).unwrap();
-        Ok(result)
+    /// Walk from `tip` down to the commits that have a bitmap, returning those commits and the
+    /// objects reachable from `tip` but not from them. The objects read are kept in `loaded`,
+    /// not to read them again for the pack. `None` when an object is missing.
+    async fn walk_to_bitmaps(
+        &self,
+        bitmaps: &ReachabilityBitmaps,
+        tip: Hash,
+        loaded: &mut HashMap<Hash, objects::Model>,
+    ) -> Result<Option<(Vec<Hash>, Vec<Hash>)>, GitError> {
+        let mut bases: Vec<Hash> = Vec::new();
+        // the objects the bitmaps of `bases` reach, not walked again
+        let mut covered: HashSet<Hash> = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut objects = Vec::new();
+        let mut pending = vec![tip];
+        while !pending.is_empty() {
+            let (found, rest): (Vec<Hash>, Vec<Hash>) =
+                pending.drain(..).partition(|id| bitmaps.contains(id));
+            let found = found
+                .into_iter()
+                .filter(|id| !bases.contains(id))
+                .collect_vec();
+            if !found.is_empty() {
+                bases.extend(found);
+                covered = bitmaps
+                    .reachable(&bases)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect();
+            }
+            let ids = rest
+                .into_iter()
+                .filter(|id| !covered.contains(id) && visited.insert(*id))
+                .collect_vec();
+            let wanted = ids
+                .iter()
+                .filter(|id| !loaded.contains_key(*id))
+                .map(|id| id.to_plain_str())
+                .collect_vec();
+            if !wanted.is_empty() {
+                for obj in self.storage.get_obj_data_by_ids(wanted).await? {
+                    loaded.insert(Hash::new_from_str(&obj.git_id), obj);
+                }
+            }
+            for id in ids {
+                let Some(obj) = loaded.get(&id) else {
+                    return Ok(None);
+                };
+                match obj.object_type.as_str() {
+                    "commit" => {
+                        let commit = Commit::new_from_data(obj.data.clone());
+                        pending.push(commit.tree_id);
+                        pending.extend(commit.parent_commit_ids);
+                    }
+                    "tree" => pending.extend(
+                        Tree::new_from_data(obj.data.clone())
+                            .tree_items
+                            .into_iter()
+                            .filter(|item| item.mode != TreeItemMode::Commit)
+                            .map(|item| item.id),
+                    ),
+                    "tag" => pending.push(Tag::new_from_data(obj.data.clone()).object_hash),
+                    _ => {}
+                }
+                objects.push(id);
+            }
+        }
+        // walked before the commit with the bitmap reaching them was found
+        objects.retain(|id| !covered.contains(id));
+        Ok(Some((bases, objects)))
+    }
+
+    /// Write the reachability bitmaps of the refs `ref_ids` of `repo_path`, from `objects`
+    /// being every object reachable from them, for the next clone to skip walking them.
+    async fn save_bitmaps(
+        &self,
+        repo_path: &Path,
+        ref_ids: &[String],
+        objects: &HashMap<Hash, Arc<dyn ObjectT>>,
+    ) {
+        let mut edges: HashMap<Hash, Vec<Hash>> = HashMap::with_capacity(objects.len());
+        for (id, obj) in objects {
+            let targets = match obj.get_type() {
+                ObjectType::Commit => {
+                    let commit = Commit::new_from_data(obj.get_raw());
+                    let mut targets = vec![commit.tree_id];
+                    targets.extend(commit.parent_commit_ids);
+                    targets
+                }
+                ObjectType::Tree => Tree::new_from_data(obj.get_raw())
+                    .tree_items
+                    .into_iter()
+                    .filter(|item| item.mode != TreeItemMode::Commit)
+                    .map(|item| item.id)
+                    .collect(),
+                ObjectType::Tag => vec![Tag::new_from_data(obj.get_raw()).object_hash],
+                _ => vec![],
+            };
+            // only what is in the pack, so a bitmap never names an object that isn't stored
+            let targets = targets
+                .into_iter()
+                .filter(|t| objects.contains_key(t))
+                .collect();
+            edges.insert(*id, targets);
+        }
+        let tips = ref_ids
+            .iter()
+            .map(|id| Hash::new_from_str(id))
+            .collect_vec();
+        let bitmaps = ReachabilityBitmaps::build(&edges, &tips);
+        self.write_bitmaps(repo_path, &bitmaps).await;
+    }
+
+    async fn write_bitmaps(&self, repo_path: &Path, bitmaps: &ReachabilityBitmaps) {
+        let now = chrono::Utc::now().naive_utc();
+        let model = mega_pack_bitmap::Model {
+            id: generate_id(),
+            repo_path: repo_path.to_str().unwrap().to_owned(),
+            data: bitmaps.to_bytes(),
+            object_count: bitmaps.object_count() as i32,
+            created_at: now,
+            updated_at: now,
+        };
+        if let Err(e) = self.storage.save_pack_bitmap(model).await {
+            tracing::warn!("failed to save the pack bitmaps of {:?}: {}", repo_path, e);
+        }
+    }
+
+    fn log_filter_stats(&self, stats: &FilterStats) {
+        if let Some(filter) = &self.filter {
+            tracing::info!(
+                "filter {:?} omitted {} blobs, prefetched {} blobs",
+                filter,
+                stats.omitted,
+                stats.prefetched
+            );
+        }
+    }
+
+    /// Refuse the fetch when objects the pack needs are missing, rather than send a pack the
+    /// client would find broken once it is done, reporting each of them.
+    fn check_missing(&self, stats: &FilterStats) -> Result<(), GitError> {
+        if stats.missing.is_empty() {
+            return Ok(());
+        }
+        let repo_path = self.path.to_string_lossy();
+        for id in &stats.missing {
+            missing_objects::report(Some(&repo_path), id, "upload-pack");
+        }
+        Err(GitError::FetchRejected(FetchRejection::MissingObjects {
+            count: stats.missing.len(),
+        }))
     }
 
     pub async fn get_all_tags(
@@ -213,27 +602,21 @@ impl PackProtocol {
         }
     }
 
+    /// Id of the commit `repo_path` is at, publishing it first when it is a directory of a
+    /// repository, or the zero id when there is no such commit.
     pub async fn get_head_object_id(&self, repo_path: &Path) -> String {
+        if repo_path == self.path {
+            self.publish_subdir().await;
+        }
         let path_str = repo_path.to_str().unwrap();
         let refs_list = self.storage.search_refs(path_str).await.unwrap();
-
-        if refs_list.is_empty() {
-            ZERO_ID.to_string()
-        } else {
-            for refs in &refs_list {
-                if repo_path.to_str().unwrap() == refs.repo_path {
-                    return refs.ref_git_id.clone();
-                }
-            }
-            for refs in &refs_list {
-                // if repo_path is subdirectory of some commit, we should generae a fake commit
-                if repo_path.starts_with(refs.repo_path.clone()) {
-                    return self.generate_subdir_commit(refs, repo_path).await;
-                }
-            }
-            //situation: repo_path: root/repotest2/src, commit: root/repotest
-            ZERO_ID.to_string()
-        }
+        refs_list
+            .into_iter()
+            .find(|refs| {
+                refs.repo_path == path_str && !refs.ref_name.starts_with(HIDDEN_REF_PREFIX)
+            })
+            .map(|refs| refs.ref_git_id)
+            .unwrap_or_else(|| ZERO_ID.to_string())
     }
 
     // get all objects id from have tree
@@ -264,35 +647,57 @@ impl PackProtocol {
         exist_objs.insert(t.id);
     }
 
-    // retrieve all sub trees recursively
+    // retrieve all sub trees recursively, `depth` counts the trees above `want_t`
     #[async_recursion]
     async fn traverse_want_trees(
         &self,
         want_t: &objects::Model,
         all_objects: &mut HashMap<Hash, Arc<dyn ObjectT>>,
         exist_objs: &HashSet<Hash>,
+        depth: usize,
+        stats: &mut FilterStats,
     ) {
         let mut t = Tree::new_from_data(want_t.data.clone());
         t.set_hash(Hash::new_from_str(&want_t.git_id));
 
         let mut search_child_ids = vec![];
         for item in &t.tree_items {
-            if !all_objects.contains_key(&item.id) && !exist_objs.contains(&item.id) {
+            // gitlinks name commits of other repositories
+            if item.mode != TreeItemMode::Commit
+                && !all_objects.contains_key(&item.id)
+                && !exist_objs.contains(&item.id)
+            {
                 search_child_ids.push(item.id.to_plain_str());
             }
         }
         let objs = self
             .storage
-            .get_obj_data_by_ids(search_child_ids)
+            .get_obj_data_by_ids(search_child_ids.clone())
             .await
             .unwrap();
+        let found: HashSet<&str> = objs.iter().map(|obj| obj.git_id.as_str()).collect();
+        let missing = search_child_ids
+            .iter()
+            .filter(|id| !found.contains(id.as_str()))
+            .cloned()
+            .collect_vec();
+        stats.missing.extend(missing);
         for obj in objs {
             if obj.object_type == "tree" {
-                self.traverse_want_trees(&obj, all_objects, exist_objs)
+                self.traverse_want_trees(&obj, all_objects, exist_objs, depth + 1, stats)
                     .await;
             } else {
-                let mut blob = Blob::new_from_data(obj.data.clone());
                 let blob_id = Hash::new_from_str(&obj.git_id.clone());
+                let name = t
+                    .tree_items
+                    .iter()
+                    .find(|item| item.id == blob_id)
+                    .map(|item| item.name.as_str())
+                    .unwrap_or_default();
+                if !self.send_blob(name, depth, obj.data.len() as u64, stats) {
+                    continue;
+                }
+                let mut blob = Blob::new_from_data(obj.data.clone());
                 blob.set_hash(blob_id);
                 all_objects.insert(blob_id, Arc::new(blob));
             }
@@ -346,53 +751,6 @@ impl PackProtocol {
         Ok(())
     }
 
-    /// Generates a new commit for a subdirectory of the original project directory.
-    /// Steps:
-    /// 1. Retrieve the root commit based on the provided reference's Git ID.
-    /// 2. If a root tree is found by searching for the repository path:
-    ///    a. Construct a child commit using the retrieved root commit and the root tree.
-    ///    b. Save the child commit.
-    ///    c. Obtain the commit ID of the child commit.
-    ///    d. Construct a child reference with the repository path, reference name, commit ID, and other relevant information.
-    ///    e. Save the child reference in the database.
-    /// 3. Return the commit ID of the child commit if successful; otherwise, return a default ID.
-    pub async fn generate_subdir_commit(&self, refs: &refs::Model, repo_path: &Path) -> String {
-        let root_commit: Commit = self
-            .storage
-            .get_commit_by_hash(&refs.ref_git_id.clone(), &refs.repo_path)
-            .await
-            .unwrap()
-            .unwrap()
-            .into();
-
-        let relative_path = PathBuf::from(&repo_path.to_str().unwrap()[refs.repo_path.len()..]);
-        let mut comp = relative_path.components();
-        // skip the first root dir
-        comp.next();
-        let t_id = self
-            .search_dir_from_tree(&root_commit.tree_id.to_plain_str(), comp)
-            .await;
-
-        let child_commit =
-            Commit::subdir_commit(root_commit.to_data().unwrap(), Hash::new_from_str(&t_id));
-        let child_c_model = child_commit.convert_to_model(repo_path);
-        self.storage
-            .save_commits(None, vec![child_c_model.clone()])
-            .await
-            .unwrap();
-        let commit_id = child_commit.id.to_plain_str();
-        let child_refs = refs::ActiveModel {
-            id: NotSet,
-            repo_path: Set(repo_path.to_str().unwrap().to_string()),
-            ref_name: Set(refs.ref_name.clone()),
-            ref_git_id: Set(commit_id.clone()),
-            created_at: Set(chrono::Utc::now().naive_utc()),
-            updated_at: Set(chrono::Utc::now().naive_utc()),
-        };
-        self.storage.save_refs(vec![child_refs]).await.unwrap();
-        commit_id
-    }
-
     // find search_dir's tree id from a provided tree
     #[async_recursion]
     pub async fn search_dir_from_tree<'a>(
@@ -422,6 +780,13 @@ impl PackProtocol {
     }
 }
 
+/// The object stored as `model`, with the id it is stored under.
+fn load_object<T: ObjectT + 'static>(model: objects::Model) -> Arc<dyn ObjectT> {
+    let mut obj = T::new_from_data(model.data);
+    obj.set_hash(Hash::new_from_str(&model.git_id));
+    Arc::new(obj)
+}
+
 pub async fn save_node_from_mr(
     storage: Arc<dyn ObjectStorage>,
     mr_id: i64,
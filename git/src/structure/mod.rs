@@ -6,15 +6,11 @@ use sea_orm::{ActiveValue::NotSet, Set};
 
 use crate::{
     hash::Hash,
-    internal::{
-        object::{
-            blob::Blob,
-            commit::Commit,
-            meta::Meta,
-            tree::{Tree, TreeItem, TreeItemMode},
-            ObjectT,
-        },
-        ObjectType,
+    internal::object::{
+        blob::Blob,
+        commit::Commit,
+        tree::{Tree, TreeItem, TreeItemMode},
+        ObjectT,
     },
 };
 
@@ -22,6 +18,7 @@ use self::nodes::{FileNode, Node, TreeNode};
 
 pub mod conversion;
 pub mod nodes;
+pub mod subrepo;
 /// only blob and tree should implement this trait
 pub trait GitNodeObject {
     fn convert_to_node(
@@ -69,18 +66,6 @@ impl GitNodeObject for Blob {
 }
 
 impl Commit {
-    pub fn subdir_commit(meta: Vec<u8>, tree_id: Hash) -> Commit {
-        let mut c = Commit::new_from_meta(Meta::new_from_data_with_object_type(
-            ObjectType::Commit,
-            meta,
-        ))
-        .unwrap();
-        c.tree_id = tree_id;
-        c.parent_commit_ids.clear();
-        c.id = Meta::calculate_id(ObjectType::Commit, &c.to_data().unwrap());
-        c
-    }
-
     pub fn convert_to_model(&self, repo_path: &Path) -> commit::ActiveModel {
         let pid = self
             .parent_commit_ids
@@ -0,0 +1,865 @@
+//!
+//! Publishing a directory of a repository as a repository of its own.
+//!
+//! Fetching `/project/lib.git`, where `/project` is a repository and `lib` one of its
+//! directories, gets the history of `lib` alone: each commit of `/project` changing the
+//! directory is made into a commit with the same author, committer and message, the directory as
+//! its tree and the commits made from its parents as parents. Commits not changing the directory
+//! are left out. The ids only depend on the commits of `/project`, so publishing again gives the
+//! same history, and the published path is recorded as a snapshot.
+//!
+//! The commit each commit of `/project` maps to is recorded as a path mapping, so publishing
+//! again only walks the commits added since. The branches and tags of `/project` are published
+//! under their own names, as [`PUBLISH_REFSPECS`] map them.
+//!
+//! A push to a published path goes the other way: each commit pushed is made into a commit of
+//! `/project` whose tree is the one of the branch head with the directory replaced, and the
+//! branch moves to it. The commits made are mapped to the very commits that were pushed.
+//!
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use async_recursion::async_recursion;
+use sea_orm::{ActiveValue::NotSet, DbErr, Set, TransactionTrait};
+
+use common::operation::{self, OperationKind};
+use db_entity::{mega_path_mapping, mega_snapshot};
+use entity::{objects, refs};
+use storage::utils::id_generator::generate_id;
+
+use crate::hash::Hash;
+use crate::internal::object::blob::Blob;
+use crate::internal::object::commit::Commit;
+use crate::internal::object::meta::Meta;
+use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use crate::internal::ObjectType;
+use crate::protocol::refspec::RefSpecs;
+use crate::protocol::{CommandType, PackProtocol, RefsType};
+use crate::structure::conversion::{get_objects_from_mr, get_objects_vec_from_mr};
+use crate::structure::nodes::NodeBuilder;
+
+/// The refs of a repository its published directories have, and the names they have there.
+pub const PUBLISH_REFSPECS: &[&str] = &["refs/heads/*:refs/heads/*", "refs/tags/*:refs/tags/*"];
+
+fn publish_refspecs() -> RefSpecs {
+    RefSpecs::fetch(PUBLISH_REFSPECS).unwrap()
+}
+
+/// A directory published as a repository.
+pub struct Published {
+    /// Path of the repository the directory is in.
+    pub repo: String,
+    /// Path of the directory below the root of `repo`.
+    pub dir: PathBuf,
+    pub split: Split,
+}
+
+/// History of a directory split out of the history of its repository.
+#[derive(Default)]
+pub struct Split {
+    /// Commit of the directory each commit of the repository maps to, `None` for commits the
+    /// directory doesn't exist in yet.
+    pub mapped: HashMap<Hash, Option<Hash>>,
+    /// Commit of the repository each commit of the directory was made from.
+    pub origin: HashMap<Hash, Hash>,
+    /// Commits of the directory made by [`Split::extend`], parents first.
+    pub commits: Vec<Commit>,
+    /// Commits of the repository mapped by [`Split::extend`], parents first.
+    pub walked: Vec<Hash>,
+    trees: HashMap<Hash, Hash>,
+}
+
+impl Split {
+    /// Split the history of `heads` out of `commits`, `dirs` giving the tree of the directory in
+    /// each commit it exists in.
+    pub fn new(
+        commits: &HashMap<Hash, Commit>,
+        dirs: &HashMap<Hash, Hash>,
+        heads: &[Hash],
+    ) -> Split {
+        let mut split = Split::default();
+        split.extend(commits, dirs, heads);
+        split
+    }
+
+    /// Record that `commit` of the repository maps to the commit of the directory `mapped`, with
+    /// its tree, and whether that commit was made from `commit`.
+    pub fn record(&mut self, commit: Hash, mapped: Option<(Hash, Hash)>, made: bool) {
+        if let Some((id, tree)) = mapped {
+            self.trees.insert(id, tree);
+            if made {
+                self.origin.insert(id, commit);
+            }
+        }
+        self.mapped.insert(commit, mapped.map(|(id, _)| id));
+    }
+
+    /// Split the history of `heads` which isn't mapped yet out of `commits`, the commits of the
+    /// repository not mapped yet.
+    pub fn extend(
+        &mut self,
+        commits: &HashMap<Hash, Commit>,
+        dirs: &HashMap<Hash, Hash>,
+        heads: &[Hash],
+    ) {
+        for head in heads {
+            let mut stack = vec![*head];
+            while let Some(&id) = stack.last() {
+                if self.mapped.contains_key(&id) {
+                    stack.pop();
+                    continue;
+                }
+                let Some(commit) = commits.get(&id) else {
+                    // annotated tags and commits of other repositories
+                    self.mapped.insert(id, None);
+                    stack.pop();
+                    continue;
+                };
+                let pending: Vec<Hash> = commit
+                    .parent_commit_ids
+                    .iter()
+                    .filter(|p| !self.mapped.contains_key(p))
+                    .copied()
+                    .collect();
+                if !pending.is_empty() {
+                    stack.extend(pending);
+                    continue;
+                }
+                stack.pop();
+                let mapped = self.make(commit, dirs.get(&id).copied());
+                self.mapped.insert(id, mapped);
+                self.walked.push(id);
+            }
+        }
+    }
+
+    /// Tree of the commit of the directory `id`.
+    pub fn tree(&self, id: &Hash) -> Option<Hash> {
+        self.trees.get(id).copied()
+    }
+
+    fn make(&mut self, commit: &Commit, dir: Option<Hash>) -> Option<Hash> {
+        let mut parents = vec![];
+        for parent in &commit.parent_commit_ids {
+            if let Some(Some(mapped)) = self.mapped.get(parent) {
+                if !parents.contains(mapped) {
+                    parents.push(*mapped);
+                }
+            }
+        }
+        let Some(tree) = dir else {
+            return parents.first().copied();
+        };
+        if let [parent] = parents[..] {
+            if self.trees[&parent] == tree {
+                return Some(parent);
+            }
+        }
+        let made = commit_with(commit, tree, parents);
+        if let Entry::Vacant(entry) = self.trees.entry(made.id) {
+            entry.insert(tree);
+            self.commits.push(made.clone());
+        }
+        self.origin.insert(made.id, commit.id);
+        Some(made.id)
+    }
+}
+
+/// Commit with the author, committer and message of `commit`, and `tree` and `parents`.
+fn commit_with(commit: &Commit, tree: Hash, parents: Vec<Hash>) -> Commit {
+    let mut made = Commit {
+        id: Hash::default(),
+        tree_id: tree,
+        parent_commit_ids: parents,
+        author: commit.author.clone(),
+        committer: commit.committer.clone(),
+        message: commit.message.clone(),
+    };
+    made.id = Meta::calculate_id(ObjectType::Commit, &made.to_data().unwrap());
+    made
+}
+
+/// Put `item` in `items` in place of the item of the same name, in the order git sorts the
+/// items of a tree: by name, with the names of trees ending with a `/`.
+fn set_tree_item(items: &mut Vec<TreeItem>, item: TreeItem) {
+    fn sort_key(item: &TreeItem) -> Vec<u8> {
+        let mut key = item.name.as_bytes().to_vec();
+        if item.mode == TreeItemMode::Tree {
+            key.push(b'/');
+        }
+        key
+    }
+    items.retain(|i| i.name != item.name);
+    let key = sort_key(&item);
+    let at = items.partition_point(|i| sort_key(i) < key);
+    items.insert(at, item);
+}
+
+fn dir_names(dir: &Path) -> Vec<String> {
+    dir.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+impl PackProtocol {
+    /// Repository the path of this protocol is a published directory of, along with the
+    /// directory below its root. `None` when the path is a repository of its own, or no
+    /// repository contains it.
+    pub async fn publish_target(&self) -> Option<(String, PathBuf)> {
+        let path = self.path.to_str().unwrap();
+        let published = self.storage.get_snapshot(path).await.unwrap().is_some();
+        let mut repos: Vec<String> = vec![];
+        for r in self.storage.search_refs(path).await.unwrap() {
+            if r.repo_path == path {
+                if !published {
+                    return None;
+                }
+            } else if self.path.starts_with(&r.repo_path) && !repos.contains(&r.repo_path) {
+                repos.push(r.repo_path);
+            }
+        }
+        // the innermost repository which isn't a published directory itself
+        repos.sort_by_key(|r| std::cmp::Reverse(r.len()));
+        for repo in repos {
+            if self.storage.get_snapshot(&repo).await.unwrap().is_none() {
+                let dir = self.path.strip_prefix(&repo).unwrap().to_path_buf();
+                return Some((repo, dir));
+            }
+        }
+        None
+    }
+
+    /// Publish the directory at the path of this protocol, when it is one: the refs of the path
+    /// are set to the split history of the refs of its repository, and the commits of that
+    /// history are saved at the path.
+    ///
+    /// Returns `None` when the path isn't a directory of a repository, the directory never
+    /// existed in it, or the split was cancelled before it saved anything.
+    pub async fn publish_subdir(&self) -> Option<Published> {
+        let (repo, dir) = self.publish_target().await?;
+        let path = self.path.to_str().unwrap();
+        let operation = operation::start(OperationKind::HistorySplit, path);
+
+        let mut split = Split::default();
+        for m in self.storage.get_path_mappings(path).await.unwrap() {
+            let mapped = m
+                .split_commit
+                .as_deref()
+                .zip(m.split_tree.as_deref())
+                .map(|(id, tree)| (Hash::new_from_str(id), Hash::new_from_str(tree)));
+            split.record(Hash::new_from_str(&m.repo_commit), mapped, m.made);
+        }
+        let repo_refs = self.storage.get_all_refs_by_path(&repo).await.unwrap();
+        let heads: Vec<Hash> = repo_refs
+            .iter()
+            .map(|r| Hash::new_from_str(&r.ref_git_id))
+            .collect();
+
+        // the commits added since the last time, down to those mapped then
+        let mut commits: HashMap<Hash, Commit> = HashMap::new();
+        let mut wanted: HashSet<Hash> = heads
+            .iter()
+            .filter(|h| !split.mapped.contains_key(h))
+            .copied()
+            .collect();
+        operation.set_stage("walking history", 0);
+        while !wanted.is_empty() {
+            if let Err(e) = operation.check() {
+                tracing::warn!("{}, {} stays as published before", e, path);
+                return None;
+            }
+            operation.advance(wanted.len() as u64);
+            let found = self
+                .storage
+                .get_commit_by_hashes(wanted.iter().map(|h| h.to_plain_str()).collect(), &repo)
+                .await
+                .unwrap();
+            wanted.clear();
+            for m in found {
+                let commit: Commit = m.into();
+                wanted.extend(commit.parent_commit_ids.iter().copied());
+                commits.insert(commit.id, commit);
+            }
+            wanted.retain(|id| !split.mapped.contains_key(id) && !commits.contains_key(id));
+        }
+        let mut cache = HashMap::new();
+        let mut dirs = HashMap::new();
+        operation.set_stage("splitting commits", commits.len() as u64);
+        for commit in commits.values() {
+            if let Err(e) = operation.check() {
+                tracing::warn!("{}, {} stays as published before", e, path);
+                return None;
+            }
+            operation.advance(1);
+            if let Some(tree) = self.find_dir(commit.tree_id, &dir, &mut cache).await {
+                dirs.insert(commit.id, tree);
+            }
+        }
+        split.extend(&commits, &dirs, &heads);
+
+        let now = chrono::Utc::now().naive_utc();
+        if !split.commits.is_empty() {
+            let objs = split
+                .commits
+                .iter()
+                .map(|c| objects::ActiveModel {
+                    id: Set(generate_id()),
+                    git_id: Set(c.id.to_plain_str()),
+                    object_type: Set("commit".to_owned()),
+                    data: Set(c.to_data().unwrap()),
+                    link: Set(None),
+                })
+                .collect();
+            self.storage.save_obj_data(None, objs).await.unwrap();
+            let models = split
+                .commits
+                .iter()
+                .map(|c| c.convert_to_model(&self.path))
+                .collect();
+            self.storage.save_commits(None, models).await.unwrap();
+        }
+        let mappings = split
+            .walked
+            .iter()
+            .map(|id| {
+                let mapped = split.mapped[id];
+                mega_path_mapping::Model {
+                    id: generate_id(),
+                    path: path.to_owned(),
+                    repo_path: repo.clone(),
+                    repo_commit: id.to_plain_str(),
+                    split_commit: mapped.map(|m| m.to_plain_str()),
+                    split_tree: mapped
+                        .and_then(|m| split.tree(&m))
+                        .map(|t| t.to_plain_str()),
+                    made: mapped.is_some_and(|m| split.origin.get(&m) == Some(id)),
+                    created_at: now,
+                }
+            })
+            .collect();
+        self.storage.save_path_mappings(mappings).await.unwrap();
+
+        let specs = publish_refspecs();
+        let mut published_refs = vec![];
+        for r in &repo_refs {
+            if let Some(Some(id)) = split.mapped.get(&Hash::new_from_str(&r.ref_git_id)) {
+                for mapping in specs.map(&r.ref_name) {
+                    published_refs.push((mapping.dst, *id));
+                }
+            }
+        }
+        for r in self.storage.get_all_refs_by_path(path).await.unwrap() {
+            if !published_refs.iter().any(|(name, _)| *name == r.ref_name) {
+                self.storage.remove_ref(path, &r.ref_name).await.unwrap();
+            }
+        }
+        if published_refs.is_empty() {
+            self.storage.get_snapshot(path).await.unwrap()?;
+        } else {
+            let models = published_refs
+                .iter()
+                .map(|(name, id)| refs::ActiveModel {
+                    id: NotSet,
+                    repo_path: Set(path.to_owned()),
+                    ref_name: Set(name.clone()),
+                    ref_git_id: Set(id.to_plain_str()),
+                    created_at: Set(now),
+                    updated_at: Set(now),
+                })
+                .collect();
+            self.storage.save_refs(models).await.unwrap();
+
+            let (_, head) = published_refs
+                .iter()
+                .find(|(name, _)| name == "refs/heads/master" || name == "refs/heads/main")
+                .unwrap_or(&published_refs[0]);
+            let snapshot = mega_snapshot::Model {
+                id: generate_id(),
+                path: path.to_owned(),
+                name: self
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+                import_dir: false,
+                tree_id: split.tree(head).map(|t| t.to_plain_str()),
+                sub_trees: None,
+                commit_id: Some(head.to_plain_str()),
+                size: 0,
+                created_at: now,
+                updated_at: now,
+            };
+            self.storage.save_snapshot(snapshot).await.unwrap();
+        }
+        Some(Published { repo, dir, split })
+    }
+
+    /// Take the pack `mr_id` pushed to a published directory: the commits pushed are made into
+    /// commits of its repository and its branches move to them. Only branches existing in the
+    /// repository can be pushed, fast-forward from the commit the directory was published at.
+    pub async fn receive_published(&mut self, mr_id: i64, published: Published) {
+        let mut trees: HashMap<Hash, Tree> =
+            get_objects_from_mr(self.storage.clone(), mr_id, "tree").await;
+        let blobs: HashMap<Hash, Blob> =
+            get_objects_from_mr(self.storage.clone(), mr_id, "blob").await;
+        let pushed: HashMap<Hash, Commit> =
+            get_objects_vec_from_mr::<Commit>(self.storage.clone(), mr_id, "commit")
+                .await
+                .into_iter()
+                .map(|c| (c.id, c))
+                .collect();
+        let repo_refs: HashMap<String, String> = self
+            .storage
+            .get_all_refs_by_path(&published.repo)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.ref_name, r.ref_git_id))
+            .collect();
+
+        let specs = publish_refspecs();
+        let mut translation = Translation::default();
+        let mut moved = vec![];
+        let mut commands = self.command_list.clone();
+        for command in commands.iter_mut() {
+            // the branch of the repository the pushed ref is published from
+            let repo_ref = specs
+                .reverse(&command.ref_name)
+                .unwrap_or_else(|| command.ref_name.clone());
+            let head = match (&command.refs_type, &command.command_type) {
+                (RefsType::Tag, _) => Err(format!(
+                    "tags of a published directory are made in {}",
+                    published.repo
+                )),
+                (_, CommandType::Create | CommandType::Delete) => Err(format!(
+                    "branches of a published directory are created and deleted in {}",
+                    published.repo
+                )),
+                _ => repo_refs
+                    .get(&repo_ref)
+                    .map(|id| Hash::new_from_str(id))
+                    .ok_or_else(|| format!("no branch {} in {}", repo_ref, published.repo)),
+            };
+            let result = match head {
+                Ok(head) => {
+                    self.translate(
+                        &published,
+                        &pushed,
+                        head,
+                        Hash::new_from_str(&command.old_id),
+                        Hash::new_from_str(&command.new_id),
+                        &mut trees,
+                        &mut translation,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(id) => moved.push((repo_ref, id)),
+                Err(e) => command.failed(e),
+            }
+        }
+        self.command_list = commands;
+        if moved.is_empty() {
+            return;
+        }
+
+        let mut objs: Vec<objects::ActiveModel> = translation
+            .trees
+            .iter()
+            .map(|t| objects::ActiveModel {
+                id: Set(generate_id()),
+                git_id: Set(t.id.to_plain_str()),
+                object_type: Set("tree".to_owned()),
+                data: Set(t.to_data().unwrap()),
+                link: Set(None),
+            })
+            .collect();
+        objs.extend(translation.commits.iter().map(|c| objects::ActiveModel {
+            id: Set(generate_id()),
+            git_id: Set(c.id.to_plain_str()),
+            object_type: Set("commit".to_owned()),
+            data: Set(c.to_data().unwrap()),
+            link: Set(None),
+        }));
+        self.storage.save_obj_data(None, objs).await.unwrap();
+        let builder = NodeBuilder {
+            storage: self.storage.clone(),
+            tree_map: trees,
+            blob_map: blobs,
+            repo_path: PathBuf::from(&published.repo),
+            commits: translation.commits,
+        };
+        let nodes = builder.build_node_tree().await.unwrap();
+        self.storage
+            .get_connection()
+            .transaction::<_, (), DbErr>(|txn| {
+                Box::pin(async move {
+                    builder.save_nodes(Some(txn), nodes).await.unwrap();
+                    builder.save_commits(Some(txn)).await.unwrap();
+                    Ok(())
+                })
+            })
+            .await
+            .unwrap();
+
+        let now = chrono::Utc::now().naive_utc();
+        let models = moved
+            .iter()
+            .map(|(name, id)| refs::ActiveModel {
+                id: NotSet,
+                repo_path: Set(published.repo.clone()),
+                ref_name: Set(name.clone()),
+                ref_git_id: Set(id.to_plain_str()),
+                created_at: Set(now),
+                updated_at: Set(now),
+            })
+            .collect();
+        self.storage.save_refs(models).await.unwrap();
+
+        // the pushed commits are those of the directory the commits made map to
+        let translated: Vec<(&Commit, Hash)> = translation
+            .made
+            .iter()
+            .map(|(id, made)| (&pushed[id], *made))
+            .collect();
+        let objs = translated
+            .iter()
+            .map(|(c, _)| objects::ActiveModel {
+                id: Set(generate_id()),
+                git_id: Set(c.id.to_plain_str()),
+                object_type: Set("commit".to_owned()),
+                data: Set(c.to_data().unwrap()),
+                link: Set(None),
+            })
+            .collect();
+        self.storage.save_obj_data(None, objs).await.unwrap();
+        let models = translated
+            .iter()
+            .map(|(c, _)| c.convert_to_model(&self.path))
+            .collect();
+        self.storage.save_commits(None, models).await.unwrap();
+        let mappings = translated
+            .iter()
+            .map(|(c, made)| mega_path_mapping::Model {
+                id: generate_id(),
+                path: self.path.to_str().unwrap().to_owned(),
+                repo_path: published.repo.clone(),
+                repo_commit: made.to_plain_str(),
+                split_commit: Some(c.id.to_plain_str()),
+                split_tree: Some(c.tree_id.to_plain_str()),
+                made: true,
+                created_at: now,
+            })
+            .collect();
+        self.storage.save_path_mappings(mappings).await.unwrap();
+        self.publish_subdir().await;
+    }
+
+    /// Make the commits pushed from `old` to `new` into commits of the repository on top of
+    /// `head`, its branch, and return the commit the branch moves to.
+    #[allow(clippy::too_many_arguments)]
+    async fn translate(
+        &self,
+        published: &Published,
+        pushed: &HashMap<Hash, Commit>,
+        head: Hash,
+        old: Hash,
+        new: Hash,
+        trees: &mut HashMap<Hash, Tree>,
+        translation: &mut Translation,
+    ) -> Result<Hash, String> {
+        if published.split.mapped.get(&head).copied().flatten() != Some(old) {
+            return Err("fetch first".to_owned());
+        }
+        if new == old {
+            return Ok(head);
+        }
+        let head_tree = self
+            .storage
+            .get_commit_by_hash(&head.to_plain_str(), &published.repo)
+            .await
+            .unwrap()
+            .map(|c| Hash::new_from_str(&c.tree))
+            .ok_or_else(|| format!("commit {} of {} not found", head, published.repo))?;
+        let dir = dir_names(&published.dir);
+
+        // commits of the repository each parent stands for, those pushed made first
+        let known = |id: &Hash, translation: &Translation| {
+            if *id == old {
+                Some(head)
+            } else if let Some(made) = translation.made.get(id) {
+                Some(*made)
+            } else {
+                published.split.origin.get(id).copied()
+            }
+        };
+        let mut fast_forward = HashSet::from([old]);
+        let mut stack = vec![new];
+        while let Some(&id) = stack.last() {
+            if known(&id, translation).is_some() {
+                stack.pop();
+                continue;
+            }
+            let commit = pushed
+                .get(&id)
+                .ok_or_else(|| format!("commit {} not found", id))?;
+            if commit.parent_commit_ids.is_empty() {
+                return Err(format!("{} has no parent in {}", id, self.path.display()));
+            }
+            let pending: Vec<Hash> = commit
+                .parent_commit_ids
+                .iter()
+                .filter(|p| known(p, translation).is_none())
+                .copied()
+                .collect();
+            if !pending.is_empty() {
+                stack.extend(pending);
+                continue;
+            }
+            stack.pop();
+            let parents: Vec<Hash> = commit
+                .parent_commit_ids
+                .iter()
+                .map(|p| known(p, translation).unwrap())
+                .collect();
+            if commit
+                .parent_commit_ids
+                .iter()
+                .any(|p| fast_forward.contains(p))
+            {
+                fast_forward.insert(id);
+            }
+            let tree = self
+                .replace_dir(Some(head_tree), &dir, commit.tree_id, trees, translation)
+                .await?;
+            let made = commit_with(commit, tree, parents);
+            translation.made.insert(id, made.id);
+            translation.commits.push(made);
+        }
+        if !fast_forward.contains(&new) {
+            return Err("non-fast-forward".to_owned());
+        }
+        Ok(translation.made[&new])
+    }
+
+    /// Tree made of the tree `root` with the directory at `dir` set to `tree`.
+    #[async_recursion]
+    async fn replace_dir(
+        &self,
+        root: Option<Hash>,
+        dir: &[String],
+        tree: Hash,
+        trees: &mut HashMap<Hash, Tree>,
+        translation: &mut Translation,
+    ) -> Result<Hash, String> {
+        let Some((name, rest)) = dir.split_first() else {
+            return Ok(tree);
+        };
+        let mut parent = match root {
+            Some(id) => self
+                .load_tree(id, trees)
+                .await
+                .ok_or_else(|| format!("tree {} not found", id))?,
+            None => Tree {
+                id: Hash::default(),
+                tree_items: vec![],
+            },
+        };
+        let child = parent
+            .tree_items
+            .iter()
+            .find(|i| i.name == *name && i.mode == TreeItemMode::Tree)
+            .map(|i| i.id);
+        let id = self
+            .replace_dir(child, rest, tree, trees, translation)
+            .await?;
+        set_tree_item(
+            &mut parent.tree_items,
+            TreeItem::new(TreeItemMode::Tree, id, name.clone()),
+        );
+        parent.id = Meta::calculate_id(ObjectType::Tree, &parent.to_data().unwrap());
+        if let Entry::Vacant(entry) = trees.entry(parent.id) {
+            entry.insert(parent.clone());
+            translation.trees.push(parent.clone());
+        }
+        Ok(parent.id)
+    }
+
+    /// Tree of the directory at `dir` below the tree `root`.
+    async fn find_dir(
+        &self,
+        root: Hash,
+        dir: &Path,
+        cache: &mut HashMap<Hash, Tree>,
+    ) -> Option<Hash> {
+        let mut id = root;
+        for name in dir_names(dir) {
+            let tree = self.load_tree(id, cache).await?;
+            id = tree
+                .tree_items
+                .iter()
+                .find(|i| i.name == name && i.mode == TreeItemMode::Tree)?
+                .id;
+        }
+        Some(id)
+    }
+
+    async fn load_tree(&self, id: Hash, cache: &mut HashMap<Hash, Tree>) -> Option<Tree> {
+        if let Some(tree) = cache.get(&id) {
+            return Some(tree.clone());
+        }
+        let model = self
+            .storage
+            .get_obj_data_by_id(&id.to_plain_str())
+            .await
+            .unwrap()?;
+        let tree: Tree = model.into();
+        cache.insert(id, tree.clone());
+        Some(tree)
+    }
+}
+
+/// Commits and trees made for the repository out of a push to a published directory.
+#[derive(Default)]
+struct Translation {
+    /// Commit of the repository each pushed commit was made into.
+    made: HashMap<Hash, Hash>,
+    commits: Vec<Commit>,
+    trees: Vec<Tree>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::hash::Hash;
+    use crate::internal::object::commit::Commit;
+    use crate::internal::object::signature::Signature;
+    use crate::internal::object::tree::{TreeItem, TreeItemMode};
+
+    use super::{commit_with, set_tree_item, Split};
+
+    fn hash(n: u8) -> Hash {
+        Hash([n; 20])
+    }
+
+    fn commit(n: u8, parents: &[u8]) -> Commit {
+        let signature =
+            Signature::new_from_data(b"author mega <mega@example.com> 1700000000 +0800".to_vec())
+                .unwrap();
+        Commit {
+            id: hash(n),
+            tree_id: hash(100 + n),
+            parent_commit_ids: parents.iter().map(|p| hash(*p)).collect(),
+            author: signature.clone(),
+            committer: signature,
+            message: format!("commit {}\n", n),
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        // 1 -> 2 -> 3 -> 4, the directory added in 2, left alone in 3 and changed in 4
+        let commits: HashMap<Hash, Commit> = [
+            commit(1, &[]),
+            commit(2, &[1]),
+            commit(3, &[2]),
+            commit(4, &[3]),
+        ]
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+        let dirs = HashMap::from([
+            (hash(2), hash(50)),
+            (hash(3), hash(50)),
+            (hash(4), hash(51)),
+        ]);
+        let split = Split::new(&commits, &dirs, &[hash(4)]);
+
+        assert_eq!(split.mapped[&hash(1)], None);
+        assert_eq!(split.commits.len(), 2);
+        let (first, second) = (&split.commits[0], &split.commits[1]);
+        assert_eq!(first.tree_id, hash(50));
+        assert!(first.parent_commit_ids.is_empty());
+        assert_eq!(first.message, "commit 2\n");
+        assert_eq!(split.mapped[&hash(3)], Some(first.id));
+        assert_eq!(second.parent_commit_ids, vec![first.id]);
+        assert_eq!(split.origin[&second.id], hash(4));
+
+        // the same history gives the same commits
+        let again = Split::new(&commits, &dirs, &[hash(4)]);
+        assert_eq!(again.mapped[&hash(4)], Some(second.id));
+        assert_eq!(
+            second.id,
+            commit_with(&commits[&hash(4)], hash(51), vec![first.id]).id
+        );
+    }
+
+    #[test]
+    fn test_split_extend() {
+        let commits: HashMap<Hash, Commit> = [
+            commit(1, &[]),
+            commit(2, &[1]),
+            commit(3, &[2]),
+            commit(4, &[3]),
+        ]
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+        let dirs = HashMap::from([
+            (hash(1), hash(50)),
+            (hash(3), hash(51)),
+            (hash(4), hash(51)),
+        ]);
+        let whole = Split::new(&commits, &dirs, &[hash(4)]);
+
+        // resuming from the mappings of 1 and 2 only walks 3 and 4
+        let first = Split::new(&commits, &dirs, &[hash(2)]);
+        let mut split = Split::default();
+        for id in &first.walked {
+            let mapped = first.mapped[id];
+            split.record(
+                *id,
+                mapped.map(|m| (m, first.tree(&m).unwrap())),
+                mapped.is_some_and(|m| first.origin.get(&m) == Some(id)),
+            );
+        }
+        let added: HashMap<Hash, Commit> = [commit(3, &[2]), commit(4, &[3])]
+            .into_iter()
+            .map(|c| (c.id, c))
+            .collect();
+        split.extend(&added, &dirs, &[hash(4)]);
+
+        assert_eq!(split.walked, vec![hash(3), hash(4)]);
+        assert_eq!(split.commits.len(), 1);
+        assert_eq!(split.mapped[&hash(4)], whole.mapped[&hash(4)]);
+        assert_eq!(split.origin[&split.commits[0].id], hash(3));
+    }
+
+    #[test]
+    fn test_set_tree_item() {
+        let item = |mode, name: &str| TreeItem::new(mode, Hash::default(), name.to_owned());
+        let mut items = vec![
+            item(TreeItemMode::Blob, "a.txt"),
+            item(TreeItemMode::Blob, "b"),
+        ];
+        set_tree_item(&mut items, item(TreeItemMode::Tree, "a"));
+        set_tree_item(&mut items, item(TreeItemMode::Tree, "a-b"));
+        set_tree_item(&mut items, item(TreeItemMode::Tree, "b"));
+        let names: Vec<(&str, bool)> = items
+            .iter()
+            .map(|i| (i.name.as_str(), i.mode == TreeItemMode::Tree))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("a-b", true), ("a.txt", false), ("a", true), ("b", true)]
+        );
+    }
+}
@@ -203,10 +203,11 @@ pub fn write_offset_encoding(number: u64) -> Vec<u8> {
     num.push((number & 0x7f) as u8);
     number >>= 7;
 
-    // Encode the remaining bits in subsequent bytes
+    // Encode the remaining bits in subsequent bytes, less the 1 the decoding adds back
     while number > 0 {
+        number -= 1;
         // Set the most significant bit to indicate continuation
-        num.push(((number & 0x7f) - 1) as u8 | 0x80);
+        num.push((number & 0x7f) as u8 | 0x80);
         number >>= 7;
     }
 
@@ -406,4 +407,15 @@ mod test {
         get_env_number("GIT_INTERNAL_DECODE_STORAGE_BATCH_SIZE", &mut batch_size);
         assert_eq!(batch_size, 10000);
     }
+
+    #[test]
+    fn test_offset_encoding() {
+        for offset in [0, 1, 127, 128, 300, 16383, 16384, 16512, 1 << 32] {
+            let bytes = write_offset_encoding(offset);
+            let mut consume = 0;
+            let decoded = read_offset_encoding(&mut std::io::Cursor::new(&bytes), &mut consume);
+            assert_eq!(decoded.unwrap(), offset);
+            assert_eq!(consume, bytes.len());
+        }
+    }
 }
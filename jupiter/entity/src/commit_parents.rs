@@ -0,0 +1,148 @@
+//!
+//! The parents of a commit row, kept in a text column as space separated ids so that the same
+//! column works in every database, and checked when the row is loaded.
+//!
+use std::fmt::Display;
+
+use sea_orm::sea_query::{ArrayType, ColumnType, Nullable, ValueType, ValueTypeErr};
+use sea_orm::{ColIdx, DbErr, QueryResult, TryGetError, TryGetable, Value};
+use serde::{Deserialize, Serialize};
+
+/// The parent ids of a commit in order, each one a lowercase hex SHA1.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct CommitParents(Vec<String>);
+
+impl CommitParents {
+    /// The parents `ids`, failing on the first one which isn't a SHA1.
+    pub fn new<S: Into<String>>(ids: impl IntoIterator<Item = S>) -> Result<Self, String> {
+        let ids = ids
+            .into_iter()
+            .map(|id| {
+                let id: String = id.into();
+                let valid = id.len() == 40
+                    && id
+                        .bytes()
+                        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+                if valid {
+                    Ok(id)
+                } else {
+                    Err(id)
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|id| format!("invalid parent id: {:?}", id))?;
+        Ok(CommitParents(ids))
+    }
+
+    /// The parents in the text of the column.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        CommitParents::new(text.split_whitespace())
+    }
+
+    pub fn ids(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for CommitParents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join(" "))
+    }
+}
+
+impl TryFrom<Vec<String>> for CommitParents {
+    type Error = String;
+
+    fn try_from(value: Vec<String>) -> Result<Self, Self::Error> {
+        CommitParents::new(value)
+    }
+}
+
+impl From<CommitParents> for Vec<String> {
+    fn from(value: CommitParents) -> Self {
+        value.0
+    }
+}
+
+impl From<CommitParents> for Value {
+    fn from(value: CommitParents) -> Self {
+        Value::String(Some(Box::new(value.to_string())))
+    }
+}
+
+impl TryGetable for CommitParents {
+    fn try_get_by<I: ColIdx>(res: &QueryResult, index: I) -> Result<Self, TryGetError> {
+        let text = String::try_get_by(res, index)?;
+        CommitParents::parse(&text).map_err(|e| TryGetError::DbErr(DbErr::Type(e)))
+    }
+}
+
+impl ValueType for CommitParents {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        match v {
+            Value::String(Some(text)) => CommitParents::parse(&text).map_err(|_| ValueTypeErr),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "CommitParents".to_owned()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Text
+    }
+}
+
+impl Nullable for CommitParents {
+    fn null() -> Value {
+        Value::String(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::sea_query::ValueType;
+    use sea_orm::Value;
+
+    use super::CommitParents;
+
+    const FIRST: &str = "8ab686eafeb1f44702738c8b0f24f2567c36da6d";
+    const SECOND: &str = "341e54913a3a43069f2927cc0f703e5a9f730df1";
+
+    #[test]
+    fn test_commit_parents() {
+        let parents = CommitParents::new([FIRST, SECOND]).unwrap();
+        assert_eq!(parents.ids(), [FIRST, SECOND]);
+        assert_eq!(parents.to_string(), format!("{} {}", FIRST, SECOND));
+        assert_eq!(
+            CommitParents::parse(&parents.to_string()),
+            Ok(parents.clone())
+        );
+        assert_eq!(
+            <CommitParents as ValueType>::try_from(Value::from(parents.clone())).unwrap(),
+            parents
+        );
+        assert!(CommitParents::parse("").unwrap().is_empty());
+
+        assert_eq!(
+            CommitParents::new([FIRST, "not an id"]),
+            Err("invalid parent id: \"not an id\"".to_owned())
+        );
+        assert!(CommitParents::new([FIRST.to_uppercase()]).is_err());
+        assert!(CommitParents::parse(&FIRST[1..]).is_err());
+    }
+}
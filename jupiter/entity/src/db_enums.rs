@@ -24,12 +24,32 @@ impl ToString for StorageType {
 #[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
 #[sea_orm(rs_type = "String", db_type = "String(Some(1))")]
 pub enum MergeStatus {
+    #[sea_orm(string_value = "draft")]
+    Draft,
     #[sea_orm(string_value = "open")]
     Open,
+    #[sea_orm(string_value = "approved")]
+    Approved,
     #[sea_orm(string_value = "merged")]
     Merged,
     #[sea_orm(string_value = "closed")]
     Closed,
+    #[sea_orm(string_value = "reopened")]
+    Reopened,
+}
+
+impl MergeStatus {
+    /// The states of a merge request which is neither merged nor closed.
+    pub const ACTIVE: [MergeStatus; 4] = [
+        MergeStatus::Draft,
+        MergeStatus::Open,
+        MergeStatus::Approved,
+        MergeStatus::Reopened,
+    ];
+
+    pub fn is_active(&self) -> bool {
+        MergeStatus::ACTIVE.contains(self)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
@@ -40,3 +60,12 @@ pub enum RefType {
     #[sea_orm(string_value = "tag")]
     Tag,
 }
+
+#[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(1))")]
+pub enum ReviewState {
+    #[sea_orm(string_value = "approved")]
+    Approved,
+    #[sea_orm(string_value = "changes_requested")]
+    ChangesRequested,
+}
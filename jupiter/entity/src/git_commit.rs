@@ -2,6 +2,8 @@
 
 use sea_orm::entity::prelude::*;
 
+use crate::commit_parents::CommitParents;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "git_commit")]
 pub struct Model {
@@ -10,7 +12,8 @@ pub struct Model {
     pub repo_id: i64,
     pub commit_id: String,
     pub tree: String,
-    pub parents_id: Vec<String>,
+    #[sea_orm(column_type = "Text")]
+    pub parents_id: CommitParents,
     #[sea_orm(column_type = "Text", nullable)]
     pub author: Option<String>,
     #[sea_orm(column_type = "Text", nullable)]
@@ -2,6 +2,7 @@
 
 pub mod prelude;
 
+pub mod commit_parents;
 pub mod db_enums;
 pub mod git_blob;
 pub mod git_commit;
@@ -13,11 +14,71 @@ pub mod git_tag;
 pub mod git_tree;
 pub mod lfs_locks;
 pub mod lfs_objects;
+pub mod mega_access_token;
+pub mod mega_archive;
+pub mod mega_assignee;
+pub mod mega_autolink;
 pub mod mega_blob;
+pub mod mega_board;
+pub mod mega_board_card;
+pub mod mega_board_column;
+pub mod mega_check_run;
+pub mod mega_ci_job;
+pub mod mega_ci_log;
+pub mod mega_ci_log_chunk;
 pub mod mega_commit;
+pub mod mega_commit_stat;
+pub mod mega_diffstat;
+pub mod mega_erasure;
+pub mod mega_event;
+pub mod mega_feature_flag;
+pub mod mega_import;
+pub mod mega_import_job;
+pub mod mega_import_job_repo;
 pub mod mega_issue;
+pub mod mega_issue_ref;
+pub mod mega_label;
+pub mod mega_label_link;
+pub mod mega_language_stat;
+pub mod mega_mailmap;
+pub mod mega_merge_queue;
+pub mod mega_milestone;
+pub mod mega_mirror;
 pub mod mega_mr;
+pub mod mega_mr_comment;
+pub mod mega_mr_review;
+pub mod mega_mr_thread;
+pub mod mega_notification;
+pub mod mega_notification_pref;
+pub mod mega_object_delta;
+pub mod mega_oidc_login;
+pub mod mega_org;
+pub mod mega_org_member;
+pub mod mega_pack_bitmap;
+pub mod mega_path_grant;
+pub mod mega_path_mapping;
+pub mod mega_path_redirect;
+pub mod mega_push_profile;
+pub mod mega_quarantine_object;
+pub mod mega_reaction;
+pub mod mega_ref_audit;
+pub mod mega_ref_hook;
+pub mod mega_ref_hook_retry;
+pub mod mega_ref_trigger;
+pub mod mega_reference;
+pub mod mega_release;
+pub mod mega_release_asset;
+pub mod mega_required_check;
+pub mod mega_signing_key;
 pub mod mega_snapshot;
+pub mod mega_snapshot_export;
+pub mod mega_snippet;
+pub mod mega_snippet_file;
+pub mod mega_ssh_key;
+pub mod mega_subscription;
 pub mod mega_tag;
 pub mod mega_tree;
+pub mod mega_user;
+pub mod mega_user_identity;
+pub mod mega_webhook;
 pub mod raw_objects;
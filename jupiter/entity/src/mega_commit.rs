@@ -2,6 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
+use crate::commit_parents::CommitParents;
 use crate::db_enums::MergeStatus;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -12,7 +13,8 @@ pub struct Model {
     #[sea_orm(unique)]
     pub commit_id: String,
     pub tree: String,
-    pub parents_id: Vec<String>,
+    #[sea_orm(column_type = "Text")]
+    pub parents_id: CommitParents,
     #[sea_orm(column_type = "Text", nullable)]
     pub author: Option<String>,
     #[sea_orm(column_type = "Text", nullable)]
@@ -8,10 +8,15 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: i64,
     pub number: i64,
+    #[sea_orm(column_type = "Text")]
+    pub repo_path: String,
     pub title: String,
+    #[sea_orm(column_type = "Text")]
+    pub body: String,
     pub sender_name: String,
     pub sender_id: i64,
     pub state: String,
+    pub milestone_id: Option<i64>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
     pub closed_at: Option<DateTime>,
@@ -2,6 +2,8 @@
 
 use sea_orm::entity::prelude::*;
 
+use crate::db_enums::MergeStatus;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "mega_mr")]
 pub struct Model {
@@ -9,8 +11,18 @@ pub struct Model {
     pub id: i64,
     pub mr_link: String,
     pub mr_msg: String,
+    #[sea_orm(column_type = "Text")]
+    pub repo_path: String,
+    #[sea_orm(column_type = "Text")]
+    pub source_ref: String,
+    #[sea_orm(column_type = "Text")]
+    pub target_ref: String,
+    pub merge_commit_id: Option<String>,
     pub merge_date: Option<DateTime>,
-    pub status: String,
+    pub status: MergeStatus,
+    pub milestone_id: Option<i64>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub author: Option<String>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
@@ -10,11 +10,71 @@ pub use super::git_tag::Entity as GitTag;
 pub use super::git_tree::Entity as GitTree;
 pub use super::lfs_locks::Entity as LfsLocks;
 pub use super::lfs_objects::Entity as LfsObjects;
+pub use super::mega_access_token::Entity as MegaAccessToken;
+pub use super::mega_archive::Entity as MegaArchive;
+pub use super::mega_assignee::Entity as MegaAssignee;
+pub use super::mega_autolink::Entity as MegaAutolink;
 pub use super::mega_blob::Entity as MegaBlob;
+pub use super::mega_board::Entity as MegaBoard;
+pub use super::mega_board_card::Entity as MegaBoardCard;
+pub use super::mega_board_column::Entity as MegaBoardColumn;
+pub use super::mega_check_run::Entity as MegaCheckRun;
+pub use super::mega_ci_job::Entity as MegaCiJob;
+pub use super::mega_ci_log::Entity as MegaCiLog;
+pub use super::mega_ci_log_chunk::Entity as MegaCiLogChunk;
 pub use super::mega_commit::Entity as MegaCommit;
+pub use super::mega_commit_stat::Entity as MegaCommitStat;
+pub use super::mega_diffstat::Entity as MegaDiffstat;
+pub use super::mega_erasure::Entity as MegaErasure;
+pub use super::mega_event::Entity as MegaEvent;
+pub use super::mega_feature_flag::Entity as MegaFeatureFlag;
+pub use super::mega_import::Entity as MegaImport;
+pub use super::mega_import_job::Entity as MegaImportJob;
+pub use super::mega_import_job_repo::Entity as MegaImportJobRepo;
 pub use super::mega_issue::Entity as MegaIssue;
+pub use super::mega_issue_ref::Entity as MegaIssueRef;
+pub use super::mega_label::Entity as MegaLabel;
+pub use super::mega_label_link::Entity as MegaLabelLink;
+pub use super::mega_language_stat::Entity as MegaLanguageStat;
+pub use super::mega_mailmap::Entity as MegaMailmap;
+pub use super::mega_merge_queue::Entity as MegaMergeQueue;
+pub use super::mega_milestone::Entity as MegaMilestone;
+pub use super::mega_mirror::Entity as MegaMirror;
 pub use super::mega_mr::Entity as MegaMr;
+pub use super::mega_mr_comment::Entity as MegaMrComment;
+pub use super::mega_mr_review::Entity as MegaMrReview;
+pub use super::mega_mr_thread::Entity as MegaMrThread;
+pub use super::mega_notification::Entity as MegaNotification;
+pub use super::mega_notification_pref::Entity as MegaNotificationPref;
+pub use super::mega_object_delta::Entity as MegaObjectDelta;
+pub use super::mega_oidc_login::Entity as MegaOidcLogin;
+pub use super::mega_org::Entity as MegaOrg;
+pub use super::mega_org_member::Entity as MegaOrgMember;
+pub use super::mega_pack_bitmap::Entity as MegaPackBitmap;
+pub use super::mega_path_grant::Entity as MegaPathGrant;
+pub use super::mega_path_mapping::Entity as MegaPathMapping;
+pub use super::mega_path_redirect::Entity as MegaPathRedirect;
+pub use super::mega_push_profile::Entity as MegaPushProfile;
+pub use super::mega_quarantine_object::Entity as MegaQuarantineObject;
+pub use super::mega_reaction::Entity as MegaReaction;
+pub use super::mega_ref_audit::Entity as MegaRefAudit;
+pub use super::mega_ref_hook::Entity as MegaRefHook;
+pub use super::mega_ref_hook_retry::Entity as MegaRefHookRetry;
+pub use super::mega_ref_trigger::Entity as MegaRefTrigger;
+pub use super::mega_reference::Entity as MegaReference;
+pub use super::mega_release::Entity as MegaRelease;
+pub use super::mega_release_asset::Entity as MegaReleaseAsset;
+pub use super::mega_required_check::Entity as MegaRequiredCheck;
+pub use super::mega_signing_key::Entity as MegaSigningKey;
 pub use super::mega_snapshot::Entity as MegaSnapshot;
+pub use super::mega_snapshot_export::Entity as MegaSnapshotExport;
+pub use super::mega_snippet::Entity as MegaSnippet;
+pub use super::mega_snippet_file::Entity as MegaSnippetFile;
+pub use super::mega_ssh_key::Entity as MegaSshKey;
+pub use super::mega_subscription::Entity as MegaSubscription;
 pub use super::mega_tag::Entity as MegaTag;
 pub use super::mega_tree::Entity as MegaTree;
+pub use super::mega_user::Entity as MegaUser;
+pub use super::mega_user_identity::Entity as MegaUserIdentity;
+pub use super::mega_webhook::Entity as MegaWebhook;
 pub use super::raw_objects::Entity as RawObjects;
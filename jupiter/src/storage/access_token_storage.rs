@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_access_token;
+
+/// Access tokens issued to users, stored in the `mega_access_token` table by the hash of the
+/// token.
+#[derive(Clone)]
+pub struct AccessTokenStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl AccessTokenStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        AccessTokenStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_tokens(
+        &self,
+        username: &str,
+    ) -> Result<Vec<mega_access_token::Model>, MegaError> {
+        Ok(mega_access_token::Entity::find()
+            .filter(mega_access_token::Column::Username.eq(username))
+            .order_by_asc(mega_access_token::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<mega_access_token::Model>, MegaError> {
+        Ok(mega_access_token::Entity::find()
+            .filter(mega_access_token::Column::TokenHash.eq(token_hash))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_token(&self, token: mega_access_token::Model) -> Result<(), MegaError> {
+        mega_access_token::Entity::insert(token.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Revoke a token of `username`, returns false if the user has no token with this id.
+    pub async fn delete_token(&self, username: &str, id: i64) -> Result<bool, MegaError> {
+        let res = mega_access_token::Entity::delete_many()
+            .filter(mega_access_token::Column::Id.eq(id))
+            .filter(mega_access_token::Column::Username.eq(username))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    pub async fn touch_last_used(&self, id: i64) -> Result<(), MegaError> {
+        mega_access_token::Entity::update_many()
+            .col_expr(
+                mega_access_token::Column::LastUsedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_access_token::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_archive;
+
+/// Archived and deleted monorepo paths, in `mega_archive`. An archived path is read-only, a
+/// deleted one keeps a tombstone of its refs until its objects are collected.
+#[derive(Clone)]
+pub struct ArchiveStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ArchiveStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ArchiveStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_archives(&self) -> Result<Vec<mega_archive::Model>, MegaError> {
+        Ok(mega_archive::Entity::find()
+            .order_by_asc(mega_archive::Column::Path)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_archive(&self, path: &str) -> Result<Option<mega_archive::Model>, MegaError> {
+        Ok(mega_archive::Entity::find()
+            .filter(mega_archive::Column::Path.eq(path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The archive of `path` or of the closest of its parents, `path` being normalized.
+    pub async fn find_archive(&self, path: &str) -> Result<Option<mega_archive::Model>, MegaError> {
+        let ancestors: Vec<&str> = path
+            .match_indices('/')
+            .skip(1)
+            .map(|(i, _)| &path[..i])
+            .chain([path])
+            .filter(|p| p.len() > 1)
+            .collect();
+        if ancestors.is_empty() {
+            return Ok(None);
+        }
+        let archives = mega_archive::Entity::find()
+            .filter(mega_archive::Column::Path.is_in(ancestors))
+            .all(self.get_connection())
+            .await?;
+        Ok(archives.into_iter().max_by_key(|a| a.path.len()))
+    }
+
+    /// Save the archive of `archive.path`, replacing the one recorded before.
+    pub async fn save_archive(&self, archive: mega_archive::Model) -> Result<(), MegaError> {
+        mega_archive::Entity::insert(archive.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_archive::Column::Path)
+                    .update_columns([
+                        mega_archive::Column::State,
+                        mega_archive::Column::Reason,
+                        mega_archive::Column::Actor,
+                        mega_archive::Column::Refs,
+                        mega_archive::Column::Bundles,
+                        mega_archive::Column::GcAfter,
+                        mega_archive::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the archive of `path`, `false` when there was none.
+    pub async fn delete_archive(&self, path: &str) -> Result<bool, MegaError> {
+        let res = mega_archive::Entity::delete_many()
+            .filter(mega_archive::Column::Path.eq(path))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, Query, SelectStatement},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::mega_assignee;
+
+/// Ids of the items of `item_type` assigned to `username`, for filtering list queries.
+pub fn assigned_items(item_type: &str, username: &str) -> SelectStatement {
+    Query::select()
+        .column(mega_assignee::Column::ItemId)
+        .from(mega_assignee::Entity)
+        .and_where(Expr::col(mega_assignee::Column::ItemType).eq(item_type))
+        .and_where(Expr::col(mega_assignee::Column::Username).eq(username))
+        .to_owned()
+}
+
+/// Users assigned to issues and merge requests, stored in the `mega_assignee` table.
+#[derive(Clone)]
+pub struct AssigneeStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl AssigneeStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        AssigneeStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// The assignees of each of `item_ids`, as `(item_id, username)` pairs ordered by name.
+    pub async fn assignees_of(
+        &self,
+        item_type: &str,
+        item_ids: &[i64],
+    ) -> Result<Vec<(i64, String)>, MegaError> {
+        Ok(mega_assignee::Entity::find()
+            .filter(mega_assignee::Column::ItemType.eq(item_type))
+            .filter(mega_assignee::Column::ItemId.is_in(item_ids.iter().copied()))
+            .order_by_asc(mega_assignee::Column::Username)
+            .all(self.get_connection())
+            .await?
+            .into_iter()
+            .map(|a| (a.item_id, a.username))
+            .collect())
+    }
+
+    /// Replace the assignees of an item with `usernames`.
+    pub async fn set_assignees(
+        &self,
+        item_type: &str,
+        item_id: i64,
+        usernames: &[String],
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_assignee::Entity::delete_many()
+            .filter(mega_assignee::Column::ItemType.eq(item_type))
+            .filter(mega_assignee::Column::ItemId.eq(item_id))
+            .exec(&txn)
+            .await?;
+        if !usernames.is_empty() {
+            let assignees = usernames.iter().map(|username| {
+                mega_assignee::Model {
+                    id: generate_id(),
+                    item_type: item_type.to_owned(),
+                    item_id,
+                    username: username.clone(),
+                }
+                .into_active_model()
+            });
+            mega_assignee::Entity::insert_many(assignees)
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_autolink;
+
+/// Autolink rules of organizations stored in the `mega_autolink` table.
+#[derive(Clone)]
+pub struct AutolinkStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl AutolinkStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        AutolinkStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Rules of every organization, oldest first.
+    pub async fn list_all(&self) -> Result<Vec<mega_autolink::Model>, MegaError> {
+        Ok(mega_autolink::Entity::find()
+            .order_by_asc(mega_autolink::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn list_rules(&self, org_id: i64) -> Result<Vec<mega_autolink::Model>, MegaError> {
+        Ok(mega_autolink::Entity::find()
+            .filter(mega_autolink::Column::OrgId.eq(org_id))
+            .order_by_asc(mega_autolink::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_rule(&self, rule: mega_autolink::Model) -> Result<(), MegaError> {
+        mega_autolink::Entity::insert(rule.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove a rule of an organization, returns false if it has none with this id.
+    pub async fn delete_rule(&self, org_id: i64, id: i64) -> Result<bool, MegaError> {
+        let res = mega_autolink::Entity::delete_many()
+            .filter(mega_autolink::Column::OrgId.eq(org_id))
+            .filter(mega_autolink::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
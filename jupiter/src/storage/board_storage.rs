@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_board, mega_board_card, mega_board_column};
+
+/// Project boards of a repository in `mega_board`, their columns in `mega_board_column` and the
+/// issues and merge requests on them in `mega_board_card`. Columns and the cards of a column are
+/// ordered by `position`.
+#[derive(Clone)]
+pub struct BoardStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl BoardStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        BoardStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_boards(&self, repo_path: &str) -> Result<Vec<mega_board::Model>, MegaError> {
+        Ok(mega_board::Entity::find()
+            .filter(mega_board::Column::RepoPath.eq(repo_path))
+            .order_by_asc(mega_board::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_board(&self, id: i64) -> Result<Option<mega_board::Model>, MegaError> {
+        Ok(mega_board::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_by_name(
+        &self,
+        repo_path: &str,
+        name: &str,
+    ) -> Result<Option<mega_board::Model>, MegaError> {
+        Ok(mega_board::Entity::find()
+            .filter(mega_board::Column::RepoPath.eq(repo_path))
+            .filter(mega_board::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Store a new board with its first columns.
+    pub async fn save_board(
+        &self,
+        board: mega_board::Model,
+        columns: Vec<mega_board_column::Model>,
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_board::Entity::insert(board.into_active_model())
+            .exec(&txn)
+            .await?;
+        if !columns.is_empty() {
+            mega_board_column::Entity::insert_many(
+                columns.into_iter().map(IntoActiveModel::into_active_model),
+            )
+            .exec(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored board with the same id.
+    pub async fn update_board(
+        &self,
+        board: mega_board::Model,
+    ) -> Result<mega_board::Model, MegaError> {
+        Ok(board
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a board with its columns and cards, the issues and merge requests are kept.
+    /// Returns false if there is no such board.
+    pub async fn delete_board(&self, id: i64) -> Result<bool, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_board_card::Entity::delete_many()
+            .filter(mega_board_card::Column::BoardId.eq(id))
+            .exec(&txn)
+            .await?;
+        mega_board_column::Entity::delete_many()
+            .filter(mega_board_column::Column::BoardId.eq(id))
+            .exec(&txn)
+            .await?;
+        let res = mega_board::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    /// The columns of a board, left to right.
+    pub async fn list_columns(
+        &self,
+        board_id: i64,
+    ) -> Result<Vec<mega_board_column::Model>, MegaError> {
+        Ok(mega_board_column::Entity::find()
+            .filter(mega_board_column::Column::BoardId.eq(board_id))
+            .order_by_asc(mega_board_column::Column::Position)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_column(&self, column: mega_board_column::Model) -> Result<(), MegaError> {
+        mega_board_column::Entity::insert(column.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_column(
+        &self,
+        column: mega_board_column::Model,
+    ) -> Result<mega_board_column::Model, MegaError> {
+        Ok(column
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Give the columns `ids` of a board the positions of their order.
+    pub async fn order_columns(&self, ids: &[i64]) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        for (position, id) in ids.iter().enumerate() {
+            mega_board_column::Entity::update_many()
+                .col_expr(
+                    mega_board_column::Column::Position,
+                    Expr::value(position as i32),
+                )
+                .filter(mega_board_column::Column::Id.eq(*id))
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    pub async fn delete_column(&self, id: i64) -> Result<(), MegaError> {
+        mega_board_column::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn count_cards(&self, column_id: i64) -> Result<u64, MegaError> {
+        Ok(mega_board_card::Entity::find()
+            .filter(mega_board_card::Column::ColumnId.eq(column_id))
+            .count(self.get_connection())
+            .await?)
+    }
+
+    /// The cards of a board, by column and position.
+    pub async fn list_cards(
+        &self,
+        board_id: i64,
+    ) -> Result<Vec<mega_board_card::Model>, MegaError> {
+        Ok(mega_board_card::Entity::find()
+            .filter(mega_board_card::Column::BoardId.eq(board_id))
+            .order_by_asc(mega_board_card::Column::ColumnId)
+            .order_by_asc(mega_board_card::Column::Position)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_card(&self, card: mega_board_card::Model) -> Result<(), MegaError> {
+        mega_board_card::Entity::insert(card.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Put the cards `ids` in `column_id`, at the positions of their order.
+    pub async fn order_cards(&self, column_id: i64, ids: &[i64]) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        let now = chrono::Utc::now().naive_utc();
+        for (position, id) in ids.iter().enumerate() {
+            mega_board_card::Entity::update_many()
+                .col_expr(mega_board_card::Column::ColumnId, Expr::value(column_id))
+                .col_expr(
+                    mega_board_card::Column::Position,
+                    Expr::value(position as i32),
+                )
+                .col_expr(mega_board_card::Column::UpdatedAt, Expr::value(now))
+                .filter(mega_board_card::Column::Id.eq(*id))
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    pub async fn delete_card(&self, id: i64) -> Result<(), MegaError> {
+        mega_board_card::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::{mega_check_run, mega_required_check};
+
+/// Statuses CI systems report for commits in `mega_check_run`, one per check and commit, and
+/// the checks a branch requires before merge requests into it can be merged in
+/// `mega_required_check`.
+#[derive(Clone)]
+pub struct CheckRunStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl CheckRunStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        CheckRunStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_runs(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+    ) -> Result<Vec<mega_check_run::Model>, MegaError> {
+        Ok(mega_check_run::Entity::find()
+            .filter(mega_check_run::Column::RepoPath.eq(repo_path))
+            .filter(mega_check_run::Column::CommitId.eq(commit_id))
+            .order_by_asc(mega_check_run::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_run(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        name: &str,
+    ) -> Result<Option<mega_check_run::Model>, MegaError> {
+        Ok(mega_check_run::Entity::find()
+            .filter(mega_check_run::Column::RepoPath.eq(repo_path))
+            .filter(mega_check_run::Column::CommitId.eq(commit_id))
+            .filter(mega_check_run::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Save `run`, replacing the status reported before for the same check and commit, and
+    /// return the stored run.
+    pub async fn save_run(
+        &self,
+        run: mega_check_run::Model,
+    ) -> Result<mega_check_run::Model, MegaError> {
+        let (repo_path, commit_id, name) = (
+            run.repo_path.clone(),
+            run.commit_id.clone(),
+            run.name.clone(),
+        );
+        mega_check_run::Entity::insert(run.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_check_run::Column::RepoPath,
+                    mega_check_run::Column::CommitId,
+                    mega_check_run::Column::Name,
+                ])
+                .update_columns([
+                    mega_check_run::Column::Status,
+                    mega_check_run::Column::Description,
+                    mega_check_run::Column::TargetUrl,
+                    mega_check_run::Column::Reporter,
+                    mega_check_run::Column::UpdatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        self.get_run(&repo_path, &commit_id, &name)
+            .await?
+            .ok_or_else(|| MegaError::with_message("check run disappeared after insert"))
+    }
+
+    /// Names of the checks `ref_name` requires.
+    pub async fn required_checks(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+    ) -> Result<Vec<String>, MegaError> {
+        Ok(mega_required_check::Entity::find()
+            .filter(mega_required_check::Column::RepoPath.eq(repo_path))
+            .filter(mega_required_check::Column::RefName.eq(ref_name))
+            .order_by_asc(mega_required_check::Column::Name)
+            .all(self.get_connection())
+            .await?
+            .into_iter()
+            .map(|check| check.name)
+            .collect())
+    }
+
+    /// Replace the checks `ref_name` requires with `names`.
+    pub async fn set_required_checks(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+        names: &[String],
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_required_check::Entity::delete_many()
+            .filter(mega_required_check::Column::RepoPath.eq(repo_path))
+            .filter(mega_required_check::Column::RefName.eq(ref_name))
+            .exec(&txn)
+            .await?;
+        if !names.is_empty() {
+            let now = chrono::Utc::now().naive_utc();
+            let models = names.iter().map(|name| {
+                mega_required_check::Model {
+                    id: generate_id(),
+                    repo_path: repo_path.to_owned(),
+                    ref_name: ref_name.to_owned(),
+                    name: name.clone(),
+                    created_at: now,
+                }
+                .into_active_model()
+            });
+            mega_required_check::Entity::insert_many(models)
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+}
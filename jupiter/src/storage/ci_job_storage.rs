@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect, TryInsertResult,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_ci_job;
+
+/// The statuses of a job of a pipeline.
+pub const CI_JOB_QUEUED: &str = "queued";
+pub const CI_JOB_RUNNING: &str = "running";
+pub const CI_JOB_SUCCESS: &str = "success";
+pub const CI_JOB_FAILURE: &str = "failure";
+/// The job couldn't be run, as opposed to running and failing
+pub const CI_JOB_ERROR: &str = "error";
+
+/// The queue of the jobs of the pipelines the server runs for pushed commits, in `mega_ci_job`.
+/// A commit runs each job of its pipeline once, whatever the refs it is pushed to.
+#[derive(Clone)]
+pub struct CiJobStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl CiJobStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        CiJobStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Queue `jobs`, leaving out the ones their commit already has. Returns the jobs queued.
+    pub async fn enqueue(
+        &self,
+        jobs: Vec<mega_ci_job::Model>,
+    ) -> Result<Vec<mega_ci_job::Model>, MegaError> {
+        let mut queued = Vec::new();
+        for job in jobs {
+            let res = mega_ci_job::Entity::insert(job.clone().into_active_model())
+                .on_conflict(
+                    OnConflict::columns([
+                        mega_ci_job::Column::RepoPath,
+                        mega_ci_job::Column::CommitId,
+                        mega_ci_job::Column::Name,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .do_nothing()
+                .exec(self.get_connection())
+                .await?;
+            if matches!(res, TryInsertResult::Inserted(_)) {
+                queued.push(job);
+            }
+        }
+        Ok(queued)
+    }
+
+    pub async fn list_jobs(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+    ) -> Result<Vec<mega_ci_job::Model>, MegaError> {
+        Ok(mega_ci_job::Entity::find()
+            .filter(mega_ci_job::Column::RepoPath.eq(repo_path))
+            .filter(mega_ci_job::Column::CommitId.eq(commit_id))
+            .order_by_asc(mega_ci_job::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Jobs whose time has come, oldest first: queued ones, and running ones whose server
+    /// instance didn't finish them in time, most likely since it stopped.
+    pub async fn due_jobs(
+        &self,
+        now: NaiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<mega_ci_job::Model>, MegaError> {
+        Ok(mega_ci_job::Entity::find()
+            .filter(mega_ci_job::Column::Status.is_in([CI_JOB_QUEUED, CI_JOB_RUNNING]))
+            .filter(mega_ci_job::Column::NextAttemptAt.lte(now))
+            .order_by_asc(mega_ci_job::Column::NextAttemptAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Count another attempt at `job` and hold it until `until`, so no other server instance
+    /// runs it meanwhile. Returns false when another instance already claimed it.
+    pub async fn claim_job(
+        &self,
+        job: &mega_ci_job::Model,
+        until: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        let res = mega_ci_job::Entity::update_many()
+            .col_expr(mega_ci_job::Column::Status, Expr::value(CI_JOB_RUNNING))
+            .col_expr(mega_ci_job::Column::Attempts, Expr::value(job.attempts + 1))
+            .col_expr(mega_ci_job::Column::NextAttemptAt, Expr::value(until))
+            .col_expr(
+                mega_ci_job::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_ci_job::Column::Id.eq(job.id))
+            .filter(mega_ci_job::Column::Attempts.eq(job.attempts))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Record the final `status` of the job `id`, with the exit code of its script when it ran.
+    pub async fn finish_job(
+        &self,
+        id: i64,
+        status: &str,
+        exit_code: Option<i32>,
+    ) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        mega_ci_job::Entity::update_many()
+            .col_expr(mega_ci_job::Column::Status, Expr::value(status))
+            .col_expr(mega_ci_job::Column::ExitCode, Expr::value(exit_code))
+            .col_expr(
+                mega_ci_job::Column::NextAttemptAt,
+                Expr::value(None::<NaiveDateTime>),
+            )
+            .col_expr(mega_ci_job::Column::UpdatedAt, Expr::value(now))
+            .col_expr(mega_ci_job::Column::FinishedAt, Expr::value(now))
+            .filter(mega_ci_job::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
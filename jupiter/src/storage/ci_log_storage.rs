@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_ci_log, mega_ci_log_chunk};
+
+/// Job logs posted by CI runners. The `mega_ci_log` table has one row per job of a commit, the
+/// log itself is split in chunks kept in the content store and listed in `mega_ci_log_chunk`.
+#[derive(Clone)]
+pub struct CiLogStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl CiLogStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        CiLogStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_logs(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+    ) -> Result<Vec<mega_ci_log::Model>, MegaError> {
+        Ok(mega_ci_log::Entity::find()
+            .filter(mega_ci_log::Column::RepoPath.eq(repo_path))
+            .filter(mega_ci_log::Column::CommitId.eq(commit_id))
+            .order_by_asc(mega_ci_log::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_log(
+        &self,
+        repo_path: &str,
+        commit_id: &str,
+        job_name: &str,
+    ) -> Result<Option<mega_ci_log::Model>, MegaError> {
+        Ok(mega_ci_log::Entity::find()
+            .filter(mega_ci_log::Column::RepoPath.eq(repo_path))
+            .filter(mega_ci_log::Column::CommitId.eq(commit_id))
+            .filter(mega_ci_log::Column::JobName.eq(job_name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Insert `log` unless its job already has one, and return the stored log.
+    pub async fn get_or_create_log(
+        &self,
+        log: mega_ci_log::Model,
+    ) -> Result<mega_ci_log::Model, MegaError> {
+        let (repo_path, commit_id, job_name) = (
+            log.repo_path.clone(),
+            log.commit_id.clone(),
+            log.job_name.clone(),
+        );
+        mega_ci_log::Entity::insert(log.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_ci_log::Column::RepoPath,
+                    mega_ci_log::Column::CommitId,
+                    mega_ci_log::Column::JobName,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        self.get_log(&repo_path, &commit_id, &job_name)
+            .await?
+            .ok_or_else(|| MegaError::with_message("ci log disappeared after insert"))
+    }
+
+    /// Chunks of a log holding bytes at or after `offset`, in order.
+    pub async fn chunks_from(
+        &self,
+        log_id: i64,
+        offset: i64,
+    ) -> Result<Vec<mega_ci_log_chunk::Model>, MegaError> {
+        Ok(mega_ci_log_chunk::Entity::find()
+            .filter(mega_ci_log_chunk::Column::LogId.eq(log_id))
+            .filter(
+                Expr::expr(
+                    Expr::col(mega_ci_log_chunk::Column::Offset)
+                        .add(Expr::col(mega_ci_log_chunk::Column::Size)),
+                )
+                .gt(offset),
+            )
+            .order_by_asc(mega_ci_log_chunk::Column::Seq)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Add `chunk` to the end of `log`. Returns false when the log grew or was finished since
+    /// `log` was read, the chunk is not added then.
+    pub async fn append_chunk(
+        &self,
+        log: &mega_ci_log::Model,
+        chunk: mega_ci_log_chunk::Model,
+    ) -> Result<bool, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        let res = mega_ci_log::Entity::update_many()
+            .col_expr(
+                mega_ci_log::Column::Size,
+                Expr::col(mega_ci_log::Column::Size).add(chunk.size),
+            )
+            .col_expr(
+                mega_ci_log::Column::Chunks,
+                Expr::col(mega_ci_log::Column::Chunks).add(1),
+            )
+            .col_expr(
+                mega_ci_log::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_ci_log::Column::Id.eq(log.id))
+            .filter(mega_ci_log::Column::Size.eq(log.size))
+            .filter(mega_ci_log::Column::FinishedAt.is_null())
+            .exec(&txn)
+            .await?;
+        if res.rows_affected == 0 {
+            return Ok(false);
+        }
+        mega_ci_log_chunk::Entity::insert(chunk.into_active_model())
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    /// Mark a log as complete with the final `status` of its job, returns false if it already
+    /// was.
+    pub async fn finish_log(&self, id: i64, status: &str) -> Result<bool, MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let res = mega_ci_log::Entity::update_many()
+            .col_expr(mega_ci_log::Column::Status, Expr::value(status))
+            .col_expr(mega_ci_log::Column::UpdatedAt, Expr::value(now))
+            .col_expr(mega_ci_log::Column::FinishedAt, Expr::value(now))
+            .filter(mega_ci_log::Column::Id.eq(id))
+            .filter(mega_ci_log::Column::FinishedAt.is_null())
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    /// Logs not written to since `before`.
+    pub async fn expired_logs(
+        &self,
+        before: NaiveDateTime,
+    ) -> Result<Vec<mega_ci_log::Model>, MegaError> {
+        Ok(mega_ci_log::Entity::find()
+            .filter(mega_ci_log::Column::UpdatedAt.lt(before))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a log and its chunks, returning the chunks so their content can be removed too.
+    pub async fn delete_log(&self, id: i64) -> Result<Vec<mega_ci_log_chunk::Model>, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        let chunks = mega_ci_log_chunk::Entity::find()
+            .filter(mega_ci_log_chunk::Column::LogId.eq(id))
+            .all(&txn)
+            .await?;
+        mega_ci_log_chunk::Entity::delete_many()
+            .filter(mega_ci_log_chunk::Column::LogId.eq(id))
+            .exec(&txn)
+            .await?;
+        mega_ci_log::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(chunks)
+    }
+}
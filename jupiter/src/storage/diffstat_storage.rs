@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_diffstat;
+
+/// Files changed, lines added and lines removed, kept in `mega_diffstat` so lists don't diff
+/// trees on every request.
+///
+/// The stat of a commit compares it with its first parent and never changes once stored. The
+/// stat of a merge request compares the head of its source branch with the merge base, there is
+/// one per merge request and it is replaced when the head moves.
+#[derive(Clone)]
+pub struct DiffstatStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl DiffstatStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        DiffstatStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// The stats stored for the commits `commit_ids` of a repository, by commit id.
+    pub async fn commit_stats(
+        &self,
+        repo_path: &str,
+        commit_ids: &[String],
+    ) -> Result<HashMap<String, mega_diffstat::Model>, MegaError> {
+        if commit_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        Ok(mega_diffstat::Entity::find()
+            .filter(mega_diffstat::Column::RepoPath.eq(repo_path))
+            .filter(mega_diffstat::Column::MrId.is_null())
+            .filter(mega_diffstat::Column::HeadId.is_in(commit_ids.iter().cloned()))
+            .all(self.get_connection())
+            .await?
+            .into_iter()
+            .map(|stat| (stat.head_id.clone(), stat))
+            .collect())
+    }
+
+    /// Store the stat of a commit, unless it already has one.
+    pub async fn save_commit_stat(&self, stat: mega_diffstat::Model) -> Result<(), MegaError> {
+        mega_diffstat::Entity::insert(stat.into_active_model())
+            .on_conflict(OnConflict::new().do_nothing().to_owned())
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// The stats of the merge requests `mr_ids`, by merge request id.
+    pub async fn mr_stats(
+        &self,
+        mr_ids: &[i64],
+    ) -> Result<HashMap<i64, mega_diffstat::Model>, MegaError> {
+        if mr_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        Ok(mega_diffstat::Entity::find()
+            .filter(mega_diffstat::Column::MrId.is_in(mr_ids.iter().copied()))
+            .all(self.get_connection())
+            .await?
+            .into_iter()
+            .filter_map(|stat| Some((stat.mr_id?, stat)))
+            .collect())
+    }
+
+    /// Replace the stat of the merge request `stat` is about.
+    pub async fn save_mr_stat(&self, stat: mega_diffstat::Model) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_diffstat::Entity::delete_many()
+            .filter(mega_diffstat::Column::MrId.eq(stat.mr_id))
+            .exec(&txn)
+            .await?;
+        mega_diffstat::Entity::insert(stat.into_active_model())
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::{
+    mega_access_token, mega_assignee, mega_erasure, mega_event, mega_issue, mega_mr_comment,
+    mega_mr_review, mega_mr_thread, mega_notification, mega_notification_pref, mega_org_member,
+    mega_path_redirect, mega_reaction, mega_ref_audit, mega_reference, mega_signing_key,
+    mega_snippet, mega_ssh_key, mega_subscription, mega_user, mega_user_identity,
+};
+
+use crate::storage::reference_storage::TARGET_USER;
+
+/// Erasure of a user's personal data from the collaboration tables, with a record of each
+/// erasure in the `mega_erasure` table.
+#[derive(Clone)]
+pub struct ErasureStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ErasureStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ErasureStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Put `replacement` in place of `username` wherever the user is named as the author or
+    /// actor of something, and remove the account with its keys, tokens, memberships and linked
+    /// logins. Everything is done in one transaction. Returns the number of rows changed in each
+    /// table, tables without changes left out.
+    pub async fn erase_user(
+        &self,
+        username: &str,
+        replacement: &str,
+    ) -> Result<Vec<(&'static str, u64)>, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        let rows = vec![
+            (
+                "mega_issue",
+                mega_issue::Entity::update_many()
+                    .col_expr(mega_issue::Column::SenderName, Expr::value(replacement))
+                    .filter(mega_issue::Column::SenderName.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_mr_comment",
+                mega_mr_comment::Entity::update_many()
+                    .col_expr(mega_mr_comment::Column::Author, Expr::value(replacement))
+                    .filter(mega_mr_comment::Column::Author.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_mr_review",
+                mega_mr_review::Entity::update_many()
+                    .col_expr(mega_mr_review::Column::Reviewer, Expr::value(replacement))
+                    .filter(mega_mr_review::Column::Reviewer.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_mr_thread",
+                mega_mr_thread::Entity::update_many()
+                    .col_expr(mega_mr_thread::Column::ResolvedBy, Expr::value(replacement))
+                    .filter(mega_mr_thread::Column::ResolvedBy.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_assignee",
+                mega_assignee::Entity::update_many()
+                    .col_expr(mega_assignee::Column::Username, Expr::value(replacement))
+                    .filter(mega_assignee::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_event",
+                mega_event::Entity::update_many()
+                    .col_expr(mega_event::Column::Actor, Expr::value(replacement))
+                    .filter(mega_event::Column::Actor.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_notification",
+                mega_notification::Entity::update_many()
+                    .col_expr(mega_notification::Column::Actor, Expr::value(replacement))
+                    .filter(mega_notification::Column::Actor.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_path_redirect",
+                mega_path_redirect::Entity::update_many()
+                    .col_expr(mega_path_redirect::Column::Actor, Expr::value(replacement))
+                    .filter(mega_path_redirect::Column::Actor.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_ref_audit",
+                mega_ref_audit::Entity::update_many()
+                    .col_expr(mega_ref_audit::Column::Actor, Expr::value(replacement))
+                    .filter(mega_ref_audit::Column::Actor.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_snippet",
+                mega_snippet::Entity::update_many()
+                    .col_expr(mega_snippet::Column::Owner, Expr::value(replacement))
+                    .filter(mega_snippet::Column::Owner.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_ssh_key",
+                mega_ssh_key::Entity::delete_many()
+                    .filter(mega_ssh_key::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_signing_key",
+                mega_signing_key::Entity::delete_many()
+                    .filter(mega_signing_key::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_access_token",
+                mega_access_token::Entity::delete_many()
+                    .filter(mega_access_token::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_org_member",
+                mega_org_member::Entity::delete_many()
+                    .filter(mega_org_member::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_notification",
+                mega_notification::Entity::delete_many()
+                    .filter(mega_notification::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_notification_pref",
+                mega_notification_pref::Entity::delete_many()
+                    .filter(mega_notification_pref::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_subscription",
+                mega_subscription::Entity::delete_many()
+                    .filter(mega_subscription::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_reaction",
+                mega_reaction::Entity::delete_many()
+                    .filter(mega_reaction::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_reference",
+                mega_reference::Entity::delete_many()
+                    .filter(mega_reference::Column::TargetType.eq(TARGET_USER))
+                    .filter(mega_reference::Column::TargetId.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_user_identity",
+                mega_user_identity::Entity::delete_many()
+                    .filter(mega_user_identity::Column::Username.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+            (
+                "mega_user",
+                mega_user::Entity::delete_many()
+                    .filter(mega_user::Column::Name.eq(username))
+                    .exec(&txn)
+                    .await?
+                    .rows_affected,
+            ),
+        ];
+        txn.commit().await?;
+        Ok(rows.into_iter().filter(|(_, n)| *n > 0).collect())
+    }
+
+    pub async fn save_erasure(&self, erasure: mega_erasure::Model) -> Result<(), MegaError> {
+        mega_erasure::Entity::insert(erasure.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_erasure(&self, id: i64) -> Result<Option<mega_erasure::Model>, MegaError> {
+        Ok(mega_erasure::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Recorded erasures, newest first, optionally only those of the user whose name hashes to
+    /// `subject_hash`.
+    pub async fn list_erasures(
+        &self,
+        subject_hash: Option<&str>,
+    ) -> Result<Vec<mega_erasure::Model>, MegaError> {
+        let mut query = mega_erasure::Entity::find();
+        if let Some(subject_hash) = subject_hash {
+            query = query.filter(mega_erasure::Column::SubjectHash.eq(subject_hash));
+        }
+        Ok(query
+            .order_by_desc(mega_erasure::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, IdenStatic, IntoActiveModel,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+
+use common::errors::MegaError;
+use common::utils::under_path;
+use db_entity::mega_event;
+
+/// Repository events, stored in the `mega_event` table so every server instance can stream them.
+#[derive(Clone)]
+pub struct EventStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl EventStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        EventStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn save_event(&self, event: mega_event::Model) -> Result<(), MegaError> {
+        mega_event::Entity::insert(event.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_event(&self, id: i64) -> Result<Option<mega_event::Model>, MegaError> {
+        Ok(mega_event::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn latest_id(&self) -> Result<Option<i64>, MegaError> {
+        Ok(mega_event::Entity::find()
+            .order_by_desc(mega_event::Column::Id)
+            .one(self.get_connection())
+            .await?
+            .map(|e| e.id))
+    }
+
+    /// Up to `limit` events after the event `after`, oldest first. Only events of repositories at
+    /// or below `repo_path` and of `types` are returned when they are given.
+    pub async fn events_after(
+        &self,
+        after: i64,
+        repo_path: Option<&str>,
+        types: &[String],
+        limit: u64,
+    ) -> Result<Vec<mega_event::Model>, MegaError> {
+        let mut query = mega_event::Entity::find().filter(mega_event::Column::Id.gt(after));
+        if let Some(repo_path) = repo_path.filter(|p| *p != "/") {
+            query = query.filter(under_path(mega_event::Column::RepoPath.as_str(), repo_path));
+        }
+        if !types.is_empty() {
+            query = query.filter(mega_event::Column::EventType.is_in(types.iter().cloned()));
+        }
+        Ok(query
+            .order_by_asc(mega_event::Column::Id)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Events whose payload contains `text` anywhere.
+    pub async fn events_mentioning(&self, text: &str) -> Result<Vec<mega_event::Model>, MegaError> {
+        Ok(mega_event::Entity::find()
+            .filter(mega_event::Column::Payload.contains(text))
+            .order_by_asc(mega_event::Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn update_payload(&self, id: i64, payload: String) -> Result<(), MegaError> {
+        mega_event::Entity::update_many()
+            .col_expr(mega_event::Column::Payload, Expr::value(payload))
+            .filter(mega_event::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_before(&self, before: NaiveDateTime) -> Result<u64, MegaError> {
+        let res = mega_event::Entity::delete_many()
+            .filter(mega_event::Column::CreatedAt.lt(before))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+}
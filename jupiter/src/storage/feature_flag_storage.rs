@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_feature_flag;
+
+/// Names of the flags consulted by the server. Flags that are not stored are disabled.
+pub mod flags {
+    pub const PROTOCOL_V2: &str = "protocol_v2";
+    pub const MERGE_QUEUE: &str = "merge_queue";
+    pub const CODE_SEARCH: &str = "code_search";
+    pub const OBJECTS_V2: &str = "objects_v2";
+}
+
+/// How long a flag snapshot is trusted before it is reloaded from the database, so changes made
+/// through another server instance are picked up.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+type FlagSnapshot = (Instant, HashMap<String, mega_feature_flag::Model>);
+
+/// Runtime feature flags stored in the `mega_feature_flag` table.
+///
+/// Lookups are served from an in-memory snapshot, writes through this storage refresh it at once.
+#[derive(Clone)]
+pub struct FeatureFlagStorage {
+    pub connection: Arc<DatabaseConnection>,
+    cache: Arc<RwLock<Option<FlagSnapshot>>>,
+}
+
+impl FeatureFlagStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        FeatureFlagStorage {
+            connection,
+            cache: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_flags(&self) -> Result<Vec<mega_feature_flag::Model>, MegaError> {
+        Ok(mega_feature_flag::Entity::find()
+            .order_by_asc(mega_feature_flag::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_flag(&self, name: &str) -> Result<Option<mega_feature_flag::Model>, MegaError> {
+        Ok(mega_feature_flag::Entity::find()
+            .filter(mega_feature_flag::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Insert the flag, or update everything but its id and creation time if the name exists.
+    pub async fn save_flag(&self, flag: mega_feature_flag::Model) -> Result<(), MegaError> {
+        mega_feature_flag::Entity::insert(flag.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_feature_flag::Column::Name)
+                    .update_columns([
+                        mega_feature_flag::Column::Description,
+                        mega_feature_flag::Column::Enabled,
+                        mega_feature_flag::Column::EnabledOrgs,
+                        mega_feature_flag::Column::DisabledOrgs,
+                        mega_feature_flag::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        self.invalidate();
+        Ok(())
+    }
+
+    pub async fn delete_flag(&self, name: &str) -> Result<bool, MegaError> {
+        let res = mega_feature_flag::Entity::delete_many()
+            .filter(mega_feature_flag::Column::Name.eq(name))
+            .exec(self.get_connection())
+            .await?;
+        self.invalidate();
+        Ok(res.rows_affected > 0)
+    }
+
+    /// Whether the flag `name` is on, optionally for a specific organization.
+    ///
+    /// A database error is logged and treated as "disabled", a flag lookup must never fail the
+    /// request that consults it.
+    pub async fn is_enabled(&self, name: &str, org: Option<&str>) -> bool {
+        let fresh = {
+            let cache = self.cache.read().unwrap();
+            match cache.as_ref() {
+                Some((loaded_at, flags)) if loaded_at.elapsed() < CACHE_TTL => {
+                    Some(flags.get(name).is_some_and(|f| evaluate(f, org)))
+                }
+                _ => None,
+            }
+        };
+        if let Some(enabled) = fresh {
+            return enabled;
+        }
+
+        match self.list_flags().await {
+            Ok(all) => {
+                let flags: HashMap<String, mega_feature_flag::Model> =
+                    all.into_iter().map(|f| (f.name.clone(), f)).collect();
+                let enabled = flags.get(name).is_some_and(|f| evaluate(f, org));
+                *self.cache.write().unwrap() = Some((Instant::now(), flags));
+                enabled
+            }
+            Err(err) => {
+                tracing::error!("failed to load feature flags: {}", err);
+                false
+            }
+        }
+    }
+
+    fn invalidate(&self) {
+        *self.cache.write().unwrap() = None;
+    }
+}
+
+/// Org specific settings win over the global switch, and a disable wins over an enable.
+pub fn evaluate(flag: &mega_feature_flag::Model, org: Option<&str>) -> bool {
+    if let Some(org) = org {
+        if flag.disabled_orgs.iter().any(|o| o == org) {
+            return false;
+        }
+        if flag.enabled_orgs.iter().any(|o| o == org) {
+            return true;
+        }
+    }
+    flag.enabled
+}
+
+#[cfg(test)]
+mod test {
+    use db_entity::mega_feature_flag;
+
+    use super::evaluate;
+
+    fn flag(enabled: bool, enabled_orgs: &[&str], disabled_orgs: &[&str]) -> mega_feature_flag::Model {
+        let now = chrono::Utc::now().naive_utc();
+        mega_feature_flag::Model {
+            id: 1,
+            name: "merge_queue".to_owned(),
+            description: None,
+            enabled,
+            enabled_orgs: enabled_orgs.iter().map(|s| s.to_string()).collect(),
+            disabled_orgs: disabled_orgs.iter().map(|s| s.to_string()).collect(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_flag() {
+        let dark = flag(false, &["projects"], &[]);
+        assert!(!evaluate(&dark, None));
+        assert!(!evaluate(&dark, Some("third_parts")));
+        assert!(evaluate(&dark, Some("projects")));
+
+        let rolled_out = flag(true, &[], &["third_parts"]);
+        assert!(evaluate(&rolled_out, None));
+        assert!(evaluate(&rolled_out, Some("projects")));
+        assert!(!evaluate(&rolled_out, Some("third_parts")));
+
+        let conflicting = flag(true, &["docs"], &["docs"]);
+        assert!(!evaluate(&conflicting, Some("docs")));
+    }
+}
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_import, mega_import_job, mega_import_job_repo};
+
+/// The statuses of a repository of an import job.
+pub const JOB_REPO_PENDING: &str = "pending";
+pub const JOB_REPO_RUNNING: &str = "running";
+pub const JOB_REPO_DONE: &str = "done";
+pub const JOB_REPO_FAILED: &str = "failed";
+
+/// Provenance of the repositories imported from external remotes, stored in the `mega_import`
+/// table, and the import jobs of many repositories with the progress of each repository, in
+/// `mega_import_job` and `mega_import_job_repo`.
+#[derive(Clone)]
+pub struct ImportStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ImportStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ImportStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn save_import(&self, import: mega_import::Model) -> Result<(), MegaError> {
+        mega_import::Entity::insert(import.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_import(&self, id: i64) -> Result<Option<mega_import::Model>, MegaError> {
+        Ok(mega_import::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_import(
+        &self,
+        repo_path: &str,
+    ) -> Result<Option<mega_import::Model>, MegaError> {
+        Ok(mega_import::Entity::find()
+            .filter(mega_import::Column::RepoPath.eq(repo_path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Recorded imports, newest first.
+    pub async fn list_imports(&self) -> Result<Vec<mega_import::Model>, MegaError> {
+        Ok(mega_import::Entity::find()
+            .order_by_desc(mega_import::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_job(
+        &self,
+        job: mega_import_job::Model,
+        repos: Vec<mega_import_job_repo::Model>,
+    ) -> Result<(), MegaError> {
+        mega_import_job::Entity::insert(job.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        for chunk in repos.chunks(1000) {
+            mega_import_job_repo::Entity::insert_many(
+                chunk.iter().cloned().map(IntoActiveModel::into_active_model),
+            )
+            .exec(self.get_connection())
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_job(&self, id: i64) -> Result<Option<mega_import_job::Model>, MegaError> {
+        Ok(mega_import_job::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Import jobs, newest first.
+    pub async fn list_jobs(&self) -> Result<Vec<mega_import_job::Model>, MegaError> {
+        Ok(mega_import_job::Entity::find()
+            .order_by_desc(mega_import_job::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn job_repos(
+        &self,
+        job_id: i64,
+    ) -> Result<Vec<mega_import_job_repo::Model>, MegaError> {
+        Ok(mega_import_job_repo::Entity::find()
+            .filter(mega_import_job_repo::Column::JobId.eq(job_id))
+            .order_by_asc(mega_import_job_repo::Column::RepoPath)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Repositories of import jobs whose time has come, oldest first: pending ones, and running
+    /// ones whose server instance didn't finish them in time, most likely since it stopped.
+    pub async fn due_job_repos(
+        &self,
+        now: NaiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<mega_import_job_repo::Model>, MegaError> {
+        Ok(mega_import_job_repo::Entity::find()
+            .filter(
+                mega_import_job_repo::Column::Status.is_in([JOB_REPO_PENDING, JOB_REPO_RUNNING]),
+            )
+            .filter(mega_import_job_repo::Column::NextAttemptAt.lte(now))
+            .order_by_asc(mega_import_job_repo::Column::NextAttemptAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Count another attempt at `repo` and hold it until `until`, so no other server instance
+    /// imports it meanwhile. Returns false when another instance already claimed it.
+    pub async fn claim_job_repo(
+        &self,
+        repo: &mega_import_job_repo::Model,
+        until: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        let res = mega_import_job_repo::Entity::update_many()
+            .col_expr(
+                mega_import_job_repo::Column::Status,
+                Expr::value(JOB_REPO_RUNNING),
+            )
+            .col_expr(
+                mega_import_job_repo::Column::Attempts,
+                Expr::value(repo.attempts + 1),
+            )
+            .col_expr(
+                mega_import_job_repo::Column::NextAttemptAt,
+                Expr::value(until),
+            )
+            .col_expr(
+                mega_import_job_repo::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_import_job_repo::Column::Id.eq(repo.id))
+            .filter(mega_import_job_repo::Column::Attempts.eq(repo.attempts))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Record that storing the repository `id` started, the checkpoint attempts after an
+    /// interrupted one continue from, and hold it until `until`.
+    pub async fn mark_storing(&self, id: i64, until: NaiveDateTime) -> Result<(), MegaError> {
+        mega_import_job_repo::Entity::update_many()
+            .col_expr(
+                mega_import_job_repo::Column::StartedStoring,
+                Expr::value(true),
+            )
+            .col_expr(
+                mega_import_job_repo::Column::NextAttemptAt,
+                Expr::value(until),
+            )
+            .col_expr(
+                mega_import_job_repo::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_import_job_repo::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Record the progress of the repository `id`: its status, when it is due again, and why
+    /// the last attempt failed or the import it finished with.
+    pub async fn update_job_repo(
+        &self,
+        id: i64,
+        status: &str,
+        next_attempt_at: Option<NaiveDateTime>,
+        last_error: Option<String>,
+        import_id: Option<i64>,
+    ) -> Result<(), MegaError> {
+        mega_import_job_repo::Entity::update_many()
+            .col_expr(mega_import_job_repo::Column::Status, Expr::value(status))
+            .col_expr(
+                mega_import_job_repo::Column::NextAttemptAt,
+                Expr::value(next_attempt_at),
+            )
+            .col_expr(
+                mega_import_job_repo::Column::LastError,
+                Expr::value(last_error),
+            )
+            .col_expr(mega_import_job_repo::Column::ImportId, Expr::value(import_id))
+            .col_expr(
+                mega_import_job_repo::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_import_job_repo::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Make the unfinished repositories of the job due now with no attempts counted, the failed
+    /// ones pending again. Returns how many there are.
+    pub async fn requeue_job(&self, job_id: i64, now: NaiveDateTime) -> Result<u64, MegaError> {
+        mega_import_job_repo::Entity::update_many()
+            .col_expr(
+                mega_import_job_repo::Column::Status,
+                Expr::value(JOB_REPO_PENDING),
+            )
+            .filter(mega_import_job_repo::Column::JobId.eq(job_id))
+            .filter(mega_import_job_repo::Column::Status.eq(JOB_REPO_FAILED))
+            .exec(self.get_connection())
+            .await?;
+        let res = mega_import_job_repo::Entity::update_many()
+            .col_expr(mega_import_job_repo::Column::Attempts, Expr::value(0))
+            .col_expr(
+                mega_import_job_repo::Column::NextAttemptAt,
+                Expr::value(now),
+            )
+            .col_expr(mega_import_job_repo::Column::UpdatedAt, Expr::value(now))
+            .filter(mega_import_job_repo::Column::JobId.eq(job_id))
+            .filter(mega_import_job_repo::Column::Status.ne(JOB_REPO_DONE))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, Select,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_issue, mega_issue_ref};
+
+use crate::storage::assignee_storage::assigned_items;
+use crate::storage::label_storage::{labeled_items, ITEM_ISSUE};
+use crate::storage::page::{self, Keyset};
+
+/// Filters of [`IssueStorage::list_issues`], unset fields match every issue.
+#[derive(Debug, Default)]
+pub struct IssueFilter<'a> {
+    pub repo_path: Option<&'a str>,
+    pub state: Option<&'a str>,
+    pub label: Option<&'a str>,
+    pub assignee: Option<&'a str>,
+    pub milestone_id: Option<i64>,
+}
+
+/// The issues matching `filter`, in no particular order.
+fn filtered(filter: IssueFilter<'_>) -> Select<mega_issue::Entity> {
+    let mut query = mega_issue::Entity::find();
+    if let Some(repo_path) = filter.repo_path {
+        query = query.filter(mega_issue::Column::RepoPath.eq(repo_path));
+    }
+    if let Some(state) = filter.state {
+        query = query.filter(mega_issue::Column::State.eq(state));
+    }
+    if let Some(label) = filter.label {
+        query = query.filter(mega_issue::Column::Id.in_subquery(labeled_items(ITEM_ISSUE, label)));
+    }
+    if let Some(assignee) = filter.assignee {
+        query =
+            query.filter(mega_issue::Column::Id.in_subquery(assigned_items(ITEM_ISSUE, assignee)));
+    }
+    if let Some(milestone_id) = filter.milestone_id {
+        query = query.filter(mega_issue::Column::MilestoneId.eq(milestone_id));
+    }
+    query
+}
+
+/// Issues stored in the `mega_issue` table, and the commits and merge requests referring to them.
+#[derive(Clone)]
+pub struct IssueStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl IssueStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        IssueStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn get_issue(&self, id: i64) -> Result<Option<mega_issue::Model>, MegaError> {
+        Ok(mega_issue::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_issues(&self, ids: &[i64]) -> Result<Vec<mega_issue::Model>, MegaError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(mega_issue::Entity::find()
+            .filter(mega_issue::Column::Id.is_in(ids.iter().copied()))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_by_number(
+        &self,
+        repo_path: &str,
+        number: i64,
+    ) -> Result<Option<mega_issue::Model>, MegaError> {
+        Ok(mega_issue::Entity::find()
+            .filter(mega_issue::Column::RepoPath.eq(repo_path))
+            .filter(mega_issue::Column::Number.eq(number))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Issues matching `filter`, newest first.
+    pub async fn list_issues(
+        &self,
+        filter: IssueFilter<'_>,
+    ) -> Result<Vec<mega_issue::Model>, MegaError> {
+        Ok(filtered(filter)
+            .order_by_desc(mega_issue::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// A page of the issues matching `filter`, sorted by `sort`.
+    pub async fn list_issues_page(
+        &self,
+        filter: IssueFilter<'_>,
+        sort: mega_issue::Column,
+        keyset: &Keyset,
+    ) -> Result<Vec<mega_issue::Model>, MegaError> {
+        Ok(
+            page::paginate(filtered(filter), sort, mega_issue::Column::Id, keyset)
+                .all(self.get_connection())
+                .await?,
+        )
+    }
+
+    /// The number the next issue of `repo_path` gets, numbers start at 1 in every repository.
+    pub async fn next_number(&self, repo_path: &str) -> Result<i64, MegaError> {
+        let last: Option<Option<i64>> = mega_issue::Entity::find()
+            .select_only()
+            .column_as(mega_issue::Column::Number.max(), "number")
+            .filter(mega_issue::Column::RepoPath.eq(repo_path))
+            .into_tuple()
+            .one(self.get_connection())
+            .await?;
+        Ok(last.flatten().unwrap_or(0) + 1)
+    }
+
+    pub async fn save_issue(&self, issue: mega_issue::Model) -> Result<(), MegaError> {
+        mega_issue::Entity::insert(issue.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored issue with the same id.
+    pub async fn update_issue(
+        &self,
+        issue: mega_issue::Model,
+    ) -> Result<mega_issue::Model, MegaError> {
+        Ok(issue
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    pub async fn list_refs(&self, issue_id: i64) -> Result<Vec<mega_issue_ref::Model>, MegaError> {
+        Ok(mega_issue_ref::Entity::find()
+            .filter(mega_issue_ref::Column::IssueId.eq(issue_id))
+            .order_by_asc(mega_issue_ref::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Record that a commit or merge request refers to an issue, once per source.
+    pub async fn save_ref(&self, issue_ref: mega_issue_ref::Model) -> Result<(), MegaError> {
+        mega_issue_ref::Entity::insert(issue_ref.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_issue_ref::Column::IssueId,
+                    mega_issue_ref::Column::SourceType,
+                    mega_issue_ref::Column::SourceId,
+                ])
+                .update_column(mega_issue_ref::Column::Closes)
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
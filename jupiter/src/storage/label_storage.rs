@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, Query, SelectStatement},
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::{mega_label, mega_label_link};
+
+/// Item type of issues in the `mega_label_link` and `mega_assignee` tables.
+pub const ITEM_ISSUE: &str = "issue";
+/// Item type of merge requests in the `mega_label_link` and `mega_assignee` tables.
+pub const ITEM_MR: &str = "mr";
+
+/// Ids of the items of `item_type` carrying a label named `name`, for filtering list queries.
+pub fn labeled_items(item_type: &str, name: &str) -> SelectStatement {
+    Query::select()
+        .column((mega_label_link::Entity, mega_label_link::Column::ItemId))
+        .from(mega_label_link::Entity)
+        .inner_join(
+            mega_label::Entity,
+            Expr::col((mega_label::Entity, mega_label::Column::Id))
+                .equals((mega_label_link::Entity, mega_label_link::Column::LabelId)),
+        )
+        .and_where(
+            Expr::col((mega_label_link::Entity, mega_label_link::Column::ItemType)).eq(item_type),
+        )
+        .and_where(Expr::col((mega_label::Entity, mega_label::Column::Name)).eq(name))
+        .to_owned()
+}
+
+/// Labels of a repository in the `mega_label` table, and the issues and merge requests they
+/// are attached to in `mega_label_link`.
+#[derive(Clone)]
+pub struct LabelStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl LabelStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        LabelStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_labels(&self, repo_path: &str) -> Result<Vec<mega_label::Model>, MegaError> {
+        Ok(mega_label::Entity::find()
+            .filter(mega_label::Column::RepoPath.eq(repo_path))
+            .order_by_asc(mega_label::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_label(&self, id: i64) -> Result<Option<mega_label::Model>, MegaError> {
+        Ok(mega_label::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_labels(
+        &self,
+        repo_path: &str,
+        names: &[String],
+    ) -> Result<Vec<mega_label::Model>, MegaError> {
+        Ok(mega_label::Entity::find()
+            .filter(mega_label::Column::RepoPath.eq(repo_path))
+            .filter(mega_label::Column::Name.is_in(names))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_label(&self, label: mega_label::Model) -> Result<(), MegaError> {
+        mega_label::Entity::insert(label.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored label with the same id.
+    pub async fn update_label(
+        &self,
+        label: mega_label::Model,
+    ) -> Result<mega_label::Model, MegaError> {
+        Ok(label
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a label and detach it from every item, returns false if there is no such label.
+    pub async fn delete_label(&self, id: i64) -> Result<bool, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_label_link::Entity::delete_many()
+            .filter(mega_label_link::Column::LabelId.eq(id))
+            .exec(&txn)
+            .await?;
+        let res = mega_label::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    /// The labels attached to each of `item_ids`, as `(item_id, label)` pairs ordered by label name.
+    pub async fn labels_of(
+        &self,
+        item_type: &str,
+        item_ids: &[i64],
+    ) -> Result<Vec<(i64, mega_label::Model)>, MegaError> {
+        let links = mega_label_link::Entity::find()
+            .filter(mega_label_link::Column::ItemType.eq(item_type))
+            .filter(mega_label_link::Column::ItemId.is_in(item_ids.iter().copied()))
+            .all(self.get_connection())
+            .await?;
+        if links.is_empty() {
+            return Ok(Vec::new());
+        }
+        let labels = mega_label::Entity::find()
+            .filter(mega_label::Column::Id.is_in(links.iter().map(|l| l.label_id)))
+            .order_by_asc(mega_label::Column::Name)
+            .all(self.get_connection())
+            .await?;
+        Ok(labels
+            .into_iter()
+            .flat_map(|label| {
+                links
+                    .iter()
+                    .filter(|l| l.label_id == label.id)
+                    .map(|l| (l.item_id, label.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    /// Replace the labels of an item with `label_ids`.
+    pub async fn set_labels(
+        &self,
+        item_type: &str,
+        item_id: i64,
+        label_ids: &[i64],
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_label_link::Entity::delete_many()
+            .filter(mega_label_link::Column::ItemType.eq(item_type))
+            .filter(mega_label_link::Column::ItemId.eq(item_id))
+            .exec(&txn)
+            .await?;
+        if !label_ids.is_empty() {
+            let links = label_ids.iter().map(|&label_id| {
+                mega_label_link::Model {
+                    id: generate_id(),
+                    item_type: item_type.to_owned(),
+                    item_id,
+                    label_id,
+                }
+                .into_active_model()
+            });
+            mega_label_link::Entity::insert_many(links)
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+}
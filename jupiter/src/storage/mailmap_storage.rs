@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, DatabaseConnection, EntityTrait, IntoActiveModel, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_mailmap;
+
+/// Name and email shown in place of a commit identity, stored in the `mega_mailmap` table.
+/// Commits can't be rewritten without changing their ids, so identities are replaced when
+/// displayed, like git does with a `.mailmap` file.
+#[derive(Clone)]
+pub struct MailmapStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl MailmapStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        MailmapStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_entries(&self) -> Result<Vec<mega_mailmap::Model>, MegaError> {
+        Ok(mega_mailmap::Entity::find()
+            .order_by_asc(mega_mailmap::Column::Email)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Insert the entries, replacing the name and email shown for emails already mapped.
+    pub async fn save_entries(&self, entries: Vec<mega_mailmap::Model>) -> Result<(), MegaError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        mega_mailmap::Entity::insert_many(entries.into_iter().map(|e| e.into_active_model()))
+            .on_conflict(
+                OnConflict::column(mega_mailmap::Column::Email)
+                    .update_columns([
+                        mega_mailmap::Column::DisplayName,
+                        mega_mailmap::Column::DisplayEmail,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
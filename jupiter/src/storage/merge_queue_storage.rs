@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, QuerySelect,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_merge_queue;
+
+/// The statuses of a merge request in a merge queue. Entries waiting or being tested are in the
+/// queue, the others record how they left it.
+pub const QUEUE_WAITING: &str = "waiting";
+pub const QUEUE_TESTING: &str = "testing";
+pub const QUEUE_MERGED: &str = "merged";
+pub const QUEUE_FAILED: &str = "failed";
+pub const QUEUE_REMOVED: &str = "removed";
+
+pub const QUEUE_ACTIVE: &[&str] = &[QUEUE_WAITING, QUEUE_TESTING];
+
+/// The merge queues of the branches of repositories, one entry per merge request, ordered by
+/// when it was queued.
+#[derive(Clone)]
+pub struct MergeQueueStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl MergeQueueStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        MergeQueueStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn get_entry(
+        &self,
+        mr_id: i64,
+    ) -> Result<Option<mega_merge_queue::Model>, MegaError> {
+        Ok(mega_merge_queue::Entity::find()
+            .filter(mega_merge_queue::Column::MrId.eq(mr_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The entries in the queue of `target_ref`, first to merge first.
+    pub async fn list_entries(
+        &self,
+        repo_path: &str,
+        target_ref: &str,
+    ) -> Result<Vec<mega_merge_queue::Model>, MegaError> {
+        Ok(mega_merge_queue::Entity::find()
+            .filter(mega_merge_queue::Column::RepoPath.eq(repo_path))
+            .filter(mega_merge_queue::Column::TargetRef.eq(target_ref))
+            .filter(mega_merge_queue::Column::Status.is_in(QUEUE_ACTIVE.iter().copied()))
+            .order_by_asc(mega_merge_queue::Column::CreatedAt)
+            .order_by_asc(mega_merge_queue::Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The branches with merge requests in their queue, as `(repo_path, target_ref)`.
+    pub async fn queued_targets(&self) -> Result<Vec<(String, String)>, MegaError> {
+        Ok(mega_merge_queue::Entity::find()
+            .select_only()
+            .column(mega_merge_queue::Column::RepoPath)
+            .column(mega_merge_queue::Column::TargetRef)
+            .filter(mega_merge_queue::Column::Status.is_in(QUEUE_ACTIVE.iter().copied()))
+            .distinct()
+            .into_tuple()
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Put a merge request at the end of the queue, replacing how it left the queue before.
+    pub async fn enqueue(&self, entry: mega_merge_queue::Model) -> Result<(), MegaError> {
+        mega_merge_queue::Entity::insert(entry.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_merge_queue::Column::MrId)
+                    .update_columns([
+                        mega_merge_queue::Column::RepoPath,
+                        mega_merge_queue::Column::TargetRef,
+                        mega_merge_queue::Column::Status,
+                        mega_merge_queue::Column::SourceCommitId,
+                        mega_merge_queue::Column::BaseCommitId,
+                        mega_merge_queue::Column::HeadCommitId,
+                        mega_merge_queue::Column::Reason,
+                        mega_merge_queue::Column::EnqueuedBy,
+                        mega_merge_queue::Column::CreatedAt,
+                        mega_merge_queue::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_entry(
+        &self,
+        entry: mega_merge_queue::Model,
+    ) -> Result<mega_merge_queue::Model, MegaError> {
+        Ok(entry
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_issue, mega_milestone, mega_mr};
+
+/// Milestones of a repository, stored in the `mega_milestone` table. Issues and merge requests
+/// point to their milestone through `milestone_id`.
+#[derive(Clone)]
+pub struct MilestoneStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl MilestoneStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        MilestoneStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Milestones of `repo_path`, the ones due first at the top and those without a due date last.
+    pub async fn list_milestones(
+        &self,
+        repo_path: &str,
+        state: Option<&str>,
+    ) -> Result<Vec<mega_milestone::Model>, MegaError> {
+        let mut query =
+            mega_milestone::Entity::find().filter(mega_milestone::Column::RepoPath.eq(repo_path));
+        if let Some(state) = state {
+            query = query.filter(mega_milestone::Column::State.eq(state));
+        }
+        Ok(query
+            .order_by_asc(Expr::cust("due_date IS NULL"))
+            .order_by_asc(mega_milestone::Column::DueDate)
+            .order_by_asc(mega_milestone::Column::Title)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_milestone(&self, id: i64) -> Result<Option<mega_milestone::Model>, MegaError> {
+        Ok(mega_milestone::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_by_title(
+        &self,
+        repo_path: &str,
+        title: &str,
+    ) -> Result<Option<mega_milestone::Model>, MegaError> {
+        Ok(mega_milestone::Entity::find()
+            .filter(mega_milestone::Column::RepoPath.eq(repo_path))
+            .filter(mega_milestone::Column::Title.eq(title))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_milestone(&self, milestone: mega_milestone::Model) -> Result<(), MegaError> {
+        mega_milestone::Entity::insert(milestone.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored milestone with the same id.
+    pub async fn update_milestone(
+        &self,
+        milestone: mega_milestone::Model,
+    ) -> Result<mega_milestone::Model, MegaError> {
+        Ok(milestone
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a milestone, issues and merge requests in it are left without one.
+    /// Returns false if there is no such milestone.
+    pub async fn delete_milestone(&self, id: i64) -> Result<bool, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_issue::Entity::update_many()
+            .col_expr(mega_issue::Column::MilestoneId, Expr::value(None::<i64>))
+            .filter(mega_issue::Column::MilestoneId.eq(id))
+            .exec(&txn)
+            .await?;
+        mega_mr::Entity::update_many()
+            .col_expr(mega_mr::Column::MilestoneId, Expr::value(None::<i64>))
+            .filter(mega_mr::Column::MilestoneId.eq(id))
+            .exec(&txn)
+            .await?;
+        let res = mega_milestone::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    /// The number of issues in each of `milestone_ids` by state, as `(milestone_id, state, count)`.
+    pub async fn issue_counts(
+        &self,
+        milestone_ids: &[i64],
+    ) -> Result<Vec<(i64, String, i64)>, MegaError> {
+        Ok(mega_issue::Entity::find()
+            .select_only()
+            .column(mega_issue::Column::MilestoneId)
+            .column(mega_issue::Column::State)
+            .column_as(mega_issue::Column::Id.count(), "count")
+            .filter(mega_issue::Column::MilestoneId.is_in(milestone_ids.iter().copied()))
+            .group_by(mega_issue::Column::MilestoneId)
+            .group_by(mega_issue::Column::State)
+            .into_tuple()
+            .all(self.get_connection())
+            .await?)
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_mirror;
+
+/// Repositories kept in sync with an upstream remote, stored in the `mega_mirror` table.
+#[derive(Clone)]
+pub struct MirrorStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl MirrorStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        MirrorStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_mirrors(&self) -> Result<Vec<mega_mirror::Model>, MegaError> {
+        Ok(mega_mirror::Entity::find()
+            .order_by_asc(mega_mirror::Column::RepoPath)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_mirror(&self, id: i64) -> Result<Option<mega_mirror::Model>, MegaError> {
+        Ok(mega_mirror::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_mirror(
+        &self,
+        repo_path: &str,
+    ) -> Result<Option<mega_mirror::Model>, MegaError> {
+        Ok(mega_mirror::Entity::find()
+            .filter(mega_mirror::Column::RepoPath.eq(repo_path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Enabled mirrors whose next sync is at or before `now`.
+    pub async fn due_mirrors(
+        &self,
+        now: NaiveDateTime,
+    ) -> Result<Vec<mega_mirror::Model>, MegaError> {
+        Ok(mega_mirror::Entity::find()
+            .filter(mega_mirror::Column::Enabled.eq(true))
+            .filter(mega_mirror::Column::NextSyncAt.lte(now))
+            .order_by_asc(mega_mirror::Column::NextSyncAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Move the next sync of a mirror from `scheduled` to `next`. Returns false when another
+    /// server instance already did, so every scheduled sync happens once.
+    pub async fn claim_sync(
+        &self,
+        id: i64,
+        scheduled: NaiveDateTime,
+        next: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        let res = mega_mirror::Entity::update_many()
+            .col_expr(mega_mirror::Column::NextSyncAt, Expr::value(next))
+            .filter(mega_mirror::Column::Id.eq(id))
+            .filter(mega_mirror::Column::NextSyncAt.eq(scheduled))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Record the outcome of a sync.
+    pub async fn record_sync(
+        &self,
+        id: i64,
+        status: &str,
+        message: Option<String>,
+    ) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        mega_mirror::Entity::update_many()
+            .col_expr(mega_mirror::Column::LastSyncAt, Expr::value(now))
+            .col_expr(mega_mirror::Column::LastStatus, Expr::value(status))
+            .col_expr(mega_mirror::Column::LastMessage, Expr::value(message))
+            .col_expr(mega_mirror::Column::UpdatedAt, Expr::value(now))
+            .filter(mega_mirror::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn save_mirror(&self, mirror: mega_mirror::Model) -> Result<(), MegaError> {
+        mega_mirror::Entity::insert(mirror.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored mirror with the same id.
+    pub async fn update_mirror(
+        &self,
+        mirror: mega_mirror::Model,
+    ) -> Result<mega_mirror::Model, MegaError> {
+        Ok(mirror
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Stop mirroring, returns false if there is no mirror with this id.
+    pub async fn delete_mirror(&self, id: i64) -> Result<bool, MegaError> {
+        let res = mega_mirror::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
@@ -1,5 +1,45 @@
+pub mod access_token_storage;
+pub mod archive_storage;
+pub mod assignee_storage;
+pub mod autolink_storage;
+pub mod board_storage;
+pub mod check_run_storage;
+pub mod ci_job_storage;
+pub mod ci_log_storage;
+pub mod diffstat_storage;
+pub mod erasure_storage;
+pub mod event_storage;
+pub mod feature_flag_storage;
 pub mod git_storage;
+pub mod import_storage;
+pub mod issue_storage;
+pub mod label_storage;
+pub mod mailmap_storage;
+pub mod merge_queue_storage;
 pub mod mega_storage;
+pub mod milestone_storage;
+pub mod mirror_storage;
+pub mod mr_review_storage;
+pub mod mr_storage;
+pub mod notification_storage;
+pub mod oidc_storage;
+pub mod org_storage;
+pub mod page;
+pub mod path_grant_storage;
+pub mod path_redirect_storage;
+pub mod push_profile_storage;
+pub mod reaction_storage;
+pub mod ref_audit_storage;
+pub mod ref_hook_storage;
+pub mod ref_trigger_storage;
+pub mod reference_storage;
+pub mod release_storage;
+pub mod signing_key_storage;
+pub mod snapshot_export_storage;
+pub mod snippet_storage;
+pub mod ssh_key_storage;
+pub mod stats_storage;
+pub mod user_storage;
 
 use async_trait::async_trait;
 use std::rc::Rc;
@@ -81,3 +121,4 @@ pub trait MegaStorageProvider: StorageProvider + Send {
         commits: Vec<Commit>,
     ) -> Result<(), MegaError>;
 }
+pub mod webhook_storage;
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_mr_comment, mega_mr_review, mega_mr_thread};
+
+/// Review threads, their comments and the reviews of merge requests.
+#[derive(Clone)]
+pub struct MrReviewStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl MrReviewStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        MrReviewStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_threads(
+        &self,
+        mr_id: i64,
+        resolved: Option<bool>,
+    ) -> Result<Vec<mega_mr_thread::Model>, MegaError> {
+        let mut query =
+            mega_mr_thread::Entity::find().filter(mega_mr_thread::Column::MrId.eq(mr_id));
+        if let Some(resolved) = resolved {
+            query = query.filter(mega_mr_thread::Column::Resolved.eq(resolved));
+        }
+        Ok(query
+            .order_by_asc(mega_mr_thread::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_thread(
+        &self,
+        mr_id: i64,
+        id: i64,
+    ) -> Result<Option<mega_mr_thread::Model>, MegaError> {
+        Ok(mega_mr_thread::Entity::find_by_id(id)
+            .filter(mega_mr_thread::Column::MrId.eq(mr_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_thread(&self, thread: mega_mr_thread::Model) -> Result<(), MegaError> {
+        mega_mr_thread::Entity::insert(thread.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Mark a thread resolved by `user`, or unresolved when `user` is `None`.
+    pub async fn set_resolved(&self, id: i64, user: Option<&str>) -> Result<(), MegaError> {
+        mega_mr_thread::Entity::update_many()
+            .col_expr(
+                mega_mr_thread::Column::Resolved,
+                Expr::value(user.is_some()),
+            )
+            .col_expr(
+                mega_mr_thread::Column::ResolvedBy,
+                Expr::value(user.map(str::to_owned)),
+            )
+            .col_expr(
+                mega_mr_thread::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_mr_thread::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Comments of every thread of a merge request, oldest first.
+    pub async fn list_comments(
+        &self,
+        mr_id: i64,
+    ) -> Result<Vec<mega_mr_comment::Model>, MegaError> {
+        Ok(mega_mr_comment::Entity::find()
+            .filter(mega_mr_comment::Column::MrId.eq(mr_id))
+            .order_by_asc(mega_mr_comment::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_comment(
+        &self,
+        mr_id: i64,
+        id: i64,
+    ) -> Result<Option<mega_mr_comment::Model>, MegaError> {
+        Ok(mega_mr_comment::Entity::find_by_id(id)
+            .filter(mega_mr_comment::Column::MrId.eq(mr_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_comment(&self, comment: mega_mr_comment::Model) -> Result<(), MegaError> {
+        mega_mr_comment::Entity::insert(comment.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_reviews(&self, mr_id: i64) -> Result<Vec<mega_mr_review::Model>, MegaError> {
+        Ok(mega_mr_review::Entity::find()
+            .filter(mega_mr_review::Column::MrId.eq(mr_id))
+            .order_by_asc(mega_mr_review::Column::UpdatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Record the review of a reviewer, replacing the one they gave before.
+    pub async fn save_review(&self, review: mega_mr_review::Model) -> Result<(), MegaError> {
+        mega_mr_review::Entity::insert(review.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_mr_review::Column::MrId,
+                    mega_mr_review::Column::Reviewer,
+                ])
+                .update_columns([
+                    mega_mr_review::Column::State,
+                    mega_mr_review::Column::Body,
+                    mega_mr_review::Column::CommitId,
+                    mega_mr_review::Column::UpdatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Select,
+};
+
+use common::errors::MegaError;
+use db_entity::{db_enums::MergeStatus, mega_mr};
+
+use crate::storage::assignee_storage::assigned_items;
+use crate::storage::label_storage::{labeled_items, ITEM_MR};
+use crate::storage::page::{self, Keyset};
+
+/// Filters of [`MrStorage::list_mrs`], unset fields match every merge request.
+#[derive(Debug, Default)]
+pub struct MrFilter<'a> {
+    pub repo_path: Option<&'a str>,
+    /// Full name of the source branch
+    pub source_ref: Option<&'a str>,
+    /// Any of these states, every state when empty
+    pub statuses: Vec<MergeStatus>,
+    pub label: Option<&'a str>,
+    pub assignee: Option<&'a str>,
+    pub milestone_id: Option<i64>,
+}
+
+/// The merge requests matching `filter`, in no particular order.
+fn filtered(filter: MrFilter<'_>) -> Select<mega_mr::Entity> {
+    let mut query = mega_mr::Entity::find();
+    if let Some(repo_path) = filter.repo_path {
+        query = query.filter(mega_mr::Column::RepoPath.eq(repo_path));
+    }
+    if let Some(source_ref) = filter.source_ref {
+        query = query.filter(mega_mr::Column::SourceRef.eq(source_ref));
+    }
+    if !filter.statuses.is_empty() {
+        query = query.filter(mega_mr::Column::Status.is_in(filter.statuses));
+    }
+    if let Some(label) = filter.label {
+        query = query.filter(mega_mr::Column::Id.in_subquery(labeled_items(ITEM_MR, label)));
+    }
+    if let Some(assignee) = filter.assignee {
+        query = query.filter(mega_mr::Column::Id.in_subquery(assigned_items(ITEM_MR, assignee)));
+    }
+    if let Some(milestone_id) = filter.milestone_id {
+        query = query.filter(mega_mr::Column::MilestoneId.eq(milestone_id));
+    }
+    query
+}
+
+/// Merge requests stored in the `mega_mr` table.
+#[derive(Clone)]
+pub struct MrStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl MrStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        MrStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn get_mr(&self, id: i64) -> Result<Option<mega_mr::Model>, MegaError> {
+        Ok(mega_mr::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_mrs(&self, ids: &[i64]) -> Result<Vec<mega_mr::Model>, MegaError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(mega_mr::Entity::find()
+            .filter(mega_mr::Column::Id.is_in(ids.iter().copied()))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Merge requests matching `filter`, newest first.
+    pub async fn list_mrs(&self, filter: MrFilter<'_>) -> Result<Vec<mega_mr::Model>, MegaError> {
+        Ok(filtered(filter)
+            .order_by_desc(mega_mr::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// A page of the merge requests matching `filter`, sorted by `sort`.
+    pub async fn list_mrs_page(
+        &self,
+        filter: MrFilter<'_>,
+        sort: mega_mr::Column,
+        keyset: &Keyset,
+    ) -> Result<Vec<mega_mr::Model>, MegaError> {
+        Ok(
+            page::paginate(filtered(filter), sort, mega_mr::Column::Id, keyset)
+                .all(self.get_connection())
+                .await?,
+        )
+    }
+    /// The merge request from `source_ref` into `target_ref` which is neither merged nor closed,
+    /// there is at most one.
+    pub async fn find_open_mr(
+        &self,
+        repo_path: &str,
+        source_ref: &str,
+        target_ref: &str,
+    ) -> Result<Option<mega_mr::Model>, MegaError> {
+        Ok(mega_mr::Entity::find()
+            .filter(mega_mr::Column::RepoPath.eq(repo_path))
+            .filter(mega_mr::Column::SourceRef.eq(source_ref))
+            .filter(mega_mr::Column::TargetRef.eq(target_ref))
+            .filter(mega_mr::Column::Status.is_in(MergeStatus::ACTIVE))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_mr(&self, mr: mega_mr::Model) -> Result<(), MegaError> {
+        mega_mr::Entity::insert(mr.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored merge request with the same id.
+    pub async fn update_mr(&self, mr: mega_mr::Model) -> Result<mega_mr::Model, MegaError> {
+        Ok(mr
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_notification, mega_notification_pref, mega_subscription};
+
+/// The states of the email of a notification, which has none when it isn't emailed.
+pub const EMAIL_PENDING: &str = "pending";
+pub const EMAIL_SENDING: &str = "sending";
+pub const EMAIL_SENT: &str = "sent";
+pub const EMAIL_FAILED: &str = "failed";
+
+/// The notifications of users in `mega_notification`, the issues and merge requests they follow
+/// in `mega_subscription` and how they want to be notified in `mega_notification_pref`.
+#[derive(Clone)]
+pub struct NotificationStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl NotificationStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        NotificationStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Store `notifications`, leaving out those of an event a user was already notified of.
+    pub async fn save_notifications(
+        &self,
+        notifications: Vec<mega_notification::Model>,
+    ) -> Result<(), MegaError> {
+        if notifications.is_empty() {
+            return Ok(());
+        }
+        mega_notification::Entity::insert_many(
+            notifications
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                mega_notification::Column::Username,
+                mega_notification::Column::EventId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(self.get_connection())
+        .await?;
+        Ok(())
+    }
+
+    /// The notifications of `username`, newest first.
+    pub async fn list_notifications(
+        &self,
+        username: &str,
+        unread_only: bool,
+        limit: u64,
+    ) -> Result<Vec<mega_notification::Model>, MegaError> {
+        let mut query = mega_notification::Entity::find()
+            .filter(mega_notification::Column::Username.eq(username));
+        if unread_only {
+            query = query.filter(mega_notification::Column::ReadAt.is_null());
+        }
+        Ok(query
+            .order_by_desc(mega_notification::Column::CreatedAt)
+            .order_by_desc(mega_notification::Column::Id)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn unread_count(&self, username: &str) -> Result<u64, MegaError> {
+        Ok(mega_notification::Entity::find()
+            .filter(mega_notification::Column::Username.eq(username))
+            .filter(mega_notification::Column::ReadAt.is_null())
+            .count(self.get_connection())
+            .await?)
+    }
+
+    /// Mark the notifications `ids` of `username` as read, all of them when `ids` is `None`.
+    /// Returns how many were unread.
+    pub async fn mark_read(&self, username: &str, ids: Option<&[i64]>) -> Result<u64, MegaError> {
+        let mut update = mega_notification::Entity::update_many()
+            .col_expr(
+                mega_notification::Column::ReadAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_notification::Column::Username.eq(username))
+            .filter(mega_notification::Column::ReadAt.is_null());
+        if let Some(ids) = ids {
+            update = update.filter(mega_notification::Column::Id.is_in(ids.iter().copied()));
+        }
+        Ok(update.exec(self.get_connection()).await?.rows_affected)
+    }
+
+    /// Notifications whose email waits to be sent, oldest first.
+    pub async fn pending_emails(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<mega_notification::Model>, MegaError> {
+        Ok(mega_notification::Entity::find()
+            .filter(mega_notification::Column::EmailState.eq(EMAIL_PENDING))
+            .order_by_asc(mega_notification::Column::CreatedAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Take the pending email of `notification` for sending, false when another server
+    /// instance took it first.
+    pub async fn claim_email(
+        &self,
+        notification: &mega_notification::Model,
+    ) -> Result<bool, MegaError> {
+        let res = mega_notification::Entity::update_many()
+            .col_expr(
+                mega_notification::Column::EmailState,
+                Expr::value(EMAIL_SENDING),
+            )
+            .col_expr(
+                mega_notification::Column::EmailAttempts,
+                Expr::value(notification.email_attempts + 1),
+            )
+            .filter(mega_notification::Column::Id.eq(notification.id))
+            .filter(mega_notification::Column::EmailState.eq(EMAIL_PENDING))
+            .filter(mega_notification::Column::EmailAttempts.eq(notification.email_attempts))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    pub async fn set_email_state(&self, id: i64, state: &str) -> Result<(), MegaError> {
+        mega_notification::Entity::update_many()
+            .col_expr(mega_notification::Column::EmailState, Expr::value(state))
+            .filter(mega_notification::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// The users following an issue or merge request, and those who stopped following it.
+    pub async fn subscriptions(
+        &self,
+        subject_type: &str,
+        subject_id: i64,
+    ) -> Result<Vec<mega_subscription::Model>, MegaError> {
+        Ok(mega_subscription::Entity::find()
+            .filter(mega_subscription::Column::SubjectType.eq(subject_type))
+            .filter(mega_subscription::Column::SubjectId.eq(subject_id))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Subscribe users taking part in an issue or merge request, unless they already follow it
+    /// or chose to stop.
+    pub async fn subscribe_participants(
+        &self,
+        subscriptions: Vec<mega_subscription::Model>,
+    ) -> Result<(), MegaError> {
+        if subscriptions.is_empty() {
+            return Ok(());
+        }
+        mega_subscription::Entity::insert_many(
+            subscriptions
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                mega_subscription::Column::Username,
+                mega_subscription::Column::SubjectType,
+                mega_subscription::Column::SubjectId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(self.get_connection())
+        .await?;
+        Ok(())
+    }
+
+    /// Follow or stop following an issue or merge request, replacing how the user followed it.
+    pub async fn save_subscription(
+        &self,
+        subscription: mega_subscription::Model,
+    ) -> Result<(), MegaError> {
+        mega_subscription::Entity::insert(subscription.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_subscription::Column::Username,
+                    mega_subscription::Column::SubjectType,
+                    mega_subscription::Column::SubjectId,
+                ])
+                .update_columns([
+                    mega_subscription::Column::Reason,
+                    mega_subscription::Column::Subscribed,
+                    mega_subscription::Column::UpdatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_pref(
+        &self,
+        username: &str,
+    ) -> Result<Option<mega_notification_pref::Model>, MegaError> {
+        Ok(mega_notification_pref::Entity::find()
+            .filter(mega_notification_pref::Column::Username.eq(username))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_prefs(
+        &self,
+        usernames: &[String],
+    ) -> Result<Vec<mega_notification_pref::Model>, MegaError> {
+        if usernames.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(mega_notification_pref::Entity::find()
+            .filter(mega_notification_pref::Column::Username.is_in(usernames.iter().cloned()))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_pref(&self, pref: mega_notification_pref::Model) -> Result<(), MegaError> {
+        mega_notification_pref::Entity::insert(pref.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_notification_pref::Column::Username)
+                    .update_columns([
+                        mega_notification_pref::Column::InApp,
+                        mega_notification_pref::Column::Email,
+                        mega_notification_pref::Column::ParticipatingOnly,
+                        mega_notification_pref::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_oidc_login, mega_user_identity};
+
+/// Logins through an OpenID Connect provider: the ones waiting for the provider to send the user
+/// back in `mega_oidc_login`, and the provider accounts linked to users in `mega_user_identity`.
+#[derive(Clone)]
+pub struct OidcStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl OidcStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        OidcStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn save_login(&self, login: mega_oidc_login::Model) -> Result<(), MegaError> {
+        mega_oidc_login::Entity::insert(login.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove and return the pending login `state`, so it can be completed only once.
+    pub async fn take_login(
+        &self,
+        state: &str,
+    ) -> Result<Option<mega_oidc_login::Model>, MegaError> {
+        let Some(login) = mega_oidc_login::Entity::find()
+            .filter(mega_oidc_login::Column::State.eq(state))
+            .one(self.get_connection())
+            .await?
+        else {
+            return Ok(None);
+        };
+        let res = mega_oidc_login::Entity::delete_by_id(login.id)
+            .exec(self.get_connection())
+            .await?;
+        Ok((res.rows_affected == 1).then_some(login))
+    }
+
+    /// Remove the logins started before `before` and never completed.
+    pub async fn delete_logins_before(&self, before: NaiveDateTime) -> Result<u64, MegaError> {
+        let res = mega_oidc_login::Entity::delete_many()
+            .filter(mega_oidc_login::Column::CreatedAt.lt(before))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+
+    pub async fn find_identity(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<mega_user_identity::Model>, MegaError> {
+        Ok(mega_user_identity::Entity::find()
+            .filter(mega_user_identity::Column::Issuer.eq(issuer))
+            .filter(mega_user_identity::Column::Subject.eq(subject))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Link a provider account to a user, or note that it logged in again.
+    pub async fn save_identity(
+        &self,
+        identity: mega_user_identity::Model,
+    ) -> Result<(), MegaError> {
+        mega_user_identity::Entity::insert(identity.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_user_identity::Column::Issuer,
+                    mega_user_identity::Column::Subject,
+                ])
+                .update_column(mega_user_identity::Column::UpdatedAt)
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_org, mega_org_member};
+
+/// Organizations in the `mega_org` table, and their members in `mega_org_member`.
+#[derive(Clone)]
+pub struct OrgStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl OrgStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        OrgStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn get_org_by_name(&self, name: &str) -> Result<Option<mega_org::Model>, MegaError> {
+        Ok(mega_org::Entity::find()
+            .filter(mega_org::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_org(&self, org: mega_org::Model) -> Result<(), MegaError> {
+        mega_org::Entity::insert(org.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_members(
+        &self,
+        org_id: i64,
+    ) -> Result<Vec<mega_org_member::Model>, MegaError> {
+        Ok(mega_org_member::Entity::find()
+            .filter(mega_org_member::Column::OrgId.eq(org_id))
+            .order_by_asc(mega_org_member::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Add a member, or change the role of one already in the organization.
+    pub async fn save_member(&self, member: mega_org_member::Model) -> Result<(), MegaError> {
+        mega_org_member::Entity::insert(member.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_org_member::Column::OrgId,
+                    mega_org_member::Column::Username,
+                ])
+                .update_column(mega_org_member::Column::Role)
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove `username` from the organization, returns false if they weren't a member.
+    pub async fn delete_member(&self, org_id: i64, username: &str) -> Result<bool, MegaError> {
+        let res = mega_org_member::Entity::delete_many()
+            .filter(mega_org_member::Column::OrgId.eq(org_id))
+            .filter(mega_org_member::Column::Username.eq(username))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    /// The organizations `username` is a member of, with the membership.
+    pub async fn list_user_orgs(
+        &self,
+        username: &str,
+    ) -> Result<Vec<(mega_org_member::Model, mega_org::Model)>, MegaError> {
+        let members = mega_org_member::Entity::find()
+            .filter(mega_org_member::Column::Username.eq(username))
+            .order_by_asc(mega_org_member::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?;
+        let orgs = mega_org::Entity::find()
+            .filter(mega_org::Column::Id.is_in(members.iter().map(|m| m.org_id)))
+            .all(self.get_connection())
+            .await?;
+        Ok(members
+            .into_iter()
+            .filter_map(|m| {
+                let org = orgs.iter().find(|o| o.id == m.org_id)?.clone();
+                Some((m, org))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,44 @@
+use sea_orm::sea_query::{Condition, Order};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Select, Value};
+
+/// A page of a list query, by keyset: the rows after the last one of the previous page in the
+/// order of a sort column and the id, so a page costs the same however deep it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyset {
+    pub descending: bool,
+    /// Sort key and id of the last row of the previous page, `None` for the first page
+    pub after: Option<(Value, i64)>,
+    /// Most rows of the page, one more is read to tell whether another page follows
+    pub limit: u64,
+}
+
+/// `query` limited to the page `keyset` of its rows sorted by `sort` then `id`.
+pub fn paginate<E>(query: Select<E>, sort: E::Column, id: E::Column, keyset: &Keyset) -> Select<E>
+where
+    E: EntityTrait,
+{
+    let query = match &keyset.after {
+        Some((key, after_id)) => {
+            let (beyond, beyond_id) = if keyset.descending {
+                (sort.lt(key.clone()), id.lt(*after_id))
+            } else {
+                (sort.gt(key.clone()), id.gt(*after_id))
+            };
+            query.filter(
+                Condition::any()
+                    .add(beyond)
+                    .add(Condition::all().add(sort.eq(key.clone())).add(beyond_id)),
+            )
+        }
+        None => query,
+    };
+    let order = if keyset.descending {
+        Order::Desc
+    } else {
+        Order::Asc
+    };
+    query
+        .order_by(sort, order.clone())
+        .order_by(id, order)
+        .limit(keyset.limit + 1)
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IdenStatic,
+    IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use common::utils::under_path;
+use db_entity::mega_path_grant;
+
+/// Permissions granted to users and organizations on monorepo paths, in `mega_path_grant`.
+#[derive(Clone)]
+pub struct PathGrantStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl PathGrantStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        PathGrantStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// The grants on `under` and the paths below it, every grant when it is `None`.
+    pub async fn list_grants(
+        &self,
+        under: Option<&str>,
+    ) -> Result<Vec<mega_path_grant::Model>, MegaError> {
+        let mut query = mega_path_grant::Entity::find();
+        if let Some(path) = under {
+            query = query.filter(under_path(mega_path_grant::Column::Path.as_str(), path));
+        }
+        Ok(query
+            .order_by_asc(mega_path_grant::Column::Path)
+            .order_by_asc(mega_path_grant::Column::SubjectKind)
+            .order_by_asc(mega_path_grant::Column::Subject)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The grants on exactly the given paths, such as the parents of a path.
+    pub async fn grants_on(
+        &self,
+        paths: &[String],
+    ) -> Result<Vec<mega_path_grant::Model>, MegaError> {
+        Ok(mega_path_grant::Entity::find()
+            .filter(mega_path_grant::Column::Path.is_in(paths))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_grant(&self, id: i64) -> Result<Option<mega_path_grant::Model>, MegaError> {
+        Ok(mega_path_grant::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Save `grant`, replacing the permission the same subject had on the same path.
+    pub async fn save_grant(
+        &self,
+        grant: mega_path_grant::Model,
+    ) -> Result<mega_path_grant::Model, MegaError> {
+        let (path, kind, subject) = (
+            grant.path.clone(),
+            grant.subject_kind.clone(),
+            grant.subject.clone(),
+        );
+        mega_path_grant::Entity::insert(grant.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_path_grant::Column::Path,
+                    mega_path_grant::Column::SubjectKind,
+                    mega_path_grant::Column::Subject,
+                ])
+                .update_columns([
+                    mega_path_grant::Column::Permission,
+                    mega_path_grant::Column::GrantedBy,
+                    mega_path_grant::Column::UpdatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        mega_path_grant::Entity::find()
+            .filter(mega_path_grant::Column::Path.eq(path))
+            .filter(mega_path_grant::Column::SubjectKind.eq(kind))
+            .filter(mega_path_grant::Column::Subject.eq(subject))
+            .one(self.get_connection())
+            .await?
+            .ok_or_else(|| MegaError::with_message("the saved grant is gone"))
+    }
+
+    pub async fn delete_grant(&self, id: i64) -> Result<bool, MegaError> {
+        let res = mega_path_grant::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
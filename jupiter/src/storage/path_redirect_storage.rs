@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, IdenStatic,
+    IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use common::utils::{rebase_path, under_path};
+use db_entity::{
+    git_repo, mega_blob, mega_ci_log, mega_commit, mega_import, mega_issue, mega_label,
+    mega_milestone, mega_mirror, mega_mr, mega_path_grant, mega_path_mapping, mega_path_redirect,
+    mega_ref_trigger, mega_snapshot, mega_tree,
+};
+
+/// Replace the leading `from` of `column` by `to` in every row of `E` at or below `from`.
+async fn rebase<E, C>(db: &C, column: E::Column, from: &str, to: &str) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+    C: ConnectionTrait,
+{
+    E::update_many()
+        .col_expr(column, rebase_path(column.as_str(), from, to))
+        .filter(under_path(column.as_str(), from))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Monorepo paths that were moved, stored in the `mega_path_redirect` table so the old paths
+/// keep pointing to the new ones.
+#[derive(Clone)]
+pub struct PathRedirectStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl PathRedirectStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        PathRedirectStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_redirects(&self) -> Result<Vec<mega_path_redirect::Model>, MegaError> {
+        Ok(mega_path_redirect::Entity::find()
+            .order_by_asc(mega_path_redirect::Column::FromPath)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The redirect of `path` or of the closest of its parents, `path` being normalized.
+    pub async fn find_redirect(
+        &self,
+        path: &str,
+    ) -> Result<Option<mega_path_redirect::Model>, MegaError> {
+        let ancestors: Vec<&str> = path
+            .match_indices('/')
+            .skip(1)
+            .map(|(i, _)| &path[..i])
+            .chain([path])
+            .filter(|p| p.len() > 1)
+            .collect();
+        if ancestors.is_empty() {
+            return Ok(None);
+        }
+        let redirects = mega_path_redirect::Entity::find()
+            .filter(mega_path_redirect::Column::FromPath.is_in(ancestors))
+            .all(self.get_connection())
+            .await?;
+        Ok(redirects.into_iter().max_by_key(|r| r.from_path.len()))
+    }
+
+    /// Re-point every row of the mega tables at or below `redirect.from_path` to
+    /// `redirect.to_path` and record the redirect, in one transaction.
+    ///
+    /// Redirects to the old path are updated to lead straight to the new one, and those from
+    /// the new path are dropped since it is in use again.
+    pub async fn record_move(&self, redirect: mega_path_redirect::Model) -> Result<(), MegaError> {
+        let (from, to) = (redirect.from_path.as_str(), redirect.to_path.as_str());
+        let txn = self.get_connection().begin().await?;
+        rebase::<mega_tree::Entity, _>(&txn, mega_tree::Column::FullPath, from, to).await?;
+        rebase::<mega_blob::Entity, _>(&txn, mega_blob::Column::FullPath, from, to).await?;
+        rebase::<mega_commit::Entity, _>(&txn, mega_commit::Column::FullPath, from, to).await?;
+        mega_snapshot::Entity::update_many()
+            .col_expr(
+                mega_snapshot::Column::Name,
+                Expr::value(to.rsplit('/').next().unwrap_or(to)),
+            )
+            .filter(mega_snapshot::Column::Path.eq(from))
+            .exec(&txn)
+            .await?;
+        rebase::<mega_snapshot::Entity, _>(&txn, mega_snapshot::Column::Path, from, to).await?;
+        rebase::<git_repo::Entity, _>(&txn, git_repo::Column::RepoPath, from, to).await?;
+        rebase::<mega_mr::Entity, _>(&txn, mega_mr::Column::RepoPath, from, to).await?;
+        rebase::<mega_issue::Entity, _>(&txn, mega_issue::Column::RepoPath, from, to).await?;
+        rebase::<mega_label::Entity, _>(&txn, mega_label::Column::RepoPath, from, to).await?;
+        rebase::<mega_milestone::Entity, _>(&txn, mega_milestone::Column::RepoPath, from, to)
+            .await?;
+        rebase::<mega_ci_log::Entity, _>(&txn, mega_ci_log::Column::RepoPath, from, to).await?;
+        rebase::<mega_ref_trigger::Entity, _>(&txn, mega_ref_trigger::Column::RepoPath, from, to)
+            .await?;
+        rebase::<mega_import::Entity, _>(&txn, mega_import::Column::RepoPath, from, to).await?;
+        rebase::<mega_mirror::Entity, _>(&txn, mega_mirror::Column::RepoPath, from, to).await?;
+        // grants follow the directory, stale ones left at the new path are dropped
+        mega_path_grant::Entity::delete_many()
+            .filter(under_path(mega_path_grant::Column::Path.as_str(), to))
+            .exec(&txn)
+            .await?;
+        rebase::<mega_path_grant::Entity, _>(&txn, mega_path_grant::Column::Path, from, to).await?;
+        rebase::<mega_path_mapping::Entity, _>(&txn, mega_path_mapping::Column::Path, from, to)
+            .await?;
+        rebase::<mega_path_mapping::Entity, _>(&txn, mega_path_mapping::Column::RepoPath, from, to)
+            .await?;
+
+        rebase::<mega_path_redirect::Entity, _>(&txn, mega_path_redirect::Column::ToPath, from, to)
+            .await?;
+        mega_path_redirect::Entity::delete_many()
+            .filter(under_path(
+                mega_path_redirect::Column::FromPath.as_str(),
+                to,
+            ))
+            .exec(&txn)
+            .await?;
+        mega_path_redirect::Entity::insert(redirect.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_path_redirect::Column::FromPath)
+                    .update_columns([
+                        mega_path_redirect::Column::ToPath,
+                        mega_path_redirect::Column::CommitId,
+                        mega_path_redirect::Column::Actor,
+                        mega_path_redirect::Column::CreatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+}
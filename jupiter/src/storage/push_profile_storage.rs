@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, IdenStatic, IntoActiveModel, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+
+use common::errors::MegaError;
+use common::utils::under_path;
+use db_entity::mega_push_profile;
+
+/// Stage timings of recent pushes, in `mega_push_profile`.
+#[derive(Clone)]
+pub struct PushProfileStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl PushProfileStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        PushProfileStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn save_profile(&self, profile: mega_push_profile::Model) -> Result<(), MegaError> {
+        mega_push_profile::Entity::insert(profile.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_profile(
+        &self,
+        id: i64,
+    ) -> Result<Option<mega_push_profile::Model>, MegaError> {
+        Ok(mega_push_profile::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Up to `limit` profiles, newest first, of pushes to `repo_path` and below it which took at
+    /// least `min_ms`.
+    pub async fn list_profiles(
+        &self,
+        repo_path: Option<&str>,
+        min_ms: Option<i64>,
+        limit: u64,
+    ) -> Result<Vec<mega_push_profile::Model>, MegaError> {
+        let mut query = mega_push_profile::Entity::find();
+        if let Some(repo_path) = repo_path.filter(|p| *p != "/") {
+            query = query.filter(under_path(
+                mega_push_profile::Column::RepoPath.as_str(),
+                repo_path,
+            ));
+        }
+        if let Some(min_ms) = min_ms {
+            query = query.filter(mega_push_profile::Column::TotalMs.gte(min_ms));
+        }
+        Ok(query
+            .order_by_desc(mega_push_profile::Column::CreatedAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Delete all but the `keep` newest profiles.
+    pub async fn prune(&self, keep: u64) -> Result<u64, MegaError> {
+        let count = mega_push_profile::Entity::find()
+            .count(self.get_connection())
+            .await?;
+        if count <= keep {
+            return Ok(0);
+        }
+        let Some(oldest_kept) = mega_push_profile::Entity::find()
+            .order_by_desc(mega_push_profile::Column::CreatedAt)
+            .offset(keep.saturating_sub(1))
+            .one(self.get_connection())
+            .await?
+        else {
+            return Ok(0);
+        };
+        let res = mega_push_profile::Entity::delete_many()
+            .filter(mega_push_profile::Column::CreatedAt.lt(oldest_kept.created_at))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+}
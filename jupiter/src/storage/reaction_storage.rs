@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_reaction;
+
+/// The emoji reactions of users to issues, merge requests and review comments.
+#[derive(Clone)]
+pub struct ReactionStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ReactionStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ReactionStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Store a reaction, a user reacting twice with the same content is kept once.
+    pub async fn add_reaction(&self, reaction: mega_reaction::Model) -> Result<(), MegaError> {
+        mega_reaction::Entity::insert(reaction.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_reaction::Column::SubjectType,
+                    mega_reaction::Column::SubjectId,
+                    mega_reaction::Column::Username,
+                    mega_reaction::Column::Content,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the reaction of `username`, if they reacted so.
+    pub async fn remove_reaction(
+        &self,
+        subject_type: &str,
+        subject_id: i64,
+        username: &str,
+        content: &str,
+    ) -> Result<(), MegaError> {
+        mega_reaction::Entity::delete_many()
+            .filter(mega_reaction::Column::SubjectType.eq(subject_type))
+            .filter(mega_reaction::Column::SubjectId.eq(subject_id))
+            .filter(mega_reaction::Column::Username.eq(username))
+            .filter(mega_reaction::Column::Content.eq(content))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// The reactions to a subject, oldest first.
+    pub async fn list_reactions(
+        &self,
+        subject_type: &str,
+        subject_id: i64,
+    ) -> Result<Vec<mega_reaction::Model>, MegaError> {
+        Ok(mega_reaction::Entity::find()
+            .filter(mega_reaction::Column::SubjectType.eq(subject_type))
+            .filter(mega_reaction::Column::SubjectId.eq(subject_id))
+            .order_by_asc(mega_reaction::Column::CreatedAt)
+            .order_by_asc(mega_reaction::Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+}
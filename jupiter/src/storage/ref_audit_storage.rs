@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_ref_audit;
+
+/// Ref updates made by the server, with who made them and why, stored in the `mega_ref_audit`
+/// table.
+#[derive(Clone)]
+pub struct RefAuditStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl RefAuditStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        RefAuditStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn save_audit(&self, entry: mega_ref_audit::Model) -> Result<(), MegaError> {
+        mega_ref_audit::Entity::insert(entry.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Audit entries of a repository, optionally of a single ref, newest first.
+    pub async fn list_audit(
+        &self,
+        repo_path: &str,
+        ref_name: Option<&str>,
+    ) -> Result<Vec<mega_ref_audit::Model>, MegaError> {
+        let mut query =
+            mega_ref_audit::Entity::find().filter(mega_ref_audit::Column::RepoPath.eq(repo_path));
+        if let Some(ref_name) = ref_name {
+            query = query.filter(mega_ref_audit::Column::RefName.eq(ref_name));
+        }
+        Ok(query
+            .order_by_desc(mega_ref_audit::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+}
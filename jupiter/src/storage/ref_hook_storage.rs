@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use common::errors::MegaError;
+use common::utils::generate_id;
+use db_entity::{mega_ref_hook, mega_ref_hook_retry};
+
+/// How far each ref hook got through the events in `mega_ref_hook`, and the deliveries to retry
+/// in `mega_ref_hook_retry`.
+#[derive(Clone)]
+pub struct RefHookStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl RefHookStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        RefHookStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn get_cursor(&self, name: &str) -> Result<Option<i64>, MegaError> {
+        Ok(mega_ref_hook::Entity::find()
+            .filter(mega_ref_hook::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?
+            .map(|hook| hook.last_event_id))
+    }
+
+    /// Start the cursor of a hook at `last_event_id`, unless it already has one.
+    pub async fn init_cursor(&self, name: &str, last_event_id: i64) -> Result<(), MegaError> {
+        let hook = mega_ref_hook::Model {
+            id: generate_id(),
+            name: name.to_owned(),
+            last_event_id,
+            updated_at: chrono::Utc::now().naive_utc(),
+        };
+        mega_ref_hook::Entity::insert(hook.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_ref_hook::Column::Name)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Move the cursor of a hook from `from` to `to`. Returns false when another server instance
+    /// already did, so each event is handed to a hook once.
+    pub async fn claim_events(&self, name: &str, from: i64, to: i64) -> Result<bool, MegaError> {
+        let res = mega_ref_hook::Entity::update_many()
+            .col_expr(mega_ref_hook::Column::LastEventId, Expr::value(to))
+            .col_expr(
+                mega_ref_hook::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_ref_hook::Column::Name.eq(name))
+            .filter(mega_ref_hook::Column::LastEventId.eq(from))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    pub async fn save_retry(&self, retry: mega_ref_hook_retry::Model) -> Result<(), MegaError> {
+        mega_ref_hook_retry::Entity::insert(retry.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Retries of `hook` whose time has come, oldest first.
+    pub async fn due_retries(
+        &self,
+        hook: &str,
+        now: NaiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<mega_ref_hook_retry::Model>, MegaError> {
+        Ok(mega_ref_hook_retry::Entity::find()
+            .filter(mega_ref_hook_retry::Column::Hook.eq(hook))
+            .filter(mega_ref_hook_retry::Column::NextAttemptAt.lte(now))
+            .order_by_asc(mega_ref_hook_retry::Column::NextAttemptAt)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Count another attempt of `retry` and put off the next one until `next`, so no other server
+    /// instance makes it meanwhile. Returns false when another instance already claimed it.
+    pub async fn claim_retry(
+        &self,
+        retry: &mega_ref_hook_retry::Model,
+        next: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        let res = mega_ref_hook_retry::Entity::update_many()
+            .col_expr(
+                mega_ref_hook_retry::Column::Attempts,
+                Expr::value(retry.attempts + 1),
+            )
+            .col_expr(
+                mega_ref_hook_retry::Column::NextAttemptAt,
+                Expr::value(next),
+            )
+            .col_expr(
+                mega_ref_hook_retry::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_ref_hook_retry::Column::Id.eq(retry.id))
+            .filter(mega_ref_hook_retry::Column::Attempts.eq(retry.attempts))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Record why an attempt failed, `next` being `None` when the hook gives up on the event.
+    pub async fn record_failure(
+        &self,
+        id: i64,
+        next: Option<NaiveDateTime>,
+        error: &str,
+    ) -> Result<(), MegaError> {
+        mega_ref_hook_retry::Entity::update_many()
+            .col_expr(
+                mega_ref_hook_retry::Column::NextAttemptAt,
+                Expr::value(next),
+            )
+            .col_expr(mega_ref_hook_retry::Column::LastError, Expr::value(error))
+            .col_expr(
+                mega_ref_hook_retry::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_ref_hook_retry::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_retry(&self, id: i64) -> Result<(), MegaError> {
+        mega_ref_hook_retry::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_retries(
+        &self,
+        hook: &str,
+    ) -> Result<Vec<mega_ref_hook_retry::Model>, MegaError> {
+        Ok(mega_ref_hook_retry::Entity::find()
+            .filter(mega_ref_hook_retry::Column::Hook.eq(hook))
+            .order_by_asc(mega_ref_hook_retry::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Attempt the events `hook` gave up on once more, returning how many there are.
+    pub async fn requeue_failed(&self, hook: &str) -> Result<u64, MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let res = mega_ref_hook_retry::Entity::update_many()
+            .col_expr(mega_ref_hook_retry::Column::NextAttemptAt, Expr::value(now))
+            .col_expr(mega_ref_hook_retry::Column::UpdatedAt, Expr::value(now))
+            .filter(mega_ref_hook_retry::Column::Hook.eq(hook))
+            .filter(mega_ref_hook_retry::Column::NextAttemptAt.is_null())
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_ref_trigger;
+
+/// Scheduled ref creation rules stored in the `mega_ref_trigger` table.
+#[derive(Clone)]
+pub struct RefTriggerStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl RefTriggerStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        RefTriggerStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_triggers(&self) -> Result<Vec<mega_ref_trigger::Model>, MegaError> {
+        Ok(mega_ref_trigger::Entity::find()
+            .order_by_asc(mega_ref_trigger::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_trigger(
+        &self,
+        name: &str,
+    ) -> Result<Option<mega_ref_trigger::Model>, MegaError> {
+        Ok(mega_ref_trigger::Entity::find()
+            .filter(mega_ref_trigger::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Enabled triggers whose next run is at or before `now`.
+    pub async fn due_triggers(
+        &self,
+        now: NaiveDateTime,
+    ) -> Result<Vec<mega_ref_trigger::Model>, MegaError> {
+        Ok(mega_ref_trigger::Entity::find()
+            .filter(mega_ref_trigger::Column::Enabled.eq(true))
+            .filter(mega_ref_trigger::Column::NextRunAt.lte(now))
+            .order_by_asc(mega_ref_trigger::Column::NextRunAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Move the next run of a trigger from `scheduled` to `next`. Returns false when another
+    /// server instance already did, so every scheduled run happens once.
+    pub async fn claim_run(
+        &self,
+        id: i64,
+        scheduled: NaiveDateTime,
+        next: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        let res = mega_ref_trigger::Entity::update_many()
+            .col_expr(mega_ref_trigger::Column::NextRunAt, Expr::value(next))
+            .filter(mega_ref_trigger::Column::Id.eq(id))
+            .filter(mega_ref_trigger::Column::NextRunAt.eq(scheduled))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Record the outcome of a run, `Ok` holding the ref that was created.
+    pub async fn record_run(&self, id: i64, result: Result<&str, &str>) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let (last_ref, last_error) = match result {
+            Ok(ref_name) => (Some(ref_name.to_owned()), None),
+            Err(error) => (None, Some(error.to_owned())),
+        };
+        let mut update = mega_ref_trigger::Entity::update_many()
+            .col_expr(mega_ref_trigger::Column::LastRunAt, Expr::value(now))
+            .col_expr(mega_ref_trigger::Column::LastError, Expr::value(last_error))
+            .col_expr(mega_ref_trigger::Column::UpdatedAt, Expr::value(now));
+        if last_ref.is_some() {
+            update = update.col_expr(mega_ref_trigger::Column::LastRef, Expr::value(last_ref));
+        }
+        update
+            .filter(mega_ref_trigger::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn save_trigger(&self, trigger: mega_ref_trigger::Model) -> Result<(), MegaError> {
+        mega_ref_trigger::Entity::insert(trigger.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored trigger with the same id.
+    pub async fn update_trigger(
+        &self,
+        trigger: mega_ref_trigger::Model,
+    ) -> Result<mega_ref_trigger::Model, MegaError> {
+        Ok(trigger
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a trigger, returns false if there is none with this name.
+    pub async fn delete_trigger(&self, name: &str) -> Result<bool, MegaError> {
+        let res = mega_ref_trigger::Entity::delete_many()
+            .filter(mega_ref_trigger::Column::Name.eq(name))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
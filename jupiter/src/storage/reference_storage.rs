@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_reference;
+
+/// Texts with references besides the titles and bodies of issues and merge requests, whose
+/// types are the ones of [`super::label_storage`].
+pub const SOURCE_COMMENT: &str = "comment";
+pub const SOURCE_REVIEW: &str = "review";
+
+/// Targets of references besides issues and merge requests.
+pub const TARGET_USER: &str = "user";
+pub const TARGET_COMMIT: &str = "commit";
+
+/// What the texts of issues, merge requests and their reviews refer to: users, issues, merge
+/// requests and commits. Each row is a backlink from the text to its target.
+#[derive(Clone)]
+pub struct ReferenceStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ReferenceStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ReferenceStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Replace the references of a text with `references`.
+    pub async fn replace_references(
+        &self,
+        source_type: &str,
+        source_id: i64,
+        references: Vec<mega_reference::Model>,
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_reference::Entity::delete_many()
+            .filter(mega_reference::Column::SourceType.eq(source_type))
+            .filter(mega_reference::Column::SourceId.eq(source_id))
+            .exec(&txn)
+            .await?;
+        if !references.is_empty() {
+            mega_reference::Entity::insert_many(
+                references
+                    .into_iter()
+                    .map(IntoActiveModel::into_active_model),
+            )
+            .exec(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// The references of the texts `source_ids` of type `source_type`.
+    pub async fn list_by_sources(
+        &self,
+        source_type: &str,
+        source_ids: &[i64],
+    ) -> Result<Vec<mega_reference::Model>, MegaError> {
+        if source_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(mega_reference::Entity::find()
+            .filter(mega_reference::Column::SourceType.eq(source_type))
+            .filter(mega_reference::Column::SourceId.is_in(source_ids.iter().copied()))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The texts of `repo_path` referring to a target, oldest first.
+    pub async fn list_backlinks(
+        &self,
+        repo_path: &str,
+        target_type: &str,
+        target_id: &str,
+    ) -> Result<Vec<mega_reference::Model>, MegaError> {
+        Ok(mega_reference::Entity::find()
+            .filter(mega_reference::Column::RepoPath.eq(repo_path))
+            .filter(mega_reference::Column::TargetType.eq(target_type))
+            .filter(mega_reference::Column::TargetId.eq(target_id))
+            .order_by_asc(mega_reference::Column::CreatedAt)
+            .order_by_asc(mega_reference::Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+}
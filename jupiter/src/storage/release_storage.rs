@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait, TryInsertResult,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_release, mega_release_asset};
+
+/// Releases published from tags, with the files attached to them. The content of the files is
+/// kept in the content store, `mega_release_asset` only lists them.
+#[derive(Clone)]
+pub struct ReleaseStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ReleaseStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ReleaseStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Releases of a repository, newest first.
+    pub async fn list_releases(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<mega_release::Model>, MegaError> {
+        Ok(mega_release::Entity::find()
+            .filter(mega_release::Column::RepoPath.eq(repo_path))
+            .order_by_desc(mega_release::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_release(&self, id: i64) -> Result<Option<mega_release::Model>, MegaError> {
+        Ok(mega_release::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_release(
+        &self,
+        repo_path: &str,
+        tag_name: &str,
+    ) -> Result<Option<mega_release::Model>, MegaError> {
+        Ok(mega_release::Entity::find()
+            .filter(mega_release::Column::RepoPath.eq(repo_path))
+            .filter(mega_release::Column::TagName.eq(tag_name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Insert `release`, returns false when its tag already has one.
+    pub async fn create_release(&self, release: mega_release::Model) -> Result<bool, MegaError> {
+        let res = mega_release::Entity::insert(release.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_release::Column::RepoPath,
+                    mega_release::Column::TagName,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(matches!(res, TryInsertResult::Inserted(_)))
+    }
+
+    pub async fn update_release(
+        &self,
+        release: mega_release::Model,
+    ) -> Result<mega_release::Model, MegaError> {
+        Ok(release
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a release and the list of its assets, returning the assets so their content can
+    /// be removed too.
+    pub async fn delete_release(
+        &self,
+        id: i64,
+    ) -> Result<Vec<mega_release_asset::Model>, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        let assets = mega_release_asset::Entity::find()
+            .filter(mega_release_asset::Column::ReleaseId.eq(id))
+            .all(&txn)
+            .await?;
+        mega_release_asset::Entity::delete_many()
+            .filter(mega_release_asset::Column::ReleaseId.eq(id))
+            .exec(&txn)
+            .await?;
+        mega_release::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(assets)
+    }
+
+    pub async fn list_assets(
+        &self,
+        release_id: i64,
+    ) -> Result<Vec<mega_release_asset::Model>, MegaError> {
+        Ok(mega_release_asset::Entity::find()
+            .filter(mega_release_asset::Column::ReleaseId.eq(release_id))
+            .order_by_asc(mega_release_asset::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_asset(
+        &self,
+        release_id: i64,
+        name: &str,
+    ) -> Result<Option<mega_release_asset::Model>, MegaError> {
+        Ok(mega_release_asset::Entity::find()
+            .filter(mega_release_asset::Column::ReleaseId.eq(release_id))
+            .filter(mega_release_asset::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Save `asset`, replacing the asset of the same name.
+    pub async fn save_asset(&self, asset: mega_release_asset::Model) -> Result<(), MegaError> {
+        mega_release_asset::Entity::insert(asset.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    mega_release_asset::Column::ReleaseId,
+                    mega_release_asset::Column::Name,
+                ])
+                .update_columns([
+                    mega_release_asset::Column::ContentType,
+                    mega_release_asset::Column::Size,
+                    mega_release_asset::Column::Sha256,
+                    mega_release_asset::Column::Uploader,
+                    mega_release_asset::Column::CreatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_asset(&self, id: i64) -> Result<(), MegaError> {
+        mega_release_asset::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Whether an asset still uses the content stored under `sha256`.
+    pub async fn content_in_use(&self, sha256: &str) -> Result<bool, MegaError> {
+        Ok(mega_release_asset::Entity::find()
+            .filter(mega_release_asset::Column::Sha256.eq(sha256))
+            .one(self.get_connection())
+            .await?
+            .is_some())
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_signing_key;
+
+/// GPG and SSH keys users sign commits with, stored in the `mega_signing_key` table.
+#[derive(Clone)]
+pub struct SigningKeyStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SigningKeyStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SigningKeyStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_keys(
+        &self,
+        username: &str,
+    ) -> Result<Vec<mega_signing_key::Model>, MegaError> {
+        Ok(mega_signing_key::Entity::find()
+            .filter(mega_signing_key::Column::Username.eq(username))
+            .order_by_asc(mega_signing_key::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_key(
+        &self,
+        username: &str,
+        id: i64,
+    ) -> Result<Option<mega_signing_key::Model>, MegaError> {
+        Ok(mega_signing_key::Entity::find_by_id(id)
+            .filter(mega_signing_key::Column::Username.eq(username))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_by_key_id(
+        &self,
+        key_id: &str,
+    ) -> Result<Option<mega_signing_key::Model>, MegaError> {
+        Ok(mega_signing_key::Entity::find()
+            .filter(mega_signing_key::Column::KeyId.eq(key_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The verified key whose primary key or one of its subkeys has the id `key_id`.
+    pub async fn find_verified_signer(
+        &self,
+        key_id: &str,
+    ) -> Result<Option<mega_signing_key::Model>, MegaError> {
+        Ok(mega_signing_key::Entity::find()
+            .filter(mega_signing_key::Column::Verified.eq(true))
+            .filter(
+                Condition::any()
+                    .add(mega_signing_key::Column::KeyId.eq(key_id))
+                    .add(Expr::cust_with_values("? = ANY(subkey_ids)", [key_id])),
+            )
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_key(&self, key: mega_signing_key::Model) -> Result<(), MegaError> {
+        mega_signing_key::Entity::insert(key.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn mark_verified(&self, id: i64) -> Result<(), MegaError> {
+        mega_signing_key::Entity::update_many()
+            .col_expr(mega_signing_key::Column::Verified, Expr::value(true))
+            .col_expr(
+                mega_signing_key::Column::VerifiedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_signing_key::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove a key of `username`, returns false if the user has no key with this id.
+    pub async fn delete_key(&self, username: &str, id: i64) -> Result<bool, MegaError> {
+        let res = mega_signing_key::Entity::delete_many()
+            .filter(mega_signing_key::Column::Id.eq(id))
+            .filter(mega_signing_key::Column::Username.eq(username))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_snapshot_export;
+
+/// Exports of directory snapshots stored in the `mega_snapshot_export` table.
+#[derive(Clone)]
+pub struct SnapshotExportStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SnapshotExportStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SnapshotExportStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_exports(&self) -> Result<Vec<mega_snapshot_export::Model>, MegaError> {
+        Ok(mega_snapshot_export::Entity::find()
+            .order_by_asc(mega_snapshot_export::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_export(
+        &self,
+        name: &str,
+    ) -> Result<Option<mega_snapshot_export::Model>, MegaError> {
+        Ok(mega_snapshot_export::Entity::find()
+            .filter(mega_snapshot_export::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Enabled exports of `repo_path` run when a tag is created.
+    pub async fn tag_exports(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<mega_snapshot_export::Model>, MegaError> {
+        Ok(mega_snapshot_export::Entity::find()
+            .filter(mega_snapshot_export::Column::Enabled.eq(true))
+            .filter(mega_snapshot_export::Column::RepoPath.eq(repo_path))
+            .filter(mega_snapshot_export::Column::TagPattern.is_not_null())
+            .order_by_asc(mega_snapshot_export::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Enabled scheduled exports whose next run is at or before `now`.
+    pub async fn due_exports(
+        &self,
+        now: NaiveDateTime,
+    ) -> Result<Vec<mega_snapshot_export::Model>, MegaError> {
+        Ok(mega_snapshot_export::Entity::find()
+            .filter(mega_snapshot_export::Column::Enabled.eq(true))
+            .filter(mega_snapshot_export::Column::NextRunAt.lte(now))
+            .order_by_asc(mega_snapshot_export::Column::NextRunAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Move the next run of an export from `scheduled` to `next`. Returns false when another
+    /// server instance already did, so every scheduled run happens once.
+    pub async fn claim_run(
+        &self,
+        id: i64,
+        scheduled: NaiveDateTime,
+        next: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        let res = mega_snapshot_export::Entity::update_many()
+            .col_expr(mega_snapshot_export::Column::NextRunAt, Expr::value(next))
+            .filter(mega_snapshot_export::Column::Id.eq(id))
+            .filter(mega_snapshot_export::Column::NextRunAt.eq(scheduled))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Record the outcome of a run, `Ok` holding the exported commit and the key of its manifest.
+    pub async fn record_run(
+        &self,
+        id: i64,
+        result: Result<(&str, &str), &str>,
+    ) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let mut update = mega_snapshot_export::Entity::update_many()
+            .col_expr(mega_snapshot_export::Column::LastRunAt, Expr::value(now))
+            .col_expr(mega_snapshot_export::Column::UpdatedAt, Expr::value(now));
+        update = match result {
+            Ok((commit, manifest)) => update
+                .col_expr(
+                    mega_snapshot_export::Column::LastCommit,
+                    Expr::value(commit),
+                )
+                .col_expr(
+                    mega_snapshot_export::Column::LastManifest,
+                    Expr::value(manifest),
+                )
+                .col_expr(
+                    mega_snapshot_export::Column::LastError,
+                    Expr::value(Option::<String>::None),
+                ),
+            Err(error) => {
+                update.col_expr(mega_snapshot_export::Column::LastError, Expr::value(error))
+            }
+        };
+        update
+            .filter(mega_snapshot_export::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn save_export(&self, export: mega_snapshot_export::Model) -> Result<(), MegaError> {
+        mega_snapshot_export::Entity::insert(export.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored export with the same id.
+    pub async fn update_export(
+        &self,
+        export: mega_snapshot_export::Model,
+    ) -> Result<mega_snapshot_export::Model, MegaError> {
+        Ok(export
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove an export, returns false if there is none with this name.
+    pub async fn delete_export(&self, name: &str) -> Result<bool, MegaError> {
+        let res = mega_snapshot_export::Entity::delete_many()
+            .filter(mega_snapshot_export::Column::Name.eq(name))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_snippet, mega_snippet_file};
+
+/// Everyone sees the snippet, even anonymous callers.
+pub const VISIBILITY_PUBLIC: &str = "public";
+/// Every signed in user sees the snippet.
+pub const VISIBILITY_INTERNAL: &str = "internal";
+/// Only the owner of the snippet and admins see it.
+pub const VISIBILITY_PRIVATE: &str = "private";
+
+/// Snippets of code shared by users in `mega_snippet`, with their files in `mega_snippet_file`.
+/// The content of the files is kept as blobs in the object store, the files only name them.
+#[derive(Clone)]
+pub struct SnippetStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SnippetStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SnippetStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// The snippets with one of `visibilities` or owned by `viewer`, newest first. Only those of
+    /// `owner` when it is given.
+    pub async fn list_snippets(
+        &self,
+        owner: Option<&str>,
+        visibilities: &[&str],
+        viewer: Option<&str>,
+    ) -> Result<Vec<mega_snippet::Model>, MegaError> {
+        let mut visible =
+            Condition::any().add(mega_snippet::Column::Visibility.is_in(visibilities.to_vec()));
+        if let Some(viewer) = viewer {
+            visible = visible.add(mega_snippet::Column::Owner.eq(viewer));
+        }
+        let mut query = mega_snippet::Entity::find().filter(visible);
+        if let Some(owner) = owner {
+            query = query.filter(mega_snippet::Column::Owner.eq(owner));
+        }
+        Ok(query
+            .order_by_desc(mega_snippet::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_snippet(&self, id: i64) -> Result<Option<mega_snippet::Model>, MegaError> {
+        Ok(mega_snippet::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The files of a snippet, in their order.
+    pub async fn list_files(
+        &self,
+        snippet_id: i64,
+    ) -> Result<Vec<mega_snippet_file::Model>, MegaError> {
+        Ok(mega_snippet_file::Entity::find()
+            .filter(mega_snippet_file::Column::SnippetId.eq(snippet_id))
+            .order_by_asc(mega_snippet_file::Column::Position)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_snippet(
+        &self,
+        snippet: mega_snippet::Model,
+        files: Vec<mega_snippet_file::Model>,
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_snippet::Entity::insert(snippet.into_active_model())
+            .exec(&txn)
+            .await?;
+        mega_snippet_file::Entity::insert_many(
+            files.into_iter().map(IntoActiveModel::into_active_model),
+        )
+        .exec(&txn)
+        .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Overwrite the stored snippet with the same id, and replace its files when `files` is
+    /// given.
+    pub async fn update_snippet(
+        &self,
+        snippet: mega_snippet::Model,
+        files: Option<Vec<mega_snippet_file::Model>>,
+    ) -> Result<mega_snippet::Model, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        if let Some(files) = files {
+            mega_snippet_file::Entity::delete_many()
+                .filter(mega_snippet_file::Column::SnippetId.eq(snippet.id))
+                .exec(&txn)
+                .await?;
+            mega_snippet_file::Entity::insert_many(
+                files.into_iter().map(IntoActiveModel::into_active_model),
+            )
+            .exec(&txn)
+            .await?;
+        }
+        let snippet = snippet.into_active_model().reset_all().update(&txn).await?;
+        txn.commit().await?;
+        Ok(snippet)
+    }
+
+    /// Remove a snippet with its files, the blobs stay in the object store. Returns false if
+    /// there is no such snippet.
+    pub async fn delete_snippet(&self, id: i64) -> Result<bool, MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_snippet_file::Entity::delete_many()
+            .filter(mega_snippet_file::Column::SnippetId.eq(id))
+            .exec(&txn)
+            .await?;
+        let res = mega_snippet::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(res.rows_affected > 0)
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_ssh_key;
+
+/// SSH public keys registered for users, stored in the `mega_ssh_key` table.
+#[derive(Clone)]
+pub struct SshKeyStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SshKeyStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SshKeyStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_keys(&self, username: &str) -> Result<Vec<mega_ssh_key::Model>, MegaError> {
+        Ok(mega_ssh_key::Entity::find()
+            .filter(mega_ssh_key::Column::Username.eq(username))
+            .order_by_asc(mega_ssh_key::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn find_by_fingerprint(
+        &self,
+        fingerprint: &str,
+    ) -> Result<Option<mega_ssh_key::Model>, MegaError> {
+        Ok(mega_ssh_key::Entity::find()
+            .filter(mega_ssh_key::Column::Fingerprint.eq(fingerprint))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_key(&self, key: mega_ssh_key::Model) -> Result<(), MegaError> {
+        mega_ssh_key::Entity::insert(key.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Remove a key of `username`, returns false if the user has no key with this id.
+    pub async fn delete_key(&self, username: &str, id: i64) -> Result<bool, MegaError> {
+        let res = mega_ssh_key::Entity::delete_many()
+            .filter(mega_ssh_key::Column::Id.eq(id))
+            .filter(mega_ssh_key::Column::Username.eq(username))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    pub async fn touch_last_used(&self, id: i64) -> Result<(), MegaError> {
+        mega_ssh_key::Entity::update_many()
+            .col_expr(
+                mega_ssh_key::Column::LastUsedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(mega_ssh_key::Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
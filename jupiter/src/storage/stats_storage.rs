@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_commit_stat, mega_language_stat};
+
+/// Statistics of the default branch of repositories, gathered as commits are pushed so graphs
+/// don't walk the history on every request.
+///
+/// `mega_commit_stat` keeps the author, date and line counts of every commit of the branch and
+/// never changes a row once stored. `mega_language_stat` keeps the files and bytes of each
+/// language in every directory of the tree of the latest commit counted, those below it
+/// included, replaced as a whole when it moves.
+#[derive(Clone)]
+pub struct StatsStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl StatsStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        StatsStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    /// Which of the commits `commit_ids` of a repository are counted already.
+    pub async fn counted_commits(
+        &self,
+        repo_path: &str,
+        commit_ids: &[String],
+    ) -> Result<HashSet<String>, MegaError> {
+        if commit_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+        Ok(mega_commit_stat::Entity::find()
+            .filter(mega_commit_stat::Column::RepoPath.eq(repo_path))
+            .filter(mega_commit_stat::Column::CommitId.is_in(commit_ids.iter().cloned()))
+            .all(self.get_connection())
+            .await?
+            .into_iter()
+            .map(|stat| stat.commit_id)
+            .collect())
+    }
+
+    /// Store the stat of a commit, unless it is counted already.
+    pub async fn save_commit_stat(&self, stat: mega_commit_stat::Model) -> Result<(), MegaError> {
+        mega_commit_stat::Entity::insert(stat.into_active_model())
+            .on_conflict(OnConflict::new().do_nothing().to_owned())
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// The stats of the commits of a repository authored in `[since, until)`, oldest first.
+    pub async fn list_commit_stats(
+        &self,
+        repo_path: &str,
+        since: Option<chrono::NaiveDateTime>,
+        until: Option<chrono::NaiveDateTime>,
+    ) -> Result<Vec<mega_commit_stat::Model>, MegaError> {
+        let mut query = mega_commit_stat::Entity::find()
+            .filter(mega_commit_stat::Column::RepoPath.eq(repo_path));
+        if let Some(since) = since {
+            query = query.filter(mega_commit_stat::Column::AuthoredAt.gte(since));
+        }
+        if let Some(until) = until {
+            query = query.filter(mega_commit_stat::Column::AuthoredAt.lt(until));
+        }
+        Ok(query
+            .order_by_asc(mega_commit_stat::Column::AuthoredAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The languages of the latest commit counted in a repository, largest first. Only those
+    /// of the directory `path`, `""` for the root, when it is given.
+    pub async fn language_stats(
+        &self,
+        repo_path: &str,
+        path: Option<&str>,
+    ) -> Result<Vec<mega_language_stat::Model>, MegaError> {
+        let mut query = mega_language_stat::Entity::find()
+            .filter(mega_language_stat::Column::RepoPath.eq(repo_path));
+        if let Some(path) = path {
+            query = query.filter(mega_language_stat::Column::Path.eq(path));
+        }
+        Ok(query
+            .order_by_desc(mega_language_stat::Column::Bytes)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Replace the languages of a repository with `stats`.
+    pub async fn replace_language_stats(
+        &self,
+        repo_path: &str,
+        stats: Vec<mega_language_stat::Model>,
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_language_stat::Entity::delete_many()
+            .filter(mega_language_stat::Column::RepoPath.eq(repo_path))
+            .exec(&txn)
+            .await?;
+        if !stats.is_empty() {
+            mega_language_stat::Entity::insert_many(
+                stats.into_iter().map(IntoActiveModel::into_active_model),
+            )
+            .exec(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+}
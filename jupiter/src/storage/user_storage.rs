@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_user;
+
+/// Accounts stored in the `mega_user` table.
+#[derive(Clone)]
+pub struct UserStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl UserStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        UserStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn get_user_by_name(&self, name: &str) -> Result<Option<mega_user::Model>, MegaError> {
+        Ok(mega_user::Entity::find()
+            .filter(mega_user::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_users_by_names(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<mega_user::Model>, MegaError> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(mega_user::Entity::find()
+            .filter(mega_user::Column::Name.is_in(names.iter().cloned()))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Insert the user, or update its profile and password if the name is taken.
+    pub async fn save_user(&self, user: mega_user::Model) -> Result<(), MegaError> {
+        mega_user::Entity::insert(user.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_user::Column::Name)
+                    .update_columns([
+                        mega_user::Column::DisplayName,
+                        mega_user::Column::Email,
+                        mega_user::Column::PasswordHash,
+                        mega_user::Column::IsAdmin,
+                        mega_user::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
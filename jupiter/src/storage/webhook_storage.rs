@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+
+use common::errors::MegaError;
+use db_entity::mega_webhook;
+
+/// Webhook subscriptions stored in the `mega_webhook` table.
+#[derive(Clone)]
+pub struct WebhookStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl WebhookStorage {
+    pub fn new(connection: Arc<DatabaseConnection>) -> Self {
+        WebhookStorage { connection }
+    }
+
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn list_webhooks(&self) -> Result<Vec<mega_webhook::Model>, MegaError> {
+        Ok(mega_webhook::Entity::find()
+            .order_by_asc(mega_webhook::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_webhook(&self, name: &str) -> Result<Option<mega_webhook::Model>, MegaError> {
+        Ok(mega_webhook::Entity::find()
+            .filter(mega_webhook::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Enabled webhooks of `repo_path` or one of its parents, `repo_path` being normalized.
+    pub async fn webhooks_for(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<mega_webhook::Model>, MegaError> {
+        let ancestors: Vec<&str> = repo_path
+            .match_indices('/')
+            .skip(1)
+            .map(|(i, _)| &repo_path[..i])
+            .chain(["/", repo_path])
+            .collect();
+        Ok(mega_webhook::Entity::find()
+            .filter(mega_webhook::Column::Enabled.eq(true))
+            .filter(mega_webhook::Column::RepoPath.is_in(ancestors))
+            .order_by_asc(mega_webhook::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_webhook(&self, webhook: mega_webhook::Model) -> Result<(), MegaError> {
+        mega_webhook::Entity::insert(webhook.into_active_model())
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrite every column of the stored webhook with the same id.
+    pub async fn update_webhook(
+        &self,
+        webhook: mega_webhook::Model,
+    ) -> Result<mega_webhook::Model, MegaError> {
+        Ok(webhook
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a webhook, returns false if there is none with this name.
+    pub async fn delete_webhook(&self, name: &str) -> Result<bool, MegaError> {
+        let res = mega_webhook::Entity::delete_many()
+            .filter(mega_webhook::Column::Name.eq(name))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
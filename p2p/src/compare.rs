@@ -0,0 +1,251 @@
+//! Compare a repository with the same repository on another node.
+//!
+//! Nodes exchange a summary of their refs and commit graph instead of objects, so the sync state
+//! of a path can be reviewed before pulling anything.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use common::errors::MegaError;
+use common::utils::ZERO_ID;
+use storage::driver::database::storage::ObjectStorage;
+
+use crate::get_pack_protocol;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitSummary {
+    pub id: String,
+    pub parents: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefSummary {
+    pub name: String,
+    pub id: String,
+}
+
+/// What a node knows about a repository, without the objects.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoSummary {
+    /// Tip of the path, `None` when the node does not have it
+    pub head: Option<String>,
+    pub refs: Vec<RefSummary>,
+    pub commits: Vec<CommitSummary>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+    Identical,
+    /// The local node has commits the peer does not
+    Ahead,
+    /// The peer has commits the local node does not, a pull fast-forwards
+    Behind,
+    /// Both nodes have commits the other does not
+    Diverged,
+    LocalMissing,
+    RemoteMissing,
+}
+
+/// A ref pointing to different commits on the two nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RefDifference {
+    pub name: String,
+    pub local: Option<String>,
+    pub remote: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepoComparison {
+    pub status: SyncStatus,
+    pub local_head: Option<String>,
+    pub remote_head: Option<String>,
+    /// Latest commit both heads descend from
+    pub merge_base: Option<String>,
+    /// Commits only the local node has, newest first
+    pub ahead: Vec<String>,
+    /// Commits only the peer has, newest first
+    pub behind: Vec<String>,
+    pub refs: Vec<RefDifference>,
+}
+
+/// Summarize the repository at `path` of the local storage.
+pub async fn repo_summary(
+    path: &str,
+    storage: Arc<dyn ObjectStorage>,
+) -> Result<RepoSummary, MegaError> {
+    let pack_protocol = get_pack_protocol(path, storage.clone());
+    let head = pack_protocol.get_head_object_id(Path::new(path)).await;
+    if head == ZERO_ID {
+        return Ok(RepoSummary::default());
+    }
+    let refs = storage
+        .get_all_refs_by_path(path)
+        .await?
+        .into_iter()
+        .map(|r| RefSummary {
+            name: r.ref_name,
+            id: r.ref_git_id,
+        })
+        .collect();
+    let commits = storage
+        .get_all_commits_by_path(path)
+        .await?
+        .into_iter()
+        .map(|c| CommitSummary {
+            id: c.git_id,
+            parents: c.pid,
+        })
+        .collect();
+    Ok(RepoSummary {
+        head: Some(head),
+        refs,
+        commits,
+    })
+}
+
+/// Commits reachable from `head`, newest first. A commit missing from `commits`, like the one
+/// generated for a subdirectory, is reachable but its parents are unknown.
+fn ancestors(head: &str, commits: &[CommitSummary]) -> Vec<String> {
+    let parents: HashMap<&str, &[String]> = commits
+        .iter()
+        .map(|c| (c.id.as_str(), c.parents.as_slice()))
+        .collect();
+    let mut seen = HashSet::from([head.to_owned()]);
+    let mut queue = VecDeque::from([head.to_owned()]);
+    let mut result = Vec::new();
+    while let Some(id) = queue.pop_front() {
+        for parent in parents.get(id.as_str()).copied().unwrap_or_default() {
+            if seen.insert(parent.clone()) {
+                queue.push_back(parent.clone());
+            }
+        }
+        result.push(id);
+    }
+    result
+}
+
+fn ref_differences(local: &[RefSummary], remote: &[RefSummary]) -> Vec<RefDifference> {
+    let remote_ids: HashMap<&str, &str> = remote
+        .iter()
+        .map(|r| (r.name.as_str(), r.id.as_str()))
+        .collect();
+    let local_ids: HashMap<&str, &str> = local
+        .iter()
+        .map(|r| (r.name.as_str(), r.id.as_str()))
+        .collect();
+    let mut names: Vec<&str> = local_ids.keys().chain(remote_ids.keys()).copied().collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| local_ids.get(name) != remote_ids.get(name))
+        .map(|name| RefDifference {
+            name: name.to_owned(),
+            local: local_ids.get(name).map(|id| id.to_string()),
+            remote: remote_ids.get(name).map(|id| id.to_string()),
+        })
+        .collect()
+}
+
+/// Compare the local summary of a repository with the one of a peer.
+pub fn compare(local: &RepoSummary, remote: &RepoSummary) -> RepoComparison {
+    let mut comparison = RepoComparison {
+        status: SyncStatus::Identical,
+        local_head: local.head.clone(),
+        remote_head: remote.head.clone(),
+        merge_base: None,
+        ahead: Vec::new(),
+        behind: Vec::new(),
+        refs: ref_differences(&local.refs, &remote.refs),
+    };
+    let (local_head, remote_head) = match (&local.head, &remote.head) {
+        (Some(local_head), Some(remote_head)) => (local_head, remote_head),
+        (None, _) => {
+            comparison.status = SyncStatus::LocalMissing;
+            return comparison;
+        }
+        (_, None) => {
+            comparison.status = SyncStatus::RemoteMissing;
+            return comparison;
+        }
+    };
+    let local_ancestors = ancestors(local_head, &local.commits);
+    let remote_ancestors = ancestors(remote_head, &remote.commits);
+    let local_set: HashSet<&String> = local_ancestors.iter().collect();
+    let remote_set: HashSet<&String> = remote_ancestors.iter().collect();
+    comparison.merge_base = local_ancestors
+        .iter()
+        .find(|id| remote_set.contains(id))
+        .cloned();
+    comparison.ahead = local_ancestors
+        .iter()
+        .filter(|id| !remote_set.contains(id))
+        .cloned()
+        .collect();
+    comparison.behind = remote_ancestors
+        .iter()
+        .filter(|id| !local_set.contains(id))
+        .cloned()
+        .collect();
+    comparison.status = match (comparison.ahead.is_empty(), comparison.behind.is_empty()) {
+        (true, true) => SyncStatus::Identical,
+        (false, true) => SyncStatus::Ahead,
+        (true, false) => SyncStatus::Behind,
+        (false, false) => SyncStatus::Diverged,
+    };
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, CommitSummary, RepoSummary, SyncStatus};
+
+    fn summary(head: &str, graph: &[(&str, &[&str])]) -> RepoSummary {
+        RepoSummary {
+            head: Some(head.to_owned()),
+            refs: Vec::new(),
+            commits: graph
+                .iter()
+                .map(|(id, parents)| CommitSummary {
+                    id: id.to_string(),
+                    parents: parents.iter().map(|p| p.to_string()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_compare_behind() {
+        let local = summary("b", &[("a", &[]), ("b", &["a"])]);
+        let remote = summary(
+            "d",
+            &[("a", &[]), ("b", &["a"]), ("c", &["b"]), ("d", &["c"])],
+        );
+        let comparison = compare(&local, &remote);
+        assert_eq!(comparison.status, SyncStatus::Behind);
+        assert_eq!(comparison.behind, vec!["d", "c"]);
+        assert!(comparison.ahead.is_empty());
+        assert_eq!(comparison.merge_base.as_deref(), Some("b"));
+        assert_eq!(compare(&remote, &local).status, SyncStatus::Ahead);
+    }
+
+    #[test]
+    fn test_compare_diverged() {
+        let local = summary("c", &[("a", &[]), ("b", &["a"]), ("c", &["b"])]);
+        let remote = summary("d", &[("a", &[]), ("b", &["a"]), ("d", &["b"])]);
+        let comparison = compare(&local, &remote);
+        assert_eq!(comparison.status, SyncStatus::Diverged);
+        assert_eq!(comparison.ahead, vec!["c"]);
+        assert_eq!(comparison.behind, vec!["d"]);
+        assert_eq!(comparison.merge_base.as_deref(), Some("b"));
+        assert_eq!(compare(&local, &local).status, SyncStatus::Identical);
+        assert_eq!(
+            compare(&RepoSummary::default(), &remote).status,
+            SyncStatus::LocalMissing
+        );
+    }
+}
@@ -81,6 +81,7 @@ pub fn mega_routers() -> Router<P2pNodeState> {
         .route("/clone-object", get(mega_clone_obj))
         .route("/pull", get(mega_pull))
         .route("/pull-object", get(mega_pull_obj))
+        .route("/compare", get(mega_compare))
 }
 
 async fn life_cycle_check() -> Result<impl IntoResponse, (StatusCode, String)> {
@@ -145,6 +146,16 @@ async fn mega_pull_obj(
         .await
 }
 
+async fn mega_compare(
+    Query(query): Query<HashMap<String, String>>,
+    state: State<P2pNodeState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mega_address = query.get("mega_address").unwrap();
+    get_http_handler(state)
+        .mega_compare(mega_address.clone())
+        .await
+}
+
 pub fn nostr_routers() -> Router<P2pNodeState> {
     Router::new()
         .route("/subscribe", get(nostr_subscribe))
@@ -16,8 +16,11 @@ use secp256k1::{rand, KeyPair, Secp256k1};
 use common::utils;
 use storage::driver::database::storage::ObjectStorage;
 
+use crate::compare::{self, repo_summary};
 use crate::get_utc_timestamp;
-use crate::network::behaviour::{GitInfoRefsReq, GitObjectReq, GitUploadPackReq, GitUploadPackRes};
+use crate::network::behaviour::{
+    GitInfoRefsReq, GitObjectReq, GitRepoSummaryReq, GitUploadPackReq, GitUploadPackRes,
+};
 use crate::network::{get_all_git_obj_ids, Client};
 use crate::node::{Fork, MegaRepoInfo};
 use crate::nostr::client_message::{ClientMessage, Filter, SubscriptionId};
@@ -200,6 +203,31 @@ impl HttpHandler {
         }
     }
 
+    /// Compare the local repository with the same repository on a peer, to review what a pull
+    /// would bring in.
+    pub async fn mega_compare(
+        &mut self,
+        mega_address: String,
+    ) -> Result<impl IntoResponse, (StatusCode, String)> {
+        let (peer_id, repo_name) = match parse_mega_address(mega_address.as_str()) {
+            Ok((peer_id, repo_name)) => (peer_id, repo_name),
+            Err(e) => {
+                eprintln!("{}", e);
+                return Err((StatusCode::BAD_REQUEST, e));
+            }
+        };
+        let path = get_repo_full_path(repo_name);
+        let local = repo_summary(&path, self.storage.clone())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let remote = self
+            .network_client
+            .git_repo_summary(peer_id, GitRepoSummaryReq(path))
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+        Ok(Json(compare::compare(&local, &remote.0)))
+    }
+
     pub async fn mega_clone_or_pull_obj(
         &mut self,
         repo_name: String,
@@ -6,6 +6,7 @@ use libp2p::{dcutr, identify, relay, rendezvous, request_response};
 use serde::{Deserialize, Serialize};
 
 use crate::cbor;
+use crate::compare::RepoSummary;
 use crate::nostr::{NostrReq, NostrRes};
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "Event")]
@@ -18,6 +19,7 @@ pub struct Behaviour {
     pub git_upload_pack: cbor::Behaviour<GitUploadPackReq, GitUploadPackRes>,
     pub git_info_refs: cbor::Behaviour<GitInfoRefsReq, GitInfoRefsRes>,
     pub git_object: cbor::Behaviour<GitObjectReq, GitObjectRes>,
+    pub git_repo_summary: cbor::Behaviour<GitRepoSummaryReq, GitRepoSummaryRes>,
     pub nostr: cbor::Behaviour<NostrReq, NostrRes>,
 }
 
@@ -46,6 +48,15 @@ pub struct GitObjectReq(pub String, pub Vec<String>);
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GitObjectRes(pub Vec<objects::Model>);
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitRepoSummaryReq(
+    //path
+    pub String,
+);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitRepoSummaryRes(pub RepoSummary);
+
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum Event {
@@ -57,6 +68,7 @@ pub enum Event {
     GitUploadPack(request_response::Event<GitUploadPackReq, GitUploadPackRes>),
     GitInfoRefs(request_response::Event<GitInfoRefsReq, GitInfoRefsRes>),
     GitObject(request_response::Event<GitObjectReq, GitObjectRes>),
+    GitRepoSummary(request_response::Event<GitRepoSummaryReq, GitRepoSummaryRes>),
     Nostr(request_response::Event<NostrReq, NostrRes>),
 }
 
@@ -108,6 +120,12 @@ impl From<request_response::Event<GitObjectReq, GitObjectRes>> for Event {
     }
 }
 
+impl From<request_response::Event<GitRepoSummaryReq, GitRepoSummaryRes>> for Event {
+    fn from(event: request_response::Event<GitRepoSummaryReq, GitRepoSummaryRes>) -> Self {
+        Event::GitRepoSummary(event)
+    }
+}
+
 impl From<request_response::Event<NostrReq, NostrRes>> for Event {
     fn from(event: request_response::Event<NostrReq, NostrRes>) -> Self {
         Event::Nostr(event)
@@ -16,12 +16,13 @@ use libp2p::{
 };
 use storage::driver::database::storage::ObjectStorage;
 
+use crate::compare::repo_summary;
 use crate::network::{get_all_git_obj_ids, git_upload_pack_handler};
 use crate::{get_pack_protocol, nostr::NostrRes};
 
 use behaviour::{Behaviour, Event, GitObjectRes};
 
-use super::behaviour::{GitInfoRefsRes, GitUploadPackRes};
+use super::behaviour::{GitInfoRefsRes, GitRepoSummaryRes, GitUploadPackRes};
 use super::{behaviour, Command};
 
 pub const NAMESPACE: &str = "rendezvous_mega";
@@ -42,6 +43,8 @@ pub(crate) struct EventLoop {
         HashMap<OutboundRequestId, oneshot::Sender<Result<GitInfoRefsRes, OutboundFailure>>>,
     pending_git_object:
         HashMap<OutboundRequestId, oneshot::Sender<Result<GitObjectRes, OutboundFailure>>>,
+    pending_git_repo_summary:
+        HashMap<OutboundRequestId, oneshot::Sender<Result<GitRepoSummaryRes, OutboundFailure>>>,
     pending_nostr: HashMap<OutboundRequestId, oneshot::Sender<Result<NostrRes, OutboundFailure>>>,
 }
 
@@ -64,6 +67,7 @@ impl EventLoop {
             pending_git_upload_pack: Default::default(),
             pending_git_info_refs: Default::default(),
             pending_git_object: Default::default(),
+            pending_git_repo_summary: Default::default(),
             pending_nostr: Default::default(),
         }
     }
@@ -437,6 +441,56 @@ impl EventLoop {
                     sender.send(Err(error)).expect("Receiver not to be dropped");
                 }
             }
+            //GitRepoSummary events
+            SwarmEvent::Behaviour(Event::GitRepoSummary(request_response::Event::Message {
+                message,
+                ..
+            })) => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => {
+                    let path = request.0;
+                    tracing::info!("Receive git repo summary request, path: {}", path);
+                    let summary = match repo_summary(&path, self.storage.clone()).await {
+                        Ok(summary) => summary,
+                        Err(e) => {
+                            tracing::error!("{:?}", e);
+                            return;
+                        }
+                    };
+                    let _ = self
+                        .swarm
+                        .behaviour_mut()
+                        .git_repo_summary
+                        .send_response(channel, GitRepoSummaryRes(summary));
+                }
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(sender) = self.pending_git_repo_summary.remove(&request_id) {
+                        sender
+                            .send(Ok(response))
+                            .expect("Receiver not to be dropped");
+                    }
+                }
+            },
+            SwarmEvent::Behaviour(Event::GitRepoSummary(
+                request_response::Event::OutboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                },
+            )) => {
+                tracing::error!(
+                    "GitRepoSummary OutboundFailure:\n {} \nfrom {}",
+                    error,
+                    peer
+                );
+                if let Some(sender) = self.pending_git_repo_summary.remove(&request_id) {
+                    sender.send(Err(error)).expect("Receiver not to be dropped");
+                }
+            }
             //Nostr events
             SwarmEvent::Behaviour(Event::Nostr(request_response::Event::Message {
                 message,
@@ -575,6 +629,18 @@ impl EventLoop {
                     .send_request(&peer_id, git_object_req);
                 self.pending_git_object.insert(request_id, sender);
             }
+            Command::GitRepoSummary {
+                peer_id,
+                git_repo_summary_req,
+                sender,
+            } => {
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .git_repo_summary
+                    .send_request(&peer_id, git_repo_summary_req);
+                self.pending_git_repo_summary.insert(request_id, sender);
+            }
             Command::Nostr {
                 peer_id,
                 nostr_req,
@@ -37,8 +37,8 @@ use crate::{
 
 use self::{
     behaviour::{
-        Behaviour, GitInfoRefsReq, GitInfoRefsRes, GitObjectReq, GitObjectRes, GitUploadPackReq,
-        GitUploadPackRes,
+        Behaviour, GitInfoRefsReq, GitInfoRefsRes, GitObjectReq, GitObjectRes, GitRepoSummaryReq,
+        GitRepoSummaryRes, GitUploadPackReq, GitUploadPackRes,
     },
     event_handler::EventLoop,
 };
@@ -89,6 +89,14 @@ pub(crate) async fn new(
                 [(StreamProtocol::new("/mega/git_obj"), ProtocolSupport::Full)],
                 request_response::Config::default().with_request_timeout(Duration::from_secs(60)),
             ),
+            // refs and commit graph, to compare with a peer before pulling
+            git_repo_summary: cbor::Behaviour::new(
+                [(
+                    StreamProtocol::new("/mega/git_repo_summary"),
+                    ProtocolSupport::Full,
+                )],
+                request_response::Config::default().with_request_timeout(Duration::from_secs(60)),
+            ),
             nostr: cbor::Behaviour::new(
                 [(StreamProtocol::new("/mega/nostr"), ProtocolSupport::Full)],
                 request_response::Config::default(),
@@ -231,6 +239,23 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.")
     }
 
+    pub(crate) async fn git_repo_summary(
+        &mut self,
+        peer_id: PeerId,
+        git_repo_summary_req: GitRepoSummaryReq,
+    ) -> Result<GitRepoSummaryRes, OutboundFailure> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::GitRepoSummary {
+                peer_id,
+                git_repo_summary_req,
+                sender,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
     pub(crate) async fn nostr(
         &mut self,
         peer_id: PeerId,
@@ -287,6 +312,11 @@ pub enum Command {
         git_object_req: GitObjectReq,
         sender: oneshot::Sender<Result<GitObjectRes, OutboundFailure>>,
     },
+    GitRepoSummary {
+        peer_id: PeerId,
+        git_repo_summary_req: GitRepoSummaryReq,
+        sender: oneshot::Sender<Result<GitRepoSummaryRes, OutboundFailure>>,
+    },
     Nostr {
         peer_id: PeerId,
         nostr_req: NostrReq,
@@ -309,7 +339,11 @@ async fn git_upload_pack_handler(
     tracing::info!("want: {:?}, have: {:?}", want, have);
     if have.is_empty() {
         //clone
-        let send_pack_data = match pack_protocol.get_full_pack_data(Path::new(path)).await {
+        let send_pack_data = match pack_protocol
+            .get_full_pack_data(Path::new(path))
+            .await
+            .and_then(|pack| Ok(pack.into_vec()?))
+        {
             Ok(send_pack_data) => send_pack_data,
             Err(e) => {
                 tracing::error!("{}", e);
@@ -319,7 +353,11 @@ async fn git_upload_pack_handler(
         Ok((send_pack_data, object_id))
     } else {
         //pull
-        let send_pack_data = match pack_protocol.get_incremental_pack_data(want, have).await {
+        let send_pack_data = match pack_protocol
+            .get_incremental_pack_data(want, have)
+            .await
+            .and_then(|pack| Ok(pack.into_vec()?))
+        {
             Ok(send_pack_data) => send_pack_data,
             Err(e) => {
                 tracing::error!("{}", e);
@@ -73,23 +73,33 @@ CREATE TABLE IF NOT EXISTS "mega_mr" (
   "id" BIGINT PRIMARY KEY,
   "mr_link" VARCHAR(40) NOT NULL,
   "mr_msg" VARCHAR(255) NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "source_ref" TEXT NOT NULL,
+  "target_ref" TEXT NOT NULL,
+  "merge_commit_id" VARCHAR(40),
   "merge_date" TIMESTAMP,
   "status" VARCHAR(20) NOT NULL,
+  "milestone_id" BIGINT,
   "created_at" TIMESTAMP NOT NULL,
   "updated_at" TIMESTAMP NOT NULL
 );
 CREATE TABLE IF NOT EXISTS "mega_issue" (
   "id" BIGINT PRIMARY KEY,
   "number" BIGINT NOT NULL,
+  "repo_path" TEXT NOT NULL,
   "title" VARCHAR(255) NOT NULL,
+  "body" TEXT NOT NULL,
   "sender_name" VARCHAR(255) NOT NULL,
   "sender_id" BIGINT NOT NULL,
   "state" VARCHAR(255) NOT NULL,
+  "milestone_id" BIGINT,
   "created_at" TIMESTAMP NOT NULL,
   "updated_at" TIMESTAMP NOT NULL,
-  "closed_at" TIMESTAMP DEFAULT NULL
+  "closed_at" TIMESTAMP DEFAULT NULL,
+  CONSTRAINT uniq_issue_repo_number UNIQUE (repo_path, number)
 );
 CREATE INDEX "idx_info_mr_link" ON "mega_mr" ("mr_link");
+CREATE INDEX "idx_mr_repo_path" ON "mega_mr" ("repo_path");
 CREATE TABLE IF NOT EXISTS "git_refs" (
   "id" BIGINT PRIMARY KEY,
   "repo_id" BIGINT NOT NULL,
@@ -216,4 +226,698 @@ CREATE TABLE IF NOT EXISTS "lfs_objects" (
   "oid" VARCHAR(64) PRIMARY KEY,
   "size" BIGINT,
   "exist" BOOLEAN
-);
\ No newline at end of file
+);
+CREATE TABLE IF NOT EXISTS "mega_feature_flag" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(128) NOT NULL,
+  "description" TEXT,
+  "enabled" BOOLEAN NOT NULL,
+  "enabled_orgs" TEXT [] NOT NULL,
+  "disabled_orgs" TEXT [] NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_ff_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_user" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(128) NOT NULL,
+  "display_name" VARCHAR(255),
+  "email" VARCHAR(255),
+  "password_hash" TEXT,
+  "is_admin" BOOLEAN NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_user_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_ssh_key" (
+  "id" BIGINT PRIMARY KEY,
+  "username" VARCHAR(128) NOT NULL,
+  "title" VARCHAR(255) NOT NULL,
+  "key_type" VARCHAR(32) NOT NULL,
+  "public_key" TEXT NOT NULL,
+  "fingerprint" VARCHAR(64) NOT NULL,
+  "expires_at" TIMESTAMP,
+  "last_used_at" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_ssh_key_fingerprint UNIQUE (fingerprint)
+);
+CREATE INDEX "idx_ssh_key_username" ON "mega_ssh_key" ("username");
+CREATE TABLE IF NOT EXISTS "mega_signing_key" (
+  "id" BIGINT PRIMARY KEY,
+  "username" VARCHAR(128) NOT NULL,
+  "title" VARCHAR(255) NOT NULL,
+  "key_type" VARCHAR(8) NOT NULL,
+  "key_id" VARCHAR(64) NOT NULL,
+  "subkey_ids" TEXT [] NOT NULL,
+  "emails" TEXT [] NOT NULL,
+  "public_key" TEXT NOT NULL,
+  "challenge" VARCHAR(64) NOT NULL,
+  "verified" BOOLEAN NOT NULL,
+  "verified_at" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_signing_key_id UNIQUE (key_id)
+);
+CREATE INDEX "idx_signing_key_username" ON "mega_signing_key" ("username");
+CREATE TABLE IF NOT EXISTS "mega_access_token" (
+  "id" BIGINT PRIMARY KEY,
+  "username" VARCHAR(128) NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "token_hash" VARCHAR(64) NOT NULL,
+  "scopes" TEXT [] NOT NULL,
+  "expires_at" TIMESTAMP,
+  "last_used_at" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_access_token_hash UNIQUE (token_hash)
+);
+CREATE INDEX "idx_access_token_username" ON "mega_access_token" ("username");
+CREATE TABLE IF NOT EXISTS "mega_org" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(128) NOT NULL,
+  "display_name" VARCHAR(255),
+  "description" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_org_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_org_member" (
+  "id" BIGINT PRIMARY KEY,
+  "org_id" BIGINT NOT NULL,
+  "username" VARCHAR(128) NOT NULL,
+  "role" VARCHAR(20) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_org_member UNIQUE (org_id, username)
+);
+CREATE INDEX "idx_org_member_username" ON "mega_org_member" ("username");
+CREATE TABLE IF NOT EXISTS "mega_mr_thread" (
+  "id" BIGINT PRIMARY KEY,
+  "mr_id" BIGINT NOT NULL,
+  "path" TEXT,
+  "line" INTEGER,
+  "commit_id" VARCHAR(40),
+  "resolved" BOOLEAN NOT NULL,
+  "resolved_by" VARCHAR(128),
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mr_thread_mr_id" ON "mega_mr_thread" ("mr_id");
+CREATE TABLE IF NOT EXISTS "mega_mr_comment" (
+  "id" BIGINT PRIMARY KEY,
+  "mr_id" BIGINT NOT NULL,
+  "thread_id" BIGINT NOT NULL,
+  "author" VARCHAR(128) NOT NULL,
+  "body" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mr_comment_mr_id" ON "mega_mr_comment" ("mr_id");
+CREATE TABLE IF NOT EXISTS "mega_mr_review" (
+  "id" BIGINT PRIMARY KEY,
+  "mr_id" BIGINT NOT NULL,
+  "reviewer" VARCHAR(128) NOT NULL,
+  "state" VARCHAR(20) NOT NULL,
+  "body" TEXT,
+  "commit_id" VARCHAR(40),
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_mr_review_reviewer UNIQUE (mr_id, reviewer)
+);
+CREATE TABLE IF NOT EXISTS "mega_issue_ref" (
+  "id" BIGINT PRIMARY KEY,
+  "issue_id" BIGINT NOT NULL,
+  "source_type" VARCHAR(20) NOT NULL,
+  "source_id" VARCHAR(40) NOT NULL,
+  "closes" BOOLEAN NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_issue_ref_source UNIQUE (issue_id, source_type, source_id)
+);
+CREATE TABLE IF NOT EXISTS "mega_ref_trigger" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(128) NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "source_ref" TEXT NOT NULL,
+  "ref_type" VARCHAR(20) NOT NULL,
+  "name_template" TEXT NOT NULL,
+  "schedule" VARCHAR(64) NOT NULL,
+  "enabled" BOOLEAN NOT NULL,
+  "next_run_at" TIMESTAMP NOT NULL,
+  "last_run_at" TIMESTAMP,
+  "last_ref" TEXT,
+  "last_error" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_ref_trigger_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_snapshot_export" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(128) NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "source_ref" TEXT NOT NULL,
+  "paths" TEXT NOT NULL,
+  "format" VARCHAR(16) NOT NULL,
+  "schedule" VARCHAR(64),
+  "tag_pattern" TEXT,
+  "enabled" BOOLEAN NOT NULL,
+  "next_run_at" TIMESTAMP,
+  "last_run_at" TIMESTAMP,
+  "last_commit" VARCHAR(40),
+  "last_manifest" TEXT,
+  "last_error" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_snapshot_export_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_ref_audit" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "ref_name" TEXT NOT NULL,
+  "old_id" VARCHAR(40),
+  "new_id" VARCHAR(40) NOT NULL,
+  "actor" VARCHAR(128) NOT NULL,
+  "reason" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_ref_audit_repo_path" ON "mega_ref_audit" ("repo_path");
+CREATE TABLE IF NOT EXISTS "mega_label" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "name" VARCHAR(64) NOT NULL,
+  "color" VARCHAR(7) NOT NULL,
+  "description" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_label_repo_name UNIQUE (repo_path, name)
+);
+CREATE TABLE IF NOT EXISTS "mega_milestone" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "title" VARCHAR(255) NOT NULL,
+  "description" TEXT,
+  "due_date" DATE,
+  "state" VARCHAR(20) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  "closed_at" TIMESTAMP,
+  CONSTRAINT uniq_milestone_repo_title UNIQUE (repo_path, title)
+);
+CREATE TABLE IF NOT EXISTS "mega_label_link" (
+  "id" BIGINT PRIMARY KEY,
+  "item_type" VARCHAR(20) NOT NULL,
+  "item_id" BIGINT NOT NULL,
+  "label_id" BIGINT NOT NULL,
+  CONSTRAINT uniq_label_link UNIQUE (item_type, item_id, label_id)
+);
+CREATE INDEX "idx_label_link_label_id" ON "mega_label_link" ("label_id");
+CREATE TABLE IF NOT EXISTS "mega_assignee" (
+  "id" BIGINT PRIMARY KEY,
+  "item_type" VARCHAR(20) NOT NULL,
+  "item_id" BIGINT NOT NULL,
+  "username" VARCHAR(128) NOT NULL,
+  CONSTRAINT uniq_assignee UNIQUE (item_type, item_id, username)
+);
+CREATE INDEX "idx_assignee_username" ON "mega_assignee" ("username");
+CREATE TABLE IF NOT EXISTS "mega_path_redirect" (
+  "id" BIGINT PRIMARY KEY,
+  "from_path" TEXT NOT NULL,
+  "to_path" TEXT NOT NULL,
+  "commit_id" VARCHAR(40),
+  "actor" VARCHAR(128) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_path_redirect_from UNIQUE (from_path)
+);
+CREATE TABLE IF NOT EXISTS "mega_ci_log" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "job_name" VARCHAR(255) NOT NULL,
+  "status" VARCHAR(20) NOT NULL,
+  "size" BIGINT NOT NULL,
+  "chunks" INTEGER NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  "finished_at" TIMESTAMP,
+  CONSTRAINT uniq_ci_log_job UNIQUE (repo_path, commit_id, job_name)
+);
+CREATE INDEX "idx_ci_log_updated_at" ON "mega_ci_log" ("updated_at");
+CREATE TABLE IF NOT EXISTS "mega_ci_log_chunk" (
+  "id" BIGINT PRIMARY KEY,
+  "log_id" BIGINT NOT NULL,
+  "seq" INTEGER NOT NULL,
+  "offset" BIGINT NOT NULL,
+  "size" BIGINT NOT NULL,
+  "object_id" VARCHAR(64) NOT NULL,
+  CONSTRAINT uniq_ci_log_chunk_seq UNIQUE (log_id, seq)
+);
+CREATE TABLE IF NOT EXISTS "mega_event" (
+  "id" BIGINT PRIMARY KEY,
+  "event_type" VARCHAR(40) NOT NULL,
+  "action" VARCHAR(40) NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "actor" VARCHAR(128),
+  "payload" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_event_created_at" ON "mega_event" ("created_at");
+CREATE TABLE IF NOT EXISTS "mega_mailmap" (
+  "id" BIGINT PRIMARY KEY,
+  "email" VARCHAR(255) NOT NULL,
+  "display_name" VARCHAR(255) NOT NULL,
+  "display_email" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_mailmap_email UNIQUE (email)
+);
+CREATE TABLE IF NOT EXISTS "mega_erasure" (
+  "id" BIGINT PRIMARY KEY,
+  "subject_hash" VARCHAR(64) NOT NULL,
+  "replacement" VARCHAR(128) NOT NULL,
+  "requested_by" VARCHAR(128) NOT NULL,
+  "reason" TEXT NOT NULL,
+  "report" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_erasure_subject_hash" ON "mega_erasure" ("subject_hash");
+CREATE TABLE IF NOT EXISTS "mega_import" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "source_url" TEXT NOT NULL,
+  "default_branch" VARCHAR(255),
+  "head_commit" VARCHAR(40),
+  "ref_count" INTEGER NOT NULL,
+  "object_count" BIGINT NOT NULL,
+  "imported_by" VARCHAR(128) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_import_repo_path UNIQUE (repo_path)
+);
+CREATE TABLE IF NOT EXISTS "mega_import_job" (
+  "id" BIGINT PRIMARY KEY,
+  "imported_by" VARCHAR(128) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE TABLE IF NOT EXISTS "mega_import_job_repo" (
+  "id" BIGINT PRIMARY KEY,
+  "job_id" BIGINT NOT NULL,
+  "url" TEXT NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "status" VARCHAR(16) NOT NULL,
+  "attempts" INTEGER NOT NULL,
+  "started_storing" BOOLEAN NOT NULL DEFAULT FALSE,
+  "next_attempt_at" TIMESTAMP,
+  "last_error" TEXT,
+  "import_id" BIGINT,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_import_job_repo_job" ON "mega_import_job_repo" ("job_id");
+CREATE INDEX "idx_import_job_repo_next" ON "mega_import_job_repo" ("next_attempt_at");
+CREATE TABLE IF NOT EXISTS "mega_mirror" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "url" TEXT NOT NULL,
+  "interval_secs" BIGINT NOT NULL,
+  "force" BOOLEAN NOT NULL,
+  "prune" BOOLEAN NOT NULL,
+  "enabled" BOOLEAN NOT NULL,
+  "refspecs" TEXT,
+  "next_sync_at" TIMESTAMP NOT NULL,
+  "last_sync_at" TIMESTAMP,
+  "last_status" VARCHAR(20),
+  "last_message" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_mirror_repo_path UNIQUE (repo_path)
+);
+CREATE INDEX "idx_mirror_next_sync_at" ON "mega_mirror" ("next_sync_at");
+CREATE TABLE IF NOT EXISTS "mega_path_mapping" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "repo_commit" VARCHAR(40) NOT NULL,
+  "split_commit" VARCHAR(40),
+  "split_tree" VARCHAR(40),
+  "made" BOOLEAN NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_path_mapping_commit UNIQUE (path, repo_commit)
+);
+CREATE INDEX "idx_path_mapping_split_commit" ON "mega_path_mapping" ("path", "split_commit");
+CREATE TABLE IF NOT EXISTS "mega_ref_hook" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(64) NOT NULL,
+  "last_event_id" BIGINT NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_ref_hook_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_ref_hook_retry" (
+  "id" BIGINT PRIMARY KEY,
+  "hook" VARCHAR(64) NOT NULL,
+  "event_id" BIGINT NOT NULL,
+  "attempts" INTEGER NOT NULL,
+  "next_attempt_at" TIMESTAMP,
+  "last_error" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_ref_hook_retry_next" ON "mega_ref_hook_retry" ("hook", "next_attempt_at");
+CREATE TABLE IF NOT EXISTS "mega_oidc_login" (
+  "id" BIGINT PRIMARY KEY,
+  "state" VARCHAR(64) NOT NULL,
+  "code_verifier" VARCHAR(128) NOT NULL,
+  "return_to" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_oidc_login_state UNIQUE (state)
+);
+CREATE TABLE IF NOT EXISTS "mega_user_identity" (
+  "id" BIGINT PRIMARY KEY,
+  "issuer" VARCHAR(255) NOT NULL,
+  "subject" VARCHAR(255) NOT NULL,
+  "username" VARCHAR(128) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_user_identity_subject UNIQUE (issuer, subject)
+);
+CREATE INDEX "idx_user_identity_username" ON "mega_user_identity" ("username");
+CREATE TABLE IF NOT EXISTS "mega_path_grant" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "subject_kind" VARCHAR(16) NOT NULL,
+  "subject" VARCHAR(128) NOT NULL,
+  "permission" VARCHAR(16) NOT NULL,
+  "granted_by" VARCHAR(128),
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_path_grant_subject UNIQUE (path, subject_kind, subject)
+);
+CREATE INDEX "idx_path_grant_subject" ON "mega_path_grant" ("subject_kind", "subject");
+CREATE TABLE IF NOT EXISTS "mega_push_profile" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "pusher" VARCHAR(128),
+  "transport" VARCHAR(16) NOT NULL,
+  "status" VARCHAR(16) NOT NULL,
+  "reason" TEXT,
+  "pack_bytes" BIGINT NOT NULL,
+  "object_count" BIGINT NOT NULL,
+  "total_ms" BIGINT NOT NULL,
+  "slowest_stage" VARCHAR(40),
+  "stages" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_push_profile_created_at" ON "mega_push_profile" ("created_at");
+CREATE TABLE IF NOT EXISTS "mega_archive" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "state" VARCHAR(16) NOT NULL,
+  "reason" TEXT,
+  "actor" VARCHAR(128) NOT NULL,
+  "refs" TEXT,
+  "bundles" TEXT,
+  "gc_after" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_archive_path UNIQUE (path)
+);
+CREATE TABLE IF NOT EXISTS "mega_webhook" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(64) NOT NULL,
+  "url" TEXT NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "events" TEXT NOT NULL,
+  "dialect" VARCHAR(16) NOT NULL,
+  "secret" TEXT,
+  "enabled" BOOLEAN NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_webhook_name UNIQUE (name)
+);
+CREATE TABLE IF NOT EXISTS "mega_autolink" (
+  "id" BIGINT PRIMARY KEY,
+  "org_id" BIGINT NOT NULL,
+  "pattern" TEXT NOT NULL,
+  "url_template" TEXT NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_autolink_org_id" ON "mega_autolink" ("org_id");
+CREATE TABLE IF NOT EXISTS "mega_pack_bitmap" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "data" BYTEA NOT NULL,
+  "object_count" INT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_pack_bitmap_path UNIQUE (repo_path)
+);
+CREATE TABLE IF NOT EXISTS "mega_object_delta" (
+  "id" BIGINT PRIMARY KEY,
+  "git_id" TEXT NOT NULL,
+  "base_id" TEXT NOT NULL,
+  "data" BYTEA NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_object_delta_git_id UNIQUE (git_id)
+);
+CREATE TABLE IF NOT EXISTS "mega_quarantine_object" (
+  "id" BIGINT PRIMARY KEY,
+  "quarantine_id" BIGINT NOT NULL,
+  "git_id" TEXT NOT NULL,
+  "object_type" VARCHAR(16) NOT NULL,
+  "data" BYTEA NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_quarantine_object_git_id UNIQUE (quarantine_id, git_id)
+);
+CREATE TABLE IF NOT EXISTS "mega_release" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "tag_name" VARCHAR(255) NOT NULL,
+  "title" VARCHAR(255) NOT NULL,
+  "notes" TEXT NOT NULL,
+  "author" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_release_tag UNIQUE (repo_path, tag_name)
+);
+CREATE TABLE IF NOT EXISTS "mega_release_asset" (
+  "id" BIGINT PRIMARY KEY,
+  "release_id" BIGINT NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "content_type" VARCHAR(255) NOT NULL,
+  "size" BIGINT NOT NULL,
+  "sha256" VARCHAR(64) NOT NULL,
+  "uploader" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_release_asset_name UNIQUE (release_id, name)
+);
+CREATE TABLE IF NOT EXISTS "mega_check_run" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "status" VARCHAR(16) NOT NULL,
+  "description" TEXT,
+  "target_url" TEXT,
+  "reporter" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_check_run_name UNIQUE (repo_path, commit_id, name)
+);
+CREATE TABLE IF NOT EXISTS "mega_required_check" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "ref_name" TEXT NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_required_check_name UNIQUE (repo_path, ref_name, name)
+);
+CREATE TABLE IF NOT EXISTS "mega_ci_job" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "ref_name" TEXT NOT NULL,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "script" TEXT NOT NULL,
+  "image" VARCHAR(255),
+  "timeout_secs" INT NOT NULL,
+  "status" VARCHAR(16) NOT NULL,
+  "attempts" INT NOT NULL,
+  "next_attempt_at" TIMESTAMP,
+  "exit_code" INT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  "finished_at" TIMESTAMP,
+  CONSTRAINT uniq_ci_job_name UNIQUE (repo_path, commit_id, name)
+);
+CREATE INDEX "idx_ci_job_next_attempt" ON "mega_ci_job" ("next_attempt_at");
+CREATE TABLE IF NOT EXISTS "mega_merge_queue" (
+  "id" BIGINT PRIMARY KEY,
+  "mr_id" BIGINT NOT NULL UNIQUE,
+  "repo_path" TEXT NOT NULL,
+  "target_ref" TEXT NOT NULL,
+  "status" VARCHAR(16) NOT NULL,
+  "source_commit_id" VARCHAR(40),
+  "base_commit_id" VARCHAR(40),
+  "head_commit_id" VARCHAR(40),
+  "reason" TEXT,
+  "enqueued_by" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_merge_queue_target" ON "mega_merge_queue" ("repo_path", "target_ref", "status");
+
+CREATE TABLE IF NOT EXISTS "mega_diffstat" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "mr_id" BIGINT,
+  "base_id" VARCHAR(40) NOT NULL,
+  "head_id" VARCHAR(40) NOT NULL,
+  "files_changed" INTEGER NOT NULL,
+  "additions" BIGINT NOT NULL,
+  "deletions" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE UNIQUE INDEX "uniq_diffstat_commit" ON "mega_diffstat" ("repo_path", "head_id") WHERE "mr_id" IS NULL;
+CREATE UNIQUE INDEX "uniq_diffstat_mr" ON "mega_diffstat" ("mr_id") WHERE "mr_id" IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS "mega_subscription" (
+  "id" BIGINT PRIMARY KEY,
+  "username" VARCHAR(255) NOT NULL,
+  "subject_type" VARCHAR(16) NOT NULL,
+  "subject_id" BIGINT NOT NULL,
+  "reason" VARCHAR(16) NOT NULL,
+  "subscribed" BOOLEAN NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_subscription_subject UNIQUE (username, subject_type, subject_id)
+);
+CREATE INDEX "idx_subscription_subject" ON "mega_subscription" ("subject_type", "subject_id");
+
+CREATE TABLE IF NOT EXISTS "mega_notification" (
+  "id" BIGINT PRIMARY KEY,
+  "username" VARCHAR(255) NOT NULL,
+  "event_id" BIGINT NOT NULL,
+  "repo_path" TEXT NOT NULL,
+  "subject_type" VARCHAR(16) NOT NULL,
+  "subject_id" BIGINT NOT NULL,
+  "title" TEXT NOT NULL,
+  "action" VARCHAR(32) NOT NULL,
+  "actor" VARCHAR(255),
+  "reason" VARCHAR(16) NOT NULL,
+  "read_at" TIMESTAMP,
+  "email_state" VARCHAR(16),
+  "email_attempts" INTEGER NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_notification_event UNIQUE (username, event_id)
+);
+CREATE INDEX "idx_notification_user" ON "mega_notification" ("username", "created_at");
+CREATE INDEX "idx_notification_email" ON "mega_notification" ("email_state");
+
+CREATE TABLE IF NOT EXISTS "mega_notification_pref" (
+  "id" BIGINT PRIMARY KEY,
+  "username" VARCHAR(255) NOT NULL UNIQUE,
+  "in_app" BOOLEAN NOT NULL,
+  "email" BOOLEAN NOT NULL,
+  "participating_only" BOOLEAN NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mega_reference" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "source_type" VARCHAR(16) NOT NULL,
+  "source_id" BIGINT NOT NULL,
+  "subject_id" BIGINT NOT NULL,
+  "target_type" VARCHAR(16) NOT NULL,
+  "target_id" VARCHAR(255) NOT NULL,
+  "text" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_reference_text UNIQUE (source_type, source_id, text)
+);
+CREATE INDEX "idx_reference_target" ON "mega_reference" ("repo_path", "target_type", "target_id");
+
+CREATE TABLE IF NOT EXISTS "mega_reaction" (
+  "id" BIGINT PRIMARY KEY,
+  "subject_type" VARCHAR(16) NOT NULL,
+  "subject_id" BIGINT NOT NULL,
+  "username" VARCHAR(255) NOT NULL,
+  "content" VARCHAR(16) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_reaction_user UNIQUE (subject_type, subject_id, username, content)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_board" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "description" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_board_name UNIQUE (repo_path, name)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_board_column" (
+  "id" BIGINT PRIMARY KEY,
+  "board_id" BIGINT NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "position" INTEGER NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_board_column_board" ON "mega_board_column" ("board_id");
+
+CREATE TABLE IF NOT EXISTS "mega_board_card" (
+  "id" BIGINT PRIMARY KEY,
+  "board_id" BIGINT NOT NULL,
+  "column_id" BIGINT NOT NULL,
+  "item_type" VARCHAR(16) NOT NULL,
+  "item_id" BIGINT NOT NULL,
+  "position" INTEGER NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_board_card_item UNIQUE (board_id, item_type, item_id)
+);
+CREATE INDEX "idx_board_card_item" ON "mega_board_card" ("item_type", "item_id");
+
+CREATE TABLE IF NOT EXISTS "mega_snippet" (
+  "id" BIGINT PRIMARY KEY,
+  "title" VARCHAR(255) NOT NULL,
+  "description" TEXT,
+  "visibility" VARCHAR(16) NOT NULL,
+  "owner" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_snippet_owner" ON "mega_snippet" ("owner");
+
+CREATE TABLE IF NOT EXISTS "mega_snippet_file" (
+  "id" BIGINT PRIMARY KEY,
+  "snippet_id" BIGINT NOT NULL,
+  "name" VARCHAR(255) NOT NULL,
+  "language" VARCHAR(64) NOT NULL,
+  "blob_id" VARCHAR(40) NOT NULL,
+  "size" BIGINT NOT NULL,
+  "position" INTEGER NOT NULL,
+  CONSTRAINT uniq_snippet_file_name UNIQUE (snippet_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_commit_stat" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "author_name" VARCHAR(255) NOT NULL,
+  "author_email" VARCHAR(255) NOT NULL,
+  "authored_at" TIMESTAMP NOT NULL,
+  "is_merge" BOOLEAN NOT NULL,
+  "additions" BIGINT NOT NULL,
+  "deletions" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_commit_stat_commit UNIQUE (repo_path, commit_id)
+);
+CREATE INDEX "idx_commit_stat_authored" ON "mega_commit_stat" ("repo_path", "authored_at");
+
+CREATE TABLE IF NOT EXISTS "mega_language_stat" (
+  "id" BIGINT PRIMARY KEY,
+  "repo_path" TEXT NOT NULL,
+  "path" TEXT NOT NULL,
+  "language" VARCHAR(64) NOT NULL,
+  "detectable" BOOLEAN NOT NULL,
+  "files" BIGINT NOT NULL,
+  "bytes" BIGINT NOT NULL,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_language_stat_language UNIQUE (repo_path, path, language, detectable)
+);
@@ -0,0 +1,17 @@
+-- Commit parents are stored as their ids separated by spaces instead of a TEXT array.
+-- Columns already converted are left as they are, so the script can be applied again.
+DO $$
+BEGIN
+  IF (SELECT data_type FROM information_schema.columns
+      WHERE table_schema = current_schema() AND table_name = 'mega_commit'
+        AND column_name = 'parents_id') = 'ARRAY' THEN
+    ALTER TABLE "mega_commit"
+      ALTER COLUMN "parents_id" TYPE TEXT USING array_to_string("parents_id", ' ');
+  END IF;
+  IF (SELECT data_type FROM information_schema.columns
+      WHERE table_schema = current_schema() AND table_name = 'git_commit'
+        AND column_name = 'parents_id') = 'ARRAY' THEN
+    ALTER TABLE "git_commit"
+      ALTER COLUMN "parents_id" TYPE TEXT USING array_to_string("parents_id", ' ');
+  END IF;
+END $$;
@@ -0,0 +1,3 @@
+-- Who opened a merge request, to keep them from approving it. Merge requests opened before
+-- have none.
+ALTER TABLE "mega_mr" ADD COLUMN IF NOT EXISTS "author" TEXT;
@@ -0,0 +1,46 @@
+use clap::{ArgMatches, Command, FromArgMatches, Subcommand};
+
+use common::errors::MegaResult;
+use gateway::bundle::{self, BundleCreateOptions};
+
+use crate::cli::Config;
+
+#[derive(Subcommand, Clone, Debug)]
+enum BundleCommand {
+    /// Write refs of a repository and their history to a bundle file
+    Create(BundleCreateOptions),
+}
+
+pub fn cli() -> Command {
+    BundleCommand::augment_subcommands(
+        Command::new("bundle")
+            .about("Move repositories in git bundles, for servers no network reaches")
+            .subcommand_required(true),
+    )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let command = BundleCommand::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+    match command {
+        BundleCommand::Create(options) => {
+            let created = bundle::run_create(&options).await?;
+            println!(
+                "Wrote {} refs of {} to {}, {} bytes",
+                created.refs.len(),
+                options.path,
+                options.output.display(),
+                created.bytes
+            );
+            for name in created.refs {
+                println!("  {}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {}
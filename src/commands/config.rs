@@ -0,0 +1,82 @@
+use clap::{ArgMatches, Command, FromArgMatches, Subcommand};
+
+use common::errors::{MegaError, MegaResult};
+use mega_client::config::{Config as ClientConfig, Scope};
+use mega_client::ConfigError;
+
+use crate::cli::Config;
+
+#[derive(Subcommand, Clone, Debug)]
+enum ConfigCommand {
+    /// Print the value of a key, from the scope with the highest precedence setting it
+    Get { key: String },
+    /// Set a key in the file of a scope
+    Set {
+        key: String,
+        value: String,
+        /// system, user or repo
+        #[arg(long, default_value = "user")]
+        scope: String,
+    },
+    /// Print every value of every scope, the lowest precedence first
+    List {
+        /// Only the values of system, user or repo
+        #[arg(long)]
+        scope: Option<String>,
+        /// Print the file each value comes from
+        #[arg(long)]
+        show_origin: bool,
+    },
+}
+
+pub fn cli() -> Command {
+    ConfigCommand::augment_subcommands(
+        Command::new("config")
+            .about("Get, set and list the settings of the client")
+            .subcommand_required(true),
+    )
+}
+
+pub(crate) fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let command = ConfigCommand::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+    let mut config = ClientConfig::load().map_err(config_error)?;
+    match command {
+        ConfigCommand::Get { key } => match config.get(&key) {
+            Some(value) => println!("{}", value),
+            None => return Err(config_error(ConfigError::Missing(key))),
+        },
+        ConfigCommand::Set { key, value, scope } => {
+            let scope = scope.parse::<Scope>().map_err(config_error)?;
+            config.set(scope, &key, &value).map_err(config_error)?;
+        }
+        ConfigCommand::List { scope, show_origin } => {
+            let scope = scope
+                .map(|scope| scope.parse::<Scope>())
+                .transpose()
+                .map_err(config_error)?;
+            for entry in config.list(scope) {
+                if show_origin {
+                    println!(
+                        "{}:{}\t{}={}",
+                        entry.scope,
+                        entry.path.display(),
+                        entry.key,
+                        entry.value
+                    );
+                } else {
+                    println!("{}={}", entry.key, entry.value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn config_error(err: ConfigError) -> MegaError {
+    MegaError::new(err.into(), 1)
+}
+
+#[cfg(test)]
+mod tests {}
@@ -0,0 +1,45 @@
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+
+use common::errors::{MegaError, MegaResult};
+use gateway::doctor::{self, CheckStatus, DoctorOptions};
+
+use crate::cli::Config;
+
+pub fn cli() -> Command {
+    DoctorOptions::augment_args_for_update(
+        Command::new("doctor")
+            .about("Diagnose the database, object storage and configuration of a mega deployment"),
+    )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let options = DoctorOptions::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+
+    let results = doctor::run_checks(&options).await;
+    for result in &results {
+        println!("{}", result);
+    }
+
+    let failed = results
+        .iter()
+        .filter(|r| r.status == CheckStatus::Fail)
+        .count();
+    let warned = results
+        .iter()
+        .filter(|r| r.status == CheckStatus::Warn)
+        .count();
+    println!("\n{} checks, {} failed, {} warnings", results.len(), failed, warned);
+    if failed > 0 {
+        return Err(MegaError::new(
+            anyhow::anyhow!("mega doctor found {} failing checks", failed),
+            1,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {}
@@ -0,0 +1,69 @@
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+
+use common::errors::{MegaError, MegaResult};
+use mega_client::config::Config as ClientConfig;
+use mega_client::remote::Remote;
+
+use crate::cli::Config;
+
+#[derive(Args, Clone, Debug)]
+struct FetchOptions {
+    /// The remote to fetch, origin by default
+    remote: Option<String>,
+
+    /// Fetch every remote
+    #[arg(long, conflicts_with = "remote")]
+    all: bool,
+
+    /// Delete the refs fetched before which are gone from the remote
+    #[arg(short, long)]
+    prune: bool,
+}
+
+pub fn cli() -> Command {
+    FetchOptions::augment_args_for_update(
+        Command::new("fetch").about("Record where the refs of one or more remotes point"),
+    )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let options = FetchOptions::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+    let config = ClientConfig::load().map_err(|e| MegaError::new(e.into(), 1))?;
+    let remotes = if options.all {
+        Remote::list(&config)
+    } else {
+        let name = options.remote.as_deref().unwrap_or("origin");
+        vec![Remote::load(&config, name).map_err(|e| MegaError::new(e.into(), 1))?]
+    };
+    for remote in remotes {
+        let updates = remote
+            .fetch(&config, options.prune)
+            .await
+            .map_err(|e| MegaError::new(e.into(), 1))?;
+        if updates.is_empty() {
+            continue;
+        }
+        println!("From {}", remote.url);
+        for update in updates {
+            match (&update.old, &update.new) {
+                (None, Some(_)) => println!(" * [new ref]         {}", update.local),
+                (Some(_), None) => println!(" - [deleted]         {}", update.local),
+                (Some(old), Some(new)) => {
+                    println!("   {}..{}  {}", abbrev(old), abbrev(new), update.local)
+                }
+                (None, None) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn abbrev(id: &str) -> &str {
+    id.get(..7).unwrap_or(id)
+}
+
+#[cfg(test)]
+mod tests {}
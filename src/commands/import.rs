@@ -0,0 +1,33 @@
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+
+use common::errors::MegaResult;
+use gateway::import::{self, ImportOptions};
+
+use crate::cli::Config;
+
+pub fn cli() -> Command {
+    ImportOptions::augment_args_for_update(
+        Command::new("import")
+            .about("Import a repository from an external Git remote into the monorepo"),
+    )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let options = ImportOptions::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+
+    let imported = import::run_import(&options).await?;
+    println!(
+        "Imported {} at {}: {} branches and tags, {} objects",
+        imported.source_url, imported.repo_path, imported.ref_count, imported.object_count
+    );
+    if let (Some(branch), Some(commit)) = (&imported.default_branch, &imported.head_commit) {
+        println!("HEAD is {} at {}", branch, commit);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {}
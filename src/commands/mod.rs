@@ -3,8 +3,15 @@
 //!
 //!
 //!
+mod bundle;
+mod config;
+mod doctor;
+mod fetch;
+mod import;
 mod init;
+mod remote;
 mod service;
+mod show;
 
 use clap::{ArgMatches, Command};
 
@@ -13,15 +20,29 @@ use common::errors::MegaResult;
 
 pub fn builtin() -> Vec<Command> {
     vec![
+        bundle::cli(),
+        config::cli(),
+        doctor::cli(),
+        fetch::cli(),
+        import::cli(),
         init::cli(),
+        remote::cli(),
         service::cli(),
+        show::cli(),
     ]
 }
 
 pub(crate) fn builtin_exec(cmd: &str) -> Option<fn(Config, &ArgMatches) -> MegaResult> {
     let f = match cmd {
+        "bundle" => bundle::exec,
+        "config" => config::exec,
+        "doctor" => doctor::exec,
+        "fetch" => fetch::exec,
+        "import" => import::exec,
         "init" => init::exec,
+        "remote" => remote::exec,
         "service" => service::exec,
+        "show" => show::exec,
         _ => return None,
     };
 
@@ -0,0 +1,71 @@
+use clap::{ArgMatches, Command, FromArgMatches, Subcommand};
+
+use common::errors::{MegaError, MegaResult};
+use mega_client::config::Config as ClientConfig;
+use mega_client::remote::Remote;
+use mega_client::ConfigError;
+
+use crate::cli::Config;
+
+#[derive(Subcommand, Clone, Debug)]
+enum RemoteCommand {
+    /// Add a remote, fetching its branches to refs/remotes/<name>
+    Add { name: String, url: String },
+    /// Remove a remote and the refs fetched from it
+    #[command(alias = "rm")]
+    Remove { name: String },
+    /// Rename a remote, moving the refs fetched from it along
+    Rename { old: String, new: String },
+    /// Print the remotes
+    List {
+        /// Print their urls and fetch refspecs too
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+pub fn cli() -> Command {
+    RemoteCommand::augment_subcommands(
+        Command::new("remote")
+            .about("Manage the remotes the repository fetches from")
+            .subcommand_required(true),
+    )
+}
+
+pub(crate) fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let command = RemoteCommand::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+    let mut config = ClientConfig::load().map_err(config_error)?;
+    match command {
+        RemoteCommand::Add { name, url } => {
+            Remote::add(&mut config, &name, &url).map_err(config_error)?;
+        }
+        RemoteCommand::Remove { name } => {
+            Remote::remove(&mut config, &name).map_err(config_error)?;
+        }
+        RemoteCommand::Rename { old, new } => {
+            Remote::rename(&mut config, &old, &new).map_err(config_error)?;
+        }
+        RemoteCommand::List { verbose } => {
+            for remote in Remote::list(&config) {
+                if verbose {
+                    println!("{}\t{}", remote.name, remote.url);
+                    for spec in &remote.fetch {
+                        println!("\tfetch {}", spec);
+                    }
+                } else {
+                    println!("{}", remote.name);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn config_error(err: ConfigError) -> MegaError {
+    MegaError::new(err.into(), 1)
+}
+
+#[cfg(test)]
+mod tests {}
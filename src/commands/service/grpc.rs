@@ -0,0 +1,25 @@
+//! `mega service grpc`: serves the objects, refs and pack writes of the storage API over gRPC.
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+
+use common::errors::MegaResult;
+use gateway::grpc_server::{self, GrpcOptions};
+
+use crate::cli::Config;
+
+pub fn cli() -> Command {
+    GrpcOptions::augment_args_for_update(
+        Command::new("grpc").about("Start the gRPC server of the storage API"),
+    )
+}
+
+pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let server_matchers = GrpcOptions::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+    println!("{server_matchers:#?}");
+    grpc_server::start_server(&server_matchers).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {}
@@ -9,15 +9,22 @@ use common::errors::MegaResult;
 
 use crate::cli::Config;
 
+mod grpc;
 mod https;
 mod p2p;
 mod ssh;
 mod start;
 
 pub fn cli() -> Command {
-    let subcommands = vec![https::cli(), ssh::cli(), p2p::cli(), start::cli()];
+    let subcommands = vec![
+        https::cli(),
+        ssh::cli(),
+        grpc::cli(),
+        p2p::cli(),
+        start::cli(),
+    ];
     Command::new("service")
-        .about("Start different kinds of server: for example https, ssh, p2p")
+        .about("Start different kinds of server: for example https, ssh, grpc, p2p")
         .subcommands(subcommands)
 }
 
@@ -33,6 +40,7 @@ pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
     match cmd {
         "https" => https::exec(_config, subcommand_args).await,
         "ssh" => ssh::exec(_config, subcommand_args).await,
+        "grpc" => grpc::exec(_config, subcommand_args).await,
         "p2p" => p2p::exec(_config, subcommand_args).await,
         "start" => start::exec(_config, subcommand_args).await,
         _ => Ok(()),
@@ -2,7 +2,9 @@ use clap::{ArgMatches, Args, Command, FromArgMatches, ValueEnum};
 
 use common::{errors::MegaResult, model::CommonOptions};
 use gateway::{
+    grpc_server::{self, GrpcCustom, GrpcOptions},
     https_server::{self, HttpCustom, HttpOptions},
+    shutdown,
     ssh_server::{self, SshCustom, SshOptions},
 };
 use p2p::peer::{self, P2pCustom, P2pOptions};
@@ -14,6 +16,7 @@ pub enum StartCommand {
     Http,
     Https,
     Ssh,
+    Grpc,
     P2p,
 }
 
@@ -30,6 +33,9 @@ pub struct StartOptions {
     #[clap(flatten)]
     pub ssh: SshCustom,
 
+    #[clap(flatten)]
+    pub grpc: GrpcCustom,
+
     #[clap(flatten)]
     pub p2p: P2pCustom,
 }
@@ -71,19 +77,33 @@ pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
         tokio::task::spawn(async {})
     };
 
+    let grpc_server = if service_type.contains(&StartCommand::Grpc) {
+        let grpc = GrpcOptions {
+            common: server_matchers.common.clone(),
+            custom: server_matchers.grpc,
+        };
+        tokio::spawn(async move { grpc_server::start_server(&grpc).await })
+    } else {
+        tokio::task::spawn(async {})
+    };
+
     let p2p_server = if service_type.contains(&StartCommand::P2p) {
         let p2p = P2pOptions {
             common: server_matchers.common.clone(),
             custom: server_matchers.p2p,
         };
         tokio::spawn(async move {
-            peer::run(&p2p).await.unwrap();
+            // stops with the servers of the gateway, which listen for the signals
+            tokio::select! {
+                result = peer::run(&p2p) => result.unwrap(),
+                _ = shutdown::requested() => {}
+            }
         })
     } else {
         tokio::task::spawn(async {})
     };
 
-    let _ = tokio::join!(http_server, ssh_server, p2p_server);
+    let _ = tokio::join!(http_server, ssh_server, grpc_server, p2p_server);
 
     Ok(())
 }
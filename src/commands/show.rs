@@ -0,0 +1,33 @@
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+
+use common::errors::MegaResult;
+use gateway::show::{self, ShowOptions, Style};
+
+use crate::cli::Config;
+
+pub fn cli() -> Command {
+    ShowOptions::augment_args_for_update(
+        Command::new("show").about("Show a commit, tree, blob or tag of the monorepo"),
+    )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(_config: Config, args: &ArgMatches) -> MegaResult {
+    let options = ShowOptions::from_arg_matches(args)
+        .map_err(|err| err.exit())
+        .unwrap();
+
+    let style = match &options.format {
+        Some(format) => Style::from_str(format).unwrap(),
+        None if std::io::stdout().is_terminal() => Style::Ansi,
+        None => Style::Plain,
+    };
+    println!("{}", show::run_show(&options, style).await?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {}
@@ -8,12 +8,13 @@
 use std::env;
 mod cli;
 mod commands;
+mod telemetry;
 mod utils;
 
 fn main() {
     env::set_var("RUST_LOG", "debug");
-    tracing_subscriber::fmt::init();
     dotenvy::dotenv().ok();
+    telemetry::init();
 
     // Parse the command line arguments
     let result = cli::parse();
@@ -22,4 +23,5 @@ fn main() {
     if let Err(e) = result {
         e.print()
     }
+    telemetry::shutdown();
 }
@@ -0,0 +1,68 @@
+//!
+//! Sets up the logs of mega, and with the `otlp` feature the export of its spans.
+//!
+//! Log lines are written to stdout with the spans they were written in, so the lines of a request
+//! show its `request{id=..}`. When `MEGA_OTLP_ENDPOINT` is set to the OTLP/HTTP endpoint of a
+//! collector, like `http://localhost:4318/v1/traces` of Jaeger or Tempo, the spans are also
+//! exported there in batches, as the `mega` service or `MEGA_OTLP_SERVICE_NAME`.
+//!
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+pub fn init() {
+    let registry = tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer());
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp::layer());
+    registry.init();
+}
+
+/// Export the spans not exported yet, before the process exits.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use tracing::Subscriber;
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    /// The layer exporting spans to `MEGA_OTLP_ENDPOINT`, `None` when it isn't set.
+    pub fn layer<S>() -> Option<impl Layer<S>>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let endpoint = std::env::var("MEGA_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.trim().is_empty())?;
+        let service_name =
+            std::env::var("MEGA_OTLP_SERVICE_NAME").unwrap_or_else(|_| "mega".to_owned());
+        // the batches are sent from a thread of their own, the runtime of the servers starts later
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(endpoint.trim()),
+            )
+            .with_trace_config(
+                trace::config()
+                    .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)])),
+            )
+            .install_batch(runtime::TokioCurrentThread);
+        match tracer {
+            Ok(tracer) => Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+            Err(e) => {
+                eprintln!("failed to set up the OTLP exporter, spans are not exported: {}", e);
+                None
+            }
+        }
+    }
+}
@@ -1,19 +1,31 @@
 use std::{env, sync::Arc, time::Duration};
 
-use sea_orm::{ConnectOptions, Database};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 use tracing::log;
 
 use self::{mysql_storage::MysqlStorage, pg_storage::PgStorage, storage::ObjectStorage};
 use crate::utils::id_generator;
 use common::enums::DataSource;
+use common::metrics::metrics;
 
 pub mod mysql_storage;
 pub mod pg_storage;
+pub mod quarantine;
 pub mod storage;
 
 pub async fn init(data_source: &DataSource) -> Arc<dyn ObjectStorage> {
     id_generator::set_up_options().unwrap();
 
+    let connection = connect(data_source).await;
+    match data_source {
+        DataSource::Mysql => Arc::new(MysqlStorage { connection }),
+        DataSource::Postgres => Arc::new(PgStorage { connection }),
+    }
+}
+
+/// Open a connection pool to the configured database, for storages living outside of
+/// [`ObjectStorage`].
+pub async fn connect(data_source: &DataSource) -> DatabaseConnection {
     let db_url = match data_source {
         DataSource::Mysql => {
             env::var("MEGA_DB_MYSQL_URL").expect("DATABASE_URL is not set in .env file")
@@ -46,11 +58,9 @@ pub async fn init(data_source: &DataSource) -> Arc<dyn ObjectStorage> {
                 .unwrap(),
         )
         .sqlx_logging_level(log::LevelFilter::Debug);
-    let connection = Database::connect(opt)
+    let mut connection = Database::connect(opt)
         .await
         .expect("Database connection failed");
-    match data_source {
-        DataSource::Mysql => Arc::new(MysqlStorage { connection }),
-        DataSource::Postgres => Arc::new(PgStorage { connection }),
-    }
+    connection.set_metric_callback(|info| metrics().observe_query(info.elapsed, info.failed));
+    connection
 }
@@ -0,0 +1,210 @@
+//!
+//! A quarantine for the objects of a push, like the temporary object directory of git: they are
+//! staged under the id of the push until its checks pass, then moved to `objects` in one
+//! transaction. A refused push leaves nothing behind.
+//!
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait, TryIntoModel,
+};
+
+use common::errors::MegaError;
+use db_entity::{mega_object_delta, mega_quarantine_object};
+use entity::{commit, mr, mr_info, objects, refs};
+
+use crate::driver::database::storage::{batch_save_model, ObjectStorage};
+use crate::utils::id_generator::generate_id;
+
+/// An [`ObjectStorage`] holding the objects saved through it in quarantine, while everything
+/// else goes to the storage it wraps. Reads see the quarantined objects too, so the checks of a
+/// push can look at what it brings.
+pub struct QuarantineStorage {
+    inner: Arc<dyn ObjectStorage>,
+    id: i64,
+    /// Deltas are only kept for objects in `objects`, they wait in memory with the push.
+    deltas: Mutex<Vec<mega_object_delta::ActiveModel>>,
+}
+
+impl QuarantineStorage {
+    pub fn new(inner: Arc<dyn ObjectStorage>) -> Self {
+        QuarantineStorage {
+            inner,
+            id: generate_id(),
+            deltas: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// The quarantined objects among `git_ids`.
+    async fn staged(&self, git_ids: Vec<String>) -> Result<Vec<objects::Model>, MegaError> {
+        let mut staged = Vec::new();
+        for chunk in git_ids.chunks(1000) {
+            let rows = mega_quarantine_object::Entity::find()
+                .filter(mega_quarantine_object::Column::QuarantineId.eq(self.id))
+                .filter(mega_quarantine_object::Column::GitId.is_in(chunk))
+                .all(self.get_connection())
+                .await?;
+            staged.extend(rows.into_iter().map(to_object));
+        }
+        Ok(staged)
+    }
+
+    /// Move the quarantined objects to `objects`, once the push has passed its checks. Either
+    /// all of them are moved or none.
+    pub async fn release(&self) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        let mut pages = mega_quarantine_object::Entity::find()
+            .filter(mega_quarantine_object::Column::QuarantineId.eq(self.id))
+            .order_by_asc(mega_quarantine_object::Column::Id)
+            .paginate(&txn, 1000);
+        while let Some(rows) = pages.fetch_and_next().await? {
+            let models = rows
+                .into_iter()
+                .map(|row| to_object(row).into_active_model())
+                .collect();
+            self.inner.save_obj_data(Some(&txn), models).await?;
+        }
+        mega_quarantine_object::Entity::delete_many()
+            .filter(mega_quarantine_object::Column::QuarantineId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+
+        let deltas = std::mem::take(&mut *self.deltas.lock().unwrap());
+        self.inner.save_obj_deltas(deltas).await
+    }
+
+    /// Drop the quarantined objects of a refused push, with the rows the push `mr_id` left
+    /// when it got that far.
+    pub async fn discard(&self, mr_id: Option<i64>) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_quarantine_object::Entity::delete_many()
+            .filter(mega_quarantine_object::Column::QuarantineId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        if let Some(mr_id) = mr_id {
+            mr::Entity::delete_many()
+                .filter(mr::Column::MrId.eq(mr_id))
+                .exec(&txn)
+                .await?;
+            mr_info::Entity::delete_many()
+                .filter(mr_info::Column::MrId.eq(mr_id))
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        self.deltas.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+fn to_object(row: mega_quarantine_object::Model) -> objects::Model {
+    objects::Model {
+        id: generate_id(),
+        git_id: row.git_id,
+        object_type: row.object_type,
+        data: row.data,
+        link: None,
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for QuarantineStorage {
+    fn get_connection(&self) -> &DatabaseConnection {
+        self.inner.get_connection()
+    }
+
+    async fn save_obj_data(
+        &self,
+        txn: Option<&DatabaseTransaction>,
+        obj_data: Vec<objects::ActiveModel>,
+    ) -> Result<bool, MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let staged = obj_data
+            .into_iter()
+            .map(|model| {
+                let obj = model.try_into_model()?;
+                Ok(mega_quarantine_object::ActiveModel {
+                    id: Set(generate_id()),
+                    quarantine_id: Set(self.id),
+                    git_id: Set(obj.git_id),
+                    object_type: Set(obj.object_type),
+                    data: Set(obj.data),
+                    created_at: Set(now),
+                })
+            })
+            .collect::<Result<Vec<_>, MegaError>>()?;
+        match txn {
+            Some(txn) => batch_save_model(txn, staged).await?,
+            None => batch_save_model(self.get_connection(), staged).await?,
+        }
+        Ok(true)
+    }
+
+    async fn save_obj_data_to_db(
+        &self,
+        txn: Option<&DatabaseTransaction>,
+        obj_data: Vec<objects::ActiveModel>,
+    ) -> Result<bool, MegaError> {
+        self.save_obj_data(txn, obj_data).await
+    }
+
+    async fn get_obj_data_by_ids(
+        &self,
+        git_ids: Vec<String>,
+    ) -> Result<Vec<objects::Model>, MegaError> {
+        let mut objs = self.inner.get_obj_data_by_ids(git_ids.clone()).await?;
+        let found: HashSet<String> = objs.iter().map(|obj| obj.git_id.clone()).collect();
+        let missing = git_ids
+            .into_iter()
+            .filter(|id| !found.contains(id))
+            .collect();
+        let staged = self.staged(missing).await?;
+        objs.extend(staged);
+        Ok(objs)
+    }
+
+    async fn get_obj_data_by_id(&self, git_id: &str) -> Result<Option<objects::Model>, MegaError> {
+        match self.inner.get_obj_data_by_id(git_id).await? {
+            Some(obj) => Ok(Some(obj)),
+            None => Ok(self.staged(vec![git_id.to_owned()]).await?.pop()),
+        }
+    }
+
+    async fn get_obj_type_by_id(&self, git_id: &str) -> Result<Option<String>, MegaError> {
+        match self.inner.get_obj_type_by_id(git_id).await? {
+            Some(object_type) => Ok(Some(object_type)),
+            None => Ok(mega_quarantine_object::Entity::find()
+                .select_only()
+                .column(mega_quarantine_object::Column::ObjectType)
+                .filter(mega_quarantine_object::Column::QuarantineId.eq(self.id))
+                .filter(mega_quarantine_object::Column::GitId.eq(git_id))
+                .into_tuple()
+                .one(self.get_connection())
+                .await?),
+        }
+    }
+
+    async fn search_refs(&self, path_str: &str) -> Result<Vec<refs::Model>, MegaError> {
+        self.inner.search_refs(path_str).await
+    }
+
+    async fn search_commits(&self, path_str: &str) -> Result<Vec<commit::Model>, MegaError> {
+        self.inner.search_commits(path_str).await
+    }
+
+    async fn save_obj_deltas(
+        &self,
+        deltas: Vec<mega_object_delta::ActiveModel>,
+    ) -> Result<(), MegaError> {
+        self.deltas.lock().unwrap().extend(deltas);
+        Ok(())
+    }
+}
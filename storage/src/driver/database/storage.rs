@@ -4,12 +4,14 @@
 
 extern crate common;
 
+use std::collections::HashSet;
 use std::env;
 use std::path::Path;
 use std::path::PathBuf;
 
 use async_trait::async_trait;
 use entity::model::query_result::SelectResult;
+use sea_orm::sea_query::Expr;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::ActiveModelTrait;
 use sea_orm::ColumnTrait;
@@ -23,9 +25,12 @@ use sea_orm::PaginatorTrait;
 use sea_orm::QueryFilter;
 use sea_orm::QuerySelect;
 use sea_orm::Set;
+use sea_orm::TransactionTrait;
 use sea_orm::TryIntoModel;
 
 use common::errors::MegaError;
+use common::utils::{rebase_path, under_path};
+use db_entity::{mega_object_delta, mega_pack_bitmap, mega_path_mapping, mega_snapshot};
 use entity::commit;
 use entity::issue;
 use entity::locks;
@@ -56,6 +61,7 @@ pub trait ObjectStorage: Send + Sync {
         Ok(true)
     }
 
+    #[tracing::instrument(skip_all, fields(objects = obj_data.len()))]
     async fn save_obj_data(
         &self,
         txn: Option<&DatabaseTransaction>,
@@ -119,6 +125,7 @@ pub trait ObjectStorage: Send + Sync {
             .unwrap())
     }
 
+    #[tracing::instrument(skip_all, fields(ids = git_ids.len()))]
     async fn get_obj_data_by_ids(
         &self,
         git_ids: Vec<String>,
@@ -162,6 +169,62 @@ pub trait ObjectStorage: Send + Sync {
         Ok(None)
     }
 
+    /// The type of the stored object `git_id`, `None` when it isn't stored. Only the type is
+    /// read, not the object.
+    async fn get_obj_type_by_id(&self, git_id: &str) -> Result<Option<String>, MegaError> {
+        Ok(objects::Entity::find()
+            .select_only()
+            .column(objects::Column::ObjectType)
+            .filter(objects::Column::GitId.eq(git_id))
+            .into_tuple()
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The ids among `git_ids` a fetch of `repo_path` may want: its commits and refs, and the
+    /// stored trees, blobs and tags. Only the ids are read, not the objects.
+    #[tracing::instrument(skip_all, fields(ids = git_ids.len()))]
+    async fn find_known_ids(
+        &self,
+        repo_path: &str,
+        git_ids: &[String],
+    ) -> Result<HashSet<String>, MegaError> {
+        let mut known = HashSet::new();
+        for chunk in git_ids.chunks(1000) {
+            let commits: Vec<String> = commit::Entity::find()
+                .select_only()
+                .column(commit::Column::GitId)
+                .filter(commit::Column::GitId.is_in(chunk))
+                .filter(commit::Column::RepoPath.eq(repo_path))
+                .into_tuple()
+                .all(self.get_connection())
+                .await?;
+            known.extend(commits);
+            let refs: Vec<String> = refs::Entity::find()
+                .select_only()
+                .column(refs::Column::RefGitId)
+                .filter(refs::Column::RefGitId.is_in(chunk))
+                .filter(refs::Column::RepoPath.eq(repo_path))
+                .into_tuple()
+                .all(self.get_connection())
+                .await?;
+            known.extend(refs);
+            let rest: Vec<&String> = chunk.iter().filter(|id| !known.contains(*id)).collect();
+            if rest.is_empty() {
+                continue;
+            }
+            let objs: Vec<String> = objects::Entity::find()
+                .select_only()
+                .column(objects::Column::GitId)
+                .filter(objects::Column::GitId.is_in(rest))
+                .into_tuple()
+                .all(self.get_connection())
+                .await?;
+            known.extend(objs);
+        }
+        Ok(known)
+    }
+
     async fn get_all_refs_by_path(&self, repo_path: &str) -> Result<Vec<refs::Model>, MegaError> {
         // assuming HEAD points to branch master.
         Ok(refs::Entity::find()
@@ -200,6 +263,21 @@ pub trait ObjectStorage: Send + Sync {
         .unwrap())
     }
 
+    /// Commits of `repo_path` whose id starts with `prefix`, at most `limit` of them.
+    async fn get_commits_by_prefix(
+        &self,
+        prefix: &str,
+        repo_path: &str,
+        limit: u64,
+    ) -> Result<Vec<commit::Model>, MegaError> {
+        Ok(commit::Entity::find()
+            .filter(commit::Column::GitId.starts_with(prefix))
+            .filter(commit::Column::RepoPath.eq(repo_path))
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+
     async fn get_all_commits_by_path(
         &self,
         repo_path: &str,
@@ -254,6 +332,97 @@ pub trait ObjectStorage: Send + Sync {
             .unwrap();
     }
 
+    /// Move the refs of the repository at `repo_path` in one transaction: the `updated` ones
+    /// are set to their id, created when missing, and the refs named `deleted` removed. None of
+    /// them moves unless all of them do.
+    async fn commit_refs(
+        &self,
+        repo_path: &str,
+        updated: Vec<refs::ActiveModel>,
+        deleted: Vec<String>,
+    ) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        if !updated.is_empty() {
+            refs::Entity::insert_many(updated)
+                .on_conflict(
+                    OnConflict::columns(vec![refs::Column::RepoPath, refs::Column::RefName])
+                        .update_columns([refs::Column::RefGitId, refs::Column::UpdatedAt])
+                        .to_owned(),
+                )
+                .exec(&txn)
+                .await?;
+        }
+        if !deleted.is_empty() {
+            refs::Entity::delete_many()
+                .filter(refs::Column::RepoPath.eq(repo_path))
+                .filter(refs::Column::RefName.is_in(deleted))
+                .exec(&txn)
+                .await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Delete the ref `ref_name` of the repository at `repo_path`.
+    async fn remove_ref(&self, repo_path: &str, ref_name: &str) -> Result<(), MegaError> {
+        refs::Entity::delete_many()
+            .filter(refs::Column::RepoPath.eq(repo_path))
+            .filter(refs::Column::RefName.eq(ref_name))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Refs of the repository at `path` and of every repository below it.
+    async fn get_refs_under_path(&self, path: &str) -> Result<Vec<refs::Model>, MegaError> {
+        Ok(refs::Entity::find()
+            .filter(under_path("repo_path", path))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Move the repositories at and below `from` to `to`, along with their commits, nodes and
+    /// directory entries. The directory `from` itself is renamed and attached to `to_parent`.
+    async fn move_repo_path(&self, from: &str, to: &str, to_parent: i32) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        refs::Entity::update_many()
+            .col_expr(refs::Column::RepoPath, rebase_path("repo_path", from, to))
+            .filter(under_path("repo_path", from))
+            .exec(&txn)
+            .await?;
+        commit::Entity::update_many()
+            .col_expr(commit::Column::RepoPath, rebase_path("repo_path", from, to))
+            .filter(under_path("repo_path", from))
+            .exec(&txn)
+            .await?;
+        node::Entity::update_many()
+            .col_expr(node::Column::RepoPath, rebase_path("repo_path", from, to))
+            .filter(under_path("repo_path", from))
+            .exec(&txn)
+            .await?;
+        let name = to.rsplit('/').next().unwrap_or(to);
+        repo_directory::Entity::update_many()
+            .col_expr(repo_directory::Column::Pid, Expr::value(to_parent))
+            .col_expr(repo_directory::Column::Name, Expr::value(name))
+            .filter(repo_directory::Column::FullPath.eq(from))
+            .exec(&txn)
+            .await?;
+        repo_directory::Entity::update_many()
+            .col_expr(
+                repo_directory::Column::FullPath,
+                rebase_path("full_path", from, to),
+            )
+            .col_expr(
+                repo_directory::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(under_path("full_path", from))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
     async fn get_nodes_by_hashes(
         &self,
         hashes: Vec<String>,
@@ -504,6 +673,103 @@ pub trait ObjectStorage: Send + Sync {
             .await
             .unwrap())
     }
+
+    async fn get_snapshot(&self, path: &str) -> Result<Option<mega_snapshot::Model>, MegaError> {
+        Ok(mega_snapshot::Entity::find()
+            .filter(mega_snapshot::Column::Path.eq(path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Insert the snapshot of `snapshot.path`, or update the tree and commit it points to.
+    async fn save_snapshot(&self, snapshot: mega_snapshot::Model) -> Result<(), MegaError> {
+        mega_snapshot::Entity::insert(snapshot.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_snapshot::Column::Path)
+                    .update_columns([
+                        mega_snapshot::Column::TreeId,
+                        mega_snapshot::Column::CommitId,
+                        mega_snapshot::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Commits of the repository of the directory published at `path`, mapped to commits of
+    /// the directory so far.
+    async fn get_path_mappings(
+        &self,
+        path: &str,
+    ) -> Result<Vec<mega_path_mapping::Model>, MegaError> {
+        Ok(mega_path_mapping::Entity::find()
+            .filter(mega_path_mapping::Column::Path.eq(path))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Record `mappings`, keeping the mapping already recorded for a commit if there is one.
+    async fn save_path_mappings(
+        &self,
+        mappings: Vec<mega_path_mapping::Model>,
+    ) -> Result<(), MegaError> {
+        let models: Vec<mega_path_mapping::ActiveModel> = mappings
+            .into_iter()
+            .map(|m| m.into_active_model())
+            .collect();
+        batch_save_model(self.get_connection(), models).await
+    }
+
+    async fn get_pack_bitmap(
+        &self,
+        repo_path: &str,
+    ) -> Result<Option<mega_pack_bitmap::Model>, MegaError> {
+        Ok(mega_pack_bitmap::Entity::find()
+            .filter(mega_pack_bitmap::Column::RepoPath.eq(repo_path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Insert the bitmaps of `bitmap.repo_path`, or replace the ones written before.
+    async fn save_pack_bitmap(&self, bitmap: mega_pack_bitmap::Model) -> Result<(), MegaError> {
+        mega_pack_bitmap::Entity::insert(bitmap.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_pack_bitmap::Column::RepoPath)
+                    .update_columns([
+                        mega_pack_bitmap::Column::Data,
+                        mega_pack_bitmap::Column::ObjectCount,
+                        mega_pack_bitmap::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Keep the deltas of pushed objects, the ones already kept for an object are left as they are.
+    async fn save_obj_deltas(
+        &self,
+        deltas: Vec<mega_object_delta::ActiveModel>,
+    ) -> Result<(), MegaError> {
+        batch_save_model(self.get_connection(), deltas).await
+    }
+
+    async fn get_obj_deltas_by_ids(
+        &self,
+        git_ids: Vec<String>,
+    ) -> Result<Vec<mega_object_delta::Model>, MegaError> {
+        batch_query_by_columns::<mega_object_delta::Entity, mega_object_delta::Column>(
+            self.get_connection(),
+            mega_object_delta::Column::GitId,
+            git_ids,
+            None,
+            None,
+        )
+        .await
+    }
 }
 
 /// Performs batch saving of models in the database.
@@ -529,6 +795,7 @@ pub trait ObjectStorage: Send + Sync {
 /// # Errors
 ///
 /// Returns a `MegaError` if an error occurs during the batch save operation.
+#[tracing::instrument(skip_all, fields(models = save_models.len()))]
 pub async fn batch_save_model<E, A>(
     connection: &impl ConnectionTrait,
     save_models: Vec<A>,
@@ -549,6 +816,7 @@ where
     Ok(())
 }
 
+#[tracing::instrument(skip_all, fields(ids = ids.len()))]
 async fn batch_query_by_columns<T, C>(
     connection: &DatabaseConnection,
     column: C,
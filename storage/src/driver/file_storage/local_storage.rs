@@ -57,11 +57,38 @@ impl FileStorage for LocalStorage {
         Ok(path.to_str().unwrap().to_string())
     }
 
+    async fn put_file(&self, key: &str, body_content: &[u8]) -> Result<String, MegaError> {
+        let path = self.base_path.join(key.trim_start_matches('/'));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // readers never see a partly written file
+        let partial = path.with_extension("partial");
+        fs::write(&partial, body_content)?;
+        fs::rename(&partial, &path)?;
+        Ok(path.to_str().unwrap().to_string())
+    }
+
     fn exist(&self, object_id: &str) -> bool {
         let path = path::Path::new(&self.base_path).join(self.transform_path(object_id));
 
         path::Path::exists(&path)
     }
+
+    async fn remove(&self, object_id: &str) -> Result<(), MegaError> {
+        let path = path::Path::new(&self.base_path).join(self.transform_path(object_id));
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn check(&self) -> Result<(), MegaError> {
+        let probe = self.base_path.join(".mega-health-probe");
+        fs::write(&probe, b"probe")?;
+        fs::remove_file(&probe)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -27,8 +27,17 @@ pub trait FileStorage: Sync + Send {
         body_content: &[u8],
     ) -> Result<String, MegaError>;
 
+    /// Store `body_content` under `key` as it is, not spread in directories like objects, for
+    /// files read by name outside of the server. Returns where it was stored.
+    async fn put_file(&self, key: &str, body_content: &[u8]) -> Result<String, MegaError>;
+
     fn exist(&self, object_id: &str) -> bool;
 
+    async fn remove(&self, object_id: &str) -> Result<(), MegaError>;
+
+    /// Check that objects can be stored, for the readiness probe of the server.
+    async fn check(&self) -> Result<(), MegaError>;
+
     async fn list(&self) {
         unreachable!("not implement")
     }
@@ -76,7 +76,38 @@ impl FileStorage for RemoteStorage {
         Ok(url)
     }
 
+    async fn put_file(&self, key: &str, body_content: &[u8]) -> Result<String, MegaError> {
+        let key = key.trim_start_matches('/');
+        s3_service::upload_object_from_content(&self.client, &self.bucket_name, body_content, key)
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        Ok(format!(
+            "https://{}.obs.{}.myhuaweicloud.com/{}",
+            self.bucket_name,
+            self.region.as_ref(),
+            key
+        ))
+    }
+
     fn exist(&self, _object_id: &str) -> bool {
         todo!()
     }
+
+    async fn remove(&self, object_id: &str) -> Result<(), MegaError> {
+        let key = self.transform_path(object_id);
+        s3_service::delete_object(&self.client, &self.bucket_name, &key)
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        Ok(())
+    }
+
+    async fn check(&self) -> Result<(), MegaError> {
+        self.client
+            .head_bucket()
+            .bucket(&self.bucket_name)
+            .send()
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        Ok(())
+    }
 }
@@ -8,6 +8,7 @@
 use aws_sdk_s3::operation::{
     copy_object::{CopyObjectError, CopyObjectOutput},
     create_bucket::{CreateBucketError, CreateBucketOutput},
+    delete_object::{DeleteObjectError, DeleteObjectOutput},
     get_object::{GetObjectError, GetObjectOutput},
     list_objects_v2::ListObjectsV2Output,
     put_object::{PutObjectError, PutObjectOutput},
@@ -119,6 +120,19 @@ pub async fn download_object(
 }
 // snippet-end:[rust.example_code.s3.basics.download_object]
 
+pub async fn delete_object(
+    client: &Client,
+    bucket_name: &str,
+    key: &str,
+) -> Result<DeleteObjectOutput, SdkError<DeleteObjectError>> {
+    client
+        .delete_object()
+        .bucket(bucket_name)
+        .key(key)
+        .send()
+        .await
+}
+
 // snippet-start:[rust.example_code.s3.basics.upload_object]
 // snippet-start:[rust.example_code.s3.basics.put_object]
 pub async fn upload_object_from_content(
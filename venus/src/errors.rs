@@ -25,6 +25,15 @@ pub enum GitError {
     #[error("`{0}`.")]
     EmptyTreeItems(String),
 
+    #[error("The `{0}` entry ends before it is complete.")]
+    TruncatedEntry(String),
+
+    #[error("The `{0}` is not a valid tree item mode.")]
+    InvalidMode(String),
+
+    #[error("The {0} is not valid UTF-8.")]
+    InvalidUtf8(String),
+
     #[error("The `{0}` is not a valid git commit signature.")]
     InvalidSignatureType(String),
 
@@ -5,9 +5,11 @@
 
 use std::fmt::Display;
 
-use colored::Colorize;
 use sha1_smol::Digest;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::internal::object::types::ObjectType;
+use crate::render::{paint, Part, Render, Style};
 
 /// The `SHA1` struct, encapsulating a `[u8; 20]` array, is specifically designed to represent Git hash IDs.
 /// In Git's context, these IDs are 40-character hexadecimal strings generated via the SHA-1 algorithm.
@@ -25,19 +27,47 @@ use serde::{Deserialize, Serialize};
 /// understandable. - Nov 26, 2023 (by @genedna)
 ///
 #[allow(unused)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default,Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
 pub struct SHA1(pub [u8; 20]);
 
-/// Display trait for SHA1, and colored output improve the readability in the terminal.
+/// Serialized as the 40 hex characters in human readable formats like JSON, and as the 20 bytes
+/// in binary ones like bincode.
+impl Serialize for SHA1 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_plain_str())
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SHA1 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            std::str::FromStr::from_str(&hex).map_err(de::Error::custom)
+        } else {
+            <[u8; 20]>::deserialize(deserializer).map(SHA1)
+        }
+    }
+}
+
+/// Display trait for SHA1, the same as `to_plain_str()`. Use [`Render::styled`] for the hash
+/// colored in the terminal.
 impl Display for SHA1 {
-    /// # Attention
-    /// cause of the color chars for ,if you want to use the string without color ,
-    /// please call the func:`to_plain_str()` rather than the func:`to_string()`
-    /// # Example
-    ///  the hash value `18fd2deaaf152c7f1222c52fb2673f6192b375f0`<br>
-    ///  will be the `1;31m8d2deaaf152c7f1222c52fb2673f6192b375f00m`
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.to_plain_str().red().bold())
+        self.render_text(Style::Plain, f)
+    }
+}
+
+impl Render for SHA1 {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", paint(&self.to_plain_str(), Part::Id, style))
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::String(self.to_plain_str())
     }
 }
 
@@ -100,6 +130,18 @@ impl SHA1 {
         SHA1(result)
     }
 
+    /// Calculate the id of a git object: the hash of `<type> <size>\0` followed by the object data
+    pub fn from_type_and_data(object_type: ObjectType, data: &[u8]) -> SHA1 {
+        let mut d = sha1_smol::Sha1::new();
+        d.update(object_type.to_bytes());
+        d.update(b" ");
+        d.update(data.len().to_string().as_bytes());
+        d.update(b"\0");
+        d.update(data);
+
+        SHA1(d.digest().bytes())
+    }
+
     /// Create Hash from a byte array, which is a 20-byte array already calculated
     pub fn from_bytes(bytes: &[u8]) -> SHA1 {
         let mut h = SHA1::default();
@@ -130,6 +172,7 @@ mod tests {
     use std::io::Read;
 
     use crate::hash::SHA1;
+    use crate::internal::object::types::ObjectType;
 
     #[test]
     fn test_sha1_new() {
@@ -220,4 +263,22 @@ mod tests {
             
         }
     }
+
+    #[test]
+    fn test_sha1_from_type_and_data() {
+        let blob = SHA1::from_type_and_data(ObjectType::Blob, b"Hello, world!\n");
+        assert_eq!(blob.to_plain_str(), "af5626b4a114abcb82d63db7c8082c3c4756e51b");
+
+        let empty_tree = SHA1::from_type_and_data(ObjectType::Tree, &[]);
+        assert_eq!(empty_tree.to_plain_str(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+    }
+
+    #[test]
+    fn test_sha1_serde() {
+        let hash = SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap();
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, "\"8ab686eafeb1f44702738c8b0f24f2567c36da6d\"");
+        assert_eq!(serde_json::from_str::<SHA1>(&json).unwrap(), hash);
+        assert!(serde_json::from_str::<SHA1>("\"8ab686\"").is_err());
+    }
 }
@@ -0,0 +1,249 @@
+//! Line level blame.
+//!
+//! Blame starts from the lines of a file at some commit and walks backwards through history.
+//! At every commit the lines still waiting for an origin are diffed against the same file in each
+//! parent: lines that also exist in a parent are handed down to it (with their position in the
+//! parent's version), and lines no parent has are attributed to the commit itself.
+//!
+//! This module only does the line bookkeeping. Loading commits and file contents, and deciding the
+//! order in which commits are visited, is up to the caller, who feeds each visited commit into
+//! [`Blame::pass_blame`] together with the lines it received from its children.
+//!
+use crate::hash::SHA1;
+use crate::internal::diff::{self, DiffOp};
+
+/// A line of the blamed file that has not been attributed yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingLine {
+    /// Index of the line in the blamed (final) version of the file.
+    pub final_index: usize,
+    /// Index of the same line in the version of the file currently being examined.
+    pub index: usize,
+}
+
+/// The origin of one line of the blamed file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlameLine {
+    /// 1-based line number in the blamed version.
+    pub line_no: usize,
+    /// 1-based line number in the commit that introduced the line.
+    pub orig_line_no: usize,
+    pub commit_id: SHA1,
+    pub content: String,
+}
+
+pub struct Blame {
+    lines: Vec<String>,
+    origins: Vec<Option<(SHA1, usize)>>,
+}
+
+impl Blame {
+    pub fn new(content: &str) -> Self {
+        let lines: Vec<String> = diff::split_lines(content)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let origins = vec![None; lines.len()];
+        Blame { lines, origins }
+    }
+
+    /// Every line of the file, to be passed to the commit the blame starts from.
+    pub fn initial_pending(&self) -> Vec<PendingLine> {
+        (0..self.lines.len())
+            .map(|i| PendingLine {
+                final_index: i,
+                index: i,
+            })
+            .collect()
+    }
+
+    /// Examine `commit`, whose version of the file is `content`, for the `pending` lines it was handed.
+    ///
+    /// `parents` holds the parent versions of the file in parent order, `None` when the file does
+    /// not exist in that parent. Lines found in a parent are handed to the first such parent;
+    /// the returned vector has one entry per parent with the lines it must explain. Lines not found
+    /// in any parent are attributed to `commit`.
+    pub fn pass_blame(
+        &mut self,
+        commit: SHA1,
+        content: &str,
+        pending: Vec<PendingLine>,
+        parents: &[Option<&str>],
+    ) -> Vec<Vec<PendingLine>> {
+        let current = diff::split_lines(content);
+        let mut remaining = pending;
+        let mut handed = Vec::with_capacity(parents.len());
+
+        for parent in parents {
+            let Some(parent) = parent else {
+                handed.push(Vec::new());
+                continue;
+            };
+            let parent_lines = diff::split_lines(parent);
+            let ops = diff::diff(&parent_lines, &current);
+            let (to_parent, rest) = split_by_parent(&ops, remaining);
+            handed.push(to_parent);
+            remaining = rest;
+        }
+
+        self.attribute(commit, &remaining);
+        handed
+    }
+
+    /// Attribute every given line to `commit`, used for root commits and when the walk is cut short.
+    pub fn attribute(&mut self, commit: SHA1, lines: &[PendingLine]) {
+        for line in lines {
+            self.origins[line.final_index] = Some((commit, line.index));
+        }
+    }
+
+    /// Whether every line has an origin.
+    pub fn is_complete(&self) -> bool {
+        self.origins.iter().all(Option::is_some)
+    }
+
+    /// The blamed lines in file order. Lines without an origin are attributed to `boundary`.
+    pub fn finish(self, boundary: SHA1) -> Vec<BlameLine> {
+        self.lines
+            .into_iter()
+            .zip(self.origins)
+            .enumerate()
+            .map(|(i, (content, origin))| {
+                let (commit_id, orig) = origin.unwrap_or((boundary, i));
+                BlameLine {
+                    line_no: i + 1,
+                    orig_line_no: orig + 1,
+                    commit_id,
+                    content,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Partition `pending` into lines that exist unchanged in the parent (translated to parent
+/// indexes) and lines the parent does not have.
+fn split_by_parent(
+    ops: &[DiffOp],
+    mut pending: Vec<PendingLine>,
+) -> (Vec<PendingLine>, Vec<PendingLine>) {
+    pending.sort_by_key(|l| l.index);
+    let mut to_parent = Vec::new();
+    let mut rest = Vec::new();
+    let mut op_iter = ops.iter().filter_map(|op| match *op {
+        DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } => Some((old_index, new_index, len)),
+        _ => None,
+    });
+    let mut current = op_iter.next();
+
+    for line in pending {
+        while let Some((_, new_index, len)) = current {
+            if line.index >= new_index + len {
+                current = op_iter.next();
+            } else {
+                break;
+            }
+        }
+        match current {
+            Some((old_index, new_index, _)) if line.index >= new_index => to_parent.push(PendingLine {
+                final_index: line.final_index,
+                index: old_index + (line.index - new_index),
+            }),
+            _ => rest.push(line),
+        }
+    }
+    (to_parent, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blame, PendingLine};
+    use crate::hash::SHA1;
+
+    fn id(n: u8) -> SHA1 {
+        SHA1([n; 20])
+    }
+
+    #[test]
+    fn test_blame_linear_history() {
+        // c1: a b c  ->  c2: a B c d  ->  c3: x a B c d
+        let v1 = "a\nb\nc\n";
+        let v2 = "a\nB\nc\nd\n";
+        let v3 = "x\na\nB\nc\nd\n";
+
+        let mut blame = Blame::new(v3);
+        let pending = blame.initial_pending();
+        let to_c2 = blame.pass_blame(id(3), v3, pending, &[Some(v2)]).remove(0);
+        assert_eq!(to_c2.len(), 4);
+        let to_c1 = blame.pass_blame(id(2), v2, to_c2, &[Some(v1)]).remove(0);
+        assert_eq!(
+            to_c1,
+            vec![
+                PendingLine {
+                    final_index: 1,
+                    index: 0
+                },
+                PendingLine {
+                    final_index: 3,
+                    index: 2
+                },
+            ]
+        );
+        let rest = blame.pass_blame(id(1), v1, to_c1, &[]);
+        assert!(rest.is_empty());
+        assert!(blame.is_complete());
+
+        let lines = blame.finish(id(0));
+        let origins: Vec<(SHA1, usize)> =
+            lines.iter().map(|l| (l.commit_id, l.orig_line_no)).collect();
+        assert_eq!(
+            origins,
+            vec![(id(3), 1), (id(1), 1), (id(2), 2), (id(1), 3), (id(2), 4)]
+        );
+        assert_eq!(lines[2].content, "B\n");
+    }
+
+    #[test]
+    fn test_blame_merge_commit() {
+        // base: a b; left adds L at top, right adds R at bottom; merge has both plus M
+        let base = "a\nb\n";
+        let left = "L\na\nb\n";
+        let right = "a\nb\nR\n";
+        let merged = "L\na\nb\nR\nM\n";
+
+        let mut blame = Blame::new(merged);
+        let pending = blame.initial_pending();
+        let handed = blame.pass_blame(id(9), merged, pending, &[Some(left), Some(right)]);
+        assert_eq!(handed[0].len(), 3);
+        assert_eq!(
+            handed[1],
+            vec![PendingLine {
+                final_index: 3,
+                index: 2
+            }]
+        );
+
+        let to_base = blame
+            .pass_blame(id(1), left, handed[0].clone(), &[Some(base)])
+            .remove(0);
+        blame.pass_blame(id(2), right, handed[1].clone(), &[Some(base)]);
+        blame.pass_blame(id(0), base, to_base, &[]);
+
+        let commits: Vec<SHA1> = blame.finish(id(7)).into_iter().map(|l| l.commit_id).collect();
+        assert_eq!(commits, vec![id(1), id(0), id(0), id(2), id(9)]);
+    }
+
+    #[test]
+    fn test_blame_file_added_in_parent_missing() {
+        let content = "one\ntwo\n";
+        let mut blame = Blame::new(content);
+        let pending = blame.initial_pending();
+        let handed = blame.pass_blame(id(4), content, pending, &[None]);
+        assert!(handed[0].is_empty());
+        assert!(blame.is_complete());
+    }
+}
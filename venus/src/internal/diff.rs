@@ -0,0 +1,534 @@
+//! Line oriented diff engine.
+//!
+//! The implementation follows Eugene W. Myers, "An O(ND) Difference Algorithm and Its Variations",
+//! using the linear space refinement: the middle snake of the edit graph is found by running the
+//! greedy algorithm forward and backward at the same time, and the problem is split there
+//! recursively. Memory use stays proportional to the input size regardless of how different the
+//! two sides are, which matters for blame and merge where whole files are compared.
+//!
+
+/// One run of the edit script, expressed in indexes of the old and new sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffOp {
+    /// `len` items are the same in both sequences.
+    Equal {
+        old_index: usize,
+        new_index: usize,
+        len: usize,
+    },
+    /// `old_len` items of the old sequence are removed before `new_index`.
+    Delete {
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+    },
+    /// `new_len` items of the new sequence are inserted before `old_index`.
+    Insert {
+        old_index: usize,
+        new_index: usize,
+        new_len: usize,
+    },
+}
+
+impl DiffOp {
+    /// Range of the old sequence covered by this operation.
+    pub fn old_range(&self) -> std::ops::Range<usize> {
+        match *self {
+            DiffOp::Equal { old_index, len, .. } => old_index..old_index + len,
+            DiffOp::Delete {
+                old_index, old_len, ..
+            } => old_index..old_index + old_len,
+            DiffOp::Insert { old_index, .. } => old_index..old_index,
+        }
+    }
+
+    /// Range of the new sequence covered by this operation.
+    pub fn new_range(&self) -> std::ops::Range<usize> {
+        match *self {
+            DiffOp::Equal { new_index, len, .. } => new_index..new_index + len,
+            DiffOp::Delete { new_index, .. } => new_index..new_index,
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => new_index..new_index + new_len,
+        }
+    }
+}
+
+/// Split text into lines, keeping the line terminators so the pieces can be joined back losslessly.
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Compute a minimal edit script turning `old` into `new`.
+///
+/// Consecutive operations of the same kind are merged, so the result alternates between
+/// `Equal` runs and change runs (a `Delete` possibly followed by an `Insert`).
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let mut builder = OpBuilder::default();
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut vf = V::new(max_d);
+    let mut vb = V::new(max_d);
+    conquer(
+        old,
+        0..old.len(),
+        new,
+        0..new.len(),
+        &mut vf,
+        &mut vb,
+        &mut builder,
+    );
+    builder.ops
+}
+
+/// Changes of an edit script with the unchanged items around them, as shown in a unified diff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    /// Index of the first item of the old sequence in the hunk, from 0.
+    pub old_start: usize,
+    pub old_len: usize,
+    /// Index of the first item of the new sequence in the hunk, from 0.
+    pub new_start: usize,
+    pub new_len: usize,
+    /// The operations of the hunk, its first and last `Equal` runs cut down to the context.
+    pub ops: Vec<DiffOp>,
+}
+
+impl Hunk {
+    fn new(ops: Vec<DiffOp>) -> Hunk {
+        let old_start = ops[0].old_range().start;
+        let new_start = ops[0].new_range().start;
+        let last = ops[ops.len() - 1];
+        Hunk {
+            old_start,
+            old_len: last.old_range().end - old_start,
+            new_start,
+            new_len: last.new_range().end - new_start,
+            ops,
+        }
+    }
+}
+
+/// Group the changes of `ops` into hunks showing `context` unchanged items before and after each
+/// change. Changes less than twice the context apart share a hunk.
+pub fn hunks(ops: &[DiffOp], context: usize) -> Vec<Hunk> {
+    let mut hunks = vec![];
+    let mut current: Vec<DiffOp> = vec![];
+    let mut changed = false;
+    for (i, op) in ops.iter().enumerate() {
+        let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = *op
+        else {
+            current.push(*op);
+            changed = true;
+            continue;
+        };
+        let last = i == ops.len() - 1;
+        if changed && !last && len <= 2 * context {
+            current.push(*op);
+            continue;
+        }
+        if changed {
+            let after = len.min(context);
+            if after > 0 {
+                current.push(DiffOp::Equal {
+                    old_index,
+                    new_index,
+                    len: after,
+                });
+            }
+            hunks.push(Hunk::new(std::mem::take(&mut current)));
+            changed = false;
+        }
+        let before = len.min(context);
+        if !last && before > 0 {
+            current.push(DiffOp::Equal {
+                old_index: old_index + len - before,
+                new_index: new_index + len - before,
+                len: before,
+            });
+        }
+    }
+    if changed {
+        hunks.push(Hunk::new(current));
+    }
+    hunks
+}
+
+/// Diagonal indexed vector used by the greedy search, `k` ranges over `-max_d..=max_d`.
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> Self {
+        V {
+            offset: max_d as isize,
+            v: vec![0; 2 * max_d + 2],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+#[derive(Default)]
+struct OpBuilder {
+    ops: Vec<DiffOp>,
+}
+
+impl OpBuilder {
+    fn equal(&mut self, old_index: usize, new_index: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        if let Some(DiffOp::Equal { len: prev, .. }) = self.ops.last_mut() {
+            *prev += len;
+        } else {
+            self.ops.push(DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            });
+        }
+    }
+
+    fn delete(&mut self, old_index: usize, old_len: usize, new_index: usize) {
+        if old_len == 0 {
+            return;
+        }
+        match self.ops.last_mut() {
+            Some(DiffOp::Delete { old_len: prev, .. }) => *prev += old_len,
+            // keep deletes in front of inserts so every change run reads "delete then insert"
+            Some(DiffOp::Insert {
+                old_index: ins_old,
+                new_len,
+                ..
+            }) if *ins_old == old_index => {
+                let new_len = *new_len;
+                self.ops.pop();
+                self.delete(old_index, old_len, new_index - new_len);
+                self.insert(old_index + old_len, new_index - new_len, new_len);
+            }
+            _ => self.ops.push(DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            }),
+        }
+    }
+
+    fn insert(&mut self, old_index: usize, new_index: usize, new_len: usize) {
+        if new_len == 0 {
+            return;
+        }
+        if let Some(DiffOp::Insert { new_len: prev, .. }) = self.ops.last_mut() {
+            *prev += new_len;
+        } else {
+            self.ops.push(DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            });
+        }
+    }
+}
+
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_range: std::ops::Range<usize>,
+    new: &[T],
+    mut new_range: std::ops::Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+    builder: &mut OpBuilder,
+) {
+    // common prefix
+    let mut prefix = 0;
+    while old_range.start + prefix < old_range.end
+        && new_range.start + prefix < new_range.end
+        && old[old_range.start + prefix] == new[new_range.start + prefix]
+    {
+        prefix += 1;
+    }
+    builder.equal(old_range.start, new_range.start, prefix);
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    // common suffix, emitted after the middle part
+    let mut suffix = 0;
+    while old_range.end - suffix > old_range.start
+        && new_range.end - suffix > new_range.start
+        && old[old_range.end - suffix - 1] == new[new_range.end - suffix - 1]
+    {
+        suffix += 1;
+    }
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() {
+        builder.insert(old_range.start, new_range.start, new_range.len());
+    } else if new_range.is_empty() {
+        builder.delete(old_range.start, old_range.len(), new_range.start);
+    } else if let Some((x, y)) =
+        find_middle_snake(old, old_range.clone(), new, new_range.clone(), vf, vb)
+    {
+        conquer(old, old_range.start..x, new, new_range.start..y, vf, vb, builder);
+        conquer(old, x..old_range.end, new, y..new_range.end, vf, vb, builder);
+    } else {
+        builder.delete(old_range.start, old_range.len(), new_range.start);
+        builder.insert(old_range.end, new_range.start, new_range.len());
+    }
+
+    builder.equal(old_range.end, new_range.end, suffix);
+}
+
+/// Find a point on an optimal path through the edit graph of the two ranges, splitting the
+/// problem into two independent halves.
+fn find_middle_snake<T: PartialEq>(
+    old: &[T],
+    old_range: std::ops::Range<usize>,
+    new: &[T],
+    new_range: std::ops::Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+) -> Option<(usize, usize)> {
+    let n = old_range.len();
+    let m = new_range.len();
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    let d_max = ((n + m).div_ceil(2) + 1) as isize;
+
+    vf[1] = 0;
+    vb[1] = 0;
+
+    for d in 0..d_max {
+        // forward search
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
+                vf[k + 1]
+            } else {
+                vf[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[old_range.start + x] == new[new_range.start + y] {
+                x += 1;
+                y += 1;
+            }
+            vf[k] = x;
+            if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                return Some((old_range.start + x0, new_range.start + y0));
+            }
+        }
+
+        // backward search
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) {
+                vb[k + 1]
+            } else {
+                vb[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            while x < n
+                && y < m
+                && old[old_range.start + n - x - 1] == new[new_range.start + m - y - 1]
+            {
+                x += 1;
+                y += 1;
+            }
+            vb[k] = x;
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                return Some((old_range.start + n - x, new_range.start + m - y));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, hunks, split_lines, DiffOp, Hunk};
+
+    /// Rebuild `new` from `old` and the edit script to check the script is consistent.
+    fn apply<T: Clone + PartialEq + std::fmt::Debug>(old: &[T], new: &[T], ops: &[DiffOp]) -> Vec<T> {
+        let mut out = Vec::new();
+        let mut old_pos = 0;
+        for op in ops {
+            assert_eq!(op.old_range().start, old_pos);
+            match *op {
+                DiffOp::Equal { .. } => {
+                    assert_eq!(&old[op.old_range()], &new[op.new_range()]);
+                    out.extend_from_slice(&old[op.old_range()]);
+                }
+                DiffOp::Delete { .. } => {}
+                DiffOp::Insert { .. } => out.extend_from_slice(&new[op.new_range()]),
+            }
+            old_pos = op.old_range().end;
+        }
+        assert_eq!(old_pos, old.len());
+        out
+    }
+
+    fn lcs_len<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+        let mut dp = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                dp[i][j] = if a[i] == b[j] {
+                    dp[i + 1][j + 1] + 1
+                } else {
+                    dp[i + 1][j].max(dp[i][j + 1])
+                };
+            }
+        }
+        dp[0][0]
+    }
+
+    fn equal_len(ops: &[DiffOp]) -> usize {
+        ops.iter()
+            .map(|op| match op {
+                DiffOp::Equal { len, .. } => *len,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_diff_identical_and_empty() {
+        let a = vec!["a", "b", "c"];
+        assert_eq!(
+            diff(&a, &a),
+            vec![DiffOp::Equal {
+                old_index: 0,
+                new_index: 0,
+                len: 3
+            }]
+        );
+        let empty: Vec<&str> = vec![];
+        assert!(diff(&empty, &empty).is_empty());
+        assert_eq!(
+            diff(&empty, &a),
+            vec![DiffOp::Insert {
+                old_index: 0,
+                new_index: 0,
+                new_len: 3
+            }]
+        );
+        assert_eq!(
+            diff(&a, &empty),
+            vec![DiffOp::Delete {
+                old_index: 0,
+                old_len: 3,
+                new_index: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_replace_line() {
+        let old = split_lines("fn main() {\n    println!(\"a\");\n}\n");
+        let new = split_lines("fn main() {\n    println!(\"b\");\n}\n");
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                DiffOp::Equal {
+                    old_index: 0,
+                    new_index: 0,
+                    len: 1
+                },
+                DiffOp::Delete {
+                    old_index: 1,
+                    old_len: 1,
+                    new_index: 1
+                },
+                DiffOp::Insert {
+                    old_index: 2,
+                    new_index: 1,
+                    new_len: 1
+                },
+                DiffOp::Equal {
+                    old_index: 2,
+                    new_index: 2,
+                    len: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_is_minimal() {
+        let cases = [
+            ("abcabba", "cbabac"),
+            ("xaxbxcx", "abc"),
+            ("abcdefgh", "hgfedcba"),
+            ("aaaaaaab", "baaaaaaa"),
+            ("the quick brown fox", "the slow brown dog"),
+        ];
+        for (a, b) in cases {
+            let old: Vec<char> = a.chars().collect();
+            let new: Vec<char> = b.chars().collect();
+            let ops = diff(&old, &new);
+            assert_eq!(apply(&old, &new, &ops), new);
+            assert_eq!(equal_len(&ops), lcs_len(&old, &new), "{} -> {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_split_lines_keeps_terminators() {
+        assert_eq!(split_lines("a\nb\n"), vec!["a\n", "b\n"]);
+        assert_eq!(split_lines("a\nb"), vec!["a\n", "b"]);
+        assert!(split_lines("").is_empty());
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old: Vec<char> = "abcdefghijklmnop".chars().collect();
+        let new: Vec<char> = "abcdXfghijkYmnop".chars().collect();
+        let ops = diff(&old, &new);
+
+        let far = hunks(&ops, 2);
+        assert_eq!(far.len(), 2);
+        assert_eq!(
+            (
+                far[0].old_start,
+                far[0].old_len,
+                far[0].new_start,
+                far[0].new_len
+            ),
+            (2, 5, 2, 5)
+        );
+        assert_eq!(far[1].old_start, 9);
+        assert_eq!(
+            far[1].ops.first(),
+            Some(&DiffOp::Equal {
+                old_index: 9,
+                new_index: 9,
+                len: 2
+            })
+        );
+
+        let near = hunks(&ops, 3);
+        assert_eq!(near.len(), 1);
+        assert_eq!((near[0].old_start, near[0].old_len), (1, 14));
+
+        let bare = hunks(&ops, 0);
+        assert!(bare
+            .iter()
+            .all(|h: &Hunk| h.ops.iter().all(|op| !matches!(op, DiffOp::Equal { .. }))));
+        assert!(hunks(&diff(&old, &old), 3).is_empty());
+    }
+}
@@ -0,0 +1,321 @@
+//! `.gitattributes` files, which set attributes such as `export-ignore`, `eol` or `filter` on
+//! the paths their patterns match.
+//!
+//! Each line of a file is a pattern and the attributes it sets: `name` sets one, `-name` unsets
+//! it, `name=value` gives it a value and `!name` leaves it unspecified again, as if no line before
+//! had set it. Patterns match like those of `.gitignore`: one without a `/` matches the name of a
+//! file at any depth below the directory of its file, the others match the path below that
+//! directory, `*` and `?` never crossing a `/` and `**` crossing any number of them.
+//!
+//! When several lines set the same attribute of a path, the last one wins, and the files of
+//! deeper directories win over those of the directories above them. Macros other than the
+//! built-in `binary`, `-diff -merge -text`, are not expanded.
+//!
+use std::collections::HashMap;
+
+/// The name of the files.
+pub const FILE_NAME: &str = ".gitattributes";
+
+/// The state of an attribute of a path, one no line specifies being absent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    Set,
+    Unset,
+    Value(String),
+}
+
+struct Rule {
+    pattern: String,
+    /// `None` for the attributes `!name` leaves unspecified
+    attrs: Vec<(String, Option<AttrValue>)>,
+}
+
+/// The `.gitattributes` files of a tree.
+#[derive(Default)]
+pub struct Gitattributes {
+    /// The directory of each file, `/` separated below the root, `""` for the root, with its rules
+    files: Vec<(String, Vec<Rule>)>,
+}
+
+impl Gitattributes {
+    /// Add the file of the directory `dir`, `""` for the root of the tree.
+    pub fn add(&mut self, dir: &str, text: &str) {
+        let mut rules = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            // `[attr]` lines define macros
+            if line.is_empty() || line.starts_with('#') || line.starts_with("[attr]") {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next() else {
+                continue;
+            };
+            // negative patterns are forbidden, and patterns of directories never match a file
+            if pattern.starts_with('!') || pattern.ends_with('/') {
+                continue;
+            }
+            let mut attrs = Vec::new();
+            for field in fields {
+                if field == "binary" {
+                    for name in ["diff", "merge", "text"] {
+                        attrs.push((name.to_owned(), Some(AttrValue::Unset)));
+                    }
+                    attrs.push(("binary".to_owned(), Some(AttrValue::Set)));
+                } else if let Some(name) = field.strip_prefix('-') {
+                    attrs.push((name.to_owned(), Some(AttrValue::Unset)));
+                } else if let Some(name) = field.strip_prefix('!') {
+                    attrs.push((name.to_owned(), None));
+                } else if let Some((name, value)) = field.split_once('=') {
+                    attrs.push((name.to_owned(), Some(AttrValue::Value(value.to_owned()))));
+                } else {
+                    attrs.push((field.to_owned(), Some(AttrValue::Set)));
+                }
+            }
+            rules.push(Rule {
+                pattern: pattern.to_owned(),
+                attrs,
+            });
+        }
+        let dir = dir.trim_matches('/').to_owned();
+        let level = depth(&dir);
+        let at = self.files.partition_point(|(d, _)| depth(d) <= level);
+        self.files.insert(at, (dir, rules));
+    }
+
+    /// The attributes of the file at `path`, `/` separated below the root of the tree.
+    pub fn attributes(&self, path: &str) -> HashMap<String, AttrValue> {
+        let path = path.trim_matches('/');
+        let mut attributes = HashMap::new();
+        for (dir, rules) in &self.files {
+            let below = if dir.is_empty() {
+                path
+            } else {
+                match path.strip_prefix(dir.as_str()) {
+                    Some(rest) if rest.starts_with('/') => &rest[1..],
+                    _ => continue,
+                }
+            };
+            for rule in rules {
+                if !pattern_matches(&rule.pattern, below) {
+                    continue;
+                }
+                for (name, value) in &rule.attrs {
+                    match value {
+                        Some(value) => attributes.insert(name.clone(), value.clone()),
+                        None => attributes.remove(name),
+                    };
+                }
+            }
+        }
+        attributes
+    }
+
+    /// The state of the attribute `name` of the file at `path`, `None` when unspecified.
+    pub fn get(&self, path: &str, name: &str) -> Option<AttrValue> {
+        self.attributes(path).remove(name)
+    }
+
+    /// Whether the attribute `name` of the file at `path` is set.
+    pub fn is_set(&self, path: &str, name: &str) -> bool {
+        self.get(path, name) == Some(AttrValue::Set)
+    }
+
+    /// The value of the attribute `name` of the file at `path`, when it has one.
+    pub fn value(&self, path: &str, name: &str) -> Option<String> {
+        match self.get(path, name) {
+            Some(AttrValue::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The `text` and `eol` attributes of the file at `path` as `git ls-files --eol` shows them,
+    /// e.g. `text=auto eol=lf`, `None` when neither is specified.
+    pub fn eol_attributes(&self, path: &str) -> Option<String> {
+        let attributes = self.attributes(path);
+        let mut shown = Vec::new();
+        match attributes.get("text") {
+            Some(AttrValue::Set) => shown.push("text".to_owned()),
+            Some(AttrValue::Unset) => shown.push("-text".to_owned()),
+            Some(AttrValue::Value(value)) => shown.push(format!("text={}", value)),
+            None => {}
+        }
+        if let Some(AttrValue::Value(value)) = attributes.get("eol") {
+            shown.push(format!("eol={}", value));
+        }
+        (!shown.is_empty()).then(|| shown.join(" "))
+    }
+}
+
+fn depth(dir: &str) -> usize {
+    if dir.is_empty() {
+        0
+    } else {
+        dir.split('/').count()
+    }
+}
+
+/// Whether `pattern` of a file matches `path`, below the directory of the file.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let anchored = pattern.trim_start_matches('/');
+    if !pattern.contains('/') {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        return glob(pattern.as_bytes(), name.as_bytes());
+    }
+    glob(anchored.as_bytes(), path.as_bytes())
+}
+
+/// Match `text` against the glob `pattern`, `*` and `?` not matching `/`.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*'] => true,
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, c)| *c == b'/' && glob(rest, &text[i + 1..]))
+        }
+        [b'*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => matches!(text, [c, ..] if *c != b'/') && glob(rest, &text[1..]),
+        [b'[', rest @ ..] => match class(rest, text.first().copied()) {
+            Some((true, rest)) => glob(rest, &text[1..]),
+            Some((false, _)) => false,
+            // no closing `]`, the `[` is itself
+            None => matches!(text, [b'[', ..]) && glob(rest, &text[1..]),
+        },
+        [b'\\', c, rest @ ..] => matches!(text, [t, ..] if t == c) && glob(rest, &text[1..]),
+        [c, rest @ ..] => matches!(text, [t, ..] if t == c) && glob(rest, &text[1..]),
+    }
+}
+
+/// Whether `c` is in the class at the start of `pattern`, just after its `[`, with what follows
+/// the class. `None` when the class has no end.
+fn class(pattern: &[u8], c: Option<u8>) -> Option<(bool, &[u8])> {
+    let (negated, mut rest) = match pattern {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [] => return None,
+            [b']', after @ ..] if !first => {
+                let matched = c.is_some_and(|c| c != b'/' && found != negated);
+                return Some((matched, after));
+            }
+            [low, b'-', high, after @ ..] if *high != b']' => {
+                found |= c.is_some_and(|c| (*low..=*high).contains(&c));
+                rest = after;
+            }
+            [x, after @ ..] => {
+                found |= c == Some(*x);
+                rest = after;
+            }
+        }
+        first = false;
+    }
+}
+
+/// The line endings of `data` as `git ls-files --eol` shows those of the index: `-text` for
+/// binary data, else `none`, `lf`, `crlf` or `mixed`.
+pub fn detect_eol(data: &[u8]) -> &'static str {
+    // like git, data with a NUL byte in its first 8000 is binary
+    if data.iter().take(8000).any(|b| *b == 0) {
+        return "-text";
+    }
+    let mut lf = 0;
+    let mut crlf = 0;
+    for (i, b) in data.iter().enumerate() {
+        if *b == b'\n' {
+            if i > 0 && data[i - 1] == b'\r' {
+                crlf += 1;
+            } else {
+                lf += 1;
+            }
+        }
+    }
+    match (lf, crlf) {
+        (0, 0) => "none",
+        (_, 0) => "lf",
+        (0, _) => "crlf",
+        _ => "mixed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_eol, glob, AttrValue, Gitattributes};
+
+    #[test]
+    fn test_glob() {
+        assert!(glob(b"*.rs", b"main.rs"));
+        assert!(!glob(b"*.rs", b"src/main.rs"));
+        assert!(glob(b"src/*.rs", b"src/main.rs"));
+        assert!(glob(b"**/test/*", b"a/b/test/x"));
+        assert!(glob(b"**/test/*", b"test/x"));
+        assert!(glob(b"docs/**", b"docs/a/b.md"));
+        assert!(glob(b"a/**/b", b"a/b"));
+        assert!(glob(b"a/**/b", b"a/x/y/b"));
+        assert!(glob(b"file?.[ch]", b"file1.c"));
+        assert!(!glob(b"file?.[!ch]", b"file1.c"));
+        assert!(glob(b"[a-c]x", b"bx"));
+        assert!(glob(b"\\*x", b"*x"));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut attributes = Gitattributes::default();
+        attributes.add(
+            "",
+            "# root\n*.png binary\n*.psd filter=lfs diff=lfs merge=lfs -text\n/docs export-ignore\n*.sh text eol=lf\n",
+        );
+        attributes.add("tools", "*.sh eol=crlf\nbuild.sh !eol\n");
+
+        assert!(attributes.is_set("docs", "export-ignore"));
+        // anchored at the root
+        assert!(!attributes.is_set("src/docs", "export-ignore"));
+        assert_eq!(
+            attributes.value("art/logo.psd", "filter").as_deref(),
+            Some("lfs")
+        );
+        assert_eq!(attributes.get("logo.png", "text"), Some(AttrValue::Unset));
+        assert!(attributes.is_set("img/logo.png", "binary"));
+
+        assert_eq!(
+            attributes.eol_attributes("run.sh").as_deref(),
+            Some("text eol=lf")
+        );
+        // the file below wins, and `!` unspecifies
+        assert_eq!(
+            attributes.eol_attributes("tools/install.sh").as_deref(),
+            Some("text eol=crlf")
+        );
+        assert_eq!(
+            attributes.eol_attributes("tools/build.sh").as_deref(),
+            Some("text")
+        );
+        assert_eq!(attributes.eol_attributes("README.md"), None);
+    }
+
+    #[test]
+    fn test_detect_eol() {
+        assert_eq!(detect_eol(b"a\nb\n"), "lf");
+        assert_eq!(detect_eol(b"a\r\nb\r\n"), "crlf");
+        assert_eq!(detect_eol(b"a\r\nb\n"), "mixed");
+        assert_eq!(detect_eol(b"no newline"), "none");
+        assert_eq!(detect_eol(b"\0binary\n"), "-text");
+    }
+}
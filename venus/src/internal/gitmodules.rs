@@ -0,0 +1,253 @@
+//! The `.gitmodules` file, which names the repositories the gitlinks of a tree point into.
+//!
+//! A tree item of mode `160000` pins a commit of another repository at its path, and the
+//! `.gitmodules` at the root of the tree tells where that repository is:
+//!
+//! ```text
+//! [submodule "libfoo"]
+//!     path = vendor/libfoo
+//!     url = https://example.com/libfoo.git
+//!     branch = main
+//! ```
+//!
+//! Only the part of the git config syntax `git submodule` writes is read: sections, `key = value`
+//! lines, comments, quoted values and their escapes. Unknown keys and sections are skipped.
+//!
+use std::fmt::{self, Display};
+
+/// A submodule of a `.gitmodules` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submodule {
+    pub name: String,
+    /// Path of the gitlink below the root of the tree
+    pub path: String,
+    pub url: String,
+    pub branch: Option<String>,
+}
+
+/// The submodules of a `.gitmodules` file, in the order they appear.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Gitmodules {
+    pub submodules: Vec<Submodule>,
+}
+
+impl Gitmodules {
+    /// Read a `.gitmodules` file. Submodules without a path or url are left out, as git leaves
+    /// them out.
+    pub fn parse(text: &str) -> Gitmodules {
+        let mut submodules = Vec::new();
+        let mut current: Option<Section> = None;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[') {
+                submodules.extend(current.take().and_then(Section::finish));
+                let Some((header, _)) = section.split_once(']') else {
+                    continue;
+                };
+                let header = header.trim();
+                if let Some(name) = header.strip_prefix("submodule") {
+                    let name = name.trim();
+                    if name.len() >= 2 && name.starts_with('"') && name.ends_with('"') {
+                        current = Some(Section {
+                            name: unquote(&name[1..name.len() - 1]),
+                            ..Section::default()
+                        });
+                    }
+                }
+                continue;
+            }
+            let Some(section) = current.as_mut() else {
+                continue;
+            };
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value_of(value)),
+                None => (line, "true".to_owned()),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "path" => section.path = Some(value),
+                "url" => section.url = Some(value),
+                "branch" => section.branch = Some(value),
+                _ => {}
+            }
+        }
+        submodules.extend(current.and_then(Section::finish));
+        Gitmodules { submodules }
+    }
+
+    /// The submodule whose gitlink is at `path`.
+    pub fn find(&self, path: &str) -> Option<&Submodule> {
+        let path = path.trim_matches('/');
+        self.submodules.iter().find(|s| s.path == path)
+    }
+}
+
+impl Display for Gitmodules {
+    /// The file as `git submodule add` writes it.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for submodule in &self.submodules {
+            writeln!(f, "[submodule \"{}\"]", quote(&submodule.name))?;
+            writeln!(f, "\tpath = {}", submodule.path)?;
+            writeln!(f, "\turl = {}", submodule.url)?;
+            if let Some(branch) = &submodule.branch {
+                writeln!(f, "\tbranch = {}", branch)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `submodule` section being read.
+#[derive(Default)]
+struct Section {
+    name: String,
+    path: Option<String>,
+    url: Option<String>,
+    branch: Option<String>,
+}
+
+impl Section {
+    fn finish(self) -> Option<Submodule> {
+        Some(Submodule {
+            name: self.name,
+            path: self.path?.trim_matches('/').to_owned(),
+            url: self.url?,
+            branch: self.branch,
+        })
+    }
+}
+
+/// The value after the `=` of a line, unquoted and without a trailing comment.
+fn value_of(raw: &str) -> String {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = raw.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c) => value.push(c),
+                None => {}
+            },
+            '#' | ';' if !quoted => break,
+            c => value.push(c),
+        }
+    }
+    if quoted {
+        value
+    } else {
+        value.trim_end().to_owned()
+    }
+}
+
+fn unquote(name: &str) -> String {
+    let mut out = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn quote(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The url of a submodule, `url` resolved against `base`, the url of the repository of the
+/// `.gitmodules`, when it is relative like `../lib.git`, as `git submodule` resolves it.
+pub fn resolve_url(base: &str, url: &str) -> String {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return url.to_owned();
+    }
+    let mut base = base.trim_end_matches('/').to_owned();
+    let mut rest = url;
+    loop {
+        if let Some(r) = rest.strip_prefix("./") {
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("../") {
+            rest = r;
+            match base.rfind('/') {
+                // the authority of a url is kept
+                Some(at) if !base[..at].ends_with('/') => base.truncate(at),
+                _ => {}
+            }
+        } else {
+            break;
+        }
+    }
+    format!("{}/{}", base, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_url, Gitmodules, Submodule};
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+# written by git submodule add
+[submodule "libfoo"]
+	path = vendor/libfoo
+	url = https://example.com/libfoo.git
+	branch = main ; the stable branch
+[core]
+	bare = false
+[submodule "has \"quotes\""]
+	Path = "docs/site"
+	url = ../site.git
+[submodule "no-url"]
+	path = broken
+"#;
+        let modules = Gitmodules::parse(text);
+        assert_eq!(
+            modules.submodules,
+            vec![
+                Submodule {
+                    name: "libfoo".to_owned(),
+                    path: "vendor/libfoo".to_owned(),
+                    url: "https://example.com/libfoo.git".to_owned(),
+                    branch: Some("main".to_owned()),
+                },
+                Submodule {
+                    name: "has \"quotes\"".to_owned(),
+                    path: "docs/site".to_owned(),
+                    url: "../site.git".to_owned(),
+                    branch: None,
+                },
+            ]
+        );
+        assert_eq!(modules.find("/docs/site").unwrap().url, "../site.git");
+        assert!(modules.find("broken").is_none());
+
+        // what is written reads back the same
+        assert_eq!(Gitmodules::parse(&modules.to_string()), modules);
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "https://example.com/group/project.git";
+        assert_eq!(
+            resolve_url(base, "../site.git"),
+            "https://example.com/group/site.git"
+        );
+        assert_eq!(
+            resolve_url(base, "../../other/site.git"),
+            "https://example.com/other/site.git"
+        );
+        assert_eq!(
+            resolve_url(base, "./sub.git"),
+            "https://example.com/group/project.git/sub.git"
+        );
+        assert_eq!(
+            resolve_url(base, "git@example.com:lib.git"),
+            "git@example.com:lib.git"
+        );
+    }
+}
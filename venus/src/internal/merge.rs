@@ -0,0 +1,464 @@
+//! Merge base computation and three-way merge.
+//!
+//! Like [`crate::internal::blame`], nothing here loads objects. [`MergeBase`] walks the commit
+//! graph one commit at a time and asks the caller for the parents of each commit it visits, while
+//! [`merge_entry`] and [`merge_text`] decide the result of merging a single tree entry or a single
+//! file. Recursing into sub trees and writing the merged objects is left to the caller.
+//!
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::hash::SHA1;
+use crate::internal::diff::{self, DiffOp};
+use crate::internal::object::tree::{TreeItem, TreeItemMode};
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 1 << 1;
+const STALE: u8 = 1 << 2;
+const RESULT: u8 = 1 << 3;
+
+/// Finds the best common ancestors of two commits.
+///
+/// Commits reachable from either side are painted with the side(s) they are reachable from,
+/// visiting the newest commit first. A commit painted by both sides is a merge base candidate;
+/// everything below it is marked stale, so a candidate later reached through another candidate is
+/// dropped. The walk stops once only stale commits are left in the queue.
+pub struct MergeBase {
+    queue: BinaryHeap<(usize, SHA1)>,
+    flags: HashMap<SHA1, u8>,
+    results: Vec<(usize, SHA1)>,
+    current: Option<SHA1>,
+}
+
+impl MergeBase {
+    /// Start a search from `one` and `two`, given with their committer timestamps.
+    pub fn new(one: (SHA1, usize), two: (SHA1, usize)) -> Self {
+        let mut base = MergeBase {
+            queue: BinaryHeap::new(),
+            flags: HashMap::new(),
+            results: Vec::new(),
+            current: None,
+        };
+        if one.0 == two.0 {
+            base.results.push((one.1, one.0));
+            return base;
+        }
+        base.paint(one.0, one.1, PARENT1);
+        base.paint(two.0, two.1, PARENT2);
+        base
+    }
+
+    fn paint(&mut self, id: SHA1, timestamp: usize, flags: u8) {
+        let current = self.flags.entry(id).or_insert(0);
+        if *current & flags == flags {
+            return;
+        }
+        *current |= flags;
+        self.queue.push((timestamp, id));
+    }
+
+    /// The next commit whose parents must be passed to [`MergeBase::visit`], `None` once the
+    /// search is over.
+    pub fn next_commit(&mut self) -> Option<SHA1> {
+        if !self.queue.iter().any(|(_, id)| self.flags[id] & STALE == 0) {
+            self.current = None;
+            return None;
+        }
+        let (timestamp, id) = self.queue.pop()?;
+        let flags = self.flags.get_mut(&id).unwrap();
+        if *flags & (PARENT1 | PARENT2) == PARENT1 | PARENT2 && *flags & RESULT == 0 {
+            *flags |= RESULT;
+            self.results.push((timestamp, id));
+        }
+        self.current = Some(id);
+        Some(id)
+    }
+
+    /// Hand over the parents of the commit last returned by [`MergeBase::next_commit`], with their
+    /// committer timestamps.
+    pub fn visit(&mut self, parents: &[(SHA1, usize)]) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        let mut flags = self.flags[&current] & (PARENT1 | PARENT2 | STALE);
+        // everything below a common ancestor is common too, but never a better one
+        if flags & (PARENT1 | PARENT2) == PARENT1 | PARENT2 {
+            flags |= STALE;
+        }
+        for (parent, timestamp) in parents {
+            self.paint(*parent, *timestamp, flags);
+        }
+    }
+
+    /// How many commits are reachable from the first commit but not from the second, and the
+    /// other way around, once [`MergeBase::next_commit`] has returned `None`.
+    pub fn unique_counts(&self) -> (usize, usize) {
+        let only = |side: u8| {
+            self.flags
+                .values()
+                .filter(|f| *f & (PARENT1 | PARENT2) == side)
+                .count()
+        };
+        (only(PARENT1), only(PARENT2))
+    }
+
+    /// The commits counted by [`MergeBase::unique_counts`], those reachable from the first commit
+    /// but not from the second and the other way around, in no particular order.
+    pub fn unique_commits(&self) -> (Vec<SHA1>, Vec<SHA1>) {
+        let only = |side: u8| {
+            self.flags
+                .iter()
+                .filter(|(_, f)| **f & (PARENT1 | PARENT2) == side)
+                .map(|(id, _)| *id)
+                .collect()
+        };
+        (only(PARENT1), only(PARENT2))
+    }
+
+    /// The merge bases, newest first. Empty when the commits share no history.
+    pub fn finish(self) -> Vec<SHA1> {
+        let mut results: Vec<(usize, SHA1)> = self
+            .results
+            .into_iter()
+            .filter(|(_, id)| self.flags.get(id).is_none_or(|f| f & STALE == 0))
+            .collect();
+        results.sort_by(|a, b| b.cmp(a));
+        results.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+/// Why an entry could not be merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the same lines of a file, or changed a binary file, link or submodule.
+    Content,
+    /// One side changed the entry, the other deleted it.
+    ModifyDelete,
+    /// Both sides changed the file mode differently.
+    FileMode,
+    /// The sides disagree on what the entry is, e.g. a file on one side and a directory on the other.
+    EntryType,
+}
+
+/// The outcome of merging one name of a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryMerge {
+    /// Decided without looking at content, `None` when the entry is gone from the result.
+    Resolved(Option<TreeItem>),
+    /// Both sides changed a directory, the sub trees must be merged.
+    Trees {
+        base: Option<SHA1>,
+        ours: SHA1,
+        theirs: SHA1,
+    },
+    /// Both sides changed a file, its content must be merged. `mode` is already merged.
+    Blobs {
+        base: Option<SHA1>,
+        ours: SHA1,
+        theirs: SHA1,
+        mode: TreeItemMode,
+    },
+    Conflict(ConflictKind),
+}
+
+fn is_file(mode: TreeItemMode) -> bool {
+    matches!(mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable)
+}
+
+/// Merge one entry present in at least one of the three trees.
+pub fn merge_entry(
+    base: Option<&TreeItem>,
+    ours: Option<&TreeItem>,
+    theirs: Option<&TreeItem>,
+) -> EntryMerge {
+    let same = |a: Option<&TreeItem>, b: Option<&TreeItem>| {
+        a.map(|i| (i.mode, i.id)) == b.map(|i| (i.mode, i.id))
+    };
+    if same(ours, theirs) || same(base, theirs) {
+        return EntryMerge::Resolved(ours.cloned());
+    }
+    if same(base, ours) {
+        return EntryMerge::Resolved(theirs.cloned());
+    }
+
+    let (Some(ours), Some(theirs)) = (ours, theirs) else {
+        return EntryMerge::Conflict(ConflictKind::ModifyDelete);
+    };
+    if ours.mode == TreeItemMode::Tree && theirs.mode == TreeItemMode::Tree {
+        return EntryMerge::Trees {
+            base: base.filter(|b| b.mode == TreeItemMode::Tree).map(|b| b.id),
+            ours: ours.id,
+            theirs: theirs.id,
+        };
+    }
+    if is_file(ours.mode) && is_file(theirs.mode) {
+        let base_mode = base.map(|b| b.mode);
+        let mode = if ours.mode == theirs.mode || base_mode == Some(theirs.mode) {
+            ours.mode
+        } else if base_mode == Some(ours.mode) {
+            theirs.mode
+        } else {
+            return EntryMerge::Conflict(ConflictKind::FileMode);
+        };
+        return EntryMerge::Blobs {
+            base: base.filter(|b| is_file(b.mode)).map(|b| b.id),
+            ours: ours.id,
+            theirs: theirs.id,
+            mode,
+        };
+    }
+    if ours.mode == theirs.mode {
+        EntryMerge::Conflict(ConflictKind::Content)
+    } else {
+        EntryMerge::Conflict(ConflictKind::EntryType)
+    }
+}
+
+/// Result of a line based three-way merge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextMerge {
+    /// The merged text, with conflict markers around every conflicting region.
+    pub content: String,
+    /// Number of conflicting regions, the merge is clean when this is zero.
+    pub conflicts: usize,
+}
+
+/// Merge `ours` and `theirs`, both derived from `base`, line by line (diff3).
+///
+/// The base is diffed against each side. Regions where both sides still match the base are
+/// copied through; every region in between is taken from the side that changed it, or marked as
+/// a conflict labelled with `labels` when both sides changed it differently.
+pub fn merge_text(base: &str, ours: &str, theirs: &str, labels: (&str, &str)) -> TextMerge {
+    let base_lines = diff::split_lines(base);
+    let our_lines = diff::split_lines(ours);
+    let their_lines = diff::split_lines(theirs);
+    let our_match = matching_lines(&diff::diff(&base_lines, &our_lines), base_lines.len());
+    let their_match = matching_lines(&diff::diff(&base_lines, &their_lines), base_lines.len());
+
+    let mut content = String::with_capacity(ours.len().max(theirs.len()));
+    let mut conflicts = 0;
+    let (mut i, mut a, mut b) = (0, 0, 0);
+    loop {
+        if i < base_lines.len() && our_match[i] == Some(a) && their_match[i] == Some(b) {
+            content.push_str(base_lines[i]);
+            i += 1;
+            a += 1;
+            b += 1;
+            continue;
+        }
+
+        // the unstable chunk ends at the next base line both sides kept
+        let (k, a_end, b_end) = (i..base_lines.len())
+            .find_map(|k| Some((k, our_match[k]?, their_match[k]?)))
+            .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+        if (i, a, b) == (k, a_end, b_end) {
+            break;
+        }
+
+        let base_chunk = &base_lines[i..k];
+        let our_chunk = &our_lines[a..a_end];
+        let their_chunk = &their_lines[b..b_end];
+        if our_chunk == their_chunk || their_chunk == base_chunk {
+            content.extend(our_chunk.iter().copied());
+        } else if our_chunk == base_chunk {
+            content.extend(their_chunk.iter().copied());
+        } else {
+            conflicts += 1;
+            content.push_str(&format!("<<<<<<< {}\n", labels.0));
+            push_terminated(&mut content, our_chunk);
+            content.push_str("=======\n");
+            push_terminated(&mut content, their_chunk);
+            content.push_str(&format!(">>>>>>> {}\n", labels.1));
+        }
+        (i, a, b) = (k, a_end, b_end);
+    }
+
+    TextMerge { content, conflicts }
+}
+
+/// For every line of the base, the index of the same line on the other side of the diff.
+fn matching_lines(ops: &[DiffOp], base_len: usize) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base_len];
+    for op in ops {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = *op
+        {
+            for k in 0..len {
+                matches[old_index + k] = Some(new_index + k);
+            }
+        }
+    }
+    matches
+}
+
+/// Append lines inside a conflict, making sure the following marker starts on its own line.
+fn push_terminated(content: &mut String, lines: &[&str]) {
+    content.extend(lines.iter().copied());
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{merge_entry, merge_text, ConflictKind, EntryMerge, MergeBase};
+    use crate::hash::SHA1;
+    use crate::internal::object::tree::{TreeItem, TreeItemMode};
+
+    fn id(n: u8) -> SHA1 {
+        SHA1([n; 20])
+    }
+
+    /// Run the merge base search over a graph of `commit -> parents`, using the id as timestamp.
+    fn walk(graph: &HashMap<u8, Vec<u8>>, one: u8, two: u8) -> MergeBase {
+        let mut search = MergeBase::new((id(one), one as usize), (id(two), two as usize));
+        while let Some(commit) = search.next_commit() {
+            let parents: Vec<(SHA1, usize)> = graph[&commit.0[0]]
+                .iter()
+                .map(|p| (id(*p), *p as usize))
+                .collect();
+            search.visit(&parents);
+        }
+        search
+    }
+
+    fn merge_bases(graph: &HashMap<u8, Vec<u8>>, one: u8, two: u8) -> Vec<SHA1> {
+        walk(graph, one, two).finish()
+    }
+
+    #[test]
+    fn test_merge_base() {
+        // 1 - 2 - 3 - 5
+        //      \     /
+        //       4 - 6 - 7
+        let graph = HashMap::from([
+            (1, vec![]),
+            (2, vec![1]),
+            (3, vec![2]),
+            (4, vec![2]),
+            (5, vec![3, 6]),
+            (6, vec![4]),
+            (7, vec![6]),
+        ]);
+        assert_eq!(merge_bases(&graph, 3, 4), vec![id(2)]);
+        assert_eq!(merge_bases(&graph, 5, 7), vec![id(6)]);
+        assert_eq!(merge_bases(&graph, 7, 6), vec![id(6)]);
+        assert_eq!(merge_bases(&graph, 5, 5), vec![id(5)]);
+
+        let disjoint = HashMap::from([(1, vec![]), (2, vec![])]);
+        assert!(merge_bases(&disjoint, 1, 2).is_empty());
+
+        assert_eq!(walk(&graph, 5, 7).unique_counts(), (2, 1));
+        assert_eq!(walk(&graph, 3, 4).unique_counts(), (1, 1));
+        assert_eq!(walk(&graph, 7, 2).unique_counts(), (3, 0));
+        assert_eq!(walk(&graph, 5, 5).unique_counts(), (0, 0));
+        assert_eq!(walk(&disjoint, 1, 2).unique_counts(), (1, 1));
+
+        let (mut ours, theirs) = walk(&graph, 5, 7).unique_commits();
+        ours.sort();
+        assert_eq!((ours, theirs), (vec![id(3), id(5)], vec![id(7)]));
+    }
+
+    #[test]
+    fn test_merge_base_criss_cross() {
+        //   3 - 5
+        //  / \ /
+        // 1   X
+        //  \ / \
+        //   4 - 6
+        let graph = HashMap::from([
+            (1, vec![]),
+            (3, vec![1]),
+            (4, vec![1]),
+            (5, vec![3, 4]),
+            (6, vec![4, 3]),
+        ]);
+        assert_eq!(merge_bases(&graph, 5, 6), vec![id(4), id(3)]);
+    }
+
+    #[test]
+    fn test_merge_text_clean() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "a\nB\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nD\ne\nf\n";
+        let merged = merge_text(base, ours, theirs, ("ours", "theirs"));
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(merged.content, "a\nB\nc\nD\ne\nf\n");
+
+        // the same change on both sides is not a conflict
+        let merged = merge_text(base, ours, ours, ("ours", "theirs"));
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(merged.content, ours);
+    }
+
+    #[test]
+    fn test_merge_text_conflict() {
+        let base = "a\nb\nc\n";
+        let ours = "a\nmine\nc\n";
+        let theirs = "a\nyours\nc\n";
+        let merged = merge_text(base, ours, theirs, ("main", "feature"));
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.content,
+            "a\n<<<<<<< main\nmine\n=======\nyours\n>>>>>>> feature\nc\n"
+        );
+
+        // markers stay on their own line when a side lacks the final newline
+        let merged = merge_text("a\nb", "a\nmine", "a\nyours", ("main", "feature"));
+        assert_eq!(
+            merged.content,
+            "a\n<<<<<<< main\nmine\n=======\nyours\n>>>>>>> feature\n"
+        );
+
+        // both sides adding a file with different content
+        let merged = merge_text("", "one\n", "two\n", ("main", "feature"));
+        assert_eq!(merged.conflicts, 1);
+    }
+
+    #[test]
+    fn test_merge_entry() {
+        let file = |n: u8| TreeItem::new(TreeItemMode::Blob, id(n), "f".to_owned());
+        let dir = |n: u8| TreeItem::new(TreeItemMode::Tree, id(n), "f".to_owned());
+
+        assert_eq!(
+            merge_entry(Some(&file(1)), Some(&file(1)), Some(&file(2))),
+            EntryMerge::Resolved(Some(file(2)))
+        );
+        assert_eq!(
+            merge_entry(Some(&file(1)), None, Some(&file(1))),
+            EntryMerge::Resolved(None)
+        );
+        assert_eq!(
+            merge_entry(Some(&file(1)), None, Some(&file(2))),
+            EntryMerge::Conflict(ConflictKind::ModifyDelete)
+        );
+        assert_eq!(
+            merge_entry(None, Some(&file(1)), Some(&dir(2))),
+            EntryMerge::Conflict(ConflictKind::EntryType)
+        );
+        assert_eq!(
+            merge_entry(Some(&dir(1)), Some(&dir(2)), Some(&dir(3))),
+            EntryMerge::Trees {
+                base: Some(id(1)),
+                ours: id(2),
+                theirs: id(3)
+            }
+        );
+
+        let mut executable = file(3);
+        executable.mode = TreeItemMode::BlobExecutable;
+        assert_eq!(
+            merge_entry(Some(&file(1)), Some(&file(2)), Some(&executable)),
+            EntryMerge::Blobs {
+                base: Some(id(1)),
+                ours: id(2),
+                theirs: id(3),
+                mode: TreeItemMode::BlobExecutable
+            }
+        );
+    }
+}
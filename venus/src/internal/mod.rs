@@ -1,3 +1,8 @@
+pub mod blame;
+pub mod diff;
+pub mod gitattributes;
+pub mod gitmodules;
+pub mod merge;
 pub mod object;
 pub mod pack;
 pub mod repo;
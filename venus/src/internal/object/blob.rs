@@ -29,9 +29,13 @@
 //!
 use std::fmt::Display;
 
+use serde_json::json;
+
 use crate::errors::GitError;
+use crate::hash::SHA1;
 use crate::internal::object::types::ObjectType;
 use crate::internal::object::ObjectTrait;
+use crate::render::{paint, Part, Render, Style};
 
 /// **The Blob Object**
 ///
@@ -50,14 +54,28 @@ impl PartialEq for Blob {
 
 impl Display for Blob {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        writeln!(f, "Type: Blob").unwrap();
-        writeln!(f, "Size: {}", self.data.len())
+        self.render_text(Style::Plain, f)
+    }
+}
+
+impl Render for Blob {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = |text| paint(text, Part::Label, style);
+        writeln!(f, "{} {}", label("Type:"), paint("Blob", Part::Kind, style))?;
+        writeln!(f, "{} {}", label("Size:"), self.data.len())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "id": SHA1::from_type_and_data(ObjectType::Blob, &self.data).to_plain_str(),
+            "size": self.data.len(),
+        })
     }
 }
 
 impl ObjectTrait for Blob {
     /// Creates a new object from a byte slice.
-    fn from_bytes(data: Vec<u8>) -> Result<Self, GitError>
+    fn from_bytes(data: &[u8]) -> Result<Self, GitError>
     where
         Self: Sized,
     {
@@ -18,12 +18,14 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 use bstr::ByteSlice;
+use serde::{Deserialize, Serialize};
 
 use crate::errors::GitError;
 use crate::hash::SHA1;
 use crate::internal::object::signature::Signature;
 use crate::internal::object::ObjectTrait;
 use crate::internal::object::ObjectType;
+use crate::render::{paint, Part, Render, Style};
 
 /// The `Commit` struct is used to represent a commit object.
 ///
@@ -35,15 +37,22 @@ use crate::internal::object::ObjectType;
 /// history of a repository with a single commit object at its root.
 /// - The author and committer fields contain the name, email address, timestamp and timezone.
 /// - The message field contains the commit message, which maybe include signed or DCO.
+/// - The gpgsig field contains the armored GPG or SSH signature of a signed commit.
 #[allow(unused)]
-#[derive(Eq, Debug, Clone)]
+#[derive(Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct Commit {
     pub id: SHA1,
     pub tree_id: SHA1,
+    #[serde(rename = "parent_ids")]
     pub parent_commit_ids: Vec<SHA1>,
     pub author: Signature,
     pub committer: Signature,
+    /// Everything after the committer line but the `gpgsig` header: other headers, if any, then
+    /// the blank line and the message itself.
     pub message: String,
+    /// The signature in the `gpgsig` header, with its lines unindented.
+    #[serde(default)]
+    pub gpgsig: Option<String>,
 }
 
 impl PartialEq for Commit {
@@ -54,14 +63,25 @@ impl PartialEq for Commit {
 
 impl Display for Commit {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        writeln!(f, "tree: {}", self.tree_id)?;
+        self.render_text(Style::Plain, f)
+    }
+}
+
+impl Render for Commit {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = |text| paint(text, Part::Label, style);
+        writeln!(f, "{} {}", label("tree:"), self.tree_id.styled(style))?;
         for parent in self.parent_commit_ids.iter() {
-            writeln!(f, "parent: {}", parent)?;
+            writeln!(f, "{} {}", label("parent:"), parent.styled(style))?;
         }
-        writeln!(f, "author {}", self.author)?;
-        writeln!(f, "committer {}", self.committer)?;
+        writeln!(f, "{} {}", label("author"), self.author.styled(style))?;
+        writeln!(f, "{} {}", label("committer"), self.committer.styled(style))?;
         writeln!(f, "{}", self.message)
     }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
 }
 
 impl Commit {
@@ -83,55 +103,119 @@ impl Commit {
         data.extend(&[0x0a]);
         data.extend(self.committer.to_data()?);
         data.extend(&[0x0a]);
-        data.extend(self.message.as_bytes());
+        data.extend(join_gpgsig(self.gpgsig.as_deref(), &self.message).as_bytes());
 
         Ok(data)
     }
+
+    /// The data a signed commit's signature covers, which is the commit without its `gpgsig`.
+    pub fn signed_data(&self) -> Result<Vec<u8>, GitError> {
+        Commit {
+            gpgsig: None,
+            ..self.clone()
+        }
+        .to_data()
+    }
+}
+
+/// Where the headers after the committer line end in `rest`, the start of the blank line before
+/// the message.
+fn headers_end(rest: &str) -> usize {
+    if rest.starts_with('\n') {
+        return 0;
+    }
+    rest.find("\n\n").map_or(rest.len(), |i| i + 1)
+}
+
+/// Split the `gpgsig` header out of what follows the committer line. git writes it after the
+/// other headers, and it is only taken out there so that [`join_gpgsig`] puts it back in place.
+pub(crate) fn split_gpgsig(rest: &str) -> (Option<String>, String) {
+    let end = headers_end(rest);
+    let Some(start) = rest[..end]
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .find(|(_, line)| line.starts_with("gpgsig "))
+        .map(|(start, _)| start)
+    else {
+        return (None, rest.to_owned());
+    };
+    let mut lines = rest[start..end].split_inclusive('\n');
+    let first = lines.next().unwrap();
+    let mut signature = vec![first["gpgsig ".len()..].trim_end_matches('\n')];
+    for line in lines {
+        match line.strip_prefix(' ') {
+            Some(line) => signature.push(line.trim_end_matches('\n')),
+            None => return (None, rest.to_owned()),
+        }
+    }
+    let message = format!("{}{}", &rest[..start], &rest[end..]);
+    (Some(signature.join("\n")), message)
+}
+
+/// What follows the committer line of a commit with `message` and the signature `gpgsig`.
+pub(crate) fn join_gpgsig(gpgsig: Option<&str>, message: &str) -> String {
+    let Some(gpgsig) = gpgsig else {
+        return message.to_owned();
+    };
+    let end = headers_end(message);
+    format!(
+        "{}gpgsig {}\n{}",
+        &message[..end],
+        gpgsig.split('\n').collect::<Vec<_>>().join("\n "),
+        &message[end..]
+    )
 }
 
 impl ObjectTrait for Commit {
-    fn from_bytes(data: Vec<u8>) -> Result<Self, GitError>
+    fn from_bytes(data: &[u8]) -> Result<Self, GitError>
     where
         Self: Sized,
     {
-        let mut commit = data;
-        // Find the tree id and remove it from the data
-        let tree_end = commit.find_byte(0x0a).unwrap();
-        let tree_id: SHA1 = SHA1::from_str(
-            String::from_utf8(commit[5..tree_end].to_owned())
-                .unwrap()
-                .as_str(),
-        )
-        .unwrap();
-        commit = commit[tree_end + 1..].to_vec();
+        let invalid = |what: &str| GitError::InvalidCommitObject(what.to_owned());
+        let parse_id = |line: &[u8]| {
+            line.to_str()
+                .ok()
+                .and_then(|id| SHA1::from_str(id).ok())
+                .ok_or_else(|| invalid("object id"))
+        };
+        // Find the tree id and step past it
+        let tree_end = data.find_byte(0x0a).ok_or_else(|| invalid("tree"))?;
+        let tree_id: SHA1 = parse_id(data.get(5..tree_end).ok_or_else(|| invalid("tree"))?)?;
+        let commit = &data[tree_end + 1..];
 
-        // Find the parent commit ids and remove them from the data
-        let author_begin = commit.find("author").unwrap();
+        // Find the parent commit ids and step past them
+        let author_begin = commit.find("author").ok_or_else(|| invalid("author"))?;
         let parent_commit_ids: Vec<SHA1> = commit[..author_begin]
             .find_iter("parent")
             .map(|parent| {
-                let parent_end = commit[parent..].find_byte(0x0a).unwrap();
-                SHA1::from_str(
-                    String::from_utf8(commit[parent + 7..parent + parent_end].to_owned())
-                        .unwrap()
-                        .as_str(),
+                let parent_end = commit[parent..]
+                    .find_byte(0x0a)
+                    .ok_or_else(|| invalid("parent"))?;
+                parse_id(
+                    commit
+                        .get(parent + 7..parent + parent_end)
+                        .ok_or_else(|| invalid("parent"))?,
                 )
-                .unwrap()
             })
-            .collect();
-        commit = commit[author_begin..].to_vec();
-
-        // Find the author and committer and remove them from the data
-        let author =
-            Signature::new_from_data(commit[..commit.find_byte(0x0a).unwrap()].to_vec()).unwrap();
-        commit = commit[commit.find_byte(0x0a).unwrap() + 1..].to_vec();
-        let committer =
-            Signature::new_from_data(commit[..commit.find_byte(0x0a).unwrap()].to_vec()).unwrap();
-
-        // The rest is the message
-        let message = unsafe {
-            String::from_utf8_unchecked(commit[commit.find_byte(0x0a).unwrap() + 1..].to_vec())
-        };
+            .collect::<Result<_, _>>()?;
+        let commit = &commit[author_begin..];
+
+        // Find the author and committer and step past them
+        let author_end = commit.find_byte(0x0a).ok_or_else(|| invalid("author"))?;
+        let author = Signature::new_from_data(&commit[..author_end])?;
+        let commit = &commit[author_end + 1..];
+        let committer_end = commit.find_byte(0x0a).ok_or_else(|| invalid("committer"))?;
+        let committer = Signature::new_from_data(&commit[..committer_end])?;
+
+        // The rest is the message, after the signature if the commit is signed
+        let rest = commit[committer_end + 1..]
+            .to_str()
+            .map_err(|_| GitError::InvalidUtf8("commit message".to_owned()))?;
+        let (gpgsig, message) = split_gpgsig(rest);
 
         Ok(Commit {
             id: SHA1([0u8; 20]),
@@ -140,6 +224,7 @@ impl ObjectTrait for Commit {
             author,
             committer,
             message,
+            gpgsig,
         })
     }
 
@@ -148,6 +233,60 @@ impl ObjectTrait for Commit {
     }
 
     fn get_size(&self) -> usize {
-        todo!()
+        self.to_data().map(|data| data.len()).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::internal::object::commit::Commit;
+    use crate::internal::object::ObjectTrait;
+
+    const SIGNED: &str = "tree 341e54913a3a43069f2927cc0f703e5a9f730df1\n\
+        parent 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+        author mega <mega@example.com> 1700000000 +0800\n\
+        committer mega <mega@example.com> 1700000000 +0800\n\
+        gpgsig -----BEGIN PGP SIGNATURE-----\n \n \
+        iHUEABYKAB0WIQ\n \
+        -----END PGP SIGNATURE-----\n\
+        \n\
+        signed commit\n";
+
+    #[test]
+    fn test_commit_gpgsig() {
+        let commit = Commit::from_bytes(SIGNED.as_bytes()).unwrap();
+        assert_eq!(
+            commit.gpgsig.as_deref(),
+            Some("-----BEGIN PGP SIGNATURE-----\n\niHUEABYKAB0WIQ\n-----END PGP SIGNATURE-----")
+        );
+        assert_eq!(commit.message, "\nsigned commit\n");
+        assert_eq!(commit.to_data().unwrap(), SIGNED.as_bytes());
+        assert_eq!(
+            String::from_utf8(commit.signed_data().unwrap()).unwrap(),
+            "tree 341e54913a3a43069f2927cc0f703e5a9f730df1\n\
+            parent 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+            author mega <mega@example.com> 1700000000 +0800\n\
+            committer mega <mega@example.com> 1700000000 +0800\n\
+            \n\
+            signed commit\n"
+        );
+
+        // a header after the signature keeps it in the message, in place
+        let data = SIGNED.replace("\n\nsigned", "\nencoding UTF-8\n\nsigned");
+        let commit = Commit::from_bytes(data.as_bytes()).unwrap();
+        assert_eq!(commit.gpgsig, None);
+        assert_eq!(commit.to_data().unwrap(), data.as_bytes());
+
+        let unsigned = Commit::from_bytes(&commit.signed_data().unwrap()).unwrap();
+        assert_eq!(unsigned.gpgsig, None);
+        assert!(Commit::from_bytes(
+            b"tree 341e54913a3a43069f2927cc0f703e5a9f730df1\n\
+            author mega <mega@example.com> 1700000000 +0800\n\
+            committer mega <mega@example.com> 1700000000 +0800\n\
+            \nmessage mentioning gpgsig \n"
+        )
+        .unwrap()
+        .gpgsig
+        .is_none());
     }
 }
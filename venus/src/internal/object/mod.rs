@@ -12,8 +12,8 @@ use crate::errors::GitError;
 use crate::internal::object::types::ObjectType;
 
 pub trait ObjectTrait: Send + Sync + Display {
-    /// Creates a new object from a byte slice.
-    fn from_bytes(data: Vec<u8>) -> Result<Self, GitError>
+    /// Creates a new object from a byte slice, borrowing from it while parsing.
+    fn from_bytes(data: &[u8]) -> Result<Self, GitError>
     where
         Self: Sized;
 
@@ -23,3 +23,88 @@ pub trait ObjectTrait: Send + Sync + Display {
     ///
     fn get_size(&self) -> usize;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::internal::object::blob::Blob;
+    use crate::internal::object::commit::Commit;
+    use crate::internal::object::signature::Signature;
+    use crate::internal::object::tag::Tag;
+    use crate::internal::object::tree::Tree;
+    use crate::internal::object::ObjectTrait;
+
+    const OBJECTS: [&[u8]; 3] = [
+        b"tree 341e54913a3a43069f2927cc0f703e5a9f730df1\n\
+        parent 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+        author mega <mega@example.com> 1700000000 +0800\n\
+        committer mega <mega@example.com> 1700000000 +0800\n\
+        \n\
+        message\n",
+        b"object 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+        type commit\n\
+        tag v1.0\n\
+        tagger mega <mega@example.com> 1700000000 +0800\n\
+        \n\
+        release\n",
+        b"100644 hello.txt\0\x8a\xb6\x86\xea\xfe\xb1\xf4\x47\x02\x73\x8c\x8b\x0f\x24\xf2\x56\x7c\x36\xda\x6d\
+        40000 src\0\x34\x1e\x54\x91\x3a\x3a\x43\x06\x9f\x29\x27\xcc\x0f\x70\x3e\x5a\x9f\x73\x0d\xf1",
+    ];
+
+    /// A xorshift generator, so every run feeds the parsers the same bytes.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn parse_all(data: &[u8]) {
+        let _ = Blob::from_bytes(data);
+        let _ = Commit::from_bytes(data);
+        let _ = Tag::from_bytes(data);
+        let _ = Tree::from_bytes(data);
+        let _ = Signature::new_from_data(data);
+    }
+
+    #[test]
+    fn test_parse_random_bytes() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let data: Vec<u8> = (0..rng.below(128)).map(|_| rng.next() as u8).collect();
+            parse_all(&data);
+        }
+    }
+
+    #[test]
+    fn test_parse_mutated_objects() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for object in OBJECTS {
+            parse_all(object);
+            for len in 0..object.len() {
+                parse_all(&object[..len]);
+            }
+            for _ in 0..2000 {
+                let mut data = object.to_vec();
+                for _ in 0..=rng.below(4) {
+                    let at = rng.below(data.len());
+                    match rng.below(3) {
+                        0 => data[at] = rng.next() as u8,
+                        1 => {
+                            data.remove(at);
+                        }
+                        _ => data.insert(at, b"\n <>\0 "[rng.below(6)]),
+                    }
+                }
+                parse_all(&data);
+            }
+        }
+    }
+}
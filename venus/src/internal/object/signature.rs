@@ -9,11 +9,13 @@
 //! - Timezone: The timezone offset of the author's local time from Coordinated Universal Time (UTC),
 //! encoded as a string in the format "+HHMM" or "-HHMM".
 //!
-use std::{fmt::Display, str::FromStr};
+use std::{borrow::Cow, fmt::Display, str::FromStr};
 
 use bstr::ByteSlice;
+use serde::{Deserialize, Serialize};
 
 use crate::errors::GitError;
+use crate::render::{paint, Part, Render, Style};
 
 /// In addition to the author signature, Git also includes a "committer" signature, which indicates
 /// who committed the changes to the repository. The committer signature is similar in structure to
@@ -28,7 +30,8 @@ use crate::errors::GitError;
 /// ```
 ///
 /// So, we design a `SignatureType` enum to indicate the signature type.
-#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone)]
+#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SignatureType {
     Author,
     Committer,
@@ -76,7 +79,7 @@ impl SignatureType {
 }
 
 #[allow(unused)]
-#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone)]
+#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone, Serialize, Deserialize)]
 pub struct Signature {
     pub signature_type: SignatureType,
     pub name: String,
@@ -87,69 +90,108 @@ pub struct Signature {
 
 impl Display for Signature {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        writeln!(f, "{} <{}>", self.name, self.email).unwrap();
-        writeln!(f, "Date: {}", self.timestamp)
+        self.render_text(Style::Plain, f)
     }
 }
 
-impl Signature {
-    #[allow(unused)]
-    pub fn new_from_data(data: Vec<u8>) -> Result<Signature, GitError> {
-        // Make a mutable copy of the input data vector.
-        let mut sign = data;
-
-        // Find the index of the first space byte in the data vector.
-        let name_start = sign.find_byte(0x20).unwrap();
-
-        // Parse the author name from the bytes up to the first space byte.
-        // If the parsing fails, unwrap will panic.
-        let signature_type = SignatureType::from_data(sign[..name_start].to_vec()).unwrap();
-
-        let (name, email) = {
-            let email_start = sign.find_byte(0x3C).unwrap();
-            let email_end = sign.find_byte(0x3E).unwrap();
-
-            unsafe {
-                (
-                    sign[name_start + 1..email_start - 1]
-                        .to_str_unchecked()
-                        .to_string(),
-                    sign[email_start + 1..email_end]
-                        .to_str_unchecked()
-                        .to_string(),
-                )
-            }
-        };
+impl Render for Signature {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{} <{}>", self.name, self.email)?;
+        writeln!(
+            f,
+            "{} {}",
+            paint("Date:", Part::Label, style),
+            self.timestamp
+        )
+    }
 
-        // Update the data vector to remove the author and email bytes.
-        sign = sign[sign.find_byte(0x3E).unwrap() + 2..].to_vec();
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
 
-        // Find the index of the second space byte in the updated data vector.
-        let timestamp_split = sign.find_byte(0x20).unwrap();
+/// A signature borrowing its text from the object it was parsed from, so reading the author of
+/// many commits doesn't allocate for each. Turn it into a [`Signature`] to keep it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SignatureRef<'a> {
+    pub signature_type: SignatureType,
+    pub name: Cow<'a, str>,
+    pub email: Cow<'a, str>,
+    pub timestamp: usize,
+    pub timezone: Cow<'a, str>,
+}
 
-        // Parse the timestamp integer from the bytes up to the second space byte.
-        // If the parsing fails, unwrap will panic.
-        let timestamp = unsafe {
-            sign[0..timestamp_split]
-                .to_str_unchecked()
-                .parse::<usize>()
-                .unwrap()
+impl<'a> SignatureRef<'a> {
+    /// Parse a signature line, `<type> <name> <<email>> <timestamp> <timezone>`, without its
+    /// newline.
+    pub fn parse(data: &'a [u8]) -> Result<Self, GitError> {
+        let invalid = || GitError::InvalidSignatureType(String::from_utf8_lossy(data).into_owned());
+        let text = |field: &str, bytes: &'a [u8]| {
+            bytes.to_str().map_err(|_| {
+                GitError::InvalidUtf8(format!(
+                    "signature {} `{}`",
+                    field,
+                    String::from_utf8_lossy(bytes)
+                ))
+            })
         };
 
-        // Parse the timezone string from the bytes after the second space byte.
-        // If the parsing fails, unwrap will panic.
-        let timezone = unsafe { sign[timestamp_split + 1..].to_str_unchecked().to_string() };
-
-        // Return a Result object indicating success
-        Ok(Signature {
+        // The type is the text up to the first space
+        let name_start = data.find_byte(0x20).ok_or_else(invalid)?;
+        let signature_type = data[..name_start]
+            .to_str()
+            .ok()
+            .and_then(|t| SignatureType::from_str(t).ok())
+            .ok_or_else(invalid)?;
+
+        // The email is between the angle brackets, the name before it
+        let email_start = data.find_byte(0x3C).ok_or_else(invalid)?;
+        let email_end = data.find_byte(0x3E).ok_or_else(invalid)?;
+        if email_start <= name_start || email_end < email_start {
+            return Err(invalid());
+        }
+        // no name leaves a single space before the email
+        let name_end = (email_start - 1).max(name_start + 1);
+        let name = text("name", &data[name_start + 1..name_end])?;
+        let email = text("email", &data[email_start + 1..email_end])?;
+
+        // The timestamp and the timezone follow the email, separated by a space
+        let rest = data.get(email_end + 2..).ok_or_else(invalid)?;
+        let timestamp_split = rest.find_byte(0x20).ok_or_else(invalid)?;
+        let timestamp = rest[..timestamp_split]
+            .to_str()
+            .ok()
+            .and_then(|t| t.parse::<usize>().ok())
+            .ok_or_else(invalid)?;
+        let timezone = text("timezone", &rest[timestamp_split + 1..])?;
+
+        Ok(SignatureRef {
             signature_type,
-            name,
-            email,
+            name: Cow::Borrowed(name),
+            email: Cow::Borrowed(email),
             timestamp,
-            timezone,
+            timezone: Cow::Borrowed(timezone),
         })
     }
 
+    pub fn into_owned(self) -> Signature {
+        Signature {
+            signature_type: self.signature_type,
+            name: self.name.into_owned(),
+            email: self.email.into_owned(),
+            timestamp: self.timestamp,
+            timezone: self.timezone.into_owned(),
+        }
+    }
+}
+
+impl Signature {
+    /// Parse a signature line, see [`SignatureRef::parse`].
+    #[allow(unused)]
+    pub fn new_from_data(data: &[u8]) -> Result<Signature, GitError> {
+        SignatureRef::parse(data).map(SignatureRef::into_owned)
+    }
+
     ///
     #[allow(unused)]
     pub fn to_data(&self) -> Result<Vec<u8>, GitError> {
@@ -182,9 +224,11 @@ impl Signature {
 
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
     use std::str::FromStr;
 
-    use crate::internal::object::signature::{Signature, SignatureType};
+    use crate::errors::GitError;
+    use crate::internal::object::signature::{Signature, SignatureRef, SignatureType};
 
     #[test]
     fn test_signature_type_from_str() {
@@ -227,12 +271,8 @@ mod tests {
 
     #[test]
     fn test_signature_new_from_data() {
-        let sign = Signature::new_from_data(
-            "author Quanyi Ma <eli@patch.sh> 1678101573 +0800"
-                .to_string()
-                .into_bytes(),
-        )
-        .unwrap();
+        let sign =
+            Signature::new_from_data(b"author Quanyi Ma <eli@patch.sh> 1678101573 +0800").unwrap();
 
         assert_eq!(sign.signature_type, SignatureType::Author);
         assert_eq!(sign.name, "Quanyi Ma");
@@ -241,14 +281,36 @@ mod tests {
         assert_eq!(sign.timezone, "+0800");
     }
 
+    #[test]
+    fn test_signature_new_from_invalid_data() {
+        for data in [
+            "author Quanyi Ma",
+            "author Quanyi Ma <eli@patch.sh>",
+            "author Quanyi Ma <eli@patch.sh> yesterday +0800",
+            "reviewer Quanyi Ma <eli@patch.sh> 1678101573 +0800",
+        ] {
+            assert!(Signature::new_from_data(data.as_bytes()).is_err());
+        }
+
+        assert!(matches!(
+            Signature::new_from_data(b"author Quanyi \xff <eli@patch.sh> 1678101573 +0800"),
+            Err(GitError::InvalidUtf8(_))
+        ));
+    }
+
+    #[test]
+    fn test_signature_ref_borrows() {
+        let data = b"author Quanyi Ma <eli@patch.sh> 1678101573 +0800";
+        let sign = SignatureRef::parse(data).unwrap();
+        assert!(matches!(sign.name, Cow::Borrowed("Quanyi Ma")));
+        assert!(matches!(sign.email, Cow::Borrowed("eli@patch.sh")));
+        assert_eq!(sign.into_owned(), Signature::new_from_data(data).unwrap());
+    }
+
     #[test]
     fn test_signature_to_data() {
-        let sign = Signature::new_from_data(
-            "committer Quanyi Ma <eli@patch.sh> 1678101573 +0800"
-                .to_string()
-                .into_bytes(),
-        )
-        .unwrap();
+        let sign = Signature::new_from_data(b"committer Quanyi Ma <eli@patch.sh> 1678101573 +0800")
+            .unwrap();
 
         let dest = sign.to_data().unwrap();
 
@@ -40,16 +40,18 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 use bstr::ByteSlice;
+use serde::{Deserialize, Serialize};
 
 use crate::errors::GitError;
 use crate::hash::SHA1;
 use crate::internal::object::signature::Signature;
 use crate::internal::object::ObjectTrait;
 use crate::internal::object::ObjectType;
+use crate::render::{paint, Part, Render, Style};
 
 /// The tag object is used to Annotated tag
 #[allow(unused)]
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Tag {
     pub id: SHA1,
     pub object_hash: SHA1,
@@ -61,12 +63,31 @@ pub struct Tag {
 
 impl Display for Tag {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.render_text(Style::Plain, f)
+    }
+}
+
+impl Render for Tag {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = |text| paint(text, Part::Label, style);
         write!(
             f,
-            "object {}\ntype {}\ntag {}\ntagger {}\n\n{}",
-            self.object_hash, self.object_type, self.tag_name, self.tagger, self.message
+            "{} {}\n{} {}\n{} {}\n{} {}\n\n{}",
+            label("object"),
+            self.object_hash.styled(style),
+            label("type"),
+            paint(&self.object_type.to_string(), Part::Kind, style),
+            label("tag"),
+            self.tag_name,
+            label("tagger"),
+            self.tagger.styled(style),
+            self.message
         )
     }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
 }
 
 impl Tag {
@@ -123,41 +144,48 @@ impl ObjectTrait for Tag {
     /// tagger <tagger> 0x0a # The name, email address, and date of the person who created the annotated tag
     /// <message>
     /// ```
-    fn from_bytes(row_data: Vec<u8>) -> Result<Self, GitError>
+    fn from_bytes(row_data: &[u8]) -> Result<Self, GitError>
     where
         Self: Sized,
     {
-        let mut data = row_data;
-
-        let hash_begin = data.find_byte(0x20).unwrap();
-        let hash_end = data.find_byte(0x0a).unwrap();
-        let object_hash = SHA1::from_str(data[hash_begin + 1..hash_end].to_str().unwrap()).unwrap();
-        data = data[hash_end + 1..].to_vec();
-
-        let type_begin = data.find_byte(0x20).unwrap();
-        let type_end = data.find_byte(0x0a).unwrap();
-        let object_type =
-            ObjectType::from_string(data[type_begin + 1..type_end].to_str().unwrap()).unwrap();
-        data = data[type_end + 1..].to_vec();
-
-        let tag_begin = data.find_byte(0x20).unwrap();
-        let tag_end = data.find_byte(0x0a).unwrap();
-        let tag_name = String::from_utf8(data[tag_begin + 1..tag_end].to_vec()).unwrap();
-        data = data[tag_end + 1..].to_vec();
-
-        let tagger_begin = data.find("tagger").unwrap();
-        let tagger_end = data.find_byte(0x0a).unwrap();
-        let tagger_data = data[tagger_begin..tagger_end].to_vec();
-        let tagger = Signature::new_from_data(tagger_data).unwrap();
-        data = data[data.find_byte(0x0a).unwrap() + 1..].to_vec();
-
-        let message = unsafe {
-            data[data.find_byte(0x0a).unwrap()..]
-                .to_vec()
-                .to_str_unchecked()
-                .to_string()
+        let truncated = |what: &str| GitError::TruncatedEntry(format!("tag {}", what));
+        let invalid = |what: &str| GitError::InvalidTagObject(what.to_owned());
+        let text = |what: &str, bytes: &[u8]| {
+            bytes
+                .to_str()
+                .map(str::to_owned)
+                .map_err(|_| GitError::InvalidUtf8(format!("tag {}", what)))
+        };
+        // Find the header line `<key> <value>\n`, giving where its value starts and ends
+        let header = |key: &str, data: &[u8]| -> Result<(usize, usize), GitError> {
+            let end = data.find_byte(0x0a).ok_or_else(|| truncated(key))?;
+            if !data[..end].starts_with(key.as_bytes()) || data.get(key.len()) != Some(&0x20) {
+                return Err(invalid(key));
+            }
+            Ok((key.len() + 1, end))
         };
 
+        let data = row_data;
+        let (begin, end) = header("object", data)?;
+        let object_hash =
+            SHA1::from_str(&text("object", &data[begin..end])?).map_err(|_| invalid("object"))?;
+        let data = &data[end + 1..];
+
+        let (begin, end) = header("type", data)?;
+        let object_type = ObjectType::from_string(&text("type", &data[begin..end])?)?;
+        let data = &data[end + 1..];
+
+        let (begin, end) = header("tag", data)?;
+        let tag_name = text("tag", &data[begin..end])?;
+        let data = &data[end + 1..];
+
+        let (_, end) = header("tagger", data)?;
+        let tagger = Signature::new_from_data(&data[..end])?;
+        let data = &data[end + 1..];
+
+        // The message keeps the blank line before it, see `to_data`
+        let message = text("message", data)?;
+
         Ok(Tag {
             id: SHA1([0u8; 20]),
             object_hash,
@@ -173,6 +201,53 @@ impl ObjectTrait for Tag {
     }
 
     fn get_size(&self) -> usize {
-        todo!()
+        self.to_data().map(|data| data.len()).unwrap_or(0)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::errors::GitError;
+    use crate::internal::object::tag::Tag;
+    use crate::internal::object::ObjectTrait;
+
+    const TAG: &str = "object 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+        type commit\n\
+        tag v1.0\n\
+        tagger mega <mega@example.com> 1700000000 +0800\n\
+        \n\
+        release 1.0\n";
+
+    #[test]
+    fn test_tag_from_bytes() {
+        let tag = Tag::from_bytes(TAG.as_bytes()).unwrap();
+        assert_eq!(
+            tag.object_hash.to_plain_str(),
+            "8ab686eafeb1f44702738c8b0f24f2567c36da6d"
+        );
+        assert_eq!(tag.tag_name, "v1.0");
+        assert_eq!(tag.tagger.name, "mega");
+        assert_eq!(tag.to_data().unwrap(), TAG.as_bytes());
+    }
+
+    #[test]
+    fn test_tag_from_invalid_bytes() {
+        assert!(matches!(
+            Tag::from_bytes(&TAG.as_bytes()[..20]),
+            Err(GitError::TruncatedEntry(_))
+        ));
+        assert!(matches!(
+            Tag::from_bytes(TAG.replace("type commit", "type branch").as_bytes()),
+            Err(GitError::InvalidObjectType(_))
+        ));
+        assert!(matches!(
+            Tag::from_bytes(TAG.replace("tag v1.0", "name v1.0").as_bytes()),
+            Err(GitError::InvalidTagObject(_))
+        ));
+        let mut data = TAG.as_bytes().to_vec();
+        data.extend_from_slice(b"\xff\n");
+        assert!(matches!(
+            Tag::from_bytes(&data),
+            Err(GitError::InvalidUtf8(_))
+        ));
     }
-}
\ No newline at end of file
+}
@@ -14,22 +14,25 @@
 //! have been added, modified, or deleted between two points in time. This allows Git to perform
 //! operations like merging and rebasing more quickly and accurately.
 //!
+use std::borrow::Cow;
 use std::fmt::Display;
 
 use bstr::ByteSlice;
-use colored::Colorize;
+use serde::{Deserialize, Serialize};
 
 use crate::errors::GitError;
 use crate::hash::SHA1;
 use crate::internal::object::ObjectTrait;
 use crate::internal::object::ObjectType;
+use crate::render::{paint, Part, Render, Style};
 
 /// In Git, the mode field in a tree object's entry specifies the type of the object represented by
 /// that entry. The mode is a three-digit octal number that encodes both the permissions and the
 /// type of the object. The first digit specifies the object type, and the remaining two digits
 /// specify the file mode or permissions.
 #[allow(unused)]
-#[derive(PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TreeItemMode {
     Blob,
     BlobExecutable,
@@ -40,15 +43,14 @@ pub enum TreeItemMode {
 
 impl Display for TreeItemMode {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let _print = match *self {
+        let print = match *self {
             TreeItemMode::Blob => "blob",
             TreeItemMode::BlobExecutable => "blob executable",
             TreeItemMode::Tree => "tree",
             TreeItemMode::Commit => "commit",
             TreeItemMode::Link => "link",
         };
-
-        write!(f, "{}", String::from(_print).blue())
+        write!(f, "{}", print)
     }
 }
 
@@ -91,8 +93,8 @@ impl TreeItemMode {
             b"100664" => TreeItemMode::Blob,
             b"100640" => TreeItemMode::Blob,
             _ => {
-                return Err(GitError::InvalidTreeItem(
-                    String::from_utf8(mode.to_vec()).unwrap(),
+                return Err(GitError::InvalidMode(
+                    String::from_utf8_lossy(mode).into_owned(),
                 ));
             }
         })
@@ -133,7 +135,7 @@ impl TreeItemMode {
 /// 040000 data\0<tree object ID>
 /// ```
 #[allow(unused)]
-#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone)]
+#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone, Serialize, Deserialize)]
 pub struct TreeItem {
     pub mode: TreeItemMode,
     pub id: SHA1,
@@ -142,14 +144,24 @@ pub struct TreeItem {
 
 impl Display for TreeItem {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.render_text(Style::Plain, f)
+    }
+}
+
+impl Render for TreeItem {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
             "{} {} {}",
-            self.mode,
+            paint(&self.mode.to_string(), Part::Kind, style),
             self.name,
-            self.id.to_string().blue()
+            self.id.styled(style)
         )
     }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
 }
 
 impl TreeItem {
@@ -182,18 +194,13 @@ impl TreeItem {
     ///
     #[allow(unused)]
     pub fn new_from_bytes(bytes: &[u8]) -> Result<Self, GitError> {
-        let mut parts = bytes.splitn(2, |b| *b == b' ');
-        let mode = parts.next().unwrap();
-        let rest = parts.next().unwrap();
-        let mut parts = rest.splitn(2, |b| *b == b'\0');
-        let name = parts.next().unwrap();
-        let id = parts.next().unwrap();
-
-        Ok(TreeItem {
-            mode: TreeItemMode::tree_item_type_from_bytes(mode)?,
-            id: SHA1::from_bytes(id),
-            name: String::from_utf8(name.to_vec())?,
-        })
+        let (item, len) = TreeItemRef::parse(bytes)?;
+        if len != bytes.len() {
+            return Err(GitError::InvalidTreeItem(
+                String::from_utf8_lossy(bytes).into_owned(),
+            ));
+        }
+        Ok(item.into_owned())
     }
 
     /// Convert a TreeItem to a byte vector
@@ -223,26 +230,130 @@ impl TreeItem {
     }
 }
 
+/// A tree item borrowing its name from the data of the tree it was parsed from, for walking trees
+/// without allocating a `String` per entry. Turn it into a [`TreeItem`] to keep it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TreeItemRef<'a> {
+    pub mode: TreeItemMode,
+    pub id: SHA1,
+    pub name: Cow<'a, str>,
+}
+
+impl<'a> TreeItemRef<'a> {
+    /// Parse the first entry of `data`, returning it with the number of bytes it takes.
+    pub fn parse(data: &'a [u8]) -> Result<(Self, usize), GitError> {
+        let truncated = || {
+            GitError::TruncatedEntry(
+                String::from_utf8_lossy(&data[..data.len().min(64)]).into_owned(),
+            )
+        };
+        let space = data.find_byte(b' ').ok_or_else(truncated)?;
+        let mode = TreeItemMode::tree_item_type_from_bytes(&data[..space])?;
+        let nul = space + 1 + data[space + 1..].find_byte(b'\0').ok_or_else(truncated)?;
+        let end = nul + 21;
+        let id = data.get(nul + 1..end).ok_or_else(truncated)?;
+        let name = std::str::from_utf8(&data[space + 1..nul]).map_err(|_| {
+            GitError::InvalidUtf8(format!(
+                "tree item name `{}`",
+                String::from_utf8_lossy(&data[space + 1..nul])
+            ))
+        })?;
+        Ok((
+            TreeItemRef {
+                mode,
+                id: SHA1::from_bytes(id),
+                name: Cow::Borrowed(name),
+            },
+            end,
+        ))
+    }
+
+    pub fn into_owned(self) -> TreeItem {
+        TreeItem {
+            mode: self.mode,
+            id: self.id,
+            name: self.name.into_owned(),
+        }
+    }
+}
+
+impl<'a> From<&'a TreeItem> for TreeItemRef<'a> {
+    fn from(item: &'a TreeItem) -> Self {
+        TreeItemRef {
+            mode: item.mode,
+            id: item.id,
+            name: Cow::Borrowed(&item.name),
+        }
+    }
+}
+
+/// The entries of the data of a tree object, in the order they are stored.
+pub struct TreeItems<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for TreeItems<'a> {
+    type Item = Result<TreeItemRef<'a>, GitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match TreeItemRef::parse(self.data) {
+            Ok((item, len)) => {
+                self.data = &self.data[len..];
+                Some(Ok(item))
+            }
+            Err(e) => {
+                // nothing after a broken entry can be found
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
 /// A tree object is a Git object that represents a directory. It contains a list of entries, one
 /// for each file or directory in the tree.
-#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone)]
+#[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone, Serialize, Deserialize)]
 pub struct Tree {
     pub id: SHA1,
+    #[serde(rename = "items")]
     pub tree_items: Vec<TreeItem>,
 }
 
 impl Display for Tree {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        writeln!(f, "Tree: {}", self.id.to_string().blue())?;
+        self.render_text(Style::Plain, f)
+    }
+}
+
+impl Render for Tree {
+    fn render_text(&self, style: Style, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} {}",
+            paint("Tree:", Part::Label, style),
+            self.id.styled(style)
+        )?;
         for item in &self.tree_items {
-            writeln!(f, "{}", item)?;
+            writeln!(f, "{}", item.styled(style))?;
         }
 
         Ok(())
     }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
 }
 
 impl Tree {
+    /// The entries of the tree object data `data`, borrowing their names from it.
+    pub fn items(data: &[u8]) -> TreeItems<'_> {
+        TreeItems { data }
+    }
+
     #[allow(unused)]
     pub fn new_from_tree_items(tree_items: Vec<TreeItem>) -> Result<Self, GitError> {
         if tree_items.is_empty() {
@@ -279,19 +390,13 @@ impl Tree {
 }
 
 impl ObjectTrait for Tree {
-    fn from_bytes(data: Vec<u8>) -> Result<Self, GitError>
+    fn from_bytes(data: &[u8]) -> Result<Self, GitError>
     where
         Self: Sized,
     {
-        let mut tree_items = Vec::new();
-        let mut i = 0;
-        while i < data.len() {
-            let index = data[i..].find_byte(0x00).unwrap();
-            let next = i + index + 21;
-
-            tree_items.push(TreeItem::new_from_bytes(&data[i..next]).unwrap());
-            i = next
-        }
+        let tree_items = Tree::items(data)
+            .map(|item| item.map(TreeItemRef::into_owned))
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(Tree {
             id: SHA1([0u8; 20]),
@@ -304,7 +409,7 @@ impl ObjectTrait for Tree {
     }
 
     fn get_size(&self) -> usize {
-        todo!()
+        self.to_data().map(|data| data.len()).unwrap_or(0)
     }
 }
 
@@ -313,8 +418,12 @@ mod tests {
 
     use std::str::FromStr;
 
+    use crate::errors::GitError;
     use crate::hash::SHA1;
-    use crate::internal::object::tree::{TreeItem, TreeItemMode};
+    use std::borrow::Cow;
+
+    use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+    use crate::internal::object::ObjectTrait;
 
     #[test]
     fn test_tree_item_new() {
@@ -364,4 +473,62 @@ mod tests {
         assert_eq!(tree_item.mode, TreeItemMode::Blob);
         assert_eq!(tree_item.id.to_plain_str(), item.id.to_plain_str());
     }
+
+    #[test]
+    fn test_tree_items_borrow_names() {
+        let items = vec![
+            TreeItem::new(
+                TreeItemMode::Tree,
+                SHA1::from_str("341e54913a3a43069f2927cc0f703e5a9f730df1").unwrap(),
+                "src".to_string(),
+            ),
+            TreeItem::new(
+                TreeItemMode::Blob,
+                SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap(),
+                "hello world.txt".to_string(),
+            ),
+        ];
+        let data: Vec<u8> = items.iter().flat_map(TreeItem::to_data).collect();
+
+        let parsed: Vec<_> = Tree::items(&data).collect::<Result<_, _>>().unwrap();
+        assert!(matches!(parsed[1].name, Cow::Borrowed("hello world.txt")));
+        assert_eq!(Tree::from_bytes(&data).unwrap().tree_items, items);
+
+        // an entry cut short is an error rather than a panic
+        assert!(matches!(
+            Tree::from_bytes(&data[..data.len() - 1]),
+            Err(GitError::TruncatedEntry(_))
+        ));
+        assert!(TreeItem::new_from_bytes(&data).is_err());
+
+        let mut data = data;
+        data[0] = b'9';
+        assert!(matches!(
+            Tree::from_bytes(&data),
+            Err(GitError::InvalidMode(_))
+        ));
+    }
+
+    #[test]
+    fn test_tree_serde() {
+        let item = TreeItem::new(
+            TreeItemMode::BlobExecutable,
+            SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap(),
+            "build.sh".to_string(),
+        );
+        let tree = Tree::new_from_tree_items(vec![item]).unwrap();
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["id"], tree.id.to_plain_str());
+        assert_eq!(
+            json["items"][0],
+            serde_json::json!({
+                "mode": "blob_executable",
+                "id": "8ab686eafeb1f44702738c8b0f24f2567c36da6d",
+                "name": "build.sh",
+            })
+        );
+        let parsed: Tree = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, tree);
+    }
 }
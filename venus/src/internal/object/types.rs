@@ -29,6 +29,7 @@ use crate::errors::GitError;
 /// repository, Git can use the integer value of an object's type to determine how to parse
 /// the object's content.
 #[derive(PartialEq, Eq, Hash, Ord, PartialOrd, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ObjectType {
     Commit,
     Tree,
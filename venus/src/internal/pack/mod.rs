@@ -1,3 +1,4 @@
 pub mod entry;
 pub mod header;
+#[cfg(feature = "db")]
 pub mod reference;
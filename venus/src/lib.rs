@@ -1,4 +1,8 @@
 pub mod errors;
 pub mod hash;
 pub mod internal;
+#[cfg(feature = "db")]
 pub mod model;
+pub mod render;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,27 +1,100 @@
 use std::str::FromStr;
 
 use common::utils::generate_id;
-use db_entity::{db_enums::MergeStatus, git_commit, mega_commit};
+use db_entity::{commit_parents::CommitParents, db_enums::MergeStatus, git_commit, mega_commit};
 
 use crate::{
+    errors::GitError,
     hash::SHA1,
-    internal::object::{commit::Commit, signature::Signature, ObjectTrait},
+    internal::object::{
+        commit::{join_gpgsig, split_gpgsig, Commit},
+        ObjectTrait,
+    },
+    model::{parse_id, parse_signature, signature_text},
 };
 
-impl From<git_commit::Model> for Commit {
-    fn from(value: git_commit::Model) -> Self {
-        Commit {
-            id: SHA1::from_str(&value.commit_id).unwrap(),
-            tree_id: SHA1::from_str(&value.tree).unwrap(),
-            parent_commit_ids: value
-                .parents_id
-                .into_iter()
-                .map(|id| SHA1::from_str(&id).unwrap())
-                .collect(),
-            author: Signature::new_from_data(value.author.unwrap().into()).unwrap(),
-            committer: Signature::new_from_data(value.committer.unwrap().into()).unwrap(),
-            message: value.content.unwrap(),
-        }
+/// The parent ids of a commit row, in order.
+pub trait ParentIds {
+    fn parent_ids(&self) -> Vec<SHA1>;
+}
+
+impl ParentIds for CommitParents {
+    fn parent_ids(&self) -> Vec<SHA1> {
+        // the ids were checked when the row was loaded
+        self.ids()
+            .iter()
+            .map(|id| SHA1::from_str(id).unwrap())
+            .collect()
+    }
+}
+
+impl ParentIds for git_commit::Model {
+    fn parent_ids(&self) -> Vec<SHA1> {
+        self.parents_id.parent_ids()
+    }
+}
+
+impl ParentIds for mega_commit::Model {
+    fn parent_ids(&self) -> Vec<SHA1> {
+        self.parents_id.parent_ids()
+    }
+}
+
+fn commit_parents(ids: &[SHA1]) -> CommitParents {
+    CommitParents::new(ids.iter().map(|id| id.to_plain_str())).unwrap()
+}
+
+/// The commit of the text columns of a commit row.
+fn parse_commit(
+    commit_id: &str,
+    tree: &str,
+    parents_id: &CommitParents,
+    author: Option<&str>,
+    committer: Option<&str>,
+    content: Option<String>,
+) -> Result<Commit, GitError> {
+    let missing =
+        |column: &str| GitError::InvalidCommitObject(format!("{} without {}", commit_id, column));
+    // the content column keeps the signature in its header, as in the object
+    let (gpgsig, message) = split_gpgsig(&content.unwrap_or_default());
+    Ok(Commit {
+        id: parse_id(commit_id)?,
+        tree_id: parse_id(tree)?,
+        parent_commit_ids: parents_id.parent_ids(),
+        author: parse_signature(author.ok_or_else(|| missing("author"))?)?,
+        committer: parse_signature(committer.ok_or_else(|| missing("committer"))?)?,
+        message,
+        gpgsig,
+    })
+}
+
+impl TryFrom<git_commit::Model> for Commit {
+    type Error = GitError;
+
+    fn try_from(value: git_commit::Model) -> Result<Self, Self::Error> {
+        parse_commit(
+            &value.commit_id,
+            &value.tree,
+            &value.parents_id,
+            value.author.as_deref(),
+            value.committer.as_deref(),
+            value.content,
+        )
+    }
+}
+
+impl TryFrom<mega_commit::Model> for Commit {
+    type Error = GitError;
+
+    fn try_from(value: mega_commit::Model) -> Result<Self, Self::Error> {
+        parse_commit(
+            &value.commit_id,
+            &value.tree,
+            &value.parents_id,
+            value.author.as_deref(),
+            value.committer.as_deref(),
+            value.content,
+        )
     }
 }
 
@@ -32,14 +105,10 @@ impl From<Commit> for git_commit::Model {
             repo_id: 0,
             commit_id: value.id.to_plain_str(),
             tree: value.tree_id.to_plain_str(),
-            parents_id: value
-                .parent_commit_ids
-                .iter()
-                .map(|x| x.to_plain_str())
-                .collect(),
-            author: Some(value.author.to_string()),
-            committer: Some(value.committer.to_string()),
-            content: Some(value.message.clone()),
+            parents_id: commit_parents(&value.parent_commit_ids),
+            author: Some(signature_text(&value.author)),
+            committer: Some(signature_text(&value.committer)),
+            content: Some(join_gpgsig(value.gpgsig.as_deref(), &value.message)),
             size: value.get_size() as i32,
             full_path: "".to_string(),
             created_at: chrono::Utc::now().naive_utc(),
@@ -53,14 +122,10 @@ impl From<Commit> for mega_commit::Model {
             id: generate_id(),
             commit_id: value.id.to_plain_str(),
             tree: value.tree_id.to_plain_str(),
-            parents_id: value
-                .parent_commit_ids
-                .iter()
-                .map(|x| x.to_plain_str())
-                .collect(),
-            author: Some(value.author.to_string()),
-            committer: Some(value.committer.to_string()),
-            content: Some(value.message.clone()),
+            parents_id: commit_parents(&value.parent_commit_ids),
+            author: Some(signature_text(&value.author)),
+            committer: Some(signature_text(&value.committer)),
+            content: Some(join_gpgsig(value.gpgsig.as_deref(), &value.message)),
             size: value.get_size() as i32,
             full_path: "".to_string(),
             mr_id: None,
@@ -70,3 +135,86 @@ impl From<Commit> for mega_commit::Model {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use db_entity::{git_commit, mega_commit};
+
+    use crate::errors::GitError;
+    use crate::hash::SHA1;
+    use crate::internal::object::commit::Commit;
+    use crate::internal::object::ObjectTrait;
+
+    use super::ParentIds;
+
+    fn commit() -> Commit {
+        let data = b"tree 341e54913a3a43069f2927cc0f703e5a9f730df1\n\
+            parent 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+            author mega <mega@example.com> 1700000000 +0800\n\
+            committer Quanyi Ma <eli@patch.sh> 1700000100 -0130\n\
+            \nadd hello\n";
+        let mut commit = Commit::from_bytes(data).unwrap();
+        commit.id = SHA1::from_str("d85e84e0abd9ecd2b50a2b0b1e0ee0d8bbb3b8e8").unwrap();
+        commit
+    }
+
+    fn assert_same(a: &Commit, b: &Commit) {
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.tree_id, b.tree_id);
+        assert_eq!(a.parent_commit_ids, b.parent_commit_ids);
+        assert_eq!(a.author, b.author);
+        assert_eq!(a.committer, b.committer);
+        assert_eq!(a.message, b.message);
+        assert_eq!(a.gpgsig, b.gpgsig);
+    }
+
+    #[test]
+    fn test_commit_round_trip() {
+        let commit = commit();
+
+        let model = git_commit::Model::from(commit.clone());
+        assert_eq!(
+            model.author.as_deref(),
+            Some("author mega <mega@example.com> 1700000000 +0800")
+        );
+        assert_eq!(
+            model.parents_id.ids(),
+            ["8ab686eafeb1f44702738c8b0f24f2567c36da6d"]
+        );
+        assert_eq!(model.parent_ids(), commit.parent_commit_ids);
+        assert_same(&Commit::try_from(model).unwrap(), &commit);
+
+        let model = mega_commit::Model::from(commit.clone());
+        assert_same(&Commit::try_from(model).unwrap(), &commit);
+
+        let mut signed = commit;
+        signed.gpgsig =
+            Some("-----BEGIN SSH SIGNATURE-----\nU1NIU0lH\n-----END SSH SIGNATURE-----".to_owned());
+        let model = mega_commit::Model::from(signed.clone());
+        assert!(model.content.as_deref().unwrap().starts_with("gpgsig "));
+        assert_same(&Commit::try_from(model).unwrap(), &signed);
+    }
+
+    #[test]
+    fn test_invalid_commit_model() {
+        let mut model = git_commit::Model::from(commit());
+        model.tree = "not an id".to_owned();
+        assert!(matches!(
+            Commit::try_from(model),
+            Err(GitError::InvalidHashValue(id)) if id == "not an id"
+        ));
+
+        let mut model = mega_commit::Model::from(commit());
+        model.committer = None;
+        assert!(matches!(
+            Commit::try_from(model),
+            Err(GitError::InvalidCommitObject(_))
+        ));
+
+        let mut model = mega_commit::Model::from(commit());
+        model.author = Some("mega <mega@example.com>\nDate: 1700000000\n".to_owned());
+        assert!(Commit::try_from(model).is_err());
+    }
+}
@@ -7,6 +7,7 @@ use db_entity::db_enums::MergeStatus;
 use db_entity::{mega_snapshot, mega_tree};
 
 use crate::internal::object::tree::Tree;
+use crate::model::tree::tree_item_text;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct MegaNode {
@@ -25,7 +26,7 @@ impl MegaNode {
     pub fn convert_to_mega_tree(&self, tree: &Tree) {
         let mut model: mega_tree::Model = self.to_owned().into();
         model.tree_id = tree.id.to_plain_str();
-        model.sub_trees = tree.tree_items.iter().map(|x| x.to_string()).collect();
+        model.sub_trees = tree.tree_items.iter().map(tree_item_text).collect();
         model.size = 0;
     }
 }
@@ -1,6 +1,36 @@
+//! Conversions between the objects and the `git_*` and `mega_*` database models.
+//!
+//! Object ids are stored as hex strings and signatures as the text of their line in the object,
+//! e.g. `author mega <mega@example.com> 1700000000 +0800`, which parses back into the same
+//! [`Signature`]. Models are turned into objects with `TryFrom`, failing with a [`GitError`] on
+//! rows whose text doesn't parse rather than panicking.
+//!
+use std::str::FromStr;
+
+use crate::errors::GitError;
+use crate::hash::SHA1;
+use crate::internal::object::signature::Signature;
+
 pub mod commit;
 pub mod create_file;
 pub mod entry;
 pub mod mega_node;
 pub mod reference;
 pub mod repo;
+pub mod tag;
+pub mod tree;
+
+/// The object id in the hex string `id`.
+pub fn parse_id(id: &str) -> Result<SHA1, GitError> {
+    SHA1::from_str(id).map_err(|_| GitError::InvalidHashValue(id.to_owned()))
+}
+
+/// The text a signature is stored as, its line in the object.
+pub fn signature_text(signature: &Signature) -> String {
+    String::from_utf8_lossy(&signature.to_data().unwrap()).into_owned()
+}
+
+/// The signature stored as `text` by [`signature_text`].
+pub fn parse_signature(text: &str) -> Result<Signature, GitError> {
+    Signature::new_from_data(text.as_bytes())
+}
@@ -0,0 +1,140 @@
+use common::utils::generate_id;
+use db_entity::{git_tag, mega_tag};
+
+use crate::{
+    errors::GitError,
+    internal::object::{tag::Tag, types::ObjectType},
+    model::{parse_id, parse_signature, signature_text},
+};
+
+/// The tag of the text columns of a tag row.
+fn parse_tag(
+    tag_id: &str,
+    object_id: &str,
+    object_type: Option<&str>,
+    tag_name: String,
+    tagger: &str,
+    message: String,
+) -> Result<Tag, GitError> {
+    let object_type = object_type
+        .ok_or_else(|| GitError::InvalidTagObject(format!("{} without object_type", tag_id)))?;
+    Ok(Tag {
+        id: parse_id(tag_id)?,
+        object_hash: parse_id(object_id)?,
+        object_type: ObjectType::from_string(object_type)?,
+        tag_name,
+        tagger: parse_signature(tagger)?,
+        message,
+    })
+}
+
+impl TryFrom<git_tag::Model> for Tag {
+    type Error = GitError;
+
+    fn try_from(value: git_tag::Model) -> Result<Self, Self::Error> {
+        parse_tag(
+            &value.tag_id,
+            &value.object_id,
+            value.object_type.as_deref(),
+            value.tag_name,
+            &value.tagger,
+            value.message,
+        )
+    }
+}
+
+impl TryFrom<mega_tag::Model> for Tag {
+    type Error = GitError;
+
+    fn try_from(value: mega_tag::Model) -> Result<Self, Self::Error> {
+        parse_tag(
+            &value.tag_id,
+            &value.object_id,
+            value.object_type.as_deref(),
+            value.tag_name,
+            &value.tagger,
+            value.message,
+        )
+    }
+}
+
+impl From<Tag> for git_tag::Model {
+    fn from(value: Tag) -> Self {
+        git_tag::Model {
+            id: generate_id(),
+            repo_id: 0,
+            tag_id: value.id.to_plain_str(),
+            object_id: value.object_hash.to_plain_str(),
+            object_type: Some(value.object_type.to_string()),
+            tag_name: value.tag_name,
+            tagger: signature_text(&value.tagger),
+            message: value.message,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl From<Tag> for mega_tag::Model {
+    fn from(value: Tag) -> Self {
+        mega_tag::Model {
+            id: generate_id(),
+            tag_id: value.id.to_plain_str(),
+            object_id: value.object_hash.to_plain_str(),
+            object_type: Some(value.object_type.to_string()),
+            tag_name: value.tag_name,
+            tagger: signature_text(&value.tagger),
+            message: value.message,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use db_entity::{git_tag, mega_tag};
+
+    use crate::errors::GitError;
+    use crate::hash::SHA1;
+    use crate::internal::object::signature::Signature;
+    use crate::internal::object::tag::Tag;
+    use crate::internal::object::types::ObjectType;
+
+    #[test]
+    fn test_tag_round_trip() {
+        let tag = Tag {
+            id: SHA1::from_str("85fe8bd6d1b3cb1c5d2e8a3f5d0f2f1ab0e4c1d2").unwrap(),
+            object_hash: SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap(),
+            object_type: ObjectType::Commit,
+            tag_name: "v1.0.0".to_owned(),
+            tagger: Signature::new_from_data(b"tagger mega <mega@example.com> 1700000000 +0800")
+                .unwrap(),
+            message: "release\n".to_owned(),
+        };
+
+        let model = mega_tag::Model::from(tag.clone());
+        assert_eq!(model.object_type.as_deref(), Some("commit"));
+        assert_eq!(
+            model.tagger,
+            "tagger mega <mega@example.com> 1700000000 +0800"
+        );
+        let back = Tag::try_from(model).unwrap();
+        assert_eq!(back.id, tag.id);
+        assert_eq!(back.object_hash, tag.object_hash);
+        assert_eq!(back.object_type, tag.object_type);
+        assert_eq!(back.tag_name, tag.tag_name);
+        assert_eq!(back.tagger, tag.tagger);
+        assert_eq!(back.message, tag.message);
+
+        let mut model = git_tag::Model::from(tag);
+        assert_eq!(Tag::try_from(model.clone()).unwrap().tag_name, "v1.0.0");
+        model.object_type = Some("branch".to_owned());
+        assert!(matches!(
+            Tag::try_from(model),
+            Err(GitError::InvalidObjectType(_))
+        ));
+    }
+}
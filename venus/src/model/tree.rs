@@ -0,0 +1,130 @@
+use common::utils::generate_id;
+use db_entity::{db_enums::MergeStatus, git_tree, mega_tree};
+
+use crate::{
+    errors::GitError,
+    internal::object::{
+        tree::{Tree, TreeItem, TreeItemMode},
+        ObjectTrait,
+    },
+    model::parse_id,
+};
+
+/// The text a tree item is stored as in `sub_trees`: `<mode> <id> <name>`, e.g.
+/// `100644 8ab686eafeb1f44702738c8b0f24f2567c36da6d hello.txt`, the name last as it may have spaces.
+pub fn tree_item_text(item: &TreeItem) -> String {
+    format!(
+        "{} {} {}",
+        String::from_utf8_lossy(item.mode.to_bytes()),
+        item.id,
+        item.name
+    )
+}
+
+/// The tree item stored as `text` by [`tree_item_text`].
+pub fn parse_tree_item(text: &str) -> Result<TreeItem, GitError> {
+    let invalid = || GitError::InvalidTreeItem(text.to_owned());
+    let (mode, rest) = text.split_once(' ').ok_or_else(invalid)?;
+    let (id, name) = rest.split_once(' ').ok_or_else(invalid)?;
+    Ok(TreeItem::new(
+        TreeItemMode::tree_item_type_from_bytes(mode.as_bytes())?,
+        parse_id(id)?,
+        name.to_owned(),
+    ))
+}
+
+fn parse_tree(tree_id: &str, sub_trees: &[String]) -> Result<Tree, GitError> {
+    Ok(Tree {
+        id: parse_id(tree_id)?,
+        tree_items: sub_trees
+            .iter()
+            .map(|item| parse_tree_item(item))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+impl TryFrom<git_tree::Model> for Tree {
+    type Error = GitError;
+
+    fn try_from(value: git_tree::Model) -> Result<Self, Self::Error> {
+        parse_tree(&value.tree_id, &value.sub_trees.unwrap_or_default())
+    }
+}
+
+impl TryFrom<mega_tree::Model> for Tree {
+    type Error = GitError;
+
+    fn try_from(value: mega_tree::Model) -> Result<Self, Self::Error> {
+        parse_tree(&value.tree_id, &value.sub_trees)
+    }
+}
+
+impl From<Tree> for git_tree::Model {
+    fn from(value: Tree) -> Self {
+        git_tree::Model {
+            id: generate_id(),
+            repo_id: 0,
+            tree_id: value.id.to_plain_str(),
+            sub_trees: Some(value.tree_items.iter().map(tree_item_text).collect()),
+            name: None,
+            size: value.get_size() as i32,
+            full_path: "".to_string(),
+            commit_id: "".to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl From<Tree> for mega_tree::Model {
+    fn from(value: Tree) -> Self {
+        mega_tree::Model {
+            id: generate_id(),
+            tree_id: value.id.to_plain_str(),
+            sub_trees: value.tree_items.iter().map(tree_item_text).collect(),
+            import_dir: false,
+            mr_id: String::new(),
+            status: MergeStatus::Open,
+            size: value.get_size() as i32,
+            full_path: "".to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use db_entity::{git_tree, mega_tree};
+
+    use crate::errors::GitError;
+    use crate::hash::SHA1;
+    use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+    #[test]
+    fn test_tree_round_trip() {
+        let id = SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap();
+        let tree = Tree::new_from_tree_items(vec![
+            TreeItem::new(TreeItemMode::BlobExecutable, id, "build it.sh".to_owned()),
+            TreeItem::new(TreeItemMode::Tree, id, "src".to_owned()),
+        ])
+        .unwrap();
+
+        let model = mega_tree::Model::from(tree.clone());
+        assert_eq!(
+            model.sub_trees[0],
+            "100755 8ab686eafeb1f44702738c8b0f24f2567c36da6d build it.sh"
+        );
+        assert_eq!(Tree::try_from(model).unwrap(), tree);
+        let back = Tree::try_from(git_tree::Model::from(tree.clone())).unwrap();
+        assert_eq!(back.tree_items, tree.tree_items);
+
+        let mut model = mega_tree::Model::from(tree);
+        model.sub_trees.push("blob hello.txt".to_owned());
+        assert!(matches!(
+            Tree::try_from(model),
+            Err(GitError::InvalidTreeItem(_))
+        ));
+    }
+}
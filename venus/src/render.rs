@@ -0,0 +1,151 @@
+//! Rendering of objects for people and programs.
+//!
+//! The `Display` of the objects is plain text, fit for logs and API responses. [`Render`] writes
+//! them in the [`Style`] the caller asks for: plain text, text colored with ANSI escapes for
+//! terminals, or JSON.
+//!
+//! ```rust
+//! use std::str::FromStr;
+//!
+//! use venus::hash::SHA1;
+//! use venus::render::{Render, Style};
+//!
+//! let id = SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap();
+//! assert_eq!(id.styled(Style::Plain).to_string(), id.to_plain_str());
+//! assert_eq!(
+//!     id.styled(Style::Json).to_string(),
+//!     "\"8ab686eafeb1f44702738c8b0f24f2567c36da6d\""
+//! );
+//! ```
+//!
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+#[cfg(feature = "color")]
+use colored::Colorize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Style {
+    #[default]
+    Plain,
+    /// Text colored with ANSI escapes, plain without the `color` feature.
+    Ansi,
+    Json,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    /// Parse `plain`, `ansi` or `json`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Style::Plain),
+            "ansi" => Ok(Style::Ansi),
+            "json" => Ok(Style::Json),
+            _ => Err(format!("unknown style {}, expected plain, ansi or json", s)),
+        }
+    }
+}
+
+/// The part of an object some text is, which the ANSI style gives a color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Part {
+    /// Object ids.
+    Id,
+    /// Object types and tree item modes.
+    Kind,
+    /// Field names, e.g. `tree` or `author`.
+    Label,
+}
+
+/// `text` colored as `part` in the ANSI style, as is in the others.
+pub fn paint(text: &str, part: Part, style: Style) -> String {
+    match style {
+        #[cfg(feature = "color")]
+        Style::Ansi => match part {
+            Part::Id => text.red().bold().to_string(),
+            Part::Kind => text.blue().to_string(),
+            Part::Label => text.yellow().to_string(),
+        },
+        _ => {
+            let _ = part;
+            text.to_owned()
+        }
+    }
+}
+
+pub trait Render {
+    /// Write `self` as text, painting its parts in `style`, either `Plain` or `Ansi`.
+    fn render_text(&self, style: Style, f: &mut Formatter) -> fmt::Result;
+
+    fn to_json(&self) -> serde_json::Value;
+
+    fn render(&self, style: Style, f: &mut Formatter) -> fmt::Result {
+        match style {
+            Style::Json => write!(f, "{}", self.to_json()),
+            _ => self.render_text(style, f),
+        }
+    }
+
+    /// `self` rendered in `style` by its `Display`, e.g. `println!("{}", tree.styled(style))`.
+    fn styled(&self, style: Style) -> Styled<'_, Self> {
+        Styled {
+            object: self,
+            style,
+        }
+    }
+}
+
+/// An object along with the style it is displayed in.
+pub struct Styled<'a, T: ?Sized> {
+    object: &'a T,
+    style: Style,
+}
+
+impl<T: Render + ?Sized> Display for Styled<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.object.render(self.style, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::hash::SHA1;
+    use crate::internal::object::signature::Signature;
+    use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+    use super::{Render, Style};
+
+    #[test]
+    fn test_styles() {
+        let id = SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap();
+        let item = TreeItem::new(TreeItemMode::Blob, id, "hello.txt".to_owned());
+        let tree = Tree::new_from_tree_items(vec![item.clone()]).unwrap();
+
+        assert_eq!(
+            item.to_string(),
+            "blob hello.txt 8ab686eafeb1f44702738c8b0f24f2567c36da6d"
+        );
+        assert_eq!(tree.styled(Style::Plain).to_string(), tree.to_string());
+        assert!(!tree.to_string().contains('\x1b'));
+        #[cfg(feature = "color")]
+        {
+            colored::control::set_override(true);
+            assert!(item.styled(Style::Ansi).to_string().contains('\x1b'));
+        }
+
+        let json: serde_json::Value =
+            serde_json::from_str(&tree.styled(Style::Json).to_string()).unwrap();
+        assert_eq!(json["id"], tree.id.to_plain_str());
+        assert_eq!(json["items"][0]["mode"], "blob");
+        assert_eq!(json["items"][0]["name"], "hello.txt");
+
+        let signature =
+            Signature::new_from_data(b"author mega <mega@example.com> 1700000000 +0800").unwrap();
+        assert_eq!(signature.to_json()["timestamp"], 1700000000);
+        assert_eq!(Style::from_str("ansi"), Ok(Style::Ansi));
+        assert!(Style::from_str("html").is_err());
+    }
+}
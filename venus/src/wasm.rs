@@ -0,0 +1,96 @@
+//! API of venus for the web UI, compiled to WebAssembly with wasm-bindgen:
+//!
+//! ```bash
+//! cargo build -p venus --target wasm32-unknown-unknown --no-default-features --features wasm
+//! ```
+//!
+//! The UI fetches raw objects from the objects API and renders or checks them in the browser:
+//! `parseCommit` reads a commit, `verifyObject` checks the data of an object has the id it was
+//! fetched by, and `diffHunks` computes the hunks of a line diff between two versions of a file.
+//!
+use std::str::FromStr;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::hash::SHA1;
+use crate::internal::diff::{self, DiffOp};
+use crate::internal::object::commit::Commit;
+use crate::internal::object::types::ObjectType;
+use crate::internal::object::ObjectTrait;
+
+/// A hunk with 1-based line numbers, as in the `@@ -1,3 +1,4 @@` header of a unified diff.
+#[derive(Serialize)]
+struct HunkView {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+    lines: Vec<LineView>,
+}
+
+#[derive(Serialize)]
+struct LineView {
+    /// `context`, `delete` or `insert`.
+    kind: &'static str,
+    text: String,
+}
+
+fn js_error(e: impl ToString) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+/// Parse the data of a commit object, without its `commit <size>` header.
+#[wasm_bindgen(js_name = parseCommit)]
+pub fn parse_commit(data: &[u8]) -> Result<JsValue, JsError> {
+    let mut commit = Commit::from_bytes(data).map_err(js_error)?;
+    commit.id = SHA1::from_type_and_data(ObjectType::Commit, data);
+    serde_wasm_bindgen::to_value(&commit).map_err(js_error)
+}
+
+/// Id of the object of `object_type`, one of `blob`, `tree`, `commit` or `tag`, with `data`.
+#[wasm_bindgen(js_name = objectId)]
+pub fn object_id(object_type: &str, data: &[u8]) -> Result<String, JsError> {
+    let object_type = ObjectType::from_string(object_type).map_err(js_error)?;
+    Ok(SHA1::from_type_and_data(object_type, data).to_plain_str())
+}
+
+/// Whether `data` is the data of the object `id` of `object_type`.
+#[wasm_bindgen(js_name = verifyObject)]
+pub fn verify_object(object_type: &str, id: &str, data: &[u8]) -> Result<bool, JsError> {
+    let id = SHA1::from_str(id).map_err(js_error)?;
+    Ok(object_id(object_type, data)? == id.to_plain_str())
+}
+
+/// Hunks of the line diff from `old` to `new`, with `context` unchanged lines around changes.
+#[wasm_bindgen(js_name = diffHunks)]
+pub fn diff_hunks(old: &str, new: &str, context: usize) -> Result<JsValue, JsError> {
+    let old_lines = diff::split_lines(old);
+    let new_lines = diff::split_lines(new);
+    let ops = diff::diff(&old_lines, &new_lines);
+    let hunks: Vec<HunkView> = diff::hunks(&ops, context)
+        .into_iter()
+        .map(|hunk| {
+            let mut lines = vec![];
+            for op in &hunk.ops {
+                let (kind, text) = match op {
+                    DiffOp::Equal { .. } => ("context", &old_lines[op.old_range()]),
+                    DiffOp::Delete { .. } => ("delete", &old_lines[op.old_range()]),
+                    DiffOp::Insert { .. } => ("insert", &new_lines[op.new_range()]),
+                };
+                lines.extend(text.iter().map(|line| LineView {
+                    kind,
+                    text: line.to_string(),
+                }));
+            }
+            HunkView {
+                old_start: hunk.old_start + 1,
+                old_lines: hunk.old_len,
+                new_start: hunk.new_start + 1,
+                new_lines: hunk.new_len,
+                lines,
+            }
+        })
+        .collect();
+    serde_wasm_bindgen::to_value(&hunks).map_err(js_error)
+}